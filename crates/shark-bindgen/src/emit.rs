@@ -0,0 +1,301 @@
+//! Writes the Shark declarations for what a header declares. `typedef`s are followed to the types
+//! they name rather than declared themselves, as Shark has no aliases for types
+
+use std::collections::{HashMap, HashSet};
+
+use shark_core::{
+    diagnostic::Diagnostic,
+    symbol::{sym, Symbol},
+};
+use shark_lex::token::KeywordKind;
+
+use crate::parse::{CType, Decl, DeclKind};
+
+/// The Shark types of the names headers usually get from `<stdint.h>` and `<stddef.h>`, which
+/// are not read
+const STANDARD_TYPES: &[(&str, &str)] = &[
+    ("int8_t", "Int8"),
+    ("uint8_t", "UInt8"),
+    ("int32_t", "Int32"),
+    ("uint32_t", "UInt32"),
+    ("int64_t", "Int64"),
+    ("uint64_t", "UInt64"),
+    ("size_t", "UInt64"),
+    ("ssize_t", "Int64"),
+    ("ptrdiff_t", "Int64"),
+    ("intptr_t", "Int64"),
+    ("uintptr_t", "UInt64"),
+];
+
+/// Where a type is used, which decides what it becomes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Position {
+    Parameter,
+    Return,
+    Field,
+    /// Behind a pointer, where a type which is not declared can be opaque
+    Pointee,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ItemKind {
+    Type,
+    Const,
+    Function,
+}
+
+/// Gets a name which can be used in Shark for a C identifier
+fn identifier(name: &str) -> String {
+    let symbol = Symbol::intern(name);
+    match KeywordKind::from_symbol(symbol).is_some() || symbol == sym::TRUE || symbol == sym::FALSE
+    {
+        true => format!("{}_", name),
+        false => name.to_string(),
+    }
+}
+
+/// Writes the declarations, along with a warning for every one which can not be written in Shark
+pub fn emit(decls: &[Decl]) -> (String, Vec<Diagnostic>) {
+    let mut emitter = Emitter {
+        typedefs: HashMap::new(),
+        structs: HashMap::new(),
+        emitted: HashSet::new(),
+        items: Vec::new(),
+        diagnostics: Vec::new(),
+    };
+    for decl in decls {
+        match &decl.kind {
+            DeclKind::Typedef(ty) => {
+                emitter.typedefs.entry(&decl.name).or_insert(ty);
+                if let CType::Struct(tag) = ty {
+                    let entry = emitter.structs.entry(tag).or_default();
+                    entry.name.get_or_insert_with(|| identifier(&decl.name));
+                }
+            }
+            DeclKind::Struct(fields) => {
+                let entry = emitter.structs.entry(&decl.name).or_default();
+                entry.is_defined |= fields.as_ref().is_some_and(|x| !x.is_empty());
+            }
+            _ => {}
+        }
+    }
+    let mut opaque = Vec::new();
+    for decl in decls {
+        let mut found = Vec::new();
+        match emitter.item(decl, &mut found) {
+            Ok(()) => opaque.append(&mut found),
+            Err(reason) => emitter.diagnostics.push(
+                Diagnostic::warning(format!("skipped `{}`", decl.name))
+                    .with_primary(decl.span, reason),
+            ),
+        }
+    }
+    // The types found behind pointers which the header does not declare come first
+    let mut items = Vec::new();
+    for name in opaque {
+        if emitter.emitted.insert(name.clone()) {
+            items.push((ItemKind::Type, format!("extern \"C\" type {};", name)));
+        }
+    }
+    items.append(&mut emitter.items);
+
+    let mut code = String::new();
+    let mut previous: Option<(ItemKind, bool)> = None;
+    for (kind, text) in items {
+        let is_block = text.contains('\n');
+        if let Some((previous_kind, previous_block)) = previous {
+            if previous_kind != kind || previous_block || is_block {
+                code.push('\n');
+            }
+        }
+        code.push_str(&text);
+        code.push('\n');
+        previous = Some((kind, is_block));
+    }
+    (code, emitter.diagnostics)
+}
+
+#[derive(Debug, Default)]
+struct StructInfo {
+    /// The name of the first `typedef` naming the `struct`, which it is called in Shark
+    name: Option<String>,
+    /// Whether the header defines its fields
+    is_defined: bool,
+}
+
+struct Emitter<'d> {
+    typedefs: HashMap<&'d str, &'d CType>,
+    /// Every `struct` the header declares, by its tag
+    structs: HashMap<&'d str, StructInfo>,
+    /// The names of the types and functions which have been written
+    emitted: HashSet<String>,
+    items: Vec<(ItemKind, String)>,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl<'d> Emitter<'d> {
+    fn struct_name(&self, tag: &str) -> String {
+        match self.structs.get(tag).and_then(|x| x.name.clone()) {
+            Some(name) => name,
+            None => identifier(tag),
+        }
+    }
+
+    /// Follows the `typedef`s of the header to the type they name
+    fn resolve<'a>(&'a self, mut ty: &'a CType) -> &'a CType {
+        // Limited, as a `typedef` can name itself
+        for _ in 0..64 {
+            match ty {
+                CType::Name(name) if self.typedefs.contains_key(name.as_str()) => {
+                    ty = self.typedefs[name.as_str()];
+                }
+                _ => break,
+            }
+        }
+        ty
+    }
+
+    fn opaque(&mut self, name: String) {
+        let text = format!("extern \"C\" type {};", name);
+        self.emitted.insert(name);
+        self.items.push((ItemKind::Type, text));
+    }
+
+    /// Writes the item for a declaration, adding the types it uses which the header does not
+    /// declare to `found`
+    fn item(&mut self, decl: &'d Decl, found: &mut Vec<String>) -> Result<(), String> {
+        match &decl.kind {
+            DeclKind::Function {
+                parameters,
+                return_type,
+                is_variadic,
+            } => {
+                // Headers can declare a function more than once
+                if self.emitted.contains(&decl.name) {
+                    return Ok(());
+                }
+                if identifier(&decl.name) != decl.name {
+                    return Err("the name is a keyword of Shark".to_string());
+                }
+                let mut written = Vec::new();
+                for (index, parameter) in parameters.iter().enumerate() {
+                    let name = match &parameter.name {
+                        Some(name) => identifier(name),
+                        None => format!("arg{}", index),
+                    };
+                    let ty = self.shark_type(&parameter.ty, Position::Parameter, found);
+                    written.push(format!("{} :: {}", name, ty?));
+                }
+                if *is_variadic {
+                    written.push("...".to_string());
+                }
+                let mut text = format!("extern \"C\" fun {}({})", decl.name, written.join(", "));
+                if self.resolve(return_type) != &CType::Void {
+                    let ty = self.shark_type(return_type, Position::Return, found)?;
+                    text.push_str(&format!(" :: {}", ty));
+                }
+                text.push(';');
+                self.emitted.insert(decl.name.clone());
+                self.items.push((ItemKind::Function, text));
+            }
+            DeclKind::Struct(Some(fields)) if !fields.is_empty() => {
+                let name = self.struct_name(&decl.name);
+                let mut text = format!("extern \"C\" type {} {{\n", name);
+                for (field, ty) in fields {
+                    let ty = self.shark_type(ty, Position::Field, found)?;
+                    text.push_str(&format!("    {} :: {},\n", identifier(field), ty));
+                }
+                text.push('}');
+                self.emitted.insert(name);
+                self.items.push((ItemKind::Type, text));
+            }
+            DeclKind::Struct(_) => {
+                let name = self.struct_name(&decl.name);
+                if !self.structs[decl.name.as_str()].is_defined && !self.emitted.contains(&name) {
+                    self.opaque(name);
+                }
+            }
+            // A `typedef` of a `struct` which is never defined declares an opaque type
+            DeclKind::Typedef(CType::Struct(tag)) => {
+                let name = self.struct_name(tag);
+                if !self.structs[tag.as_str()].is_defined && !self.emitted.contains(&name) {
+                    self.opaque(name);
+                }
+            }
+            DeclKind::Typedef(_) => {}
+            DeclKind::Enum(enumerators) => {
+                for (name, value) in enumerators {
+                    let text = format!("const {} :: Int32 = {};", identifier(name), value);
+                    self.items.push((ItemKind::Const, text));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Gets the Shark type of a C type where it is used, or why it has none
+    fn shark_type(
+        &self,
+        ty: &CType,
+        position: Position,
+        found: &mut Vec<String>,
+    ) -> Result<String, String> {
+        match ty {
+            CType::Void => Err("`void` can only be the return type of a function".to_string()),
+            CType::Char => Ok("Int8".to_string()),
+            CType::Primitive(name) => Ok(name.to_string()),
+            CType::Enum(_) => Ok("Int32".to_string()),
+            CType::Name(name) => {
+                if let Some(ty) = self.typedefs.get(name.as_str()) {
+                    return self.shark_type(ty, position, found);
+                }
+                if let Some((_, ty)) = STANDARD_TYPES.iter().find(|(x, _)| x == name) {
+                    return Ok(ty.to_string());
+                }
+                match position {
+                    Position::Pointee => {
+                        found.push(identifier(name));
+                        Ok(identifier(name))
+                    }
+                    _ => Err(format!(
+                        "`{}` is not declared by the header, so it can only be used behind a \
+                         pointer",
+                        name
+                    )),
+                }
+            }
+            CType::Struct(tag) => {
+                let name = self.struct_name(tag);
+                match self.structs.get(tag.as_str()) {
+                    Some(info) if info.is_defined => Ok(name),
+                    _ if position != Position::Pointee => Err(format!(
+                        "`struct {}` has no fields in the header, so it can only be used behind a \
+                         pointer",
+                        tag
+                    )),
+                    Some(_) => Ok(name),
+                    None => {
+                        found.push(name.clone());
+                        Ok(name)
+                    }
+                }
+            }
+            CType::Pointer { pointee, is_const } => {
+                let keyword = if *is_const { "ptr" } else { "ptr mut" };
+                match self.resolve(pointee) {
+                    CType::Void => Ok(format!("{} UInt8", keyword)),
+                    CType::Char
+                        if *is_const
+                            && matches!(position, Position::Parameter | Position::Return) =>
+                    {
+                        Ok("Str".to_string())
+                    }
+                    _ => {
+                        let pointee = self.shark_type(pointee, Position::Pointee, found)?;
+                        Ok(format!("{} {}", keyword, pointee))
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,129 @@
+//! Splits a C header into tokens. Comments and the lines of the preprocessor are skipped, as
+//! bindings are generated from what a header declares rather than from its macros
+
+use shark_core::{diagnostic::Diagnostic, source::Span};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TokenKind {
+    Identifier(String),
+    /// A number or a character, which is kept as it was written
+    Number(String),
+    /// A string, such as the `"C"` of `extern "C" {`
+    Str(String),
+    /// `...`
+    Ellipsis,
+    /// Any other character, such as `{` or `*`
+    Punct(char),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub span: Span,
+}
+
+/// Splits a header into tokens, failing only on a comment or a string which is never closed
+pub fn lex(source: &str) -> Result<Vec<Token>, Diagnostic> {
+    let bytes = source.as_bytes();
+    let mut tokens = Vec::new();
+    // Whether only whitespace came before on the current line, where a `#` starts a directive
+    let mut line_start = true;
+    let mut index = 0;
+    while index < bytes.len() {
+        let start = index;
+        let byte = bytes[index];
+        match byte {
+            b'\n' => {
+                line_start = true;
+                index += 1;
+                continue;
+            }
+            _ if byte.is_ascii_whitespace() => {
+                index += 1;
+                continue;
+            }
+            b'#' if line_start => {
+                // Directives carry on past lines ending with a backslash
+                while index < bytes.len() && bytes[index] != b'\n' {
+                    if bytes[index] == b'\\' && bytes.get(index + 1) == Some(&b'\n') {
+                        index += 1;
+                    }
+                    index += 1;
+                }
+                continue;
+            }
+            b'/' if bytes.get(index + 1) == Some(&b'/') => {
+                while index < bytes.len() && bytes[index] != b'\n' {
+                    index += 1;
+                }
+                continue;
+            }
+            b'/' if bytes.get(index + 1) == Some(&b'*') => {
+                match source[index + 2..].find("*/") {
+                    Some(end) => index += end + 4,
+                    None => {
+                        return Err(Diagnostic::error("unterminated comment")
+                            .with_primary(Span::new(start, start + 2), "the comment starts here"))
+                    }
+                }
+                continue;
+            }
+            _ => line_start = false,
+        }
+        let kind = match byte {
+            b'"' | b'\'' => {
+                index += 1;
+                while index < bytes.len() && bytes[index] != byte && bytes[index] != b'\n' {
+                    if bytes[index] == b'\\' {
+                        index += 1;
+                    }
+                    index += 1;
+                }
+                if bytes.get(index) != Some(&byte) {
+                    let what = if byte == b'"' { "string" } else { "character" };
+                    return Err(Diagnostic::error(format!("unterminated {}", what))
+                        .with_primary(Span::new(start, index.min(bytes.len())), ""));
+                }
+                index += 1;
+                let text = source[start + 1..index - 1].to_string();
+                match byte {
+                    b'"' => TokenKind::Str(text),
+                    _ => TokenKind::Number(source[start..index].to_string()),
+                }
+            }
+            b'.' if bytes[index..].starts_with(b"...") => {
+                index += 3;
+                TokenKind::Ellipsis
+            }
+            _ if byte.is_ascii_digit() => {
+                while index < bytes.len()
+                    && (bytes[index].is_ascii_alphanumeric() || bytes[index] == b'.')
+                {
+                    index += 1;
+                }
+                TokenKind::Number(source[start..index].to_string())
+            }
+            _ if byte.is_ascii_alphabetic() || byte == b'_' => {
+                while index < bytes.len()
+                    && (bytes[index].is_ascii_alphanumeric() || bytes[index] == b'_')
+                {
+                    index += 1;
+                }
+                TokenKind::Identifier(source[start..index].to_string())
+            }
+            _ => {
+                let character = source[index..]
+                    .chars()
+                    .next()
+                    .expect("the index is in bounds");
+                index += character.len_utf8();
+                TokenKind::Punct(character)
+            }
+        };
+        tokens.push(Token {
+            kind,
+            span: Span::new(start, index),
+        });
+    }
+    Ok(tokens)
+}
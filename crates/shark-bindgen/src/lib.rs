@@ -0,0 +1,44 @@
+//! Generates Shark declarations for a C header, for `sharkc bindgen`. Only the subset of C which
+//! headers declare functions and types with is read: the prototypes of functions, `struct`s,
+//! `enum`s and `typedef`s. Comments and the lines of the preprocessor are skipped, as is every
+//! declaration using something else, with a warning saying why
+//!
+//! Types are translated for 64-bit Linux and macOS, where `long` is 64 bits:
+//!
+//! - `struct`s become `extern "C"` types with the same fields, or opaque ones if the header does
+//!   not define their fields
+//! - the enumerators of an `enum` become `Int32` constants, and the `enum` itself `Int32`
+//! - `const char *` parameters and return values become `Str`, pointers to `void` become pointers
+//!   to `UInt8`, and every other pointer a `ptr`, which is `mut` unless its pointee is `const`
+//! - a type the header uses behind a pointer without declaring it, such as `FILE`, becomes an
+//!   opaque `extern "C"` type
+//!
+//! Names which are keywords in Shark get an `_` added to them, apart from the names of functions,
+//! which C links by name and so are skipped
+
+use shark_core::diagnostic::Diagnostic;
+
+pub mod emit;
+pub mod lex;
+pub mod parse;
+
+#[cfg(test)]
+pub mod tests;
+
+/// The Shark declarations generated for a header
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bindings {
+    pub code: String,
+    /// A warning for every declaration which was skipped, in the order of the header
+    pub warnings: Vec<Diagnostic>,
+}
+
+/// Generates the declarations for a header, failing only if it can not be split into tokens
+pub fn generate(header: &str) -> Result<Bindings, Diagnostic> {
+    let tokens = lex::lex(header)?;
+    let (decls, mut warnings) = parse::parse(&tokens);
+    let (code, mut skipped) = emit::emit(&decls);
+    warnings.append(&mut skipped);
+    warnings.sort_by_key(|x| x.primary_span().map(|x| x.start));
+    Ok(Bindings { code, warnings })
+}
@@ -0,0 +1,568 @@
+//! Reads the declarations of a C header which bindings can be generated for: the prototypes of
+//! functions, `struct`s, `enum`s and `typedef`s. A declaration using anything else, such as a
+//! `union`, an array or a pointer to a function, is skipped with a warning saying why
+
+use std::collections::HashMap;
+
+use shark_core::{diagnostic::Diagnostic, source::Span};
+
+use crate::lex::{Token, TokenKind};
+
+/// A C type, as it was written in the header
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CType {
+    Void,
+    /// A plain `char`, whose `const` pointers are strings
+    Char,
+    /// A type named by keywords, such as `unsigned long`, as the Shark primitive type it is
+    Primitive(&'static str),
+    /// A name declared by a `typedef`, either in the header or in one it includes
+    Name(String),
+    Struct(String),
+    Enum(String),
+    Pointer {
+        pointee: Box<CType>,
+        /// Whether the pointee is `const`
+        is_const: bool,
+    },
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Parameter {
+    pub name: Option<String>,
+    pub ty: CType,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DeclKind {
+    Function {
+        parameters: Vec<Parameter>,
+        return_type: CType,
+        is_variadic: bool,
+    },
+    /// A `struct`, named by its tag, whose fields are [None] if it is only declared
+    Struct(Option<Vec<(String, CType)>>),
+    /// An `enum` along with the value of each of its enumerators. An `enum` without a tag has an
+    /// empty name
+    Enum(Vec<(String, i64)>),
+    Typedef(CType),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decl {
+    pub name: String,
+    pub span: Span,
+    pub kind: DeclKind,
+}
+
+/// Why a declaration was skipped, pointing at what could not be read
+struct Skip {
+    reason: String,
+    span: Span,
+}
+
+type Result<T> = std::result::Result<T, Skip>;
+
+fn skip<T>(reason: impl Into<String>, span: Span) -> Result<T> {
+    Err(Skip {
+        reason: reason.into(),
+        span,
+    })
+}
+
+/// Reads the declarations of a header, along with a warning for every one which was skipped
+pub fn parse(tokens: &[Token]) -> (Vec<Decl>, Vec<Diagnostic>) {
+    let mut parser = Parser {
+        tokens,
+        position: 0,
+        decls: Vec::new(),
+        enumerators: HashMap::new(),
+        name: None,
+        in_struct: false,
+    };
+    let mut diagnostics = Vec::new();
+    while parser.position < tokens.len() {
+        // The closing brace of `extern "C" {` is skipped along with any stray semicolons
+        if parser.eat_punct(';') || parser.eat_punct('}') {
+            continue;
+        }
+        if parser.at_word("extern") && matches!(parser.peek_nth(1), Some(TokenKind::Str(_))) {
+            parser.position += 2;
+            parser.eat_punct('{');
+            continue;
+        }
+        let start = parser.position;
+        let decls = parser.decls.len();
+        parser.name = None;
+        parser.in_struct = false;
+        if let Err(skip) = parser.declaration() {
+            parser.decls.truncate(decls);
+            let message = match parser.name.take().or_else(|| parser.name_in(start)) {
+                Some(name) => format!("skipped `{}`", name),
+                None => "skipped a declaration".to_string(),
+            };
+            diagnostics.push(Diagnostic::warning(message).with_primary(skip.span, skip.reason));
+            parser.recover(start);
+        }
+    }
+    (parser.decls, diagnostics)
+}
+
+struct Parser<'t> {
+    tokens: &'t [Token],
+    position: usize,
+    decls: Vec<Decl>,
+    /// The value of every enumerator so far, which later ones can be given
+    enumerators: HashMap<String, i64>,
+    /// The name of the declaration being read, once it is known
+    name: Option<String>,
+    /// Whether the fields of a `struct` are being read, where another can not be defined
+    in_struct: bool,
+}
+
+impl<'t> Parser<'t> {
+    fn peek(&self) -> Option<&'t TokenKind> {
+        self.peek_nth(0)
+    }
+
+    fn peek_nth(&self, n: usize) -> Option<&'t TokenKind> {
+        self.tokens.get(self.position + n).map(|x| &x.kind)
+    }
+
+    fn peek_word(&self) -> Option<&'t str> {
+        match self.peek() {
+            Some(TokenKind::Identifier(word)) => Some(word),
+            _ => None,
+        }
+    }
+
+    /// Gets the span of the next token, or of the end of the header
+    fn span(&self) -> Span {
+        match self.tokens.get(self.position) {
+            Some(token) => token.span,
+            None => {
+                let end = self.tokens.last().map_or(0, |x| x.span.end);
+                Span::new(end, end)
+            }
+        }
+    }
+
+    fn previous_span(&self) -> Span {
+        self.tokens[self.position - 1].span
+    }
+
+    fn at_punct(&self, punct: char) -> bool {
+        self.peek() == Some(&TokenKind::Punct(punct))
+    }
+
+    fn eat_punct(&mut self, punct: char) -> bool {
+        let at = self.at_punct(punct);
+        self.position += at as usize;
+        at
+    }
+
+    fn expect_punct(&mut self, punct: char) -> Result<()> {
+        match self.eat_punct(punct) {
+            true => Ok(()),
+            false => skip(format!("expected `{}`", punct), self.span()),
+        }
+    }
+
+    fn at_word(&self, word: &str) -> bool {
+        self.peek_word() == Some(word)
+    }
+
+    fn identifier(&mut self) -> Result<(String, Span)> {
+        match self.peek_word() {
+            Some(word) => {
+                self.position += 1;
+                Ok((word.to_string(), self.previous_span()))
+            }
+            None => skip("expected a name", self.span()),
+        }
+    }
+
+    /// Guesses the name of a declaration which failed before its name was read, as the last
+    /// identifier before its parameters or body
+    fn name_in(&self, start: usize) -> Option<String> {
+        self.tokens[start..]
+            .iter()
+            .take_while(|x| !matches!(x.kind, TokenKind::Punct('(' | '{' | ';' | '[' | ',')))
+            .filter_map(|x| match &x.kind {
+                TokenKind::Identifier(name) => Some(name.clone()),
+                _ => None,
+            })
+            .last()
+    }
+
+    /// Skips the rest of a declaration which could not be read, which ends with a semicolon or
+    /// with the body of a function
+    fn recover(&mut self, start: usize) {
+        self.position = start;
+        let mut depth = 0usize;
+        let mut body = false;
+        while let Some(token) = self.tokens.get(self.position) {
+            self.position += 1;
+            match token.kind {
+                TokenKind::Punct('{') if depth == 0 && self.position >= 2 => {
+                    body = self.tokens[self.position - 2].kind == TokenKind::Punct(')');
+                    depth += 1;
+                }
+                TokenKind::Punct('{' | '(' | '[') => depth += 1,
+                TokenKind::Punct('}' | ')' | ']') => {
+                    depth = depth.saturating_sub(1);
+                    if depth == 0 && body {
+                        return;
+                    }
+                }
+                TokenKind::Punct(';') if depth == 0 => return,
+                _ => {}
+            }
+        }
+    }
+
+    fn declaration(&mut self) -> Result<()> {
+        let is_typedef = self.peek_word() == Some("typedef");
+        self.position += is_typedef as usize;
+        let decls = self.decls.len();
+        let (base, is_const) = self.specifiers(is_typedef)?;
+        if self.at_punct(';') {
+            // Declaring a `struct` without defining it makes it opaque
+            if let (CType::Struct(tag), true) = (&base, self.decls.len() == decls) {
+                let span = self.previous_span();
+                self.decls.push(Decl {
+                    name: tag.clone(),
+                    span,
+                    kind: DeclKind::Struct(None),
+                });
+            }
+            self.position += 1;
+            return Ok(());
+        }
+        loop {
+            let (name, ty) = self.declarator(base.clone(), is_const)?;
+            let Some((name, span)) = name else {
+                return skip("expected a name", self.span());
+            };
+            self.name = Some(name.clone());
+            if is_typedef {
+                if self.at_punct('(') {
+                    return skip("function types are not supported", self.span());
+                }
+                self.decls.push(Decl {
+                    name,
+                    span,
+                    kind: DeclKind::Typedef(ty),
+                });
+            } else if self.at_punct('(') {
+                let (parameters, is_variadic) = self.parameters()?;
+                if self.at_punct('{') {
+                    return skip("functions defined by the header are not supported", span);
+                }
+                self.decls.push(Decl {
+                    name,
+                    span,
+                    kind: DeclKind::Function {
+                        parameters,
+                        return_type: ty,
+                        is_variadic,
+                    },
+                });
+            } else {
+                return skip("variables are not supported", span);
+            }
+            if !self.eat_punct(',') {
+                break;
+            }
+        }
+        self.expect_punct(';')
+    }
+
+    /// Reads the type a declaration starts with, along with whether it is `const`
+    fn specifiers(&mut self, is_typedef: bool) -> Result<(CType, bool)> {
+        let start = self.span();
+        let mut words = Vec::new();
+        let mut base = None;
+        let mut is_const = false;
+        while let Some(word) = self.peek_word() {
+            match word {
+                "const" => is_const = true,
+                "volatile" | "restrict" | "__restrict" | "extern" | "static" | "inline"
+                | "__inline" | "__inline__" | "register" => {}
+                "signed" | "unsigned" | "short" | "long" | "int" | "char" | "float" | "double"
+                | "void" | "_Bool" | "bool" => words.push(word),
+                "union" => return skip("unions are not supported", self.span()),
+                "struct" | "enum" if base.is_none() && words.is_empty() => {
+                    self.position += 1;
+                    base = Some(self.tagged(word == "struct", is_typedef)?);
+                    continue;
+                }
+                _ if base.is_none() && words.is_empty() => base = Some(CType::Name(word.into())),
+                _ => break,
+            }
+            self.position += 1;
+        }
+        match (base, words.is_empty()) {
+            (Some(base), true) => Ok((base, is_const)),
+            (None, false) => Ok((scalar(&words, start.to(self.previous_span()))?, is_const)),
+            _ => skip("expected a type", self.span()),
+        }
+    }
+
+    /// Reads a `struct` or `enum` type, after its keyword, along with its body if it has one
+    fn tagged(&mut self, is_struct: bool, is_typedef: bool) -> Result<CType> {
+        let keyword = self.previous_span();
+        let mut tag = match self.peek_word() {
+            Some(word) => {
+                self.position += 1;
+                Some((word.to_string(), self.previous_span()))
+            }
+            None => None,
+        };
+        if let Some((tag, _)) = &tag {
+            self.name.get_or_insert_with(|| tag.clone());
+        }
+        if !self.at_punct('{') {
+            let Some((tag, _)) = tag else {
+                return skip("expected a name", self.span());
+            };
+            return Ok(match is_struct {
+                true => CType::Struct(tag),
+                false => CType::Enum(tag),
+            });
+        }
+        if self.in_struct {
+            return skip("nested definitions are not supported", keyword);
+        }
+        let kind = match is_struct {
+            true => {
+                self.in_struct = true;
+                let fields = self.fields();
+                self.in_struct = false;
+                DeclKind::Struct(Some(fields?))
+            }
+            false => DeclKind::Enum(self.enumerators()?),
+        };
+        // A `struct` without a tag takes the name the `typedef` gives it
+        if tag.is_none() && is_struct {
+            match (self.peek_word(), self.peek_nth(1)) {
+                (Some(name), Some(TokenKind::Punct(';' | ','))) if is_typedef => {
+                    tag = Some((name.to_string(), self.span()));
+                }
+                _ => {
+                    return skip(
+                        "a `struct` without a name must be named by a `typedef`",
+                        keyword,
+                    )
+                }
+            }
+        }
+        let (name, span) = tag.unwrap_or_else(|| (String::new(), keyword));
+        self.decls.push(Decl {
+            name: name.clone(),
+            span,
+            kind,
+        });
+        Ok(match is_struct {
+            true => CType::Struct(name),
+            false => CType::Enum(name),
+        })
+    }
+
+    fn fields(&mut self) -> Result<Vec<(String, CType)>> {
+        self.expect_punct('{')?;
+        let mut fields = Vec::new();
+        while !self.eat_punct('}') {
+            if self.peek().is_none() {
+                return skip("expected `}`", self.span());
+            }
+            let (base, is_const) = self.specifiers(false)?;
+            loop {
+                let (name, ty) = self.declarator(base.clone(), is_const)?;
+                let Some((name, span)) = name else {
+                    return skip("fields without a name are not supported", self.span());
+                };
+                if self.at_punct(':') {
+                    return skip("bit-fields are not supported", span);
+                }
+                if self.at_punct('(') {
+                    return skip("function types are not supported", self.span());
+                }
+                fields.push((name, ty));
+                if !self.eat_punct(',') {
+                    break;
+                }
+            }
+            self.expect_punct(';')?;
+        }
+        Ok(fields)
+    }
+
+    fn enumerators(&mut self) -> Result<Vec<(String, i64)>> {
+        self.expect_punct('{')?;
+        let mut enumerators = Vec::new();
+        let mut next = 0;
+        while !self.eat_punct('}') {
+            let (name, span) = self.identifier()?;
+            let value = match self.eat_punct('=') {
+                true => self.enumerator_value()?,
+                false => next,
+            };
+            if i32::try_from(value).is_err() {
+                return skip(
+                    format!("the value of `{}` does not fit in an `int`", name),
+                    span,
+                );
+            }
+            self.enumerators.insert(name.clone(), value);
+            enumerators.push((name, value));
+            next = value + 1;
+            if !self.eat_punct(',') {
+                self.expect_punct('}')?;
+                break;
+            }
+        }
+        Ok(enumerators)
+    }
+
+    /// Reads the value given to an enumerator, which can be a number or an earlier enumerator
+    fn enumerator_value(&mut self) -> Result<i64> {
+        let start = self.span();
+        let negative = self.eat_punct('-');
+        let value = match self.peek() {
+            Some(TokenKind::Number(number)) => integer(number),
+            Some(TokenKind::Identifier(name)) => self.enumerators.get(name).copied(),
+            _ => None,
+        };
+        self.position += 1;
+        match (value, self.peek()) {
+            (Some(value), Some(TokenKind::Punct(',' | '}'))) => {
+                Ok(if negative { -value } else { value })
+            }
+            _ => skip(
+                "only numbers and earlier enumerators can be the values of enumerators",
+                start.to(self.previous_span()),
+            ),
+        }
+    }
+
+    /// Reads the pointers and the name of a declaration, where both can be left out
+    fn declarator(
+        &mut self,
+        base: CType,
+        is_const: bool,
+    ) -> Result<(Option<(String, Span)>, CType)> {
+        let mut ty = base;
+        let mut is_const = is_const;
+        loop {
+            if self.eat_punct('*') {
+                ty = CType::Pointer {
+                    pointee: Box::new(ty),
+                    is_const,
+                };
+                is_const = false;
+                continue;
+            }
+            match self.peek_word() {
+                // A `const` after a `*` makes the pointer itself `const`, which the pointer to it
+                // would point to
+                Some("const") => is_const = true,
+                Some("volatile" | "restrict" | "__restrict") => {}
+                _ => break,
+            }
+            self.position += 1;
+        }
+        if self.at_punct('(') {
+            return skip("pointers to functions are not supported", self.span());
+        }
+        let name = match self.peek_word() {
+            Some(word) => {
+                self.position += 1;
+                Some((word.to_string(), self.previous_span()))
+            }
+            None => None,
+        };
+        if self.at_punct('[') {
+            return skip("arrays are not supported", self.span());
+        }
+        Ok((name, ty))
+    }
+
+    /// Reads the parameters of a function, along with whether it is variadic
+    fn parameters(&mut self) -> Result<(Vec<Parameter>, bool)> {
+        self.expect_punct('(')?;
+        if self.at_word("void") && self.peek_nth(1) == Some(&TokenKind::Punct(')')) {
+            self.position += 2;
+            return Ok((Vec::new(), false));
+        }
+        let mut parameters = Vec::new();
+        if self.eat_punct(')') {
+            return Ok((parameters, false));
+        }
+        loop {
+            if self.peek() == Some(&TokenKind::Ellipsis) {
+                self.position += 1;
+                self.expect_punct(')')?;
+                return Ok((parameters, true));
+            }
+            let (base, is_const) = self.specifiers(false)?;
+            let (name, ty) = self.declarator(base, is_const)?;
+            if self.at_punct('(') {
+                return skip("function types are not supported", self.span());
+            }
+            parameters.push(Parameter {
+                name: name.map(|(name, _)| name),
+                ty,
+            });
+            if !self.eat_punct(',') {
+                self.expect_punct(')')?;
+                return Ok((parameters, false));
+            }
+        }
+    }
+}
+
+/// Gets the type named by keywords such as `unsigned long`, where `long` is 64 bits
+fn scalar(words: &[&str], span: Span) -> Result<CType> {
+    let has = |word| words.contains(&word);
+    let ty = if has("void") {
+        CType::Void
+    } else if has("short") || (has("long") && has("double")) {
+        return skip(
+            format!("`{}` has no Shark type of the same size", words.join(" ")),
+            span,
+        );
+    } else if has("char") {
+        match (has("signed"), has("unsigned")) {
+            (false, false) => CType::Char,
+            (_, false) => CType::Primitive("Int8"),
+            (_, true) => CType::Primitive("UInt8"),
+        }
+    } else if has("float") {
+        CType::Primitive("Float32")
+    } else if has("double") {
+        CType::Primitive("Float64")
+    } else if has("_Bool") || has("bool") {
+        CType::Primitive("Bool")
+    } else {
+        match (has("long"), has("unsigned")) {
+            (false, false) => CType::Primitive("Int32"),
+            (false, true) => CType::Primitive("UInt32"),
+            (true, false) => CType::Primitive("Int64"),
+            (true, true) => CType::Primitive("UInt64"),
+        }
+    };
+    Ok(ty)
+}
+
+/// Reads a C integer literal, which may have a suffix such as `u` or `L`
+fn integer(text: &str) -> Option<i64> {
+    let text = text.trim_end_matches(['u', 'U', 'l', 'L']);
+    if let Some(hex) = text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        return i64::from_str_radix(hex, 16).ok();
+    }
+    match text.len() > 1 && text.starts_with('0') {
+        true => i64::from_str_radix(&text[1..], 8).ok(),
+        false => text.parse().ok(),
+    }
+}
@@ -0,0 +1,207 @@
+use crate::generate;
+
+/// Generates the declarations for a header which must have nothing skipped
+fn bindings(header: &str) -> String {
+    let bindings = generate(header).expect("failed to read the header");
+    assert!(bindings.warnings.is_empty(), "{:?}", bindings.warnings);
+    bindings.code
+}
+
+/// Generates the declarations for a header, returning the message and label of every warning
+fn warnings(header: &str) -> Vec<(String, String)> {
+    let bindings = generate(header).expect("failed to read the header");
+    bindings
+        .warnings
+        .into_iter()
+        .map(|x| (x.message, x.labels[0].message.clone()))
+        .collect()
+}
+
+#[test]
+fn test_functions() {
+    let code = bindings(
+        "#include <stddef.h>
+        #define MAX(a, b) \\
+            ((a) > (b) ? (a) : (b))
+
+        // Functions taking and returning numbers
+        int add(int a, int b);
+        unsigned long long widen(unsigned x, signed char y, unsigned char z);
+        double scale(float value, long factor);
+        _Bool is_empty(void);
+        void reset();
+
+        /* Pointers and strings */
+        size_t length(const char *text);
+        const char *name(void);
+        char *buffer(char *into, const int *values, int **out);
+        void *data(const void *from, int const *const *table);
+        int printf(const char *format, ...);
+        int types(int ref, int);",
+    );
+    assert_eq!(
+        code,
+        "extern \"C\" fun add(a :: Int32, b :: Int32) :: Int32;
+extern \"C\" fun widen(x :: UInt32, y :: Int8, z :: UInt8) :: UInt64;
+extern \"C\" fun scale(value :: Float32, factor :: Int64) :: Float64;
+extern \"C\" fun is_empty() :: Bool;
+extern \"C\" fun reset();
+extern \"C\" fun length(text :: Str) :: UInt64;
+extern \"C\" fun name() :: Str;
+extern \"C\" fun buffer(into :: ptr mut Int8, values :: ptr Int32, out :: ptr mut ptr mut Int32) :: ptr mut Int8;
+extern \"C\" fun data(from :: ptr UInt8, table :: ptr ptr Int32) :: ptr mut UInt8;
+extern \"C\" fun printf(format :: Str, ...) :: Int32;
+extern \"C\" fun types(ref_ :: Int32, arg1 :: Int32) :: Int32;
+"
+    );
+}
+
+#[test]
+fn test_types() {
+    let code = bindings(
+        "#ifdef __cplusplus
+        extern \"C\" {
+        #endif
+
+        typedef struct {
+            int32_t x, y;
+            const char *label;
+        } Point;
+
+        struct node {
+            struct node *next;
+            Point point;
+        };
+        typedef struct node Node;
+
+        typedef struct Counter Counter;
+        struct Hidden;
+        typedef unsigned int id_t;
+        typedef Point *PointRef;
+
+        enum Color { RED, GREEN = 5, BLUE, BLACK = -1, WHITE = 0x10, GREY = GREEN };
+        typedef enum { SMALL = 1u, LARGE } Size;
+
+        Counter *counter_new(id_t id);
+        void move(PointRef point, const Node *node, enum Color color, Size size);
+        void hide(struct Hidden *hidden, FILE *file, struct Unknown *unknown);
+
+        #ifdef __cplusplus
+        }
+        #endif",
+    );
+    assert_eq!(
+        code,
+        "extern \"C\" type FILE;
+extern \"C\" type Unknown;
+
+extern \"C\" type Point {
+    x :: Int32,
+    y :: Int32,
+    label :: ptr Int8,
+}
+
+extern \"C\" type Node {
+    next :: ptr mut Node,
+    point :: Point,
+}
+
+extern \"C\" type Counter;
+extern \"C\" type Hidden;
+
+const RED :: Int32 = 0;
+const GREEN :: Int32 = 5;
+const BLUE :: Int32 = 6;
+const BLACK :: Int32 = -1;
+const WHITE :: Int32 = 16;
+const GREY :: Int32 = 5;
+const SMALL :: Int32 = 1;
+const LARGE :: Int32 = 2;
+
+extern \"C\" fun counter_new(id :: UInt32) :: ptr mut Counter;
+extern \"C\" fun move(point :: ptr mut Point, node :: ptr Node, color :: Int32, size :: Int32);
+extern \"C\" fun hide(hidden :: ptr mut Hidden, file :: ptr mut FILE, unknown :: ptr mut Unknown);
+"
+    );
+}
+
+#[test]
+fn test_skipped() {
+    let result = warnings(
+        "union Value { int i; float f; };
+        void each(void (*callback)(int));
+        int fill(int values[4]);
+        short small(void);
+        static inline int twice(int x) { return x * 2; }
+        int ref(int x);
+        struct Flags { unsigned ready : 1; };
+        struct Outer { struct Inner { int x; } inner; };
+        enum Mask { READ = 1 << 0 };
+        int counter;
+        struct Opaque;
+        void take(struct Opaque opaque);
+        void open(FILE file);
+        int fine(void);",
+    );
+    let expected = [
+        ("skipped `Value`", "unions are not supported"),
+        ("skipped `each`", "pointers to functions are not supported"),
+        ("skipped `fill`", "arrays are not supported"),
+        (
+            "skipped `small`",
+            "`short` has no Shark type of the same size",
+        ),
+        (
+            "skipped `twice`",
+            "functions defined by the header are not supported",
+        ),
+        ("skipped `ref`", "the name is a keyword of Shark"),
+        ("skipped `Flags`", "bit-fields are not supported"),
+        ("skipped `Outer`", "nested definitions are not supported"),
+        (
+            "skipped `Mask`",
+            "only numbers and earlier enumerators can be the values of enumerators",
+        ),
+        ("skipped `counter`", "variables are not supported"),
+        (
+            "skipped `take`",
+            "`struct Opaque` has no fields in the header, so it can only be used behind a pointer",
+        ),
+        (
+            "skipped `open`",
+            "`FILE` is not declared by the header, so it can only be used behind a pointer",
+        ),
+    ];
+    let expected: Vec<_> = expected
+        .iter()
+        .map(|(x, y)| (x.to_string(), y.to_string()))
+        .collect();
+    assert_eq!(result, expected);
+
+    let bindings = generate("struct Opaque;\nvoid take(struct Opaque opaque);\nint fine(void);")
+        .expect("failed to read the header");
+    assert_eq!(
+        bindings.code,
+        "extern \"C\" type Opaque;\n\nextern \"C\" fun fine() :: Int32;\n"
+    );
+
+    let error = generate("int add(int a, int b); /* never closed").unwrap_err();
+    assert_eq!(error.message, "unterminated comment");
+}
+
+#[test]
+fn test_bindings_check() {
+    let code = bindings(
+        "typedef struct Counter Counter;
+        typedef struct { int x; long long y; unsigned char flags; } Point;
+        enum Mode { FAST, SLOW };
+
+        Counter *counter_new(const char *name, enum Mode mode);
+        void counter_add(Counter *counter, Point point, ...);
+        Point counter_get(const Counter *counter, void *data);
+        const char *counter_name(const Counter *counter);",
+    );
+    let module = shark_parse::parse(None, &code).expect("failed to parse the bindings");
+    let (_, diagnostics) = shark_sema::check_module(&module);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+}
@@ -0,0 +1,1099 @@
+//! Checks the ownership rules within a function body. Every value has one owner: values which are
+//! not copied, such as instances of a `type`, move to wherever they are used and their old owner
+//! can not be used again until it is assigned. A `ref` borrows a value without taking ownership,
+//! and while a `ref mut` is alive nothing else can use the value it borrows, while a `ref` stops
+//! the value from being changed. A borrow lives until the last use of the binding holding it, and
+//! can not outlive the value it borrows. Raw `ptr`s are not checked at all, which is why they can
+//! only be dereferenced within `unsafe` code
+//!
+//! The body is walked twice. The first walk only finds the last use of every binding, which the
+//! second walk needs to know which borrows are still alive
+
+use std::collections::HashMap;
+
+use shark_core::{diagnostic::Diagnostic, source::Span, symbol::Symbol};
+use shark_lower::lower::free_names;
+use shark_parse::ast::{
+    Block, Expr, ExprKind, Function, Pattern, PatternKind, ReferenceKind, StatementKind,
+    UnaryOperator,
+};
+use shark_sema::{ty::AdtId, ModuleDefs};
+use shark_typeck::{ty::Ty, TypeckResults};
+
+/// Identifies a binding within the function being checked
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct LocalId(usize);
+
+#[derive(Debug, Clone)]
+struct Local {
+    name: Symbol,
+    mutable: bool,
+    /// Where the binding is declared
+    span: Span,
+    /// How many loops deep the binding is declared
+    loop_depth: usize,
+    initialized: bool,
+    /// Where the value was moved out, if it has been
+    moved: Option<Span>,
+    /// Whether the binding is the copy of a value a closure captured, which is never mutable
+    captured: bool,
+}
+
+/// A borrow of a binding by a `ref` or a `ref mut`
+#[derive(Debug, Clone, Copy)]
+struct Loan {
+    local: LocalId,
+    mutable: bool,
+    span: Span,
+    /// The binding the reference was stored in. A borrow without one only lives until the end of
+    /// the statement which made it
+    holder: Option<LocalId>,
+}
+
+/// An expression which names a value in memory, rather than producing a temporary one
+#[derive(Debug, Clone, Copy)]
+struct Place {
+    /// The binding the place is part of, or the reference it is reached through
+    root: LocalId,
+    /// Whether the place is a field of the binding rather than the whole binding
+    projected: bool,
+    /// The kind of the reference the place is reached through, and whether it is `mut`
+    through: Option<(ReferenceKind, bool)>,
+}
+
+/// The moves which happened along one path through the code
+type MoveState = Vec<Option<Span>>;
+
+/// The paths through the code which are being checked one at a time
+struct Branches {
+    before: MoveState,
+    merged: MoveState,
+    /// Whether the code had already returned before the paths split
+    diverged: bool,
+    all_diverge: bool,
+}
+
+pub struct OwnershipChecker<'check> {
+    defs: &'check ModuleDefs,
+    types: &'check TypeckResults,
+    locals: Vec<Local>,
+    scopes: Vec<Vec<(Symbol, LocalId)>>,
+    loans: Vec<Loan>,
+    /// The byte offset just past the last use of every binding
+    last_use: Vec<usize>,
+    /// The spans of the loops around the expression being checked, outermost first
+    loops: Vec<Span>,
+    /// Whether the expression being checked is within an `unsafe` block or function
+    unsafe_depth: usize,
+    /// Whether the path being checked has returned, so its moves do not matter afterwards
+    diverged: bool,
+    /// Whether this walk reports what it finds, which only the second walk does
+    report: bool,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<'check> OwnershipChecker<'check> {
+    pub fn new(defs: &'check ModuleDefs, types: &'check TypeckResults) -> Self {
+        Self {
+            defs,
+            types,
+            locals: Vec::new(),
+            scopes: Vec::new(),
+            loans: Vec::new(),
+            last_use: Vec::new(),
+            loops: Vec::new(),
+            unsafe_depth: 0,
+            diverged: false,
+            report: false,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    pub fn check_function(&mut self, function: &Function) {
+        let Some(body) = &function.body else {
+            return;
+        };
+        self.last_use = Vec::new();
+        for report in [false, true] {
+            self.report = report;
+            self.locals.clear();
+            self.loans.clear();
+            self.diverged = false;
+            self.unsafe_depth = usize::from(function.is_unsafe);
+
+            let parameters = function
+                .parameters
+                .iter()
+                .map(|x| {
+                    (
+                        x.name.symbol,
+                        self.declare_local(x.name.symbol, x.mutable, x.name.span, true),
+                    )
+                })
+                .collect();
+            self.scopes.push(parameters);
+            self.check_block_with(body, |checker, tail| {
+                if let Some(tail) = tail {
+                    checker.consume(tail);
+                    checker.check_escape(tail, "return");
+                }
+            });
+            self.scopes.pop();
+        }
+    }
+
+    fn error(&mut self, diagnostic: Diagnostic) {
+        let duplicate = self.diagnostics.iter().any(|x| {
+            x.message == diagnostic.message && x.labels.first() == diagnostic.labels.first()
+        });
+        // Loop bodies are checked twice, which would report the same mistakes twice
+        if self.report && !duplicate {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    fn declare_local(
+        &mut self,
+        name: Symbol,
+        mutable: bool,
+        span: Span,
+        initialized: bool,
+    ) -> LocalId {
+        let id = LocalId(self.locals.len());
+        self.locals.push(Local {
+            name,
+            mutable,
+            span,
+            loop_depth: self.loops.len(),
+            initialized,
+            moved: None,
+            captured: false,
+        });
+        if self.last_use.len() <= id.0 {
+            self.last_use.push(0);
+        }
+        id
+    }
+
+    fn declare(&mut self, name: Symbol, mutable: bool, span: Span, initialized: bool) -> LocalId {
+        let id = self.declare_local(name, mutable, span, initialized);
+        self.scopes
+            .last_mut()
+            .expect("there is always a scope within a function")
+            .push((name, id));
+        id
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<LocalId> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|x| x.iter().rev().find(|(local, _)| *local == name))
+            .map(|(_, id)| *id)
+    }
+
+    fn local(&self, id: LocalId) -> &Local {
+        &self.locals[id.0]
+    }
+
+    /// Records a use of a binding during the first walk. A binding declared outside a loop and used
+    /// within it is used until the end of the loop, since the next iteration uses it again
+    fn record_use(&mut self, id: LocalId, span: Span) {
+        if self.report {
+            return;
+        }
+        let loop_depth = self.local(id).loop_depth;
+        let end = match self.loops.get(loop_depth) {
+            Some(outer) => outer.end.max(span.end),
+            None => span.end,
+        };
+        self.last_use[id.0] = self.last_use[id.0].max(end);
+    }
+
+    /// Checks if a borrow is still alive at a byte offset
+    fn is_live(&self, loan: &Loan, offset: usize) -> bool {
+        loan.holder
+            .is_none_or(|holder| self.last_use[holder.0] > offset)
+    }
+
+    fn live_loans(&self, local: LocalId, offset: usize) -> Vec<Loan> {
+        self.loans
+            .iter()
+            .filter(|x| x.local == local && self.is_live(x, offset))
+            .copied()
+            .collect()
+    }
+
+    fn ty(&self, expr: &Expr) -> Option<&'check Ty> {
+        self.types.type_of(expr.id)
+    }
+
+    /// Checks if values of a type are copied when used rather than moved. References are copied
+    /// too, a `ref mut` is borrowed again each time it is used
+    fn is_copy(&self, ty: Option<&Ty>) -> bool {
+        match ty {
+            None => true,
+            Some(Ty::Adt(..) | Ty::Param(_) | Ty::Array(..)) => false,
+            Some(Ty::Tuple(elements)) => elements.iter().all(|x| self.is_copy(Some(x))),
+            Some(_) => true,
+        }
+    }
+
+    /// Checks if a value of a type can hold a `ref`, so that storing it keeps borrows alive
+    fn holds_reference(&self, ty: &Ty, visited: &mut Vec<AdtId>) -> bool {
+        match ty {
+            Ty::Reference {
+                kind: ReferenceKind::Ref,
+                ..
+            } => true,
+            Ty::Tuple(elements) => elements.iter().any(|x| self.holds_reference(x, visited)),
+            Ty::Array(element, _) => self.holds_reference(element, visited),
+            // What a closure captured is not part of its type
+            Ty::Function { .. } => true,
+            Ty::Adt(id, arguments) => {
+                if arguments.iter().any(|x| self.holds_reference(x, visited)) {
+                    return true;
+                }
+                if visited.contains(id) {
+                    return false;
+                }
+                visited.push(*id);
+                let adt = self.defs.types.adt(*id);
+                adt.fields()
+                    .iter()
+                    .map(|x| &x.ty)
+                    .chain(adt.variants().iter().flat_map(|x| &x.payload))
+                    .any(|x| self.holds_reference(&Ty::from_type(x, &HashMap::new()), visited))
+            }
+            _ => false,
+        }
+    }
+
+    fn type_name(&self, ty: Option<&Ty>) -> String {
+        match ty.and_then(Ty::to_type) {
+            Some(ty) => self.defs.types.type_name(&ty),
+            None => "{unknown}".to_string(),
+        }
+    }
+
+    fn moves(&self) -> MoveState {
+        self.locals.iter().map(|x| x.moved).collect()
+    }
+
+    fn restore(&mut self, state: &MoveState) {
+        for (local, moved) in self.locals.iter_mut().zip(state) {
+            local.moved = *moved;
+        }
+    }
+
+    /// Starts checking paths through the code which are taken one at a time, such as the branches
+    /// of an `if`. Afterwards a value counts as moved if any path which carries on moved it
+    fn start_branches(&self) -> Branches {
+        let before = self.moves();
+        Branches {
+            merged: before.clone(),
+            before,
+            diverged: self.diverged,
+            all_diverge: true,
+        }
+    }
+
+    fn start_branch(&mut self, branches: &Branches) {
+        self.restore(&branches.before);
+        self.diverged = false;
+    }
+
+    fn end_branch(&mut self, branches: &mut Branches) {
+        if self.diverged {
+            return;
+        }
+        branches.all_diverge = false;
+        for (merged, moved) in branches.merged.iter_mut().zip(self.moves()) {
+            *merged = merged.or(moved);
+        }
+    }
+
+    fn finish_branches(&mut self, branches: Branches) {
+        self.restore(&branches.merged);
+        self.diverged = branches.diverged || branches.all_diverge;
+    }
+
+    fn check_block(&mut self, block: &Block) {
+        self.check_block_with(block, |checker, tail| {
+            if let Some(tail) = tail {
+                checker.consume(tail);
+            }
+        });
+    }
+
+    /// Checks the statements of a block, leaving its tail to `tail` while the bindings of the block
+    /// are still in scope
+    fn check_block_with(&mut self, block: &Block, tail: impl FnOnce(&mut Self, Option<&Expr>)) {
+        self.scopes.push(Vec::new());
+        for statement in &block.statements {
+            match &statement.kind {
+                StatementKind::Let(let_statement) => {
+                    let loans = self.loans.len();
+                    if let Some(value) = &let_statement.value {
+                        self.check_expr(value);
+                    }
+                    let name = let_statement.name;
+                    let local = self.declare(
+                        name.symbol,
+                        let_statement.mutable,
+                        name.span,
+                        let_statement.value.is_some(),
+                    );
+                    let ty = self.types.type_of(statement.id);
+                    if ty.is_some_and(|x| self.holds_reference(x, &mut Vec::new())) {
+                        self.hold_loans(loans, local);
+                    }
+                }
+                StatementKind::Expr(expr) => self.check_expr(expr),
+                StatementKind::Error => {}
+            }
+            // Borrows which were not stored anywhere end with their statement
+            self.loans.retain(|x| x.holder.is_some());
+        }
+        tail(self, block.tail.as_deref());
+        self.end_scope(block);
+    }
+
+    /// Makes the binding hold every borrow made since there were `start` of them
+    fn hold_loans(&mut self, start: usize, holder: LocalId) {
+        for loan in &mut self.loans[start..] {
+            loan.holder.get_or_insert(holder);
+        }
+    }
+
+    /// Drops the bindings of a block, reporting borrows of them which are used afterwards
+    fn end_scope(&mut self, block: &Block) {
+        let scope = self
+            .scopes
+            .pop()
+            .expect("the scope was pushed by the block");
+        let end = Span::new(block.span.end.saturating_sub(1), block.span.end);
+        let dropped: Vec<LocalId> = scope.iter().map(|(_, id)| *id).collect();
+        for loan in self.loans.clone() {
+            let Some(holder) = loan.holder else {
+                continue;
+            };
+            if dropped.contains(&loan.local)
+                && !dropped.contains(&holder)
+                && self.last_use[holder.0] > block.span.end
+            {
+                let name = self.local(loan.local).name;
+                self.error(
+                    Diagnostic::error(format!("`{}` does not live long enough", name))
+                        .with_primary(loan.span, "borrowed value does not live long enough")
+                        .with_secondary(
+                            end,
+                            format!("`{}` dropped here while still borrowed", name),
+                        ),
+                );
+            }
+        }
+        self.loans.retain(|x| {
+            !dropped.contains(&x.local) && !x.holder.is_some_and(|x| dropped.contains(&x))
+        });
+    }
+
+    /// Finds the place an expression names, checking the dereferences along the way
+    fn place(&mut self, expr: &Expr) -> Option<Place> {
+        match &expr.kind {
+            ExprKind::Name(name) => self.lookup(name.symbol).map(|root| Place {
+                root,
+                projected: false,
+                through: None,
+            }),
+            ExprKind::Field { object, .. } => {
+                let mut place = self.place(object)?;
+                place.projected = true;
+                // Fields are reached through references automatically
+                if let Some(Ty::Reference { kind, mutable, .. }) = self.ty(object) {
+                    place.through = Some((*kind, *mutable));
+                }
+                Some(place)
+            }
+            ExprKind::Unary {
+                operator: UnaryOperator::Deref,
+                operand,
+            } => {
+                let mut place = self.place(operand)?;
+                if let Some(Ty::Reference { kind, mutable, .. }) = self.ty(operand) {
+                    self.check_deref(*kind, expr.span);
+                    place.through = Some((*kind, *mutable));
+                }
+                place.projected = true;
+                Some(place)
+            }
+            _ => None,
+        }
+    }
+
+    fn check_deref(&mut self, kind: ReferenceKind, span: Span) {
+        if kind == ReferenceKind::Ptr && self.unsafe_depth == 0 {
+            self.error(
+                Diagnostic::error(
+                    "dereference of raw pointer is unsafe and requires an `unsafe` block or function",
+                )
+                .with_primary(span, "dereference of raw pointer"),
+            );
+        }
+    }
+
+    /// Reads a binding without moving it
+    fn read(&mut self, id: LocalId, span: Span) {
+        self.record_use(id, span);
+        let local = self.local(id).clone();
+        if let Some(moved) = local.moved {
+            self.error(
+                Diagnostic::error(format!("use of moved value `{}`", local.name))
+                    .with_primary(span, "value used here after move")
+                    .with_secondary(moved, "value moved here"),
+            );
+            return;
+        }
+        if let Some(loan) = self
+            .live_loans(id, span.start)
+            .into_iter()
+            .find(|x| x.mutable)
+        {
+            self.error(
+                Diagnostic::error(format!(
+                    "cannot use `{}` because it is mutably borrowed",
+                    local.name
+                ))
+                .with_primary(span, format!("use of borrowed `{}`", local.name))
+                .with_secondary(
+                    loan.span,
+                    format!("`{}` is borrowed as mutable here", local.name),
+                ),
+            );
+        }
+    }
+
+    /// Moves the value out of a binding, which can not be used again until it is assigned
+    fn move_out(&mut self, id: LocalId, span: Span, ty: Option<&Ty>) {
+        self.record_use(id, span);
+        let local = self.local(id).clone();
+        if let Some(moved) = local.moved {
+            let label = match moved == span {
+                true => "value moved here, in the previous iteration of the loop",
+                false => "value moved here",
+            };
+            self.error(
+                Diagnostic::error(format!("use of moved value `{}`", local.name))
+                    .with_primary(span, "value used here after move")
+                    .with_secondary(moved, label)
+                    .with_note(format!(
+                        "`{}` has type `{}`, which is moved rather than copied",
+                        local.name,
+                        self.type_name(ty)
+                    )),
+            );
+            return;
+        }
+        if let Some(loan) = self.live_loans(id, span.start).first() {
+            self.error(
+                Diagnostic::error(format!(
+                    "cannot move out of `{}` because it is borrowed",
+                    local.name
+                ))
+                .with_primary(span, format!("move out of `{}` occurs here", local.name))
+                .with_secondary(loan.span, format!("`{}` is borrowed here", local.name)),
+            );
+        }
+        if !self.diverged {
+            self.locals[id.0].moved = Some(span);
+        }
+    }
+
+    /// Borrows a binding for as long as the reference is alive
+    fn borrow(&mut self, id: LocalId, mutable: bool, span: Span) {
+        self.record_use(id, span);
+        let local = self.local(id).clone();
+        if let Some(moved) = local.moved {
+            self.error(
+                Diagnostic::error(format!("borrow of moved value `{}`", local.name))
+                    .with_primary(span, "value borrowed here after move")
+                    .with_secondary(moved, "value moved here"),
+            );
+            return;
+        }
+        if mutable && !local.mutable {
+            let diagnostic = Diagnostic::error(format!(
+                "cannot borrow `{}` as mutable, as it is not declared as mutable",
+                local.name
+            ))
+            .with_primary(span, "cannot borrow as mutable");
+            self.error(declare_mutable(diagnostic, &local));
+        }
+        let conflict = self
+            .live_loans(id, span.start)
+            .into_iter()
+            .find(|x| mutable || x.mutable);
+        if let Some(loan) = conflict {
+            let (message, label, previous) = match (mutable, loan.mutable) {
+                (true, true) => (
+                    format!(
+                        "cannot borrow `{}` as mutable more than once at a time",
+                        local.name
+                    ),
+                    "second mutable borrow occurs here",
+                    "first mutable borrow occurs here",
+                ),
+                (true, false) => (
+                    format!(
+                        "cannot borrow `{}` as mutable because it is also borrowed as immutable",
+                        local.name
+                    ),
+                    "mutable borrow occurs here",
+                    "immutable borrow occurs here",
+                ),
+                _ => (
+                    format!(
+                        "cannot borrow `{}` as immutable because it is also borrowed as mutable",
+                        local.name
+                    ),
+                    "immutable borrow occurs here",
+                    "mutable borrow occurs here",
+                ),
+            };
+            self.error(
+                Diagnostic::error(message)
+                    .with_primary(span, label)
+                    .with_secondary(loan.span, previous),
+            );
+        }
+        self.loans.push(Loan {
+            local: id,
+            mutable,
+            span,
+            holder: None,
+        });
+    }
+
+    /// Uses the value of an expression, moving it out of the place it names if it is not copied
+    fn consume(&mut self, expr: &Expr) {
+        let Some(place) = self.place(expr) else {
+            match &expr.kind {
+                ExprKind::Name(_) => {}
+                ExprKind::Field { object, .. } => self.consume(object),
+                ExprKind::Unary {
+                    operator: UnaryOperator::Deref,
+                    operand,
+                } => {
+                    if let Some(Ty::Reference { kind, .. }) = self.ty(operand) {
+                        self.check_deref(*kind, expr.span);
+                    }
+                    self.consume(operand);
+                }
+                _ => self.check_expr(expr),
+            }
+            return;
+        };
+        let ty = self.ty(expr);
+        let copy = self.is_copy(ty);
+        match place.through {
+            Some(_) => {
+                self.read(place.root, expr.span);
+                if !copy {
+                    self.error(
+                        Diagnostic::error("cannot move out of a value behind a reference")
+                            .with_primary(expr.span, "move occurs here")
+                            .with_note(format!(
+                                "`{}` is moved rather than copied, consider borrowing it with `ref`",
+                                self.type_name(ty)
+                            )),
+                    );
+                }
+            }
+            None if copy => self.read(place.root, expr.span),
+            None => self.move_out(place.root, expr.span, ty),
+        }
+    }
+
+    /// Uses the value of an expression without moving it, such as an operand of a comparison
+    fn inspect(&mut self, expr: &Expr) {
+        match self.place(expr) {
+            Some(place) => self.read(place.root, expr.span),
+            None => self.check_expr(expr),
+        }
+    }
+
+    fn check_reference(&mut self, kind: ReferenceKind, mutable: bool, operand: &Expr, span: Span) {
+        let Some(place) = self.place(operand) else {
+            self.check_expr(operand);
+            return;
+        };
+        match place.through {
+            Some((through_kind, through_mutable)) => {
+                self.read(place.root, operand.span);
+                if mutable && !through_mutable {
+                    self.error(
+                        Diagnostic::error(format!(
+                            "cannot borrow as mutable through a `{}` which is not `mut`",
+                            through_kind
+                        ))
+                        .with_primary(span, "cannot borrow as mutable"),
+                    );
+                }
+            }
+            // Raw pointers are not tracked, only made sure to be allowed to change the value
+            None if kind == ReferenceKind::Ptr => {
+                self.read(place.root, operand.span);
+                let local = self.local(place.root).clone();
+                if mutable && !local.mutable {
+                    let diagnostic = Diagnostic::error(format!(
+                        "cannot take a `ptr mut` of `{}`, as it is not declared as mutable",
+                        local.name
+                    ))
+                    .with_primary(span, "cannot take a mutable pointer");
+                    self.error(declare_mutable(diagnostic, &local));
+                }
+            }
+            None => self.borrow(place.root, mutable, span),
+        }
+    }
+
+    fn check_assign(&mut self, target: &Expr, compound: bool) {
+        let Some(place) = self.place(target) else {
+            if let ExprKind::Name(name) = &target.kind {
+                if let Some(constant) = self.defs.constants.lookup(name.symbol) {
+                    self.error(
+                        Diagnostic::error(format!(
+                            "cannot assign to the constant `{}`",
+                            name.symbol
+                        ))
+                        .with_primary(target.span, "cannot assign")
+                        .with_secondary(constant.name.span, "the constant is declared here"),
+                    );
+                }
+            }
+            self.check_expr(target);
+            return;
+        };
+        if let Some((kind, mutable)) = place.through {
+            self.read(place.root, target.span);
+            if !mutable {
+                self.error(
+                    Diagnostic::error(format!(
+                        "cannot assign through a `{}` which is not `mut`",
+                        kind
+                    ))
+                    .with_primary(target.span, "cannot assign through this reference"),
+                );
+            }
+            return;
+        }
+
+        self.record_use(place.root, target.span);
+        let local = self.local(place.root).clone();
+        if compound || place.projected {
+            self.read(place.root, target.span);
+        }
+        if let Some(loan) = self.live_loans(place.root, target.span.start).first() {
+            self.error(
+                Diagnostic::error(format!(
+                    "cannot assign to `{}` because it is borrowed",
+                    local.name
+                ))
+                .with_primary(target.span, format!("`{}` is assigned to here", local.name))
+                .with_secondary(loan.span, format!("`{}` is borrowed here", local.name)),
+            );
+        }
+        if !local.mutable && (local.initialized || place.projected) {
+            let message = match place.projected {
+                true => format!(
+                    "cannot assign to a part of `{}`, as it is not declared as mutable",
+                    local.name
+                ),
+                false if local.captured => {
+                    format!(
+                        "cannot assign to `{}`, which the closure captured",
+                        local.name
+                    )
+                }
+                false => format!("cannot assign twice to immutable binding `{}`", local.name),
+            };
+            let diagnostic = Diagnostic::error(message).with_primary(target.span, "cannot assign");
+            self.error(declare_mutable(diagnostic, &local));
+        }
+        if !place.projected {
+            let local = &mut self.locals[place.root.0];
+            local.initialized = true;
+            local.moved = None;
+        }
+    }
+
+    fn check_call(&mut self, callee: &Expr, arguments: &[Expr], span: Span) {
+        // The values after the parameters of a variadic function are only shown, never moved
+        let mut moved = arguments.len();
+        match &callee.kind {
+            ExprKind::Name(name) if self.lookup(name.symbol).is_none() => {
+                let sig = self.defs.functions.lookup(name.symbol);
+                if let Some(sig) = sig.filter(|x| x.is_variadic) {
+                    moved = sig.parameters.len().min(moved);
+                }
+                if sig.is_some_and(|x| x.is_unsafe) && self.unsafe_depth == 0 {
+                    self.error(
+                        Diagnostic::error(format!(
+                            "call to unsafe function `{}` requires an `unsafe` block or function",
+                            name.symbol
+                        ))
+                        .with_primary(span, "call to unsafe function"),
+                    );
+                }
+            }
+            // The object of a method call is borrowed rather than moved
+            ExprKind::Field { object, .. } => self.inspect(object),
+            _ => self.consume(callee),
+        }
+        for argument in &arguments[..moved] {
+            self.consume(argument);
+        }
+        for argument in &arguments[moved..] {
+            self.inspect(argument);
+        }
+    }
+
+    /// Checks that a value leaving the function does not borrow anything the function owns. The
+    /// verb is how the value leaves, either `return` or `yield`, since a generator can be dropped
+    /// while the values it yielded are still in use
+    fn check_escape(&mut self, value: &Expr, verb: &str) {
+        match &value.kind {
+            ExprKind::Reference {
+                kind: ReferenceKind::Ref,
+                operand,
+                ..
+            } => match self.place(operand) {
+                Some(place) if place.through.is_none() => {
+                    let name = self.local(place.root).name;
+                    self.error(
+                        Diagnostic::error(format!(
+                            "cannot {} a reference to local `{}`",
+                            verb, name
+                        ))
+                        .with_primary(
+                            value.span,
+                            format!(
+                                "{}s a reference to data owned by the current function",
+                                verb
+                            ),
+                        ),
+                    );
+                }
+                Some(_) => {}
+                None => self.error(
+                    Diagnostic::error(format!("cannot {} a reference to a temporary value", verb))
+                        .with_primary(
+                            value.span,
+                            format!(
+                                "{}s a reference to data owned by the current function",
+                                verb
+                            ),
+                        ),
+                ),
+            },
+            ExprKind::Name(name) => {
+                let Some(holder) = self.lookup(name.symbol) else {
+                    return;
+                };
+                let loan = self
+                    .loans
+                    .iter()
+                    .find(|x| x.holder == Some(holder))
+                    .copied();
+                if let Some(loan) = loan {
+                    let borrowed = self.local(loan.local).name;
+                    self.error(
+                        Diagnostic::error(format!(
+                            "cannot {} `{}`, which borrows the local `{}`",
+                            verb, name.symbol, borrowed
+                        ))
+                        .with_primary(
+                            value.span,
+                            format!(
+                                "{}s a value referencing data owned by the current function",
+                                verb
+                            ),
+                        )
+                        .with_secondary(loan.span, format!("`{}` is borrowed here", borrowed)),
+                    );
+                }
+            }
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => {
+                if let Some(tail) = &block.tail {
+                    self.check_escape(tail, verb);
+                }
+            }
+            ExprKind::Closure(function) => {
+                for name in free_names(function) {
+                    self.check_escape(name, verb);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Checks a closure, which copies the values it captures when it is made, and then its body as
+    /// a function of its own. A closure holds the borrows of the values it captures
+    fn check_closure(&mut self, function: &Function) {
+        let Some(body) = &function.body else {
+            return;
+        };
+        let loans = self.loans.len();
+        let mut captures = Vec::new();
+        for name_expr in free_names(function) {
+            let ExprKind::Name(name) = name_expr.kind else {
+                continue;
+            };
+            let Some(id) = self.lookup(name.symbol) else {
+                continue;
+            };
+            let ty = self.ty(name_expr);
+            if !self.is_copy(ty) {
+                self.error(
+                    Diagnostic::error(format!(
+                        "cannot capture `{}`, which is moved rather than copied",
+                        name.symbol
+                    ))
+                    .with_primary(
+                        name_expr.span,
+                        format!("`{}` has type `{}`", name.symbol, self.type_name(ty)),
+                    )
+                    .with_note("closures capture copies of values, consider capturing a `ref`"),
+                );
+            }
+            self.read(id, name_expr.span);
+            let held: Vec<Loan> = self.loans[..loans]
+                .iter()
+                .filter(|x| x.holder == Some(id))
+                .map(|x| Loan { holder: None, ..*x })
+                .collect();
+            self.loans.extend(held);
+            captures.push(name);
+        }
+
+        let scopes = std::mem::take(&mut self.scopes);
+        let outer_loans = std::mem::take(&mut self.loans);
+        let loops = std::mem::take(&mut self.loops);
+        let diverged = std::mem::replace(&mut self.diverged, false);
+        let unsafe_depth =
+            std::mem::replace(&mut self.unsafe_depth, usize::from(function.is_unsafe));
+
+        let mut scope: Vec<(Symbol, LocalId)> = function
+            .parameters
+            .iter()
+            .map(|x| {
+                (
+                    x.name.symbol,
+                    self.declare_local(x.name.symbol, x.mutable, x.name.span, true),
+                )
+            })
+            .collect();
+        for name in captures {
+            let id = self.declare_local(name.symbol, false, function.name.span, true);
+            self.locals[id.0].captured = true;
+            scope.push((name.symbol, id));
+        }
+        self.scopes.push(scope);
+        self.check_block_with(body, |checker, tail| {
+            if let Some(tail) = tail {
+                checker.consume(tail);
+                checker.check_escape(tail, "return");
+            }
+        });
+
+        self.scopes = scopes;
+        self.loans = outer_loans;
+        self.loops = loops;
+        self.diverged = diverged;
+        self.unsafe_depth = unsafe_depth;
+    }
+
+    fn check_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Literal(_) | ExprKind::Path(_) | ExprKind::Error => {}
+            ExprKind::Name(_) | ExprKind::Field { .. } => self.consume(expr),
+            ExprKind::Unary {
+                operator: UnaryOperator::Deref,
+                ..
+            } => self.consume(expr),
+            ExprKind::Unary { operand, .. } => self.consume(operand),
+            ExprKind::Reference {
+                kind,
+                mutable,
+                operand,
+            } => self.check_reference(*kind, *mutable, operand, expr.span),
+            ExprKind::StructLiteral { fields, .. } => {
+                for field in fields {
+                    self.consume(&field.value);
+                }
+            }
+            ExprKind::Binary {
+                operator,
+                left,
+                right,
+            } if operator.is_comparison() => {
+                self.inspect(left);
+                self.inspect(right);
+            }
+            ExprKind::Binary { left, right, .. } => {
+                self.consume(left);
+                self.consume(right);
+            }
+            ExprKind::Assign {
+                operator,
+                target,
+                value,
+            } => {
+                let loans = self.loans.len();
+                self.consume(value);
+                self.check_assign(target, operator.is_some());
+                let holder = match &target.kind {
+                    ExprKind::Name(name) => self.lookup(name.symbol),
+                    _ => None,
+                };
+                let holds = self
+                    .ty(target)
+                    .is_some_and(|x| self.holds_reference(x, &mut Vec::new()));
+                if let (Some(holder), true) = (holder, holds) {
+                    self.hold_loans(loans, holder);
+                }
+            }
+            ExprKind::Tuple(elements) | ExprKind::Array(elements) => {
+                for element in elements {
+                    self.consume(element);
+                }
+            }
+            ExprKind::Repeat { value, .. } => {
+                self.consume(value);
+                let ty = self.ty(value);
+                if !self.is_copy(ty) {
+                    let note = format!(
+                        "`{}` is moved rather than copied, consider writing out each value",
+                        self.type_name(ty)
+                    );
+                    self.error(
+                        Diagnostic::error(
+                            "cannot repeat a value which is moved rather than copied",
+                        )
+                        .with_primary(value.span, "repeated here")
+                        .with_note(note),
+                    );
+                }
+            }
+            ExprKind::Call { callee, arguments } => self.check_call(callee, arguments, expr.span),
+            ExprKind::Block(block) => self.check_block(block),
+            ExprKind::Unsafe(block) => {
+                self.unsafe_depth += 1;
+                self.check_block(block);
+                self.unsafe_depth -= 1;
+            }
+            ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.consume(condition);
+                let mut branches = self.start_branches();
+                self.start_branch(&branches);
+                self.check_block(then_branch);
+                self.end_branch(&mut branches);
+                self.start_branch(&branches);
+                if let Some(else_branch) = else_branch {
+                    self.consume(else_branch);
+                }
+                self.end_branch(&mut branches);
+                self.finish_branches(branches);
+            }
+            ExprKind::For {
+                pattern,
+                iterable,
+                body,
+                ..
+            } => {
+                self.consume(iterable);
+                self.loops.push(expr.span);
+                // The second time through finds values moved by the previous iteration
+                for _ in 0..2 {
+                    self.scopes.push(Vec::new());
+                    self.declare_pattern(pattern);
+                    self.check_block(body);
+                    self.scopes.pop();
+                }
+                self.loops.pop();
+            }
+            ExprKind::When { scrutinee, arms } => {
+                let binds_by_move = arms.iter().any(|x| self.binds_by_move(&x.pattern));
+                match binds_by_move {
+                    true => self.consume(scrutinee),
+                    false => self.inspect(scrutinee),
+                }
+                let mut branches = self.start_branches();
+                for arm in arms {
+                    self.start_branch(&branches);
+                    self.scopes.push(Vec::new());
+                    self.declare_pattern(&arm.pattern);
+                    if let Some(guard) = &arm.guard {
+                        self.inspect(guard);
+                    }
+                    self.consume(&arm.body);
+                    self.scopes.pop();
+                    self.end_branch(&mut branches);
+                }
+                self.finish_branches(branches);
+            }
+            ExprKind::Return(value) => {
+                if let Some(value) = value {
+                    self.consume(value);
+                    self.check_escape(value, "return");
+                }
+                self.diverged = true;
+            }
+            ExprKind::Yield(value) => {
+                self.consume(value);
+                self.check_escape(value, "yield");
+            }
+            ExprKind::Closure(function) => self.check_closure(function),
+        }
+    }
+
+    /// Checks if a pattern binds part of the value it matches by moving it
+    fn binds_by_move(&self, pattern: &Pattern) -> bool {
+        match &pattern.kind {
+            PatternKind::Binding { .. } => !self.is_copy(self.types.type_of(pattern.id)),
+            PatternKind::Tuple(elements) => elements.iter().any(|x| self.binds_by_move(x)),
+            PatternKind::Variant { payload, .. } => payload.iter().any(|x| self.binds_by_move(x)),
+            PatternKind::Wildcard | PatternKind::Literal(_) | PatternKind::Range { .. } => false,
+        }
+    }
+
+    fn declare_pattern(&mut self, pattern: &Pattern) {
+        match &pattern.kind {
+            PatternKind::Binding { mutable, name } => {
+                self.declare(name.symbol, *mutable, name.span, true);
+            }
+            PatternKind::Tuple(elements) => elements.iter().for_each(|x| self.declare_pattern(x)),
+            PatternKind::Variant { payload, .. } => {
+                payload.iter().for_each(|x| self.declare_pattern(x))
+            }
+            PatternKind::Wildcard | PatternKind::Literal(_) | PatternKind::Range { .. } => {}
+        }
+    }
+}
+
+/// Points out how a binding which can not be changed could be
+fn declare_mutable(diagnostic: Diagnostic, local: &Local) -> Diagnostic {
+    match local.captured {
+        true => diagnostic
+            .with_secondary(local.span, format!("`{}` is captured here", local.name))
+            .with_note("a closure captures copies of the values it uses, which it can not change"),
+        false => diagnostic.with_secondary(
+            local.span,
+            format!("consider declaring it as `mut {}`", local.name),
+        ),
+    }
+}
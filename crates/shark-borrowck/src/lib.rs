@@ -0,0 +1,31 @@
+use check::OwnershipChecker;
+use shark_core::diagnostic::Diagnostic;
+use shark_parse::ast::{ItemKind, Module};
+use shark_sema::ModuleDefs;
+use shark_typeck::TypeckResults;
+
+pub mod check;
+
+#[cfg(test)]
+pub mod tests;
+
+/// Step five of compilation. Checks that every function and method body of a [Module] follows the
+/// ownership rules, once the types within them have been inferred
+pub fn check_module(module: &Module, defs: &ModuleDefs, types: &TypeckResults) -> Vec<Diagnostic> {
+    let mut checker = OwnershipChecker::new(defs, types);
+    for item in &module.items {
+        match &item.kind {
+            ItemKind::Function(function) => checker.check_function(function),
+            ItemKind::Trait(trait_decl) => trait_decl
+                .methods
+                .iter()
+                .for_each(|x| checker.check_function(x)),
+            ItemKind::Impl(impl_decl) => impl_decl
+                .methods
+                .iter()
+                .for_each(|x| checker.check_function(x)),
+            _ => {}
+        }
+    }
+    checker.diagnostics
+}
@@ -0,0 +1,217 @@
+use shark_parse::parse;
+
+use crate::check_module;
+
+fn check(source: &str) -> Vec<String> {
+    let module = parse(None, source).expect("failed to parse module");
+    let (defs, diagnostics) = shark_sema::check_module(&module);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let (types, diagnostics) = shark_typeck::check_module(&module, &defs);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    check_module(&module, &defs, &types)
+        .iter()
+        .map(|x| x.message.clone())
+        .collect()
+}
+
+#[test]
+fn test_moves() {
+    let errors = check(
+        "type Point { x :: Int32, y :: Int32 }
+        fun take(point :: Point) {}
+        fun main(flag :: Bool, numbers :: yield Int32) {
+            let point = Point { x = 1, y = 2 };
+            let copy = point.x;
+            take(point);
+            let x = point.x;
+            let mut other = Point { x = 3, y = 4 };
+            if flag {
+                take(other);
+            }
+            take(other);
+            other = Point { x = 5, y = 6 };
+            take(other);
+            let last = Point { x = 7, y = 8 };
+            for i in numbers {
+                take(last);
+            }
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "use of moved value `point`",
+            "use of moved value `other`",
+            "use of moved value `last`",
+        ]
+    );
+}
+
+#[test]
+fn test_borrows() {
+    let errors = check(
+        "fun main() {
+            let mut a = 1;
+            let first = ref mut a;
+            let second = ref mut a;
+            *first = 2;
+            *second = 3;
+
+            let mut b = 1;
+            let shared = ref b;
+            b = 2;
+            let read = *shared;
+
+            let mut c = 1;
+            let unique = ref mut c;
+            *unique = 2;
+            c = 3;
+            let done = ref c;
+
+            let d = 1;
+            let e = ref mut d;
+            let f = ref d;
+            *f = 2;
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "cannot borrow `a` as mutable more than once at a time",
+            "cannot assign to `b` because it is borrowed",
+            "cannot borrow `d` as mutable, as it is not declared as mutable",
+            "cannot assign through a `ref` which is not `mut`",
+        ]
+    );
+}
+
+#[test]
+fn test_lifetimes() {
+    let errors = check(
+        "fun local() :: ref Int32 {
+            let value = 1;
+            ref value
+        }
+        fun through(value :: ref Int32) :: ref Int32 {
+            ref *value
+        }
+        fun held() :: ref Int32 {
+            let value = 1;
+            let borrowed = ref value;
+            ret borrowed;
+        }
+        fun scoped() {
+            let outer;
+            {
+                let inner = 1;
+                outer = ref inner;
+            }
+            let read = *outer;
+        }
+        fun generator() :: yield ref Int32 {
+            let value = 1;
+            yield ref value;
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "cannot return a reference to local `value`",
+            "cannot return `borrowed`, which borrows the local `value`",
+            "`inner` does not live long enough",
+            "cannot yield a reference to local `value`",
+        ]
+    );
+}
+
+#[test]
+fn test_unsafe() {
+    let errors = check(
+        "unsafe fun read(pointer :: ptr Int32) :: Int32 {
+            *pointer
+        }
+        fun main() {
+            let mut value = 1;
+            let pointer = ptr mut value;
+            let a = *pointer;
+            let b = read(pointer);
+            unsafe {
+                *pointer = 2;
+                let c = read(pointer);
+            }
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "dereference of raw pointer is unsafe and requires an `unsafe` block or function",
+            "call to unsafe function `read` requires an `unsafe` block or function",
+        ]
+    );
+}
+
+#[test]
+fn test_closures() {
+    let errors = check(
+        "type Point { x :: Int32, y :: Int32 }
+        fun take(point :: Point) {}
+        fun escape() :: fun() :: Int32 {
+            let value = 1;
+            let borrowed = ref value;
+            fun() :: Int32 { *borrowed }
+        }
+        fun main() {
+            let point = Point { x = 1, y = 2 };
+            let moves = fun() { take(point); };
+            let mut count = 1;
+            let counts = fun() { count += 1; };
+            let copied = fun() :: Int32 { count };
+            count = 2;
+
+            let mut a = 1;
+            let unique = ref mut a;
+            let holds = fun() :: Int32 { *unique };
+            a = 2;
+            let read = holds();
+
+            let other = Point { x = 3, y = 4 };
+            take(other);
+            let late = fun() :: Int32 { other.x };
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "cannot return `borrowed`, which borrows the local `value`",
+            "cannot capture `point`, which is moved rather than copied",
+            "cannot assign to `count`, which the closure captured",
+            "cannot assign to `a` because it is borrowed",
+            "cannot capture `other`, which is moved rather than copied",
+            "use of moved value `other`",
+        ]
+    );
+}
+
+#[test]
+fn test_arrays() {
+    let errors = check(
+        "type Point { x :: Int32, y :: Int32 }
+        fun main() {
+            let point = Point { x = 1, y = 2 };
+            let points = [point; 2];
+            let numbers = [1, 2];
+            let all = [numbers, numbers];
+            let first = Point { x = 3, y = 4 };
+            let listed = [first];
+            let x = first.x;
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "cannot repeat a value which is moved rather than copied",
+            "use of moved value `numbers`",
+            "use of moved value `first`",
+        ]
+    );
+}
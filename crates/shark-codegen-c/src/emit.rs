@@ -0,0 +1,609 @@
+//! Generates a C function for every function of the IR. Every value becomes a C variable and every
+//! block a label, with the jumps between them assigning the parameters of the block they go to.
+//! Every `slot` and `alloca` is an array of the C function, so it is a new place on every call
+//!
+//! Memory is read and written with `memcpy`, which C compilers turn into plain loads and stores,
+//! so that the same bytes can be seen through values of different types
+
+use std::{collections::HashMap, fmt::Write, path::Path};
+
+use shark_core::source::{LineIndex, Span};
+use shark_ir::ir::{
+    BinaryOp, CType, Extern, Foreign, Function, Inst, InstKind, Module, Target, Terminator, Type,
+    UnaryOp, Value,
+};
+use shark_lex::token::LiteralKind;
+use shark_sema::numeric::Overflow;
+
+use crate::runtime::conversion_name;
+
+pub struct CodeGen<'g> {
+    module: &'g Module,
+    path: Option<&'g Path>,
+    line_index: LineIndex<'g>,
+    /// The C name of every function of the module and every extern, by the name the IR gives it
+    names: HashMap<&'g str, String>,
+    /// Every `struct` a foreign function takes or returns, whose C name is `shark_struct_` and its
+    /// index
+    structs: Vec<Vec<CType>>,
+    pub declarations: Vec<String>,
+    pub prototypes: Vec<String>,
+    pub definitions: Vec<String>,
+}
+
+/// Gets the C type of a value
+pub fn ctype(ty: Type) -> &'static str {
+    match ty {
+        Type::Int8 => "Int8",
+        Type::UInt8 => "UInt8",
+        Type::Int32 => "Int32",
+        Type::UInt32 => "UInt32",
+        Type::Int64 => "Int64",
+        Type::UInt64 => "UInt64",
+        Type::Float32 => "Float32",
+        Type::Float64 => "Float64",
+        Type::Bool => "Bool",
+        Type::Char => "Char",
+        Type::Ptr => "Ptr",
+    }
+}
+
+/// Turns the name of a function into a C identifier, keeping its letters and digits so that the
+/// generated code can still be followed
+fn mangle(index: usize, name: &str) -> String {
+    let mut result = format!("shark_{}_", index);
+    let mut separated = true;
+    for character in name.chars() {
+        match character.is_ascii_alphanumeric() {
+            true => {
+                result.push(character);
+                separated = false;
+            }
+            false if !separated => {
+                result.push('_');
+                separated = true;
+            }
+            false => {}
+        }
+    }
+    result.trim_end_matches('_').to_string()
+}
+
+/// Writes text as a C string literal, escaping every byte which is not printable ASCII
+pub fn string_literal(text: &str) -> String {
+    let mut result = String::from("\"");
+    for byte in text.bytes() {
+        match byte {
+            b'"' => result.push_str("\\\""),
+            b'\\' => result.push_str("\\\\"),
+            // A `?` could start a trigraph
+            b'?' => result.push_str("\\?"),
+            b' '..=b'~' => result.push(byte as char),
+            _ => {
+                let _ = write!(result, "\\{:03o}", byte);
+            }
+        }
+    }
+    result.push('"');
+    result
+}
+
+/// Writes a float as a C constant, whose digits read back as the same float
+fn float_literal(value: f64, digits: String) -> String {
+    match value {
+        _ if value.is_nan() => "NAN".to_string(),
+        f64::INFINITY => "INFINITY".to_string(),
+        f64::NEG_INFINITY => "(-INFINITY)".to_string(),
+        _ => format!("({})", digits),
+    }
+}
+
+fn constant(literal: &LiteralKind) -> String {
+    match literal {
+        LiteralKind::UInt8(x) => format!("(UInt8){}", x),
+        LiteralKind::Int8(x) => format!("(Int8){}", x),
+        LiteralKind::UInt32(x) => format!("(UInt32){}u", x),
+        LiteralKind::Int32(x) if *x == i32::MIN => "INT32_MIN".to_string(),
+        LiteralKind::Int32(x) => format!("(Int32){}", x),
+        LiteralKind::UInt64(x) => format!("UINT64_C({})", x),
+        LiteralKind::Int64(x) if *x == i64::MIN => "INT64_MIN".to_string(),
+        LiteralKind::Int64(x) => format!("INT64_C({})", x),
+        LiteralKind::Float32(x) => float_literal(*x as f64, format!("{:?}f", x)),
+        LiteralKind::Float64(x) => float_literal(*x, format!("{:?}", x)),
+        LiteralKind::Char(x) => format!("(Char){}", *x as u32),
+        LiteralKind::Boolean(x) => x.to_string(),
+        LiteralKind::Str(x) => format!("(Ptr){}", string_literal(x.as_str())),
+    }
+}
+
+fn value(value: Value) -> String {
+    format!("v{}", value.0)
+}
+
+fn values(values: &[Value]) -> String {
+    let names: Vec<String> = values.iter().map(|x| self::value(*x)).collect();
+    names.join(", ")
+}
+
+impl<'g> CodeGen<'g> {
+    pub fn new(module: &'g Module, path: Option<&'g Path>, source: &'g str) -> Self {
+        let mut names = HashMap::new();
+        for (index, function) in module.functions.iter().enumerate() {
+            names.insert(function.name.as_str(), mangle(index, &function.name));
+        }
+        for declaration in &module.externs {
+            let name = match declaration.foreign {
+                Some(_) => format!("shark_ffi_{}", declaration.name),
+                None => declaration.name.clone(),
+            };
+            names.insert(declaration.name.as_str(), name);
+        }
+        Self {
+            module,
+            path,
+            line_index: LineIndex::new(source),
+            names,
+            structs: Vec::new(),
+            declarations: Vec::new(),
+            prototypes: Vec::new(),
+            definitions: Vec::new(),
+        }
+    }
+
+    /// Gets the C name of a function of the module or an extern
+    pub fn name(&self, name: &str) -> &str {
+        &self.names[name]
+    }
+
+    /// Declares every foreign function under the symbol C knows it by, and the prototype of every
+    /// function of the module
+    pub fn declare(&mut self) {
+        for declaration in &self.module.externs {
+            if let Some(foreign) = &declaration.foreign {
+                let prototype = self.foreign_prototype(declaration, foreign);
+                self.prototypes.push(prototype);
+            }
+        }
+        for function in &self.module.functions {
+            let prototype = format!("{};", self.signature(function));
+            self.prototypes.push(prototype);
+        }
+    }
+
+    fn signature(&self, function: &Function) -> String {
+        let params: Vec<String> = function.blocks[0]
+            .params
+            .iter()
+            .map(|(x, ty)| format!("{} {}", ctype(*ty), value(*x)))
+            .collect();
+        let params = match params.is_empty() {
+            true => "void".to_string(),
+            false => params.join(", "),
+        };
+        let return_type = function.return_type.map_or("void", ctype);
+        format!(
+            "static {} {}({})",
+            return_type,
+            self.name(&function.name),
+            params
+        )
+    }
+
+    /// Gets the C type of a value given to or taken from a foreign function, defining the
+    /// `struct` it is if it is the first time
+    fn foreign_type(&mut self, ty: &CType) -> String {
+        match ty {
+            CType::Scalar(Type::Ptr) => "void *".to_string(),
+            CType::Scalar(ty) => ctype(*ty).to_string(),
+            CType::Struct(fields) => {
+                if let Some(index) = self.structs.iter().position(|x| x == fields) {
+                    return format!("struct shark_struct_{}", index);
+                }
+                let fields: Vec<String> = fields
+                    .iter()
+                    .enumerate()
+                    .map(|(index, x)| format!("    {} _{};\n", self.foreign_type(x), index))
+                    .collect();
+                let index = self.structs.len();
+                self.structs.push(match ty {
+                    CType::Struct(fields) => fields.clone(),
+                    CType::Scalar(_) => unreachable!(),
+                });
+                self.declarations.push(format!(
+                    "struct shark_struct_{} {{\n{}}};",
+                    index,
+                    fields.concat()
+                ));
+                format!("struct shark_struct_{}", index)
+            }
+        }
+    }
+
+    fn foreign_prototype(&mut self, declaration: &Extern, foreign: &Foreign) -> String {
+        let mut params: Vec<String> = foreign
+            .params
+            .iter()
+            .map(|x| self.foreign_type(x))
+            .collect();
+        if foreign.variadic {
+            params.push("...".to_string());
+        }
+        if params.is_empty() {
+            params.push("void".to_string());
+        }
+        let return_type = match &foreign.return_type {
+            Some(ty) => self.foreign_type(ty),
+            None => "void".to_string(),
+        };
+        format!(
+            "extern {} {}({}) SHARK_FOREIGN({});",
+            return_type,
+            self.name(&declaration.name),
+            params.join(", "),
+            declaration.name
+        )
+    }
+
+    /// Gets a C string literal naming where a span starts in the source
+    fn at(&self, at: Option<Span>) -> String {
+        match at {
+            Some(span) => {
+                let position = self.line_index.position(self.path, span.start);
+                string_literal(&position.to_string())
+            }
+            None => string_literal("<runtime>"),
+        }
+    }
+
+    /// Generates the C function of a function of the IR
+    pub fn emit(&mut self, function: &Function) {
+        let mut emitter = FunctionEmitter {
+            codegen: self,
+            types: HashMap::new(),
+            lines: Vec::new(),
+        };
+        emitter.function(function);
+        let lines = emitter.lines;
+        let mut result = format!("/* {} */\n{} {{\n", function.name, self.signature(function));
+        for line in lines {
+            result.push_str(&line);
+            result.push('\n');
+        }
+        result.push('}');
+        self.definitions.push(result);
+    }
+}
+
+struct FunctionEmitter<'e, 'g> {
+    codegen: &'e mut CodeGen<'g>,
+    /// The type of every value of the function
+    types: HashMap<Value, Type>,
+    lines: Vec<String>,
+}
+
+impl FunctionEmitter<'_, '_> {
+    fn line(&mut self, text: impl AsRef<str>) {
+        self.lines.push(format!("    {}", text.as_ref()));
+    }
+
+    fn function(&mut self, function: &Function) {
+        for (index, block) in function.blocks.iter().enumerate() {
+            for (param, ty) in &block.params {
+                self.types.insert(*param, *ty);
+                if index > 0 {
+                    self.line(format!("{} {};", ctype(*ty), value(*param)));
+                }
+            }
+            for inst in &block.insts {
+                let Some((result, ty)) = inst.result else {
+                    continue;
+                };
+                self.types.insert(result, ty);
+                self.line(format!("{} {};", ctype(ty), value(result)));
+                match inst.kind {
+                    InstKind::Alloca(ty) => self.slot(result, ty.size()),
+                    InstKind::Slot(size, _) => self.slot(result, size),
+                    _ => {}
+                }
+            }
+        }
+        for (index, block) in function.blocks.iter().enumerate() {
+            self.lines.push(format!("bb{}:;", index));
+            for inst in &block.insts {
+                self.inst(inst);
+            }
+            self.terminator(function, &block.terminator);
+        }
+    }
+
+    /// Declares the array a `slot` or `alloca` gives the address of, whose items are aligned for
+    /// any value of the IR
+    fn slot(&mut self, result: Value, size: u64) {
+        self.line(format!(
+            "uint64_t {}_slot[{}];",
+            value(result),
+            size.div_ceil(8).max(1)
+        ));
+    }
+
+    fn ty(&self, value: Value) -> Type {
+        self.types[&value]
+    }
+
+    fn inst(&mut self, inst: &Inst) {
+        let at = self.codegen.at(inst.at);
+        let result = inst.result.map(|(x, _)| value(x));
+        let assign = |expression: String| match &result {
+            Some(result) => format!("{} = {};", result, expression),
+            None => format!("{};", expression),
+        };
+        let text = match &inst.kind {
+            InstKind::Const(literal) => assign(constant(literal)),
+            InstKind::Copy(x) => assign(value(*x)),
+            InstKind::Unary(op, x) => {
+                let ty = self.ty(*x);
+                assign(match (op, ty) {
+                    (UnaryOp::Neg, _) if ty.is_float() => format!("-{}", value(*x)),
+                    (UnaryOp::Neg, _) => format!("shark_neg_{}({}, {})", ctype(ty), value(*x), at),
+                    (UnaryOp::Not, Type::Bool) => format!("!{}", value(*x)),
+                    (UnaryOp::Not, _) => format!("({})~{}", ctype(ty), value(*x)),
+                })
+            }
+            InstKind::Binary(op, x, y) => assign(self.binary(*op, *x, *y, &at)),
+            InstKind::Arith(overflow, op, x, y) => {
+                let suffix = match overflow {
+                    Overflow::Trap => "",
+                    Overflow::Wrap => "_wrap",
+                    Overflow::Saturate => "_sat",
+                };
+                assign(format!(
+                    "shark_{}{}_{}({}, {}, {})",
+                    op.name(),
+                    suffix,
+                    ctype(self.ty(*x)),
+                    value(*x),
+                    value(*y),
+                    at
+                ))
+            }
+            InstKind::Convert(overflow, x) => {
+                let (source, target) = (
+                    self.ty(*x),
+                    inst.result.expect("conversions give a value").1,
+                );
+                assign(match (source.primitive(), target.primitive()) {
+                    (Some(source), Some(target)) => format!(
+                        "{}({}, {})",
+                        conversion_name(source, target, *overflow),
+                        value(*x),
+                        at
+                    ),
+                    _ => format!("({}){}", ctype(target), value(*x)),
+                })
+            }
+            InstKind::Alloca(_) | InstKind::Slot(..) => {
+                let result = result.expect("slots give an address");
+                format!("{} = (Ptr){}_slot;", result, result)
+            }
+            InstKind::Offset(x, y) => assign(format!("{} + {}", value(*x), value(*y))),
+            InstKind::Load(x) => {
+                let result = result.expect("loads give a value");
+                format!("memcpy(&{}, {}, sizeof({}));", result, value(*x), result)
+            }
+            InstKind::Store(x, y) => {
+                format!(
+                    "memcpy({}, &{}, sizeof({}));",
+                    value(*x),
+                    value(*y),
+                    value(*y)
+                )
+            }
+            InstKind::MemCopy(x, y, z) => format!(
+                "memmove({}, {}, (size_t){});",
+                value(*x),
+                value(*y),
+                value(*z)
+            ),
+            InstKind::Call(name, arguments) => {
+                let module = self.codegen.module;
+                if let Some(declaration) = module.extern_function(name) {
+                    if let Some(foreign) = &declaration.foreign {
+                        return self.foreign_call(declaration, foreign, arguments, result);
+                    }
+                }
+                let call = format!("{}({})", self.codegen.name(name), values(arguments));
+                let counted = inst.at.is_some() && module.function(name).is_some();
+                return self.counted(assign(call), counted, &at);
+            }
+            InstKind::FuncAddr(name) => {
+                assign(format!("(Ptr)(void *)&{}", self.codegen.name(name)))
+            }
+            InstKind::CallIndirect(callee, arguments) => {
+                let params: Vec<&str> = arguments.iter().map(|x| ctype(self.ty(*x))).collect();
+                let params = match params.is_empty() {
+                    true => "void".to_string(),
+                    false => params.join(", "),
+                };
+                let return_type = inst.result.map_or("void", |(_, ty)| ctype(ty));
+                let call = format!(
+                    "(({} (*)({}))(void *){})({})",
+                    return_type,
+                    params,
+                    value(*callee),
+                    values(arguments)
+                );
+                return self.counted(assign(call), inst.at.is_some(), &at);
+            }
+            InstKind::Panic(message, text) => format!(
+                "shark_panic({}, {}, {});",
+                string_literal(message),
+                text.map_or("NULL".to_string(), value),
+                at
+            ),
+        };
+        self.line(text);
+    }
+
+    /// Adds a call to how deep calls are nested while it runs, if it counts
+    fn counted(&mut self, call: String, counted: bool, at: &str) {
+        if !counted {
+            return self.line(call);
+        }
+        self.line(format!("shark_enter({});", at));
+        self.line(call);
+        self.line("shark_depth--;");
+    }
+
+    fn binary(&self, op: BinaryOp, x: Value, y: Value, at: &str) -> String {
+        let ty = self.ty(x);
+        let operator = match op {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::And => "&",
+            BinaryOp::Eq => "==",
+            BinaryOp::Ne => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Le => "<=",
+            BinaryOp::Gt => ">",
+            BinaryOp::Ge => ">=",
+            BinaryOp::Shl | BinaryOp::Shr => {
+                return format!(
+                    "shark_{}_{}({}, (Int64){}, {})",
+                    op.name(),
+                    ctype(ty),
+                    value(x),
+                    value(y),
+                    at
+                );
+            }
+        };
+        match op {
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div if ty.is_integer() => {
+                format!(
+                    "shark_{}_{}({}, {}, {})",
+                    op.name(),
+                    ctype(ty),
+                    value(x),
+                    value(y),
+                    at
+                )
+            }
+            BinaryOp::And => format!("({}){} & {}", ctype(ty), value(x), value(y)),
+            _ => format!("{} {} {}", value(x), operator, value(y)),
+        }
+    }
+
+    /// Calls a foreign function with the types C sees. A `struct` is given by its address and one
+    /// returned is stored at the address given first, and the values given after the parameters
+    /// of a variadic function are promoted the way C promotes them
+    fn foreign_call(
+        &mut self,
+        declaration: &Extern,
+        foreign: &Foreign,
+        arguments: &[Value],
+        result: Option<String>,
+    ) {
+        let mut arguments = arguments.iter();
+        let sret = match foreign.return_type {
+            Some(CType::Struct(_)) => arguments.next(),
+            _ => None,
+        };
+        let mut values = Vec::new();
+        for (index, argument) in arguments.enumerate() {
+            let argument_value = value(*argument);
+            values.push(match foreign.params.get(index) {
+                Some(CType::Struct(_)) => {
+                    let ty = self.codegen.foreign_type(&foreign.params[index]);
+                    format!("*({} *){}", ty, argument_value)
+                }
+                Some(_) => argument_value,
+                None => match self.ty(*argument) {
+                    Type::Float32 => format!("(double){}", argument_value),
+                    Type::Int8 | Type::UInt8 | Type::Bool => format!("(int){}", argument_value),
+                    _ => argument_value,
+                },
+            });
+        }
+        let call = format!(
+            "{}({})",
+            self.codegen.name(&declaration.name),
+            values.join(", ")
+        );
+        let line = match (sret, result) {
+            (Some(sret), _) => {
+                let ty = self
+                    .codegen
+                    .foreign_type(foreign.return_type.as_ref().expect("a struct"));
+                format!(
+                    "{{ {} result = {}; memcpy({}, &result, sizeof(result)); }}",
+                    ty,
+                    call,
+                    value(*sret)
+                )
+            }
+            (None, Some(result)) => format!(
+                "{} = ({}){};",
+                result,
+                ctype(self.result_type(&result)),
+                call
+            ),
+            (None, None) => format!("{};", call),
+        };
+        self.line(line);
+    }
+
+    fn result_type(&self, name: &str) -> Type {
+        let number = name[1..].parse().expect("values are named by their number");
+        self.ty(Value(number))
+    }
+
+    /// Assigns the parameters of the block a target goes to, then goes there. The arguments are
+    /// read before any parameter is assigned, as they may be parameters of the same block
+    fn target(&self, target: &Target, params: &[(Value, Type)]) -> String {
+        let mut text = String::new();
+        match target.arguments.len() {
+            0 => {}
+            1 => {
+                let (param, _) = params[0];
+                let _ = write!(text, "{} = {}; ", value(param), value(target.arguments[0]));
+            }
+            _ => {
+                text.push_str("{ ");
+                for (index, argument) in target.arguments.iter().enumerate() {
+                    let _ = write!(
+                        text,
+                        "{} t{} = {}; ",
+                        ctype(self.ty(*argument)),
+                        index,
+                        value(*argument)
+                    );
+                }
+                for (index, (param, _)) in params.iter().enumerate() {
+                    let _ = write!(text, "{} = t{}; ", value(*param), index);
+                }
+                text.push_str("} ");
+            }
+        }
+        let _ = write!(text, "goto bb{};", target.block.0);
+        text
+    }
+
+    fn terminator(&mut self, function: &Function, terminator: &Terminator) {
+        let params = |target: &Target| function.block(target.block).params.clone();
+        let text = match terminator {
+            Terminator::Jump(target) => self.target(target, &params(target)),
+            Terminator::Branch(condition, then_target, else_target) => format!(
+                "if ({}) {{ {} }} else {{ {} }}",
+                value(*condition),
+                self.target(then_target, &params(then_target)),
+                self.target(else_target, &params(else_target))
+            ),
+            Terminator::Return(Some(result)) => format!("return {};", value(*result)),
+            Terminator::Return(None) => "return;".to_string(),
+            Terminator::Unreachable => "abort();".to_string(),
+        };
+        self.line(text);
+    }
+}
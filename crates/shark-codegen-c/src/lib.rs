@@ -0,0 +1,80 @@
+//! Generates portable C99 from the optimised IR of a program, which a C compiler can then turn
+//! into an executable. The C program behaves like the interpreter: arithmetic overflows, runtime
+//! errors are printed to stderr and exit with code 101, and `main` decides the exit code
+//!
+//! The program includes the C runtime of `shark-std`, which the IR calls for the standard library
+//!
+//! `extern "C"` functions are called directly, so the executable must be linked with the C
+//! code or libraries defining them, which [build_executable] is given
+
+use std::{
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use emit::CodeGen;
+use shark_ir::{build::ENTRY, ir::Module};
+
+pub mod emit;
+pub mod runtime;
+
+#[cfg(test)]
+pub mod tests;
+
+/// Generates a C program for a module of the IR, whose `main` starts the runtime with the
+/// arguments of the program and then calls the [ENTRY] of the module. `path` and `source` are
+/// those of the module, which runtime errors point into
+pub fn generate(module: &Module, path: Option<&Path>, source: &str) -> String {
+    let mut codegen = CodeGen::new(module, path, source);
+    codegen.declare();
+    for function in &module.functions {
+        codegen.emit(function);
+    }
+
+    let mut result = runtime::prelude();
+    for part in [
+        &codegen.declarations,
+        &codegen.prototypes,
+        &codegen.definitions,
+    ] {
+        result.push('\n');
+        for text in part {
+            result.push_str(text);
+            result.push('\n');
+        }
+    }
+    result.push_str("\nint main(int argc, char **argv) {\n    shark_start(argc, argv);\n");
+    match module.function(ENTRY) {
+        Some(_) => {
+            let entry = codegen.name(ENTRY);
+            result.push_str(&format!("    return (UInt8){}();\n", entry));
+        }
+        None => result.push_str("    return 0;\n"),
+    }
+    result.push_str("}\n");
+    result
+}
+
+/// Compiles a C file into an executable with the C compiler named by `$CC`, or `cc`. The C files,
+/// objects and libraries in `link` are compiled and linked along with it, for the `extern "C"`
+/// functions the program calls
+pub fn build_executable(source: &Path, output: &Path, link: &[PathBuf]) -> Result<(), String> {
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let result = Command::new(&compiler)
+        .args(["-std=c99", "-O2", "-o"])
+        .arg(output)
+        .arg(source)
+        .args(link)
+        .arg("-lm")
+        .output()
+        .map_err(|x| format!("could not run `{}`: {}", compiler, x))?;
+    match result.status.success() {
+        true => Ok(()),
+        false => Err(format!(
+            "`{}` failed to compile `{}`:\n{}",
+            compiler,
+            source.display(),
+            String::from_utf8_lossy(&result.stderr)
+        )),
+    }
+}
@@ -0,0 +1,408 @@
+//! The C code every generated program starts with: the runtime of `shark-std`, then the arithmetic
+//! of every type. Integer arithmetic overflows the same way as in the interpreter, stopping the
+//! program with the exit code 101 when a checked result does not fit in its type. Every operation has a function for each way of overflowing, `shark_add_Int8`
+//! trapping, `shark_add_wrap_Int8` wrapping around and `shark_add_sat_Int8` saturating
+
+use shark_sema::{
+    numeric::{self, Overflow},
+    ty::PrimitiveType,
+};
+
+/// Defines the checked arithmetic of an integer type narrower than 64 bits, which is done in
+/// `int64_t` and then checked to fit
+const NARROW_INTEGER: &str = r#"
+static inline NAME shark_add_NAME(NAME a, NAME b, const char *at) {
+    int64_t result = (int64_t)a + (int64_t)b;
+    if (result < MIN || result > MAX) shark_panic("attempt to add with overflow", NULL, at);
+    return (NAME)result;
+}
+
+static inline NAME shark_sub_NAME(NAME a, NAME b, const char *at) {
+    int64_t result = (int64_t)a - (int64_t)b;
+    if (result < MIN || result > MAX) shark_panic("attempt to subtract with overflow", NULL, at);
+    return (NAME)result;
+}
+
+static inline NAME shark_mul_NAME(NAME a, NAME b, const char *at) {
+    int64_t result = (int64_t)a * (int64_t)b;
+    if (result < MIN || result > MAX) shark_panic("attempt to multiply with overflow", NULL, at);
+    return (NAME)result;
+}
+
+static inline NAME shark_div_NAME(NAME a, NAME b, const char *at) {
+    if (b == 0) shark_panic("attempt to divide by zero", NULL, at);
+    int64_t result = (int64_t)a / (int64_t)b;
+    if (result < MIN || result > MAX) shark_panic("attempt to divide with overflow", NULL, at);
+    return (NAME)result;
+}
+
+static inline NAME shark_neg_NAME(NAME a, const char *at) {
+    int64_t result = -(int64_t)a;
+    if (result < MIN || result > MAX) shark_panic("attempt to negate with overflow", NULL, at);
+    return (NAME)result;
+}
+"#;
+
+/// Defines the wrapping arithmetic of an integer type, which is done on the unsigned type of the
+/// same width so that it is defined. Only dividing the smallest value of a signed type by -1
+/// wraps around
+const WRAPPING: &str = r#"
+static inline NAME shark_add_wrap_NAME(NAME a, NAME b, const char *at) {
+    return (NAME)((UNSIGNED)a + (UNSIGNED)b);
+}
+
+static inline NAME shark_sub_wrap_NAME(NAME a, NAME b, const char *at) {
+    return (NAME)((UNSIGNED)a - (UNSIGNED)b);
+}
+
+static inline NAME shark_mul_wrap_NAME(NAME a, NAME b, const char *at) {
+    return (NAME)((UNSIGNED)a * (UNSIGNED)b);
+}
+
+static inline NAME shark_div_wrap_NAME(NAME a, NAME b, const char *at) {
+    if (b == 0) shark_panic("attempt to divide by zero", NULL, at);
+    if (a == MIN && b == (NAME)-1) return a;
+    return a / b;
+}
+
+static inline NAME shark_neg_wrap_NAME(NAME a, const char *at) {
+    return (NAME)(0 - (UNSIGNED)a);
+}
+"#;
+
+/// Defines the saturating arithmetic of an integer type narrower than 64 bits, which is done in
+/// `int64_t` and then clamped to fit
+const NARROW_SATURATING: &str = r#"
+static inline NAME shark_clamp_NAME(int64_t result) {
+    return result < MIN ? MIN : result > MAX ? MAX : (NAME)result;
+}
+
+static inline NAME shark_add_sat_NAME(NAME a, NAME b, const char *at) {
+    return shark_clamp_NAME((int64_t)a + (int64_t)b);
+}
+
+static inline NAME shark_sub_sat_NAME(NAME a, NAME b, const char *at) {
+    return shark_clamp_NAME((int64_t)a - (int64_t)b);
+}
+
+static inline NAME shark_mul_sat_NAME(NAME a, NAME b, const char *at) {
+    return shark_clamp_NAME((int64_t)a * (int64_t)b);
+}
+
+static inline NAME shark_div_sat_NAME(NAME a, NAME b, const char *at) {
+    if (b == 0) shark_panic("attempt to divide by zero", NULL, at);
+    return shark_clamp_NAME((int64_t)a / (int64_t)b);
+}
+
+static inline NAME shark_neg_sat_NAME(NAME a, const char *at) {
+    return shark_clamp_NAME(-(int64_t)a);
+}
+"#;
+
+const SIGNED_64: &str = r#"
+static inline Int64 shark_add_Int64(Int64 a, Int64 b, const char *at) {
+    if ((b > 0 && a > INT64_MAX - b) || (b < 0 && a < INT64_MIN - b))
+        shark_panic("attempt to add with overflow", NULL, at);
+    return a + b;
+}
+
+static inline Int64 shark_sub_Int64(Int64 a, Int64 b, const char *at) {
+    if ((b < 0 && a > INT64_MAX + b) || (b > 0 && a < INT64_MIN + b))
+        shark_panic("attempt to subtract with overflow", NULL, at);
+    return a - b;
+}
+
+static inline Int64 shark_mul_Int64(Int64 a, Int64 b, const char *at) {
+    int overflow;
+    if (a > 0) {
+        overflow = b > 0 ? a > INT64_MAX / b : b < INT64_MIN / a;
+    } else {
+        overflow = b > 0 ? a < INT64_MIN / b : a != 0 && b < INT64_MAX / a;
+    }
+    if (overflow) shark_panic("attempt to multiply with overflow", NULL, at);
+    return a * b;
+}
+
+static inline Int64 shark_div_Int64(Int64 a, Int64 b, const char *at) {
+    if (b == 0) shark_panic("attempt to divide by zero", NULL, at);
+    if (a == INT64_MIN && b == -1) shark_panic("attempt to divide with overflow", NULL, at);
+    return a / b;
+}
+
+static inline Int64 shark_neg_Int64(Int64 a, const char *at) {
+    if (a == INT64_MIN) shark_panic("attempt to negate with overflow", NULL, at);
+    return -a;
+}
+
+static inline Int64 shark_add_sat_Int64(Int64 a, Int64 b, const char *at) {
+    if (b > 0 && a > INT64_MAX - b) return INT64_MAX;
+    if (b < 0 && a < INT64_MIN - b) return INT64_MIN;
+    return a + b;
+}
+
+static inline Int64 shark_sub_sat_Int64(Int64 a, Int64 b, const char *at) {
+    if (b < 0 && a > INT64_MAX + b) return INT64_MAX;
+    if (b > 0 && a < INT64_MIN + b) return INT64_MIN;
+    return a - b;
+}
+
+static inline Int64 shark_mul_sat_Int64(Int64 a, Int64 b, const char *at) {
+    int overflow;
+    if (a > 0) {
+        overflow = b > 0 ? a > INT64_MAX / b : b < INT64_MIN / a;
+    } else {
+        overflow = b > 0 ? a < INT64_MIN / b : a != 0 && b < INT64_MAX / a;
+    }
+    if (overflow) return (a < 0) != (b < 0) ? INT64_MIN : INT64_MAX;
+    return a * b;
+}
+
+static inline Int64 shark_div_sat_Int64(Int64 a, Int64 b, const char *at) {
+    if (b == 0) shark_panic("attempt to divide by zero", NULL, at);
+    if (a == INT64_MIN && b == -1) return INT64_MAX;
+    return a / b;
+}
+
+static inline Int64 shark_neg_sat_Int64(Int64 a, const char *at) {
+    return a == INT64_MIN ? INT64_MAX : -a;
+}
+"#;
+
+const UNSIGNED_64: &str = r#"
+static inline UInt64 shark_add_UInt64(UInt64 a, UInt64 b, const char *at) {
+    if (a > UINT64_MAX - b) shark_panic("attempt to add with overflow", NULL, at);
+    return a + b;
+}
+
+static inline UInt64 shark_sub_UInt64(UInt64 a, UInt64 b, const char *at) {
+    if (a < b) shark_panic("attempt to subtract with overflow", NULL, at);
+    return a - b;
+}
+
+static inline UInt64 shark_mul_UInt64(UInt64 a, UInt64 b, const char *at) {
+    if (b != 0 && a > UINT64_MAX / b) shark_panic("attempt to multiply with overflow", NULL, at);
+    return a * b;
+}
+
+static inline UInt64 shark_div_UInt64(UInt64 a, UInt64 b, const char *at) {
+    if (b == 0) shark_panic("attempt to divide by zero", NULL, at);
+    return a / b;
+}
+
+static inline UInt64 shark_neg_UInt64(UInt64 a, const char *at) {
+    if (a != 0) shark_panic("attempt to negate with overflow", NULL, at);
+    return a;
+}
+
+static inline UInt64 shark_add_sat_UInt64(UInt64 a, UInt64 b, const char *at) {
+    return a > UINT64_MAX - b ? UINT64_MAX : a + b;
+}
+
+static inline UInt64 shark_sub_sat_UInt64(UInt64 a, UInt64 b, const char *at) {
+    return a < b ? 0 : a - b;
+}
+
+static inline UInt64 shark_mul_sat_UInt64(UInt64 a, UInt64 b, const char *at) {
+    return b != 0 && a > UINT64_MAX / b ? UINT64_MAX : a * b;
+}
+
+static inline UInt64 shark_div_sat_UInt64(UInt64 a, UInt64 b, const char *at) {
+    if (b == 0) shark_panic("attempt to divide by zero", NULL, at);
+    return a / b;
+}
+
+static inline UInt64 shark_neg_sat_UInt64(UInt64 a, const char *at) {
+    return 0;
+}
+"#;
+
+/// Shifts are done on the unsigned type of the same width, so that shifting bits out of a signed
+/// integer is defined. The amount is checked to be less than the number of bits, and `RIGHT` is
+/// the right shift itself, which is made arithmetic for signed integers
+const SHIFT: &str = r#"
+static inline NAME shark_shl_NAME(NAME a, Int64 amount, const char *at) {
+    if (amount < 0 || amount >= BITS) shark_panic("attempt to shift left with overflow", NULL, at);
+    return (NAME)((UNSIGNED)a << amount);
+}
+
+static inline NAME shark_shr_NAME(NAME a, Int64 amount, const char *at) {
+    if (amount < 0 || amount >= BITS) shark_panic("attempt to shift right with overflow", NULL, at);
+    return RIGHT;
+}
+"#;
+
+const PRELUDE: &str = r#"
+typedef int8_t Int8;
+typedef uint8_t UInt8;
+typedef int32_t Int32;
+typedef uint32_t UInt32;
+typedef int64_t Int64;
+typedef uint64_t UInt64;
+typedef float Float32;
+typedef double Float64;
+typedef bool Bool;
+typedef uint32_t Char;
+typedef uint8_t *Ptr;
+
+/* How deep calls can nest before the program is stopped, the same as in the interpreter */
+#define SHARK_MAX_CALL_DEPTH 2048
+
+static unsigned shark_depth = 0;
+
+static inline void shark_enter(const char *at) {
+    if (++shark_depth > SHARK_MAX_CALL_DEPTH) shark_panic("stack overflow", NULL, at);
+}
+
+/* Links the declaration of a foreign function to the symbol C knows the function by */
+#define SHARK_QUOTE(x) #x
+#define SHARK_QUOTE_EXPANDED(x) SHARK_QUOTE(x)
+#ifdef __USER_LABEL_PREFIX__
+#define SHARK_FOREIGN(name) __asm__(SHARK_QUOTE_EXPANDED(__USER_LABEL_PREFIX__) #name)
+#else
+#define SHARK_FOREIGN(name) __asm__(#name)
+#endif
+"#;
+
+/// Gets the C code every generated program starts with
+pub fn prelude() -> String {
+    let mut result = shark_std::runtime::C_SOURCE.to_string();
+    result.push_str(PRELUDE);
+    let narrow = [
+        ("Int8", "INT8_MIN", "INT8_MAX"),
+        ("UInt8", "0", "UINT8_MAX"),
+        ("Int32", "INT32_MIN", "INT32_MAX"),
+        ("UInt32", "0", "UINT32_MAX"),
+    ];
+    for (name, min, max) in narrow {
+        for template in [NARROW_INTEGER, NARROW_SATURATING] {
+            result.push_str(
+                &template
+                    .replace("NAME", name)
+                    .replace("MIN", min)
+                    .replace("MAX", max),
+            );
+        }
+    }
+    result.push_str(SIGNED_64);
+    result.push_str(UNSIGNED_64);
+    let wrapping = [
+        ("Int8", "UInt8", "INT8_MIN"),
+        ("UInt8", "UInt8", "0"),
+        ("Int32", "UInt32", "INT32_MIN"),
+        ("UInt32", "UInt32", "0"),
+        ("Int64", "UInt64", "INT64_MIN"),
+        ("UInt64", "UInt64", "0"),
+    ];
+    for (name, unsigned, min) in wrapping {
+        result.push_str(
+            &WRAPPING
+                .replace("UNSIGNED", unsigned)
+                .replace("NAME", name)
+                .replace("MIN", min),
+        );
+    }
+    for source in numeric::NUMERIC {
+        for target in numeric::NUMERIC {
+            for overflow in [Overflow::Trap, Overflow::Wrap] {
+                result.push_str(&conversion(source, target, overflow));
+            }
+        }
+    }
+    let shifts = [
+        ("Int8", "UInt8", "8"),
+        ("UInt8", "UInt8", "8"),
+        ("Int32", "UInt32", "32"),
+        ("UInt32", "UInt32", "32"),
+        ("Int64", "UInt64", "64"),
+        ("UInt64", "UInt64", "64"),
+    ];
+    for (name, unsigned, bits) in shifts {
+        let right = match name == unsigned {
+            true => "(NAME)(a >> amount)",
+            false => "a < 0 ? (NAME)~(~a >> amount) : (NAME)(a >> amount)",
+        };
+        result.push_str(
+            &SHIFT
+                .replace("RIGHT", right)
+                .replace("UNSIGNED", unsigned)
+                .replace("NAME", name)
+                .replace("BITS", bits),
+        );
+    }
+    result
+}
+
+/// Gets the name of the function converting a number of type `source` to `target`
+pub fn conversion_name(source: PrimitiveType, target: PrimitiveType, overflow: Overflow) -> String {
+    match overflow {
+        Overflow::Trap => format!("shark_to_{}_{}", target, source),
+        _ => format!("shark_to_{}_wrap_{}", target, source),
+    }
+}
+
+/// Defines the function converting a number of type `source` to `target`, see
+/// [numeric::convert]. A float which does not fit is checked against bounds which are powers of
+/// two and so exact, and `NaN` fails every comparison
+fn conversion(source: PrimitiveType, target: PrimitiveType, overflow: Overflow) -> String {
+    let mut body = String::new();
+    if target.is_integer() {
+        let (min, max) = target.bounds();
+        if source.is_float() {
+            // The smallest `Int64` is the only bound with no float just below it
+            let lower = match numeric::bits(target) {
+                64 => format!("(double)a >= {}.0", min),
+                _ => format!("(double)a > {}.0", min - 1),
+            };
+            let upper = format!("(double)a < {}.0", max + 1);
+            match overflow {
+                Overflow::Trap => body.push_str(&format!(
+                    "    if (!({} && {})) shark_panic(\"attempt to convert with overflow\", NULL, at);\n",
+                    lower, upper
+                )),
+                _ => body.push_str(&format!(
+                    "    if (a != a) return 0;\n    if (!({})) return {};\n    if (!({})) return {};\n",
+                    lower,
+                    bound(min),
+                    upper,
+                    bound(max)
+                )),
+            }
+        } else if overflow == Overflow::Trap && !numeric::is_lossless(source, target) {
+            let (source_min, source_max) = source.bounds();
+            let mut conditions = Vec::new();
+            match source_min < 0 {
+                true => {
+                    if source_min < min {
+                        conditions.push(format!("(Int64)a < {}", bound(min)));
+                    }
+                    if source_max > max {
+                        conditions.push(format!("(Int64)a > {}", bound(max)));
+                    }
+                }
+                false => conditions.push(format!("(UInt64)a > {}", bound(max))),
+            }
+            body.push_str(&format!(
+                "    if ({}) shark_panic(\"attempt to convert with overflow\", NULL, at);\n",
+                conditions.join(" || ")
+            ));
+        }
+    }
+    format!(
+        "\nstatic inline {} {}({} a, const char *at) {{\n{}    return ({})a;\n}}\n",
+        target,
+        conversion_name(source, target, overflow),
+        source,
+        body,
+        target
+    )
+}
+
+/// Writes a bound of an integer type as a C constant, naming the two which cannot be written as
+/// an integer literal
+fn bound(value: i128) -> String {
+    match value {
+        _ if value == i64::MIN.into() => "INT64_MIN".to_string(),
+        _ if value == u64::MAX.into() => "UINT64_MAX".to_string(),
+        _ => value.to_string(),
+    }
+}
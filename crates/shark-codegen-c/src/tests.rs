@@ -0,0 +1,447 @@
+use std::{
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+use shark_ir::{verify::verify, OptLevel};
+use shark_sema::numeric::Overflow;
+use shark_testing::{
+    exit_status, interpret, overflow::check_overflow_modes, scratch_directory, suite,
+};
+
+use crate::{build_executable, generate};
+
+/// Generates C for a module which must check without errors, from its IR optimised
+/// at `opt_level`
+fn generate_c(source: &str, overflow: Overflow, opt_level: OptLevel) -> String {
+    let checked = shark_testing::check(source);
+    let bodies = checked.lower();
+    let (module, defs, types) = (&checked.module, &checked.defs, &checked.types);
+    let mut module = shark_ir::build(module, defs, types, &bodies, overflow);
+    shark_ir::optimize(&mut module, opt_level);
+    verify(&module).expect("the optimised IR verifies");
+    generate(&module, None, source)
+}
+
+/// Compiles a module to an executable and runs it, returning its exit code along with the first
+/// line of what it printed to stderr. The result must be the same as when the module is run by
+/// the interpreter
+fn execute(source: &str) -> (u8, String) {
+    execute_as(source, Overflow::Trap)
+}
+
+/// Like [execute], for a program built to overflow some way
+fn execute_as(source: &str, overflow: Overflow) -> (u8, String) {
+    execute_with(source, overflow, OptLevel::O2)
+}
+
+/// Like [execute_as], for a program optimised at `opt_level`
+fn execute_with(source: &str, overflow: Overflow, opt_level: OptLevel) -> (u8, String) {
+    let code = generate_c(source, overflow, opt_level);
+    let output = build_and_run(code, &[], Command::new);
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let status = output.status.code().expect("the program was killed") as u8;
+    let result = (status, stderr.lines().collect::<Vec<_>>().join("\n"));
+    assert_eq!(
+        result,
+        interpret(source, overflow, |x| exit_status(source, x))
+    );
+    result
+}
+
+/// Builds a generated C program, linked with the files in `link`, and runs it with the command
+/// `run` makes for the executable
+fn build_and_run(code: String, link: &[PathBuf], run: impl FnOnce(PathBuf) -> Command) -> Output {
+    let directory = scratch_directory("shark-codegen-c");
+    let c_path = directory.join("main.c");
+    let executable: PathBuf = directory.join("main");
+    std::fs::write(&c_path, code).expect("failed to write the C program");
+    build_executable(&c_path, &executable, link).expect("failed to compile the C program");
+    let output = run(executable).output().expect("failed to run the program");
+    let _ = std::fs::remove_dir_all(&directory);
+    output
+}
+
+#[test]
+fn test_arithmetic() {
+    let result = execute(
+        "pub fun main() :: Int32 {
+            let mut total = 2 + 3 * 4;
+            total -= 10 / 3;
+            total *= -2;
+            let shifted = (1 << 4) >> 1;
+            let masked = 13 & 7;
+            total + shifted + masked + 100
+        }",
+    );
+    assert_eq!(result, (91, String::new()));
+
+    let result = execute(
+        "fun widths() :: Bool {
+            let half :: Float64 = 1.0 / 4.0 * 2.0;
+            let third :: Float32 = 1.0 / 3.0;
+            let max :: UInt8 = 200 + 55;
+            let low :: Int8 = -128;
+            let big :: UInt64 = 9000000000000000000uint64 * 2;
+            let small :: Int64 = -9223372036854775807int64 - 1;
+            let wide :: UInt32 = 4000000000uint32;
+            let shifted :: Int32 = -16 >> 2;
+            3 >= 3 && 1 != 2 && !(!true | 2 < 1) && half == 0.5 && third < 0.34 && max == 255
+                && low == -128 && big / 2 == 9000000000000000000uint64 && small < -1 && wide > 1
+                && shifted == -4
+        }
+
+        pub fun main() :: Int32 {
+            if widths() { 1 } else { 2 }
+        }",
+    );
+    assert_eq!(result, (1, String::new()));
+}
+
+#[test]
+fn test_control_flow() {
+    let result = execute(
+        "fun fib(n :: Int64) :: Int64 {
+            if n < 2 {
+                ret n;
+            }
+            fib(n - 1) + fib(n - 2)
+        }
+
+        fun sign(n :: Int32) :: Int32 {
+            if n > 0 { 1 } else if n < 0 { -1 } else { 0 }
+        }
+
+        pub fun main() :: Int32 {
+            let text = \"shark\";
+            if sign(-5) == -1 && sign(0) == 0 && sign(7) == 1 && text == \"shark\" {
+                if fib(20) == 6765 { 42 } else { 1 }
+            } else {
+                0
+            }
+        }",
+    );
+    assert_eq!(result, (42, String::new()));
+}
+
+#[test]
+fn test_return_at_the_end() {
+    // The body of each function ends after a `ret`, which must build at every level
+    let source = "fun f(n :: Int32) :: Int32 { ret n; }
+
+        fun fib(n :: Int32) :: Int32 {
+            if n < 2 { ret n; }
+            ret fib(n - 1) + fib(n - 2);
+        }
+
+        fun pick(c :: Bool, a :: Int32, b :: Int32) :: Int32 {
+            if c { ret a; } else { ret b; }
+        }
+
+        pub fun main() :: Int32 {
+            ret f(3) + fib(10) + pick(false, 1, 2);
+        }";
+    for opt_level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+        assert_eq!(
+            execute_with(source, Overflow::Trap, opt_level),
+            (60, String::new())
+        );
+    }
+}
+
+#[test]
+fn test_types_and_patterns() {
+    let result = execute(
+        "type Point { x :: Int32, y :: Int32 }
+
+        enum Shape {
+            Circle(Int32),
+            Rectangle(Point, Point),
+            Empty,
+        }
+
+        fun area(shape :: Shape) :: Int32 {
+            when shape {
+                Shape::Circle(radius) => 3 * radius * radius,
+                Shape::Rectangle(a, b) => (b.x - a.x) * (b.y - a.y),
+                Shape::Empty => 0,
+            }
+        }
+
+        fun size(n :: Int32) :: Int32 {
+            when n {
+                0 => 0,
+                1..10 => 1,
+                x if x > 100 => 3,
+                _ => 2,
+            }
+        }
+
+        pub fun main() :: Int32 {
+            let rectangle = Shape::Rectangle(Point { x = 1, y = 2 }, Point { y = 6, x = 4 });
+            let same = Shape::Circle(2) == Shape::Circle(2) && Shape::Circle(1) != Shape::Empty;
+            let total = area(Shape::Circle(2)) + area(rectangle) + area(Shape::Empty);
+            let sizes = size(0) * 1000 + size(9) * 100 + size(10) * 10 + size(500);
+            if same { total + sizes - 100 } else { 0 }
+        }",
+    );
+    assert_eq!(result, (47, String::new()));
+}
+
+#[test]
+fn test_references() {
+    let result = execute(
+        "type Counter { count :: Int32 }
+
+        fun bump(counter :: ref mut Counter) {
+            counter.count += 1;
+        }
+
+        fun set(target :: ref mut Int32, value :: Int32) {
+            *target = value;
+        }
+
+        pub fun main() :: Int32 {
+            let mut counter = Counter { count = 0 };
+            bump(ref mut counter);
+            bump(ref mut counter);
+            let mut n = 1;
+            set(ref mut n, 40);
+            let pointer = ptr mut n;
+            unsafe {
+                *pointer += 2;
+            }
+            counter.count * 100 + n
+        }",
+    );
+    assert_eq!(result, (242, String::new()));
+}
+
+#[test]
+fn test_methods() {
+    let result = execute(
+        "trait Shape {
+            fun area(self :: ref Self) :: Int32;
+            fun double(self :: ref Self) :: Int32 {
+                Shape::area(self) * 2
+            }
+        }
+
+        type Square { side :: Int32 }
+        type Circle { radius :: Int32 }
+
+        impl Shape for Square {
+            fun area(self :: ref Self) :: Int32 {
+                self.side * self.side
+            }
+        }
+
+        impl Shape for Circle {
+            fun area(self :: ref Self) :: Int32 {
+                3 * self.radius * self.radius
+            }
+
+            fun double(self :: ref Self) :: Int32 {
+                0
+            }
+        }
+
+        pub fun main() :: Int32 {
+            let square = Square { side = 3 };
+            let circle = Circle { radius = 1 };
+            square.double() * 10 + circle.double() * 10 + Shape::area(ref circle)
+        }",
+    );
+    assert_eq!(result, (183, String::new()));
+}
+
+#[test]
+fn test_generators() {
+    let result = execute(
+        "fun upto(n :: Int32) :: yield Int32 {
+            if n > 0 {
+                for x in upto(n - 1) {
+                    yield x;
+                }
+                yield n;
+            }
+        }
+
+        fun evens(numbers :: yield Int32) :: yield Int32 {
+            for x in numbers {
+                if x / 2 * 2 == x {
+                    yield x;
+                }
+            }
+        }
+
+        pub fun main() :: Int32 {
+            let mut sum = 0;
+            for x in evens(upto(10)) {
+                sum += x;
+            }
+            let mut last :: Int64 = 0;
+            for (i, x) of upto(5) {
+                last = i;
+            }
+            if last == 4 { sum } else { -1 }
+        }",
+    );
+    assert_eq!(result, (30, String::new()));
+}
+
+#[test]
+fn test_runtime_errors() {
+    let result = execute(
+        "pub fun main() :: Int8 {
+            let x :: Int8 = 100;
+            x + x
+        }",
+    );
+    assert_eq!(
+        result,
+        (
+            101,
+            "error: attempt to add with overflow\n --> unknown:3:13".to_string()
+        )
+    );
+
+    let result = execute(
+        "fun divide(a :: Int32, b :: Int32) :: Int32 {
+            a / b
+        }
+
+        pub fun main() :: Int32 {
+            divide(1, 0)
+        }",
+    );
+    assert_eq!(
+        result,
+        (
+            101,
+            "error: attempt to divide by zero\n --> unknown:2:13".to_string()
+        )
+    );
+
+    let result = execute(
+        "pub fun main() :: Int32 {
+            let x :: UInt32 = 1;
+            let y = -x;
+            0
+        }",
+    );
+    assert_eq!(result.0, 101);
+
+    let result =
+        execute("fun forever(n :: Int32) :: Int32 { forever(n) } pub fun main() { forever(1); }");
+    assert_eq!(
+        result,
+        (101, "error: stack overflow\n --> unknown:1:36".to_string())
+    );
+}
+
+#[test]
+fn test_generics_and_std() {
+    let result = execute(
+        "fun first<T>(value :: T) :: T { value }
+
+        pub fun main() :: Int32 {
+            first(1)
+        }",
+    );
+    assert_eq!(result, (1, String::new()));
+
+    let source = "pub fun main() :: Int32 {
+            let mut values = Vec::new();
+            values.push(1);
+            values.push(2);
+            println(\"{} values\", values.len());
+            exit(values.len().to_int32());
+            0
+        }";
+    let code = generate_c(source, Overflow::Trap, OptLevel::O2);
+    let output = build_and_run(code, &[], Command::new);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2 values\n");
+    assert_eq!(output.status.code(), Some(2));
+}
+
+#[test]
+fn test_overflow_modes() {
+    check_overflow_modes(execute_as);
+}
+
+#[test]
+fn test_foreign_functions() {
+    let code = generate_c(
+        "extern \"C\" type Point {
+            x :: Int32,
+            y :: Int64,
+        }
+
+        extern \"C\" type Counter;
+
+        extern \"C\" fun add(a :: Int32, b :: Int32) :: Int32;
+        extern \"C\" fun scale(point :: Point, factor :: Int32) :: Point;
+        extern \"C\" fun bump(value :: ptr mut Int32);
+        extern \"C\" fun length(text :: Str) :: UInt64;
+        extern \"C\" fun greeting(name :: Str) :: Str;
+        extern \"C\" fun nothing() :: Str;
+        extern \"C\" fun counter_new() :: ptr mut Counter;
+        extern \"C\" fun counter_add(counter :: ptr mut Counter, amount :: Int8);
+        extern \"C\" fun counter_get(counter :: ptr Counter) :: Int32;
+        extern \"C\" fun counter_free(counter :: ptr mut Counter);
+        extern \"C\" fun printf(format :: Str, ...) :: Int32;
+        extern \"C\" fun abs(value :: Int32) :: Int32;
+
+        pub fun main() :: Int32 {
+            let mut value = 41;
+            unsafe {
+                bump(ptr mut value);
+                let point = scale(Point { x = 2, y = -3 }, add(value, -39));
+                let name = greeting(\"Shark\");
+                printf(\"%d %lld %llu %s|%s|\\n\", point.x, point.y, length(\"four\"), name, nothing());
+                let half :: Float32 = 0.5;
+                let small :: UInt8 = 200;
+                printf(\"%.2f %d %c %d\\n\", half, small, 65, true);
+                let counter = counter_new();
+                counter_add(counter, 5);
+                counter_add(counter, -7);
+                let total = counter_get(counter);
+                counter_free(counter);
+                ret total + abs(-40);
+            }
+        }",
+        Overflow::Trap,
+        OptLevel::O2,
+    );
+    let helper = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/foreign.c");
+    let output = build_and_run(code, &[helper], Command::new);
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "6 -9 4 hello, Shark||\n0.50 200 A 1\n"
+    );
+    assert_eq!(output.status.code(), Some(38));
+}
+
+#[test]
+fn test_library_programs() {
+    for test in suite::programs() {
+        let code = generate_c(&test.source, Overflow::Trap, OptLevel::O2);
+        // The shell writes stderr to stdout, for errors to come after what was printed before them
+        let output = build_and_run(code, &[], |executable| {
+            let mut command = Command::new("sh");
+            command
+                .args(["-c", "\"$0\" \"$@\" 2>&1"])
+                .arg(executable)
+                .args(&test.args);
+            command
+        });
+        let code = output.status.code().expect("the program was killed");
+        assert_eq!(
+            suite::executable_transcript(&String::from_utf8_lossy(&output.stdout), code),
+            test.expected,
+            "{}",
+            test.name
+        );
+    }
+}
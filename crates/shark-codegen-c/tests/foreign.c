@@ -0,0 +1,43 @@
+/* The C functions the tests of foreign functions call */
+
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+
+typedef struct {
+    int32_t x;
+    int64_t y;
+} Point;
+
+typedef struct Counter {
+    int32_t total;
+} Counter;
+
+int32_t add(int32_t a, int32_t b) { return a + b; }
+
+Point scale(Point point, int32_t factor) {
+    point.x *= factor;
+    point.y *= factor;
+    return point;
+}
+
+void bump(int32_t *value) { *value += 1; }
+
+uint64_t length(const char *text) { return strlen(text); }
+
+const char *greeting(const char *name) {
+    static char buffer[64];
+    snprintf(buffer, sizeof buffer, "hello, %s", name);
+    return buffer;
+}
+
+const char *nothing(void) { return NULL; }
+
+Counter *counter_new(void) { return calloc(1, sizeof(Counter)); }
+
+void counter_add(Counter *counter, int8_t amount) { counter->total += amount; }
+
+int32_t counter_get(const Counter *counter) { return counter->total; }
+
+void counter_free(Counter *counter) { free(counter); }
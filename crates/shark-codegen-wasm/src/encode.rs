@@ -0,0 +1,290 @@
+//! Encodes a [Module] in the WebAssembly binary format
+
+use crate::module::{BlockType, ExportKind, FuncType, Instr, Module};
+
+const MAGIC: &[u8] = b"\0asm";
+const VERSION: &[u8] = &[1, 0, 0, 0];
+
+mod section {
+    pub const TYPE: u8 = 1;
+    pub const IMPORT: u8 = 2;
+    pub const FUNCTION: u8 = 3;
+    pub const TABLE: u8 = 4;
+    pub const MEMORY: u8 = 5;
+    pub const GLOBAL: u8 = 6;
+    pub const EXPORT: u8 = 7;
+    pub const ELEMENT: u8 = 9;
+    pub const CODE: u8 = 10;
+    pub const DATA: u8 = 11;
+}
+
+/// Appends an unsigned LEB128 integer
+pub fn unsigned(out: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+/// Appends a signed LEB128 integer
+pub fn signed(out: &mut Vec<u8>, mut value: i64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        let done = (value == 0 && byte & 0x40 == 0) || (value == -1 && byte & 0x40 != 0);
+        if done {
+            out.push(byte);
+            return;
+        }
+        out.push(byte | 0x80);
+    }
+}
+
+fn name(out: &mut Vec<u8>, name: &str) {
+    unsigned(out, name.len() as u64);
+    out.extend_from_slice(name.as_bytes());
+}
+
+fn func_type(out: &mut Vec<u8>, ty: &FuncType) {
+    out.push(0x60);
+    unsigned(out, ty.params.len() as u64);
+    out.extend(ty.params.iter().map(|x| x.byte()));
+    unsigned(out, ty.results.len() as u64);
+    out.extend(ty.results.iter().map(|x| x.byte()));
+}
+
+fn block_type(out: &mut Vec<u8>, ty: BlockType) {
+    match ty {
+        BlockType::Empty => out.push(0x40),
+        BlockType::Value(ty) => out.push(ty.byte()),
+    }
+}
+
+/// Appends an instruction, where `type_index` gives the index of the type of a `call_indirect`
+pub fn instr(out: &mut Vec<u8>, instr: &Instr, type_index: &impl Fn(&FuncType) -> u64) {
+    match instr {
+        Instr::Unreachable => out.push(0x00),
+        Instr::Block(ty) => {
+            out.push(0x02);
+            block_type(out, *ty);
+        }
+        Instr::Loop(ty) => {
+            out.push(0x03);
+            block_type(out, *ty);
+        }
+        Instr::If(ty) => {
+            out.push(0x04);
+            block_type(out, *ty);
+        }
+        Instr::Else => out.push(0x05),
+        Instr::End => out.push(0x0B),
+        Instr::Br(depth) => {
+            out.push(0x0C);
+            unsigned(out, *depth as u64);
+        }
+        Instr::BrIf(depth) => {
+            out.push(0x0D);
+            unsigned(out, *depth as u64);
+        }
+        Instr::BrTable(targets, default) => {
+            out.push(0x0E);
+            unsigned(out, targets.len() as u64);
+            for target in targets {
+                unsigned(out, *target as u64);
+            }
+            unsigned(out, *default as u64);
+        }
+        Instr::Return => out.push(0x0F),
+        Instr::Call(function) => {
+            out.push(0x10);
+            unsigned(out, *function as u64);
+        }
+        Instr::CallIndirect(signature) => {
+            out.push(0x11);
+            unsigned(out, type_index(signature));
+            out.push(0x00);
+        }
+        Instr::Drop => out.push(0x1A),
+        Instr::Select => out.push(0x1B),
+        Instr::LocalGet(index)
+        | Instr::LocalSet(index)
+        | Instr::LocalTee(index)
+        | Instr::GlobalGet(index)
+        | Instr::GlobalSet(index) => {
+            out.push(match instr {
+                Instr::LocalGet(_) => 0x20,
+                Instr::LocalSet(_) => 0x21,
+                Instr::LocalTee(_) => 0x22,
+                Instr::GlobalGet(_) => 0x23,
+                _ => 0x24,
+            });
+            unsigned(out, *index as u64);
+        }
+        Instr::Load(load, offset) => {
+            out.push(load.opcode());
+            unsigned(out, load.align() as u64);
+            unsigned(out, *offset as u64);
+        }
+        Instr::Store(store, offset) => {
+            out.push(store.opcode());
+            unsigned(out, store.align() as u64);
+            unsigned(out, *offset as u64);
+        }
+        Instr::MemorySize => out.extend([0x3F, 0x00]),
+        Instr::MemoryGrow => out.extend([0x40, 0x00]),
+        Instr::I32Const(value) => {
+            out.push(0x41);
+            signed(out, *value as i64);
+        }
+        Instr::I64Const(value) => {
+            out.push(0x42);
+            signed(out, *value);
+        }
+        Instr::F32Const(bits) => {
+            out.push(0x43);
+            out.extend_from_slice(&bits.to_le_bytes());
+        }
+        Instr::F64Const(bits) => {
+            out.push(0x44);
+            out.extend_from_slice(&bits.to_le_bytes());
+        }
+        Instr::Op(op) => out.push(op.opcode()),
+    }
+}
+
+/// Appends a section, unless it would have no entries
+fn section(out: &mut Vec<u8>, id: u8, count: usize, entries: Vec<u8>) {
+    if count == 0 {
+        return;
+    }
+    let mut contents = Vec::new();
+    unsigned(&mut contents, count as u64);
+    contents.extend(entries);
+    out.push(id);
+    unsigned(out, contents.len() as u64);
+    out.extend(contents);
+}
+
+/// Encodes a module in the binary format
+pub fn encode(module: &Module) -> Vec<u8> {
+    let mut out = Vec::new();
+    out.extend_from_slice(MAGIC);
+    out.extend_from_slice(VERSION);
+
+    let types = module.types();
+    let type_index = |ty: &FuncType| {
+        types
+            .iter()
+            .position(|x| x == ty)
+            .expect("every signature has a type") as u64
+    };
+    let mut entries = Vec::new();
+    for ty in &types {
+        func_type(&mut entries, ty);
+    }
+    section(&mut out, section::TYPE, types.len(), entries);
+
+    let mut entries = Vec::new();
+    for import in &module.imports {
+        name(&mut entries, &import.module);
+        name(&mut entries, &import.name);
+        entries.push(0x00);
+        unsigned(&mut entries, type_index(&import.signature));
+    }
+    section(&mut out, section::IMPORT, module.imports.len(), entries);
+
+    let mut entries = Vec::new();
+    for function in &module.functions {
+        unsigned(&mut entries, type_index(&function.signature));
+    }
+    section(&mut out, section::FUNCTION, module.functions.len(), entries);
+
+    if !module.table.is_empty() {
+        let mut entries = vec![0x70, 0x00];
+        unsigned(&mut entries, module.table.len() as u64 + 1);
+        section(&mut out, section::TABLE, 1, entries);
+    }
+
+    if let Some(pages) = module.memory {
+        let mut entries = vec![0x00];
+        unsigned(&mut entries, pages as u64);
+        section(&mut out, section::MEMORY, 1, entries);
+    }
+
+    let mut entries = Vec::new();
+    for global in &module.globals {
+        entries.push(global.ty.byte());
+        entries.push(global.mutable as u8);
+        instr(&mut entries, &global.init, &type_index);
+        instr(&mut entries, &Instr::End, &type_index);
+    }
+    section(&mut out, section::GLOBAL, module.globals.len(), entries);
+
+    let mut entries = Vec::new();
+    for export in &module.exports {
+        name(&mut entries, &export.name);
+        let (kind, index) = match export.kind {
+            ExportKind::Function(index) => (0x00, index),
+            ExportKind::Memory(index) => (0x02, index),
+        };
+        entries.push(kind);
+        unsigned(&mut entries, index as u64);
+    }
+    section(&mut out, section::EXPORT, module.exports.len(), entries);
+
+    if !module.table.is_empty() {
+        let mut entries = vec![0x00];
+        instr(&mut entries, &Instr::I32Const(1), &type_index);
+        instr(&mut entries, &Instr::End, &type_index);
+        unsigned(&mut entries, module.table.len() as u64);
+        for function in &module.table {
+            unsigned(&mut entries, *function as u64);
+        }
+        section(&mut out, section::ELEMENT, 1, entries);
+    }
+
+    let mut entries = Vec::new();
+    for function in &module.functions {
+        let mut code = Vec::new();
+        // Runs of locals of the same type are declared together
+        let mut runs: Vec<(u32, _)> = Vec::new();
+        for local in &function.locals {
+            match runs.last_mut() {
+                Some((count, ty)) if ty == local => *count += 1,
+                _ => runs.push((1, *local)),
+            }
+        }
+        unsigned(&mut code, runs.len() as u64);
+        for (count, ty) in runs {
+            unsigned(&mut code, count as u64);
+            code.push(ty.byte());
+        }
+        for x in &function.body {
+            instr(&mut code, x, &type_index);
+        }
+        instr(&mut code, &Instr::End, &type_index);
+        unsigned(&mut entries, code.len() as u64);
+        entries.extend(code);
+    }
+    section(&mut out, section::CODE, module.functions.len(), entries);
+
+    let mut entries = Vec::new();
+    for data in &module.data {
+        entries.push(0x00);
+        instr(
+            &mut entries,
+            &Instr::I32Const(data.offset as i32),
+            &type_index,
+        );
+        instr(&mut entries, &Instr::End, &type_index);
+        unsigned(&mut entries, data.bytes.len() as u64);
+        entries.extend_from_slice(&data.bytes);
+    }
+    section(&mut out, section::DATA, module.data.len(), entries);
+    out
+}
@@ -0,0 +1,432 @@
+//! The functions a generated module imports from the host, implemented over the memory of the
+//! module so that any engine can run it: the engine provides a [Memory] and hands every call of
+//! an import to [call]. They behave like the C runtime of `shark-std`, and so like the interpreter
+//!
+//! A `Str` is the address of its bytes followed by their number, and a `Vec` is the address of its
+//! items followed by how many there are and how many fit, every one of them taking 8 bytes
+
+use std::io::Write;
+
+use shark_ir::runtime as ir_runtime;
+use shark_sema::format::{fill_template, split_template};
+use shark_std::runtime::Host;
+
+/// The memory of a running module
+pub trait Memory {
+    /// Reads a number of bytes from an address
+    fn read(&mut self, address: u64, length: u64) -> Result<Vec<u8>, Stop>;
+    fn write(&mut self, address: u64, bytes: &[u8]) -> Result<(), Stop>;
+    /// Gives a number of bytes of zeroed memory through the `shark_alloc` of the module
+    fn alloc(&mut self, size: i64) -> Result<u64, Stop>;
+}
+
+/// A value given to or by an import
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    I32(i32),
+    I64(i64),
+    F32(f32),
+    F64(f64),
+}
+
+/// Why an import stopped the program instead of returning
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Stop {
+    /// The program exits with a code, which is 101 after a runtime error
+    Exit(i32),
+    /// The module did something the host can not go along with, such as giving an address out of
+    /// its memory
+    Trap(String),
+}
+
+/// Gets the `Str` at an address
+fn read_str(memory: &mut impl Memory, address: u64) -> Result<Vec<u8>, Stop> {
+    let fields = memory.read(address, 16)?;
+    let bytes = u64::from_le_bytes(fields[..8].try_into().expect("8 bytes"));
+    let length = i64::from_le_bytes(fields[8..].try_into().expect("8 bytes"));
+    memory.read(bytes, length.max(0) as u64)
+}
+
+/// Gets the text of the `Str` at an address
+fn read_text(memory: &mut impl Memory, address: u64) -> Result<String, Stop> {
+    let bytes = read_str(memory, address)?;
+    String::from_utf8(bytes).map_err(|_| Stop::Trap("a `Str` is not UTF-8".to_string()))
+}
+
+/// Gets the text ending with a zero at an address
+fn read_terminated(memory: &mut impl Memory, mut address: u64) -> Result<Vec<u8>, Stop> {
+    let mut bytes = Vec::new();
+    loop {
+        let byte = memory.read(address, 1)?[0];
+        if byte == 0 {
+            return Ok(bytes);
+        }
+        bytes.push(byte);
+        address += 1;
+    }
+}
+
+/// Copies bytes into memory given by the module, followed by a zero so that C can use them too
+fn copy(memory: &mut impl Memory, bytes: &[u8]) -> Result<u64, Stop> {
+    let address = memory.alloc(bytes.len() as i64 + 1)?;
+    memory.write(address, bytes)?;
+    Ok(address)
+}
+
+fn write_u64(memory: &mut impl Memory, address: u64, values: &[u64]) -> Result<(), Stop> {
+    let bytes: Vec<u8> = values.iter().flat_map(|x| x.to_le_bytes()).collect();
+    memory.write(address, &bytes)
+}
+
+/// Stores a copy of bytes as a `Str`
+fn store_str(memory: &mut impl Memory, out: u64, bytes: &[u8]) -> Result<(), Stop> {
+    let address = copy(memory, bytes)?;
+    write_u64(memory, out, &[address, bytes.len() as u64])
+}
+
+/// Stores a `Vec` of items which are given as their bytes one after the other
+fn store_vec(memory: &mut impl Memory, out: u64, items: &[u8], count: usize) -> Result<(), Stop> {
+    let address = memory.alloc(items.len() as i64)?;
+    memory.write(address, items)?;
+    write_u64(memory, out, &[address, count as u64, count as u64])
+}
+
+/// Stores a `Vec<Str>` of copies of texts
+fn store_strs<'t>(
+    memory: &mut impl Memory,
+    out: u64,
+    texts: impl Iterator<Item = &'t str>,
+) -> Result<(), Stop> {
+    let mut items = Vec::new();
+    let mut count = 0;
+    for text in texts {
+        let address = copy(memory, text.as_bytes())?;
+        items.extend(address.to_le_bytes());
+        items.extend((text.len() as u64).to_le_bytes());
+        count += 1;
+    }
+    store_vec(memory, out, &items, count)
+}
+
+/// Gets the address of the items, the length and the capacity of the `Vec` at an address
+fn read_vec(memory: &mut impl Memory, address: u64) -> Result<(u64, u64, u64), Stop> {
+    let fields = memory.read(address, 24)?;
+    let field =
+        |index: usize| u64::from_le_bytes(fields[index * 8..][..8].try_into().expect("8 bytes"));
+    Ok((field(0), field(1), field(2)))
+}
+
+/// Makes room for one more item of a number of bytes at the end of the `Vec` at an address,
+/// giving the address of its items and its length
+fn reserve(memory: &mut impl Memory, vec: u64, size: u64) -> Result<(u64, u64), Stop> {
+    let (items, length, capacity) = read_vec(memory, vec)?;
+    if length < capacity {
+        return Ok((items, length));
+    }
+    let capacity = match capacity {
+        0 => 4,
+        _ => capacity * 2,
+    };
+    let grown = memory.alloc((capacity * size) as i64)?;
+    let bytes = memory.read(items, length * size)?;
+    memory.write(grown, &bytes)?;
+    write_u64(memory, vec, &[grown, length, capacity])?;
+    Ok((grown, length))
+}
+
+fn integer(value: Value) -> i64 {
+    match value {
+        Value::I32(x) => x as i64,
+        Value::I64(x) => x,
+        Value::F32(_) | Value::F64(_) => 0,
+    }
+}
+
+/// Stops the program with a runtime error. The message and where it happened are texts ending
+/// with a zero, and the message is followed by the `Str` at an address unless it is zero
+pub fn panic(
+    memory: &mut impl Memory,
+    host: &mut Host,
+    message: u64,
+    text: u64,
+    at: u64,
+) -> Result<(), Stop> {
+    let mut output = b"error: ".to_vec();
+    output.extend(read_terminated(memory, message)?);
+    if text != 0 {
+        output.extend(read_str(memory, text)?);
+    }
+    output.extend(b"\n --> ");
+    output.extend(read_terminated(memory, at)?);
+    output.push(b'\n');
+    let _ = host.stdout.flush();
+    let _ = host.stderr.write_all(&output);
+    let _ = host.stderr.flush();
+    Err(Stop::Exit(101))
+}
+
+/// Carries out the import of the module `shark` with a name, which is `panic` or a function of
+/// `shark_ir::runtime` without the `shark_` its name starts with
+pub fn call(
+    memory: &mut impl Memory,
+    host: &mut Host,
+    name: &str,
+    args: &[Value],
+) -> Result<Option<Value>, Stop> {
+    let arg = |index: usize| integer(args[index]);
+    let address = |index: usize| integer(args[index]) as u64;
+    let boolean = |value: bool| Ok(Some(Value::I32(value as i32)));
+    if name == "panic" {
+        panic(memory, host, address(0), address(1), address(2))?;
+        return Ok(None);
+    }
+    let Some(function) = ir_runtime::lookup(&format!("shark_{}", name)) else {
+        return Err(Stop::Trap(format!("there is no import named `{}`", name)));
+    };
+    if args.len() != function.params.len() {
+        return Err(Stop::Trap(format!(
+            "`{}` takes {} arguments but {} were given",
+            name,
+            function.params.len(),
+            args.len()
+        )));
+    }
+    match name {
+        "alloc" => return memory.alloc(arg(0)).map(|x| Some(Value::I64(x as i64))),
+        "write" => {
+            let text = read_str(memory, address(1))?;
+            let stream = match arg(0) {
+                1 => &mut host.stdout,
+                _ => &mut host.stderr,
+            };
+            let written = stream.write_all(&text).and_then(|()| stream.flush());
+            return boolean(written.is_ok());
+        }
+        "exit" => {
+            let _ = host.stdout.flush();
+            return Err(Stop::Exit(arg(0) as i32));
+        }
+        "args" => {
+            let args = host.args.clone();
+            store_strs(memory, address(0), args.iter().map(|x| x.as_str()))?;
+        }
+        "read_file" => {
+            let path = read_text(memory, address(1))?;
+            let (contents, succeeded) = match std::fs::read(&path) {
+                Ok(contents) => (contents, true),
+                Err(reason) => (reason.to_string().into_bytes(), false),
+            };
+            store_str(memory, address(0), &contents)?;
+            return boolean(succeeded);
+        }
+        "write_file" => {
+            let path = read_text(memory, address(1))?;
+            let contents = read_str(memory, address(2))?;
+            if let Err(reason) = std::fs::write(&path, contents) {
+                store_str(memory, address(0), reason.to_string().as_bytes())?;
+                return boolean(false);
+            }
+            return boolean(true);
+        }
+        "show_int" => store_str(memory, address(0), arg(1).to_string().as_bytes())?,
+        "show_uint" => {
+            let text = (arg(1) as u64).to_string();
+            store_str(memory, address(0), text.as_bytes())?;
+        }
+        "show_float32" | "show_float64" => {
+            let text = match args[1] {
+                Value::F32(x) => format!("{:?}", x),
+                Value::F64(x) => format!("{:?}", x),
+                _ => return Err(Stop::Trap(format!("`{}` takes a float", name))),
+            };
+            store_str(memory, address(0), text.as_bytes())?;
+        }
+        "show_char" => {
+            let character = char::from_u32(arg(1) as u32).unwrap_or(char::REPLACEMENT_CHARACTER);
+            store_str(memory, address(0), character.to_string().as_bytes())?;
+        }
+        "placeholders" => {
+            let template = read_text(memory, address(0))?;
+            let count = split_template(&template).map_or(-1, |x| x.len() as i64 - 1);
+            return Ok(Some(Value::I64(count)));
+        }
+        "format" => {
+            let template = read_text(memory, address(1))?;
+            let pieces = split_template(&template)
+                .map_err(|x| Stop::Trap(format!("invalid template: {}", x)))?;
+            let mut parts = Vec::new();
+            for index in 0..arg(3).max(0) as u64 {
+                parts.push(read_text(memory, address(2) + index * 16)?);
+            }
+            store_str(
+                memory,
+                address(0),
+                fill_template(&pieces, &parts).as_bytes(),
+            )?;
+        }
+        "str_compare" => {
+            let ordering = read_str(memory, address(0))?.cmp(&read_str(memory, address(1))?);
+            return Ok(Some(Value::I32(ordering as i32)));
+        }
+        "str_len" => {
+            let length = read_text(memory, address(0))?.chars().count();
+            return Ok(Some(Value::I64(length as i64)));
+        }
+        "str_concat" => {
+            let mut text = read_str(memory, address(1))?;
+            text.extend(read_str(memory, address(2))?);
+            store_str(memory, address(0), &text)?;
+        }
+        "str_find" => {
+            let haystack = read_text(memory, address(0))?;
+            let pattern = read_text(memory, address(1))?;
+            let found = haystack
+                .find(&pattern)
+                .map_or(-1, |x| haystack[..x].chars().count() as i64);
+            return Ok(Some(Value::I64(found)));
+        }
+        "str_starts_with" => {
+            let text = read_str(memory, address(0))?;
+            return boolean(text.starts_with(&read_str(memory, address(1))?));
+        }
+        "str_ends_with" => {
+            let text = read_str(memory, address(0))?;
+            return boolean(text.ends_with(&read_str(memory, address(1))?));
+        }
+        "str_slice" => {
+            let text = read_text(memory, address(1))?;
+            let (start, end) = (arg(2), arg(3));
+            if start < 0 || end < start || end > text.chars().count() as i64 {
+                return boolean(false);
+            }
+            let slice: String = text
+                .chars()
+                .skip(start as usize)
+                .take((end - start) as usize)
+                .collect();
+            store_str(memory, address(0), slice.as_bytes())?;
+            return boolean(true);
+        }
+        "str_char_at" => {
+            let text = read_text(memory, address(1))?;
+            let character = usize::try_from(arg(2))
+                .ok()
+                .and_then(|x| text.chars().nth(x));
+            let Some(character) = character else {
+                return boolean(false);
+            };
+            memory.write(address(0), &(character as u32).to_le_bytes())?;
+            return boolean(true);
+        }
+        "str_trim" | "str_to_upper" | "str_to_lower" => {
+            let text = read_text(memory, address(1))?;
+            let changed = match name {
+                "str_trim" => text.trim().to_string(),
+                "str_to_upper" => text.to_uppercase(),
+                _ => text.to_lowercase(),
+            };
+            store_str(memory, address(0), changed.as_bytes())?;
+        }
+        "str_repeat" => {
+            let count = arg(2);
+            if count < 0 {
+                return boolean(false);
+            }
+            let text = read_str(memory, address(1))?;
+            store_str(memory, address(0), &text.repeat(count as usize))?;
+            return boolean(true);
+        }
+        "str_replace" => {
+            let text = read_text(memory, address(1))?;
+            let from = read_text(memory, address(2))?;
+            let to = read_text(memory, address(3))?;
+            store_str(memory, address(0), text.replace(&from, &to).as_bytes())?;
+        }
+        "str_split" => {
+            let text = read_text(memory, address(1))?;
+            let separator = read_text(memory, address(2))?;
+            match separator.is_empty() {
+                true => {
+                    let characters: Vec<String> = text.chars().map(String::from).collect();
+                    store_strs(memory, address(0), characters.iter().map(|x| x.as_str()))?;
+                }
+                false => store_strs(memory, address(0), text.split(&separator))?,
+            }
+        }
+        "str_lines" => {
+            let text = read_text(memory, address(1))?;
+            store_strs(memory, address(0), text.lines())?;
+        }
+        "str_chars" => {
+            let text = read_text(memory, address(1))?;
+            let items: Vec<u8> = text
+                .chars()
+                .flat_map(|x| (x as u32).to_le_bytes())
+                .collect();
+            store_vec(memory, address(0), &items, text.chars().count())?;
+        }
+        "str_parse_int" => {
+            let text = read_text(memory, address(1))?;
+            let Ok(value) = text.trim().parse::<i64>() else {
+                return boolean(false);
+            };
+            memory.write(address(0), &value.to_le_bytes())?;
+            return boolean(true);
+        }
+        "str_parse_float" => {
+            let text = read_text(memory, address(1))?;
+            let Ok(value) = text.trim().parse::<f64>() else {
+                return boolean(false);
+            };
+            memory.write(address(0), &value.to_le_bytes())?;
+            return boolean(true);
+        }
+        "cstr" => {
+            let text = read_str(memory, address(0))?;
+            return copy(memory, &text).map(|x| Some(Value::I64(x as i64)));
+        }
+        "str_from_cstr" => {
+            let text = match address(1) {
+                0 => Vec::new(),
+                text => read_terminated(memory, text)?,
+            };
+            store_str(memory, address(0), &text)?;
+        }
+        "vec_push" | "vec_insert" => {
+            let (vec, size) = (address(0), arg(args.len() - 1) as u64);
+            let (item, index) = match name {
+                "vec_push" => (address(1), None),
+                _ => (address(2), Some(arg(1) as u64)),
+            };
+            let item = memory.read(item, size)?;
+            let (items, length) = reserve(memory, vec, size)?;
+            let index = index.unwrap_or(length);
+            let moved = memory.read(items + index * size, (length - index) * size)?;
+            memory.write(items + (index + 1) * size, &moved)?;
+            memory.write(items + index * size, &item)?;
+            memory.write(vec + 8, &(length + 1).to_le_bytes())?;
+        }
+        "vec_remove" => {
+            let (vec, index, out, size) = (address(0), arg(1) as u64, address(2), arg(3) as u64);
+            let (items, length, _) = read_vec(memory, vec)?;
+            let item = memory.read(items + index * size, size)?;
+            memory.write(out, &item)?;
+            let moved = memory.read(items + (index + 1) * size, (length - index - 1) * size)?;
+            memory.write(items + index * size, &moved)?;
+            memory.write(vec + 8, &(length - 1).to_le_bytes())?;
+        }
+        "vec_copy" => {
+            let size = arg(2) as u64;
+            let (items, length, _) = read_vec(memory, address(1))?;
+            let bytes = memory.read(items, length * size)?;
+            store_vec(memory, address(0), &bytes, length as usize)?;
+        }
+        _ => {
+            return Err(Stop::Trap(format!(
+                "`{}` is not implemented by the host",
+                name
+            )))
+        }
+    }
+    Ok(None)
+}
@@ -0,0 +1,79 @@
+//! Compiles the optimised IR of a program to a WebAssembly module, which can be encoded in the
+//! binary format with [encode::encode] or printed in the text format with [wat::print]. Modules
+//! are built in memory as a [module::Module] and checked by [validate::validate] before they are
+//! written anywhere
+//!
+//! A module exports its memory, its `shark_alloc` and the [ENTRY] of the program as `main`, and
+//! imports the runtime from the host under the module `shark`, see [runtime]. [host] implements
+//! those imports for any engine. The program behaves like the interpreter: arithmetic overflows,
+//! calls nest as deep and runtime errors are reported the same. Foreign functions can not be
+//! called, see [select]
+
+use std::path::Path;
+
+use module::{Data, Export, ExportKind};
+use select::Selector;
+use shark_core::diagnostic::Diagnostic;
+use shark_ir::{build::ENTRY, ir::Module};
+
+pub mod encode;
+pub mod host;
+pub mod module;
+pub mod runtime;
+pub mod select;
+pub mod validate;
+pub mod wat;
+
+#[cfg(test)]
+pub mod tests;
+
+/// Generates a WebAssembly module for a module of the IR. `path` and `source` are those of the
+/// module, which runtime errors point into
+pub fn generate(
+    module: &Module,
+    path: Option<&Path>,
+    source: &str,
+) -> Result<module::Module, Vec<Diagnostic>> {
+    let mut selector = Selector::new(module, path, source);
+    let out_of_memory = (
+        selector.texts.add(b"out of memory"),
+        selector.texts.add(b"<runtime>"),
+    );
+    let mut functions = runtime::functions(out_of_memory);
+    for (index, function) in module.functions.iter().enumerate() {
+        functions.push(selector.select(index, function));
+    }
+    if !selector.diagnostics.is_empty() {
+        return Err(selector.diagnostics);
+    }
+
+    let mut exports = vec![
+        Export {
+            name: "memory".to_string(),
+            kind: ExportKind::Memory(0),
+        },
+        Export {
+            name: "shark_alloc".to_string(),
+            kind: ExportKind::Function(runtime::ALLOC),
+        },
+    ];
+    if module.function(ENTRY).is_some() {
+        exports.push(Export {
+            name: "main".to_string(),
+            kind: ExportKind::Function(selector.index(ENTRY)),
+        });
+    }
+    let end = selector.texts.end();
+    Ok(module::Module {
+        imports: runtime::imports(),
+        functions,
+        memory: Some(runtime::pages(end)),
+        table: selector.table,
+        globals: runtime::globals(end),
+        exports,
+        data: vec![Data {
+            offset: runtime::DATA_START,
+            bytes: selector.texts.data,
+        }],
+    })
+}
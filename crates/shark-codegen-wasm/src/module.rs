@@ -0,0 +1,434 @@
+//! A WebAssembly module as it is generated, which is encoded to the binary format by [crate::encode]
+//! and printed to the text format by [crate::wat]. Only the parts of the format the backend uses
+//! are covered: functions, one memory, one table of functions, globals, exports and active data
+//! segments
+
+use std::fmt::{self, Display};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ValType {
+    I32,
+    I64,
+    F32,
+    F64,
+}
+
+impl ValType {
+    /// The byte encoding the type in the binary format
+    pub fn byte(self) -> u8 {
+        match self {
+            Self::I32 => 0x7F,
+            Self::I64 => 0x7E,
+            Self::F32 => 0x7D,
+            Self::F64 => 0x7C,
+        }
+    }
+}
+
+impl Display for ValType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::I32 => "i32",
+            Self::I64 => "i64",
+            Self::F32 => "f32",
+            Self::F64 => "f64",
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FuncType {
+    pub params: Vec<ValType>,
+    pub results: Vec<ValType>,
+}
+
+/// The type of a `block`, `loop` or `if`, which takes no operands and leaves at most one result
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockType {
+    Empty,
+    Value(ValType),
+}
+
+impl BlockType {
+    pub fn results(self) -> Vec<ValType> {
+        match self {
+            Self::Empty => Vec::new(),
+            Self::Value(ty) => vec![ty],
+        }
+    }
+}
+
+macro_rules! ops {
+    ($($op:ident = $opcode:literal $text:literal ($($param:ident),*) -> $result:ident,)*) => {
+        /// A numeric instruction, which pops its operands and pushes its result
+        #[derive(Debug, Clone, Copy, PartialEq, Eq)]
+        pub enum Op {
+            $($op,)*
+        }
+
+        impl Op {
+            pub fn opcode(self) -> u8 {
+                match self {
+                    $(Self::$op => $opcode,)*
+                }
+            }
+
+            pub fn text(self) -> &'static str {
+                match self {
+                    $(Self::$op => $text,)*
+                }
+            }
+
+            /// Gets the types of the operands and of the result
+            pub fn signature(self) -> (&'static [ValType], ValType) {
+                match self {
+                    $(Self::$op => (&[$(ValType::$param),*], ValType::$result),)*
+                }
+            }
+        }
+    };
+}
+
+ops! {
+    I32Eqz = 0x45 "i32.eqz" (I32) -> I32,
+    I32Eq = 0x46 "i32.eq" (I32, I32) -> I32,
+    I32Ne = 0x47 "i32.ne" (I32, I32) -> I32,
+    I32LtS = 0x48 "i32.lt_s" (I32, I32) -> I32,
+    I32LtU = 0x49 "i32.lt_u" (I32, I32) -> I32,
+    I32GtS = 0x4A "i32.gt_s" (I32, I32) -> I32,
+    I32GtU = 0x4B "i32.gt_u" (I32, I32) -> I32,
+    I32LeS = 0x4C "i32.le_s" (I32, I32) -> I32,
+    I32LeU = 0x4D "i32.le_u" (I32, I32) -> I32,
+    I32GeS = 0x4E "i32.ge_s" (I32, I32) -> I32,
+    I32GeU = 0x4F "i32.ge_u" (I32, I32) -> I32,
+    I64Eqz = 0x50 "i64.eqz" (I64) -> I32,
+    I64Eq = 0x51 "i64.eq" (I64, I64) -> I32,
+    I64Ne = 0x52 "i64.ne" (I64, I64) -> I32,
+    I64LtS = 0x53 "i64.lt_s" (I64, I64) -> I32,
+    I64LtU = 0x54 "i64.lt_u" (I64, I64) -> I32,
+    I64GtS = 0x55 "i64.gt_s" (I64, I64) -> I32,
+    I64GtU = 0x56 "i64.gt_u" (I64, I64) -> I32,
+    I64LeS = 0x57 "i64.le_s" (I64, I64) -> I32,
+    I64LeU = 0x58 "i64.le_u" (I64, I64) -> I32,
+    I64GeS = 0x59 "i64.ge_s" (I64, I64) -> I32,
+    I64GeU = 0x5A "i64.ge_u" (I64, I64) -> I32,
+    F32Eq = 0x5B "f32.eq" (F32, F32) -> I32,
+    F32Ne = 0x5C "f32.ne" (F32, F32) -> I32,
+    F32Lt = 0x5D "f32.lt" (F32, F32) -> I32,
+    F32Gt = 0x5E "f32.gt" (F32, F32) -> I32,
+    F32Le = 0x5F "f32.le" (F32, F32) -> I32,
+    F32Ge = 0x60 "f32.ge" (F32, F32) -> I32,
+    F64Eq = 0x61 "f64.eq" (F64, F64) -> I32,
+    F64Ne = 0x62 "f64.ne" (F64, F64) -> I32,
+    F64Lt = 0x63 "f64.lt" (F64, F64) -> I32,
+    F64Gt = 0x64 "f64.gt" (F64, F64) -> I32,
+    F64Le = 0x65 "f64.le" (F64, F64) -> I32,
+    F64Ge = 0x66 "f64.ge" (F64, F64) -> I32,
+    I32Add = 0x6A "i32.add" (I32, I32) -> I32,
+    I32Sub = 0x6B "i32.sub" (I32, I32) -> I32,
+    I32Mul = 0x6C "i32.mul" (I32, I32) -> I32,
+    I32DivS = 0x6D "i32.div_s" (I32, I32) -> I32,
+    I32DivU = 0x6E "i32.div_u" (I32, I32) -> I32,
+    I32And = 0x71 "i32.and" (I32, I32) -> I32,
+    I32Or = 0x72 "i32.or" (I32, I32) -> I32,
+    I32Xor = 0x73 "i32.xor" (I32, I32) -> I32,
+    I32Shl = 0x74 "i32.shl" (I32, I32) -> I32,
+    I32ShrS = 0x75 "i32.shr_s" (I32, I32) -> I32,
+    I32ShrU = 0x76 "i32.shr_u" (I32, I32) -> I32,
+    I64Add = 0x7C "i64.add" (I64, I64) -> I64,
+    I64Sub = 0x7D "i64.sub" (I64, I64) -> I64,
+    I64Mul = 0x7E "i64.mul" (I64, I64) -> I64,
+    I64DivS = 0x7F "i64.div_s" (I64, I64) -> I64,
+    I64DivU = 0x80 "i64.div_u" (I64, I64) -> I64,
+    I64And = 0x83 "i64.and" (I64, I64) -> I64,
+    I64Or = 0x84 "i64.or" (I64, I64) -> I64,
+    I64Xor = 0x85 "i64.xor" (I64, I64) -> I64,
+    I64Shl = 0x86 "i64.shl" (I64, I64) -> I64,
+    I64ShrS = 0x87 "i64.shr_s" (I64, I64) -> I64,
+    I64ShrU = 0x88 "i64.shr_u" (I64, I64) -> I64,
+    F32Neg = 0x8C "f32.neg" (F32) -> F32,
+    F32Add = 0x92 "f32.add" (F32, F32) -> F32,
+    F32Sub = 0x93 "f32.sub" (F32, F32) -> F32,
+    F32Mul = 0x94 "f32.mul" (F32, F32) -> F32,
+    F32Div = 0x95 "f32.div" (F32, F32) -> F32,
+    F64Neg = 0x9A "f64.neg" (F64) -> F64,
+    F64Add = 0xA0 "f64.add" (F64, F64) -> F64,
+    F64Sub = 0xA1 "f64.sub" (F64, F64) -> F64,
+    F64Mul = 0xA2 "f64.mul" (F64, F64) -> F64,
+    F64Div = 0xA3 "f64.div" (F64, F64) -> F64,
+    I32WrapI64 = 0xA7 "i32.wrap_i64" (I64) -> I32,
+    I32TruncF64S = 0xAA "i32.trunc_f64_s" (F64) -> I32,
+    I32TruncF64U = 0xAB "i32.trunc_f64_u" (F64) -> I32,
+    I64ExtendI32S = 0xAC "i64.extend_i32_s" (I32) -> I64,
+    I64ExtendI32U = 0xAD "i64.extend_i32_u" (I32) -> I64,
+    I64TruncF64S = 0xB0 "i64.trunc_f64_s" (F64) -> I64,
+    I64TruncF64U = 0xB1 "i64.trunc_f64_u" (F64) -> I64,
+    F32ConvertI32S = 0xB2 "f32.convert_i32_s" (I32) -> F32,
+    F32ConvertI32U = 0xB3 "f32.convert_i32_u" (I32) -> F32,
+    F32ConvertI64S = 0xB4 "f32.convert_i64_s" (I64) -> F32,
+    F32ConvertI64U = 0xB5 "f32.convert_i64_u" (I64) -> F32,
+    F32DemoteF64 = 0xB6 "f32.demote_f64" (F64) -> F32,
+    F64ConvertI32S = 0xB7 "f64.convert_i32_s" (I32) -> F64,
+    F64ConvertI32U = 0xB8 "f64.convert_i32_u" (I32) -> F64,
+    F64ConvertI64S = 0xB9 "f64.convert_i64_s" (I64) -> F64,
+    F64ConvertI64U = 0xBA "f64.convert_i64_u" (I64) -> F64,
+    F64PromoteF32 = 0xBB "f64.promote_f32" (F32) -> F64,
+}
+
+/// How a value is read from memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Load {
+    I32,
+    I64,
+    F32,
+    F64,
+    /// Reads a byte, sign extending it to an `i32`
+    I32Int8,
+    /// Reads a byte, zero extending it to an `i32`
+    I32UInt8,
+}
+
+impl Load {
+    pub fn opcode(self) -> u8 {
+        match self {
+            Self::I32 => 0x28,
+            Self::I64 => 0x29,
+            Self::F32 => 0x2A,
+            Self::F64 => 0x2B,
+            Self::I32Int8 => 0x2C,
+            Self::I32UInt8 => 0x2D,
+        }
+    }
+
+    pub fn text(self) -> &'static str {
+        match self {
+            Self::I32 => "i32.load",
+            Self::I64 => "i64.load",
+            Self::F32 => "f32.load",
+            Self::F64 => "f64.load",
+            Self::I32Int8 => "i32.load8_s",
+            Self::I32UInt8 => "i32.load8_u",
+        }
+    }
+
+    pub fn ty(self) -> ValType {
+        match self {
+            Self::I32 | Self::I32Int8 | Self::I32UInt8 => ValType::I32,
+            Self::I64 => ValType::I64,
+            Self::F32 => ValType::F32,
+            Self::F64 => ValType::F64,
+        }
+    }
+
+    /// The alignment of the access as a power of two, which is its size
+    pub fn align(self) -> u32 {
+        match self {
+            Self::I32 | Self::F32 => 2,
+            Self::I64 | Self::F64 => 3,
+            Self::I32Int8 | Self::I32UInt8 => 0,
+        }
+    }
+}
+
+/// How a value is written to memory
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Store {
+    I32,
+    I64,
+    F32,
+    F64,
+    /// Writes the low byte of an `i32`
+    I32Byte,
+}
+
+impl Store {
+    pub fn opcode(self) -> u8 {
+        match self {
+            Self::I32 => 0x36,
+            Self::I64 => 0x37,
+            Self::F32 => 0x38,
+            Self::F64 => 0x39,
+            Self::I32Byte => 0x3A,
+        }
+    }
+
+    pub fn text(self) -> &'static str {
+        match self {
+            Self::I32 => "i32.store",
+            Self::I64 => "i64.store",
+            Self::F32 => "f32.store",
+            Self::F64 => "f64.store",
+            Self::I32Byte => "i32.store8",
+        }
+    }
+
+    pub fn ty(self) -> ValType {
+        match self {
+            Self::I32 | Self::I32Byte => ValType::I32,
+            Self::I64 => ValType::I64,
+            Self::F32 => ValType::F32,
+            Self::F64 => ValType::F64,
+        }
+    }
+
+    /// The alignment of the access as a power of two, which is its size
+    pub fn align(self) -> u32 {
+        match self {
+            Self::I32 | Self::F32 => 2,
+            Self::I64 | Self::F64 => 3,
+            Self::I32Byte => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Instr {
+    Unreachable,
+    Block(BlockType),
+    Loop(BlockType),
+    If(BlockType),
+    Else,
+    End,
+    /// Branches to the label this many blocks out
+    Br(u32),
+    BrIf(u32),
+    /// Branches to the label of the operand, or to the default label if it is out of range
+    BrTable(Vec<u32>, u32),
+    Return,
+    Call(u32),
+    /// Calls the function at the index of the table on the stack, which must have this signature
+    CallIndirect(FuncType),
+    Drop,
+    Select,
+    LocalGet(u32),
+    LocalSet(u32),
+    LocalTee(u32),
+    GlobalGet(u32),
+    GlobalSet(u32),
+    /// Reads from the address on the stack plus an offset
+    Load(Load, u32),
+    /// Writes to the address below the value on the stack plus an offset
+    Store(Store, u32),
+    /// Pushes the number of pages of memory
+    MemorySize,
+    /// Grows memory by the number of pages on the stack, pushing the number of pages it had or -1
+    /// if it could not grow
+    MemoryGrow,
+    I32Const(i32),
+    I64Const(i64),
+    /// Pushes the `f32` with these bits
+    F32Const(u32),
+    /// Pushes the `f64` with these bits
+    F64Const(u64),
+    Op(Op),
+}
+
+/// A function imported from the host
+#[derive(Debug, Clone)]
+pub struct Import {
+    pub module: String,
+    pub name: String,
+    /// The name the function is given within the module
+    pub function_name: String,
+    pub signature: FuncType,
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    pub signature: FuncType,
+    /// The types of the locals after the parameters
+    pub locals: Vec<ValType>,
+    /// The instructions of the function, without the `end` closing it
+    pub body: Vec<Instr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Global {
+    pub name: String,
+    pub ty: ValType,
+    pub mutable: bool,
+    /// The constant instruction giving the initial value
+    pub init: Instr,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportKind {
+    Function(u32),
+    Memory(u32),
+}
+
+#[derive(Debug, Clone)]
+pub struct Export {
+    pub name: String,
+    pub kind: ExportKind,
+}
+
+/// Bytes copied into memory at an offset when the module is instantiated
+#[derive(Debug, Clone)]
+pub struct Data {
+    pub offset: u32,
+    pub bytes: Vec<u8>,
+}
+
+/// The size of a page of memory, in bytes
+pub const PAGE_SIZE: u32 = 65536;
+
+#[derive(Debug, Clone, Default)]
+pub struct Module {
+    /// The imported functions, which come first in the index space of functions
+    pub imports: Vec<Import>,
+    pub functions: Vec<Function>,
+    /// The number of pages of the memory, if the module has one
+    pub memory: Option<u32>,
+    /// The functions `call_indirect` can call, which are in the table from index one on so that
+    /// zero is never the index of a function
+    pub table: Vec<u32>,
+    pub globals: Vec<Global>,
+    pub exports: Vec<Export>,
+    pub data: Vec<Data>,
+}
+
+impl Module {
+    /// Gets every distinct function type, in the order they are first used by an import, a
+    /// function or a `call_indirect`
+    pub fn types(&self) -> Vec<FuncType> {
+        let mut types: Vec<FuncType> = Vec::new();
+        let signatures = self.imports.iter().map(|x| &x.signature);
+        let indirect = self
+            .functions
+            .iter()
+            .flat_map(|x| &x.body)
+            .filter_map(|x| match x {
+                Instr::CallIndirect(signature) => Some(signature),
+                _ => None,
+            });
+        for signature in signatures
+            .chain(self.functions.iter().map(|x| &x.signature))
+            .chain(indirect)
+        {
+            if !types.contains(signature) {
+                types.push(signature.clone());
+            }
+        }
+        types
+    }
+
+    /// Gets the signature of the function with an index, counting imports first
+    pub fn signature(&self, index: u32) -> Option<&FuncType> {
+        let index = index as usize;
+        match index.checked_sub(self.imports.len()) {
+            None => Some(&self.imports[index].signature),
+            Some(index) => self.functions.get(index).map(|x| &x.signature),
+        }
+    }
+
+    /// Gets the name of the function with an index, counting imports first
+    pub fn function_name(&self, index: u32) -> Option<&str> {
+        let index = index as usize;
+        match index.checked_sub(self.imports.len()) {
+            None => Some(&self.imports[index].function_name),
+            Some(index) => self.functions.get(index).map(|x| x.name.as_str()),
+        }
+    }
+}
@@ -0,0 +1,337 @@
+//! What every generated module includes besides its functions. The functions of
+//! `shark_ir::runtime` are imported from the host under the module `shark`, without the `shark_`
+//! their names start with, except for `shark_alloc`, which the module defines and exports so that
+//! the host can allocate too. See [crate::host] for a host implementing them. Runtime errors are
+//! reported through the imported `shark.panic`, which must not return
+//!
+//! Memory holds the texts of the program from [DATA_START], followed by a stack growing down
+//! which holds the frames of functions, followed by memory given by `shark_alloc`, which grows
+//! memory when it runs out and never frees anything
+//!
+//! Checking that a product of 64-bit integers does not overflow takes a division, so it is done by
+//! helper functions rather than at every multiplication
+
+use shark_ir::{
+    ir::Type,
+    runtime::{self as ir_runtime, RuntimeFunction},
+};
+
+use crate::module::{
+    BlockType, FuncType, Function, Global, Import, Instr, Load, Op, Store, ValType, PAGE_SIZE,
+};
+
+/// The index of the imported function reporting a runtime error
+pub const PANIC: u32 = 0;
+/// How many functions of the runtime are imported, which is all of them but `shark_alloc`
+const RUNTIME_IMPORTS: u32 = ir_runtime::FUNCTIONS.len() as u32 - 1;
+/// The index of the helper checking if a product of two `Int64`s overflows
+pub const MUL_OVERFLOWS_INT64: u32 = RUNTIME_IMPORTS + 1;
+/// The index of the helper checking if a product of two `UInt64`s overflows
+pub const MUL_OVERFLOWS_UINT64: u32 = RUNTIME_IMPORTS + 2;
+/// The index of `shark_alloc`
+pub const ALLOC: u32 = RUNTIME_IMPORTS + 3;
+/// The index of the helper copying bytes between places which may overlap
+pub const MEMMOVE: u32 = RUNTIME_IMPORTS + 4;
+/// The index of the first function of the program
+pub const FIRST_FUNCTION: u32 = RUNTIME_IMPORTS + 5;
+
+/// The global holding the address of the top of the stack
+pub const STACK_POINTER: u32 = 0;
+/// The global holding how many calls deep the program is
+pub const DEPTH: u32 = 1;
+/// The global holding the address `shark_alloc` gives next
+pub const HEAP: u32 = 2;
+
+/// How deep calls can be nested before it is reported as a stack overflow, which is the same as
+/// for the interpreter
+pub const MAX_DEPTH: i32 = 2048;
+
+/// The address the texts of the program start at, so that no value is ever at address zero
+pub const DATA_START: u32 = 8;
+/// How many bytes the stack takes
+pub const STACK_SIZE: u32 = 16 * PAGE_SIZE;
+/// How many pages memory grows by at once
+const GROWTH: i32 = 16;
+
+/// Gets the type a value of a type of the IR is within the module. Addresses are `i64`s, as they
+/// take 8 bytes in memory, and are wrapped to `i32`s to be used
+pub fn val_type(ty: Type) -> ValType {
+    match ty {
+        Type::Int64 | Type::UInt64 | Type::Ptr => ValType::I64,
+        Type::Float32 => ValType::F32,
+        Type::Float64 => ValType::F64,
+        _ => ValType::I32,
+    }
+}
+
+/// Gets the signature a function of the runtime has within the module
+pub fn signature_of(function: &RuntimeFunction) -> FuncType {
+    FuncType {
+        params: function.params.iter().map(|x| val_type(*x)).collect(),
+        results: function.return_type.map(val_type).into_iter().collect(),
+    }
+}
+
+/// Gets the functions of the runtime the module imports, in the order of their indices after
+/// [PANIC]
+fn imported() -> impl Iterator<Item = &'static RuntimeFunction> {
+    ir_runtime::FUNCTIONS
+        .iter()
+        .filter(|x| x.name != ir_runtime::ALLOC.name)
+}
+
+/// Gets the index of a function of the runtime
+pub fn index_of(name: &str) -> Option<u32> {
+    if name == ir_runtime::ALLOC.name {
+        return Some(ALLOC);
+    }
+    imported()
+        .position(|x| x.name == name)
+        .map(|x| x as u32 + 1)
+}
+
+pub fn imports() -> Vec<Import> {
+    let panic = Import {
+        module: "shark".to_string(),
+        name: "panic".to_string(),
+        function_name: "shark_panic".to_string(),
+        signature: FuncType {
+            params: vec![ValType::I32, ValType::I64, ValType::I32],
+            results: Vec::new(),
+        },
+    };
+    let functions = imported().map(|function| Import {
+        module: "shark".to_string(),
+        name: function.name["shark_".len()..].to_string(),
+        function_name: function.name.to_string(),
+        signature: signature_of(function),
+    });
+    std::iter::once(panic).chain(functions).collect()
+}
+
+/// Gets where the stack starts and `shark_alloc` starts giving memory from, once the texts of the
+/// program end at an address
+pub fn heap_start(data_end: u32) -> u32 {
+    data_end.next_multiple_of(16) + STACK_SIZE
+}
+
+/// Gets how many pages memory starts with
+pub fn pages(data_end: u32) -> u32 {
+    heap_start(data_end) / PAGE_SIZE + GROWTH as u32
+}
+
+pub fn globals(data_end: u32) -> Vec<Global> {
+    let heap = heap_start(data_end) as i32;
+    vec![
+        Global {
+            name: "shark_stack_pointer".to_string(),
+            ty: ValType::I32,
+            mutable: true,
+            init: Instr::I32Const(heap),
+        },
+        Global {
+            name: "shark_depth".to_string(),
+            ty: ValType::I32,
+            mutable: true,
+            init: Instr::I32Const(0),
+        },
+        Global {
+            name: "shark_heap".to_string(),
+            ty: ValType::I32,
+            mutable: true,
+            init: Instr::I32Const(heap),
+        },
+    ]
+}
+
+/// Checks if the product of the two operands is not the product wrapped to 64 bits, by dividing it
+/// by the first operand when that is not zero
+fn product_differs(divide: Op) -> Vec<Instr> {
+    vec![
+        Instr::LocalGet(0),
+        Instr::Op(Op::I64Eqz),
+        Instr::If(BlockType::Value(ValType::I32)),
+        Instr::I32Const(0),
+        Instr::Else,
+        Instr::LocalGet(0),
+        Instr::LocalGet(1),
+        Instr::Op(Op::I64Mul),
+        Instr::LocalGet(0),
+        Instr::Op(divide),
+        Instr::LocalGet(1),
+        Instr::Op(Op::I64Ne),
+        Instr::End,
+    ]
+}
+
+/// Gives a number of bytes aligned to 16 from the end of what was given before, growing memory
+/// until they fit. Memory is zero until it is first given, and is never given twice
+fn alloc(out_of_memory: (u32, u32)) -> Function {
+    let (message, at) = out_of_memory;
+    let result = 1;
+    let body = vec![
+        Instr::GlobalGet(HEAP),
+        Instr::LocalTee(result),
+        Instr::LocalGet(0),
+        Instr::Op(Op::I32WrapI64),
+        Instr::I32Const(15),
+        Instr::Op(Op::I32Add),
+        Instr::I32Const(-16),
+        Instr::Op(Op::I32And),
+        Instr::Op(Op::I32Add),
+        Instr::GlobalSet(HEAP),
+        Instr::Block(BlockType::Empty),
+        Instr::Loop(BlockType::Empty),
+        Instr::GlobalGet(HEAP),
+        Instr::MemorySize,
+        Instr::I32Const(16),
+        Instr::Op(Op::I32Shl),
+        Instr::Op(Op::I32LeU),
+        Instr::BrIf(1),
+        Instr::I32Const(GROWTH),
+        Instr::MemoryGrow,
+        Instr::I32Const(-1),
+        Instr::Op(Op::I32Eq),
+        Instr::If(BlockType::Empty),
+        Instr::I32Const(message as i32),
+        Instr::I64Const(0),
+        Instr::I32Const(at as i32),
+        Instr::Call(PANIC),
+        Instr::Unreachable,
+        Instr::End,
+        Instr::Br(0),
+        Instr::End,
+        Instr::End,
+        Instr::LocalGet(result),
+        Instr::Op(Op::I64ExtendI32U),
+    ];
+    Function {
+        name: ir_runtime::ALLOC.name.to_string(),
+        signature: signature_of(&ir_runtime::ALLOC),
+        locals: vec![ValType::I32],
+        body,
+    }
+}
+
+/// Copies a number of bytes one at a time, from the first when the copy is below the original and
+/// from the last otherwise, so that bytes are read before they are written over
+fn memmove() -> Function {
+    let (to, from, length) = (3, 4, 5);
+    let mut body = Vec::new();
+    for (param, local) in [(0, to), (1, from), (2, length)] {
+        body.extend([
+            Instr::LocalGet(param),
+            Instr::Op(Op::I32WrapI64),
+            Instr::LocalSet(local),
+        ]);
+    }
+    // The address of the byte `length` bytes past an address, once `length` is decremented
+    let past = |address| {
+        [
+            Instr::LocalGet(address),
+            Instr::LocalGet(length),
+            Instr::Op(Op::I32Add),
+        ]
+    };
+    let decrement = [
+        Instr::LocalGet(length),
+        Instr::I32Const(1),
+        Instr::Op(Op::I32Sub),
+        Instr::LocalSet(length),
+    ];
+    let copy = |body: &mut Vec<Instr>, forward: bool| {
+        body.extend([
+            Instr::Block(BlockType::Empty),
+            Instr::Loop(BlockType::Empty),
+            Instr::LocalGet(length),
+            Instr::Op(Op::I32Eqz),
+            Instr::BrIf(1),
+        ]);
+        body.extend(decrement.clone());
+        match forward {
+            true => {
+                body.extend([
+                    Instr::LocalGet(to),
+                    Instr::LocalGet(from),
+                    Instr::Load(Load::I32UInt8, 0),
+                    Instr::Store(Store::I32Byte, 0),
+                ]);
+                for local in [to, from] {
+                    body.extend([
+                        Instr::LocalGet(local),
+                        Instr::I32Const(1),
+                        Instr::Op(Op::I32Add),
+                        Instr::LocalSet(local),
+                    ]);
+                }
+            }
+            false => {
+                body.extend(past(to));
+                body.extend(past(from));
+                body.extend([
+                    Instr::Load(Load::I32UInt8, 0),
+                    Instr::Store(Store::I32Byte, 0),
+                ]);
+            }
+        }
+        body.extend([Instr::Br(0), Instr::End, Instr::End]);
+    };
+    body.extend([
+        Instr::LocalGet(to),
+        Instr::LocalGet(from),
+        Instr::Op(Op::I32LeU),
+        Instr::If(BlockType::Empty),
+    ]);
+    copy(&mut body, true);
+    body.push(Instr::Else);
+    copy(&mut body, false);
+    body.push(Instr::End);
+    Function {
+        name: "shark_memmove".to_string(),
+        signature: FuncType {
+            params: vec![ValType::I64; 3],
+            results: Vec::new(),
+        },
+        locals: vec![ValType::I32; 3],
+        body,
+    }
+}
+
+/// Gets the helper functions, in the order of their indices. `shark_alloc` reports running out of
+/// memory with the texts at the addresses given
+pub fn functions(out_of_memory: (u32, u32)) -> Vec<Function> {
+    let signature = FuncType {
+        params: vec![ValType::I64, ValType::I64],
+        results: vec![ValType::I32],
+    };
+    // Dividing by -1 overflows for the smallest value, which is the only product with -1 which
+    // overflows anyway
+    let mut signed = vec![
+        Instr::LocalGet(0),
+        Instr::I64Const(-1),
+        Instr::Op(Op::I64Eq),
+        Instr::If(BlockType::Value(ValType::I32)),
+        Instr::LocalGet(1),
+        Instr::I64Const(i64::MIN),
+        Instr::Op(Op::I64Eq),
+        Instr::Else,
+    ];
+    signed.extend(product_differs(Op::I64DivS));
+    signed.push(Instr::End);
+    vec![
+        Function {
+            name: "shark_mul_overflows_int64".to_string(),
+            signature: signature.clone(),
+            locals: Vec::new(),
+            body: signed,
+        },
+        Function {
+            name: "shark_mul_overflows_uint64".to_string(),
+            signature,
+            locals: Vec::new(),
+            body: product_differs(Op::I64DivU),
+        },
+        alloc(out_of_memory),
+        memmove(),
+    ]
+}
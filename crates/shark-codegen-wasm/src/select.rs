@@ -0,0 +1,1242 @@
+//! Selects WebAssembly instructions for every function of the IR. Every value is a WebAssembly
+//! local, and the parameters of the entry are the parameters of the function. Every `slot` and
+//! `alloca` is a place in the frame of the function, which is on the stack in memory so that its
+//! address can be taken
+//!
+//! `Int32`, `Int64`, `Float32` and `Float64` map directly to `i32`, `i64`, `f32` and `f64`, and
+//! addresses are `i64`s. The other integers are kept in an `i32` sign or zero extended according
+//! to their type, with arithmetic on `Int8` and `UInt8` masked back to their width. Arithmetic
+//! overflows as it does in the interpreter, by doing it on 64 bits and checking the range of the
+//! result for narrower integers
+//!
+//! Functions with more than one block dispatch on the block to run next within a loop, as
+//! WebAssembly only has structured control flow. The address of a function is its index in the
+//! table. Foreign functions are reported, as only the C backend can call them
+
+use std::{collections::HashMap, path::Path};
+
+use shark_core::{
+    diagnostic::Diagnostic,
+    source::{LineIndex, Span},
+};
+use shark_ir::ir::{self, BinaryOp, InstKind, Module, Target, Terminator, Type, UnaryOp, Value};
+use shark_lex::token::LiteralKind;
+use shark_sema::numeric::Overflow;
+
+use crate::{
+    module::{BlockType, FuncType, Function, Instr, Load, Op, Store, ValType},
+    runtime::{self, val_type},
+};
+
+/// The texts of the program, which are placed in memory from [runtime::DATA_START] on: the bytes
+/// of its strings and the texts of runtime errors, each followed by a zero byte
+#[derive(Debug, Clone, Default)]
+pub struct Texts {
+    addresses: HashMap<Vec<u8>, u32>,
+    pub data: Vec<u8>,
+}
+
+impl Texts {
+    /// Gets the address of a text
+    pub fn add(&mut self, text: &[u8]) -> u32 {
+        if let Some(address) = self.addresses.get(text) {
+            return *address;
+        }
+        let address = runtime::DATA_START + self.data.len() as u32;
+        self.data.extend_from_slice(text);
+        self.data.push(0);
+        self.addresses.insert(text.to_vec(), address);
+        address
+    }
+
+    /// Gets the address where memory after the texts starts
+    pub fn end(&self) -> u32 {
+        runtime::DATA_START + self.data.len() as u32
+    }
+}
+
+pub struct Selector<'g> {
+    module: &'g Module,
+    path: Option<&'g Path>,
+    line_index: LineIndex<'g>,
+    /// The index of every function of the module, by the name the IR gives it
+    indices: HashMap<&'g str, u32>,
+    /// The functions whose address is taken, which is one more than their index here
+    pub table: Vec<u32>,
+    pub texts: Texts,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// How the values of a type are represented
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    /// An integer of some width, which also covers `Char` and addresses
+    Int {
+        bits: u8,
+        signed: bool,
+    },
+    /// A `Bool`, which is zero or one
+    Bool,
+    Float32,
+    Float64,
+}
+
+/// Gets the class of the values of a type
+pub fn class_of(ty: Type) -> Class {
+    let int = |bits, signed| Class::Int { bits, signed };
+    match ty {
+        Type::Int8 => int(8, true),
+        Type::UInt8 => int(8, false),
+        Type::Int32 => int(32, true),
+        Type::UInt32 | Type::Char => int(32, false),
+        Type::Int64 => int(64, true),
+        Type::UInt64 | Type::Ptr => int(64, false),
+        Type::Float32 => Class::Float32,
+        Type::Float64 => Class::Float64,
+        Type::Bool => Class::Bool,
+    }
+}
+
+impl Class {
+    pub fn val_type(self) -> ValType {
+        match self {
+            Self::Int { bits: 64, .. } => ValType::I64,
+            Self::Int { .. } | Self::Bool => ValType::I32,
+            Self::Float32 => ValType::F32,
+            Self::Float64 => ValType::F64,
+        }
+    }
+
+    fn load(self) -> Load {
+        match self {
+            Self::Int { bits: 8, signed } => match signed {
+                true => Load::I32Int8,
+                false => Load::I32UInt8,
+            },
+            Self::Bool => Load::I32UInt8,
+            Self::Int { bits: 64, .. } => Load::I64,
+            Self::Int { .. } => Load::I32,
+            Self::Float32 => Load::F32,
+            Self::Float64 => Load::F64,
+        }
+    }
+
+    fn store(self) -> Store {
+        match self {
+            Self::Int { bits: 8, .. } | Self::Bool => Store::I32Byte,
+            Self::Int { bits: 64, .. } => Store::I64,
+            Self::Int { .. } => Store::I32,
+            Self::Float32 => Store::F32,
+            Self::Float64 => Store::F64,
+        }
+    }
+}
+
+/// Gets the signature a function of the module has
+pub fn signature_of(function: &ir::Function) -> FuncType {
+    FuncType {
+        params: function.params().into_iter().map(val_type).collect(),
+        results: function.return_type.map(val_type).into_iter().collect(),
+    }
+}
+
+/// Gets the instruction pushing a constant which is not a string
+fn literal(literal: &LiteralKind) -> Instr {
+    match *literal {
+        LiteralKind::UInt8(x) => Instr::I32Const(x as i32),
+        LiteralKind::Int8(x) => Instr::I32Const(x as i32),
+        LiteralKind::UInt32(x) => Instr::I32Const(x as i32),
+        LiteralKind::Int32(x) => Instr::I32Const(x),
+        LiteralKind::UInt64(x) => Instr::I64Const(x as i64),
+        LiteralKind::Int64(x) => Instr::I64Const(x),
+        LiteralKind::Float32(x) => Instr::F32Const(x.to_bits()),
+        LiteralKind::Float64(x) => Instr::F64Const(x.to_bits()),
+        LiteralKind::Char(x) => Instr::I32Const(x as i32),
+        LiteralKind::Boolean(x) => Instr::I32Const(x as i32),
+        LiteralKind::Str(_) => unreachable!("strings are addresses"),
+    }
+}
+
+/// Gets the smallest and largest values of an integer class, as the bits of an `i64`
+fn limits(bits: u8, signed: bool) -> (i64, i64) {
+    match (signed, bits) {
+        (true, _) => (-1i64 << (bits - 1), !(-1i64 << (bits - 1))),
+        (false, 64) => (0, -1),
+        (false, _) => (0, (1i64 << bits) - 1),
+    }
+}
+
+/// Gets the instruction comparing two values of a class
+fn compare(op: BinaryOp, class: Class) -> Op {
+    use BinaryOp::*;
+    match (class, op) {
+        (Class::Float32, Gt) => Op::F32Gt,
+        (Class::Float32, Lt) => Op::F32Lt,
+        (Class::Float32, Ge) => Op::F32Ge,
+        (Class::Float32, Le) => Op::F32Le,
+        (Class::Float32, Eq) => Op::F32Eq,
+        (Class::Float32, _) => Op::F32Ne,
+        (Class::Float64, Gt) => Op::F64Gt,
+        (Class::Float64, Lt) => Op::F64Lt,
+        (Class::Float64, Ge) => Op::F64Ge,
+        (Class::Float64, Le) => Op::F64Le,
+        (Class::Float64, Eq) => Op::F64Eq,
+        (Class::Float64, _) => Op::F64Ne,
+        (Class::Int { bits: 64, signed }, _) => match (op, signed) {
+            (Gt, true) => Op::I64GtS,
+            (Gt, false) => Op::I64GtU,
+            (Lt, true) => Op::I64LtS,
+            (Lt, false) => Op::I64LtU,
+            (Ge, true) => Op::I64GeS,
+            (Ge, false) => Op::I64GeU,
+            (Le, true) => Op::I64LeS,
+            (Le, false) => Op::I64LeU,
+            (Eq, _) => Op::I64Eq,
+            _ => Op::I64Ne,
+        },
+        _ => {
+            let signed = matches!(class, Class::Int { signed: true, .. });
+            match (op, signed) {
+                (Gt, true) => Op::I32GtS,
+                (Gt, false) => Op::I32GtU,
+                (Lt, true) => Op::I32LtS,
+                (Lt, false) => Op::I32LtU,
+                (Ge, true) => Op::I32GeS,
+                (Ge, false) => Op::I32GeU,
+                (Le, true) => Op::I32LeS,
+                (Le, false) => Op::I32LeU,
+                (Eq, _) => Op::I32Eq,
+                _ => Op::I32Ne,
+            }
+        }
+    }
+}
+
+/// Turns the name of a function into a name the text format takes, keeping its letters and
+/// digits so that the module can still be followed
+fn function_name(index: usize, name: &str) -> String {
+    let mut result = format!("shark_{}_", index);
+    for character in name.chars() {
+        match character.is_ascii_alphanumeric() {
+            true => result.push(character),
+            false if !result.ends_with('_') => result.push('_'),
+            false => {}
+        }
+    }
+    result.trim_end_matches('_').to_string()
+}
+
+impl<'g> Selector<'g> {
+    pub fn new(module: &'g Module, path: Option<&'g Path>, source: &'g str) -> Self {
+        let indices = module
+            .functions
+            .iter()
+            .enumerate()
+            .map(|(index, function)| {
+                let index = runtime::FIRST_FUNCTION + index as u32;
+                (function.name.as_str(), index)
+            })
+            .collect();
+        Self {
+            module,
+            path,
+            line_index: LineIndex::new(source),
+            indices,
+            table: Vec::new(),
+            texts: Texts::default(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Gets the index of a function of the module
+    pub fn index(&self, name: &str) -> u32 {
+        self.indices[name]
+    }
+
+    /// Selects the instructions of a function
+    pub fn select(&mut self, index: usize, function: &ir::Function) -> Function {
+        let signature = signature_of(function);
+        let mut locals = vec![None; function.next_value as usize];
+        let mut classes = vec![Class::Bool; function.next_value as usize];
+        let mut local_types = signature.params.clone();
+        for (position, (param, ty)) in function.blocks[0].params.iter().enumerate() {
+            locals[param.0 as usize] = Some(position as u32);
+            classes[param.0 as usize] = class_of(*ty);
+        }
+        let defined = function
+            .blocks
+            .iter()
+            .enumerate()
+            .flat_map(|(index, block)| {
+                let params = block.params.iter().filter(move |_| index > 0);
+                params.chain(block.insts.iter().filter_map(|x| x.result.as_ref()))
+            });
+        for (value, ty) in defined {
+            locals[value.0 as usize] = Some(local_types.len() as u32);
+            classes[value.0 as usize] = class_of(*ty);
+            local_types.push(val_type(*ty));
+        }
+
+        // Every `slot` and `alloca` gets its offset within the frame up front
+        let mut offsets = HashMap::new();
+        let mut size: u64 = 0;
+        for inst in function.blocks.iter().flat_map(|x| &x.insts) {
+            let (bytes, align) = match inst.kind {
+                InstKind::Alloca(ty) => (ty.size(), ty.size()),
+                InstKind::Slot(bytes, align) => (bytes, align),
+                _ => continue,
+            };
+            let (value, _) = inst.result.expect("slots give their address");
+            size = size.next_multiple_of(align.clamp(1, 16));
+            offsets.insert(value, size as u32);
+            size += bytes;
+        }
+        let frame = (size > 0).then(|| {
+            local_types.push(ValType::I32);
+            Frame {
+                pointer: local_types.len() as u32 - 1,
+                size: size.next_multiple_of(16) as u32,
+            }
+        });
+
+        let mut selector = FunctionSelector {
+            selector: self,
+            function,
+            locals,
+            classes,
+            local_types,
+            offsets,
+            frame,
+            labels: 0,
+            dispatch: None,
+            code: Vec::new(),
+        };
+        if let Some(frame) = frame {
+            selector.code.extend([
+                Instr::GlobalGet(runtime::STACK_POINTER),
+                Instr::I32Const(frame.size as i32),
+                Instr::Op(Op::I32Sub),
+                Instr::LocalTee(frame.pointer),
+                Instr::GlobalSet(runtime::STACK_POINTER),
+            ]);
+        }
+        match function.blocks.len() {
+            1 => selector.block(0),
+            count => {
+                // Every block is the end of a `block`, so that the `br_table` branches to the start
+                // of the code of a block by branching out of the `block` it ends
+                let next = selector.new_local(ValType::I32);
+                let dispatch = selector.open(Instr::Loop(BlockType::Empty));
+                selector.dispatch = Some((dispatch, next));
+                for _ in 0..count {
+                    selector.open(Instr::Block(BlockType::Empty));
+                }
+                selector.push(Instr::LocalGet(next));
+                selector.push(Instr::BrTable(
+                    (0..count as u32).collect(),
+                    count as u32 - 1,
+                ));
+                for index in 0..count {
+                    selector.close();
+                    selector.block(index);
+                }
+                selector.close();
+            }
+        }
+        // Every block ends with a terminator, so the end of the function is never reached
+        selector.push(Instr::Unreachable);
+        let locals = selector.local_types.split_off(signature.params.len());
+        Function {
+            name: function_name(index, &function.name),
+            signature,
+            locals,
+            body: selector.code,
+        }
+    }
+}
+
+/// The frame of a function using slots, which is on the stack in memory
+#[derive(Debug, Clone, Copy)]
+struct Frame {
+    /// The local holding the address of the frame
+    pointer: u32,
+    size: u32,
+}
+
+struct FunctionSelector<'s, 'g> {
+    selector: &'s mut Selector<'g>,
+    function: &'s ir::Function,
+    /// The local of every value
+    locals: Vec<Option<u32>>,
+    classes: Vec<Class>,
+    /// The type of every WebAssembly local, starting with the parameters
+    local_types: Vec<ValType>,
+    /// The offset of every `slot` and `alloca` within the frame
+    offsets: HashMap<Value, u32>,
+    frame: Option<Frame>,
+    /// How many blocks are open at this point
+    labels: u32,
+    /// The label of the loop dispatching on the local holding the next block, for functions with
+    /// more than one block
+    dispatch: Option<(u32, u32)>,
+    code: Vec<Instr>,
+}
+
+impl FunctionSelector<'_, '_> {
+    /// Gets the address of the text of the position a span starts at
+    fn at(&mut self, at: Option<Span>) -> u32 {
+        let text = match at {
+            Some(span) => {
+                let position = self
+                    .selector
+                    .line_index
+                    .position(self.selector.path, span.start);
+                position.to_string()
+            }
+            None => "<runtime>".to_string(),
+        };
+        self.selector.texts.add(text.as_bytes())
+    }
+
+    fn push(&mut self, instr: Instr) {
+        self.code.push(instr);
+    }
+
+    fn op(&mut self, op: Op) {
+        self.code.push(Instr::Op(op));
+    }
+
+    fn new_local(&mut self, ty: ValType) -> u32 {
+        self.local_types.push(ty);
+        self.local_types.len() as u32 - 1
+    }
+
+    fn local(&self, value: Value) -> u32 {
+        self.locals[value.0 as usize].expect("every value is defined")
+    }
+
+    fn get(&mut self, value: Value) {
+        self.push(Instr::LocalGet(self.local(value)));
+    }
+
+    fn class(&self, value: Value) -> Class {
+        self.classes[value.0 as usize]
+    }
+
+    /// Pushes the address a value holds, as an `i32`
+    fn address(&mut self, value: Value) {
+        self.get(value);
+        self.op(Op::I32WrapI64);
+    }
+
+    /// Opens a `block`, `loop` or `if`, returning its label
+    fn open(&mut self, instr: Instr) -> u32 {
+        self.push(instr);
+        self.labels += 1;
+        self.labels - 1
+    }
+
+    fn close(&mut self) {
+        self.push(Instr::End);
+        self.labels -= 1;
+    }
+
+    /// Gets how many blocks out a label is
+    fn depth(&self, label: u32) -> u32 {
+        self.labels - 1 - label
+    }
+
+    fn br(&mut self, label: u32) {
+        self.push(Instr::Br(self.depth(label)));
+    }
+
+    /// Reports a runtime error, whose message is followed by the `Str` at the address on the stack
+    fn panic_with_text(&mut self, message: &str, at: u32) {
+        // The text goes between the message and the position
+        let text = self.new_local(ValType::I64);
+        self.push(Instr::LocalSet(text));
+        let message = self.selector.texts.add(message.as_bytes());
+        self.push(Instr::I32Const(message as i32));
+        self.push(Instr::LocalGet(text));
+        self.push(Instr::I32Const(at as i32));
+        self.push(Instr::Call(runtime::PANIC));
+        self.push(Instr::Unreachable);
+    }
+
+    /// Reports a runtime error
+    fn panic(&mut self, message: &str, at: u32) {
+        let message = self.selector.texts.add(message.as_bytes());
+        self.push(Instr::I32Const(message as i32));
+        self.push(Instr::I64Const(0));
+        self.push(Instr::I32Const(at as i32));
+        self.push(Instr::Call(runtime::PANIC));
+        self.push(Instr::Unreachable);
+    }
+
+    /// Reports a runtime error if the condition on the stack holds
+    fn panic_if(&mut self, message: &str, at: u32) {
+        self.open(Instr::If(BlockType::Empty));
+        self.panic(message, at);
+        self.close();
+    }
+
+    /// Returns the value on the stack, if any, once the frame is taken off the stack
+    fn ret(&mut self) {
+        if let Some(frame) = self.frame {
+            self.code.extend([
+                Instr::LocalGet(frame.pointer),
+                Instr::I32Const(frame.size as i32),
+                Instr::Op(Op::I32Add),
+                Instr::GlobalSet(runtime::STACK_POINTER),
+            ]);
+        }
+        self.push(Instr::Return);
+    }
+
+    fn block(&mut self, index: usize) {
+        let function = self.function;
+        let block = &function.blocks[index];
+        for inst in &block.insts {
+            self.inst(inst);
+        }
+        self.terminator(index, &block.terminator);
+    }
+
+    fn inst(&mut self, inst: &ir::Inst) {
+        let at = match inst.kind.has_effects() {
+            true => self.at(inst.at),
+            false => 0,
+        };
+        match &inst.kind {
+            InstKind::Const(LiteralKind::Str(text)) => {
+                let address = self.selector.texts.add(text.as_str().as_bytes());
+                self.push(Instr::I64Const(address as i64));
+            }
+            InstKind::Const(constant) => self.push(literal(constant)),
+            InstKind::Copy(x) => self.get(*x),
+            InstKind::Unary(UnaryOp::Neg, x) => {
+                self.get(*x);
+                self.negate(self.class(*x), Overflow::Trap, at);
+            }
+            InstKind::Unary(UnaryOp::Not, x) => {
+                self.get(*x);
+                self.not(self.class(*x));
+            }
+            InstKind::Binary(op, x, y) => self.binary(*op, Overflow::Trap, *x, *y, at),
+            InstKind::Arith(overflow, op, x, y) => self.binary(*op, *overflow, *x, *y, at),
+            InstKind::Convert(overflow, x) => {
+                let to = class_of(inst.result.expect("conversions give a value").1);
+                self.get(*x);
+                self.convert(self.class(*x), to, *overflow, at);
+            }
+            InstKind::Alloca(_) | InstKind::Slot(..) => {
+                let (value, _) = inst.result.expect("slots give their address");
+                let frame = self.frame.expect("functions with slots have a frame");
+                self.code.extend([
+                    Instr::LocalGet(frame.pointer),
+                    Instr::I32Const(self.offsets[&value] as i32),
+                    Instr::Op(Op::I32Add),
+                    Instr::Op(Op::I64ExtendI32U),
+                ]);
+            }
+            InstKind::Offset(x, y) => {
+                self.get(*x);
+                self.get(*y);
+                self.op(Op::I64Add);
+            }
+            InstKind::Load(x) => {
+                let (_, ty) = inst.result.expect("loads give a value");
+                self.address(*x);
+                self.push(Instr::Load(class_of(ty).load(), 0));
+            }
+            InstKind::Store(x, y) => {
+                self.address(*x);
+                self.get(*y);
+                self.push(Instr::Store(self.class(*y).store(), 0));
+            }
+            InstKind::MemCopy(x, y, z) => {
+                for value in [x, y, z] {
+                    self.get(*value);
+                }
+                self.push(Instr::Call(runtime::MEMMOVE));
+            }
+            InstKind::Call(name, arguments) => self.call(inst, name, arguments, at),
+            InstKind::FuncAddr(name) => {
+                let index = self.selector.index(name);
+                let table = &mut self.selector.table;
+                let position = match table.iter().position(|x| *x == index) {
+                    Some(position) => position,
+                    None => {
+                        table.push(index);
+                        table.len() - 1
+                    }
+                };
+                self.push(Instr::I64Const(position as i64 + 1));
+            }
+            InstKind::CallIndirect(callee, arguments) => {
+                for argument in arguments {
+                    self.get(*argument);
+                }
+                self.address(*callee);
+                let signature = FuncType {
+                    params: arguments
+                        .iter()
+                        .map(|x| self.class(*x).val_type())
+                        .collect(),
+                    results: inst
+                        .result
+                        .map(|(_, ty)| val_type(ty))
+                        .into_iter()
+                        .collect(),
+                };
+                self.counted(inst.at.is_some(), at, Instr::CallIndirect(signature));
+            }
+            InstKind::Panic(message, text) => match text {
+                Some(text) => {
+                    self.get(*text);
+                    self.panic_with_text(message, at);
+                }
+                None => self.panic(message, at),
+            },
+        }
+        if let Some((value, _)) = inst.result {
+            self.push(Instr::LocalSet(self.local(value)));
+        }
+    }
+
+    /// Makes a call, counting it towards how deep calls are nested if it is counted
+    fn counted(&mut self, counted: bool, at: u32, call: Instr) {
+        if !counted {
+            self.push(call);
+            return;
+        }
+        self.push(Instr::GlobalGet(runtime::DEPTH));
+        self.push(Instr::I32Const(1));
+        self.op(Op::I32Add);
+        self.push(Instr::GlobalSet(runtime::DEPTH));
+        self.push(Instr::GlobalGet(runtime::DEPTH));
+        self.push(Instr::I32Const(runtime::MAX_DEPTH));
+        self.op(Op::I32GtS);
+        self.panic_if("stack overflow", at);
+        self.push(call);
+        self.push(Instr::GlobalGet(runtime::DEPTH));
+        self.push(Instr::I32Const(1));
+        self.op(Op::I32Sub);
+        self.push(Instr::GlobalSet(runtime::DEPTH));
+    }
+
+    fn call(&mut self, inst: &ir::Inst, name: &str, arguments: &[Value], at: u32) {
+        let module = self.selector.module;
+        if let Some(declaration) = module.extern_function(name) {
+            let index = match declaration.foreign {
+                Some(_) => None,
+                None => runtime::index_of(name),
+            };
+            let Some(index) = index else {
+                self.foreign(name, inst.at);
+                self.push(Instr::Unreachable);
+                return;
+            };
+            for argument in arguments {
+                self.get(*argument);
+            }
+            self.push(Instr::Call(index));
+            if declaration.return_type.is_some() && inst.result.is_none() {
+                self.push(Instr::Drop);
+            }
+            return;
+        }
+        for argument in arguments {
+            self.get(*argument);
+        }
+        let callee = module.function(name).expect("calls name a function");
+        let call = Instr::Call(self.selector.index(name));
+        self.counted(inst.at.is_some(), at, call);
+        if callee.return_type.is_some() && inst.result.is_none() {
+            self.push(Instr::Drop);
+        }
+    }
+
+    /// Reports a call of a foreign function
+    fn foreign(&mut self, name: &str, at: Option<Span>) {
+        let mut diagnostic = Diagnostic::error(format!(
+            "the foreign function `{}` can only be called by a program built with `--target=c`",
+            name
+        ));
+        if let Some(span) = at {
+            diagnostic = diagnostic.with_primary(span, "called here");
+        }
+        self.selector.diagnostics.push(diagnostic);
+    }
+
+    /// Continues with a block, assigning its parameters. The arguments are all pushed before any
+    /// parameter is assigned, as they may be parameters of the same block
+    fn jump(&mut self, from: usize, target: &Target) {
+        let function = self.function;
+        for argument in &target.arguments {
+            self.get(*argument);
+        }
+        for (param, _) in function.block(target.block).params.iter().rev() {
+            self.push(Instr::LocalSet(self.local(*param)));
+        }
+        // The code of the next block comes right after
+        if target.block.0 as usize == from + 1 {
+            return;
+        }
+        let (dispatch, next) = self.dispatch.expect("functions with jumps dispatch");
+        self.push(Instr::I32Const(target.block.0 as i32));
+        self.push(Instr::LocalSet(next));
+        self.br(dispatch);
+    }
+
+    fn terminator(&mut self, index: usize, terminator: &Terminator) {
+        match terminator {
+            Terminator::Jump(target) => self.jump(index, target),
+            Terminator::Branch(condition, then_target, else_target) => {
+                self.get(*condition);
+                self.open(Instr::If(BlockType::Empty));
+                // Neither target comes right after the code of an `if`
+                self.jump(usize::MAX - 1, then_target);
+                self.push(Instr::Else);
+                self.jump(usize::MAX - 1, else_target);
+                self.close();
+            }
+            Terminator::Return(value) => {
+                if let Some(value) = value {
+                    self.get(*value);
+                }
+                self.ret();
+            }
+            Terminator::Unreachable => self.push(Instr::Unreachable),
+        }
+    }
+
+    /// Masks the result of a shift on an `Int8` or `UInt8` back to its width
+    fn normalize(&mut self, class: Class) {
+        match class {
+            Class::Int {
+                bits: 8,
+                signed: true,
+            } => {
+                self.push(Instr::I32Const(24));
+                self.op(Op::I32Shl);
+                self.push(Instr::I32Const(24));
+                self.op(Op::I32ShrS);
+            }
+            Class::Int {
+                bits: 8,
+                signed: false,
+            } => {
+                self.push(Instr::I32Const(0xFF));
+                self.op(Op::I32And);
+            }
+            _ => {}
+        }
+    }
+
+    fn negate(&mut self, class: Class, overflow: Overflow, at: u32) {
+        let message = "attempt to negate with overflow";
+        let Class::Int { bits, signed } = class else {
+            match class {
+                Class::Float32 => self.op(Op::F32Neg),
+                Class::Float64 => self.op(Op::F64Neg),
+                _ => unreachable!("only numbers are negated"),
+            }
+            return;
+        };
+        let wide = bits == 64;
+        let (ty, zero, eq, sub) = match wide {
+            true => (ValType::I64, Instr::I64Const(0), Op::I64Eq, Op::I64Sub),
+            false => (ValType::I32, Instr::I32Const(0), Op::I32Eq, Op::I32Sub),
+        };
+        let (min, max) = limits(bits, signed);
+        let constant = |value: i64| match wide {
+            true => Instr::I64Const(value),
+            false => Instr::I32Const(value as i32),
+        };
+        let value = self.new_local(ty);
+        self.push(Instr::LocalSet(value));
+        // Only the smallest signed value overflows, while every unsigned value but zero does
+        let overflows = self.new_local(ValType::I32);
+        self.push(Instr::LocalGet(value));
+        match signed {
+            true => {
+                self.push(constant(min));
+                self.op(eq);
+            }
+            false => {
+                self.push(zero.clone());
+                self.op(if wide { Op::I64Ne } else { Op::I32Ne });
+            }
+        }
+        self.push(Instr::LocalSet(overflows));
+        if overflow == Overflow::Trap {
+            self.push(Instr::LocalGet(overflows));
+            self.panic_if(message, at);
+        }
+        if overflow == Overflow::Saturate {
+            self.push(constant(if signed { max } else { 0 }));
+        }
+        self.push(zero);
+        self.push(Instr::LocalGet(value));
+        self.op(sub);
+        self.normalize(class);
+        if overflow == Overflow::Saturate {
+            self.push(Instr::LocalGet(overflows));
+            self.push(Instr::Select);
+        }
+    }
+
+    fn not(&mut self, class: Class) {
+        match class {
+            Class::Bool => self.op(Op::I32Eqz),
+            Class::Int { bits: 64, .. } => {
+                self.push(Instr::I64Const(-1));
+                self.op(Op::I64Xor);
+            }
+            Class::Int {
+                bits: 8,
+                signed: false,
+            } => {
+                self.push(Instr::I32Const(0xFF));
+                self.op(Op::I32Xor);
+            }
+            _ => {
+                self.push(Instr::I32Const(-1));
+                self.op(Op::I32Xor);
+            }
+        }
+    }
+
+    /// Applies an operator to two values, overflowing the way `overflow` says to
+    fn binary(&mut self, op: BinaryOp, overflow: Overflow, left: Value, right: Value, at: u32) {
+        let (class, amount) = (self.class(left), self.class(right));
+        self.get(left);
+        self.get(right);
+        match op {
+            _ if op.is_comparison() => self.op(compare(op, class)),
+            BinaryOp::Shl | BinaryOp::Shr => self.shift(op, class, amount, at),
+            BinaryOp::And => match class.val_type() {
+                ValType::I64 => self.op(Op::I64And),
+                _ => self.op(Op::I32And),
+            },
+            _ => match class {
+                Class::Float32 => self.op(match op {
+                    BinaryOp::Add => Op::F32Add,
+                    BinaryOp::Sub => Op::F32Sub,
+                    BinaryOp::Mul => Op::F32Mul,
+                    _ => Op::F32Div,
+                }),
+                Class::Float64 => self.op(match op {
+                    BinaryOp::Add => Op::F64Add,
+                    BinaryOp::Sub => Op::F64Sub,
+                    BinaryOp::Mul => Op::F64Mul,
+                    _ => Op::F64Div,
+                }),
+                Class::Int { bits: 64, signed } => self.arith_64(op, overflow, signed, at),
+                Class::Int { bits, signed } => self.arith_narrow(op, overflow, bits, signed, at),
+                Class::Bool => unreachable!("`{}` is only applied to numbers", op.name()),
+            },
+        }
+    }
+
+    /// Applies `add`, `sub`, `mul` or `div` to two integers of at most 32 bits, by applying it to
+    /// them extended to 64 bits and checking that the result is within range
+    fn arith_narrow(&mut self, op: BinaryOp, overflow: Overflow, bits: u8, signed: bool, at: u32) {
+        let extend = match signed {
+            true => Op::I64ExtendI32S,
+            false => Op::I64ExtendI32U,
+        };
+        let right = self.new_local(ValType::I32);
+        self.push(Instr::LocalSet(right));
+        if op == BinaryOp::Div {
+            self.push(Instr::LocalGet(right));
+            self.op(Op::I32Eqz);
+            self.panic_if("attempt to divide by zero", at);
+        }
+        self.op(extend);
+        self.push(Instr::LocalGet(right));
+        self.op(extend);
+        let (op, message) = match (op, signed) {
+            (BinaryOp::Add, _) => (Op::I64Add, "attempt to add with overflow"),
+            (BinaryOp::Sub, _) => (Op::I64Sub, "attempt to subtract with overflow"),
+            (BinaryOp::Mul, _) => (Op::I64Mul, "attempt to multiply with overflow"),
+            (_, true) => (Op::I64DivS, "attempt to divide with overflow"),
+            (_, false) => (Op::I64DivU, "attempt to divide with overflow"),
+        };
+        let subtract = op == Op::I64Sub;
+        self.op(op);
+        let result = self.new_local(ValType::I64);
+        self.push(Instr::LocalSet(result));
+        let (min, max) = limits(bits, signed);
+        let get = Instr::LocalGet;
+        match overflow {
+            Overflow::Trap => {
+                self.push(get(result));
+                match signed {
+                    true => {
+                        self.push(Instr::I64Const(min));
+                        self.op(Op::I64LtS);
+                        self.push(get(result));
+                        self.push(Instr::I64Const(max));
+                        self.op(Op::I64GtS);
+                        self.op(Op::I32Or);
+                    }
+                    false => {
+                        self.push(Instr::I64Const(max));
+                        self.op(Op::I64GtU);
+                    }
+                }
+                self.panic_if(message, at);
+            }
+            Overflow::Wrap => {}
+            // A result below zero only comes from subtracting unsigned integers, while any other
+            // one beyond the largest `Int64` is a product of two `UInt32`s which is too large
+            Overflow::Saturate => {
+                let (below, above) = match signed {
+                    true => (Op::I64LtS, Op::I64GtS),
+                    false => (Op::I64LtS, Op::I64GtU),
+                };
+                let clamps = match subtract || signed {
+                    true => vec![(min, below), (max, above)],
+                    false => vec![(max, above)],
+                };
+                for (bound, compare) in clamps {
+                    self.code
+                        .extend([Instr::I64Const(bound), get(result), get(result)]);
+                    self.push(Instr::I64Const(bound));
+                    self.op(compare);
+                    self.push(Instr::Select);
+                    self.push(Instr::LocalSet(result));
+                }
+            }
+        }
+        self.push(get(result));
+        self.op(Op::I32WrapI64);
+        self.normalize(Class::Int { bits, signed });
+    }
+
+    /// Applies `add`, `sub`, `mul` or `div` to two 64-bit integers
+    fn arith_64(&mut self, op: BinaryOp, overflow: Overflow, signed: bool, at: u32) {
+        let right = self.new_local(ValType::I64);
+        let left = self.new_local(ValType::I64);
+        let result = self.new_local(ValType::I64);
+        let overflows = self.new_local(ValType::I32);
+        self.push(Instr::LocalSet(right));
+        self.push(Instr::LocalSet(left));
+        let get = Instr::LocalGet;
+        let message = match op {
+            BinaryOp::Add | BinaryOp::Sub => {
+                let add = op == BinaryOp::Add;
+                self.push(get(left));
+                self.push(get(right));
+                self.op(if add { Op::I64Add } else { Op::I64Sub });
+                self.push(Instr::LocalSet(result));
+                // A signed sum overflows when its sign differs from the sign of both operands, and
+                // a difference when the operands differ in sign and the result differs from the
+                // left one. Unsigned results overflow when they wrap around
+                match (add, signed) {
+                    (true, true) => {
+                        self.code.extend([get(left), get(result)]);
+                        self.op(Op::I64Xor);
+                        self.code.extend([get(right), get(result)]);
+                        self.op(Op::I64Xor);
+                        self.op(Op::I64And);
+                        self.push(Instr::I64Const(0));
+                        self.op(Op::I64LtS);
+                    }
+                    (false, true) => {
+                        self.code.extend([get(left), get(right)]);
+                        self.op(Op::I64Xor);
+                        self.code.extend([get(left), get(result)]);
+                        self.op(Op::I64Xor);
+                        self.op(Op::I64And);
+                        self.push(Instr::I64Const(0));
+                        self.op(Op::I64LtS);
+                    }
+                    (true, false) => {
+                        self.code.extend([get(result), get(left)]);
+                        self.op(Op::I64LtU);
+                    }
+                    (false, false) => {
+                        self.code.extend([get(left), get(right)]);
+                        self.op(Op::I64LtU);
+                    }
+                }
+                match add {
+                    true => "attempt to add with overflow",
+                    false => "attempt to subtract with overflow",
+                }
+            }
+            BinaryOp::Mul => {
+                let helper = match signed {
+                    true => runtime::MUL_OVERFLOWS_INT64,
+                    false => runtime::MUL_OVERFLOWS_UINT64,
+                };
+                self.code.extend([get(left), get(right)]);
+                self.op(Op::I64Mul);
+                self.push(Instr::LocalSet(result));
+                self.code
+                    .extend([get(left), get(right), Instr::Call(helper)]);
+                "attempt to multiply with overflow"
+            }
+            _ => {
+                self.push(get(right));
+                self.op(Op::I64Eqz);
+                self.panic_if("attempt to divide by zero", at);
+                match signed {
+                    true => {
+                        self.code.extend([get(left), Instr::I64Const(i64::MIN)]);
+                        self.op(Op::I64Eq);
+                        self.code.extend([get(right), Instr::I64Const(-1)]);
+                        self.op(Op::I64Eq);
+                        self.op(Op::I32And);
+                    }
+                    false => self.push(Instr::I32Const(0)),
+                }
+                "attempt to divide with overflow"
+            }
+        };
+        self.push(Instr::LocalSet(overflows));
+        if overflow == Overflow::Trap {
+            self.push(get(overflows));
+            self.panic_if(message, at);
+        }
+        if op == BinaryOp::Div {
+            // `div_s` traps on the one quotient which overflows, so the smallest value is
+            // divided by one instead, which is what the quotient wraps around to
+            self.code
+                .extend([get(left), Instr::I64Const(1), get(right), get(overflows)]);
+            self.push(Instr::Select);
+            self.op(match signed {
+                true => Op::I64DivS,
+                false => Op::I64DivU,
+            });
+            self.push(Instr::LocalSet(result));
+        }
+        if overflow == Overflow::Saturate {
+            self.saturated(op, signed, left, right);
+            self.code.extend([get(result), get(overflows)]);
+            self.push(Instr::Select);
+            self.push(Instr::LocalSet(result));
+        }
+        self.push(get(result));
+    }
+
+    /// Pushes the value `add`, `sub`, `mul` or `div` on two 64-bit integers saturates to when it
+    /// overflows, which only depends on the signs of its operands
+    fn saturated(&mut self, op: BinaryOp, signed: bool, left: u32, right: u32) {
+        if !signed {
+            self.push(Instr::I64Const(match op {
+                BinaryOp::Sub => 0,
+                _ => -1,
+            }));
+            return;
+        }
+        match op {
+            BinaryOp::Add => self.code.extend([
+                Instr::I64Const(i64::MIN),
+                Instr::I64Const(i64::MAX),
+                Instr::LocalGet(right),
+            ]),
+            BinaryOp::Sub => self.code.extend([
+                Instr::I64Const(i64::MAX),
+                Instr::I64Const(i64::MIN),
+                Instr::LocalGet(right),
+            ]),
+            BinaryOp::Mul => {
+                self.code.extend([
+                    Instr::I64Const(i64::MIN),
+                    Instr::I64Const(i64::MAX),
+                    Instr::LocalGet(left),
+                    Instr::LocalGet(right),
+                ]);
+                self.op(Op::I64Xor);
+            }
+            _ => {
+                self.push(Instr::I64Const(i64::MAX));
+                return;
+            }
+        }
+        self.push(Instr::I64Const(0));
+        self.op(Op::I64LtS);
+        self.push(Instr::Select);
+    }
+
+    /// Converts the number on the stack from one class to another, see
+    /// [shark_sema::numeric::convert]. A float is compared with bounds which are powers of two and
+    /// so exact, after being promoted to an `f64`, and `NaN` fails every comparison
+    fn convert(&mut self, from: Class, to: Class, overflow: Overflow, at: u32) {
+        let message = "attempt to convert with overflow";
+        match (from, to) {
+            (
+                Class::Int { bits, signed },
+                Class::Int {
+                    bits: to_bits,
+                    signed: to_signed,
+                },
+            ) => {
+                if bits < 64 {
+                    self.op(match signed {
+                        true => Op::I64ExtendI32S,
+                        false => Op::I64ExtendI32U,
+                    });
+                }
+                let lossless = (to_bits > bits && (to_signed || !signed))
+                    || (to_bits == bits && to_signed == signed);
+                if overflow == Overflow::Trap && !lossless {
+                    let value = self.new_local(ValType::I64);
+                    self.push(Instr::LocalTee(value));
+                    let (min, max) = limits(to_bits, to_signed);
+                    match (to_bits, signed) {
+                        // Values whose sign bit is set do not fit when the signedness changes
+                        (64, _) => {
+                            self.push(Instr::I64Const(0));
+                            self.op(Op::I64LtS);
+                        }
+                        (_, true) => {
+                            self.push(Instr::I64Const(min));
+                            self.op(Op::I64LtS);
+                            self.push(Instr::LocalGet(value));
+                            self.push(Instr::I64Const(max));
+                            self.op(Op::I64GtS);
+                            self.op(Op::I32Or);
+                        }
+                        (_, false) => {
+                            self.push(Instr::I64Const(max));
+                            self.op(Op::I64GtU);
+                        }
+                    }
+                    self.panic_if(message, at);
+                    self.push(Instr::LocalGet(value));
+                }
+                if to_bits < 64 {
+                    self.op(Op::I32WrapI64);
+                    self.normalize(to);
+                }
+            }
+            (Class::Int { bits, signed }, _) => self.op(match (to, bits, signed) {
+                (Class::Float32, 64, true) => Op::F32ConvertI64S,
+                (Class::Float32, 64, false) => Op::F32ConvertI64U,
+                (Class::Float32, _, true) => Op::F32ConvertI32S,
+                (Class::Float32, _, false) => Op::F32ConvertI32U,
+                (_, 64, true) => Op::F64ConvertI64S,
+                (_, 64, false) => Op::F64ConvertI64U,
+                (_, _, true) => Op::F64ConvertI32S,
+                (_, _, false) => Op::F64ConvertI32U,
+            }),
+            (Class::Float32, Class::Float64) => self.op(Op::F64PromoteF32),
+            (Class::Float64, Class::Float32) => self.op(Op::F32DemoteF64),
+            (_, Class::Float32 | Class::Float64) => {}
+            (_, Class::Int { bits, signed }) => {
+                if from == Class::Float32 {
+                    self.op(Op::F64PromoteF32);
+                }
+                let value = self.new_local(ValType::F64);
+                self.push(Instr::LocalSet(value));
+                let (min, max) = limits(bits, signed);
+                let (min, max) = (min as i128, max as i128);
+                let max = if (bits, signed) == (64, false) {
+                    u64::MAX.into()
+                } else {
+                    max
+                };
+                // The smallest `Int64` is the only bound with no float just below it
+                let (lower, above) = match bits {
+                    64 => (min as f64, Op::F64Ge),
+                    _ => ((min - 1) as f64, Op::F64Gt),
+                };
+                let upper = (max + 1) as f64;
+                let (ty, truncate) = match (bits, signed) {
+                    (64, true) => (ValType::I64, Op::I64TruncF64S),
+                    (64, false) => (ValType::I64, Op::I64TruncF64U),
+                    (_, true) => (ValType::I32, Op::I32TruncF64S),
+                    (_, false) => (ValType::I32, Op::I32TruncF64U),
+                };
+                let constant = |value: i128| match ty {
+                    ValType::I64 => Instr::I64Const(value as i64),
+                    _ => Instr::I32Const(value as i32),
+                };
+                let get = Instr::LocalGet(value);
+                if overflow == Overflow::Trap {
+                    self.code
+                        .extend([get.clone(), Instr::F64Const(lower.to_bits())]);
+                    self.op(above);
+                    self.code
+                        .extend([get.clone(), Instr::F64Const(upper.to_bits())]);
+                    self.op(Op::F64Lt);
+                    self.op(Op::I32And);
+                    self.op(Op::I32Eqz);
+                    self.panic_if(message, at);
+                    self.push(get);
+                    self.op(truncate);
+                    return;
+                }
+                // `NaN` becomes zero, while any other value saturates
+                self.code.extend([get.clone(), get.clone()]);
+                self.op(Op::F64Ne);
+                self.open(Instr::If(BlockType::Value(ty)));
+                self.push(constant(0));
+                self.push(Instr::Else);
+                self.code
+                    .extend([get.clone(), Instr::F64Const(lower.to_bits())]);
+                self.op(above);
+                self.op(Op::I32Eqz);
+                self.open(Instr::If(BlockType::Value(ty)));
+                self.push(constant(min));
+                self.push(Instr::Else);
+                self.code
+                    .extend([get.clone(), Instr::F64Const(upper.to_bits())]);
+                self.op(Op::F64Lt);
+                self.op(Op::I32Eqz);
+                self.open(Instr::If(BlockType::Value(ty)));
+                self.push(constant(max));
+                self.push(Instr::Else);
+                self.push(get);
+                self.op(truncate);
+                self.close();
+                self.close();
+                self.close();
+            }
+            _ => unreachable!("only numbers are converted"),
+        }
+    }
+
+    /// Shifts an integer by an amount of any integer type, which must be less than its width
+    fn shift(&mut self, op: BinaryOp, class: Class, amount: Class, at: u32) {
+        match amount {
+            Class::Int { bits: 64, .. } => {}
+            Class::Int { signed: true, .. } => self.op(Op::I64ExtendI32S),
+            _ => self.op(Op::I64ExtendI32U),
+        }
+        let Class::Int { bits, signed } = class else {
+            unreachable!("only integers are shifted");
+        };
+        let value = self.new_local(ValType::I64);
+        self.push(Instr::LocalTee(value));
+        // Negative amounts are too large once they are taken as unsigned
+        self.push(Instr::I64Const(bits as i64));
+        self.op(Op::I64GeU);
+        let message = match op {
+            BinaryOp::Shl => "attempt to shift left with overflow",
+            _ => "attempt to shift right with overflow",
+        };
+        self.panic_if(message, at);
+        self.push(Instr::LocalGet(value));
+        if bits == 64 {
+            self.op(match (op, signed) {
+                (BinaryOp::Shl, _) => Op::I64Shl,
+                (_, true) => Op::I64ShrS,
+                (_, false) => Op::I64ShrU,
+            });
+            return;
+        }
+        self.op(Op::I32WrapI64);
+        self.op(match (op, signed) {
+            (BinaryOp::Shl, _) => Op::I32Shl,
+            (_, true) => Op::I32ShrS,
+            (_, false) => Op::I32ShrU,
+        });
+        self.normalize(class);
+    }
+}
@@ -0,0 +1,387 @@
+//! Checks that a [Module] is well formed before it is encoded: that every index refers to
+//! something, that exports and data fit, and that every function body is well typed. Bodies are
+//! checked with the algorithm of the validation appendix of the specification, which tracks the
+//! types on the operand stack along with the blocks they are in
+//!
+//! A module which fails is a bug in the backend, so errors only describe what is wrong for
+//! whoever is fixing it
+
+use std::collections::HashSet;
+
+use crate::module::{BlockType, ExportKind, Function, Instr, Module, ValType, PAGE_SIZE};
+
+/// A block being checked
+struct Frame {
+    /// The types a branch to the block takes, which for a `loop` are none as it branches back to
+    /// the start
+    label_types: Vec<ValType>,
+    end_types: Vec<ValType>,
+    /// The height of the operand stack when the block was entered
+    height: usize,
+    /// Set once the rest of the block can not be reached, after which the operand stack is
+    /// polymorphic
+    unreachable: bool,
+    /// Whether the block is an `if` still waiting for its `else`
+    is_if: bool,
+}
+
+struct BodyValidator<'m> {
+    module: &'m Module,
+    locals: Vec<ValType>,
+    /// The operand stack, where [None] is a type which is not known as it was popped from an
+    /// unreachable part of a block
+    operands: Vec<Option<ValType>>,
+    frames: Vec<Frame>,
+}
+
+impl<'m> BodyValidator<'m> {
+    fn push(&mut self, ty: ValType) {
+        self.operands.push(Some(ty));
+    }
+
+    fn pop(&mut self) -> Result<Option<ValType>, String> {
+        let frame = self.frames.last().expect("there is always a frame");
+        if self.operands.len() == frame.height {
+            return match frame.unreachable {
+                true => Ok(None),
+                false => Err("the operand stack is empty".to_string()),
+            };
+        }
+        Ok(self.operands.pop().expect("the stack is above the frame"))
+    }
+
+    fn pop_expected(&mut self, expected: ValType) -> Result<(), String> {
+        match self.pop()? {
+            Some(ty) if ty != expected => Err(format!("expected `{}`, found `{}`", expected, ty)),
+            _ => Ok(()),
+        }
+    }
+
+    fn pop_all(&mut self, types: &[ValType]) -> Result<(), String> {
+        for ty in types.iter().rev() {
+            self.pop_expected(*ty)?;
+        }
+        Ok(())
+    }
+
+    fn enter(&mut self, ty: BlockType, is_loop: bool, is_if: bool) {
+        let end_types = ty.results();
+        self.frames.push(Frame {
+            label_types: match is_loop {
+                true => Vec::new(),
+                false => end_types.clone(),
+            },
+            end_types,
+            height: self.operands.len(),
+            unreachable: false,
+            is_if,
+        });
+    }
+
+    /// Ends the block on top, checking that it leaves exactly its results
+    fn exit(&mut self) -> Result<Frame, String> {
+        let end_types = self
+            .frames
+            .last()
+            .expect("a frame to exit")
+            .end_types
+            .clone();
+        self.pop_all(&end_types)?;
+        let frame = self.frames.last().expect("a frame to exit");
+        if self.operands.len() != frame.height {
+            return Err(format!(
+                "{} values are left at the end of a block",
+                self.operands.len() - frame.height
+            ));
+        }
+        Ok(self.frames.pop().expect("a frame to exit"))
+    }
+
+    fn set_unreachable(&mut self) {
+        let frame = self.frames.last_mut().expect("there is always a frame");
+        self.operands.truncate(frame.height);
+        frame.unreachable = true;
+    }
+
+    fn label(&self, depth: u32) -> Result<Vec<ValType>, String> {
+        let index = (self.frames.len() as u32)
+            .checked_sub(depth + 1)
+            .ok_or_else(|| format!("there is no label {} blocks out", depth))?;
+        Ok(self.frames[index as usize].label_types.clone())
+    }
+
+    fn local(&self, index: u32) -> Result<ValType, String> {
+        self.locals
+            .get(index as usize)
+            .copied()
+            .ok_or_else(|| format!("there is no local {}", index))
+    }
+
+    fn global(&self, index: u32) -> Result<(ValType, bool), String> {
+        self.module
+            .globals
+            .get(index as usize)
+            .map(|x| (x.ty, x.mutable))
+            .ok_or_else(|| format!("there is no global {}", index))
+    }
+
+    fn memory(&self) -> Result<(), String> {
+        match self.module.memory {
+            Some(_) => Ok(()),
+            None => Err("memory is used without a memory".to_string()),
+        }
+    }
+
+    fn instr(&mut self, instr: &Instr) -> Result<(), String> {
+        match instr {
+            Instr::Unreachable => self.set_unreachable(),
+            Instr::Block(ty) => self.enter(*ty, false, false),
+            Instr::Loop(ty) => self.enter(*ty, true, false),
+            Instr::If(ty) => {
+                self.pop_expected(ValType::I32)?;
+                self.enter(*ty, false, true);
+            }
+            Instr::Else => {
+                if !self.frames.last().is_some_and(|x| x.is_if) {
+                    return Err("`else` is not within an `if`".to_string());
+                }
+                let frame = self.exit()?;
+                self.frames.push(Frame {
+                    height: self.operands.len(),
+                    unreachable: false,
+                    is_if: false,
+                    ..frame
+                });
+            }
+            Instr::End => {
+                if self.frames.len() == 1 {
+                    return Err("`end` closes the function".to_string());
+                }
+                let frame = self.exit()?;
+                if frame.is_if && !frame.end_types.is_empty() {
+                    return Err("an `if` with a result has no `else`".to_string());
+                }
+                for ty in frame.end_types {
+                    self.push(ty);
+                }
+            }
+            Instr::Br(depth) => {
+                let types = self.label(*depth)?;
+                self.pop_all(&types)?;
+                self.set_unreachable();
+            }
+            Instr::BrIf(depth) => {
+                self.pop_expected(ValType::I32)?;
+                let types = self.label(*depth)?;
+                self.pop_all(&types)?;
+                for ty in types {
+                    self.push(ty);
+                }
+            }
+            Instr::BrTable(targets, default) => {
+                self.pop_expected(ValType::I32)?;
+                let types = self.label(*default)?;
+                for target in targets {
+                    if self.label(*target)? != types {
+                        return Err("the labels of a `br_table` take different types".to_string());
+                    }
+                }
+                self.pop_all(&types)?;
+                self.set_unreachable();
+            }
+            Instr::Return => {
+                let types = self.frames[0].end_types.clone();
+                self.pop_all(&types)?;
+                self.set_unreachable();
+            }
+            Instr::Call(index) => {
+                let signature = self
+                    .module
+                    .signature(*index)
+                    .ok_or_else(|| format!("there is no function {}", index))?
+                    .clone();
+                self.pop_all(&signature.params)?;
+                for ty in signature.results {
+                    self.push(ty);
+                }
+            }
+            Instr::CallIndirect(signature) => {
+                if self.module.table.is_empty() {
+                    return Err("`call_indirect` is used without a table".to_string());
+                }
+                self.pop_expected(ValType::I32)?;
+                self.pop_all(&signature.params)?;
+                for ty in &signature.results {
+                    self.push(*ty);
+                }
+            }
+            Instr::Drop => {
+                self.pop()?;
+            }
+            Instr::Select => {
+                self.pop_expected(ValType::I32)?;
+                let first = self.pop()?;
+                let second = self.pop()?;
+                match (first, second) {
+                    (Some(first), Some(second)) if first != second => {
+                        return Err(format!("`select` between `{}` and `{}`", second, first))
+                    }
+                    (Some(ty), _) | (_, Some(ty)) => self.push(ty),
+                    (None, None) => self.operands.push(None),
+                }
+            }
+            Instr::LocalGet(index) => {
+                let ty = self.local(*index)?;
+                self.push(ty);
+            }
+            Instr::LocalSet(index) => {
+                let ty = self.local(*index)?;
+                self.pop_expected(ty)?;
+            }
+            Instr::LocalTee(index) => {
+                let ty = self.local(*index)?;
+                self.pop_expected(ty)?;
+                self.push(ty);
+            }
+            Instr::GlobalGet(index) => {
+                let (ty, _) = self.global(*index)?;
+                self.push(ty);
+            }
+            Instr::GlobalSet(index) => {
+                let (ty, mutable) = self.global(*index)?;
+                if !mutable {
+                    return Err(format!("global {} is immutable", index));
+                }
+                self.pop_expected(ty)?;
+            }
+            Instr::Load(load, _) => {
+                self.memory()?;
+                self.pop_expected(ValType::I32)?;
+                self.push(load.ty());
+            }
+            Instr::Store(store, _) => {
+                self.memory()?;
+                self.pop_expected(store.ty())?;
+                self.pop_expected(ValType::I32)?;
+            }
+            Instr::MemorySize => {
+                self.memory()?;
+                self.push(ValType::I32);
+            }
+            Instr::MemoryGrow => {
+                self.memory()?;
+                self.pop_expected(ValType::I32)?;
+                self.push(ValType::I32);
+            }
+            Instr::I32Const(_) => self.push(ValType::I32),
+            Instr::I64Const(_) => self.push(ValType::I64),
+            Instr::F32Const(_) => self.push(ValType::F32),
+            Instr::F64Const(_) => self.push(ValType::F64),
+            Instr::Op(op) => {
+                let (params, result) = op.signature();
+                self.pop_all(params)?;
+                self.push(result);
+            }
+        }
+        Ok(())
+    }
+}
+
+fn validate_function(module: &Module, function: &Function) -> Result<(), String> {
+    let mut validator = BodyValidator {
+        module,
+        locals: function
+            .signature
+            .params
+            .iter()
+            .chain(&function.locals)
+            .copied()
+            .collect(),
+        operands: Vec::new(),
+        frames: vec![Frame {
+            label_types: function.signature.results.clone(),
+            end_types: function.signature.results.clone(),
+            height: 0,
+            unreachable: false,
+            is_if: false,
+        }],
+    };
+    for (index, instr) in function.body.iter().enumerate() {
+        validator
+            .instr(instr)
+            .map_err(|x| format!("at instruction {} (`{:?}`): {}", index, instr, x))?;
+    }
+    if validator.frames.len() != 1 {
+        return Err(format!(
+            "{} blocks are not closed",
+            validator.frames.len() - 1
+        ));
+    }
+    validator
+        .exit()
+        .map_err(|x| format!("at the end of the function: {}", x))?;
+    Ok(())
+}
+
+/// Checks that a module is well formed
+pub fn validate(module: &Module) -> Result<(), String> {
+    let mut names = HashSet::new();
+    let function_names = module.imports.iter().map(|x| &x.function_name);
+    for name in function_names.chain(module.functions.iter().map(|x| &x.name)) {
+        if !names.insert(name) {
+            return Err(format!("there are two functions named `{}`", name));
+        }
+    }
+
+    for global in &module.globals {
+        let init = match global.init {
+            Instr::I32Const(_) => ValType::I32,
+            Instr::I64Const(_) => ValType::I64,
+            Instr::F32Const(_) => ValType::F32,
+            Instr::F64Const(_) => ValType::F64,
+            _ => return Err(format!("global `{}` is not set to a constant", global.name)),
+        };
+        if init != global.ty {
+            return Err(format!(
+                "global `{}` of type `{}` is set to a `{}`",
+                global.name, global.ty, init
+            ));
+        }
+    }
+
+    let mut exported = HashSet::new();
+    for export in &module.exports {
+        if !exported.insert(&export.name) {
+            return Err(format!("`{}` is exported twice", export.name));
+        }
+        let exists = match export.kind {
+            ExportKind::Function(index) => module.signature(index).is_some(),
+            ExportKind::Memory(index) => index == 0 && module.memory.is_some(),
+        };
+        if !exists {
+            return Err(format!("export `{}` refers to nothing", export.name));
+        }
+    }
+
+    for index in &module.table {
+        if module.signature(*index).is_none() {
+            return Err(format!(
+                "the table refers to function {}, which does not exist",
+                index
+            ));
+        }
+    }
+
+    let size = module.memory.map_or(0, |x| x as u64 * PAGE_SIZE as u64);
+    for data in &module.data {
+        if module.memory.is_none() || data.offset as u64 + data.bytes.len() as u64 > size {
+            return Err(format!("data at {} does not fit in memory", data.offset));
+        }
+    }
+
+    for function in &module.functions {
+        validate_function(module, function)
+            .map_err(|x| format!("in function `{}`: {}", function.name, x))?;
+    }
+    Ok(())
+}
@@ -0,0 +1,234 @@
+//! Prints a [Module] in the WebAssembly text format. Functions and globals are referred to by
+//! their names, while locals and labels are referred to by index as in the binary format
+
+use std::fmt::{Debug, Write};
+
+use crate::module::{BlockType, ExportKind, FuncType, Instr, Module};
+
+fn signature(out: &mut String, ty: &FuncType) {
+    if !ty.params.is_empty() {
+        out.push_str(" (param");
+        for param in &ty.params {
+            write!(out, " {}", param).unwrap();
+        }
+        out.push(')');
+    }
+    if !ty.results.is_empty() {
+        out.push_str(" (result");
+        for result in &ty.results {
+            write!(out, " {}", result).unwrap();
+        }
+        out.push(')');
+    }
+}
+
+fn block_type(ty: BlockType) -> String {
+    match ty {
+        BlockType::Empty => String::new(),
+        BlockType::Value(ty) => format!(" (result {})", ty),
+    }
+}
+
+/// Prints a float so that it reads back as the same value
+fn float<T: Copy + Debug + Into<f64>>(value: T) -> String {
+    let wide: f64 = value.into();
+    if wide.is_nan() {
+        return "nan".to_string();
+    }
+    match wide.is_infinite() {
+        true if wide < 0.0 => "-inf".to_string(),
+        true => "inf".to_string(),
+        false => format!("{:?}", value),
+    }
+}
+
+/// Prints bytes as a string, escaping every byte which is not printable ASCII
+pub fn string(bytes: &[u8]) -> String {
+    let mut result = String::from("\"");
+    for byte in bytes {
+        match byte {
+            b'"' | b'\\' => write!(result, "\\{}", *byte as char).unwrap(),
+            0x20..=0x7E => result.push(*byte as char),
+            _ => write!(result, "\\{:02x}", byte).unwrap(),
+        }
+    }
+    result.push('"');
+    result
+}
+
+fn instr(out: &mut String, module: &Module, types: &[FuncType], instr: &Instr) {
+    let function = |index: u32| match module.function_name(index) {
+        Some(name) => format!("${}", name),
+        None => index.to_string(),
+    };
+    let global = |index: u32| match module.globals.get(index as usize) {
+        Some(global) => format!("${}", global.name),
+        None => index.to_string(),
+    };
+    match instr {
+        Instr::Unreachable => out.push_str("unreachable"),
+        Instr::Block(ty) => write!(out, "block{}", block_type(*ty)).unwrap(),
+        Instr::Loop(ty) => write!(out, "loop{}", block_type(*ty)).unwrap(),
+        Instr::If(ty) => write!(out, "if{}", block_type(*ty)).unwrap(),
+        Instr::Else => out.push_str("else"),
+        Instr::End => out.push_str("end"),
+        Instr::Br(depth) => write!(out, "br {}", depth).unwrap(),
+        Instr::BrIf(depth) => write!(out, "br_if {}", depth).unwrap(),
+        Instr::BrTable(targets, default) => {
+            out.push_str("br_table");
+            for target in targets {
+                write!(out, " {}", target).unwrap();
+            }
+            write!(out, " {}", default).unwrap();
+        }
+        Instr::Return => out.push_str("return"),
+        Instr::Call(index) => write!(out, "call {}", function(*index)).unwrap(),
+        Instr::CallIndirect(signature) => {
+            let index = types.iter().position(|x| x == signature);
+            let index = index.expect("every signature has a type");
+            write!(out, "call_indirect (type {})", index).unwrap();
+        }
+        Instr::Drop => out.push_str("drop"),
+        Instr::Select => out.push_str("select"),
+        Instr::LocalGet(index) => write!(out, "local.get {}", index).unwrap(),
+        Instr::LocalSet(index) => write!(out, "local.set {}", index).unwrap(),
+        Instr::LocalTee(index) => write!(out, "local.tee {}", index).unwrap(),
+        Instr::GlobalGet(index) => write!(out, "global.get {}", global(*index)).unwrap(),
+        Instr::GlobalSet(index) => write!(out, "global.set {}", global(*index)).unwrap(),
+        Instr::Load(load, offset) => {
+            out.push_str(load.text());
+            if *offset != 0 {
+                write!(out, " offset={}", offset).unwrap();
+            }
+        }
+        Instr::Store(store, offset) => {
+            out.push_str(store.text());
+            if *offset != 0 {
+                write!(out, " offset={}", offset).unwrap();
+            }
+        }
+        Instr::MemorySize => out.push_str("memory.size"),
+        Instr::MemoryGrow => out.push_str("memory.grow"),
+        Instr::I32Const(value) => write!(out, "i32.const {}", value).unwrap(),
+        Instr::I64Const(value) => write!(out, "i64.const {}", value).unwrap(),
+        Instr::F32Const(bits) => write!(out, "f32.const {}", float(f32::from_bits(*bits))).unwrap(),
+        Instr::F64Const(bits) => write!(out, "f64.const {}", float(f64::from_bits(*bits))).unwrap(),
+        Instr::Op(op) => out.push_str(op.text()),
+    }
+}
+
+/// Prints a module in the text format
+pub fn print(module: &Module) -> String {
+    let types = module.types();
+    let type_index = |ty: &FuncType| {
+        types
+            .iter()
+            .position(|x| x == ty)
+            .expect("every signature has a type")
+    };
+    let mut out = String::from("(module\n");
+    for (index, ty) in types.iter().enumerate() {
+        write!(out, "  (type (;{};) (func", index).unwrap();
+        signature(&mut out, ty);
+        out.push_str("))\n");
+    }
+    for import in &module.imports {
+        write!(
+            out,
+            "  (import {} {} (func ${} (type {})",
+            string(import.module.as_bytes()),
+            string(import.name.as_bytes()),
+            import.function_name,
+            type_index(&import.signature)
+        )
+        .unwrap();
+        signature(&mut out, &import.signature);
+        out.push_str("))\n");
+    }
+    for function in &module.functions {
+        write!(
+            out,
+            "  (func ${} (type {})",
+            function.name,
+            type_index(&function.signature)
+        )
+        .unwrap();
+        signature(&mut out, &function.signature);
+        out.push('\n');
+        if !function.locals.is_empty() {
+            out.push_str("    (local");
+            for local in &function.locals {
+                write!(out, " {}", local).unwrap();
+            }
+            out.push_str(")\n");
+        }
+        let mut depth = 2;
+        for x in &function.body {
+            if matches!(x, Instr::Else | Instr::End) {
+                depth -= 1;
+            }
+            out.push_str(&"  ".repeat(depth));
+            instr(&mut out, module, &types, x);
+            out.push('\n');
+            if matches!(
+                x,
+                Instr::Block(_) | Instr::Loop(_) | Instr::If(_) | Instr::Else
+            ) {
+                depth += 1;
+            }
+        }
+        out.push_str("  )\n");
+    }
+    if !module.table.is_empty() {
+        writeln!(out, "  (table (;0;) {} funcref)", module.table.len() + 1).unwrap();
+    }
+    if let Some(pages) = module.memory {
+        writeln!(out, "  (memory (;0;) {})", pages).unwrap();
+    }
+    for global in &module.globals {
+        let ty = match global.mutable {
+            true => format!("(mut {})", global.ty),
+            false => global.ty.to_string(),
+        };
+        let mut init = String::new();
+        instr(&mut init, module, &types, &global.init);
+        writeln!(out, "  (global ${} {} ({}))", global.name, ty, init).unwrap();
+    }
+    for export in &module.exports {
+        let kind = match export.kind {
+            ExportKind::Function(index) => match module.function_name(index) {
+                Some(name) => format!("func ${}", name),
+                None => format!("func {}", index),
+            },
+            ExportKind::Memory(index) => format!("memory {}", index),
+        };
+        writeln!(
+            out,
+            "  (export {} ({}))",
+            string(export.name.as_bytes()),
+            kind
+        )
+        .unwrap();
+    }
+    if !module.table.is_empty() {
+        out.push_str("  (elem (i32.const 1) func");
+        for index in &module.table {
+            match module.function_name(*index) {
+                Some(name) => write!(out, " ${}", name).unwrap(),
+                None => write!(out, " {}", index).unwrap(),
+            }
+        }
+        out.push_str(")\n");
+    }
+    for data in &module.data {
+        writeln!(
+            out,
+            "  (data (i32.const {}) {})",
+            data.offset,
+            string(&data.bytes)
+        )
+        .unwrap();
+    }
+    out.push_str(")\n");
+    out
+}
@@ -0,0 +1,985 @@
+//! Writes out allocated low-level IR as x86-64 assembly in AT&T syntax, following the System V
+//! calling convention. Every instruction loads its operands into the scratch registers, computes
+//! its result there and then stores it to where its destination lives
+//!
+//! The stack frame of a function holds, below the saved `%rbp`, the callee-saved registers it uses,
+//! its spill slots, the slots of locals whose address is taken and a staging area which arguments
+//! are copied through, so that moving them into argument registers never overwrites one another
+
+use std::{collections::HashMap, fmt::Write};
+
+use shark_sema::numeric::Overflow;
+
+use crate::{
+    lir::{ArithOp, Callee, Class, CompareOp, Function, Inst, Label, VReg},
+    regalloc::{allocate, Allocation, Location},
+};
+
+/// The registers integer arguments are passed in, in order
+const INTEGER_ARGUMENTS: [&str; 6] = ["%rdi", "%rsi", "%rdx", "%rcx", "%r8", "%r9"];
+
+/// How many floating point arguments are passed in `%xmm0` to `%xmm7`
+const FLOAT_ARGUMENTS: usize = 8;
+
+/// The deepest calls can nest before the program stops with a stack overflow, as in the
+/// interpreter
+pub const MAX_CALL_DEPTH: u32 = 2048;
+
+/// Where each argument of a call is passed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Passing {
+    Register(&'static str),
+    /// On the stack, at an index counting up from the return address
+    Stack(usize),
+}
+
+fn passing(classes: impl Iterator<Item = Class>) -> Vec<Passing> {
+    let mut integers = 0;
+    let mut floats = 0;
+    let mut stack = 0;
+    classes
+        .map(|class| match class.is_float() {
+            true if floats < FLOAT_ARGUMENTS => {
+                floats += 1;
+                Passing::Register(XMM_ARGUMENTS[floats - 1])
+            }
+            false if integers < INTEGER_ARGUMENTS.len() => {
+                integers += 1;
+                Passing::Register(INTEGER_ARGUMENTS[integers - 1])
+            }
+            _ => {
+                stack += 1;
+                Passing::Stack(stack - 1)
+            }
+        })
+        .collect()
+}
+
+const XMM_ARGUMENTS: [&str; FLOAT_ARGUMENTS] = [
+    "%xmm0", "%xmm1", "%xmm2", "%xmm3", "%xmm4", "%xmm5", "%xmm6", "%xmm7",
+];
+
+/// The texts of the program, which live in read-only data: the bytes of its strings, along with
+/// the messages of runtime errors and where they happen. Every text is followed by a zero byte, so
+/// that the runtime can read them as C strings
+#[derive(Debug, Default)]
+pub struct Texts {
+    labels: HashMap<String, usize>,
+    pub texts: Vec<String>,
+}
+
+impl Texts {
+    /// Gets the label of a text
+    pub fn label(&mut self, text: &str) -> String {
+        let index = match self.labels.get(text) {
+            Some(index) => *index,
+            None => {
+                self.labels.insert(text.to_string(), self.texts.len());
+                self.texts.push(text.to_string());
+                self.texts.len() - 1
+            }
+        };
+        format!(".Ltext{}", index)
+    }
+}
+
+/// Writes a GNU assembler string holding some text followed by a zero byte
+pub fn string_directive(text: &str) -> String {
+    let mut result = String::from(".ascii \"");
+    for byte in text.bytes() {
+        match byte {
+            b'"' => result.push_str("\\\""),
+            b'\\' => result.push_str("\\\\"),
+            b' '..=b'~' => result.push(byte as char),
+            _ => {
+                let _ = write!(result, "\\{:03o}", byte);
+            }
+        }
+    }
+    result.push_str("\\0\"");
+    result
+}
+
+/// Gets the name of the low bits of a general purpose register, given its 64-bit name
+fn low(register: &str, bits: u8) -> String {
+    let name = &register[1..];
+    let numbered = name.starts_with('r') && name[1..].chars().all(|x| x.is_ascii_digit());
+    match (numbered, bits) {
+        (_, 64) => register.to_string(),
+        (true, 32) => format!("%{}d", name),
+        (true, _) => format!("%{}b", name),
+        (false, 32) => format!("%e{}", &name[1..]),
+        (false, _) => match name {
+            "rax" => "%al".to_string(),
+            "rbx" => "%bl".to_string(),
+            "rcx" => "%cl".to_string(),
+            "rdx" => "%dl".to_string(),
+            "rsi" => "%sil".to_string(),
+            "rdi" => "%dil".to_string(),
+            _ => unreachable!("`{}` has no low byte", register),
+        },
+    }
+}
+
+struct FunctionEmitter<'e> {
+    function: &'e Function,
+    allocation: Allocation,
+    texts: &'e mut Texts,
+    /// A counter for local labels which are not labels of the IR
+    labels: &'e mut usize,
+    /// How many slots sit between the saved `%rbp` and each part of the frame
+    spill_base: u32,
+    local_base: u32,
+    staging_base: u32,
+    lines: Vec<String>,
+}
+
+impl<'e> FunctionEmitter<'e> {
+    fn line(&mut self, text: impl AsRef<str>) {
+        self.lines.push(format!("    {}", text.as_ref()));
+    }
+
+    fn class(&self, register: VReg) -> Class {
+        self.function.registers[register.0 as usize]
+    }
+
+    fn slot(index: u32) -> String {
+        format!("-{}(%rbp)", 8 * (index + 1))
+    }
+
+    fn operand(&self, register: VReg) -> String {
+        match self.allocation.locations[&register] {
+            Location::Register(name) => name.to_string(),
+            Location::Spill(index) => Self::slot(self.spill_base + index),
+        }
+    }
+
+    fn label(&self, label: Label) -> String {
+        format!(".L{}_{}", self.function.name, label.0)
+    }
+
+    fn local_label(&mut self) -> String {
+        *self.labels += 1;
+        format!(".Llocal{}", *self.labels - 1)
+    }
+
+    /// Copies a register into `%rax`/`%rcx` or `%xmm0`/`%xmm1`
+    fn load(&mut self, register: VReg, scratch: &str) {
+        let operand = self.operand(register);
+        if operand == scratch {
+            return;
+        }
+        match scratch.starts_with("%xmm") {
+            true if operand.starts_with("%xmm") => {
+                self.line(format!("movaps {}, {}", operand, scratch))
+            }
+            true => self.line(format!("movsd {}, {}", operand, scratch)),
+            false => self.line(format!("movq {}, {}", operand, scratch)),
+        }
+    }
+
+    /// Copies a scratch register into where a register lives
+    fn store(&mut self, scratch: &str, register: VReg) {
+        let operand = self.operand(register);
+        if operand == scratch {
+            return;
+        }
+        match scratch.starts_with("%xmm") {
+            true if operand.starts_with("%xmm") => {
+                self.line(format!("movaps {}, {}", scratch, operand))
+            }
+            true => self.line(format!("movsd {}, {}", scratch, operand)),
+            false => self.line(format!("movq {}, {}", scratch, operand)),
+        }
+    }
+
+    fn scratch(class: Class) -> &'static str {
+        match class.is_float() {
+            true => "%xmm0",
+            false => "%rax",
+        }
+    }
+
+    /// Sign or zero extends the low bits of a general purpose register to the whole of it
+    fn normalize(&mut self, class: Class, register: &str) {
+        match class {
+            Class::Int { bits: 8, signed } => {
+                let op = if signed { "movsbq" } else { "movzbq" };
+                self.line(format!("{} {}, {}", op, low(register, 8), register));
+            }
+            Class::Bool => {
+                self.line(format!("movzbq {}, {}", low(register, 8), register));
+            }
+            Class::Int {
+                bits: 32,
+                signed: true,
+            } => self.line(format!("movslq {}, {}", low(register, 32), register)),
+            Class::Int { bits: 32, .. } => {
+                let low = low(register, 32);
+                self.line(format!("movl {}, {}", low, low));
+            }
+            _ => {}
+        }
+    }
+
+    /// Stops the program with a runtime error if a condition code holds
+    fn panic_if(&mut self, condition: &str, message: &str, at: &str) {
+        let ok = self.local_label();
+        let inverse = match condition.strip_prefix('n') {
+            Some(condition) => condition.to_string(),
+            None => format!("n{}", condition),
+        };
+        self.line(format!("j{} {}", inverse, ok));
+        self.panic(message, at);
+        self.lines.push(format!("{}:", ok));
+    }
+
+    /// Calls the runtime to stop the program with a runtime error, whose message is followed by
+    /// the `Str` at the address in `text` if there is one
+    fn panic_with(&mut self, message: &str, text: Option<VReg>, at: &str) {
+        match text {
+            Some(text) => {
+                self.load(text, "%rax");
+                self.line("movq %rax, %rsi");
+            }
+            None => self.line("xorl %esi, %esi"),
+        }
+        let message = self.texts.label(message);
+        let at = self.texts.label(at);
+        self.line(format!("leaq {}(%rip), %rdi", message));
+        self.line(format!("leaq {}(%rip), %rdx", at));
+        self.line("call shark_panic");
+    }
+
+    fn panic(&mut self, message: &str, at: &str) {
+        self.panic_with(message, None, at);
+    }
+
+    /// Checks that the result of narrow arithmetic in `%rax` fits its class
+    fn check_range(&mut self, class: Class, message: &str, at: &str) {
+        self.line("movq %rax, %rcx");
+        self.normalize(class, "%rcx");
+        self.line("cmpq %rax, %rcx");
+        self.panic_if("ne", message, at);
+    }
+
+    fn emit(mut self) -> String {
+        let frame = self.staging_base
+            + self
+                .function
+                .code
+                .iter()
+                .map(|x| match x {
+                    Inst::Call { arguments, .. } => arguments.len() as u32,
+                    _ => 0,
+                })
+                .chain([self.function.parameters.len() as u32])
+                .max()
+                .unwrap_or(0);
+        let frame = (8 * frame).next_multiple_of(16);
+
+        let name = self.function.name.clone();
+        self.lines.push(format!("{}:", name));
+        self.line("pushq %rbp");
+        self.line("movq %rsp, %rbp");
+        if frame > 0 {
+            self.line(format!("subq ${}, %rsp", frame));
+        }
+        for (index, register) in self.allocation.callee_saved.clone().iter().enumerate() {
+            self.line(format!("movq {}, {}", register, Self::slot(index as u32)));
+        }
+
+        // Parameters in registers go through the staging area, as their registers may be where
+        // other parameters are allocated
+        let parameters = self.function.parameters.clone();
+        let passing = passing(parameters.iter().map(|x| self.class(*x)));
+        for (index, passed) in passing.iter().enumerate() {
+            if let Passing::Register(register) = passed {
+                let slot = Self::slot(self.staging_base + index as u32);
+                match register.starts_with("%xmm") {
+                    true => self.line(format!("movsd {}, {}", register, slot)),
+                    false => self.line(format!("movq {}, {}", register, slot)),
+                }
+            }
+        }
+        for (index, (parameter, passed)) in parameters.iter().zip(&passing).enumerate() {
+            if !self.allocation.locations.contains_key(parameter) {
+                continue;
+            }
+            let source = match passed {
+                Passing::Register(_) => Self::slot(self.staging_base + index as u32),
+                Passing::Stack(index) => format!("{}(%rbp)", 16 + 8 * index),
+            };
+            let scratch = Self::scratch(self.class(*parameter));
+            match scratch {
+                "%xmm0" => self.line(format!("movsd {}, %xmm0", source)),
+                _ => self.line(format!("movq {}, %rax", source)),
+            }
+            self.store(scratch, *parameter);
+        }
+
+        for inst in &self.function.code {
+            self.inst(inst);
+        }
+        let mut result = String::new();
+        for line in &self.lines {
+            let _ = writeln!(result, "{}", line);
+        }
+        result
+    }
+
+    fn epilogue(&mut self) {
+        for (index, register) in self.allocation.callee_saved.clone().iter().enumerate() {
+            self.line(format!("movq {}, {}", Self::slot(index as u32), register));
+        }
+        self.line("leave");
+        self.line("ret");
+    }
+
+    fn inst(&mut self, inst: &Inst) {
+        match inst {
+            Inst::Label(label) => {
+                let label = self.label(*label);
+                self.lines.push(format!("{}:", label));
+            }
+            Inst::Const { dst, bits } => {
+                let class = self.class(*dst);
+                match *bits as i64 >= i32::MIN as i64 && *bits as i64 <= i32::MAX as i64 {
+                    true => self.line(format!("movq ${}, %rax", *bits as i64)),
+                    false => self.line(format!("movabsq ${}, %rax", *bits as i64)),
+                }
+                match class.is_float() {
+                    true => {
+                        self.line("movq %rax, %xmm0");
+                        self.store("%xmm0", *dst);
+                    }
+                    false => self.store("%rax", *dst),
+                }
+            }
+            Inst::Move { dst, src } => {
+                if self.operand(*dst) != self.operand(*src) {
+                    let scratch = Self::scratch(self.class(*dst));
+                    self.load(*src, scratch);
+                    self.store(scratch, *dst);
+                }
+            }
+            Inst::Arith {
+                op,
+                class,
+                overflow,
+                dst,
+                left,
+                right,
+                at,
+            } => match class.is_float() {
+                true => {
+                    self.load(*left, "%xmm0");
+                    self.load(*right, "%xmm1");
+                    let suffix = if *class == Class::Float32 { "ss" } else { "sd" };
+                    let name = match op {
+                        ArithOp::Add => "add",
+                        ArithOp::Subtract => "sub",
+                        ArithOp::Multiply => "mul",
+                        ArithOp::Divide => "div",
+                        _ => unreachable!("`{:?}` is not defined on floats", op),
+                    };
+                    self.line(format!("{}{} %xmm1, %xmm0", name, suffix));
+                    self.store("%xmm0", *dst);
+                }
+                false => {
+                    self.load(*left, "%rax");
+                    self.load(*right, "%rcx");
+                    self.integer_arith(*op, *class, *overflow, at);
+                    self.store("%rax", *dst);
+                }
+            },
+            Inst::Compare {
+                op,
+                class,
+                dst,
+                left,
+                right,
+            } => {
+                self.compare(*op, *class, *left, *right);
+                self.line("movzbq %al, %rax");
+                self.store("%rax", *dst);
+            }
+            Inst::Negate {
+                class,
+                overflow,
+                dst,
+                src,
+                at,
+            } => match class {
+                Class::Float32 | Class::Float64 => {
+                    self.load(*src, "%xmm0");
+                    let mask: u64 = match class {
+                        Class::Float32 => 1 << 31,
+                        _ => 1 << 63,
+                    };
+                    self.line(format!("movabsq ${}, %rax", mask as i64));
+                    self.line("movq %rax, %xmm1");
+                    self.line("xorps %xmm1, %xmm0");
+                    self.store("%xmm0", *dst);
+                }
+                _ => {
+                    self.load(*src, "%rax");
+                    self.integer_negate(*class, *overflow, at);
+                    self.store("%rax", *dst);
+                }
+            },
+            Inst::Convert {
+                from,
+                to,
+                overflow,
+                dst,
+                src,
+                at,
+            } => {
+                self.load(*src, Self::scratch(*from));
+                self.convert(*from, *to, *overflow, at);
+                self.store(Self::scratch(*to), *dst);
+            }
+            Inst::Not { class, dst, src } => {
+                self.load(*src, "%rax");
+                match class {
+                    Class::Bool => self.line("xorq $1, %rax"),
+                    _ => {
+                        self.line("notq %rax");
+                        self.normalize(*class, "%rax");
+                    }
+                }
+                self.store("%rax", *dst);
+            }
+            Inst::Call {
+                dst,
+                callee,
+                arguments,
+                depth,
+            } => self.call(*dst, callee, arguments, depth.as_deref()),
+            Inst::Address { dst, symbol } => {
+                self.line(format!("leaq {}(%rip), %rax", symbol));
+                self.store("%rax", *dst);
+            }
+            Inst::SlotAddress { dst, slot } => {
+                let slot = Self::slot(self.local_base + slot);
+                self.line(format!("leaq {}, %rax", slot));
+                self.store("%rax", *dst);
+            }
+            Inst::Load {
+                class,
+                dst,
+                address,
+            } => {
+                self.load(*address, "%rax");
+                match class {
+                    Class::Int {
+                        bits: 8,
+                        signed: true,
+                    } => self.line("movsbq (%rax), %rax"),
+                    Class::Int { bits: 8, .. } | Class::Bool => self.line("movzbq (%rax), %rax"),
+                    Class::Int {
+                        bits: 32,
+                        signed: true,
+                    } => self.line("movslq (%rax), %rax"),
+                    Class::Int { bits: 32, .. } => self.line("movl (%rax), %eax"),
+                    Class::Int { .. } => self.line("movq (%rax), %rax"),
+                    Class::Float32 => self.line("movss (%rax), %xmm0"),
+                    Class::Float64 => self.line("movsd (%rax), %xmm0"),
+                }
+                self.store(Self::scratch(*class), *dst);
+            }
+            Inst::Store {
+                class,
+                address,
+                src,
+            } => {
+                self.load(*address, "%rax");
+                match class {
+                    Class::Float32 => {
+                        self.load(*src, "%xmm0");
+                        self.line("movss %xmm0, (%rax)");
+                    }
+                    Class::Float64 => {
+                        self.load(*src, "%xmm0");
+                        self.line("movsd %xmm0, (%rax)");
+                    }
+                    _ => {
+                        self.load(*src, "%rcx");
+                        match class.size() {
+                            1 => self.line("movb %cl, (%rax)"),
+                            4 => self.line("movl %ecx, (%rax)"),
+                            _ => self.line("movq %rcx, (%rax)"),
+                        }
+                    }
+                }
+            }
+            Inst::Jump(label) => {
+                let label = self.label(*label);
+                self.line(format!("jmp {}", label));
+            }
+            Inst::JumpIfZero { condition, target } => {
+                self.load(*condition, "%rax");
+                self.line("testq %rax, %rax");
+                let target = self.label(*target);
+                self.line(format!("je {}", target));
+            }
+            Inst::Return(value) => {
+                if let Some(value) = value {
+                    let scratch = Self::scratch(self.class(*value));
+                    self.load(*value, scratch);
+                }
+                self.epilogue();
+            }
+            Inst::Panic { message, text, at } => self.panic_with(message, *text, at),
+            Inst::Unreachable => self.line("ud2"),
+        }
+    }
+
+    /// Applies an operator to `%rax` and `%rcx`, leaving the result in `%rax`
+    fn integer_arith(&mut self, op: ArithOp, class: Class, overflow: Overflow, at: &str) {
+        let (bits, signed) = match class {
+            Class::Int { bits, signed } => (bits, signed),
+            _ => (8, false),
+        };
+        let message = |name| format!("attempt to {} with overflow", name);
+        match op {
+            ArithOp::Add | ArithOp::Subtract | ArithOp::Multiply => {
+                let (instruction, name) = match op {
+                    ArithOp::Add => ("addq %rcx, %rax", "add"),
+                    ArithOp::Subtract => ("subq %rcx, %rax", "subtract"),
+                    _ if bits == 64 && !signed => ("mulq %rcx", "multiply"),
+                    _ => ("imulq %rcx, %rax", "multiply"),
+                };
+                // A signed result saturates towards the sign of the operand in `%rdx`, which
+                // is computed before the operation overwrites `%rax`
+                if (bits, signed, overflow) == (64, true, Overflow::Saturate) {
+                    match op {
+                        ArithOp::Add => self.line("movq %rcx, %rdx"),
+                        ArithOp::Subtract => {
+                            self.line("movq %rcx, %rdx");
+                            self.line("notq %rdx");
+                        }
+                        _ => {
+                            self.line("movq %rax, %rdx");
+                            self.line("xorq %rcx, %rdx");
+                        }
+                    }
+                }
+                self.line(instruction);
+                let condition = match (signed, op) {
+                    (false, ArithOp::Add | ArithOp::Subtract) => "c",
+                    _ => "o",
+                };
+                match (bits, overflow) {
+                    (64, Overflow::Trap) => self.panic_if(condition, &message(name), at),
+                    (64, Overflow::Wrap) => {}
+                    (64, Overflow::Saturate) => {
+                        let ok = self.local_label();
+                        self.line(format!("jn{} {}", condition, ok));
+                        match (signed, op) {
+                            (true, _) => {
+                                self.line("sarq $63, %rdx");
+                                self.line(format!("movabsq ${}, %rax", i64::MAX));
+                                self.line("xorq %rdx, %rax");
+                            }
+                            (false, ArithOp::Subtract) => self.line("xorl %eax, %eax"),
+                            (false, _) => self.line("movq $-1, %rax"),
+                        }
+                        self.lines.push(format!("{}:", ok));
+                    }
+                    (_, Overflow::Trap) => self.check_range(class, &message(name), at),
+                    (_, Overflow::Wrap) => self.normalize(class, "%rax"),
+                    (_, Overflow::Saturate) => self.clamp(class, op),
+                }
+            }
+            ArithOp::Divide => {
+                self.line("testq %rcx, %rcx");
+                self.panic_if("e", "attempt to divide by zero", at);
+                match signed {
+                    true => {
+                        let done = self.local_label();
+                        if bits == 64 {
+                            // The one quotient which does not fit, and which `idiv` traps on
+                            let ok = self.local_label();
+                            self.line("cmpq $-1, %rcx");
+                            self.line(format!("jne {}", ok));
+                            self.line("movabsq $-9223372036854775808, %rdx");
+                            self.line("cmpq %rdx, %rax");
+                            match overflow {
+                                Overflow::Trap => self.panic_if("e", &message("divide"), at),
+                                // The smallest value is what the quotient wraps around to
+                                Overflow::Wrap => self.line(format!("je {}", done)),
+                                Overflow::Saturate => {
+                                    self.line(format!("jne {}", ok));
+                                    self.line(format!("movabsq ${}, %rax", i64::MAX));
+                                    self.line(format!("jmp {}", done));
+                                }
+                            }
+                            self.lines.push(format!("{}:", ok));
+                        }
+                        self.line("cqto");
+                        self.line("idivq %rcx");
+                        if bits < 64 {
+                            match overflow {
+                                Overflow::Trap => self.check_range(class, &message("divide"), at),
+                                Overflow::Wrap => self.normalize(class, "%rax"),
+                                Overflow::Saturate => self.clamp(class, op),
+                            }
+                        }
+                        self.lines.push(format!("{}:", done));
+                    }
+                    false => {
+                        self.line("xorl %edx, %edx");
+                        self.line("divq %rcx");
+                    }
+                }
+            }
+            ArithOp::BitwiseAnd => self.line("andq %rcx, %rax"),
+            ArithOp::ShiftLeft | ArithOp::ShiftRight => {
+                let (name, instruction) = match (op, signed) {
+                    (ArithOp::ShiftLeft, _) => ("shift left", "shlq"),
+                    (_, true) => ("shift right", "sarq"),
+                    _ => ("shift right", "shrq"),
+                };
+                // The amount is compared unsigned, which also rules out negative amounts
+                self.line(format!("cmpq ${}, %rcx", bits));
+                self.panic_if("ae", &message(name), at);
+                self.line(format!("{} %cl, %rax", instruction));
+                self.normalize(class, "%rax");
+            }
+        }
+    }
+
+    /// Negates the integer in `%rax`
+    fn integer_negate(&mut self, class: Class, overflow: Overflow, at: &str) {
+        let message = "attempt to negate with overflow";
+        match (class, overflow) {
+            (Class::Int { signed: false, .. } | Class::Bool, Overflow::Trap) => {
+                self.line("testq %rax, %rax");
+                self.panic_if("ne", message, at);
+            }
+            (Class::Int { signed: false, .. } | Class::Bool, Overflow::Saturate) => {
+                self.line("xorl %eax, %eax");
+            }
+            (Class::Int { bits: 64, .. }, _) => {
+                self.line("negq %rax");
+                match overflow {
+                    Overflow::Trap => self.panic_if("o", message, at),
+                    Overflow::Wrap => {}
+                    Overflow::Saturate => {
+                        // Only the smallest value overflows, and flipping its bits gives the
+                        // largest
+                        let ok = self.local_label();
+                        self.line(format!("jno {}", ok));
+                        self.line("notq %rax");
+                        self.lines.push(format!("{}:", ok));
+                    }
+                }
+            }
+            _ => {
+                self.line("negq %rax");
+                match overflow {
+                    Overflow::Trap => self.check_range(class, message, at),
+                    Overflow::Wrap => self.normalize(class, "%rax"),
+                    Overflow::Saturate => self.clamp(class, ArithOp::Subtract),
+                }
+            }
+        }
+    }
+
+    /// Gives the result of narrow arithmetic in `%rax` the nearest value which fits its class
+    fn clamp(&mut self, class: Class, op: ArithOp) {
+        let Class::Int { bits, signed } = class else {
+            unreachable!("only integers are clamped");
+        };
+        match signed {
+            true => {
+                let max = (1i64 << (bits - 1)) - 1;
+                self.line(format!("movq ${}, %rdx", max));
+                self.line("cmpq %rdx, %rax");
+                self.line("cmovgq %rdx, %rax");
+                self.line(format!("movq ${}, %rdx", -max - 1));
+                self.line("cmpq %rdx, %rax");
+                self.line("cmovlq %rdx, %rax");
+            }
+            false => {
+                // A result below zero comes from subtracting, while any other result beyond the
+                // largest `Int64` is the product of two `UInt32`s, which is too large
+                if op == ArithOp::Subtract {
+                    self.line("xorl %edx, %edx");
+                    self.line("testq %rax, %rax");
+                    self.line("cmovsq %rdx, %rax");
+                }
+                self.line(format!("movl ${}, %edx", (1u64 << bits) - 1));
+                self.line("cmpq %rdx, %rax");
+                self.line("cmovaq %rdx, %rax");
+            }
+        }
+    }
+
+    /// Converts the number in `%rax` or `%xmm0` from one class to another, leaving the result in
+    /// `%rax` or `%xmm0`. A float is compared with bounds which are powers of two and so exact,
+    /// after being widened to a `Float64`, and `NaN` fails every comparison
+    fn convert(&mut self, from: Class, to: Class, overflow: Overflow, at: &str) {
+        let message = "attempt to convert with overflow";
+        match (from, to) {
+            (
+                Class::Int { bits, signed },
+                Class::Int {
+                    bits: to_bits,
+                    signed: to_signed,
+                },
+            ) => {
+                let lossless = (to_bits > bits && (to_signed || !signed))
+                    || (to_bits == bits && to_signed == signed);
+                if overflow == Overflow::Trap && !lossless {
+                    // Values whose sign bit is set do not fit when the signedness changes at 64
+                    // bits, while narrower values must keep their value when truncated
+                    if to_bits == 64 || bits == 64 && !signed {
+                        self.line("testq %rax, %rax");
+                        self.panic_if("s", message, at);
+                    }
+                    if to_bits < 64 {
+                        self.check_range(to, message, at);
+                    }
+                }
+                self.normalize(to, "%rax");
+            }
+            (Class::Int { bits, signed }, _) => {
+                let suffix = if to == Class::Float32 { "ss" } else { "sd" };
+                if (bits, signed) != (64, false) {
+                    self.line(format!("cvtsi2{}q %rax, %xmm0", suffix));
+                    return;
+                }
+                // A `UInt64` beyond the largest `Int64` is halved, keeping the bit which rounds
+                // it, then doubled again
+                let large = self.local_label();
+                let done = self.local_label();
+                self.line("testq %rax, %rax");
+                self.line(format!("js {}", large));
+                self.line(format!("cvtsi2{}q %rax, %xmm0", suffix));
+                self.line(format!("jmp {}", done));
+                self.lines.push(format!("{}:", large));
+                self.line("movq %rax, %rcx");
+                self.line("shrq $1, %rcx");
+                self.line("andl $1, %eax");
+                self.line("orq %rax, %rcx");
+                self.line(format!("cvtsi2{}q %rcx, %xmm0", suffix));
+                self.line(format!("add{} %xmm0, %xmm0", suffix));
+                self.lines.push(format!("{}:", done));
+            }
+            (Class::Float32, Class::Float64) => self.line("cvtss2sd %xmm0, %xmm0"),
+            (Class::Float64, Class::Float32) => self.line("cvtsd2ss %xmm0, %xmm0"),
+            (_, Class::Float32 | Class::Float64) => {}
+            (_, Class::Int { bits, signed }) => {
+                if from == Class::Float32 {
+                    self.line("cvtss2sd %xmm0, %xmm0");
+                }
+                let (min, max) = match signed {
+                    true => (-(1i128 << (bits - 1)), (1i128 << (bits - 1)) - 1),
+                    false => (0, (1i128 << bits) - 1),
+                };
+                // The smallest `Int64` is the only bound with no float just below it
+                let (lower, below) = match bits {
+                    64 => (min as f64, "b"),
+                    _ => ((min - 1) as f64, "be"),
+                };
+                let upper = (max + 1) as f64;
+                let (zero, smallest, largest, done) = (
+                    self.local_label(),
+                    self.local_label(),
+                    self.local_label(),
+                    self.local_label(),
+                );
+                if overflow != Overflow::Trap {
+                    self.line("ucomisd %xmm0, %xmm0");
+                    self.line(format!("jp {}", zero));
+                }
+                self.line(format!("movabsq ${}, %rax", lower.to_bits() as i64));
+                self.line("movq %rax, %xmm1");
+                self.line("ucomisd %xmm1, %xmm0");
+                match overflow {
+                    Overflow::Trap => self.panic_if(below, message, at),
+                    _ => self.line(format!("j{} {}", below, smallest)),
+                }
+                self.line(format!("movabsq ${}, %rax", upper.to_bits() as i64));
+                self.line("movq %rax, %xmm1");
+                self.line("ucomisd %xmm0, %xmm1");
+                match overflow {
+                    Overflow::Trap => self.panic_if("be", message, at),
+                    _ => self.line(format!("jbe {}", largest)),
+                }
+                if (bits, signed) == (64, false) {
+                    // Values from 2^63 on are moved into the range of an `Int64` first
+                    let small = self.local_label();
+                    self.line(format!(
+                        "movabsq ${}, %rax",
+                        (2f64.powi(63)).to_bits() as i64
+                    ));
+                    self.line("movq %rax, %xmm1");
+                    self.line("ucomisd %xmm1, %xmm0");
+                    self.line(format!("jb {}", small));
+                    self.line("subsd %xmm1, %xmm0");
+                    self.line("cvttsd2siq %xmm0, %rax");
+                    self.line("btcq $63, %rax");
+                    self.line(format!("jmp {}", done));
+                    self.lines.push(format!("{}:", small));
+                }
+                self.line("cvttsd2siq %xmm0, %rax");
+                if overflow != Overflow::Trap {
+                    self.line(format!("jmp {}", done));
+                    self.lines.push(format!("{}:", zero));
+                    self.line("xorl %eax, %eax");
+                    self.line(format!("jmp {}", done));
+                    self.lines.push(format!("{}:", smallest));
+                    self.line(format!("movabsq ${}, %rax", min as i64));
+                    self.line(format!("jmp {}", done));
+                    self.lines.push(format!("{}:", largest));
+                    self.line(format!("movabsq ${}, %rax", max as i64));
+                }
+                self.lines.push(format!("{}:", done));
+            }
+            _ => unreachable!("only numbers are converted"),
+        }
+    }
+
+    /// Compares two registers, leaving whether the comparison holds in `%al`
+    fn compare(&mut self, op: CompareOp, class: Class, left: VReg, right: VReg) {
+        if class.is_float() {
+            let instruction = match class {
+                Class::Float32 => "ucomiss",
+                _ => "ucomisd",
+            };
+            // Only `above` conditions are false for unordered operands, so `<` swaps them
+            let (first, second) = match op {
+                CompareOp::Lesser | CompareOp::LessOrEqual => (right, left),
+                _ => (left, right),
+            };
+            self.load(first, "%xmm0");
+            self.load(second, "%xmm1");
+            self.line(format!("{} %xmm1, %xmm0", instruction));
+            match op {
+                CompareOp::Greater | CompareOp::Lesser => self.line("seta %al"),
+                CompareOp::GreaterOrEqual | CompareOp::LessOrEqual => self.line("setae %al"),
+                CompareOp::EqualTo => {
+                    self.line("sete %al");
+                    self.line("setnp %cl");
+                    self.line("andb %cl, %al");
+                }
+                CompareOp::NotEqual => {
+                    self.line("setne %al");
+                    self.line("setp %cl");
+                    self.line("orb %cl, %al");
+                }
+            }
+            return;
+        }
+        self.load(left, "%rax");
+        self.load(right, "%rcx");
+        self.line("cmpq %rcx, %rax");
+        let signed = matches!(class, Class::Int { signed: true, .. });
+        let condition = match (op, signed) {
+            (CompareOp::Greater, true) => "g",
+            (CompareOp::Lesser, true) => "l",
+            (CompareOp::GreaterOrEqual, true) => "ge",
+            (CompareOp::LessOrEqual, true) => "le",
+            (CompareOp::Greater, false) => "a",
+            (CompareOp::Lesser, false) => "b",
+            (CompareOp::GreaterOrEqual, false) => "ae",
+            (CompareOp::LessOrEqual, false) => "be",
+            (CompareOp::EqualTo, _) => "e",
+            (CompareOp::NotEqual, _) => "ne",
+        };
+        self.line(format!("set{} %al", condition));
+    }
+
+    /// Calls a function, following the System V calling convention. The result of a function of
+    /// the runtime is only extended to 32 bits if at all, so it is extended to the whole register
+    fn call(
+        &mut self,
+        dst: Option<VReg>,
+        callee: &Callee,
+        arguments: &[VReg],
+        depth: Option<&str>,
+    ) {
+        if let Some(at) = depth {
+            self.line("incl shark_depth(%rip)");
+            self.line(format!("cmpl ${}, shark_depth(%rip)", MAX_CALL_DEPTH));
+            self.panic_if("a", "stack overflow", at);
+        }
+
+        for (index, argument) in arguments.iter().enumerate() {
+            let scratch = Self::scratch(self.class(*argument));
+            self.load(*argument, scratch);
+            let slot = Self::slot(self.staging_base + index as u32);
+            match scratch {
+                "%xmm0" => self.line(format!("movsd %xmm0, {}", slot)),
+                _ => self.line(format!("movq %rax, {}", slot)),
+            }
+        }
+        let passing = passing(arguments.iter().map(|x| self.class(*x)));
+        let on_stack: Vec<usize> = (0..arguments.len())
+            .filter(|x| matches!(passing[*x], Passing::Stack(_)))
+            .collect();
+        // The stack must stay aligned to 16 bytes at the call
+        let padding = on_stack.len() % 2 == 1;
+        if padding {
+            self.line("subq $8, %rsp");
+        }
+        for index in on_stack.iter().rev() {
+            let slot = Self::slot(self.staging_base + *index as u32);
+            self.line(format!("pushq {}", slot));
+        }
+        // The address is taken before the arguments are moved into registers it may live in
+        if let Callee::Address(address) = callee {
+            self.load(*address, "%r11");
+        }
+        for (index, passed) in passing.iter().enumerate() {
+            if let Passing::Register(register) = passed {
+                let slot = Self::slot(self.staging_base + index as u32);
+                match register.starts_with("%xmm") {
+                    true => self.line(format!("movsd {}, {}", slot, register)),
+                    false => self.line(format!("movq {}, {}", slot, register)),
+                }
+            }
+        }
+        match callee {
+            Callee::Function(function) => self.line(format!("call {}", function)),
+            Callee::Address(_) => self.line("call *%r11"),
+        }
+        let popped = 8 * (on_stack.len() + padding as usize);
+        if popped > 0 {
+            self.line(format!("addq ${}, %rsp", popped));
+        }
+        if depth.is_some() {
+            self.line("decl shark_depth(%rip)");
+        }
+        if let Some(dst) = dst {
+            let class = self.class(dst);
+            if !class.is_float() {
+                self.normalize(class, "%rax");
+            }
+            self.store(Self::scratch(class), dst);
+        }
+    }
+}
+
+/// Allocates registers for a function and writes it out as assembly
+pub fn emit_function(function: &Function, texts: &mut Texts, labels: &mut usize) -> String {
+    let allocation = allocate(function);
+    let spill_base = allocation.callee_saved.len() as u32;
+    let local_base = spill_base + allocation.spills;
+    let staging_base = local_base + function.slots;
+    FunctionEmitter {
+        function,
+        allocation,
+        texts,
+        labels,
+        spill_base,
+        local_base,
+        staging_base,
+        lines: Vec::new(),
+    }
+    .emit()
+}
@@ -0,0 +1,105 @@
+//! Compiles the optimised IR of a program to x86-64 assembly for Linux, which the system toolchain
+//! then assembles and links into an executable along with the C runtime of `shark-std`.
+//! Instructions are first selected into a low-level IR over virtual registers, see [lir], which a
+//! linear-scan register allocator then maps onto machine registers and stack slots
+//!
+//! The program behaves like the interpreter: arithmetic overflows, runtime errors are printed to
+//! stderr and exit with code 101, and `main` decides the exit code. Foreign functions can not be
+//! called, see [select]
+
+use std::{
+    io::Write,
+    path::Path,
+    process::{Command, Stdio},
+};
+
+use emit::{emit_function, string_directive};
+use select::Selector;
+use shark_core::diagnostic::Diagnostic;
+use shark_ir::{build::ENTRY, ir::Module};
+
+pub mod emit;
+pub mod lir;
+pub mod regalloc;
+pub mod runtime;
+pub mod select;
+
+#[cfg(test)]
+pub mod tests;
+
+/// Generates the assembly of a program for a module of the IR, whose `main` starts the runtime
+/// with the arguments of the program and then calls the [ENTRY] of the module. `path` and `source`
+/// are those of the module, which runtime errors point into
+pub fn generate(
+    module: &Module,
+    path: Option<&Path>,
+    source: &str,
+) -> Result<String, Vec<Diagnostic>> {
+    let mut selector = Selector::new(module, path, source);
+    let functions: Vec<_> = module
+        .functions
+        .iter()
+        .map(|x| selector.select(x))
+        .collect();
+    if !selector.diagnostics.is_empty() {
+        return Err(selector.diagnostics);
+    }
+
+    let entry = module.function(ENTRY).map(|_| selector.name(ENTRY));
+    let mut result = String::from("    .text\n");
+    result.push_str(&runtime::entry(entry));
+    let mut labels = 0;
+    for function in &functions {
+        result.push('\n');
+        result.push_str(&emit_function(function, &mut selector.texts, &mut labels));
+    }
+    result.push_str("\n    .section .rodata\n");
+    for (index, text) in selector.texts.texts.iter().enumerate() {
+        result.push_str(&format!(
+            ".Ltext{}:\n    {}\n",
+            index,
+            string_directive(text)
+        ));
+    }
+    result.push_str("\n    .bss\n    .p2align 2\nshark_depth:\n    .zero 4\n");
+    result.push_str("\n    .section .note.GNU-stack,\"\",@progbits\n");
+    Ok(result)
+}
+
+/// Assembles and links an assembly file into an executable with the C compiler named by `$CC`, or
+/// `cc`, which drives the system assembler and linker. The C runtime of `shark-std` is compiled
+/// along with it, given to the compiler on its stdin
+pub fn build_executable(source: &Path, output: &Path) -> Result<(), String> {
+    let compiler = std::env::var("CC").unwrap_or_else(|_| "cc".to_string());
+    let mut child = Command::new(&compiler)
+        .args(["-std=c99", "-O2", "-o"])
+        .arg(output)
+        .args(["-x", "c", "-", "-x", "none"])
+        .arg(source)
+        .arg("-lm")
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .map_err(|x| format!("could not run `{}`: {}", compiler, x))?;
+    let mut stdin = child
+        .stdin
+        .take()
+        .expect("the stdin of the compiler is piped");
+    stdin
+        .write_all(shark_std::runtime::C_SOURCE.as_bytes())
+        .map_err(|x| format!("could not give `{}` the runtime: {}", compiler, x))?;
+    drop(stdin);
+    let result = child
+        .wait_with_output()
+        .map_err(|x| format!("could not run `{}`: {}", compiler, x))?;
+    match result.status.success() {
+        true => Ok(()),
+        false => Err(format!(
+            "`{}` failed to assemble `{}`:\n{}",
+            compiler,
+            source.display(),
+            String::from_utf8_lossy(&result.stderr)
+        )),
+    }
+}
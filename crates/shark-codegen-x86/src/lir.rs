@@ -0,0 +1,255 @@
+//! The low-level IR instructions are selected into. It is a flat list of instructions over an
+//! unlimited number of virtual registers, each holding a scalar which fits in a machine register.
+//! Integers are kept sign or zero extended to 64 bits according to their type, so that they can
+//! always be moved and compared as a whole register
+
+use std::fmt::{self, Display};
+
+use shark_sema::numeric::Overflow;
+
+/// A virtual register, which is given a machine register or a stack slot by the register allocator
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct VReg(pub u32);
+
+impl Display for VReg {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}", self.0)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Label(pub u32);
+
+/// How the value in a register is operated on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Class {
+    /// An integer of some width, which also covers `Char` and addresses
+    Int {
+        bits: u8,
+        signed: bool,
+    },
+    /// A `Bool`, which is zero or one
+    Bool,
+    Float32,
+    Float64,
+}
+
+impl Class {
+    pub const POINTER: Self = Self::Int {
+        bits: 64,
+        signed: false,
+    };
+
+    pub fn is_float(self) -> bool {
+        matches!(self, Self::Float32 | Self::Float64)
+    }
+
+    /// The size of a value of the class in memory, in bytes
+    pub fn size(self) -> u8 {
+        match self {
+            Self::Int { bits, .. } => bits / 8,
+            Self::Bool => 1,
+            Self::Float32 => 4,
+            Self::Float64 => 8,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ArithOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    BitwiseAnd,
+    ShiftLeft,
+    ShiftRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CompareOp {
+    Greater,
+    Lesser,
+    GreaterOrEqual,
+    LessOrEqual,
+    EqualTo,
+    NotEqual,
+}
+
+/// Where a runtime error is reported from, as the text of a source position
+pub type At = String;
+
+/// What a call calls
+#[derive(Debug, Clone, PartialEq)]
+pub enum Callee {
+    /// A function by its symbol
+    Function(String),
+    /// The function whose address is in a register
+    Address(VReg),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Inst {
+    Label(Label),
+    /// Loads the bits of a constant, which for `Float32` are in the low 32 bits
+    Const {
+        dst: VReg,
+        bits: u64,
+    },
+    Move {
+        dst: VReg,
+        src: VReg,
+    },
+    /// Applies an operator to two values of a class, which overflows the way `overflow` says to
+    Arith {
+        op: ArithOp,
+        class: Class,
+        overflow: Overflow,
+        dst: VReg,
+        left: VReg,
+        right: VReg,
+        at: At,
+    },
+    /// Compares two values of a class, giving a `Bool`
+    Compare {
+        op: CompareOp,
+        class: Class,
+        dst: VReg,
+        left: VReg,
+        right: VReg,
+    },
+    Negate {
+        class: Class,
+        overflow: Overflow,
+        dst: VReg,
+        src: VReg,
+        at: At,
+    },
+    /// Converts a number from one class to another, a value which does not fit trapping or
+    /// wrapping around as `overflow` says to
+    Convert {
+        from: Class,
+        to: Class,
+        overflow: Overflow,
+        dst: VReg,
+        src: VReg,
+        at: At,
+    },
+    /// Flips a `Bool`, or every bit of an integer
+    Not {
+        class: Class,
+        dst: VReg,
+        src: VReg,
+    },
+    /// Calls a function. A call which counts towards how deep calls are nested has where it is,
+    /// for the stack overflow it may stop the program with
+    Call {
+        dst: Option<VReg>,
+        callee: Callee,
+        arguments: Vec<VReg>,
+        depth: Option<At>,
+    },
+    /// Gets the address of a symbol: a function, or a text in read-only data
+    Address {
+        dst: VReg,
+        symbol: String,
+    },
+    /// Gets the address of a stack slot, which is that of the lowest of the words it takes
+    SlotAddress {
+        dst: VReg,
+        slot: u32,
+    },
+    Load {
+        class: Class,
+        dst: VReg,
+        address: VReg,
+    },
+    Store {
+        class: Class,
+        address: VReg,
+        src: VReg,
+    },
+    Jump(Label),
+    JumpIfZero {
+        condition: VReg,
+        target: Label,
+    },
+    Return(Option<VReg>),
+    /// Reports a runtime error and stops the program. Its message is followed by the `Str` at the
+    /// address in `text`, if there is one
+    Panic {
+        message: String,
+        text: Option<VReg>,
+        at: At,
+    },
+    /// Where the program never gets to, as it follows a runtime error
+    Unreachable,
+}
+
+impl Inst {
+    /// Gets the registers the instruction reads
+    pub fn uses(&self) -> Vec<VReg> {
+        match self {
+            Self::Move { src, .. }
+            | Self::Negate { src, .. }
+            | Self::Convert { src, .. }
+            | Self::Not { src, .. } => vec![*src],
+            Self::Store { address, src, .. } => vec![*address, *src],
+            Self::Arith { left, right, .. } | Self::Compare { left, right, .. } => {
+                vec![*left, *right]
+            }
+            Self::Call {
+                callee, arguments, ..
+            } => {
+                let mut uses = arguments.clone();
+                if let Callee::Address(address) = callee {
+                    uses.push(*address);
+                }
+                uses
+            }
+            Self::Panic { text, .. } => text.iter().copied().collect(),
+            Self::Load { address, .. } => vec![*address],
+            Self::JumpIfZero { condition, .. } => vec![*condition],
+            Self::Return(value) => value.iter().copied().collect(),
+            Self::Label(_)
+            | Self::Const { .. }
+            | Self::SlotAddress { .. }
+            | Self::Address { .. }
+            | Self::Jump(_)
+            | Self::Unreachable => Vec::new(),
+        }
+    }
+
+    /// Gets the register the instruction writes, if any
+    pub fn def(&self) -> Option<VReg> {
+        match self {
+            Self::Const { dst, .. }
+            | Self::Move { dst, .. }
+            | Self::Arith { dst, .. }
+            | Self::Compare { dst, .. }
+            | Self::Negate { dst, .. }
+            | Self::Convert { dst, .. }
+            | Self::Not { dst, .. }
+            | Self::SlotAddress { dst, .. }
+            | Self::Address { dst, .. }
+            | Self::Load { dst, .. } => Some(*dst),
+            Self::Call { dst, .. } => *dst,
+            _ => None,
+        }
+    }
+}
+
+/// A function in the low-level IR
+#[derive(Debug, Clone)]
+pub struct Function {
+    /// The symbol the function is given in the assembly
+    pub name: String,
+    pub parameters: Vec<VReg>,
+    /// The class of the value returned, or [None] for functions returning `()`
+    pub return_class: Option<Class>,
+    /// The class of every virtual register
+    pub registers: Vec<Class>,
+    /// How many words of the stack frame the slots of the function take
+    pub slots: u32,
+    pub code: Vec<Inst>,
+}
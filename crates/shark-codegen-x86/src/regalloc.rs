@@ -0,0 +1,175 @@
+//! A linear-scan register allocator. Every virtual register is given the interval from the first
+//! to the last instruction mentioning it, widened over any loop it is live within, and intervals
+//! are then handed machine registers in order of where they start. When none is free, the interval
+//! ending last is spilled to a stack slot
+//!
+//! Every XMM register and some general purpose registers are clobbered by calls, so intervals which
+//! live across a call only ever get callee-saved registers, or a stack slot
+
+use std::collections::HashMap;
+
+use crate::lir::{Function, Inst, VReg};
+
+/// General purpose registers which keep their value across calls, and so must be saved by the
+/// function using them
+pub const CALLEE_SAVED: [&str; 5] = ["%rbx", "%r12", "%r13", "%r14", "%r15"];
+
+/// General purpose registers which calls clobber. The rest are the scratch registers the emitter
+/// works in: `%rax`, `%rcx`, `%rdx` and `%r11`
+const CALLER_SAVED: [&str; 5] = ["%rsi", "%rdi", "%r8", "%r9", "%r10"];
+
+/// XMM registers which can be allocated, leaving `%xmm0` and `%xmm1` for scratch
+const XMM: [&str; 14] = [
+    "%xmm2", "%xmm3", "%xmm4", "%xmm5", "%xmm6", "%xmm7", "%xmm8", "%xmm9", "%xmm10", "%xmm11",
+    "%xmm12", "%xmm13", "%xmm14", "%xmm15",
+];
+
+/// Where a virtual register lives
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Location {
+    Register(&'static str),
+    /// A spill slot within the stack frame
+    Spill(u32),
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Allocation {
+    pub locations: HashMap<VReg, Location>,
+    pub spills: u32,
+    /// Every callee-saved register given out, which the function must save and restore
+    pub callee_saved: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Interval {
+    register: VReg,
+    start: usize,
+    end: usize,
+    /// Whether a call happens while the register is live
+    crosses_call: bool,
+}
+
+/// Works out the interval every virtual register is live within
+fn intervals(function: &Function) -> Vec<Interval> {
+    let mut ranges: HashMap<VReg, (usize, usize)> = HashMap::new();
+    let mut mention = |register: VReg, position: usize| {
+        let range = ranges.entry(register).or_insert((position, position));
+        range.0 = range.0.min(position);
+        range.1 = range.1.max(position);
+    };
+    // Parameters are defined on entry, before the first instruction
+    for parameter in &function.parameters {
+        mention(*parameter, 0);
+    }
+    let mut labels = HashMap::new();
+    let mut calls = Vec::new();
+    for (position, inst) in function.code.iter().enumerate() {
+        for register in inst.uses().into_iter().chain(inst.def()) {
+            mention(register, position);
+        }
+        match inst {
+            Inst::Label(label) => {
+                labels.insert(*label, position);
+            }
+            Inst::Call { .. } => calls.push(position),
+            _ => {}
+        }
+    }
+
+    // A register live at the start of a loop is live throughout it, so widen every interval which
+    // covers the target of a jump backwards until nothing changes
+    let mut loops = Vec::new();
+    for (position, inst) in function.code.iter().enumerate() {
+        let target = match inst {
+            Inst::Jump(target) | Inst::JumpIfZero { target, .. } => target,
+            _ => continue,
+        };
+        if labels[target] < position {
+            loops.push((labels[target], position));
+        }
+    }
+    let mut changed = !loops.is_empty();
+    while changed {
+        changed = false;
+        for range in ranges.values_mut() {
+            for (start, end) in &loops {
+                if range.0 < *start && range.1 >= *start && range.1 < *end {
+                    range.1 = *end;
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    let mut result: Vec<Interval> = ranges
+        .into_iter()
+        .map(|(register, (start, end))| Interval {
+            register,
+            start,
+            end,
+            crosses_call: calls.iter().any(|x| start < *x && *x < end),
+        })
+        .collect();
+    result.sort_by_key(|x| (x.start, x.register));
+    result
+}
+
+/// Gives every virtual register of a function a machine register or a spill slot
+pub fn allocate(function: &Function) -> Allocation {
+    let mut allocation = Allocation::default();
+    let mut active: Vec<(Interval, &'static str)> = Vec::new();
+    for interval in intervals(function) {
+        active.retain(|(x, _)| x.end > interval.start);
+        let is_float = function.registers[interval.register.0 as usize].is_float();
+        let candidates: Vec<&'static str> = match (is_float, interval.crosses_call) {
+            (true, true) => Vec::new(),
+            (true, false) => XMM.to_vec(),
+            (false, true) => CALLEE_SAVED.to_vec(),
+            (false, false) => CALLER_SAVED.iter().chain(&CALLEE_SAVED).copied().collect(),
+        };
+        let free = candidates
+            .iter()
+            .find(|x| !active.iter().any(|(_, register)| register == *x));
+        let register = match free {
+            Some(register) => Some(*register),
+            None => {
+                // Take the register of the interval which ends last, if it ends after this one
+                let victim = active
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, (x, register))| {
+                        candidates.contains(register) && x.end > interval.end
+                    })
+                    .max_by_key(|(_, (x, _))| x.end)
+                    .map(|(index, _)| index);
+                victim.map(|index| {
+                    let (spilled, register) = active.remove(index);
+                    allocation
+                        .locations
+                        .insert(spilled.register, Location::Spill(allocation.spills));
+                    allocation.spills += 1;
+                    register
+                })
+            }
+        };
+        match register {
+            Some(register) => {
+                if CALLEE_SAVED.contains(&register) && !allocation.callee_saved.contains(&register)
+                {
+                    allocation.callee_saved.push(register);
+                }
+                allocation
+                    .locations
+                    .insert(interval.register, Location::Register(register));
+                active.push((interval, register));
+            }
+            None => {
+                allocation
+                    .locations
+                    .insert(interval.register, Location::Spill(allocation.spills));
+                allocation.spills += 1;
+            }
+        }
+    }
+    allocation
+}
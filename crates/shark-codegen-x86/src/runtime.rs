@@ -0,0 +1,25 @@
+//! The entry of every generated program. The runtime is the C runtime of `shark-std`, which is
+//! compiled and linked along with the assembly, and which also stops the program on runtime errors
+
+/// Gets the `main` the C runtime calls, which starts the runtime with the arguments of the program
+/// then calls the function giving its exit code, if there is one
+pub fn entry(function: Option<&str>) -> String {
+    let mut result = String::from(
+        "    .globl main
+    .type main, @function
+main:
+    pushq %rbp
+    movq %rsp, %rbp
+    call shark_start
+",
+    );
+    match function {
+        Some(function) => {
+            result.push_str(&format!("    call {}\n", function));
+            result.push_str("    movzbl %al, %eax\n");
+        }
+        None => result.push_str("    xorl %eax, %eax\n"),
+    }
+    result.push_str("    popq %rbp\n    ret\n");
+    result
+}
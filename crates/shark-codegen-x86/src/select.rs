@@ -0,0 +1,414 @@
+//! Selects low-level IR for every function of the IR. Every value is a virtual register and every
+//! block a label, with the jumps between them assigning the parameters of the block they go to.
+//! Every `slot` and `alloca` is a stack slot of the function, so it is a new place on every call
+//!
+//! Foreign functions are reported, as calling them needs the C types of what they are given, which
+//! only the C backend writes out
+
+use std::{collections::HashMap, path::Path};
+
+use shark_core::{
+    diagnostic::Diagnostic,
+    source::{LineIndex, Span},
+};
+use shark_ir::ir::{self, BinaryOp, InstKind, Module, Target, Terminator, Type, UnaryOp, Value};
+use shark_lex::token::LiteralKind;
+use shark_sema::numeric::Overflow;
+
+use crate::{
+    emit::Texts,
+    lir::{self, ArithOp, Callee, Class, CompareOp, Inst, Label, VReg},
+};
+
+pub struct Selector<'g> {
+    module: &'g Module,
+    path: Option<&'g Path>,
+    line_index: LineIndex<'g>,
+    /// The symbol of every function of the module, by the name the IR gives it
+    names: HashMap<&'g str, String>,
+    /// The bytes of the strings of the program, along with the texts of runtime errors
+    pub texts: Texts,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Gets the class of the values of a type
+pub fn class_of(ty: Type) -> Class {
+    let int = |bits, signed| Class::Int { bits, signed };
+    match ty {
+        Type::Int8 => int(8, true),
+        Type::UInt8 => int(8, false),
+        Type::Int32 => int(32, true),
+        Type::UInt32 | Type::Char => int(32, false),
+        Type::Int64 => int(64, true),
+        Type::UInt64 => int(64, false),
+        Type::Float32 => Class::Float32,
+        Type::Float64 => Class::Float64,
+        Type::Bool => Class::Bool,
+        Type::Ptr => Class::POINTER,
+    }
+}
+
+/// Gets the bits of a constant which is not a string
+fn literal_bits(literal: &LiteralKind) -> u64 {
+    match *literal {
+        LiteralKind::UInt8(x) => x as u64,
+        LiteralKind::Int8(x) => x as i64 as u64,
+        LiteralKind::UInt32(x) => x as u64,
+        LiteralKind::Int32(x) => x as i64 as u64,
+        LiteralKind::UInt64(x) => x,
+        LiteralKind::Int64(x) => x as u64,
+        LiteralKind::Float32(x) => x.to_bits() as u64,
+        LiteralKind::Float64(x) => x.to_bits(),
+        LiteralKind::Char(x) => x as u64,
+        LiteralKind::Boolean(x) => x as u64,
+        LiteralKind::Str(_) => unreachable!("strings are addresses"),
+    }
+}
+
+/// Turns the name of a function into a symbol, keeping its letters and digits so that the
+/// assembly can still be followed
+fn symbol(index: usize, name: &str) -> String {
+    let mut result = format!("shark_{}_", index);
+    for character in name.chars() {
+        match character.is_ascii_alphanumeric() {
+            true => result.push(character),
+            false if !result.ends_with('_') => result.push('_'),
+            false => {}
+        }
+    }
+    result.trim_end_matches('_').to_string()
+}
+
+impl<'g> Selector<'g> {
+    pub fn new(module: &'g Module, path: Option<&'g Path>, source: &'g str) -> Self {
+        let names = module
+            .functions
+            .iter()
+            .enumerate()
+            .map(|(index, function)| (function.name.as_str(), symbol(index, &function.name)))
+            .collect();
+        Self {
+            module,
+            path,
+            line_index: LineIndex::new(source),
+            names,
+            texts: Texts::default(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Gets the symbol of a function of the module
+    pub fn name(&self, name: &str) -> &str {
+        &self.names[name]
+    }
+
+    /// Selects the instructions of a function
+    pub fn select(&mut self, function: &ir::Function) -> lir::Function {
+        let mut registers = vec![Class::POINTER; function.next_value as usize];
+        for block in &function.blocks {
+            for (param, ty) in &block.params {
+                registers[param.0 as usize] = class_of(*ty);
+            }
+            for inst in &block.insts {
+                if let Some((value, ty)) = inst.result {
+                    registers[value.0 as usize] = class_of(ty);
+                }
+            }
+        }
+        let mut selector = FunctionSelector {
+            selector: self,
+            function,
+            registers,
+            slots: 0,
+            labels: function.blocks.len() as u32,
+            code: Vec::new(),
+        };
+        // Blocks are laid out after the blocks dominating them, for every value to be defined
+        // before the instructions using it
+        for id in function.reverse_postorder() {
+            let block = function.block(id);
+            selector.code.push(Inst::Label(Label(id.0)));
+            for inst in &block.insts {
+                selector.inst(inst);
+            }
+            selector.terminator(&block.terminator);
+        }
+        let (registers, slots, code) = (selector.registers, selector.slots, selector.code);
+        lir::Function {
+            name: self.name(&function.name).to_string(),
+            parameters: function.blocks[0]
+                .params
+                .iter()
+                .map(|(x, _)| VReg(x.0))
+                .collect(),
+            return_class: function.return_type.map(class_of),
+            registers,
+            slots,
+            code,
+        }
+    }
+}
+
+struct FunctionSelector<'s, 'g> {
+    selector: &'s mut Selector<'g>,
+    function: &'s ir::Function,
+    registers: Vec<Class>,
+    slots: u32,
+    labels: u32,
+    code: Vec<Inst>,
+}
+
+impl FunctionSelector<'_, '_> {
+    /// Gets the text of the position a span starts at
+    fn at(&self, at: Option<Span>) -> String {
+        match at {
+            Some(span) => {
+                let position = self
+                    .selector
+                    .line_index
+                    .position(self.selector.path, span.start);
+                position.to_string()
+            }
+            None => "<runtime>".to_string(),
+        }
+    }
+
+    fn register(&mut self, class: Class) -> VReg {
+        self.registers.push(class);
+        VReg(self.registers.len() as u32 - 1)
+    }
+
+    fn class(&self, value: Value) -> Class {
+        self.registers[value.0 as usize]
+    }
+
+    fn new_label(&mut self) -> Label {
+        self.labels += 1;
+        Label(self.labels - 1)
+    }
+
+    /// Makes a stack slot of some bytes, giving the index of its lowest word
+    fn slot(&mut self, size: u64) -> u32 {
+        self.slots += size.div_ceil(8).max(1) as u32;
+        self.slots - 1
+    }
+
+    fn inst(&mut self, inst: &ir::Inst) {
+        let dst = inst.result.map(|(x, _)| VReg(x.0));
+        let at = self.at(inst.at);
+        let result = || dst.expect("the instruction gives a value");
+        let lir = match &inst.kind {
+            InstKind::Const(LiteralKind::Str(text)) => {
+                let label = self.selector.texts.label(text.as_str());
+                Inst::Address {
+                    dst: result(),
+                    symbol: label,
+                }
+            }
+            InstKind::Const(literal) => Inst::Const {
+                dst: result(),
+                bits: literal_bits(literal),
+            },
+            InstKind::Copy(x) => Inst::Move {
+                dst: result(),
+                src: VReg(x.0),
+            },
+            InstKind::Unary(UnaryOp::Neg, x) => Inst::Negate {
+                class: self.class(*x),
+                overflow: Overflow::Trap,
+                dst: result(),
+                src: VReg(x.0),
+                at,
+            },
+            InstKind::Unary(UnaryOp::Not, x) => Inst::Not {
+                class: self.class(*x),
+                dst: result(),
+                src: VReg(x.0),
+            },
+            InstKind::Binary(op, x, y) => self.binary(*op, Overflow::Trap, result(), *x, *y, at),
+            InstKind::Arith(overflow, op, x, y) => {
+                self.binary(*op, *overflow, result(), *x, *y, at)
+            }
+            InstKind::Convert(overflow, x) => Inst::Convert {
+                from: self.class(*x),
+                to: class_of(inst.result.expect("conversions give a value").1),
+                overflow: *overflow,
+                dst: result(),
+                src: VReg(x.0),
+                at,
+            },
+            InstKind::Alloca(ty) => Inst::SlotAddress {
+                dst: result(),
+                slot: self.slot(ty.size()),
+            },
+            InstKind::Slot(size, _) => Inst::SlotAddress {
+                dst: result(),
+                slot: self.slot(*size),
+            },
+            InstKind::Offset(x, y) => Inst::Arith {
+                op: ArithOp::Add,
+                class: Class::POINTER,
+                overflow: Overflow::Wrap,
+                dst: result(),
+                left: VReg(x.0),
+                right: VReg(y.0),
+                at,
+            },
+            InstKind::Load(x) => Inst::Load {
+                class: self.registers[result().0 as usize],
+                dst: result(),
+                address: VReg(x.0),
+            },
+            InstKind::Store(x, y) => Inst::Store {
+                class: self.class(*y),
+                address: VReg(x.0),
+                src: VReg(y.0),
+            },
+            InstKind::MemCopy(x, y, z) => Inst::Call {
+                dst: None,
+                callee: Callee::Function("memmove".to_string()),
+                arguments: vec![VReg(x.0), VReg(y.0), VReg(z.0)],
+                depth: None,
+            },
+            InstKind::Call(name, arguments) => {
+                let module = self.selector.module;
+                let (callee, counted) = match module.extern_function(name) {
+                    Some(declaration) => {
+                        if declaration.foreign.is_some() {
+                            self.foreign(name, inst.at);
+                        }
+                        (name.clone(), false)
+                    }
+                    None => (self.selector.name(name).to_string(), inst.at.is_some()),
+                };
+                Inst::Call {
+                    dst,
+                    callee: Callee::Function(callee),
+                    arguments: arguments.iter().map(|x| VReg(x.0)).collect(),
+                    depth: counted.then_some(at),
+                }
+            }
+            InstKind::FuncAddr(name) => Inst::Address {
+                dst: result(),
+                symbol: self.selector.name(name).to_string(),
+            },
+            InstKind::CallIndirect(callee, arguments) => Inst::Call {
+                dst,
+                callee: Callee::Address(VReg(callee.0)),
+                arguments: arguments.iter().map(|x| VReg(x.0)).collect(),
+                depth: inst.at.is_some().then_some(at),
+            },
+            InstKind::Panic(message, text) => Inst::Panic {
+                message: message.clone(),
+                text: text.map(|x| VReg(x.0)),
+                at,
+            },
+        };
+        self.code.push(lir);
+    }
+
+    /// Reports a call of a foreign function
+    fn foreign(&mut self, name: &str, at: Option<Span>) {
+        let mut diagnostic = Diagnostic::error(format!(
+            "the foreign function `{}` can only be called by a program built with `--target=c`",
+            name
+        ));
+        if let Some(span) = at {
+            diagnostic = diagnostic.with_primary(span, "called here");
+        }
+        self.selector.diagnostics.push(diagnostic);
+    }
+
+    fn binary(
+        &mut self,
+        op: BinaryOp,
+        overflow: Overflow,
+        dst: VReg,
+        left: Value,
+        right: Value,
+        at: String,
+    ) -> Inst {
+        let class = self.class(left);
+        let (left, right) = (VReg(left.0), VReg(right.0));
+        let compare = match op {
+            BinaryOp::Eq => Some(CompareOp::EqualTo),
+            BinaryOp::Ne => Some(CompareOp::NotEqual),
+            BinaryOp::Lt => Some(CompareOp::Lesser),
+            BinaryOp::Le => Some(CompareOp::LessOrEqual),
+            BinaryOp::Gt => Some(CompareOp::Greater),
+            BinaryOp::Ge => Some(CompareOp::GreaterOrEqual),
+            _ => None,
+        };
+        if let Some(op) = compare {
+            return Inst::Compare {
+                op,
+                class,
+                dst,
+                left,
+                right,
+            };
+        }
+        let op = match op {
+            BinaryOp::Add => ArithOp::Add,
+            BinaryOp::Sub => ArithOp::Subtract,
+            BinaryOp::Mul => ArithOp::Multiply,
+            BinaryOp::Div => ArithOp::Divide,
+            BinaryOp::Shl => ArithOp::ShiftLeft,
+            BinaryOp::Shr => ArithOp::ShiftRight,
+            _ => ArithOp::BitwiseAnd,
+        };
+        Inst::Arith {
+            op,
+            class,
+            overflow,
+            dst,
+            left,
+            right,
+            at,
+        }
+    }
+
+    /// Assigns the parameters of the block a target goes to, then goes there. The arguments are
+    /// copied out before any parameter is assigned, as they may be parameters of the same block
+    fn jump(&mut self, target: &Target) {
+        let function = self.function;
+        let params = &function.block(target.block).params;
+        let temporaries: Vec<VReg> = target
+            .arguments
+            .iter()
+            .map(|argument| {
+                let temporary = self.register(self.class(*argument));
+                self.code.push(Inst::Move {
+                    dst: temporary,
+                    src: VReg(argument.0),
+                });
+                temporary
+            })
+            .collect();
+        for ((param, _), temporary) in params.iter().zip(temporaries) {
+            self.code.push(Inst::Move {
+                dst: VReg(param.0),
+                src: temporary,
+            });
+        }
+        self.code.push(Inst::Jump(Label(target.block.0)));
+    }
+
+    fn terminator(&mut self, terminator: &Terminator) {
+        match terminator {
+            Terminator::Jump(target) => self.jump(target),
+            Terminator::Branch(condition, then_target, else_target) => {
+                let otherwise = self.new_label();
+                self.code.push(Inst::JumpIfZero {
+                    condition: VReg(condition.0),
+                    target: otherwise,
+                });
+                self.jump(then_target);
+                self.code.push(Inst::Label(otherwise));
+                self.jump(else_target);
+            }
+            Terminator::Return(value) => self.code.push(Inst::Return(value.map(|x| VReg(x.0)))),
+            Terminator::Unreachable => self.code.push(Inst::Unreachable),
+        }
+    }
+}
@@ -0,0 +1,440 @@
+use std::{
+    path::PathBuf,
+    process::{Command, Output},
+};
+
+use shark_core::diagnostic::Diagnostic;
+use shark_ir::{verify::verify, OptLevel};
+use shark_sema::numeric::Overflow;
+use shark_testing::{
+    exit_status, interpret, overflow::check_overflow_modes, scratch_directory, suite,
+};
+
+use crate::{build_executable, generate};
+
+/// Generates assembly for a module which must check without errors, from its IR optimised
+/// at `opt_level`
+fn generate_assembly(
+    source: &str,
+    overflow: Overflow,
+    opt_level: OptLevel,
+) -> Result<String, Vec<Diagnostic>> {
+    let checked = shark_testing::check(source);
+    let bodies = checked.lower();
+    let (module, defs, types) = (&checked.module, &checked.defs, &checked.types);
+    let mut module = shark_ir::build(module, defs, types, &bodies, overflow);
+    shark_ir::optimize(&mut module, opt_level);
+    verify(&module).expect("the optimised IR verifies");
+    generate(&module, None, source)
+}
+
+/// Assembles a program into an executable and runs it with the command `run` makes for the
+/// executable
+fn build_and_run(assembly: String, run: impl FnOnce(PathBuf) -> Command) -> Output {
+    let directory = scratch_directory("shark-codegen-x86");
+    let assembly_path = directory.join("main.s");
+    let executable = directory.join("main");
+    std::fs::write(&assembly_path, assembly).expect("failed to write the assembly");
+    build_executable(&assembly_path, &executable).expect("failed to assemble the program");
+    let output = run(executable).output().expect("failed to run the program");
+    let _ = std::fs::remove_dir_all(&directory);
+    output
+}
+
+/// Assembles a module into an executable and runs it, returning its exit code along with what it
+/// printed to stderr. The result must be the same as when the module is run by the interpreter
+fn execute(source: &str) -> (u8, String) {
+    execute_as(source, Overflow::Trap)
+}
+
+/// Like [execute], for a program built to overflow some way
+fn execute_as(source: &str, overflow: Overflow) -> (u8, String) {
+    execute_with(source, overflow, OptLevel::O2)
+}
+
+/// Like [execute_as], for a program optimised at `opt_level`
+fn execute_with(source: &str, overflow: Overflow, opt_level: OptLevel) -> (u8, String) {
+    let assembly =
+        generate_assembly(source, overflow, opt_level).expect("failed to generate assembly");
+    let output = build_and_run(assembly, Command::new);
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let status = output.status.code().expect("the program was killed") as u8;
+    let result = (status, stderr.lines().collect::<Vec<_>>().join("\n"));
+    assert_eq!(
+        result,
+        interpret(source, overflow, |x| exit_status(source, x))
+    );
+    result
+}
+
+#[test]
+fn test_arithmetic() {
+    let result = execute(
+        "pub fun main() :: Int32 {
+            let mut total = 2 + 3 * 4;
+            total -= 10 / 3;
+            total *= -2;
+            let shifted = (1 << 4) >> 1;
+            let masked = 13 & 7;
+            total + shifted + masked + 100
+        }",
+    );
+    assert_eq!(result, (91, String::new()));
+
+    let result = execute(
+        "fun widths() :: Bool {
+            let half :: Float64 = 1.0 / 4.0 * 2.0;
+            let third :: Float32 = 1.0 / 3.0;
+            let max :: UInt8 = 200 + 55;
+            let low :: Int8 = -128;
+            let short :: Int8 = -100 + 27;
+            let unsigned :: UInt32 = 65535;
+            let big :: UInt64 = 9000000000000000000uint64 * 2;
+            let small :: Int64 = -9223372036854775807int64 - 1;
+            let wide :: UInt32 = 4000000000uint32;
+            let shifted :: Int32 = -16 >> 2;
+            let high :: UInt32 = wide >> 30;
+            3 >= 3 && 1 != 2 && !(!true | 2 < 1) && half == 0.5 && third < 0.34 && max == 255
+                && low == -128 && short == -73 && unsigned / 5 == 13107
+                && big / 2 == 9000000000000000000uint64 && small < -1 && wide > 1
+                && shifted == -4 && high == 3 && -half < 0.0 && !(third != third)
+        }
+
+        pub fun main() :: Int32 {
+            if widths() { 1 } else { 2 }
+        }",
+    );
+    assert_eq!(result, (1, String::new()));
+}
+
+#[test]
+fn test_control_flow() {
+    let result = execute(
+        "fun fib(n :: Int64) :: Int64 {
+            if n < 2 {
+                ret n;
+            }
+            fib(n - 1) + fib(n - 2)
+        }
+
+        fun sign(n :: Int32) :: Int32 {
+            if n > 0 { 1 } else if n < 0 { -1 } else { 0 }
+        }
+
+        fun size(n :: Int32) :: Int32 {
+            when n {
+                0 => 0,
+                1..10 => 1,
+                x if x > 100 => 3,
+                _ => 2,
+            }
+        }
+
+        pub fun main() :: Int32 {
+            let sizes = size(0) * 1000 + size(9) * 100 + size(10) * 10 + size(500);
+            if sign(-5) == -1 && sign(0) == 0 && sign(7) == 1 && sizes == 123 {
+                if fib(20) == 6765 { 42 } else { 1 }
+            } else {
+                0
+            }
+        }",
+    );
+    assert_eq!(result, (42, String::new()));
+}
+
+#[test]
+fn test_return_at_the_end() {
+    // The body of each function ends after a `ret`, which must build at every level
+    let source = "fun f(n :: Int32) :: Int32 { ret n; }
+
+        fun fib(n :: Int32) :: Int32 {
+            if n < 2 { ret n; }
+            ret fib(n - 1) + fib(n - 2);
+        }
+
+        fun pick(c :: Bool, a :: Int32, b :: Int32) :: Int32 {
+            if c { ret a; } else { ret b; }
+        }
+
+        pub fun main() :: Int32 {
+            ret f(3) + fib(10) + pick(false, 1, 2);
+        }";
+    for opt_level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+        assert_eq!(
+            execute_with(source, Overflow::Trap, opt_level),
+            (60, String::new())
+        );
+    }
+}
+
+#[test]
+fn test_calling_convention() {
+    // Enough parameters of each kind that some are passed on the stack
+    let result = execute(
+        "fun ints(
+            a :: Int8, b :: UInt8, c :: Int32, d :: Int64, e :: UInt8, f :: Int32, g :: Int64,
+            h :: UInt32,
+        ) :: Int64 {
+            if a == -1 && b == 2 && c == 3 && e == 5 && f == 6 && h == 8 { d + g } else { 0 }
+        }
+
+        fun floats(
+            a :: Float64, b :: Float32, c :: Float64, d :: Float64, e :: Float64, f :: Float64,
+            g :: Float64, h :: Float64, i :: Float32, j :: Float64, n :: Int32,
+        ) :: Float64 {
+            if b == 2.0 && i == 9.0 && n == 11 { a + c + d + e + f + g + h + j } else { 0.0 }
+        }
+
+        pub fun main() :: Int32 {
+            let x = ints(-1, 2, 3, 4int64, 5, 6, 7int64, 8);
+            let y = floats(1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11);
+            if y == 44.0 && x == 11int64 { 34 } else { 0 }
+        }",
+    );
+    assert_eq!(result, (34, String::new()));
+}
+
+#[test]
+fn test_references() {
+    let result = execute(
+        "fun set(target :: ref mut Int32, value :: Int32) {
+            *target = value;
+        }
+
+        fun get(source :: ref Float64) :: Float64 {
+            *source
+        }
+
+        pub fun main() :: Int32 {
+            let mut n = 1;
+            set(ref mut n, 40);
+            let pointer = ptr mut n;
+            unsafe {
+                *pointer += 2;
+            }
+            let half :: Float64 = 0.5;
+            if get(ref half) == 0.5 { n } else { 0 }
+        }",
+    );
+    assert_eq!(result, (42, String::new()));
+}
+
+#[test]
+fn test_methods() {
+    let result = execute(
+        "trait Shape {
+            fun area(self :: ref Self) :: Int32;
+            fun double(self :: ref Self) :: Int32 {
+                Shape::area(self) * 2
+            }
+        }
+
+        impl Shape for Int32 {
+            fun area(self :: ref Self) :: Int32 {
+                *self * *self
+            }
+        }
+
+        impl Shape for Bool {
+            fun area(self :: ref Self) :: Int32 {
+                if *self { 1 } else { 0 }
+            }
+
+            fun double(self :: ref Self) :: Int32 {
+                7
+            }
+        }
+
+        pub fun main() :: Int32 {
+            let side = 3;
+            let flag = true;
+            side.double() * 10 + flag.double() * 10 + Shape::area(ref flag)
+        }",
+    );
+    assert_eq!(result, (251, String::new()));
+}
+
+#[test]
+fn test_runtime_errors() {
+    let result = execute(
+        "pub fun main() :: Int8 {
+            let x :: Int8 = 100;
+            x + x
+        }",
+    );
+    assert_eq!(
+        result,
+        (
+            101,
+            "error: attempt to add with overflow\n --> unknown:3:13".to_string()
+        )
+    );
+
+    let result = execute(
+        "fun divide(a :: Int32, b :: Int32) :: Int32 {
+            a / b
+        }
+
+        pub fun main() :: Int32 {
+            divide(1, 0)
+        }",
+    );
+    assert_eq!(
+        result,
+        (
+            101,
+            "error: attempt to divide by zero\n --> unknown:2:13".to_string()
+        )
+    );
+
+    let result = execute(
+        "pub fun main() :: Int32 {
+            let x :: Int64 = 9223372036854775807int64;
+            let y :: UInt64 = 1;
+            let z :: UInt64 = y - 2;
+            0
+        }",
+    );
+    assert_eq!(result.0, 101);
+
+    let result = execute(
+        "pub fun main() :: Int32 {
+            let x :: Int32 = 1;
+            x << 32
+        }",
+    );
+    assert_eq!(result.0, 101);
+
+    let result =
+        execute("fun forever(n :: Int32) :: Int32 { forever(n) } pub fun main() { forever(1); }");
+    assert_eq!(
+        result,
+        (101, "error: stack overflow\n --> unknown:1:36".to_string())
+    );
+}
+
+#[test]
+fn test_language() {
+    let result = execute(
+        "type Point { x :: Int32, y :: Int32 }
+
+        enum Shape {
+            Circle(Int32),
+            Rectangle(Point, Point),
+            Empty,
+        }
+
+        fun area(shape :: Shape) :: Int32 {
+            when shape {
+                Shape::Circle(radius) => 3 * radius * radius,
+                Shape::Rectangle(a, b) => (b.x - a.x) * (b.y - a.y),
+                Shape::Empty => 0,
+            }
+        }
+
+        fun first<T>(a :: T, b :: T) :: T {
+            a
+        }
+
+        pub fun main() :: Int32 {
+            let rectangle = Shape::Rectangle(Point { x = 1, y = 2 }, Point { y = 6, x = 4 });
+            let same = Shape::Circle(2) == Shape::Circle(2) && Shape::Circle(1) != Shape::Empty;
+            let total = area(Shape::Circle(2)) + area(rectangle) + area(Shape::Empty);
+            if same { total + first(1, 2) } else { 0 }
+        }",
+    );
+    assert_eq!(result, (25, String::new()));
+
+    let result = execute(
+        "fun upto(n :: Int32) :: yield Int32 {
+            if n > 0 {
+                for x in upto(n - 1) {
+                    yield x;
+                }
+                yield n;
+            }
+        }
+
+        fun apply(f :: fun(Int32) :: Int32, x :: Int32) :: Int32 {
+            f(x)
+        }
+
+        fun scale(by :: Int32) :: fun(Int32) :: Int32 {
+            fun(x :: Int32) :: Int32 { x * by }
+        }
+
+        pub fun main() :: Int32 {
+            let offset = 2;
+            let add = fun(x :: Int32) :: Int32 { x + offset };
+            let triple = scale(3);
+            let mut sum = 0;
+            for x in upto(4) {
+                sum += apply(add, x);
+            }
+            sum + triple(5)
+        }",
+    );
+    assert_eq!(result, (33, String::new()));
+}
+
+#[test]
+fn test_standard_library() {
+    let source = "pub fun main() :: Int32 {
+            let mut values = Vec::new();
+            values.push(1);
+            values.push(2);
+            println(\"{} values\", values.len());
+            exit(values.len().to_int32());
+            0
+        }";
+    let assembly =
+        generate_assembly(source, Overflow::Trap, OptLevel::O2).expect("failed to generate");
+    let output = build_and_run(assembly, Command::new);
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "2 values\n");
+    assert_eq!(output.status.code(), Some(2));
+
+    let result = generate_assembly(
+        "extern \"C\" fun abs(value :: Int32) :: Int32;
+
+        pub fun main() :: Int32 {
+            unsafe { abs(-1) }
+        }",
+        Overflow::Trap,
+        OptLevel::O2,
+    );
+    let diagnostics = result.expect_err("foreign functions are not supported");
+    assert_eq!(
+        diagnostics[0].message,
+        "the foreign function `abs` can only be called by a program built with `--target=c`"
+    );
+}
+
+#[test]
+fn test_overflow_modes() {
+    check_overflow_modes(execute_as);
+}
+
+#[test]
+fn test_library_programs() {
+    for test in suite::programs() {
+        let assembly = generate_assembly(&test.source, Overflow::Trap, OptLevel::O2)
+            .expect("failed to generate");
+        // The shell writes stderr to stdout, for errors to come after what was printed before them
+        let output = build_and_run(assembly, |executable| {
+            let mut command = Command::new("sh");
+            command
+                .args(["-c", "\"$0\" \"$@\" 2>&1"])
+                .arg(executable)
+                .args(&test.args);
+            command
+        });
+        let code = output.status.code().expect("the program was killed");
+        assert_eq!(
+            suite::executable_transcript(&String::from_utf8_lossy(&output.stdout), code),
+            test.expected,
+            "{}",
+            test.name
+        );
+    }
+}
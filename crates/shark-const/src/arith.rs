@@ -0,0 +1,228 @@
+//! Arithmetic on constants, with exactly the semantics the program has when it runs: integer
+//! arithmetic is checked against the width of its type, shifts must be by less than the number of
+//! bits and drop what is shifted out, and `Float32`s are rounded after every operation. Constants
+//! are checked whichever way the program is built, so only the [Intrinsic]s which say so wrap
+
+use std::cmp::Ordering;
+
+use shark_lex::token::LiteralKind;
+use shark_parse::ast::{BinaryOperator, UnaryOperator};
+use shark_sema::{
+    numeric::{self, Intrinsic, Number, NumericError, Overflow},
+    ty::PrimitiveType,
+};
+
+/// Why an operation on constants failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArithError {
+    /// What went wrong, which is followed by the expression being evaluated
+    pub message: &'static str,
+    pub label: String,
+}
+
+impl ArithError {
+    fn overflow(ty: PrimitiveType) -> Self {
+        Self {
+            message: "overflow",
+            label: format!("the result does not fit in `{}`", ty),
+        }
+    }
+}
+
+/// Gets the value of an integer constant
+pub fn integer(literal: &LiteralKind) -> Option<i128> {
+    Some(match *literal {
+        LiteralKind::UInt8(x) => x.into(),
+        LiteralKind::Int8(x) => x.into(),
+        LiteralKind::UInt32(x) => x.into(),
+        LiteralKind::Int32(x) => x.into(),
+        LiteralKind::UInt64(x) => x.into(),
+        LiteralKind::Int64(x) => x.into(),
+        _ => return None,
+    })
+}
+
+/// Makes an integer constant of a primitive type, returning [None] if it does not fit
+pub fn integer_of(ty: PrimitiveType, value: i128) -> Option<LiteralKind> {
+    Some(match ty {
+        PrimitiveType::Int8 => LiteralKind::Int8(value.try_into().ok()?),
+        PrimitiveType::UInt8 => LiteralKind::UInt8(value.try_into().ok()?),
+        PrimitiveType::Int32 => LiteralKind::Int32(value.try_into().ok()?),
+        PrimitiveType::UInt32 => LiteralKind::UInt32(value.try_into().ok()?),
+        PrimitiveType::Int64 => LiteralKind::Int64(value.try_into().ok()?),
+        PrimitiveType::UInt64 => LiteralKind::UInt64(value.try_into().ok()?),
+        _ => return None,
+    })
+}
+
+/// Gives a literal the type it was inferred to have, which differs from the one the lexer gave it
+/// when it adapts to its context
+pub fn literal_of(literal: &LiteralKind, ty: PrimitiveType) -> LiteralKind {
+    match (*literal, ty) {
+        (LiteralKind::Float32(x), PrimitiveType::Float64) => LiteralKind::Float64(x.into()),
+        (LiteralKind::Float64(x), PrimitiveType::Float32) => LiteralKind::Float32(x as f32),
+        (literal, _) => integer(&literal)
+            .and_then(|x| integer_of(ty, x))
+            .unwrap_or(literal),
+    }
+}
+
+/// Applies a unary `-` or `!` to a constant
+pub fn unary(operator: UnaryOperator, operand: &LiteralKind) -> Result<LiteralKind, ArithError> {
+    let ty = PrimitiveType::of_literal(operand);
+    match (operator, *operand) {
+        (UnaryOperator::Negate, LiteralKind::Float32(x)) => Ok(LiteralKind::Float32(-x)),
+        (UnaryOperator::Negate, LiteralKind::Float64(x)) => Ok(LiteralKind::Float64(-x)),
+        (UnaryOperator::Not, LiteralKind::Boolean(x)) => Ok(LiteralKind::Boolean(!x)),
+        (UnaryOperator::Negate, _) => {
+            let value = integer(operand).expect("only numbers are negated");
+            integer_of(ty, -value).ok_or_else(|| ArithError::overflow(ty))
+        }
+        (_, _) => {
+            let value = integer(operand).expect("only integers and `Bool`s are flipped");
+            Ok(integer_of(ty, numeric::wrap(ty, !value)).expect("flipping bits can not fail"))
+        }
+    }
+}
+
+/// Orders two constants of the same type, or gives [None] for a `NaN`
+pub fn compare(left: &LiteralKind, right: &LiteralKind) -> Option<Ordering> {
+    match (*left, *right) {
+        (LiteralKind::Float32(x), LiteralKind::Float32(y)) => x.partial_cmp(&y),
+        (LiteralKind::Float64(x), LiteralKind::Float64(y)) => x.partial_cmp(&y),
+        (LiteralKind::Boolean(x), LiteralKind::Boolean(y)) => x.partial_cmp(&y),
+        (LiteralKind::Char(x), LiteralKind::Char(y)) => x.partial_cmp(&y),
+        (LiteralKind::Str(x), LiteralKind::Str(y)) => x.as_str().partial_cmp(y.as_str()),
+        _ => integer(left)?.partial_cmp(&integer(right)?),
+    }
+}
+
+/// Applies an operator other than `&&` and `|` to two constants. Both are of the same type, apart
+/// from the amount of a shift which can be any integer
+pub fn binary(
+    operator: BinaryOperator,
+    left: &LiteralKind,
+    right: &LiteralKind,
+) -> Result<LiteralKind, ArithError> {
+    if operator.is_comparison() {
+        let ordering = compare(left, right);
+        let result = match operator {
+            BinaryOperator::Greater => ordering == Some(Ordering::Greater),
+            BinaryOperator::Lesser => ordering == Some(Ordering::Less),
+            BinaryOperator::GreaterOrEqual => {
+                matches!(ordering, Some(Ordering::Greater | Ordering::Equal))
+            }
+            BinaryOperator::LessOrEqual => {
+                matches!(ordering, Some(Ordering::Less | Ordering::Equal))
+            }
+            BinaryOperator::EqualTo => ordering == Some(Ordering::Equal),
+            _ => ordering != Some(Ordering::Equal),
+        };
+        return Ok(LiteralKind::Boolean(result));
+    }
+    match (*left, *right) {
+        (LiteralKind::Float32(x), LiteralKind::Float32(y)) => {
+            return Ok(LiteralKind::Float32(float(operator, x, y)))
+        }
+        (LiteralKind::Float64(x), LiteralKind::Float64(y)) => {
+            return Ok(LiteralKind::Float64(float(operator, x, y)))
+        }
+        (LiteralKind::Boolean(x), LiteralKind::Boolean(y)) => {
+            return Ok(LiteralKind::Boolean(x & y))
+        }
+        _ => {}
+    }
+
+    let ty = PrimitiveType::of_literal(left);
+    let x = integer(left).expect("arithmetic is on numbers");
+    let y = integer(right).expect("arithmetic is on numbers");
+    let result = match operator {
+        BinaryOperator::Add => x + y,
+        BinaryOperator::Subtract => x - y,
+        BinaryOperator::Multiply => x * y,
+        BinaryOperator::Divide if y == 0 => {
+            return Err(ArithError {
+                message: "division by zero",
+                label: "the divisor is zero".to_string(),
+            })
+        }
+        BinaryOperator::Divide => x / y,
+        BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => {
+            let name = match operator {
+                BinaryOperator::ShiftLeft => "left",
+                _ => "right",
+            };
+            if !(0..i128::from(numeric::bits(ty))).contains(&y) {
+                return Err(ArithError {
+                    message: "overflow",
+                    label: format!("cannot shift {} by {}", name, y),
+                });
+            }
+            match operator {
+                BinaryOperator::ShiftLeft => numeric::wrap(ty, x << y),
+                _ => x >> y,
+            }
+        }
+        BinaryOperator::BitwiseAnd => x & y,
+        _ => panic!("`{}` is not an arithmetic operator", operator),
+    };
+    integer_of(ty, result).ok_or_else(|| ArithError::overflow(ty))
+}
+
+/// Calls an [Intrinsic] of a number with the arguments given to it
+pub fn intrinsic(
+    intrinsic: Intrinsic,
+    receiver: &LiteralKind,
+    arguments: &[LiteralKind],
+) -> Result<LiteralKind, ArithError> {
+    let ty = PrimitiveType::of_literal(receiver);
+    let result = match intrinsic {
+        Intrinsic::Arith(operator, overflow) => {
+            let x = integer(receiver).expect("only integers have arithmetic methods");
+            let y = integer(&arguments[0]).expect("the argument is of the same type");
+            numeric::arith(operator, overflow, ty, x, y)
+                .map(|x| integer_of(ty, x).expect("the result fits"))
+        }
+        Intrinsic::Convert(target) => {
+            let number = match *receiver {
+                LiteralKind::Float32(x) => Number::Float(x.into()),
+                LiteralKind::Float64(x) => Number::Float(x),
+                _ => Number::Integer(integer(receiver).expect("only numbers are converted")),
+            };
+            numeric::convert(number, target, Overflow::Trap).map(|x| match x {
+                Number::Integer(x) => integer_of(target, x).expect("the result fits"),
+                Number::Float(x) if target == PrimitiveType::Float32 => {
+                    LiteralKind::Float32(x as f32)
+                }
+                Number::Float(x) => LiteralKind::Float64(x),
+            })
+        }
+    };
+    result.map_err(|error| match (error, intrinsic) {
+        (NumericError::DivisionByZero, _) => ArithError {
+            message: "division by zero",
+            label: "the divisor is zero".to_string(),
+        },
+        (NumericError::Overflow, Intrinsic::Convert(target)) => ArithError {
+            message: "overflow",
+            label: format!("the value does not fit in `{}`", target),
+        },
+        (NumericError::Overflow, _) => ArithError::overflow(ty),
+    })
+}
+
+fn float<T>(operator: BinaryOperator, x: T, y: T) -> T
+where
+    T: std::ops::Add<Output = T>
+        + std::ops::Sub<Output = T>
+        + std::ops::Mul<Output = T>
+        + std::ops::Div<Output = T>,
+{
+    match operator {
+        BinaryOperator::Add => x + y,
+        BinaryOperator::Subtract => x - y,
+        BinaryOperator::Multiply => x * y,
+        BinaryOperator::Divide => x / y,
+        _ => panic!("`{}` is not an arithmetic operator on floats", operator),
+    }
+}
@@ -0,0 +1,524 @@
+//! A tree-walking evaluator of the pure subset of the language: literals, constants, arithmetic,
+//! blocks with `let`s, `if`, `when` on primitive values and calls to functions which only do the
+//! same, or to the methods of numbers. Anything else, such as a struct literal or a reference, is
+//! reported as not being evaluable while compiling
+
+use std::collections::HashMap;
+
+use shark_core::{diagnostic::Diagnostic, source::Span, symbol::Symbol};
+use shark_lex::token::LiteralKind;
+use shark_parse::ast::{
+    BinaryOperator, Block, ConstDecl, Expr, ExprKind, Function, ItemKind, Module, Pattern,
+    PatternKind, StatementKind, UnaryOperator,
+};
+use shark_sema::ty::PrimitiveType;
+use shark_typeck::{ty::Ty, TypeckResults};
+
+use crate::arith::{self, ArithError};
+
+/// How many expressions can be evaluated for a single constant before giving up
+pub const STEP_LIMIT: usize = 1_000_000;
+
+/// How deeply calls can nest while evaluating a constant
+pub const DEPTH_LIMIT: usize = 128;
+
+/// The value of an expression
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Value {
+    Literal(LiteralKind),
+    /// What blocks without a tail and statements such as assignments evaluate to
+    Unit,
+}
+
+impl Value {
+    fn literal(self) -> LiteralKind {
+        match self {
+            Self::Literal(literal) => literal,
+            Self::Unit => panic!("expected a primitive value, found `()`"),
+        }
+    }
+
+    fn as_bool(self) -> bool {
+        match self.literal() {
+            LiteralKind::Boolean(x) => x,
+            literal => panic!("expected a `Bool`, found {:?}", literal),
+        }
+    }
+}
+
+/// Why evaluation stopped before reaching the end of an expression
+#[derive(Debug)]
+enum Stop {
+    Error(Diagnostic),
+    /// A constant the expression uses could not be evaluated, which has already been reported
+    Failed,
+    /// A `ret` leaving the function being called
+    Return(Value),
+}
+
+type Eval<T> = Result<T, Stop>;
+
+/// Evaluates the constants of a module, remembering the value of each one so that it is only
+/// worked out once however many times it is used
+pub struct Evaluator<'e> {
+    types: &'e TypeckResults,
+    source: &'e str,
+    constants: HashMap<Symbol, &'e ConstDecl>,
+    functions: HashMap<Symbol, &'e Function>,
+    /// The value of every constant evaluated so far, or [None] if it could not be evaluated
+    values: HashMap<Symbol, Option<LiteralKind>>,
+    /// The constants being evaluated, each one using the one after it
+    evaluating: Vec<Symbol>,
+    /// The locals of the function being called, innermost block last
+    scopes: Vec<HashMap<Symbol, Value>>,
+    steps: usize,
+    depth: usize,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<'e> Evaluator<'e> {
+    pub fn new(module: &'e Module, types: &'e TypeckResults, source: &'e str) -> Self {
+        let mut constants = HashMap::new();
+        let mut functions = HashMap::new();
+        for item in &module.items {
+            match &item.kind {
+                ItemKind::Const(const_decl) => {
+                    constants
+                        .entry(const_decl.name.symbol)
+                        .or_insert(const_decl);
+                }
+                ItemKind::Function(function) => {
+                    functions.entry(function.name.symbol).or_insert(function);
+                }
+                _ => {}
+            }
+        }
+        Self {
+            types,
+            source,
+            constants,
+            functions,
+            values: HashMap::new(),
+            evaluating: Vec::new(),
+            scopes: Vec::new(),
+            steps: 0,
+            depth: 0,
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Gets the value of a constant, evaluating it if that has not been done yet. [None] means it
+    /// could not be evaluated, which has been reported
+    pub fn constant(&mut self, name: Symbol) -> Option<LiteralKind> {
+        self.eval_constant(name, None).ok()?.map(Value::literal)
+    }
+
+    /// Evaluates an expression standing on its own, such as a discriminant, which can only use
+    /// constants
+    pub fn evaluate(&mut self, expr: &Expr) -> Option<LiteralKind> {
+        self.steps = 0;
+        let scopes = std::mem::take(&mut self.scopes);
+        let result = self.eval(expr);
+        self.scopes = scopes;
+        match result {
+            Ok(value) => Some(value.literal()),
+            Err(Stop::Error(diagnostic)) => {
+                self.diagnostics.push(diagnostic);
+                None
+            }
+            Err(Stop::Failed) => None,
+            Err(Stop::Return(_)) => unreachable!("a `ret` can only be within a function"),
+        }
+    }
+
+    /// Gets the text an expression was written as
+    fn text(&self, span: Span) -> &'e str {
+        self.source.get(span.start..span.end).unwrap_or_default()
+    }
+
+    fn unsupported(&self, expr: &Expr, label: &str) -> Stop {
+        Stop::Error(
+            Diagnostic::error(format!(
+                "`{}` can not be evaluated while compiling",
+                self.text(expr.span)
+            ))
+            .with_primary(expr.span, label)
+            .with_note(
+                "constants can only use arithmetic, blocks, `if`, `when` and calls to functions \
+                 which do the same or to the methods of numbers",
+            ),
+        )
+    }
+
+    fn arith_error(&self, error: ArithError, span: Span) -> Stop {
+        Stop::Error(
+            Diagnostic::error(format!(
+                "{} evaluating `{}`",
+                error.message,
+                self.text(span)
+            ))
+            .with_primary(span, error.label),
+        )
+    }
+
+    /// Evaluates a constant, or gets the value it was evaluated to. `used` is where it is used
+    /// from, if it is used by another constant, which makes it part of a cycle if that constant
+    /// is used while evaluating it
+    fn eval_constant(&mut self, name: Symbol, used: Option<Span>) -> Eval<Option<Value>> {
+        if let Some(value) = self.values.get(&name) {
+            return value.map(|x| Some(Value::Literal(x))).ok_or(Stop::Failed);
+        }
+        let Some(const_decl) = self.constants.get(&name).copied() else {
+            return Ok(None);
+        };
+        if let Some(position) = self.evaluating.iter().position(|x| *x == name) {
+            let cycle: Vec<_> = self.evaluating[position + 1..]
+                .iter()
+                .chain([&name])
+                .map(|x| format!("`{}`", x))
+                .collect();
+            let mut diagnostic =
+                Diagnostic::error(format!("cycle evaluating the constant `{}`", name))
+                    .with_primary(const_decl.name.span, "")
+                    .with_note(format!("`{}` uses {}", name, cycle.join(", which uses ")));
+            if let Some(used) = used {
+                diagnostic = diagnostic.with_secondary(used, "used again here");
+            }
+            // Everything in the cycle fails, without reporting it again
+            for constant in self.evaluating.drain(position..) {
+                self.values.insert(constant, None);
+            }
+            return Err(Stop::Error(diagnostic));
+        }
+
+        self.evaluating.push(name);
+        let (steps, depth) = (self.steps, self.depth);
+        self.steps = 0;
+        self.depth = 0;
+        let scopes = std::mem::take(&mut self.scopes);
+        let result = self.eval(&const_decl.value);
+        self.scopes = scopes;
+        self.steps = steps;
+        self.depth = depth;
+        if self.evaluating.last() == Some(&name) {
+            self.evaluating.pop();
+        }
+
+        let value = match result {
+            Ok(value) => Some(value.literal()),
+            Err(Stop::Error(diagnostic)) => {
+                self.diagnostics.push(diagnostic);
+                None
+            }
+            Err(Stop::Failed) => None,
+            Err(Stop::Return(_)) => unreachable!("a `ret` can only be within a function"),
+        };
+        if self.values.contains_key(&name) {
+            // The constant was part of a cycle, which is reported once
+            return Err(Stop::Failed);
+        }
+        self.values.insert(name, value);
+        match value {
+            Some(value) => Ok(Some(Value::Literal(value))),
+            None => Err(Stop::Failed),
+        }
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<Value> {
+        self.scopes.iter().rev().find_map(|x| x.get(&name).copied())
+    }
+
+    fn eval_block(&mut self, block: &Block) -> Eval<Value> {
+        self.scopes.push(HashMap::new());
+        let result = self.eval_statements(block);
+        self.scopes.pop();
+        result
+    }
+
+    fn eval_statements(&mut self, block: &Block) -> Eval<Value> {
+        for statement in &block.statements {
+            match &statement.kind {
+                StatementKind::Let(let_statement) => {
+                    let value = match &let_statement.value {
+                        Some(value) => self.eval(value)?,
+                        None => Value::Unit,
+                    };
+                    self.scopes
+                        .last_mut()
+                        .expect("there is always a scope within a block")
+                        .insert(let_statement.name.symbol, value);
+                }
+                StatementKind::Expr(expr) => {
+                    self.eval(expr)?;
+                }
+                StatementKind::Error => {}
+            }
+        }
+        match &block.tail {
+            Some(tail) => self.eval(tail),
+            None => Ok(Value::Unit),
+        }
+    }
+
+    fn eval(&mut self, expr: &Expr) -> Eval<Value> {
+        self.steps += 1;
+        if self.steps > STEP_LIMIT {
+            return Err(Stop::Error(
+                Diagnostic::error(format!(
+                    "evaluating `{}` takes too long",
+                    self.text(expr.span)
+                ))
+                .with_primary(expr.span, "")
+                .with_note(format!(
+                    "constants are evaluated in at most {} steps",
+                    STEP_LIMIT
+                )),
+            ));
+        }
+
+        match &expr.kind {
+            ExprKind::Literal(literal) => {
+                let ty = match self.types.type_of(expr.id) {
+                    Some(Ty::Primitive(primitive)) => *primitive,
+                    _ => PrimitiveType::of_literal(literal),
+                };
+                Ok(Value::Literal(arith::literal_of(literal, ty)))
+            }
+            ExprKind::Name(name) => match self.lookup(name.symbol) {
+                Some(value) => Ok(value),
+                None => match self.eval_constant(name.symbol, Some(expr.span))? {
+                    Some(value) => Ok(value),
+                    None => Err(self.unsupported(expr, "this is not a constant")),
+                },
+            },
+            ExprKind::Unary {
+                operator: operator @ (UnaryOperator::Negate | UnaryOperator::Not),
+                operand,
+            } => {
+                let value = self.eval(operand)?.literal();
+                arith::unary(*operator, &value)
+                    .map(Value::Literal)
+                    .map_err(|x| self.arith_error(x, expr.span))
+            }
+            ExprKind::Binary {
+                operator: operator @ (BinaryOperator::And | BinaryOperator::Or),
+                left,
+                right,
+            } => {
+                let left = self.eval(left)?.as_bool();
+                match (operator, left) {
+                    (BinaryOperator::And, false) => Ok(Value::Literal(LiteralKind::Boolean(false))),
+                    (BinaryOperator::Or, true) => Ok(Value::Literal(LiteralKind::Boolean(true))),
+                    _ => self.eval(right),
+                }
+            }
+            ExprKind::Binary {
+                operator,
+                left,
+                right,
+            } => {
+                let left = self.eval(left)?.literal();
+                let right = self.eval(right)?.literal();
+                arith::binary(*operator, &left, &right)
+                    .map(Value::Literal)
+                    .map_err(|x| self.arith_error(x, expr.span))
+            }
+            ExprKind::Assign {
+                operator,
+                target,
+                value,
+            } => {
+                let value = self.eval(value)?;
+                let ExprKind::Name(name) = &target.kind else {
+                    return Err(self.unsupported(target, "only locals can be assigned to"));
+                };
+                let Some(current) = self.lookup(name.symbol) else {
+                    return Err(self.unsupported(target, "only locals can be assigned to"));
+                };
+                let value = match operator {
+                    Some(operator) => {
+                        arith::binary(*operator, &current.literal(), &value.literal())
+                            .map(Value::Literal)
+                            .map_err(|x| self.arith_error(x, expr.span))?
+                    }
+                    None => value,
+                };
+                let scope = self
+                    .scopes
+                    .iter_mut()
+                    .rev()
+                    .find(|x| x.contains_key(&name.symbol))
+                    .expect("the local was just found");
+                scope.insert(name.symbol, value);
+                Ok(Value::Unit)
+            }
+            ExprKind::Tuple(elements) if elements.is_empty() => Ok(Value::Unit),
+            ExprKind::Call { callee, arguments } => self.eval_call(expr, callee, arguments),
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => self.eval_block(block),
+            ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => match (self.eval(condition)?.as_bool(), else_branch) {
+                (true, _) => self.eval_block(then_branch),
+                (false, Some(else_branch)) => self.eval(else_branch),
+                (false, None) => Ok(Value::Unit),
+            },
+            ExprKind::When { scrutinee, arms } => {
+                let value = self.eval(scrutinee)?;
+                for arm in arms {
+                    let mut bindings = HashMap::new();
+                    if !self.matches(&arm.pattern, value, &mut bindings)? {
+                        continue;
+                    }
+                    self.scopes.push(bindings);
+                    let result = match &arm.guard {
+                        Some(guard) => match self.eval(guard) {
+                            Ok(guard) if guard.as_bool() => self.eval(&arm.body).map(Some),
+                            Ok(_) => Ok(None),
+                            Err(stop) => Err(stop),
+                        },
+                        None => self.eval(&arm.body).map(Some),
+                    };
+                    self.scopes.pop();
+                    if let Some(value) = result? {
+                        return Ok(value);
+                    }
+                }
+                Err(self.unsupported(scrutinee, "no arm matches this value"))
+            }
+            ExprKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval(value)?,
+                    None => Value::Unit,
+                };
+                Err(Stop::Return(value))
+            }
+            ExprKind::Unary { .. } | ExprKind::Reference { .. } => {
+                Err(self.unsupported(expr, "references can not be used while compiling"))
+            }
+            ExprKind::Path(_)
+            | ExprKind::StructLiteral { .. }
+            | ExprKind::Tuple(_)
+            | ExprKind::Array(_)
+            | ExprKind::Repeat { .. } => {
+                Err(self.unsupported(expr, "only primitive values can be used while compiling"))
+            }
+            ExprKind::Field { .. } => {
+                Err(self.unsupported(expr, "fields can not be used while compiling"))
+            }
+            ExprKind::For { .. } | ExprKind::Yield(_) => {
+                Err(self.unsupported(expr, "generators can not be used while compiling"))
+            }
+            ExprKind::Closure(_) => {
+                Err(self.unsupported(expr, "closures can not be used while compiling"))
+            }
+            ExprKind::Error => Err(Stop::Failed),
+        }
+    }
+
+    /// Checks whether a value matches a pattern, collecting what the pattern binds
+    fn matches(
+        &self,
+        pattern: &Pattern,
+        value: Value,
+        bindings: &mut HashMap<Symbol, Value>,
+    ) -> Eval<bool> {
+        let ty = |x: &LiteralKind| match self.types.type_of(pattern.id) {
+            Some(Ty::Primitive(primitive)) => arith::literal_of(x, *primitive),
+            _ => *x,
+        };
+        let ordering = |x: &LiteralKind| arith::compare(&value.literal(), &ty(x));
+        match &pattern.kind {
+            PatternKind::Wildcard => Ok(true),
+            PatternKind::Binding { name, .. } => {
+                bindings.insert(name.symbol, value);
+                Ok(true)
+            }
+            PatternKind::Literal(literal) => Ok(ordering(literal).is_some_and(|x| x.is_eq())),
+            PatternKind::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let above = ordering(start).is_some_and(|x| x.is_ge());
+                let below = match inclusive {
+                    true => ordering(end).is_some_and(|x| x.is_le()),
+                    false => ordering(end).is_some_and(|x| x.is_lt()),
+                };
+                Ok(above && below)
+            }
+            PatternKind::Tuple(_) | PatternKind::Variant { .. } => Err(Stop::Error(
+                Diagnostic::error("this pattern can not be matched while compiling").with_primary(
+                    pattern.span,
+                    "only primitive values can be used while compiling",
+                ),
+            )),
+        }
+    }
+
+    fn eval_call(&mut self, expr: &Expr, callee: &Expr, arguments: &[Expr]) -> Eval<Value> {
+        if let (Some(intrinsic), ExprKind::Field { object, .. }) =
+            (self.types.intrinsic_of(expr.id), &callee.kind)
+        {
+            let receiver = self.eval(object)?.literal();
+            let arguments = arguments
+                .iter()
+                .map(|x| Ok(self.eval(x)?.literal()))
+                .collect::<Eval<Vec<_>>>()?;
+            return arith::intrinsic(intrinsic, &receiver, &arguments)
+                .map(Value::Literal)
+                .map_err(|x| self.arith_error(x, expr.span));
+        }
+        let function = match &callee.kind {
+            ExprKind::Name(name) if self.lookup(name.symbol).is_none() => {
+                self.functions.get(&name.symbol).copied()
+            }
+            _ => None,
+        };
+        let Some(function) = function else {
+            return Err(self.unsupported(expr, "only functions can be called while compiling"));
+        };
+        let Some(body) = &function.body else {
+            return Err(Stop::Failed);
+        };
+        if !function.generics.params.is_empty() {
+            return Err(
+                self.unsupported(expr, "generic functions can not be called while compiling")
+            );
+        }
+        if self.depth >= DEPTH_LIMIT {
+            return Err(Stop::Error(
+                Diagnostic::error(format!(
+                    "reached the recursion limit evaluating `{}`",
+                    self.text(expr.span)
+                ))
+                .with_primary(expr.span, "")
+                .with_note(format!(
+                    "calls made while compiling can be at most {} deep",
+                    DEPTH_LIMIT
+                )),
+            ));
+        }
+
+        let mut scope = HashMap::new();
+        for (parameter, argument) in function.parameters.iter().zip(arguments) {
+            scope.insert(parameter.name.symbol, self.eval(argument)?);
+        }
+        let scopes = std::mem::replace(&mut self.scopes, vec![scope]);
+        self.depth += 1;
+        let result = self.eval_statements(body);
+        self.depth -= 1;
+        self.scopes = scopes;
+        match result {
+            Ok(value) | Err(Stop::Return(value)) => Ok(value),
+            Err(Stop::Error(diagnostic)) if self.depth == 0 => {
+                // Errors within a function are pointed at from where it was called
+                Err(Stop::Error(
+                    diagnostic.with_secondary(expr.span, "while evaluating this call"),
+                ))
+            }
+            Err(stop) => Err(stop),
+        }
+    }
+}
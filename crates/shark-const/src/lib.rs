@@ -0,0 +1,207 @@
+//! Step five of compilation, after checking. Evaluates the value of every `const`, the
+//! discriminant of every variant of an `enum` and the length of every array with
+//! [eval::Evaluator], then [substitute]s the uses of constants so that the steps after it never see
+//! them
+//!
+//! Arithmetic follows the semantics the program has when it runs, see [arith], so a constant whose
+//! value would stop the program instead stops the compilation with a diagnostic pointing at the
+//! expression which failed
+
+use std::collections::HashMap;
+
+use eval::Evaluator;
+use shark_core::{diagnostic::Diagnostic, symbol::Symbol};
+use shark_lex::token::LiteralKind;
+use shark_parse::{
+    ast::{ItemKind, Module, NodeId, Variant},
+    visit,
+};
+use shark_sema::ty::Length;
+use shark_typeck::TypeckResults;
+
+pub mod arith;
+pub mod eval;
+pub mod substitute;
+
+pub use substitute::substitute;
+
+#[cfg(test)]
+pub mod tests;
+
+/// The values worked out while compiling a [Module]
+#[derive(Debug, Clone, Default)]
+pub struct ConstResults {
+    /// The value of every constant which could be evaluated
+    values: HashMap<Symbol, LiteralKind>,
+    /// The discriminant of every variant of every `enum`
+    discriminants: HashMap<NodeId, i64>,
+    /// The length of every array, by the id of the expression it is written as
+    lengths: HashMap<NodeId, u64>,
+}
+
+impl ConstResults {
+    pub fn value_of(&self, name: Symbol) -> Option<&LiteralKind> {
+        self.values.get(&name)
+    }
+
+    /// Gets the discriminant of a [Variant], which is one more than that of the variant before it
+    /// unless it is given one
+    pub fn discriminant_of(&self, variant: NodeId) -> Option<i64> {
+        self.discriminants.get(&variant).copied()
+    }
+
+    /// Gets the length of an array type or of a `[value; length]`, by the id of the expression
+    /// the length is written as
+    pub fn length_of(&self, length: NodeId) -> Option<u64> {
+        self.lengths.get(&length).copied()
+    }
+
+    /// Gets the value of a [Length], if it could be evaluated
+    fn length(&self, length: Length) -> Option<u64> {
+        match length {
+            Length::Count(count) => Some(count),
+            Length::Const(id) => self.length_of(id),
+        }
+    }
+}
+
+/// Evaluates the discriminants of the variants of an `enum`, reporting those which are given to
+/// more than one variant
+fn discriminants(
+    evaluator: &mut Evaluator,
+    variants: &[Variant],
+    results: &mut ConstResults,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let mut given: Vec<(i64, &Variant)> = Vec::new();
+    // The discriminant of the next variant without one, if the one before it could be evaluated
+    let mut next = Some(0);
+    for variant in variants {
+        let value = match &variant.discriminant {
+            Some(value) => evaluator.evaluate(value).and_then(|x| arith::integer(&x)),
+            None => next,
+        };
+        let Some(value) = value else {
+            next = None;
+            continue;
+        };
+        let Ok(discriminant) = i64::try_from(value) else {
+            let previous = given.last().expect("the first variant is given zero").1;
+            diagnostics.push(
+                Diagnostic::error(format!(
+                    "overflow evaluating the discriminant of `{}`",
+                    variant.name.symbol
+                ))
+                .with_primary(
+                    variant.name.span,
+                    "its discriminant does not fit in `Int64`",
+                )
+                .with_secondary(previous.span, "it is one more than that of this variant"),
+            );
+            next = None;
+            continue;
+        };
+
+        if let Some((_, previous)) = given.iter().find(|(x, _)| *x == discriminant) {
+            diagnostics.push(
+                Diagnostic::error(format!(
+                    "the discriminant `{}` is given to more than one variant",
+                    discriminant
+                ))
+                .with_primary(
+                    variant.span,
+                    format!("`{}` is given it here", variant.name.symbol),
+                )
+                .with_secondary(
+                    previous.span,
+                    format!("`{}` was given it first", previous.name.symbol),
+                ),
+            );
+        }
+        given.push((discriminant, variant));
+        results.discriminants.insert(variant.id, discriminant);
+        next = Some(i128::from(discriminant) + 1);
+    }
+}
+
+/// Evaluates the lengths of the arrays of a module, which can not be negative, then compares those
+/// which checking required to be the same
+fn lengths(
+    evaluator: &mut Evaluator,
+    module: &Module,
+    types: &TypeckResults,
+    results: &mut ConstResults,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    for length in visit::array_lengths(module) {
+        let Some(value) = evaluator.evaluate(length).and_then(|x| arith::integer(&x)) else {
+            continue;
+        };
+        match u64::try_from(value) {
+            Ok(value) => {
+                results.lengths.insert(length.id, value);
+            }
+            Err(_) => diagnostics.push(
+                Diagnostic::error(format!("the array length `{}` is negative", value))
+                    .with_primary(length.span, ""),
+            ),
+        }
+    }
+    diagnostics.append(&mut evaluator.diagnostics);
+
+    for (expected, found, span) in types.length_checks() {
+        let (Some(expected), Some(found)) = (results.length(*expected), results.length(*found))
+        else {
+            continue;
+        };
+        if expected != found {
+            diagnostics.push(
+                Diagnostic::error(format!(
+                    "mismatched array lengths: expected {}, found {}",
+                    expected, found
+                ))
+                .with_primary(*span, format!("this has {} values", found)),
+            );
+        }
+    }
+}
+
+/// Evaluates the constants, discriminants and array lengths of a checked [Module]. The source is
+/// what the module was parsed from, which diagnostics quote the expressions of
+pub fn evaluate(
+    module: &Module,
+    types: &TypeckResults,
+    source: &str,
+) -> (ConstResults, Vec<Diagnostic>) {
+    let mut evaluator = Evaluator::new(module, types, source);
+    let mut results = ConstResults::default();
+    let mut diagnostics = Vec::new();
+    for item in &module.items {
+        match &item.kind {
+            ItemKind::Const(const_decl) => {
+                let name = const_decl.name.symbol;
+                if let Some(value) = evaluator.constant(name) {
+                    results.values.insert(name, value);
+                }
+            }
+            ItemKind::Enum(enum_decl) => {
+                discriminants(
+                    &mut evaluator,
+                    &enum_decl.variants,
+                    &mut results,
+                    &mut diagnostics,
+                );
+            }
+            _ => continue,
+        }
+        diagnostics.append(&mut evaluator.diagnostics);
+    }
+    lengths(
+        &mut evaluator,
+        module,
+        types,
+        &mut results,
+        &mut diagnostics,
+    );
+    (results, diagnostics)
+}
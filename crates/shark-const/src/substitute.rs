@@ -0,0 +1,178 @@
+//! Replaces every use of a constant within the bodies of functions by its value, so that the steps
+//! after checking only ever see literals. A replaced name keeps its id, and so the type it was
+//! inferred to have. The length of each `[value; length]` is replaced by its value too
+
+use std::collections::HashSet;
+
+use shark_core::symbol::Symbol;
+use shark_lex::token::LiteralKind;
+use shark_parse::ast::{
+    Block, Expr, ExprKind, Function, ItemKind, Module, Pattern, PatternKind, StatementKind,
+};
+
+use crate::ConstResults;
+
+struct Substitution<'s> {
+    results: &'s ConstResults,
+    /// The locals in scope, which hide constants of the same name
+    scopes: Vec<HashSet<Symbol>>,
+}
+
+impl Substitution<'_> {
+    fn declare(&mut self, name: Symbol) {
+        self.scopes
+            .last_mut()
+            .expect("there is always a scope within a function")
+            .insert(name);
+    }
+
+    fn declare_pattern(&mut self, pattern: &Pattern) {
+        match &pattern.kind {
+            PatternKind::Binding { name, .. } => self.declare(name.symbol),
+            PatternKind::Tuple(patterns)
+            | PatternKind::Variant {
+                payload: patterns, ..
+            } => patterns.iter().for_each(|x| self.declare_pattern(x)),
+            PatternKind::Wildcard | PatternKind::Literal(_) | PatternKind::Range { .. } => {}
+        }
+    }
+
+    fn function(&mut self, function: &mut Function) {
+        let Some(body) = &mut function.body else {
+            return;
+        };
+        self.scopes = vec![function.parameters.iter().map(|x| x.name.symbol).collect()];
+        self.block(body);
+    }
+
+    fn block(&mut self, block: &mut Block) {
+        self.scopes.push(HashSet::new());
+        for statement in &mut block.statements {
+            match &mut statement.kind {
+                StatementKind::Let(let_statement) => {
+                    if let Some(value) = &mut let_statement.value {
+                        self.expr(value);
+                    }
+                    self.declare(let_statement.name.symbol);
+                }
+                StatementKind::Expr(expr) => self.expr(expr),
+                StatementKind::Error => {}
+            }
+        }
+        if let Some(tail) = &mut block.tail {
+            self.expr(tail);
+        }
+        self.scopes.pop();
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        match &mut expr.kind {
+            ExprKind::Name(name) => {
+                let local = self.scopes.iter().any(|x| x.contains(&name.symbol));
+                if let (false, Some(value)) = (local, self.results.value_of(name.symbol)) {
+                    expr.kind = ExprKind::Literal(*value);
+                }
+            }
+            ExprKind::Literal(_) | ExprKind::Path(_) | ExprKind::Error => {}
+            ExprKind::StructLiteral { fields, .. } => {
+                fields.iter_mut().for_each(|x| self.expr(&mut x.value))
+            }
+            ExprKind::Unary { operand, .. } | ExprKind::Reference { operand, .. } => {
+                self.expr(operand)
+            }
+            ExprKind::Binary { left, right, .. }
+            | ExprKind::Assign {
+                target: left,
+                value: right,
+                ..
+            } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Tuple(elements) | ExprKind::Array(elements) => {
+                elements.iter_mut().for_each(|x| self.expr(x))
+            }
+            ExprKind::Repeat { value, length } => {
+                self.expr(value);
+                if let Some(count) = self.results.length_of(length.id) {
+                    length.kind = ExprKind::Literal(LiteralKind::Int64(count as i64));
+                }
+            }
+            ExprKind::Call { callee, arguments } => {
+                self.expr(callee);
+                arguments.iter_mut().for_each(|x| self.expr(x));
+            }
+            ExprKind::Field { object, .. } => self.expr(object),
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => self.block(block),
+            ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(condition);
+                self.block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.expr(else_branch);
+                }
+            }
+            ExprKind::For {
+                pattern,
+                iterable,
+                body,
+                ..
+            } => {
+                self.expr(iterable);
+                self.scopes.push(HashSet::new());
+                self.declare_pattern(pattern);
+                self.block(body);
+                self.scopes.pop();
+            }
+            ExprKind::When { scrutinee, arms } => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    self.scopes.push(HashSet::new());
+                    self.declare_pattern(&arm.pattern);
+                    if let Some(guard) = &mut arm.guard {
+                        self.expr(guard);
+                    }
+                    self.expr(&mut arm.body);
+                    self.scopes.pop();
+                }
+            }
+            ExprKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            ExprKind::Yield(value) => self.expr(value),
+            ExprKind::Closure(function) => {
+                let Some(body) = &mut function.body else {
+                    return;
+                };
+                self.scopes
+                    .push(function.parameters.iter().map(|x| x.name.symbol).collect());
+                self.block(body);
+                self.scopes.pop();
+            }
+        }
+    }
+}
+
+/// Replaces the uses of constants within every function and method of a module by their values
+pub fn substitute(module: &mut Module, results: &ConstResults) {
+    let mut substitution = Substitution {
+        results,
+        scopes: Vec::new(),
+    };
+    for item in &mut module.items {
+        let functions: Vec<&mut Function> = match &mut item.kind {
+            ItemKind::Function(function) => vec![function],
+            ItemKind::Trait(trait_decl) => trait_decl.methods.iter_mut().collect(),
+            ItemKind::Impl(impl_decl) => impl_decl.methods.iter_mut().collect(),
+            _ => continue,
+        };
+        for function in functions {
+            substitution.function(function);
+        }
+    }
+}
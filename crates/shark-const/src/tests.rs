@@ -0,0 +1,300 @@
+use shark_core::symbol::Symbol;
+use shark_lex::token::LiteralKind;
+use shark_parse::{
+    ast::{ItemKind, Module},
+    dump::dump_block,
+    parse,
+};
+
+use crate::{evaluate, substitute, ConstResults};
+
+/// Evaluates the constants of a module which must check without errors, returning the messages of
+/// the diagnostics from evaluating them
+fn evaluate_source(source: &str) -> (Module, ConstResults, Vec<String>) {
+    let module = parse(None, source).expect("failed to parse module");
+    let (defs, diagnostics) = shark_sema::check_module(&module);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let (types, diagnostics) = shark_typeck::check_module(&module, &defs);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let (results, diagnostics) = evaluate(&module, &types, source);
+    let messages = diagnostics.iter().map(|x| x.message.clone()).collect();
+    (module, results, messages)
+}
+
+/// Gets the value of every constant of a module, which must all be evaluated without errors
+fn values(source: &str) -> Vec<(String, LiteralKind)> {
+    let (module, results, errors) = evaluate_source(source);
+    assert!(errors.is_empty(), "{:?}", errors);
+    module
+        .items
+        .iter()
+        .filter_map(|x| match &x.kind {
+            ItemKind::Const(const_decl) => Some(const_decl.name.symbol),
+            _ => None,
+        })
+        .map(|x| (x.to_string(), *results.value_of(x).expect("missing value")))
+        .collect()
+}
+
+#[test]
+fn test_arithmetic() {
+    let values = values(
+        "const A :: UInt8 = 254 + 1;
+        const B :: Int8 = 0 - 100 - 28;
+        const C :: UInt8 = 3uint8 << 7;
+        const D :: Int32 = (0 - 16) >> 2;
+        const E :: UInt64 = 18446744073709551615uint64 / 5;
+        const F :: Float32 = 0.1 + 0.2;
+        const G :: Float64 = 1.5float64 * 4.0float64;
+        const H :: Bool = ('a' < 'b') & (\"shark\" == \"shark\");
+        const I :: UInt8 = A & (7 << 2);
+        const J :: Bool = !(1 > 2) | G / 0.0float64 > 1.0float64;
+        const K :: Char = 'k';",
+    );
+    assert_eq!(
+        values,
+        [
+            ("A".to_string(), LiteralKind::UInt8(255)),
+            ("B".to_string(), LiteralKind::Int8(-128)),
+            ("C".to_string(), LiteralKind::UInt8(128)),
+            ("D".to_string(), LiteralKind::Int32(-4)),
+            ("E".to_string(), LiteralKind::UInt64(3689348814741910323)),
+            ("F".to_string(), LiteralKind::Float32(0.1f32 + 0.2f32)),
+            ("G".to_string(), LiteralKind::Float64(6.0)),
+            ("H".to_string(), LiteralKind::Boolean(true)),
+            ("I".to_string(), LiteralKind::UInt8(28)),
+            ("J".to_string(), LiteralKind::Boolean(true)),
+            ("K".to_string(), LiteralKind::Char('k')),
+        ]
+    );
+}
+
+#[test]
+fn test_functions() {
+    let values = values(
+        "const FACTORIAL :: Int64 = factorial(20int64);
+        const SMALL :: Str = size(7);
+        const LARGE :: Str = size(LIMIT);
+        const LIMIT :: Int32 = 1000;
+        const SUM :: Int32 = sum_to(10);
+        fun factorial(n :: Int64) :: Int64 {
+            if n <= 1 { ret 1; }
+            n * factorial(n - 1)
+        }
+        fun size(n :: Int32) :: Str {
+            when n {
+                0..10 => \"small\",
+                x if x < LIMIT => \"medium\",
+                _ => \"large\",
+            }
+        }
+        fun sum_to(n :: Int32) :: Int32 {
+            let mut total = 0;
+            let mut LIMIT = n;
+            total += LIMIT;
+            LIMIT = LIMIT - 1;
+            if LIMIT > 0 { total += sum_to(LIMIT); }
+            total
+        }",
+    );
+    assert_eq!(
+        values,
+        [
+            (
+                "FACTORIAL".to_string(),
+                LiteralKind::Int64(2432902008176640000)
+            ),
+            (
+                "SMALL".to_string(),
+                LiteralKind::Str(Symbol::intern("small"))
+            ),
+            (
+                "LARGE".to_string(),
+                LiteralKind::Str(Symbol::intern("large"))
+            ),
+            ("LIMIT".to_string(), LiteralKind::Int32(1000)),
+            ("SUM".to_string(), LiteralKind::Int32(55)),
+        ]
+    );
+}
+
+#[test]
+fn test_overflow() {
+    let (_, results, errors) = evaluate_source(
+        "const A :: UInt8 = 255uint8 + 1;
+        const B :: Int8 = 0 - 127 - 2;
+        const C :: UInt32 = 0 - 1;
+        const D :: Int64 = 9223372036854775807int64 * 2;
+        const E :: Int32 = 1 << 32;
+        const F :: Int32 = ratio(0);
+        const G :: Int32 = F + 1;
+        const H :: Int8 = -(0 - 127 - 1);
+        fun ratio(n :: Int32) :: Int32 { 10 / n }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "overflow evaluating `255uint8 + 1`",
+            "overflow evaluating `0 - 127 - 2`",
+            "overflow evaluating `0 - 1`",
+            "overflow evaluating `9223372036854775807int64 * 2`",
+            "overflow evaluating `1 << 32`",
+            "division by zero evaluating `10 / n`",
+            "overflow evaluating `-(0 - 127 - 1)`",
+        ]
+    );
+    // A constant using one which failed is not reported again
+    assert_eq!(results.value_of(Symbol::intern("G")), None);
+}
+
+#[test]
+fn test_evaluation_errors() {
+    let (_, _, errors) = evaluate_source(
+        "type Point { x :: Int32 }
+        const A :: Int32 = B + 1;
+        const B :: Int32 = C;
+        const C :: Int32 = A;
+        const D :: Int32 = D;
+        const E :: Int32 = forever(1);
+        const F :: Int32 = Point { x = 1 }.x;
+        fun forever(n :: Int32) :: Int32 { forever(n + 1) }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "cycle evaluating the constant `A`",
+            "cycle evaluating the constant `D`",
+            "reached the recursion limit evaluating `forever(n + 1)`",
+            "`Point { x = 1 }.x` can not be evaluated while compiling",
+        ]
+    );
+}
+
+#[test]
+fn test_discriminants() {
+    let (module, results, errors) = evaluate_source(
+        "const BASE :: Int64 = 10;
+        enum Colour { Red, Green = BASE * 2, Blue }
+        enum Shape { Circle(Float64), Square, Empty }
+        enum Clash { A = 4, B = 3, C }",
+    );
+    assert_eq!(
+        errors,
+        ["the discriminant `4` is given to more than one variant"]
+    );
+    let discriminants: Vec<Vec<i64>> = module
+        .items
+        .iter()
+        .filter_map(|x| match &x.kind {
+            ItemKind::Enum(enum_decl) => Some(
+                enum_decl
+                    .variants
+                    .iter()
+                    .map(|x| results.discriminant_of(x.id).expect("missing discriminant"))
+                    .collect(),
+            ),
+            _ => None,
+        })
+        .collect();
+    assert_eq!(
+        discriminants,
+        [vec![0, 20, 21], vec![0, 1, 2], vec![4, 3, 4]]
+    );
+}
+
+#[test]
+fn test_substitute() {
+    let (mut module, results, errors) = evaluate_source(
+        "const LIMIT :: UInt8 = 200;
+        fun main(flag :: Bool) :: UInt8 {
+            let small = LIMIT;
+            let LIMIT = 3uint8;
+            when flag {
+                true => small,
+                false => LIMIT,
+            }
+        }",
+    );
+    assert!(errors.is_empty(), "{:?}", errors);
+    substitute(&mut module, &results);
+    let ItemKind::Function(function) = &module.items[1].kind else {
+        panic!("expected a function");
+    };
+    let body = function.body.as_ref().expect("missing body");
+    assert_eq!(
+        dump_block(body),
+        "{ (let small 200uint8) (let LIMIT 3uint8) (when flag (true => small) (false => LIMIT)) }"
+    );
+}
+
+#[test]
+fn test_numeric_methods() {
+    // Constants always trap on overflow, unless a method says otherwise
+    assert_eq!(
+        values(
+            "const A :: UInt8 = (250uint8).wrapping_add(10);
+            const B :: Int8 = (-100int8).saturating_sub(100);
+            const C :: Int64 = (7).to_int64() * 3;
+            const D :: Int32 = (2.9).to_int32();
+            const E :: Float64 = (1).to_float64() / 4.0;"
+        ),
+        [
+            ("A".to_string(), LiteralKind::UInt8(4)),
+            ("B".to_string(), LiteralKind::Int8(-128)),
+            ("C".to_string(), LiteralKind::Int64(21)),
+            ("D".to_string(), LiteralKind::Int32(2)),
+            ("E".to_string(), LiteralKind::Float64(0.25)),
+        ]
+    );
+
+    let (_, _, errors) = evaluate_source(
+        "const A :: UInt8 = (300).to_uint8();
+        const B :: Int32 = (1).trapping_div(0);
+        const C :: Int32 = (2147483647).trapping_add(1);",
+    );
+    assert_eq!(
+        errors,
+        [
+            "overflow evaluating `(300).to_uint8()`",
+            "division by zero evaluating `(1).trapping_div(0)`",
+            "overflow evaluating `(2147483647).trapping_add(1)`",
+        ]
+    );
+}
+
+#[test]
+fn test_array_lengths() {
+    let (mut module, results, errors) = evaluate_source(
+        "const SIZE :: Int64 = 2 + 1;
+        fun first(values :: [Int64; SIZE]) :: [Int64; 3] { values }
+        fun main() :: [Int64; 3] {
+            let values = [0; SIZE * 2 - 3];
+            first(values)
+        }",
+    );
+    assert!(errors.is_empty(), "{:?}", errors);
+    substitute(&mut module, &results);
+    let ItemKind::Function(function) = &module.items[2].kind else {
+        panic!("expected a function");
+    };
+    let body = function.body.as_ref().expect("missing body");
+    assert_eq!(
+        dump_block(body),
+        "{ (let values (repeat 0 3int64)) (call first values) }"
+    );
+
+    let (_, _, errors) = evaluate_source(
+        "const SIZE :: Int64 = 2;
+        fun first(values :: [Int64; SIZE]) :: [Int64; 3] { values }
+        fun second() :: [Int64; SIZE] { [1, 2, 3] }
+        fun third() { let values = [true; 1 - SIZE]; }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "the array length `-1` is negative",
+            "mismatched array lengths: expected 3, found 2",
+            "mismatched array lengths: expected 2, found 3",
+        ]
+    );
+}
@@ -0,0 +1,233 @@
+use std::{fmt::Display, path::Path};
+
+use crate::source::{LineIndex, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Note,
+    Warning,
+    Error,
+}
+
+impl Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self {
+            Self::Note => "note",
+            Self::Warning => "warning",
+            Self::Error => "error",
+        };
+        write!(f, "{}", severity)
+    }
+}
+
+/// A message attached to a [Span] of a [Diagnostic]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Label {
+    pub span: Span,
+    pub message: String,
+    /// The primary label points at the cause of the [Diagnostic], every other label adds context
+    pub primary: bool,
+}
+
+/// A problem found while compiling, along with everything needed to show it to the user. Every
+/// stage of compilation reports its problems as [Diagnostic]s so they can be rendered the same way
+#[derive(Debug, Clone, PartialEq)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub message: String,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic {
+    pub fn new(severity: Severity, message: impl Into<String>) -> Self {
+        Self {
+            severity,
+            message: message.into(),
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    pub fn error(message: impl Into<String>) -> Self {
+        Self::new(Severity::Error, message)
+    }
+
+    pub fn warning(message: impl Into<String>) -> Self {
+        Self::new(Severity::Warning, message)
+    }
+
+    /// Adds the label pointing at the cause of this [Diagnostic]
+    pub fn with_primary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: true,
+        });
+        self
+    }
+
+    /// Adds a label giving more context to this [Diagnostic]
+    pub fn with_secondary(mut self, span: Span, message: impl Into<String>) -> Self {
+        self.labels.push(Label {
+            span,
+            message: message.into(),
+            primary: false,
+        });
+        self
+    }
+
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.notes.push(note.into());
+        self
+    }
+
+    pub fn is_error(&self) -> bool {
+        self.severity == Severity::Error
+    }
+
+    /// Gets the [Span] of the primary label, falling back to the first label
+    pub fn primary_span(&self) -> Option<Span> {
+        self.labels
+            .iter()
+            .find(|x| x.primary)
+            .or(self.labels.first())
+            .map(|x| x.span)
+    }
+
+    /// Renders this [Diagnostic] along with the lines of source it points at
+    ///
+    /// ```text
+    /// error: expected an expression, found `;`
+    ///  --> main.shark:2:13
+    ///   |
+    /// 2 |     let a = ;
+    ///   |             ^ expected an expression
+    /// ```
+    pub fn render(&self, path: Option<&Path>, source: &str) -> String {
+        let line_index = LineIndex::new(source);
+        let mut result = format!("{}: {}\n", self.severity, self.message);
+
+        let mut labels: Vec<&Label> = self.labels.iter().collect();
+        labels.sort_by_key(|x| (x.span.start, !x.primary));
+        let gutter_width = labels
+            .iter()
+            .map(|x| line_index.line_column(x.span.start).0.to_string().len())
+            .max()
+            .unwrap_or(1);
+        let gutter = " ".repeat(gutter_width);
+
+        if let Some(span) = self.primary_span() {
+            let position = line_index.position(path, span.start);
+            result.push_str(&format!("{}--> {}\n", gutter, position));
+            result.push_str(&format!("{} |\n", gutter));
+        }
+
+        // Each line is shown once, with the markers of every label starting on it
+        let mut index = 0;
+        while index < labels.len() {
+            let line = line_index.line_column(labels[index].span.start).0;
+            let count = labels[index..]
+                .iter()
+                .take_while(|x| line_index.line_column(x.span.start).0 == line)
+                .count();
+            let line_text = line_index.line_text(line);
+            result.push_str(&format!("{:>gutter_width$} | {}\n", line, line_text));
+            render_markers(
+                &mut result,
+                &gutter,
+                &line_index,
+                line_text,
+                &labels[index..][..count],
+            );
+            index += count;
+        }
+
+        for note in &self.notes {
+            result.push_str(&format!("{} = note: {}\n", gutter, note));
+        }
+        result
+    }
+}
+
+impl Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.severity, self.message)
+    }
+}
+
+/// Renders the markers under a line for the labels starting on it, which are sorted by where they
+/// start. The message of the last label follows the markers, and those of the labels before it
+/// hang below their markers
+///
+/// ```text
+/// 2 |     let a = b + c;
+///   |         -       ^ expected `Int32`
+///   |         |
+///   |         declared here
+/// ```
+fn render_markers(
+    result: &mut String,
+    gutter: &str,
+    line_index: &LineIndex,
+    line_text: &str,
+    labels: &[&Label],
+) {
+    let mut markers: Vec<char> = Vec::new();
+    let mut columns = Vec::with_capacity(labels.len());
+    for label in labels {
+        let (line, column) = line_index.line_column(label.span.start);
+        let (end_line, end_column) = line_index.line_column(label.span.end);
+        // Labels spanning multiple lines are underlined up to the end of their first line
+        let underline_length = if end_line == line {
+            end_column.saturating_sub(column).max(1)
+        } else {
+            (line_text.chars().count() + 1)
+                .saturating_sub(column)
+                .max(1)
+        };
+        let marker = if label.primary { '^' } else { '-' };
+        if markers.len() < column - 1 + underline_length {
+            markers.resize(column - 1 + underline_length, ' ');
+        }
+        for slot in &mut markers[column - 1..][..underline_length] {
+            // A primary label stands out where it overlaps a secondary one
+            if *slot != '^' {
+                *slot = marker;
+            }
+        }
+        columns.push(column - 1);
+    }
+
+    let (last, hanging) = labels.split_last().expect("a line has at least one label");
+    let markers: String = markers.into_iter().collect();
+    result.push_str(&format!("{} | {}", gutter, markers.trim_end()));
+    if !last.message.is_empty() {
+        result.push_str(&format!(" {}", last.message));
+    }
+    result.push('\n');
+
+    let hanging: Vec<(usize, &str)> = hanging
+        .iter()
+        .zip(&columns)
+        .filter(|(label, _)| !label.message.is_empty())
+        .map(|(label, column)| (*column, label.message.as_str()))
+        .collect();
+    let connectors = |count: usize| {
+        let mut row = String::new();
+        for (column, _) in &hanging[..count] {
+            row.push_str(&" ".repeat(column - row.chars().count()));
+            row.push('|');
+        }
+        row
+    };
+    if !hanging.is_empty() {
+        result.push_str(&format!("{} | {}\n", gutter, connectors(hanging.len())));
+    }
+    for (index, (column, message)) in hanging.iter().enumerate().rev() {
+        let mut row = connectors(index);
+        row.push_str(&" ".repeat(column - row.chars().count()));
+        row.push_str(message);
+        result.push_str(&format!("{} | {}\n", gutter, row));
+    }
+}
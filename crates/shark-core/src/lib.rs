@@ -1,4 +1,7 @@
+pub mod diagnostic;
 pub mod source;
+pub mod suggest;
+pub mod symbol;
 
 #[cfg(test)]
 mod tests;
@@ -52,7 +52,7 @@ impl<'position> Display for SourcePosition<'position> {
             .path
             .as_ref()
             .and_then(|x| x.to_str())
-            .map_or_else(|| "unknown", |x| x);
+            .unwrap_or("unknown");
         write!(formatter, "{}:{}:{}", path_str, self.line, self.column)
     }
 }
@@ -84,6 +84,107 @@ impl<'position> PartialEq for SourcePosition<'position> {
     }
 }
 
+/// Represents a range of bytes within a source file. Unlike a range of [SourcePosition]s a [Span]
+/// can be used to slice the source text directly
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl Span {
+    /// Creates a new [Span] going from `start` up to, but not including, `end`
+    pub fn new(start: usize, end: usize) -> Self {
+        debug_assert!(start <= end, "a Span can not end before it starts");
+        Self { start, end }
+    }
+
+    pub fn len(&self) -> usize {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+
+    /// Creates a [Span] covering both this [Span] and the provided one
+    pub fn to(&self, other: Span) -> Span {
+        Span::new(self.start.min(other.start), self.end.max(other.end))
+    }
+
+    /// Checks if the byte offset is within this [Span]
+    pub fn contains(&self, offset: usize) -> bool {
+        self.start <= offset && offset < self.end
+    }
+}
+
+/// Maps byte offsets within a source file to lines and columns. Building a [LineIndex] is a single
+/// pass over the source, after which every lookup is a binary search
+#[derive(Debug, Clone)]
+pub struct LineIndex<'source> {
+    source: &'source str,
+    line_starts: Vec<usize>,
+}
+
+impl<'source> LineIndex<'source> {
+    pub fn new(source: &'source str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(source.match_indices('\n').map(|(index, _)| index + 1));
+        Self {
+            source,
+            line_starts,
+        }
+    }
+
+    /// Gets the one-based line and column of a byte offset. Columns are counted in characters
+    pub fn line_column(&self, offset: usize) -> (usize, usize) {
+        let offset = offset.min(self.source.len());
+        let line = match self.line_starts.binary_search(&offset) {
+            Ok(line) => line,
+            Err(line) => line - 1,
+        };
+        let column = self.source[self.line_starts[line]..offset].chars().count();
+        (line + 1, column + 1)
+    }
+
+    /// Gets the [SourcePosition] of a byte offset
+    pub fn position<'position>(
+        &self,
+        path: Option<&'position Path>,
+        offset: usize,
+    ) -> SourcePosition<'position> {
+        let (line, column) = self.line_column(offset);
+        SourcePosition::new(path, line, column)
+    }
+
+    /// Gets the byte offset of a zero-based line and column, clamping it to the end of the line
+    pub fn offset(&self, line: usize, column: usize) -> usize {
+        let Some(&start) = self.line_starts.get(line) else {
+            return self.source.len();
+        };
+        let line_text = self.line_text(line + 1);
+        let column_offset = line_text
+            .char_indices()
+            .nth(column)
+            .map_or(line_text.len(), |(index, _)| index);
+        start + column_offset
+    }
+
+    /// Gets the text of a one-based line without its line ending
+    pub fn line_text(&self, line: usize) -> &'source str {
+        let start = self.line_starts[line - 1];
+        let end = self
+            .line_starts
+            .get(line)
+            .map_or(self.source.len(), |x| x - 1);
+        self.source[start..end].trim_end_matches('\r')
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.line_starts.len()
+    }
+}
+
 // This is done because [std::iter::Step] is currently in nightly. When that reaches full release
 // this will be removed
 pub struct SourcePositionIterator<'position> {
@@ -0,0 +1,36 @@
+//! Suggestions for names which could not be found, based on how many edits it takes to turn one
+//! name into another
+
+use crate::symbol::Symbol;
+
+/// Counts the insertions, deletions and substitutions of single characters needed to turn `a`
+/// into `b`, which is the Levenshtein distance between them
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    let mut current = vec![0; b.len() + 1];
+    for (i, a_char) in a.chars().enumerate() {
+        current[0] = i + 1;
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(a_char != *b_char);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        std::mem::swap(&mut previous, &mut current);
+    }
+    previous[b.len()]
+}
+
+/// Finds the candidate closest to a name which could not be found, if any is close enough to
+/// probably be what was meant. A candidate may be a third of the name's length in edits away, and
+/// the earliest candidate wins a tie
+pub fn closest_match(name: Symbol, candidates: impl IntoIterator<Item = Symbol>) -> Option<Symbol> {
+    let name = name.as_str();
+    let limit = (name.chars().count() / 3).max(1);
+    candidates
+        .into_iter()
+        .filter(|x| x.as_str() != name)
+        .map(|x| (edit_distance(name, x.as_str()), x))
+        .filter(|(distance, _)| *distance <= limit)
+        .min_by_key(|(distance, _)| *distance)
+        .map(|(_, x)| x)
+}
@@ -0,0 +1,192 @@
+use std::{
+    collections::HashMap,
+    fmt::{Debug, Display},
+    sync::{Mutex, OnceLock},
+};
+
+/// An interned string. Identifiers and string literals are stored once in the global [Interner]
+/// and passed around as a [Symbol], which makes them [Copy] and turns comparisons into integer
+/// comparisons
+#[derive(Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Symbol(u32);
+
+impl Symbol {
+    /// Interns the provided string, returning the existing [Symbol] if it was already interned
+    pub fn intern(string: &str) -> Self {
+        with_interner(|interner| interner.intern(string))
+    }
+
+    /// Gets the string this [Symbol] was interned from
+    pub fn as_str(&self) -> &'static str {
+        with_interner(|interner| interner.get(*self))
+    }
+
+    /// Gets the raw index of this [Symbol] within the [Interner]
+    pub fn as_u32(&self) -> u32 {
+        self.0
+    }
+
+    /// Checks if this [Symbol] is one of the pre-interned keywords found in [kw]
+    pub fn is_keyword(&self) -> bool {
+        self.0 <= kw::YIELD.0
+    }
+}
+
+impl Debug for Symbol {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(formatter, "Symbol({:?})", self.as_str())
+    }
+}
+
+impl Display for Symbol {
+    fn fmt(&self, formatter: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(formatter, "{}", self.as_str())
+    }
+}
+
+impl From<&str> for Symbol {
+    fn from(value: &str) -> Self {
+        Symbol::intern(value)
+    }
+}
+
+/// Storage for every interned string. Strings are leaked so that [Symbol::as_str] can hand out a
+/// `&'static str`; since every unique string is only stored once this stays small in practice
+pub struct Interner {
+    symbols: HashMap<&'static str, Symbol>,
+    strings: Vec<&'static str>,
+}
+
+impl Interner {
+    /// Creates a new [Interner] with every pre-interned symbol already present
+    fn new() -> Self {
+        let mut interner = Self {
+            symbols: HashMap::with_capacity(PRE_INTERNED.len()),
+            strings: Vec::with_capacity(PRE_INTERNED.len()),
+        };
+        for string in PRE_INTERNED {
+            interner.insert(string);
+        }
+        interner
+    }
+
+    fn intern(&mut self, string: &str) -> Symbol {
+        if let Some(symbol) = self.symbols.get(string) {
+            return *symbol;
+        }
+        self.insert(Box::leak(string.to_owned().into_boxed_str()))
+    }
+
+    fn insert(&mut self, string: &'static str) -> Symbol {
+        let symbol = Symbol(self.strings.len() as u32);
+        self.strings.push(string);
+        self.symbols.insert(string, symbol);
+        symbol
+    }
+
+    fn get(&self, symbol: Symbol) -> &'static str {
+        self.strings[symbol.0 as usize]
+    }
+}
+
+fn with_interner<T>(function: impl FnOnce(&mut Interner) -> T) -> T {
+    static INTERNER: OnceLock<Mutex<Interner>> = OnceLock::new();
+    let mut interner = INTERNER
+        .get_or_init(|| Mutex::new(Interner::new()))
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner());
+    function(&mut interner)
+}
+
+/// Every string which is interned before anything else, in the order of the constants in [kw] and
+/// [sym]. Keep this in sync with them
+const PRE_INTERNED: &[&str] = &[
+    "const",
+    "else",
+    "enum",
+    "extern",
+    "for",
+    "fun",
+    "if",
+    "impl",
+    "in",
+    "let",
+    "mut",
+    "of",
+    "ptr",
+    "pub",
+    "ref",
+    "ret",
+    "trait",
+    "type",
+    "unsafe",
+    "use",
+    "when",
+    "where",
+    "yield",
+    "true",
+    "false",
+    "",
+    "self",
+    "Self",
+    "<closure>",
+    "std",
+    "std::Show",
+    "std::Option",
+    "std::Result",
+    "std::Vec",
+    "std::Map",
+];
+
+/// Pre-interned [Symbol]s for every keyword
+pub mod kw {
+    use super::Symbol;
+
+    pub const CONST: Symbol = Symbol(0);
+    pub const ELSE: Symbol = Symbol(1);
+    pub const ENUM: Symbol = Symbol(2);
+    pub const EXTERN: Symbol = Symbol(3);
+    pub const FOR: Symbol = Symbol(4);
+    pub const FUN: Symbol = Symbol(5);
+    pub const IF: Symbol = Symbol(6);
+    pub const IMPL: Symbol = Symbol(7);
+    pub const IN: Symbol = Symbol(8);
+    pub const LET: Symbol = Symbol(9);
+    pub const MUT: Symbol = Symbol(10);
+    pub const OF: Symbol = Symbol(11);
+    pub const PTR: Symbol = Symbol(12);
+    pub const PUB: Symbol = Symbol(13);
+    pub const REF: Symbol = Symbol(14);
+    pub const RET: Symbol = Symbol(15);
+    pub const TRAIT: Symbol = Symbol(16);
+    pub const TYPE: Symbol = Symbol(17);
+    pub const UNSAFE: Symbol = Symbol(18);
+    pub const USE: Symbol = Symbol(19);
+    pub const WHEN: Symbol = Symbol(20);
+    pub const WHERE: Symbol = Symbol(21);
+    pub const YIELD: Symbol = Symbol(22);
+}
+
+/// Pre-interned [Symbol]s for other commonly used strings
+pub mod sym {
+    use super::Symbol;
+
+    pub const TRUE: Symbol = Symbol(23);
+    pub const FALSE: Symbol = Symbol(24);
+    pub const EMPTY: Symbol = Symbol(25);
+    /// The name of the value a method is called on
+    pub const SELF: Symbol = Symbol(26);
+    /// The type a method is called on, within a `trait` or `impl`
+    pub const SELF_TYPE: Symbol = Symbol(27);
+    /// The name of every closure, which can never be written
+    pub const CLOSURE: Symbol = Symbol(28);
+    /// The path of the standard library, whose public items every module can use
+    pub const STD: Symbol = Symbol(29);
+    /// The items of the standard library which checking and the engines rely on, named the way
+    /// they are once the modules of a program are merged
+    pub const SHOW: Symbol = Symbol(30);
+    pub const OPTION: Symbol = Symbol(31);
+    pub const RESULT: Symbol = Symbol(32);
+    pub const VEC: Symbol = Symbol(33);
+    pub const MAP: Symbol = Symbol(34);
+}
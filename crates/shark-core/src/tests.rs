@@ -1,6 +1,11 @@
 use std::path::Path;
 
-use crate::source::SourcePosition;
+use crate::{
+    diagnostic::Diagnostic,
+    source::{LineIndex, SourcePosition, Span},
+    suggest::{closest_match, edit_distance},
+    symbol::{kw, sym, Symbol},
+};
 
 #[test]
 fn source_position_equality_test() {
@@ -35,3 +40,112 @@ fn source_position_iter_test() {
     }
     assert_eq!(count, 5)
 }
+
+#[test]
+fn symbol_interning_test() {
+    let symbol_one = Symbol::intern("shark");
+    let symbol_two = Symbol::intern("shark");
+    let symbol_three = Symbol::intern("sharks");
+
+    assert_eq!(symbol_one, symbol_two);
+    assert_ne!(symbol_one, symbol_three);
+    assert_eq!(symbol_one.as_str(), "shark");
+    assert_eq!(symbol_three.as_str(), "sharks");
+}
+
+#[test]
+fn symbol_pre_interned_test() {
+    assert_eq!(Symbol::intern("fun"), kw::FUN);
+    assert_eq!(Symbol::intern("yield"), kw::YIELD);
+    assert_eq!(Symbol::intern("true"), sym::TRUE);
+    assert_eq!(kw::WHERE.as_str(), "where");
+    assert_eq!(Symbol::intern("impl"), kw::IMPL);
+    assert_eq!(Symbol::intern("Self"), sym::SELF_TYPE);
+
+    assert!(kw::ELSE.is_keyword());
+    assert!(!sym::FALSE.is_keyword());
+    assert!(!sym::SELF.is_keyword());
+    assert!(!Symbol::intern("not_a_keyword").is_keyword());
+}
+
+#[test]
+fn span_test() {
+    let span_one = Span::new(2, 6);
+    let span_two = Span::new(10, 12);
+
+    assert_eq!(span_one.len(), 4);
+    assert_eq!(span_one.to(span_two), Span::new(2, 12));
+    assert!(span_one.contains(2));
+    assert!(!span_one.contains(6));
+    assert!(Span::new(3, 3).is_empty());
+}
+
+#[test]
+fn line_index_test() {
+    let line_index = LineIndex::new("fun\n  main\r\n\nλx");
+
+    assert_eq!(line_index.line_count(), 4);
+    assert_eq!(line_index.line_column(0), (1, 1));
+    assert_eq!(line_index.line_column(6), (2, 3));
+    assert_eq!(line_index.line_column(15), (4, 2));
+    assert_eq!(line_index.line_text(2), "  main");
+    assert_eq!(line_index.offset(1, 2), 6);
+    assert_eq!(line_index.offset(3, 1), 15);
+}
+
+#[test]
+fn diagnostic_render_test() {
+    let source = "fun main() {\n    let a = ;\n}";
+    let diagnostic = Diagnostic::error("expected an expression, found `;`")
+        .with_primary(Span::new(25, 26), "expected an expression")
+        .with_secondary(Span::new(21, 22), "while parsing this")
+        .with_note("a let needs a value after its `=`");
+
+    assert_eq!(
+        diagnostic.render(Some(Path::new("main.shark")), source),
+        "error: expected an expression, found `;`
+ --> main.shark:2:13
+  |
+2 |     let a = ;
+  |         -   ^ expected an expression
+  |         |
+  |         while parsing this
+  = note: a let needs a value after its `=`
+"
+    );
+
+    let source = "fun add(a :: Int32) :: Int32 {\n    a + b + c\n}";
+    let diagnostic = Diagnostic::error("mismatched types")
+        .with_secondary(Span::new(35, 36), "this is `Int32`")
+        .with_secondary(Span::new(39, 40), "")
+        .with_primary(Span::new(43, 44), "this is `Bool`")
+        .with_secondary(Span::new(13, 18), "expected because of this");
+    assert_eq!(
+        diagnostic.render(None, source),
+        "error: mismatched types
+ --> unknown:2:13
+  |
+1 | fun add(a :: Int32) :: Int32 {
+  |              ----- expected because of this
+2 |     a + b + c
+  |     -   -   ^ this is `Bool`
+  |     |
+  |     this is `Int32`
+"
+    );
+}
+
+#[test]
+fn suggestion_test() {
+    assert_eq!(edit_distance("kitten", "sitting"), 3);
+    assert_eq!(edit_distance("", "abc"), 3);
+    assert_eq!(edit_distance("same", "same"), 0);
+
+    let candidates = ["count", "counter", "amount"].map(Symbol::intern);
+    assert_eq!(
+        closest_match(Symbol::intern("cout"), candidates),
+        Some(Symbol::intern("count"))
+    );
+    assert_eq!(closest_match(Symbol::intern("xyz"), candidates), None);
+    assert_eq!(closest_match(Symbol::intern("count"), candidates), None);
+}
@@ -0,0 +1,90 @@
+//! The settings of the formatter, read from a `sharkfmt.toml` file. Only the subset of TOML the
+//! settings need is understood: one `key = value` pair per line, where every value is a number,
+//! and comments starting with `#`
+
+use std::path::{Path, PathBuf};
+
+use shark_core::{diagnostic::Diagnostic, source::Span};
+
+/// The name of the file the settings are read from, found by searching the directory of the file
+/// being formatted and then every directory above it
+pub const CONFIG_FILE: &str = "sharkfmt.toml";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Config {
+    /// How many columns a line can take up before the lists on it are split over several lines
+    pub width: usize,
+    /// How many spaces each level of indentation is
+    pub indent: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            width: 100,
+            indent: 4,
+        }
+    }
+}
+
+impl Config {
+    /// Reads the settings from the text of a `sharkfmt.toml` file. Settings which are not given
+    /// keep their default
+    pub fn parse(text: &str) -> Result<Self, Diagnostic> {
+        let mut config = Self::default();
+        let mut offset = 0;
+        for line in text.split_inclusive('\n') {
+            let start = offset;
+            offset += line.len();
+            let line = match line.find('#') {
+                Some(comment) => &line[..comment],
+                None => line,
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            let line_span = Span::new(start, start + line.trim_end().len());
+            let Some((key, value)) = line.split_once('=') else {
+                return Err(Diagnostic::error("expected a setting")
+                    .with_primary(line_span, "expected `key = value`"));
+            };
+            let value_start = start + key.len() + 1 + (value.len() - value.trim_start().len());
+            let value_span = Span::new(value_start, value_start + value.trim().len());
+            let value = match value.trim().parse::<usize>() {
+                Ok(value) if value > 0 => value,
+                _ => {
+                    return Err(Diagnostic::error("invalid value")
+                        .with_primary(value_span, "expected a positive number"))
+                }
+            };
+            match key.trim() {
+                "width" => config.width = value,
+                "indent" => config.indent = value,
+                key => {
+                    let key_start = start + (line.len() - line.trim_start().len());
+                    return Err(Diagnostic::error(format!("unknown setting `{}`", key))
+                        .with_primary(
+                            Span::new(key_start, key_start + key.len()),
+                            "expected `width` or `indent`",
+                        ));
+                }
+            }
+        }
+        Ok(config)
+    }
+
+    /// Finds the `sharkfmt.toml` which applies to a file, searching from the directory it is in
+    /// upwards
+    pub fn find(path: &Path) -> Option<PathBuf> {
+        let directory = path.parent()?;
+        let directory = match directory.as_os_str().is_empty() {
+            true => Path::new("."),
+            false => directory,
+        };
+        let directory = directory.canonicalize().ok()?;
+        directory
+            .ancestors()
+            .map(|x| x.join(CONFIG_FILE))
+            .find(|x| x.is_file())
+    }
+}
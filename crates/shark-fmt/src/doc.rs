@@ -0,0 +1,142 @@
+//! The layout a file is formatted into before it is printed. A [Doc] says where lines may be broken
+//! rather than where they are, and the printer breaks a [Doc::Group] only when it does not fit
+//! within the width of the line, in the style of Wadler's "A prettier printer"
+
+use crate::config::Config;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Doc {
+    Text(Box<str>),
+    /// A single space, which is left out at the start of a line
+    Space,
+    /// A space, or a new line if the group it is in is broken
+    Line,
+    /// Nothing, or a new line if the group it is in is broken
+    SoftLine,
+    /// Always a new line, which breaks every group around it
+    HardLine,
+    /// A new line with an empty line before it
+    BlankLine,
+    /// Indents the lines which start within it by one level
+    Indent(Vec<Doc>),
+    /// Printed on one line if it fits, otherwise with every [Doc::Line] and [Doc::SoftLine] which
+    /// is directly within it broken
+    Group(Vec<Doc>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Mode {
+    Flat,
+    Break,
+}
+
+/// Prints the [Doc]s, ending the text with a single new line
+pub fn print(docs: &[Doc], config: &Config) -> String {
+    let mut printer = Printer {
+        result: String::new(),
+        column: 0,
+        pending_lines: 0,
+        pending_space: false,
+    };
+    let mut stack: Vec<(usize, Mode, &Doc)> =
+        docs.iter().rev().map(|x| (0, Mode::Break, x)).collect();
+    while let Some((indent, mode, doc)) = stack.pop() {
+        match doc {
+            Doc::Text(text) => printer.text(text, indent),
+            Doc::Space => printer.pending_space = true,
+            Doc::Line if mode == Mode::Flat => printer.pending_space = true,
+            Doc::SoftLine if mode == Mode::Flat => {}
+            Doc::Line | Doc::SoftLine | Doc::HardLine => printer.new_lines(1),
+            Doc::BlankLine => printer.new_lines(2),
+            Doc::Indent(docs) => {
+                let indent = indent + config.indent;
+                stack.extend(docs.iter().rev().map(|x| (indent, mode, x)));
+            }
+            Doc::Group(docs) => {
+                let mode = match mode {
+                    Mode::Flat => Mode::Flat,
+                    Mode::Break => {
+                        let column = match printer.pending_lines {
+                            0 => printer.column + usize::from(printer.pending_space),
+                            _ => indent,
+                        };
+                        let remaining = config.width as isize - column as isize;
+                        match fits(docs, &stack, remaining) {
+                            true => Mode::Flat,
+                            false => Mode::Break,
+                        }
+                    }
+                };
+                stack.extend(docs.iter().rev().map(|x| (indent, mode, x)));
+            }
+        }
+    }
+    printer.result.push('\n');
+    printer.result
+}
+
+/// Checks if the [Doc]s fit within the remaining width when printed flat, along with whatever
+/// follows them up to the next place a line is broken
+fn fits(docs: &[Doc], rest: &[(usize, Mode, &Doc)], mut remaining: isize) -> bool {
+    let mut stack: Vec<(Mode, &Doc)> = docs.iter().rev().map(|x| (Mode::Flat, x)).collect();
+    let mut rest = rest.iter().rev();
+    loop {
+        let (mode, doc) = match stack.pop() {
+            Some(next) => next,
+            None => match rest.next() {
+                Some(&(_, mode, doc)) => (mode, doc),
+                None => return true,
+            },
+        };
+        match doc {
+            Doc::Text(text) if text.contains('\n') => return false,
+            Doc::Text(text) => remaining -= text.chars().count() as isize,
+            Doc::Space => remaining -= 1,
+            Doc::Line if mode == Mode::Flat => remaining -= 1,
+            Doc::SoftLine if mode == Mode::Flat => {}
+            Doc::HardLine | Doc::BlankLine if mode == Mode::Flat => return false,
+            Doc::Line | Doc::SoftLine | Doc::HardLine | Doc::BlankLine => return true,
+            Doc::Indent(docs) | Doc::Group(docs) => {
+                stack.extend(docs.iter().rev().map(|x| (mode, x)));
+            }
+        }
+        if remaining < 0 {
+            return false;
+        }
+    }
+}
+
+struct Printer {
+    result: String,
+    column: usize,
+    /// How many new lines to start before the next text, so that breaks next to each other are
+    /// merged rather than leaving empty lines
+    pending_lines: usize,
+    pending_space: bool,
+}
+
+impl Printer {
+    fn new_lines(&mut self, count: usize) {
+        self.pending_lines = self.pending_lines.max(count);
+    }
+
+    fn text(&mut self, text: &str, indent: usize) {
+        if self.pending_lines > 0 && !self.result.is_empty() {
+            for _ in 0..self.pending_lines {
+                self.result.push('\n');
+            }
+            self.result.push_str(&" ".repeat(indent));
+            self.column = indent;
+        } else if self.pending_space && self.column > 0 {
+            self.result.push(' ');
+            self.column += 1;
+        }
+        self.pending_lines = 0;
+        self.pending_space = false;
+        self.result.push_str(text);
+        match text.rfind('\n') {
+            Some(line_start) => self.column = text[line_start + 1..].chars().count(),
+            None => self.column += text.chars().count(),
+        }
+    }
+}
@@ -0,0 +1,394 @@
+//! Turns the concrete syntax tree of a file into the [Doc]s it is printed from. Spacing is decided
+//! one pair of tokens at a time from their [TokenKind]s and the nodes they are in, while the nodes
+//! which hold lists and the chains of binary operators decide where lines are broken
+//!
+//! Only whitespace is ever added or removed. Where two tokens written next to each other would be
+//! lexed differently, such as `-` followed by `1`, they are kept apart by a space
+
+use shark_lex::{
+    token::{CommentKind, TokenKind},
+    Lexer,
+};
+use shark_parse::{
+    ast::BinaryOperator,
+    cst::{NodeKind, SyntaxElement, SyntaxNode, SyntaxToken},
+};
+
+use crate::doc::Doc;
+
+/// Lays out a whole file
+pub fn layout(root: &SyntaxNode) -> Vec<Doc> {
+    let mut layout = Layout {
+        previous: None,
+        newlines: 0,
+        comment: None,
+        comment_starts_line: false,
+        pending: None,
+    };
+    let mut docs = Vec::new();
+    layout.node(root, &mut docs);
+    docs
+}
+
+/// A break in the line which has been asked for before the next token
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Break {
+    /// A [Doc::SoftLine]
+    Soft,
+    /// A [Doc::Line]
+    Line,
+    /// A new line before a closing brace
+    Hard,
+    /// A new line before an item or statement, which keeps one empty line if the source had any
+    Item,
+}
+
+/// The last token which was laid out which is not trivia
+struct Previous {
+    kind: TokenKind,
+    parent: NodeKind,
+    text: Box<str>,
+}
+
+struct Layout {
+    previous: Option<Previous>,
+    /// How many new lines the whitespace since the previous token or comment had
+    newlines: usize,
+    /// The kind of comment which was laid out last, if nothing but whitespace came after it
+    comment: Option<CommentKind>,
+    /// Whether that comment was the first thing on its line
+    comment_starts_line: bool,
+    pending: Option<Break>,
+}
+
+impl Layout {
+    fn node(&mut self, node: &SyntaxNode, out: &mut Vec<Doc>) {
+        match node.kind() {
+            NodeKind::SourceFile => {
+                for child in node.children_with_tokens() {
+                    if let SyntaxElement::Node(_) = child {
+                        self.pending = Some(Break::Item);
+                    }
+                    self.element(&child, out);
+                }
+            }
+            NodeKind::Block
+            | NodeKind::FieldList
+            | NodeKind::VariantList
+            | NodeKind::MethodList
+            | NodeKind::WhenArmList => self.broken_list(node, out),
+            NodeKind::ParameterList
+            | NodeKind::ArgumentList
+            | NodeKind::TupleExpr
+            | NodeKind::ArrayExpr
+            | NodeKind::TuplePattern
+            | NodeKind::PatternList
+            | NodeKind::UseGroup
+            | NodeKind::Variant => self.list(node, Break::Soft, out),
+            NodeKind::FieldInitList => self.list(node, Break::Line, out),
+            NodeKind::BinaryExpr => self.binary(node, out),
+            _ => self.node_children(node, out),
+        }
+    }
+
+    fn element(&mut self, element: &SyntaxElement, out: &mut Vec<Doc>) {
+        match element {
+            SyntaxElement::Node(node) => self.node(node, out),
+            SyntaxElement::Token(token) => self.token(token, out),
+        }
+    }
+
+    /// Splits the children of a node around its first opening and last closing bracket
+    fn brackets(node: &SyntaxNode) -> Option<(Vec<SyntaxElement>, usize, usize)> {
+        let children = node.children_with_tokens();
+        let is_token = |x: &SyntaxElement, kind: fn(bool) -> TokenKind, opened: bool| matches!(x, SyntaxElement::Token(token) if token.kind() == kind(opened));
+        let bracket = |x: &SyntaxElement, opened| {
+            is_token(x, |opened| TokenKind::CurlyBrace { opened }, opened)
+                || is_token(x, |opened| TokenKind::Parenthesis { opened }, opened)
+                || is_token(x, |opened| TokenKind::SquareBracket { opened }, opened)
+        };
+        let open = children.iter().position(|x| bracket(x, true))?;
+        let close = children.iter().rposition(|x| bracket(x, false))?;
+        Some((children, open, close))
+    }
+
+    /// Lays out a list which is always written one item per line, such as the statements of a
+    /// block
+    fn broken_list(&mut self, node: &SyntaxNode, out: &mut Vec<Doc>) {
+        let Some((children, open, close)) = Self::brackets(node) else {
+            return self.node_children(node, out);
+        };
+        for child in &children[..=open] {
+            self.element(child, out);
+        }
+        let mut inner = Vec::new();
+        for child in &children[open + 1..close] {
+            if let SyntaxElement::Node(_) = child {
+                self.pending = Some(Break::Item);
+            }
+            self.element(child, &mut inner);
+        }
+        self.pending = None;
+        if !inner.is_empty() {
+            out.push(Doc::Indent(inner));
+            self.pending = Some(Break::Hard);
+        }
+        for child in &children[close..] {
+            self.element(child, out);
+        }
+    }
+
+    /// Lays out a list which is written on one line if it fits, or otherwise with one item per
+    /// line. `edge` is the break after the opening and before the closing bracket
+    fn list(&mut self, node: &SyntaxNode, edge: Break, out: &mut Vec<Doc>) {
+        let Some((children, open, close)) = Self::brackets(node) else {
+            return self.node_children(node, out);
+        };
+        for child in &children[..open] {
+            self.element(child, out);
+        }
+        let mut group = Vec::new();
+        self.element(&children[open], &mut group);
+        self.pending = Some(edge);
+        let mut inner = Vec::new();
+        for child in &children[open + 1..close] {
+            self.element(child, &mut inner);
+            if let SyntaxElement::Token(token) = child {
+                if token.kind() == TokenKind::Comma {
+                    self.pending = Some(Break::Line);
+                }
+            }
+        }
+        self.pending = None;
+        if !inner.is_empty() {
+            group.push(Doc::Indent(inner));
+            self.pending = Some(edge);
+        }
+        self.element(&children[close], &mut group);
+        out.push(Doc::Group(group));
+        for child in &children[close + 1..] {
+            self.element(child, out);
+        }
+    }
+
+    /// Lays out a chain of binary operators of the same precedence, such as `a + b - c`, which is
+    /// written on one line if it fits, or otherwise with a line broken before every operator
+    fn binary(&mut self, node: &SyntaxNode, out: &mut Vec<Doc>) {
+        let Some(precedence) = precedence(node) else {
+            return self.node_children(node, out);
+        };
+        // The group starts at the first operator, so that whatever is pending before the first
+        // operand stays out of it
+        let mut inner = Vec::new();
+        for child in chain(node, precedence) {
+            let is_operator = matches!(&child, SyntaxElement::Token(token) if is_operator(token));
+            if is_operator {
+                self.pending = Some(Break::Line);
+            }
+            match inner.is_empty() && !is_operator {
+                true => self.element(&child, out),
+                false => self.element(&child, &mut inner),
+            }
+        }
+        out.push(Doc::Group(vec![Doc::Indent(inner)]));
+    }
+
+    fn node_children(&mut self, node: &SyntaxNode, out: &mut Vec<Doc>) {
+        for child in node.children_with_tokens() {
+            self.element(&child, out);
+        }
+    }
+
+    fn token(&mut self, token: &SyntaxToken, out: &mut Vec<Doc>) {
+        match token.kind() {
+            TokenKind::Whitespace => self.newlines += token.text().matches('\n').count(),
+            TokenKind::Comment(kind) => {
+                let starts_line = self.newlines > 0 || self.previous.is_none();
+                if self.previous.is_some() || self.comment.is_some() {
+                    match self.newlines {
+                        0 => out.push(Doc::Space),
+                        _ if self.keeps_blank_line() => out.push(Doc::BlankLine),
+                        _ => out.push(Doc::HardLine),
+                    }
+                }
+                let text = match kind {
+                    CommentKind::SingleLine => token.text().trim_end(),
+                    CommentKind::MultiLine => token.text(),
+                };
+                out.push(Doc::Text(text.into()));
+                self.comment = Some(kind);
+                self.comment_starts_line = starts_line;
+                self.newlines = 0;
+            }
+            kind => {
+                let parent = token.parent().kind();
+                self.separator(kind, parent, token.text(), out);
+                out.push(Doc::Text(token.text().into()));
+                self.previous = Some(Previous {
+                    kind,
+                    parent,
+                    text: token.text().into(),
+                });
+                self.comment = None;
+                self.newlines = 0;
+            }
+        }
+    }
+
+    /// Checks if an empty line from the source should be kept before what comes next
+    fn keeps_blank_line(&self) -> bool {
+        let after_opening = matches!(
+            self.previous.as_ref().map(|x| x.kind),
+            Some(TokenKind::CurlyBrace { opened: true } | TokenKind::Parenthesis { opened: true })
+        );
+        self.newlines >= 2 && (self.comment.is_some() || !after_opening)
+    }
+
+    /// Adds whatever goes between the previous token and the next one
+    fn separator(&mut self, kind: TokenKind, parent: NodeKind, text: &str, out: &mut Vec<Doc>) {
+        let pending = self.pending.take();
+        let line = match self.keeps_blank_line() {
+            true => Doc::BlankLine,
+            false => Doc::HardLine,
+        };
+        match self.comment {
+            // A comment which ends its line, or is followed by one, keeps what follows it off
+            // its line
+            Some(_) if self.newlines > 0 && pending == Some(Break::Hard) => {
+                return out.push(Doc::HardLine)
+            }
+            Some(CommentKind::SingleLine) => return out.push(line),
+            Some(CommentKind::MultiLine) if self.newlines > 0 => return out.push(line),
+            // A comment which starts the line of the item or statement after it stays with it
+            Some(CommentKind::MultiLine)
+                if self.comment_starts_line
+                    && matches!(pending, Some(Break::Item | Break::Hard)) =>
+            {
+                return out.push(Doc::Space)
+            }
+            Some(CommentKind::MultiLine) => out.push(Doc::Space),
+            None => {}
+        }
+        match pending {
+            Some(Break::Soft) => out.push(Doc::SoftLine),
+            Some(Break::Line) => out.push(Doc::Line),
+            Some(Break::Hard) => out.push(Doc::HardLine),
+            Some(Break::Item) => out.push(line),
+            None if self.comment.is_some() => {}
+            None => {
+                let Some(previous) = &self.previous else {
+                    return;
+                };
+                if needs_space(previous, kind, parent) || !can_join(previous, kind, text) {
+                    out.push(Doc::Space);
+                }
+            }
+        }
+    }
+}
+
+fn is_operator(token: &SyntaxToken) -> bool {
+    BinaryOperator::from_token(&token.kind()).is_some()
+}
+
+/// Gets the precedence of the operator of a binary expression. A `-` which the lexer glued onto
+/// the number after it, as in `x -1`, is not a token of its own, and so has none
+fn precedence(node: &SyntaxNode) -> Option<u8> {
+    node.child_tokens()
+        .iter()
+        .find_map(|x| BinaryOperator::from_token(&x.kind()))
+        .map(|x| x.precedence())
+}
+
+/// Gets the operands and operators of a binary expression, along with those of the expressions on
+/// their left which have operators of the same precedence
+fn chain(node: &SyntaxNode, precedence: u8) -> Vec<SyntaxElement> {
+    let mut elements = Vec::new();
+    for child in node.children_with_tokens() {
+        match child {
+            SyntaxElement::Node(left)
+                if elements.is_empty()
+                    && left.kind() == NodeKind::BinaryExpr
+                    && self::precedence(&left) == Some(precedence) =>
+            {
+                elements.extend(chain(&left, precedence));
+            }
+            child => elements.push(child),
+        }
+    }
+    elements
+}
+
+/// Whether a `::` is between a name and its type, as in `x :: Int32`, rather than within a path
+/// such as `Option::None`
+fn is_annotation(parent: NodeKind) -> bool {
+    matches!(
+        parent,
+        NodeKind::Parameter
+            | NodeKind::FieldDecl
+            | NodeKind::LetStatement
+            | NodeKind::Function
+            | NodeKind::FunctionType
+            | NodeKind::ClosureExpr
+            | NodeKind::ConstDecl
+            | NodeKind::GenericParam
+            | NodeKind::WherePredicate
+    )
+}
+
+fn is_type_list(parent: NodeKind) -> bool {
+    matches!(
+        parent,
+        NodeKind::GenericParamList | NodeKind::TypeArgumentList
+    )
+}
+
+/// Decides if a space goes between two tokens on the same line
+fn needs_space(previous: &Previous, kind: TokenKind, parent: NodeKind) -> bool {
+    match (previous.kind, kind) {
+        (TokenKind::Parenthesis { opened: true }, _)
+        | (_, TokenKind::Parenthesis { opened: false })
+        | (TokenKind::SquareBracket { opened: true }, _)
+        | (_, TokenKind::SquareBracket { opened: false })
+        | (_, TokenKind::Comma | TokenKind::EOL)
+        | (TokenKind::CurlyBrace { opened: true }, TokenKind::CurlyBrace { opened: false })
+        | (TokenKind::Dot, _)
+        | (_, TokenKind::Dot) => false,
+        (TokenKind::TypeAssign, _) => is_annotation(previous.parent),
+        (_, TokenKind::TypeAssign) => is_annotation(parent),
+        (_, TokenKind::Lesser | TokenKind::Greater | TokenKind::ShiftRight)
+            if is_type_list(parent) =>
+        {
+            false
+        }
+        (TokenKind::Lesser, _) if is_type_list(previous.parent) => false,
+        (_, TokenKind::Parenthesis { opened: true }) => !matches!(
+            parent,
+            NodeKind::ParameterList
+                | NodeKind::ArgumentList
+                | NodeKind::PatternList
+                | NodeKind::Variant
+                | NodeKind::FunctionType
+        ),
+        (TokenKind::DotDot | TokenKind::DotDotEqual, _)
+            if previous.parent == NodeKind::RangePattern =>
+        {
+            false
+        }
+        (_, TokenKind::DotDot | TokenKind::DotDotEqual) if parent == NodeKind::RangePattern => {
+            false
+        }
+        // The operator of a unary expression is its only token, the operand is a node
+        _ => previous.parent != NodeKind::UnaryExpr,
+    }
+}
+
+/// Checks that two tokens written next to each other are still lexed as the same two tokens
+fn can_join(previous: &Previous, kind: TokenKind, text: &str) -> bool {
+    let joined = format!("{}{}", previous.text, text);
+    let mut lexer = Lexer::new(None, &joined);
+    lexer.keep_trivia = true;
+    lexer.lex();
+    let kinds: Vec<TokenKind> = lexer.completed_tokens.iter().map(|x| x.kind).collect();
+    lexer.errors.is_empty() && kinds == [previous.kind, kind]
+}
@@ -0,0 +1,63 @@
+//! Formats Shark source code, for `sharkc fmt`. The formatter is opinionated, so the layout of a
+//! file only depends on its tokens, its comments and where it has empty lines:
+//!
+//! - items, statements, fields, variants and the arms of a `when` each start a new line, indented
+//!   by the braces around them, with at most one empty line kept between them
+//! - lists in brackets, such as the arguments of a call, stay on one line if they fit within the
+//!   width, and are otherwise split with one item per line
+//! - binary operators, `=`, `=>` and the `::` before a type have a space on either side, while the
+//!   `::` of a path, `.` and the operators of unary expressions have none
+//! - comments are kept where they are, either at the end of a line or on their own
+//!
+//! Formatting only ever changes whitespace, which [format] checks by lexing the result again
+
+use std::path::Path;
+
+use shark_core::diagnostic::Diagnostic;
+use shark_lex::{token::TokenKind, Lexer};
+
+pub mod config;
+pub mod doc;
+pub mod layout;
+
+#[cfg(test)]
+pub mod tests;
+
+pub use config::Config;
+
+/// Formats a file, failing if it has syntax errors
+pub fn format(
+    path: Option<&Path>,
+    source: &str,
+    config: &Config,
+) -> Result<String, Vec<Diagnostic>> {
+    let (_, syntax, diagnostics) = shark_parse::parse_syntax(path, source);
+    if diagnostics.iter().any(Diagnostic::is_error) {
+        return Err(diagnostics);
+    }
+    let docs = layout::layout(&syntax);
+    let formatted = doc::print(&docs, config);
+    if tokens(&formatted) != tokens(source) {
+        return Err(vec![Diagnostic::error(
+            "formatting the file would change its tokens, which is a bug in the formatter",
+        )]);
+    }
+    Ok(formatted)
+}
+
+/// Lexes the source, returning the kind of every token which is not whitespace along with the text
+/// of every comment
+pub fn tokens(source: &str) -> Vec<(TokenKind, Option<&str>)> {
+    let mut lexer = Lexer::new(None, source);
+    lexer.keep_trivia = true;
+    lexer.lex();
+    lexer
+        .completed_tokens
+        .iter()
+        .filter(|x| x.kind != TokenKind::Whitespace)
+        .map(|x| match x.kind {
+            TokenKind::Comment(_) => (x.kind, Some(x.text.trim_end())),
+            _ => (x.kind, None),
+        })
+        .collect()
+}
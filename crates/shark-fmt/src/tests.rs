@@ -0,0 +1,299 @@
+use std::path::Path;
+
+use shark_lex::{token::TokenKind, Lexer};
+
+use crate::{format, Config};
+
+/// Formats the source with the default settings, checking that formatting it again changes nothing
+fn formatted(source: &str) -> String {
+    formatted_with(source, &Config::default())
+}
+
+fn formatted_with(source: &str, config: &Config) -> String {
+    let result = format(None, source, config).expect("failed to format the source");
+    let again = format(None, &result, config).expect("failed to format the formatted source");
+    assert_eq!(result, again, "formatting is not idempotent");
+    result
+}
+
+/// Lexes the source, leaving out whitespace and comments
+fn token_kinds(source: &str) -> Vec<TokenKind> {
+    let mut lexer = Lexer::new(None, source);
+    lexer.lex();
+    assert!(lexer.errors.is_empty(), "{:?}", lexer.errors);
+    lexer.completed_tokens.iter().map(|x| x.kind).collect()
+}
+
+#[test]
+fn test_format() {
+    let result = formatted(
+        "use a::b::{c,d};
+        const N::Int32=-1;
+        pub type Point<T::Show> where T::Show{x::T,y::Int32,}
+        enum E{A(Int32,Str),B}
+        trait Tr{fun f(self::ref Self)::Int32;}
+        impl<T> Tr for Vec<Map<Str,T>>{fun f(self::ref Self)::Int32{ret 1;}}
+        unsafe fun g(p::ptr mut Int32,...)::yield Int32{
+        let mut x::Int32=-a+!b* *p;
+        let r=ref mut x;
+          x=x-1;x+=2;
+            let q=Point{x=1,y=(2,3)};
+        let c=if a<b{a}else{b};
+        for i in xs{yield i;}
+        when x{1..=3=>0,E::A(z,_)if z>1=>{1}_=>2,}
+        unsafe{*p=3};
+        a.b.c(1,(2,),()).d
+        }",
+    );
+    assert_eq!(
+        result,
+        "use a::b::{c, d};
+const N :: Int32 = -1;
+pub type Point<T :: Show> where T :: Show {
+    x :: T,
+    y :: Int32,
+}
+enum E {
+    A(Int32, Str),
+    B
+}
+trait Tr {
+    fun f(self :: ref Self) :: Int32;
+}
+impl<T> Tr for Vec<Map<Str, T>> {
+    fun f(self :: ref Self) :: Int32 {
+        ret 1;
+    }
+}
+unsafe fun g(p :: ptr mut Int32, ...) :: yield Int32 {
+    let mut x :: Int32 = -a + !b * *p;
+    let r = ref mut x;
+    x = x -1;
+    x += 2;
+    let q = Point { x = 1, y = (2, 3) };
+    let c = if a < b {
+        a
+    } else {
+        b
+    };
+    for i in xs {
+        yield i;
+    }
+    when x {
+        1..=3 => 0,
+        E::A(z, _) if z > 1 => {
+            1
+        }
+        _ => 2,
+    }
+    unsafe {
+        *p = 3
+    };
+    a.b.c(1, (2,), ()).d
+}
+"
+    );
+}
+
+#[test]
+fn test_comments() {
+    let result = formatted(
+        "// The first line
+
+
+/* lead */ fun f(a :: Int32, // first
+    b :: Int32) {
+        foo( // why
+            1, /* one */ 2);
+    /* a
+       multi-line comment */
+    x; /* after */ y;
+
+
+    /* before */ z;
+
+    // dangling
+
+}
+// at the end   ",
+    );
+    assert_eq!(
+        result,
+        "// The first line
+
+/* lead */ fun f(
+    a :: Int32, // first
+    b :: Int32
+) {
+    foo( // why
+        1, /* one */
+        2
+    );
+    /* a
+       multi-line comment */
+    x; /* after */
+    y;
+
+    /* before */ z;
+
+    // dangling
+}
+// at the end
+"
+    );
+}
+
+#[test]
+fn test_width() {
+    let source = "fun area(width :: Float64, height :: Float64) :: Float64 { width * height }";
+    let config = Config {
+        width: 40,
+        indent: 2,
+    };
+    assert_eq!(
+        formatted_with(source, &config),
+        "fun area(
+  width :: Float64,
+  height :: Float64
+) :: Float64 {
+  width * height
+}
+"
+    );
+    assert_eq!(
+        formatted_with(
+            "fun main() { print(\"{} {}\", first(1, 2), second(3)); }",
+            &config
+        ),
+        "fun main() {
+  print(
+    \"{} {}\",
+    first(1, 2),
+    second(3)
+  );
+}
+"
+    );
+    assert_eq!(
+        formatted_with(
+            "fun f() :: Bool { let total = first + second * factor - third(1); total > 0 && total < 100 }",
+            &config
+        ),
+        "fun f() :: Bool {
+  let total = first
+    + second * factor
+    - third(1);
+  total > 0 && total < 100
+}
+"
+    );
+    let config = Config {
+        width: 24,
+        indent: 2,
+    };
+    assert_eq!(
+        formatted_with(
+            "fun f() :: Bool { (first + second) * factor > 0 && ready }",
+            &config
+        ),
+        "fun f() :: Bool {
+  (first + second)
+    * factor > 0
+    && ready
+}
+"
+    );
+}
+
+/// Formatting must never change the tokens of a file, only the whitespace between them
+#[test]
+fn test_tokens_unchanged() {
+    let directory = Path::new(env!("CARGO_MANIFEST_DIR")).join("..");
+    let mut sources = vec![
+        std::fs::read_to_string(directory.join("shark-std/src/std.shark"))
+            .expect("failed to read std.shark"),
+    ];
+    let programs = std::fs::read_dir(directory.join("shark-testing/tests/programs"))
+        .expect("failed to read the programs of the suite");
+    for entry in programs {
+        let path = entry.expect("failed to read a program").path();
+        if path.extension().is_some_and(|x| x == "shark") {
+            sources.push(std::fs::read_to_string(path).expect("failed to read a program"));
+        }
+    }
+    // Tokens which would be lexed differently if they were written next to each other
+    sources.push(
+        "fun f() { a - - 1; x = y -1; when r { -1 .. 2 => 0, _ => 1, }; ret - -x; }".to_string(),
+    );
+
+    let narrow = Config {
+        width: 20,
+        indent: 3,
+    };
+    for source in &sources {
+        for config in [Config::default(), narrow] {
+            let result = formatted_with(source, &config);
+            assert_eq!(token_kinds(&result), token_kinds(source), "{}", result);
+        }
+    }
+}
+
+#[test]
+fn test_syntax_errors() {
+    let errors = format(None, "fun main() { let x = ; }", &Config::default()).unwrap_err();
+    assert_eq!(errors[0].message, "expected an expression, found `;`");
+}
+
+#[test]
+fn test_config() {
+    let config = Config::parse("# Settings\nwidth = 80 # columns\n\nindent=2\n").unwrap();
+    assert_eq!(
+        config,
+        Config {
+            width: 80,
+            indent: 2
+        }
+    );
+    assert_eq!(Config::parse("").unwrap(), Config::default());
+
+    let error = Config::parse("width = 80\ntabs = 4").unwrap_err();
+    assert_eq!(error.message, "unknown setting `tabs`");
+    assert_eq!(
+        error.primary_span().map(|x| (x.start, x.end)),
+        Some((11, 15))
+    );
+    let error = Config::parse("indent = four").unwrap_err();
+    assert_eq!(error.message, "invalid value");
+    assert_eq!(
+        error.primary_span().map(|x| (x.start, x.end)),
+        Some((9, 13))
+    );
+    let error = Config::parse("width").unwrap_err();
+    assert_eq!(error.message, "expected a setting");
+}
+
+#[test]
+fn test_arrays() {
+    assert_eq!(
+        formatted("fun f(a::[Int64;N])::[Bool ;3]{let b=[ 1,2 ,3];[true;3]}"),
+        "fun f(a :: [Int64; N]) :: [Bool; 3] {
+    let b = [1, 2, 3];
+    [true; 3]
+}
+"
+    );
+    let config = Config {
+        width: 20,
+        indent: 2,
+    };
+    assert_eq!(
+        formatted_with("fun f() { let names = [first, second]; }", &config),
+        "fun f() {
+  let names = [
+    first,
+    second
+  ];
+}
+"
+    );
+}
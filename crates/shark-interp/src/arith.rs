@@ -0,0 +1,317 @@
+//! Arithmetic on the values of primitive types. What integer arithmetic does when a result does
+//! not fit in its type depends on the [Overflow] the program was built with, see
+//! [shark_sema::numeric]
+
+use shark_parse::ast::BinaryOperator;
+use shark_sema::{
+    numeric::{self, Intrinsic, Number, Overflow},
+    ty::PrimitiveType,
+};
+
+use crate::value::Value;
+
+/// Why an operation on integers failed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ArithError {
+    pub message: &'static str,
+    pub label: String,
+}
+
+impl ArithError {
+    fn overflow(message: &'static str, ty: &str) -> Self {
+        Self {
+            message,
+            label: format!("the result does not fit in `{}`", ty),
+        }
+    }
+}
+
+trait Integer: Copy + Sized {
+    const NAME: &'static str;
+    const ZERO: Self;
+    fn checked_add(self, other: Self) -> Option<Self>;
+    fn checked_sub(self, other: Self) -> Option<Self>;
+    fn checked_mul(self, other: Self) -> Option<Self>;
+    fn checked_div(self, other: Self) -> Option<Self>;
+    fn wrapping_add(self, other: Self) -> Self;
+    fn wrapping_sub(self, other: Self) -> Self;
+    fn wrapping_mul(self, other: Self) -> Self;
+    fn wrapping_div(self, other: Self) -> Self;
+    fn saturating_add(self, other: Self) -> Self;
+    fn saturating_sub(self, other: Self) -> Self;
+    fn saturating_mul(self, other: Self) -> Self;
+    fn saturating_div(self, other: Self) -> Self;
+    fn checked_shl(self, amount: u32) -> Option<Self>;
+    fn checked_shr(self, amount: u32) -> Option<Self>;
+    fn bit_and(self, other: Self) -> Self;
+    fn bit_not(self) -> Self;
+    fn is_zero(self) -> bool;
+}
+
+macro_rules! impl_integer {
+    ($($ty:ty => $name:literal),* $(,)?) => {
+        $(
+            impl Integer for $ty {
+                const NAME: &'static str = $name;
+                const ZERO: Self = 0;
+
+                fn checked_add(self, other: Self) -> Option<Self> {
+                    <$ty>::checked_add(self, other)
+                }
+
+                fn checked_sub(self, other: Self) -> Option<Self> {
+                    <$ty>::checked_sub(self, other)
+                }
+
+                fn checked_mul(self, other: Self) -> Option<Self> {
+                    <$ty>::checked_mul(self, other)
+                }
+
+                fn checked_div(self, other: Self) -> Option<Self> {
+                    <$ty>::checked_div(self, other)
+                }
+
+                fn wrapping_add(self, other: Self) -> Self {
+                    <$ty>::wrapping_add(self, other)
+                }
+
+                fn wrapping_sub(self, other: Self) -> Self {
+                    <$ty>::wrapping_sub(self, other)
+                }
+
+                fn wrapping_mul(self, other: Self) -> Self {
+                    <$ty>::wrapping_mul(self, other)
+                }
+
+                fn wrapping_div(self, other: Self) -> Self {
+                    <$ty>::wrapping_div(self, other)
+                }
+
+                fn saturating_add(self, other: Self) -> Self {
+                    <$ty>::saturating_add(self, other)
+                }
+
+                fn saturating_sub(self, other: Self) -> Self {
+                    <$ty>::saturating_sub(self, other)
+                }
+
+                fn saturating_mul(self, other: Self) -> Self {
+                    <$ty>::saturating_mul(self, other)
+                }
+
+                fn saturating_div(self, other: Self) -> Self {
+                    <$ty>::saturating_div(self, other)
+                }
+
+                fn checked_shl(self, amount: u32) -> Option<Self> {
+                    <$ty>::checked_shl(self, amount)
+                }
+
+                fn checked_shr(self, amount: u32) -> Option<Self> {
+                    <$ty>::checked_shr(self, amount)
+                }
+
+                fn bit_and(self, other: Self) -> Self {
+                    self & other
+                }
+
+                fn bit_not(self) -> Self {
+                    !self
+                }
+
+                fn is_zero(self) -> bool {
+                    self == 0
+                }
+            }
+        )*
+    };
+}
+
+impl_integer! {
+    i8 => "Int8",
+    u8 => "UInt8",
+    i32 => "Int32",
+    u32 => "UInt32",
+    i64 => "Int64",
+    u64 => "UInt64",
+}
+
+/// Applies a function to two integers of the same type, or to one integer for a unary operation,
+/// keeping the type of the result
+macro_rules! with_integers {
+    ($left:expr, $right:expr, $f:expr) => {
+        match ($left, $right) {
+            (Value::Int8(x), Value::Int8(y)) => $f(*x, *y).map(Value::Int8),
+            (Value::UInt8(x), Value::UInt8(y)) => $f(*x, *y).map(Value::UInt8),
+            (Value::Int32(x), Value::Int32(y)) => $f(*x, *y).map(Value::Int32),
+            (Value::UInt32(x), Value::UInt32(y)) => $f(*x, *y).map(Value::UInt32),
+            (Value::Int64(x), Value::Int64(y)) => $f(*x, *y).map(Value::Int64),
+            (Value::UInt64(x), Value::UInt64(y)) => $f(*x, *y).map(Value::UInt64),
+            (left, right) => panic!("mismatched integers {:?} and {:?}", left, right),
+        }
+    };
+    ($value:expr, $f:expr) => {
+        match $value {
+            Value::Int8(x) => $f(*x).map(Value::Int8),
+            Value::UInt8(x) => $f(*x).map(Value::UInt8),
+            Value::Int32(x) => $f(*x).map(Value::Int32),
+            Value::UInt32(x) => $f(*x).map(Value::UInt32),
+            Value::Int64(x) => $f(*x).map(Value::Int64),
+            Value::UInt64(x) => $f(*x).map(Value::UInt64),
+            value => panic!("expected an integer, found {:?}", value),
+        }
+    };
+}
+
+fn arithmetic<T: Integer>(
+    operator: BinaryOperator,
+    overflow: Overflow,
+    x: T,
+    y: T,
+) -> Result<T, ArithError> {
+    let message = match operator {
+        BinaryOperator::Add => "attempt to add with overflow",
+        BinaryOperator::Subtract => "attempt to subtract with overflow",
+        BinaryOperator::Multiply => "attempt to multiply with overflow",
+        BinaryOperator::Divide if y.is_zero() => {
+            return Err(ArithError {
+                message: "attempt to divide by zero",
+                label: "the divisor is zero".to_string(),
+            })
+        }
+        BinaryOperator::Divide => "attempt to divide with overflow",
+        BinaryOperator::BitwiseAnd => return Ok(x.bit_and(y)),
+        _ => panic!("`{}` is not an arithmetic operator", operator),
+    };
+    let result = match (operator, overflow) {
+        (BinaryOperator::Add, Overflow::Trap) => x.checked_add(y),
+        (BinaryOperator::Subtract, Overflow::Trap) => x.checked_sub(y),
+        (BinaryOperator::Multiply, Overflow::Trap) => x.checked_mul(y),
+        (_, Overflow::Trap) => x.checked_div(y),
+        (BinaryOperator::Add, Overflow::Wrap) => Some(x.wrapping_add(y)),
+        (BinaryOperator::Subtract, Overflow::Wrap) => Some(x.wrapping_sub(y)),
+        (BinaryOperator::Multiply, Overflow::Wrap) => Some(x.wrapping_mul(y)),
+        (_, Overflow::Wrap) => Some(x.wrapping_div(y)),
+        (BinaryOperator::Add, Overflow::Saturate) => Some(x.saturating_add(y)),
+        (BinaryOperator::Subtract, Overflow::Saturate) => Some(x.saturating_sub(y)),
+        (BinaryOperator::Multiply, Overflow::Saturate) => Some(x.saturating_mul(y)),
+        (_, Overflow::Saturate) => Some(x.saturating_div(y)),
+    };
+    result.ok_or_else(|| ArithError::overflow(message, T::NAME))
+}
+
+/// Applies `+`, `-`, `*`, `/` or `&` to two numbers of the same type
+pub fn binary(
+    operator: BinaryOperator,
+    overflow: Overflow,
+    left: &Value,
+    right: &Value,
+) -> Result<Value, ArithError> {
+    match (left, right) {
+        (Value::Float32(x), Value::Float32(y)) => Ok(Value::Float32(float(operator, *x, *y))),
+        (Value::Float64(x), Value::Float64(y)) => Ok(Value::Float64(float(operator, *x, *y))),
+        (Value::Bool(x), Value::Bool(y)) if operator == BinaryOperator::BitwiseAnd => {
+            Ok(Value::Bool(*x & *y))
+        }
+        _ => with_integers!(left, right, |x, y| arithmetic(operator, overflow, x, y)),
+    }
+}
+
+fn float<T>(operator: BinaryOperator, x: T, y: T) -> T
+where
+    T: std::ops::Add<Output = T>
+        + std::ops::Sub<Output = T>
+        + std::ops::Mul<Output = T>
+        + std::ops::Div<Output = T>,
+{
+    match operator {
+        BinaryOperator::Add => x + y,
+        BinaryOperator::Subtract => x - y,
+        BinaryOperator::Multiply => x * y,
+        BinaryOperator::Divide => x / y,
+        _ => panic!("`{}` is not an arithmetic operator on floats", operator),
+    }
+}
+
+/// Shifts an integer by an amount of any integer type, which must be less than the number of bits
+/// in the integer
+pub fn shift(operator: BinaryOperator, value: &Value, amount: i128) -> Result<Value, ArithError> {
+    let (message, name) = match operator {
+        BinaryOperator::ShiftLeft => ("attempt to shift left with overflow", "left"),
+        _ => ("attempt to shift right with overflow", "right"),
+    };
+    let error = || ArithError {
+        message,
+        label: format!("cannot shift {} by {}", name, amount),
+    };
+    let amount = u32::try_from(amount).map_err(|_| error())?;
+    with_integers!(value, |x: _| {
+        let result = match operator {
+            BinaryOperator::ShiftLeft => Integer::checked_shl(x, amount),
+            _ => Integer::checked_shr(x, amount),
+        };
+        result.ok_or_else(error)
+    })
+}
+
+/// Applies a unary `-` to a number
+pub fn negate(overflow: Overflow, value: &Value) -> Result<Value, ArithError> {
+    match value {
+        Value::Float32(x) => Ok(Value::Float32(-x)),
+        Value::Float64(x) => Ok(Value::Float64(-x)),
+        _ => with_integers!(value, |x| negate_integer(overflow, x)),
+    }
+}
+
+fn negate_integer<T: Integer>(overflow: Overflow, x: T) -> Result<T, ArithError> {
+    arithmetic(BinaryOperator::Subtract, overflow, T::ZERO, x).map_err(|x| ArithError {
+        message: "attempt to negate with overflow",
+        ..x
+    })
+}
+
+/// Flips every bit of an integer, or a `Bool`
+pub fn not(value: &Value) -> Value {
+    match value {
+        Value::Bool(x) => Value::Bool(!x),
+        _ => {
+            let result: Result<Value, ArithError> =
+                with_integers!(value, |x: _| Ok(Integer::bit_not(x)));
+            result.expect("flipping bits can not fail")
+        }
+    }
+}
+
+/// Converts a number to a numeric type
+pub fn convert(
+    overflow: Overflow,
+    value: &Value,
+    target: PrimitiveType,
+) -> Result<Value, ArithError> {
+    let number = match value.as_integer() {
+        Some(x) => Number::Integer(x),
+        None => Number::Float(value.as_float().expect("only numbers are converted")),
+    };
+    match numeric::convert(number, target, overflow) {
+        Ok(Number::Integer(x)) => Ok(Value::from_integer(x, target).expect("the result fits")),
+        Ok(Number::Float(x)) => Ok(Value::from_float(x, target)),
+        Err(_) => Err(ArithError {
+            message: "attempt to convert with overflow",
+            label: format!("the value does not fit in `{}`", target),
+        }),
+    }
+}
+
+/// Calls an [Intrinsic] of a number with the arguments given to it. Only conversions depend on
+/// how the program was built
+pub fn intrinsic(
+    intrinsic: Intrinsic,
+    overflow: Overflow,
+    receiver: &Value,
+    arguments: &[Value],
+) -> Result<Value, ArithError> {
+    match intrinsic {
+        Intrinsic::Arith(operator, overflow) => binary(operator, overflow, receiver, &arguments[0]),
+        Intrinsic::Convert(target) => convert(overflow, receiver, target),
+    }
+}
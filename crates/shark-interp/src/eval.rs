@@ -0,0 +1,1239 @@
+//! Evaluates lowered function bodies. The blocks of a [Body] are run one after another, and the
+//! expressions within them are evaluated by walking the tree. Generators keep their locals within
+//! a [GeneratorFrame] between being resumed
+//!
+//! The `extern` functions of the standard library are run by its [runtime], which the interpreter
+//! provides its values to as a [Machine]
+
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap, rc::Rc};
+
+use shark_core::{
+    diagnostic::Diagnostic,
+    source::Span,
+    symbol::{sym, Symbol},
+};
+use shark_lex::token::LiteralKind;
+use shark_lower::{
+    body::{BlockId, LocalId, Statement, Terminator},
+    Body,
+};
+use shark_parse::ast::{
+    BinaryOperator, Block, Expr, ExprKind, ForKind, Function, ItemKind, Module, Path, Pattern,
+    PatternKind, StatementKind, TypeExprKind, UnaryOperator,
+};
+use shark_sema::{
+    generics::FunctionSig,
+    numeric::Overflow,
+    traits::TraitId,
+    ty::{AdtId, PrimitiveType, Type},
+    ModuleDefs,
+};
+use shark_std::runtime::{self, Builtin, Entries, Host, Machine, Stop};
+use shark_typeck::{ty::Ty, TypeckResults};
+
+use crate::{
+    arith::{self, ArithError},
+    value::{Place, Slot, Value},
+};
+
+/// How deep calls can nest before the program is stopped
+pub const MAX_CALL_DEPTH: usize = 2048;
+
+/// The state of a generator between being resumed
+#[derive(Debug)]
+pub enum GeneratorFrame {
+    Body {
+        /// The index of the body of the generator
+        body: usize,
+        locals: Vec<Slot>,
+        /// The block to continue from once resumed, or [None] once the generator is done
+        state: Option<BlockId>,
+        /// Whether the generator is currently running, in which case it can not be resumed again
+        running: bool,
+    },
+    /// A generator made by the runtime, which yields values it already has
+    Items(std::vec::IntoIter<Value>),
+}
+
+/// Why evaluation stopped before producing a value
+#[derive(Debug)]
+pub enum Unwind {
+    /// A `ret`, which stops the function it is within
+    Return(Value),
+    /// A runtime error, which stops the whole program
+    Error(Diagnostic),
+    /// A call of `exit`, which stops the whole program as if `main` returned the code
+    Exit(i32),
+}
+
+/// Why a program stopped before its function returned
+#[derive(Debug)]
+pub enum Halt {
+    Error(Diagnostic),
+    /// The program called `exit` with a code
+    Exit(i32),
+}
+
+type Eval<T> = Result<T, Unwind>;
+
+/// How running the blocks of a body ended
+enum Exit {
+    Return(Value),
+    /// A `yield`, which continues from the block once resumed
+    Yield(Value, BlockId),
+}
+
+/// The body being run along with its locals
+struct Frame<'run, 'ast> {
+    body: &'run Body<'ast>,
+    locals: Vec<Slot>,
+}
+
+impl Frame<'_, '_> {
+    fn set(&mut self, local: LocalId, value: Value) {
+        self.locals[local.0 as usize] = Rc::new(RefCell::new(value));
+    }
+
+    fn slot(&self, local: LocalId) -> &Slot {
+        &self.locals[local.0 as usize]
+    }
+}
+
+/// What running a function or method means
+#[derive(Debug, Clone, Copy)]
+enum Callee {
+    /// Running the body with an index
+    Body(usize),
+    /// Running a function of the runtime, for an `extern` function
+    Builtin(Builtin),
+    /// An `extern "C"` function, which can not be run
+    Foreign(Symbol),
+}
+
+/// The methods an `impl` provides for its type, along with whether they take `self` by reference
+struct ImplMethods<'run> {
+    self_type: &'run Type,
+    trait_id: Option<TraitId>,
+    methods: HashMap<Symbol, (Callee, bool)>,
+}
+
+fn error(message: impl Into<String>, span: Span, label: impl Into<String>) -> Unwind {
+    Unwind::Error(Diagnostic::error(message).with_primary(span, label))
+}
+
+/// The error for a name which checking should have found to be missing, for programs run without
+/// being checked first
+fn not_found(message: impl Into<String>, span: Span) -> Unwind {
+    error(message, span, "not found")
+}
+
+/// Turns a builtin stopping into unwinding from the call at `span`
+fn stopped(stop: Stop<Unwind>, span: Span) -> Unwind {
+    match stop {
+        Stop::Error { message, label } => error(message, span, label),
+        Stop::Exit(code) => Unwind::Exit(code),
+        Stop::Engine(unwind) => unwind,
+    }
+}
+
+impl From<(ArithError, Span)> for Unwind {
+    fn from((error, span): (ArithError, Span)) -> Self {
+        Unwind::Error(Diagnostic::error(error.message).with_primary(span, error.label))
+    }
+}
+
+pub struct Interpreter<'run, 'ast> {
+    defs: &'run ModuleDefs,
+    types: &'run TypeckResults,
+    bodies: &'run [Body<'ast>],
+    functions: HashMap<Symbol, Callee>,
+    impls: Vec<ImplMethods<'run>>,
+    /// The default methods of every trait
+    defaults: HashMap<(TraitId, Symbol), usize>,
+    /// What integer arithmetic does when its result does not fit in its type
+    overflow: Overflow,
+    depth: usize,
+    host: Host,
+    /// The `Vec`, `Map`, `Option` and `Result` of the standard library, when the program uses them
+    library: Library,
+    /// Where the builtin being run was called, which errors within it point to
+    span: Span,
+}
+
+/// The types of the standard library the runtime makes values of
+#[derive(Default)]
+struct Library {
+    vec: Option<AdtId>,
+    map: Option<AdtId>,
+    option: Option<AdtId>,
+    result: Option<AdtId>,
+}
+
+impl<'run, 'ast> Interpreter<'run, 'ast> {
+    /// Prepares to run a module, given the lowered body of each of its functions and methods, how
+    /// it was built and what it runs within
+    pub fn new(
+        module: &'ast Module,
+        defs: &'run ModuleDefs,
+        types: &'run TypeckResults,
+        bodies: &'run [Body<'ast>],
+        overflow: Overflow,
+        host: Host,
+    ) -> Self {
+        let body_of = |function: &Function| {
+            bodies
+                .iter()
+                .position(|x| std::ptr::eq(x.function, function))
+        };
+        let mut interpreter = Self {
+            defs,
+            types,
+            bodies,
+            functions: HashMap::new(),
+            impls: Vec::new(),
+            defaults: HashMap::new(),
+            overflow,
+            depth: 0,
+            host,
+            library: Library::default(),
+            span: Span::new(0, 0),
+        };
+        let lookup = |name| defs.types.lookup(name);
+        interpreter.library = Library {
+            vec: lookup(sym::VEC),
+            map: lookup(sym::MAP),
+            option: lookup(sym::OPTION),
+            result: lookup(sym::RESULT),
+        };
+        for item in &module.items {
+            match &item.kind {
+                ItemKind::Function(function) if function.abi.is_some() => {
+                    let callee = Callee::Foreign(function.name.symbol);
+                    interpreter.functions.insert(function.name.symbol, callee);
+                }
+                ItemKind::Function(function) if function.is_extern => {
+                    if let Some(builtin) = Builtin::lookup(None, function.name.symbol.as_str()) {
+                        let callee = Callee::Builtin(builtin);
+                        interpreter.functions.insert(function.name.symbol, callee);
+                    }
+                }
+                ItemKind::Function(function) => {
+                    if let Some(body) = body_of(function) {
+                        let callee = Callee::Body(body);
+                        interpreter.functions.insert(function.name.symbol, callee);
+                    }
+                }
+                ItemKind::Trait(trait_decl) => {
+                    let Some(trait_id) = defs.traits.trait_of_item(item.id) else {
+                        continue;
+                    };
+                    for method in &trait_decl.methods {
+                        if let Some(body) = body_of(method) {
+                            interpreter
+                                .defaults
+                                .insert((trait_id, method.name.symbol), body);
+                        }
+                    }
+                }
+                ItemKind::Impl(impl_decl) => {
+                    let Some(implementation) = defs.traits.impl_of_item(item.id) else {
+                        continue;
+                    };
+                    let owner = type_name(defs, &implementation.self_type);
+                    let methods = impl_decl
+                        .methods
+                        .iter()
+                        .filter_map(|x| {
+                            let callee = match x.is_extern {
+                                true => Callee::Builtin(Builtin::lookup(
+                                    owner.as_deref(),
+                                    x.name.symbol.as_str(),
+                                )?),
+                                false => Callee::Body(body_of(x)?),
+                            };
+                            let by_reference = implementation
+                                .method(x.name.symbol)
+                                .is_some_and(takes_reference);
+                            Some((x.name.symbol, (callee, by_reference)))
+                        })
+                        .collect();
+                    interpreter.impls.push(ImplMethods {
+                        self_type: &implementation.self_type,
+                        trait_id: implementation.trait_id,
+                        methods,
+                    });
+                }
+                _ => {}
+            }
+        }
+        interpreter
+    }
+
+    /// Calls the function with a name, which must exist and have a body
+    pub fn call_function(&mut self, name: Symbol, arguments: Vec<Value>) -> Result<Value, Halt> {
+        let Callee::Body(body) = self.functions[&name] else {
+            panic!("`{}` has no body", name);
+        };
+        let span = self.bodies[body].function.name.span;
+        match self.call(body, arguments, span) {
+            Ok(value) | Err(Unwind::Return(value)) => Ok(value),
+            Err(Unwind::Error(diagnostic)) => Err(Halt::Error(diagnostic)),
+            Err(Unwind::Exit(code)) => Err(Halt::Exit(code)),
+        }
+    }
+
+    /// Sets how many calls deep the program already is, for when the interpreter is called from
+    /// code run some other way
+    pub fn set_depth(&mut self, depth: usize) {
+        self.depth = depth;
+    }
+
+    /// Asks a generator for its next value, returning [None] once it is done
+    pub fn next(&mut self, generator: &Value) -> Result<Option<Value>, Halt> {
+        let Value::Generator(generator) = generator else {
+            panic!("expected a generator, found {:?}", generator);
+        };
+        match self.resume(generator, Span::new(0, 0)) {
+            Ok(value) => Ok(value),
+            Err(Unwind::Error(diagnostic)) => Err(Halt::Error(diagnostic)),
+            Err(Unwind::Exit(code)) => Err(Halt::Exit(code)),
+            Err(Unwind::Return(_)) => unreachable!("a return never leaves the body it is within"),
+        }
+    }
+
+    fn call_callee(&mut self, callee: Callee, arguments: Vec<Value>, span: Span) -> Eval<Value> {
+        match callee {
+            Callee::Body(body) => self.call(body, arguments, span),
+            Callee::Builtin(builtin) => {
+                let previous = std::mem::replace(&mut self.span, span);
+                let result = runtime::call(self, builtin, arguments);
+                self.span = previous;
+                result.map_err(|stop| stopped(stop, span))
+            }
+            Callee::Foreign(name) => Err(stopped(runtime::foreign(name.as_str()), span)),
+        }
+    }
+
+    fn call(&mut self, body_index: usize, arguments: Vec<Value>, span: Span) -> Eval<Value> {
+        let body = &self.bodies[body_index];
+        let mut locals: Vec<Slot> = arguments
+            .into_iter()
+            .map(|x| Rc::new(RefCell::new(x)))
+            .collect();
+        locals.resize_with(body.locals.len(), || Rc::new(RefCell::new(Value::Unit)));
+
+        if body.generator.is_some() {
+            return Ok(Value::Generator(Rc::new(RefCell::new(
+                GeneratorFrame::Body {
+                    body: body_index,
+                    locals,
+                    state: Some(Body::ENTRY),
+                    running: false,
+                },
+            ))));
+        }
+        if self.depth >= MAX_CALL_DEPTH {
+            return Err(error(
+                "stack overflow",
+                span,
+                format!("calls are nested more than {} deep", MAX_CALL_DEPTH),
+            ));
+        }
+        self.depth += 1;
+        let mut frame = Frame { body, locals };
+        let result = self.run(&mut frame, Body::ENTRY);
+        self.depth -= 1;
+        match result? {
+            Exit::Return(value) => Ok(value),
+            Exit::Yield(..) => unreachable!("only generators yield"),
+        }
+    }
+
+    fn resume(
+        &mut self,
+        generator: &Rc<RefCell<GeneratorFrame>>,
+        span: Span,
+    ) -> Eval<Option<Value>> {
+        let (body, locals, state) = match &mut *generator.borrow_mut() {
+            GeneratorFrame::Items(items) => return Ok(items.next()),
+            GeneratorFrame::Body { running: true, .. } => {
+                return Err(error(
+                    "generator resumed while it is already running",
+                    span,
+                    "the generator is resumed from within itself",
+                ));
+            }
+            GeneratorFrame::Body { state: None, .. } => return Ok(None),
+            GeneratorFrame::Body {
+                body,
+                locals,
+                state: Some(state),
+                running,
+            } => {
+                *running = true;
+                (*body, std::mem::take(locals), *state)
+            }
+        };
+
+        let mut frame = Frame {
+            body: &self.bodies[body],
+            locals,
+        };
+        self.depth += 1;
+        let result = match self.depth > MAX_CALL_DEPTH {
+            true => Err(error(
+                "stack overflow",
+                span,
+                format!("calls are nested more than {} deep", MAX_CALL_DEPTH),
+            )),
+            false => self.run(&mut frame, state),
+        };
+        self.depth -= 1;
+
+        let mut generator = generator.borrow_mut();
+        let GeneratorFrame::Body {
+            locals,
+            state,
+            running,
+            ..
+        } = &mut *generator
+        else {
+            unreachable!("only generators with a body are run");
+        };
+        *running = false;
+        *locals = frame.locals;
+        match result {
+            Ok(Exit::Yield(value, resume)) => {
+                *state = Some(resume);
+                Ok(Some(value))
+            }
+            Ok(Exit::Return(_)) => {
+                *state = None;
+                Ok(None)
+            }
+            Err(unwind) => {
+                *state = None;
+                Err(unwind)
+            }
+        }
+    }
+
+    /// Runs the blocks of a body from a block until it returns or yields
+    fn run(&mut self, frame: &mut Frame<'run, 'ast>, start: BlockId) -> Eval<Exit> {
+        match self.run_blocks(frame, start) {
+            Err(Unwind::Return(value)) => Ok(Exit::Return(value)),
+            result => result,
+        }
+    }
+
+    fn run_blocks(&mut self, frame: &mut Frame<'run, 'ast>, start: BlockId) -> Eval<Exit> {
+        let mut current = start;
+        loop {
+            let block = frame.body.block(current);
+            for statement in &block.statements {
+                match statement {
+                    Statement::Let(local, value) => {
+                        let value = match value {
+                            Some(value) => self.eval(frame, value)?,
+                            None => Value::Unit,
+                        };
+                        frame.set(*local, value);
+                    }
+                    Statement::Eval(expr) => {
+                        self.eval(frame, expr)?;
+                    }
+                    Statement::StartIteration {
+                        iterator,
+                        counter,
+                        iterable,
+                    } => {
+                        let value = self.eval(frame, iterable)?;
+                        frame.set(*iterator, value);
+                        if let Some(counter) = counter {
+                            frame.set(*counter, Value::Int64(0));
+                        }
+                    }
+                }
+            }
+            current = match &block.terminator {
+                Terminator::Goto(target) => *target,
+                Terminator::Branch {
+                    condition,
+                    then_block,
+                    else_block,
+                } => match self.eval(frame, condition)?.as_bool() {
+                    true => *then_block,
+                    false => *else_block,
+                },
+                Terminator::When { scrutinee, arms } => {
+                    let value = self.eval(frame, scrutinee)?;
+                    let mut target = None;
+                    for (arm, block) in arms {
+                        if self.matches(&arm.pattern, &value) {
+                            self.bind(frame, &arm.pattern, value.clone());
+                            let guard = match &arm.guard {
+                                Some(guard) => self.eval(frame, guard)?.as_bool(),
+                                None => true,
+                            };
+                            if guard {
+                                target = Some(*block);
+                                break;
+                            }
+                        }
+                    }
+                    target.ok_or_else(|| no_arm(scrutinee.span))?
+                }
+                Terminator::Next {
+                    iterator,
+                    counter,
+                    pattern,
+                    body,
+                    exit,
+                } => {
+                    let generator = frame.slot(*iterator).borrow().clone();
+                    let Value::Generator(generator) = generator else {
+                        panic!("only generators are iterated");
+                    };
+                    match self.resume(&generator, pattern.span)? {
+                        Some(value) => {
+                            let value = match counter {
+                                Some(counter) => {
+                                    let index = frame.slot(*counter).borrow().clone();
+                                    let next = arith::binary(
+                                        BinaryOperator::Add,
+                                        Overflow::Trap,
+                                        &index,
+                                        &Value::Int64(1),
+                                    )
+                                    .map_err(|x| Unwind::from((x, pattern.span)))?;
+                                    frame.set(*counter, next);
+                                    Value::Tuple(vec![index, value])
+                                }
+                                None => value,
+                            };
+                            self.bind(frame, pattern, value);
+                            *body
+                        }
+                        None => *exit,
+                    }
+                }
+                Terminator::Yield { value, resume } => {
+                    let value = self.eval(frame, value)?;
+                    return Ok(Exit::Yield(value, *resume));
+                }
+                Terminator::Return(value) => {
+                    let value = match value {
+                        Some(value) => self.eval(frame, value)?,
+                        None => Value::Unit,
+                    };
+                    return Ok(Exit::Return(value));
+                }
+            };
+        }
+    }
+
+    fn eval_block(&mut self, frame: &mut Frame<'run, 'ast>, block: &'ast Block) -> Eval<Value> {
+        for statement in &block.statements {
+            match &statement.kind {
+                StatementKind::Let(let_statement) => {
+                    let value = match &let_statement.value {
+                        Some(value) => self.eval(frame, value)?,
+                        None => Value::Unit,
+                    };
+                    let local = frame.body.declarations[&statement.id];
+                    frame.set(local, value);
+                }
+                StatementKind::Expr(expr) => {
+                    self.eval(frame, expr)?;
+                }
+                StatementKind::Error => {}
+            }
+        }
+        match &block.tail {
+            Some(tail) => self.eval(frame, tail),
+            None => Ok(Value::Unit),
+        }
+    }
+
+    fn eval(&mut self, frame: &mut Frame<'run, 'ast>, expr: &'ast Expr) -> Eval<Value> {
+        match &expr.kind {
+            ExprKind::Literal(literal) => {
+                let ty = match self.types.type_of(expr.id) {
+                    Some(Ty::Primitive(primitive)) => *primitive,
+                    _ => PrimitiveType::of_literal(literal),
+                };
+                Ok(Value::from_literal(literal, ty))
+            }
+            ExprKind::Name(name) => match frame.body.names.get(&expr.id) {
+                Some(local) => Ok(frame.slot(*local).borrow().clone()),
+                None => Err(not_found(
+                    format!("unresolved name `{}`", name.symbol),
+                    name.span,
+                )),
+            },
+            ExprKind::Path(path) => {
+                let (id, index) = self.variant(path)?;
+                Ok(Value::Variant(id, index, Vec::new()))
+            }
+            ExprKind::StructLiteral { path, fields } => {
+                let Some(id) = self.defs.types.lookup(path.name().symbol) else {
+                    return Err(not_found(format!("cannot find type `{}`", path), path.span));
+                };
+                let adt = self.defs.types.adt(id);
+                let mut values = vec![Value::Unit; adt.fields().len()];
+                for field in fields {
+                    let value = self.eval(frame, &field.value)?;
+                    if let Some((index, _)) = adt.field(field.name.symbol) {
+                        values[index] = value;
+                    }
+                }
+                Ok(Value::Struct(id, values))
+            }
+            ExprKind::Unary { operator, operand } => {
+                let value = self.eval(frame, operand)?;
+                match operator {
+                    UnaryOperator::Negate => arith::negate(self.overflow, &value)
+                        .map_err(|x| Unwind::from((x, expr.span))),
+                    UnaryOperator::Not => Ok(arith::not(&value)),
+                    UnaryOperator::Deref => match value {
+                        Value::Reference(place) => Ok(place.read()),
+                        value => panic!("expected a reference, found {:?}", value),
+                    },
+                }
+            }
+            ExprKind::Reference { operand, .. } => {
+                let place = match self.place(frame, operand)? {
+                    Some(place) => place,
+                    // A temporary lives as long as the reference to it
+                    None => Place::new(Rc::new(RefCell::new(self.eval(frame, operand)?))),
+                };
+                Ok(Value::Reference(place))
+            }
+            ExprKind::Binary {
+                operator: operator @ (BinaryOperator::And | BinaryOperator::Or),
+                left,
+                right,
+            } => {
+                let left = self.eval(frame, left)?.as_bool();
+                match (operator, left) {
+                    (BinaryOperator::And, false) => Ok(Value::Bool(false)),
+                    (BinaryOperator::Or, true) => Ok(Value::Bool(true)),
+                    _ => self.eval(frame, right),
+                }
+            }
+            ExprKind::Binary {
+                operator,
+                left,
+                right,
+            } => {
+                let left = self.eval(frame, left)?;
+                let right = self.eval(frame, right)?;
+                self.binary(*operator, &left, &right, expr.span)
+            }
+            ExprKind::Assign {
+                operator,
+                target,
+                value,
+            } => {
+                let value = self.eval(frame, value)?;
+                let place = self
+                    .place(frame, target)?
+                    .expect("assignments are checked to have a place as their target");
+                let value = match operator {
+                    Some(operator) => self.binary(*operator, &place.read(), &value, expr.span)?,
+                    None => value,
+                };
+                place.write(value);
+                Ok(Value::Unit)
+            }
+            ExprKind::Tuple(elements) if elements.is_empty() => Ok(Value::Unit),
+            ExprKind::Tuple(elements) => Ok(Value::Tuple(
+                elements
+                    .iter()
+                    .map(|x| self.eval(frame, x))
+                    .collect::<Eval<_>>()?,
+            )),
+            // An array is a `Vec` at runtime
+            ExprKind::Array(elements) => {
+                let items = elements
+                    .iter()
+                    .map(|x| self.eval(frame, x))
+                    .collect::<Eval<_>>()?;
+                Ok(self.list(Rc::new(items)))
+            }
+            ExprKind::Repeat { value, length } => {
+                let ExprKind::Literal(LiteralKind::Int64(count)) = length.kind else {
+                    unreachable!("lengths are replaced by their value");
+                };
+                let value = self.eval(frame, value)?;
+                Ok(self.list(Rc::new(vec![value; count as usize])))
+            }
+            ExprKind::Call { callee, arguments } => self.eval_call(frame, expr, callee, arguments),
+            ExprKind::Field { .. } => {
+                let place = self
+                    .place(frame, expr)?
+                    .expect("a field access always has a place");
+                Ok(place.read())
+            }
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => self.eval_block(frame, block),
+            ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => match (self.eval(frame, condition)?.as_bool(), else_branch) {
+                (true, _) => self.eval_block(frame, then_branch),
+                (false, Some(else_branch)) => self.eval(frame, else_branch),
+                (false, None) => Ok(Value::Unit),
+            },
+            ExprKind::For {
+                kind,
+                pattern,
+                iterable,
+                body,
+            } => {
+                let Value::Generator(generator) = self.eval(frame, iterable)? else {
+                    panic!("only generators are iterated");
+                };
+                let mut index = 0;
+                while let Some(value) = self.resume(&generator, pattern.span)? {
+                    let value = match kind {
+                        ForKind::In => value,
+                        ForKind::Of => Value::Tuple(vec![Value::Int64(index), value]),
+                    };
+                    index += 1;
+                    self.bind(frame, pattern, value);
+                    self.eval_block(frame, body)?;
+                }
+                Ok(Value::Unit)
+            }
+            ExprKind::When { scrutinee, arms } => {
+                let value = self.eval(frame, scrutinee)?;
+                for arm in arms {
+                    if !self.matches(&arm.pattern, &value) {
+                        continue;
+                    }
+                    self.bind(frame, &arm.pattern, value.clone());
+                    let guard = match &arm.guard {
+                        Some(guard) => self.eval(frame, guard)?.as_bool(),
+                        None => true,
+                    };
+                    if guard {
+                        return self.eval(frame, &arm.body);
+                    }
+                }
+                Err(no_arm(scrutinee.span))
+            }
+            ExprKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.eval(frame, value)?,
+                    None => Value::Unit,
+                };
+                Err(Unwind::Return(value))
+            }
+            ExprKind::Yield(_) => unreachable!("a `yield` is always lowered into a terminator"),
+            ExprKind::Closure(function) => {
+                let body = self
+                    .bodies
+                    .iter()
+                    .position(|x| std::ptr::eq(x.function, &**function))
+                    .expect("every closure is lowered");
+                let captures = self.bodies[body]
+                    .captures
+                    .iter()
+                    .map(|x| frame.slot(*x).borrow().clone())
+                    .collect();
+                Ok(Value::Closure(body, Rc::new(captures)))
+            }
+            ExprKind::Error => unreachable!("programs with errors are never run"),
+        }
+    }
+
+    /// Evaluates an expression naming a value in memory to where that value is, or [None] if the
+    /// expression produces a temporary value instead
+    fn place(&mut self, frame: &mut Frame<'run, 'ast>, expr: &'ast Expr) -> Eval<Option<Place>> {
+        match &expr.kind {
+            ExprKind::Name(_) => Ok(frame
+                .body
+                .names
+                .get(&expr.id)
+                .map(|x| Place::new(frame.slot(*x).clone()))),
+            ExprKind::Field { object, field } => {
+                let mut place = match self.place(frame, object)? {
+                    Some(place) => place,
+                    None => Place::new(Rc::new(RefCell::new(self.eval(frame, object)?))),
+                };
+                // Fields are reached through references automatically
+                let id = loop {
+                    match place.read() {
+                        Value::Reference(inner) => place = inner,
+                        Value::Struct(id, _) => break id,
+                        value => panic!("expected a struct, found {:?}", value),
+                    }
+                };
+                let Some((index, _)) = self.defs.types.adt(id).field(field.symbol) else {
+                    let message = format!("no field `{}`", field.symbol);
+                    return Err(not_found(message, field.span));
+                };
+                Ok(Some(place.project(index)))
+            }
+            ExprKind::Unary {
+                operator: UnaryOperator::Deref,
+                operand,
+            } => match self.eval(frame, operand)? {
+                Value::Reference(place) => Ok(Some(place)),
+                value => panic!("expected a reference, found {:?}", value),
+            },
+            _ => Ok(None),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        operator: BinaryOperator,
+        left: &Value,
+        right: &Value,
+        span: Span,
+    ) -> Eval<Value> {
+        let ordering = || left.compare(right);
+        let result = match operator {
+            BinaryOperator::Greater => Value::Bool(ordering() == Some(Ordering::Greater)),
+            BinaryOperator::Lesser => Value::Bool(ordering() == Some(Ordering::Less)),
+            BinaryOperator::GreaterOrEqual => Value::Bool(matches!(
+                ordering(),
+                Some(Ordering::Greater | Ordering::Equal)
+            )),
+            BinaryOperator::LessOrEqual => {
+                Value::Bool(matches!(ordering(), Some(Ordering::Less | Ordering::Equal)))
+            }
+            BinaryOperator::EqualTo => Value::Bool(ordering() == Some(Ordering::Equal)),
+            BinaryOperator::NotEqual => Value::Bool(ordering() != Some(Ordering::Equal)),
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => {
+                let amount = right.as_integer().expect("shift amounts are integers");
+                arith::shift(operator, left, amount).map_err(|x| Unwind::from((x, span)))?
+            }
+            BinaryOperator::And | BinaryOperator::Or => {
+                unreachable!("`&&` and `|` short circuit before reaching here")
+            }
+            _ => arith::binary(operator, self.overflow, left, right)
+                .map_err(|x| Unwind::from((x, span)))?,
+        };
+        Ok(result)
+    }
+
+    fn eval_call(
+        &mut self,
+        frame: &mut Frame<'run, 'ast>,
+        expr: &'ast Expr,
+        callee: &'ast Expr,
+        arguments: &'ast [Expr],
+    ) -> Eval<Value> {
+        if let (Some(intrinsic), ExprKind::Field { object, .. }) =
+            (self.types.intrinsic_of(expr.id), &callee.kind)
+        {
+            let receiver = self.eval(frame, object)?;
+            let values = arguments
+                .iter()
+                .map(|x| self.eval(frame, x))
+                .collect::<Eval<Vec<_>>>()?;
+            return arith::intrinsic(intrinsic, self.overflow, &receiver, &values)
+                .map_err(|x| Unwind::from((x, expr.span)));
+        }
+        match &callee.kind {
+            ExprKind::Field { object, field } => {
+                let mut receiver = self.eval(frame, object)?;
+                // The place the receiver was last reached through, if it was dereferenced
+                let mut through = None;
+                let (callee, by_reference) = loop {
+                    if let Some(found) = self.find_method(&receiver, field.symbol) {
+                        break found;
+                    }
+                    match receiver {
+                        Value::Reference(place) => {
+                            receiver = place.read();
+                            through = Some(place);
+                        }
+                        _ => {
+                            let message = format!("no method named `{}`", field.symbol);
+                            return Err(not_found(message, field.span));
+                        }
+                    }
+                };
+                if by_reference && !matches!(receiver, Value::Reference(_)) {
+                    let place = match through {
+                        Some(place) => place,
+                        None => match self.place(frame, object)? {
+                            Some(place) => place,
+                            None => Place::new(Rc::new(RefCell::new(receiver))),
+                        },
+                    };
+                    receiver = Value::Reference(place);
+                }
+                let mut values = vec![receiver];
+                for argument in arguments {
+                    values.push(self.eval(frame, argument)?);
+                }
+                self.call_callee(callee, values, expr.span)
+            }
+            ExprKind::Path(path) if self.defs.traits.lookup(path.segments[0].symbol).is_some() => {
+                let values = arguments
+                    .iter()
+                    .map(|x| self.eval(frame, x))
+                    .collect::<Eval<Vec<_>>>()?;
+                let method = path.name().symbol;
+                let mut receiver = values.first().cloned().unwrap_or(Value::Unit);
+                let callee = loop {
+                    if let Some((callee, _)) = self.find_method(&receiver, method) {
+                        break callee;
+                    }
+                    match receiver {
+                        Value::Reference(place) => receiver = place.read(),
+                        _ => {
+                            let message = format!("no method named `{}`", method);
+                            return Err(not_found(message, path.span));
+                        }
+                    }
+                };
+                self.call_callee(callee, values, expr.span)
+            }
+            ExprKind::Path(path) => {
+                if let Some((implementation, sig)) =
+                    self.defs.traits.resolve_associated(&self.defs.types, path)
+                {
+                    let values = arguments
+                        .iter()
+                        .map(|x| self.eval(frame, x))
+                        .collect::<Eval<_>>()?;
+                    let found = self
+                        .impls
+                        .iter()
+                        .find(|x| std::ptr::eq(x.self_type, &implementation.self_type))
+                        .and_then(|x| x.methods.get(&sig.name.symbol))
+                        .copied();
+                    let Some((callee, _)) = found else {
+                        return Err(not_found(format!("cannot find `{}`", path), path.span));
+                    };
+                    return self.call_callee(callee, values, expr.span);
+                }
+                let (id, index) = self.variant(path)?;
+                let values = arguments
+                    .iter()
+                    .map(|x| self.eval(frame, x))
+                    .collect::<Eval<_>>()?;
+                Ok(Value::Variant(id, index, values))
+            }
+            ExprKind::Name(name) if !frame.body.names.contains_key(&callee.id) => {
+                let values = arguments
+                    .iter()
+                    .map(|x| self.eval(frame, x))
+                    .collect::<Eval<_>>()?;
+                let Some(callee) = self.functions.get(&name.symbol).copied() else {
+                    let message = format!("cannot find function `{}`", name.symbol);
+                    return Err(not_found(message, name.span));
+                };
+                self.call_callee(callee, values, expr.span)
+            }
+            _ => {
+                let closure = self.eval(frame, callee)?;
+                let values = arguments
+                    .iter()
+                    .map(|x| self.eval(frame, x))
+                    .collect::<Eval<_>>()?;
+                self.call_closure(&closure, values, callee.span, expr.span)
+            }
+        }
+    }
+
+    /// Calls a closure, whose captured values follow the arguments
+    fn call_closure(
+        &mut self,
+        closure: &Value,
+        mut arguments: Vec<Value>,
+        callee_span: Span,
+        span: Span,
+    ) -> Eval<Value> {
+        let Value::Closure(body, captures) = closure else {
+            return Err(error(
+                "expected a function, a method or a variant",
+                callee_span,
+                "cannot be called",
+            ));
+        };
+        arguments.extend(captures.iter().cloned());
+        self.call(*body, arguments, span)
+    }
+
+    /// Finds the method a value of some type would call, along with whether it takes `self` by
+    /// reference
+    fn find_method(&self, receiver: &Value, method: Symbol) -> Option<(Callee, bool)> {
+        let candidates = self
+            .impls
+            .iter()
+            .filter(|x| value_has_type(receiver, x.self_type));
+        // Implementations for one particular type take priority over generic ones
+        let (specific, generic): (Vec<_>, Vec<_>) =
+            candidates.partition(|x| !matches!(x.self_type, Type::Param(_)));
+        specific.into_iter().chain(generic).find_map(|x| {
+            x.methods.get(&method).copied().or_else(|| {
+                let body = *self.defaults.get(&(x.trait_id?, method))?;
+                let by_reference = self.bodies[body]
+                    .function
+                    .parameters
+                    .first()
+                    .is_some_and(|x| matches!(x.ty.kind, TypeExprKind::Reference { .. }));
+                Some((Callee::Body(body), by_reference))
+            })
+        })
+    }
+
+    fn variant(&self, path: &Path) -> Eval<(AdtId, usize)> {
+        self.defs
+            .types
+            .resolve_variant(path, &mut Vec::new())
+            .ok_or_else(|| not_found(format!("cannot find variant `{}`", path), path.span))
+    }
+
+    fn matches(&self, pattern: &Pattern, value: &Value) -> bool {
+        match &pattern.kind {
+            PatternKind::Wildcard | PatternKind::Binding { .. } => true,
+            PatternKind::Literal(literal) => {
+                self.literal_ordering(literal, value) == Some(Ordering::Equal)
+            }
+            PatternKind::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                let above = matches!(
+                    self.literal_ordering(start, value),
+                    Some(Ordering::Less | Ordering::Equal)
+                );
+                let below = match self.literal_ordering(end, value) {
+                    Some(Ordering::Greater) => true,
+                    Some(Ordering::Equal) => *inclusive,
+                    _ => false,
+                };
+                above && below
+            }
+            PatternKind::Tuple(elements) => elements
+                .iter()
+                .zip(value.parts())
+                .all(|(x, y)| self.matches(x, y)),
+            PatternKind::Variant { path, payload } => {
+                // A variant which does not exist matches nothing
+                let Ok((_, expected)) = self.variant(path) else {
+                    return false;
+                };
+                match value {
+                    Value::Variant(_, index, values) if *index == expected => {
+                        payload.iter().zip(values).all(|(x, y)| self.matches(x, y))
+                    }
+                    _ => false,
+                }
+            }
+        }
+    }
+
+    /// Compares a literal within a pattern to a value of the same type
+    fn literal_ordering(&self, literal: &LiteralKind, value: &Value) -> Option<Ordering> {
+        let ty = value.primitive_type()?;
+        Value::from_literal(literal, ty).compare(value)
+    }
+
+    /// Stores the parts of a value in the locals a pattern binds, once it is known to match
+    fn bind(&self, frame: &mut Frame<'run, 'ast>, pattern: &Pattern, value: Value) {
+        match &pattern.kind {
+            PatternKind::Binding { .. } => {
+                let local = frame.body.declarations[&pattern.id];
+                frame.set(local, value);
+            }
+            PatternKind::Tuple(elements)
+            | PatternKind::Variant {
+                payload: elements, ..
+            } => {
+                for (element, value) in elements.iter().zip(value.parts()) {
+                    self.bind(frame, element, value.clone());
+                }
+            }
+            PatternKind::Wildcard | PatternKind::Literal(_) | PatternKind::Range { .. } => {}
+        }
+    }
+}
+
+fn no_arm(span: Span) -> Unwind {
+    error("no arm of the `when` matched", span, "matched here")
+}
+
+/// Checks if a value could be of a type, where a generic parameter could be any type
+fn value_has_type(value: &Value, ty: &Type) -> bool {
+    match ty {
+        Type::Primitive(primitive) => value.primitive_type() == Some(*primitive),
+        Type::Unit => matches!(value, Value::Unit),
+        Type::Adt(id, _) => matches!(
+            value,
+            Value::Struct(x, _) | Value::Variant(x, _, _) | Value::List(x, _) | Value::Map(x, _)
+                if x == id
+        ),
+        Type::Reference { .. } => matches!(value, Value::Reference(_)),
+        Type::Generator(_) => matches!(value, Value::Generator(_)),
+        Type::Array(..) => matches!(value, Value::List(..)),
+        Type::Function { .. } => matches!(value, Value::Closure(..)),
+        Type::Param(_) => true,
+        Type::Error => false,
+    }
+}
+
+/// Gets the name of the type an implementation is for, which the builtins of its `extern` methods
+/// are named after
+fn type_name(defs: &ModuleDefs, ty: &Type) -> Option<String> {
+    match ty {
+        Type::Primitive(primitive) => Some(primitive.name().to_string()),
+        Type::Adt(id, _) => Some(defs.types.adt(*id).name.symbol.as_str().to_string()),
+        _ => None,
+    }
+}
+
+fn takes_reference(sig: &FunctionSig) -> bool {
+    sig.has_self && matches!(sig.parameters.first(), Some(Type::Reference { .. }))
+}
+
+impl Machine for Interpreter<'_, '_> {
+    type Value = Value;
+    type Error = Unwind;
+
+    fn host(&mut self) -> &mut Host {
+        &mut self.host
+    }
+
+    fn show(&mut self, value: &Value) -> Eval<String> {
+        let mut value = value.clone();
+        let show = Symbol::intern("show");
+        let (callee, by_reference) = loop {
+            if let Some(found) = self.find_method(&value, show) {
+                break found;
+            }
+            match value {
+                Value::Reference(place) => value = place.read(),
+                _ => panic!("the values shown are checked to implement `Show`"),
+            }
+        };
+        if by_reference && !matches!(value, Value::Reference(_)) {
+            value = Value::Reference(Place::new(Rc::new(RefCell::new(value))));
+        }
+        match self.call_callee(callee, vec![value], self.span)? {
+            Value::Str(text) => Ok(text.to_string()),
+            value => panic!("`show` is checked to give a `Str`, found {:?}", value),
+        }
+    }
+
+    fn show_primitive(&self, value: &Value) -> String {
+        value.to_string()
+    }
+
+    fn unit(&self) -> Value {
+        Value::Unit
+    }
+
+    fn bool(&self, value: bool) -> Value {
+        Value::Bool(value)
+    }
+
+    fn char(&self, value: char) -> Value {
+        Value::Char(value)
+    }
+
+    fn int64(&self, value: i64) -> Value {
+        Value::Int64(value)
+    }
+
+    fn float64(&self, value: f64) -> Value {
+        Value::Float64(value)
+    }
+
+    fn string(&self, value: &str) -> Value {
+        Value::Str(value.into())
+    }
+
+    fn list(&self, items: Rc<Vec<Value>>) -> Value {
+        Value::List(self.library.vec.expect("`Vec` is used"), items)
+    }
+
+    fn map(&self, entries: Entries<Value>) -> Value {
+        Value::Map(self.library.map.expect("`Map` is used"), entries)
+    }
+
+    fn option(&self, value: Option<Value>) -> Value {
+        let id = self.library.option.expect("`Option` is used");
+        match value {
+            Some(value) => Value::Variant(id, 0, vec![value]),
+            None => Value::Variant(id, 1, Vec::new()),
+        }
+    }
+
+    fn result(&self, value: Result<Value, Value>) -> Value {
+        let id = self.library.result.expect("`Result` is used");
+        match value {
+            Ok(value) => Value::Variant(id, 0, vec![value]),
+            Err(error) => Value::Variant(id, 1, vec![error]),
+        }
+    }
+
+    fn generator(&mut self, items: Vec<Value>) -> Value {
+        Value::Generator(Rc::new(RefCell::new(GeneratorFrame::Items(
+            items.into_iter(),
+        ))))
+    }
+
+    fn to_integer(&self, value: &Value) -> Option<i128> {
+        value.as_integer()
+    }
+
+    fn to_str(&self, value: &Value) -> Option<Rc<str>> {
+        match value {
+            Value::Str(text) => Some(text.clone()),
+            _ => None,
+        }
+    }
+
+    fn to_list(&self, value: &Value) -> Option<Rc<Vec<Value>>> {
+        match value {
+            Value::List(_, items) => Some(items.clone()),
+            _ => None,
+        }
+    }
+
+    fn to_map(&self, value: &Value) -> Option<Entries<Value>> {
+        match value {
+            Value::Map(_, entries) => Some(entries.clone()),
+            _ => None,
+        }
+    }
+
+    fn to_variant(&self, value: &Value) -> Option<(bool, Option<Value>)> {
+        match value {
+            Value::Variant(_, index, payload) => Some((*index == 0, payload.first().cloned())),
+            _ => None,
+        }
+    }
+
+    fn compare(&self, left: &Value, right: &Value) -> Option<Ordering> {
+        left.compare(right)
+    }
+
+    fn read(&self, value: &Value) -> Value {
+        match value {
+            Value::Reference(place) => self.read(&place.read()),
+            value => value.clone(),
+        }
+    }
+
+    fn write(&self, reference: &Value, value: Value) -> bool {
+        let Value::Reference(mut place) = reference.clone() else {
+            return false;
+        };
+        while let Value::Reference(inner) = place.read() {
+            place = inner;
+        }
+        place.write(value);
+        true
+    }
+}
@@ -0,0 +1,68 @@
+use eval::{Halt, Interpreter};
+use shark_core::{diagnostic::Diagnostic, source::Span, symbol::Symbol};
+use shark_lower::Body;
+use shark_parse::ast::{Function, ItemKind, Module, Visibility};
+use shark_sema::{numeric::Overflow, ModuleDefs};
+use shark_std::runtime::Host;
+use shark_typeck::TypeckResults;
+use value::Value;
+
+pub mod arith;
+pub mod eval;
+pub mod value;
+
+#[cfg(test)]
+pub mod tests;
+
+/// How much stack the thread running the interpreter should be given, so that programs can
+/// recurse as deep as [eval::MAX_CALL_DEPTH] allows
+pub const STACK_SIZE: usize = 256 * 1024 * 1024;
+
+/// Finds the `pub fun main()` a program starts from, or the error explaining why it can not be run
+pub fn find_main(module: &Module) -> Result<&Function, Diagnostic> {
+    let main = Symbol::intern("main");
+    let item = module.items.iter().find(|x| match &x.kind {
+        ItemKind::Function(function) => function.name.symbol == main,
+        _ => false,
+    });
+    let Some(item) = item else {
+        return Err(Diagnostic::error("`main` function not found").with_primary(
+            Span::new(module.span.start, module.span.start),
+            "consider adding `pub fun main()` to this file",
+        ));
+    };
+    let ItemKind::Function(function) = &item.kind else {
+        unreachable!();
+    };
+    if item.visibility != Visibility::Public {
+        return Err(Diagnostic::error("`main` function is not public")
+            .with_primary(function.name.span, "consider making it `pub fun main()`"));
+    }
+    if !function.parameters.is_empty() {
+        return Err(Diagnostic::error("`main` function takes no parameters")
+            .with_primary(function.name.span, "declared here"));
+    }
+    Ok(function)
+}
+
+/// Runs a [Module] which has been checked and lowered without errors, starting from its
+/// `pub fun main()`, as a program built to overflow a certain way and within a host. Returns the
+/// value `main` returns, the code given to `exit` as an `Int32`, or the runtime error which stopped
+/// the program
+pub fn run(
+    module: &Module,
+    defs: &ModuleDefs,
+    types: &TypeckResults,
+    bodies: &[Body<'_>],
+    overflow: Overflow,
+    host: Host,
+) -> Result<Value, Diagnostic> {
+    let main = find_main(module)?;
+    let result = Interpreter::new(module, defs, types, bodies, overflow, host)
+        .call_function(main.name.symbol, Vec::new());
+    match result {
+        Ok(value) => Ok(value),
+        Err(Halt::Exit(code)) => Ok(Value::Int32(code)),
+        Err(Halt::Error(diagnostic)) => Err(diagnostic),
+    }
+}
@@ -0,0 +1,402 @@
+use shark_parse::parse;
+use shark_sema::numeric::Overflow;
+use shark_std::runtime::Host;
+use shark_testing::{
+    error_source, on_interpreter_stack, overflow::check_overflow_modes, suite, text_status,
+};
+
+use crate::run;
+
+/// Runs a module which must check without errors, returning what `main` returned as text, or the
+/// message of the runtime error along with the source its span covers
+fn interpret(source: &str) -> Result<String, (String, String)> {
+    interpret_as(source, Overflow::Trap)
+}
+
+/// Like [interpret], for a program built to overflow some way
+fn interpret_as(source: &str, overflow: Overflow) -> Result<String, (String, String)> {
+    let checked = shark_testing::check(source);
+    let bodies = checked.lower();
+    let (module, defs, types) = (&checked.module, &checked.defs, &checked.types);
+    on_interpreter_stack(|| {
+        run(module, defs, types, &bodies, overflow, Host::default())
+            .map(|x| x.to_string())
+            .map_err(|x| error_source(source, x))
+    })
+}
+
+#[test]
+fn test_arithmetic() {
+    let result = interpret(
+        "pub fun main() :: Int32 {
+            let mut total = 2 + 3 * 4;
+            total -= 10 / 3;
+            total *= -2;
+            let shifted = (1 << 4) >> 1;
+            let masked = 13 & 7;
+            total + shifted + masked
+        }",
+    );
+    assert_eq!(result, Ok("-9".to_string()));
+
+    let result = interpret(
+        "pub fun main() :: Bool {
+            let half :: Float64 = 1.0 / 4.0 * 2.0;
+            let max :: UInt8 = 200 + 55;
+            3 >= 3 && 1 != 2 && !(!true | 2 < 1) && half == 0.5 && max == 255
+        }",
+    );
+    assert_eq!(result, Ok("true".to_string()));
+}
+
+#[test]
+fn test_control_flow() {
+    let result = interpret(
+        "fun fib(n :: Int64) :: Int64 {
+            if n < 2 {
+                ret n;
+            }
+            fib(n - 1) + fib(n - 2)
+        }
+
+        fun sign(n :: Int32) :: Int32 {
+            if n > 0 { 1 } else if n < 0 { -1 } else { 0 }
+        }
+
+        pub fun main() :: Int64 {
+            if sign(-5) == -1 && sign(0) == 0 && sign(7) == 1 {
+                fib(20)
+            } else {
+                0
+            }
+        }",
+    );
+    assert_eq!(result, Ok("6765".to_string()));
+}
+
+#[test]
+fn test_types_and_patterns() {
+    let result = interpret(
+        "type Point { x :: Int32, y :: Int32 }
+
+        enum Shape {
+            Circle(Int32),
+            Rectangle(Point, Point),
+            Empty,
+        }
+
+        fun area(shape :: Shape) :: Int32 {
+            when shape {
+                Shape::Circle(radius) => 3 * radius * radius,
+                Shape::Rectangle(a, b) => (b.x - a.x) * (b.y - a.y),
+                Shape::Empty => 0,
+            }
+        }
+
+        fun size(n :: Int32) :: Int32 {
+            when n {
+                0 => 0,
+                1..10 => 1,
+                x if x > 100 => 3,
+                _ => 2,
+            }
+        }
+
+        pub fun main() :: Int32 {
+            let rectangle = Shape::Rectangle(Point { x = 1, y = 2 }, Point { y = 6, x = 4 });
+            let total = area(Shape::Circle(2)) + area(rectangle) + area(Shape::Empty);
+            total * 10000 + size(0) * 1000 + size(9) * 100 + size(10) * 10 + size(500)
+        }",
+    );
+    assert_eq!(result, Ok("240123".to_string()));
+}
+
+#[test]
+fn test_references() {
+    let result = interpret(
+        "type Counter { count :: Int32 }
+
+        fun bump(counter :: ref mut Counter) {
+            counter.count += 1;
+        }
+
+        fun set(target :: ref mut Int32, value :: Int32) {
+            *target = value;
+        }
+
+        pub fun main() :: Int32 {
+            let mut counter = Counter { count = 0 };
+            bump(ref mut counter);
+            bump(ref mut counter);
+            let mut n = 1;
+            set(ref mut n, 40);
+            let pointer = ptr mut n;
+            unsafe {
+                *pointer += 2;
+            }
+            counter.count * 100 + n
+        }",
+    );
+    assert_eq!(result, Ok("242".to_string()));
+}
+
+#[test]
+fn test_methods() {
+    let result = interpret(
+        "trait Shape {
+            fun area(self :: ref Self) :: Int32;
+            fun double(self :: ref Self) :: Int32 {
+                Shape::area(self) * 2
+            }
+        }
+
+        type Square { side :: Int32 }
+        type Circle { radius :: Int32 }
+
+        impl Shape for Square {
+            fun area(self :: ref Self) :: Int32 {
+                self.side * self.side
+            }
+        }
+
+        impl Shape for Circle {
+            fun area(self :: ref Self) :: Int32 {
+                3 * self.radius * self.radius
+            }
+
+            fun double(self :: ref Self) :: Int32 {
+                0
+            }
+        }
+
+        pub fun main() :: Int32 {
+            let square = Square { side = 3 };
+            let circle = Circle { radius = 1 };
+            square.double() * 100 + circle.double() * 10 + Shape::area(ref circle)
+        }",
+    );
+    assert_eq!(result, Ok("1803".to_string()));
+}
+
+#[test]
+fn test_trait_method_receivers() {
+    let result = interpret(
+        "trait Area {
+            fun area(self :: ref Self) :: Int32;
+            fun double(self :: ref Self) :: Int32 { self.area() * 2 }
+        }
+
+        type Square { side :: Int32 }
+
+        impl Area for Square {
+            fun area(self :: ref Self) :: Int32 { self.side * self.side }
+        }
+
+        fun twice<T>(x :: ref T) :: Int32 where T :: Area { x.double() }
+
+        pub fun main() :: Int32 {
+            let square = Square { side = 3 };
+            twice(ref square) + (ref square).area()
+        }",
+    );
+    assert_eq!(result, Ok("27".to_string()));
+}
+
+#[test]
+fn test_generators() {
+    let result = interpret(
+        "fun upto(n :: Int32) :: yield Int32 {
+            if n > 0 {
+                for x in upto(n - 1) {
+                    yield x;
+                }
+                yield n;
+            }
+        }
+
+        fun evens(numbers :: yield Int32) :: yield Int32 {
+            for x in numbers {
+                if x / 2 * 2 == x {
+                    yield x;
+                }
+            }
+        }
+
+        pub fun main() :: Int64 {
+            let mut sum = 0;
+            for x in evens(upto(10)) {
+                sum += x;
+            }
+            let mut last = 0;
+            for (i, x) of upto(5) {
+                last = i;
+            }
+            if sum == 30 { last } else { -1 }
+        }",
+    );
+    assert_eq!(result, Ok("4".to_string()));
+}
+
+#[test]
+fn test_closures() {
+    let result = interpret(
+        "fun apply(f :: fun(Int32) :: Int32, x :: Int32) :: Int32 {
+            f(x)
+        }
+
+        fun scale(by :: Int32) :: fun(Int32) :: Int32 {
+            fun(x :: Int32) :: Int32 { x * by }
+        }
+
+        pub fun main() :: Int32 {
+            let offset = 2;
+            let add = fun(x :: Int32) :: Int32 { x + offset };
+            let triple = scale(3);
+            let nested = fun() :: Int32 {
+                let inner = fun(y :: Int32) :: Int32 { apply(triple, y) + offset };
+                inner(4)
+            };
+            apply(add, 10) + triple(5) + nested()
+        }",
+    );
+    assert_eq!(result, Ok("41".to_string()));
+
+    let result = interpret(
+        "pub fun main() :: Int8 {
+            let limit :: Int8 = 100;
+            let grow = fun(x :: Int8) :: Int8 { x + limit };
+            grow(100)
+        }",
+    );
+    assert_eq!(
+        result,
+        Err((
+            "attempt to add with overflow".to_string(),
+            "x + limit".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_runtime_errors() {
+    let result = interpret(
+        "pub fun main() :: Int8 {
+            let x :: Int8 = 100;
+            x + x
+        }",
+    );
+    assert_eq!(
+        result,
+        Err((
+            "attempt to add with overflow".to_string(),
+            "x + x".to_string()
+        ))
+    );
+
+    let result = interpret(
+        "fun divide(a :: Int32, b :: Int32) :: Int32 {
+            a / b
+        }
+
+        pub fun main() :: Int32 {
+            divide(1, 0)
+        }",
+    );
+    assert_eq!(
+        result,
+        Err(("attempt to divide by zero".to_string(), "a / b".to_string()))
+    );
+
+    let result = interpret("fun main() {}");
+    assert_eq!(
+        result,
+        Err((
+            "`main` function is not public".to_string(),
+            "main".to_string()
+        ))
+    );
+
+    let result =
+        interpret("fun forever(n :: Int32) :: Int32 { forever(n) } pub fun main() { forever(1); }");
+    assert_eq!(
+        result,
+        Err(("stack overflow".to_string(), "forever(n)".to_string()))
+    );
+
+    let result = interpret(
+        "extern \"C\" fun abs(value :: Int32) :: Int32;
+        pub fun main() :: Int32 { unsafe { ret abs(-1); } }",
+    );
+    assert_eq!(
+        result,
+        Err((
+            "the foreign function `abs` can only be called by a program built with `--target=c`"
+                .to_string(),
+            "abs(-1)".to_string()
+        ))
+    );
+}
+
+/// Runs a module without requiring it to check, as an embedder skipping name resolution might
+fn interpret_unchecked(source: &str) -> Result<String, (String, String)> {
+    let module = parse(None, source).expect("failed to parse module");
+    let (defs, _) = shark_sema::check_module(&module);
+    let (types, _) = shark_typeck::check_module(&module, &defs);
+    let (bodies, _) = shark_lower::lower_module(&module);
+    run(
+        &module,
+        &defs,
+        &types,
+        &bodies,
+        Overflow::Trap,
+        Host::default(),
+    )
+    .map(|x| x.to_string())
+    .map_err(|x| error_source(source, x))
+}
+
+#[test]
+fn test_unresolved_names() {
+    let result = interpret_unchecked("pub fun main() :: Int32 { x }");
+    assert_eq!(
+        result,
+        Err(("unresolved name `x`".to_string(), "x".to_string()))
+    );
+
+    let result = interpret_unchecked(
+        "fun double(x :: Int32) :: Int32 { x * 2 }
+        pub fun main() :: Int32 { dobule(1) }",
+    );
+    assert_eq!(
+        result,
+        Err((
+            "cannot find function `dobule`".to_string(),
+            "dobule".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_overflow_modes() {
+    check_overflow_modes(|source, overflow| text_status(interpret_as(source, overflow)));
+}
+
+#[test]
+fn test_library_programs() {
+    for program in suite::programs() {
+        let checked = shark_testing::check(&program.source);
+        let bodies = checked.lower();
+        let (module, defs, types) = (&checked.module, &checked.defs, &checked.types);
+        let (host, captured) = Host::captured(program.args.clone());
+        let result = run(module, defs, types, &bodies, Overflow::Trap, host);
+        let result = result
+            .map(|x| x.as_integer().expect("`main` returns an integer"))
+            .map_err(|x| x.message);
+        assert_eq!(
+            suite::transcript(&captured.text(), result),
+            program.expected,
+            "{}",
+            program.name
+        );
+    }
+}
@@ -0,0 +1,307 @@
+//! The values a running program works with. Every binding lives in a [Slot], which references
+//! point into, and values are copied whenever they are read. The ownership checker has already
+//! made sure a moved value is never used again, so moving is the same as copying here
+
+use std::{cell::RefCell, cmp::Ordering, fmt::Display, rc::Rc};
+
+use shark_lex::token::LiteralKind;
+use shark_sema::ty::{AdtId, PrimitiveType};
+
+use crate::eval::GeneratorFrame;
+
+/// Where a binding keeps its value. A `let` within a loop makes a new slot every iteration, so a
+/// reference to the slot of an earlier iteration still sees the value it was taken from
+pub type Slot = Rc<RefCell<Value>>;
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Int8(i8),
+    UInt8(u8),
+    Int32(i32),
+    UInt32(u32),
+    Int64(i64),
+    UInt64(u64),
+    Float32(f32),
+    Float64(f64),
+    Bool(bool),
+    Char(char),
+    Str(Rc<str>),
+    Unit,
+    Tuple(Vec<Value>),
+    /// An instance of a `type`, with its fields in the order they are declared
+    Struct(AdtId, Vec<Value>),
+    /// A variant of an `enum`, identified by its index, along with its payload
+    Variant(AdtId, usize, Vec<Value>),
+    /// A `ref` or a `ptr`
+    Reference(Place),
+    Generator(Rc<RefCell<GeneratorFrame>>),
+    /// A closure, identified by the index of its body, along with the values it captured
+    Closure(usize, Rc<Vec<Value>>),
+    /// A `Vec` of the standard library, whose values are only copied when a shared one changes
+    List(AdtId, Rc<Vec<Value>>),
+    /// A `Map` of the standard library, with its entries in the order of their keys
+    Map(AdtId, Rc<Vec<(Value, Value)>>),
+}
+
+/// A value in memory: a slot, or a part of the value within it reached by following field,
+/// element and payload indices
+#[derive(Debug, Clone)]
+pub struct Place {
+    pub slot: Slot,
+    pub path: Vec<usize>,
+}
+
+impl Place {
+    pub fn new(slot: Slot) -> Self {
+        Self {
+            slot,
+            path: Vec::new(),
+        }
+    }
+
+    /// Gets the place of a field, element or payload of the value at this place
+    pub fn project(&self, index: usize) -> Self {
+        let mut path = self.path.clone();
+        path.push(index);
+        Self {
+            slot: self.slot.clone(),
+            path,
+        }
+    }
+
+    pub fn read(&self) -> Value {
+        let value = self.slot.borrow();
+        let mut current = &*value;
+        for index in &self.path {
+            current = &current.parts()[*index];
+        }
+        current.clone()
+    }
+
+    pub fn write(&self, new_value: Value) {
+        let mut value = self.slot.borrow_mut();
+        let mut current = &mut *value;
+        for index in &self.path {
+            current = &mut current.parts_mut()[*index];
+        }
+        *current = new_value;
+    }
+}
+
+impl Value {
+    /// Makes a value of a primitive type from a literal, which type checking has already made sure
+    /// fits within it
+    pub fn from_literal(literal: &LiteralKind, ty: PrimitiveType) -> Self {
+        let integer = match *literal {
+            LiteralKind::UInt8(x) => x as i128,
+            LiteralKind::Int8(x) => x as i128,
+            LiteralKind::UInt32(x) => x as i128,
+            LiteralKind::Int32(x) => x as i128,
+            LiteralKind::UInt64(x) => x as i128,
+            LiteralKind::Int64(x) => x as i128,
+            LiteralKind::Float32(x) => return Self::from_float(x as f64, ty),
+            LiteralKind::Float64(x) => return Self::from_float(x, ty),
+            LiteralKind::Str(x) => return Self::Str(x.as_str().into()),
+            LiteralKind::Char(x) => return Self::Char(x),
+            LiteralKind::Boolean(x) => return Self::Bool(x),
+        };
+        Self::from_integer(integer, ty).expect("literals are checked to fit in their type")
+    }
+
+    /// Makes an integer of a primitive type, returning [None] if it does not fit
+    pub fn from_integer(value: i128, ty: PrimitiveType) -> Option<Self> {
+        Some(match ty {
+            PrimitiveType::Int8 => Self::Int8(value.try_into().ok()?),
+            PrimitiveType::UInt8 => Self::UInt8(value.try_into().ok()?),
+            PrimitiveType::Int32 => Self::Int32(value.try_into().ok()?),
+            PrimitiveType::UInt32 => Self::UInt32(value.try_into().ok()?),
+            PrimitiveType::Int64 => Self::Int64(value.try_into().ok()?),
+            PrimitiveType::UInt64 => Self::UInt64(value.try_into().ok()?),
+            _ => return None,
+        })
+    }
+
+    /// Makes a `Float32` or a `Float64`, rounding the value if it is a `Float32`
+    pub fn from_float(value: f64, ty: PrimitiveType) -> Self {
+        match ty {
+            PrimitiveType::Float64 => Self::Float64(value),
+            _ => Self::Float32(value as f32),
+        }
+    }
+
+    /// Gets the primitive type of the value, if it is of one
+    pub fn primitive_type(&self) -> Option<PrimitiveType> {
+        Some(match self {
+            Self::Int8(_) => PrimitiveType::Int8,
+            Self::UInt8(_) => PrimitiveType::UInt8,
+            Self::Int32(_) => PrimitiveType::Int32,
+            Self::UInt32(_) => PrimitiveType::UInt32,
+            Self::Int64(_) => PrimitiveType::Int64,
+            Self::UInt64(_) => PrimitiveType::UInt64,
+            Self::Float32(_) => PrimitiveType::Float32,
+            Self::Float64(_) => PrimitiveType::Float64,
+            Self::Bool(_) => PrimitiveType::Bool,
+            Self::Char(_) => PrimitiveType::Char,
+            Self::Str(_) => PrimitiveType::Str,
+            _ => return None,
+        })
+    }
+
+    /// Gets the value of an integer, widened so that every integer type fits
+    pub fn as_integer(&self) -> Option<i128> {
+        Some(match *self {
+            Self::Int8(x) => x as i128,
+            Self::UInt8(x) => x as i128,
+            Self::Int32(x) => x as i128,
+            Self::UInt32(x) => x as i128,
+            Self::Int64(x) => x as i128,
+            Self::UInt64(x) => x as i128,
+            _ => return None,
+        })
+    }
+
+    pub fn as_float(&self) -> Option<f64> {
+        match *self {
+            Self::Float32(x) => Some(x as f64),
+            Self::Float64(x) => Some(x),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> bool {
+        match self {
+            Self::Bool(x) => *x,
+            _ => panic!("expected a `Bool`, found {:?}", self),
+        }
+    }
+
+    /// Gets the fields, elements or payload within the value
+    pub fn parts(&self) -> &[Value] {
+        match self {
+            Self::Tuple(parts) | Self::Struct(_, parts) | Self::Variant(_, _, parts) => parts,
+            _ => &[],
+        }
+    }
+
+    fn parts_mut(&mut self) -> &mut [Value] {
+        match self {
+            Self::Tuple(parts) | Self::Struct(_, parts) | Self::Variant(_, _, parts) => parts,
+            _ => &mut [],
+        }
+    }
+
+    /// Compares two values of the same type. Values of types which have no order, such as a
+    /// `type`, are only ever equal or not
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        if let (Some(x), Some(y)) = (self.as_integer(), other.as_integer()) {
+            return x.partial_cmp(&y);
+        }
+        if let (Some(x), Some(y)) = (self.as_float(), other.as_float()) {
+            return x.partial_cmp(&y);
+        }
+        match (self, other) {
+            (Self::Bool(x), Self::Bool(y)) => x.partial_cmp(y),
+            (Self::Char(x), Self::Char(y)) => x.partial_cmp(y),
+            (Self::Str(x), Self::Str(y)) => x.partial_cmp(y),
+            (Self::Unit, Self::Unit) => Some(Ordering::Equal),
+            (Self::Tuple(x), Self::Tuple(y)) | (Self::Struct(_, x), Self::Struct(_, y)) => {
+                equal_parts(x, y)
+            }
+            (Self::Variant(_, x, x_payload), Self::Variant(_, y, y_payload)) => match x == y {
+                true => equal_parts(x_payload, y_payload),
+                false => None,
+            },
+            (Self::Reference(x), Self::Reference(y)) => {
+                match Rc::ptr_eq(&x.slot, &y.slot) && x.path == y.path {
+                    true => Some(Ordering::Equal),
+                    false => None,
+                }
+            }
+            (Self::Generator(x), Self::Generator(y)) if Rc::ptr_eq(x, y) => Some(Ordering::Equal),
+            (Self::List(_, x), Self::List(_, y)) if x.len() == y.len() => equal_parts(x, y),
+            (Self::Map(_, x), Self::Map(_, y)) if x.len() == y.len() => {
+                let equal = x.iter().zip(y.iter()).all(|((a, b), (c, d))| {
+                    a.compare(c) == Some(Ordering::Equal) && b.compare(d) == Some(Ordering::Equal)
+                });
+                equal.then_some(Ordering::Equal)
+            }
+            _ => None,
+        }
+    }
+}
+
+fn equal_parts(left: &[Value], right: &[Value]) -> Option<Ordering> {
+    let equal = left
+        .iter()
+        .zip(right)
+        .all(|(x, y)| x.compare(y) == Some(Ordering::Equal));
+    equal.then_some(Ordering::Equal)
+}
+
+/// Writes the value out the way it would be written in the source, apart from the names of
+/// `type`s and `enum`s which are not known here
+impl Display for Value {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let list = |f: &mut std::fmt::Formatter<'_>, values: &[Value]| {
+            for (index, value) in values.iter().enumerate() {
+                if index > 0 {
+                    write!(f, ", ")?;
+                }
+                write!(f, "{}", value)?;
+            }
+            Ok(())
+        };
+        match self {
+            Self::Int8(x) => write!(f, "{}", x),
+            Self::UInt8(x) => write!(f, "{}", x),
+            Self::Int32(x) => write!(f, "{}", x),
+            Self::UInt32(x) => write!(f, "{}", x),
+            Self::Int64(x) => write!(f, "{}", x),
+            Self::UInt64(x) => write!(f, "{}", x),
+            Self::Float32(x) => write!(f, "{:?}", x),
+            Self::Float64(x) => write!(f, "{:?}", x),
+            Self::Bool(x) => write!(f, "{}", x),
+            Self::Char(x) => write!(f, "{}", x),
+            Self::Str(x) => write!(f, "{}", x),
+            Self::Unit => write!(f, "()"),
+            Self::Tuple(values) if values.len() == 1 => write!(f, "({},)", values[0]),
+            Self::Tuple(values) => {
+                write!(f, "(")?;
+                list(f, values)?;
+                write!(f, ")")
+            }
+            Self::Struct(_, values) => {
+                write!(f, "{{ ")?;
+                list(f, values)?;
+                write!(f, " }}")
+            }
+            Self::Variant(_, index, values) => {
+                write!(f, "#{}", index)?;
+                if !values.is_empty() {
+                    write!(f, "(")?;
+                    list(f, values)?;
+                    write!(f, ")")?;
+                }
+                Ok(())
+            }
+            Self::Reference(place) => write!(f, "ref {}", place.read()),
+            Self::Generator(_) => write!(f, "<generator>"),
+            Self::Closure(..) => write!(f, "<closure>"),
+            Self::List(_, values) => {
+                write!(f, "[")?;
+                list(f, values)?;
+                write!(f, "]")
+            }
+            Self::Map(_, entries) => {
+                write!(f, "{{")?;
+                for (index, (key, value)) in entries.iter().enumerate() {
+                    if index > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}: {}", key, value)?;
+                }
+                write!(f, "}}")
+            }
+        }
+    }
+}
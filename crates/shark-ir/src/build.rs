@@ -0,0 +1,2537 @@
+//! Builds the IR of every lowered function body. Every block of a body becomes a block of the
+//! function, and so does every branch within an expression. Scalar locals are kept in SSA form by
+//! giving every block a parameter for each of them, which each branch passes the current value of,
+//! except for locals whose address is taken, which live in an `alloca` so that references to them
+//! stay valid. Every other local lives in a `slot`
+//!
+//! This makes for many parameters which only ever receive the same value, which copy propagation
+//! removes
+//!
+//! Functions are built from the ones which are not generic, and every function they call is built
+//! for the generic arguments it is called with, under a name which spells them out such as
+//! `max<Int32>` or `Vec<Str>::join`. A closure is built as a function of its own taking the address
+//! of the values it captured, and a generator as a function making its frame and one resuming it,
+//! see [Builder]. The `extern` functions of the standard library are built out of calls into the
+//! [crate::runtime], see [crate::builtin]
+//!
+//! A program with a `main` also gets an [ENTRY] function, which the backends compiling programs
+//! start from
+
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use shark_core::{
+    source::Span,
+    symbol::{sym, Symbol},
+};
+use shark_lex::token::LiteralKind;
+use shark_lower::{
+    body::{LocalId, LocalKind, Statement, Terminator as BodyTerminator},
+    lower::visit_block,
+    Body,
+};
+use shark_parse::ast::{
+    BinaryOperator, Block as AstBlock, Expr, ExprKind, ForKind, Function as AstFunction, ItemKind,
+    Module as AstModule, NodeId, Pattern, PatternKind, StatementKind, UnaryOperator,
+};
+use shark_sema::{
+    generics::FunctionSig,
+    layout::Layout,
+    numeric::{Intrinsic, Overflow},
+    traits::{ImplDef, TraitId},
+    ty::{PrimitiveType, Type as SemaType},
+    ModuleDefs,
+};
+use shark_std::runtime::Builtin;
+use shark_typeck::{ty::Ty, TypeckResults};
+
+use crate::{
+    builtin::Helper,
+    ir::{
+        BinaryOp, Block, BlockId, Extern, Function, Inst, InstKind, Module, Target, Terminator,
+        Type, UnaryOp, Value,
+    },
+    layout::{match_ty, substitute, Layouts, Repr},
+    runtime::{self, RuntimeFunction},
+};
+
+/// The offsets within the frame of a generator of the function resuming it, the state it is in
+/// and whether it is running. The state of a generator which is done is -1
+pub const GENERATOR_RESUME: u64 = 0;
+pub const GENERATOR_STATE: u64 = 8;
+pub const GENERATOR_RUNNING: u64 = 12;
+/// How big the part of the frame of a generator before its locals is
+pub const GENERATOR_HEADER: u64 = 16;
+
+/// The name of the function calling `main`, which gives the exit code of the program: what `main`
+/// returned if it is an `Int32`, or 0
+pub const ENTRY: &str = "{main}";
+
+/// A function body to build for one set of generic arguments
+#[derive(Debug, Clone)]
+pub(crate) struct Instance<'b, 'ast> {
+    pub name: String,
+    pub body: &'b Body<'ast>,
+    /// What the generic parameters of the function stand for, along with `Self` within a method
+    pub mapping: HashMap<Symbol, Ty>,
+    pub parameters: Vec<Ty>,
+    pub return_type: Ty,
+    /// The types of the values a closure captured, which it is given the address of
+    pub captures: Option<Vec<Ty>>,
+}
+
+/// A function to build
+enum Job<'b, 'ast> {
+    Body(Instance<'b, 'ast>),
+    Helper(String, Helper),
+}
+
+/// An `impl` along with the bodies of its methods
+struct Implementation<'b, 'ast> {
+    def: &'b ImplDef,
+    /// The type it is for, with the generic parameters of the `impl` left in
+    self_type: Ty,
+    bodies: HashMap<Symbol, &'b Body<'ast>>,
+}
+
+/// The method a value of some type calls
+pub(crate) struct Method<'b, 'ast> {
+    implementation: usize,
+    signature: &'b FunctionSig,
+    /// The body of the method, or the default of its trait, which `extern` methods have none of
+    body: Option<&'b Body<'ast>>,
+    /// What the generic parameters of the `impl` and `Self` stand for
+    mapping: HashMap<Symbol, Ty>,
+}
+
+/// What a call calls
+#[derive(Debug, Clone)]
+pub(crate) enum Callee<'b> {
+    /// A function of the module, which is built if it has not been already. Calling a generator
+    /// function only makes its frame
+    Function {
+        name: String,
+        parameters: Vec<Ty>,
+        return_type: Ty,
+        generator: bool,
+    },
+    /// An `extern` function of the standard library
+    Builtin {
+        builtin: Builtin,
+        parameters: Vec<Ty>,
+        return_type: Ty,
+    },
+    /// An `extern "C"` function
+    Foreign(&'b FunctionSig),
+}
+
+impl Callee<'_> {
+    fn parameters(&self) -> Vec<Ty> {
+        match self {
+            Self::Function { parameters, .. } | Self::Builtin { parameters, .. } => {
+                parameters.clone()
+            }
+            Self::Foreign(signature) => signature
+                .parameters
+                .iter()
+                .map(|x| Ty::from_type(x, &HashMap::new()))
+                .collect(),
+        }
+    }
+}
+
+/// Builds every function of a module, along with every instance of a generic function it needs
+pub struct Builder<'b, 'ast> {
+    pub defs: &'b ModuleDefs,
+    pub types: &'b TypeckResults,
+    pub bodies: &'b [Body<'ast>],
+    /// What arithmetic does when it overflows
+    pub overflow: Overflow,
+    pub layouts: Layouts<'b>,
+    /// The body of every function which is not a method
+    functions: HashMap<Symbol, &'b Body<'ast>>,
+    impls: Vec<Implementation<'b, 'ast>>,
+    /// The bodies of the methods traits provide a default for
+    defaults: HashMap<(TraitId, Symbol), &'b Body<'ast>>,
+    jobs: VecDeque<Job<'b, 'ast>>,
+    /// The name of every function which has been asked for
+    requested: HashSet<String>,
+    externs: Vec<Extern>,
+    built: Vec<Function>,
+}
+
+impl<'b, 'ast> Builder<'b, 'ast> {
+    /// Collects every function and `impl` of a module, asking for every function and method
+    /// which is not generic to be built
+    pub fn new(
+        module: &AstModule,
+        defs: &'b ModuleDefs,
+        types: &'b TypeckResults,
+        bodies: &'b [Body<'ast>],
+        overflow: Overflow,
+    ) -> Self {
+        let mut builder = Self {
+            defs,
+            types,
+            bodies,
+            overflow,
+            layouts: Layouts::new(defs),
+            functions: HashMap::new(),
+            impls: Vec::new(),
+            defaults: HashMap::new(),
+            jobs: VecDeque::new(),
+            requested: HashSet::new(),
+            externs: Vec::new(),
+            built: Vec::new(),
+        };
+        let body_of =
+            |function: &AstFunction| bodies.iter().find(|x| std::ptr::eq(x.function, function));
+        for item in &module.items {
+            match &item.kind {
+                ItemKind::Function(function) => {
+                    if let Some(body) = body_of(function) {
+                        builder.functions.insert(function.name.symbol, body);
+                    }
+                }
+                ItemKind::Trait(trait_decl) => {
+                    let Some(trait_id) = defs.traits.trait_of_item(item.id) else {
+                        continue;
+                    };
+                    for method in &trait_decl.methods {
+                        if let Some(body) = body_of(method) {
+                            builder
+                                .defaults
+                                .insert((trait_id, method.name.symbol), body);
+                        }
+                    }
+                }
+                ItemKind::Impl(impl_decl) => {
+                    let Some(def) = defs.traits.impl_of_item(item.id) else {
+                        continue;
+                    };
+                    let bodies = impl_decl
+                        .methods
+                        .iter()
+                        .filter_map(|x| Some((x.name.symbol, body_of(x)?)))
+                        .collect();
+                    builder.impls.push(Implementation {
+                        def,
+                        self_type: builder.layouts.from_type(&def.self_type, &HashMap::new()),
+                        bodies,
+                    });
+                }
+                _ => {}
+            }
+        }
+
+        for item in &module.items {
+            let ItemKind::Function(function) = &item.kind else {
+                continue;
+            };
+            let Some(signature) = defs.functions.signature_of(item.id) else {
+                continue;
+            };
+            if builder.functions.contains_key(&function.name.symbol)
+                && signature.generics.params.is_empty()
+            {
+                builder.function(signature, &[], &Ty::Unit);
+            }
+        }
+        for index in 0..builder.impls.len() {
+            let implementation = &builder.impls[index];
+            if !implementation.def.generics.params.is_empty() {
+                continue;
+            }
+            let self_type = implementation.self_type.clone();
+            let mut names: Vec<Symbol> = implementation
+                .def
+                .methods
+                .iter()
+                .map(|x| x.name.symbol)
+                .collect();
+            if let Some(trait_id) = implementation.def.trait_id {
+                let trait_def = defs.traits.trait_def(trait_id);
+                for method in &trait_def.methods {
+                    if !names.contains(&method.name.symbol) {
+                        names.push(method.name.symbol);
+                    }
+                }
+            }
+            for name in names {
+                let Some(method) = builder.method_of(index, &self_type, name) else {
+                    continue;
+                };
+                if method.body.is_some() && method.signature.generics.params.is_empty() {
+                    builder.method(method, &[], &Ty::Unit);
+                }
+            }
+        }
+        builder
+    }
+
+    /// Builds every function which has been asked for, and every function they need in turn
+    pub fn finish(mut self) -> Module {
+        while let Some(job) = self.jobs.pop_front() {
+            let function = match job {
+                Job::Body(instance) => {
+                    let functions = FunctionBuilder::build(&mut self, &instance);
+                    self.built.extend(functions);
+                    continue;
+                }
+                Job::Helper(name, helper) => FunctionBuilder::helper(&mut self, name, helper),
+            };
+            self.built.push(function);
+        }
+        if let Some(entry) = self.entry() {
+            self.built.push(entry);
+        }
+        Module {
+            externs: self.externs,
+            functions: self.built,
+        }
+    }
+
+    /// Builds the [ENTRY] of the program, giving `main` room for its result if it is in memory
+    fn entry(&mut self) -> Option<Function> {
+        let main = self.built.iter().find(|x| x.name == "main")?;
+        let (params, return_type) = (main.params(), main.return_type);
+        let signature = self.defs.functions.lookup(Symbol::intern("main"))?;
+        let layout = self
+            .layouts
+            .layout(&Ty::from_type(&signature.return_type, &HashMap::new()));
+        let (mut this, _) = FunctionBuilder::new(self, ENTRY.to_string(), &[], Some(Type::Int32));
+        let arguments = params
+            .iter()
+            .map(|_| this.value_of(InstKind::Slot(layout.size, layout.align), Type::Ptr))
+            .collect();
+        let result = this.inst(InstKind::Call("main".to_string(), arguments), return_type);
+        let code = match (result, return_type) {
+            (Some(result), Some(Type::Int32)) => result,
+            _ => this.constant(LiteralKind::Int32(0), Type::Int32),
+        };
+        this.terminate(Terminator::Return(Some(code)));
+        Some(this.function)
+    }
+
+    /// Asks for a function to be built unless it already has been, giving its name
+    fn request(&mut self, name: String, job: impl FnOnce(String) -> Job<'b, 'ast>) -> String {
+        if self.requested.insert(name.clone()) {
+            self.jobs.push_back(job(name.clone()));
+        }
+        name
+    }
+
+    /// Asks for one of the functions the builder writes itself, giving its name
+    pub(crate) fn helper(&mut self, helper: Helper) -> String {
+        let name = helper.name(&self.layouts);
+        self.request(name, |name| Job::Helper(name, helper))
+    }
+
+    /// Declares a function the module calls without defining it, giving its name
+    pub(crate) fn declare(&mut self, declaration: Extern) -> String {
+        let name = declaration.name.clone();
+        if self.externs.iter().all(|x| x.name != name) {
+            self.externs.push(declaration);
+        }
+        name
+    }
+
+    /// Gets the method a value of a type calls, either from the `impl` at an index or from any,
+    /// preferring the `impl`s for the type itself over the ones for any type
+    fn method_of(&self, index: usize, ty: &Ty, name: Symbol) -> Option<Method<'b, 'ast>> {
+        let implementation = &self.impls[index];
+        let mut mapping = HashMap::new();
+        if !match_ty(&implementation.self_type, ty, &mut mapping) {
+            return None;
+        }
+        mapping.insert(sym::SELF_TYPE, ty.clone());
+        if let Some(signature) = implementation.def.method(name) {
+            return Some(Method {
+                implementation: index,
+                signature,
+                body: implementation.bodies.get(&name).copied(),
+                mapping,
+            });
+        }
+        let trait_id = implementation.def.trait_id?;
+        let signature = self.defs.traits.trait_def(trait_id).method(name)?;
+        Some(Method {
+            implementation: index,
+            signature,
+            body: Some(*self.defaults.get(&(trait_id, name))?),
+            mapping,
+        })
+    }
+
+    /// Finds the method a value of a type calls, optionally only looking at the `impl`s of one
+    /// trait
+    pub(crate) fn find_method(
+        &self,
+        ty: &Ty,
+        name: Symbol,
+        trait_id: Option<TraitId>,
+    ) -> Option<Method<'b, 'ast>> {
+        let candidates = (0..self.impls.len())
+            .filter(|x| trait_id.is_none_or(|id| self.impls[*x].def.trait_id == Some(id)));
+        let (blanket, specific): (Vec<usize>, Vec<usize>) =
+            candidates.partition(|x| matches!(self.impls[*x].self_type, Ty::Param(_)));
+        specific
+            .into_iter()
+            .chain(blanket)
+            .find_map(|x| self.method_of(x, ty, name))
+    }
+
+    /// Resolves a call of a method, building it for the types it is called with
+    pub(crate) fn method(
+        &mut self,
+        method: Method<'b, 'ast>,
+        arguments: &[Ty],
+        return_type: &Ty,
+    ) -> Callee<'b> {
+        let implementation = &self.impls[method.implementation];
+        let def = implementation.def;
+        let self_type = implementation.self_type.clone();
+        let mut mapping = method.mapping;
+        let generics: Vec<Symbol> = def
+            .generics
+            .params
+            .iter()
+            .chain(&method.signature.generics.params)
+            .map(|x| x.symbol)
+            .collect();
+        let (parameters, return_type) = infer(
+            &self.layouts,
+            method.signature,
+            &generics,
+            &mut mapping,
+            arguments,
+            return_type,
+        );
+        let self_type = substitute(&self_type, &mapping);
+        if method.signature.is_extern {
+            let owner = match &def.self_type {
+                SemaType::Adt(id, _) => self.defs.types.adt(*id).name.symbol.to_string(),
+                SemaType::Primitive(primitive) => primitive.to_string(),
+                _ => String::new(),
+            };
+            let name = method.signature.name.symbol;
+            let builtin = Builtin::lookup(Some(&owner), name.as_str())
+                .unwrap_or_else(|| panic!("`{}::{}` is not a builtin", owner, name));
+            return Callee::Builtin {
+                builtin,
+                parameters,
+                return_type,
+            };
+        }
+        let owner = match def.trait_id {
+            Some(trait_id) => format!(
+                "<{} as {}>",
+                self.layouts.name(&self_type),
+                self.defs.traits.trait_def(trait_id).name.symbol
+            ),
+            None => self.layouts.name(&self_type),
+        };
+        let name = format!(
+            "{}::{}{}",
+            owner,
+            method.signature.name.symbol,
+            self.arguments_of(&method.signature.generics.params, &mapping)
+        );
+        let body = method
+            .body
+            .expect("methods which are not `extern` have a body");
+        self.instance(name, body, mapping, parameters, return_type)
+    }
+
+    /// Resolves a call of a function which is not a method, building it for the types it is
+    /// called with
+    pub(crate) fn function(
+        &mut self,
+        signature: &'b FunctionSig,
+        arguments: &[Ty],
+        return_type: &Ty,
+    ) -> Callee<'b> {
+        if signature.abi.is_some() {
+            return Callee::Foreign(signature);
+        }
+        let mut mapping = HashMap::new();
+        let generics: Vec<Symbol> = signature.generics.params.iter().map(|x| x.symbol).collect();
+        let (parameters, return_type) = infer(
+            &self.layouts,
+            signature,
+            &generics,
+            &mut mapping,
+            arguments,
+            return_type,
+        );
+        let name = signature.name.symbol;
+        if signature.is_extern {
+            let builtin = Builtin::lookup(None, name.as_str())
+                .unwrap_or_else(|| panic!("`{}` is not a builtin", name));
+            return Callee::Builtin {
+                builtin,
+                parameters,
+                return_type,
+            };
+        }
+        let body = self.functions[&name];
+        let name = format!(
+            "{}{}",
+            name,
+            self.arguments_of(&signature.generics.params, &mapping)
+        );
+        self.instance(name, body, mapping, parameters, return_type)
+    }
+
+    /// Asks for a body to be built for what its generic parameters stand for
+    fn instance(
+        &mut self,
+        name: String,
+        body: &'b Body<'ast>,
+        mapping: HashMap<Symbol, Ty>,
+        parameters: Vec<Ty>,
+        return_type: Ty,
+    ) -> Callee<'b> {
+        let callee = Callee::Function {
+            name: name.clone(),
+            parameters: parameters.clone(),
+            return_type: return_type.clone(),
+            generator: body.generator.is_some(),
+        };
+        self.request(name, |name| {
+            Job::Body(Instance {
+                name,
+                body,
+                mapping,
+                parameters,
+                return_type,
+                captures: None,
+            })
+        });
+        callee
+    }
+
+    /// Spells out what generic parameters stand for, which is nothing if there are none
+    fn arguments_of(
+        &self,
+        generics: &[shark_parse::ast::Ident],
+        mapping: &HashMap<Symbol, Ty>,
+    ) -> String {
+        if generics.is_empty() {
+            return String::new();
+        }
+        let names: Vec<String> = generics
+            .iter()
+            .map(|x| self.layouts.name(&mapping[&x.symbol]))
+            .collect();
+        format!("<{}>", names.join(", "))
+    }
+}
+
+/// Works out what the generic parameters of a function stand for from the types of the arguments
+/// it is called with and of the result the call gives, giving the types of its parameters and
+/// result. Parameters which nothing decides stand for `()`
+fn infer(
+    layouts: &Layouts,
+    signature: &FunctionSig,
+    generics: &[Symbol],
+    mapping: &mut HashMap<Symbol, Ty>,
+    arguments: &[Ty],
+    return_type: &Ty,
+) -> (Vec<Ty>, Ty) {
+    let pairs = signature
+        .parameters
+        .iter()
+        .zip(arguments)
+        .chain(std::iter::once((&signature.return_type, return_type)));
+    for (pattern, ty) in pairs {
+        if matches!(ty, Ty::Never | Ty::Var(_) | Ty::Error) {
+            continue;
+        }
+        let mut bindings = HashMap::new();
+        if match_ty(&layouts.from_type(pattern, mapping), ty, &mut bindings) {
+            for (name, ty) in bindings {
+                mapping.entry(name).or_insert(ty);
+            }
+        }
+    }
+    for generic in generics {
+        mapping.entry(*generic).or_insert(Ty::Unit);
+    }
+    let parameters = signature
+        .parameters
+        .iter()
+        .map(|x| layouts.from_type(x, mapping))
+        .collect();
+    (
+        parameters,
+        layouts.from_type(&signature.return_type, mapping),
+    )
+}
+
+/// Gets the constant of a type with the value of a literal, which the type checker has already
+/// made sure fits
+pub fn constant(literal: &LiteralKind, ty: Type) -> LiteralKind {
+    let integer = match *literal {
+        LiteralKind::UInt8(x) => x as i128,
+        LiteralKind::Int8(x) => x as i128,
+        LiteralKind::UInt32(x) => x as i128,
+        LiteralKind::Int32(x) => x as i128,
+        LiteralKind::UInt64(x) => x as i128,
+        LiteralKind::Int64(x) => x as i128,
+        LiteralKind::Float32(x) => return float(x as f64, ty),
+        LiteralKind::Float64(x) => return float(x, ty),
+        LiteralKind::Char(_) | LiteralKind::Boolean(_) | LiteralKind::Str(_) => return *literal,
+    };
+    match ty {
+        Type::Int8 => LiteralKind::Int8(integer as i8),
+        Type::UInt8 => LiteralKind::UInt8(integer as u8),
+        Type::Int32 => LiteralKind::Int32(integer as i32),
+        Type::UInt32 => LiteralKind::UInt32(integer as u32),
+        Type::Int64 => LiteralKind::Int64(integer as i64),
+        Type::UInt64 => LiteralKind::UInt64(integer as u64),
+        _ => float(integer as f64, ty),
+    }
+}
+
+fn float(value: f64, ty: Type) -> LiteralKind {
+    match ty {
+        Type::Float32 => LiteralKind::Float32(value as f32),
+        _ => LiteralKind::Float64(value),
+    }
+}
+
+/// Gets the constant zero of a type, which is `false` for a `bool`
+pub fn zero(ty: Type) -> Option<LiteralKind> {
+    Some(match ty {
+        Type::Bool => LiteralKind::Boolean(false),
+        Type::Char => LiteralKind::Char('\0'),
+        Type::Ptr => return None,
+        _ => constant(&LiteralKind::Int32(0), ty),
+    })
+}
+
+/// Maps an operator other than `&&` and `||` to the instruction applying it
+fn binary_op(operator: BinaryOperator) -> BinaryOp {
+    match operator {
+        BinaryOperator::Add => BinaryOp::Add,
+        BinaryOperator::Subtract => BinaryOp::Sub,
+        BinaryOperator::Multiply => BinaryOp::Mul,
+        BinaryOperator::Divide => BinaryOp::Div,
+        BinaryOperator::ShiftLeft => BinaryOp::Shl,
+        BinaryOperator::ShiftRight => BinaryOp::Shr,
+        BinaryOperator::BitwiseAnd => BinaryOp::And,
+        BinaryOperator::Greater => BinaryOp::Gt,
+        BinaryOperator::Lesser => BinaryOp::Lt,
+        BinaryOperator::GreaterOrEqual => BinaryOp::Ge,
+        BinaryOperator::LessOrEqual => BinaryOp::Le,
+        BinaryOperator::EqualTo => BinaryOp::Eq,
+        BinaryOperator::NotEqual => BinaryOp::Ne,
+        BinaryOperator::And | BinaryOperator::Or => unreachable!("`{}` branches", operator),
+    }
+}
+
+/// Where a local lives, or where any value in memory is
+#[derive(Debug, Clone)]
+pub(crate) enum Place {
+    /// A value of type `()`, which has nowhere to live
+    Unit,
+    /// A local in SSA form, with the index of its variable
+    Variable(usize),
+    /// A value at an address
+    Address(Value, Ty),
+}
+
+/// Builds one function: the instance of a body, or a helper
+pub(crate) struct FunctionBuilder<'f, 'b, 'ast> {
+    pub builder: &'f mut Builder<'b, 'ast>,
+    pub function: Function,
+    /// The type of every variable
+    variables: Vec<Type>,
+    /// The block instructions are added to, and the current value of every variable within it
+    current: BlockId,
+    values: Vec<Value>,
+    /// The body being built, which helpers have none of, along with what its generic parameters
+    /// stand for
+    body: Option<&'b Body<'ast>>,
+    /// The name of the instance being built, after which its closures are named
+    name: String,
+    mapping: HashMap<Symbol, Ty>,
+    locals: Vec<Place>,
+    local_types: Vec<Ty>,
+    blocks: Vec<BlockId>,
+    return_type: Ty,
+    /// Where a result which lives in memory is stored
+    sret: Option<Value>,
+    /// Within the function resuming a generator, its frame and where the values it yields go
+    generator: Option<(Value, Value)>,
+}
+
+impl<'f, 'b, 'ast> FunctionBuilder<'f, 'b, 'ast> {
+    /// Starts a function taking parameters of the types given, whose values are given back
+    pub(crate) fn new(
+        builder: &'f mut Builder<'b, 'ast>,
+        name: String,
+        params: &[Type],
+        return_type: Option<Type>,
+    ) -> (Self, Vec<Value>) {
+        let mut function = Function {
+            name: name.clone(),
+            return_type,
+            blocks: vec![Block {
+                params: Vec::new(),
+                insts: Vec::new(),
+                terminator: Terminator::Unreachable,
+            }],
+            next_value: 0,
+        };
+        let values: Vec<Value> = params
+            .iter()
+            .map(|ty| {
+                let value = function.new_value();
+                function.blocks[0].params.push((value, *ty));
+                value
+            })
+            .collect();
+        let this = Self {
+            builder,
+            function,
+            variables: Vec::new(),
+            current: BlockId::ENTRY,
+            values: Vec::new(),
+            body: None,
+            name,
+            mapping: HashMap::new(),
+            locals: Vec::new(),
+            local_types: Vec::new(),
+            blocks: Vec::new(),
+            return_type: Ty::Unit,
+            sret: None,
+            generator: None,
+        };
+        (this, values)
+    }
+
+    /// Builds an instance of a body, which is two functions for a generator: one making its frame
+    /// and one resuming it
+    fn build(builder: &mut Builder<'b, 'ast>, instance: &Instance<'b, 'ast>) -> Vec<Function> {
+        let body = instance.body;
+        let parameter_types = Self::parameter_types(builder, instance);
+        let Some(generator) = &body.generator else {
+            let return_repr = builder.layouts.repr(&instance.return_type);
+            let mut params = Vec::new();
+            if return_repr == Repr::Memory {
+                params.push(Type::Ptr);
+            }
+            if instance.captures.is_some() {
+                params.push(Type::Ptr);
+            }
+            let return_type = match return_repr {
+                Repr::Scalar(ty) => Some(ty),
+                _ => None,
+            };
+            let (mut this, mut values) =
+                FunctionBuilder::new(builder, instance.name.clone(), &params, return_type);
+            this.start(instance);
+            if return_repr == Repr::Memory {
+                this.sret = Some(values.remove(0));
+            }
+            let environment = instance.captures.as_ref().map(|_| values.remove(0));
+            this.locals(instance, environment);
+            this.body(body);
+            return vec![this.function];
+        };
+
+        // The function making the frame, copying what the generator starts with into it
+        let resume = format!("{}::resume", instance.name);
+        let layout = Self::frame(builder, &parameter_types);
+        let mut params = Vec::new();
+        if instance.captures.is_some() {
+            params.push(Type::Ptr);
+        }
+        let (mut this, values) =
+            FunctionBuilder::new(builder, instance.name.clone(), &params, Some(Type::Ptr));
+        this.start(instance);
+        let size = this.int64(layout.size as i64);
+        let frame = this
+            .call(&runtime::ALLOC, vec![size])
+            .expect("allocating gives an address");
+        let address = this.value_of(InstKind::FuncAddr(resume.clone()), Type::Ptr);
+        this.store_at(frame, GENERATOR_RESUME, address);
+        // The first field is the header
+        let offsets = &layout_offsets(&layout)[1..];
+        for (index, local) in body.locals.iter().enumerate() {
+            let ty = parameter_types[index].clone();
+            let to = this.offset(frame, offsets[index]);
+            match local.kind {
+                LocalKind::Parameter => {
+                    let param = this.ir_type(&ty).map(|ir_type| {
+                        let value = this.function.new_value();
+                        this.function.blocks[0].params.push((value, ir_type));
+                        value
+                    });
+                    this.store_value(to, param, &ty);
+                }
+                LocalKind::Capture => {
+                    let environment = values[0];
+                    let capture = body.locals[..index]
+                        .iter()
+                        .filter(|x| x.kind == LocalKind::Capture)
+                        .count();
+                    let captures = instance.captures.as_ref().expect("closures capture");
+                    let from = this.capture(environment, captures, capture);
+                    this.copy_deep(to, from, &ty);
+                }
+                _ => {}
+            }
+        }
+        this.function.blocks[0].terminator = Terminator::Return(Some(frame));
+        let start = this.function;
+
+        // The function resuming it, which continues from the block of the state it is in
+        let (mut this, values) =
+            FunctionBuilder::new(builder, resume, &[Type::Ptr, Type::Ptr], Some(Type::Bool));
+        this.start(instance);
+        let (frame, out) = (values[0], values[1]);
+        this.generator = Some((frame, out));
+        for (index, ty) in parameter_types.iter().enumerate() {
+            let address = this.offset(frame, offsets[index]);
+            this.locals.push(Place::Address(address, ty.clone()));
+            this.local_types.push(ty.clone());
+        }
+        let running = this.bool_const(true);
+        this.store_at(frame, GENERATOR_RUNNING, running);
+        let state = this.load_at(frame, GENERATOR_STATE, Type::Int32);
+        this.blocks = body.blocks.iter().map(|_| this.new_block(&[])).collect();
+        for (index, block) in generator.states.iter().enumerate() {
+            let expected = this.constant(LiteralKind::Int32(index as i32), Type::Int32);
+            let matches =
+                this.value_of(InstKind::Binary(BinaryOp::Eq, state, expected), Type::Bool);
+            let next = this.new_block(&[]);
+            this.branch(matches, this.blocks[block.0 as usize], next);
+            this.switch_to(next);
+        }
+        this.block_mut().terminator = Terminator::Unreachable;
+        this.blocks_of(body);
+        vec![start, this.function]
+    }
+
+    /// Sets up what building any instance of a body needs
+    fn start(&mut self, instance: &Instance<'b, 'ast>) {
+        self.body = Some(instance.body);
+        self.name = instance.name.clone();
+        self.mapping = instance.mapping.clone();
+        self.return_type = instance.return_type.clone();
+    }
+
+    /// Gets the type of every local of an instance
+    fn parameter_types(builder: &Builder<'b, 'ast>, instance: &Instance<'b, 'ast>) -> Vec<Ty> {
+        let body = instance.body;
+        let mut captures = instance.captures.iter().flatten();
+        body.locals
+            .iter()
+            .enumerate()
+            .map(|(index, local)| match local.kind {
+                LocalKind::Parameter => instance.parameters[index].clone(),
+                LocalKind::Capture => captures.next().cloned().unwrap_or(Ty::Unit),
+                LocalKind::Let(id) | LocalKind::Binding(id) => match builder.types.type_of(id) {
+                    Some(ty) => builder.layouts.erase(&substitute(ty, &instance.mapping)),
+                    None => Ty::Unit,
+                },
+                LocalKind::Iterator => Ty::Generator(Box::new(Ty::Unit)),
+                LocalKind::Counter => Ty::Primitive(PrimitiveType::Int64),
+            })
+            .collect()
+    }
+
+    /// Lays out the frame of a generator: its header followed by every local
+    fn frame(builder: &Builder<'b, 'ast>, locals: &[Ty]) -> Layout {
+        let header = Layout {
+            size: GENERATOR_HEADER,
+            align: 8,
+            kind: shark_sema::layout::LayoutKind::Scalar,
+        };
+        let fields: Vec<Layout> = std::iter::once(header)
+            .chain(locals.iter().map(|x| builder.layouts.layout(x)))
+            .collect();
+        Layout::structure(&fields)
+    }
+
+    /// Gives every local of a body which is not a generator a place. Parameters are taken from
+    /// the entry block, and captured values are copied out of the environment of the closure
+    fn locals(&mut self, instance: &Instance<'b, 'ast>, environment: Option<Value>) {
+        let body = instance.body;
+        let types = Self::parameter_types(self.builder, instance);
+        let mut addressed = Vec::new();
+        let typeck = self.builder.types;
+        if let Some(block) = &body.function.body {
+            visit_block(block, &mut |expr| {
+                let operand = match &expr.kind {
+                    ExprKind::Reference { operand, .. } => &**operand,
+                    // Intrinsics take their receiver by value
+                    ExprKind::Call { .. } if typeck.intrinsic_of(expr.id).is_some() => return,
+                    ExprKind::Call { callee, .. } => match &callee.kind {
+                        ExprKind::Field { object, .. } => &**object,
+                        _ => return,
+                    },
+                    _ => return,
+                };
+                if let Some(local) = body.names.get(&operand.id) {
+                    addressed.push(*local);
+                }
+            });
+        }
+        let mut capture = 0;
+        for (index, local) in body.locals.iter().enumerate() {
+            let ty = types[index].clone();
+            let repr = self.builder.layouts.repr(&ty);
+            let initial = match (repr, local.kind) {
+                (Repr::Unit, _) => None,
+                (Repr::Scalar(ir_type), LocalKind::Parameter) => {
+                    let value = self.function.new_value();
+                    self.function.blocks[0].params.push((value, ir_type));
+                    Some(value)
+                }
+                (Repr::Memory, LocalKind::Parameter) => {
+                    let value = self.function.new_value();
+                    self.function.blocks[0].params.push((value, Type::Ptr));
+                    self.locals.push(Place::Address(value, ty.clone()));
+                    self.local_types.push(ty);
+                    continue;
+                }
+                (_, LocalKind::Capture) => {
+                    let environment = environment.expect("closures have an environment");
+                    let captures = instance.captures.as_ref().expect("closures capture");
+                    let from = self.capture(environment, captures, capture);
+                    capture += 1;
+                    match repr {
+                        Repr::Scalar(ir_type) => Some(self.value_of(InstKind::Load(from), ir_type)),
+                        _ => {
+                            let to = self.temporary(&ty);
+                            self.copy_deep(to, from, &ty);
+                            self.locals.push(Place::Address(to, ty.clone()));
+                            self.local_types.push(ty);
+                            continue;
+                        }
+                    }
+                }
+                (Repr::Scalar(ir_type), _) => Some(self.zero(ir_type)),
+                (Repr::Memory, _) => {
+                    let address = self.temporary(&ty);
+                    self.locals.push(Place::Address(address, ty.clone()));
+                    self.local_types.push(ty);
+                    continue;
+                }
+            };
+            let place = match (repr, initial) {
+                (Repr::Scalar(ir_type), Some(initial))
+                    if addressed.contains(&LocalId(index as u32)) =>
+                {
+                    let address = self.value_of(InstKind::Alloca(ir_type), Type::Ptr);
+                    self.effect(InstKind::Store(address, initial));
+                    Place::Address(address, ty.clone())
+                }
+                (Repr::Scalar(ir_type), Some(initial)) => {
+                    self.variables.push(ir_type);
+                    self.values.push(initial);
+                    Place::Variable(self.variables.len() - 1)
+                }
+                _ => Place::Unit,
+            };
+            self.locals.push(place);
+            self.local_types.push(ty);
+        }
+    }
+
+    /// Gets the address of a captured value within the environment of a closure
+    fn capture(&mut self, environment: Value, captures: &[Ty], index: usize) -> Value {
+        let layouts: Vec<Layout> = captures
+            .iter()
+            .map(|x| self.builder.layouts.layout(x))
+            .collect();
+        let offsets = layout_offsets(&Layout::structure(&layouts));
+        self.offset(environment, offsets[index])
+    }
+
+    /// Builds the blocks of a body which is not a generator
+    fn body(&mut self, body: &'b Body<'ast>) {
+        self.blocks = body.blocks.iter().map(|_| self.new_block(&[])).collect();
+        self.jump(self.blocks[0], Vec::new());
+        self.blocks_of(body);
+    }
+
+    fn blocks_of(&mut self, body: &'b Body<'ast>) {
+        for (index, block) in body.blocks.iter().enumerate() {
+            self.switch_to(self.blocks[index]);
+            for statement in &block.statements {
+                self.statement(statement);
+            }
+            self.terminator(&block.terminator);
+        }
+    }
+
+    fn body_of(&self) -> &'b Body<'ast> {
+        self.body.expect("only bodies have expressions")
+    }
+
+    pub(crate) fn ty(&self, id: NodeId) -> Ty {
+        match self.builder.types.type_of(id) {
+            Some(ty) => self.builder.layouts.erase(&substitute(ty, &self.mapping)),
+            None => Ty::Unit,
+        }
+    }
+
+    pub(crate) fn repr(&self, ty: &Ty) -> Repr {
+        self.builder.layouts.repr(ty)
+    }
+
+    pub(crate) fn ir_type(&self, ty: &Ty) -> Option<Type> {
+        self.builder.layouts.ir_type(ty)
+    }
+
+    fn block_mut(&mut self) -> &mut Block {
+        self.function.block_mut(self.current)
+    }
+
+    /// Adds an instruction to the current block, giving its result if it has a type
+    pub(crate) fn inst(&mut self, kind: InstKind, ty: Option<Type>) -> Option<Value> {
+        self.inst_at(kind, ty, None)
+    }
+
+    /// Adds an instruction which reports the runtime errors it may stop the program with at a
+    /// place
+    pub(crate) fn inst_at(
+        &mut self,
+        kind: InstKind,
+        ty: Option<Type>,
+        at: Option<Span>,
+    ) -> Option<Value> {
+        let result = ty.map(|ty| (self.function.new_value(), ty));
+        let mut inst = Inst::new(result, kind);
+        inst.at = at;
+        self.block_mut().insts.push(inst);
+        result.map(|(value, _)| value)
+    }
+
+    pub(crate) fn value_of(&mut self, kind: InstKind, ty: Type) -> Value {
+        self.inst(kind, Some(ty))
+            .expect("an instruction with a type gives a value")
+    }
+
+    pub(crate) fn effect(&mut self, kind: InstKind) {
+        self.inst(kind, None);
+    }
+
+    pub(crate) fn constant(&mut self, literal: LiteralKind, ty: Type) -> Value {
+        self.value_of(InstKind::Const(literal), ty)
+    }
+
+    pub(crate) fn int64(&mut self, value: i64) -> Value {
+        self.constant(LiteralKind::Int64(value), Type::Int64)
+    }
+
+    pub(crate) fn bool_const(&mut self, value: bool) -> Value {
+        self.constant(LiteralKind::Boolean(value), Type::Bool)
+    }
+
+    fn zero(&mut self, ty: Type) -> Value {
+        let kind = match zero(ty) {
+            Some(literal) => InstKind::Const(literal),
+            // There is no constant address, so any will do for a value which is never used
+            None => InstKind::Alloca(Type::Int8),
+        };
+        self.value_of(kind, ty)
+    }
+
+    /// Makes up a value of a type, for expressions which do not finish
+    pub(crate) fn zero_of(&mut self, ty: &Ty) -> Option<Value> {
+        match self.repr(ty) {
+            Repr::Unit => None,
+            Repr::Scalar(ir_type) => Some(self.zero(ir_type)),
+            Repr::Memory => Some(self.temporary(ty)),
+        }
+    }
+
+    /// Makes room in the frame for a value of a type, giving its address
+    pub(crate) fn temporary(&mut self, ty: &Ty) -> Value {
+        let layout = self.builder.layouts.layout(ty);
+        self.value_of(InstKind::Slot(layout.size, layout.align), Type::Ptr)
+    }
+
+    /// Adds a number of bytes to an address
+    pub(crate) fn offset(&mut self, address: Value, offset: u64) -> Value {
+        if offset == 0 {
+            return address;
+        }
+        let offset = self.int64(offset as i64);
+        self.value_of(InstKind::Offset(address, offset), Type::Ptr)
+    }
+
+    pub(crate) fn load_at(&mut self, address: Value, offset: u64, ty: Type) -> Value {
+        let address = self.offset(address, offset);
+        self.value_of(InstKind::Load(address), ty)
+    }
+
+    pub(crate) fn store_at(&mut self, address: Value, offset: u64, value: Value) {
+        let address = self.offset(address, offset);
+        self.effect(InstKind::Store(address, value));
+    }
+
+    /// Copies a number of bytes
+    pub(crate) fn mem_copy(&mut self, to: Value, from: Value, size: u64) {
+        if size > 0 {
+            let size = self.int64(size as i64);
+            self.effect(InstKind::MemCopy(to, from, size));
+        }
+    }
+
+    /// Gets the value of a type at an address, which is the address itself for a value which
+    /// lives in memory
+    pub(crate) fn load_value(&mut self, address: Value, ty: &Ty) -> Option<Value> {
+        match self.repr(ty) {
+            Repr::Unit => None,
+            Repr::Scalar(ir_type) => Some(self.value_of(InstKind::Load(address), ir_type)),
+            Repr::Memory => Some(address),
+        }
+    }
+
+    /// Stores a value of a type at an address, copying a value which lives in memory
+    pub(crate) fn store_value(&mut self, address: Value, value: Option<Value>, ty: &Ty) {
+        let Some(value) = value else {
+            return;
+        };
+        match self.repr(ty) {
+            Repr::Unit => {}
+            Repr::Scalar(_) => self.effect(InstKind::Store(address, value)),
+            Repr::Memory => {
+                let size = self.builder.layouts.layout(ty).size;
+                self.mem_copy(address, value, size);
+            }
+        }
+    }
+
+    /// Copies a value which lives in memory, so that it can not change under whoever has it
+    pub(crate) fn owned(&mut self, value: Option<Value>, ty: &Ty) -> Option<Value> {
+        match self.repr(ty) {
+            Repr::Memory => {
+                let copy = self.temporary(ty);
+                self.store_value(copy, value, ty);
+                Some(copy)
+            }
+            _ => value,
+        }
+    }
+
+    /// Gets the address of a value, storing a scalar in the frame to have one
+    pub(crate) fn spill(&mut self, value: Option<Value>, ty: &Ty) -> Value {
+        match (self.repr(ty), value) {
+            (Repr::Memory, Some(value)) => value,
+            (Repr::Scalar(ir_type), Some(value)) => {
+                let address = self.value_of(InstKind::Alloca(ir_type), Type::Ptr);
+                self.effect(InstKind::Store(address, value));
+                address
+            }
+            _ => self.value_of(InstKind::Slot(1, 1), Type::Ptr),
+        }
+    }
+
+    /// Whether copying a value of a type has to copy what it owns too, which is the items of a
+    /// `Vec` or a `Map` anywhere within it
+    pub(crate) fn owns_memory(&self, ty: &Ty) -> bool {
+        let layouts = &self.builder.layouts;
+        if layouts.runtime(ty).is_some() {
+            return true;
+        }
+        match ty {
+            Ty::Tuple(_) => layouts.fields(ty).iter().any(|x| self.owns_memory(x)),
+            Ty::Adt(id, _) => {
+                let adt = self.builder.defs.types.adt(*id);
+                match adt.is_enum() {
+                    true => (0..adt.variants().len())
+                        .any(|x| layouts.payload(ty, x).iter().any(|x| self.owns_memory(x))),
+                    false => layouts.fields(ty).iter().any(|x| self.owns_memory(x)),
+                }
+            }
+            _ => false,
+        }
+    }
+
+    /// Copies a value of a type, along with everything it owns
+    pub(crate) fn copy_deep(&mut self, to: Value, from: Value, ty: &Ty) {
+        match self.owns_memory(ty) {
+            true => {
+                let name = self.builder.helper(Helper::Copy(ty.clone()));
+                self.effect(InstKind::Call(name, vec![to, from]));
+            }
+            false => {
+                let value = self.load_value(from, ty);
+                self.store_value(to, value, ty);
+            }
+        }
+    }
+
+    /// Calls a function of the runtime
+    pub(crate) fn call(
+        &mut self,
+        function: &RuntimeFunction,
+        arguments: Vec<Value>,
+    ) -> Option<Value> {
+        let name = self.builder.declare(function.declaration());
+        self.inst(InstKind::Call(name, arguments), function.return_type)
+    }
+
+    /// Makes a `Str` of a text
+    pub(crate) fn string(&mut self, text: &str) -> Value {
+        let address = self.value_of(InstKind::Slot(16, 8), Type::Ptr);
+        let bytes = self.constant(LiteralKind::Str(Symbol::intern(text)), Type::Ptr);
+        self.effect(InstKind::Store(address, bytes));
+        let length = self.int64(text.len() as i64);
+        self.store_at(address, 8, length);
+        address
+    }
+
+    /// Makes a block taking the variables, followed by parameters of the types given
+    pub(crate) fn new_block(&mut self, extra: &[Type]) -> BlockId {
+        let mut params = Vec::new();
+        for ty in self.variables.iter().chain(extra) {
+            params.push((self.function.new_value(), *ty));
+        }
+        self.function.blocks.push(Block {
+            params,
+            insts: Vec::new(),
+            terminator: Terminator::Unreachable,
+        });
+        BlockId(self.function.blocks.len() as u32 - 1)
+    }
+
+    /// Continues adding instructions to a block, giving its extra parameters
+    pub(crate) fn switch_to(&mut self, block: BlockId) -> Vec<Value> {
+        self.current = block;
+        let params: Vec<_> = self
+            .function
+            .block(block)
+            .params
+            .iter()
+            .map(|x| x.0)
+            .collect();
+        let (variables, extra) = params.split_at(self.variables.len());
+        self.values = variables.to_vec();
+        extra.to_vec()
+    }
+
+    fn target(&self, block: BlockId, extra: Vec<Value>) -> Target {
+        let mut arguments = self.values.clone();
+        arguments.extend(extra);
+        Target::new(block, arguments)
+    }
+
+    /// Ends the current block, continuing in a new one which nothing branches to
+    pub(crate) fn terminate(&mut self, terminator: Terminator) {
+        self.block_mut().terminator = terminator;
+        let block = self.new_block(&[]);
+        self.switch_to(block);
+    }
+
+    pub(crate) fn jump(&mut self, block: BlockId, extra: Vec<Value>) {
+        let target = self.target(block, extra);
+        self.block_mut().terminator = Terminator::Jump(target);
+    }
+
+    pub(crate) fn branch(&mut self, condition: Value, then_block: BlockId, else_block: BlockId) {
+        let terminator = Terminator::Branch(
+            condition,
+            self.target(then_block, Vec::new()),
+            self.target(else_block, Vec::new()),
+        );
+        self.block_mut().terminator = terminator;
+    }
+
+    /// Continues only if a condition holds, stopping the program with a runtime error otherwise
+    pub(crate) fn check(&mut self, condition: Value, message: &str, at: Span) {
+        let fail = self.new_block(&[]);
+        let next = self.new_block(&[]);
+        self.branch(condition, next, fail);
+        self.switch_to(fail);
+        self.fail(message, None, at);
+        self.switch_to(next);
+    }
+
+    /// Stops the program with a runtime error, continuing in a block which is never reached
+    pub(crate) fn fail(&mut self, message: &str, text: Option<Value>, at: Span) {
+        self.inst_at(InstKind::Panic(message.to_string(), text), None, Some(at));
+        self.terminate(Terminator::Unreachable);
+    }
+
+    /// Compares two values, giving a `bool`
+    pub(crate) fn compare_op(&mut self, op: BinaryOp, left: Value, right: Value) -> Value {
+        self.value_of(InstKind::Binary(op, left, right), Type::Bool)
+    }
+
+    /// Gets the value of a place, which is its address for a value in memory
+    pub(crate) fn read(&mut self, place: &Place) -> Option<Value> {
+        match place {
+            Place::Unit => None,
+            Place::Variable(index) => Some(self.values[*index]),
+            Place::Address(address, ty) => self.load_value(*address, ty),
+        }
+    }
+
+    /// Gets the value of a place, copying a value in memory so that it is not changed by what
+    /// happens to the place
+    fn read_owned(&mut self, place: &Place) -> Option<Value> {
+        let value = self.read(place);
+        match place {
+            Place::Address(_, ty) => self.owned(value, ty),
+            _ => value,
+        }
+    }
+
+    fn write(&mut self, place: &Place, value: Value) {
+        match place {
+            Place::Unit => {}
+            Place::Variable(index) => self.values[*index] = value,
+            Place::Address(address, ty) => self.store_value(*address, Some(value), ty),
+        }
+    }
+
+    /// Stores a value in a local under a name of its own, so that the local can be told apart
+    fn assign_local(&mut self, local: LocalId, value: Value) {
+        let place = self.locals[local.0 as usize].clone();
+        let value = match place {
+            Place::Variable(index) => {
+                let ty = self.variables[index];
+                self.value_of(InstKind::Copy(value), ty)
+            }
+            _ => value,
+        };
+        self.write(&place, value);
+    }
+
+    fn statement(&mut self, statement: &Statement<'ast>) {
+        match statement {
+            Statement::Let(local, value) => {
+                if let Some(value) = value.and_then(|x| self.value(x)) {
+                    self.assign_local(*local, value);
+                }
+            }
+            Statement::Eval(expr) => {
+                self.expr(expr);
+            }
+            Statement::StartIteration {
+                iterator,
+                counter,
+                iterable,
+            } => {
+                let generator = self.value(iterable).expect("generators are addresses");
+                let place = self.locals[iterator.0 as usize].clone();
+                self.write(&place, generator);
+                if let Some(counter) = counter {
+                    let zero = self.int64(0);
+                    let place = self.locals[counter.0 as usize].clone();
+                    self.write(&place, zero);
+                }
+            }
+        }
+    }
+
+    fn terminator(&mut self, terminator: &BodyTerminator<'ast>) {
+        match terminator {
+            BodyTerminator::Goto(target) => self.jump(self.blocks[target.0 as usize], Vec::new()),
+            BodyTerminator::Branch {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                let condition = self.value(condition).expect("a `Bool`");
+                let then_block = self.blocks[then_block.0 as usize];
+                let else_block = self.blocks[else_block.0 as usize];
+                self.branch(condition, then_block, else_block);
+            }
+            BodyTerminator::When { scrutinee, arms } => {
+                let ty = self.ty(scrutinee.id);
+                let value = self.value(scrutinee);
+                for (arm, target) in arms {
+                    let next = self.new_block(&[]);
+                    self.test(&arm.pattern, value, &ty, next);
+                    self.bind(&arm.pattern, value, &ty);
+                    if let Some(guard) = &arm.guard {
+                        let guard = self.value(guard).expect("a `Bool`");
+                        let body = self.new_block(&[]);
+                        self.branch(guard, body, next);
+                        self.switch_to(body);
+                    }
+                    self.jump(self.blocks[target.0 as usize], Vec::new());
+                    self.switch_to(next);
+                }
+                // Arms cover every value
+                self.block_mut().terminator = Terminator::Unreachable;
+            }
+            BodyTerminator::Next {
+                iterator,
+                counter,
+                pattern,
+                body,
+                exit,
+            } => {
+                let place = self.locals[iterator.0 as usize].clone();
+                let generator = self.read(&place).expect("generators are addresses");
+                let counter = counter.map(|x| self.locals[x.0 as usize].clone());
+                let exit = self.blocks[exit.0 as usize];
+                self.next(generator, pattern, counter.as_ref(), exit);
+                self.jump(self.blocks[body.0 as usize], Vec::new());
+            }
+            BodyTerminator::Yield { value, resume } => {
+                let (frame, out) = self.generator.expect("only generators yield");
+                let Ty::Generator(item) = self.return_type.clone() else {
+                    unreachable!("generators give a generator");
+                };
+                let value = self.value(value);
+                self.store_value(out, value, &item);
+                let generator = self.body_of().generator.as_ref();
+                let state = generator
+                    .and_then(|x| x.state_of(*resume))
+                    .expect("the block after a `yield` is a state");
+                let state = self.constant(LiteralKind::Int32(state as i32), Type::Int32);
+                self.store_at(frame, GENERATOR_STATE, state);
+                let running = self.bool_const(false);
+                self.store_at(frame, GENERATOR_RUNNING, running);
+                let yielded = self.bool_const(true);
+                self.block_mut().terminator = Terminator::Return(Some(yielded));
+            }
+            BodyTerminator::Return(value) => {
+                let value = value.and_then(|x| self.value(x));
+                self.return_value(value);
+                // Nothing follows a terminator of the body, so the block it leaves is unused
+                self.current = BlockId(self.function.blocks.len() as u32 - 1);
+            }
+        }
+    }
+
+    /// Returns from the function, or finishes the generator
+    fn return_value(&mut self, value: Option<Value>) {
+        if let Some((frame, _)) = self.generator {
+            let done = self.constant(LiteralKind::Int32(-1), Type::Int32);
+            self.store_at(frame, GENERATOR_STATE, done);
+            let running = self.bool_const(false);
+            self.store_at(frame, GENERATOR_RUNNING, running);
+            let yielded = self.bool_const(false);
+            self.terminate(Terminator::Return(Some(yielded)));
+            return;
+        }
+        let value = match self.sret {
+            Some(sret) => {
+                let ty = self.return_type.clone();
+                self.store_value(sret, value, &ty);
+                None
+            }
+            None if self.function.return_type.is_none() => None,
+            // A function returning a value only returns nothing from code which is never
+            // reached, such as the end of its body after a `ret`
+            None if value.is_none() => {
+                self.terminate(Terminator::Unreachable);
+                return;
+            }
+            None => value,
+        };
+        self.terminate(Terminator::Return(value));
+    }
+
+    /// Resumes a generator. If it yields, the value is bound to the pattern along with the
+    /// counter of a `for ... of`, which is incremented. Otherwise it is done, and the loop
+    /// continues with `exit`
+    fn next(
+        &mut self,
+        generator: Value,
+        pattern: &Pattern,
+        counter: Option<&Place>,
+        exit: BlockId,
+    ) {
+        let pattern_type = self.ty(pattern.id);
+        let item = match (counter, &pattern_type) {
+            (Some(_), Ty::Tuple(elements)) => elements[1].clone(),
+            _ => pattern_type.clone(),
+        };
+        let value = self.resume(generator, &item, pattern.span, exit);
+        let value = match counter {
+            Some(counter) => {
+                let index = self.read(counter).expect("counters are numbers");
+                let one = self.int64(1);
+                let next = self
+                    .inst_at(
+                        InstKind::Binary(BinaryOp::Add, index, one),
+                        Some(Type::Int64),
+                        Some(pattern.span),
+                    )
+                    .expect("adding gives a number");
+                self.write(counter, next);
+                let pair = self.temporary(&pattern_type);
+                self.effect(InstKind::Store(pair, index));
+                let offsets = self.builder.layouts.offsets(&pattern_type);
+                let address = self.offset(pair, offsets[1]);
+                self.store_value(address, value, &item);
+                Some(pair)
+            }
+            None => value,
+        };
+        self.bind(pattern, value, &pattern_type);
+    }
+
+    /// Asks a generator for its next value, continuing with `exit` if it is done. A generator
+    /// which is resumed while it is running, from within itself, stops the program
+    pub(crate) fn resume(
+        &mut self,
+        generator: Value,
+        item: &Ty,
+        at: Span,
+        exit: BlockId,
+    ) -> Option<Value> {
+        let running = self.load_at(generator, GENERATOR_RUNNING, Type::Bool);
+        let idle = self.value_of(InstKind::Unary(UnaryOp::Not, running), Type::Bool);
+        self.check(idle, "generator resumed while it is already running", at);
+        let state = self.load_at(generator, GENERATOR_STATE, Type::Int32);
+        let finished = self.constant(LiteralKind::Int32(-1), Type::Int32);
+        let done = self.compare_op(BinaryOp::Eq, state, finished);
+        let resume = self.new_block(&[]);
+        self.branch(done, exit, resume);
+        self.switch_to(resume);
+        let out = match self.repr(item) {
+            Repr::Unit => self.value_of(InstKind::Slot(1, 1), Type::Ptr),
+            _ => self.temporary(item),
+        };
+        let function = self.load_at(generator, GENERATOR_RESUME, Type::Ptr);
+        let yielded = self
+            .inst_at(
+                InstKind::CallIndirect(function, vec![generator, out]),
+                Some(Type::Bool),
+                Some(at),
+            )
+            .expect("resuming gives whether the generator yielded");
+        let body = self.new_block(&[]);
+        self.branch(yielded, body, exit);
+        self.switch_to(body);
+        self.load_value(out, item)
+    }
+
+    /// Evaluates an expression, making up a value if it does not finish
+    fn value(&mut self, expr: &Expr) -> Option<Value> {
+        let value = self.expr(expr);
+        match value {
+            Some(value) => Some(value),
+            None => {
+                let ty = self.ty(expr.id);
+                self.zero_of(&ty)
+            }
+        }
+    }
+
+    /// Evaluates an argument given for a parameter of a type
+    fn argument(&mut self, expr: &Expr, ty: &Ty) -> Option<Value> {
+        match self.expr(expr) {
+            Some(value) => Some(value),
+            None => self.zero_of(ty),
+        }
+    }
+
+    /// Evaluates an expression, giving its value unless it is of type `()` or does not finish
+    fn expr(&mut self, expr: &Expr) -> Option<Value> {
+        let ty = self.ty(expr.id);
+        let ir_type = self.ir_type(&ty);
+        let span = expr.span;
+        let value = match &expr.kind {
+            ExprKind::Literal(LiteralKind::Str(text)) => Some(self.string(text.as_str())),
+            ExprKind::Literal(literal) => {
+                let ty = ir_type.expect("literals have a type");
+                Some(self.constant(constant(literal, ty), ty))
+            }
+            ExprKind::Name(_) => match self.body_of().names.get(&expr.id) {
+                Some(local) => {
+                    let place = self.locals[local.0 as usize].clone();
+                    self.read_owned(&place)
+                }
+                None => unreachable!("functions are never used as values"),
+            },
+            ExprKind::Path(path) => {
+                let (_, index) = self
+                    .builder
+                    .defs
+                    .types
+                    .resolve_variant(path, &mut Vec::new())
+                    .expect("paths used as values are variants");
+                self.variant(&ty, index, Vec::new())
+            }
+            ExprKind::Array(elements) => {
+                let values: Vec<Option<Value>> = elements.iter().map(|x| self.expr(x)).collect();
+                Some(self.array(&ty, values))
+            }
+            ExprKind::Repeat { value, length } => {
+                let ExprKind::Literal(LiteralKind::Int64(count)) = length.kind else {
+                    unreachable!("lengths are replaced by their value");
+                };
+                let value = self.expr(value);
+                Some(self.repeat(&ty, value, count))
+            }
+            ExprKind::Tuple(elements) if elements.is_empty() => None,
+            ExprKind::Tuple(elements) => {
+                let values: Vec<Option<Value>> = elements.iter().map(|x| self.expr(x)).collect();
+                let fields = self.builder.layouts.fields(&ty);
+                self.aggregate(&ty, &fields, values)
+            }
+            ExprKind::StructLiteral { fields, .. } => {
+                let Ty::Adt(id, _) = &ty else {
+                    unreachable!("struct literals make a `type`");
+                };
+                let adt = self.builder.defs.types.adt(*id);
+                let types = self.builder.layouts.fields(&ty);
+                let offsets = self.builder.layouts.offsets(&ty);
+                let result = match self.repr(&ty) {
+                    Repr::Memory => Some(self.temporary(&ty)),
+                    _ => None,
+                };
+                // Fields are evaluated in the order they are written
+                for field in fields {
+                    let value = self.expr(&field.value);
+                    let (index, _) = adt.field(field.name.symbol).expect("fields are checked");
+                    if let Some(result) = result {
+                        let address = self.offset(result, offsets[index]);
+                        self.store_value(address, value, &types[index]);
+                    }
+                }
+                result
+            }
+            ExprKind::Unary {
+                operator: UnaryOperator::Deref,
+                ..
+            }
+            | ExprKind::Field { .. } => {
+                let place = self
+                    .place(expr)
+                    .expect("fields and dereferences are places");
+                self.read_owned(&place)
+            }
+            ExprKind::Unary { operator, operand } => {
+                let value = self.value(operand)?;
+                match operator {
+                    // Negating an integer which wraps around or saturates is subtracting it from zero
+                    UnaryOperator::Negate => match (self.builder.overflow, ir_type) {
+                        (overflow @ (Overflow::Wrap | Overflow::Saturate), Some(ty))
+                            if ty.is_integer() =>
+                        {
+                            let zero = self.zero(ty);
+                            let kind = InstKind::Arith(overflow, BinaryOp::Sub, zero, value);
+                            self.inst_at(kind, ir_type, Some(span))
+                        }
+                        _ => {
+                            self.inst_at(InstKind::Unary(UnaryOp::Neg, value), ir_type, Some(span))
+                        }
+                    },
+                    UnaryOperator::Not => self.inst(InstKind::Unary(UnaryOp::Not, value), ir_type),
+                    UnaryOperator::Deref => unreachable!("dereferences are places"),
+                }
+            }
+            ExprKind::Reference { operand, .. } => Some(self.address_of(operand)),
+            ExprKind::Binary {
+                operator: operator @ (BinaryOperator::And | BinaryOperator::Or),
+                left,
+                right,
+            } => {
+                let left = self.value(left).expect("a `Bool`");
+                let evaluate = self.new_block(&[]);
+                let merge = self.new_block(&[Type::Bool]);
+                let short = self.bool_const(*operator == BinaryOperator::Or);
+                let short = self.target(merge, vec![short]);
+                let evaluate_target = self.target(evaluate, Vec::new());
+                self.block_mut().terminator = match operator {
+                    BinaryOperator::And => Terminator::Branch(left, evaluate_target, short),
+                    _ => Terminator::Branch(left, short, evaluate_target),
+                };
+                self.switch_to(evaluate);
+                let right = self.value(right).expect("a `Bool`");
+                self.jump(merge, vec![right]);
+                self.switch_to(merge).pop()
+            }
+            ExprKind::Binary {
+                operator,
+                left,
+                right,
+            } => {
+                let operand_type = self.ty(left.id);
+                let left_value = self.value(left);
+                let right_value = self.value(right);
+                match self.repr(&operand_type) {
+                    Repr::Scalar(operand) if operand != Type::Ptr => {
+                        self.binary(*operator, left_value, right_value, ir_type, span)
+                    }
+                    Repr::Unit => self.binary(*operator, left_value, right_value, ir_type, span),
+                    _ => {
+                        let (left, right) = (
+                            left_value.expect("only `()` has no value"),
+                            right_value.expect("only `()` has no value"),
+                        );
+                        Some(self.compare(*operator, left, right, &operand_type))
+                    }
+                }
+            }
+            ExprKind::Assign {
+                operator,
+                target,
+                value,
+            } => {
+                self.assign(*operator, target, value, span);
+                None
+            }
+            ExprKind::Call { callee, arguments } => {
+                match self.builder.types.intrinsic_of(expr.id) {
+                    Some(intrinsic) => self.intrinsic(intrinsic, callee, arguments, ir_type, span),
+                    None => self.call_expr(expr, callee, arguments),
+                }
+            }
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => {
+                let value = self.block(block);
+                value.filter(|_| ir_type.is_some())
+            }
+            ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let condition = self.value(condition).expect("a `Bool`");
+                let then_block = self.new_block(&[]);
+                let else_block = self.new_block(&[]);
+                let merge = self.new_block(&ir_type.into_iter().collect::<Vec<_>>());
+                self.branch(condition, then_block, else_block);
+                self.switch_to(then_block);
+                let value = self.block(then_branch);
+                self.merge(merge, &ty, value);
+                self.switch_to(else_block);
+                let value = match else_branch {
+                    Some(else_branch) => self.expr(else_branch),
+                    None => None,
+                };
+                self.merge(merge, &ty, value);
+                self.switch_to(merge).pop()
+            }
+            ExprKind::When { scrutinee, arms } => {
+                let scrutinee_type = self.ty(scrutinee.id);
+                let value = self.value(scrutinee);
+                let merge = self.new_block(&ir_type.into_iter().collect::<Vec<_>>());
+                for arm in arms {
+                    let next = self.new_block(&[]);
+                    self.test(&arm.pattern, value, &scrutinee_type, next);
+                    self.bind(&arm.pattern, value, &scrutinee_type);
+                    if let Some(guard) = &arm.guard {
+                        let guard = self.value(guard).expect("a `Bool`");
+                        let body = self.new_block(&[]);
+                        self.branch(guard, body, next);
+                        self.switch_to(body);
+                    }
+                    let value = self.expr(&arm.body);
+                    self.merge(merge, &ty, value);
+                    self.switch_to(next);
+                }
+                // Arms cover every value
+                self.block_mut().terminator = Terminator::Unreachable;
+                self.switch_to(merge).pop()
+            }
+            ExprKind::For {
+                kind,
+                pattern,
+                iterable,
+                body,
+            } => {
+                let generator = self.value(iterable).expect("generators are addresses");
+                let counter = match kind {
+                    ForKind::In => None,
+                    ForKind::Of => {
+                        let counter = Ty::Primitive(PrimitiveType::Int64);
+                        let address = self.temporary(&counter);
+                        let zero = self.int64(0);
+                        self.effect(InstKind::Store(address, zero));
+                        Some(Place::Address(address, counter))
+                    }
+                };
+                let header = self.new_block(&[]);
+                let exit = self.new_block(&[]);
+                self.jump(header, Vec::new());
+                self.switch_to(header);
+                self.next(generator, pattern, counter.as_ref(), exit);
+                self.block(body);
+                self.jump(header, Vec::new());
+                self.switch_to(exit);
+                None
+            }
+            ExprKind::Return(value) => {
+                let value = value.as_ref().and_then(|x| self.value(x));
+                self.return_value(value);
+                None
+            }
+            ExprKind::Yield(_) => unreachable!("`yield` is lowered into a terminator"),
+            ExprKind::Closure(function) => Some(self.closure(&ty, function)),
+            ExprKind::Error => unreachable!("programs with errors are never built"),
+        };
+        match ty {
+            Ty::Never => None,
+            _ => value,
+        }
+    }
+
+    /// Stores values one after the other as a tuple or a `type` of a type
+    fn aggregate(&mut self, ty: &Ty, types: &[Ty], values: Vec<Option<Value>>) -> Option<Value> {
+        if self.repr(ty) != Repr::Memory {
+            return None;
+        }
+        let result = self.temporary(ty);
+        let offsets = self.builder.layouts.offsets(ty);
+        for (index, value) in values.into_iter().enumerate() {
+            let address = self.offset(result, offsets[index]);
+            self.store_value(address, value, &types[index]);
+        }
+        Some(result)
+    }
+
+    /// Makes a value of an `enum` holding the variant at an index
+    pub(crate) fn variant(
+        &mut self,
+        ty: &Ty,
+        index: usize,
+        payload: Vec<Option<Value>>,
+    ) -> Option<Value> {
+        if self.repr(ty) != Repr::Memory {
+            return None;
+        }
+        let result = self.temporary(ty);
+        let (tag, offsets) = self.builder.layouts.variant(ty, index);
+        let tag = self.constant(constant(&LiteralKind::Int64(index as i64), tag), tag);
+        self.effect(InstKind::Store(result, tag));
+        let types = self.builder.layouts.payload(ty, index);
+        for (index, value) in payload.into_iter().enumerate() {
+            let address = self.offset(result, offsets[index]);
+            self.store_value(address, value, &types[index]);
+        }
+        Some(result)
+    }
+
+    /// Gets the index of the variant an `enum` holds as a number of the type of its tag
+    pub(crate) fn tag(&mut self, value: Value, ty: &Ty) -> (Value, Type) {
+        let (tag, _) = self.builder.layouts.variant(ty, 0);
+        (self.value_of(InstKind::Load(value), tag), tag)
+    }
+
+    /// Jumps to the block after a branch, with the value of the branch if it has one
+    fn merge(&mut self, merge: BlockId, ty: &Ty, value: Option<Value>) {
+        let value = match self.repr(ty) {
+            Repr::Unit => None,
+            _ => value.or_else(|| self.zero_of(ty)),
+        };
+        self.jump(merge, value.into_iter().collect());
+    }
+
+    fn block(&mut self, block: &AstBlock) -> Option<Value> {
+        for statement in &block.statements {
+            match &statement.kind {
+                StatementKind::Let(let_statement) => {
+                    if let Some(value) = &let_statement.value {
+                        let local = self.body_of().declarations[&statement.id];
+                        if let Some(value) = self.value(value) {
+                            self.assign_local(local, value);
+                        }
+                    }
+                }
+                StatementKind::Expr(expr) => {
+                    self.expr(expr);
+                }
+                StatementKind::Error => {}
+            }
+        }
+        match &block.tail {
+            Some(tail) => self.expr(tail),
+            None => None,
+        }
+    }
+
+    /// Applies a binary operator other than `&&` and `|` to scalars, giving a result of a type
+    fn binary(
+        &mut self,
+        operator: BinaryOperator,
+        left: Option<Value>,
+        right: Option<Value>,
+        ty: Option<Type>,
+        at: Span,
+    ) -> Option<Value> {
+        let overflow = self.builder.overflow;
+        self.arith(operator, overflow, left, right, ty, at)
+    }
+
+    /// Applies a binary operator other than `&&` and `|` which overflows some way
+    fn arith(
+        &mut self,
+        operator: BinaryOperator,
+        overflow: Overflow,
+        left: Option<Value>,
+        right: Option<Value>,
+        ty: Option<Type>,
+        at: Span,
+    ) -> Option<Value> {
+        let (Some(left), Some(right)) = (left, right) else {
+            // Values of type `()` are all equal
+            let equal = matches!(
+                operator,
+                BinaryOperator::GreaterOrEqual
+                    | BinaryOperator::LessOrEqual
+                    | BinaryOperator::EqualTo
+            );
+            let literal = LiteralKind::Boolean(equal);
+            return ty.and_then(|ty| self.inst(InstKind::Const(literal), Some(ty)));
+        };
+        let op = binary_op(operator);
+        let arithmetic = matches!(
+            op,
+            BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div
+        );
+        let kind = match overflow {
+            Overflow::Wrap | Overflow::Saturate
+                if arithmetic && ty.is_some_and(|x| x.is_integer()) =>
+            {
+                InstKind::Arith(overflow, op, left, right)
+            }
+            _ => InstKind::Binary(op, left, right),
+        };
+        let at = match op.is_comparison() {
+            true => None,
+            false => Some(at),
+        };
+        self.inst_at(kind, ty, at)
+    }
+
+    /// Compares two values which are not numbers, `bool`s or `char`s with an operator
+    fn compare(&mut self, operator: BinaryOperator, left: Value, right: Value, ty: &Ty) -> Value {
+        if self.repr(ty) == Repr::Scalar(Type::Ptr) {
+            // References and generators are only equal to themselves, and are never ordered
+            return match operator {
+                BinaryOperator::EqualTo
+                | BinaryOperator::LessOrEqual
+                | BinaryOperator::GreaterOrEqual => self.compare_op(BinaryOp::Eq, left, right),
+                BinaryOperator::NotEqual => self.compare_op(BinaryOp::Ne, left, right),
+                _ => self.bool_const(false),
+            };
+        }
+        // -1, 0 or 1 for less, equal or greater, and 2 for values which are not ordered
+        let ordering = self.ordering(left, right, ty);
+        let is = |this: &mut Self, op, expected: i32| {
+            let expected = this.constant(LiteralKind::Int32(expected), Type::Int32);
+            this.compare_op(op, ordering, expected)
+        };
+        match operator {
+            BinaryOperator::EqualTo => is(self, BinaryOp::Eq, 0),
+            BinaryOperator::NotEqual => is(self, BinaryOp::Ne, 0),
+            BinaryOperator::Lesser => is(self, BinaryOp::Eq, -1),
+            BinaryOperator::Greater => is(self, BinaryOp::Eq, 1),
+            BinaryOperator::LessOrEqual | BinaryOperator::GreaterOrEqual => {
+                let excluded = match operator {
+                    BinaryOperator::LessOrEqual => 1,
+                    _ => -1,
+                };
+                let ordered = is(self, BinaryOp::Ne, 2);
+                let allowed = is(self, BinaryOp::Ne, excluded);
+                self.value_of(
+                    InstKind::Binary(BinaryOp::And, ordered, allowed),
+                    Type::Bool,
+                )
+            }
+            _ => unreachable!("only comparisons apply to values in memory"),
+        }
+    }
+
+    /// Orders two values of a type, giving -1, 0 or 1 if the first is less than, equal to or
+    /// greater than the second, and 2 if they are not ordered
+    pub(crate) fn ordering(&mut self, left: Value, right: Value, ty: &Ty) -> Value {
+        match self.repr(ty) {
+            Repr::Unit => self.constant(LiteralKind::Int32(0), Type::Int32),
+            Repr::Scalar(Type::Ptr) => {
+                let equal = self.compare_op(BinaryOp::Eq, left, right);
+                self.select(equal, 0, 2)
+            }
+            Repr::Scalar(_) => {
+                let merge = self.new_block(&[Type::Int32]);
+                for (op, result) in [(BinaryOp::Eq, 0), (BinaryOp::Lt, -1), (BinaryOp::Gt, 1)] {
+                    let holds = self.compare_op(op, left, right);
+                    let result = self.constant(LiteralKind::Int32(result), Type::Int32);
+                    let next = self.new_block(&[]);
+                    let target = self.target(merge, vec![result]);
+                    let next_target = self.target(next, Vec::new());
+                    self.block_mut().terminator = Terminator::Branch(holds, target, next_target);
+                    self.switch_to(next);
+                }
+                let unordered = self.constant(LiteralKind::Int32(2), Type::Int32);
+                self.jump(merge, vec![unordered]);
+                self.switch_to(merge)[0]
+            }
+            Repr::Memory if *ty == Ty::Primitive(PrimitiveType::Str) => self
+                .call(&runtime::STR_COMPARE, vec![left, right])
+                .expect("comparing gives an ordering"),
+            Repr::Memory => {
+                let name = self.builder.helper(Helper::Compare(ty.clone()));
+                self.value_of(InstKind::Call(name, vec![left, right]), Type::Int32)
+            }
+        }
+    }
+
+    /// Gives one `i32` if a condition holds and another if it does not
+    pub(crate) fn select(&mut self, condition: Value, then_value: i32, else_value: i32) -> Value {
+        let merge = self.new_block(&[Type::Int32]);
+        let then_value = self.constant(LiteralKind::Int32(then_value), Type::Int32);
+        let else_value = self.constant(LiteralKind::Int32(else_value), Type::Int32);
+        let terminator = Terminator::Branch(
+            condition,
+            self.target(merge, vec![then_value]),
+            self.target(merge, vec![else_value]),
+        );
+        self.block_mut().terminator = terminator;
+        self.switch_to(merge)[0]
+    }
+
+    /// Applies one of the methods of numbers which the backends implement themselves, giving a
+    /// result of a type
+    fn intrinsic(
+        &mut self,
+        intrinsic: Intrinsic,
+        callee: &Expr,
+        arguments: &[Expr],
+        ty: Option<Type>,
+        at: Span,
+    ) -> Option<Value> {
+        let ExprKind::Field { object, .. } = &callee.kind else {
+            unreachable!("intrinsics are called as methods");
+        };
+        let value = self.value(object);
+        match intrinsic {
+            Intrinsic::Arith(operator, overflow) => {
+                let right = self.value(&arguments[0]);
+                self.arith(operator, overflow, value, right, ty, at)
+            }
+            Intrinsic::Convert(_) => {
+                let overflow = match self.builder.overflow {
+                    Overflow::Trap => Overflow::Trap,
+                    _ => Overflow::Wrap,
+                };
+                value.and_then(|x| self.inst_at(InstKind::Convert(overflow, x), ty, Some(at)))
+            }
+        }
+    }
+
+    fn assign(&mut self, operator: Option<BinaryOperator>, target: &Expr, value: &Expr, at: Span) {
+        let ty = self.ty(target.id);
+        let ir_type = self.ir_type(&ty);
+        let value = self.value(value);
+        // The target is found after the value is evaluated, as by the interpreter
+        let place = self
+            .place(target)
+            .expect("assignments are checked to have a place as their target");
+        let value = match operator {
+            Some(operator) => {
+                let current = self.read(&place);
+                self.binary(operator, current, value, ir_type, at)
+            }
+            None => value,
+        };
+        if let Some(value) = value {
+            self.write(&place, value);
+        }
+    }
+
+    /// Finds the place an expression names, if it names one: a local, a field of a place or of a
+    /// temporary value, or what a reference points to
+    fn place(&mut self, expr: &Expr) -> Option<Place> {
+        match &expr.kind {
+            ExprKind::Name(_) => {
+                let local = self.body_of().names.get(&expr.id)?;
+                Some(self.locals[local.0 as usize].clone())
+            }
+            ExprKind::Unary {
+                operator: UnaryOperator::Deref,
+                operand,
+            } => {
+                let address = self.value(operand).expect("references are addresses");
+                Some(Place::Address(address, self.ty(expr.id)))
+            }
+            ExprKind::Field { object, field } => {
+                let mut ty = self.ty(object.id);
+                let mut place = match self.place(object) {
+                    Some(place) => place,
+                    None => match self.value(object) {
+                        Some(value) => Place::Address(value, ty.clone()),
+                        None => Place::Unit,
+                    },
+                };
+                // Fields are found through any number of references
+                while let Ty::Reference { pointee, .. } = ty {
+                    let address = self.read(&place).expect("references are addresses");
+                    ty = *pointee;
+                    place = Place::Address(address, ty.clone());
+                }
+                let Ty::Adt(id, _) = &ty else {
+                    unreachable!("only `type`s have fields");
+                };
+                let (index, _) = self
+                    .builder
+                    .defs
+                    .types
+                    .adt(*id)
+                    .field(field.symbol)
+                    .expect("fields are checked");
+                let field_type = self.builder.layouts.fields(&ty)[index].clone();
+                Some(match place {
+                    Place::Address(address, _) => {
+                        let offset = self.builder.layouts.offsets(&ty)[index];
+                        let address = self.offset(address, offset);
+                        Place::Address(address, field_type)
+                    }
+                    _ => Place::Unit,
+                })
+            }
+            _ => None,
+        }
+    }
+
+    /// Gets the address of the value an expression names, or of a copy of a temporary value
+    fn address_of(&mut self, expr: &Expr) -> Value {
+        let ty = self.ty(expr.id);
+        match self.place(expr) {
+            Some(Place::Address(address, _)) => return address,
+            Some(place @ Place::Variable(_)) => {
+                let value = self.read(&place);
+                return self.spill(value, &ty);
+            }
+            Some(Place::Unit) => return self.spill(None, &ty),
+            None => {}
+        }
+        let value = self.value(expr);
+        match self.repr(&ty) {
+            Repr::Memory => value.expect("values in memory are addresses"),
+            _ => self.spill(value, &ty),
+        }
+    }
+
+    /// Calls a function, a method, a closure or a variant, giving its result
+    fn call_expr(&mut self, expr: &Expr, callee: &Expr, arguments: &[Expr]) -> Option<Value> {
+        let defs = self.builder.defs;
+        let return_type = self.ty(expr.id);
+        let argument_types: Vec<Ty> = arguments.iter().map(|x| self.ty(x.id)).collect();
+        let (callee, receiver) = match &callee.kind {
+            ExprKind::Field { object, field } => {
+                let (method, receiver, receiver_type) = self.receiver(object, field.symbol);
+                let types: Vec<Ty> = std::iter::once(receiver_type)
+                    .chain(argument_types.iter().cloned())
+                    .collect();
+                let callee = self.builder.method(method, &types, &return_type);
+                (callee, Some(receiver))
+            }
+            ExprKind::Path(path) if path.segments.len() == 2 => {
+                let trait_id = defs.traits.lookup(path.segments[0].symbol);
+                let callee = if let Some(trait_id) = trait_id {
+                    let mut ty = argument_types[0].clone();
+                    let method = loop {
+                        if let Some(method) =
+                            self.builder
+                                .find_method(&ty, path.name().symbol, Some(trait_id))
+                        {
+                            break method;
+                        }
+                        let Ty::Reference { pointee, .. } = ty else {
+                            unreachable!("trait methods are checked to be implemented");
+                        };
+                        ty = *pointee;
+                    };
+                    self.builder.method(method, &argument_types, &return_type)
+                } else if let Some((def, signature)) =
+                    defs.traits.resolve_associated(&defs.types, path)
+                {
+                    let implementation = self
+                        .builder
+                        .impls
+                        .iter()
+                        .position(|x| std::ptr::eq(x.def, def))
+                        .expect("every `impl` is collected");
+                    let body = self.builder.impls[implementation]
+                        .bodies
+                        .get(&signature.name.symbol)
+                        .copied();
+                    let method = Method {
+                        implementation,
+                        signature,
+                        body,
+                        mapping: HashMap::new(),
+                    };
+                    self.builder.method(method, &argument_types, &return_type)
+                } else {
+                    let (_, index) = defs
+                        .types
+                        .resolve_variant(path, &mut Vec::new())
+                        .expect("paths which are called are variants or functions");
+                    let payload = self.builder.layouts.payload(&return_type, index);
+                    let values = arguments
+                        .iter()
+                        .zip(&payload)
+                        .map(|(x, ty)| self.argument(x, ty))
+                        .collect();
+                    return self.variant(&return_type, index, values);
+                };
+                (callee, None)
+            }
+            ExprKind::Name(name) if !self.body_of().names.contains_key(&callee.id) => {
+                let signature = defs
+                    .functions
+                    .lookup(name.symbol)
+                    .expect("calls are checked to name a function");
+                let callee = self
+                    .builder
+                    .function(signature, &argument_types, &return_type);
+                (callee, None)
+            }
+            _ => return self.call_closure(callee, arguments, &return_type, expr.span),
+        };
+
+        let mut parameters = callee.parameters();
+        let mut values = Vec::new();
+        let mut types = Vec::new();
+        if let Some(receiver) = receiver {
+            values.push(receiver);
+            types.push(parameters.remove(0));
+        }
+        for (index, argument) in arguments.iter().enumerate() {
+            // Values given after the parameters of a variadic function keep their own type
+            let ty = match parameters.get(index) {
+                Some(ty) => ty.clone(),
+                None => argument_types[index].clone(),
+            };
+            values.push(self.argument(argument, &ty));
+            types.push(ty);
+        }
+        self.emit_call(callee, values, types, expr.span)
+    }
+
+    /// Finds the method a receiver calls by following its references, and evaluates the receiver
+    /// the way the method takes it: by reference, or as the value behind any references
+    fn receiver(&mut self, object: &Expr, name: Symbol) -> (Method<'b, 'ast>, Option<Value>, Ty) {
+        let object_type = self.ty(object.id);
+        let mut ty = object_type.clone();
+        let mut depth = 0;
+        let method = loop {
+            if let Some(method) = self.builder.find_method(&ty, name, None) {
+                break method;
+            }
+            let Ty::Reference { pointee, .. } = ty else {
+                unreachable!("methods are checked to exist");
+            };
+            ty = *pointee;
+            depth += 1;
+        };
+        let by_reference = matches!(
+            method.signature.parameters.first(),
+            Some(SemaType::Reference { .. })
+        );
+        if by_reference {
+            let receiver_type = Ty::Reference {
+                kind: shark_parse::ast::ReferenceKind::Ref,
+                mutable: false,
+                pointee: Box::new(ty),
+            };
+            let mut receiver = match depth {
+                0 => Some(self.address_of(object)),
+                _ => self.value(object),
+            };
+            for _ in 1..depth {
+                receiver = receiver.map(|x| self.value_of(InstKind::Load(x), Type::Ptr));
+            }
+            return (method, receiver, receiver_type);
+        }
+        let mut receiver = self.value(object);
+        let mut pointee = object_type;
+        for _ in 0..depth {
+            let Ty::Reference { pointee: inner, .. } = pointee else {
+                unreachable!("the receiver is a reference");
+            };
+            pointee = *inner;
+            receiver = receiver.and_then(|x| self.load_value(x, &pointee));
+            receiver = self.owned(receiver, &pointee);
+        }
+        (method, receiver, pointee)
+    }
+
+    /// Calls what a callee resolved to with the values of the arguments, which are of the types
+    /// given
+    fn emit_call(
+        &mut self,
+        callee: Callee<'b>,
+        arguments: Vec<Option<Value>>,
+        types: Vec<Ty>,
+        at: Span,
+    ) -> Option<Value> {
+        match callee {
+            Callee::Function {
+                name,
+                return_type,
+                generator,
+                ..
+            } => {
+                let mut values = Vec::new();
+                let result = match self.repr(&return_type) {
+                    Repr::Memory => {
+                        let result = self.temporary(&return_type);
+                        values.push(result);
+                        Some(result)
+                    }
+                    _ => None,
+                };
+                values.extend(arguments.into_iter().flatten());
+                let ty = match self.repr(&return_type) {
+                    Repr::Scalar(ty) => Some(ty),
+                    _ => None,
+                };
+                // Calling a generator function only makes its frame, which does not nest
+                let at = match generator {
+                    true => None,
+                    false => Some(at),
+                };
+                let value = self.inst_at(InstKind::Call(name, values), ty, at);
+                result.or(value)
+            }
+            Callee::Builtin {
+                builtin,
+                return_type,
+                ..
+            } => self.builtin(builtin, arguments, &types, &return_type, at),
+            Callee::Foreign(signature) => self.foreign(signature, arguments, &types, at),
+        }
+    }
+
+    /// Calls a closure, whose function is given the address of the values it captured
+    fn call_closure(
+        &mut self,
+        callee: &Expr,
+        arguments: &[Expr],
+        return_type: &Ty,
+        at: Span,
+    ) -> Option<Value> {
+        let Ty::Function { parameters, .. } = self.ty(callee.id) else {
+            unreachable!("only closures are called through a value");
+        };
+        let closure = self.value(callee).expect("closures live in memory");
+        let values: Vec<Option<Value>> = arguments
+            .iter()
+            .zip(&parameters)
+            .map(|(x, ty)| self.argument(x, ty))
+            .collect();
+        let function = self.load_at(closure, 0, Type::Ptr);
+        let environment = self.load_at(closure, 8, Type::Ptr);
+        let mut arguments = Vec::new();
+        let result = match self.repr(return_type) {
+            Repr::Memory => {
+                let result = self.temporary(return_type);
+                arguments.push(result);
+                Some(result)
+            }
+            _ => None,
+        };
+        arguments.push(environment);
+        arguments.extend(values.into_iter().flatten());
+        let ty = match self.repr(return_type) {
+            Repr::Scalar(ty) => Some(ty),
+            _ => None,
+        };
+        // A closure which is a generator only makes its frame
+        let at = match return_type {
+            Ty::Generator(_) => None,
+            _ => Some(at),
+        };
+        let value = self.inst_at(InstKind::CallIndirect(function, arguments), ty, at);
+        result.or(value)
+    }
+
+    /// Makes a closure: the address of its function followed by that of a copy of every value it
+    /// captures
+    fn closure(&mut self, ty: &Ty, function: &AstFunction) -> Value {
+        let bodies = self.builder.bodies;
+        let index = bodies
+            .iter()
+            .position(|x| std::ptr::eq(x.function, function))
+            .expect("closures are lowered");
+        let body = &bodies[index];
+        let own = bodies
+            .iter()
+            .position(|x| std::ptr::eq(x, self.body_of()))
+            .expect("the body being built is lowered");
+        let captures: Vec<Ty> = body
+            .captures
+            .iter()
+            .map(|x| self.local_types[x.0 as usize].clone())
+            .collect();
+        let layouts: Vec<Layout> = captures
+            .iter()
+            .map(|x| self.builder.layouts.layout(x))
+            .collect();
+        let layout = Layout::structure(&layouts);
+        let size = self.int64(layout.size as i64);
+        let environment = self
+            .call(&runtime::ALLOC, vec![size])
+            .expect("allocating gives an address");
+        let offsets = layout_offsets(&layout);
+        for (index, local) in body.captures.iter().enumerate() {
+            let place = self.locals[local.0 as usize].clone();
+            let value = self.read(&place);
+            let address = self.offset(environment, offsets[index]);
+            match self.repr(&captures[index]) {
+                Repr::Memory => {
+                    let value = value.expect("values in memory are addresses");
+                    self.copy_deep(address, value, &captures[index]);
+                }
+                _ => self.store_value(address, value, &captures[index]),
+            }
+        }
+        let Ty::Function {
+            parameters,
+            return_type,
+        } = ty
+        else {
+            unreachable!("closures are functions");
+        };
+        let name = format!("{}::{{closure#{}}}", self.name, index - own);
+        let instance = Instance {
+            name: name.clone(),
+            body,
+            mapping: self.mapping.clone(),
+            parameters: parameters.clone(),
+            return_type: (**return_type).clone(),
+            captures: Some(captures),
+        };
+        self.builder.request(name.clone(), |_| Job::Body(instance));
+        let address = self.value_of(InstKind::FuncAddr(name), Type::Ptr);
+        let closure = self.temporary(ty);
+        self.effect(InstKind::Store(closure, address));
+        self.store_at(closure, 8, environment);
+        closure
+    }
+
+    /// Continues with `next` unless a value matches a pattern
+    fn test(&mut self, pattern: &Pattern, value: Option<Value>, ty: &Ty, next: BlockId) {
+        let check = |this: &mut Self, op, literal: &LiteralKind| {
+            if let (Some(value), Some(ty)) = (value, this.ir_type(ty)) {
+                let expected = this.constant(constant(literal, ty), ty);
+                let matches = this.compare_op(op, value, expected);
+                let body = this.new_block(&[]);
+                this.branch(matches, body, next);
+                this.switch_to(body);
+            }
+        };
+        match &pattern.kind {
+            PatternKind::Wildcard | PatternKind::Binding { .. } => {}
+            PatternKind::Literal(LiteralKind::Str(text)) => {
+                let value = value.expect("strings live in memory");
+                let expected = self.string(text.as_str());
+                let ordering = self
+                    .call(&runtime::STR_COMPARE, vec![value, expected])
+                    .expect("comparing gives an ordering");
+                let equal = self.constant(LiteralKind::Int32(0), Type::Int32);
+                let matches = self.compare_op(BinaryOp::Eq, ordering, equal);
+                let body = self.new_block(&[]);
+                self.branch(matches, body, next);
+                self.switch_to(body);
+            }
+            PatternKind::Literal(literal) => check(self, BinaryOp::Eq, literal),
+            PatternKind::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                check(self, BinaryOp::Ge, start);
+                match inclusive {
+                    true => check(self, BinaryOp::Le, end),
+                    false => check(self, BinaryOp::Lt, end),
+                }
+            }
+            PatternKind::Tuple(elements) => {
+                let types = self.builder.layouts.fields(ty);
+                let offsets = self.builder.layouts.offsets(ty);
+                for (index, element) in elements.iter().enumerate() {
+                    let element_value = value.and_then(|x| {
+                        let address = self.offset(x, offsets[index]);
+                        self.load_value(address, &types[index])
+                    });
+                    self.test(element, element_value, &types[index], next);
+                }
+            }
+            PatternKind::Variant { path, payload } => {
+                let (_, index) = self
+                    .builder
+                    .defs
+                    .types
+                    .resolve_variant(path, &mut Vec::new())
+                    .expect("patterns are checked");
+                let Some(value) = value else {
+                    return;
+                };
+                let (tag, tag_type) = self.tag(value, ty);
+                let expected = constant(&LiteralKind::Int64(index as i64), tag_type);
+                let expected = self.constant(expected, tag_type);
+                let matches = self.compare_op(BinaryOp::Eq, tag, expected);
+                let body = self.new_block(&[]);
+                self.branch(matches, body, next);
+                self.switch_to(body);
+                let (_, offsets) = self.builder.layouts.variant(ty, index);
+                let types = self.builder.layouts.payload(ty, index);
+                for (index, element) in payload.iter().enumerate() {
+                    let address = self.offset(value, offsets[index]);
+                    let element_value = self.load_value(address, &types[index]);
+                    self.test(element, element_value, &types[index], next);
+                }
+            }
+        }
+    }
+
+    /// Stores the parts of a value in the locals a pattern binds, once it is known to match
+    fn bind(&mut self, pattern: &Pattern, value: Option<Value>, ty: &Ty) {
+        match &pattern.kind {
+            PatternKind::Binding { .. } => {
+                let local = self.body_of().declarations[&pattern.id];
+                if let Some(value) = value {
+                    self.assign_local(local, value);
+                }
+            }
+            PatternKind::Tuple(elements) => {
+                let types = self.builder.layouts.fields(ty);
+                let offsets = self.builder.layouts.offsets(ty);
+                for (index, element) in elements.iter().enumerate() {
+                    let element_value = value.and_then(|x| {
+                        let address = self.offset(x, offsets[index]);
+                        self.load_value(address, &types[index])
+                    });
+                    self.bind(element, element_value, &types[index]);
+                }
+            }
+            PatternKind::Variant { path, payload } if !payload.is_empty() => {
+                let (_, index) = self
+                    .builder
+                    .defs
+                    .types
+                    .resolve_variant(path, &mut Vec::new())
+                    .expect("patterns are checked");
+                let (_, offsets) = self.builder.layouts.variant(ty, index);
+                let types = self.builder.layouts.payload(ty, index);
+                for (index, element) in payload.iter().enumerate() {
+                    let element_value = value.and_then(|x| {
+                        let address = self.offset(x, offsets[index]);
+                        self.load_value(address, &types[index])
+                    });
+                    self.bind(element, element_value, &types[index]);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Gets the offsets of the fields of a layout
+pub(crate) fn layout_offsets(layout: &Layout) -> Vec<u64> {
+    match &layout.kind {
+        shark_sema::layout::LayoutKind::Struct { offsets } => offsets.clone(),
+        _ => Vec::new(),
+    }
+}
@@ -0,0 +1,1102 @@
+//! Builds the `extern` functions of the standard library out of calls into the [crate::runtime],
+//! along with calls of `extern "C"` functions and the functions the builder writes itself, see
+//! [Helper]
+//!
+//! What a builtin does matches the runtime of the interpreter in `shark_std::runtime`, down to the
+//! messages of the errors it stops the program with. `Vec`s and `Map`s are looked into directly:
+//! a `Map` is a `Vec` of its entries, kept in the order of their keys
+
+use shark_core::{
+    source::Span,
+    symbol::{sym, Symbol},
+};
+use shark_lex::token::LiteralKind;
+use shark_sema::{
+    adt::AdtKind,
+    generics::FunctionSig,
+    ty::{PrimitiveType, Type as SemaType},
+};
+use shark_std::runtime::Builtin;
+use shark_typeck::ty::Ty;
+
+use crate::{
+    build::{Builder, Callee, FunctionBuilder, GENERATOR_RESUME, GENERATOR_STATE},
+    ir::{BinaryOp, BlockId, CType, Extern, Foreign, Function, InstKind, Terminator, Type, Value},
+    layout::{Layouts, Runtime, ITEMS, LENGTH},
+    runtime,
+};
+
+/// The offsets within the frame of the generator iterating a `Vec` of the copy of the `Vec` and of
+/// the index of the next item, and how big the frame is
+const ITERATION_ITEMS: u64 = 16;
+const ITERATION_INDEX: u64 = 40;
+const ITERATION_SIZE: u64 = 48;
+
+/// A function the builder writes itself rather than building it from a body, which is built once
+/// for every type it is needed for
+#[derive(Debug, Clone, PartialEq)]
+pub enum Helper {
+    /// `show` of a type whose implementation of `Show` is `extern`, storing the `Str` at the first
+    /// address given
+    Show(Ty),
+    /// Compares two values of a type which are not scalars, giving 0 if they are equal and 2 if
+    /// they are not, since such values are never ordered
+    Compare(Ty),
+    /// Copies a value of a type along with the items of every `Vec` and `Map` within it
+    Copy(Ty),
+    /// Finds a key within a `Map` of a type, giving its index, or -1 less the index it would be
+    /// inserted at
+    Find(Ty),
+    /// Resumes the generator iterating over the copy of a `Vec` of items of a type
+    Iterate(Ty),
+}
+
+impl Helper {
+    /// Gets the name of the function, which no function of the program can have
+    pub fn name(&self, layouts: &Layouts) -> String {
+        match self {
+            Self::Show(ty) => format!("<{} as {}>::show", layouts.name(ty), sym::SHOW),
+            Self::Compare(ty) => format!("{{compare}}<{}>", layouts.name(ty)),
+            Self::Copy(ty) => format!("{{copy}}<{}>", layouts.name(ty)),
+            Self::Find(ty) => format!("{}::{{find}}", layouts.name(ty)),
+            Self::Iterate(ty) => format!("{{iterate}}<{}>", layouts.name(ty)),
+        }
+    }
+}
+
+/// Gets the type of the items of a `Vec`, or of the entries of a `Map`
+fn item_type(layouts: &Layouts, ty: &Ty) -> Ty {
+    match (layouts.runtime(ty), ty) {
+        (Some(Runtime::Vec), Ty::Adt(_, arguments)) => arguments[0].clone(),
+        (Some(Runtime::Map), Ty::Adt(_, arguments)) => Ty::Tuple(arguments.clone()),
+        _ => unreachable!("only `Vec`s and `Map`s have items"),
+    }
+}
+
+/// Gets the type a reference points to
+fn pointee(ty: &Ty) -> Ty {
+    match ty {
+        Ty::Reference { pointee, .. } => (**pointee).clone(),
+        _ => ty.clone(),
+    }
+}
+
+impl<'f, 'b, 'ast> FunctionBuilder<'f, 'b, 'ast> {
+    /// Carries out a builtin with the values of its arguments, which are of the types given
+    pub(crate) fn builtin(
+        &mut self,
+        builtin: Builtin,
+        arguments: Vec<Option<Value>>,
+        types: &[Ty],
+        return_type: &Ty,
+        at: Span,
+    ) -> Option<Value> {
+        let argument = |index: usize| arguments[index].expect("the argument has a value");
+        let str_type = Ty::Primitive(PrimitiveType::Str);
+        match builtin {
+            Builtin::Print | Builtin::Println | Builtin::Eprint | Builtin::Eprintln => {
+                let output = self.fill(&arguments, types, at);
+                let output = match builtin {
+                    Builtin::Println | Builtin::Eprintln => {
+                        let new_line = self.string("\n");
+                        self.concat(output, new_line)
+                    }
+                    _ => output,
+                };
+                let stream = match builtin {
+                    Builtin::Print | Builtin::Println => 1,
+                    _ => 2,
+                };
+                let stream = self.constant(LiteralKind::Int32(stream), Type::Int32);
+                let written = self
+                    .call(&runtime::WRITE, vec![stream, output])
+                    .expect("writing gives whether it succeeded");
+                self.check(written, "could not write the output", at);
+                None
+            }
+            Builtin::Format => Some(self.fill(&arguments, types, at)),
+            Builtin::Panic => {
+                self.fail("panicked: ", Some(argument(0)), at);
+                self.zero_of(return_type)
+            }
+            Builtin::Exit => {
+                self.call(&runtime::EXIT, vec![argument(0)]);
+                self.terminate(Terminator::Unreachable);
+                None
+            }
+            Builtin::Args => {
+                let result = self.temporary(return_type);
+                self.call(&runtime::ARGS, vec![result]);
+                Some(result)
+            }
+            Builtin::ReadFile | Builtin::WriteFile => {
+                let text = self.temporary(&str_type);
+                let succeeded = match builtin {
+                    Builtin::ReadFile => self.call(&runtime::READ_FILE, vec![text, argument(0)]),
+                    _ => self.call(&runtime::WRITE_FILE, vec![text, argument(0), argument(1)]),
+                };
+                let succeeded = succeeded.expect("files give whether they could be used");
+                let result = self.temporary(return_type);
+                let ((_, _), (failed, end)) = self.branches(succeeded);
+                let payload = self.set_variant(result, return_type, "Ok");
+                match builtin {
+                    Builtin::ReadFile => self.mem_copy(payload, text, 16),
+                    _ => {
+                        let length = self.load_at(argument(1), 8, Type::Int64);
+                        self.effect(InstKind::Store(payload, length));
+                    }
+                }
+                self.jump(end, Vec::new());
+                self.switch_to(failed);
+                let payload = self.set_variant(result, return_type, "Err");
+                self.mem_copy(payload, text, 16);
+                self.jump(end, Vec::new());
+                self.switch_to(end);
+                Some(result)
+            }
+            Builtin::ShowPrimitive(_)
+            | Builtin::ShowOption
+            | Builtin::ShowResult
+            | Builtin::ShowVec
+            | Builtin::ShowMap => {
+                let result = self.temporary(&str_type);
+                let name = self.builder.helper(Helper::Show(pointee(&types[0])));
+                self.effect(InstKind::Call(name, vec![result, argument(0)]));
+                Some(result)
+            }
+            Builtin::StrLen => self.call(&runtime::STR_LEN, vec![argument(0)]),
+            Builtin::StrIsEmpty => {
+                let length = self.load_at(argument(0), 8, Type::Int64);
+                let zero = self.int64(0);
+                Some(self.compare_op(BinaryOp::Eq, length, zero))
+            }
+            Builtin::StrConcat => Some(self.concat(argument(0), argument(1))),
+            Builtin::StrContains => {
+                let found = self
+                    .call(&runtime::STR_FIND, vec![argument(0), argument(1)])
+                    .expect("finding gives an index");
+                let missing = self.int64(-1);
+                Some(self.compare_op(BinaryOp::Ne, found, missing))
+            }
+            Builtin::StrStartsWith => {
+                self.call(&runtime::STR_STARTS_WITH, vec![argument(0), argument(1)])
+            }
+            Builtin::StrEndsWith => {
+                self.call(&runtime::STR_ENDS_WITH, vec![argument(0), argument(1)])
+            }
+            Builtin::StrFind => {
+                let found = self
+                    .call(&runtime::STR_FIND, vec![argument(0), argument(1)])
+                    .expect("finding gives an index");
+                let missing = self.int64(-1);
+                let present = self.compare_op(BinaryOp::Ne, found, missing);
+                Some(self.option(return_type, present, |this, payload| {
+                    this.effect(InstKind::Store(payload, found));
+                }))
+            }
+            Builtin::StrSlice | Builtin::StrRepeat => {
+                let result = self.temporary(&str_type);
+                let (function, mut rest, message) = match builtin {
+                    Builtin::StrSlice => (
+                        &runtime::STR_SLICE,
+                        vec![argument(1), argument(2)],
+                        "string index out of bounds",
+                    ),
+                    _ => (
+                        &runtime::STR_REPEAT,
+                        vec![argument(1)],
+                        "cannot repeat a string a negative number of times",
+                    ),
+                };
+                let mut values = vec![result, argument(0)];
+                values.append(&mut rest);
+                let succeeded = self.call(function, values).expect("gives whether it fit");
+                self.check(succeeded, message, at);
+                Some(result)
+            }
+            Builtin::StrCharAt | Builtin::StrParseInt | Builtin::StrParseFloat => {
+                let (function, ty) = match builtin {
+                    Builtin::StrCharAt => (&runtime::STR_CHAR_AT, Type::Char),
+                    Builtin::StrParseInt => (&runtime::STR_PARSE_INT, Type::Int64),
+                    _ => (&runtime::STR_PARSE_FLOAT, Type::Float64),
+                };
+                let value = self.value_of(InstKind::Alloca(ty), Type::Ptr);
+                let mut values = vec![value, argument(0)];
+                values.extend(arguments.get(1).copied().flatten());
+                let present = self
+                    .call(function, values)
+                    .expect("gives whether it found one");
+                Some(self.option(return_type, present, |this, payload| {
+                    let found = this.value_of(InstKind::Load(value), ty);
+                    this.effect(InstKind::Store(payload, found));
+                }))
+            }
+            Builtin::StrTrim
+            | Builtin::StrToUpper
+            | Builtin::StrToLower
+            | Builtin::StrReplace
+            | Builtin::StrSplit
+            | Builtin::StrLines
+            | Builtin::StrChars => {
+                let function = match builtin {
+                    Builtin::StrTrim => &runtime::STR_TRIM,
+                    Builtin::StrToUpper => &runtime::STR_TO_UPPER,
+                    Builtin::StrToLower => &runtime::STR_TO_LOWER,
+                    Builtin::StrReplace => &runtime::STR_REPLACE,
+                    Builtin::StrSplit => &runtime::STR_SPLIT,
+                    Builtin::StrLines => &runtime::STR_LINES,
+                    _ => &runtime::STR_CHARS,
+                };
+                let result = self.temporary(return_type);
+                let values = std::iter::once(result)
+                    .chain(arguments.iter().flatten().copied())
+                    .collect();
+                self.call(function, values);
+                Some(result)
+            }
+            Builtin::VecNew | Builtin::MapNew => Some(self.empty_vec(return_type)),
+            Builtin::VecLen | Builtin::MapLen => {
+                Some(self.load_at(argument(0), LENGTH, Type::Int64))
+            }
+            Builtin::VecClear | Builtin::MapClear => {
+                let zero = self.int64(0);
+                self.store_at(argument(0), LENGTH, zero);
+                None
+            }
+            Builtin::VecPush => {
+                let item = &types[1];
+                let value = self.spill(arguments[1], item);
+                let size = self.size_of(item);
+                self.call(&runtime::VEC_PUSH, vec![argument(0), value, size]);
+                None
+            }
+            Builtin::VecPop => {
+                let item = item_type(&self.builder.layouts, &pointee(&types[0]));
+                let vec = argument(0);
+                let length = self.load_at(vec, LENGTH, Type::Int64);
+                let zero = self.int64(0);
+                let present = self.compare_op(BinaryOp::Ne, length, zero);
+                Some(self.option(return_type, present, |this, payload| {
+                    let one = this.int64(1);
+                    let last =
+                        this.value_of(InstKind::Binary(BinaryOp::Sub, length, one), Type::Int64);
+                    this.store_at(vec, LENGTH, last);
+                    let address = this.item_address(vec, last, &item);
+                    let value = this.load_value(address, &item);
+                    this.store_value(payload, value, &item);
+                }))
+            }
+            Builtin::VecGet => {
+                let item = item_type(&self.builder.layouts, &pointee(&types[0]));
+                let (vec, index) = (argument(0), argument(1));
+                let present = self.in_bounds(vec, index, false);
+                Some(self.option(return_type, present, |this, payload| {
+                    let address = this.item_address(vec, index, &item);
+                    this.copy_deep(payload, address, &item);
+                }))
+            }
+            Builtin::VecSet | Builtin::VecInsert | Builtin::VecRemove => {
+                let item = item_type(&self.builder.layouts, &pointee(&types[0]));
+                let (vec, index) = (argument(0), argument(1));
+                let fits = self.in_bounds(vec, index, builtin == Builtin::VecInsert);
+                self.check(fits, "index out of bounds", at);
+                let size = self.size_of(&item);
+                match builtin {
+                    Builtin::VecSet => {
+                        let address = self.item_address(vec, index, &item);
+                        self.store_value(address, arguments[2], &item);
+                        None
+                    }
+                    Builtin::VecInsert => {
+                        let value = self.spill(arguments[2], &item);
+                        self.call(&runtime::VEC_INSERT, vec![vec, index, value, size]);
+                        None
+                    }
+                    _ => {
+                        let result = self.spill(None, &item);
+                        let result = match self.repr_is_unit(&item) {
+                            true => result,
+                            false => self.temporary(&item),
+                        };
+                        self.call(&runtime::VEC_REMOVE, vec![vec, index, result, size]);
+                        self.load_value(result, &item)
+                    }
+                }
+            }
+            Builtin::VecIter | Builtin::MapKeys | Builtin::MapValues => {
+                let collection = pointee(&types[0]);
+                let size = self.int64(ITERATION_SIZE as i64);
+                let frame = self
+                    .call(&runtime::ALLOC, vec![size])
+                    .expect("allocating gives an address");
+                let items = self.offset(frame, ITERATION_ITEMS);
+                let item = match builtin {
+                    Builtin::VecIter => {
+                        self.copy_deep(items, argument(0), &collection);
+                        item_type(&self.builder.layouts, &collection)
+                    }
+                    _ => {
+                        let Ty::Adt(_, arguments) = &collection else {
+                            unreachable!("maps have a key and a value type");
+                        };
+                        let part = match builtin {
+                            Builtin::MapKeys => 0,
+                            _ => 1,
+                        };
+                        self.project(items, argument(0), &collection, part);
+                        arguments[part].clone()
+                    }
+                };
+                let name = self.builder.helper(Helper::Iterate(item));
+                let function = self.value_of(InstKind::FuncAddr(name), Type::Ptr);
+                self.store_at(frame, GENERATOR_RESUME, function);
+                Some(frame)
+            }
+            Builtin::MapInsert | Builtin::MapGet | Builtin::MapContainsKey | Builtin::MapRemove => {
+                let map_type = pointee(&types[0]);
+                let entry = item_type(&self.builder.layouts, &map_type);
+                let Ty::Tuple(parts) = &entry else {
+                    unreachable!("entries are pairs");
+                };
+                let (key_type, value_type) = (parts[0].clone(), parts[1].clone());
+                let offsets = self.builder.layouts.offsets(&entry);
+                let map = argument(0);
+                let key = self.spill(arguments[1], &key_type);
+                let name = self.builder.helper(Helper::Find(map_type));
+                let position = self.value_of(InstKind::Call(name, vec![map, key]), Type::Int64);
+                let zero = self.int64(0);
+                let found = self.compare_op(BinaryOp::Ge, position, zero);
+                match builtin {
+                    Builtin::MapContainsKey => Some(found),
+                    Builtin::MapGet => Some(self.option(return_type, found, |this, payload| {
+                        let address = this.item_address(map, position, &entry);
+                        let address = this.offset(address, offsets[1]);
+                        this.copy_deep(payload, address, &value_type);
+                    })),
+                    Builtin::MapRemove => Some(self.option(return_type, found, |this, payload| {
+                        let removed = this.temporary(&entry);
+                        let size = this.size_of(&entry);
+                        this.call(&runtime::VEC_REMOVE, vec![map, position, removed, size]);
+                        let address = this.offset(removed, offsets[1]);
+                        let value = this.load_value(address, &value_type);
+                        this.store_value(payload, value, &value_type);
+                    })),
+                    _ => {
+                        let result = self.temporary(return_type);
+                        let ((replace, _), (add, end)) = self.branches(found);
+                        self.switch_to(replace);
+                        let payload = self.set_variant(result, return_type, "Some");
+                        let address = self.item_address(map, position, &entry);
+                        let address = self.offset(address, offsets[1]);
+                        let previous = self.load_value(address, &value_type);
+                        self.store_value(payload, previous, &value_type);
+                        self.store_value(address, arguments[2], &value_type);
+                        self.jump(end, Vec::new());
+                        self.switch_to(add);
+                        self.set_variant(result, return_type, "None");
+                        let pair = self.temporary(&entry);
+                        self.store_value(pair, arguments[1], &key_type);
+                        let address = self.offset(pair, offsets[1]);
+                        self.store_value(address, arguments[2], &value_type);
+                        let one = self.int64(1);
+                        let negated = self
+                            .value_of(InstKind::Binary(BinaryOp::Sub, zero, position), Type::Int64);
+                        let index = self
+                            .value_of(InstKind::Binary(BinaryOp::Sub, negated, one), Type::Int64);
+                        let size = self.size_of(&entry);
+                        self.call(&runtime::VEC_INSERT, vec![map, index, pair, size]);
+                        self.jump(end, Vec::new());
+                        self.switch_to(end);
+                        Some(result)
+                    }
+                }
+            }
+        }
+    }
+
+    fn repr_is_unit(&self, ty: &Ty) -> bool {
+        self.ir_type(ty).is_none()
+    }
+
+    /// Gets the size of a value of a type as an `i64`
+    fn size_of(&mut self, ty: &Ty) -> Value {
+        let size = self.builder.layouts.layout(ty).size;
+        self.int64(size as i64)
+    }
+
+    /// Gets the address of the item at an index of a `Vec` or a `Map`
+    fn item_address(&mut self, vec: Value, index: Value, item: &Ty) -> Value {
+        let items = self.load_at(vec, ITEMS, Type::Ptr);
+        let size = self.size_of(item);
+        let offset = self.value_of(InstKind::Binary(BinaryOp::Mul, index, size), Type::Int64);
+        self.value_of(InstKind::Offset(items, offset), Type::Ptr)
+    }
+
+    /// Whether an index is within a `Vec`, which includes its length when inserting
+    fn in_bounds(&mut self, vec: Value, index: Value, inserting: bool) -> Value {
+        let length = self.load_at(vec, LENGTH, Type::Int64);
+        let zero = self.int64(0);
+        let positive = self.compare_op(BinaryOp::Ge, index, zero);
+        let op = match inserting {
+            true => BinaryOp::Le,
+            false => BinaryOp::Lt,
+        };
+        let below = self.compare_op(op, index, length);
+        self.value_of(InstKind::Binary(BinaryOp::And, positive, below), Type::Bool)
+    }
+
+    /// Branches on a condition into two new blocks, the second of which is also given a block
+    /// to join at. Building continues in the first
+    fn branches(&mut self, condition: Value) -> ((BlockId, BlockId), (BlockId, BlockId)) {
+        let then_block = self.new_block(&[]);
+        let else_block = self.new_block(&[]);
+        let end = self.new_block(&[]);
+        self.branch(condition, then_block, else_block);
+        self.switch_to(then_block);
+        ((then_block, end), (else_block, end))
+    }
+
+    /// Stores the tag of the variant of an `enum` with a name, giving the address of its payload
+    fn set_variant(&mut self, result: Value, ty: &Ty, name: &str) -> Value {
+        let Ty::Adt(id, _) = ty else {
+            unreachable!("only `enum`s have variants");
+        };
+        let (index, _) = self
+            .builder
+            .defs
+            .types
+            .adt(*id)
+            .variant(Symbol::intern(name))
+            .expect("the standard library declares the variant");
+        let (tag, offsets) = self.builder.layouts.variant(ty, index);
+        let value = self.constant(
+            crate::build::constant(&LiteralKind::Int64(index as i64), tag),
+            tag,
+        );
+        self.effect(InstKind::Store(result, value));
+        match offsets.first() {
+            Some(offset) => self.offset(result, *offset),
+            None => result,
+        }
+    }
+
+    /// Makes an `Option` holding a value if it is present, which the closure stores at the address
+    /// of the payload
+    fn option(&mut self, ty: &Ty, present: Value, some: impl FnOnce(&mut Self, Value)) -> Value {
+        let result = self.temporary(ty);
+        let ((_, _), (none, end)) = self.branches(present);
+        let payload = self.set_variant(result, ty, "Some");
+        some(self, payload);
+        self.jump(end, Vec::new());
+        self.switch_to(none);
+        self.set_variant(result, ty, "None");
+        self.jump(end, Vec::new());
+        self.switch_to(end);
+        result
+    }
+
+    /// Builds a `Vec` of copies of the keys or the values of a `Map`
+    fn project(&mut self, result: Value, map: Value, ty: &Ty, part: usize) {
+        let entry = item_type(&self.builder.layouts, ty);
+        let Ty::Tuple(parts) = &entry else {
+            unreachable!("entries are pairs");
+        };
+        let item = parts[part].clone();
+        let offset = self.builder.layouts.offsets(&entry)[part];
+        let zero = self.int64(0);
+        for field in [0, 8, 16] {
+            self.store_at(result, field, zero);
+        }
+        self.for_each(map, &entry, |this, address, _| {
+            let address = this.offset(address, offset);
+            let copy = match this.repr_is_unit(&item) {
+                true => this.spill(None, &item),
+                false => this.temporary(&item),
+            };
+            this.copy_deep(copy, address, &item);
+            let size = this.size_of(&item);
+            this.call(&runtime::VEC_PUSH, vec![result, copy, size]);
+        });
+    }
+
+    /// Makes the `Vec` an array of a type is kept as, out of the values of its elements
+    pub(crate) fn array(&mut self, ty: &Ty, values: Vec<Option<Value>>) -> Value {
+        let item = item_type(&self.builder.layouts, ty);
+        let result = self.empty_vec(ty);
+        for value in values {
+            let value = self.spill(value, &item);
+            let size = self.size_of(&item);
+            self.call(&runtime::VEC_PUSH, vec![result, value, size]);
+        }
+        result
+    }
+
+    /// Makes the `Vec` a `[value; count]` of a type is kept as. The value is copied each time,
+    /// which checking made sure it can be
+    pub(crate) fn repeat(&mut self, ty: &Ty, value: Option<Value>, count: i64) -> Value {
+        let item = item_type(&self.builder.layouts, ty);
+        let result = self.empty_vec(ty);
+        let value = self.spill(value, &item);
+        let counter = self.value_of(InstKind::Alloca(Type::Int64), Type::Ptr);
+        let zero = self.int64(0);
+        self.effect(InstKind::Store(counter, zero));
+        let header = self.new_block(&[]);
+        let next = self.new_block(&[]);
+        let exit = self.new_block(&[]);
+        self.jump(header, Vec::new());
+        self.switch_to(header);
+        let index = self.value_of(InstKind::Load(counter), Type::Int64);
+        let count = self.int64(count);
+        let more = self.compare_op(BinaryOp::Lt, index, count);
+        self.branch(more, next, exit);
+        self.switch_to(next);
+        let size = self.size_of(&item);
+        self.call(&runtime::VEC_PUSH, vec![result, value, size]);
+        let one = self.int64(1);
+        let index = self.value_of(InstKind::Binary(BinaryOp::Add, index, one), Type::Int64);
+        self.effect(InstKind::Store(counter, index));
+        self.jump(header, Vec::new());
+        self.switch_to(exit);
+        result
+    }
+
+    /// Makes a `Vec` or a `Map` without any items, the way `new` does
+    fn empty_vec(&mut self, ty: &Ty) -> Value {
+        let result = self.temporary(ty);
+        let zero = self.int64(0);
+        for offset in [0, 8, 16] {
+            self.store_at(result, offset, zero);
+        }
+        result
+    }
+
+    /// Goes through the items of a `Vec` or the entries of a `Map` in order, giving the closure
+    /// their addresses and indices
+    fn for_each(&mut self, vec: Value, item: &Ty, mut body: impl FnMut(&mut Self, Value, Value)) {
+        let counter = self.value_of(InstKind::Alloca(Type::Int64), Type::Ptr);
+        let zero = self.int64(0);
+        self.effect(InstKind::Store(counter, zero));
+        let header = self.new_block(&[]);
+        let next = self.new_block(&[]);
+        let exit = self.new_block(&[]);
+        self.jump(header, Vec::new());
+        self.switch_to(header);
+        let index = self.value_of(InstKind::Load(counter), Type::Int64);
+        let length = self.load_at(vec, LENGTH, Type::Int64);
+        let more = self.compare_op(BinaryOp::Lt, index, length);
+        self.branch(more, next, exit);
+        self.switch_to(next);
+        let address = self.item_address(vec, index, item);
+        body(self, address, index);
+        let one = self.int64(1);
+        let index = self.value_of(InstKind::Binary(BinaryOp::Add, index, one), Type::Int64);
+        self.effect(InstKind::Store(counter, index));
+        self.jump(header, Vec::new());
+        self.switch_to(exit);
+    }
+
+    /// Builds the code in the closure only when a condition holds
+    fn when(&mut self, condition: Value, body: impl FnOnce(&mut Self)) {
+        let ((_, _), (skip, end)) = self.branches(condition);
+        body(self);
+        self.jump(end, Vec::new());
+        self.switch_to(skip);
+        self.jump(end, Vec::new());
+        self.switch_to(end);
+    }
+
+    /// Stores two `Str`s one after the other in a new one
+    fn concat(&mut self, left: Value, right: Value) -> Value {
+        let result = self.value_of(InstKind::Slot(16, 8), Type::Ptr);
+        self.call(&runtime::STR_CONCAT, vec![result, left, right]);
+        result
+    }
+
+    /// Appends a `Str` to the one at an address
+    fn append(&mut self, text: Value, piece: Value) {
+        let joined = self.concat(text, piece);
+        self.mem_copy(text, joined, 16);
+    }
+
+    /// Fills in the template which is the first argument with the values after it, which are
+    /// shown in order. A template whose placeholders do not match the values stops the program
+    fn fill(&mut self, arguments: &[Option<Value>], types: &[Ty], at: Span) -> Value {
+        let template = arguments[0].expect("templates are `Str`s");
+        let count = arguments.len() as i64 - 1;
+        let placeholders = self
+            .call(&runtime::PLACEHOLDERS, vec![template])
+            .expect("counting gives a number");
+        let expected = self.int64(count);
+        let matches = self.compare_op(BinaryOp::Eq, placeholders, expected);
+        self.check(matches, "invalid template", at);
+        let parts = self.value_of(InstKind::Slot(16 * count.max(1) as u64, 8), Type::Ptr);
+        for (index, (value, ty)) in arguments[1..].iter().zip(&types[1..]).enumerate() {
+            let address = self.spill(*value, ty);
+            let part = self.offset(parts, 16 * index as u64);
+            self.show(part, address, ty, Some(at));
+        }
+        let result = self.value_of(InstKind::Slot(16, 8), Type::Ptr);
+        let count = self.int64(count);
+        self.call(&runtime::FORMAT, vec![result, template, parts, count]);
+        result
+    }
+
+    /// Stores the `Str` a value of a type at an address is shown as, through its implementation
+    /// of `Show`
+    pub(crate) fn show(&mut self, result: Value, address: Value, ty: &Ty, at: Option<Span>) {
+        let (mut ty, mut address) = (ty.clone(), address);
+        let name = Symbol::intern("show");
+        let show = self.builder.defs.traits.lookup(sym::SHOW);
+        let method = loop {
+            if let Some(method) = self.builder.find_method(&ty, name, show) {
+                break method;
+            }
+            let Ty::Reference { pointee, .. } = ty else {
+                unreachable!("the values shown are checked to implement `Show`");
+            };
+            address = self.value_of(InstKind::Load(address), Type::Ptr);
+            ty = *pointee;
+        };
+        let receiver = Ty::Reference {
+            kind: shark_parse::ast::ReferenceKind::Ref,
+            mutable: false,
+            pointee: Box::new(ty.clone()),
+        };
+        let str_type = Ty::Primitive(PrimitiveType::Str);
+        match self.builder.method(method, &[receiver], &str_type) {
+            Callee::Function { name, .. } => {
+                self.inst_at(InstKind::Call(name, vec![result, address]), None, at);
+            }
+            _ => {
+                let name = self.builder.helper(Helper::Show(ty));
+                self.effect(InstKind::Call(name, vec![result, address]));
+            }
+        }
+    }
+
+    /// Calls an `extern "C"` function. A `Str` is given as a copy of its bytes followed by a zero,
+    /// and one returned is copied. The call keeps its place for the backends which can not make it
+    pub(crate) fn foreign(
+        &mut self,
+        signature: &FunctionSig,
+        arguments: Vec<Option<Value>>,
+        types: &[Ty],
+        at: Span,
+    ) -> Option<Value> {
+        let defs = self.builder.defs;
+        let ctype = |ty: &SemaType| -> Option<CType> {
+            fn ctype(defs: &shark_sema::ModuleDefs, ty: &SemaType) -> Option<CType> {
+                Some(match ty {
+                    SemaType::Primitive(PrimitiveType::Str) | SemaType::Reference { .. } => {
+                        CType::Scalar(Type::Ptr)
+                    }
+                    SemaType::Primitive(primitive) => {
+                        CType::Scalar(Layouts::primitive(*primitive).expect("not a `Str`"))
+                    }
+                    SemaType::Adt(id, _) => match &defs.types.adt(*id).kind {
+                        AdtKind::Struct { .. } => CType::Struct(
+                            defs.types
+                                .adt(*id)
+                                .fields()
+                                .iter()
+                                .filter_map(|x| ctype(defs, &x.ty))
+                                .collect(),
+                        ),
+                        _ => return None,
+                    },
+                    _ => return None,
+                })
+            }
+            ctype(defs, ty)
+        };
+        let params: Vec<CType> = signature.parameters.iter().filter_map(ctype).collect();
+        let return_type = ctype(&signature.return_type);
+        let declaration = Extern::foreign(
+            signature.name.symbol.to_string(),
+            Foreign {
+                params,
+                return_type: return_type.clone(),
+                variadic: signature.is_variadic,
+            },
+        );
+        let name = self.builder.declare(declaration);
+
+        let mut values = Vec::new();
+        let result = match &return_type {
+            Some(CType::Struct(_)) => {
+                let ty = Ty::from_type(&signature.return_type, &Default::default());
+                let result = self.temporary(&ty);
+                values.push(result);
+                Some(result)
+            }
+            _ => None,
+        };
+        for (value, ty) in arguments.into_iter().zip(types) {
+            let Some(value) = value else {
+                continue;
+            };
+            let value = match ty {
+                Ty::Primitive(PrimitiveType::Str) => self
+                    .call(&runtime::CSTR, vec![value])
+                    .expect("copying gives an address"),
+                _ => value,
+            };
+            values.push(value);
+        }
+        let ty = match &return_type {
+            Some(CType::Scalar(ty)) => Some(*ty),
+            _ => None,
+        };
+        let value = self.inst_at(InstKind::Call(name, values), ty, Some(at));
+        match signature.return_type {
+            SemaType::Primitive(PrimitiveType::Str) => {
+                let text = self.value_of(InstKind::Slot(16, 8), Type::Ptr);
+                let value = value.expect("strings are returned as addresses");
+                self.call(&runtime::STR_FROM_CSTR, vec![text, value]);
+                Some(text)
+            }
+            _ => result.or(value),
+        }
+    }
+
+    /// Builds a helper function
+    pub(crate) fn helper(
+        builder: &mut Builder<'b, 'ast>,
+        name: String,
+        helper: Helper,
+    ) -> Function {
+        let (params, return_type) = match helper {
+            Helper::Show(_) | Helper::Copy(_) => (vec![Type::Ptr, Type::Ptr], None),
+            Helper::Compare(_) => (vec![Type::Ptr, Type::Ptr], Some(Type::Int32)),
+            Helper::Find(_) => (vec![Type::Ptr, Type::Ptr], Some(Type::Int64)),
+            Helper::Iterate(_) => (vec![Type::Ptr, Type::Ptr], Some(Type::Bool)),
+        };
+        let (mut this, values) = FunctionBuilder::new(builder, name, &params, return_type);
+        let (first, second) = (values[0], values[1]);
+        match helper {
+            Helper::Show(ty) => this.show_helper(first, second, &ty),
+            Helper::Compare(ty) => this.compare_helper(first, second, &ty),
+            Helper::Copy(ty) => this.copy_helper(first, second, &ty),
+            Helper::Find(ty) => this.find_helper(first, second, &ty),
+            Helper::Iterate(ty) => this.iterate_helper(first, second, &ty),
+        }
+        this.function
+    }
+
+    /// Goes through the variants of an `enum`, building the closure for the one a value holds
+    fn each_variant(&mut self, value: Value, ty: &Ty, mut body: impl FnMut(&mut Self, usize)) {
+        let Ty::Adt(id, _) = ty else {
+            unreachable!("only `enum`s have variants");
+        };
+        let count = self.builder.defs.types.adt(*id).variants().len();
+        let (tag, tag_type) = self.tag(value, ty);
+        let end = self.new_block(&[]);
+        for index in 0..count {
+            let expected = crate::build::constant(&LiteralKind::Int64(index as i64), tag_type);
+            let expected = self.constant(expected, tag_type);
+            let matches = self.compare_op(BinaryOp::Eq, tag, expected);
+            let case = self.new_block(&[]);
+            let next = self.new_block(&[]);
+            self.branch(matches, case, next);
+            self.switch_to(case);
+            body(self, index);
+            self.jump(end, Vec::new());
+            self.switch_to(next);
+        }
+        self.terminate(Terminator::Unreachable);
+        self.switch_to(end);
+    }
+
+    fn show_helper(&mut self, result: Value, address: Value, ty: &Ty) {
+        let copy_text = |this: &mut Self, text: &str| {
+            let text = this.string(text);
+            this.mem_copy(result, text, 16);
+        };
+        match ty {
+            Ty::Primitive(PrimitiveType::Str) => self.mem_copy(result, address, 16),
+            Ty::Primitive(PrimitiveType::Bool) => {
+                let value = self.value_of(InstKind::Load(address), Type::Bool);
+                let ((_, _), (otherwise, end)) = self.branches(value);
+                copy_text(self, "true");
+                self.jump(end, Vec::new());
+                self.switch_to(otherwise);
+                copy_text(self, "false");
+                self.jump(end, Vec::new());
+                self.switch_to(end);
+            }
+            Ty::Primitive(primitive) => {
+                let ir_type = Layouts::primitive(*primitive).expect("not a `Str`");
+                let value = self.value_of(InstKind::Load(address), ir_type);
+                let (function, widened) = match ir_type {
+                    Type::Float32 => (&runtime::SHOW_FLOAT32, ir_type),
+                    Type::Float64 => (&runtime::SHOW_FLOAT64, ir_type),
+                    Type::Char => (&runtime::SHOW_CHAR, ir_type),
+                    Type::UInt8 | Type::UInt32 | Type::UInt64 => {
+                        (&runtime::SHOW_UINT, Type::UInt64)
+                    }
+                    _ => (&runtime::SHOW_INT, Type::Int64),
+                };
+                let value = match widened == ir_type {
+                    true => value,
+                    false => self.value_of(
+                        InstKind::Convert(shark_sema::numeric::Overflow::Wrap, value),
+                        widened,
+                    ),
+                };
+                self.call(function, vec![result, value]);
+            }
+            Ty::Adt(id, _) if self.builder.defs.types.adt(*id).is_enum() => {
+                let adt = self.builder.defs.types.adt(*id);
+                self.each_variant(address, ty, |this, index| {
+                    let name = adt.variants()[index].name.symbol;
+                    let payload = this.builder.layouts.payload(ty, index);
+                    if payload.is_empty() {
+                        copy_text(this, name.as_str());
+                        return;
+                    }
+                    let (_, offsets) = this.builder.layouts.variant(ty, index);
+                    let opening = this.string(&format!("{}(", name));
+                    this.mem_copy(result, opening, 16);
+                    let shown = this.value_of(InstKind::Slot(16, 8), Type::Ptr);
+                    let value = this.offset(address, offsets[0]);
+                    this.show(shown, value, &payload[0], None);
+                    this.append(result, shown);
+                    let closing = this.string(")");
+                    this.append(result, closing);
+                });
+            }
+            _ => {
+                let map = self.builder.layouts.runtime(ty) == Some(Runtime::Map);
+                let (opening, closing) = match map {
+                    true => ("{", "}"),
+                    false => ("[", "]"),
+                };
+                copy_text(self, opening);
+                let item = item_type(&self.builder.layouts, ty);
+                let offsets = self.builder.layouts.offsets(&item);
+                self.for_each(address, &item, |this, address, index| {
+                    let zero = this.int64(0);
+                    let later = this.compare_op(BinaryOp::Gt, index, zero);
+                    this.when(later, |this| {
+                        let separator = this.string(", ");
+                        this.append(result, separator);
+                    });
+                    let shown = this.value_of(InstKind::Slot(16, 8), Type::Ptr);
+                    match (map, &item) {
+                        (true, Ty::Tuple(parts)) => {
+                            this.show(shown, address, &parts[0], None);
+                            this.append(result, shown);
+                            let separator = this.string(": ");
+                            this.append(result, separator);
+                            let value = this.offset(address, offsets[1]);
+                            this.show(shown, value, &parts[1], None);
+                        }
+                        _ => this.show(shown, address, &item, None),
+                    }
+                    this.append(result, shown);
+                });
+                let closing = self.string(closing);
+                self.append(result, closing);
+            }
+        }
+        self.terminate(Terminator::Return(None));
+    }
+
+    fn compare_helper(&mut self, left: Value, right: Value, ty: &Ty) {
+        let unequal = self.new_block(&[]);
+        // Continues only if two parts of the values are equal
+        let equal_parts = |this: &mut Self, left: Value, right: Value, offset: u64, ty: &Ty| {
+            let left = this.offset(left, offset);
+            let right = this.offset(right, offset);
+            let left = this.load_value(left, ty);
+            let right = this.load_value(right, ty);
+            let (Some(left), Some(right)) = (left, right) else {
+                return;
+            };
+            let ordering = this.ordering(left, right, ty);
+            let zero = this.constant(LiteralKind::Int32(0), Type::Int32);
+            let equal = this.compare_op(BinaryOp::Eq, ordering, zero);
+            let next = this.new_block(&[]);
+            this.branch(equal, next, unequal);
+            this.switch_to(next);
+        };
+        match ty {
+            Ty::Function { .. } => self.jump(unequal, Vec::new()),
+            Ty::Adt(id, _) if self.builder.defs.types.adt(*id).is_enum() => {
+                let (left_tag, _) = self.tag(left, ty);
+                let (right_tag, _) = self.tag(right, ty);
+                let same = self.compare_op(BinaryOp::Eq, left_tag, right_tag);
+                let next = self.new_block(&[]);
+                self.branch(same, next, unequal);
+                self.switch_to(next);
+                self.each_variant(left, ty, |this, index| {
+                    let (_, offsets) = this.builder.layouts.variant(ty, index);
+                    let payload = this.builder.layouts.payload(ty, index);
+                    for (offset, ty) in offsets.iter().zip(&payload) {
+                        equal_parts(this, left, right, *offset, ty);
+                    }
+                });
+            }
+            _ if self.builder.layouts.runtime(ty).is_some() => {
+                let item = item_type(&self.builder.layouts, ty);
+                let left_length = self.load_at(left, LENGTH, Type::Int64);
+                let right_length = self.load_at(right, LENGTH, Type::Int64);
+                let same = self.compare_op(BinaryOp::Eq, left_length, right_length);
+                let next = self.new_block(&[]);
+                self.branch(same, next, unequal);
+                self.switch_to(next);
+                self.for_each(left, &item, |this, address, index| {
+                    let other = this.item_address(right, index, &item);
+                    equal_parts(this, address, other, 0, &item);
+                });
+            }
+            _ => {
+                let offsets = self.builder.layouts.offsets(ty);
+                let fields = self.builder.layouts.fields(ty);
+                for (offset, field) in offsets.iter().zip(&fields) {
+                    equal_parts(self, left, right, *offset, field);
+                }
+            }
+        }
+        let zero = self.constant(LiteralKind::Int32(0), Type::Int32);
+        self.terminate(Terminator::Return(Some(zero)));
+        self.switch_to(unequal);
+        let two = self.constant(LiteralKind::Int32(2), Type::Int32);
+        self.terminate(Terminator::Return(Some(two)));
+    }
+
+    fn copy_helper(&mut self, to: Value, from: Value, ty: &Ty) {
+        let size = self.builder.layouts.layout(ty).size;
+        self.mem_copy(to, from, size);
+        let copy_parts = |this: &mut Self, offsets: Vec<u64>, types: Vec<Ty>| {
+            for (offset, ty) in offsets.into_iter().zip(types) {
+                if this.owns_memory(&ty) {
+                    let to = this.offset(to, offset);
+                    let from = this.offset(from, offset);
+                    this.copy_deep(to, from, &ty);
+                }
+            }
+        };
+        match ty {
+            _ if self.builder.layouts.runtime(ty).is_some() => {
+                let item = item_type(&self.builder.layouts, ty);
+                let size = self.size_of(&item);
+                self.call(&runtime::VEC_COPY, vec![to, from, size]);
+                if self.owns_memory(&item) {
+                    self.for_each(from, &item, |this, address, index| {
+                        let copy = this.item_address(to, index, &item);
+                        this.copy_deep(copy, address, &item);
+                    });
+                }
+            }
+            Ty::Adt(id, _) if self.builder.defs.types.adt(*id).is_enum() => {
+                self.each_variant(from, ty, |this, index| {
+                    let (_, offsets) = this.builder.layouts.variant(ty, index);
+                    let payload = this.builder.layouts.payload(ty, index);
+                    copy_parts(this, offsets, payload);
+                });
+            }
+            _ => {
+                let offsets = self.builder.layouts.offsets(ty);
+                let fields = self.builder.layouts.fields(ty);
+                copy_parts(self, offsets, fields);
+            }
+        }
+        self.terminate(Terminator::Return(None));
+    }
+
+    /// Searches the entries of a `Map` for a key the way `binary_search_by` does, treating keys
+    /// which are not ordered as less
+    fn find_helper(&mut self, map: Value, key: Value, ty: &Ty) {
+        let entry = item_type(&self.builder.layouts, ty);
+        let Ty::Tuple(parts) = &entry else {
+            unreachable!("entries are pairs");
+        };
+        let key_type = parts[0].clone();
+        let key_value = self.load_value(key, &key_type);
+        // The ordering of the key of the entry at an index to the key searched for
+        let ordering = |this: &mut Self, index: Value| {
+            let address = this.item_address(map, index, &entry);
+            let value = this.load_value(address, &key_type);
+            match (value, key_value) {
+                (Some(value), Some(key)) => this.ordering(value, key, &key_type),
+                _ => this.constant(LiteralKind::Int32(0), Type::Int32),
+            }
+        };
+        let zero = self.int64(0);
+        let one = self.int64(1);
+        let two = self.int64(2);
+        let equal = self.constant(LiteralKind::Int32(0), Type::Int32);
+        let greater = self.constant(LiteralKind::Int32(1), Type::Int32);
+        let length = self.load_at(map, LENGTH, Type::Int64);
+        let empty = self.compare_op(BinaryOp::Eq, length, zero);
+        self.when(empty, |this| {
+            let missing = this.int64(-1);
+            this.terminate(Terminator::Return(Some(missing)));
+        });
+        let base = self.value_of(InstKind::Alloca(Type::Int64), Type::Ptr);
+        self.effect(InstKind::Store(base, zero));
+        let size = self.value_of(InstKind::Alloca(Type::Int64), Type::Ptr);
+        self.effect(InstKind::Store(size, length));
+        let header = self.new_block(&[]);
+        let halve = self.new_block(&[]);
+        let tail = self.new_block(&[]);
+        self.jump(header, Vec::new());
+        self.switch_to(header);
+        let current = self.value_of(InstKind::Load(size), Type::Int64);
+        let more = self.compare_op(BinaryOp::Gt, current, one);
+        self.branch(more, halve, tail);
+        self.switch_to(halve);
+        let half = self.value_of(InstKind::Binary(BinaryOp::Div, current, two), Type::Int64);
+        let start = self.value_of(InstKind::Load(base), Type::Int64);
+        let middle = self.value_of(InstKind::Binary(BinaryOp::Add, start, half), Type::Int64);
+        let found = ordering(self, middle);
+        let not_greater = self.compare_op(BinaryOp::Ne, found, greater);
+        self.when(not_greater, |this| {
+            this.effect(InstKind::Store(base, middle));
+        });
+        let rest = self.value_of(InstKind::Binary(BinaryOp::Sub, current, half), Type::Int64);
+        self.effect(InstKind::Store(size, rest));
+        self.jump(header, Vec::new());
+        self.switch_to(tail);
+        let start = self.value_of(InstKind::Load(base), Type::Int64);
+        let found = ordering(self, start);
+        let matches = self.compare_op(BinaryOp::Eq, found, equal);
+        self.when(matches, |this| {
+            this.terminate(Terminator::Return(Some(start)));
+        });
+        // Keys which are not ordered count as less, so the key would go after them
+        let less = self.compare_op(BinaryOp::Ne, found, greater);
+        let after = self.value_of(InstKind::Binary(BinaryOp::Add, start, one), Type::Int64);
+        let position = self.value_of(InstKind::Alloca(Type::Int64), Type::Ptr);
+        self.effect(InstKind::Store(position, start));
+        self.when(less, |this| {
+            this.effect(InstKind::Store(position, after));
+        });
+        let position = self.value_of(InstKind::Load(position), Type::Int64);
+        let negated = self.value_of(InstKind::Binary(BinaryOp::Sub, zero, position), Type::Int64);
+        let result = self.value_of(InstKind::Binary(BinaryOp::Sub, negated, one), Type::Int64);
+        self.terminate(Terminator::Return(Some(result)));
+    }
+
+    fn iterate_helper(&mut self, frame: Value, out: Value, item: &Ty) {
+        let items = self.offset(frame, ITERATION_ITEMS);
+        let index = self.load_at(frame, ITERATION_INDEX, Type::Int64);
+        let length = self.load_at(items, LENGTH, Type::Int64);
+        let more = self.compare_op(BinaryOp::Lt, index, length);
+        let ((_, _), (done, _)) = self.branches(more);
+        let address = self.item_address(items, index, item);
+        let size = self.builder.layouts.layout(item).size;
+        self.mem_copy(out, address, size);
+        let one = self.int64(1);
+        let next = self.value_of(InstKind::Binary(BinaryOp::Add, index, one), Type::Int64);
+        self.store_at(frame, ITERATION_INDEX, next);
+        let yielded = self.bool_const(true);
+        self.terminate(Terminator::Return(Some(yielded)));
+        self.switch_to(done);
+        let finished = self.constant(LiteralKind::Int32(-1), Type::Int32);
+        self.store_at(frame, GENERATOR_STATE, finished);
+        let yielded = self.bool_const(false);
+        self.terminate(Terminator::Return(Some(yielded)));
+    }
+}
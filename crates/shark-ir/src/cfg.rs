@@ -0,0 +1,66 @@
+//! Simplification of the control flow: removes the blocks which can not be reached, turns branches
+//! whose targets are the same into jumps, and merges a block into its predecessor when that is the
+//! only one and it jumps straight to it
+
+use std::collections::HashMap;
+
+use crate::ir::{Block, BlockId, Function, Terminator};
+
+/// Finds a block which only its predecessor jumps to, along with that predecessor
+fn mergeable(function: &Function) -> Option<(BlockId, BlockId)> {
+    let predecessors = function.predecessors();
+    let reachable = function.reachable();
+    (1..function.blocks.len()).find_map(|index| {
+        let [predecessor] = predecessors[index][..] else {
+            return None;
+        };
+        let jumps = matches!(function.block(predecessor).terminator, Terminator::Jump(_));
+        let merge = jumps && predecessor.0 as usize != index && reachable[index];
+        merge.then_some((predecessor, BlockId(index as u32)))
+    })
+}
+
+/// Simplifies the control flow of a function, returning whether anything changed
+pub fn simplify(function: &mut Function) -> bool {
+    let mut changed = false;
+    for block in &mut function.blocks {
+        if let Terminator::Branch(_, then_target, else_target) = &block.terminator {
+            if then_target == else_target {
+                block.terminator = Terminator::Jump(then_target.clone());
+                changed = true;
+            }
+        }
+    }
+    while let Some((predecessor, id)) = mergeable(function) {
+        let block = std::mem::replace(
+            function.block_mut(id),
+            Block {
+                params: Vec::new(),
+                insts: Vec::new(),
+                terminator: Terminator::Unreachable,
+            },
+        );
+        let Terminator::Jump(target) = std::mem::replace(
+            &mut function.block_mut(predecessor).terminator,
+            block.terminator,
+        ) else {
+            unreachable!("only blocks jumped to are merged");
+        };
+        let predecessor = function.block_mut(predecessor);
+        predecessor.insts.extend(block.insts);
+        let replacements: HashMap<_, _> = block
+            .params
+            .iter()
+            .map(|(value, _)| *value)
+            .zip(target.arguments)
+            .collect();
+        function.replace_uses(|x| replacements.get(&x).copied().unwrap_or(x));
+        changed = true;
+    }
+    let reachable = function.reachable();
+    if reachable.contains(&false) {
+        function.retain_blocks(&reachable);
+        changed = true;
+    }
+    changed
+}
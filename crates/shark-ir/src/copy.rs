@@ -0,0 +1,86 @@
+//! Copy propagation: replaces the uses of a copy by the value it copies, and removes the
+//! parameters of blocks which are always given the same value, using that value instead. Building
+//! the IR gives every block a parameter for every local, most of which are of the second kind
+
+use std::collections::HashMap;
+
+use crate::ir::{BlockId, Function, InstKind, Value};
+
+/// Follows a value through the replacements found so far
+fn resolve(replacements: &HashMap<Value, Value>, mut value: Value) -> Value {
+    while let Some(replacement) = replacements.get(&value) {
+        value = *replacement;
+    }
+    value
+}
+
+/// Finds the parameters of a block which are always given the same value, other than themselves
+fn trivial_params(
+    function: &Function,
+    replacements: &HashMap<Value, Value>,
+    id: BlockId,
+) -> Vec<Option<Value>> {
+    let block = function.block(id);
+    let mut incoming: Vec<Option<Value>> = vec![None; block.params.len()];
+    let mut trivial = vec![id != BlockId::ENTRY; block.params.len()];
+    let mut reached = false;
+    for target in function
+        .blocks
+        .iter()
+        .flat_map(|x| x.terminator.targets())
+        .filter(|x| x.block == id)
+    {
+        reached = true;
+        for (index, argument) in target.arguments.iter().enumerate() {
+            let argument = resolve(replacements, *argument);
+            if argument == block.params[index].0 {
+                continue;
+            }
+            match incoming[index] {
+                Some(value) if value != argument => trivial[index] = false,
+                _ => incoming[index] = Some(argument),
+            }
+        }
+    }
+    // Blocks which nothing branches to are left for the simplification of the control flow
+    incoming
+        .into_iter()
+        .zip(trivial)
+        .map(|(value, trivial)| value.filter(|_| trivial && reached))
+        .collect()
+}
+
+/// Propagates the copies of a function, returning whether anything changed
+pub fn propagate(function: &mut Function) -> bool {
+    let mut replacements = HashMap::new();
+    for block in &mut function.blocks {
+        block.insts.retain(|inst| match (&inst.kind, inst.result) {
+            (InstKind::Copy(source), Some((result, _))) => {
+                replacements.insert(result, *source);
+                false
+            }
+            _ => true,
+        });
+    }
+    for index in 0..function.blocks.len() {
+        let id = BlockId(index as u32);
+        let trivial = trivial_params(function, &replacements, id);
+        if trivial.iter().all(Option::is_none) {
+            continue;
+        }
+        for (param, value) in function.block(id).params.iter().zip(&trivial) {
+            if let Some(value) = value {
+                replacements.insert(param.0, *value);
+            }
+        }
+        let keep: Vec<_> = trivial.iter().map(Option::is_none).collect();
+        function.retain_params(id, &keep);
+        // Removing a parameter can make the parameters it was given to trivial in turn, which
+        // the next round finds
+    }
+    if replacements.is_empty() {
+        return false;
+    }
+    function.replace_uses(|x| resolve(&replacements, x));
+    true
+}
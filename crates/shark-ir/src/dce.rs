@@ -0,0 +1,87 @@
+//! Dead-code elimination: removes the instructions whose results are never used and which do
+//! nothing else, and the parameters of blocks which are never used, along with the arguments
+//! given for them. A value is only used by a branch if the parameter it is given to is used, so
+//! values which only flow around a loop are removed too
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ir::{BlockId, Function, Terminator};
+
+enum Definition {
+    Param(BlockId, usize),
+    Inst(BlockId, usize),
+}
+
+/// Removes the dead code of a function, returning whether anything changed
+pub fn eliminate(function: &mut Function) -> bool {
+    let mut definitions = HashMap::new();
+    let mut live = HashSet::new();
+    let mut worklist = Vec::new();
+    for (index, block) in function.blocks.iter().enumerate() {
+        let id = BlockId(index as u32);
+        for (position, (value, _)) in block.params.iter().enumerate() {
+            definitions.insert(*value, Definition::Param(id, position));
+        }
+        for (position, inst) in block.insts.iter().enumerate() {
+            if let Some((value, _)) = inst.result {
+                definitions.insert(value, Definition::Inst(id, position));
+            }
+            if inst.kind.has_effects() {
+                worklist.extend(inst.kind.operands());
+            }
+        }
+        match &block.terminator {
+            Terminator::Branch(condition, ..) => worklist.push(*condition),
+            Terminator::Return(Some(value)) => worklist.push(*value),
+            _ => {}
+        }
+    }
+    let predecessors = function.predecessors();
+    while let Some(value) = worklist.pop() {
+        if !live.insert(value) {
+            continue;
+        }
+        match definitions.get(&value) {
+            Some(Definition::Inst(block, position)) => {
+                let inst = &function.block(*block).insts[*position];
+                worklist.extend(inst.kind.operands());
+            }
+            Some(Definition::Param(block, position)) => {
+                for predecessor in &predecessors[block.0 as usize] {
+                    let terminator = &function.block(*predecessor).terminator;
+                    for target in terminator.targets() {
+                        if target.block == *block {
+                            worklist.push(target.arguments[*position]);
+                        }
+                    }
+                }
+            }
+            None => {}
+        }
+    }
+
+    let mut changed = false;
+    for block in &mut function.blocks {
+        let before = block.insts.len();
+        block.insts.retain(|inst| match inst.result {
+            Some((value, _)) => inst.kind.has_effects() || live.contains(&value),
+            None => true,
+        });
+        changed |= block.insts.len() != before;
+    }
+    // The parameters of the entry are those of the function, which stay
+    for index in 1..function.blocks.len() {
+        let id = BlockId(index as u32);
+        let keep: Vec<_> = function
+            .block(id)
+            .params
+            .iter()
+            .map(|(value, _)| live.contains(value))
+            .collect();
+        if keep.contains(&false) {
+            function.retain_params(id, &keep);
+            changed = true;
+        }
+    }
+    changed
+}
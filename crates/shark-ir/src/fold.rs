@@ -0,0 +1,215 @@
+//! Constant folding: replaces arithmetic on constants by its result, and branches on a constant
+//! by a jump. Checked arithmetic whose result does not fit is left alone, so that it still stops
+//! the program when it is reached
+
+use std::collections::HashMap;
+
+use shark_lex::token::LiteralKind;
+use shark_parse::ast::BinaryOperator;
+use shark_sema::numeric::{self, Number, Overflow};
+
+use crate::ir::{BinaryOp, Function, InstKind, Terminator, Type, UnaryOp, Value};
+
+/// Gets the value of an integer constant
+fn integer(literal: &LiteralKind) -> Option<i128> {
+    Some(match *literal {
+        LiteralKind::UInt8(x) => x as i128,
+        LiteralKind::Int8(x) => x as i128,
+        LiteralKind::UInt32(x) => x as i128,
+        LiteralKind::Int32(x) => x as i128,
+        LiteralKind::UInt64(x) => x as i128,
+        LiteralKind::Int64(x) => x as i128,
+        _ => return None,
+    })
+}
+
+/// Gets the constant of an integer type with a value, if it fits
+fn integer_of(ty: Type, value: i128) -> Option<LiteralKind> {
+    Some(match ty {
+        Type::Int8 => LiteralKind::Int8(value.try_into().ok()?),
+        Type::UInt8 => LiteralKind::UInt8(value.try_into().ok()?),
+        Type::Int32 => LiteralKind::Int32(value.try_into().ok()?),
+        Type::UInt32 => LiteralKind::UInt32(value.try_into().ok()?),
+        Type::Int64 => LiteralKind::Int64(value.try_into().ok()?),
+        Type::UInt64 => LiteralKind::UInt64(value.try_into().ok()?),
+        _ => return None,
+    })
+}
+
+/// Gets the number of bits of an integer type
+fn bits(ty: Type) -> u32 {
+    match ty {
+        Type::Int8 | Type::UInt8 => 8,
+        Type::Int32 | Type::UInt32 => 32,
+        _ => 64,
+    }
+}
+
+/// Keeps the bits of a value which fit in an integer type, as a shift to the left does
+fn truncate(ty: Type, value: i128) -> i128 {
+    let bits = bits(ty);
+    let value = value & ((1 << bits) - 1);
+    let signed = matches!(ty, Type::Int8 | Type::Int32 | Type::Int64);
+    match signed && value >> (bits - 1) == 1 {
+        true => value - (1 << bits),
+        false => value,
+    }
+}
+
+/// Applies a unary operator to a constant, unless it does not fit
+pub fn unary(op: UnaryOp, operand: &LiteralKind) -> Option<LiteralKind> {
+    let ty = Type::of_literal(operand);
+    match (op, *operand) {
+        (UnaryOp::Neg, LiteralKind::Float32(x)) => Some(LiteralKind::Float32(-x)),
+        (UnaryOp::Neg, LiteralKind::Float64(x)) => Some(LiteralKind::Float64(-x)),
+        (UnaryOp::Not, LiteralKind::Boolean(x)) => Some(LiteralKind::Boolean(!x)),
+        (UnaryOp::Neg, _) => integer_of(ty, -integer(operand)?),
+        (UnaryOp::Not, _) => integer_of(ty, truncate(ty, !integer(operand)?)),
+    }
+}
+
+/// Applies a binary operator to constants, unless it does not fit or divides by zero
+pub fn binary(op: BinaryOp, left: &LiteralKind, right: &LiteralKind) -> Option<LiteralKind> {
+    let ty = Type::of_literal(left);
+    if op.is_comparison() {
+        let ordering = match (*left, *right) {
+            (LiteralKind::Float32(x), LiteralKind::Float32(y)) => x.partial_cmp(&y),
+            (LiteralKind::Float64(x), LiteralKind::Float64(y)) => x.partial_cmp(&y),
+            (LiteralKind::Boolean(x), LiteralKind::Boolean(y)) => x.partial_cmp(&y),
+            (LiteralKind::Char(x), LiteralKind::Char(y)) => x.partial_cmp(&y),
+            _ => integer(left)?.partial_cmp(&integer(right)?),
+        };
+        let result = match op {
+            BinaryOp::Eq => ordering.is_some_and(|x| x.is_eq()),
+            BinaryOp::Ne => !ordering.is_some_and(|x| x.is_eq()),
+            BinaryOp::Lt => ordering.is_some_and(|x| x.is_lt()),
+            BinaryOp::Le => ordering.is_some_and(|x| x.is_le()),
+            BinaryOp::Gt => ordering.is_some_and(|x| x.is_gt()),
+            _ => ordering.is_some_and(|x| x.is_ge()),
+        };
+        return Some(LiteralKind::Boolean(result));
+    }
+    match (op, *left, *right) {
+        (_, LiteralKind::Float32(x), LiteralKind::Float32(y)) => {
+            return float(op, x as f64, y as f64).map(|x| LiteralKind::Float32(x as f32))
+        }
+        (_, LiteralKind::Float64(x), LiteralKind::Float64(y)) => {
+            return float(op, x, y).map(LiteralKind::Float64)
+        }
+        (BinaryOp::And, LiteralKind::Boolean(x), LiteralKind::Boolean(y)) => {
+            return Some(LiteralKind::Boolean(x & y))
+        }
+        _ => {}
+    }
+    let (x, y) = (integer(left)?, integer(right)?);
+    let result = match op {
+        BinaryOp::Add => x + y,
+        BinaryOp::Sub => x - y,
+        BinaryOp::Mul => x.checked_mul(y)?,
+        BinaryOp::Div if y == 0 => return None,
+        BinaryOp::Div => x / y,
+        BinaryOp::Shl | BinaryOp::Shr if !(0..bits(ty) as i128).contains(&y) => return None,
+        BinaryOp::Shl => truncate(ty, x << y),
+        BinaryOp::Shr => x >> y,
+        _ => x & y,
+    };
+    integer_of(ty, result)
+}
+
+/// Applies an operator to floats, which never fails. Results of `float32`s are worked out as
+/// `float64`s, which is exact for the four operators before rounding back
+fn float(op: BinaryOp, x: f64, y: f64) -> Option<f64> {
+    match op {
+        BinaryOp::Add => Some(x + y),
+        BinaryOp::Sub => Some(x - y),
+        BinaryOp::Mul => Some(x * y),
+        BinaryOp::Div => Some(x / y),
+        _ => None,
+    }
+}
+
+/// Applies an operator which wraps around or saturates to integer constants, unless it divides
+/// by zero
+pub fn arith(
+    overflow: Overflow,
+    op: BinaryOp,
+    left: &LiteralKind,
+    right: &LiteralKind,
+) -> Option<LiteralKind> {
+    let ty = Type::of_literal(left);
+    let operator = match op {
+        BinaryOp::Add => BinaryOperator::Add,
+        BinaryOp::Sub => BinaryOperator::Subtract,
+        BinaryOp::Mul => BinaryOperator::Multiply,
+        BinaryOp::Div => BinaryOperator::Divide,
+        _ => return None,
+    };
+    let (x, y) = (integer(left)?, integer(right)?);
+    let result = numeric::arith(operator, overflow, ty.primitive()?, x, y).ok()?;
+    integer_of(ty, result)
+}
+
+/// Converts a numeric constant to another numeric type, unless it does not fit and traps
+pub fn convert(overflow: Overflow, ty: Type, operand: &LiteralKind) -> Option<LiteralKind> {
+    let number = match *operand {
+        LiteralKind::Float32(x) => Number::Float(x.into()),
+        LiteralKind::Float64(x) => Number::Float(x),
+        _ => Number::Integer(integer(operand)?),
+    };
+    match numeric::convert(number, ty.primitive()?, overflow).ok()? {
+        Number::Float(x) if ty == Type::Float32 => Some(LiteralKind::Float32(x as f32)),
+        Number::Float(x) => Some(LiteralKind::Float64(x)),
+        Number::Integer(x) => integer_of(ty, x),
+    }
+}
+
+/// Folds the constants of a function, returning whether anything changed
+pub fn fold(function: &mut Function) -> bool {
+    let mut constants: HashMap<Value, LiteralKind> = HashMap::new();
+    let mut changed = false;
+    // Blocks are not visited in an order where every definition comes before its uses, so this
+    // may take a few rounds to reach everything, as the pass is run until nothing changes
+    for block in &mut function.blocks {
+        for inst in &mut block.insts {
+            let folded = match &inst.kind {
+                InstKind::Const(literal) => {
+                    if let Some((result, _)) = inst.result {
+                        constants.insert(result, *literal);
+                    }
+                    continue;
+                }
+                InstKind::Copy(x) => constants.get(x).copied(),
+                InstKind::Unary(op, x) => constants.get(x).and_then(|x| unary(*op, x)),
+                InstKind::Binary(op, x, y) => match (constants.get(x), constants.get(y)) {
+                    (Some(x), Some(y)) => binary(*op, x, y),
+                    _ => None,
+                },
+                InstKind::Arith(overflow, op, x, y) => match (constants.get(x), constants.get(y)) {
+                    (Some(x), Some(y)) => arith(*overflow, *op, x, y),
+                    _ => None,
+                },
+                InstKind::Convert(overflow, x) => match (constants.get(x), inst.result) {
+                    (Some(x), Some((_, ty))) => convert(*overflow, ty, x),
+                    _ => None,
+                },
+                _ => None,
+            };
+            if let (Some(literal), Some((result, _))) = (folded, inst.result) {
+                inst.kind = InstKind::Const(literal);
+                constants.insert(result, literal);
+                changed = true;
+            }
+        }
+        if let Terminator::Branch(condition, then_target, else_target) = &block.terminator {
+            if let Some(LiteralKind::Boolean(holds)) = constants.get(condition) {
+                let target = match holds {
+                    true => then_target.clone(),
+                    false => else_target.clone(),
+                };
+                block.terminator = Terminator::Jump(target);
+                changed = true;
+            }
+        }
+    }
+    changed
+}
@@ -0,0 +1,129 @@
+//! Inlining: replaces calls to small functions by a copy of their body. The block holding the call
+//! is split at it, the copy of the entry is jumped to with the arguments, and every `ret` of the
+//! copy jumps to the rest of the block, which takes the result as a parameter
+//!
+//! Functions calling themselves are never inlined. Inlining a function which calls another only
+//! copies the call, which a later round can inline in turn, so the rounds are bounded to keep
+//! mutually recursive functions from growing without end
+
+use std::collections::HashMap;
+
+use crate::ir::{Block, BlockId, Function, Inst, InstKind, Module, Target, Terminator, Value};
+
+/// The size up to which a function is inlined, see [Function::size]
+pub const INLINE_SIZE: usize = 40;
+
+/// How many times calls are looked for, including those copied by inlining
+pub const ROUNDS: usize = 3;
+
+/// Whether calls to a function may be replaced by its body
+fn inlinable(function: &Function) -> bool {
+    let recursive = function
+        .blocks
+        .iter()
+        .flat_map(|x| &x.insts)
+        .any(|x| matches!(&x.kind, InstKind::Call(name, _) if *name == function.name));
+    !recursive && function.size() <= INLINE_SIZE
+}
+
+/// Replaces the call at a position of a block by a copy of the body of a function
+fn inline_call(function: &mut Function, id: BlockId, position: usize, callee: &Function) {
+    let block = function.block_mut(id);
+    let rest = block.insts.split_off(position + 1);
+    let call = block.insts.pop().expect("the call is at the position");
+    let InstKind::Call(_, arguments) = call.kind else {
+        unreachable!("only calls are inlined");
+    };
+    let continuation = Block {
+        params: call.result.into_iter().collect(),
+        insts: rest,
+        terminator: std::mem::replace(&mut block.terminator, Terminator::Unreachable),
+    };
+    let offset = function.blocks.len() as u32;
+    let continuation_id = BlockId(offset + callee.blocks.len() as u32);
+    function.block_mut(id).terminator = Terminator::Jump(Target::new(BlockId(offset), arguments));
+
+    let mut values = HashMap::new();
+    let mut value = |function: &mut Function, x: Value| {
+        *values.entry(x).or_insert_with(|| function.new_value())
+    };
+    for block in &callee.blocks {
+        let params = block
+            .params
+            .iter()
+            .map(|(x, ty)| (value(function, *x), *ty))
+            .collect();
+        let mut insts = Vec::new();
+        for inst in &block.insts {
+            let mut kind = inst.kind.clone();
+            for operand in kind.operands_mut() {
+                *operand = value(function, *operand);
+            }
+            let result = inst.result.map(|(x, ty)| (value(function, x), ty));
+            insts.push(Inst {
+                result,
+                kind,
+                at: inst.at,
+            });
+        }
+        let mut terminator = match &block.terminator {
+            Terminator::Return(result) => Terminator::Jump(Target::new(
+                continuation_id,
+                result.iter().copied().collect(),
+            )),
+            terminator => terminator.clone(),
+        };
+        for operand in terminator.operands_mut() {
+            *operand = value(function, *operand);
+        }
+        for target in terminator.targets_mut() {
+            if target.block != continuation_id {
+                target.block = BlockId(target.block.0 + offset);
+            }
+        }
+        function.blocks.push(Block {
+            params,
+            insts,
+            terminator,
+        });
+    }
+    function.blocks.push(continuation);
+}
+
+/// Inlines the calls of every function of a module to the functions small enough, returning
+/// whether anything changed
+pub fn inline(module: &mut Module) -> bool {
+    let mut changed = false;
+    for _ in 0..ROUNDS {
+        let callees: HashMap<String, Function> = module
+            .functions
+            .iter()
+            .filter(|x| inlinable(x))
+            .map(|x| (x.name.clone(), x.clone()))
+            .collect();
+        let mut round = false;
+        for function in &mut module.functions {
+            let mut calls = Vec::new();
+            for (index, block) in function.blocks.iter().enumerate() {
+                for (position, inst) in block.insts.iter().enumerate() {
+                    if let InstKind::Call(name, _) = &inst.kind {
+                        if let Some(callee) = callees.get(name) {
+                            calls.push((BlockId(index as u32), position, callee));
+                        }
+                    }
+                }
+            }
+            // Later calls are inlined first, so that splitting a block leaves the positions of the
+            // calls before them as they were
+            for (id, position, callee) in calls.into_iter().rev() {
+                inline_call(function, id, position, callee);
+                round = true;
+            }
+        }
+        if !round {
+            break;
+        }
+        changed = true;
+    }
+    changed
+}
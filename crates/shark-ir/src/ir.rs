@@ -0,0 +1,627 @@
+//! The IR itself. A [Function] is a list of [Block]s, the first of which is its entry, whose
+//! parameters are the parameters of the function. Values are defined once, either as a parameter
+//! of a block or as the result of an [Inst], and blocks take the values flowing into them from
+//! their predecessors as parameters rather than through phi nodes
+//!
+//! Integer arithmetic is checked: `add`, `sub`, `mul`, `div`, `neg`, `shl` and `shr` stop the
+//! program with the same runtime error as the interpreter when their result does not fit, so they
+//! can only be removed once their operands are known. A release build wraps around instead, which
+//! is an [InstKind::Arith] such as `add.wrap`, as are the `wrapping_` and `saturating_` methods
+//!
+//! Values which are not scalars, such as strings, structs and enums, live in memory and are handed
+//! around by their address. Every `slot` and `alloca` is one place in the frame of its function,
+//! which stays the same however many times it is run, and memory which outlives a call is given by
+//! the runtime. Functions the module calls but does not define are declared as [Extern]s
+
+use std::fmt;
+
+use shark_core::source::Span;
+use shark_lex::token::LiteralKind;
+use shark_sema::{numeric::Overflow, ty::PrimitiveType};
+
+/// Identifies a value within a [Function]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Value(pub u32);
+
+/// Identifies a [Block] within a [Function], where the entry is always the first
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+impl BlockId {
+    pub const ENTRY: Self = Self(0);
+}
+
+/// The type of a value. Values of type `()` are never made, so there is no type for them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Type {
+    Int8,
+    UInt8,
+    Int32,
+    UInt32,
+    Int64,
+    UInt64,
+    Float32,
+    Float64,
+    Bool,
+    Char,
+    /// The address of a value of any type
+    Ptr,
+}
+
+impl Type {
+    pub const ALL: [Self; 11] = [
+        Self::Int8,
+        Self::UInt8,
+        Self::Int32,
+        Self::UInt32,
+        Self::Int64,
+        Self::UInt64,
+        Self::Float32,
+        Self::Float64,
+        Self::Bool,
+        Self::Char,
+        Self::Ptr,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Int8 => "i8",
+            Self::UInt8 => "u8",
+            Self::Int32 => "i32",
+            Self::UInt32 => "u32",
+            Self::Int64 => "i64",
+            Self::UInt64 => "u64",
+            Self::Float32 => "f32",
+            Self::Float64 => "f64",
+            Self::Bool => "bool",
+            Self::Char => "char",
+            Self::Ptr => "ptr",
+        }
+    }
+
+    pub fn is_integer(self) -> bool {
+        matches!(
+            self,
+            Self::Int8 | Self::UInt8 | Self::Int32 | Self::UInt32 | Self::Int64 | Self::UInt64
+        )
+    }
+
+    pub fn is_float(self) -> bool {
+        matches!(self, Self::Float32 | Self::Float64)
+    }
+
+    /// Gets the primitive type of a number
+    pub fn primitive(self) -> Option<PrimitiveType> {
+        Some(match self {
+            Self::Int8 => PrimitiveType::Int8,
+            Self::UInt8 => PrimitiveType::UInt8,
+            Self::Int32 => PrimitiveType::Int32,
+            Self::UInt32 => PrimitiveType::UInt32,
+            Self::Int64 => PrimitiveType::Int64,
+            Self::UInt64 => PrimitiveType::UInt64,
+            Self::Float32 => PrimitiveType::Float32,
+            Self::Float64 => PrimitiveType::Float64,
+            _ => return None,
+        })
+    }
+
+    /// Gets how many bytes a value of the type takes in memory, which is also its alignment
+    pub fn size(self) -> u64 {
+        match self {
+            Self::Int8 | Self::UInt8 | Self::Bool => 1,
+            Self::Int32 | Self::UInt32 | Self::Float32 | Self::Char => 4,
+            Self::Int64 | Self::UInt64 | Self::Float64 | Self::Ptr => 8,
+        }
+    }
+
+    /// Gets the type of a constant. A string is the address of its bytes
+    pub fn of_literal(literal: &LiteralKind) -> Self {
+        match literal {
+            LiteralKind::UInt8(_) => Self::UInt8,
+            LiteralKind::Int8(_) => Self::Int8,
+            LiteralKind::UInt32(_) => Self::UInt32,
+            LiteralKind::Int32(_) => Self::Int32,
+            LiteralKind::UInt64(_) => Self::UInt64,
+            LiteralKind::Int64(_) => Self::Int64,
+            LiteralKind::Float32(_) => Self::Float32,
+            LiteralKind::Float64(_) => Self::Float64,
+            LiteralKind::Char(_) => Self::Char,
+            LiteralKind::Boolean(_) => Self::Bool,
+            LiteralKind::Str(_) => Self::Ptr,
+        }
+    }
+}
+
+impl fmt::Display for Type {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum UnaryOp {
+    Neg,
+    /// Logical not of a `bool`, and bitwise not of an integer
+    Not,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Shl,
+    Shr,
+    /// Bitwise and of integers, or of `bool`s
+    And,
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// Gets what follows the name of an instruction which overflows some way
+pub fn overflow_suffix(overflow: Overflow) -> &'static str {
+    match overflow {
+        Overflow::Trap => "",
+        Overflow::Wrap => ".wrap",
+        Overflow::Saturate => ".sat",
+    }
+}
+
+impl BinaryOp {
+    pub const ALL: [Self; 13] = [
+        Self::Add,
+        Self::Sub,
+        Self::Mul,
+        Self::Div,
+        Self::Shl,
+        Self::Shr,
+        Self::And,
+        Self::Eq,
+        Self::Ne,
+        Self::Lt,
+        Self::Le,
+        Self::Gt,
+        Self::Ge,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Add => "add",
+            Self::Sub => "sub",
+            Self::Mul => "mul",
+            Self::Div => "div",
+            Self::Shl => "shl",
+            Self::Shr => "shr",
+            Self::And => "and",
+            Self::Eq => "eq",
+            Self::Ne => "ne",
+            Self::Lt => "lt",
+            Self::Le => "le",
+            Self::Gt => "gt",
+            Self::Ge => "ge",
+        }
+    }
+
+    /// Whether the operator compares its operands, giving a `bool`
+    pub fn is_comparison(self) -> bool {
+        matches!(
+            self,
+            Self::Eq | Self::Ne | Self::Lt | Self::Le | Self::Gt | Self::Ge
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum InstKind {
+    /// A number, a `bool`, a `char`, or the address of the UTF-8 bytes of a string, whose length
+    /// is a constant of its own
+    Const(LiteralKind),
+    /// The same value under another name
+    Copy(Value),
+    Unary(UnaryOp, Value),
+    /// Applies an operator to two values of the same type, except for shifts, whose amount can be
+    /// of any integer type
+    Binary(BinaryOp, Value, Value),
+    /// `add`, `sub`, `mul` or `div` on two integers of the same type which wraps around or
+    /// saturates rather than stopping the program, printed as `add.wrap` or `add.sat`. Dividing by
+    /// zero still stops it
+    Arith(Overflow, BinaryOp, Value, Value),
+    /// Converts a number to the numeric type of the result, either trapping or wrapping around
+    /// when it does not fit, printed as `conv` or `conv.wrap`
+    Convert(Overflow, Value),
+    /// Makes room for a value of a type in the frame of the function, giving its address
+    Alloca(Type),
+    /// Makes room for a size of bytes with an alignment in the frame of the function, giving its
+    /// address
+    Slot(u64, u64),
+    /// Adds an `i64` number of bytes to an address
+    Offset(Value, Value),
+    Load(Value),
+    /// Stores the second value at the address which is the first
+    Store(Value, Value),
+    /// Copies an `i64` number of bytes from the second address to the first, which may overlap
+    MemCopy(Value, Value, Value),
+    /// Calls a function of the module or an [Extern] by its name
+    Call(String, Vec<Value>),
+    /// The address of a function of the module, which [InstKind::CallIndirect] can call
+    FuncAddr(String),
+    /// Calls the function at an address with the rest of the values
+    CallIndirect(Value, Vec<Value>),
+    /// Stops the program with a runtime error, whose message is the text followed by the `Str` at
+    /// the address if there is one. It is followed by `unreachable`
+    Panic(String, Option<Value>),
+}
+
+impl InstKind {
+    /// Gets the values the instruction uses
+    pub fn operands(&self) -> Vec<Value> {
+        match self {
+            Self::Const(_) | Self::Alloca(_) | Self::Slot(..) | Self::FuncAddr(_) => Vec::new(),
+            Self::Panic(_, x) => x.iter().copied().collect(),
+            Self::Copy(x) | Self::Unary(_, x) | Self::Convert(_, x) | Self::Load(x) => vec![*x],
+            Self::Binary(_, x, y) | Self::Arith(_, _, x, y) | Self::Store(x, y) => vec![*x, *y],
+            Self::Offset(x, y) => vec![*x, *y],
+            Self::MemCopy(x, y, z) => vec![*x, *y, *z],
+            Self::Call(_, arguments) => arguments.clone(),
+            Self::CallIndirect(callee, arguments) => std::iter::once(*callee)
+                .chain(arguments.iter().copied())
+                .collect(),
+        }
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Self::Const(_) | Self::Alloca(_) | Self::Slot(..) | Self::FuncAddr(_) => Vec::new(),
+            Self::Panic(_, x) => x.iter_mut().collect(),
+            Self::Copy(x) | Self::Unary(_, x) | Self::Convert(_, x) | Self::Load(x) => vec![x],
+            Self::Binary(_, x, y) | Self::Arith(_, _, x, y) | Self::Store(x, y) => vec![x, y],
+            Self::Offset(x, y) => vec![x, y],
+            Self::MemCopy(x, y, z) => vec![x, y, z],
+            Self::Call(_, arguments) => arguments.iter_mut().collect(),
+            Self::CallIndirect(callee, arguments) => std::iter::once(callee)
+                .chain(arguments.iter_mut())
+                .collect(),
+        }
+    }
+
+    /// Whether the instruction does anything besides giving its result, which means it can not be
+    /// removed even if its result is unused. Checked arithmetic can stop the program, so it counts
+    pub fn has_effects(&self) -> bool {
+        match self {
+            Self::Const(_) | Self::Copy(_) | Self::Alloca(_) | Self::Load(_) => false,
+            Self::Slot(..) | Self::Offset(..) | Self::FuncAddr(_) => false,
+            Self::Unary(UnaryOp::Not, _) => false,
+            Self::Binary(op, _, _) => !op.is_comparison() && *op != BinaryOp::And,
+            Self::Arith(_, op, _, _) => *op == BinaryOp::Div,
+            Self::Convert(overflow, _) => *overflow == Overflow::Trap,
+            Self::Unary(UnaryOp::Neg, _) | Self::Store(..) | Self::MemCopy(..) => true,
+            Self::Call(..) | Self::CallIndirect(..) | Self::Panic(..) => true,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Inst {
+    /// The value the instruction defines, along with its type
+    pub result: Option<(Value, Type)>,
+    pub kind: InstKind,
+    /// Where the runtime error the instruction may stop the program with is reported. Calls of
+    /// functions of the module which have a place count towards how deep calls are nested, the
+    /// others are calls into the runtime. Calls of foreign functions keep theirs for the backends
+    /// which can not make them
+    pub at: Option<Span>,
+}
+
+impl Inst {
+    pub fn new(result: Option<(Value, Type)>, kind: InstKind) -> Self {
+        Self {
+            result,
+            kind,
+            at: None,
+        }
+    }
+}
+
+/// A block to continue with, along with the values for its parameters
+#[derive(Debug, Clone, PartialEq)]
+pub struct Target {
+    pub block: BlockId,
+    pub arguments: Vec<Value>,
+}
+
+impl Target {
+    pub fn new(block: BlockId, arguments: Vec<Value>) -> Self {
+        Self { block, arguments }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Terminator {
+    Jump(Target),
+    /// Continues with the first target if the `bool` holds and the second if it does not
+    Branch(Value, Target, Target),
+    Return(Option<Value>),
+    /// Ends a block which can never be reached the end of
+    Unreachable,
+}
+
+impl Terminator {
+    pub fn targets(&self) -> Vec<&Target> {
+        match self {
+            Self::Jump(target) => vec![target],
+            Self::Branch(_, then_target, else_target) => vec![then_target, else_target],
+            Self::Return(_) | Self::Unreachable => Vec::new(),
+        }
+    }
+
+    pub fn targets_mut(&mut self) -> Vec<&mut Target> {
+        match self {
+            Self::Jump(target) => vec![target],
+            Self::Branch(_, then_target, else_target) => vec![then_target, else_target],
+            Self::Return(_) | Self::Unreachable => Vec::new(),
+        }
+    }
+
+    /// Gets the values the terminator uses, including the arguments of its targets
+    pub fn operands(&self) -> Vec<Value> {
+        let mut operands = match self {
+            Self::Branch(condition, ..) => vec![*condition],
+            Self::Return(Some(value)) => vec![*value],
+            _ => Vec::new(),
+        };
+        for target in self.targets() {
+            operands.extend(&target.arguments);
+        }
+        operands
+    }
+
+    pub fn operands_mut(&mut self) -> Vec<&mut Value> {
+        match self {
+            Self::Jump(target) => target.arguments.iter_mut().collect(),
+            Self::Branch(condition, then_target, else_target) => std::iter::once(condition)
+                .chain(&mut then_target.arguments)
+                .chain(&mut else_target.arguments)
+                .collect(),
+            Self::Return(Some(value)) => vec![value],
+            Self::Return(None) | Self::Unreachable => Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Block {
+    pub params: Vec<(Value, Type)>,
+    pub insts: Vec<Inst>,
+    pub terminator: Terminator,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    /// The name calls refer to the function by, such as `fib` or `<Int32 as Shape>::area`
+    pub name: String,
+    pub return_type: Option<Type>,
+    pub blocks: Vec<Block>,
+    /// One more than the greatest value defined so far
+    pub next_value: u32,
+}
+
+impl Function {
+    /// Gets the types of the parameters, which are those of the entry
+    pub fn params(&self) -> Vec<Type> {
+        self.blocks[0].params.iter().map(|(_, ty)| *ty).collect()
+    }
+
+    pub fn block(&self, id: BlockId) -> &Block {
+        &self.blocks[id.0 as usize]
+    }
+
+    pub fn block_mut(&mut self, id: BlockId) -> &mut Block {
+        &mut self.blocks[id.0 as usize]
+    }
+
+    pub fn new_value(&mut self) -> Value {
+        self.next_value += 1;
+        Value(self.next_value - 1)
+    }
+
+    /// Gets the predecessors of every block, once for every target naming it
+    pub fn predecessors(&self) -> Vec<Vec<BlockId>> {
+        let mut predecessors = vec![Vec::new(); self.blocks.len()];
+        for (index, block) in self.blocks.iter().enumerate() {
+            for target in block.terminator.targets() {
+                predecessors[target.block.0 as usize].push(BlockId(index as u32));
+            }
+        }
+        predecessors
+    }
+
+    /// Gets whether every block can be reached from the entry
+    pub fn reachable(&self) -> Vec<bool> {
+        let mut reachable = vec![false; self.blocks.len()];
+        let mut stack = vec![BlockId::ENTRY];
+        while let Some(id) = stack.pop() {
+            if std::mem::replace(&mut reachable[id.0 as usize], true) {
+                continue;
+            }
+            stack.extend(self.block(id).terminator.targets().iter().map(|x| x.block));
+        }
+        reachable
+    }
+
+    /// Gets the blocks which can be reached from the entry in reverse postorder, where every block
+    /// comes after the blocks dominating it
+    pub fn reverse_postorder(&self) -> Vec<BlockId> {
+        let mut visited = vec![false; self.blocks.len()];
+        let mut order = Vec::with_capacity(self.blocks.len());
+        // Every block is on the stack along with how many of its targets have been visited
+        let mut stack = vec![(BlockId::ENTRY, 0)];
+        visited[0] = true;
+        while let Some((id, next)) = stack.pop() {
+            let targets = self.block(id).terminator.targets();
+            match targets.get(next) {
+                Some(target) => {
+                    stack.push((id, next + 1));
+                    let block = target.block;
+                    if !std::mem::replace(&mut visited[block.0 as usize], true) {
+                        stack.push((block, 0));
+                    }
+                }
+                None => order.push(id),
+            }
+        }
+        order.reverse();
+        order
+    }
+
+    /// Removes the blocks which are not kept, renumbering the others. No kept block may branch to
+    /// a removed one
+    pub fn retain_blocks(&mut self, keep: &[bool]) {
+        let mut numbers = Vec::with_capacity(keep.len());
+        let mut next = 0;
+        for kept in keep {
+            numbers.push(BlockId(next));
+            next += *kept as u32;
+        }
+        let mut index = 0;
+        self.blocks.retain(|_| {
+            index += 1;
+            keep[index - 1]
+        });
+        for block in &mut self.blocks {
+            for target in block.terminator.targets_mut() {
+                target.block = numbers[target.block.0 as usize];
+            }
+        }
+    }
+
+    /// Removes the parameters of a block which are not kept, along with the arguments every
+    /// branch to it passes for them
+    pub fn retain_params(&mut self, id: BlockId, keep: &[bool]) {
+        let mut index = 0;
+        self.block_mut(id).params.retain(|_| {
+            index += 1;
+            keep[index - 1]
+        });
+        for block in &mut self.blocks {
+            for target in block.terminator.targets_mut() {
+                if target.block == id {
+                    let mut index = 0;
+                    target.arguments.retain(|_| {
+                        index += 1;
+                        keep[index - 1]
+                    });
+                }
+            }
+        }
+    }
+
+    /// Replaces every use of a value by the value it maps to
+    pub fn replace_uses(&mut self, replace: impl Fn(Value) -> Value) {
+        for block in &mut self.blocks {
+            for inst in &mut block.insts {
+                for operand in inst.kind.operands_mut() {
+                    *operand = replace(*operand);
+                }
+            }
+            for operand in block.terminator.operands_mut() {
+                *operand = replace(*operand);
+            }
+        }
+    }
+
+    /// Counts the instructions of the function, which is how big it is for inlining
+    pub fn size(&self) -> usize {
+        self.blocks.iter().map(|x| x.insts.len() + 1).sum()
+    }
+}
+
+/// How C sees a value given to or taken from a foreign function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CType {
+    Scalar(Type),
+    /// A C `struct` of the fields, which the IR hands around by its address
+    Struct(Vec<CType>),
+}
+
+impl fmt::Display for CType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Scalar(ty) => write!(f, "{}", ty),
+            Self::Struct(fields) => {
+                let fields: Vec<_> = fields.iter().map(|x| x.to_string()).collect();
+                write!(f, "{{{}}}", fields.join(", "))
+            }
+        }
+    }
+}
+
+/// The C signature of an `extern "C"` function. A `struct` parameter is given by its address, and
+/// a `struct` result is stored at the address given before the other parameters
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Foreign {
+    pub params: Vec<CType>,
+    pub return_type: Option<CType>,
+    /// Whether the function takes more values after its parameters, like `printf`
+    pub variadic: bool,
+}
+
+impl CType {
+    /// Gets the type the IR hands the value around as
+    pub fn ir_type(&self) -> Type {
+        match self {
+            Self::Scalar(ty) => *ty,
+            Self::Struct(_) => Type::Ptr,
+        }
+    }
+}
+
+/// A function the module calls without defining it: either a function of the runtime, or a
+/// foreign function declared `extern "C"`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Extern {
+    pub name: String,
+    pub params: Vec<Type>,
+    pub return_type: Option<Type>,
+    pub foreign: Option<Foreign>,
+}
+
+impl Extern {
+    /// Declares a foreign function, which the IR calls with the types C sees
+    pub fn foreign(name: String, foreign: Foreign) -> Self {
+        let (params, return_type) = match &foreign.return_type {
+            Some(CType::Struct(_)) => (vec![Type::Ptr], None),
+            Some(CType::Scalar(ty)) => (Vec::new(), Some(*ty)),
+            None => (Vec::new(), None),
+        };
+        Self {
+            name,
+            params: params
+                .into_iter()
+                .chain(foreign.params.iter().map(CType::ir_type))
+                .collect(),
+            return_type,
+            foreign: Some(foreign),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Module {
+    pub externs: Vec<Extern>,
+    pub functions: Vec<Function>,
+}
+
+impl Module {
+    pub fn function(&self, name: &str) -> Option<&Function> {
+        self.functions.iter().find(|x| x.name == name)
+    }
+
+    pub fn extern_function(&self, name: &str) -> Option<&Extern> {
+        self.externs.iter().find(|x| x.name == name)
+    }
+}
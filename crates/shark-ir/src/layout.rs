@@ -0,0 +1,379 @@
+//! How the builder lays out the values of every type, along with what it needs to build one
+//! instance of a generic function: substituting its generic parameters, binding them by matching
+//! types, and naming the types it is built for
+//!
+//! Numbers, `Bool`s, `Char`s, references and generators are scalars. Every other value lives in
+//! memory, laid out by [shark_sema::layout]: a `Str` is the address of its bytes followed by their
+//! number, a `Vec` or a `Map` is the address of its items followed by how many there are and how
+//! many fit, and a closure is the address of its function followed by that of the values it
+//! captured. An array is kept as a `Vec`, which every type is turned into by [Layouts::erase]
+//! before the builder looks at it
+
+use std::collections::HashMap;
+
+use shark_core::symbol::{sym, Symbol};
+use shark_parse::ast::ReferenceKind;
+use shark_sema::{
+    adt::AdtKind,
+    layout::{Layout, LayoutKind},
+    ty::{PrimitiveType, Type as SemaType},
+    ModuleDefs,
+};
+use shark_typeck::ty::Ty;
+
+use crate::ir::Type;
+
+/// How the IR holds a value of some type
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Repr {
+    /// A value taking no room, which is never made
+    Unit,
+    Scalar(Type),
+    /// A value in memory, handed around by its address
+    Memory,
+}
+
+/// A type of the runtime, whose values only the builder looks into
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Runtime {
+    Vec,
+    Map,
+}
+
+/// The offsets within a `Vec` or a `Map` of the address of its items, their number and how many
+/// fit
+pub const ITEMS: u64 = 0;
+pub const LENGTH: u64 = 8;
+pub const CAPACITY: u64 = 16;
+
+pub struct Layouts<'b> {
+    pub defs: &'b ModuleDefs,
+}
+
+impl<'b> Layouts<'b> {
+    pub fn new(defs: &'b ModuleDefs) -> Self {
+        Self { defs }
+    }
+
+    /// Replaces every array within a type by the `Vec` it is kept as, since the two only differ
+    /// while checking
+    pub fn erase(&self, ty: &Ty) -> Ty {
+        let all = |types: &[Ty]| types.iter().map(|x| self.erase(x)).collect();
+        match ty {
+            Ty::Array(element, _) => {
+                let vec = self
+                    .defs
+                    .types
+                    .lookup(sym::VEC)
+                    .expect("arrays are only checked along with the standard library");
+                Ty::Adt(vec, vec![self.erase(element)])
+            }
+            Ty::Adt(id, arguments) => Ty::Adt(*id, all(arguments)),
+            Ty::Tuple(elements) => Ty::Tuple(all(elements)),
+            Ty::Reference {
+                kind,
+                mutable,
+                pointee,
+            } => Ty::Reference {
+                kind: *kind,
+                mutable: *mutable,
+                pointee: Box::new(self.erase(pointee)),
+            },
+            Ty::Generator(item) => Ty::Generator(Box::new(self.erase(item))),
+            Ty::Function {
+                parameters,
+                return_type,
+            } => Ty::Function {
+                parameters: all(parameters),
+                return_type: Box::new(self.erase(return_type)),
+            },
+            _ => ty.clone(),
+        }
+    }
+
+    /// Converts a declared type, replacing the generic parameters which have a mapping and
+    /// [erasing](Layouts::erase) its arrays
+    pub fn from_type(&self, ty: &SemaType, mapping: &HashMap<Symbol, Ty>) -> Ty {
+        self.erase(&Ty::from_type(ty, mapping))
+    }
+
+    /// Gets the IR type of a primitive other than `Str`
+    pub fn primitive(primitive: PrimitiveType) -> Option<Type> {
+        Some(match primitive {
+            PrimitiveType::Int8 => Type::Int8,
+            PrimitiveType::UInt8 => Type::UInt8,
+            PrimitiveType::Int32 => Type::Int32,
+            PrimitiveType::UInt32 => Type::UInt32,
+            PrimitiveType::Int64 => Type::Int64,
+            PrimitiveType::UInt64 => Type::UInt64,
+            PrimitiveType::Float32 => Type::Float32,
+            PrimitiveType::Float64 => Type::Float64,
+            PrimitiveType::Bool => Type::Bool,
+            PrimitiveType::Char => Type::Char,
+            PrimitiveType::Str => return None,
+        })
+    }
+
+    pub fn repr(&self, ty: &Ty) -> Repr {
+        match ty {
+            Ty::Primitive(primitive) => match Self::primitive(*primitive) {
+                Some(ty) => Repr::Scalar(ty),
+                None => Repr::Memory,
+            },
+            Ty::Reference { .. } | Ty::Generator(_) => Repr::Scalar(Type::Ptr),
+            _ => match self.layout(ty).size {
+                0 => Repr::Unit,
+                _ => Repr::Memory,
+            },
+        }
+    }
+
+    /// Gets the IR type a value of a type is handed around as, which is [None] for `()`
+    pub fn ir_type(&self, ty: &Ty) -> Option<Type> {
+        match self.repr(ty) {
+            Repr::Unit => None,
+            Repr::Scalar(ty) => Some(ty),
+            Repr::Memory => Some(Type::Ptr),
+        }
+    }
+
+    /// Gets the runtime type a type is, if it is one
+    pub fn runtime(&self, ty: &Ty) -> Option<Runtime> {
+        let Ty::Adt(id, _) = ty else {
+            return None;
+        };
+        let adt = self.defs.types.adt(*id);
+        match (&adt.kind, adt.abi, adt.name.symbol) {
+            (AdtKind::Extern, None, sym::VEC) => Some(Runtime::Vec),
+            (AdtKind::Extern, None, sym::MAP) => Some(Runtime::Map),
+            _ => None,
+        }
+    }
+
+    pub fn layout(&self, ty: &Ty) -> Layout {
+        match ty {
+            Ty::Primitive(primitive) => Layout::primitive(*primitive),
+            Ty::Reference { .. } | Ty::Generator(_) | Ty::Array(..) => Layout::pointer(),
+            Ty::Function { .. } => Layout::structure(&[Layout::pointer(), Layout::pointer()]),
+            Ty::Tuple(elements) => {
+                let fields: Vec<Layout> = elements.iter().map(|x| self.layout(x)).collect();
+                Layout::structure(&fields)
+            }
+            Ty::Adt(id, _) => match &self.defs.types.adt(*id).kind {
+                AdtKind::Struct { .. } => {
+                    let fields: Vec<Layout> =
+                        self.fields(ty).iter().map(|x| self.layout(x)).collect();
+                    Layout::structure(&fields)
+                }
+                AdtKind::Enum { variants } => {
+                    let payloads: Vec<Vec<Layout>> = (0..variants.len())
+                        .map(|x| self.payload(ty, x).iter().map(|x| self.layout(x)).collect())
+                        .collect();
+                    Layout::enumeration(&payloads)
+                }
+                AdtKind::Extern => match self.runtime(ty) {
+                    Some(_) => Layout::structure(&[
+                        Layout::pointer(),
+                        Layout::pointer(),
+                        Layout::pointer(),
+                    ]),
+                    // A foreign type which is only ever handled through a `ptr`
+                    None => Layout::unit(),
+                },
+            },
+            Ty::Unit | Ty::Never | Ty::Param(_) | Ty::Var(_) | Ty::Error => Layout::unit(),
+        }
+    }
+
+    /// Gets the offsets of the fields of a `type` or a tuple
+    pub fn offsets(&self, ty: &Ty) -> Vec<u64> {
+        match self.layout(ty).kind {
+            LayoutKind::Struct { offsets } => offsets,
+            _ => Vec::new(),
+        }
+    }
+
+    /// Gets the type of the tag of an `enum` along with the offsets of the payload of a variant
+    pub fn variant(&self, ty: &Ty, variant: usize) -> (Type, Vec<u64>) {
+        match self.layout(ty).kind {
+            LayoutKind::Enum { tag, mut variants } => (
+                Self::primitive(tag).expect("tags are integers"),
+                variants.swap_remove(variant),
+            ),
+            _ => unreachable!("only `enum`s have variants"),
+        }
+    }
+
+    /// Gets the types of the fields of a `type` or a tuple, with its generic arguments filled in
+    pub fn fields(&self, ty: &Ty) -> Vec<Ty> {
+        match ty {
+            Ty::Tuple(elements) => elements.clone(),
+            Ty::Adt(id, arguments) => {
+                let adt = self.defs.types.adt(*id);
+                let mapping = self.mapping(&adt.generics, arguments);
+                adt.fields()
+                    .iter()
+                    .map(|x| self.from_type(&x.ty, &mapping))
+                    .collect()
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Gets the types of the payload of a variant of an `enum`
+    pub fn payload(&self, ty: &Ty, variant: usize) -> Vec<Ty> {
+        let Ty::Adt(id, arguments) = ty else {
+            return Vec::new();
+        };
+        let adt = self.defs.types.adt(*id);
+        let mapping = self.mapping(&adt.generics, arguments);
+        adt.variants()[variant]
+            .payload
+            .iter()
+            .map(|x| self.from_type(x, &mapping))
+            .collect()
+    }
+
+    fn mapping(
+        &self,
+        generics: &[shark_parse::ast::Ident],
+        arguments: &[Ty],
+    ) -> HashMap<Symbol, Ty> {
+        generics
+            .iter()
+            .map(|x| x.symbol)
+            .zip(arguments.iter().cloned())
+            .collect()
+    }
+
+    /// Gets the name of a type as it is written in the source, which is how the functions built
+    /// for it are told apart
+    pub fn name(&self, ty: &Ty) -> String {
+        let list = |types: &[Ty]| -> String {
+            let names: Vec<String> = types.iter().map(|x| self.name(x)).collect();
+            names.join(", ")
+        };
+        match ty {
+            Ty::Primitive(primitive) => primitive.to_string(),
+            Ty::Unit | Ty::Never => "()".to_string(),
+            Ty::Adt(id, arguments) => {
+                let name = self.defs.types.adt(*id).name.symbol;
+                match arguments.is_empty() {
+                    true => name.to_string(),
+                    false => format!("{}<{}>", name, list(arguments)),
+                }
+            }
+            Ty::Tuple(elements) => format!("({})", list(elements)),
+            Ty::Reference {
+                kind,
+                mutable,
+                pointee,
+            } => {
+                let kind = match kind {
+                    ReferenceKind::Ref => "ref",
+                    ReferenceKind::Ptr => "ptr",
+                };
+                let mutable = if *mutable { "mut " } else { "" };
+                format!("{} {}{}", kind, mutable, self.name(pointee))
+            }
+            Ty::Generator(item) => format!("yield {}", self.name(item)),
+            Ty::Array(element, length) => format!("[{}; {}]", self.name(element), length),
+            Ty::Function {
+                parameters,
+                return_type,
+            } => match **return_type {
+                Ty::Unit => format!("fun({})", list(parameters)),
+                _ => format!("fun({}) :: {}", list(parameters), self.name(return_type)),
+            },
+            Ty::Param(name) => name.to_string(),
+            Ty::Var(_) | Ty::Error => "{unknown}".to_string(),
+        }
+    }
+}
+
+/// Replaces the generic parameters within a type which have a mapping
+pub fn substitute(ty: &Ty, mapping: &HashMap<Symbol, Ty>) -> Ty {
+    let all = |types: &[Ty]| types.iter().map(|x| substitute(x, mapping)).collect();
+    match ty {
+        Ty::Param(name) => mapping.get(name).cloned().unwrap_or_else(|| ty.clone()),
+        Ty::Adt(id, arguments) => Ty::Adt(*id, all(arguments)),
+        Ty::Tuple(elements) => Ty::Tuple(all(elements)),
+        Ty::Reference {
+            kind,
+            mutable,
+            pointee,
+        } => Ty::Reference {
+            kind: *kind,
+            mutable: *mutable,
+            pointee: Box::new(substitute(pointee, mapping)),
+        },
+        Ty::Generator(item) => Ty::Generator(Box::new(substitute(item, mapping))),
+        Ty::Function {
+            parameters,
+            return_type,
+        } => Ty::Function {
+            parameters: all(parameters),
+            return_type: Box::new(substitute(return_type, mapping)),
+        },
+        _ => ty.clone(),
+    }
+}
+
+/// Matches `pattern` against `ty`, binding the generic parameters of `pattern` to the parts of
+/// `ty` they stand for, like [shark_sema::generics::match_type] does for declared types
+pub fn match_ty(pattern: &Ty, ty: &Ty, bindings: &mut HashMap<Symbol, Ty>) -> bool {
+    let all = |patterns: &[Ty], types: &[Ty], bindings: &mut HashMap<Symbol, Ty>| {
+        patterns.len() == types.len()
+            && patterns
+                .iter()
+                .zip(types)
+                .all(|(x, y)| match_ty(x, y, bindings))
+    };
+    match (pattern, ty) {
+        (Ty::Param(param), _) => match bindings.get(param) {
+            Some(bound) => bound == ty,
+            None => {
+                bindings.insert(*param, ty.clone());
+                true
+            }
+        },
+        (Ty::Adt(pattern_id, patterns), Ty::Adt(id, types)) => {
+            pattern_id == id && all(patterns, types, bindings)
+        }
+        (Ty::Tuple(patterns), Ty::Tuple(types)) => all(patterns, types, bindings),
+        (
+            Ty::Reference {
+                kind: pattern_kind,
+                mutable: pattern_mutable,
+                pointee: pattern_pointee,
+            },
+            Ty::Reference {
+                kind,
+                mutable,
+                pointee,
+            },
+        ) => {
+            pattern_kind == kind
+                && pattern_mutable == mutable
+                && match_ty(pattern_pointee, pointee, bindings)
+        }
+        (Ty::Generator(pattern_item), Ty::Generator(item)) => {
+            match_ty(pattern_item, item, bindings)
+        }
+        (
+            Ty::Function {
+                parameters: pattern_parameters,
+                return_type: pattern_return,
+            },
+            Ty::Function {
+                parameters,
+                return_type,
+            },
+        ) => {
+            all(pattern_parameters, parameters, bindings)
+                && match_ty(pattern_return, return_type, bindings)
+        }
+        (Ty::Unit, Ty::Tuple(types)) | (Ty::Tuple(types), Ty::Unit) => types.is_empty(),
+        _ => pattern == ty,
+    }
+}
@@ -0,0 +1,94 @@
+//! A typed SSA IR between lowered bodies and the backends, see [ir], along with a textual format
+//! which can be printed and parsed back, a verifier, and the passes which optimise it
+//!
+//! [build] makes the IR of every function of a program, including the standard library it uses,
+//! and [optimize] runs the passes of an [OptLevel] on it. The backends which compile programs
+//! lower from the optimised IR, calling the functions of the [runtime] it declares
+
+use build::Builder;
+use shark_lower::Body;
+use shark_parse::ast::Module;
+use shark_sema::{numeric::Overflow, ModuleDefs};
+use shark_typeck::TypeckResults;
+
+pub mod build;
+pub mod builtin;
+pub mod cfg;
+pub mod copy;
+pub mod dce;
+pub mod fold;
+pub mod inline;
+pub mod ir;
+pub mod layout;
+pub mod parse;
+pub mod print;
+pub mod runtime;
+pub mod verify;
+
+#[cfg(test)]
+pub mod tests;
+
+/// How hard to optimise, as given by `-O0`, `-O1` or `-O2`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Default)]
+pub enum OptLevel {
+    /// Leaves the IR as it is built
+    #[default]
+    O0,
+    /// Folds constants, propagates copies, removes dead code and simplifies the control flow
+    /// until none of them changes anything
+    O1,
+    /// Inlines small functions before doing what `-O1` does
+    O2,
+}
+
+impl OptLevel {
+    /// Gets the level named by a flag, without its `-O`
+    pub fn from_flag(level: &str) -> Option<Self> {
+        match level {
+            "0" => Some(Self::O0),
+            "1" => Some(Self::O1),
+            "2" => Some(Self::O2),
+            _ => None,
+        }
+    }
+}
+
+/// Runs the passes of `-O1` over a function until it stops changing, returning whether it
+/// changed at all
+pub fn simplify(function: &mut ir::Function) -> bool {
+    let mut changed = false;
+    loop {
+        let mut round = fold::fold(function);
+        round |= copy::propagate(function);
+        round |= dce::eliminate(function);
+        round |= cfg::simplify(function);
+        if !round {
+            return changed;
+        }
+        changed = true;
+    }
+}
+
+/// Optimises every function of a module
+pub fn optimize(module: &mut ir::Module, level: OptLevel) {
+    if level >= OptLevel::O2 {
+        inline::inline(module);
+    }
+    if level >= OptLevel::O1 {
+        for function in &mut module.functions {
+            simplify(function);
+        }
+    }
+}
+
+/// Builds the IR of a checked and lowered module whose arithmetic overflows some way: every
+/// function which is not generic, along with every instance of a generic one they need
+pub fn build(
+    module: &Module,
+    defs: &ModuleDefs,
+    types: &TypeckResults,
+    bodies: &[Body],
+    overflow: Overflow,
+) -> ir::Module {
+    Builder::new(module, defs, types, bodies, overflow).finish()
+}
@@ -0,0 +1,591 @@
+//! Reads the textual format [crate::print] writes, so that tests can be written as IR in and IR
+//! out. Blocks must be numbered in order, but values can be numbered any way as long as each is
+//! defined once. Errors name the line they are on
+
+use std::collections::{HashMap, HashSet};
+
+use shark_core::symbol::Symbol;
+use shark_lex::token::LiteralKind;
+use shark_sema::numeric::Overflow;
+
+use crate::ir::{
+    BinaryOp, Block, BlockId, CType, Extern, Foreign, Function, Inst, InstKind, Module, Target,
+    Terminator, Type, UnaryOp, Value,
+};
+
+/// What is left of the line being read
+struct Line<'t> {
+    rest: &'t str,
+    number: usize,
+}
+
+impl<'t> Line<'t> {
+    fn error<T>(&self, message: impl Into<String>) -> Result<T, String> {
+        Err(format!("line {}: {}", self.number, message.into()))
+    }
+
+    fn eat(&mut self, token: &str) -> bool {
+        let rest = self.rest.trim_start();
+        match rest.strip_prefix(token) {
+            Some(rest) => {
+                self.rest = rest;
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn expect(&mut self, token: &str) -> Result<(), String> {
+        match self.eat(token) {
+            true => Ok(()),
+            false => self.error(format!(
+                "expected `{}`, found `{}`",
+                token,
+                self.rest.trim()
+            )),
+        }
+    }
+
+    fn is_done(&self) -> bool {
+        self.rest.trim().is_empty()
+    }
+
+    fn end(&self) -> Result<(), String> {
+        match self.is_done() {
+            true => Ok(()),
+            false => self.error(format!("unexpected `{}`", self.rest.trim())),
+        }
+    }
+
+    /// Reads a run of characters which can be part of a word, a number or a constant
+    fn word(&mut self) -> &'t str {
+        let rest = self.rest.trim_start();
+        let end = rest
+            .find(|x: char| !(x.is_ascii_alphanumeric() || matches!(x, '_' | '.' | '-' | '+')))
+            .unwrap_or(rest.len());
+        self.rest = &rest[end..];
+        &rest[..end]
+    }
+
+    fn number(&mut self, prefix: &str, what: &str) -> Result<u32, String> {
+        if !self.eat(prefix) {
+            return self.error(format!("expected {}, found `{}`", what, self.rest.trim()));
+        }
+        let word = self.word();
+        match word.parse() {
+            Ok(number) => Ok(number),
+            Err(_) => self.error(format!("invalid {} `{}{}`", what, prefix, word)),
+        }
+    }
+
+    fn size(&mut self) -> Result<u64, String> {
+        let word = self.word();
+        match word.parse() {
+            Ok(number) => Ok(number),
+            Err(_) => self.error(format!("invalid size `{}`", word)),
+        }
+    }
+
+    /// Reads a string quoted the way Rust debug-prints it
+    fn string(&mut self) -> Result<String, String> {
+        let rest = self.rest.trim_start();
+        let mut chars = rest.char_indices();
+        if !matches!(chars.next(), Some((_, '"'))) {
+            return self.error(format!("expected a string, found `{}`", rest.trim()));
+        }
+        let mut text = String::new();
+        while let Some((index, x)) = chars.next() {
+            let escaped = match x {
+                '"' => {
+                    self.rest = &rest[index + 1..];
+                    return Ok(text);
+                }
+                '\\' => chars.next().map(|(_, x)| x),
+                _ => {
+                    text.push(x);
+                    continue;
+                }
+            };
+            let escaped = match escaped {
+                Some('n') => '\n',
+                Some('r') => '\r',
+                Some('t') => '\t',
+                Some('0') => '\0',
+                Some(x @ ('\\' | '"' | '\'')) => x,
+                Some('u') => {
+                    let digits: String = chars
+                        .by_ref()
+                        .map(|(_, x)| x)
+                        .skip_while(|x| *x == '{')
+                        .take_while(|x| *x != '}')
+                        .collect();
+                    let code = u32::from_str_radix(&digits, 16).ok();
+                    match code.and_then(char::from_u32) {
+                        Some(x) => x,
+                        None => return self.error(format!("invalid escape `\\u{{{}}}`", digits)),
+                    }
+                }
+                _ => return self.error("invalid escape in a string"),
+            };
+            text.push(escaped);
+        }
+        self.error("unterminated string")
+    }
+
+    fn ty(&mut self) -> Result<Type, String> {
+        let word = self.word();
+        match Type::ALL.iter().find(|x| x.name() == word) {
+            Some(ty) => Ok(*ty),
+            None => self.error(format!("unknown type `{}`", word)),
+        }
+    }
+
+    fn function_name(&mut self) -> Result<String, String> {
+        self.expect("@")?;
+        if self.rest.starts_with('"') {
+            let Some(end) = self.rest[1..].find('"') else {
+                return self.error("unterminated name");
+            };
+            let name = self.rest[1..end + 1].to_string();
+            self.rest = &self.rest[end + 2..];
+            return Ok(name);
+        }
+        match self.word() {
+            "" => self.error("expected the name of a function"),
+            word => Ok(word.to_string()),
+        }
+    }
+
+    /// Reads a constant, which has the suffix of its type unless it is a `bool` or a `char`
+    fn constant(&mut self) -> Result<LiteralKind, String> {
+        if self.rest.trim_start().starts_with('"') {
+            return Ok(LiteralKind::Str(Symbol::intern(&self.string()?)));
+        }
+        let rest = self.rest.trim();
+        self.rest = "";
+        let literal = if rest.starts_with('\'') {
+            LiteralKind::into_char_literal(rest).map_err(|x| x.to_string())
+        } else if let Some(boolean) = LiteralKind::into_boolean_literal(rest) {
+            Ok(boolean)
+        } else if let Some(float) = rest.strip_suffix("float32") {
+            float
+                .parse()
+                .map(LiteralKind::Float32)
+                .map_err(|x| x.to_string())
+        } else if let Some(float) = rest.strip_suffix("float64") {
+            float
+                .parse()
+                .map(LiteralKind::Float64)
+                .map_err(|x| x.to_string())
+        } else {
+            LiteralKind::into_numeric_literal(rest).map_err(|x| x.to_string())
+        };
+        literal.or_else(|x| self.error(format!("invalid constant `{}`: {}", rest, x)))
+    }
+}
+
+struct FunctionParser {
+    values: HashMap<u32, Value>,
+    defined: HashSet<u32>,
+    function: Function,
+}
+
+impl FunctionParser {
+    fn value_named(&mut self, number: u32) -> Value {
+        *self
+            .values
+            .entry(number)
+            .or_insert_with(|| self.function.new_value())
+    }
+
+    /// Gets the value for a name, which may be defined later
+    fn value(&mut self, line: &mut Line) -> Result<Value, String> {
+        let number = line.number("%", "a value")?;
+        Ok(self.value_named(number))
+    }
+
+    fn define(&mut self, line: &mut Line) -> Result<Value, String> {
+        let number = line.number("%", "a value")?;
+        if !self.defined.insert(number) {
+            return line.error(format!("`%{}` is defined twice", number));
+        }
+        Ok(self.value_named(number))
+    }
+
+    fn values(&mut self, line: &mut Line) -> Result<Vec<Value>, String> {
+        let mut values = Vec::new();
+        if line.eat(")") {
+            return Ok(values);
+        }
+        loop {
+            values.push(self.value(line)?);
+            if line.eat(")") {
+                return Ok(values);
+            }
+            line.expect(",")?;
+        }
+    }
+
+    fn target(&mut self, line: &mut Line) -> Result<Target, String> {
+        let block = BlockId(line.number("bb", "a block")?);
+        let arguments = match line.eat("(") {
+            true => self.values(line)?,
+            false => Vec::new(),
+        };
+        Ok(Target::new(block, arguments))
+    }
+
+    fn terminator(&mut self, line: &mut Line) -> Result<Option<Terminator>, String> {
+        let terminator = if line.eat("jump ") {
+            Terminator::Jump(self.target(line)?)
+        } else if line.eat("br ") {
+            let condition = self.value(line)?;
+            line.expect(",")?;
+            let then_target = self.target(line)?;
+            line.expect(",")?;
+            Terminator::Branch(condition, then_target, self.target(line)?)
+        } else if line.eat("ret") {
+            match line.is_done() {
+                true => Terminator::Return(None),
+                false => Terminator::Return(Some(self.value(line)?)),
+            }
+        } else if line.eat("unreachable") {
+            Terminator::Unreachable
+        } else {
+            return Ok(None);
+        };
+        line.end()?;
+        Ok(Some(terminator))
+    }
+
+    fn inst(&mut self, line: &mut Line) -> Result<Inst, String> {
+        let result = match line.rest.trim_start().starts_with('%') {
+            true => {
+                let value = self.define(line)?;
+                line.expect("=")?;
+                Some(value)
+            }
+            false => None,
+        };
+        let opcode = line.word();
+        let (ty, kind) = match opcode {
+            "const" => {
+                let literal = line.constant()?;
+                (Some(Type::of_literal(&literal)), InstKind::Const(literal))
+            }
+            "copy" => (None, InstKind::Copy(self.value(line)?)),
+            "neg" => (None, InstKind::Unary(UnaryOp::Neg, self.value(line)?)),
+            "not" => (None, InstKind::Unary(UnaryOp::Not, self.value(line)?)),
+            "alloca" => (Some(Type::Ptr), InstKind::Alloca(line.ty()?)),
+            "slot" => {
+                let size = line.size()?;
+                line.expect(",")?;
+                (Some(Type::Ptr), InstKind::Slot(size, line.size()?))
+            }
+            "offset" => {
+                let address = self.value(line)?;
+                line.expect(",")?;
+                (
+                    Some(Type::Ptr),
+                    InstKind::Offset(address, self.value(line)?),
+                )
+            }
+            "memcopy" => {
+                let to = self.value(line)?;
+                line.expect(",")?;
+                let from = self.value(line)?;
+                line.expect(",")?;
+                (None, InstKind::MemCopy(to, from, self.value(line)?))
+            }
+            "funcaddr" => (Some(Type::Ptr), InstKind::FuncAddr(line.function_name()?)),
+            "panic" => {
+                let message = line.string()?;
+                let value = match line.eat(",") {
+                    true => Some(self.value(line)?),
+                    false => None,
+                };
+                (None, InstKind::Panic(message, value))
+            }
+            "load" => {
+                let ty = line.ty()?;
+                (Some(ty), InstKind::Load(self.value(line)?))
+            }
+            "conv" | "conv.wrap" => {
+                let overflow = match opcode {
+                    "conv" => Overflow::Trap,
+                    _ => Overflow::Wrap,
+                };
+                let ty = line.ty()?;
+                (Some(ty), InstKind::Convert(overflow, self.value(line)?))
+            }
+            "store" => {
+                let address = self.value(line)?;
+                line.expect(",")?;
+                (None, InstKind::Store(address, self.value(line)?))
+            }
+            "call" => {
+                let callee = |line: &Line| line.rest.trim_start().starts_with(['@', '%']);
+                let ty = match callee(line) {
+                    true => None,
+                    false => Some(line.ty()?),
+                };
+                if result.is_some() && ty.is_none() {
+                    return line.error("calls defining a value must name its type");
+                }
+                if line.rest.trim_start().starts_with('%') {
+                    let callee = self.value(line)?;
+                    line.expect("(")?;
+                    (ty, InstKind::CallIndirect(callee, self.values(line)?))
+                } else {
+                    let name = line.function_name()?;
+                    line.expect("(")?;
+                    (ty, InstKind::Call(name, self.values(line)?))
+                }
+            }
+            _ => {
+                let (name, overflow) = match opcode.split_once('.') {
+                    Some((name, "wrap")) => (name, Some(Overflow::Wrap)),
+                    Some((name, "sat")) => (name, Some(Overflow::Saturate)),
+                    _ => (opcode, None),
+                };
+                let Some(op) = BinaryOp::ALL.iter().find(|x| x.name() == name) else {
+                    return line.error(format!("unknown instruction `{}`", opcode));
+                };
+                let left = self.value(line)?;
+                line.expect(",")?;
+                let right = self.value(line)?;
+                match overflow {
+                    Some(overflow) => (None, InstKind::Arith(overflow, *op, left, right)),
+                    None => (None, InstKind::Binary(*op, left, right)),
+                }
+            }
+        };
+        line.end()?;
+        // The types of the other results follow from their operands, which may not be known yet
+        let result = match (result, ty) {
+            (Some(value), ty) => Some((value, ty.unwrap_or(Type::Bool))),
+            (None, Some(_)) => return line.error(format!("`{}` must define a value", opcode)),
+            (None, None) => None,
+        };
+        Ok(Inst::new(result, kind))
+    }
+
+    /// Works out the types of the results which follow from their operands, now that every value
+    /// is known
+    fn infer_types(&mut self) -> Result<(), String> {
+        let mut types = HashMap::new();
+        for block in &self.function.blocks {
+            types.extend(block.params.iter().copied());
+        }
+        // Copies can refer to values defined later, so types are worked out until none changes
+        loop {
+            let mut changed = false;
+            for block in &mut self.function.blocks {
+                for inst in &mut block.insts {
+                    let Some((result, ty)) = &mut inst.result else {
+                        continue;
+                    };
+                    let inferred = match &inst.kind {
+                        InstKind::Copy(x) | InstKind::Unary(_, x) => types.get(x).copied(),
+                        InstKind::Binary(op, _, _) if op.is_comparison() => Some(Type::Bool),
+                        InstKind::Binary(_, x, _) | InstKind::Arith(_, _, x, _) => {
+                            types.get(x).copied()
+                        }
+                        _ => Some(*ty),
+                    };
+                    if let Some(inferred) = inferred {
+                        *ty = inferred;
+                        changed |= types.insert(*result, inferred) != Some(inferred);
+                    }
+                }
+            }
+            if !changed {
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Reads the type C sees a value as, which is a type of the IR or a `struct` of fields in braces
+fn c_type(line: &mut Line) -> Result<CType, String> {
+    if !line.eat("{") {
+        return Ok(CType::Scalar(line.ty()?));
+    }
+    let mut fields = Vec::new();
+    loop {
+        fields.push(c_type(line)?);
+        if line.eat("}") {
+            return Ok(CType::Struct(fields));
+        }
+        line.expect(",")?;
+    }
+}
+
+/// Reads the declaration of an extern function, after its `extern`
+fn declaration(line: &mut Line) -> Result<Extern, String> {
+    let foreign = line.eat("\"C\"");
+    let name = line.function_name()?;
+    line.expect("(")?;
+    let mut params = Vec::new();
+    let mut variadic = false;
+    if !line.eat(")") {
+        loop {
+            if line.eat("...") {
+                variadic = true;
+                line.expect(")")?;
+                break;
+            }
+            params.push(c_type(line)?);
+            if line.eat(")") {
+                break;
+            }
+            line.expect(",")?;
+        }
+    }
+    let return_type = match line.eat("->") {
+        true => Some(c_type(line)?),
+        false => None,
+    };
+    line.end()?;
+    if foreign {
+        let foreign = Foreign {
+            params,
+            return_type,
+            variadic,
+        };
+        return Ok(Extern::foreign(name, foreign));
+    }
+    let scalar = |ty: CType| match ty {
+        CType::Scalar(ty) => Ok(ty),
+        CType::Struct(_) => line.error("only foreign functions take `struct`s"),
+    };
+    if variadic {
+        return line.error("only foreign functions take more values than their parameters");
+    }
+    Ok(Extern {
+        name,
+        params: params.into_iter().map(scalar).collect::<Result<_, _>>()?,
+        return_type: return_type.map(scalar).transpose()?,
+        foreign: None,
+    })
+}
+
+/// Reads a module, which holds the declarations of its externs followed by its functions
+pub fn parse(text: &str) -> Result<Module, String> {
+    let mut lines = text
+        .lines()
+        .enumerate()
+        .map(|(index, rest)| Line {
+            rest,
+            number: index + 1,
+        })
+        .filter(|x| !x.is_done())
+        .peekable();
+    let mut module = Module::default();
+    while let Some(mut line) = lines.next() {
+        if line.eat("extern") {
+            module.externs.push(declaration(&mut line)?);
+            continue;
+        }
+        line.expect("fun")?;
+        let name = line.function_name()?;
+        line.expect("(")?;
+        let mut params = Vec::new();
+        if !line.eat(")") {
+            loop {
+                params.push(line.ty()?);
+                if line.eat(")") {
+                    break;
+                }
+                line.expect(",")?;
+            }
+        }
+        let return_type = match line.eat("->") {
+            true => Some(line.ty()?),
+            false => None,
+        };
+        line.expect("{")?;
+        line.end()?;
+
+        let mut parser = FunctionParser {
+            values: HashMap::new(),
+            defined: HashSet::new(),
+            function: Function {
+                name,
+                return_type,
+                blocks: Vec::new(),
+                next_value: 0,
+            },
+        };
+        loop {
+            let Some(mut line) = lines.next() else {
+                return Err("unexpected end of the text, expected `}`".to_string());
+            };
+            if line.eat("}") {
+                line.end()?;
+                break;
+            }
+            let number = line.number("bb", "a block")?;
+            if number as usize != parser.function.blocks.len() {
+                return line.error(format!(
+                    "expected `bb{}`, as blocks are numbered in order",
+                    parser.function.blocks.len()
+                ));
+            }
+            let mut block_params = Vec::new();
+            if line.eat("(") {
+                loop {
+                    let value = parser.define(&mut line)?;
+                    line.expect(":")?;
+                    block_params.push((value, line.ty()?));
+                    if line.eat(")") {
+                        break;
+                    }
+                    line.expect(",")?;
+                }
+            }
+            line.expect(":")?;
+            line.end()?;
+            let mut insts = Vec::new();
+            let terminator = loop {
+                let Some(mut line) = lines.next() else {
+                    return Err("unexpected end of the text, expected a terminator".to_string());
+                };
+                if let Some(terminator) = parser.terminator(&mut line)? {
+                    break terminator;
+                }
+                insts.push(parser.inst(&mut line)?);
+            };
+            parser.function.blocks.push(Block {
+                params: block_params,
+                insts,
+                terminator,
+            });
+        }
+
+        let Some(entry) = parser.function.blocks.first() else {
+            return line.error("a function needs an entry block");
+        };
+        if entry
+            .params
+            .iter()
+            .map(|(_, x)| *x)
+            .ne(params.iter().copied())
+        {
+            return line.error("the parameters of the entry block differ from the function");
+        }
+        if let Some((number, _)) = parser
+            .values
+            .iter()
+            .find(|(x, _)| !parser.defined.contains(x))
+        {
+            return Err(format!(
+                "`%{}` is used in `{}` but never defined",
+                number, parser.function.name
+            ));
+        }
+        parser.infer_types()?;
+        module.functions.push(parser.function);
+    }
+    Ok(module)
+}
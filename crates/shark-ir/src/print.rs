@@ -0,0 +1,246 @@
+//! Prints the textual format of the IR, which [crate::parse] reads back. Values are numbered from
+//! zero in the order they are defined, so that printing a function gives the same text however
+//! its values came to be numbered:
+//!
+//! ```text
+//! fun @fib(i64) -> i64 {
+//! bb0(%0: i64):
+//!     %1 = const 2int64
+//!     %2 = lt %0, %1
+//!     br %2, bb1, bb2
+//! bb1:
+//!     ret %0
+//! bb2:
+//!     %3 = const 1int64
+//!     %4 = sub %0, %3
+//!     %5 = call i64 @fib(%4)
+//!     ...
+//! }
+//! ```
+//!
+//! The [Extern]s of a module come before its functions, as `extern @shark_alloc(i64) -> ptr`, or
+//! with the types C sees for a foreign function, as `extern "C" @scale({i32, i32}, i32) -> {i32,
+//! i32}`. Where instructions report their runtime errors is not printed
+
+use std::{collections::HashMap, fmt};
+
+use shark_lex::token::LiteralKind;
+
+use crate::ir::{
+    overflow_suffix, Extern, Function, Inst, InstKind, Module, Target, Terminator, UnaryOp, Value,
+};
+
+/// Prints a constant with the suffix of its type, even where Shark would not need one
+pub fn constant(literal: &LiteralKind) -> String {
+    match literal {
+        LiteralKind::UInt8(x) => format!("{}uint8", x),
+        LiteralKind::Int8(x) => format!("{}int8", x),
+        LiteralKind::UInt32(x) => format!("{}uint32", x),
+        LiteralKind::Int32(x) => format!("{}int32", x),
+        LiteralKind::UInt64(x) => format!("{}uint64", x),
+        LiteralKind::Int64(x) => format!("{}int64", x),
+        LiteralKind::Float32(x) => format!("{:?}float32", x),
+        LiteralKind::Float64(x) => format!("{:?}float64", x),
+        LiteralKind::Str(x) => format!("{:?}", x.as_str()),
+        LiteralKind::Char(x) => format!("{:?}", x),
+        LiteralKind::Boolean(x) => x.to_string(),
+    }
+}
+
+/// Prints the name of a function, quoting it unless it is an identifier
+pub fn function_name(name: &str) -> String {
+    match !name.is_empty() && name.chars().all(|x| x.is_ascii_alphanumeric() || x == '_') {
+        true => format!("@{}", name),
+        false => format!("@{:?}", name),
+    }
+}
+
+struct Printer<'f> {
+    numbers: HashMap<Value, usize>,
+    function: &'f Function,
+}
+
+impl Printer<'_> {
+    fn value(&self, value: Value) -> String {
+        match self.numbers.get(&value) {
+            Some(number) => format!("%{}", number),
+            // Only a function which fails to verify uses a value it does not define
+            None => format!("%undefined{}", value.0),
+        }
+    }
+
+    fn values(&self, values: &[Value]) -> String {
+        values
+            .iter()
+            .map(|x| self.value(*x))
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    fn target(&self, target: &Target) -> String {
+        match target.arguments.is_empty() {
+            true => format!("bb{}", target.block.0),
+            false => format!("bb{}({})", target.block.0, self.values(&target.arguments)),
+        }
+    }
+
+    fn inst(&self, inst: &Inst) -> String {
+        let value = |x| self.value(x);
+        let operation = match &inst.kind {
+            InstKind::Const(literal) => format!("const {}", constant(literal)),
+            InstKind::Copy(x) => format!("copy {}", value(*x)),
+            InstKind::Unary(UnaryOp::Neg, x) => format!("neg {}", value(*x)),
+            InstKind::Unary(UnaryOp::Not, x) => format!("not {}", value(*x)),
+            InstKind::Binary(op, x, y) => format!("{} {}, {}", op.name(), value(*x), value(*y)),
+            InstKind::Arith(overflow, op, x, y) => format!(
+                "{}{} {}, {}",
+                op.name(),
+                overflow_suffix(*overflow),
+                value(*x),
+                value(*y)
+            ),
+            InstKind::Convert(overflow, x) => {
+                let ty = inst.result.map_or("?", |(_, ty)| ty.name());
+                format!("conv{} {} {}", overflow_suffix(*overflow), ty, value(*x))
+            }
+            InstKind::Alloca(ty) => format!("alloca {}", ty),
+            InstKind::Slot(size, align) => format!("slot {}, {}", size, align),
+            InstKind::Offset(x, y) => format!("offset {}, {}", value(*x), value(*y)),
+            InstKind::Load(x) => {
+                let ty = inst.result.map_or("?", |(_, ty)| ty.name());
+                format!("load {} {}", ty, value(*x))
+            }
+            InstKind::Store(address, x) => format!("store {}, {}", value(*address), value(*x)),
+            InstKind::MemCopy(to, from, size) => {
+                format!("memcopy {}, {}, {}", value(*to), value(*from), value(*size))
+            }
+            InstKind::Call(name, arguments) => self.call(inst, function_name(name), arguments),
+            InstKind::FuncAddr(name) => format!("funcaddr {}", function_name(name)),
+            InstKind::CallIndirect(callee, arguments) => {
+                self.call(inst, self.value(*callee), arguments)
+            }
+            InstKind::Panic(message, None) => format!("panic {:?}", message),
+            InstKind::Panic(message, Some(x)) => format!("panic {:?}, {}", message, value(*x)),
+        };
+        match inst.result {
+            Some((result, _)) => format!("{} = {}", self.value(result), operation),
+            None => operation,
+        }
+    }
+
+    fn call(&self, inst: &Inst, callee: String, arguments: &[Value]) -> String {
+        match inst.result {
+            Some((_, ty)) => format!("call {} {}({})", ty, callee, self.values(arguments)),
+            None => format!("call {}({})", callee, self.values(arguments)),
+        }
+    }
+
+    fn terminator(&self, terminator: &Terminator) -> String {
+        match terminator {
+            Terminator::Jump(target) => format!("jump {}", self.target(target)),
+            Terminator::Branch(condition, then_target, else_target) => format!(
+                "br {}, {}, {}",
+                self.value(*condition),
+                self.target(then_target),
+                self.target(else_target)
+            ),
+            Terminator::Return(Some(value)) => format!("ret {}", self.value(*value)),
+            Terminator::Return(None) => "ret".to_string(),
+            Terminator::Unreachable => "unreachable".to_string(),
+        }
+    }
+}
+
+impl fmt::Display for Function {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut numbers = HashMap::new();
+        for block in &self.blocks {
+            let defined = block.params.iter().map(|(x, _)| *x);
+            let defined = defined.chain(block.insts.iter().filter_map(|x| Some(x.result?.0)));
+            for value in defined {
+                let number = numbers.len();
+                numbers.entry(value).or_insert(number);
+            }
+        }
+        let printer = Printer {
+            numbers,
+            function: self,
+        };
+
+        let params: Vec<_> = self.params().iter().map(|x| x.to_string()).collect();
+        write!(
+            f,
+            "fun {}({})",
+            function_name(&self.name),
+            params.join(", ")
+        )?;
+        if let Some(ty) = self.return_type {
+            write!(f, " -> {}", ty)?;
+        }
+        writeln!(f, " {{")?;
+        for (index, block) in printer.function.blocks.iter().enumerate() {
+            write!(f, "bb{}", index)?;
+            if !block.params.is_empty() {
+                let params: Vec<_> = block
+                    .params
+                    .iter()
+                    .map(|(value, ty)| format!("{}: {}", printer.value(*value), ty))
+                    .collect();
+                write!(f, "({})", params.join(", "))?;
+            }
+            writeln!(f, ":")?;
+            for inst in &block.insts {
+                writeln!(f, "    {}", printer.inst(inst))?;
+            }
+            writeln!(f, "    {}", printer.terminator(&block.terminator))?;
+        }
+        write!(f, "}}")
+    }
+}
+
+impl fmt::Display for Extern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let Some(foreign) = &self.foreign else {
+            let params: Vec<_> = self.params.iter().map(|x| x.to_string()).collect();
+            write!(
+                f,
+                "extern {}({})",
+                function_name(&self.name),
+                params.join(", ")
+            )?;
+            if let Some(ty) = self.return_type {
+                write!(f, " -> {}", ty)?;
+            }
+            return Ok(());
+        };
+        let mut params: Vec<_> = foreign.params.iter().map(|x| x.to_string()).collect();
+        if foreign.variadic {
+            params.push("...".to_string());
+        }
+        write!(
+            f,
+            "extern \"C\" {}({})",
+            function_name(&self.name),
+            params.join(", ")
+        )?;
+        if let Some(ty) = &foreign.return_type {
+            write!(f, " -> {}", ty)?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for Module {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for declaration in &self.externs {
+            writeln!(f, "{}", declaration)?;
+        }
+        for (index, function) in self.functions.iter().enumerate() {
+            if index > 0 || !self.externs.is_empty() {
+                writeln!(f)?;
+            }
+            writeln!(f, "{}", function)?;
+        }
+        Ok(())
+    }
+}
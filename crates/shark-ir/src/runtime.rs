@@ -0,0 +1,167 @@
+//! The functions of the runtime, which the IR calls for what it can not do by itself: allocating
+//! memory, talking to the outside world and working with the text of strings. Every backend
+//! provides them under these names with these signatures, so they are the contract between the
+//! IR and whatever runs it
+//!
+//! A `Str` is given as the address of its bytes followed by their number, and results which are
+//! not scalars are stored at an address given before the other parameters. A `Vec` made by the
+//! runtime is the address of its items followed by how many there are and how many fit, see
+//! [crate::layout]. Functions which can fail give whether they succeeded, and the IR stops the
+//! program with the error of the interpreter when they did not
+
+use crate::ir::{Extern, Type};
+
+/// A function of the runtime
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RuntimeFunction {
+    pub name: &'static str,
+    pub params: &'static [Type],
+    pub return_type: Option<Type>,
+}
+
+impl RuntimeFunction {
+    /// Declares the function in a module
+    pub fn declaration(&self) -> Extern {
+        Extern {
+            name: self.name.to_string(),
+            params: self.params.to_vec(),
+            return_type: self.return_type,
+            foreign: None,
+        }
+    }
+}
+
+const fn function(
+    name: &'static str,
+    params: &'static [Type],
+    return_type: Option<Type>,
+) -> RuntimeFunction {
+    RuntimeFunction {
+        name,
+        params,
+        return_type,
+    }
+}
+
+use Type::{Bool, Char, Float32, Float64, Int32, Int64, Ptr, UInt64};
+
+/// Gives a number of bytes of zeroed memory aligned for any value, which is never freed
+pub const ALLOC: RuntimeFunction = function("shark_alloc", &[Int64], Some(Ptr));
+/// Writes a `Str` to the standard output when the first parameter is 1, or to the standard error
+/// when it is 2
+pub const WRITE: RuntimeFunction = function("shark_write", &[Int32, Ptr], Some(Bool));
+/// Stops the program with an exit code
+pub const EXIT: RuntimeFunction = function("shark_exit", &[Int32], None);
+/// Stores the arguments of the program as a `Vec<Str>`
+pub const ARGS: RuntimeFunction = function("shark_args", &[Ptr], None);
+/// Reads a file whose path is a `Str`, storing its contents or why it could not be read
+pub const READ_FILE: RuntimeFunction = function("shark_read_file", &[Ptr, Ptr], Some(Bool));
+/// Replaces the contents of a file, storing why it could not be written when it fails
+pub const WRITE_FILE: RuntimeFunction = function("shark_write_file", &[Ptr, Ptr, Ptr], Some(Bool));
+
+/// Write out numbers and characters the way `show` does in the interpreter
+pub const SHOW_INT: RuntimeFunction = function("shark_show_int", &[Ptr, Int64], None);
+pub const SHOW_UINT: RuntimeFunction = function("shark_show_uint", &[Ptr, UInt64], None);
+pub const SHOW_FLOAT32: RuntimeFunction = function("shark_show_float32", &[Ptr, Float32], None);
+pub const SHOW_FLOAT64: RuntimeFunction = function("shark_show_float64", &[Ptr, Float64], None);
+pub const SHOW_CHAR: RuntimeFunction = function("shark_show_char", &[Ptr, Char], None);
+
+/// Counts the placeholders of a template, giving -1 if it is invalid
+pub const PLACEHOLDERS: RuntimeFunction = function("shark_placeholders", &[Ptr], Some(Int64));
+/// Fills in a template with a number of `Str`s, which follow one another in memory
+pub const FORMAT: RuntimeFunction = function("shark_format", &[Ptr, Ptr, Ptr, Int64], None);
+
+/// Compares two `Str`s by their bytes, giving -1, 0 or 1
+pub const STR_COMPARE: RuntimeFunction = function("shark_str_compare", &[Ptr, Ptr], Some(Int32));
+/// Counts the characters of a `Str`
+pub const STR_LEN: RuntimeFunction = function("shark_str_len", &[Ptr], Some(Int64));
+pub const STR_CONCAT: RuntimeFunction = function("shark_str_concat", &[Ptr, Ptr, Ptr], None);
+/// Gives the index of the character the first match of a pattern starts at, or -1
+pub const STR_FIND: RuntimeFunction = function("shark_str_find", &[Ptr, Ptr], Some(Int64));
+pub const STR_STARTS_WITH: RuntimeFunction =
+    function("shark_str_starts_with", &[Ptr, Ptr], Some(Bool));
+pub const STR_ENDS_WITH: RuntimeFunction = function("shark_str_ends_with", &[Ptr, Ptr], Some(Bool));
+/// Stores the characters from a start up to an end, failing if they are out of bounds
+pub const STR_SLICE: RuntimeFunction =
+    function("shark_str_slice", &[Ptr, Ptr, Int64, Int64], Some(Bool));
+/// Stores the character at an index, failing if there is none
+pub const STR_CHAR_AT: RuntimeFunction =
+    function("shark_str_char_at", &[Ptr, Ptr, Int64], Some(Bool));
+pub const STR_TRIM: RuntimeFunction = function("shark_str_trim", &[Ptr, Ptr], None);
+pub const STR_TO_UPPER: RuntimeFunction = function("shark_str_to_upper", &[Ptr, Ptr], None);
+pub const STR_TO_LOWER: RuntimeFunction = function("shark_str_to_lower", &[Ptr, Ptr], None);
+/// Repeats a `Str` a number of times, failing if the number is negative
+pub const STR_REPEAT: RuntimeFunction =
+    function("shark_str_repeat", &[Ptr, Ptr, Int64], Some(Bool));
+pub const STR_REPLACE: RuntimeFunction = function("shark_str_replace", &[Ptr, Ptr, Ptr, Ptr], None);
+/// Store a `Vec<Str>` of the parts of a `Str`
+pub const STR_SPLIT: RuntimeFunction = function("shark_str_split", &[Ptr, Ptr, Ptr], None);
+pub const STR_LINES: RuntimeFunction = function("shark_str_lines", &[Ptr, Ptr], None);
+/// Stores a `Vec<Char>` of the characters of a `Str`
+pub const STR_CHARS: RuntimeFunction = function("shark_str_chars", &[Ptr, Ptr], None);
+/// Store the number a `Str` holds once trimmed, failing if it holds none
+pub const STR_PARSE_INT: RuntimeFunction = function("shark_str_parse_int", &[Ptr, Ptr], Some(Bool));
+pub const STR_PARSE_FLOAT: RuntimeFunction =
+    function("shark_str_parse_float", &[Ptr, Ptr], Some(Bool));
+
+/// Copies a `Str` for C, which expects its bytes to be followed by a zero
+pub const CSTR: RuntimeFunction = function("shark_cstr", &[Ptr], Some(Ptr));
+/// Stores a copy of a string returned by C as a `Str`, where a null pointer is the empty string
+pub const STR_FROM_CSTR: RuntimeFunction = function("shark_str_from_cstr", &[Ptr, Ptr], None);
+
+/// Appends an item of a number of bytes to a `Vec`, making room for it if there is none
+pub const VEC_PUSH: RuntimeFunction = function("shark_vec_push", &[Ptr, Ptr, Int64], None);
+/// Inserts an item of a number of bytes into a `Vec` at an index no greater than its length
+pub const VEC_INSERT: RuntimeFunction =
+    function("shark_vec_insert", &[Ptr, Int64, Ptr, Int64], None);
+/// Removes the item of a number of bytes at an index of a `Vec`, storing it
+pub const VEC_REMOVE: RuntimeFunction =
+    function("shark_vec_remove", &[Ptr, Int64, Ptr, Int64], None);
+/// Stores a `Vec` with a copy of the items of another, whose items are a number of bytes
+pub const VEC_COPY: RuntimeFunction = function("shark_vec_copy", &[Ptr, Ptr, Int64], None);
+
+/// Every function of the runtime
+pub const FUNCTIONS: [RuntimeFunction; 37] = [
+    ALLOC,
+    WRITE,
+    EXIT,
+    ARGS,
+    READ_FILE,
+    WRITE_FILE,
+    SHOW_INT,
+    SHOW_UINT,
+    SHOW_FLOAT32,
+    SHOW_FLOAT64,
+    SHOW_CHAR,
+    PLACEHOLDERS,
+    FORMAT,
+    STR_COMPARE,
+    STR_LEN,
+    STR_CONCAT,
+    STR_FIND,
+    STR_STARTS_WITH,
+    STR_ENDS_WITH,
+    STR_SLICE,
+    STR_CHAR_AT,
+    STR_TRIM,
+    STR_TO_UPPER,
+    STR_TO_LOWER,
+    STR_REPEAT,
+    STR_REPLACE,
+    STR_SPLIT,
+    STR_LINES,
+    STR_CHARS,
+    STR_PARSE_INT,
+    STR_PARSE_FLOAT,
+    CSTR,
+    STR_FROM_CSTR,
+    VEC_PUSH,
+    VEC_INSERT,
+    VEC_REMOVE,
+    VEC_COPY,
+];
+
+/// Finds the function of the runtime with a name
+pub fn lookup(name: &str) -> Option<&'static RuntimeFunction> {
+    FUNCTIONS.iter().find(|x| x.name == name)
+}
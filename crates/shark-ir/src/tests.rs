@@ -0,0 +1,782 @@
+use shark_sema::numeric::Overflow;
+
+use crate::{
+    build, cfg, copy, dce, fold, inline, ir::Module, optimize, parse::parse as parse_ir,
+    verify::verify, OptLevel,
+};
+
+/// Builds the IR of a module which must check without errors
+fn build_source(source: &str, overflow: Overflow) -> Module {
+    let checked = shark_testing::check(source);
+    let bodies = checked.lower();
+    build(
+        &checked.module,
+        &checked.defs,
+        &checked.types,
+        &bodies,
+        overflow,
+    )
+}
+
+/// Parses a module which must verify, runs a pass over it, and prints what it gives, which must
+/// verify too
+fn run_pass(text: &str, pass: impl Fn(&mut Module)) -> String {
+    let mut module = parse_ir(text).expect("failed to parse the IR");
+    verify(&module).expect("the IR given is malformed");
+    pass(&mut module);
+    verify(&module).expect("the pass made the IR malformed");
+    module.to_string()
+}
+
+/// Removes the indentation of the lines of a test, keeping that of instructions
+fn text(text: &str) -> String {
+    let mut result = String::new();
+    for line in text.lines().map(str::trim).filter(|x| !x.is_empty()) {
+        match line.starts_with("fun") || line.starts_with("bb") || line == "}" {
+            true => result.push_str(line),
+            false => result.push_str(&format!("    {}", line)),
+        }
+        result.push('\n');
+    }
+    result
+}
+
+#[test]
+fn test_round_trip() {
+    let source = text(
+        r#"fun @"<Int32 as Shape>::area"(ptr) -> i32 {
+        bb0(%0: ptr):
+            %1 = load i32 %0
+            %2 = mul %1, %1
+            ret %2
+        }
+        fun @main() {
+        bb0:
+            %0 = alloca i32
+            %1 = const -3int32
+            store %0, %1
+            %2 = call i32 @"<Int32 as Shape>::area"(%0)
+            %3 = const 2.5float64
+            %4 = const 'x'
+            %5 = neg %3
+            %6 = not %1
+            %7 = ge %2, %6
+            br %7, bb1(%2), bb2
+        bb1(%8: i32):
+            call @main()
+            jump bb2
+        bb2:
+            ret
+        }"#,
+    );
+    let module = parse_ir(&source).expect("failed to parse the IR");
+    verify(&module).expect("the IR is malformed");
+    assert_eq!(module.to_string(), source.replace("\n}\nfun", "\n}\n\nfun"));
+
+    // Values are renumbered in the order they are defined
+    let module = parse_ir(
+        "fun @f(i8) -> i8 {
+        bb0(%7: i8):
+            jump bb1(%7)
+        bb1(%3: i8):
+            %9 = copy %3
+            ret %9
+        }",
+    )
+    .expect("failed to parse the IR");
+    assert_eq!(
+        module.to_string(),
+        text(
+            "fun @f(i8) -> i8 {
+            bb0(%0: i8):
+                jump bb1(%0)
+            bb1(%1: i8):
+                %2 = copy %1
+                ret %2
+            }"
+        )
+    );
+}
+
+#[test]
+fn test_parse_errors() {
+    let error = |text: &str| parse_ir(text).expect_err("the IR should not parse");
+    assert_eq!(
+        error("fun @f() {\nbb0:\n    %0 = frob %1\n    ret\n}"),
+        "line 3: unknown instruction `frob`"
+    );
+    assert_eq!(
+        error("fun @f() {\nbb1:\n    ret\n}"),
+        "line 2: expected `bb0`, as blocks are numbered in order"
+    );
+    assert_eq!(
+        error("fun @f() {\nbb0:\n    %0 = const 1int32\n    %0 = const 2int32\n    ret\n}"),
+        "line 4: `%0` is defined twice"
+    );
+    assert_eq!(
+        error("fun @f() -> i32 {\nbb0:\n    ret %4\n}"),
+        "`%4` is used in `f` but never defined"
+    );
+    assert_eq!(
+        error("fun @f() {\nbb0:\n    %0 = const 300uint8\n    ret\n}"),
+        "line 3: invalid constant `300uint8`: number too large to fit in target type"
+    );
+    assert_eq!(
+        error("fun @f() {\nbb0:\n    %0 = call @g()\n    ret\n}"),
+        "line 3: calls defining a value must name its type"
+    );
+}
+
+#[test]
+fn test_verify_errors() {
+    let error = |text: &str| {
+        let module = parse_ir(text).expect("failed to parse the IR");
+        verify(&module).expect_err("the IR should be malformed")
+    };
+    assert_eq!(
+        error(
+            "fun @f(bool) -> i32 {
+            bb0(%0: bool):
+                br %0, bb1, bb2
+            bb1:
+                %1 = const 1int32
+                jump bb2
+            bb2:
+                ret %1
+            }"
+        ),
+        "in `f`: `%1` is used in `bb2` where it is not always defined"
+    );
+    assert_eq!(
+        error(
+            "fun @f(i32, i64) -> i32 {
+            bb0(%0: i32, %1: i64):
+                %2 = add %0, %1
+                ret %2
+            }"
+        ),
+        "in `f`: `add` of `i32` and `i64`"
+    );
+    assert_eq!(
+        error(
+            "fun @f(i32) {
+            bb0(%0: i32):
+                jump bb1
+            bb1(%1: i32):
+                ret
+            }"
+        ),
+        "in `f`: `bb1` takes 1 parameters, but `bb0` passes 0"
+    );
+    assert_eq!(
+        error(
+            "fun @f(i32) -> i32 {
+            bb0(%0: i32):
+                br %0, bb1, bb1
+            bb1:
+                ret %0
+            }"
+        ),
+        "in `f`: the condition of a branch must be `bool`, found `i32`"
+    );
+    assert_eq!(
+        error(
+            "fun @f() -> i8 {
+            bb0:
+                %0 = call i8 @g()
+                ret %0
+            }"
+        ),
+        "in `f`: `g` is called but does not exist"
+    );
+    assert_eq!(
+        error(
+            "fun @f() -> i8 {
+            bb0:
+                ret
+            }"
+        ),
+        "in `f`: `bb0` returns `()`, but the function returns `i8`"
+    );
+}
+
+#[test]
+fn test_constant_folding() {
+    let folded = run_pass(
+        "fun @f(i32) -> i32 {
+        bb0(%0: i32):
+            %1 = const 6int32
+            %2 = const 7int32
+            %3 = mul %1, %2
+            %4 = neg %3
+            %5 = lt %4, %1
+            br %5, bb1, bb2
+        bb1:
+            %6 = const 2.5float32
+            %7 = add %6, %6
+            %8 = add %0, %3
+            ret %8
+        bb2:
+            ret %4
+        }",
+        |x| {
+            fold::fold(&mut x.functions[0]);
+        },
+    );
+    assert_eq!(
+        folded,
+        text(
+            "fun @f(i32) -> i32 {
+            bb0(%0: i32):
+                %1 = const 6int32
+                %2 = const 7int32
+                %3 = const 42int32
+                %4 = const -42int32
+                %5 = const true
+                jump bb1
+            bb1:
+                %6 = const 2.5float32
+                %7 = const 5.0float32
+                %8 = add %0, %3
+                ret %8
+            bb2:
+                ret %4
+            }"
+        )
+    );
+
+    // Arithmetic which stops the program is kept, so that it still does
+    let source = text(
+        "fun @f() -> u8 {
+        bb0:
+            %0 = const 255uint8
+            %1 = const 1uint8
+            %2 = add %0, %1
+            %3 = const 0uint8
+            %4 = div %1, %3
+            %5 = const 8int32
+            %6 = shl %1, %5
+            %7 = const -128int8
+            %8 = neg %7
+            ret %2
+        }",
+    );
+    assert_eq!(
+        run_pass(&source, |x| {
+            fold::fold(&mut x.functions[0]);
+        }),
+        source
+    );
+}
+
+#[test]
+fn test_overflow_instructions() {
+    let source = text(
+        "fun @f(i8, f64) -> u8 {
+        bb0(%0: i8, %1: f64):
+            %2 = const 100int8
+            %3 = add.wrap %0, %2
+            %4 = mul.sat %3, %3
+            %5 = conv u8 %4
+            %6 = conv.wrap u8 %1
+            %7 = div.sat %5, %6
+            ret %7
+        }",
+    );
+    let module = parse_ir(&source).expect("failed to parse the IR");
+    verify(&module).expect("the IR is malformed");
+    assert_eq!(module.to_string(), source);
+
+    // They fold unless they would stop the program
+    let folded = run_pass(
+        "fun @f() -> u8 {
+        bb0:
+            %0 = const 100int8
+            %1 = add.wrap %0, %0
+            %2 = sub.sat %1, %0
+            %3 = conv.wrap u8 %1
+            %4 = const 10000000000.0float64
+            %5 = conv.wrap i32 %4
+            %6 = conv u8 %0
+            %7 = conv u8 %1
+            %8 = const 0uint8
+            %9 = div.wrap %3, %8
+            ret %9
+        }",
+        |x| {
+            fold::fold(&mut x.functions[0]);
+        },
+    );
+    assert_eq!(
+        folded,
+        text(
+            "fun @f() -> u8 {
+            bb0:
+                %0 = const 100int8
+                %1 = const -56int8
+                %2 = const -128int8
+                %3 = const 200uint8
+                %4 = const 10000000000.0float64
+                %5 = const 2147483647int32
+                %6 = const 100uint8
+                %7 = conv u8 %1
+                %8 = const 0uint8
+                %9 = div.wrap %3, %8
+                ret %9
+            }"
+        )
+    );
+
+    // Only the instructions which can stop the program are kept when unused
+    let eliminated = run_pass(
+        "fun @f(i32) {
+        bb0(%0: i32):
+            %1 = add.wrap %0, %0
+            %2 = conv.wrap u8 %0
+            %3 = conv u8 %0
+            %4 = div.sat %0, %0
+            ret
+        }",
+        |x| {
+            dce::eliminate(&mut x.functions[0]);
+        },
+    );
+    assert_eq!(
+        eliminated,
+        text(
+            "fun @f(i32) {
+            bb0(%0: i32):
+                %1 = conv u8 %0
+                %2 = div.sat %0, %0
+                ret
+            }"
+        )
+    );
+
+    let error = |text: &str| {
+        let module = parse_ir(text).expect("failed to parse the IR");
+        verify(&module).expect_err("the IR should be malformed")
+    };
+    assert_eq!(
+        error(
+            "fun @f(f32) -> f32 {
+            bb0(%0: f32):
+                %1 = add.wrap %0, %0
+                ret %1
+            }"
+        ),
+        "in `f`: `add.wrap` of `f32` and `f32`"
+    );
+    assert_eq!(
+        error(
+            "fun @f(bool) -> i32 {
+            bb0(%0: bool):
+                %1 = conv i32 %0
+                ret %1
+            }"
+        ),
+        "in `f`: `conv` of `bool`"
+    );
+
+    // A release build wraps around, including when negating
+    let module = build_source(
+        "fun f(x :: Int8, y :: Float64) :: UInt8 {
+            let z = -x * x.saturating_add(1);
+            z.to_uint8() + y.to_uint8()
+        }
+
+        pub fun main() :: Int32 {
+            0
+        }",
+        Overflow::Wrap,
+    );
+    let mut function = module.function("f").expect("`f` is built").clone();
+    copy::propagate(&mut function);
+    cfg::simplify(&mut function);
+    dce::eliminate(&mut function);
+    assert_eq!(
+        function.to_string(),
+        text(
+            "fun @f(i8, f64) -> u8 {
+            bb0(%0: i8, %1: f64):
+                %2 = const 0int8
+                %3 = sub.wrap %2, %0
+                %4 = const 1int8
+                %5 = add.sat %0, %4
+                %6 = mul.wrap %3, %5
+                %7 = conv.wrap u8 %6
+                %8 = conv.wrap u8 %1
+                %9 = add.wrap %7, %8
+                ret %9
+            }"
+        )
+        .trim_end()
+    );
+}
+
+#[test]
+fn test_copy_propagation() {
+    let propagated = run_pass(
+        "fun @f(i32, bool) -> i32 {
+        bb0(%0: i32, %1: bool):
+            %2 = copy %0
+            jump bb1(%2, %0)
+        bb1(%3: i32, %4: i32):
+            br %1, bb1(%3, %4), bb2(%4)
+        bb2(%5: i32):
+            %6 = add %5, %3
+            ret %6
+        }",
+        |x| {
+            copy::propagate(&mut x.functions[0]);
+        },
+    );
+    assert_eq!(
+        propagated,
+        text(
+            "fun @f(i32, bool) -> i32 {
+            bb0(%0: i32, %1: bool):
+                jump bb1
+            bb1:
+                br %1, bb1, bb2
+            bb2:
+                %2 = add %0, %0
+                ret %2
+            }"
+        )
+    );
+}
+
+#[test]
+fn test_dead_code_elimination() {
+    let eliminated = run_pass(
+        "fun @f(i32, bool) -> i32 {
+        bb0(%0: i32, %1: bool):
+            %2 = const 1int32
+            %3 = eq %0, %2
+            %4 = add %0, %2
+            jump bb1(%0, %0)
+        bb1(%5: i32, %6: i32):
+            %7 = add %5, %2
+            %8 = lt %6, %2
+            br %1, bb1(%7, %6), bb2
+        bb2:
+            ret %6
+        }",
+        |x| {
+            dce::eliminate(&mut x.functions[0]);
+        },
+    );
+    // Checked arithmetic is kept, but a parameter only used to work out its next value is not
+    assert_eq!(
+        eliminated,
+        text(
+            "fun @f(i32, bool) -> i32 {
+            bb0(%0: i32, %1: bool):
+                %2 = const 1int32
+                %3 = add %0, %2
+                jump bb1(%0, %0)
+            bb1(%4: i32, %5: i32):
+                %6 = add %4, %2
+                br %1, bb1(%6, %5), bb2
+            bb2:
+                ret %5
+            }"
+        )
+    );
+}
+
+#[test]
+fn test_cfg_simplification() {
+    let simplified = run_pass(
+        "fun @f(bool) -> i32 {
+        bb0(%0: bool):
+            %1 = const 1int32
+            br %0, bb1(%1), bb1(%1)
+        bb1(%2: i32):
+            jump bb3
+        bb2:
+            %3 = const 2int32
+            ret %3
+        bb3:
+            ret %2
+        }",
+        |x| {
+            cfg::simplify(&mut x.functions[0]);
+        },
+    );
+    assert_eq!(
+        simplified,
+        text(
+            "fun @f(bool) -> i32 {
+            bb0(%0: bool):
+                %1 = const 1int32
+                ret %1
+            }"
+        )
+    );
+}
+
+#[test]
+fn test_inlining() {
+    let source = "fun @double(i32) -> i32 {
+        bb0(%0: i32):
+            %1 = const 0int32
+            %2 = lt %0, %1
+            br %2, bb1, bb2
+        bb1:
+            ret %1
+        bb2:
+            %3 = add %0, %0
+            ret %3
+        }
+        fun @count(i32) -> i32 {
+        bb0(%0: i32):
+            %1 = const 1int32
+            %2 = sub %0, %1
+            %3 = call i32 @count(%2)
+            ret %3
+        }
+        fun @main() -> i32 {
+        bb0:
+            %0 = const 21int32
+            %1 = call i32 @double(%0)
+            %2 = call i32 @count(%1)
+            ret %2
+        }";
+    let inlined = run_pass(source, |x| {
+        inline::inline(x);
+    });
+    assert_eq!(
+        inlined.split("\n\n").nth(2).expect("`main` is kept"),
+        text(
+            "fun @main() -> i32 {
+            bb0:
+                %0 = const 21int32
+                jump bb1(%0)
+            bb1(%1: i32):
+                %2 = const 0int32
+                %3 = lt %1, %2
+                br %3, bb2, bb3
+            bb2:
+                jump bb4(%2)
+            bb3:
+                %4 = add %1, %1
+                jump bb4(%4)
+            bb4(%5: i32):
+                %6 = call i32 @count(%5)
+                ret %6
+            }"
+        )
+    );
+    // Recursive functions are left alone, and the rest folds away
+    let optimized = run_pass(source, |x| optimize(x, OptLevel::O2));
+    assert_eq!(
+        optimized.split("\n\n").nth(2).expect("`main` is kept"),
+        text(
+            "fun @main() -> i32 {
+            bb0:
+                %0 = const 42int32
+                %1 = call i32 @count(%0)
+                ret %1
+            }"
+        )
+    );
+}
+
+#[test]
+fn test_build() {
+    let source = "fun fib(n :: Int64) :: Int64 {
+            if n < 2 {
+                ret n;
+            }
+            fib(n - 1) + fib(n - 2)
+        }
+
+        fun square(x :: Int32) :: Int32 {
+            x * x
+        }
+
+        fun size(n :: Int32) :: Int32 {
+            when n {
+                0 => 0,
+                1..10 => 1,
+                x if x > 100 => 3,
+                _ => 2,
+            }
+        }
+
+        fun set(target :: ref mut Int32, value :: Int32) {
+            *target = value;
+        }
+
+        pub fun main() :: Int32 {
+            let mut total = square(3);
+            total += square(4);
+            let next = total + 2 * 7;
+            set(ref mut total, next);
+            if fib(10int64) == 55int64 && size(5) == 1 { total } else { 0 }
+        }";
+    let module = build_source(source, Overflow::Trap);
+    for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+        let mut module = module.clone();
+        optimize(&mut module, level);
+        verify(&module).unwrap_or_else(|x| panic!("malformed at {:?}: {}", level, x));
+        // What is printed parses back to the same text
+        let printed = module.to_string();
+        let parsed = parse_ir(&printed).expect("failed to parse the printed IR");
+        assert_eq!(parsed.to_string(), printed);
+    }
+
+    let mut optimized = module.clone();
+    optimize(&mut optimized, OptLevel::O1);
+    assert_eq!(
+        optimized
+            .function("fib")
+            .expect("`fib` is built")
+            .to_string(),
+        text(
+            "fun @fib(i64) -> i64 {
+            bb0(%0: i64):
+                %1 = const 2int64
+                %2 = lt %0, %1
+                br %2, bb1, bb2
+            bb1:
+                ret %0
+            bb2:
+                %3 = const 1int64
+                %4 = sub %0, %3
+                %5 = call i64 @fib(%4)
+                %6 = const 2int64
+                %7 = sub %0, %6
+                %8 = call i64 @fib(%7)
+                %9 = add %5, %8
+                ret %9
+            }"
+        )
+        .trim_end()
+    );
+    // Calls to `square` and `size` are inlined and folded, but `total` lives in memory as its
+    // address is taken
+    optimize(&mut optimized, OptLevel::O2);
+    assert_eq!(
+        optimized
+            .function("main")
+            .expect("`main` is built")
+            .to_string(),
+        text(
+            "fun @main() -> i32 {
+            bb0:
+                %0 = const 0int32
+                %1 = alloca i32
+                store %1, %0
+                %2 = const 9int32
+                store %1, %2
+                %3 = const 16int32
+                %4 = load i32 %1
+                %5 = add %4, %3
+                store %1, %5
+                %6 = load i32 %1
+                %7 = const 14int32
+                %8 = add %6, %7
+                store %1, %8
+                %9 = const 10int64
+                %10 = call i64 @fib(%9)
+                %11 = const 55int64
+                %12 = eq %10, %11
+                %13 = const false
+                br %12, bb1, bb2(%13)
+            bb1:
+                %14 = const true
+                jump bb2(%14)
+            bb2(%15: bool):
+                br %15, bb3, bb4
+            bb3:
+                %16 = load i32 %1
+                jump bb5(%16)
+            bb4:
+                %17 = const 0int32
+                jump bb5(%17)
+            bb5(%18: i32):
+                ret %18
+            }"
+        )
+        .trim_end()
+    );
+}
+
+#[test]
+fn test_build_language() {
+    let source = "type Point { x :: Int32, y :: Int32 }
+
+        enum Shape {
+            Circle(Int32),
+            Rectangle(Point, Point),
+        }
+
+        impl Show for Point {
+            fun show(self :: ref Self) :: Str {
+                format(\"({}, {})\", self.x, self.y)
+            }
+        }
+
+        fun area(shape :: Shape) :: Int32 {
+            when shape {
+                Shape::Circle(radius) => 3 * radius * radius,
+                Shape::Rectangle(a, b) => (b.x - a.x) * (b.y - a.y),
+            }
+        }
+
+        fun largest<T :: Show>(items :: Vec<T>, first :: T) :: Str {
+            format(\"{} of {}\", first, items)
+        }
+
+        fun count(limit :: Int64) :: yield Int64 {
+            if limit > 0 {
+                for x in count(limit - 1) {
+                    yield x;
+                }
+                yield limit;
+            }
+        }
+
+        pub fun main() :: Int32 {
+            let point = Point { x = 1, y = 2 };
+            let pair = (Point { x = 1, y = 2 }, \"pair\");
+            let offset = 10;
+            let shift = fun(x :: Int32) :: Int32 { x + offset };
+            let mut points = Vec::new();
+            when pair {
+                (first, _) => points.push(first),
+            }
+            let mut names :: Map<Str, Int64> = Map::new();
+            for (index, value) of count(3) {
+                names.insert(format(\"{}\", index), value);
+            }
+            println(\"{} {} {}\", largest(points, Point { x = 0, y = 0 }), names, names.get(\"1\"));
+            shift(area(Shape::Rectangle(point, Point { x = 4, y = 6 })))
+        }";
+    let module = build_source(source, Overflow::Trap);
+    for name in [
+        "main",
+        "area",
+        "largest<Point>",
+        "count",
+        "count::resume",
+        "main::{closure#1}",
+        "<Point as std::Show>::show",
+        "<std::Vec<Point> as std::Show>::show",
+        "<std::Option<Int64> as std::Show>::show",
+        "std::Map<Str, Int64>::{find}",
+    ] {
+        assert!(module.function(name).is_some(), "`{}` is not built", name);
+    }
+    for level in [OptLevel::O0, OptLevel::O1, OptLevel::O2] {
+        let mut module = module.clone();
+        optimize(&mut module, level);
+        verify(&module).unwrap_or_else(|x| panic!("malformed at {:?}: {}", level, x));
+        let printed = module.to_string();
+        let parsed = parse_ir(&printed).expect("failed to parse the printed IR");
+        assert_eq!(parsed.to_string(), printed);
+    }
+}
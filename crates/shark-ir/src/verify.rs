@@ -0,0 +1,375 @@
+//! Checks that a [Module] is well formed: that every value is defined once before it is used on
+//! every path to the use, that operands have the types their instructions expect, and that every
+//! branch passes its target the parameters it takes. Passes are checked by verifying what they
+//! give, so a module which fails is a bug in whatever made it
+//!
+//! Blocks which can not be reached from the entry are only checked for types, as nothing
+//! dominates them
+
+use std::collections::{HashMap, HashSet};
+
+use crate::ir::{
+    overflow_suffix, BinaryOp, BlockId, Function, InstKind, Module, Target, Terminator, Type,
+    UnaryOp, Value,
+};
+
+/// Gets the blocks which dominate each reachable block, including itself
+pub fn dominators(function: &Function) -> Vec<Option<HashSet<BlockId>>> {
+    let reachable = function.reachable();
+    let predecessors = function.predecessors();
+    let all: HashSet<_> = (0..function.blocks.len() as u32)
+        .map(BlockId)
+        .filter(|x| reachable[x.0 as usize])
+        .collect();
+    let mut dominators: Vec<_> = (0..function.blocks.len())
+        .map(|index| match (index, reachable[index]) {
+            (0, _) => Some(HashSet::from([BlockId::ENTRY])),
+            (_, true) => Some(all.clone()),
+            (_, false) => None,
+        })
+        .collect();
+    loop {
+        let mut changed = false;
+        for index in 1..function.blocks.len() {
+            if !reachable[index] {
+                continue;
+            }
+            let mut incoming = predecessors[index]
+                .iter()
+                .filter_map(|x| dominators[x.0 as usize].as_ref());
+            let mut set = incoming.next().cloned().unwrap_or_default();
+            for other in incoming {
+                set.retain(|x| other.contains(x));
+            }
+            set.insert(BlockId(index as u32));
+            if dominators[index].as_ref() != Some(&set) {
+                dominators[index] = Some(set);
+                changed = true;
+            }
+        }
+        if !changed {
+            return dominators;
+        }
+    }
+}
+
+struct FunctionVerifier<'m> {
+    module: &'m Module,
+    function: &'m Function,
+    types: HashMap<Value, Type>,
+    /// Where each value is defined: its block and its position within it, where parameters come
+    /// before the first instruction
+    definitions: HashMap<Value, (BlockId, usize)>,
+    dominators: Vec<Option<HashSet<BlockId>>>,
+}
+
+impl FunctionVerifier<'_> {
+    fn ty(&self, value: Value) -> Result<Type, String> {
+        self.types
+            .get(&value)
+            .copied()
+            .ok_or_else(|| format!("`%{}` is never defined", value.0))
+    }
+
+    /// Checks that a value is defined before a position, on every path to it
+    fn check_use(&self, value: Value, block: BlockId, position: usize) -> Result<Type, String> {
+        let ty = self.ty(value)?;
+        let Some(dominators) = &self.dominators[block.0 as usize] else {
+            return Ok(ty);
+        };
+        let (defined_in, defined_at) = self.definitions[&value];
+        let dominates = match defined_in == block {
+            true => defined_at < position,
+            false => dominators.contains(&defined_in),
+        };
+        match dominates {
+            true => Ok(ty),
+            false => Err(format!(
+                "`%{}` is used in `bb{}` where it is not always defined",
+                value.0, block.0
+            )),
+        }
+    }
+
+    fn expect(&self, ty: Type, expected: Type, what: &str) -> Result<(), String> {
+        match ty == expected {
+            true => Ok(()),
+            false => Err(format!("{} must be `{}`, found `{}`", what, expected, ty)),
+        }
+    }
+
+    fn check_target(&self, target: &Target, block: BlockId, position: usize) -> Result<(), String> {
+        let Some(to) = self.function.blocks.get(target.block.0 as usize) else {
+            return Err(format!("`bb{}` does not exist", target.block.0));
+        };
+        if to.params.len() != target.arguments.len() {
+            return Err(format!(
+                "`bb{}` takes {} parameters, but `bb{}` passes {}",
+                target.block.0,
+                to.params.len(),
+                block.0,
+                target.arguments.len()
+            ));
+        }
+        for (argument, (_, ty)) in target.arguments.iter().zip(&to.params) {
+            let argument_ty = self.check_use(*argument, block, position)?;
+            self.expect(
+                argument_ty,
+                *ty,
+                &format!("a parameter of `bb{}`", target.block.0),
+            )?;
+        }
+        Ok(())
+    }
+
+    /// Gets the type an instruction gives, checking its operands
+    fn check_inst(&self, kind: &InstKind, operands: &[Type]) -> Result<Option<Type>, String> {
+        Ok(match kind {
+            InstKind::Const(literal) => Some(Type::of_literal(literal)),
+            InstKind::Copy(_) => Some(operands[0]),
+            InstKind::Unary(UnaryOp::Neg, _) => {
+                if !operands[0].is_integer() && !operands[0].is_float() {
+                    return Err(format!("`neg` of `{}`", operands[0]));
+                }
+                Some(operands[0])
+            }
+            InstKind::Unary(UnaryOp::Not, _) => {
+                if !operands[0].is_integer() && operands[0] != Type::Bool {
+                    return Err(format!("`not` of `{}`", operands[0]));
+                }
+                Some(operands[0])
+            }
+            InstKind::Binary(op, _, _) => {
+                let (left, right) = (operands[0], operands[1]);
+                let valid = match op {
+                    BinaryOp::Shl | BinaryOp::Shr => left.is_integer() && right.is_integer(),
+                    BinaryOp::And => (left.is_integer() || left == Type::Bool) && left == right,
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div => {
+                        (left.is_integer() || left.is_float()) && left == right
+                    }
+                    BinaryOp::Eq | BinaryOp::Ne => left == right,
+                    _ => left == right && left != Type::Ptr,
+                };
+                if !valid {
+                    return Err(format!("`{}` of `{}` and `{}`", op.name(), left, right));
+                }
+                match op.is_comparison() {
+                    true => Some(Type::Bool),
+                    false => Some(left),
+                }
+            }
+            InstKind::Arith(overflow, op, _, _) => {
+                let (left, right) = (operands[0], operands[1]);
+                let arithmetic = matches!(
+                    op,
+                    BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div
+                );
+                if !arithmetic || !left.is_integer() || left != right {
+                    return Err(format!(
+                        "`{}{}` of `{}` and `{}`",
+                        op.name(),
+                        overflow_suffix(*overflow),
+                        left,
+                        right
+                    ));
+                }
+                Some(left)
+            }
+            InstKind::Convert(_, _) => {
+                if operands[0].primitive().is_none() {
+                    return Err(format!("`conv` of `{}`", operands[0]));
+                }
+                None
+            }
+            InstKind::Alloca(_) => Some(Type::Ptr),
+            InstKind::Slot(_, align) => {
+                if !align.is_power_of_two() {
+                    return Err(format!("a `slot` aligned to {}", align));
+                }
+                Some(Type::Ptr)
+            }
+            InstKind::Offset(_, _) => {
+                self.expect(operands[0], Type::Ptr, "the address of an `offset`")?;
+                self.expect(operands[1], Type::Int64, "the bytes of an `offset`")?;
+                Some(Type::Ptr)
+            }
+            InstKind::MemCopy(_, _, _) => {
+                self.expect(operands[0], Type::Ptr, "the addresses of a `memcopy`")?;
+                self.expect(operands[1], Type::Ptr, "the addresses of a `memcopy`")?;
+                self.expect(operands[2], Type::Int64, "the bytes of a `memcopy`")?;
+                None
+            }
+            InstKind::Load(_) => {
+                self.expect(operands[0], Type::Ptr, "the address of a `load`")?;
+                None
+            }
+            InstKind::Store(_, _) => {
+                self.expect(operands[0], Type::Ptr, "the address of a `store`")?;
+                None
+            }
+            InstKind::Call(name, _) => {
+                let (params, return_type, variadic) = match self.module.function(name) {
+                    Some(callee) => (callee.params(), callee.return_type, false),
+                    None => match self.module.extern_function(name) {
+                        Some(callee) => (
+                            callee.params.clone(),
+                            callee.return_type,
+                            callee.foreign.as_ref().is_some_and(|x| x.variadic),
+                        ),
+                        None => return Err(format!("`{}` is called but does not exist", name)),
+                    },
+                };
+                let matches = match variadic {
+                    true => operands.starts_with(&params),
+                    false => params == operands,
+                };
+                if !matches {
+                    let types: Vec<_> = operands.iter().map(|x| x.to_string()).collect();
+                    return Err(format!(
+                        "`{}` can not be called with `({})`",
+                        name,
+                        types.join(", ")
+                    ));
+                }
+                return_type
+            }
+            InstKind::FuncAddr(name) => {
+                if self.module.function(name).is_none() {
+                    return Err(format!(
+                        "the address of `{}` is taken but it does not exist",
+                        name
+                    ));
+                }
+                Some(Type::Ptr)
+            }
+            InstKind::CallIndirect(_, _) => {
+                self.expect(operands[0], Type::Ptr, "the function of a `call`")?;
+                None
+            }
+            InstKind::Panic(_, value) => {
+                if value.is_some() {
+                    self.expect(operands[0], Type::Ptr, "the text of a `panic`")?;
+                }
+                None
+            }
+        })
+    }
+
+    fn verify(&self) -> Result<(), String> {
+        if self.function.blocks.is_empty() {
+            return Err("there is no entry block".to_string());
+        }
+        for (index, block) in self.function.blocks.iter().enumerate() {
+            let id = BlockId(index as u32);
+            for (position, inst) in block.insts.iter().enumerate() {
+                let position = position + 1;
+                let operands = inst
+                    .kind
+                    .operands()
+                    .iter()
+                    .map(|x| self.check_use(*x, id, position))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let ty = self.check_inst(&inst.kind, &operands)?;
+                match (inst.result, ty, &inst.kind) {
+                    // Loads and indirect calls give whatever type they are asked for, and
+                    // conversions any number
+                    (Some(_), None, InstKind::Load(_) | InstKind::CallIndirect(..)) => {}
+                    (Some((_, ty)), None, InstKind::Convert(..)) => {
+                        if ty.primitive().is_none() {
+                            return Err(format!("`conv` to `{}`", ty));
+                        }
+                    }
+                    (Some((_, ty)), Some(expected), _) => {
+                        self.expect(ty, expected, "the result")?;
+                    }
+                    (None, _, InstKind::Load(_) | InstKind::Convert(..)) => {
+                        return Err("a `load` or `conv` does not define its value".into())
+                    }
+                    (None, None, _) | (None, Some(_), InstKind::Call(..)) => {}
+                    (Some(_), None, _) => return Err("an instruction defines no value".into()),
+                    (None, Some(_), _) => {
+                        return Err("an instruction does not define its value".into())
+                    }
+                }
+            }
+            let position = block.insts.len() + 1;
+            match &block.terminator {
+                Terminator::Jump(target) => self.check_target(target, id, position)?,
+                Terminator::Branch(condition, then_target, else_target) => {
+                    let ty = self.check_use(*condition, id, position)?;
+                    self.expect(ty, Type::Bool, "the condition of a branch")?;
+                    self.check_target(then_target, id, position)?;
+                    self.check_target(else_target, id, position)?;
+                }
+                Terminator::Return(value) => {
+                    let ty = match value {
+                        Some(value) => Some(self.check_use(*value, id, position)?),
+                        None => None,
+                    };
+                    if ty != self.function.return_type {
+                        let name = |x: Option<Type>| x.map_or("()", Type::name);
+                        return Err(format!(
+                            "`bb{}` returns `{}`, but the function returns `{}`",
+                            index,
+                            name(ty),
+                            name(self.function.return_type)
+                        ));
+                    }
+                }
+                Terminator::Unreachable => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Checks that a function is well formed, given the module it is in
+pub fn verify_function(module: &Module, function: &Function) -> Result<(), String> {
+    let mut types = HashMap::new();
+    let mut definitions = HashMap::new();
+    let mut defined = |value: Value, ty, block, position| match types.insert(value, ty) {
+        None => {
+            definitions.insert(value, (block, position));
+            Ok(())
+        }
+        Some(_) => Err(format!("`%{}` is defined twice", value.0)),
+    };
+    for (index, block) in function.blocks.iter().enumerate() {
+        let id = BlockId(index as u32);
+        for (value, ty) in &block.params {
+            defined(*value, *ty, id, 0)?;
+        }
+        for (position, inst) in block.insts.iter().enumerate() {
+            if let Some((value, ty)) = inst.result {
+                defined(value, ty, id, position + 1)?;
+            }
+        }
+    }
+    FunctionVerifier {
+        module,
+        function,
+        types,
+        definitions,
+        dominators: dominators(function),
+    }
+    .verify()
+    .map_err(|x| format!("in `{}`: {}", function.name, x))
+}
+
+/// Checks that every function of a module is well formed, and that no two functions or externs
+/// share a name
+pub fn verify(module: &Module) -> Result<(), String> {
+    let mut names = HashSet::new();
+    for declaration in &module.externs {
+        if !names.insert(&declaration.name) {
+            return Err(format!("`{}` is declared twice", declaration.name));
+        }
+    }
+    for function in &module.functions {
+        if !names.insert(&function.name) {
+            return Err(format!("`{}` is defined twice", function.name));
+        }
+        verify_function(module, function)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,214 @@
+//! What compiled code shares with the Rust code running it. Every compiled function is given a
+//! pointer to a [Context], through which it tracks how deep calls are nested and reports runtime
+//! errors. Compiled code can not unwind, so a runtime error is recorded by calling back into
+//! [trap], after which every function returns straight away once it sees [Context::failed] set
+//!
+//! Functions which can not be compiled are run by the interpreter, which compiled code calls into
+//! through [interpret] with its arguments in memory. Embedders can also provide functions of their
+//! own as [HostFunction]s, which compiled code calls directly
+
+use std::ffi::c_void;
+
+use shark_core::{diagnostic::Diagnostic, source::Span, symbol::Symbol};
+use shark_interp::{
+    eval::{Halt, Interpreter},
+    value::Value,
+};
+use shark_sema::ty::PrimitiveType;
+
+/// The state compiled code reads and writes directly
+#[repr(C)]
+pub struct Context {
+    /// How many calls deep the program is, counting `main`
+    pub depth: u32,
+    /// Set once a runtime error has been reported
+    pub failed: u32,
+    /// The [State] of the program being run
+    state: *mut c_void,
+}
+
+impl Context {
+    pub const DEPTH_OFFSET: i32 = 0;
+    pub const FAILED_OFFSET: i32 = 4;
+}
+
+/// The text of the label of a runtime error
+#[derive(Debug, Clone)]
+pub enum Label {
+    Fixed(String),
+    /// The label of a shift by too many bits, which names the amount. Whether the amount is
+    /// unsigned decides how its bits are read
+    Shift {
+        direction: &'static str,
+        unsigned: bool,
+    },
+}
+
+/// A runtime error compiled code can report
+#[derive(Debug, Clone)]
+pub struct Trap {
+    pub message: &'static str,
+    pub label: Label,
+    pub span: Span,
+}
+
+/// A function run by the interpreter when compiled code calls it
+#[derive(Debug, Clone)]
+pub struct Fallback {
+    pub name: Symbol,
+    pub parameters: Vec<PrimitiveType>,
+    pub return_type: Option<PrimitiveType>,
+}
+
+/// A function provided by the embedder, which replaces the Shark function of the same name in
+/// compiled code. The interpreter keeps running the Shark function, so it should behave the same
+#[derive(Debug, Clone)]
+pub struct HostFunction {
+    pub name: Symbol,
+    pub parameters: Vec<PrimitiveType>,
+    pub return_type: Option<PrimitiveType>,
+    pub address: *const u8,
+}
+
+impl HostFunction {
+    /// Describes an `extern "C"` function taking and returning the types given
+    ///
+    /// # Safety
+    ///
+    /// `address` must be an `extern "C"` function whose parameters and result are the Rust types
+    /// of the primitive types given, such as `i32` for `Int32` and `bool` for `Bool`, and it must
+    /// stay valid for as long as any program using it runs
+    pub unsafe fn new(
+        name: &str,
+        parameters: Vec<PrimitiveType>,
+        return_type: Option<PrimitiveType>,
+        address: *const u8,
+    ) -> Self {
+        Self {
+            name: Symbol::intern(name),
+            parameters,
+            return_type,
+            address,
+        }
+    }
+}
+
+// SAFETY: the address is of a function, which any thread can call
+unsafe impl Send for HostFunction {}
+unsafe impl Sync for HostFunction {}
+
+/// What the callbacks from compiled code need
+pub struct State<'run, 'ast> {
+    pub interpreter: Interpreter<'run, 'ast>,
+    pub traps: Vec<Trap>,
+    pub fallbacks: Vec<Fallback>,
+    /// The runtime error or call of `exit` which stopped the program
+    pub halt: Option<Halt>,
+}
+
+impl<'run, 'ast> State<'run, 'ast> {
+    pub fn context(&mut self, depth: u32) -> Context {
+        Context {
+            depth,
+            failed: 0,
+            state: self as *mut Self as *mut c_void,
+        }
+    }
+}
+
+/// Reads a value of a primitive type from the bits it is stored in
+pub fn from_bits(bits: u64, ty: PrimitiveType) -> Value {
+    match ty {
+        PrimitiveType::Int8 => Value::Int8(bits as i8),
+        PrimitiveType::UInt8 => Value::UInt8(bits as u8),
+        PrimitiveType::Bool => Value::Bool(bits as u8 != 0),
+        PrimitiveType::Int32 => Value::Int32(bits as i32),
+        PrimitiveType::UInt32 => Value::UInt32(bits as u32),
+        PrimitiveType::Char => Value::Char(char::from_u32(bits as u32).unwrap_or_default()),
+        PrimitiveType::Int64 => Value::Int64(bits as i64),
+        PrimitiveType::UInt64 => Value::UInt64(bits),
+        PrimitiveType::Float32 => Value::Float32(f32::from_bits(bits as u32)),
+        PrimitiveType::Float64 => Value::Float64(f64::from_bits(bits)),
+        PrimitiveType::Str => unreachable!("strings are never passed to compiled code"),
+    }
+}
+
+/// Gets the bits a scalar value is stored in
+pub fn to_bits(value: &Value) -> u64 {
+    match *value {
+        Value::Int8(x) => x as u8 as u64,
+        Value::UInt8(x) => x as u64,
+        Value::Bool(x) => x as u64,
+        Value::Int32(x) => x as u32 as u64,
+        Value::UInt32(x) => x as u64,
+        Value::Char(x) => x as u64,
+        Value::Int64(x) => x as u64,
+        Value::UInt64(x) => x,
+        Value::Float32(x) => x.to_bits() as u64,
+        Value::Float64(x) => x.to_bits(),
+        _ => 0,
+    }
+}
+
+/// Gets the [State] of a program from its context
+///
+/// # Safety
+///
+/// The context must have been made by [State::context] for a state which is still alive
+unsafe fn state<'c>(context: *mut Context) -> &'c mut State<'c, 'c> {
+    &mut *((*context).state as *mut State)
+}
+
+/// Reports a runtime error, given the value a label names
+///
+/// # Safety
+///
+/// The context must be the one compiled code was given, and the trap one of its program
+pub unsafe extern "C" fn trap(context: *mut Context, trap: u32, value: i64) {
+    let state = state(context);
+    let trap = &state.traps[trap as usize];
+    let label = match &trap.label {
+        Label::Fixed(label) => label.clone(),
+        Label::Shift {
+            direction,
+            unsigned: true,
+        } => format!("cannot shift {} by {}", direction, value as u64),
+        Label::Shift { direction, .. } => format!("cannot shift {} by {}", direction, value),
+    };
+    let diagnostic = Diagnostic::error(trap.message).with_primary(trap.span, label);
+    state.halt = Some(Halt::Error(diagnostic));
+    (*context).failed = 1;
+}
+
+/// Runs a function by the interpreter, with its arguments in memory eight bytes apart. Its result,
+/// if any, is written to `result`
+///
+/// # Safety
+///
+/// The context must be the one compiled code was given, and there must be room for the arguments
+/// and result of the fallback
+pub unsafe extern "C" fn interpret(
+    context: *mut Context,
+    fallback: u32,
+    arguments: *const u64,
+    result: *mut u64,
+) {
+    let state = state(context);
+    let fallback = &state.fallbacks[fallback as usize];
+    let arguments = fallback
+        .parameters
+        .iter()
+        .enumerate()
+        .map(|(index, ty)| from_bits(*arguments.add(index), *ty))
+        .collect();
+    // The caller has already counted the call, which the interpreter counts again
+    let depth = (*context).depth as usize;
+    state.interpreter.set_depth(depth - 1);
+    match state.interpreter.call_function(fallback.name, arguments) {
+        Ok(value) => *result = to_bits(&value),
+        Err(halt) => {
+            state.halt = Some(halt);
+            (*context).failed = 1;
+        }
+    }
+}
@@ -0,0 +1,617 @@
+//! Compiles a checked and lowered [Module] to machine code in-process with Cranelift, and runs it
+//! as a [Program]. Compiled code behaves like the interpreter: arithmetic is checked, runtime errors
+//! are reported with the same diagnostics, and calls nest as deep
+//!
+//! Functions which can not be compiled, see [translate], are run by the interpreter instead when
+//! they take and return scalars, so that compiled code can still call them. Functions calling a
+//! function which can neither be compiled nor called from compiled code are left to the
+//! interpreter too, which runs `main` itself if it is not compiled. Embedders can replace Shark
+//! functions by [HostFunction]s of their own
+
+use std::{
+    collections::HashMap,
+    fmt,
+    time::{Duration, Instant},
+};
+
+use cranelift_codegen::{
+    ir::{types, AbiParam, Signature},
+    settings::{self, Configurable},
+    Context as CodegenContext,
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{Linkage, Module as _};
+use host::{Context, Fallback, HostFunction, State};
+use shark_core::{
+    diagnostic::Diagnostic,
+    symbol::{sym, Symbol},
+};
+use shark_interp::{
+    eval::{Halt, Interpreter},
+    find_main,
+    value::Value,
+};
+use shark_lower::{impls::ImplMethods, Body};
+use shark_parse::ast::{Function, ItemKind, Module};
+use shark_sema::{numeric::Overflow, ty::PrimitiveType, ModuleDefs};
+use shark_std::runtime::Host;
+use shark_typeck::{ty::Ty, TypeckResults};
+use translate::{FunctionRef, FunctionTranslator, Instance, Status, Translator};
+
+pub mod host;
+pub mod translate;
+
+#[cfg(test)]
+pub mod tests;
+
+/// What became of a function
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Outcome {
+    Compiled,
+    /// Replaced by a host function
+    Host,
+    /// Left to the interpreter, for a reason
+    Interpreted(String),
+}
+
+/// How long compiling a function took
+#[derive(Debug, Clone)]
+pub struct FunctionTiming {
+    pub name: String,
+    pub outcome: Outcome,
+    pub time: Duration,
+}
+
+impl fmt::Display for FunctionTiming {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = self.time.as_secs_f64() * 1000.0;
+        match &self.outcome {
+            Outcome::Compiled => write!(f, "{:>9.3}ms  {}", time, self.name),
+            Outcome::Host => write!(f, "{:>9.3}ms  {} (host function)", time, self.name),
+            Outcome::Interpreted(reason) => {
+                write!(
+                    f,
+                    "{:>9.3}ms  {} (interpreted: {})",
+                    time, self.name, reason
+                )
+            }
+        }
+    }
+}
+
+/// A compiled program, ready to be run
+pub struct Program<'run, 'ast> {
+    /// The module holding the machine code, which is freed along with the program
+    jit: Option<JITModule>,
+    main: Symbol,
+    /// The code of `main` and its return type, unless it is left to the interpreter
+    entry: Option<(*const u8, Option<PrimitiveType>)>,
+    state: Box<State<'run, 'ast>>,
+    /// How long compiling each function took, in the order they appear in the module
+    pub timings: Vec<FunctionTiming>,
+}
+
+impl Program<'_, '_> {
+    /// Runs the program from its `pub fun main()`, returning the value `main` returns, the code
+    /// given to `exit` as an `Int32`, or the runtime error which stopped it. This needs as much
+    /// stack as the interpreter does
+    pub fn run(&mut self) -> Result<Value, Diagnostic> {
+        let Some((entry, return_type)) = self.entry else {
+            self.state.interpreter.set_depth(0);
+            let result = self.state.interpreter.call_function(self.main, Vec::new());
+            return match result {
+                Ok(value) => Ok(value),
+                Err(halt) => halted(halt),
+            };
+        };
+        self.state.halt = None;
+        let mut context = self.state.context(1);
+        let context = &mut context as *mut Context;
+        // SAFETY: `main` was compiled taking the context and returning a value of this type, and
+        // the module holding it lives as long as the program
+        let bits = unsafe {
+            match return_type {
+                None => {
+                    let main: extern "C" fn(*mut Context) = std::mem::transmute(entry);
+                    main(context);
+                    0
+                }
+                Some(PrimitiveType::Float32) => {
+                    let main: extern "C" fn(*mut Context) -> f32 = std::mem::transmute(entry);
+                    main(context).to_bits() as u64
+                }
+                Some(PrimitiveType::Float64) => {
+                    let main: extern "C" fn(*mut Context) -> f64 = std::mem::transmute(entry);
+                    main(context).to_bits()
+                }
+                Some(_) => {
+                    let main: extern "C" fn(*mut Context) -> u64 = std::mem::transmute(entry);
+                    main(context)
+                }
+            }
+        };
+        if let Some(halt) = self.state.halt.take() {
+            return halted(halt);
+        }
+        Ok(match return_type {
+            Some(ty) => host::from_bits(bits, ty),
+            None => Value::Unit,
+        })
+    }
+}
+
+fn halted(halt: Halt) -> Result<Value, Diagnostic> {
+    match halt {
+        Halt::Error(diagnostic) => Err(diagnostic),
+        Halt::Exit(code) => Ok(Value::Int32(code)),
+    }
+}
+
+impl Drop for Program<'_, '_> {
+    fn drop(&mut self) {
+        if let Some(jit) = self.jit.take() {
+            // SAFETY: nothing can call into the code once the program is gone
+            unsafe { jit.free_memory() };
+        }
+    }
+}
+
+/// Gets the primitive type of a parameter or result the interpreter can exchange with compiled
+/// code, which `()` is as a result
+fn scalar(ty: &Ty) -> Option<Option<PrimitiveType>> {
+    match ty {
+        Ty::Primitive(PrimitiveType::Str) => None,
+        Ty::Primitive(primitive) => Some(Some(*primitive)),
+        Ty::Unit => Some(None),
+        Ty::Tuple(elements) if elements.is_empty() => Some(None),
+        _ => None,
+    }
+}
+
+/// Gets the ISA of the host and a module to compile functions into, which can call back into the
+/// interpreter and the host functions given
+fn jit_module(hosts: &[HostFunction]) -> Result<JITModule, String> {
+    let mut flags = settings::builder();
+    for (name, value) in [
+        ("use_colocated_libcalls", "false"),
+        ("is_pic", "false"),
+        ("opt_level", "speed"),
+    ] {
+        flags.set(name, value).map_err(|x| x.to_string())?;
+    }
+    let isa = cranelift_native::builder()?
+        .finish(settings::Flags::new(flags))
+        .map_err(|x| x.to_string())?;
+    let mut builder = JITBuilder::with_isa(isa, cranelift_module::default_libcall_names());
+    builder.symbol("shark_jit_trap", host::trap as *const u8);
+    builder.symbol("shark_jit_interpret", host::interpret as *const u8);
+    for host in hosts {
+        builder.symbol(format!("shark_host_{}", host.name), host.address);
+    }
+    Ok(JITModule::new(builder))
+}
+
+/// The functions to compile, and everything known about them
+struct Functions<'b, 'ast> {
+    refs: Vec<FunctionRef>,
+    names: HashMap<Symbol, usize>,
+    impls: Vec<ImplMethods<usize>>,
+    instances: Vec<Instance<'b, 'ast>>,
+}
+
+/// Finds every function and method which is not generic, mirroring the backends
+fn collect<'b, 'ast>(
+    module: &Module,
+    defs: &ModuleDefs,
+    bodies: &'b [Body<'ast>],
+) -> Functions<'b, 'ast> {
+    let body_of = |function: &Function| bodies.iter().find(|x| std::ptr::eq(x.function, function));
+    let no_mapping = HashMap::new();
+    let mut functions = Functions {
+        refs: Vec::new(),
+        names: HashMap::new(),
+        impls: Vec::new(),
+        instances: Vec::new(),
+    };
+    let add = |functions: &mut Functions<'b, 'ast>,
+               name: String,
+               parameters: Vec<Ty>,
+               return_type: Ty,
+               body: &'b Body<'ast>,
+               self_type: Option<Ty>| {
+        functions.refs.push(FunctionRef {
+            name,
+            parameters,
+            return_type,
+            status: Status::Unavailable,
+        });
+        functions.instances.push(Instance {
+            body,
+            function: functions.refs.len() - 1,
+            self_type,
+        });
+        functions.refs.len() - 1
+    };
+
+    let mut defaults = HashMap::new();
+    for item in &module.items {
+        match &item.kind {
+            ItemKind::Function(function) => {
+                let (Some(body), Some(signature)) =
+                    (body_of(function), defs.functions.signature_of(item.id))
+                else {
+                    continue;
+                };
+                if !signature.generics.params.is_empty() {
+                    continue;
+                }
+                let index = add(
+                    &mut functions,
+                    function.name.symbol.to_string(),
+                    signature
+                        .parameters
+                        .iter()
+                        .map(|x| Ty::from_type(x, &no_mapping))
+                        .collect(),
+                    Ty::from_type(&signature.return_type, &no_mapping),
+                    body,
+                    None,
+                );
+                functions.names.insert(function.name.symbol, index);
+            }
+            ItemKind::Trait(trait_decl) => {
+                let Some(trait_id) = defs.traits.trait_of_item(item.id) else {
+                    continue;
+                };
+                for method in &trait_decl.methods {
+                    if let Some(body) = body_of(method) {
+                        defaults.insert((trait_id, method.name.symbol), body);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    // Implementations come last, as they take the defaults of their trait
+    for item in &module.items {
+        let ItemKind::Impl(impl_decl) = &item.kind else {
+            continue;
+        };
+        let Some(implementation) = defs.traits.impl_of_item(item.id) else {
+            continue;
+        };
+        let Some(trait_id) = implementation.trait_id else {
+            continue;
+        };
+        if !implementation.generics.params.is_empty() {
+            continue;
+        }
+        let self_type = Ty::from_type(&implementation.self_type, &no_mapping);
+        let trait_def = defs.traits.trait_def(trait_id);
+        let prefix = format!(
+            "<{} as {}>",
+            defs.types.type_name(&implementation.self_type),
+            trait_def.name.symbol
+        );
+        let mut methods = Vec::new();
+        for (method, signature) in impl_decl.methods.iter().zip(&implementation.methods) {
+            let Some(body) = body_of(method) else {
+                continue;
+            };
+            if !signature.generics.params.is_empty() {
+                continue;
+            }
+            let index = add(
+                &mut functions,
+                format!("{}::{}", prefix, method.name.symbol),
+                signature
+                    .parameters
+                    .iter()
+                    .map(|x| Ty::from_type(x, &no_mapping))
+                    .collect(),
+                Ty::from_type(&signature.return_type, &no_mapping),
+                body,
+                Some(self_type.clone()),
+            );
+            methods.push((method.name.symbol, index));
+        }
+        let mapping = HashMap::from([(sym::SELF_TYPE, self_type.clone())]);
+        for signature in &trait_def.methods {
+            let name = signature.name.symbol;
+            let replaced = methods.iter().any(|(x, _)| *x == name);
+            let Some(body) = defaults.get(&(trait_id, name)) else {
+                continue;
+            };
+            if replaced || !signature.generics.params.is_empty() {
+                continue;
+            }
+            let index = add(
+                &mut functions,
+                format!("{}::{}", prefix, name),
+                signature
+                    .parameters
+                    .iter()
+                    .map(|x| Ty::from_type(x, &mapping))
+                    .collect(),
+                Ty::from_type(&signature.return_type, &mapping),
+                body,
+                Some(self_type.clone()),
+            );
+            methods.push((name, index));
+        }
+        functions.impls.push(ImplMethods {
+            self_type: implementation.self_type.clone(),
+            trait_id,
+            methods,
+        });
+    }
+    functions
+}
+
+/// Translates a function into a context, whose function is left for the caller to define or clear
+fn translate(
+    translator: &mut Translator,
+    module: &mut JITModule,
+    context: &mut CodegenContext,
+    builder_context: &mut FunctionBuilderContext,
+    instance: &Instance,
+) -> Result<(), String> {
+    let function = &translator.functions[instance.function];
+    context.func.signature = translator.signature(function, false)?;
+    let builder = FunctionBuilder::new(&mut context.func, builder_context);
+    let result = FunctionTranslator::translate(translator, module, builder, instance);
+    // The builder is only cleared once it finishes a function
+    if result.is_err() {
+        *builder_context = FunctionBuilderContext::new();
+    }
+    result
+}
+
+/// Compiles every function of a module which can be compiled, replacing the functions named by the
+/// host functions given. Arithmetic overflows the way `overflow` says to, and the standard library
+/// runs within `host`
+pub fn compile<'run, 'ast>(
+    module: &'ast Module,
+    defs: &'run ModuleDefs,
+    types: &'run TypeckResults,
+    bodies: &'run [Body<'ast>],
+    overflow: Overflow,
+    host: Host,
+    hosts: &[HostFunction],
+) -> Result<Program<'run, 'ast>, Diagnostic> {
+    let main = find_main(module)?.name.symbol;
+    let mut jit = jit_module(hosts)
+        .map_err(|x| Diagnostic::error(format!("could not set up the JIT: {}", x)))?;
+    let Functions {
+        refs,
+        names,
+        impls,
+        instances,
+    } = collect(module, defs, bodies);
+    let declare_error =
+        |x: cranelift_module::ModuleError| Diagnostic::error(format!("the JIT failed: {}", x));
+
+    let pointer = jit.target_config().pointer_type();
+    let call_conv = jit.isa().default_call_conv();
+    let mut trap = Signature::new(call_conv);
+    trap.params.extend([
+        AbiParam::new(pointer),
+        AbiParam::new(types::I32),
+        AbiParam::new(types::I64),
+    ]);
+    let mut interpret = Signature::new(call_conv);
+    interpret.params.extend([
+        AbiParam::new(pointer),
+        AbiParam::new(types::I32),
+        AbiParam::new(pointer),
+        AbiParam::new(pointer),
+    ]);
+    let mut translator = Translator {
+        defs,
+        types,
+        overflow,
+        call_conv,
+        pointer,
+        functions: refs,
+        names,
+        impls,
+        traps: Vec::new(),
+        trap: jit
+            .declare_function("shark_jit_trap", Linkage::Import, &trap)
+            .map_err(declare_error)?,
+        interpret: jit
+            .declare_function("shark_jit_interpret", Linkage::Import, &interpret)
+            .map_err(declare_error)?,
+    };
+
+    let mut reasons = HashMap::new();
+    let mut fallbacks = Vec::new();
+    // A function which can not even be called is left to the interpreter like any other
+    let mut leave = |translator: &mut Translator, index: usize, reason: String| {
+        let function = &translator.functions[index];
+        let free = translator
+            .names
+            .iter()
+            .find(|(_, x)| **x == index)
+            .map(|(name, _)| *name);
+        let parameters: Option<Vec<_>> = function.parameters.iter().map(scalar).collect();
+        let parameters = parameters.and_then(|x| x.into_iter().collect::<Option<Vec<_>>>());
+        let status = match (free, parameters, scalar(&function.return_type)) {
+            (Some(name), Some(parameters), Some(return_type)) => {
+                fallbacks.push(Fallback {
+                    name,
+                    parameters,
+                    return_type,
+                });
+                Status::Interpreted(fallbacks.len() as u32 - 1)
+            }
+            _ => Status::Unavailable,
+        };
+        translator.functions[index].status = status;
+        reasons.insert(index, reason);
+    };
+    for index in 0..translator.functions.len() {
+        let function = &translator.functions[index];
+        let host = hosts
+            .iter()
+            .find(|x| translator.names.get(&x.name) == Some(&index));
+        let status = match host {
+            Some(host) => {
+                let parameters: Vec<_> =
+                    host.parameters.iter().map(|x| Ty::Primitive(*x)).collect();
+                if parameters != function.parameters
+                    || scalar(&function.return_type) != Some(host.return_type)
+                {
+                    return Err(Diagnostic::error(format!(
+                        "the host function `{}` does not take and return the same types as the \
+                         function it replaces",
+                        host.name
+                    )));
+                }
+                let signature = translator
+                    .signature(function, true)
+                    .expect("host functions only take scalars");
+                let name = format!("shark_host_{}", host.name);
+                Status::Host(
+                    jit.declare_function(&name, Linkage::Import, &signature)
+                        .map_err(declare_error)?,
+                )
+            }
+            None => match translator.signature(function, false) {
+                Ok(signature) => Status::Compiled(
+                    jit.declare_anonymous_function(&signature)
+                        .map_err(declare_error)?,
+                ),
+                Err(reason) => {
+                    leave(&mut translator, index, reason);
+                    continue;
+                }
+            },
+        };
+        translator.functions[index].status = status;
+    }
+    if let Some(host) = hosts
+        .iter()
+        .find(|x| !translator.names.contains_key(&x.name))
+    {
+        return Err(Diagnostic::error(format!(
+            "the host function `{}` does not replace any function",
+            host.name
+        )));
+    }
+
+    // Leaving a function to the interpreter can leave the functions calling it unable to call it,
+    // so functions are translated until none more fails
+    let mut context = jit.make_context();
+    let mut builder_context = FunctionBuilderContext::new();
+    let mut times = HashMap::new();
+    loop {
+        let mut changed = false;
+        for instance in &instances {
+            if !matches!(
+                translator.functions[instance.function].status,
+                Status::Compiled(_)
+            ) {
+                continue;
+            }
+            let start = Instant::now();
+            let result = translate(
+                &mut translator,
+                &mut jit,
+                &mut context,
+                &mut builder_context,
+                instance,
+            )
+            .and_then(|()| {
+                // Code which would fail verification is a bug in the JIT, which the interpreter
+                // is left to work around rather than stopping the program
+                cranelift_codegen::verify_function(&context.func, jit.isa()).map_err(|x| {
+                    format!(
+                        "the JIT generated invalid code: {}",
+                        x.to_string().trim_end()
+                    )
+                })
+            });
+            jit.clear_context(&mut context);
+            if let Err(reason) = result {
+                times.insert(instance.function, start.elapsed());
+                leave(&mut translator, instance.function, reason);
+                changed = true;
+            }
+        }
+        if !changed {
+            break;
+        }
+    }
+
+    translator.traps.clear();
+    let mut timings = Vec::new();
+    for instance in &instances {
+        let function = &translator.functions[instance.function];
+        let name = function.name.clone();
+        let (outcome, time) = match function.status {
+            Status::Compiled(id) => {
+                let start = Instant::now();
+                let result = translate(
+                    &mut translator,
+                    &mut jit,
+                    &mut context,
+                    &mut builder_context,
+                    instance,
+                )
+                .and_then(|()| {
+                    jit.define_function(id, &mut context)
+                        .map_err(|x| format!("{:?}", x))
+                });
+                jit.clear_context(&mut context);
+                if let Err(message) = result {
+                    return Err(Diagnostic::error(format!(
+                        "the JIT failed to compile `{}`: {}",
+                        name, message
+                    )));
+                }
+                (Outcome::Compiled, start.elapsed())
+            }
+            Status::Host(_) => (Outcome::Host, Duration::ZERO),
+            Status::Interpreted(_) | Status::Unavailable => (
+                Outcome::Interpreted(reasons[&instance.function].clone()),
+                times.get(&instance.function).copied().unwrap_or_default(),
+            ),
+        };
+        timings.push(FunctionTiming {
+            name,
+            outcome,
+            time,
+        });
+    }
+    jit.finalize_definitions().map_err(declare_error)?;
+
+    let entry = match translator
+        .names
+        .get(&main)
+        .map(|x| &translator.functions[*x])
+    {
+        Some(FunctionRef {
+            status: Status::Compiled(id),
+            return_type,
+            ..
+        }) => Some((
+            jit.get_finalized_function(*id),
+            scalar(return_type).expect("compiled functions return scalars"),
+        )),
+        _ => None,
+    };
+    Ok(Program {
+        jit: Some(jit),
+        main,
+        entry,
+        state: Box::new(State {
+            interpreter: Interpreter::new(module, defs, types, bodies, overflow, host),
+            traps: translator.traps,
+            fallbacks,
+            halt: None,
+        }),
+        timings,
+    })
+}
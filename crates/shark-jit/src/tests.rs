@@ -0,0 +1,511 @@
+use std::sync::atomic::{AtomicU32, Ordering};
+
+use shark_core::{diagnostic::Diagnostic, source::LineIndex};
+use shark_interp::value::Value;
+use shark_sema::{numeric::Overflow, ty::PrimitiveType};
+use shark_std::runtime::Host;
+use shark_testing::{on_interpreter_stack, overflow::check_overflow_modes, suite};
+
+use crate::{compile, host::HostFunction, FunctionTiming, Outcome};
+
+/// Gets the exit code for the result of a program, along with the error which stopped it
+fn outcome(source: &str, result: Result<Value, Diagnostic>) -> (u8, String) {
+    match result {
+        Ok(Value::Int32(code)) => (code as u8, String::new()),
+        Ok(_) => (0, String::new()),
+        Err(diagnostic) => {
+            let span = diagnostic
+                .primary_span()
+                .expect("runtime errors have a span");
+            let position = LineIndex::new(source).position(None, span.start);
+            let label = &diagnostic.labels[0].message;
+            (
+                101,
+                format!(
+                    "error: {}\n --> {}: {}",
+                    diagnostic.message, position, label
+                ),
+            )
+        }
+    }
+}
+
+/// Compiles a module which must check without errors with the host functions given, then runs it
+/// both compiled and by the interpreter. Returns the outcome, which must be the same both ways,
+/// along with how long compiling each function took
+fn run_with(
+    source: &str,
+    overflow: Overflow,
+    hosts: &[HostFunction],
+) -> ((u8, String), Vec<FunctionTiming>) {
+    let checked = shark_testing::check(source);
+    let bodies = checked.lower();
+    let (module, defs, types) = (&checked.module, &checked.defs, &checked.types);
+    on_interpreter_stack(|| {
+        let mut program = compile(
+            module,
+            defs,
+            types,
+            &bodies,
+            overflow,
+            Host::default(),
+            hosts,
+        )
+        .expect("failed to compile the module");
+        let result = outcome(source, program.run());
+        let interpreted =
+            shark_interp::run(module, defs, types, &bodies, overflow, Host::default());
+        assert_eq!(result, outcome(source, interpreted));
+        // Running again starts afresh
+        assert_eq!(outcome(source, program.run()), result);
+        (result, program.timings.clone())
+    })
+}
+
+fn execute(source: &str) -> (u8, String) {
+    execute_as(source, Overflow::Trap)
+}
+
+/// Like [execute], for a program built to overflow some way
+fn execute_as(source: &str, overflow: Overflow) -> (u8, String) {
+    let (result, timings) = run_with(source, overflow, &[]);
+    for timing in timings {
+        assert_eq!(timing.outcome, Outcome::Compiled, "{}", timing);
+    }
+    result
+}
+
+#[test]
+fn test_arithmetic() {
+    let result = execute(
+        "pub fun main() :: Int32 {
+            let mut total = 2 + 3 * 4;
+            total -= 10 / 3;
+            total *= -2;
+            let shifted = (1 << 4) >> 1;
+            let masked = 13 & 7;
+            total + shifted + masked + 100
+        }",
+    );
+    assert_eq!(result, (91, String::new()));
+
+    let result = execute(
+        "fun widths() :: Bool {
+            let half :: Float64 = 1.0 / 4.0 * 2.0;
+            let third :: Float32 = 1.0 / 3.0;
+            let max :: UInt8 = 200 + 55;
+            let low :: Int8 = -128;
+            let short :: Int8 = -100 + 27;
+            let unsigned :: UInt32 = 65535;
+            let big :: UInt64 = 9000000000000000000uint64 * 2;
+            let small :: Int64 = -9223372036854775807int64 - 1;
+            let wide :: UInt32 = 4000000000uint32;
+            let shifted :: Int32 = -16 >> 2;
+            let high :: UInt32 = wide >> 30;
+            3 >= 3 && 1 != 2 && !(!true | 2 < 1) && half == 0.5 && third < 0.34 && max == 255
+                && low == -128 && short == -73 && unsigned / 5 == 13107
+                && big / 2 == 9000000000000000000uint64 && small < -1 && wide > 1
+                && shifted == -4 && high == 3 && -half < 0.0 && !(third != third)
+        }
+
+        pub fun main() :: Int32 {
+            if widths() { 1 } else { 2 }
+        }",
+    );
+    assert_eq!(result, (1, String::new()));
+}
+
+#[test]
+fn test_control_flow() {
+    let result = execute(
+        "fun fib(n :: Int64) :: Int64 {
+            if n < 2 {
+                ret n;
+            }
+            fib(n - 1) + fib(n - 2)
+        }
+
+        fun sign(n :: Int32) :: Int32 {
+            if n > 0 { 1 } else if n < 0 { -1 } else { 0 }
+        }
+
+        fun size(n :: Int32) :: Int32 {
+            when n {
+                0 => 0,
+                1..10 => 1,
+                x if x > 100 => 3,
+                _ => 2,
+            }
+        }
+
+        pub fun main() :: Int32 {
+            let sizes = size(0) * 1000 + size(9) * 100 + size(10) * 10 + size(500);
+            if sign(-5) == -1 && sign(0) == 0 && sign(7) == 1 && sizes == 123 {
+                if fib(20) == 6765 { 42 } else { 1 }
+            } else {
+                0
+            }
+        }",
+    );
+    assert_eq!(result, (42, String::new()));
+}
+
+#[test]
+fn test_trailing_return() {
+    let result = execute(
+        "fun clamp(n :: Int32) :: Int32 {
+            if n > 10 {
+                ret 10;
+            }
+            ret n;
+        }
+
+        fun pick(flag :: Bool) :: Int64 {
+            when flag {
+                true => ret 1,
+                false => ret 2,
+            }
+        }
+
+        pub fun main() :: Int32 {
+            ret clamp(25) + clamp(5) + pick(false).to_int32();
+        }",
+    );
+    assert_eq!(result, (17, String::new()));
+}
+
+#[test]
+fn test_calling_convention() {
+    let result = execute(
+        "fun ints(
+            a :: Int8, b :: UInt8, c :: Int32, d :: Int64, e :: UInt8, f :: Int32, g :: Int64,
+            h :: UInt32,
+        ) :: Int64 {
+            if a == -1 && b == 2 && c == 3 && e == 5 && f == 6 && h == 8 { d + g } else { 0 }
+        }
+
+        fun floats(
+            a :: Float64, b :: Float32, c :: Float64, d :: Float64, e :: Float64, f :: Float64,
+            g :: Float64, h :: Float64, i :: Float32, j :: Float64, n :: Int32,
+        ) :: Float64 {
+            if b == 2.0 && i == 9.0 && n == 11 { a + c + d + e + f + g + h + j } else { 0.0 }
+        }
+
+        pub fun main() :: Int32 {
+            let x = ints(-1, 2, 3, 4int64, 5, 6, 7int64, 8);
+            let y = floats(1.0, 2.0, 3.0, 4.0, 5.0, 6.0, 7.0, 8.0, 9.0, 10.0, 11);
+            if y == 44.0 && x == 11int64 { 34 } else { 0 }
+        }",
+    );
+    assert_eq!(result, (34, String::new()));
+}
+
+#[test]
+fn test_references() {
+    let result = execute(
+        "fun set(target :: ref mut Int32, value :: Int32) {
+            *target = value;
+        }
+
+        fun get(source :: ref Float64) :: Float64 {
+            *source
+        }
+
+        pub fun main() :: Int32 {
+            let mut n = 1;
+            set(ref mut n, 40);
+            let pointer = ptr mut n;
+            unsafe {
+                *pointer += 2;
+            }
+            let half :: Float64 = 0.5;
+            if get(ref half) == 0.5 { n } else { 0 }
+        }",
+    );
+    assert_eq!(result, (42, String::new()));
+}
+
+#[test]
+fn test_methods() {
+    let result = execute(
+        "trait Shape {
+            fun area(self :: ref Self) :: Int32;
+            fun double(self :: ref Self) :: Int32 {
+                Shape::area(self) * 2
+            }
+        }
+
+        impl Shape for Int32 {
+            fun area(self :: ref Self) :: Int32 {
+                *self * *self
+            }
+        }
+
+        impl Shape for Bool {
+            fun area(self :: ref Self) :: Int32 {
+                if *self { 1 } else { 0 }
+            }
+
+            fun double(self :: ref Self) :: Int32 {
+                7
+            }
+        }
+
+        pub fun main() :: Int32 {
+            let side = 3;
+            let flag = true;
+            side.double() * 10 + flag.double() * 10 + Shape::area(ref flag)
+        }",
+    );
+    assert_eq!(result, (251, String::new()));
+}
+
+#[test]
+fn test_runtime_errors() {
+    let result = execute(
+        "pub fun main() :: Int8 {
+            let x :: Int8 = 100;
+            x + x
+        }",
+    );
+    assert_eq!(
+        result,
+        (
+            101,
+            "error: attempt to add with overflow\n --> unknown:3:13: the result does not fit in \
+             `Int8`"
+                .to_string()
+        )
+    );
+
+    let result = execute(
+        "fun divide(a :: Int32, b :: Int32) :: Int32 {
+            a / b
+        }
+
+        pub fun main() :: Int32 {
+            divide(1, 0) + divide(-2147483648, -1)
+        }",
+    );
+    assert_eq!(
+        result,
+        (
+            101,
+            "error: attempt to divide by zero\n --> unknown:2:13: the divisor is zero".to_string()
+        )
+    );
+
+    let result = execute(
+        "pub fun main() :: Int32 {
+            let x :: UInt64 = 1;
+            let y :: Int8 = -128;
+            let z = -y;
+            0
+        }",
+    );
+    assert_eq!(
+        result,
+        (
+            101,
+            "error: attempt to negate with overflow\n --> unknown:4:21: the result does not fit \
+             in `Int8`"
+                .to_string()
+        )
+    );
+
+    let result = execute(
+        "pub fun main() :: Int32 {
+            let x :: Int32 = 1;
+            let amount :: Int8 = -1;
+            x << amount
+        }",
+    );
+    assert_eq!(
+        result,
+        (
+            101,
+            "error: attempt to shift left with overflow\n --> unknown:4:13: cannot shift left by \
+             -1"
+            .to_string()
+        )
+    );
+
+    let result =
+        execute("fun forever(n :: Int32) :: Int32 { forever(n) } pub fun main() { forever(1); }");
+    assert_eq!(
+        result,
+        (
+            101,
+            "error: stack overflow\n --> unknown:1:36: calls are nested more than 2048 deep"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn test_fallback() {
+    let (result, timings) = run_with(
+        "type Point { x :: Int32, y :: Int32 }
+
+        fun sum(x :: Int32, y :: Int32) :: Int32 {
+            let point = Point { x = x, y = y };
+            point.x + point.y
+        }
+
+        fun peek(x :: ref Int32) :: Int32 {
+            let point = Point { x = *x, y = *x };
+            point.x + point.y
+        }
+
+        fun double(x :: Int32) :: Int32 {
+            peek(ref x)
+        }
+
+        fun countdown(n :: Int32) :: Int32 {
+            if n == 0 { ret 0; }
+            let point = Point { x = n, y = 0 };
+            countdown(point.x - 1) + 1
+        }
+
+        pub fun main() :: Int32 {
+            sum(30, 10) + double(1) - countdown(500) + 500
+        }",
+        Overflow::Trap,
+        &[],
+    );
+    assert_eq!(result, (42, String::new()));
+    let outcomes: Vec<_> = timings
+        .iter()
+        .map(|x| (x.name.as_str(), &x.outcome))
+        .collect();
+    let structs =
+        Outcome::Interpreted("structs, enums and tuples are not supported by the JIT".to_string());
+    assert_eq!(
+        outcomes,
+        [
+            ("sum", &structs),
+            ("peek", &structs),
+            (
+                "double",
+                &Outcome::Interpreted("`peek` can not be called from compiled code".to_string())
+            ),
+            ("countdown", &structs),
+            ("main", &Outcome::Compiled),
+        ]
+    );
+
+    // Interpreted functions count towards how deep calls are nested
+    let (result, _) = run_with(
+        "type Point { x :: Int32, y :: Int32 }
+
+        fun down(n :: Int32) :: Int32 {
+            let point = Point { x = n, y = 0 };
+            up(point.x)
+        }
+
+        fun up(n :: Int32) :: Int32 {
+            down(n + 1)
+        }
+
+        pub fun main() :: Int32 {
+            up(1)
+        }",
+        Overflow::Trap,
+        &[],
+    );
+    assert_eq!(
+        result,
+        (
+            101,
+            "error: stack overflow\n --> unknown:9:13: calls are nested more than 2048 deep"
+                .to_string()
+        )
+    );
+}
+
+static CALLS: AtomicU32 = AtomicU32::new(0);
+
+extern "C" fn scale(x: i32, by: i8) -> i32 {
+    CALLS.fetch_add(1, Ordering::Relaxed);
+    if by < 0 {
+        -x
+    } else {
+        x * 2
+    }
+}
+
+#[test]
+fn test_host_functions() {
+    let source = "fun scale(x :: Int32, by :: Int8) :: Int32 {
+            if by < 0 { -x } else { x * 2 }
+        }
+
+        pub fun main() :: Int32 {
+            scale(7, -1) + scale(20, 1)
+        }";
+    // SAFETY: `scale` takes an `Int32` and an `Int8` and returns an `Int32`
+    let host = |parameters| unsafe {
+        HostFunction::new(
+            "scale",
+            parameters,
+            Some(PrimitiveType::Int32),
+            scale as *const u8,
+        )
+    };
+    let (result, timings) = run_with(
+        source,
+        Overflow::Trap,
+        &[host(vec![PrimitiveType::Int32, PrimitiveType::Int8])],
+    );
+    assert_eq!(result, (33, String::new()));
+    assert_eq!(CALLS.load(Ordering::Relaxed), 4);
+    assert_eq!(timings[0].outcome, Outcome::Host);
+
+    let checked = shark_testing::check(source);
+    let bodies = checked.lower();
+    let result = compile(
+        &checked.module,
+        &checked.defs,
+        &checked.types,
+        &bodies,
+        Overflow::Trap,
+        Host::default(),
+        &[host(vec![PrimitiveType::Int32])],
+    );
+    assert_eq!(
+        result.err().map(|x| x.message),
+        Some(
+            "the host function `scale` does not take and return the same types as the function \
+             it replaces"
+                .to_string()
+        )
+    );
+}
+
+#[test]
+fn test_overflow_modes() {
+    check_overflow_modes(execute_as);
+}
+
+#[test]
+fn test_library_programs() {
+    for test in suite::programs() {
+        let checked = shark_testing::check(&test.source);
+        let bodies = checked.lower();
+        let (module, defs, types) = (&checked.module, &checked.defs, &checked.types);
+        let (host, captured) = Host::captured(test.args.clone());
+        let result = on_interpreter_stack(|| {
+            compile(module, defs, types, &bodies, Overflow::Trap, host, &[])
+                .expect("failed to compile the module")
+                .run()
+                .map(|x| x.as_integer().expect("`main` returns an integer"))
+                .map_err(|x| x.message)
+        });
+        assert_eq!(
+            suite::transcript(&captured.text(), result),
+            test.expected,
+            "{}",
+            test.name
+        );
+    }
+}
@@ -0,0 +1,1565 @@
+//! Translates lowered function bodies into Cranelift IR. Every block of a body becomes a block of
+//! the function, and locals become Cranelift variables, except for locals whose address is taken,
+//! which live in stack slots so that references to them stay valid
+//!
+//! Arithmetic overflows as it does in the interpreter, reporting runtime errors through a [Trap]
+//! which names the same message and label. Only scalars are supported: a function using any other
+//! type, or a construct such as a generator, fails to translate with the reason why, and is left
+//! to the interpreter
+
+use std::{cmp::Ordering, collections::HashMap};
+
+use cranelift_codegen::ir::{
+    condcodes::{FloatCC, IntCC},
+    types, AbiParam, Block as IrBlock, FuncRef, InstBuilder, MemFlags, Signature, StackSlot,
+    StackSlotData, StackSlotKind, TrapCode, Type as IrType, Value as IrValue,
+};
+use cranelift_codegen::isa::CallConv;
+use cranelift_frontend::{FunctionBuilder, Variable};
+use cranelift_jit::JITModule;
+use cranelift_module::{FuncId, Module as _};
+use shark_core::{source::Span, symbol::Symbol};
+use shark_interp::eval::MAX_CALL_DEPTH;
+use shark_lex::token::LiteralKind;
+use shark_lower::{
+    body::{LocalId, LocalKind, Statement, Terminator},
+    impls::{find_method, ImplMethods},
+    lower::visit_block,
+    Body,
+};
+use shark_parse::ast::{
+    BinaryOperator, Block, Expr, ExprKind, NodeId, Pattern, PatternKind, StatementKind,
+    UnaryOperator,
+};
+use shark_sema::{
+    numeric::{self, Intrinsic, Overflow},
+    ty::PrimitiveType,
+    ModuleDefs,
+};
+use shark_typeck::{ty::Ty, TypeckResults};
+
+use crate::host::{Context, Label, Trap};
+
+/// What code which is never reached traps with, should it somehow be reached
+const UNREACHABLE: TrapCode = TrapCode::unwrap_user(1);
+
+/// How a function is run when compiled code calls it
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Status {
+    Compiled(FuncId),
+    /// Replaced by a host function, which is not given the context
+    Host(FuncId),
+    /// Run by the interpreter, as the fallback with an index
+    Interpreted(u32),
+    /// Neither compiled nor callable from compiled code, so every caller is left to the
+    /// interpreter too
+    Unavailable,
+}
+
+/// A function compiled code can call
+#[derive(Debug, Clone)]
+pub struct FunctionRef {
+    /// How the function is named in messages
+    pub name: String,
+    pub parameters: Vec<Ty>,
+    pub return_type: Ty,
+    pub status: Status,
+}
+
+/// What translating every function shares
+pub struct Translator<'t> {
+    pub defs: &'t ModuleDefs,
+    pub types: &'t TypeckResults,
+    /// What the arithmetic of the program does when it overflows
+    pub overflow: Overflow,
+    pub call_conv: CallConv,
+    pub pointer: IrType,
+    /// Every function and method, of which those which are not generic or methods are named
+    pub functions: Vec<FunctionRef>,
+    pub names: HashMap<Symbol, usize>,
+    /// The methods of every `impl`, as indices of [Translator::functions]
+    pub impls: Vec<ImplMethods<usize>>,
+    pub traps: Vec<Trap>,
+    /// The callback reporting a runtime error
+    pub trap: FuncId,
+    /// The callback running a function by the interpreter
+    pub interpret: FuncId,
+}
+
+impl Translator<'_> {
+    /// Gets the signature of a function, which is given the context first unless it is a host
+    /// function
+    pub fn signature(&self, function: &FunctionRef, host: bool) -> Result<Signature, String> {
+        let mut signature = Signature::new(self.call_conv);
+        if !host {
+            signature.params.push(AbiParam::new(self.pointer));
+        }
+        for parameter in &function.parameters {
+            signature
+                .params
+                .extend(abi_param(class_of(parameter)?, self.pointer));
+        }
+        let return_class = class_of(&function.return_type)?;
+        signature
+            .returns
+            .extend(abi_param(return_class, self.pointer));
+        Ok(signature)
+    }
+}
+
+/// How the values of a type are represented
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Class {
+    Int {
+        bits: u8,
+        signed: bool,
+    },
+    /// A `Bool`, which is an `i8` of zero or one
+    Bool,
+    Float32,
+    Float64,
+    Pointer,
+    /// `()`, which has no representation at all
+    Unit,
+}
+
+impl Class {
+    fn ir_type(self, pointer: IrType) -> Option<IrType> {
+        match self {
+            Self::Int { bits: 8, .. } | Self::Bool => Some(types::I8),
+            Self::Int { bits: 32, .. } => Some(types::I32),
+            Self::Int { .. } => Some(types::I64),
+            Self::Float32 => Some(types::F32),
+            Self::Float64 => Some(types::F64),
+            Self::Pointer => Some(pointer),
+            Self::Unit => None,
+        }
+    }
+}
+
+fn abi_param(class: Class, pointer: IrType) -> Option<AbiParam> {
+    let param = AbiParam::new(class.ir_type(pointer)?);
+    Some(match class {
+        Class::Int {
+            bits: 8,
+            signed: true,
+        } => param.sext(),
+        Class::Int { bits: 8, .. } | Class::Bool => param.uext(),
+        _ => param,
+    })
+}
+
+/// Gets the class of the values of a type, or why it is not supported
+pub fn class_of(ty: &Ty) -> Result<Class, String> {
+    let int = |bits, signed| Class::Int { bits, signed };
+    let unsupported = |what: &str| Err(format!("{} are not supported by the JIT", what));
+    match ty {
+        Ty::Primitive(primitive) => match primitive {
+            PrimitiveType::Int8 => Ok(int(8, true)),
+            PrimitiveType::UInt8 => Ok(int(8, false)),
+            PrimitiveType::Bool => Ok(Class::Bool),
+            PrimitiveType::Int32 => Ok(int(32, true)),
+            PrimitiveType::UInt32 | PrimitiveType::Char => Ok(int(32, false)),
+            PrimitiveType::Int64 => Ok(int(64, true)),
+            PrimitiveType::UInt64 => Ok(int(64, false)),
+            PrimitiveType::Float32 => Ok(Class::Float32),
+            PrimitiveType::Float64 => Ok(Class::Float64),
+            PrimitiveType::Str => unsupported("strings"),
+        },
+        Ty::Unit | Ty::Never => Ok(Class::Unit),
+        Ty::Tuple(elements) if elements.is_empty() => Ok(Class::Unit),
+        Ty::Reference { pointee, .. } => class_of(pointee).map(|_| Class::Pointer),
+        Ty::Adt(..) | Ty::Tuple(_) => unsupported("structs, enums and tuples"),
+        Ty::Generator(_) => unsupported("generators"),
+        Ty::Array(..) => unsupported("arrays"),
+        Ty::Function { .. } => unsupported("closures"),
+        Ty::Param(_) | Ty::Var(_) | Ty::Error => unsupported("generic functions"),
+    }
+}
+
+/// Gets the smallest and largest values of an integer class, as the bits of an `i64`
+fn limits(bits: u8, signed: bool) -> (i64, i64) {
+    match (signed, bits) {
+        (true, _) => (-1i64 << (bits - 1), !(-1i64 << (bits - 1))),
+        (false, 64) => (0, -1),
+        (false, _) => (0, (1i64 << bits) - 1),
+    }
+}
+
+/// Gets the name of a primitive type for the label of an overflow
+fn type_name(ty: &Ty) -> &'static str {
+    match ty {
+        Ty::Primitive(primitive) => primitive.name(),
+        _ => "",
+    }
+}
+
+/// Where a local lives
+#[derive(Debug, Clone, Copy)]
+enum Place {
+    /// A local of type `()`, which has nowhere to live
+    Unit,
+    Variable(Variable),
+    Slot(StackSlot, IrType),
+    /// A value behind a pointer, which is only the target of an assignment
+    Address(IrValue, IrType),
+}
+
+/// A function body to translate
+pub struct Instance<'b, 'ast> {
+    pub body: &'b Body<'ast>,
+    /// The index of the function
+    pub function: usize,
+    /// The type `Self` stands for, within a method
+    pub self_type: Option<Ty>,
+}
+
+type Translate<T> = Result<T, String>;
+
+pub struct FunctionTranslator<'f, 't, 'b, 'ast> {
+    translator: &'f mut Translator<'t>,
+    module: &'f mut JITModule,
+    builder: FunctionBuilder<'f>,
+    body: &'b Body<'ast>,
+    self_type: Option<Ty>,
+    locals: Vec<Place>,
+    blocks: Vec<IrBlock>,
+    context: IrValue,
+    /// The block returning straight away once a runtime error has been reported
+    bail: IrBlock,
+    return_class: Class,
+    func_refs: HashMap<FuncId, FuncRef>,
+    variables: u32,
+}
+
+impl<'f, 't, 'b, 'ast> FunctionTranslator<'f, 't, 'b, 'ast> {
+    /// Translates an instance into the function of a builder, whose signature must be set
+    pub fn translate(
+        translator: &'f mut Translator<'t>,
+        module: &'f mut JITModule,
+        mut builder: FunctionBuilder<'f>,
+        instance: &Instance<'b, 'ast>,
+    ) -> Translate<()> {
+        let body = instance.body;
+        if body.generator.is_some() {
+            return Err("generators are not supported by the JIT".to_string());
+        }
+        let function = translator.functions[instance.function].clone();
+        let entry = builder.create_block();
+        builder.append_block_params_for_function_params(entry);
+        let bail = builder.create_block();
+        let blocks = body.blocks.iter().map(|_| builder.create_block()).collect();
+        builder.switch_to_block(entry);
+        let context = builder.block_params(entry)[0];
+        let mut this = FunctionTranslator {
+            translator,
+            module,
+            builder,
+            body,
+            self_type: instance.self_type.clone(),
+            locals: Vec::new(),
+            blocks,
+            context,
+            bail,
+            return_class: class_of(&function.return_type)?,
+            func_refs: HashMap::new(),
+            variables: 0,
+        };
+        this.locals(&function.parameters, entry)?;
+        this.builder.ins().jump(this.blocks[0], &[]);
+        for (index, block) in body.blocks.iter().enumerate() {
+            this.builder.switch_to_block(this.blocks[index]);
+            for statement in &block.statements {
+                this.statement(statement)?;
+            }
+            this.terminator(&block.terminator)?;
+        }
+        this.builder.switch_to_block(bail);
+        this.return_zero();
+        this.builder.seal_all_blocks();
+        this.builder.finalize();
+        Ok(())
+    }
+
+    /// Gives every local a place, storing the parameters in theirs
+    fn locals(&mut self, parameters: &[Ty], entry: IrBlock) -> Translate<()> {
+        let mut addressed = Vec::new();
+        if let Some(block) = &self.body.function.body {
+            visit_block(block, &mut |expr| {
+                let operand = match &expr.kind {
+                    ExprKind::Reference { operand, .. } => &**operand,
+                    ExprKind::Call { .. }
+                        if self.translator.types.intrinsic_of(expr.id).is_some() =>
+                    {
+                        return
+                    }
+                    ExprKind::Call { callee, .. } => match &callee.kind {
+                        ExprKind::Field { object, .. } => &**object,
+                        _ => return,
+                    },
+                    _ => return,
+                };
+                if let Some(local) = self.body.names.get(&operand.id) {
+                    addressed.push(*local);
+                }
+            });
+        }
+        let arguments = self.builder.block_params(entry)[1..].to_vec();
+        let mut arguments = arguments.into_iter();
+        for (index, local) in self.body.locals.iter().enumerate() {
+            let ty = match local.kind {
+                LocalKind::Parameter => parameters[index].clone(),
+                LocalKind::Let(id) | LocalKind::Binding(id) => self.ty(id),
+                _ => return Err("`for` loops are not supported by the JIT".to_string()),
+            };
+            let class = class_of(&ty)?;
+            let place = match class.ir_type(self.translator.pointer) {
+                None => Place::Unit,
+                Some(ty) if addressed.contains(&LocalId(index as u32)) => {
+                    Place::Slot(self.new_slot(), ty)
+                }
+                Some(ty) => {
+                    let variable = Variable::from_u32(self.variables);
+                    self.variables += 1;
+                    self.builder.declare_var(variable, ty);
+                    Place::Variable(variable)
+                }
+            };
+            if local.kind == LocalKind::Parameter {
+                if let Some(argument) = class
+                    .ir_type(self.translator.pointer)
+                    .and_then(|_| arguments.next())
+                {
+                    self.write(place, argument);
+                }
+            }
+            self.locals.push(place);
+        }
+        Ok(())
+    }
+
+    fn ty(&self, id: NodeId) -> Ty {
+        match self.translator.types.type_of(id) {
+            Some(ty) => ty.substitute_self(self.self_type.as_ref()),
+            None => Ty::Unit,
+        }
+    }
+
+    fn class(&self, id: NodeId) -> Translate<Class> {
+        class_of(&self.ty(id))
+    }
+
+    fn ir_type(&self, class: Class) -> Option<IrType> {
+        class.ir_type(self.translator.pointer)
+    }
+
+    fn new_slot(&mut self) -> StackSlot {
+        self.builder
+            .create_sized_stack_slot(StackSlotData::new(StackSlotKind::ExplicitSlot, 8, 3))
+    }
+
+    fn read(&mut self, place: Place) -> Option<IrValue> {
+        match place {
+            Place::Unit => None,
+            Place::Variable(variable) => Some(self.builder.use_var(variable)),
+            Place::Slot(slot, ty) => Some(self.builder.ins().stack_load(ty, slot, 0)),
+            Place::Address(address, ty) => {
+                Some(self.builder.ins().load(ty, MemFlags::trusted(), address, 0))
+            }
+        }
+    }
+
+    fn write(&mut self, place: Place, value: IrValue) {
+        match place {
+            Place::Unit => {}
+            Place::Variable(variable) => self.builder.def_var(variable, value),
+            Place::Slot(slot, _) => {
+                self.builder.ins().stack_store(value, slot, 0);
+            }
+            Place::Address(address, _) => {
+                self.builder
+                    .ins()
+                    .store(MemFlags::trusted(), value, address, 0);
+            }
+        }
+    }
+
+    fn zero(&mut self, class: Class) -> Option<IrValue> {
+        let ty = self.ir_type(class)?;
+        Some(match class {
+            Class::Float32 => self.builder.ins().f32const(0.0),
+            Class::Float64 => self.builder.ins().f64const(0.0),
+            _ => self.builder.ins().iconst(ty, 0),
+        })
+    }
+
+    fn return_zero(&mut self) {
+        let values: Vec<_> = self.zero(self.return_class).into_iter().collect();
+        self.builder.ins().return_(&values);
+    }
+
+    /// Returns a value, or nothing from a function returning `()`. A function returning anything
+    /// else only returns nothing from code which is never reached, such as the end of a block
+    /// after a `ret`, so that traps instead
+    fn return_value(&mut self, value: Option<IrValue>) {
+        match (value, self.ir_type(self.return_class)) {
+            (Some(value), _) => {
+                self.builder.ins().return_(&[value]);
+            }
+            (None, None) => {
+                self.builder.ins().return_(&[]);
+            }
+            (None, Some(_)) => {
+                self.builder.ins().trap(UNREACHABLE);
+            }
+        }
+    }
+
+    /// Continues in a new block, which nothing branches to, after an instruction which does not
+    /// continue
+    fn diverge(&mut self) {
+        let block = self.builder.create_block();
+        self.builder.switch_to_block(block);
+    }
+
+    fn func_ref(&mut self, id: FuncId) -> FuncRef {
+        if let Some(func_ref) = self.func_refs.get(&id) {
+            return *func_ref;
+        }
+        let func_ref = self.module.declare_func_in_func(id, self.builder.func);
+        self.func_refs.insert(id, func_ref);
+        func_ref
+    }
+
+    /// Reports a runtime error and returns
+    fn trap(&mut self, trap: Trap, value: Option<IrValue>) {
+        self.translator.traps.push(trap);
+        let index = self.translator.traps.len() as i64 - 1;
+        let value = match value {
+            Some(value) => value,
+            None => self.builder.ins().iconst(types::I64, 0),
+        };
+        let index = self.builder.ins().iconst(types::I32, index);
+        let callback = self.func_ref(self.translator.trap);
+        self.builder
+            .ins()
+            .call(callback, &[self.context, index, value]);
+        self.builder.ins().jump(self.bail, &[]);
+        self.diverge();
+    }
+
+    /// Reports a runtime error if a condition holds
+    fn trap_if(&mut self, condition: IrValue, trap: Trap, value: Option<IrValue>) {
+        let failed = self.builder.create_block();
+        let next = self.builder.create_block();
+        self.builder.ins().brif(condition, failed, &[], next, &[]);
+        self.builder.switch_to_block(failed);
+        self.trap(trap, value);
+        self.builder.ins().jump(next, &[]);
+        self.builder.switch_to_block(next);
+    }
+
+    /// Returns if the callee has reported a runtime error
+    fn check_failed(&mut self) {
+        let failed = self.builder.ins().load(
+            types::I32,
+            MemFlags::trusted(),
+            self.context,
+            Context::FAILED_OFFSET,
+        );
+        let next = self.builder.create_block();
+        self.builder.ins().brif(failed, self.bail, &[], next, &[]);
+        self.builder.switch_to_block(next);
+    }
+
+    fn statement(&mut self, statement: &Statement<'ast>) -> Translate<()> {
+        match statement {
+            Statement::Let(local, value) => {
+                if let Some(value) = value {
+                    let class = self.class(value.id)?;
+                    if let Some(value) = self.value(value, class)? {
+                        self.write(self.locals[local.0 as usize], value);
+                    }
+                }
+                Ok(())
+            }
+            Statement::Eval(expr) => self.expr(expr).map(|_| ()),
+            Statement::StartIteration { .. } => {
+                Err("`for` loops are not supported by the JIT".to_string())
+            }
+        }
+    }
+
+    fn terminator(&mut self, terminator: &Terminator<'ast>) -> Translate<()> {
+        match terminator {
+            Terminator::Goto(target) => {
+                self.builder.ins().jump(self.blocks[target.0 as usize], &[]);
+            }
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                let condition = self.value(condition, Class::Bool)?.expect("a `Bool`");
+                let then_block = self.blocks[then_block.0 as usize];
+                let else_block = self.blocks[else_block.0 as usize];
+                self.builder
+                    .ins()
+                    .brif(condition, then_block, &[], else_block, &[]);
+            }
+            Terminator::When { scrutinee, arms } => {
+                let class = self.class(scrutinee.id)?;
+                let value = self.value(scrutinee, class)?;
+                for (arm, target) in arms {
+                    let next = self.builder.create_block();
+                    self.test(&arm.pattern, value, class, next)?;
+                    self.bind(&arm.pattern, value);
+                    if let Some(guard) = &arm.guard {
+                        let guard = self.value(guard, Class::Bool)?.expect("a `Bool`");
+                        let body = self.builder.create_block();
+                        self.builder.ins().brif(guard, body, &[], next, &[]);
+                        self.builder.switch_to_block(body);
+                    }
+                    let target = self.blocks[target.0 as usize];
+                    self.builder.ins().jump(target, &[]);
+                    self.builder.switch_to_block(next);
+                }
+                self.no_arm_matched(scrutinee.span);
+                self.builder.ins().jump(self.bail, &[]);
+            }
+            Terminator::Next { .. } => {
+                return Err("`for` loops are not supported by the JIT".to_string())
+            }
+            Terminator::Yield { .. } => {
+                return Err("generators are not supported by the JIT".to_string())
+            }
+            Terminator::Return(value) => {
+                let value = match value {
+                    Some(value) => self.value(value, self.return_class)?,
+                    None => None,
+                };
+                self.return_value(value);
+            }
+        }
+        Ok(())
+    }
+
+    fn no_arm_matched(&mut self, span: Span) {
+        self.trap(
+            Trap {
+                message: "no arm of the `when` matched",
+                label: Label::Fixed("matched here".to_string()),
+                span,
+            },
+            None,
+        );
+    }
+
+    /// Evaluates an expression whose value is of a class, making one up if it does not finish
+    fn value(&mut self, expr: &Expr, class: Class) -> Translate<Option<IrValue>> {
+        match self.expr(expr)? {
+            Some(value) => Ok(Some(value)),
+            None => Ok(self.zero(class)),
+        }
+    }
+
+    /// Evaluates an expression, giving its value unless it is of type `()` or does not finish
+    fn expr(&mut self, expr: &Expr) -> Translate<Option<IrValue>> {
+        let ty = self.ty(expr.id);
+        let class = class_of(&ty)?;
+        let span = expr.span;
+        let value = match &expr.kind {
+            ExprKind::Literal(LiteralKind::Str(_)) => {
+                return Err("strings are not supported by the JIT".to_string())
+            }
+            ExprKind::Literal(literal) => Some(self.literal(literal, class)),
+            ExprKind::Name(_) => match self.body.names.get(&expr.id) {
+                Some(local) => self.read(self.locals[local.0 as usize]),
+                None => return Err("functions used as values are not supported by the JIT".into()),
+            },
+            ExprKind::Tuple(elements) if elements.is_empty() => None,
+            ExprKind::Path(_)
+            | ExprKind::StructLiteral { .. }
+            | ExprKind::Tuple(_)
+            | ExprKind::Field { .. } => {
+                return Err("structs, enums and tuples are not supported by the JIT".to_string())
+            }
+            ExprKind::Array(_) | ExprKind::Repeat { .. } => {
+                return Err("arrays are not supported by the JIT".to_string())
+            }
+            ExprKind::Unary { operator, operand } => {
+                let operand_class = self.class(operand.id)?;
+                let Some(value) = self.value(operand, operand_class)? else {
+                    return Ok(None);
+                };
+                match operator {
+                    UnaryOperator::Negate => {
+                        let overflow = self.translator.overflow;
+                        Some(self.negate(class, overflow, value, &ty, span))
+                    }
+                    UnaryOperator::Not => Some(match class {
+                        Class::Bool => self.builder.ins().bxor_imm(value, 1),
+                        _ => self.builder.ins().bnot(value),
+                    }),
+                    UnaryOperator::Deref => self
+                        .ir_type(class)
+                        .map(|ty| self.builder.ins().load(ty, MemFlags::trusted(), value, 0)),
+                }
+            }
+            ExprKind::Reference { operand, .. } => Some(self.address_of(operand)?),
+            ExprKind::Binary {
+                operator: operator @ (BinaryOperator::And | BinaryOperator::Or),
+                left,
+                right,
+            } => {
+                let left = self.value(left, Class::Bool)?.expect("a `Bool`");
+                let evaluate = self.builder.create_block();
+                let merge = self.builder.create_block();
+                let result = self.builder.append_block_param(merge, types::I8);
+                match operator {
+                    BinaryOperator::And => {
+                        let zero = self.builder.ins().iconst(types::I8, 0);
+                        self.builder.ins().brif(left, evaluate, &[], merge, &[zero]);
+                    }
+                    _ => {
+                        let one = self.builder.ins().iconst(types::I8, 1);
+                        self.builder.ins().brif(left, merge, &[one], evaluate, &[]);
+                    }
+                }
+                self.builder.switch_to_block(evaluate);
+                let right = self.value(right, Class::Bool)?.expect("a `Bool`");
+                self.builder.ins().jump(merge, &[right]);
+                self.builder.switch_to_block(merge);
+                Some(result)
+            }
+            ExprKind::Binary {
+                operator,
+                left,
+                right,
+            } => {
+                let left_ty = self.ty(left.id);
+                let left_class = class_of(&left_ty)?;
+                let right_class = self.class(right.id)?;
+                let left_value = self.value(left, left_class)?;
+                let right_value = self.value(right, right_class)?;
+                self.binary(
+                    *operator,
+                    &left_ty,
+                    left_value,
+                    right_value,
+                    right_class,
+                    span,
+                )?
+            }
+            ExprKind::Assign {
+                operator,
+                target,
+                value,
+            } => {
+                self.assign(*operator, target, value, span)?;
+                None
+            }
+            ExprKind::Call { callee, arguments } => {
+                match self.translator.types.intrinsic_of(expr.id) {
+                    Some(intrinsic) => self.intrinsic(intrinsic, callee, arguments, span)?,
+                    None => self.call(callee, arguments, span)?,
+                }
+            }
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => {
+                let value = self.block(block)?;
+                match class {
+                    Class::Unit => None,
+                    _ => value,
+                }
+            }
+            ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let condition = self.value(condition, Class::Bool)?.expect("a `Bool`");
+                let then_block = self.builder.create_block();
+                let else_block = self.builder.create_block();
+                let merge = self.builder.create_block();
+                let result = self
+                    .ir_type(class)
+                    .map(|ty| self.builder.append_block_param(merge, ty));
+                self.builder
+                    .ins()
+                    .brif(condition, then_block, &[], else_block, &[]);
+                self.builder.switch_to_block(then_block);
+                let value = self.block(then_branch)?;
+                self.merge(merge, class, value);
+                self.builder.switch_to_block(else_block);
+                let value = match else_branch {
+                    Some(else_branch) => self.expr(else_branch)?,
+                    None => None,
+                };
+                self.merge(merge, class, value);
+                self.builder.switch_to_block(merge);
+                result
+            }
+            ExprKind::When { scrutinee, arms } => {
+                let scrutinee_class = self.class(scrutinee.id)?;
+                let value = self.value(scrutinee, scrutinee_class)?;
+                let merge = self.builder.create_block();
+                let result = self
+                    .ir_type(class)
+                    .map(|ty| self.builder.append_block_param(merge, ty));
+                for arm in arms {
+                    let next = self.builder.create_block();
+                    self.test(&arm.pattern, value, scrutinee_class, next)?;
+                    self.bind(&arm.pattern, value);
+                    if let Some(guard) = &arm.guard {
+                        let guard = self.value(guard, Class::Bool)?.expect("a `Bool`");
+                        let body = self.builder.create_block();
+                        self.builder.ins().brif(guard, body, &[], next, &[]);
+                        self.builder.switch_to_block(body);
+                    }
+                    let value = self.expr(&arm.body)?;
+                    self.merge(merge, class, value);
+                    self.builder.switch_to_block(next);
+                }
+                self.no_arm_matched(scrutinee.span);
+                self.builder.ins().jump(self.bail, &[]);
+                self.builder.switch_to_block(merge);
+                result
+            }
+            ExprKind::For { .. } => {
+                return Err("`for` loops are not supported by the JIT".to_string())
+            }
+            ExprKind::Return(value) => {
+                let value = match value {
+                    Some(value) => self.value(value, self.return_class)?,
+                    None => None,
+                };
+                self.return_value(value);
+                self.diverge();
+                None
+            }
+            ExprKind::Yield(_) => return Err("generators are not supported by the JIT".to_string()),
+            ExprKind::Closure(_) => return Err("closures are not supported by the JIT".to_string()),
+            ExprKind::Error => unreachable!("programs with errors are never compiled"),
+        };
+        Ok(match ty {
+            Ty::Never => None,
+            _ => value,
+        })
+    }
+
+    /// Jumps to the block after a branch, with the value of the branch if it has one
+    fn merge(&mut self, merge: IrBlock, class: Class, value: Option<IrValue>) {
+        let value = match self.ir_type(class) {
+            Some(_) => value.or_else(|| self.zero(class)),
+            None => None,
+        };
+        self.builder
+            .ins()
+            .jump(merge, &value.into_iter().collect::<Vec<_>>());
+    }
+
+    fn block(&mut self, block: &Block) -> Translate<Option<IrValue>> {
+        for statement in &block.statements {
+            match &statement.kind {
+                StatementKind::Let(let_statement) => {
+                    if let Some(value) = &let_statement.value {
+                        let local = self.body.declarations[&statement.id];
+                        let class = self.class(value.id)?;
+                        if let Some(value) = self.value(value, class)? {
+                            self.write(self.locals[local.0 as usize], value);
+                        }
+                    }
+                }
+                StatementKind::Expr(expr) => {
+                    self.expr(expr)?;
+                }
+                StatementKind::Error => {}
+            }
+        }
+        match &block.tail {
+            Some(tail) => self.expr(tail),
+            None => Ok(None),
+        }
+    }
+
+    fn literal(&mut self, literal: &LiteralKind, class: Class) -> IrValue {
+        let integer = match *literal {
+            LiteralKind::UInt8(x) => x as i64,
+            LiteralKind::Int8(x) => x as i64,
+            LiteralKind::UInt32(x) => x as i64,
+            LiteralKind::Int32(x) => x as i64,
+            LiteralKind::UInt64(x) => x as i64,
+            LiteralKind::Int64(x) => x,
+            LiteralKind::Float32(x) => return self.float(x as f64, class),
+            LiteralKind::Float64(x) => return self.float(x, class),
+            LiteralKind::Char(x) => x as i64,
+            LiteralKind::Boolean(x) => x as i64,
+            LiteralKind::Str(_) => unreachable!("strings are never translated"),
+        };
+        match self.ir_type(class) {
+            Some(ty) if ty.is_int() => self.builder.ins().iconst(ty, integer),
+            _ => self.float(integer as f64, class),
+        }
+    }
+
+    fn float(&mut self, value: f64, class: Class) -> IrValue {
+        match class {
+            Class::Float32 => self.builder.ins().f32const(value as f32),
+            _ => self.builder.ins().f64const(value),
+        }
+    }
+
+    fn overflow(message: &'static str, ty: &Ty, span: Span) -> Trap {
+        Trap {
+            message,
+            label: Label::Fixed(format!("the result does not fit in `{}`", type_name(ty))),
+            span,
+        }
+    }
+
+    fn negate(
+        &mut self,
+        class: Class,
+        overflow: Overflow,
+        value: IrValue,
+        ty: &Ty,
+        span: Span,
+    ) -> IrValue {
+        let trap = Self::overflow("attempt to negate with overflow", ty, span);
+        match class {
+            Class::Float32 | Class::Float64 => self.builder.ins().fneg(value),
+            Class::Int { bits, signed } => {
+                // Only the smallest signed value overflows, while every unsigned value but zero
+                // does
+                let overflows = match signed {
+                    true => {
+                        let min = -1i64 << (bits - 1);
+                        self.builder.ins().icmp_imm(IntCC::Equal, value, min)
+                    }
+                    false => self.builder.ins().icmp_imm(IntCC::NotEqual, value, 0),
+                };
+                match overflow {
+                    Overflow::Trap => self.trap_if(overflows, trap, None),
+                    Overflow::Wrap => {}
+                    Overflow::Saturate => {
+                        let negated = self.builder.ins().ineg(value);
+                        let (_, max) = limits(bits, signed);
+                        let ty = self.builder.func.dfg.value_type(value);
+                        let limit = self.builder.ins().iconst(ty, if signed { max } else { 0 });
+                        return self.builder.ins().select(overflows, limit, negated);
+                    }
+                }
+                self.builder.ins().ineg(value)
+            }
+            _ => unreachable!("only numbers are negated"),
+        }
+    }
+
+    fn compare(
+        &mut self,
+        operator: BinaryOperator,
+        class: Class,
+        left: IrValue,
+        right: IrValue,
+    ) -> IrValue {
+        use BinaryOperator::*;
+        if let Class::Float32 | Class::Float64 = class {
+            let condition = match operator {
+                Greater => FloatCC::GreaterThan,
+                Lesser => FloatCC::LessThan,
+                GreaterOrEqual => FloatCC::GreaterThanOrEqual,
+                LessOrEqual => FloatCC::LessThanOrEqual,
+                EqualTo => FloatCC::Equal,
+                _ => FloatCC::NotEqual,
+            };
+            return self.builder.ins().fcmp(condition, left, right);
+        }
+        let signed = matches!(class, Class::Int { signed: true, .. });
+        let condition = match (operator, signed) {
+            (Greater, true) => IntCC::SignedGreaterThan,
+            (Greater, false) => IntCC::UnsignedGreaterThan,
+            (Lesser, true) => IntCC::SignedLessThan,
+            (Lesser, false) => IntCC::UnsignedLessThan,
+            (GreaterOrEqual, true) => IntCC::SignedGreaterThanOrEqual,
+            (GreaterOrEqual, false) => IntCC::UnsignedGreaterThanOrEqual,
+            (LessOrEqual, true) => IntCC::SignedLessThanOrEqual,
+            (LessOrEqual, false) => IntCC::UnsignedLessThanOrEqual,
+            (EqualTo, _) => IntCC::Equal,
+            _ => IntCC::NotEqual,
+        };
+        self.builder.ins().icmp(condition, left, right)
+    }
+
+    /// Applies a binary operator other than `&&` and `|`
+    fn binary(
+        &mut self,
+        operator: BinaryOperator,
+        ty: &Ty,
+        left: Option<IrValue>,
+        right: Option<IrValue>,
+        right_class: Class,
+        span: Span,
+    ) -> Translate<Option<IrValue>> {
+        use BinaryOperator::*;
+        let class = class_of(ty)?;
+        let (Some(left), Some(right)) = (left, right) else {
+            // Values of type `()` are all equal
+            let equal = matches!(operator, GreaterOrEqual | LessOrEqual | EqualTo);
+            return Ok(match class {
+                Class::Unit => Some(self.builder.ins().iconst(types::I8, equal as i64)),
+                _ => None,
+            });
+        };
+        Ok(Some(match operator {
+            Greater | Lesser | GreaterOrEqual | LessOrEqual | EqualTo | NotEqual => {
+                self.compare(operator, class, left, right)
+            }
+            ShiftLeft | ShiftRight => self.shift(operator, class, left, right, right_class, span),
+            BitwiseAnd => self.builder.ins().band(left, right),
+            _ => match class {
+                Class::Float32 | Class::Float64 => match operator {
+                    Add => self.builder.ins().fadd(left, right),
+                    Subtract => self.builder.ins().fsub(left, right),
+                    Multiply => self.builder.ins().fmul(left, right),
+                    _ => self.builder.ins().fdiv(left, right),
+                },
+                Class::Int { bits, signed } => {
+                    let overflow = self.translator.overflow;
+                    self.arithmetic(operator, overflow, bits, signed, left, right, ty, span)
+                }
+                _ => unreachable!("`{}` is only applied to numbers", operator),
+            },
+        }))
+    }
+
+    /// Applies `+`, `-`, `*` or `/` to two integers, overflowing the way `overflow` says to
+    #[allow(clippy::too_many_arguments)]
+    fn arithmetic(
+        &mut self,
+        operator: BinaryOperator,
+        overflow: Overflow,
+        bits: u8,
+        signed: bool,
+        left: IrValue,
+        right: IrValue,
+        ty: &Ty,
+        span: Span,
+    ) -> IrValue {
+        let ins = self.builder.ins();
+        let ((result, overflows), message) = match (operator, signed) {
+            (BinaryOperator::Add, true) => (ins.sadd_overflow(left, right), "add"),
+            (BinaryOperator::Add, false) => (ins.uadd_overflow(left, right), "add"),
+            (BinaryOperator::Subtract, true) => (ins.ssub_overflow(left, right), "subtract"),
+            (BinaryOperator::Subtract, false) => (ins.usub_overflow(left, right), "subtract"),
+            (BinaryOperator::Multiply, true) => (ins.smul_overflow(left, right), "multiply"),
+            (BinaryOperator::Multiply, false) => (ins.umul_overflow(left, right), "multiply"),
+            _ => return self.divide(overflow, bits, signed, left, right, ty, span),
+        };
+        let message = match message {
+            "add" => "attempt to add with overflow",
+            "subtract" => "attempt to subtract with overflow",
+            _ => "attempt to multiply with overflow",
+        };
+        match overflow {
+            Overflow::Trap => self.trap_if(overflows, Self::overflow(message, ty, span), None),
+            Overflow::Wrap => {}
+            Overflow::Saturate => {
+                let limit = self.saturated(operator, bits, signed, left, right);
+                return self.builder.ins().select(overflows, limit, result);
+            }
+        }
+        result
+    }
+
+    /// Gets the value `+`, `-` or `*` saturates to when it overflows, which only depends on the
+    /// signs of its operands
+    fn saturated(
+        &mut self,
+        operator: BinaryOperator,
+        bits: u8,
+        signed: bool,
+        left: IrValue,
+        right: IrValue,
+    ) -> IrValue {
+        let ty = self.builder.func.dfg.value_type(left);
+        let (min, max) = limits(bits, signed);
+        let min = self.builder.ins().iconst(ty, min);
+        let max = self.builder.ins().iconst(ty, max);
+        if !signed {
+            return match operator {
+                BinaryOperator::Subtract => min,
+                _ => max,
+            };
+        }
+        let negative = match operator {
+            BinaryOperator::Multiply => self.builder.ins().bxor(left, right),
+            _ => right,
+        };
+        let negative = self
+            .builder
+            .ins()
+            .icmp_imm(IntCC::SignedLessThan, negative, 0);
+        match operator {
+            BinaryOperator::Subtract => self.builder.ins().select(negative, max, min),
+            _ => self.builder.ins().select(negative, min, max),
+        }
+    }
+
+    #[allow(clippy::too_many_arguments)]
+    fn divide(
+        &mut self,
+        overflow: Overflow,
+        bits: u8,
+        signed: bool,
+        left: IrValue,
+        right: IrValue,
+        ty: &Ty,
+        span: Span,
+    ) -> IrValue {
+        let zero = self.builder.ins().icmp_imm(IntCC::Equal, right, 0);
+        let trap = Trap {
+            message: "attempt to divide by zero",
+            label: Label::Fixed("the divisor is zero".to_string()),
+            span,
+        };
+        self.trap_if(zero, trap, None);
+        if !signed {
+            return self.builder.ins().udiv(left, right);
+        }
+        // Dividing the smallest value by -1 is the only other division which overflows
+        let min = self
+            .builder
+            .ins()
+            .icmp_imm(IntCC::Equal, left, -1i64 << (bits - 1));
+        let minus_one = self.builder.ins().icmp_imm(IntCC::Equal, right, -1);
+        let overflows = self.builder.ins().band(min, minus_one);
+        if overflow == Overflow::Trap {
+            let trap = Self::overflow("attempt to divide with overflow", ty, span);
+            self.trap_if(overflows, trap, None);
+            return self.builder.ins().sdiv(left, right);
+        }
+        // `sdiv` would stop the program, so the smallest value is divided by one instead, which
+        // is what it wraps around to
+        let ir_type = self.builder.func.dfg.value_type(left);
+        let one = self.builder.ins().iconst(ir_type, 1);
+        let divisor = self.builder.ins().select(overflows, one, right);
+        let quotient = self.builder.ins().sdiv(left, divisor);
+        match overflow {
+            Overflow::Saturate => {
+                let (_, max) = limits(bits, signed);
+                let max = self.builder.ins().iconst(ir_type, max);
+                self.builder.ins().select(overflows, max, quotient)
+            }
+            _ => quotient,
+        }
+    }
+
+    /// Sign or zero extends an integer to a number of bits, or drops the bits which do not fit
+    fn resize(&mut self, value: IrValue, signed: bool, bits: u8) -> IrValue {
+        let from = self.builder.func.dfg.value_type(value);
+        let to = Class::Int { bits, signed }
+            .ir_type(self.translator.pointer)
+            .expect("integers have a type");
+        match (from.bits().cmp(&to.bits()), signed) {
+            (Ordering::Equal, _) => value,
+            (Ordering::Greater, _) => self.builder.ins().ireduce(to, value),
+            (Ordering::Less, true) => self.builder.ins().sextend(to, value),
+            (Ordering::Less, false) => self.builder.ins().uextend(to, value),
+        }
+    }
+
+    /// Converts a number to a numeric type, see [numeric::convert]
+    fn convert(
+        &mut self,
+        value: IrValue,
+        source: PrimitiveType,
+        target: PrimitiveType,
+        overflow: Overflow,
+        span: Span,
+    ) -> Translate<IrValue> {
+        let from = class_of(&Ty::Primitive(source))?;
+        let to = class_of(&Ty::Primitive(target))?;
+        let trap = Trap {
+            message: "attempt to convert with overflow",
+            label: Label::Fixed(format!("the value does not fit in `{}`", target)),
+            span,
+        };
+        let (min, max) = match target.is_integer() {
+            true => target.bounds(),
+            false => (0, 0),
+        };
+        Ok(match (from, to) {
+            // Reinterpreting the bits is all a conversion between signed and unsigned types of
+            // the same width does
+            (
+                Class::Int {
+                    signed: from_signed,
+                    ..
+                },
+                Class::Int { bits, .. },
+            ) => {
+                if overflow == Overflow::Trap && !numeric::is_lossless(source, target) {
+                    let wide = self.resize(value, from_signed, 64);
+                    let ins = self.builder.ins();
+                    let overflows = match (from_signed, target) {
+                        (true, PrimitiveType::UInt64) => {
+                            ins.icmp_imm(IntCC::SignedLessThan, wide, 0)
+                        }
+                        (true, _) => {
+                            let below = ins.icmp_imm(IntCC::SignedLessThan, wide, min as i64);
+                            let above = self.builder.ins().icmp_imm(
+                                IntCC::SignedGreaterThan,
+                                wide,
+                                max as i64,
+                            );
+                            self.builder.ins().bor(below, above)
+                        }
+                        (false, _) => ins.icmp_imm(IntCC::UnsignedGreaterThan, wide, max as i64),
+                    };
+                    self.trap_if(overflows, trap, None);
+                }
+                self.resize(value, from_signed, bits)
+            }
+            (Class::Int { signed, .. }, _) => {
+                let ty = self.ir_type(to).expect("floats have a type");
+                let wide = self.resize(value, signed, 64);
+                match signed {
+                    true => self.builder.ins().fcvt_from_sint(ty, wide),
+                    false => self.builder.ins().fcvt_from_uint(ty, wide),
+                }
+            }
+            (Class::Float64, Class::Float32) => self.builder.ins().fdemote(types::F32, value),
+            (Class::Float32, Class::Float64) => self.builder.ins().fpromote(types::F64, value),
+            (_, Class::Float32 | Class::Float64) => value,
+            (_, Class::Int { bits, signed }) => {
+                if overflow == Overflow::Trap {
+                    // The bounds are powers of two, which floats hold exactly. `NaN` is unordered
+                    let truncated = self.builder.ins().trunc(value);
+                    let lower = self.float(min as f64, from);
+                    let upper = self.float((max + 1) as f64, from);
+                    let below =
+                        self.builder
+                            .ins()
+                            .fcmp(FloatCC::UnorderedOrLessThan, truncated, lower);
+                    let above =
+                        self.builder
+                            .ins()
+                            .fcmp(FloatCC::GreaterThanOrEqual, truncated, upper);
+                    let overflows = self.builder.ins().bor(below, above);
+                    self.trap_if(overflows, trap, None);
+                }
+                // Saturating conversions give zero for `NaN`, as a release build does
+                let wide = match bits {
+                    64 => types::I64,
+                    _ => types::I32,
+                };
+                let ins = self.builder.ins();
+                let converted = match signed {
+                    true => ins.fcvt_to_sint_sat(wide, value),
+                    false => ins.fcvt_to_uint_sat(wide, value),
+                };
+                if bits != 8 {
+                    return Ok(converted);
+                }
+                let max = self.builder.ins().iconst(types::I32, max as i64);
+                let clamped = match signed {
+                    true => {
+                        let min = self.builder.ins().iconst(types::I32, min as i64);
+                        let clamped = self.builder.ins().smin(converted, max);
+                        self.builder.ins().smax(clamped, min)
+                    }
+                    false => self.builder.ins().umin(converted, max),
+                };
+                self.builder.ins().ireduce(types::I8, clamped)
+            }
+            _ => unreachable!("only numbers are converted"),
+        })
+    }
+
+    /// Calls an [Intrinsic] of a number
+    fn intrinsic(
+        &mut self,
+        intrinsic: Intrinsic,
+        callee: &Expr,
+        arguments: &[Expr],
+        span: Span,
+    ) -> Translate<Option<IrValue>> {
+        let ExprKind::Field { object, .. } = &callee.kind else {
+            unreachable!("intrinsics are called as methods");
+        };
+        let ty = self.ty(object.id);
+        let class = class_of(&ty)?;
+        let Some(value) = self.value(object, class)? else {
+            return Ok(None);
+        };
+        let Ty::Primitive(source) = ty else {
+            unreachable!("intrinsics are methods of numbers");
+        };
+        Ok(Some(match intrinsic {
+            Intrinsic::Arith(operator, overflow) => {
+                let Some(right) = self.value(&arguments[0], class)? else {
+                    return Ok(None);
+                };
+                let Class::Int { bits, signed } = class else {
+                    unreachable!("only integers have arithmetic methods");
+                };
+                self.arithmetic(operator, overflow, bits, signed, value, right, &ty, span)
+            }
+            Intrinsic::Convert(target) => {
+                let overflow = self.translator.overflow;
+                self.convert(value, source, target, overflow, span)?
+            }
+        }))
+    }
+
+    /// Shifts an integer by an amount of any integer type, which must be less than its width
+    fn shift(
+        &mut self,
+        operator: BinaryOperator,
+        class: Class,
+        value: IrValue,
+        amount: IrValue,
+        amount_class: Class,
+        span: Span,
+    ) -> IrValue {
+        let Class::Int { bits, signed } = class else {
+            unreachable!("only integers are shifted");
+        };
+        let unsigned = !matches!(amount_class, Class::Int { signed: true, .. });
+        let amount = match (amount_class, unsigned) {
+            (Class::Int { bits: 64, .. }, _) => amount,
+            (_, true) => self.builder.ins().uextend(types::I64, amount),
+            (_, false) => self.builder.ins().sextend(types::I64, amount),
+        };
+        // Negative amounts are too large once they are taken as unsigned
+        let too_large =
+            self.builder
+                .ins()
+                .icmp_imm(IntCC::UnsignedGreaterThanOrEqual, amount, bits as i64);
+        let direction = match operator {
+            BinaryOperator::ShiftLeft => "left",
+            _ => "right",
+        };
+        let trap = Trap {
+            message: match operator {
+                BinaryOperator::ShiftLeft => "attempt to shift left with overflow",
+                _ => "attempt to shift right with overflow",
+            },
+            label: Label::Shift {
+                direction,
+                unsigned,
+            },
+            span,
+        };
+        self.trap_if(too_large, trap, Some(amount));
+        match (operator, signed) {
+            (BinaryOperator::ShiftLeft, _) => self.builder.ins().ishl(value, amount),
+            (_, true) => self.builder.ins().sshr(value, amount),
+            (_, false) => self.builder.ins().ushr(value, amount),
+        }
+    }
+
+    fn assign(
+        &mut self,
+        operator: Option<BinaryOperator>,
+        target: &Expr,
+        value: &Expr,
+        span: Span,
+    ) -> Translate<()> {
+        let target_ty = self.ty(target.id);
+        let class = class_of(&target_ty)?;
+        let value_class = self.class(value.id)?;
+        let value = self.value(value, value_class)?;
+        // The target is read after the value is evaluated, as by the interpreter
+        let place = match &target.kind {
+            ExprKind::Name(_) => match self.body.names.get(&target.id) {
+                Some(local) => self.locals[local.0 as usize],
+                None => unreachable!("assignments are checked to have a place as their target"),
+            },
+            ExprKind::Unary {
+                operator: UnaryOperator::Deref,
+                operand,
+            } => {
+                let address = self.value(operand, Class::Pointer)?.expect("a pointer");
+                match self.ir_type(class) {
+                    Some(ty) => Place::Address(address, ty),
+                    None => Place::Unit,
+                }
+            }
+            _ => return Err("structs, enums and tuples are not supported by the JIT".into()),
+        };
+        let value = match operator {
+            Some(operator) => {
+                let current = self.read(place);
+                self.binary(operator, &target_ty, current, value, value_class, span)?
+            }
+            None => value,
+        };
+        if let Some(value) = value {
+            self.write(place, value);
+        }
+        Ok(())
+    }
+
+    /// Gets the address of the value an expression names, or of a copy of a temporary value
+    fn address_of(&mut self, expr: &Expr) -> Translate<IrValue> {
+        let pointer = self.translator.pointer;
+        match &expr.kind {
+            ExprKind::Name(_) => {
+                if let Some(Place::Slot(slot, _)) = self
+                    .body
+                    .names
+                    .get(&expr.id)
+                    .map(|x| self.locals[x.0 as usize])
+                {
+                    return Ok(self.builder.ins().stack_addr(pointer, slot, 0));
+                }
+            }
+            ExprKind::Unary {
+                operator: UnaryOperator::Deref,
+                operand,
+            } => {
+                let address = self.value(operand, Class::Pointer)?;
+                return Ok(address.expect("a pointer"));
+            }
+            _ => {}
+        }
+        let class = self.class(expr.id)?;
+        let value = self.value(expr, class)?;
+        let slot = self.new_slot();
+        if let Some(value) = value {
+            self.builder.ins().stack_store(value, slot, 0);
+        }
+        Ok(self.builder.ins().stack_addr(pointer, slot, 0))
+    }
+
+    /// Calls a function or a method, giving its result
+    fn call(
+        &mut self,
+        callee: &Expr,
+        arguments: &[Expr],
+        span: Span,
+    ) -> Translate<Option<IrValue>> {
+        let defs = self.translator.defs;
+        let mut values = Vec::new();
+        let function = match &callee.kind {
+            ExprKind::Field { object, field } => {
+                let mut receiver_ty = self.ty(object.id);
+                let mut depth = 0;
+                while let Ty::Reference { pointee, .. } = receiver_ty {
+                    receiver_ty = *pointee;
+                    depth += 1;
+                }
+                let Some(function) =
+                    find_method(&self.translator.impls, &receiver_ty, field.symbol, None).copied()
+                else {
+                    let what = defs.unsupported_call(receiver_ty.to_type().as_ref(), field.symbol);
+                    return Err(format!("{} are not supported by the JIT", what));
+                };
+                let wanted = match self.translator.functions[function].parameters.first() {
+                    Some(Ty::Reference { .. }) => 1,
+                    _ => 0,
+                };
+                // Methods taking `self` by reference are given a reference to the receiver, and
+                // methods taking it by value are given the value behind any references
+                let mut receiver = match depth < wanted {
+                    true => Some(self.address_of(object)?),
+                    false => {
+                        let class = self.class(object.id)?;
+                        self.value(object, class)?
+                    }
+                };
+                let mut pointee = self.ty(object.id);
+                for _ in wanted..depth {
+                    let Ty::Reference { pointee: inner, .. } = pointee else {
+                        unreachable!("the receiver is a reference");
+                    };
+                    pointee = *inner;
+                    let ty = self.ir_type(class_of(&pointee)?);
+                    receiver = match (receiver, ty) {
+                        (Some(address), Some(ty)) => {
+                            Some(self.builder.ins().load(ty, MemFlags::trusted(), address, 0))
+                        }
+                        _ => None,
+                    };
+                }
+                values.extend(receiver);
+                function
+            }
+            ExprKind::Path(path) if defs.traits.lookup(path.segments[0].symbol).is_some() => {
+                let trait_id = defs.traits.lookup(path.segments[0].symbol);
+                let mut self_ty = arguments.first().map(|x| self.ty(x.id));
+                while let Some(Ty::Reference { pointee, .. }) = self_ty {
+                    self_ty = Some(*pointee);
+                }
+                let function = self_ty.and_then(|x| {
+                    find_method(&self.translator.impls, &x, path.name().symbol, trait_id).copied()
+                });
+                let Some(function) = function else {
+                    return Err("calls to generic methods are not supported by the JIT".into());
+                };
+                function
+            }
+            ExprKind::Name(name) => match self.translator.names.get(&name.symbol) {
+                Some(function) => *function,
+                None => {
+                    let what = defs.unsupported_call(None, name.symbol);
+                    return Err(format!("{} are not supported by the JIT", what));
+                }
+            },
+            _ => return Err("structs, enums and tuples are not supported by the JIT".into()),
+        };
+        for argument in arguments {
+            let class = self.class(argument.id)?;
+            values.extend(self.value(argument, class)?);
+        }
+
+        let function = self.translator.functions[function].clone();
+        let return_class = class_of(&function.return_type)?;
+        let id = match function.status {
+            Status::Host(id) => {
+                let func_ref = self.func_ref(id);
+                let call = self.builder.ins().call(func_ref, &values);
+                return Ok(self.builder.inst_results(call).first().copied());
+            }
+            Status::Compiled(id) => Some(id),
+            Status::Interpreted(_) => None,
+            Status::Unavailable => {
+                return Err(format!(
+                    "`{}` can not be called from compiled code",
+                    function.name
+                ))
+            }
+        };
+
+        let depth = self.builder.ins().load(
+            types::I32,
+            MemFlags::trusted(),
+            self.context,
+            Context::DEPTH_OFFSET,
+        );
+        let too_deep = self.builder.ins().icmp_imm(
+            IntCC::UnsignedGreaterThanOrEqual,
+            depth,
+            MAX_CALL_DEPTH as i64,
+        );
+        let trap = Trap {
+            message: "stack overflow",
+            label: Label::Fixed(format!(
+                "calls are nested more than {} deep",
+                MAX_CALL_DEPTH
+            )),
+            span,
+        };
+        self.trap_if(too_deep, trap, None);
+        let deeper = self.builder.ins().iadd_imm(depth, 1);
+        self.builder.ins().store(
+            MemFlags::trusted(),
+            deeper,
+            self.context,
+            Context::DEPTH_OFFSET,
+        );
+        let result = match (function.status, id) {
+            (_, Some(id)) => {
+                let func_ref = self.func_ref(id);
+                let mut arguments = vec![self.context];
+                arguments.extend(values);
+                let call = self.builder.ins().call(func_ref, &arguments);
+                self.builder.inst_results(call).first().copied()
+            }
+            (Status::Interpreted(fallback), _) => self.interpret(fallback, &values, return_class),
+            _ => unreachable!("only compiled and interpreted functions are left"),
+        };
+        self.builder.ins().store(
+            MemFlags::trusted(),
+            depth,
+            self.context,
+            Context::DEPTH_OFFSET,
+        );
+        self.check_failed();
+        Ok(result)
+    }
+
+    /// Calls a function run by the interpreter, passing its arguments and result in stack slots
+    fn interpret(
+        &mut self,
+        fallback: u32,
+        arguments: &[IrValue],
+        return_class: Class,
+    ) -> Option<IrValue> {
+        let pointer = self.translator.pointer;
+        let size = 8 * arguments.len().max(1) as u32;
+        let slot = self.builder.create_sized_stack_slot(StackSlotData::new(
+            StackSlotKind::ExplicitSlot,
+            size,
+            3,
+        ));
+        for (index, argument) in arguments.iter().enumerate() {
+            self.builder
+                .ins()
+                .stack_store(*argument, slot, 8 * index as i32);
+        }
+        let result = self.new_slot();
+        let fallback = self.builder.ins().iconst(types::I32, fallback as i64);
+        let arguments = self.builder.ins().stack_addr(pointer, slot, 0);
+        let result_address = self.builder.ins().stack_addr(pointer, result, 0);
+        let callback = self.func_ref(self.translator.interpret);
+        self.builder.ins().call(
+            callback,
+            &[self.context, fallback, arguments, result_address],
+        );
+        let ty = self.ir_type(return_class)?;
+        Some(self.builder.ins().stack_load(ty, result, 0))
+    }
+
+    /// Branches to `next` unless a value matches a pattern
+    fn test(
+        &mut self,
+        pattern: &Pattern,
+        value: Option<IrValue>,
+        class: Class,
+        next: IrBlock,
+    ) -> Translate<()> {
+        let check = |this: &mut Self, operator, literal: &LiteralKind| {
+            if let Some(value) = value {
+                let expected = this.literal(literal, class);
+                let matches = this.compare(operator, class, value, expected);
+                let body = this.builder.create_block();
+                this.builder.ins().brif(matches, body, &[], next, &[]);
+                this.builder.switch_to_block(body);
+            }
+        };
+        match &pattern.kind {
+            PatternKind::Wildcard | PatternKind::Binding { .. } => {}
+            PatternKind::Literal(LiteralKind::Str(_)) => {
+                return Err("strings are not supported by the JIT".to_string())
+            }
+            PatternKind::Literal(literal) => check(self, BinaryOperator::EqualTo, literal),
+            PatternKind::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                check(self, BinaryOperator::GreaterOrEqual, start);
+                match inclusive {
+                    true => check(self, BinaryOperator::LessOrEqual, end),
+                    false => check(self, BinaryOperator::Lesser, end),
+                }
+            }
+            PatternKind::Tuple(_) | PatternKind::Variant { .. } => {
+                return Err("structs, enums and tuples are not supported by the JIT".to_string())
+            }
+        }
+        Ok(())
+    }
+
+    /// Stores a value in the local a pattern binds, once it is known to match
+    fn bind(&mut self, pattern: &Pattern, value: Option<IrValue>) {
+        if let PatternKind::Binding { .. } = pattern.kind {
+            let local = self.body.declarations[&pattern.id];
+            if let Some(value) = value {
+                self.write(self.locals[local.0 as usize], value);
+            }
+        }
+    }
+}
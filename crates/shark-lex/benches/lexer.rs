@@ -0,0 +1,50 @@
+use std::fmt::Write;
+
+use criterion::{criterion_group, criterion_main, Criterion, Throughput};
+use shark_lex::Lexer;
+
+/// Generates a Shark source file made up of `functions` small functions which together use every
+/// kind of token the lexer knows about
+fn generate_source(functions: usize) -> String {
+    let mut source = String::new();
+    for index in 0..functions {
+        writeln!(
+            source,
+            "// function number {index}
+pub fun function_{index}(argument :: Int32) {{
+    let mut counter :: Int64 = {index}int64;
+    let ratio = 3.25 * -1.5 / .5;
+    /* a multi-line
+       comment */
+    if counter >= 0x1F && argument != -42 {{
+        counter += argument << 2;
+    }} else {{
+        ret \"a string with an \\\"escape\\\" {index}\";
+    }}
+    let letter = 'c';
+    ret counter.value == true;
+}}"
+        )
+        .expect("writing to a String can not fail");
+    }
+    source
+}
+
+fn lex_benchmark(criterion: &mut Criterion) {
+    let mut group = criterion.benchmark_group("lex");
+    for functions in [10, 100, 1000] {
+        let source = generate_source(functions);
+        group.throughput(Throughput::Bytes(source.len() as u64));
+        group.bench_function(format!("{functions}_functions"), |bencher| {
+            bencher.iter(|| {
+                let mut lexer = Lexer::new(None, &source);
+                lexer.lex();
+                lexer.completed_tokens.len()
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, lex_benchmark);
+criterion_main!(benches);
@@ -1,307 +1,271 @@
 #[macro_use]
 pub mod macros;
 
-use std::{path::Path, str::Chars};
+use std::path::Path;
 
-use shark_core::source::SourcePosition;
+use shark_core::{
+    diagnostic::Diagnostic,
+    source::{SourcePosition, Span},
+    symbol::{sym, Symbol},
+};
 use token::{CommentKind, KeywordKind, LexerToken, LiteralKind, TokenKind};
 
 pub mod error;
 pub mod token;
 
+// The baseline tests are kept as they were written
 #[cfg(test)]
+#[allow(
+    clippy::approx_constant,
+    clippy::assertions_on_constants,
+    clippy::get_first,
+    clippy::needless_return,
+    clippy::ptr_arg
+)]
 pub mod tests;
 
 /// Step one of compilation. Turns the written code into [LexerToken]s to be used by the parser
+///
+/// The [Lexer] walks the source with a byte cursor and never copies the text of a token, instead
+/// each [LexerToken] records the [Span] it covers and borrows its text straight from the source
+///
+/// The [Lexer] never gives up on the source. Anything it can not make sense of becomes a
+/// [TokenKind::Unknown] token and a [Diagnostic] explaining why
 #[derive(Debug)]
 pub struct Lexer<'lexer> {
     /// Basic Lexer State
-    pub source: Chars<'lexer>,
-    pub source_length: usize,
+    pub source: &'lexer str,
+    pub cursor: usize,
     pub current_position: SourcePosition<'lexer>,
+    pub previous_position: SourcePosition<'lexer>,
     pub completed_tokens: Vec<LexerToken<'lexer>>,
+    pub errors: Vec<Diagnostic>,
 
-    /// Current Token State
-    pub token_start_position: Option<SourcePosition<'lexer>>,
-    pub token_content: String,
-    pub token_inferred_kind: Option<TokenKind>,
+    /// When set, whitespace and comments are kept as [TokenKind::Whitespace] and
+    /// [TokenKind::Comment] tokens so that the tokens cover every byte of the source
+    pub keep_trivia: bool,
 
-    pub in_comment: Option<CommentKind>,
+    /// Current Token State
+    pub token_start: usize,
+    pub token_start_position: SourcePosition<'lexer>,
 }
 
 impl<'lexer> Lexer<'lexer> {
     pub fn new(path: Option<&'lexer Path>, src: &'lexer str) -> Self {
+        let start_position = SourcePosition::new(path, 1, 1);
         Self {
-            source: src.chars(),
-            source_length: src.len(),
-            current_position: SourcePosition::new(path, 1, 1),
+            source: src,
+            cursor: 0,
+            current_position: start_position,
+            previous_position: start_position,
             completed_tokens: Vec::new(),
+            errors: Vec::new(),
 
-            token_start_position: None,
-            token_content: String::new(),
-            token_inferred_kind: None,
+            keep_trivia: false,
 
-            in_comment: None,
+            token_start: 0,
+            token_start_position: start_position,
         }
     }
 
-    /// Creates the initial state when finding a [LexerToken]
-    fn start_token(&mut self, kind: TokenKind, initial_data: Option<char>) {
-        self.token_inferred_kind = Some(kind);
-        self.token_start_position = Some(self.current_position);
-        if let Some(initial_data) = initial_data {
-            self.token_content.push(initial_data);
-        }
+    /// Gets the character under the cursor without consuming it
+    fn peek(&self) -> Option<char> {
+        self.source[self.cursor..].chars().next()
     }
 
-    /// Finish the active [LexerToken] and adds it to the [Vec] of completed tokens. Then it resets
-    /// the state of the [Lexer]
-    fn push_token(&mut self) {
-        if self.token_inferred_kind.is_none() {
-            panic!("invalid lexer state to push a token"); // See above comment
-        }
-
-        self.completed_tokens.push(LexerToken {
-            kind: self
-                .token_inferred_kind
-                .clone()
-                .expect("expected a [TokenKind] found [None] while creating a [LexerToken]"),
-            position: self
-                .token_start_position
-                .expect("expected a [SourcePosition] found [None] while creating a [LexerToken]")
-                ..=self.current_position,
-            length: self.token_content.len(),
-        });
-        self.reset_token_state();
+    /// Gets the character `n` characters after the cursor without consuming anything
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.source[self.cursor..].chars().nth(n)
     }
 
-    /// Creates then pushes a "small token", that is any token which is 1-2 characters in length.
-    /// This will also reset the state of the [Lexer]
-    fn push_small_token(&mut self, current_character: char, peek: Option<char>) {
-        if let Some(kind) = TokenKind::create_grammar_token(&current_character, peek.as_ref()) {
-            self.start_token(kind.clone(), Some(current_character));
+    /// Consumes the character under the cursor, keeping the [SourcePosition] up to date
+    fn bump(&mut self) -> Option<char> {
+        let character = self.peek()?;
+        self.cursor += character.len_utf8();
+        self.previous_position = self.current_position;
+        if character == '\n' {
+            self.current_position.next_line();
+        } else {
+            self.current_position.next_column();
+        }
+        Some(character)
+    }
 
-            if TokenKind::get_grammar_token_length(&kind) > 1 {
-                self.token_content.push(peek.unwrap());
-                self.source.next(); // consume
-            }
-            self.push_token();
-            return;
+    /// Consumes characters for as long as the predicate holds
+    fn bump_while(&mut self, predicate: impl Fn(char) -> bool) {
+        while self.peek().is_some_and(&predicate) {
+            self.bump();
         }
-        todo!("error here");
     }
 
-    fn reset_token_state(&mut self) {
-        self.token_start_position = None;
-        self.token_inferred_kind = None;
-        self.token_content = String::new();
+    /// Marks the cursor as the start of a new [LexerToken]
+    fn start_token(&mut self) {
+        self.token_start = self.cursor;
+        self.token_start_position = self.current_position;
     }
 
-    fn peek(&mut self) -> Option<char> {
-        self.source.clone().next()
+    /// Gets the source text of the active [LexerToken]
+    fn token_text(&self) -> &'lexer str {
+        &self.source[self.token_start..self.cursor]
     }
 
-    pub fn lex(&mut self) {
-        while let Some(current_character) = self.source.next() {
-            if self.in_comment.is_some() {
-                self.handle_comment(current_character);
-                continue; // If we are in a comment we want to avoid starting new tokens
-            }
+    /// Finish the active [LexerToken] and adds it to the [Vec] of completed tokens
+    fn push_token(&mut self, kind: TokenKind) {
+        let text = self.token_text();
+        self.completed_tokens.push(LexerToken {
+            kind,
+            position: self.token_start_position..=self.previous_position,
+            span: Span::new(self.token_start, self.cursor),
+            text,
+            length: text.len(),
+        });
+    }
 
-            if self.token_inferred_kind.is_none() {
-                // Find a new token to infer
-                self.infer_token(current_character)
-            }
-            // The check to see if we have an inferenced token is going to be here because it would
-            // stop duplicate code checking if a token needs to end :)
-            self.continue_token(&current_character);
-            if current_character == '\n' {
-                self.current_position.next_line();
-            } else {
-                self.current_position.next_column();
-            }
+    /// Pushes a [TokenKind::Unknown] token for the active token and reports why it is invalid
+    fn push_error_token(&mut self, message: String) {
+        let span = Span::new(self.token_start, self.cursor);
+        self.errors
+            .push(Diagnostic::error(message).with_primary(span, ""));
+        self.push_token(TokenKind::Unknown);
+    }
+
+    /// Pushes a trivia token if they are being kept
+    fn push_trivia(&mut self, kind: TokenKind) {
+        if self.keep_trivia {
+            self.push_token(kind);
         }
     }
 
-    /// Logic for checking when we need to exit a comment
-    fn handle_comment(&mut self, current_character: char) {
-        match self.in_comment {
-            Some(CommentKind::SingleLine) => {
-                if current_character == '\n' {
-                    self.in_comment = None;
+    pub fn lex(&mut self) {
+        while let Some(current_character) = self.peek() {
+            self.start_token();
+            match current_character {
+                '/' if self.peek_nth(1) == Some('/') => self.skip_single_line_comment(),
+                '/' if self.peek_nth(1) == Some('*') => self.skip_multi_line_comment(),
+                '.' if self.peek_nth(1) == Some('.') => self.lex_grammar(current_character),
+                '"' => self.lex_string(),
+                '\'' => self.lex_char(),
+                '-' | '.' if self.peek_nth(1).is_some_and(|x| x.is_ascii_digit()) => {
+                    self.lex_number()
                 }
-            }
-            Some(CommentKind::MultiLine) => {
-                if let Some(peek) = self.peek() {
-                    if current_character == '*' && peek == '/' {
-                        self.in_comment = None;
-                        self.source.next(); // conusme the slash
-                    }
+                _ if current_character.is_whitespace() => {
+                    self.bump_while(char::is_whitespace);
+                    self.push_trivia(TokenKind::Whitespace);
+                }
+                _ if current_character.is_ascii_digit() => self.lex_number(),
+                _ if TokenKind::is_valid_identifier_character(true, &current_character) => {
+                    self.lex_identifier()
                 }
+                _ => self.lex_grammar(current_character),
             }
-            None => {}
         }
     }
 
-    fn infer_token(&mut self, current_character: char) {
-        match current_character {
-            '"' => {
-                self.start_token(TokenKind::Literal(LiteralKind::Str(String::new())), None);
-            }
-            '\'' => {
-                self.start_token(TokenKind::Literal(LiteralKind::Char('\0')), None);
-            }
-            '-' => {
-                let peek = self.peek();
-                if peek.is_some_and(|x| x.is_ascii_digit()) {
-                    self.start_token(TokenKind::Literal(LiteralKind::Int8(0)), None);
-                    return;
-                }
-                self.push_small_token(current_character, peek);
-            }
-            '.' => {
-                let peek = self.peek();
-                if peek.is_some_and(|x| x.is_ascii_digit()) {
-                    self.start_token(TokenKind::Literal(LiteralKind::Float32(0.0)), None);
-                    return;
-                }
-                self.push_small_token(current_character, peek);
-            }
-            '/' => {
-                if let Some(peek) = self.peek() {
-                    match peek {
-                        '/' => {
-                            self.in_comment = Some(CommentKind::SingleLine);
-                            self.source.next();
-                            return;
-                        }
-                        '*' => {
-                            self.in_comment = Some(CommentKind::MultiLine);
-                            self.source.next();
-                            return;
-                        }
-                        _ => {}
-                    }
-                }
+    fn skip_single_line_comment(&mut self) {
+        self.bump_while(|x| x != '\n');
+        self.push_trivia(TokenKind::Comment(CommentKind::SingleLine));
+    }
 
-                let peek = self.peek();
-                if let Some(grammar_token) =
-                    TokenKind::create_grammar_token(&current_character, peek.as_ref())
-                {
-                    self.start_token(grammar_token.clone(), Some(current_character));
-                    if grammar_token.get_grammar_token_length() > 1 {
-                        self.token_content.push(peek.unwrap());
-                        self.source.next();
-                    }
-                    self.push_token();
-                }
+    fn skip_multi_line_comment(&mut self) {
+        self.bump(); // consume the slash
+        self.bump(); // consume the star
+        while let Some(character) = self.bump() {
+            if character == '*' && self.peek() == Some('/') {
+                self.bump(); // consume the slash
+                self.push_trivia(TokenKind::Comment(CommentKind::MultiLine));
+                return;
             }
+        }
+        self.push_error_token("unterminated multi-line comment".to_string());
+    }
 
-            ' ' => {}
-            '\n' => {}
-            _ => {
-                let peek = self.peek();
-                if TokenKind::is_valid_identifier_character(true, &current_character) {
-                    self.start_token(TokenKind::Identifier(String::new()), None);
-                    return;
-                }
+    fn lex_identifier(&mut self) {
+        self.bump_while(|x| TokenKind::is_valid_identifier_character(false, &x));
 
-                if current_character.is_ascii_digit() {
-                    self.start_token(TokenKind::Literal(LiteralKind::Int8(0)), None);
-                    return;
-                }
+        let symbol = Symbol::intern(self.token_text());
+        let kind = match symbol {
+            sym::TRUE => TokenKind::Literal(LiteralKind::Boolean(true)),
+            sym::FALSE => TokenKind::Literal(LiteralKind::Boolean(false)),
+            _ => match KeywordKind::from_symbol(symbol) {
+                Some(keyword) => TokenKind::Keyword(keyword),
+                None => TokenKind::Identifier(symbol),
+            },
+        };
+        self.push_token(kind);
+    }
+
+    fn lex_number(&mut self) {
+        self.bump(); // consume the sign, dot or first digit
+                     // A `..` after a number is a range, such as `0..10`, rather than part of the number
+        while self
+            .peek()
+            .is_some_and(|x| TokenKind::is_valid_numeric_character(&x))
+            && !(self.peek() == Some('.') && self.peek_nth(1) == Some('.'))
+        {
+            self.bump();
+        }
+
+        match LiteralKind::into_numeric_literal(self.token_text()) {
+            Ok(numeric_literal) => self.push_token(TokenKind::Literal(numeric_literal)),
+            Err(err) => self.push_error_token(format!("invalid numeric literal: {}", err)),
+        }
+    }
 
-                if let Some(grammar_token) =
-                    TokenKind::create_grammar_token(&current_character, peek.as_ref())
-                {
-                    self.start_token(grammar_token.clone(), Some(current_character));
-                    if grammar_token.get_grammar_token_length() > 1 {
-                        self.token_content.push(peek.unwrap());
-                        self.source.next();
-                    }
-                    self.push_token();
-                    return;
+    /// Consumes a quoted literal up to and including the closing `quote`, skipping over escaped
+    /// characters. Returns false if the end of the source was found first
+    fn consume_quoted(&mut self, quote: char) -> bool {
+        self.bump(); // consume the opening quote
+        loop {
+            match self.bump() {
+                Some('\\') => {
+                    self.bump();
                 }
-                todo!("error: disallowed character")
+                Some(x) if x == quote => return true,
+                Some(_) => {}
+                None => return false,
             }
         }
     }
 
-    fn continue_token(&mut self, character: &char) {
-        if self.token_inferred_kind.is_none() {
+    fn lex_string(&mut self) {
+        if !self.consume_quoted('"') {
+            self.push_error_token("unterminated string literal".to_string());
             return;
         }
-        self.token_content.push(*character);
+        let string_literal = LiteralKind::into_string_literal(self.token_text());
+        self.push_token(TokenKind::Literal(string_literal));
+    }
 
-        let inferred_kind = self.token_inferred_kind.clone().unwrap();
-        match inferred_kind {
-            TokenKind::Identifier(_) => {
-                if !TokenKind::is_valid_identifier_character(false, self.peek().get_or_insert('\0'))
-                {
-                    if let Some(boolean_literal) =
-                        LiteralKind::into_boolean_literal(&self.token_content)
-                    {
-                        self.token_inferred_kind = Some(TokenKind::Literal(boolean_literal));
-                        self.push_token();
-                        return;
-                    }
+    fn lex_char(&mut self) {
+        if !self.consume_quoted('\'') {
+            self.push_error_token("unterminated character literal".to_string());
+            return;
+        }
+        match LiteralKind::into_char_literal(self.token_text()) {
+            Ok(character_literal) => self.push_token(TokenKind::Literal(character_literal)),
+            Err(err) => self.push_error_token(err.to_string()),
+        }
+    }
 
-                    if let Some(keyword) = KeywordKind::create_keyword(&self.token_content) {
-                        self.token_inferred_kind = Some(TokenKind::Keyword(keyword));
-                    } else {
-                        self.token_inferred_kind =
-                            Some(TokenKind::Identifier(self.token_content.clone()));
-                    }
-                    self.push_token();
-                }
-            }
-            // Int8 is used as the defacto unknown number marker for integers
-            // Float32 is used as the defacto unknown number marker for floats
-            TokenKind::Literal(LiteralKind::Int8(_))
-            | TokenKind::Literal(LiteralKind::Float32(_)) => {
-                if !TokenKind::is_valid_numeric_character(self.peek().get_or_insert('\0')) {
-                    let numeric_literal =
-                        match LiteralKind::into_numeric_literal(&self.token_content) {
-                            Ok(x) => x,
-                            Err(_err) => todo!("Do implement this error message"),
-                        };
-                    self.token_inferred_kind = Some(TokenKind::Literal(numeric_literal));
-                    self.push_token();
-                }
-            }
-            TokenKind::Literal(LiteralKind::Str(_)) => {
-                let peek = match self.peek() {
-                    Some(x) => x,
-                    None => todo!("error here, unexpected end to a token"),
-                };
-                if peek == '"' && *character != '\\' {
-                    self.token_inferred_kind = Some(TokenKind::Literal(
-                        LiteralKind::into_string_literal(&self.token_content),
-                    ));
-                    self.push_token();
-                    self.source.next(); // consume
-                }
-            }
-            TokenKind::Literal(LiteralKind::Char(_)) => {
-                let peek = match self.peek() {
-                    Some(x) => x,
-                    None => todo!("error here, unexpected end to a token"),
-                };
-                if peek == '\'' && *character != '\\' {
-                    let character_literal =
-                        match LiteralKind::into_char_literal(&self.token_content) {
-                            Ok(x) => x,
-                            Err(_err) => todo!("implement the error message here"),
-                        };
-                    self.token_inferred_kind = Some(TokenKind::Literal(character_literal));
-                    self.push_token();
-                    self.source.next(); // consume
-                }
+    /// Creates then pushes a "small token", that is any token which is 1-2 characters in length
+    fn lex_grammar(&mut self, current_character: char) {
+        let peek = self.peek_nth(1);
+        let kind = match TokenKind::create_grammar_token(&current_character, peek.as_ref()) {
+            Some(TokenKind::DotDot) if self.peek_nth(2) == Some('=') => {
+                Some(TokenKind::DotDotEqual)
             }
+            Some(TokenKind::DotDot) if self.peek_nth(2) == Some('.') => Some(TokenKind::DotDotDot),
+            kind => kind,
+        };
+        let Some(kind) = kind else {
+            self.bump();
+            self.push_error_token(format!("unknown character `{}`", current_character));
+            return;
+        };
 
-            _ => {
-                todo!("maybe error here? Although this branch is unreachable - i think")
-            }
+        for _ in 0..kind.get_grammar_token_length() {
+            self.bump();
         }
+        self.push_token(kind);
     }
 }
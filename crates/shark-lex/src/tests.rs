@@ -1,6 +1,8 @@
+use shark_core::{source::Span, symbol::Symbol};
+
 use crate::{
     token::TokenKind,
-    token::{KeywordKind, LexerToken, LiteralKind},
+    token::{CommentKind, KeywordKind, LexerToken, LiteralKind},
     Lexer,
 };
 
@@ -24,9 +26,9 @@ fn test_identifier() {
     let mut lexer = Lexer::new(None, "this_is_a_crazy_identifier8080");
     lexer.lex();
 
-    let expected = vec![TokenKind::Identifier(
-        "this_is_a_crazy_identifier8080".to_string(),
-    )];
+    let expected = vec![TokenKind::Identifier(Symbol::intern(
+        "this_is_a_crazy_identifier8080",
+    ))];
     assert!(verify_tokens(&lexer.completed_tokens, &expected))
 }
 
@@ -44,7 +46,7 @@ fn test_keyword_again() {
     let mut lexer = Lexer::new(None, "FUN");
     lexer.lex();
 
-    let expected_tokens = vec![TokenKind::Identifier(String::from("FUN"))];
+    let expected_tokens = vec![TokenKind::Identifier(Symbol::intern("FUN"))];
     assert!(verify_tokens(&lexer.completed_tokens, &expected_tokens));
 }
 
@@ -53,9 +55,9 @@ fn test_literal_str() {
     let mut lexer = Lexer::new(None, "\"Hello, World\"");
     lexer.lex();
 
-    let expected_tokens = vec![TokenKind::Literal(LiteralKind::Str(
-        "Hello, World".to_string(),
-    ))];
+    let expected_tokens = vec![TokenKind::Literal(LiteralKind::Str(Symbol::intern(
+        "Hello, World",
+    )))];
     assert!(verify_tokens(&lexer.completed_tokens, &expected_tokens));
 }
 
@@ -73,9 +75,9 @@ fn test_escapes() {
     let mut lexer = Lexer::new(None, "\"\\n \\t \\\\ \\u{263A}\"");
     lexer.lex();
 
-    let expected_tokens = vec![TokenKind::Literal(LiteralKind::Str(
-        "\n \t \\ \u{263A}".to_string(),
-    ))];
+    let expected_tokens = vec![TokenKind::Literal(LiteralKind::Str(Symbol::intern(
+        "\n \t \\ \u{263A}",
+    )))];
     assert!(verify_tokens(&lexer.completed_tokens, &expected_tokens));
 }
 
@@ -176,14 +178,14 @@ fn test_big() {
     let expected_tokens = vec![
         TokenKind::Keyword(KeywordKind::Pub),
         TokenKind::Keyword(KeywordKind::Fun),
-        TokenKind::Identifier(String::from("main")),
+        TokenKind::Identifier(Symbol::intern("main")),
         TokenKind::Parenthesis { opened: true },
         TokenKind::Parenthesis { opened: false },
         TokenKind::CurlyBrace { opened: true },
         TokenKind::Keyword(KeywordKind::Let),
-        TokenKind::Identifier(String::from("a")),
+        TokenKind::Identifier(Symbol::intern("a")),
         TokenKind::TypeAssign,
-        TokenKind::Identifier(String::from("Float32")),
+        TokenKind::Identifier(Symbol::intern("Float32")),
         TokenKind::Equal,
         TokenKind::Literal(LiteralKind::Float32(3.14)),
         TokenKind::EOL,
@@ -205,6 +207,46 @@ fn test_condensed() {
     assert!(verify_tokens(&lexer.completed_tokens, &expected_tokens));
 }
 
+#[test]
+fn test_ranges_and_arrows() {
+    let mut lexer = Lexer::new(None, "0..10 'a'..='z' x.. => 1.5.. ...");
+    lexer.lex();
+
+    let expected_tokens = vec![
+        TokenKind::Literal(LiteralKind::Int32(0)),
+        TokenKind::DotDot,
+        TokenKind::Literal(LiteralKind::Int32(10)),
+        TokenKind::Literal(LiteralKind::Char('a')),
+        TokenKind::DotDotEqual,
+        TokenKind::Literal(LiteralKind::Char('z')),
+        TokenKind::Identifier(Symbol::intern("x")),
+        TokenKind::DotDot,
+        TokenKind::FatArrow,
+        TokenKind::Literal(LiteralKind::Float32(1.5)),
+        TokenKind::DotDot,
+        TokenKind::DotDotDot,
+    ];
+    assert!(verify_tokens(&lexer.completed_tokens, &expected_tokens));
+}
+
+#[test]
+fn test_square_brackets() {
+    let mut lexer = Lexer::new(None, "[Int32; 4] [x]");
+    lexer.lex();
+
+    let expected_tokens = vec![
+        TokenKind::SquareBracket { opened: true },
+        TokenKind::Identifier(Symbol::intern("Int32")),
+        TokenKind::EOL,
+        TokenKind::Literal(LiteralKind::Int32(4)),
+        TokenKind::SquareBracket { opened: false },
+        TokenKind::SquareBracket { opened: true },
+        TokenKind::Identifier(Symbol::intern("x")),
+        TokenKind::SquareBracket { opened: false },
+    ];
+    assert!(verify_tokens(&lexer.completed_tokens, &expected_tokens));
+}
+
 #[test]
 fn test_comment() {
     let mut lexer = Lexer::new(None, "1// hello \n+// hello\n1");
@@ -230,3 +272,105 @@ fn test_multiline_comment() {
     ];
     assert!(verify_tokens(&lexer.completed_tokens, &expected_tokens));
 }
+
+#[test]
+fn test_token_spans() {
+    let source = "let mut name = \"shark\";";
+    let mut lexer = Lexer::new(None, source);
+    lexer.lex();
+
+    let texts: Vec<&str> = lexer.completed_tokens.iter().map(|x| x.text).collect();
+    assert_eq!(texts, vec!["let", "mut", "name", "=", "\"shark\"", ";"]);
+    for token in &lexer.completed_tokens {
+        assert_eq!(&source[token.span.start..token.span.end], token.text);
+    }
+}
+
+#[test]
+fn test_token_positions() {
+    let mut lexer = Lexer::new(None, "fun\n  main");
+    lexer.lex();
+
+    let main = &lexer.completed_tokens[1];
+    assert_eq!(main.position.start().line, 2);
+    assert_eq!(main.position.start().column, 3);
+    assert_eq!(main.position.end().column, 6);
+}
+
+#[test]
+fn test_escaped_quote() {
+    let mut lexer = Lexer::new(None, "\"say \\\"hi\\\"\"\t'\\''");
+    lexer.lex();
+
+    let expected_tokens = vec![
+        TokenKind::Literal(LiteralKind::Str(Symbol::intern("say \"hi\""))),
+        TokenKind::Literal(LiteralKind::Char('\'')),
+    ];
+    assert!(verify_tokens(&lexer.completed_tokens, &expected_tokens));
+}
+
+#[test]
+fn test_token_display() {
+    let mut lexer = Lexer::new(None, "fun main :: 42uint8 \"hi\"");
+    lexer.lex();
+
+    let displayed: Vec<String> = lexer
+        .completed_tokens
+        .iter()
+        .map(|x| x.kind.to_string())
+        .collect();
+    assert_eq!(
+        displayed,
+        vec![
+            "keyword `fun`",
+            "identifier `main`",
+            "`::`",
+            "`42uint8`",
+            "\"hi\""
+        ]
+    );
+}
+
+#[test]
+fn test_trivia() {
+    let source = "let a = 1; // one\n/* two */ a";
+    let mut lexer = Lexer::new(None, source);
+    lexer.keep_trivia = true;
+    lexer.lex();
+
+    let rebuilt: String = lexer.completed_tokens.iter().map(|x| x.text).collect();
+    assert_eq!(rebuilt, source);
+    assert!(lexer
+        .completed_tokens
+        .iter()
+        .any(|x| x.kind == TokenKind::Comment(CommentKind::SingleLine) && x.text == "// one"));
+    assert!(lexer
+        .completed_tokens
+        .iter()
+        .any(|x| x.kind == TokenKind::Comment(CommentKind::MultiLine) && x.text == "/* two */"));
+}
+
+#[test]
+fn test_invalid_input() {
+    let mut lexer = Lexer::new(None, "a $ 12xyz \"open");
+    lexer.lex();
+
+    let expected_tokens = vec![
+        TokenKind::Identifier(Symbol::intern("a")),
+        TokenKind::Unknown,
+        TokenKind::Unknown,
+        TokenKind::Unknown,
+    ];
+    assert!(verify_tokens(&lexer.completed_tokens, &expected_tokens));
+
+    let messages: Vec<String> = lexer.errors.iter().map(|x| x.message.clone()).collect();
+    assert_eq!(
+        messages,
+        vec![
+            "unknown character `$`",
+            "invalid numeric literal: unknown numeric suffix: xyz",
+            "unterminated string literal"
+        ]
+    );
+    assert_eq!(lexer.errors[2].primary_span(), Some(Span::new(10, 15)));
+}
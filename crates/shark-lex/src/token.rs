@@ -1,10 +1,13 @@
-use std::{error::Error, ops::RangeInclusive};
+use std::{error::Error, fmt::Display, ops::RangeInclusive};
 
 use crate::error::{
     InvalidCharacterLiteralErrrorKind, InvalidCharacterLiteralSizeError, InvalidFloatRadix,
     UnknownNumericSuffixError,
 };
-use shark_core::source::SourcePosition;
+use shark_core::{
+    source::{SourcePosition, Span},
+    symbol::Symbol,
+};
 use shark_macro::make_keywords;
 
 /// Represents a token produced during lexical analysis. [LexerToken]s give more meaning to the
@@ -15,12 +18,15 @@ pub struct LexerToken<'token> {
     pub kind: TokenKind,
 
     pub position: RangeInclusive<SourcePosition<'token>>,
+    pub span: Span,
+    /// The exact source text this token was created from
+    pub text: &'token str,
     pub length: usize,
 }
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum TokenKind {
-    Identifier(String),
+    Identifier(Symbol),
     Keyword(KeywordKind),
     Literal(LiteralKind),
 
@@ -50,13 +56,31 @@ pub enum TokenKind {
     ShiftLeft,  // <<
     BitwiseAnd, // &
 
-    Comma,      // ,
-    TypeAssign, // ::
-    Dot,        // .
-    CurlyBrace { opened: bool },
-    Parenthesis { opened: bool },
+    Comma,       // ,
+    TypeAssign,  // ::
+    Dot,         // .
+    DotDot,      // ..
+    DotDotEqual, // ..=
+    DotDotDot,   // ...
+    FatArrow,    // =>
+    CurlyBrace {
+        opened: bool,
+    },
+    Parenthesis {
+        opened: bool,
+    },
+    SquareBracket {
+        opened: bool,
+    },
 
     EOL, // ; and potentially newline
+
+    /// Whitespace, only produced when [crate::Lexer::keep_trivia] is set
+    Whitespace,
+    /// A comment, only produced when [crate::Lexer::keep_trivia] is set
+    Comment(CommentKind),
+    /// Text which could not be turned into a token. The [crate::Lexer] has already reported why
+    Unknown,
 }
 
 impl TokenKind {
@@ -95,6 +119,7 @@ impl TokenKind {
             },
             '=' => match peek {
                 Some('=') => Some(TokenKind::EqualTo),
+                Some('>') => Some(TokenKind::FatArrow),
                 _ => Some(TokenKind::Equal),
             },
             '&' => match peek {
@@ -107,11 +132,16 @@ impl TokenKind {
             },
             '|' => Some(TokenKind::Or),
             ',' => Some(TokenKind::Comma),
-            '.' => Some(TokenKind::Dot),
+            '.' => match peek {
+                Some('.') => Some(TokenKind::DotDot),
+                _ => Some(TokenKind::Dot),
+            },
             '{' => Some(TokenKind::CurlyBrace { opened: true }),
             '}' => Some(TokenKind::CurlyBrace { opened: false }),
             '(' => Some(TokenKind::Parenthesis { opened: true }),
             ')' => Some(TokenKind::Parenthesis { opened: false }),
+            '[' => Some(TokenKind::SquareBracket { opened: true }),
+            ']' => Some(TokenKind::SquareBracket { opened: false }),
             ';' => Some(TokenKind::EOL),
             _ => None,
         }
@@ -132,11 +162,19 @@ impl TokenKind {
             | Self::NotEqual
             | Self::EqualTo
             | Self::And
-            | Self::TypeAssign => 2,
+            | Self::TypeAssign
+            | Self::DotDot
+            | Self::FatArrow => 2,
+            Self::DotDotEqual | Self::DotDotDot => 3,
             _ => 1,
         }
     }
 
+    /// Checks if this token is whitespace or a comment, which carry no meaning for the parser
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, Self::Whitespace | Self::Comment(_))
+    }
+
     /// Checks if the provided [char] is a valid identifier character. The [bool]
     /// parameter should be true if this is the first [char] in the identifier
     pub fn is_valid_identifier_character(start: bool, character: &char) -> bool {
@@ -152,12 +190,62 @@ impl TokenKind {
     }
 }
 
+impl Display for TokenKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let grammar = match self {
+            Self::Identifier(symbol) => return write!(f, "identifier `{}`", symbol),
+            Self::Keyword(keyword) => return write!(f, "keyword `{}`", keyword.symbol()),
+            Self::Literal(literal) => return write!(f, "{}", literal),
+
+            Self::Plus => "+",
+            Self::Minus => "-",
+            Self::Multiply => "*",
+            Self::Divide => "/",
+            Self::PlusAssign => "+=",
+            Self::MinusAssign => "-=",
+            Self::MultiplyAssign => "*=",
+            Self::DivideAssign => "/=",
+            Self::Greater => ">",
+            Self::Lesser => "<",
+            Self::Or => "|",
+            Self::Not => "!",
+            Self::And => "&&",
+            Self::Equal => "=",
+            Self::GreaterOrEqual => ">=",
+            Self::LessOrEqual => "<=",
+            Self::NotEqual => "!=",
+            Self::EqualTo => "==",
+            Self::ShiftRight => ">>",
+            Self::ShiftLeft => "<<",
+            Self::BitwiseAnd => "&",
+            Self::Comma => ",",
+            Self::TypeAssign => "::",
+            Self::Dot => ".",
+            Self::DotDot => "..",
+            Self::DotDotEqual => "..=",
+            Self::DotDotDot => "...",
+            Self::FatArrow => "=>",
+            Self::CurlyBrace { opened: true } => "{",
+            Self::CurlyBrace { opened: false } => "}",
+            Self::Parenthesis { opened: true } => "(",
+            Self::Parenthesis { opened: false } => ")",
+            Self::SquareBracket { opened: true } => "[",
+            Self::SquareBracket { opened: false } => "]",
+            Self::EOL => ";",
+            Self::Whitespace => return write!(f, "whitespace"),
+            Self::Comment(_) => return write!(f, "a comment"),
+            Self::Unknown => return write!(f, "an unknown token"),
+        };
+        write!(f, "`{}`", grammar)
+    }
+}
+
 make_keywords!(
-    Else, Enum, For, Fun, If, In, Let, Mut, Of, Ptr, Pub, Ref, Ret, Trait, Type, Unsafe, Use, When,
-    Where, Yield
+    Const, Else, Enum, Extern, For, Fun, If, Impl, In, Let, Mut, Of, Ptr, Pub, Ref, Ret, Trait,
+    Type, Unsafe, Use, When, Where, Yield
 );
 
-#[derive(Debug, Clone, PartialEq)]
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum LiteralKind {
     // Integer Literals
     UInt8(u8),
@@ -172,9 +260,7 @@ pub enum LiteralKind {
     Float64(f64),
 
     // Array-like Literals
-    Str(String),
-    // Array(String), TODO(Chloe): Figure This out
-    // Array literals are just [ ... ] where ... is a comma separated list of values
+    Str(Symbol),
     Char(char),
     Boolean(bool),
 }
@@ -189,8 +275,10 @@ impl LiteralKind {
             working_content
         };
 
-        let prefix = &operating_content[..2.min(operating_content.len())].to_lowercase();
-        let radix = match prefix.as_str() {
+        let prefix = operating_content
+            .get(..2.min(operating_content.len()))
+            .map(|x| x.to_lowercase());
+        let radix = match prefix.as_deref().unwrap_or_default() {
             "0x" => 16,
             "0o" => 8,
             "0b" => 2,
@@ -302,24 +390,15 @@ impl LiteralKind {
     /// Converts a token's working_content into a [LiteralKind::Char]
     /// This function assumes the provided content is somewhere near a character
     pub fn into_char_literal(working_content: &str) -> Result<LiteralKind, Box<dyn Error>> {
-        let mut value = working_content.to_string();
-
         // Remove surrounding '
-        if value.starts_with('\'') {
-            value = working_content[1..].to_string();
-        }
-        if value.ends_with('\'') {
-            value = working_content[..value.len()].to_string();
-        }
-
-        value = encode_characters!(value);
-        if value.len() > 1 {
-            return Err(Box::new(InvalidCharacterLiteralSizeError {
-                kind: InvalidCharacterLiteralErrrorKind::TooLong,
-            }));
-        }
+        let value = working_content
+            .strip_prefix('\'')
+            .unwrap_or(working_content);
+        let value = value.strip_suffix('\'').unwrap_or(value);
 
-        let character: char = match value.chars().next() {
+        let value = encode_characters!(value);
+        let mut characters = value.chars();
+        let character: char = match characters.next() {
             Some(x) => x,
             None => {
                 return Err(Box::new(InvalidCharacterLiteralSizeError {
@@ -327,24 +406,26 @@ impl LiteralKind {
                 }))
             }
         };
+        if characters.next().is_some() {
+            return Err(Box::new(InvalidCharacterLiteralSizeError {
+                kind: InvalidCharacterLiteralErrrorKind::TooLong,
+            }));
+        }
         Ok(LiteralKind::Char(character))
     }
 
     /// Converts a token's working_content into a [LiteralKind::Str]
-    /// This function assumes the provided content is somewhere near a string       
+    /// This function assumes the provided content is somewhere near a string. Strings without any
+    /// escapes are interned straight from the provided content
     pub fn into_string_literal(working_content: &str) -> LiteralKind {
-        let mut value = working_content.to_string();
-
         // Trim off the starting and ending quotations if they exist
-        if value.starts_with('"') {
-            value = value[1..].to_string();
-        }
-        if value.ends_with('"') {
-            value = value[..value.len()].to_string();
-        }
+        let value = working_content.strip_prefix('"').unwrap_or(working_content);
+        let value = value.strip_suffix('"').unwrap_or(value);
 
-        let value = encode_characters!(value);
-        LiteralKind::Str(value)
+        if !value.contains('\\') {
+            return LiteralKind::Str(Symbol::intern(value));
+        }
+        LiteralKind::Str(Symbol::intern(&encode_characters!(value)))
     }
 
     // Most useful function ever might remove it
@@ -357,7 +438,25 @@ impl LiteralKind {
     }
 }
 
-#[derive(Debug)]
+impl Display for LiteralKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::UInt8(x) => write!(f, "`{}uint8`", x),
+            Self::Int8(x) => write!(f, "`{}int8`", x),
+            Self::UInt32(x) => write!(f, "`{}uint32`", x),
+            Self::Int32(x) => write!(f, "`{}`", x),
+            Self::UInt64(x) => write!(f, "`{}uint64`", x),
+            Self::Int64(x) => write!(f, "`{}int64`", x),
+            Self::Float32(x) => write!(f, "`{:?}`", x),
+            Self::Float64(x) => write!(f, "`{:?}float64`", x),
+            Self::Str(x) => write!(f, "{:?}", x.as_str()),
+            Self::Char(x) => write!(f, "{:?}", x),
+            Self::Boolean(x) => write!(f, "`{}`", x),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum CommentKind {
     SingleLine,
     MultiLine,
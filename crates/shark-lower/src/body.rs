@@ -0,0 +1,196 @@
+//! The lowered form of a function body: a control flow graph of [BasicBlock]s over numbered
+//! [Local]s. Expressions which cannot suspend a generator are kept as they are, so only `yield`
+//! and the control flow around it is broken up into blocks
+//!
+//! A generator is a state machine. Its frame holds the [Generator::saved] locals along with the
+//! state it is in, which is the block to continue from once the next value is asked for. Starting
+//! or resuming it runs blocks until it reaches a [Terminator::Yield], handing out the value and
+//! moving to the state of the block after it, or a [Terminator::Return], after which it is done
+//!
+//! Iterating follows the same protocol whatever is iterated: [Statement::StartIteration] evaluates
+//! the iterable once and keeps the generator it produces in a hidden local, then every
+//! [Terminator::Next] resumes it. A `for pattern in iterable { body }` loop is the same as
+//!
+//! ```text
+//!     start iterator = iterable
+//! header:
+//!     next iterator, binding pattern => body, or when done => exit
+//! body:
+//!     body
+//!     goto header
+//! exit:
+//! ```
+//!
+//! where `for (index, pattern) of` also keeps a hidden counter, bound to `index` and incremented
+//! by every [Terminator::Next]. Loops in the parts of a body which are kept as expressions are
+//! evaluated with this same protocol
+
+use std::collections::HashMap;
+
+use shark_core::{source::Span, symbol::Symbol};
+use shark_parse::ast::{Expr, Function, NodeId, Pattern, WhenArm};
+
+/// Identifies a [Local] within a [Body]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct LocalId(pub u32);
+
+/// Identifies a [BasicBlock] within a [Body]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub u32);
+
+/// A value stored in the frame of a function: a parameter, a binding or one of the hidden values
+/// a `for` loop keeps
+#[derive(Debug, Clone)]
+pub struct Local {
+    pub name: Symbol,
+    pub mutable: bool,
+    pub span: Span,
+    pub kind: LocalKind,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LocalKind {
+    Parameter,
+    /// Declared by the `let` statement with this id
+    Let(NodeId),
+    /// Bound by the binding pattern with this id, within a `when` arm or a `for` loop
+    Binding(NodeId),
+    /// The generator a `for` loop is resuming
+    Iterator,
+    /// The number of values a `for ... of` loop has taken so far
+    Counter,
+    /// A value a closure captured from the function around it, which is copied into the closure
+    /// when it is made
+    Capture,
+}
+
+#[derive(Debug, Clone)]
+pub struct BasicBlock<'ast> {
+    pub statements: Vec<Statement<'ast>>,
+    pub terminator: Terminator<'ast>,
+}
+
+#[derive(Debug, Clone)]
+pub enum Statement<'ast> {
+    /// A `let`, storing the value if it has one
+    Let(LocalId, Option<&'ast Expr>),
+    /// Evaluates an expression for its effects
+    Eval(&'ast Expr),
+    /// Evaluates what a `for` loop iterates and stores the generator it produces, setting the
+    /// counter to zero for a `for ... of`
+    StartIteration {
+        iterator: LocalId,
+        counter: Option<LocalId>,
+        iterable: &'ast Expr,
+    },
+}
+
+#[derive(Debug, Clone)]
+pub enum Terminator<'ast> {
+    Goto(BlockId),
+    /// Continues with `then_block` if the condition holds and `else_block` if it does not
+    Branch {
+        condition: &'ast Expr,
+        then_block: BlockId,
+        else_block: BlockId,
+    },
+    /// Continues with the block of the first arm whose pattern matches and whose guard holds,
+    /// once the pattern has bound its locals. Arms always cover every value
+    When {
+        scrutinee: &'ast Expr,
+        arms: Vec<(&'ast WhenArm, BlockId)>,
+    },
+    /// Resumes the generator within `iterator`. If it yields, the value is bound to the pattern,
+    /// along with the counter for a `for ... of`, and the loop continues with `body`. Otherwise it
+    /// is done and the loop continues with `exit`
+    Next {
+        iterator: LocalId,
+        counter: Option<LocalId>,
+        pattern: &'ast Pattern,
+        body: BlockId,
+        exit: BlockId,
+    },
+    /// Suspends the generator, handing out the value. It continues with `resume` once it is
+    /// resumed
+    Yield {
+        value: &'ast Expr,
+        resume: BlockId,
+    },
+    /// Returns from the function, or finishes the generator
+    Return(Option<&'ast Expr>),
+}
+
+impl Terminator<'_> {
+    /// Gets every block which can follow this one
+    pub fn successors(&self) -> Vec<BlockId> {
+        match self {
+            Self::Goto(target) => vec![*target],
+            Self::Branch {
+                then_block,
+                else_block,
+                ..
+            } => vec![*then_block, *else_block],
+            Self::When { arms, .. } => arms.iter().map(|(_, x)| *x).collect(),
+            Self::Next { body, exit, .. } => vec![*body, *exit],
+            Self::Yield { resume, .. } => vec![*resume],
+            Self::Return(_) => Vec::new(),
+        }
+    }
+}
+
+/// What makes a generator into a state machine
+#[derive(Debug, Clone, Default)]
+pub struct Generator {
+    /// The block each state continues from. The first state is the entry, before the generator
+    /// has been started, and every `yield` adds a state for the block after it
+    pub states: Vec<BlockId>,
+    /// The locals which can be in use while the generator is suspended, which are the ones its
+    /// frame has to keep. The others only live between two `yield`s
+    pub saved: Vec<LocalId>,
+}
+
+impl Generator {
+    /// Gets the state which continues from a block, if any does
+    pub fn state_of(&self, block: BlockId) -> Option<usize> {
+        self.states.iter().position(|x| *x == block)
+    }
+}
+
+/// A lowered function body
+#[derive(Debug, Clone)]
+pub struct Body<'ast> {
+    pub function: &'ast Function,
+    /// Every local, starting with the parameters in order
+    pub locals: Vec<Local>,
+    /// Every block, starting with [Body::ENTRY]
+    pub blocks: Vec<BasicBlock<'ast>>,
+    /// The local every name expression refers to. Names which are not here refer to items
+    pub names: HashMap<NodeId, LocalId>,
+    /// The local declared by every `let` statement and binding pattern
+    pub declarations: HashMap<NodeId, LocalId>,
+    /// How the function suspends, if it is a generator
+    pub generator: Option<Generator>,
+    /// For a closure, the locals of the body around it which it captures. Their values are kept
+    /// in its [LocalKind::Capture] locals, which come right after the parameters in the same order
+    pub captures: Vec<LocalId>,
+}
+
+impl<'ast> Body<'ast> {
+    pub const ENTRY: BlockId = BlockId(0);
+
+    pub fn local(&self, id: LocalId) -> &Local {
+        &self.locals[id.0 as usize]
+    }
+
+    pub fn block(&self, id: BlockId) -> &BasicBlock<'ast> {
+        &self.blocks[id.0 as usize]
+    }
+
+    /// Gets the local a name expression or a declaration refers to
+    pub fn local_of(&self, id: NodeId) -> Option<LocalId> {
+        self.names
+            .get(&id)
+            .or_else(|| self.declarations.get(&id))
+            .copied()
+    }
+}
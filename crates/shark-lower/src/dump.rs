@@ -0,0 +1,92 @@
+//! Writes a [Body] out as text, one block after another, for use in tests
+
+use std::fmt::Write;
+
+use shark_parse::dump::{dump_expr, dump_pattern};
+
+use crate::body::{Body, LocalId, Statement, Terminator};
+
+pub fn dump_body(body: &Body) -> String {
+    let local = |id: &LocalId| format!("{}_{}", body.local(*id).name, id.0);
+    let mut result = String::new();
+    for (index, block) in body.blocks.iter().enumerate() {
+        let _ = writeln!(result, "bb{}:", index);
+        for statement in &block.statements {
+            let line = match statement {
+                Statement::Let(id, Some(value)) => {
+                    format!("let {} = {}", local(id), dump_expr(value))
+                }
+                Statement::Let(id, None) => format!("let {}", local(id)),
+                Statement::Eval(expr) => dump_expr(expr),
+                Statement::StartIteration {
+                    iterator,
+                    counter,
+                    iterable,
+                } => match counter {
+                    Some(counter) => format!(
+                        "start {}, {} = {}",
+                        local(iterator),
+                        local(counter),
+                        dump_expr(iterable)
+                    ),
+                    None => format!("start {} = {}", local(iterator), dump_expr(iterable)),
+                },
+            };
+            let _ = writeln!(result, "    {}", line);
+        }
+        let terminator = match &block.terminator {
+            Terminator::Goto(target) => format!("goto bb{}", target.0),
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+            } => format!(
+                "if {} then bb{} else bb{}",
+                dump_expr(condition),
+                then_block.0,
+                else_block.0
+            ),
+            Terminator::When { scrutinee, arms } => {
+                let arms: Vec<String> = arms
+                    .iter()
+                    .map(|(arm, block)| format!("{} => bb{}", dump_pattern(&arm.pattern), block.0))
+                    .collect();
+                format!("when {} {{ {} }}", dump_expr(scrutinee), arms.join(", "))
+            }
+            Terminator::Next {
+                iterator,
+                counter,
+                pattern,
+                body,
+                exit,
+            } => {
+                let counter = counter.map_or(String::new(), |x| format!(" counting {}", local(&x)));
+                format!(
+                    "next {}{} {} => bb{}, done => bb{}",
+                    local(iterator),
+                    counter,
+                    dump_pattern(pattern),
+                    body.0,
+                    exit.0
+                )
+            }
+            Terminator::Yield { value, resume } => {
+                format!("yield {} resume bb{}", dump_expr(value), resume.0)
+            }
+            Terminator::Return(Some(value)) => format!("ret {}", dump_expr(value)),
+            Terminator::Return(None) => "ret".to_string(),
+        };
+        let _ = writeln!(result, "    {}", terminator);
+    }
+    if let Some(generator) = &body.generator {
+        let states: Vec<String> = generator
+            .states
+            .iter()
+            .map(|x| format!("bb{}", x.0))
+            .collect();
+        let saved: Vec<String> = generator.saved.iter().map(local).collect();
+        let _ = writeln!(result, "states: {}", states.join(", "));
+        let _ = writeln!(result, "saved: {}", saved.join(", "));
+    }
+    result
+}
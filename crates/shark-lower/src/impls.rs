@@ -0,0 +1,30 @@
+//! The methods provided by the `impl`s of a program, which every backend generating code for the
+//! lowered bodies looks up the same way when a method is called
+
+use shark_core::symbol::Symbol;
+use shark_sema::{traits::TraitId, ty::Type};
+use shark_typeck::ty::Ty;
+
+/// The methods an `impl` provides, including the defaults of its trait, by however a backend
+/// refers to the functions it generates
+pub struct ImplMethods<F> {
+    pub self_type: Type,
+    pub trait_id: TraitId,
+    pub methods: Vec<(Symbol, F)>,
+}
+
+/// Finds the method a value of a type calls, optionally only looking at the implementations of
+/// one trait
+pub fn find_method<'i, F>(
+    impls: &'i [ImplMethods<F>],
+    ty: &Ty,
+    name: Symbol,
+    trait_id: Option<TraitId>,
+) -> Option<&'i F> {
+    let ty = ty.to_type()?;
+    impls
+        .iter()
+        .filter(|x| x.self_type == ty && trait_id.is_none_or(|id| x.trait_id == id))
+        .find_map(|x| x.methods.iter().find(|(method, _)| *method == name))
+        .map(|(_, function)| function)
+}
@@ -0,0 +1,41 @@
+use lower::Lowerer;
+use shark_core::diagnostic::Diagnostic;
+use shark_parse::ast::{Function, ItemKind, Module};
+
+pub use body::Body;
+
+pub mod body;
+pub mod dump;
+pub mod impls;
+pub mod lower;
+
+#[cfg(test)]
+pub mod tests;
+
+/// Lowers a function body into a control flow graph, turning it into a state machine if it is a
+/// generator. The body is followed by those of the closures within it. Returns [None] for a trait
+/// method without a default body
+pub fn lower_function(function: &Function) -> Option<(Vec<Body<'_>>, Vec<Diagnostic>)> {
+    function.body.as_ref()?;
+    Some(Lowerer::new(function).lower())
+}
+
+/// Step six of compilation. Lowers every function and method body of a [Module] once it has been
+/// checked
+pub fn lower_module(module: &Module) -> (Vec<Body<'_>>, Vec<Diagnostic>) {
+    let mut bodies = Vec::new();
+    let mut diagnostics = Vec::new();
+    let functions = module.items.iter().flat_map(|x| match &x.kind {
+        ItemKind::Function(function) => std::slice::from_ref(function),
+        ItemKind::Trait(trait_decl) => &trait_decl.methods[..],
+        ItemKind::Impl(impl_decl) => &impl_decl.methods[..],
+        _ => &[],
+    });
+    for function in functions {
+        if let Some((body, errors)) = lower_function(function) {
+            bodies.extend(body);
+            diagnostics.extend(errors);
+        }
+    }
+    (bodies, diagnostics)
+}
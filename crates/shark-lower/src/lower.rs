@@ -0,0 +1,740 @@
+//! Lowers a function body into a [Body]. Statements are lowered in order into the current block,
+//! and the control flow a `yield` is within is split into blocks so that the generator can stop
+//! at the `yield` and later continue from the block after it
+//!
+//! Every closure gets a [Body] of its own, lowered once the locals around it are known so that
+//! the ones it names can be captured
+
+use std::collections::BTreeSet;
+
+use shark_core::{diagnostic::Diagnostic, source::Span, symbol::Symbol};
+use shark_parse::ast::{
+    Block, Expr, ExprKind, ForKind, Function, Let, NodeId, Pattern, PatternKind, StatementKind,
+    TypeExprKind,
+};
+
+use crate::body::{
+    BasicBlock, BlockId, Body, Generator, Local, LocalId, LocalKind, Statement, Terminator,
+};
+
+/// A block which is still being lowered into
+struct PendingBlock<'ast> {
+    statements: Vec<Statement<'ast>>,
+    terminator: Option<Terminator<'ast>>,
+}
+
+pub struct Lowerer<'ast> {
+    body: Body<'ast>,
+    blocks: Vec<PendingBlock<'ast>>,
+    /// The block statements are being added to
+    current: BlockId,
+    /// The locals which can be named, innermost scope last
+    scopes: Vec<Vec<(Symbol, LocalId)>>,
+    /// The locals in scope at every `yield`
+    saved: BTreeSet<LocalId>,
+    /// Whether the function is declared as a generator. `yield`s anywhere else have already been
+    /// reported by type checking
+    is_generator: bool,
+    /// The bodies of the closures within the function, including those within other closures
+    closures: Vec<Body<'ast>>,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<'ast> Lowerer<'ast> {
+    pub fn new(function: &'ast Function) -> Self {
+        let is_generator = matches!(
+            function.return_type.as_ref().map(|x| &x.kind),
+            Some(TypeExprKind::Generator(_))
+        );
+        Self {
+            body: Body {
+                function,
+                locals: Vec::new(),
+                blocks: Vec::new(),
+                names: Default::default(),
+                declarations: Default::default(),
+                generator: None,
+                captures: Vec::new(),
+            },
+            blocks: Vec::new(),
+            current: Body::ENTRY,
+            scopes: Vec::new(),
+            saved: BTreeSet::new(),
+            is_generator,
+            closures: Vec::new(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Lowers the body of the function, which must have one. Returns it followed by the bodies of
+    /// the closures within it
+    pub fn lower(self) -> (Vec<Body<'ast>>, Vec<Diagnostic>) {
+        self.lower_with(&[])
+    }
+
+    /// Lowers the body of a closure which captures the locals with these names
+    fn lower_with(mut self, captures: &[Symbol]) -> (Vec<Body<'ast>>, Vec<Diagnostic>) {
+        let function = self.body.function;
+        let block = function
+            .body
+            .as_ref()
+            .expect("only functions with a body are lowered");
+
+        self.current = self.new_block();
+        self.scopes.push(Vec::new());
+        for parameter in &function.parameters {
+            self.declare(Local {
+                name: parameter.name.symbol,
+                mutable: parameter.mutable,
+                span: parameter.name.span,
+                kind: LocalKind::Parameter,
+            });
+        }
+        for name in captures {
+            self.declare(Local {
+                name: *name,
+                mutable: false,
+                span: function.name.span,
+                kind: LocalKind::Capture,
+            });
+        }
+        self.lower_statements(block);
+        let value = match &block.tail {
+            Some(tail) if self.is_generator || contains_yield(tail) => {
+                self.lower_effect(tail);
+                None
+            }
+            Some(tail) => {
+                self.resolve_expr(tail);
+                Some(&**tail)
+            }
+            None => None,
+        };
+        self.set_terminator(Terminator::Return(value));
+        self.scopes.pop();
+
+        if self.is_generator {
+            let mut states = vec![Body::ENTRY];
+            for block in &self.blocks {
+                if let Some(Terminator::Yield { resume, .. }) = &block.terminator {
+                    states.push(*resume);
+                }
+            }
+            self.body.generator = Some(Generator {
+                states,
+                saved: self.saved.iter().copied().collect(),
+            });
+        }
+        self.body.blocks = self
+            .blocks
+            .into_iter()
+            .map(|x| BasicBlock {
+                statements: x.statements,
+                terminator: x.terminator.expect("every block is terminated"),
+            })
+            .collect();
+        let mut bodies = vec![self.body];
+        bodies.extend(self.closures);
+        (bodies, self.diagnostics)
+    }
+
+    /// Lowers a closure into a body of its own, which captures the locals in scope that it names
+    fn lower_closure(&mut self, function: &'ast Function) {
+        let captures: Vec<(Symbol, LocalId)> = free_names(function)
+            .into_iter()
+            .filter_map(|x| match x.kind {
+                ExprKind::Name(name) => Some((name.symbol, self.lookup(name.symbol)?)),
+                _ => None,
+            })
+            .collect();
+        let names: Vec<Symbol> = captures.iter().map(|(x, _)| *x).collect();
+        let (mut bodies, diagnostics) = Lowerer::new(function).lower_with(&names);
+        bodies[0].captures = captures.into_iter().map(|(_, x)| x).collect();
+        self.closures.extend(bodies);
+        self.diagnostics.extend(diagnostics);
+    }
+
+    fn new_block(&mut self) -> BlockId {
+        self.blocks.push(PendingBlock {
+            statements: Vec::new(),
+            terminator: None,
+        });
+        BlockId(self.blocks.len() as u32 - 1)
+    }
+
+    fn push(&mut self, statement: Statement<'ast>) {
+        self.blocks[self.current.0 as usize]
+            .statements
+            .push(statement);
+    }
+
+    fn set_terminator(&mut self, terminator: Terminator<'ast>) {
+        self.blocks[self.current.0 as usize].terminator = Some(terminator);
+    }
+
+    /// Ends the current block. Anything lowered afterwards goes into a new block, which nothing
+    /// jumps to
+    fn terminate(&mut self, terminator: Terminator<'ast>) {
+        self.set_terminator(terminator);
+        self.current = self.new_block();
+    }
+
+    /// Ends the current block and continues lowering into `next`
+    fn terminate_into(&mut self, terminator: Terminator<'ast>, next: BlockId) {
+        self.set_terminator(terminator);
+        self.current = next;
+    }
+
+    fn declare(&mut self, local: Local) -> LocalId {
+        let id = LocalId(self.body.locals.len() as u32);
+        self.scopes
+            .last_mut()
+            .expect("a local is always declared within a scope")
+            .push((local.name, id));
+        self.body.locals.push(local);
+        id
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<LocalId> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|x| x.iter().rev())
+            .find(|(x, _)| *x == name)
+            .map(|(_, id)| *id)
+    }
+
+    fn declare_pattern(&mut self, pattern: &Pattern) {
+        match &pattern.kind {
+            PatternKind::Binding { mutable, name } => {
+                let id = self.declare(Local {
+                    name: name.symbol,
+                    mutable: *mutable,
+                    span: name.span,
+                    kind: LocalKind::Binding(pattern.id),
+                });
+                self.body.declarations.insert(pattern.id, id);
+            }
+            PatternKind::Tuple(elements) => elements.iter().for_each(|x| self.declare_pattern(x)),
+            PatternKind::Variant { payload, .. } => {
+                payload.iter().for_each(|x| self.declare_pattern(x))
+            }
+            PatternKind::Wildcard | PatternKind::Literal(_) | PatternKind::Range { .. } => {}
+        }
+    }
+
+    fn declare_let(&mut self, id: NodeId, let_statement: &Let) -> LocalId {
+        let local = self.declare(Local {
+            name: let_statement.name.symbol,
+            mutable: let_statement.mutable,
+            span: let_statement.name.span,
+            kind: LocalKind::Let(id),
+        });
+        self.body.declarations.insert(id, local);
+        local
+    }
+
+    /// Declares a hidden local for a `for` loop, with a name that can never be written so that it
+    /// can not be referred to
+    fn declare_hidden(&mut self, name: &str, span: Span, kind: LocalKind) -> LocalId {
+        self.declare(Local {
+            name: Symbol::intern(&format!("<{}>", name)),
+            mutable: true,
+            span,
+            kind,
+        })
+    }
+
+    fn lower_statements(&mut self, block: &'ast Block) {
+        for statement in &block.statements {
+            match &statement.kind {
+                StatementKind::Let(let_statement) => {
+                    if let Some(value) = &let_statement.value {
+                        self.forbid_yield(value);
+                        self.resolve_expr(value);
+                    }
+                    let id = self.declare_let(statement.id, let_statement);
+                    self.push(Statement::Let(id, let_statement.value.as_ref()));
+                }
+                StatementKind::Expr(expr) => self.lower_effect(expr),
+                StatementKind::Error => {}
+            }
+        }
+    }
+
+    /// Lowers an expression whose value is not used. Expressions which do not `yield` are kept
+    /// whole, the control flow of the others is split into blocks
+    fn lower_effect(&mut self, expr: &'ast Expr) {
+        if !contains_yield(expr) {
+            self.resolve_expr(expr);
+            self.push(Statement::Eval(expr));
+            return;
+        }
+        match &expr.kind {
+            ExprKind::Yield(value) => {
+                self.forbid_yield(value);
+                self.resolve_expr(value);
+                self.saved
+                    .extend(self.scopes.iter().flatten().map(|(_, id)| *id));
+                let resume = self.new_block();
+                self.terminate_into(Terminator::Yield { value, resume }, resume);
+            }
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => self.lower_scope(block),
+            ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.forbid_yield(condition);
+                self.resolve_expr(condition);
+                let then_block = self.new_block();
+                let else_block = self.new_block();
+                let join = self.new_block();
+                self.terminate_into(
+                    Terminator::Branch {
+                        condition,
+                        then_block,
+                        else_block,
+                    },
+                    then_block,
+                );
+                self.lower_scope(then_branch);
+                self.terminate_into(Terminator::Goto(join), else_block);
+                if let Some(else_branch) = else_branch {
+                    self.lower_effect(else_branch);
+                }
+                self.terminate_into(Terminator::Goto(join), join);
+            }
+            ExprKind::When { scrutinee, arms } => {
+                self.forbid_yield(scrutinee);
+                self.resolve_expr(scrutinee);
+                let targets: Vec<_> = arms.iter().map(|x| (x, self.new_block())).collect();
+                let join = self.new_block();
+                self.set_terminator(Terminator::When {
+                    scrutinee,
+                    arms: targets.clone(),
+                });
+                for (arm, block) in targets {
+                    self.current = block;
+                    self.scopes.push(Vec::new());
+                    self.declare_pattern(&arm.pattern);
+                    if let Some(guard) = &arm.guard {
+                        self.forbid_yield(guard);
+                        self.resolve_expr(guard);
+                    }
+                    self.lower_effect(&arm.body);
+                    self.scopes.pop();
+                    self.set_terminator(Terminator::Goto(join));
+                }
+                self.current = join;
+            }
+            ExprKind::For {
+                kind,
+                pattern,
+                iterable,
+                body,
+            } => {
+                self.forbid_yield(iterable);
+                self.resolve_expr(iterable);
+                self.scopes.push(Vec::new());
+                let iterator = self.declare_hidden("iterator", iterable.span, LocalKind::Iterator);
+                let counter = match kind {
+                    ForKind::In => None,
+                    ForKind::Of => {
+                        Some(self.declare_hidden("counter", iterable.span, LocalKind::Counter))
+                    }
+                };
+                self.push(Statement::StartIteration {
+                    iterator,
+                    counter,
+                    iterable,
+                });
+
+                let header = self.new_block();
+                let body_block = self.new_block();
+                let exit = self.new_block();
+                self.terminate_into(Terminator::Goto(header), header);
+                self.terminate_into(
+                    Terminator::Next {
+                        iterator,
+                        counter,
+                        pattern,
+                        body: body_block,
+                        exit,
+                    },
+                    body_block,
+                );
+                self.scopes.push(Vec::new());
+                self.declare_pattern(pattern);
+                self.lower_scope(body);
+                self.scopes.pop();
+                self.terminate_into(Terminator::Goto(header), exit);
+                self.scopes.pop();
+            }
+            ExprKind::Return(value) => {
+                if let Some(value) = value {
+                    self.forbid_yield(value);
+                    self.resolve_expr(value);
+                }
+                self.terminate(Terminator::Return(value.as_deref()));
+            }
+            _ => {
+                self.forbid_yield(expr);
+                self.resolve_expr(expr);
+                self.push(Statement::Eval(expr));
+            }
+        }
+    }
+
+    /// Lowers the statements and the tail of a block within a scope of its own
+    fn lower_scope(&mut self, block: &'ast Block) {
+        self.scopes.push(Vec::new());
+        self.lower_statements(block);
+        if let Some(tail) = &block.tail {
+            self.lower_effect(tail);
+        }
+        self.scopes.pop();
+    }
+
+    /// Reports every `yield` within an expression whose value is used, since a generator can only
+    /// be suspended between statements
+    fn forbid_yield(&mut self, expr: &Expr) {
+        if !self.is_generator {
+            return;
+        }
+        visit(expr, &mut |x| {
+            if let ExprKind::Yield(_) = x.kind {
+                self.diagnostics.push(
+                    Diagnostic::error("`yield` can only be used as a statement")
+                        .with_primary(x.span, "cannot yield within an expression")
+                        .with_note("a generator can only be suspended between statements"),
+                );
+            }
+        });
+    }
+
+    /// Finds the local every name within an expression refers to, declaring the bindings of the
+    /// expressions within it along the way and lowering the closures within it
+    fn resolve_expr(&mut self, expr: &'ast Expr) {
+        match &expr.kind {
+            ExprKind::Name(name) => {
+                if let Some(id) = self.lookup(name.symbol) {
+                    self.body.names.insert(expr.id, id);
+                }
+            }
+            ExprKind::Literal(_) | ExprKind::Path(_) | ExprKind::Error => {}
+            ExprKind::StructLiteral { fields, .. } => {
+                fields.iter().for_each(|x| self.resolve_expr(&x.value))
+            }
+            ExprKind::Unary { operand, .. } | ExprKind::Reference { operand, .. } => {
+                self.resolve_expr(operand)
+            }
+            ExprKind::Binary { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            ExprKind::Assign { target, value, .. } => {
+                self.resolve_expr(target);
+                self.resolve_expr(value);
+            }
+            ExprKind::Tuple(elements) | ExprKind::Array(elements) => {
+                elements.iter().for_each(|x| self.resolve_expr(x))
+            }
+            // The length is worked out while compiling, so it can not name a local
+            ExprKind::Repeat { value, .. } => self.resolve_expr(value),
+            ExprKind::Call { callee, arguments } => {
+                self.resolve_expr(callee);
+                arguments.iter().for_each(|x| self.resolve_expr(x));
+            }
+            ExprKind::Field { object, .. } => self.resolve_expr(object),
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => self.resolve_block(block),
+            ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_expr(else_branch);
+                }
+            }
+            ExprKind::For {
+                pattern,
+                iterable,
+                body,
+                ..
+            } => {
+                self.resolve_expr(iterable);
+                self.scopes.push(Vec::new());
+                self.declare_pattern(pattern);
+                self.resolve_block(body);
+                self.scopes.pop();
+            }
+            ExprKind::When { scrutinee, arms } => {
+                self.resolve_expr(scrutinee);
+                for arm in arms {
+                    self.scopes.push(Vec::new());
+                    self.declare_pattern(&arm.pattern);
+                    if let Some(guard) = &arm.guard {
+                        self.resolve_expr(guard);
+                    }
+                    self.resolve_expr(&arm.body);
+                    self.scopes.pop();
+                }
+            }
+            ExprKind::Return(Some(value)) | ExprKind::Yield(value) => self.resolve_expr(value),
+            ExprKind::Return(None) => {}
+            ExprKind::Closure(function) => self.lower_closure(function),
+        }
+    }
+
+    fn resolve_block(&mut self, block: &'ast Block) {
+        self.scopes.push(Vec::new());
+        for statement in &block.statements {
+            match &statement.kind {
+                StatementKind::Let(let_statement) => {
+                    if let Some(value) = &let_statement.value {
+                        self.resolve_expr(value);
+                    }
+                    self.declare_let(statement.id, let_statement);
+                }
+                StatementKind::Expr(expr) => self.resolve_expr(expr),
+                StatementKind::Error => {}
+            }
+        }
+        if let Some(tail) = &block.tail {
+            self.resolve_expr(tail);
+        }
+        self.scopes.pop();
+    }
+}
+
+/// Checks if an expression contains a `yield` anywhere within it
+pub fn contains_yield(expr: &Expr) -> bool {
+    let mut found = false;
+    visit(expr, &mut |x| found |= matches!(x.kind, ExprKind::Yield(_)));
+    found
+}
+
+/// Calls `f` with an expression and every expression within it. The body of a closure is a
+/// function of its own, so it is not visited
+pub fn visit(expr: &Expr, f: &mut impl FnMut(&Expr)) {
+    f(expr);
+    match &expr.kind {
+        ExprKind::Literal(_)
+        | ExprKind::Name(_)
+        | ExprKind::Path(_)
+        | ExprKind::Return(None)
+        | ExprKind::Closure(_)
+        | ExprKind::Error => {}
+        ExprKind::StructLiteral { fields, .. } => fields.iter().for_each(|x| visit(&x.value, f)),
+        ExprKind::Unary { operand, .. } | ExprKind::Reference { operand, .. } => visit(operand, f),
+        ExprKind::Binary { left, right, .. }
+        | ExprKind::Assign {
+            target: left,
+            value: right,
+            ..
+        } => {
+            visit(left, f);
+            visit(right, f);
+        }
+        ExprKind::Tuple(elements) | ExprKind::Array(elements) => {
+            elements.iter().for_each(|x| visit(x, f))
+        }
+        ExprKind::Repeat { value, length } => {
+            visit(value, f);
+            visit(length, f);
+        }
+        ExprKind::Call { callee, arguments } => {
+            visit(callee, f);
+            arguments.iter().for_each(|x| visit(x, f));
+        }
+        ExprKind::Field { object, .. } => visit(object, f),
+        ExprKind::Block(block) | ExprKind::Unsafe(block) => visit_block(block, f),
+        ExprKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            visit(condition, f);
+            visit_block(then_branch, f);
+            if let Some(else_branch) = else_branch {
+                visit(else_branch, f);
+            }
+        }
+        ExprKind::For { iterable, body, .. } => {
+            visit(iterable, f);
+            visit_block(body, f);
+        }
+        ExprKind::When { scrutinee, arms } => {
+            visit(scrutinee, f);
+            for arm in arms {
+                if let Some(guard) = &arm.guard {
+                    visit(guard, f);
+                }
+                visit(&arm.body, f);
+            }
+        }
+        ExprKind::Return(Some(value)) | ExprKind::Yield(value) => visit(value, f),
+    }
+}
+
+/// Calls `f` with every expression within a block
+pub fn visit_block(block: &Block, f: &mut impl FnMut(&Expr)) {
+    for statement in &block.statements {
+        match &statement.kind {
+            StatementKind::Let(Let {
+                value: Some(value), ..
+            })
+            | StatementKind::Expr(value) => visit(value, f),
+            StatementKind::Let(_) | StatementKind::Error => {}
+        }
+    }
+    if let Some(tail) = &block.tail {
+        visit(tail, f);
+    }
+}
+
+/// Gets the first use of every name the body of a closure uses without declaring it, which are
+/// the locals it captures along with the items it names. The names used by the closures within it
+/// count as its own
+pub fn free_names(function: &Function) -> Vec<&Expr> {
+    let mut names = FreeNames {
+        scopes: Vec::new(),
+        found: Vec::new(),
+    };
+    names.function(function);
+    names.found
+}
+
+struct FreeNames<'ast> {
+    scopes: Vec<Vec<Symbol>>,
+    found: Vec<&'ast Expr>,
+}
+
+impl<'ast> FreeNames<'ast> {
+    fn function(&mut self, function: &'ast Function) {
+        self.scopes
+            .push(function.parameters.iter().map(|x| x.name.symbol).collect());
+        if let Some(body) = &function.body {
+            self.block(body);
+        }
+        self.scopes.pop();
+    }
+
+    fn declare(&mut self, name: Symbol) {
+        self.scopes
+            .last_mut()
+            .expect("a name is always declared within a scope")
+            .push(name);
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match &pattern.kind {
+            PatternKind::Binding { name, .. } => self.declare(name.symbol),
+            PatternKind::Tuple(elements) => elements.iter().for_each(|x| self.pattern(x)),
+            PatternKind::Variant { payload, .. } => payload.iter().for_each(|x| self.pattern(x)),
+            PatternKind::Wildcard | PatternKind::Literal(_) | PatternKind::Range { .. } => {}
+        }
+    }
+
+    fn block(&mut self, block: &'ast Block) {
+        self.scopes.push(Vec::new());
+        for statement in &block.statements {
+            match &statement.kind {
+                StatementKind::Let(let_statement) => {
+                    if let Some(value) = &let_statement.value {
+                        self.expr(value);
+                    }
+                    self.declare(let_statement.name.symbol);
+                }
+                StatementKind::Expr(expr) => self.expr(expr),
+                StatementKind::Error => {}
+            }
+        }
+        if let Some(tail) = &block.tail {
+            self.expr(tail);
+        }
+        self.scopes.pop();
+    }
+
+    fn expr(&mut self, expr: &'ast Expr) {
+        match &expr.kind {
+            ExprKind::Name(name) => {
+                let declared = self.scopes.iter().flatten().any(|x| *x == name.symbol);
+                let found = self.found.iter().any(|x| match x.kind {
+                    ExprKind::Name(found) => found.symbol == name.symbol,
+                    _ => false,
+                });
+                if !declared && !found {
+                    self.found.push(expr);
+                }
+            }
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => self.block(block),
+            ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(condition);
+                self.block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.expr(else_branch);
+                }
+            }
+            ExprKind::For {
+                pattern,
+                iterable,
+                body,
+                ..
+            } => {
+                self.expr(iterable);
+                self.scopes.push(Vec::new());
+                self.pattern(pattern);
+                self.block(body);
+                self.scopes.pop();
+            }
+            ExprKind::When { scrutinee, arms } => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    self.scopes.push(Vec::new());
+                    self.pattern(&arm.pattern);
+                    if let Some(guard) = &arm.guard {
+                        self.expr(guard);
+                    }
+                    self.expr(&arm.body);
+                    self.scopes.pop();
+                }
+            }
+            ExprKind::Closure(function) => self.function(function),
+            ExprKind::Literal(_) | ExprKind::Path(_) | ExprKind::Return(None) | ExprKind::Error => {
+            }
+            ExprKind::StructLiteral { fields, .. } => {
+                fields.iter().for_each(|x| self.expr(&x.value))
+            }
+            ExprKind::Unary { operand, .. } | ExprKind::Reference { operand, .. } => {
+                self.expr(operand)
+            }
+            ExprKind::Binary { left, right, .. }
+            | ExprKind::Assign {
+                target: left,
+                value: right,
+                ..
+            } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Tuple(elements) | ExprKind::Array(elements) => {
+                elements.iter().for_each(|x| self.expr(x))
+            }
+            ExprKind::Repeat { value, .. } => self.expr(value),
+            ExprKind::Call { callee, arguments } => {
+                self.expr(callee);
+                arguments.iter().for_each(|x| self.expr(x));
+            }
+            ExprKind::Field { object, .. } => self.expr(object),
+            ExprKind::Return(Some(value)) | ExprKind::Yield(value) => self.expr(value),
+        }
+    }
+}
@@ -0,0 +1,81 @@
+use shark_parse::parse;
+
+use crate::{dump::dump_body, lower_module};
+
+/// Lowers every function within a module, returning each body as text along with the messages of
+/// the diagnostics
+fn lower(source: &str) -> (Vec<String>, Vec<String>) {
+    let module = parse(None, source).expect("failed to parse module");
+    let (bodies, diagnostics) = lower_module(&module);
+    (
+        bodies.iter().map(dump_body).collect(),
+        diagnostics.iter().map(|x| x.message.clone()).collect(),
+    )
+}
+
+fn lines(lines: &[&str]) -> String {
+    lines.iter().map(|x| format!("{}\n", x)).collect()
+}
+
+#[test]
+fn test_plain_function() {
+    let (bodies, errors) = lower("fun add(a :: Int32, b :: Int32) :: Int32 { let c = a + b; c }");
+    assert!(errors.is_empty());
+    assert_eq!(bodies, ["bb0:\n    let c_2 = (+ a b)\n    ret c\n"]);
+}
+
+#[test]
+fn test_generator() {
+    let (bodies, errors) = lower(
+        "fun evens(numbers :: yield Int32) :: yield Int32 {
+            yield 0;
+            for (i, n) of numbers {
+                if n > i {
+                    yield n;
+                }
+            }
+        }",
+    );
+    assert!(errors.is_empty());
+    let expected = [
+        "bb0:",
+        "    yield 0 resume bb1",
+        "bb1:",
+        "    start <iterator>_1, <counter>_2 = numbers",
+        "    goto bb2",
+        "bb2:",
+        "    next <iterator>_1 counting <counter>_2 (i, n) => bb3, done => bb4",
+        "bb3:",
+        "    if (> n i) then bb5 else bb6",
+        "bb4:",
+        "    ret",
+        "bb5:",
+        "    yield n resume bb8",
+        "bb6:",
+        "    goto bb7",
+        "bb7:",
+        "    goto bb2",
+        "bb8:",
+        "    goto bb7",
+        "states: bb0, bb1, bb8",
+        "saved: numbers_0, <iterator>_1, <counter>_2, i_3, n_4",
+    ];
+    assert_eq!(bodies, [lines(&expected)]);
+}
+
+#[test]
+fn test_yield_in_expression() {
+    let (_, errors) = lower(
+        "fun numbers() :: yield Int32 {
+            let a = { yield 1; 2 };
+            yield yield 3;
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "`yield` can only be used as a statement",
+            "`yield` can only be used as a statement",
+        ]
+    );
+}
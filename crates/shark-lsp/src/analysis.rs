@@ -0,0 +1,934 @@
+//! What the language server knows about a document: its diagnostics, what each name in it refers
+//! to and the outline of its items. A document is checked along with the modules it uses and the
+//! standard library the same way `sharkc` checks a file, merged into one module, and everything is
+//! worked out again whenever it changes
+//!
+//! Values and items are named the way [shark_resolve] resolves them, which it does even for a
+//! document with syntax errors. What resolution leaves for type checking, such as fields, methods,
+//! variants and generic parameters, is found by walking the syntax tree. Types are only known once
+//! the document gets through [shark_sema] without errors
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path as FilePath,
+    sync::OnceLock,
+};
+
+use shark_core::{
+    diagnostic::Diagnostic,
+    source::Span,
+    symbol::{sym, Symbol},
+};
+use shark_lex::token::TokenKind;
+use shark_parse::{
+    ast::{
+        Block, Expr, ExprKind, Function, Generics, Ident, Item, ItemKind, Module, NodeId, Path,
+        Pattern, PatternKind, StatementKind, TypeExpr, TypeExprKind, Visibility,
+    },
+    cst::{NodeKind, SyntaxElement, SyntaxNode},
+    parse_syntax,
+};
+use shark_resolve::{
+    resolve::{Res, Resolutions},
+    tree::{FileLoader, ModuleLoader, ModuleTree},
+};
+use shark_sema::{ty::PrimitiveType, ModuleDefs};
+use shark_typeck::{ty::Ty, TypeckResults};
+
+/// Indexes [Analysis::definitions]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct DefId(pub u32);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefKind {
+    Function,
+    /// A function within a `trait` or `impl`
+    Method,
+    Type,
+    Enum,
+    Variant,
+    Trait,
+    Const,
+    Field,
+    Parameter,
+    /// A binding made by `let` or by a pattern
+    Local,
+    GenericParam,
+    /// An `impl` block, which is never referred to by name but is one of the symbols of a document
+    Impl,
+}
+
+impl DefKind {
+    /// Checks if the definition is an item, which can be named anywhere in the document
+    pub fn is_item(self) -> bool {
+        matches!(
+            self,
+            Self::Function | Self::Type | Self::Enum | Self::Trait | Self::Const
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Definition {
+    pub name: Symbol,
+    pub kind: DefKind,
+    /// The span of the name where it is declared
+    pub span: Span,
+    /// How the definition is declared, such as the signature of a function
+    pub detail: String,
+    /// The comment written on the lines above the declaration
+    pub docs: String,
+    /// Whether the definition is in the document rather than the standard library
+    pub in_document: bool,
+    /// Where a binding or generic parameter can be named, which is [None] for everything else
+    pub scope: Option<Span>,
+}
+
+/// An entry of the outline of a document
+#[derive(Debug, Clone)]
+pub struct DocumentSymbol {
+    pub name: String,
+    pub kind: DefKind,
+    pub detail: String,
+    /// The whole declaration
+    pub span: Span,
+    pub name_span: Span,
+    /// The fields, variants or methods declared within the symbol
+    pub children: Vec<DocumentSymbol>,
+}
+
+pub struct Analysis {
+    /// The diagnostics which point into the document, with the labels pointing into the standard
+    /// library left out
+    pub diagnostics: Vec<Diagnostic>,
+    pub definitions: Vec<Definition>,
+    /// Every name within the document which refers to a definition, including the names at the
+    /// declarations themselves, sorted by where they start
+    pub references: Vec<(Span, DefId)>,
+    pub symbols: Vec<DocumentSymbol>,
+}
+
+impl Analysis {
+    /// Analyses the text of a document, loading the modules it uses from the directory of its
+    /// path
+    pub fn new(path: Option<&FilePath>, text: &str) -> Self {
+        let mut loader: Box<dyn ModuleLoader> = match path.and_then(FilePath::parent) {
+            Some(directory) => Box::new(FileLoader {
+                directory: directory.to_path_buf(),
+            }),
+            None => Box::new(HashMap::<String, String>::new()),
+        };
+        let tree = shark_std::load(path, text, loader.as_mut());
+        let (resolutions, resolved) = shark_resolve::resolve(&tree);
+        let module = &shark_resolve::merge(&tree, &resolutions);
+        let source = tree.text();
+        let (_, syntax, _) = parse_syntax(path, text);
+        let library = tree.module(tree.prelude().expect("the standard library is loaded"));
+        let (_, library_syntax, _) = parse_syntax(None, &library.source);
+
+        // Like the other steps, resolution only reports errors once the document parses
+        let mut diagnostics = tree.module(ModuleTree::ROOT).diagnostics.clone();
+        if !diagnostics.iter().any(Diagnostic::is_error) {
+            let resolved = resolved
+                .into_iter()
+                .filter(|(id, _)| *id == ModuleTree::ROOT);
+            diagnostics.extend(resolved.map(|(_, x)| x));
+        }
+        let checked = match diagnostics.iter().any(Diagnostic::is_error) {
+            true => None,
+            false => check(module, &source, &mut diagnostics),
+        };
+        let diagnostics = diagnostics
+            .into_iter()
+            .filter_map(|x| within_document(x, text.len()))
+            .collect();
+
+        let mut resolver = Resolver::new(&syntax, text, (&library_syntax, library.offset));
+        resolver.resolutions = Some(&resolutions);
+        resolver.types = checked.as_ref().map(|(defs, types)| (defs, types));
+        resolver.collect(module);
+        for item in &module.items {
+            if resolver.in_document(item.span) {
+                resolver.item(item);
+            }
+        }
+        resolver.references.sort_by_key(|(span, _)| span.start);
+        Self {
+            diagnostics,
+            definitions: resolver.definitions,
+            references: resolver.references,
+            symbols: resolver.symbols,
+        }
+    }
+
+    pub fn definition(&self, id: DefId) -> &Definition {
+        &self.definitions[id.0 as usize]
+    }
+
+    /// Gets the name at the offset along with what it refers to. An offset just after a name, where
+    /// a cursor at the end of it would be, also counts
+    pub fn definition_at(&self, offset: usize) -> Option<(Span, &Definition)> {
+        let (span, id) = self
+            .references
+            .iter()
+            .find(|(span, _)| span.contains(offset))
+            .or_else(|| self.references.iter().find(|(span, _)| span.end == offset))?;
+        Some((*span, self.definition(*id)))
+    }
+
+    /// Gets everything which can be named at the offset: the bindings in scope there, innermost
+    /// first, then the items of the document and those of the standard library
+    pub fn names_in_scope(&self, offset: usize) -> Vec<&Definition> {
+        let locals = self
+            .definitions
+            .iter()
+            .rev()
+            .filter(|x| x.scope.is_some_and(|scope| scope.contains(offset)));
+        let items = self
+            .definitions
+            .iter()
+            .filter(|x| x.in_document && x.kind.is_item());
+        let mut seen = HashSet::new();
+        locals
+            .chain(items)
+            .chain(library())
+            .filter(|x| seen.insert(x.name))
+            .collect()
+    }
+}
+
+/// Checks the module, stopping after the first step which finds an error. The results are kept if
+/// the names of the module could be checked, so that types are known even with type errors
+fn check(
+    module: &Module,
+    source: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Option<(ModuleDefs, TypeckResults)> {
+    let mut report = |found: Vec<Diagnostic>| {
+        let errors = found.iter().any(Diagnostic::is_error);
+        diagnostics.extend(found);
+        errors
+    };
+    let (defs, found) = shark_sema::check_module(module);
+    if report(found) {
+        return None;
+    }
+    let (types, found) = shark_typeck::check_module(module, &defs);
+    if !report(found) && !report(shark_borrowck::check_module(module, &defs, &types)) {
+        let (_, found) = shark_const::evaluate(module, &types, source);
+        report(found);
+    }
+    Some((defs, types))
+}
+
+/// Keeps a diagnostic if it points into the document, leaving out its labels which do not. The
+/// document starts the merged module, so anything past its end is in another module
+fn within_document(mut diagnostic: Diagnostic, end: usize) -> Option<Diagnostic> {
+    if diagnostic.primary_span().is_some_and(|x| x.start >= end) {
+        return None;
+    }
+    diagnostic.labels.retain(|x| x.span.start < end);
+    Some(diagnostic)
+}
+
+/// The public items of the whole standard library, which can be completed before the document
+/// uses them and so before they are part of its prelude
+fn library() -> &'static [Definition] {
+    static LIBRARY: OnceLock<Vec<Definition>> = OnceLock::new();
+    LIBRARY.get_or_init(|| {
+        let (module, syntax, _) = parse_syntax(None, shark_std::SOURCE);
+        let mut resolver = Resolver::new(&syntax, "", (&syntax, 0));
+        resolver.collect(&module);
+        module
+            .items
+            .iter()
+            .filter(|x| x.visibility == Visibility::Public)
+            .filter_map(|x| resolver.items.get(&x.kind.name()?.symbol))
+            .map(|x| resolver.definitions[x.0 as usize].clone())
+            .collect()
+    })
+}
+
+/// Writes out a type the way it would be written in Shark, or [None] if it is unknown. Types are
+/// named without the path of the module declaring them, as the document imports them by name
+fn type_name(defs: &ModuleDefs, ty: &Ty) -> Option<String> {
+    let adt = |id| {
+        let name = defs.types.adt(id).name.symbol.as_str();
+        name.rsplit("::").next().unwrap_or(name)
+    };
+    let list = |types: &[Ty]| -> Option<String> {
+        let names: Option<Vec<String>> = types.iter().map(|x| type_name(defs, x)).collect();
+        Some(names?.join(", "))
+    };
+    Some(match ty {
+        Ty::Primitive(primitive) => primitive.to_string(),
+        Ty::Unit => "()".to_string(),
+        Ty::Adt(id, arguments) if arguments.is_empty() => adt(*id).to_string(),
+        Ty::Adt(id, arguments) => format!("{}<{}>", adt(*id), list(arguments)?),
+        Ty::Tuple(elements) if elements.len() == 1 => {
+            format!("({},)", type_name(defs, &elements[0])?)
+        }
+        Ty::Tuple(elements) => format!("({})", list(elements)?),
+        Ty::Reference {
+            kind,
+            mutable,
+            pointee,
+        } => {
+            let mutable = if *mutable { "mut " } else { "" };
+            format!("{} {}{}", kind, mutable, type_name(defs, pointee)?)
+        }
+        Ty::Generator(item) => format!("yield {}", type_name(defs, item)?),
+        Ty::Array(element, length) => format!("[{}; {}]", type_name(defs, element)?, length),
+        Ty::Function {
+            parameters,
+            return_type,
+        } => match **return_type {
+            Ty::Unit => format!("fun({})", list(parameters)?),
+            _ => format!(
+                "fun({}) :: {}",
+                list(parameters)?,
+                type_name(defs, return_type)?
+            ),
+        },
+        Ty::Param(name) => name.to_string(),
+        Ty::Never => "Never".to_string(),
+        Ty::Var(_) | Ty::Error => return None,
+    })
+}
+
+/// Gets the span of a node without the comments and whitespace at its start
+fn declaration_span(node: &SyntaxNode) -> Span {
+    let start = node
+        .tokens()
+        .into_iter()
+        .find(|x| !matches!(x.kind(), TokenKind::Whitespace | TokenKind::Comment(_)))
+        .map_or(node.span().start, |x| x.span().start);
+    Span::new(start, node.span().end)
+}
+
+/// Gets the text of a declaration up to its body, its value or the `;` ending it, on one line
+fn declaration_text(node: &SyntaxNode) -> String {
+    let mut text = String::new();
+    for child in node.children_with_tokens() {
+        let tokens = match child {
+            SyntaxElement::Node(node)
+                if matches!(
+                    node.kind(),
+                    NodeKind::Block
+                        | NodeKind::FieldList
+                        | NodeKind::VariantList
+                        | NodeKind::MethodList
+                ) =>
+            {
+                break
+            }
+            SyntaxElement::Node(node) => node.tokens(),
+            SyntaxElement::Token(token)
+                if matches!(token.kind(), TokenKind::Equal | TokenKind::EOL) =>
+            {
+                break
+            }
+            SyntaxElement::Token(token) => vec![token],
+        };
+        for token in tokens {
+            match token.kind() {
+                TokenKind::Comment(_) => text.push(' '),
+                _ => text.push_str(token.text()),
+            }
+        }
+    }
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+/// Gets the `//` comments on the lines straight above a declaration starting at the offset, if it
+/// is the first thing on its line
+fn docs(source: &str, offset: usize) -> String {
+    let line_start = source[..offset].rfind('\n').map_or(0, |x| x + 1);
+    if !source[line_start..offset].trim().is_empty() {
+        return String::new();
+    }
+    let mut lines: Vec<&str> = source[..line_start]
+        .lines()
+        .rev()
+        .map_while(|x| x.trim().strip_prefix("//"))
+        .map(|x| x.strip_prefix(' ').unwrap_or(x))
+        .collect();
+    lines.reverse();
+    lines.join("\n")
+}
+
+/// Finds the definitions of a module and then what every name within it refers to, taking the
+/// names resolved by [shark_resolve] where there are any
+struct Resolver<'a> {
+    syntax: &'a SyntaxNode,
+    /// The text of the document, which the source of the merged module starts with
+    document: &'a str,
+    /// The syntax tree of the standard library, whose text is the same as [shark_std::SOURCE]
+    library: &'a SyntaxNode,
+    /// Where the standard library starts within the source of the merged module
+    library_start: usize,
+    resolutions: Option<&'a Resolutions>,
+    types: Option<(&'a ModuleDefs, &'a TypeckResults)>,
+    definitions: Vec<Definition>,
+    references: Vec<(Span, DefId)>,
+    symbols: Vec<DocumentSymbol>,
+    /// The definitions of the bindings of the document by the node declaring them, which is how
+    /// [shark_resolve] identifies them
+    nodes: HashMap<NodeId, DefId>,
+    /// The definitions of the items by the name the merged module gives them
+    items: HashMap<Symbol, DefId>,
+    /// The fields and variants of types, and the methods of types and traits, by the name of the
+    /// type or trait they are declared in
+    members: HashMap<(Symbol, Symbol), DefId>,
+    /// The bindings and generic parameters in scope, along with the offset each became visible at
+    scopes: Vec<Vec<(DefId, usize)>>,
+    /// The name of the type or trait whose methods are being resolved, which `Self` stands for
+    owner: Option<Symbol>,
+}
+
+impl<'a> Resolver<'a> {
+    fn new(
+        syntax: &'a SyntaxNode,
+        document: &'a str,
+        (library, library_start): (&'a SyntaxNode, usize),
+    ) -> Self {
+        Self {
+            syntax,
+            document,
+            library,
+            library_start,
+            resolutions: None,
+            types: None,
+            definitions: Vec::new(),
+            references: Vec::new(),
+            symbols: Vec::new(),
+            nodes: HashMap::new(),
+            items: HashMap::new(),
+            members: HashMap::new(),
+            scopes: Vec::new(),
+            owner: None,
+        }
+    }
+
+    fn in_document(&self, span: Span) -> bool {
+        span.start < self.document.len()
+    }
+
+    /// Finds the smallest node covering a span, within the syntax tree of the document or of the
+    /// standard library, along with where that tree starts within the merged module
+    fn covering_node(&self, span: Span) -> (SyntaxNode, usize) {
+        match span.start >= self.library_start {
+            true => {
+                let start = self.library_start;
+                let span = Span::new(span.start - start, span.end - start);
+                (self.library.covering_node(span), start)
+            }
+            false => (self.syntax.covering_node(span), 0),
+        }
+    }
+
+    /// Adds a definition whose name is at the span, describing it by its declaration in the syntax
+    /// tree
+    fn define(&mut self, name: Symbol, span: Span, kind: DefKind) -> DefId {
+        let (node, _) = self.covering_node(span);
+        let start = declaration_span(&node).start;
+        let docs = match span.start >= self.library_start {
+            true => docs(shark_std::SOURCE, start),
+            false => docs(self.document, start),
+        };
+        let detail = declaration_text(&node);
+        self.define_with(name, span, kind, detail, docs)
+    }
+
+    fn define_with(
+        &mut self,
+        name: Symbol,
+        span: Span,
+        kind: DefKind,
+        detail: String,
+        docs: String,
+    ) -> DefId {
+        let id = DefId(self.definitions.len() as u32);
+        self.definitions.push(Definition {
+            name,
+            kind,
+            span,
+            detail,
+            docs,
+            in_document: self.in_document(span),
+            scope: None,
+        });
+        self.refer(span, id);
+        id
+    }
+
+    fn refer(&mut self, span: Span, id: DefId) {
+        if self.in_document(span) {
+            self.references.push((span, id));
+        }
+    }
+
+    /// Adds a binding to the innermost scope, visible from the offset to the end of the scope
+    fn bind(&mut self, id: DefId, visible_from: usize) {
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.push((id, visible_from));
+        }
+    }
+
+    fn pop_scope(&mut self, end: usize) {
+        for (id, start) in self.scopes.pop().unwrap_or_default() {
+            self.definitions[id.0 as usize].scope = Some(Span::new(start.min(end), end));
+        }
+    }
+
+    /// Gets the definition of what [shark_resolve] resolved a name to. Names of items have been
+    /// replaced with the name the merged module gives the item
+    fn resolved(&self, id: NodeId, name: Symbol) -> Option<DefId> {
+        match self.resolutions?.res_of(ModuleTree::ROOT, id)? {
+            Res::Local(node) => self.nodes.get(&node).copied(),
+            Res::Def(..) => self.lookup_item(name),
+            Res::Module(_) => None,
+        }
+    }
+
+    /// Looks up an item by the name the merged module gives it
+    fn lookup_item(&self, name: Symbol) -> Option<DefId> {
+        self.items.get(&name).copied()
+    }
+
+    /// Looks up the name of a type or trait, which is either a generic parameter or an item
+    fn lookup(&self, name: Symbol) -> Option<DefId> {
+        self.scopes
+            .iter()
+            .rev()
+            .flat_map(|x| x.iter().rev())
+            .map(|&(id, _)| id)
+            .find(|id| {
+                let definition = &self.definitions[id.0 as usize];
+                definition.kind == DefKind::GenericParam && definition.name == name
+            })
+            .or_else(|| self.lookup_item(name))
+    }
+
+    /// Adds the definitions of the items of a module, with their fields, variants and methods,
+    /// along with the outline of those within the document. The items of modules other than the
+    /// document and the standard library are left out
+    fn collect(&mut self, module: &Module) {
+        for item in &module.items {
+            if !self.in_document(item.span) && item.span.start < self.library_start {
+                continue;
+            }
+            let symbol = self.collect_item(item);
+            if let Some(symbol) = symbol.filter(|x| self.in_document(x.span)) {
+                self.symbols.push(symbol);
+            }
+        }
+    }
+
+    fn collect_item(&mut self, item: &Item) -> Option<DocumentSymbol> {
+        let (name, kind) = match &item.kind {
+            ItemKind::Function(function) => (function.name, DefKind::Function),
+            ItemKind::Type(type_decl) => (type_decl.name, DefKind::Type),
+            ItemKind::Enum(enum_decl) => (enum_decl.name, DefKind::Enum),
+            ItemKind::Trait(trait_decl) => (trait_decl.name, DefKind::Trait),
+            ItemKind::Const(const_decl) => (const_decl.name, DefKind::Const),
+            ItemKind::Impl(impl_decl) => {
+                let TypeExprKind::Named { name, .. } = impl_decl.self_type.kind else {
+                    return None;
+                };
+                let (node, _) = self.covering_node(impl_decl.self_type.span);
+                let node = node
+                    .ancestors()
+                    .find(|x| x.kind() == NodeKind::ImplDecl)
+                    .unwrap_or(node);
+                let children = self.collect_methods(name.symbol, &impl_decl.methods);
+                return Some(DocumentSymbol {
+                    name: declaration_text(&node),
+                    kind: DefKind::Impl,
+                    detail: String::new(),
+                    span: item.span,
+                    name_span: impl_decl.self_type.span,
+                    children,
+                });
+            }
+            ItemKind::Use(_) | ItemKind::Error => return None,
+        };
+        let id = self.define(name.symbol, name.span, kind);
+        self.items.entry(name.symbol).or_insert(id);
+        let children = match &item.kind {
+            ItemKind::Type(type_decl) => type_decl
+                .fields
+                .iter()
+                .map(|field| {
+                    let id = self.define(field.name.symbol, field.name.span, DefKind::Field);
+                    self.members.insert((name.symbol, field.name.symbol), id);
+                    self.symbol(id, field.span)
+                })
+                .collect(),
+            ItemKind::Enum(enum_decl) => enum_decl
+                .variants
+                .iter()
+                .map(|variant| {
+                    let id = self.define(variant.name.symbol, variant.name.span, DefKind::Variant);
+                    let definition = &mut self.definitions[id.0 as usize];
+                    definition.detail = format!("{}::{}", name.symbol, definition.detail);
+                    self.members.insert((name.symbol, variant.name.symbol), id);
+                    self.symbol(id, variant.span)
+                })
+                .collect(),
+            ItemKind::Trait(trait_decl) => self.collect_methods(name.symbol, &trait_decl.methods),
+            _ => Vec::new(),
+        };
+        let mut symbol = self.symbol(id, item.span);
+        symbol.children = children;
+        Some(symbol)
+    }
+
+    fn collect_methods(&mut self, owner: Symbol, methods: &[Function]) -> Vec<DocumentSymbol> {
+        methods
+            .iter()
+            .map(|method| {
+                let id = self.define(method.name.symbol, method.name.span, DefKind::Method);
+                self.members
+                    .entry((owner, method.name.symbol))
+                    .or_insert(id);
+                let (node, offset) = self.covering_node(method.name.span);
+                let span = declaration_span(&node);
+                self.symbol(id, Span::new(span.start + offset, span.end + offset))
+            })
+            .collect()
+    }
+
+    fn symbol(&self, id: DefId, span: Span) -> DocumentSymbol {
+        let definition = &self.definitions[id.0 as usize];
+        DocumentSymbol {
+            name: definition.name.to_string(),
+            kind: definition.kind,
+            detail: definition.detail.clone(),
+            span,
+            name_span: definition.span,
+            children: Vec::new(),
+        }
+    }
+
+    /// Resolves the names within an item, whose own definitions have already been collected
+    fn item(&mut self, item: &Item) {
+        match &item.kind {
+            ItemKind::Function(function) => self.function(function),
+            ItemKind::Type(type_decl) => {
+                self.scopes.push(Vec::new());
+                self.generics(&type_decl.generics);
+                for field in &type_decl.fields {
+                    self.type_expr(&field.ty);
+                }
+                self.pop_scope(item.span.end);
+            }
+            ItemKind::Enum(enum_decl) => {
+                self.scopes.push(Vec::new());
+                self.generics(&enum_decl.generics);
+                for variant in &enum_decl.variants {
+                    variant.payload.iter().for_each(|x| self.type_expr(x));
+                    if let Some(discriminant) = &variant.discriminant {
+                        self.expr(discriminant);
+                    }
+                }
+                self.pop_scope(item.span.end);
+            }
+            ItemKind::Trait(trait_decl) => {
+                self.owner = Some(trait_decl.name.symbol);
+                trait_decl.methods.iter().for_each(|x| self.function(x));
+                self.owner = None;
+            }
+            ItemKind::Impl(impl_decl) => {
+                self.scopes.push(Vec::new());
+                self.generics(&impl_decl.generics);
+                if let Some(trait_path) = &impl_decl.trait_path {
+                    self.path(trait_path);
+                }
+                self.type_expr(&impl_decl.self_type);
+                if let TypeExprKind::Named { name, .. } = impl_decl.self_type.kind {
+                    self.owner = Some(name.symbol);
+                }
+                impl_decl.methods.iter().for_each(|x| self.function(x));
+                self.owner = None;
+                self.pop_scope(item.span.end);
+            }
+            ItemKind::Const(const_decl) => {
+                self.type_expr(&const_decl.ty);
+                self.expr(&const_decl.value);
+            }
+            ItemKind::Use(_) | ItemKind::Error => {}
+        }
+    }
+
+    fn generics(&mut self, generics: &Generics) {
+        for param in &generics.params {
+            let id = self.define(param.name.symbol, param.name.span, DefKind::GenericParam);
+            self.bind(id, param.span.start);
+            param.bounds.iter().for_each(|x| self.path(x));
+        }
+        for predicate in &generics.where_clause {
+            self.type_expr(&predicate.ty);
+            predicate.bounds.iter().for_each(|x| self.path(x));
+        }
+    }
+
+    fn function(&mut self, function: &Function) {
+        let end = match &function.body {
+            Some(body) => body.span.end,
+            None => function.name.span.end,
+        };
+        self.scopes.push(Vec::new());
+        self.generics(&function.generics);
+        for parameter in &function.parameters {
+            self.type_expr(&parameter.ty);
+            let name = parameter.name;
+            let id = self.define(name.symbol, name.span, DefKind::Parameter);
+            self.nodes.insert(parameter.id, id);
+            self.bind(id, parameter.span.start);
+        }
+        if let Some(return_type) = &function.return_type {
+            self.type_expr(return_type);
+        }
+        if let Some(body) = &function.body {
+            self.block(body);
+        }
+        self.pop_scope(end);
+    }
+
+    fn block(&mut self, block: &Block) {
+        self.scopes.push(Vec::new());
+        for statement in &block.statements {
+            match &statement.kind {
+                StatementKind::Let(binding) => {
+                    if let Some(value) = &binding.value {
+                        self.expr(value);
+                    }
+                    if let Some(ty) = &binding.ty {
+                        self.type_expr(ty);
+                    }
+                    let name = binding.name;
+                    let ty = self.type_of(statement.id).or_else(|| {
+                        let ty = binding.ty.as_ref()?;
+                        Some(declaration_text(&self.covering_node(ty.span).0))
+                    });
+                    let mutable = if binding.mutable { "mut " } else { "" };
+                    let detail = match ty {
+                        Some(ty) => format!("let {}{} :: {}", mutable, name.symbol, ty),
+                        None => format!("let {}{}", mutable, name.symbol),
+                    };
+                    let id = self.define_with(
+                        name.symbol,
+                        name.span,
+                        DefKind::Local,
+                        detail,
+                        String::new(),
+                    );
+                    self.nodes.insert(statement.id, id);
+                    self.bind(id, statement.span.end);
+                }
+                StatementKind::Expr(expr) => self.expr(expr),
+                StatementKind::Error => {}
+            }
+        }
+        if let Some(tail) = &block.tail {
+            self.expr(tail);
+        }
+        self.pop_scope(block.span.end);
+    }
+
+    fn type_of(&self, id: NodeId) -> Option<String> {
+        let (defs, types) = self.types?;
+        type_name(defs, types.type_of(id)?)
+    }
+
+    /// Gets the name of the type or trait whose members can be reached through a value
+    fn owner_of(&self, object: &Expr) -> Option<Symbol> {
+        let (defs, types) = self.types?;
+        let mut ty = types.type_of(object.id)?;
+        while let Ty::Reference { pointee, .. } = ty {
+            ty = pointee;
+        }
+        match ty {
+            Ty::Adt(id, _) => Some(defs.types.adt(*id).name.symbol),
+            Ty::Primitive(primitive) => Some(Symbol::intern(primitive.name())),
+            Ty::Param(name) if *name == sym::SELF_TYPE => self.owner,
+            _ => None,
+        }
+    }
+
+    fn member(&mut self, owner: Option<Symbol>, name: Ident) {
+        if let Some(&id) = owner.and_then(|x| self.members.get(&(x, name.symbol))) {
+            self.refer(name.span, id);
+        }
+    }
+
+    /// Resolves each segment of a path, the first by name and each one after it as a member of
+    /// the one before
+    fn path(&mut self, path: &Path) {
+        for (index, segment) in path.segments.iter().enumerate() {
+            let id = match index {
+                0 => self.lookup(segment.symbol),
+                _ => self
+                    .members
+                    .get(&(path.segments[index - 1].symbol, segment.symbol))
+                    .copied()
+                    .or_else(|| self.lookup_item(segment.symbol)),
+            };
+            if let Some(id) = id {
+                self.refer(segment.span, id);
+            }
+        }
+    }
+
+    fn type_expr(&mut self, ty: &TypeExpr) {
+        match &ty.kind {
+            TypeExprKind::Named { name, arguments } => {
+                let id = match name.symbol {
+                    sym::SELF_TYPE => self.owner.and_then(|x| self.lookup_item(x)),
+                    symbol => self.lookup(symbol),
+                };
+                if let Some(id) = id {
+                    self.refer(name.span, id);
+                }
+                arguments.iter().for_each(|x| self.type_expr(x));
+            }
+            TypeExprKind::Reference { pointee, .. } => self.type_expr(pointee),
+            TypeExprKind::Generator(item) => self.type_expr(item),
+            TypeExprKind::Function {
+                parameters,
+                return_type,
+            } => {
+                parameters.iter().for_each(|x| self.type_expr(x));
+                if let Some(return_type) = return_type {
+                    self.type_expr(return_type);
+                }
+            }
+            TypeExprKind::Array { element, length } => {
+                self.type_expr(element);
+                self.expr(length);
+            }
+            TypeExprKind::Error => {}
+        }
+    }
+
+    fn pattern(&mut self, pattern: &Pattern) {
+        match &pattern.kind {
+            PatternKind::Binding { name, .. } => {
+                let detail = match self.type_of(pattern.id) {
+                    Some(ty) => format!("{} :: {}", name.symbol, ty),
+                    None => name.symbol.to_string(),
+                };
+                let id = self.define_with(
+                    name.symbol,
+                    name.span,
+                    DefKind::Local,
+                    detail,
+                    String::new(),
+                );
+                self.nodes.insert(pattern.id, id);
+                self.bind(id, pattern.span.end);
+            }
+            PatternKind::Tuple(patterns) => patterns.iter().for_each(|x| self.pattern(x)),
+            PatternKind::Variant { path, payload } => {
+                self.path(path);
+                payload.iter().for_each(|x| self.pattern(x));
+            }
+            PatternKind::Wildcard | PatternKind::Literal(_) | PatternKind::Range { .. } => {}
+        }
+    }
+
+    fn expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Literal(_) | ExprKind::Error => {}
+            ExprKind::Name(name) => {
+                if let Some(id) = self.resolved(expr.id, name.symbol) {
+                    self.refer(name.span, id);
+                }
+            }
+            ExprKind::Path(path) => self.path(path),
+            ExprKind::StructLiteral { path, fields } => {
+                self.path(path);
+                for field in fields {
+                    self.member(Some(path.name().symbol), field.name);
+                    self.expr(&field.value);
+                }
+            }
+            ExprKind::Unary { operand, .. } | ExprKind::Reference { operand, .. } => {
+                self.expr(operand)
+            }
+            ExprKind::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Assign { target, value, .. } => {
+                self.expr(target);
+                self.expr(value);
+            }
+            ExprKind::Tuple(elements) | ExprKind::Array(elements) => {
+                elements.iter().for_each(|x| self.expr(x))
+            }
+            ExprKind::Repeat { value, length } => {
+                self.expr(value);
+                self.expr(length);
+            }
+            ExprKind::Call { callee, arguments } => {
+                self.expr(callee);
+                arguments.iter().for_each(|x| self.expr(x));
+            }
+            ExprKind::Field { object, field } => {
+                self.expr(object);
+                let owner = self.owner_of(object);
+                self.member(owner, *field);
+            }
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => self.block(block),
+            ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(condition);
+                self.block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.expr(else_branch);
+                }
+            }
+            ExprKind::For {
+                pattern,
+                iterable,
+                body,
+                ..
+            } => {
+                self.expr(iterable);
+                self.scopes.push(Vec::new());
+                self.pattern(pattern);
+                self.block(body);
+                self.pop_scope(body.span.end);
+            }
+            ExprKind::When { scrutinee, arms } => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    self.scopes.push(Vec::new());
+                    self.pattern(&arm.pattern);
+                    if let Some(guard) = &arm.guard {
+                        self.expr(guard);
+                    }
+                    self.expr(&arm.body);
+                    self.pop_scope(arm.span.end);
+                }
+            }
+            ExprKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            ExprKind::Yield(value) => self.expr(value),
+            ExprKind::Closure(function) => self.function(function),
+        }
+    }
+}
+
+/// Checks if a name which refers to no definition is still a type, such as `Int32` or `Self`
+pub fn is_builtin_type(name: Symbol) -> bool {
+    name == sym::SELF_TYPE || PrimitiveType::from_symbol(name).is_some()
+}
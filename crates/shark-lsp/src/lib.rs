@@ -0,0 +1,47 @@
+//! A language server for Shark, which editors talk to over stdin and stdout using the language
+//! server protocol. It provides:
+//!
+//! - diagnostics, sent whenever a document is opened or changed
+//! - semantic tokens, classifying tokens by their kind and names by what they refer to
+//! - going to the definition of a name, and hovering over one to see its declaration
+//! - the outline of a document's items
+//! - completion of keywords and of the names in scope
+//! - formatting with `shark-fmt`
+
+use std::io::{self, BufRead, Write};
+
+use serde_json::Value;
+
+pub mod analysis;
+pub mod position;
+pub mod rpc;
+pub mod semantic;
+pub mod server;
+
+#[cfg(test)]
+pub mod tests;
+
+pub use server::Server;
+
+/// Serves the messages read from `input` until the client says to exit or the input ends, writing
+/// replies to `output`. Gives whether the client asked the server to shut down before exiting
+pub fn run(mut input: impl BufRead, mut output: impl Write) -> io::Result<bool> {
+    let mut server = Server::new();
+    while let Some(body) = rpc::read_message(&mut input)? {
+        let replies = match serde_json::from_str(&body) {
+            Ok(message) => server.handle(message),
+            Err(error) => vec![rpc::error_response(
+                Value::Null,
+                rpc::PARSE_ERROR,
+                error.to_string(),
+            )],
+        };
+        for reply in &replies {
+            rpc::write_message(&mut output, reply)?;
+        }
+        if server.has_exited() {
+            break;
+        }
+    }
+    Ok(server.exited_cleanly())
+}
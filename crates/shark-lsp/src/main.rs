@@ -0,0 +1,12 @@
+use std::{io, process::ExitCode};
+
+fn main() -> ExitCode {
+    match shark_lsp::run(io::stdin().lock(), io::stdout().lock()) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(error) => {
+            eprintln!("error: {}", error);
+            ExitCode::FAILURE
+        }
+    }
+}
@@ -0,0 +1,74 @@
+//! Converts between byte offsets and the positions of the language server protocol, which count
+//! lines from zero and columns in UTF-16 code units
+
+use serde_json::{json, Value};
+use shark_core::source::{LineIndex, Span};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Position {
+    pub line: u32,
+    pub character: u32,
+}
+
+impl Position {
+    pub fn to_json(self) -> Value {
+        json!({ "line": self.line, "character": self.character })
+    }
+
+    /// Reads a position from the JSON of a request
+    pub fn from_json(value: &Value) -> Option<Self> {
+        Some(Self {
+            line: value.get("line")?.as_u64()?.try_into().ok()?,
+            character: value.get("character")?.as_u64()?.try_into().ok()?,
+        })
+    }
+}
+
+pub struct Positions<'source> {
+    source: &'source str,
+    lines: LineIndex<'source>,
+}
+
+impl<'source> Positions<'source> {
+    pub fn new(source: &'source str) -> Self {
+        Self {
+            source,
+            lines: LineIndex::new(source),
+        }
+    }
+
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.source.len());
+        let (line, _) = self.lines.line_column(offset);
+        let start = self.lines.offset(line - 1, 0);
+        Position {
+            line: (line - 1) as u32,
+            character: self.source[start..offset].encode_utf16().count() as u32,
+        }
+    }
+
+    /// Gets the byte offset of a position, clamping it to the end of its line
+    pub fn offset(&self, position: Position) -> usize {
+        let line = position.line as usize;
+        if line >= self.lines.line_count() {
+            return self.source.len();
+        }
+        let start = self.lines.offset(line, 0);
+        let mut units = 0;
+        for (index, character) in self.lines.line_text(line + 1).char_indices() {
+            if units >= position.character as usize {
+                return start + index;
+            }
+            units += character.len_utf16();
+        }
+        start + self.lines.line_text(line + 1).len()
+    }
+
+    /// Gets the JSON of the range a [Span] covers
+    pub fn range(&self, span: Span) -> Value {
+        json!({
+            "start": self.position(span.start).to_json(),
+            "end": self.position(span.end).to_json(),
+        })
+    }
+}
@@ -0,0 +1,186 @@
+//! Semantic tokens, which let an editor colour a document by what each token is. Tokens are
+//! classified by their [TokenKind] and [KeywordKind], and names by the [DefKind] of what they
+//! refer to
+
+use shark_core::source::Span;
+use shark_lex::token::{KeywordKind, LiteralKind, TokenKind};
+use shark_parse::{cst::NodeKind, parse_syntax};
+
+use crate::{
+    analysis::{is_builtin_type, Analysis, DefKind},
+    position::Positions,
+};
+
+/// The token types the server uses, in the order of the legend it sends at initialisation
+pub const TOKEN_TYPES: &[&str] = &[
+    "keyword",
+    "modifier",
+    "type",
+    "enum",
+    "enumMember",
+    "function",
+    "method",
+    "parameter",
+    "variable",
+    "property",
+    "typeParameter",
+    "interface",
+    "number",
+    "string",
+    "comment",
+    "operator",
+];
+
+/// The token modifiers the server uses, each one a bit of the modifiers of a token in this order
+pub const TOKEN_MODIFIERS: &[&str] = &["declaration", "readonly"];
+
+const DECLARATION: u32 = 1 << 0;
+const READONLY: u32 = 1 << 1;
+
+/// Gets the index of a token type within [TOKEN_TYPES]
+fn token_type(name: &str) -> u32 {
+    TOKEN_TYPES
+        .iter()
+        .position(|x| *x == name)
+        .expect("the token type is in the legend") as u32
+}
+
+fn def_token_type(kind: DefKind) -> &'static str {
+    match kind {
+        DefKind::Function => "function",
+        DefKind::Method => "method",
+        DefKind::Type | DefKind::Impl => "type",
+        DefKind::Enum => "enum",
+        DefKind::Variant => "enumMember",
+        DefKind::Trait => "interface",
+        DefKind::Const | DefKind::Local => "variable",
+        DefKind::Field => "property",
+        DefKind::Parameter => "parameter",
+        DefKind::GenericParam => "typeParameter",
+    }
+}
+
+fn is_operator(kind: TokenKind) -> bool {
+    matches!(
+        kind,
+        TokenKind::Plus
+            | TokenKind::Minus
+            | TokenKind::Multiply
+            | TokenKind::Divide
+            | TokenKind::PlusAssign
+            | TokenKind::MinusAssign
+            | TokenKind::MultiplyAssign
+            | TokenKind::DivideAssign
+            | TokenKind::Greater
+            | TokenKind::Lesser
+            | TokenKind::Or
+            | TokenKind::Not
+            | TokenKind::And
+            | TokenKind::Equal
+            | TokenKind::GreaterOrEqual
+            | TokenKind::LessOrEqual
+            | TokenKind::NotEqual
+            | TokenKind::EqualTo
+            | TokenKind::ShiftRight
+            | TokenKind::ShiftLeft
+            | TokenKind::BitwiseAnd
+            | TokenKind::DotDot
+            | TokenKind::DotDotEqual
+    )
+}
+
+/// A classified token, before it is encoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SemanticToken {
+    pub span: Span,
+    pub token_type: u32,
+    pub modifiers: u32,
+}
+
+/// Classifies every token of the document which has a token type. The tokens are taken from the
+/// syntax tree so that the brackets of generics are not mistaken for operators
+pub fn classify(text: &str, analysis: &Analysis) -> Vec<SemanticToken> {
+    let (_, syntax, _) = parse_syntax(None, text);
+    let mut references = analysis.references.iter().peekable();
+    let mut result = Vec::new();
+    for token in syntax.tokens() {
+        let span = token.span();
+        let (name, modifiers) = match token.kind() {
+            // Keywords which change how a declaration or type behaves rather than what it is
+            TokenKind::Keyword(
+                KeywordKind::Pub | KeywordKind::Extern | KeywordKind::Mut | KeywordKind::Unsafe,
+            ) => ("modifier", 0),
+            TokenKind::Keyword(_) | TokenKind::Literal(LiteralKind::Boolean(_)) => ("keyword", 0),
+            TokenKind::Literal(LiteralKind::Str(_) | LiteralKind::Char(_)) => ("string", 0),
+            TokenKind::Literal(_) => ("number", 0),
+            TokenKind::Comment(_) => ("comment", 0),
+            TokenKind::Identifier(symbol) => {
+                while references.next_if(|(x, _)| x.start < span.start).is_some() {}
+                match references.peek() {
+                    Some((reference, id)) if *reference == span => {
+                        let definition = analysis.definition(*id);
+                        let mut modifiers = 0;
+                        if definition.span == span && definition.in_document {
+                            modifiers |= DECLARATION;
+                        }
+                        if definition.kind == DefKind::Const {
+                            modifiers |= READONLY;
+                        }
+                        (def_token_type(definition.kind), modifiers)
+                    }
+                    _ if is_builtin_type(symbol) => ("type", 0),
+                    _ => continue,
+                }
+            }
+            TokenKind::Lesser | TokenKind::Greater | TokenKind::ShiftRight
+                if matches!(
+                    token.parent().kind(),
+                    NodeKind::GenericParamList | NodeKind::TypeArgumentList
+                ) =>
+            {
+                continue
+            }
+            kind if is_operator(kind) => ("operator", 0),
+            _ => continue,
+        };
+        result.push(SemanticToken {
+            span,
+            token_type: token_type(name),
+            modifiers,
+        });
+    }
+    result
+}
+
+/// Encodes tokens the way the protocol sends them: five numbers per token, giving its line and
+/// start relative to the token before it, its length, its type and its modifiers. Tokens spanning
+/// several lines are split into one per line
+pub fn encode(text: &str, tokens: &[SemanticToken]) -> Vec<u32> {
+    let positions = Positions::new(text);
+    let mut data = Vec::with_capacity(tokens.len() * 5);
+    let (mut previous_line, mut previous_start) = (0, 0);
+    for token in tokens {
+        let mut offset = token.span.start;
+        for line in text[token.span.start..token.span.end].split_inclusive('\n') {
+            let length = line.trim_end_matches(['\n', '\r']).encode_utf16().count() as u32;
+            let start = positions.position(offset);
+            offset += line.len();
+            if length == 0 {
+                continue;
+            }
+            let delta_start = match start.line == previous_line {
+                true => start.character - previous_start,
+                false => start.character,
+            };
+            data.extend([
+                start.line - previous_line,
+                delta_start,
+                length,
+                token.token_type,
+                token.modifiers,
+            ]);
+            (previous_line, previous_start) = (start.line, start.character);
+        }
+    }
+    data
+}
@@ -0,0 +1,444 @@
+//! The state of the server and how it answers each message. Clients send the whole text of a
+//! document whenever it changes, and the document is analysed again each time
+
+use std::{collections::HashMap, path::PathBuf};
+
+use serde_json::{json, Value};
+use shark_core::{
+    diagnostic::{Diagnostic, Severity},
+    source::Span,
+};
+use shark_fmt::Config;
+use shark_lex::token::KeywordKind;
+
+use crate::{
+    analysis::{Analysis, DefKind, DocumentSymbol},
+    position::{Position, Positions},
+    rpc::{self, INVALID_PARAMS, INVALID_REQUEST, METHOD_NOT_FOUND, SERVER_NOT_INITIALIZED},
+    semantic::{self, TOKEN_MODIFIERS, TOKEN_TYPES},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Waiting for `initialize`
+    Uninitialized,
+    Running,
+    /// `shutdown` has been received, so only `exit` is expected
+    ShuttingDown,
+    Exited,
+}
+
+struct Document {
+    text: String,
+    analysis: Analysis,
+}
+
+impl Document {
+    fn new(uri: &str, text: String) -> Self {
+        let analysis = Analysis::new(uri_to_path(uri).as_deref(), &text);
+        Self { text, analysis }
+    }
+}
+
+/// Why a request failed, which is sent back as the error of its response
+struct Failure {
+    code: i64,
+    message: String,
+}
+
+impl Failure {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+pub struct Server {
+    state: State,
+    /// Whether `shutdown` came before `exit`, which is when the server should exit successfully
+    shut_down: bool,
+    /// The open documents by their URI
+    documents: HashMap<String, Document>,
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Server {
+    pub fn new() -> Self {
+        Self {
+            state: State::Uninitialized,
+            shut_down: false,
+            documents: HashMap::new(),
+        }
+    }
+
+    pub fn has_exited(&self) -> bool {
+        self.state == State::Exited
+    }
+
+    /// Checks if the server was asked to shut down before it was told to exit
+    pub fn exited_cleanly(&self) -> bool {
+        self.has_exited() && self.shut_down
+    }
+
+    /// Handles a message from the client, giving the messages to send back
+    pub fn handle(&mut self, message: Value) -> Vec<Value> {
+        let Some(method) = message.get("method").and_then(Value::as_str) else {
+            // A response to a request from the server, which never sends any
+            return Vec::new();
+        };
+        let params = message.get("params").unwrap_or(&Value::Null);
+        match message.get("id") {
+            Some(id) => vec![self.request(id.clone(), method, params)],
+            None => self.notification(method, params),
+        }
+    }
+
+    fn request(&mut self, id: Value, method: &str, params: &Value) -> Value {
+        let result = match (self.state, method) {
+            (State::Uninitialized, "initialize") => {
+                self.state = State::Running;
+                Ok(initialize_result())
+            }
+            (State::Uninitialized, _) => Err(Failure::new(
+                SERVER_NOT_INITIALIZED,
+                "the server has not been initialized",
+            )),
+            (State::ShuttingDown | State::Exited, _) => {
+                Err(Failure::new(INVALID_REQUEST, "the server is shutting down"))
+            }
+            (State::Running, "initialize") => Err(Failure::new(
+                INVALID_REQUEST,
+                "the server has already been initialized",
+            )),
+            (State::Running, "shutdown") => {
+                self.state = State::ShuttingDown;
+                self.shut_down = true;
+                Ok(Value::Null)
+            }
+            (State::Running, "textDocument/semanticTokens/full") => self.semantic_tokens(params),
+            (State::Running, "textDocument/definition") => self.definition(params),
+            (State::Running, "textDocument/hover") => self.hover(params),
+            (State::Running, "textDocument/documentSymbol") => self.document_symbols(params),
+            (State::Running, "textDocument/completion") => self.completion(params),
+            (State::Running, "textDocument/formatting") => self.formatting(params),
+            (State::Running, method) => Err(Failure::new(
+                METHOD_NOT_FOUND,
+                format!("unknown method `{}`", method),
+            )),
+        };
+        match result {
+            Ok(result) => rpc::response(id, result),
+            Err(failure) => rpc::error_response(id, failure.code, failure.message),
+        }
+    }
+
+    fn notification(&mut self, method: &str, params: &Value) -> Vec<Value> {
+        if method == "exit" {
+            self.state = State::Exited;
+            return Vec::new();
+        }
+        if self.state != State::Running {
+            return Vec::new();
+        }
+        let uri = params["textDocument"]["uri"].as_str().unwrap_or_default();
+        match method {
+            "textDocument/didOpen" => {
+                let Some(text) = params["textDocument"]["text"].as_str() else {
+                    return Vec::new();
+                };
+                let document = Document::new(uri, text.to_string());
+                let diagnostics = publish_diagnostics(uri, &document);
+                self.documents.insert(uri.to_string(), document);
+                vec![diagnostics]
+            }
+            "textDocument/didChange" => {
+                // Every change holds the whole text, so only the last one matters
+                let changes = params["contentChanges"].as_array();
+                let Some(text) = changes
+                    .and_then(|x| x.last())
+                    .and_then(|x| x["text"].as_str())
+                else {
+                    return Vec::new();
+                };
+                let document = Document::new(uri, text.to_string());
+                let diagnostics = publish_diagnostics(uri, &document);
+                self.documents.insert(uri.to_string(), document);
+                vec![diagnostics]
+            }
+            "textDocument/didClose" => {
+                self.documents.remove(uri);
+                vec![rpc::notification(
+                    "textDocument/publishDiagnostics",
+                    json!({ "uri": uri, "diagnostics": [] }),
+                )]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Gets the URI and the open document a request is about
+    fn document<'a>(&'a self, params: &'a Value) -> Result<(&'a str, &'a Document), Failure> {
+        let uri = params["textDocument"]["uri"]
+            .as_str()
+            .ok_or_else(|| Failure::new(INVALID_PARAMS, "expected a text document"))?;
+        let document = self
+            .documents
+            .get(uri)
+            .ok_or_else(|| Failure::new(INVALID_PARAMS, format!("`{}` is not open", uri)))?;
+        Ok((uri, document))
+    }
+
+    /// Gets the URI and the open document a request is about, along with the offset of the
+    /// position it is for
+    fn document_at<'a>(
+        &'a self,
+        params: &'a Value,
+    ) -> Result<(&'a str, &'a Document, usize), Failure> {
+        let (uri, document) = self.document(params)?;
+        let position = Position::from_json(&params["position"])
+            .ok_or_else(|| Failure::new(INVALID_PARAMS, "expected a position"))?;
+        let offset = Positions::new(&document.text).offset(position);
+        Ok((uri, document, offset))
+    }
+
+    fn semantic_tokens(&self, params: &Value) -> Result<Value, Failure> {
+        let (_, document) = self.document(params)?;
+        let tokens = semantic::classify(&document.text, &document.analysis);
+        Ok(json!({ "data": semantic::encode(&document.text, &tokens) }))
+    }
+
+    fn definition(&self, params: &Value) -> Result<Value, Failure> {
+        let (uri, document, offset) = self.document_at(params)?;
+        let positions = Positions::new(&document.text);
+        Ok(match document.analysis.definition_at(offset) {
+            Some((_, definition)) if definition.in_document => {
+                json!({ "uri": uri, "range": positions.range(definition.span) })
+            }
+            _ => Value::Null,
+        })
+    }
+
+    fn hover(&self, params: &Value) -> Result<Value, Failure> {
+        let (_, document, offset) = self.document_at(params)?;
+        let Some((span, definition)) = document.analysis.definition_at(offset) else {
+            return Ok(Value::Null);
+        };
+        let mut contents = format!("```shark\n{}\n```", definition.detail);
+        if !definition.docs.is_empty() {
+            contents.push_str("\n\n");
+            contents.push_str(&definition.docs);
+        }
+        Ok(json!({
+            "contents": { "kind": "markdown", "value": contents },
+            "range": Positions::new(&document.text).range(span),
+        }))
+    }
+
+    fn document_symbols(&self, params: &Value) -> Result<Value, Failure> {
+        let (_, document) = self.document(params)?;
+        let positions = Positions::new(&document.text);
+        let symbols: Vec<Value> = document
+            .analysis
+            .symbols
+            .iter()
+            .map(|x| symbol_json(&positions, x))
+            .collect();
+        Ok(Value::Array(symbols))
+    }
+
+    fn completion(&self, params: &Value) -> Result<Value, Failure> {
+        let (_, document, offset) = self.document_at(params)?;
+        let mut items: Vec<Value> = document
+            .analysis
+            .names_in_scope(offset)
+            .into_iter()
+            .map(|x| {
+                json!({
+                    "label": x.name.as_str(),
+                    "kind": completion_kind(x.kind),
+                    "detail": x.detail,
+                })
+            })
+            .collect();
+        let keywords = KeywordKind::ALL.iter().map(|x| x.symbol().as_str());
+        for keyword in keywords.chain(["true", "false"]) {
+            // Keywords are completed with the kind `Keyword`
+            items.push(json!({ "label": keyword, "kind": 14 }));
+        }
+        Ok(Value::Array(items))
+    }
+
+    fn formatting(&self, params: &Value) -> Result<Value, Failure> {
+        let (uri, document) = self.document(params)?;
+        let path = uri_to_path(uri);
+        let config = path
+            .as_deref()
+            .and_then(Config::find)
+            .and_then(|x| std::fs::read_to_string(x).ok())
+            .and_then(|x| Config::parse(&x).ok())
+            .unwrap_or_default();
+        let Ok(formatted) = shark_fmt::format(path.as_deref(), &document.text, &config) else {
+            // A document with syntax errors is left alone, and the errors are already shown
+            return Ok(Value::Null);
+        };
+        if formatted == document.text {
+            return Ok(json!([]));
+        }
+        let whole = Span::new(0, document.text.len());
+        Ok(json!([{
+            "range": Positions::new(&document.text).range(whole),
+            "newText": formatted,
+        }]))
+    }
+}
+
+fn initialize_result() -> Value {
+    json!({
+        "capabilities": {
+            // Documents are sent in full on every change
+            "textDocumentSync": 1,
+            "semanticTokensProvider": {
+                "legend": { "tokenTypes": TOKEN_TYPES, "tokenModifiers": TOKEN_MODIFIERS },
+                "full": true,
+            },
+            "definitionProvider": true,
+            "hoverProvider": true,
+            "documentSymbolProvider": true,
+            "completionProvider": {},
+            "documentFormattingProvider": true,
+        },
+        "serverInfo": { "name": "shark-lsp", "version": env!("CARGO_PKG_VERSION") },
+    })
+}
+
+fn publish_diagnostics(uri: &str, document: &Document) -> Value {
+    let positions = Positions::new(&document.text);
+    let diagnostics: Vec<Value> = document
+        .analysis
+        .diagnostics
+        .iter()
+        .map(|x| diagnostic_json(uri, &positions, x))
+        .collect();
+    rpc::notification(
+        "textDocument/publishDiagnostics",
+        json!({ "uri": uri, "diagnostics": diagnostics }),
+    )
+}
+
+fn diagnostic_json(uri: &str, positions: &Positions, diagnostic: &Diagnostic) -> Value {
+    let severity = match diagnostic.severity {
+        Severity::Error => 1,
+        Severity::Warning => 2,
+        Severity::Note => 3,
+    };
+    let mut message = diagnostic.message.clone();
+    let primary = diagnostic.labels.iter().find(|x| x.primary);
+    if let Some(label) = primary.filter(|x| !x.message.is_empty()) {
+        message = format!("{}\n{}", message, label.message);
+    }
+    for note in &diagnostic.notes {
+        message = format!("{}\nnote: {}", message, note);
+    }
+    let related: Vec<Value> = diagnostic
+        .labels
+        .iter()
+        .filter(|x| !x.primary)
+        .map(|x| {
+            json!({
+                "location": { "uri": uri, "range": positions.range(x.span) },
+                "message": x.message,
+            })
+        })
+        .collect();
+    // Diagnostics about the file as a whole are shown at its start
+    let span = diagnostic.primary_span().unwrap_or_default();
+    json!({
+        "range": positions.range(span),
+        "severity": severity,
+        "source": "shark",
+        "message": message,
+        "relatedInformation": related,
+    })
+}
+
+fn symbol_json(positions: &Positions, symbol: &DocumentSymbol) -> Value {
+    json!({
+        "name": symbol.name,
+        "detail": symbol.detail,
+        "kind": symbol_kind(symbol.kind),
+        "range": positions.range(symbol.span),
+        "selectionRange": positions.range(symbol.name_span),
+        "children": symbol
+            .children
+            .iter()
+            .map(|x| symbol_json(positions, x))
+            .collect::<Vec<_>>(),
+    })
+}
+
+/// Gets the `SymbolKind` of the protocol for a definition
+fn symbol_kind(kind: DefKind) -> u32 {
+    match kind {
+        DefKind::Impl => 3,
+        DefKind::Method => 6,
+        DefKind::Field => 8,
+        DefKind::Enum => 10,
+        DefKind::Trait => 11,
+        DefKind::Function => 12,
+        DefKind::Parameter | DefKind::Local => 13,
+        DefKind::Const => 14,
+        DefKind::Variant => 22,
+        DefKind::Type => 23,
+        DefKind::GenericParam => 26,
+    }
+}
+
+/// Gets the `CompletionItemKind` of the protocol for a definition
+fn completion_kind(kind: DefKind) -> u32 {
+    match kind {
+        DefKind::Method => 2,
+        DefKind::Function => 3,
+        DefKind::Field => 5,
+        DefKind::Parameter | DefKind::Local => 6,
+        DefKind::Trait => 8,
+        DefKind::Impl => 9,
+        DefKind::Enum => 13,
+        DefKind::Variant => 20,
+        DefKind::Const => 21,
+        DefKind::Type => 22,
+        DefKind::GenericParam => 25,
+    }
+}
+
+/// Gets the path of a `file://` URI, which is where its modules are loaded and its `sharkfmt.toml`
+/// is searched from
+fn uri_to_path(uri: &str) -> Option<PathBuf> {
+    let path = uri.strip_prefix("file://")?;
+    let bytes = path.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let escaped = bytes
+            .get(index + 1..index + 3)
+            .and_then(|x| std::str::from_utf8(x).ok())
+            .and_then(|x| u8::from_str_radix(x, 16).ok());
+        match (bytes[index], escaped) {
+            (b'%', Some(byte)) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            (byte, _) => {
+                decoded.push(byte);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).ok().map(PathBuf::from)
+}
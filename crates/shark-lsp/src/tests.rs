@@ -0,0 +1,591 @@
+use serde_json::{json, Value};
+
+use crate::{
+    position::{Position, Positions},
+    rpc,
+    semantic::TOKEN_TYPES,
+    Server,
+};
+
+const URI: &str = "file:///project/main.shark";
+
+const PROGRAM: &str = "type Point {
+    x :: Int32,
+    y :: Int32,
+}
+
+// Adds up the coordinates
+fun length(p :: Point) :: Int32 {
+    p.x + p.y
+}
+
+pub fun main() {
+    let origin = Point { x = 1, y = 2 };
+    println(\"{}\", length(origin));
+}
+";
+
+/// Talks to a [Server] the way an editor would, without going through stdin and stdout
+struct Client {
+    server: Server,
+    next_id: i64,
+}
+
+impl Client {
+    /// Starts a server and initializes it
+    fn new() -> Self {
+        let mut client = Self {
+            server: Server::new(),
+            next_id: 0,
+        };
+        let result = client.request("initialize", json!({ "capabilities": {} }));
+        assert_eq!(result["serverInfo"]["name"], "shark-lsp");
+        assert!(client.notify("initialized", json!({})).is_empty());
+        client
+    }
+
+    /// Sends a request, giving the whole response
+    fn send(&mut self, method: &str, params: Value) -> Value {
+        self.next_id += 1;
+        let replies = self.server.handle(json!({
+            "jsonrpc": "2.0",
+            "id": self.next_id,
+            "method": method,
+            "params": params,
+        }));
+        assert_eq!(replies.len(), 1, "{:?}", replies);
+        assert_eq!(replies[0]["id"], self.next_id);
+        replies[0].clone()
+    }
+
+    /// Sends a request, giving its result and failing if it was an error
+    fn request(&mut self, method: &str, params: Value) -> Value {
+        let response = self.send(method, params);
+        assert!(response.get("error").is_none(), "{}", response);
+        response["result"].clone()
+    }
+
+    fn notify(&mut self, method: &str, params: Value) -> Vec<Value> {
+        self.server
+            .handle(json!({ "jsonrpc": "2.0", "method": method, "params": params }))
+    }
+
+    /// Opens the document, giving the diagnostics published for it
+    fn open(&mut self, text: &str) -> Vec<Value> {
+        let replies = self.notify(
+            "textDocument/didOpen",
+            json!({
+                "textDocument": { "uri": URI, "languageId": "shark", "version": 1, "text": text },
+            }),
+        );
+        published(&replies)
+    }
+
+    fn change(&mut self, text: &str) -> Vec<Value> {
+        let replies = self.notify(
+            "textDocument/didChange",
+            json!({
+                "textDocument": { "uri": URI, "version": 2 },
+                "contentChanges": [{ "text": text }],
+            }),
+        );
+        published(&replies)
+    }
+
+    /// Sends a request about the position of the `nth` occurrence of `needle` in `text`
+    fn at(&mut self, method: &str, text: &str, needle: &str, nth: usize) -> Value {
+        let offset = text
+            .match_indices(needle)
+            .nth(nth)
+            .expect("the needle is in the text")
+            .0;
+        let position = Positions::new(text).position(offset).to_json();
+        self.request(
+            method,
+            json!({ "textDocument": { "uri": URI }, "position": position }),
+        )
+    }
+}
+
+/// Gets the diagnostics from the only notification sent
+fn published(replies: &[Value]) -> Vec<Value> {
+    assert_eq!(replies.len(), 1, "{:?}", replies);
+    assert_eq!(replies[0]["method"], "textDocument/publishDiagnostics");
+    assert_eq!(replies[0]["params"]["uri"], URI);
+    replies[0]["params"]["diagnostics"]
+        .as_array()
+        .expect("diagnostics are an array")
+        .clone()
+}
+
+fn range(start: (u32, u32), end: (u32, u32)) -> Value {
+    json!({
+        "start": { "line": start.0, "character": start.1 },
+        "end": { "line": end.0, "character": end.1 },
+    })
+}
+
+#[test]
+fn test_lifecycle() {
+    let mut server = Server::new();
+    let replies = server.handle(json!({ "jsonrpc": "2.0", "id": 1, "method": "shutdown" }));
+    assert_eq!(replies[0]["error"]["code"], rpc::SERVER_NOT_INITIALIZED);
+
+    let mut client = Client::new();
+    let response = client.send("initialize", json!({}));
+    assert_eq!(response["error"]["code"], rpc::INVALID_REQUEST);
+    let response = client.send("textDocument/rename", json!({}));
+    assert_eq!(response["error"]["code"], rpc::METHOD_NOT_FOUND);
+    let response = client.send(
+        "textDocument/hover",
+        json!({ "textDocument": { "uri": "file:///closed.shark" } }),
+    );
+    assert_eq!(response["error"]["code"], rpc::INVALID_PARAMS);
+
+    assert!(client.request("shutdown", Value::Null).is_null());
+    let response = client.send("textDocument/documentSymbol", json!({}));
+    assert_eq!(response["error"]["code"], rpc::INVALID_REQUEST);
+    assert!(!client.server.has_exited());
+    client.notify("exit", Value::Null);
+    assert!(client.server.exited_cleanly());
+}
+
+#[test]
+fn test_capabilities() {
+    let mut server = Server::new();
+    let replies = server.handle(json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize" }));
+    let capabilities = &replies[0]["result"]["capabilities"];
+    assert_eq!(capabilities["textDocumentSync"], 1);
+    let legend = &capabilities["semanticTokensProvider"]["legend"];
+    assert_eq!(
+        legend["tokenTypes"].as_array().map(Vec::len),
+        Some(TOKEN_TYPES.len())
+    );
+    for provider in [
+        "definitionProvider",
+        "hoverProvider",
+        "documentSymbolProvider",
+        "documentFormattingProvider",
+    ] {
+        assert_eq!(capabilities[provider], true, "{}", provider);
+    }
+    assert!(capabilities["completionProvider"].is_object());
+}
+
+#[test]
+fn test_diagnostics() {
+    let mut client = Client::new();
+    assert_eq!(client.open(PROGRAM), Vec::<Value>::new());
+
+    let diagnostics = client.change("pub fun main() {\n    let x :: Int32 = \"one\";\n}\n");
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert_eq!(diagnostics[0]["severity"], 1);
+    assert_eq!(diagnostics[0]["source"], "shark");
+    assert_eq!(diagnostics[0]["range"], range((1, 21), (1, 26)));
+
+    let diagnostics = client.change("pub fun main() {\n    let x = ;\n}\n");
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert!(diagnostics[0]["message"]
+        .as_str()
+        .is_some_and(|x| x.starts_with("expected an expression, found `;`")));
+    assert_eq!(diagnostics[0]["range"], range((1, 12), (1, 13)));
+
+    let diagnostics = client.change("pub fun main() {\n    let value = undefined_thing;\n}\n");
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert_eq!(
+        diagnostics[0]["message"],
+        "unresolved name `undefined_thing`\nnot found in this scope"
+    );
+    assert_eq!(diagnostics[0]["range"], range((1, 16), (1, 31)));
+
+    let diagnostics = client.change(
+        "fun double(n :: Int32) :: Int32 {\n    n * 2\n}\n\npub fun main() {\n    dobule(1);\n}\n",
+    );
+    assert_eq!(diagnostics.len(), 1, "{:?}", diagnostics);
+    assert_eq!(
+        diagnostics[0]["message"],
+        "unresolved name `dobule`\nnot found in this scope\nnote: did you mean `double`?"
+    );
+
+    assert_eq!(client.change(PROGRAM), Vec::<Value>::new());
+    let replies = client.notify(
+        "textDocument/didClose",
+        json!({ "textDocument": { "uri": URI } }),
+    );
+    assert_eq!(published(&replies), Vec::<Value>::new());
+}
+
+#[test]
+fn test_definition() {
+    let mut client = Client::new();
+    client.open(PROGRAM);
+    let definition = client.at("textDocument/definition", PROGRAM, "length", 1);
+    assert_eq!(
+        definition,
+        json!({ "uri": URI, "range": range((6, 4), (6, 10)) })
+    );
+    let definition = client.at("textDocument/definition", PROGRAM, "x", 1);
+    assert_eq!(definition["range"], range((1, 4), (1, 5)));
+    let definition = client.at("textDocument/definition", PROGRAM, "origin", 1);
+    assert_eq!(definition["range"], range((11, 8), (11, 14)));
+    let definition = client.at("textDocument/definition", PROGRAM, "Point", 2);
+    assert_eq!(definition["range"], range((0, 5), (0, 10)));
+    // A field of a struct literal
+    let definition = client.at("textDocument/definition", PROGRAM, "y =", 0);
+    assert_eq!(definition["range"], range((2, 4), (2, 5)));
+    // The standard library is not a file the editor can open
+    assert!(client
+        .at("textDocument/definition", PROGRAM, "println", 0)
+        .is_null());
+    assert!(client
+        .at("textDocument/definition", PROGRAM, "{}", 0)
+        .is_null());
+
+    // The value of a `let` is resolved before its own binding is in scope
+    let text = "pub fun main() {\n    let n = 1;\n    let n = n + 1;\n}\n";
+    client.change(text);
+    let definition = client.at("textDocument/definition", text, "n + 1", 0);
+    assert_eq!(definition["range"], range((1, 8), (1, 9)));
+}
+
+#[test]
+fn test_hover() {
+    let mut client = Client::new();
+    client.open(PROGRAM);
+    let hover = client.at("textDocument/hover", PROGRAM, "length", 1);
+    assert_eq!(
+        hover["contents"]["value"],
+        "```shark\nfun length(p :: Point) :: Int32\n```\n\nAdds up the coordinates"
+    );
+    assert_eq!(hover["range"], range((12, 18), (12, 24)));
+    let hover = client.at("textDocument/hover", PROGRAM, "origin", 1);
+    assert_eq!(
+        hover["contents"]["value"],
+        "```shark\nlet origin :: Point\n```"
+    );
+    let hover = client.at("textDocument/hover", PROGRAM, "p.x", 0);
+    assert_eq!(hover["contents"]["value"], "```shark\np :: Point\n```");
+    let hover = client.at("textDocument/hover", PROGRAM, "println", 0);
+    assert_eq!(
+        hover["contents"]["value"],
+        "```shark\npub extern fun println(template :: Str, ...)\n```\n\nLike `print`, followed by a new line"
+    );
+}
+
+#[test]
+fn test_hover_types() {
+    let text = "enum Shape {
+    Circle(Float64),
+    Square(Float64),
+}
+
+pub fun main() {
+    let shapes = (Shape::Circle(1.0), 2);
+    when shapes {
+        (Shape::Square(side), n) => side,
+        _ => 0.0,
+    };
+}
+";
+    let mut client = Client::new();
+    assert_eq!(client.open(text), Vec::<Value>::new());
+    let hover = client.at("textDocument/hover", text, "shapes", 0);
+    assert_eq!(
+        hover["contents"]["value"],
+        "```shark\nlet shapes :: (Shape, Int32)\n```"
+    );
+    let hover = client.at("textDocument/hover", text, "side", 0);
+    assert_eq!(hover["contents"]["value"], "```shark\nside :: Float64\n```");
+    let hover = client.at("textDocument/hover", text, "Square", 1);
+    assert_eq!(
+        hover["contents"]["value"],
+        "```shark\nShape::Square(Float64)\n```"
+    );
+}
+
+/// Decodes semantic tokens into their text, type and modifiers
+fn decode(text: &str, data: &[Value]) -> Vec<(String, &'static str, u64)> {
+    let positions = Positions::new(text);
+    let (mut line, mut character) = (0, 0);
+    data.chunks(5)
+        .map(|token| {
+            let numbers: Vec<u64> = token.iter().map(|x| x.as_u64().unwrap()).collect();
+            if numbers[0] > 0 {
+                character = 0;
+            }
+            line += numbers[0] as u32;
+            character += numbers[1] as u32;
+            let start = positions.offset(Position { line, character });
+            let end = positions.offset(Position {
+                line,
+                character: character + numbers[2] as u32,
+            });
+            (
+                text[start..end].to_string(),
+                TOKEN_TYPES[numbers[3] as usize],
+                numbers[4],
+            )
+        })
+        .collect()
+}
+
+#[test]
+fn test_semantic_tokens() {
+    let text =
+        "/* two\n   lines */ pub fun f<T>(a :: T) :: Int32 { let x = -1; ret x * a.len(\"é\"); }";
+    let mut client = Client::new();
+    client.open(text);
+    let result = client.request(
+        "textDocument/semanticTokens/full",
+        json!({ "textDocument": { "uri": URI } }),
+    );
+    let tokens = decode(text, result["data"].as_array().unwrap());
+    let expected = [
+        ("/* two", "comment", 0),
+        ("   lines */", "comment", 0),
+        ("pub", "modifier", 0),
+        ("fun", "keyword", 0),
+        ("f", "function", 1),
+        ("T", "typeParameter", 1),
+        ("a", "parameter", 1),
+        ("T", "typeParameter", 0),
+        ("Int32", "type", 0),
+        ("let", "keyword", 0),
+        ("x", "variable", 1),
+        ("=", "operator", 0),
+        ("-1", "number", 0),
+        ("ret", "keyword", 0),
+        ("x", "variable", 0),
+        ("*", "operator", 0),
+        ("a", "parameter", 0),
+        ("\"é\"", "string", 0),
+    ];
+    let expected: Vec<(String, &str, u64)> = expected
+        .iter()
+        .map(|&(text, kind, modifiers)| (text.to_string(), kind, modifiers))
+        .collect();
+    assert_eq!(tokens, expected);
+}
+
+#[test]
+fn test_document_symbols() {
+    let text = "trait Area {
+    fun area(self :: ref Self) :: Float64;
+}
+
+type Square {
+    side :: Float64,
+}
+
+impl Area for Square {
+    fun area(self :: ref Self) :: Float64 {
+        self.side * self.side
+    }
+}
+
+const SIDES :: Int32 = 4;
+";
+    let mut client = Client::new();
+    assert_eq!(client.open(text), Vec::<Value>::new());
+    let symbols = client.request(
+        "textDocument/documentSymbol",
+        json!({ "textDocument": { "uri": URI } }),
+    );
+    let outline: Vec<(String, u64, Vec<String>)> = symbols
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|x| {
+            let children = x["children"].as_array().unwrap();
+            (
+                x["name"].as_str().unwrap().to_string(),
+                x["kind"].as_u64().unwrap(),
+                children
+                    .iter()
+                    .map(|x| x["name"].as_str().unwrap().to_string())
+                    .collect(),
+            )
+        })
+        .collect();
+    assert_eq!(
+        outline,
+        [
+            ("Area".to_string(), 11, vec!["area".to_string()]),
+            ("Square".to_string(), 23, vec!["side".to_string()]),
+            (
+                "impl Area for Square".to_string(),
+                3,
+                vec!["area".to_string()]
+            ),
+            ("SIDES".to_string(), 14, vec![]),
+        ]
+    );
+    let square = &symbols[1];
+    assert_eq!(square["range"], range((4, 0), (6, 1)));
+    assert_eq!(square["selectionRange"], range((4, 5), (4, 11)));
+    assert_eq!(square["detail"], "type Square");
+
+    // Fields reached through `self` are found through the type of the implementation
+    let hover = client.at("textDocument/hover", text, "side", 2);
+    assert_eq!(hover["contents"]["value"], "```shark\nside :: Float64\n```");
+}
+
+#[test]
+fn test_completion() {
+    let text = "fun helper(count :: Int32) :: Int32 {
+    let hidden = count;
+    hidden
+}
+
+pub fun main() {
+    let total = 1;
+
+}
+";
+    let mut client = Client::new();
+    client.open(text);
+    let items = client.at("textDocument/completion", text, "\n}", 1);
+    let labels: Vec<&str> = items
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|x| x["label"].as_str().unwrap())
+        .collect();
+    for expected in [
+        "total", "helper", "main", "println", "Option", "Vec", "let", "when", "true",
+    ] {
+        assert!(labels.contains(&expected), "{} is missing", expected);
+    }
+    for unexpected in ["hidden", "count"] {
+        assert!(!labels.contains(&unexpected), "{} is offered", unexpected);
+    }
+    let total = items
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|x| x["label"] == "total")
+        .unwrap();
+    assert_eq!(total["kind"], 6);
+    assert_eq!(total["detail"], "let total :: Int32");
+
+    // Names still complete while the document does not parse
+    let text = "pub fun main() {\n    let total = 1;\n    let next = to\n}\n";
+    client.change(text);
+    let items = client.at("textDocument/completion", text, "to\n", 0);
+    assert!(items
+        .as_array()
+        .unwrap()
+        .iter()
+        .any(|x| x["label"] == "total"));
+}
+
+#[test]
+fn test_formatting() {
+    let text = "pub fun main(){let x=1;println(\"{}\",x);}";
+    let mut client = Client::new();
+    client.open(text);
+    let params = json!({
+        "textDocument": { "uri": URI },
+        "options": { "tabSize": 4, "insertSpaces": true },
+    });
+    let edits = client.request("textDocument/formatting", params.clone());
+    assert_eq!(
+        edits,
+        json!([{
+            "range": range((0, 0), (0, 40)),
+            "newText": "pub fun main() {\n    let x = 1;\n    println(\"{}\", x);\n}\n",
+        }])
+    );
+
+    client.change("pub fun main() {\n    let x = 1;\n}\n");
+    assert_eq!(
+        client.request("textDocument/formatting", params.clone()),
+        json!([])
+    );
+    client.change("pub fun main() {\n    let x = ;\n}\n");
+    assert!(client.request("textDocument/formatting", params).is_null());
+}
+
+#[test]
+fn test_positions() {
+    let text = "a\r\n\"😀é\" b\nlast";
+    let positions = Positions::new(text);
+    let b = text.find('b').unwrap();
+    assert_eq!(
+        positions.position(b),
+        Position {
+            line: 1,
+            character: 6
+        }
+    );
+    assert_eq!(
+        positions.offset(Position {
+            line: 1,
+            character: 6
+        }),
+        b
+    );
+    assert_eq!(
+        positions.offset(Position {
+            line: 0,
+            character: 10
+        }),
+        1
+    );
+    assert_eq!(
+        positions.offset(Position {
+            line: 7,
+            character: 0
+        }),
+        text.len()
+    );
+}
+
+fn framed(message: &Value) -> String {
+    let body = message.to_string();
+    format!("Content-Length: {}\r\n\r\n{}", body.len(), body)
+}
+
+/// Reads every message written to the output
+fn messages(output: &[u8]) -> Vec<Value> {
+    let mut reader = output;
+    let mut result = Vec::new();
+    while let Some(body) = rpc::read_message(&mut reader).unwrap() {
+        result.push(serde_json::from_str(&body).unwrap());
+    }
+    result
+}
+
+#[test]
+fn test_run() {
+    let mut input = String::new();
+    input += &framed(&json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }));
+    input += "Content-Length: 9\r\n\r\n{invalid}";
+    input += &framed(&json!({
+        "jsonrpc": "2.0",
+        "method": "textDocument/didOpen",
+        "params": { "textDocument": { "uri": URI, "text": "pub fun main() { let x :: Int32 = true; }" } },
+    }));
+    input += &framed(&json!({ "jsonrpc": "2.0", "id": 2, "method": "shutdown" }));
+    input += &framed(&json!({ "jsonrpc": "2.0", "method": "exit" }));
+    input += &framed(&json!({ "jsonrpc": "2.0", "id": 3, "method": "shutdown" }));
+
+    let mut output = Vec::new();
+    assert!(crate::run(input.as_bytes(), &mut output).unwrap());
+    let messages = messages(&output);
+    assert_eq!(messages.len(), 4, "{:?}", messages);
+    assert_eq!(messages[0]["id"], 1);
+    assert_eq!(messages[1]["error"]["code"], rpc::PARSE_ERROR);
+    assert_eq!(
+        messages[2]["params"]["diagnostics"][0]["range"],
+        range((0, 34), (0, 38))
+    );
+    assert_eq!(
+        messages[3],
+        json!({ "jsonrpc": "2.0", "id": 2, "result": null })
+    );
+
+    // Without `shutdown` the server still stops at the end of the input, but not cleanly
+    let input = framed(&json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize" }));
+    assert!(!crate::run(input.as_bytes(), Vec::new()).unwrap());
+}
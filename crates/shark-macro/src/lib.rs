@@ -1,7 +1,7 @@
 use crate::util::IdentifierArray;
 
 use proc_macro::TokenStream;
-use quote::quote;
+use quote::{format_ident, quote};
 use syn::parse_macro_input;
 
 mod util;
@@ -10,6 +10,8 @@ mod util;
 /// stringified version of said keyword to the enum
 ///
 /// For example: The input `Fun` would map to `"fun" => KeywordKind::Fun`
+/// Each keyword is also mapped to and from its pre-interned `shark_core::symbol::kw` symbol, so
+/// for example `Fun` maps to `kw::FUN`
 /// This macro should only be used once
 #[proc_macro]
 pub fn make_keywords(input: TokenStream) -> TokenStream {
@@ -24,7 +26,7 @@ pub fn make_keywords(input: TokenStream) -> TokenStream {
         }
     });
     let keyword_enum = quote! {
-        #[derive(Debug, Clone, PartialEq)]
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
         pub enum KeywordKind {
             #(#keyword_variants),*
         }
@@ -39,14 +41,36 @@ pub fn make_keywords(input: TokenStream) -> TokenStream {
         }
     });
 
+    let symbol_constants = keywords
+        .iter()
+        .map(|x| format_ident!("{}", x.to_string().to_uppercase()))
+        .collect::<Vec<_>>();
+    let keyword_idents = keywords.iter().collect::<Vec<_>>();
+
     let mapping_function = quote! {
         impl KeywordKind {
+            /// Every keyword, in the order they were declared
+            pub const ALL: &'static [KeywordKind] = &[#(KeywordKind::#keyword_idents),*];
+
             pub fn create_keyword(identifier: &str) -> Option<Self> {
                 match identifier {
                     #(#mapping_arms)*
                     _ => None,
                 }
             }
+
+            pub fn from_symbol(symbol: shark_core::symbol::Symbol) -> Option<Self> {
+                match symbol {
+                    #(shark_core::symbol::kw::#symbol_constants => Some(KeywordKind::#keyword_idents),)*
+                    _ => None,
+                }
+            }
+
+            pub fn symbol(&self) -> shark_core::symbol::Symbol {
+                match self {
+                    #(KeywordKind::#keyword_idents => shark_core::symbol::kw::#symbol_constants,)*
+                }
+            }
         }
     };
     // End - Mapping Function
@@ -0,0 +1,662 @@
+use std::fmt::Display;
+
+use shark_core::{source::Span, symbol::Symbol};
+use shark_lex::token::{LiteralKind, TokenKind};
+
+/// Uniquely identifies a node within a parsed [Module]. Later stages use these to attach
+/// information, such as types, to nodes without having to change the tree itself
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct NodeId(pub u32);
+
+/// The root of the abstract syntax tree, representing a single source file
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub items: Vec<Item>,
+    pub span: Span,
+}
+
+/// An identifier along with where it was written
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Ident {
+    pub symbol: Symbol,
+    pub span: Span,
+}
+
+/// A name qualified by the modules or types it belongs to, such as `Shape::Circle`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Path {
+    pub segments: Vec<Ident>,
+    pub span: Span,
+}
+
+impl Path {
+    /// Gets the last segment, which is the name the path refers to
+    pub fn name(&self) -> Ident {
+        *self.segments.last().expect("a path always has a segment")
+    }
+}
+
+impl Display for Path {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (index, segment) in self.segments.iter().enumerate() {
+            if index > 0 {
+                write!(f, "::")?;
+            }
+            write!(f, "{}", segment.symbol)?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Visibility {
+    Private,
+    Public,
+}
+
+/// A top level declaration such as a function or a type
+#[derive(Debug, Clone)]
+pub struct Item {
+    pub id: NodeId,
+    pub visibility: Visibility,
+    pub kind: ItemKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum ItemKind {
+    Function(Function),
+    Type(TypeDecl),
+    Enum(EnumDecl),
+    Trait(TraitDecl),
+    Impl(ImplDecl),
+    Use(UseDecl),
+    Const(ConstDecl),
+    /// An item which could not be parsed. The error has already been reported
+    Error,
+}
+
+impl ItemKind {
+    /// Gets the name the item declares
+    pub fn name(&self) -> Option<Ident> {
+        match self {
+            Self::Function(function) => Some(function.name),
+            Self::Type(type_decl) => Some(type_decl.name),
+            Self::Enum(enum_decl) => Some(enum_decl.name),
+            Self::Trait(trait_decl) => Some(trait_decl.name),
+            Self::Const(const_decl) => Some(const_decl.name),
+            Self::Impl(_) | Self::Use(_) | Self::Error => None,
+        }
+    }
+}
+
+/// `[extern ["C"]] [unsafe] fun name<Generics>(parameters) :: ReturnType where ... { body }`.
+/// Only the methods of a [TraitDecl] and `extern` functions can leave out the body, ending the
+/// signature with a `;` instead
+#[derive(Debug, Clone)]
+pub struct Function {
+    /// Whether the function is `extern`, meaning it is provided by the runtime rather than written
+    /// in Shark and so has no body
+    pub is_extern: bool,
+    /// The ABI of an `extern "C"` function, which is written in another language and linked in
+    pub abi: Option<Abi>,
+    /// Whether the function is `unsafe`, meaning its body can dereference raw pointers and it can
+    /// only be called from within `unsafe` code
+    pub is_unsafe: bool,
+    pub name: Ident,
+    pub generics: Generics,
+    pub parameters: Vec<Parameter>,
+    /// Whether the parameters end with `...`, after which any number of arguments can be passed.
+    /// Only `extern` functions can be variadic
+    pub is_variadic: bool,
+    pub return_type: Option<TypeExpr>,
+    pub body: Option<Block>,
+}
+
+/// The generic parameters of an item along with its `where` clause. Both are empty when the item
+/// is not generic
+#[derive(Debug, Clone, Default)]
+pub struct Generics {
+    pub params: Vec<GenericParam>,
+    pub where_clause: Vec<WherePredicate>,
+}
+
+/// `Name` or `Name :: Bound + ...` within the `<>` of an item
+#[derive(Debug, Clone)]
+pub struct GenericParam {
+    pub id: NodeId,
+    pub name: Ident,
+    pub bounds: Vec<Path>,
+    pub span: Span,
+}
+
+/// `Type :: Bound + ...` within a `where` clause
+#[derive(Debug, Clone)]
+pub struct WherePredicate {
+    pub id: NodeId,
+    pub ty: TypeExpr,
+    pub bounds: Vec<Path>,
+    pub span: Span,
+}
+
+/// `type Name<Generics> { field :: Type, ... }`, a product type, or `extern type Name<Generics>;`,
+/// an opaque type provided by the runtime which has no fields. `extern "C" type` lays its fields
+/// out the way C does, or declares an opaque C type when it has none
+#[derive(Debug, Clone)]
+pub struct TypeDecl {
+    pub is_extern: bool,
+    pub abi: Option<Abi>,
+    pub name: Ident,
+    pub generics: Generics,
+    pub fields: Vec<FieldDecl>,
+}
+
+/// The foreign ABI named by the string after `extern`, which decides how functions are called and
+/// how the fields of types are laid out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Abi {
+    C,
+}
+
+impl Abi {
+    /// Gets the ABI a string names, as written after `extern`
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "C" => Some(Self::C),
+            _ => None,
+        }
+    }
+}
+
+impl Display for Abi {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::C => write!(f, "\"C\""),
+        }
+    }
+}
+
+/// `name :: Type` within a [TypeDecl]
+#[derive(Debug, Clone)]
+pub struct FieldDecl {
+    pub id: NodeId,
+    pub name: Ident,
+    pub ty: TypeExpr,
+    pub span: Span,
+}
+
+/// `enum Name<Generics> { Variant(Payload, ...), Other = 4, ... }`, a tagged union whose variants
+/// can carry values
+#[derive(Debug, Clone)]
+pub struct EnumDecl {
+    pub name: Ident,
+    pub generics: Generics,
+    pub variants: Vec<Variant>,
+}
+
+/// `trait Name { fun method(self) :: Type; ... }`. Methods with a body are the default used by
+/// implementations which leave them out
+#[derive(Debug, Clone)]
+pub struct TraitDecl {
+    pub name: Ident,
+    pub methods: Vec<Function>,
+}
+
+/// `impl<Generics> Trait for Type where ... { methods }`, or `impl<Generics> Type where ... {
+/// methods }` which gives a type methods of its own rather than those of a trait
+#[derive(Debug, Clone)]
+pub struct ImplDecl {
+    pub generics: Generics,
+    /// The implemented trait, or [None] for an inherent implementation
+    pub trait_path: Option<Path>,
+    pub self_type: TypeExpr,
+    pub methods: Vec<Function>,
+}
+
+/// `const NAME :: Type = value;`, a value worked out while compiling
+#[derive(Debug, Clone)]
+pub struct ConstDecl {
+    pub name: Ident,
+    pub ty: TypeExpr,
+    pub value: Expr,
+}
+
+/// `use a::b::c;` or `use a::b::{c, d};`, bringing names declared by another module into scope
+#[derive(Debug, Clone)]
+pub struct UseDecl {
+    /// Everything before the braces, or the whole path without them
+    pub path: Path,
+    /// The names within the braces, or [None] without them
+    pub group: Option<Vec<Ident>>,
+}
+
+impl UseDecl {
+    /// Gets the full path of every name being imported
+    pub fn imports(&self) -> Vec<Path> {
+        let Some(group) = &self.group else {
+            return vec![self.path.clone()];
+        };
+        group
+            .iter()
+            .map(|x| {
+                let mut segments = self.path.segments.clone();
+                segments.push(*x);
+                Path {
+                    segments,
+                    span: self.path.span.to(x.span),
+                }
+            })
+            .collect()
+    }
+}
+
+/// `Name` or `Name(Payload, ...)` within an [EnumDecl]
+#[derive(Debug, Clone)]
+pub struct Variant {
+    pub id: NodeId,
+    pub name: Ident,
+    pub payload: Vec<TypeExpr>,
+    /// The `= value` giving the variant its discriminant, which is one more than that of the
+    /// variant before it when left out
+    pub discriminant: Option<Expr>,
+    pub span: Span,
+}
+
+/// `[mut] name :: Type`. A method can take `self` without a type, which is short for
+/// `self :: Self`
+#[derive(Debug, Clone)]
+pub struct Parameter {
+    pub id: NodeId,
+    pub mutable: bool,
+    pub name: Ident,
+    pub ty: TypeExpr,
+    pub span: Span,
+}
+
+/// A type as it was written in the source
+#[derive(Debug, Clone)]
+pub struct TypeExpr {
+    pub id: NodeId,
+    pub kind: TypeExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum TypeExprKind {
+    /// `Name` or `Name<Arguments, ...>`
+    Named {
+        name: Ident,
+        arguments: Vec<TypeExpr>,
+    },
+    /// `ref T`, `ref mut T`, `ptr T` or `ptr mut T`
+    Reference {
+        kind: ReferenceKind,
+        mutable: bool,
+        pointee: Box<TypeExpr>,
+    },
+    /// `yield T`, a generator producing values of type `T`. Only the return type of a function
+    /// whose body can `yield` is written this way
+    Generator(Box<TypeExpr>),
+    /// `fun(Parameter, ...) :: ReturnType`, a closure taking and returning values of these types.
+    /// Without a return type it returns `()`
+    Function {
+        parameters: Vec<TypeExpr>,
+        return_type: Option<Box<TypeExpr>>,
+    },
+    /// `[T; length]`, an array holding `length` values of type `T`. The length is worked out
+    /// while compiling, the same way as the value of a `const`
+    Array {
+        element: Box<TypeExpr>,
+        length: Box<Expr>,
+    },
+    /// A type which could not be parsed. The error has already been reported
+    Error,
+}
+
+/// Whether a reference is a `ref`, which the ownership checker makes sure is always valid, or a raw
+/// `ptr`, which can only be dereferenced within `unsafe` code
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ReferenceKind {
+    Ref,
+    Ptr,
+}
+
+/// `{ statements tail }`. The tail is an expression without a trailing `;` at the end of the block
+/// and is the value the block evaluates to
+#[derive(Debug, Clone)]
+pub struct Block {
+    pub id: NodeId,
+    pub statements: Vec<Statement>,
+    pub tail: Option<Box<Expr>>,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Statement {
+    pub id: NodeId,
+    pub kind: StatementKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum StatementKind {
+    Let(Let),
+    /// An expression followed by a `;`, or a block-like expression such as `if` which does not
+    /// need one
+    Expr(Expr),
+    /// A statement which could not be parsed. The error has already been reported
+    Error,
+}
+
+/// `let [mut] name [:: Type] [= value];`
+#[derive(Debug, Clone)]
+pub struct Let {
+    pub mutable: bool,
+    pub name: Ident,
+    pub ty: Option<TypeExpr>,
+    pub value: Option<Expr>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Expr {
+    pub id: NodeId,
+    pub kind: ExprKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum ExprKind {
+    Literal(LiteralKind),
+    Name(Ident),
+    /// A name with at least two segments, such as `Shape::Circle`
+    Path(Path),
+    /// `Point { x = 1.0, y = 2.0 }`
+    StructLiteral {
+        path: Path,
+        fields: Vec<FieldInit>,
+    },
+    Unary {
+        operator: UnaryOperator,
+        operand: Box<Expr>,
+    },
+    /// `ref value`, `ref mut value` or their `ptr` counterparts, which take the address of a value
+    Reference {
+        kind: ReferenceKind,
+        mutable: bool,
+        operand: Box<Expr>,
+    },
+    Binary {
+        operator: BinaryOperator,
+        left: Box<Expr>,
+        right: Box<Expr>,
+    },
+    /// `target = value` or a compound assignment such as `target += value`
+    Assign {
+        operator: Option<BinaryOperator>,
+        target: Box<Expr>,
+        value: Box<Expr>,
+    },
+    /// `(a, b)`. The empty tuple `()` is the unit value
+    Tuple(Vec<Expr>),
+    /// `[a, b, c]`, an array holding these values
+    Array(Vec<Expr>),
+    /// `[value; length]`, an array holding `length` copies of the value. The length is worked out
+    /// while compiling, the same way as the value of a `const`
+    Repeat {
+        value: Box<Expr>,
+        length: Box<Expr>,
+    },
+    Call {
+        callee: Box<Expr>,
+        arguments: Vec<Expr>,
+    },
+    Field {
+        object: Box<Expr>,
+        field: Ident,
+    },
+    Block(Block),
+    /// `unsafe { ... }`, within which raw pointers can be dereferenced and `unsafe` functions
+    /// called
+    Unsafe(Block),
+    If {
+        condition: Box<Expr>,
+        then_branch: Block,
+        /// Either an [ExprKind::Block] or another [ExprKind::If]
+        else_branch: Option<Box<Expr>>,
+    },
+    /// `for pattern in iterable { body }`, or `for (index, pattern) of iterable { body }` which
+    /// also counts the iterations from zero
+    For {
+        kind: ForKind,
+        pattern: Pattern,
+        iterable: Box<Expr>,
+        body: Block,
+    },
+    /// `when scrutinee { pattern => body, ... }`
+    When {
+        scrutinee: Box<Expr>,
+        arms: Vec<WhenArm>,
+    },
+    Return(Option<Box<Expr>>),
+    /// `yield value`, which hands a value to whatever is iterating the generator and suspends it
+    /// until the next value is asked for
+    Yield(Box<Expr>),
+    /// `fun(parameters) :: ReturnType { body }`, a function without a name which captures the
+    /// values of the locals around it that its body uses. It is kept as a [Function] named
+    /// `<closure>`, a name that can never be written
+    Closure(Box<Function>),
+    /// An expression which could not be parsed. The error has already been reported
+    Error,
+}
+
+/// Whether a `for` loop binds each value with `in`, or the index and the value with `of`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ForKind {
+    In,
+    Of,
+}
+
+impl Display for ForKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::In => write!(f, "in"),
+            Self::Of => write!(f, "of"),
+        }
+    }
+}
+
+/// `name = value` within a struct literal
+#[derive(Debug, Clone)]
+pub struct FieldInit {
+    pub name: Ident,
+    pub value: Expr,
+    pub span: Span,
+}
+
+/// `pattern [if guard] => body` within a `when`
+#[derive(Debug, Clone)]
+pub struct WhenArm {
+    pub id: NodeId,
+    pub pattern: Pattern,
+    pub guard: Option<Expr>,
+    pub body: Expr,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pub id: NodeId,
+    pub kind: PatternKind,
+    pub span: Span,
+}
+
+#[derive(Debug, Clone)]
+pub enum PatternKind {
+    /// `_`, matches anything
+    Wildcard,
+    /// `[mut] name`, matches anything and binds it to the name
+    Binding {
+        mutable: bool,
+        name: Ident,
+    },
+    Literal(LiteralKind),
+    /// `start..end` or `start..=end`
+    Range {
+        start: LiteralKind,
+        end: LiteralKind,
+        inclusive: bool,
+    },
+    /// `(a, b)`
+    Tuple(Vec<Pattern>),
+    /// `Enum::Variant` or `Enum::Variant(a, b)`
+    Variant {
+        path: Path,
+        payload: Vec<Pattern>,
+    },
+}
+
+impl ExprKind {
+    /// Checks if this expression ends with a block, meaning it can be used as a statement without
+    /// a trailing `;`
+    pub fn is_block_like(&self) -> bool {
+        matches!(
+            self,
+            ExprKind::Block(_)
+                | ExprKind::Unsafe(_)
+                | ExprKind::If { .. }
+                | ExprKind::For { .. }
+                | ExprKind::When { .. }
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOperator {
+    Negate, // -
+    Not,    // !
+    Deref,  // *
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOperator {
+    Add,      // +
+    Subtract, // -
+    Multiply, // *
+    Divide,   // /
+
+    Greater,        // >
+    Lesser,         // <
+    GreaterOrEqual, // >=
+    LessOrEqual,    // <=
+    EqualTo,        // ==
+    NotEqual,       // !=
+
+    And, // &&
+    Or,  // |
+
+    BitwiseAnd, // &
+    ShiftLeft,  // <<
+    ShiftRight, // >>
+}
+
+impl BinaryOperator {
+    /// Gets the [BinaryOperator] a [TokenKind] represents when used between two expressions
+    pub fn from_token(kind: &TokenKind) -> Option<Self> {
+        Some(match kind {
+            TokenKind::Plus => Self::Add,
+            TokenKind::Minus => Self::Subtract,
+            TokenKind::Multiply => Self::Multiply,
+            TokenKind::Divide => Self::Divide,
+            TokenKind::Greater => Self::Greater,
+            TokenKind::Lesser => Self::Lesser,
+            TokenKind::GreaterOrEqual => Self::GreaterOrEqual,
+            TokenKind::LessOrEqual => Self::LessOrEqual,
+            TokenKind::EqualTo => Self::EqualTo,
+            TokenKind::NotEqual => Self::NotEqual,
+            TokenKind::And => Self::And,
+            TokenKind::Or => Self::Or,
+            TokenKind::BitwiseAnd => Self::BitwiseAnd,
+            TokenKind::ShiftLeft => Self::ShiftLeft,
+            TokenKind::ShiftRight => Self::ShiftRight,
+            _ => return None,
+        })
+    }
+
+    /// Gets the [BinaryOperator] a compound assignment [TokenKind] such as `+=` applies
+    pub fn from_assign_token(kind: &TokenKind) -> Option<Self> {
+        Some(match kind {
+            TokenKind::PlusAssign => Self::Add,
+            TokenKind::MinusAssign => Self::Subtract,
+            TokenKind::MultiplyAssign => Self::Multiply,
+            TokenKind::DivideAssign => Self::Divide,
+            _ => return None,
+        })
+    }
+
+    /// Gets how tightly this operator binds to its operands. Operators with a higher value are
+    /// applied first, and every binary operator is left associative
+    pub fn precedence(&self) -> u8 {
+        match self {
+            Self::Or => 1,
+            Self::And => 2,
+            Self::EqualTo
+            | Self::NotEqual
+            | Self::Greater
+            | Self::Lesser
+            | Self::GreaterOrEqual
+            | Self::LessOrEqual => 3,
+            Self::BitwiseAnd => 4,
+            Self::ShiftLeft | Self::ShiftRight => 5,
+            Self::Add | Self::Subtract => 6,
+            Self::Multiply | Self::Divide => 7,
+        }
+    }
+
+    pub fn is_comparison(&self) -> bool {
+        self.precedence() == 3
+    }
+}
+
+impl Display for ReferenceKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keyword = match self {
+            Self::Ref => "ref",
+            Self::Ptr => "ptr",
+        };
+        write!(f, "{}", keyword)
+    }
+}
+
+impl Display for UnaryOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operator = match self {
+            Self::Negate => "-",
+            Self::Not => "!",
+            Self::Deref => "*",
+        };
+        write!(f, "{}", operator)
+    }
+}
+
+impl Display for BinaryOperator {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let operator = match self {
+            Self::Add => "+",
+            Self::Subtract => "-",
+            Self::Multiply => "*",
+            Self::Divide => "/",
+            Self::Greater => ">",
+            Self::Lesser => "<",
+            Self::GreaterOrEqual => ">=",
+            Self::LessOrEqual => "<=",
+            Self::EqualTo => "==",
+            Self::NotEqual => "!=",
+            Self::And => "&&",
+            Self::Or => "|",
+            Self::BitwiseAnd => "&",
+            Self::ShiftLeft => "<<",
+            Self::ShiftRight => ">>",
+        };
+        write!(f, "{}", operator)
+    }
+}
@@ -0,0 +1,543 @@
+//! A lossless concrete syntax tree. Unlike the [crate::ast] the concrete syntax tree keeps every
+//! token, including whitespace, comments and anything the parser could not make sense of, so the
+//! text of the tree is always exactly the text it was parsed from
+//!
+//! The tree is made of two layers. The green tree is immutable, knows nothing about where it sits
+//! in the file and can be shared freely. The red tree is built lazily on top of the green tree
+//! and adds parent pointers and absolute offsets, which is what tools like formatters or an
+//! editor need to navigate the source
+
+use std::{fmt::Display, rc::Rc, sync::Arc};
+
+use shark_core::source::Span;
+use shark_lex::token::{LexerToken, TokenKind};
+
+/// The kind of a node within the concrete syntax tree. Tokens use the [TokenKind] the lexer gave
+/// them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum NodeKind {
+    SourceFile,
+    Function,
+    TypeDecl,
+    FieldList,
+    FieldDecl,
+    EnumDecl,
+    VariantList,
+    Variant,
+    TraitDecl,
+    ImplDecl,
+    UseDecl,
+    UseGroup,
+    ConstDecl,
+    MethodList,
+    GenericParamList,
+    GenericParam,
+    WhereClause,
+    WherePredicate,
+    BoundList,
+    ParameterList,
+    Parameter,
+    Type,
+    TypeArgumentList,
+    ReferenceType,
+    GeneratorType,
+    FunctionType,
+    ArrayType,
+    Block,
+    LetStatement,
+    ExprStatement,
+
+    LiteralExpr,
+    NameExpr,
+    PathExpr,
+    StructLiteral,
+    FieldInitList,
+    FieldInit,
+    ParenExpr,
+    UnaryExpr,
+    ReferenceExpr,
+    BinaryExpr,
+    AssignExpr,
+    CallExpr,
+    ArgumentList,
+    FieldExpr,
+    UnsafeExpr,
+    IfExpr,
+    ForExpr,
+    ReturnExpr,
+    YieldExpr,
+    ClosureExpr,
+    TupleExpr,
+    ArrayExpr,
+    RepeatExpr,
+    WhenExpr,
+    WhenArmList,
+    WhenArm,
+    WhenGuard,
+
+    WildcardPattern,
+    BindingPattern,
+    LiteralPattern,
+    RangePattern,
+    TuplePattern,
+    VariantPattern,
+    PatternList,
+
+    /// Tokens which were skipped while recovering from a syntax error
+    Error,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GreenToken {
+    pub kind: TokenKind,
+    pub text: Box<str>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GreenElement {
+    Node(Arc<GreenNode>),
+    Token(Arc<GreenToken>),
+}
+
+impl GreenElement {
+    pub fn text_len(&self) -> usize {
+        match self {
+            Self::Node(node) => node.text_len,
+            Self::Token(token) => token.text.len(),
+        }
+    }
+}
+
+/// An immutable node of the green tree. A [GreenNode] only knows the length of its text, not where
+/// it is, so identical subtrees can be shared
+#[derive(Debug, Clone, PartialEq)]
+pub struct GreenNode {
+    pub kind: NodeKind,
+    pub text_len: usize,
+    pub children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    pub fn new(kind: NodeKind, children: Vec<GreenElement>) -> Self {
+        Self {
+            kind,
+            text_len: children.iter().map(GreenElement::text_len).sum(),
+            children,
+        }
+    }
+
+    fn write_text(&self, result: &mut String) {
+        for child in &self.children {
+            match child {
+                GreenElement::Node(node) => node.write_text(result),
+                GreenElement::Token(token) => result.push_str(&token.text),
+            }
+        }
+    }
+}
+
+/// Marks a position within a [GreenNodeBuilder] that a node can later be started at, which lets a
+/// parser decide what a node is after it has already parsed its first child
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Checkpoint(usize);
+
+/// Builds a green tree from the top down
+#[derive(Debug, Default)]
+pub struct GreenNodeBuilder {
+    parents: Vec<(NodeKind, usize)>,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNodeBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn start_node(&mut self, kind: NodeKind) {
+        self.parents.push((kind, self.children.len()));
+    }
+
+    pub fn token(&mut self, kind: TokenKind, text: &str) {
+        self.children.push(GreenElement::Token(Arc::new(GreenToken {
+            kind,
+            text: text.into(),
+        })));
+    }
+
+    pub fn finish_node(&mut self) {
+        let (kind, first_child) = self
+            .parents
+            .pop()
+            .expect("finish_node was called without a matching start_node");
+        let children = self.children.split_off(first_child);
+        self.children
+            .push(GreenElement::Node(Arc::new(GreenNode::new(kind, children))));
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.children.len())
+    }
+
+    /// Starts a node which wraps everything added since the [Checkpoint] was taken
+    pub fn start_node_at(&mut self, checkpoint: Checkpoint, kind: NodeKind) {
+        let Checkpoint(first_child) = checkpoint;
+        assert!(
+            first_child <= self.children.len(),
+            "the checkpoint is no longer valid"
+        );
+        if let Some(&(_, parent_start)) = self.parents.last() {
+            assert!(
+                first_child >= parent_start,
+                "the checkpoint is outside of the current node"
+            );
+        }
+        self.parents.push((kind, first_child));
+    }
+
+    /// Gets how many nodes are currently started but not finished
+    pub fn depth(&self) -> usize {
+        self.parents.len()
+    }
+
+    /// Finishes the root node and returns it
+    pub fn finish(mut self) -> Arc<GreenNode> {
+        assert_eq!(self.parents.len(), 0, "every node must be finished first");
+        match self.children.pop() {
+            Some(GreenElement::Node(node)) if self.children.is_empty() => node,
+            _ => panic!("the builder must contain exactly one root node"),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct NodeData {
+    green: Arc<GreenNode>,
+    parent: Option<SyntaxNode>,
+    index_in_parent: usize,
+    offset: usize,
+}
+
+/// A node of the red tree, a view of a [GreenNode] which knows its parent and where it is in the
+/// source. Cloning a [SyntaxNode] is cheap
+#[derive(Debug, Clone)]
+pub struct SyntaxNode(Rc<NodeData>);
+
+/// A token of the red tree
+#[derive(Debug, Clone)]
+pub struct SyntaxToken {
+    green: Arc<GreenToken>,
+    parent: SyntaxNode,
+    index_in_parent: usize,
+    offset: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl PartialEq for SyntaxNode {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0.green, &other.0.green) && self.0.offset == other.0.offset
+    }
+}
+
+impl PartialEq for SyntaxToken {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.green, &other.green) && self.offset == other.offset
+    }
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: Arc<GreenNode>) -> Self {
+        Self(Rc::new(NodeData {
+            green,
+            parent: None,
+            index_in_parent: 0,
+            offset: 0,
+        }))
+    }
+
+    pub fn kind(&self) -> NodeKind {
+        self.0.green.kind
+    }
+
+    pub fn green(&self) -> &Arc<GreenNode> {
+        &self.0.green
+    }
+
+    /// Gets the [Span] of the source this node was parsed from
+    pub fn span(&self) -> Span {
+        Span::new(self.0.offset, self.0.offset + self.0.green.text_len)
+    }
+
+    pub fn text(&self) -> String {
+        let mut result = String::with_capacity(self.0.green.text_len);
+        self.0.green.write_text(&mut result);
+        result
+    }
+
+    pub fn parent(&self) -> Option<SyntaxNode> {
+        self.0.parent.clone()
+    }
+
+    /// Gets the index of this node within its parent's children
+    pub fn index(&self) -> usize {
+        self.0.index_in_parent
+    }
+
+    /// Gets the node or token which follows this node within its parent
+    pub fn next_sibling_or_token(&self) -> Option<SyntaxElement> {
+        let parent = self.parent()?;
+        parent
+            .children_with_tokens()
+            .into_iter()
+            .nth(self.index() + 1)
+    }
+
+    /// Gets this node, its parent, its parent's parent and so on up to the root
+    pub fn ancestors(&self) -> impl Iterator<Item = SyntaxNode> {
+        std::iter::successors(Some(self.clone()), SyntaxNode::parent)
+    }
+
+    pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+        let mut offset = self.0.offset;
+        let mut result = Vec::with_capacity(self.0.green.children.len());
+        for (index, child) in self.0.green.children.iter().enumerate() {
+            result.push(match child {
+                GreenElement::Node(green) => SyntaxElement::Node(SyntaxNode(Rc::new(NodeData {
+                    green: green.clone(),
+                    parent: Some(self.clone()),
+                    index_in_parent: index,
+                    offset,
+                }))),
+                GreenElement::Token(green) => SyntaxElement::Token(SyntaxToken {
+                    green: green.clone(),
+                    parent: self.clone(),
+                    index_in_parent: index,
+                    offset,
+                }),
+            });
+            offset += child.text_len();
+        }
+        result
+    }
+
+    pub fn children(&self) -> Vec<SyntaxNode> {
+        self.children_with_tokens()
+            .into_iter()
+            .filter_map(|x| match x {
+                SyntaxElement::Node(node) => Some(node),
+                SyntaxElement::Token(_) => None,
+            })
+            .collect()
+    }
+
+    /// Gets the tokens which are direct children of this node
+    pub fn child_tokens(&self) -> Vec<SyntaxToken> {
+        self.children_with_tokens()
+            .into_iter()
+            .filter_map(|x| match x {
+                SyntaxElement::Node(_) => None,
+                SyntaxElement::Token(token) => Some(token),
+            })
+            .collect()
+    }
+
+    /// Gets the first child node of the provided [NodeKind]
+    pub fn child_of_kind(&self, kind: NodeKind) -> Option<SyntaxNode> {
+        self.children().into_iter().find(|x| x.kind() == kind)
+    }
+
+    /// Gets this node and every node below it, parents before their children
+    pub fn descendants(&self) -> Vec<SyntaxNode> {
+        let mut result = vec![self.clone()];
+        for child in self.children() {
+            result.extend(child.descendants());
+        }
+        result
+    }
+
+    /// Gets every token below this node in source order
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        let mut result = Vec::new();
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) => result.extend(node.tokens()),
+                SyntaxElement::Token(token) => result.push(token),
+            }
+        }
+        result
+    }
+
+    pub fn first_token(&self) -> Option<SyntaxToken> {
+        self.tokens().into_iter().next()
+    }
+
+    pub fn last_token(&self) -> Option<SyntaxToken> {
+        self.tokens().into_iter().last()
+    }
+
+    /// Gets the token which covers the byte offset. At the boundary between two tokens the token
+    /// starting at the offset is returned
+    pub fn token_at_offset(&self, offset: usize) -> Option<SyntaxToken> {
+        let mut node = self.clone();
+        'descend: loop {
+            for child in node.children_with_tokens() {
+                match child {
+                    SyntaxElement::Node(child) if child.span().contains(offset) => {
+                        node = child;
+                        continue 'descend;
+                    }
+                    SyntaxElement::Token(token) if token.span().contains(offset) => {
+                        return Some(token)
+                    }
+                    _ => {}
+                }
+            }
+            return None;
+        }
+    }
+
+    /// Gets the smallest node which fully covers the [Span]
+    pub fn covering_node(&self, span: Span) -> SyntaxNode {
+        let mut node = self.clone();
+        'descend: loop {
+            for child in node.children() {
+                let child_span = child.span();
+                if child_span.start <= span.start && span.end <= child_span.end {
+                    node = child;
+                    continue 'descend;
+                }
+            }
+            return node;
+        }
+    }
+
+    /// Writes the tree out with one node or token per line, which is handy for tests and debugging
+    pub fn debug_dump(&self) -> String {
+        let mut result = String::new();
+        self.debug_dump_into(&mut result, 0);
+        result
+    }
+
+    fn debug_dump_into(&self, result: &mut String, indent: usize) {
+        let span = self.span();
+        result.push_str(&format!(
+            "{}{:?}@{}..{}\n",
+            "  ".repeat(indent),
+            self.kind(),
+            span.start,
+            span.end
+        ));
+        for child in self.children_with_tokens() {
+            match child {
+                SyntaxElement::Node(node) => node.debug_dump_into(result, indent + 1),
+                SyntaxElement::Token(token) => {
+                    let span = token.span();
+                    result.push_str(&format!(
+                        "{}{:?}@{}..{} {:?}\n",
+                        "  ".repeat(indent + 1),
+                        token.kind(),
+                        span.start,
+                        span.end,
+                        token.text()
+                    ));
+                }
+            }
+        }
+    }
+}
+
+impl Display for SyntaxNode {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> TokenKind {
+        self.green.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    pub fn span(&self) -> Span {
+        Span::new(self.offset, self.offset + self.green.text.len())
+    }
+
+    pub fn parent(&self) -> SyntaxNode {
+        self.parent.clone()
+    }
+
+    /// Gets the token straight after this one in the source, if there is one
+    pub fn next_token(&self) -> Option<SyntaxToken> {
+        let root = self.parent.ancestors().last()?;
+        root.token_at_offset(self.offset + self.green.text.len())
+    }
+
+    /// Gets the token straight before this one in the source, if there is one
+    pub fn previous_token(&self) -> Option<SyntaxToken> {
+        let root = self.parent.ancestors().last()?;
+        root.token_at_offset(self.offset.checked_sub(1)?)
+    }
+
+    /// Gets the index of this token within its parent's children
+    pub fn index(&self) -> usize {
+        self.index_in_parent
+    }
+}
+
+impl Display for SyntaxToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.text())
+    }
+}
+
+impl SyntaxElement {
+    pub fn span(&self) -> Span {
+        match self {
+            Self::Node(node) => node.span(),
+            Self::Token(token) => token.span(),
+        }
+    }
+}
+
+/// Feeds the tokens the [crate::Parser] consumes into a [GreenNodeBuilder], adding back the
+/// trivia the parser never sees in between them
+#[derive(Debug)]
+pub(crate) struct CstSink<'sink> {
+    /// Every token of the source, trivia included
+    tokens: &'sink [LexerToken<'sink>],
+    next: usize,
+    pub builder: GreenNodeBuilder,
+}
+
+impl<'sink> CstSink<'sink> {
+    pub fn new(tokens: &'sink [LexerToken<'sink>]) -> Self {
+        Self {
+            tokens,
+            next: 0,
+            builder: GreenNodeBuilder::new(),
+        }
+    }
+
+    /// Adds every token which starts before the offset
+    pub fn flush_before(&mut self, offset: usize) {
+        while let Some(token) = self.tokens.get(self.next) {
+            if token.span.start >= offset {
+                break;
+            }
+            self.builder.token(token.kind, token.text);
+            self.next += 1;
+        }
+    }
+
+    /// Adds the token the parser just consumed along with any trivia before it
+    pub fn token(&mut self, token: &LexerToken) {
+        self.flush_before(token.span.end);
+    }
+}
@@ -0,0 +1,437 @@
+//! Writes the abstract syntax tree out as s-expressions. This is mostly useful for tests and
+//! debugging since the output shows the shape of the tree without any of the noise that comes with
+//! [std::fmt::Debug]
+
+use shark_lex::token::LiteralKind;
+
+use crate::ast::{
+    Abi, Block, Expr, ExprKind, Function, Generics, Item, ItemKind, Module, Path, Pattern,
+    PatternKind, StatementKind, TypeExpr, TypeExprKind, Visibility,
+};
+
+/// Writes every item of a [Module] on its own line
+pub fn dump_module(module: &Module) -> String {
+    let mut result = String::new();
+    for item in &module.items {
+        result.push_str(&dump_item(item));
+        result.push('\n');
+    }
+    result
+}
+
+pub fn dump_item(item: &Item) -> String {
+    let visibility = match item.visibility {
+        Visibility::Public => "pub ",
+        Visibility::Private => "",
+    };
+    match &item.kind {
+        ItemKind::Function(function) => dump_function(visibility, function),
+        ItemKind::Type(type_decl) => {
+            let (params, where_clause) = dump_generics(&type_decl.generics);
+            let mut result = format!(
+                "({}{}type {}{}{}",
+                visibility,
+                dump_extern(type_decl.is_extern, type_decl.abi),
+                type_decl.name.symbol,
+                params,
+                where_clause
+            );
+            for field in &type_decl.fields {
+                result.push_str(&format!(
+                    " ({} {})",
+                    field.name.symbol,
+                    dump_type(&field.ty)
+                ));
+            }
+            result.push(')');
+            result
+        }
+        ItemKind::Enum(enum_decl) => {
+            let (params, where_clause) = dump_generics(&enum_decl.generics);
+            let mut result = format!(
+                "({}enum {}{}{}",
+                visibility, enum_decl.name.symbol, params, where_clause
+            );
+            for variant in &enum_decl.variants {
+                if let Some(discriminant) = &variant.discriminant {
+                    result.push_str(&format!(
+                        " (= {} {})",
+                        variant.name.symbol,
+                        dump_expr(discriminant)
+                    ));
+                    continue;
+                }
+                if variant.payload.is_empty() {
+                    result.push_str(&format!(" {}", variant.name.symbol));
+                    continue;
+                }
+                let payload: Vec<String> = variant.payload.iter().map(dump_type).collect();
+                result.push_str(&format!(" ({} {})", variant.name.symbol, payload.join(" ")));
+            }
+            result.push(')');
+            result
+        }
+        ItemKind::Trait(trait_decl) => {
+            let mut result = format!("({}trait {}", visibility, trait_decl.name.symbol);
+            for method in &trait_decl.methods {
+                result.push(' ');
+                result.push_str(&dump_function("", method));
+            }
+            result.push(')');
+            result
+        }
+        ItemKind::Impl(impl_decl) => {
+            let (params, where_clause) = dump_generics(&impl_decl.generics);
+            let trait_path = impl_decl
+                .trait_path
+                .as_ref()
+                .map_or(String::new(), |x| format!(" {} for", x));
+            let mut result = format!(
+                "({}impl{}{} {}{}",
+                visibility,
+                params,
+                trait_path,
+                dump_type(&impl_decl.self_type),
+                where_clause
+            );
+            for method in &impl_decl.methods {
+                result.push(' ');
+                result.push_str(&dump_function("", method));
+            }
+            result.push(')');
+            result
+        }
+        ItemKind::Use(use_decl) => match &use_decl.group {
+            Some(group) => {
+                let names: Vec<&str> = group.iter().map(|x| x.symbol.as_str()).collect();
+                format!(
+                    "({}use {}::{{{}}})",
+                    visibility,
+                    use_decl.path,
+                    names.join(", ")
+                )
+            }
+            None => format!("({}use {})", visibility, use_decl.path),
+        },
+        ItemKind::Const(const_decl) => format!(
+            "({}const {} {} {})",
+            visibility,
+            const_decl.name.symbol,
+            dump_type(&const_decl.ty),
+            dump_expr(&const_decl.value)
+        ),
+        ItemKind::Error => format!("({}<error>)", visibility),
+    }
+}
+
+/// Writes out `extern` along with its ABI, if there is one
+fn dump_extern(is_extern: bool, abi: Option<Abi>) -> String {
+    match (is_extern, abi) {
+        (true, Some(abi)) => format!("extern {} ", abi),
+        (true, None) => "extern ".to_string(),
+        (false, _) => String::new(),
+    }
+}
+
+fn dump_function(visibility: &str, function: &Function) -> String {
+    let mut parameters: Vec<String> = function
+        .parameters
+        .iter()
+        .map(|x| {
+            let mutable = if x.mutable { "mut " } else { "" };
+            format!("{}{} {}", mutable, x.name.symbol, dump_type(&x.ty))
+        })
+        .collect();
+    let return_type = function
+        .return_type
+        .as_ref()
+        .map_or(String::new(), |x| format!(" :: {}", dump_type(x)));
+    let (params, where_clause) = dump_generics(&function.generics);
+    let body = function
+        .body
+        .as_ref()
+        .map_or(String::new(), |x| format!(" {}", dump_block(x)));
+    if function.is_variadic {
+        parameters.push("...".to_string());
+    }
+    let is_extern = dump_extern(function.is_extern, function.abi);
+    let is_unsafe = if function.is_unsafe { "unsafe " } else { "" };
+    format!(
+        "({}{}{}fun {}{} ({}){}{}{})",
+        visibility,
+        is_extern,
+        is_unsafe,
+        function.name.symbol,
+        params,
+        parameters.join(", "),
+        return_type,
+        where_clause,
+        body
+    )
+}
+
+/// Writes the generic parameters of an item, such as `<T :: Show, U>`, along with its `where`
+/// clause, such as ` where T :: Show`. Both are empty for an item which is not generic
+fn dump_generics(generics: &Generics) -> (String, String) {
+    let params = if generics.params.is_empty() {
+        String::new()
+    } else {
+        let params: Vec<String> = generics
+            .params
+            .iter()
+            .map(|x| match x.bounds.is_empty() {
+                true => x.name.symbol.to_string(),
+                false => format!("{} :: {}", x.name.symbol, dump_bounds(&x.bounds)),
+            })
+            .collect();
+        format!("<{}>", params.join(", "))
+    };
+    let where_clause = if generics.where_clause.is_empty() {
+        String::new()
+    } else {
+        let predicates: Vec<String> = generics
+            .where_clause
+            .iter()
+            .map(|x| format!("{} :: {}", dump_type(&x.ty), dump_bounds(&x.bounds)))
+            .collect();
+        format!(" where {}", predicates.join(", "))
+    };
+    (params, where_clause)
+}
+
+fn dump_bounds(bounds: &[Path]) -> String {
+    let bounds: Vec<String> = bounds.iter().map(|x| x.to_string()).collect();
+    bounds.join(" + ")
+}
+
+pub fn dump_type(ty: &TypeExpr) -> String {
+    match &ty.kind {
+        TypeExprKind::Named { name, arguments } if arguments.is_empty() => name.symbol.to_string(),
+        TypeExprKind::Named { name, arguments } => {
+            let arguments: Vec<String> = arguments.iter().map(dump_type).collect();
+            format!("{}<{}>", name.symbol, arguments.join(", "))
+        }
+        TypeExprKind::Reference {
+            kind,
+            mutable,
+            pointee,
+        } => {
+            let mutable = if *mutable { "mut " } else { "" };
+            format!("{} {}{}", kind, mutable, dump_type(pointee))
+        }
+        TypeExprKind::Generator(item) => format!("yield {}", dump_type(item)),
+        TypeExprKind::Function {
+            parameters,
+            return_type,
+        } => {
+            let parameters: Vec<String> = parameters.iter().map(dump_type).collect();
+            let return_type = return_type
+                .as_ref()
+                .map_or(String::new(), |x| format!(" :: {}", dump_type(x)));
+            format!("fun({}){}", parameters.join(", "), return_type)
+        }
+        TypeExprKind::Array { element, length } => {
+            format!("[{}; {}]", dump_type(element), dump_expr(length))
+        }
+        TypeExprKind::Error => "<error>".to_string(),
+    }
+}
+
+pub fn dump_block(block: &Block) -> String {
+    let mut result = String::from("{");
+    for statement in &block.statements {
+        match &statement.kind {
+            StatementKind::Let(let_statement) => {
+                let mutable = if let_statement.mutable { "mut " } else { "" };
+                result.push_str(&format!(" (let {}{}", mutable, let_statement.name.symbol));
+                if let Some(ty) = &let_statement.ty {
+                    result.push_str(&format!(" :: {}", dump_type(ty)));
+                }
+                if let Some(value) = &let_statement.value {
+                    result.push(' ');
+                    result.push_str(&dump_expr(value));
+                }
+                result.push(')');
+            }
+            StatementKind::Expr(expr) => {
+                result.push(' ');
+                result.push_str(&dump_expr(expr));
+                result.push(';');
+            }
+            StatementKind::Error => result.push_str(" <error>;"),
+        }
+    }
+    if let Some(tail) = &block.tail {
+        result.push(' ');
+        result.push_str(&dump_expr(tail));
+    }
+    result.push_str(" }");
+    result
+}
+
+pub fn dump_literal(literal: &LiteralKind) -> String {
+    match literal {
+        LiteralKind::UInt8(x) => format!("{}uint8", x),
+        LiteralKind::Int8(x) => format!("{}int8", x),
+        LiteralKind::UInt32(x) => format!("{}uint32", x),
+        LiteralKind::Int32(x) => x.to_string(),
+        LiteralKind::UInt64(x) => format!("{}uint64", x),
+        LiteralKind::Int64(x) => format!("{}int64", x),
+        LiteralKind::Float32(x) => x.to_string(),
+        LiteralKind::Float64(x) => format!("{}float64", x),
+        LiteralKind::Str(x) => format!("{:?}", x.as_str()),
+        LiteralKind::Char(x) => format!("{:?}", x),
+        LiteralKind::Boolean(x) => x.to_string(),
+    }
+}
+
+pub fn dump_expr(expr: &Expr) -> String {
+    match &expr.kind {
+        ExprKind::Literal(literal) => dump_literal(literal),
+        ExprKind::Name(ident) => ident.symbol.to_string(),
+        ExprKind::Path(path) => path.to_string(),
+        ExprKind::StructLiteral { path, fields } => {
+            let mut result = format!("(struct {}", path);
+            for field in fields {
+                result.push_str(&format!(
+                    " ({} {})",
+                    field.name.symbol,
+                    dump_expr(&field.value)
+                ));
+            }
+            result.push(')');
+            result
+        }
+        ExprKind::Unary { operator, operand } => format!("({} {})", operator, dump_expr(operand)),
+        ExprKind::Reference {
+            kind,
+            mutable,
+            operand,
+        } => {
+            let mutable = if *mutable { " mut" } else { "" };
+            format!("({}{} {})", kind, mutable, dump_expr(operand))
+        }
+        ExprKind::Binary {
+            operator,
+            left,
+            right,
+        } => format!("({} {} {})", operator, dump_expr(left), dump_expr(right)),
+        ExprKind::Assign {
+            operator,
+            target,
+            value,
+        } => {
+            let operator = operator.map_or(String::new(), |x| x.to_string());
+            format!("({}= {} {})", operator, dump_expr(target), dump_expr(value))
+        }
+        ExprKind::Tuple(elements) => {
+            let elements: Vec<String> = elements.iter().map(dump_expr).collect();
+            format!(
+                "(tuple{})",
+                elements
+                    .iter()
+                    .map(|x| format!(" {}", x))
+                    .collect::<String>()
+            )
+        }
+        ExprKind::Array(elements) => {
+            let elements: Vec<String> = elements.iter().map(dump_expr).collect();
+            format!(
+                "(array{})",
+                elements
+                    .iter()
+                    .map(|x| format!(" {}", x))
+                    .collect::<String>()
+            )
+        }
+        ExprKind::Repeat { value, length } => {
+            format!("(repeat {} {})", dump_expr(value), dump_expr(length))
+        }
+        ExprKind::Call { callee, arguments } => {
+            let mut result = format!("(call {}", dump_expr(callee));
+            for argument in arguments {
+                result.push(' ');
+                result.push_str(&dump_expr(argument));
+            }
+            result.push(')');
+            result
+        }
+        ExprKind::Field { object, field } => format!("(. {} {})", dump_expr(object), field.symbol),
+        ExprKind::Block(block) => dump_block(block),
+        ExprKind::Unsafe(block) => format!("(unsafe {})", dump_block(block)),
+        ExprKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => match else_branch {
+            Some(else_branch) => format!(
+                "(if {} {} {})",
+                dump_expr(condition),
+                dump_block(then_branch),
+                dump_expr(else_branch)
+            ),
+            None => format!("(if {} {})", dump_expr(condition), dump_block(then_branch)),
+        },
+        ExprKind::For {
+            kind,
+            pattern,
+            iterable,
+            body,
+        } => format!(
+            "(for {} {} {} {})",
+            dump_pattern(pattern),
+            kind,
+            dump_expr(iterable),
+            dump_block(body)
+        ),
+        ExprKind::When { scrutinee, arms } => {
+            let mut result = format!("(when {}", dump_expr(scrutinee));
+            for arm in arms {
+                result.push_str(&format!(" ({}", dump_pattern(&arm.pattern)));
+                if let Some(guard) = &arm.guard {
+                    result.push_str(&format!(" if {}", dump_expr(guard)));
+                }
+                result.push_str(&format!(" => {})", dump_expr(&arm.body)));
+            }
+            result.push(')');
+            result
+        }
+        ExprKind::Return(Some(value)) => format!("(ret {})", dump_expr(value)),
+        ExprKind::Return(None) => "(ret)".to_string(),
+        ExprKind::Yield(value) => format!("(yield {})", dump_expr(value)),
+        ExprKind::Closure(function) => dump_function("", function),
+        ExprKind::Error => "<error>".to_string(),
+    }
+}
+
+/// Writes a pattern out the way it would be written in the source
+pub fn dump_pattern(pattern: &Pattern) -> String {
+    match &pattern.kind {
+        PatternKind::Wildcard => "_".to_string(),
+        PatternKind::Binding { mutable, name } => {
+            let mutable = if *mutable { "mut " } else { "" };
+            format!("{}{}", mutable, name.symbol)
+        }
+        PatternKind::Literal(literal) => dump_literal(literal),
+        PatternKind::Range {
+            start,
+            end,
+            inclusive,
+        } => {
+            let operator = if *inclusive { "..=" } else { ".." };
+            format!("{}{}{}", dump_literal(start), operator, dump_literal(end))
+        }
+        PatternKind::Tuple(elements) => {
+            let elements: Vec<String> = elements.iter().map(dump_pattern).collect();
+            match elements.len() {
+                1 => format!("({},)", elements[0]),
+                _ => format!("({})", elements.join(", ")),
+            }
+        }
+        PatternKind::Variant { path, payload } if payload.is_empty() => path.to_string(),
+        PatternKind::Variant { path, payload } => {
+            let payload: Vec<String> = payload.iter().map(dump_pattern).collect();
+            format!("{}({})", path, payload.join(", "))
+        }
+    }
+}
@@ -0,0 +1,32 @@
+use std::{error::Error, fmt::Display};
+
+use shark_core::{diagnostic::Diagnostic, source::Span};
+use shark_lex::token::TokenKind;
+
+/// Raised when the [crate::Parser] finds a token it did not expect
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnexpectedTokenError {
+    /// A description of what could have been written instead, such as "an expression"
+    pub expected: String,
+    /// The token which was found, or [None] if the end of the file was reached
+    pub found: Option<TokenKind>,
+    pub span: Span,
+}
+
+impl Display for UnexpectedTokenError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match &self.found {
+            Some(found) => write!(f, "expected {}, found {}", self.expected, found),
+            None => write!(f, "expected {}, found the end of the file", self.expected),
+        }
+    }
+}
+
+impl Error for UnexpectedTokenError {}
+
+impl UnexpectedTokenError {
+    pub fn to_diagnostic(&self) -> Diagnostic {
+        Diagnostic::error(self.to_string())
+            .with_primary(self.span, format!("expected {}", self.expected))
+    }
+}
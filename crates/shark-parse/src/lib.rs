@@ -0,0 +1,1965 @@
+use std::path;
+
+use ast::{
+    Abi, BinaryOperator, Block, ConstDecl, EnumDecl, Expr, ExprKind, FieldDecl, FieldInit, ForKind,
+    Function, GenericParam, Generics, Ident, ImplDecl, Item, ItemKind, Let, Module, NodeId,
+    Parameter, Path, Pattern, PatternKind, ReferenceKind, Statement, StatementKind, TraitDecl,
+    TypeDecl, TypeExpr, TypeExprKind, UnaryOperator, UseDecl, Variant, Visibility, WhenArm,
+    WherePredicate,
+};
+use cst::{Checkpoint, CstSink, NodeKind, SyntaxNode};
+use error::UnexpectedTokenError;
+use shark_core::{diagnostic::Diagnostic, source::Span, symbol::sym};
+use shark_lex::{
+    token::{KeywordKind, LexerToken, LiteralKind, TokenKind},
+    Lexer,
+};
+
+pub mod ast;
+pub mod cst;
+pub mod dump;
+pub mod error;
+pub mod visit;
+
+#[cfg(test)]
+pub mod tests;
+
+pub type ParseResult<T> = Result<T, UnexpectedTokenError>;
+
+/// Lexes then parses a whole source file into a [Module], failing on the first syntax error
+pub fn parse(path: Option<&path::Path>, source: &str) -> ParseResult<Module> {
+    let (module, mut errors) = parse_recovering(path, source);
+    if errors.is_empty() {
+        return Ok(module);
+    }
+    Err(errors.swap_remove(0))
+}
+
+/// Lexes then parses a whole source file into a [Module], recovering from syntax errors. Anything
+/// which could not be parsed is replaced with an error node and every error is returned
+pub fn parse_recovering(
+    path: Option<&path::Path>,
+    source: &str,
+) -> (Module, Vec<UnexpectedTokenError>) {
+    let mut lexer = Lexer::new(path, source);
+    lexer.lex();
+    let mut parser = Parser::new(&lexer.completed_tokens);
+    let module = parser.parse_module();
+    (module, parser.errors)
+}
+
+/// Lexes then parses a whole source file, building both the [Module] and a lossless concrete
+/// syntax tree whose text is exactly the source. Every problem found by the [Lexer] and the
+/// [Parser] is returned as a [Diagnostic]
+pub fn parse_syntax(
+    path: Option<&path::Path>,
+    source: &str,
+) -> (Module, SyntaxNode, Vec<Diagnostic>) {
+    let mut lexer = Lexer::new(path, source);
+    lexer.keep_trivia = true;
+    lexer.lex();
+    let tokens: Vec<LexerToken> = lexer
+        .completed_tokens
+        .iter()
+        .filter(|x| !x.kind.is_trivia())
+        .cloned()
+        .collect();
+
+    let mut parser = Parser::with_syntax_tree(&tokens, &lexer.completed_tokens);
+    let module = parser.parse_module();
+
+    let mut diagnostics = lexer.errors;
+    // Unknown tokens have already been reported by the lexer
+    diagnostics.extend(
+        parser
+            .errors
+            .iter()
+            .filter(|x| x.found != Some(TokenKind::Unknown))
+            .map(UnexpectedTokenError::to_diagnostic),
+    );
+    diagnostics.sort_by_key(|x| x.primary_span().map(|x| x.start));
+
+    let syntax = parser
+        .into_syntax_tree()
+        .expect("the parser was building a syntax tree");
+    (module, syntax, diagnostics)
+}
+
+/// Step two of compilation. Turns the [LexerToken]s produced by the [Lexer] into an abstract
+/// syntax tree. Expressions are parsed using precedence climbing, see
+/// [BinaryOperator::precedence], while everything else is plain recursive descent
+///
+/// When a syntax error is found the [Parser] records it, skips ahead to the next synchronisation
+/// point (the end of a statement, a closing `}` or the start of the next item) and carries on
+///
+/// The same grammar also builds the concrete syntax tree, see [cst]. When the [Parser] is made
+/// with [Parser::with_syntax_tree] every rule wraps the tokens it consumes in a node
+#[derive(Debug)]
+pub struct Parser<'parser> {
+    pub tokens: &'parser [LexerToken<'parser>],
+    pub cursor: usize,
+    pub errors: Vec<UnexpectedTokenError>,
+    next_id: u32,
+
+    /// Set when a negative numeric literal is being used as the right hand side of a subtraction,
+    /// such as `a -1`. The lexer glues the `-` onto the literal so the parser splits it back apart
+    split_negative_literal: bool,
+
+    /// Set while parsing an expression which is followed by a block, such as the condition of an
+    /// `if`. Struct literals are not allowed there since `if a { ... }` would be ambiguous
+    no_struct_literal: bool,
+
+    /// Set when a `>>` has been consumed by a list of type arguments, such as `Pair<Pair<T>>`,
+    /// while the second `>` still has to close the list around it
+    split_shift_right: bool,
+    /// How many lists of type arguments are being parsed within each other
+    type_argument_depth: usize,
+
+    /// Set when a statement starts with a block-like expression, such as `if c { ... }`, which
+    /// ends the statement unless it is followed by a `.`. Otherwise a next line starting with
+    /// `-1`, `*r` or `(x)` would carry on the expression
+    block_statement: bool,
+    /// Set once the block-like expression starting a statement has ended it, so that no operator
+    /// is applied to it
+    statement_ended: bool,
+
+    cst: Option<CstSink<'parser>>,
+}
+
+impl<'parser> Parser<'parser> {
+    pub fn new(tokens: &'parser [LexerToken<'parser>]) -> Self {
+        Self {
+            tokens,
+            cursor: 0,
+            errors: Vec::new(),
+            next_id: 0,
+            split_negative_literal: false,
+            no_struct_literal: false,
+            split_shift_right: false,
+            type_argument_depth: 0,
+            block_statement: false,
+            statement_ended: false,
+            cst: None,
+        }
+    }
+
+    /// Creates a [Parser] which also builds a concrete syntax tree. `tokens` are the tokens to
+    /// parse while `all_tokens` are the same tokens with the trivia between them kept
+    pub fn with_syntax_tree(
+        tokens: &'parser [LexerToken<'parser>],
+        all_tokens: &'parser [LexerToken<'parser>],
+    ) -> Self {
+        Self {
+            cst: Some(CstSink::new(all_tokens)),
+            ..Self::new(tokens)
+        }
+    }
+
+    /// Finishes the concrete syntax tree, if one was being built. Must be called after
+    /// [Parser::parse_module]
+    pub fn into_syntax_tree(self) -> Option<SyntaxNode> {
+        let cst = self.cst?;
+        Some(SyntaxNode::new_root(cst.builder.finish()))
+    }
+
+    fn next_id(&mut self) -> NodeId {
+        let id = NodeId(self.next_id);
+        self.next_id += 1;
+        id
+    }
+
+    fn peek(&self) -> Option<&'parser LexerToken<'parser>> {
+        self.tokens.get(self.cursor)
+    }
+
+    fn peek_kind(&self) -> Option<TokenKind> {
+        self.peek().map(|x| x.kind)
+    }
+
+    fn bump(&mut self) -> Option<&'parser LexerToken<'parser>> {
+        let token = self.tokens.get(self.cursor)?;
+        self.cursor += 1;
+        if let Some(cst) = &mut self.cst {
+            cst.token(token);
+        }
+        Some(token)
+    }
+
+    /// Marks the start of the next token, letting a node be started there once the parser knows
+    /// what it is
+    fn checkpoint(&mut self) -> Checkpoint {
+        let start = self.current_span().start;
+        match &mut self.cst {
+            Some(cst) => {
+                // Trivia before the next token belongs to whatever node is already open
+                cst.flush_before(start);
+                cst.builder.checkpoint()
+            }
+            None => Checkpoint::default(),
+        }
+    }
+
+    /// Parses a rule within a node of the concrete syntax tree. The node is finished even when the
+    /// rule fails so the tree stays balanced
+    fn node<T>(
+        &mut self,
+        kind: NodeKind,
+        parse: impl FnOnce(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<T> {
+        let checkpoint = self.checkpoint();
+        self.node_at(checkpoint, kind, parse)
+    }
+
+    /// Like [Parser::node], but the node also wraps everything parsed since the [Checkpoint]
+    fn node_at<T>(
+        &mut self,
+        checkpoint: Checkpoint,
+        kind: NodeKind,
+        parse: impl FnOnce(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<T> {
+        if let Some(cst) = &mut self.cst {
+            cst.builder.start_node_at(checkpoint, kind);
+        }
+        let result = parse(self);
+        if let Some(cst) = &mut self.cst {
+            cst.builder.finish_node();
+        }
+        result
+    }
+
+    /// Parses a rule with struct literals either allowed or not, see [Parser::no_struct_literal]
+    fn with_struct_literal<T>(
+        &mut self,
+        allowed: bool,
+        parse: impl FnOnce(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<T> {
+        let previous = std::mem::replace(&mut self.no_struct_literal, !allowed);
+        let result = parse(self);
+        self.no_struct_literal = previous;
+        result
+    }
+
+    /// Wraps everything parsed since the [Checkpoint] in a node
+    fn wrap_since(&mut self, checkpoint: Checkpoint, kind: NodeKind) {
+        if let Some(cst) = &mut self.cst {
+            cst.builder.start_node_at(checkpoint, kind);
+            cst.builder.finish_node();
+        }
+    }
+
+    fn at(&self, kind: TokenKind) -> bool {
+        self.peek_kind() == Some(kind)
+    }
+
+    fn at_keyword(&self, keyword: KeywordKind) -> bool {
+        self.at(TokenKind::Keyword(keyword))
+    }
+
+    /// Consumes the next token if it is of the provided [TokenKind]
+    fn eat(&mut self, kind: TokenKind) -> bool {
+        if self.at(kind) {
+            self.bump();
+            return true;
+        }
+        false
+    }
+
+    /// Consumes the next token, returning an error if it is not of the provided [TokenKind]
+    fn expect(&mut self, kind: TokenKind) -> ParseResult<Span> {
+        if self.at(kind) {
+            return Ok(self.bump().expect("a token was just peeked").span);
+        }
+        Err(self.error(&kind.to_string()))
+    }
+
+    fn expect_identifier(&mut self) -> ParseResult<Ident> {
+        if let Some(LexerToken {
+            kind: TokenKind::Identifier(symbol),
+            span,
+            ..
+        }) = self.peek()
+        {
+            self.bump();
+            return Ok(Ident {
+                symbol: *symbol,
+                span: *span,
+            });
+        }
+        Err(self.error("an identifier"))
+    }
+
+    /// Gets the [Span] of the next token, or an empty [Span] at the end of the file if there are
+    /// no tokens left
+    fn current_span(&self) -> Span {
+        match self.peek() {
+            Some(token) => token.span,
+            None => {
+                let end = self.tokens.last().map_or(0, |x| x.span.end);
+                Span::new(end, end)
+            }
+        }
+    }
+
+    /// Gets the [Span] of the most recently consumed token
+    fn previous_span(&self) -> Span {
+        match self.cursor.checked_sub(1).and_then(|x| self.tokens.get(x)) {
+            Some(token) => token.span,
+            None => Span::default(),
+        }
+    }
+
+    fn error(&self, expected: &str) -> UnexpectedTokenError {
+        UnexpectedTokenError {
+            expected: expected.to_string(),
+            found: self.peek_kind(),
+            span: self.current_span(),
+        }
+    }
+
+    /// Records an error, unless one was already recorded at the same place. This stops a single
+    /// mistake from being reported once by every rule that trips over it
+    fn report(&mut self, error: UnexpectedTokenError) {
+        if self.errors.last().is_some_and(|x| x.span == error.span) {
+            return;
+        }
+        self.errors.push(error);
+    }
+
+    /// Checks if the next token can only be the start of an item. A `fun` followed by `(` starts
+    /// a closure instead
+    fn at_item_start(&self) -> bool {
+        if self.at_keyword(KeywordKind::Fun) {
+            let next = self.tokens.get(self.cursor + 1).map(|x| x.kind);
+            return next != Some(TokenKind::Parenthesis { opened: true });
+        }
+        self.at_item_keyword()
+    }
+
+    /// Checks if the next token is a keyword which starts an item, which is where synchronising
+    /// stops even if it could start a closure
+    fn at_item_keyword(&self) -> bool {
+        matches!(
+            self.peek_kind(),
+            Some(TokenKind::Keyword(
+                KeywordKind::Fun
+                    | KeywordKind::Extern
+                    | KeywordKind::Type
+                    | KeywordKind::Enum
+                    | KeywordKind::Trait
+                    | KeywordKind::Impl
+                    | KeywordKind::Use
+                    | KeywordKind::Const
+                    | KeywordKind::Pub
+            ))
+        )
+    }
+
+    /// Skips tokens up to the next synchronisation point. A `;` is consumed while a closing `}` or
+    /// the start of an item is left for the caller. Anything nested within `{ }` is skipped whole
+    fn synchronize(&mut self) {
+        let mut depth = 0usize;
+        while let Some(kind) = self.peek_kind() {
+            match kind {
+                TokenKind::CurlyBrace { opened: true } => depth += 1,
+                TokenKind::CurlyBrace { opened: false } if depth == 0 => return,
+                TokenKind::CurlyBrace { opened: false } => depth -= 1,
+                TokenKind::EOL if depth == 0 => {
+                    self.bump();
+                    return;
+                }
+                _ if depth == 0 && self.at_item_keyword() => return,
+                _ => {}
+            }
+            self.bump();
+        }
+    }
+
+    /// Recovers from a syntax error by synchronising, see [Parser::synchronize]. If the parser is
+    /// stuck on a token and `skip_stuck` agrees, that token is skipped. Anything skipped is wrapped
+    /// in a [NodeKind::Error] node
+    fn recover(&mut self, skip_stuck: impl Fn(&Self) -> bool) {
+        let checkpoint = self.checkpoint();
+        let cursor = self.cursor;
+        self.synchronize();
+        if self.cursor == cursor && skip_stuck(self) {
+            self.bump();
+        }
+        if self.cursor != cursor {
+            self.wrap_since(checkpoint, NodeKind::Error);
+        }
+    }
+
+    pub fn parse_module(&mut self) -> Module {
+        if let Some(cst) = &mut self.cst {
+            cst.builder.start_node(NodeKind::SourceFile);
+        }
+
+        let mut items = Vec::new();
+        while self.peek().is_some() {
+            items.push(self.parse_item());
+        }
+
+        if let Some(cst) = &mut self.cst {
+            // Whatever trivia is left after the last token
+            cst.flush_before(usize::MAX);
+            cst.builder.finish_node();
+        }
+        Module {
+            items,
+            span: Span::new(0, self.current_span().end),
+        }
+    }
+
+    pub fn parse_item(&mut self) -> Item {
+        let checkpoint = self.checkpoint();
+        let start = self.current_span();
+        let visibility = if self.eat(TokenKind::Keyword(KeywordKind::Pub)) {
+            Visibility::Public
+        } else {
+            Visibility::Private
+        };
+
+        let kind = match self.parse_item_kind(checkpoint) {
+            Ok(kind) => kind,
+            Err(error) => {
+                self.report(error);
+                // A stray `}` can not be the start of anything so it has to be skipped here
+                self.recover(|x| !x.at_item_keyword());
+                ItemKind::Error
+            }
+        };
+        Item {
+            id: self.next_id(),
+            visibility,
+            kind,
+            span: start.to(self.previous_span()),
+        }
+    }
+
+    /// Parses what follows the visibility of an item. The node of the item starts at the
+    /// [Checkpoint] so that it includes the visibility
+    fn parse_item_kind(&mut self, checkpoint: Checkpoint) -> ParseResult<ItemKind> {
+        match self.peek_kind() {
+            Some(TokenKind::Keyword(KeywordKind::Extern)) if self.at_extern_type() => {
+                Ok(ItemKind::Type(self.node_at(
+                    checkpoint,
+                    NodeKind::TypeDecl,
+                    Self::parse_type_decl,
+                )?))
+            }
+            Some(TokenKind::Keyword(
+                KeywordKind::Fun | KeywordKind::Unsafe | KeywordKind::Extern,
+            )) => Ok(ItemKind::Function(self.node_at(
+                checkpoint,
+                NodeKind::Function,
+                Self::parse_function,
+            )?)),
+            Some(TokenKind::Keyword(KeywordKind::Type)) => Ok(ItemKind::Type(self.node_at(
+                checkpoint,
+                NodeKind::TypeDecl,
+                Self::parse_type_decl,
+            )?)),
+            Some(TokenKind::Keyword(KeywordKind::Enum)) => Ok(ItemKind::Enum(self.node_at(
+                checkpoint,
+                NodeKind::EnumDecl,
+                Self::parse_enum_decl,
+            )?)),
+            Some(TokenKind::Keyword(KeywordKind::Trait)) => Ok(ItemKind::Trait(self.node_at(
+                checkpoint,
+                NodeKind::TraitDecl,
+                Self::parse_trait_decl,
+            )?)),
+            Some(TokenKind::Keyword(KeywordKind::Impl)) => Ok(ItemKind::Impl(self.node_at(
+                checkpoint,
+                NodeKind::ImplDecl,
+                Self::parse_impl_decl,
+            )?)),
+            Some(TokenKind::Keyword(KeywordKind::Use)) => Ok(ItemKind::Use(self.node_at(
+                checkpoint,
+                NodeKind::UseDecl,
+                Self::parse_use_decl,
+            )?)),
+            Some(TokenKind::Keyword(KeywordKind::Const)) => Ok(ItemKind::Const(self.node_at(
+                checkpoint,
+                NodeKind::ConstDecl,
+                Self::parse_const_decl,
+            )?)),
+            _ => Err(self.error("an item")),
+        }
+    }
+
+    /// Checks if the next tokens start an `extern type` or an `extern "ABI" type`
+    fn at_extern_type(&self) -> bool {
+        let kind = |offset: usize| self.tokens.get(self.cursor + offset).map(|x| x.kind);
+        match kind(1) {
+            Some(TokenKind::Literal(LiteralKind::Str(_))) => {
+                kind(2) == Some(TokenKind::Keyword(KeywordKind::Type))
+            }
+            kind => kind == Some(TokenKind::Keyword(KeywordKind::Type)),
+        }
+    }
+
+    /// Parses `extern`, followed by the string naming a foreign ABI if there is one
+    fn parse_extern(&mut self) -> ParseResult<(bool, Option<Abi>)> {
+        if !self.eat(TokenKind::Keyword(KeywordKind::Extern)) {
+            return Ok((false, None));
+        }
+        let Some(TokenKind::Literal(LiteralKind::Str(name))) = self.peek_kind() else {
+            return Ok((true, None));
+        };
+        match Abi::from_name(name.as_str()) {
+            Some(abi) => {
+                self.bump();
+                Ok((true, Some(abi)))
+            }
+            None => Err(self.error("the ABI `\"C\"`")),
+        }
+    }
+
+    fn parse_function(&mut self) -> ParseResult<Function> {
+        let (is_extern, abi) = self.parse_extern()?;
+        let is_unsafe = self.eat(TokenKind::Keyword(KeywordKind::Unsafe));
+        self.expect(TokenKind::Keyword(KeywordKind::Fun))?;
+        let name = self.expect_identifier()?;
+        let mut generics = self.parse_generic_params()?;
+
+        // A `...` stands in for the parameters of a variadic function, and must come last
+        let mut variadic = false;
+        let parameters = self.node(NodeKind::ParameterList, |parser| {
+            parser.expect(TokenKind::Parenthesis { opened: true })?;
+            let parameters = parser.parse_comma_separated(
+                TokenKind::Parenthesis { opened: false },
+                |parser| {
+                    if variadic {
+                        return Err(parser.error("`)` after `...`"));
+                    }
+                    if parser.eat(TokenKind::DotDotDot) {
+                        variadic = true;
+                        return Ok(None);
+                    }
+                    parser.parse_parameter().map(Some)
+                },
+            )?;
+            Ok(parameters.into_iter().flatten().collect())
+        })?;
+
+        let return_type = if self.eat(TokenKind::TypeAssign) {
+            Some(self.parse_type()?)
+        } else {
+            None
+        };
+        generics.where_clause = self.parse_where_clause()?;
+
+        // A signature without a body, such as a required method of a trait
+        let body = if self.eat(TokenKind::EOL) {
+            None
+        } else {
+            Some(self.parse_block()?)
+        };
+        Ok(Function {
+            is_extern,
+            abi,
+            is_unsafe,
+            name,
+            generics,
+            parameters,
+            is_variadic: variadic,
+            return_type,
+            body,
+        })
+    }
+
+    /// Parses `<Name :: Bound, ...>` if the next token is a `<`. The `where` clause is left empty
+    /// since it comes later in the item
+    fn parse_generic_params(&mut self) -> ParseResult<Generics> {
+        if !self.at(TokenKind::Lesser) {
+            return Ok(Generics::default());
+        }
+        let params = self.node(NodeKind::GenericParamList, |parser| {
+            parser.bump();
+            parser.parse_comma_separated(TokenKind::Greater, Self::parse_generic_param)
+        })?;
+        Ok(Generics {
+            params,
+            where_clause: Vec::new(),
+        })
+    }
+
+    fn parse_generic_param(&mut self) -> ParseResult<GenericParam> {
+        self.node(NodeKind::GenericParam, |parser| {
+            let name = parser.expect_identifier()?;
+            let bounds = if parser.eat(TokenKind::TypeAssign) {
+                parser.parse_bounds()?
+            } else {
+                Vec::new()
+            };
+            Ok(GenericParam {
+                id: parser.next_id(),
+                name,
+                bounds,
+                span: name.span.to(parser.previous_span()),
+            })
+        })
+    }
+
+    /// Parses `where Type :: Bound, ...` if the next token is `where`
+    fn parse_where_clause(&mut self) -> ParseResult<Vec<WherePredicate>> {
+        if !self.at_keyword(KeywordKind::Where) {
+            return Ok(Vec::new());
+        }
+        self.node(NodeKind::WhereClause, |parser| {
+            parser.bump();
+            let mut predicates = vec![parser.parse_where_predicate()?];
+            while parser.eat(TokenKind::Comma) {
+                // A trailing comma is followed by the body of the item
+                if parser.at(TokenKind::CurlyBrace { opened: true }) || parser.at(TokenKind::EOL) {
+                    break;
+                }
+                predicates.push(parser.parse_where_predicate()?);
+            }
+            Ok(predicates)
+        })
+    }
+
+    fn parse_where_predicate(&mut self) -> ParseResult<WherePredicate> {
+        self.node(NodeKind::WherePredicate, |parser| {
+            let ty = parser.parse_type()?;
+            parser.expect(TokenKind::TypeAssign)?;
+            let bounds = parser.parse_bounds()?;
+            Ok(WherePredicate {
+                id: parser.next_id(),
+                span: ty.span.to(parser.previous_span()),
+                ty,
+                bounds,
+            })
+        })
+    }
+
+    /// Parses the traits a type is bound by, `Trait + Trait + ...`
+    fn parse_bounds(&mut self) -> ParseResult<Vec<Path>> {
+        self.node(NodeKind::BoundList, |parser| {
+            let mut bounds = vec![parser.parse_path()?];
+            while parser.eat(TokenKind::Plus) {
+                bounds.push(parser.parse_path()?);
+            }
+            Ok(bounds)
+        })
+    }
+
+    /// Parses `name::name::...` outside of an expression
+    fn parse_path(&mut self) -> ParseResult<Path> {
+        let first = self.expect_identifier()?;
+        let mut segments = vec![first];
+        while self.eat(TokenKind::TypeAssign) {
+            segments.push(self.expect_identifier()?);
+        }
+        Ok(Path {
+            segments,
+            span: first.span.to(self.previous_span()),
+        })
+    }
+
+    fn parse_trait_decl(&mut self) -> ParseResult<TraitDecl> {
+        self.expect(TokenKind::Keyword(KeywordKind::Trait))?;
+        let name = self.expect_identifier()?;
+        let methods = self.parse_methods()?;
+        Ok(TraitDecl { name, methods })
+    }
+
+    fn parse_impl_decl(&mut self) -> ParseResult<ImplDecl> {
+        self.expect(TokenKind::Keyword(KeywordKind::Impl))?;
+        let mut generics = self.parse_generic_params()?;
+        let trait_path = match self.impl_has_trait() {
+            true => {
+                let path = self.parse_path()?;
+                self.expect(TokenKind::Keyword(KeywordKind::For))?;
+                Some(path)
+            }
+            false => None,
+        };
+        let self_type = self.parse_type()?;
+        // `impl Trait Type` is a trait implementation missing its `for`
+        if trait_path.is_none() && matches!(self.peek_kind(), Some(TokenKind::Identifier(_))) {
+            return Err(self.error("keyword `for`"));
+        }
+        generics.where_clause = self.parse_where_clause()?;
+        let methods = self.parse_methods()?;
+        Ok(ImplDecl {
+            generics,
+            trait_path,
+            self_type,
+            methods,
+        })
+    }
+
+    /// Checks if the header of an `impl` names a trait, which is followed by `for` before the
+    /// body or the `where` clause starts
+    fn impl_has_trait(&self) -> bool {
+        self.tokens[self.cursor..]
+            .iter()
+            .map(|x| x.kind)
+            .take_while(|x| {
+                !matches!(
+                    x,
+                    TokenKind::CurlyBrace { opened: true }
+                        | TokenKind::EOL
+                        | TokenKind::Keyword(KeywordKind::Where)
+                )
+            })
+            .any(|x| x == TokenKind::Keyword(KeywordKind::For))
+    }
+
+    fn parse_use_decl(&mut self) -> ParseResult<UseDecl> {
+        self.expect(TokenKind::Keyword(KeywordKind::Use))?;
+        let first = self.expect_identifier()?;
+        let mut segments = vec![first];
+        let mut group = None;
+        while self.eat(TokenKind::TypeAssign) {
+            if self.at(TokenKind::CurlyBrace { opened: true }) {
+                group = Some(self.node(NodeKind::UseGroup, |parser| {
+                    parser.bump();
+                    parser.parse_comma_separated(
+                        TokenKind::CurlyBrace { opened: false },
+                        Self::expect_identifier,
+                    )
+                })?);
+                break;
+            }
+            segments.push(self.expect_identifier()?);
+        }
+        let path = Path {
+            span: first
+                .span
+                .to(segments.last().expect("there is a first segment").span),
+            segments,
+        };
+        self.expect(TokenKind::EOL)?;
+        Ok(UseDecl { path, group })
+    }
+
+    fn parse_const_decl(&mut self) -> ParseResult<ConstDecl> {
+        self.expect(TokenKind::Keyword(KeywordKind::Const))?;
+        let name = self.expect_identifier()?;
+        self.expect(TokenKind::TypeAssign)?;
+        let ty = self.parse_type()?;
+        self.expect(TokenKind::Equal)?;
+        let value = self.parse_expression()?;
+        self.expect(TokenKind::EOL)?;
+        Ok(ConstDecl { name, ty, value })
+    }
+
+    /// Parses the `{ fun ... }` of a trait or an implementation. A method which fails to parse is
+    /// skipped the same way an item would be
+    fn parse_methods(&mut self) -> ParseResult<Vec<Function>> {
+        self.node(NodeKind::MethodList, |parser| {
+            parser.expect(TokenKind::CurlyBrace { opened: true })?;
+            let mut methods = Vec::new();
+            while !parser.eat(TokenKind::CurlyBrace { opened: false }) {
+                if parser.peek().is_none() {
+                    return Err(parser.error("`}`"));
+                }
+                let result = parser.node(NodeKind::Function, Self::parse_function);
+                match result {
+                    Ok(method) => methods.push(method),
+                    Err(error) => {
+                        parser.report(error);
+                        parser.recover(|x| {
+                            !x.at(TokenKind::CurlyBrace { opened: false })
+                                && !x.at_keyword(KeywordKind::Fun)
+                                && !x.at_keyword(KeywordKind::Extern)
+                                && !x.at_keyword(KeywordKind::Unsafe)
+                        });
+                    }
+                }
+            }
+            Ok(methods)
+        })
+    }
+
+    fn parse_type_decl(&mut self) -> ParseResult<TypeDecl> {
+        let (is_extern, abi) = self.parse_extern()?;
+        self.expect(TokenKind::Keyword(KeywordKind::Type))?;
+        let name = self.expect_identifier()?;
+        let mut generics = self.parse_generic_params()?;
+        generics.where_clause = self.parse_where_clause()?;
+        // Only C types can have fields along with being `extern`, since the layout they need is
+        // known. An opaque C type ends with a `;` like the types of the runtime
+        if is_extern && (abi.is_none() || self.at(TokenKind::EOL)) {
+            self.expect(TokenKind::EOL)?;
+            return Ok(TypeDecl {
+                is_extern,
+                abi,
+                name,
+                generics,
+                fields: Vec::new(),
+            });
+        }
+        let fields = self.node(NodeKind::FieldList, |parser| {
+            parser.expect(TokenKind::CurlyBrace { opened: true })?;
+            parser.parse_comma_separated(
+                TokenKind::CurlyBrace { opened: false },
+                Self::parse_field_decl,
+            )
+        })?;
+        Ok(TypeDecl {
+            is_extern,
+            abi,
+            name,
+            generics,
+            fields,
+        })
+    }
+
+    fn parse_field_decl(&mut self) -> ParseResult<FieldDecl> {
+        self.node(NodeKind::FieldDecl, |parser| {
+            let name = parser.expect_identifier()?;
+            parser.expect(TokenKind::TypeAssign)?;
+            let ty = parser.parse_type()?;
+            Ok(FieldDecl {
+                id: parser.next_id(),
+                name,
+                span: name.span.to(parser.previous_span()),
+                ty,
+            })
+        })
+    }
+
+    fn parse_enum_decl(&mut self) -> ParseResult<EnumDecl> {
+        self.expect(TokenKind::Keyword(KeywordKind::Enum))?;
+        let name = self.expect_identifier()?;
+        let mut generics = self.parse_generic_params()?;
+        generics.where_clause = self.parse_where_clause()?;
+        let variants = self.node(NodeKind::VariantList, |parser| {
+            parser.expect(TokenKind::CurlyBrace { opened: true })?;
+            parser
+                .parse_comma_separated(TokenKind::CurlyBrace { opened: false }, Self::parse_variant)
+        })?;
+        Ok(EnumDecl {
+            name,
+            generics,
+            variants,
+        })
+    }
+
+    fn parse_variant(&mut self) -> ParseResult<Variant> {
+        self.node(NodeKind::Variant, |parser| {
+            let name = parser.expect_identifier()?;
+            let payload = if parser.eat(TokenKind::Parenthesis { opened: true }) {
+                parser.parse_comma_separated(
+                    TokenKind::Parenthesis { opened: false },
+                    Self::parse_type,
+                )?
+            } else {
+                Vec::new()
+            };
+            let discriminant = match parser.eat(TokenKind::Equal) {
+                true => Some(parser.parse_expression()?),
+                false => None,
+            };
+            Ok(Variant {
+                id: parser.next_id(),
+                name,
+                payload,
+                discriminant,
+                span: name.span.to(parser.previous_span()),
+            })
+        })
+    }
+
+    fn parse_parameter(&mut self) -> ParseResult<Parameter> {
+        self.node(NodeKind::Parameter, |parser| {
+            let start = parser.current_span();
+            let mutable = parser.eat(TokenKind::Keyword(KeywordKind::Mut));
+            let name = parser.expect_identifier()?;
+            if name.symbol == sym::SELF && !parser.at(TokenKind::TypeAssign) {
+                // `self` is short for `self :: Self`
+                let ty = TypeExpr {
+                    id: parser.next_id(),
+                    kind: TypeExprKind::Named {
+                        name: Ident {
+                            symbol: sym::SELF_TYPE,
+                            span: name.span,
+                        },
+                        arguments: Vec::new(),
+                    },
+                    span: name.span,
+                };
+                return Ok(Parameter {
+                    id: parser.next_id(),
+                    mutable,
+                    name,
+                    ty,
+                    span: start.to(parser.previous_span()),
+                });
+            }
+            if !parser.eat(TokenKind::TypeAssign) {
+                let error = parser.error("`::`");
+                // When only the `::` is missing carry on parsing the type, otherwise give up on the
+                // parameter list
+                match parser.peek_kind() {
+                    Some(
+                        TokenKind::Identifier(_)
+                        | TokenKind::Comma
+                        | TokenKind::Parenthesis { opened: false },
+                    ) => parser.report(error),
+                    _ => return Err(error),
+                }
+            }
+            let ty = parser.parse_type()?;
+            Ok(Parameter {
+                id: parser.next_id(),
+                mutable,
+                name,
+                ty,
+                span: start.to(parser.previous_span()),
+            })
+        })
+    }
+
+    /// Parses a list of comma separated elements up to and including the `closing` token. A
+    /// trailing comma is allowed. An element which fails to parse is skipped, see
+    /// [Parser::skip_list_element]
+    fn parse_comma_separated<T>(
+        &mut self,
+        closing: TokenKind,
+        mut parse_element: impl FnMut(&mut Self) -> ParseResult<T>,
+    ) -> ParseResult<Vec<T>> {
+        let mut elements = Vec::new();
+        while !self.eat(closing) {
+            match parse_element(self) {
+                Ok(element) => elements.push(element),
+                Err(error) => {
+                    self.report(error.clone());
+                    if !self.skip_list_element(closing) {
+                        return Err(error);
+                    }
+                }
+            }
+            if !self.eat(TokenKind::Comma) {
+                self.expect(closing)?;
+                break;
+            }
+        }
+        Ok(elements)
+    }
+
+    /// Skips the rest of a list element which failed to parse, up to the next `,` or the `closing`
+    /// token. Returns false if the list seems to end without either, in which case the caller
+    /// gives up on the whole list
+    fn skip_list_element(&mut self, closing: TokenKind) -> bool {
+        let checkpoint = self.checkpoint();
+        let cursor = self.cursor;
+        let mut depth = 0usize;
+        let found = loop {
+            let Some(kind) = self.peek_kind() else {
+                break false;
+            };
+            match kind {
+                _ if depth == 0 && (kind == closing || kind == TokenKind::Comma) => break true,
+                TokenKind::Parenthesis { opened: true }
+                | TokenKind::CurlyBrace { opened: true }
+                | TokenKind::SquareBracket { opened: true } => depth += 1,
+                TokenKind::Parenthesis { opened: false }
+                | TokenKind::CurlyBrace { opened: false }
+                | TokenKind::SquareBracket { opened: false }
+                | TokenKind::EOL
+                    if depth == 0 =>
+                {
+                    break false
+                }
+                TokenKind::Parenthesis { opened: false }
+                | TokenKind::CurlyBrace { opened: false }
+                | TokenKind::SquareBracket { opened: false } => depth -= 1,
+                _ if depth == 0 && self.at_item_start() => break false,
+                _ => {}
+            }
+            self.bump();
+        };
+        if self.cursor != cursor {
+            self.wrap_since(checkpoint, NodeKind::Error);
+        }
+        found
+    }
+
+    pub fn parse_type(&mut self) -> ParseResult<TypeExpr> {
+        if let Some(kind) = self.peek_reference_kind() {
+            return self.node(NodeKind::ReferenceType, |parser| {
+                let start = parser.bump().expect("a token was just peeked").span;
+                let mutable = parser.eat(TokenKind::Keyword(KeywordKind::Mut));
+                let pointee = parser.parse_type()?;
+                Ok(TypeExpr {
+                    id: parser.next_id(),
+                    kind: TypeExprKind::Reference {
+                        kind,
+                        mutable,
+                        pointee: Box::new(pointee),
+                    },
+                    span: start.to(parser.previous_span()),
+                })
+            });
+        }
+        if self.at_keyword(KeywordKind::Yield) {
+            return self.node(NodeKind::GeneratorType, |parser| {
+                let start = parser.bump().expect("a token was just peeked").span;
+                let item = parser.parse_type()?;
+                Ok(TypeExpr {
+                    id: parser.next_id(),
+                    kind: TypeExprKind::Generator(Box::new(item)),
+                    span: start.to(parser.previous_span()),
+                })
+            });
+        }
+        if self.at_keyword(KeywordKind::Fun) {
+            return self.node(NodeKind::FunctionType, |parser| {
+                let start = parser.bump().expect("a token was just peeked").span;
+                parser.expect(TokenKind::Parenthesis { opened: true })?;
+                let parameters = parser.parse_comma_separated(
+                    TokenKind::Parenthesis { opened: false },
+                    Self::parse_type,
+                )?;
+                let return_type = match parser.eat(TokenKind::TypeAssign) {
+                    true => Some(Box::new(parser.parse_type()?)),
+                    false => None,
+                };
+                Ok(TypeExpr {
+                    id: parser.next_id(),
+                    kind: TypeExprKind::Function {
+                        parameters,
+                        return_type,
+                    },
+                    span: start.to(parser.previous_span()),
+                })
+            });
+        }
+        if self.at(TokenKind::SquareBracket { opened: true }) {
+            return self.node(NodeKind::ArrayType, |parser| {
+                let start = parser.bump().expect("a token was just peeked").span;
+                let element = parser.parse_type()?;
+                parser.expect(TokenKind::EOL)?;
+                let length = parser.with_struct_literal(true, Self::parse_expression)?;
+                parser.expect(TokenKind::SquareBracket { opened: false })?;
+                Ok(TypeExpr {
+                    id: parser.next_id(),
+                    kind: TypeExprKind::Array {
+                        element: Box::new(element),
+                        length: Box::new(length),
+                    },
+                    span: start.to(parser.previous_span()),
+                })
+            });
+        }
+        self.node(NodeKind::Type, |parser| {
+            let span = parser.current_span();
+            let kind = match parser.peek_kind() {
+                Some(TokenKind::Identifier(_)) => {
+                    let name = parser.expect_identifier()?;
+                    let arguments = if parser.at(TokenKind::Lesser) {
+                        parser.parse_type_arguments()?
+                    } else {
+                        Vec::new()
+                    };
+                    TypeExprKind::Named { name, arguments }
+                }
+                Some(
+                    TokenKind::Equal
+                    | TokenKind::EOL
+                    | TokenKind::Comma
+                    | TokenKind::CurlyBrace { opened: true }
+                    | TokenKind::Parenthesis { opened: false }
+                    | TokenKind::SquareBracket { opened: false },
+                ) => {
+                    // The type is missing entirely, the token is left for the rule that follows it
+                    let error = parser.error("a type");
+                    parser.report(error);
+                    TypeExprKind::Error
+                }
+                _ => return Err(parser.error("a type")),
+            };
+            Ok(TypeExpr {
+                id: parser.next_id(),
+                kind,
+                span: span.to(parser.previous_span()),
+            })
+        })
+    }
+
+    /// Gets the kind of reference the next token starts, if it is `ref` or `ptr`
+    fn peek_reference_kind(&self) -> Option<ReferenceKind> {
+        match self.peek_kind() {
+            Some(TokenKind::Keyword(KeywordKind::Ref)) => Some(ReferenceKind::Ref),
+            Some(TokenKind::Keyword(KeywordKind::Ptr)) => Some(ReferenceKind::Ptr),
+            _ => None,
+        }
+    }
+
+    /// Parses `<Type, ...>` after the name of a type
+    fn parse_type_arguments(&mut self) -> ParseResult<Vec<TypeExpr>> {
+        self.type_argument_depth += 1;
+        let result = self.node(NodeKind::TypeArgumentList, |parser| {
+            parser.expect(TokenKind::Lesser)?;
+            let mut arguments = vec![parser.parse_type()?];
+            // The `,` check comes second so that a `>>` which closed an inner list is not skipped
+            while !parser.split_shift_right && parser.eat(TokenKind::Comma) {
+                arguments.push(parser.parse_type()?);
+            }
+            if !parser.eat_closing_angle() {
+                return Err(parser.error("`>`"));
+            }
+            Ok(arguments)
+        });
+        self.type_argument_depth -= 1;
+        if result.is_err() {
+            self.split_shift_right = false;
+        }
+        result
+    }
+
+    /// Consumes the `>` which closes a list of type arguments. The lexer turns `>>` into a single
+    /// token, so when two lists close at once the inner list consumes the token and the outer list
+    /// only clears [Parser::split_shift_right]
+    fn eat_closing_angle(&mut self) -> bool {
+        if self.split_shift_right {
+            self.split_shift_right = false;
+            return true;
+        }
+        if self.type_argument_depth > 1 && self.eat(TokenKind::ShiftRight) {
+            self.split_shift_right = true;
+            return true;
+        }
+        self.eat(TokenKind::Greater)
+    }
+
+    pub fn parse_block(&mut self) -> ParseResult<Block> {
+        self.node(NodeKind::Block, |parser| {
+            parser.with_struct_literal(true, Self::parse_block_contents)
+        })
+    }
+
+    fn parse_block_contents(&mut self) -> ParseResult<Block> {
+        let start = self.expect(TokenKind::CurlyBrace { opened: true })?;
+        let mut statements = Vec::new();
+        let mut tail = None;
+
+        while !self.eat(TokenKind::CurlyBrace { opened: false }) {
+            if self.peek().is_none() || self.at_item_start() {
+                // The block was never closed, leave whatever comes next to the item parser
+                let error = self.error("`}`");
+                self.report(error);
+                break;
+            }
+            if let Some(expr) = tail.take() {
+                // A tail expression that is followed by more statements must have been a block-like
+                // expression used as a statement
+                statements.push(Self::expression_statement(self.next_id(), expr));
+            }
+
+            let statement_start = self.current_span();
+            if let Err(error) = self.parse_statement(&mut statements, &mut tail) {
+                self.report(error);
+                self.recover(|x| !x.at(TokenKind::CurlyBrace { opened: false }));
+                statements.push(Statement {
+                    id: self.next_id(),
+                    kind: StatementKind::Error,
+                    span: statement_start.to(self.previous_span()),
+                });
+            }
+        }
+
+        Ok(Block {
+            id: self.next_id(),
+            statements,
+            tail: tail.map(Box::new),
+            span: start.to(self.previous_span()),
+        })
+    }
+
+    /// Parses the next statement of a block, which either gets added to `statements` or, if it is
+    /// an expression without a trailing `;`, becomes the `tail`
+    fn parse_statement(
+        &mut self,
+        statements: &mut Vec<Statement>,
+        tail: &mut Option<Expr>,
+    ) -> ParseResult<()> {
+        if self.eat(TokenKind::EOL) {
+            return Ok(());
+        }
+        if self.at_keyword(KeywordKind::Let) {
+            statements.push(self.parse_let()?);
+            return Ok(());
+        }
+
+        let checkpoint = self.checkpoint();
+        self.block_statement = self.at_block_like_start();
+        let expr = self.parse_expression();
+        self.block_statement = false;
+        self.statement_ended = false;
+        let expr = expr?;
+        if self.eat(TokenKind::EOL) {
+            self.wrap_since(checkpoint, NodeKind::ExprStatement);
+            statements.push(Self::expression_statement(self.next_id(), expr));
+        } else if expr.kind.is_block_like() || self.at(TokenKind::CurlyBrace { opened: false }) {
+            *tail = Some(expr);
+        } else if self.at_statement_start() {
+            // Only the `;` is missing, keep the statement and carry on with the next one
+            let error = self.error("`;` or `}`");
+            self.report(error);
+            self.wrap_since(checkpoint, NodeKind::ExprStatement);
+            statements.push(Self::expression_statement(self.next_id(), expr));
+        } else {
+            return Err(self.error("`;` or `}`"));
+        }
+        Ok(())
+    }
+
+    /// Expects a `;`. If it is missing but the statement is clearly over, because the next token
+    /// starts a new statement or closes the block, the error is recorded and parsing carries on as
+    /// if the `;` was there
+    fn expect_eol(&mut self) -> ParseResult<()> {
+        if self.eat(TokenKind::EOL) {
+            return Ok(());
+        }
+        let error = self.error("`;`");
+        if self.at_statement_start() || self.at(TokenKind::CurlyBrace { opened: false }) {
+            self.report(error);
+            return Ok(());
+        }
+        Err(error)
+    }
+
+    /// Checks if the next token starts a block-like expression, see [ExprKind::is_block_like]
+    fn at_block_like_start(&self) -> bool {
+        matches!(
+            self.peek_kind(),
+            Some(
+                TokenKind::CurlyBrace { opened: true }
+                    | TokenKind::Keyword(
+                        KeywordKind::Unsafe
+                            | KeywordKind::If
+                            | KeywordKind::For
+                            | KeywordKind::When
+                    )
+            )
+        )
+    }
+
+    /// Checks if the next token is a keyword which can only be the start of a statement
+    fn at_statement_start(&self) -> bool {
+        matches!(
+            self.peek_kind(),
+            Some(TokenKind::Keyword(
+                KeywordKind::Let
+                    | KeywordKind::Ret
+                    | KeywordKind::Yield
+                    | KeywordKind::If
+                    | KeywordKind::For
+                    | KeywordKind::When
+            ))
+        )
+    }
+
+    fn expression_statement(id: NodeId, expr: Expr) -> Statement {
+        Statement {
+            id,
+            span: expr.span,
+            kind: StatementKind::Expr(expr),
+        }
+    }
+
+    fn parse_let(&mut self) -> ParseResult<Statement> {
+        self.node(NodeKind::LetStatement, |parser| {
+            let start = parser.expect(TokenKind::Keyword(KeywordKind::Let))?;
+            let mutable = parser.eat(TokenKind::Keyword(KeywordKind::Mut));
+            let name = parser.expect_identifier()?;
+
+            let ty = if parser.eat(TokenKind::TypeAssign) {
+                Some(parser.parse_type()?)
+            } else {
+                None
+            };
+            let value = if parser.eat(TokenKind::Equal) {
+                Some(parser.parse_expression()?)
+            } else {
+                None
+            };
+            parser.expect_eol()?;
+
+            Ok(Statement {
+                id: parser.next_id(),
+                kind: StatementKind::Let(Let {
+                    mutable,
+                    name,
+                    ty,
+                    value,
+                }),
+                span: start.to(parser.previous_span()),
+            })
+        })
+    }
+
+    fn make_expr(&mut self, kind: ExprKind, span: Span) -> Expr {
+        Expr {
+            id: self.next_id(),
+            kind,
+            span,
+        }
+    }
+
+    pub fn parse_expression(&mut self) -> ParseResult<Expr> {
+        let checkpoint = self.checkpoint();
+        let target = self.parse_binary(0)?;
+        if self.statement_ended {
+            return Ok(target);
+        }
+
+        let operator = match self.peek_kind() {
+            Some(TokenKind::Equal) => None,
+            Some(kind) => match BinaryOperator::from_assign_token(&kind) {
+                Some(operator) => Some(operator),
+                None => return Ok(target),
+            },
+            None => return Ok(target),
+        };
+        self.node_at(checkpoint, NodeKind::AssignExpr, |parser| {
+            parser.bump();
+
+            // Assignments are right associative so `a = b = c` assigns `c` to `b` first
+            let value = parser.parse_expression()?;
+            let span = target.span.to(value.span);
+            Ok(parser.make_expr(
+                ExprKind::Assign {
+                    operator,
+                    target: Box::new(target),
+                    value: Box::new(value),
+                },
+                span,
+            ))
+        })
+    }
+
+    /// Checks if the next token is a numeric literal which the lexer has glued a `-` onto
+    fn at_negative_literal(&self) -> bool {
+        self.peek().is_some_and(|x| {
+            matches!(x.kind, TokenKind::Literal(_)) && x.text.starts_with('-') && x.text.len() > 1
+        })
+    }
+
+    fn parse_binary(&mut self, minimum_precedence: u8) -> ParseResult<Expr> {
+        let checkpoint = self.checkpoint();
+        let mut left = self.parse_unary()?;
+        if self.statement_ended {
+            return Ok(left);
+        }
+
+        while let Some(kind) = self.peek_kind() {
+            let (operator, split) = match BinaryOperator::from_token(&kind) {
+                Some(operator) => (operator, false),
+                None if self.at_negative_literal() => (BinaryOperator::Subtract, true),
+                None => break,
+            };
+            if operator.precedence() <= minimum_precedence {
+                break;
+            }
+
+            left = self.node_at(checkpoint, NodeKind::BinaryExpr, |parser| {
+                if split {
+                    parser.split_negative_literal = true;
+                } else {
+                    parser.bump();
+                }
+                let right = parser.parse_binary(operator.precedence())?;
+                let span = left.span.to(right.span);
+                Ok(parser.make_expr(
+                    ExprKind::Binary {
+                        operator,
+                        left: Box::new(left),
+                        right: Box::new(right),
+                    },
+                    span,
+                ))
+            })?;
+        }
+        Ok(left)
+    }
+
+    fn parse_unary(&mut self) -> ParseResult<Expr> {
+        if let Some(kind) = self.peek_reference_kind() {
+            return self.node(NodeKind::ReferenceExpr, |parser| {
+                let start = parser.bump().expect("a token was just peeked").span;
+                let mutable = parser.eat(TokenKind::Keyword(KeywordKind::Mut));
+                let operand = parser.parse_unary()?;
+                let span = start.to(operand.span);
+                Ok(parser.make_expr(
+                    ExprKind::Reference {
+                        kind,
+                        mutable,
+                        operand: Box::new(operand),
+                    },
+                    span,
+                ))
+            });
+        }
+        let operator = match self.peek_kind() {
+            Some(TokenKind::Minus) => UnaryOperator::Negate,
+            Some(TokenKind::Not) => UnaryOperator::Not,
+            Some(TokenKind::Multiply) => UnaryOperator::Deref,
+            _ => return self.parse_postfix(),
+        };
+        self.node(NodeKind::UnaryExpr, |parser| {
+            let start = parser.bump().expect("a token was just peeked").span;
+            let operand = parser.parse_unary()?;
+            let span = start.to(operand.span);
+            Ok(parser.make_expr(
+                ExprKind::Unary {
+                    operator,
+                    operand: Box::new(operand),
+                },
+                span,
+            ))
+        })
+    }
+
+    fn parse_postfix(&mut self) -> ParseResult<Expr> {
+        let checkpoint = self.checkpoint();
+        let block_statement = std::mem::take(&mut self.block_statement);
+        let mut expr = self.parse_primary()?;
+        if block_statement && expr.kind.is_block_like() && !self.at(TokenKind::Dot) {
+            self.statement_ended = true;
+            return Ok(expr);
+        }
+        loop {
+            if self.at(TokenKind::Parenthesis { opened: true }) {
+                expr = self.node_at(checkpoint, NodeKind::CallExpr, |parser| {
+                    let arguments = parser.node(NodeKind::ArgumentList, |parser| {
+                        parser.bump();
+                        parser.with_struct_literal(true, |parser| {
+                            parser.parse_comma_separated(
+                                TokenKind::Parenthesis { opened: false },
+                                Self::parse_expression,
+                            )
+                        })
+                    })?;
+                    let span = expr.span.to(parser.previous_span());
+                    Ok(parser.make_expr(
+                        ExprKind::Call {
+                            callee: Box::new(expr),
+                            arguments,
+                        },
+                        span,
+                    ))
+                })?;
+            } else if self.at(TokenKind::Dot) {
+                expr = self.node_at(checkpoint, NodeKind::FieldExpr, |parser| {
+                    parser.bump();
+                    let field = parser.expect_identifier()?;
+                    let span = expr.span.to(field.span);
+                    Ok(parser.make_expr(
+                        ExprKind::Field {
+                            object: Box::new(expr),
+                            field,
+                        },
+                        span,
+                    ))
+                })?;
+            } else {
+                return Ok(expr);
+            }
+        }
+    }
+
+    fn parse_primary(&mut self) -> ParseResult<Expr> {
+        let Some(token) = self.peek() else {
+            let error = self.error("an expression");
+            self.report(error);
+            return Ok(self.make_expr(ExprKind::Error, self.current_span()));
+        };
+
+        match token.kind {
+            TokenKind::Literal(literal) if self.split_negative_literal => {
+                self.split_negative_literal = false;
+                self.node(NodeKind::LiteralExpr, |parser| {
+                    parser.bump();
+                    let Some(literal) = negate_literal(literal) else {
+                        return Err(parser.error("a literal which fits in its type"));
+                    };
+                    let span = Span::new(token.span.start + 1, token.span.end);
+                    Ok(parser.make_expr(ExprKind::Literal(literal), span))
+                })
+            }
+            TokenKind::Literal(literal) => self.node(NodeKind::LiteralExpr, |parser| {
+                parser.bump();
+                Ok(parser.make_expr(ExprKind::Literal(literal), token.span))
+            }),
+            TokenKind::Identifier(_) => self.parse_path_expression(),
+            TokenKind::Parenthesis { opened: true } => {
+                let checkpoint = self.checkpoint();
+                let result = self.with_struct_literal(true, Self::parse_parenthesized);
+                let kind = match result {
+                    Ok((_, true)) => NodeKind::TupleExpr,
+                    _ => NodeKind::ParenExpr,
+                };
+                self.wrap_since(checkpoint, kind);
+                Ok(result?.0)
+            }
+            TokenKind::SquareBracket { opened: true } => {
+                let checkpoint = self.checkpoint();
+                let result = self.with_struct_literal(true, Self::parse_array);
+                let kind = match &result {
+                    Ok(Expr {
+                        kind: ExprKind::Repeat { .. },
+                        ..
+                    }) => NodeKind::RepeatExpr,
+                    _ => NodeKind::ArrayExpr,
+                };
+                self.wrap_since(checkpoint, kind);
+                result
+            }
+            TokenKind::CurlyBrace { opened: true } => {
+                let block = self.parse_block()?;
+                let span = block.span;
+                Ok(self.make_expr(ExprKind::Block(block), span))
+            }
+            TokenKind::Keyword(KeywordKind::Unsafe) => self.node(NodeKind::UnsafeExpr, |parser| {
+                parser.bump();
+                let block = parser.parse_block()?;
+                let span = token.span.to(block.span);
+                Ok(parser.make_expr(ExprKind::Unsafe(block), span))
+            }),
+            TokenKind::Keyword(KeywordKind::If) => self.parse_if(),
+            TokenKind::Keyword(KeywordKind::For) => self.parse_for(),
+            TokenKind::Keyword(KeywordKind::Fun) => self.parse_closure(),
+            TokenKind::Keyword(KeywordKind::When) => self.parse_when(),
+            TokenKind::EOL
+            | TokenKind::Comma
+            | TokenKind::CurlyBrace { opened: false }
+            | TokenKind::Parenthesis { opened: false }
+            | TokenKind::SquareBracket { opened: false } => {
+                // The expression is missing entirely, so report it and leave the token for whatever
+                // rule can make sense of it
+                let error = self.error("an expression");
+                self.report(error);
+                let span = Span::new(token.span.start, token.span.start);
+                Ok(self.make_expr(ExprKind::Error, span))
+            }
+            TokenKind::Keyword(KeywordKind::Ret) => self.node(NodeKind::ReturnExpr, |parser| {
+                parser.bump();
+                let value = match parser.peek_kind() {
+                    None | Some(TokenKind::EOL) | Some(TokenKind::CurlyBrace { opened: false }) => {
+                        None
+                    }
+                    Some(_) => Some(Box::new(parser.parse_expression()?)),
+                };
+                let span = token.span.to(parser.previous_span());
+                Ok(parser.make_expr(ExprKind::Return(value), span))
+            }),
+            TokenKind::Keyword(KeywordKind::Yield) => self.node(NodeKind::YieldExpr, |parser| {
+                parser.bump();
+                let value = parser.parse_expression()?;
+                let span = token.span.to(value.span);
+                Ok(parser.make_expr(ExprKind::Yield(Box::new(value)), span))
+            }),
+            _ => Err(self.error("an expression")),
+        }
+    }
+
+    /// Parses an expression within parentheses, or a tuple such as `(a, b)`. Returns true along
+    /// with the expression if it is a tuple
+    fn parse_parenthesized(&mut self) -> ParseResult<(Expr, bool)> {
+        let start = self.expect(TokenKind::Parenthesis { opened: true })?;
+        if self.eat(TokenKind::Parenthesis { opened: false }) {
+            let span = start.to(self.previous_span());
+            return Ok((self.make_expr(ExprKind::Tuple(Vec::new()), span), true));
+        }
+
+        let mut first = self.parse_expression()?;
+        if !self.eat(TokenKind::Comma) {
+            let end = self.expect(TokenKind::Parenthesis { opened: false })?;
+            first.span = start.to(end);
+            return Ok((first, false));
+        }
+        let mut elements = vec![first];
+        elements.extend(self.parse_comma_separated(
+            TokenKind::Parenthesis { opened: false },
+            Self::parse_expression,
+        )?);
+        let span = start.to(self.previous_span());
+        Ok((self.make_expr(ExprKind::Tuple(elements), span), true))
+    }
+
+    /// Parses an array such as `[a, b, c]`, or `[value; length]` which repeats a value
+    fn parse_array(&mut self) -> ParseResult<Expr> {
+        let start = self.expect(TokenKind::SquareBracket { opened: true })?;
+        if self.eat(TokenKind::SquareBracket { opened: false }) {
+            let span = start.to(self.previous_span());
+            return Ok(self.make_expr(ExprKind::Array(Vec::new()), span));
+        }
+
+        let first = self.parse_expression()?;
+        if self.eat(TokenKind::EOL) {
+            let length = self.parse_expression()?;
+            let end = self.expect(TokenKind::SquareBracket { opened: false })?;
+            let kind = ExprKind::Repeat {
+                value: Box::new(first),
+                length: Box::new(length),
+            };
+            return Ok(self.make_expr(kind, start.to(end)));
+        }
+        let mut elements = vec![first];
+        if self.eat(TokenKind::Comma) {
+            elements.extend(self.parse_comma_separated(
+                TokenKind::SquareBracket { opened: false },
+                Self::parse_expression,
+            )?);
+        } else {
+            self.expect(TokenKind::SquareBracket { opened: false })?;
+        }
+        let span = start.to(self.previous_span());
+        Ok(self.make_expr(ExprKind::Array(elements), span))
+    }
+
+    /// Parses a name, a path such as `Shape::Circle` or a struct literal such as
+    /// `Point { x = 1.0, y = 2.0 }`
+    fn parse_path_expression(&mut self) -> ParseResult<Expr> {
+        let checkpoint = self.checkpoint();
+        let first = self.expect_identifier()?;
+        let path = if self.at(TokenKind::TypeAssign) {
+            self.node_at(checkpoint, NodeKind::PathExpr, |parser| {
+                let mut segments = vec![first];
+                while parser.eat(TokenKind::TypeAssign) {
+                    segments.push(parser.expect_identifier()?);
+                }
+                Ok(Path {
+                    segments,
+                    span: first.span.to(parser.previous_span()),
+                })
+            })?
+        } else {
+            self.wrap_since(checkpoint, NodeKind::NameExpr);
+            Path {
+                segments: vec![first],
+                span: first.span,
+            }
+        };
+
+        if self.at(TokenKind::CurlyBrace { opened: true }) && !self.no_struct_literal {
+            return self.node_at(checkpoint, NodeKind::StructLiteral, |parser| {
+                parser.parse_struct_literal(path)
+            });
+        }
+        let span = path.span;
+        let kind = match path.segments.as_slice() {
+            [name] => ExprKind::Name(*name),
+            _ => ExprKind::Path(path),
+        };
+        Ok(self.make_expr(kind, span))
+    }
+
+    fn parse_struct_literal(&mut self, path: Path) -> ParseResult<Expr> {
+        let fields = self.node(NodeKind::FieldInitList, |parser| {
+            parser.expect(TokenKind::CurlyBrace { opened: true })?;
+            parser.with_struct_literal(true, |parser| {
+                parser.parse_comma_separated(
+                    TokenKind::CurlyBrace { opened: false },
+                    Self::parse_field_init,
+                )
+            })
+        })?;
+        let span = path.span.to(self.previous_span());
+        Ok(self.make_expr(ExprKind::StructLiteral { path, fields }, span))
+    }
+
+    fn parse_field_init(&mut self) -> ParseResult<FieldInit> {
+        self.node(NodeKind::FieldInit, |parser| {
+            let name = parser.expect_identifier()?;
+            parser.expect(TokenKind::Equal)?;
+            let value = parser.parse_expression()?;
+            Ok(FieldInit {
+                name,
+                span: name.span.to(value.span),
+                value,
+            })
+        })
+    }
+
+    /// Parses a closure, `fun(parameters) :: ReturnType { body }`
+    fn parse_closure(&mut self) -> ParseResult<Expr> {
+        self.node(NodeKind::ClosureExpr, |parser| {
+            let start = parser.expect(TokenKind::Keyword(KeywordKind::Fun))?;
+            let parameters = parser.node(NodeKind::ParameterList, |parser| {
+                parser.expect(TokenKind::Parenthesis { opened: true })?;
+                parser.parse_comma_separated(
+                    TokenKind::Parenthesis { opened: false },
+                    Self::parse_parameter,
+                )
+            })?;
+            let return_type = match parser.eat(TokenKind::TypeAssign) {
+                true => Some(parser.parse_type()?),
+                false => None,
+            };
+            let body = parser.parse_block()?;
+            let span = start.to(body.span);
+            let function = Function {
+                is_extern: false,
+                abi: None,
+                is_unsafe: false,
+                name: Ident {
+                    symbol: sym::CLOSURE,
+                    span: start,
+                },
+                generics: Generics::default(),
+                parameters,
+                is_variadic: false,
+                return_type,
+                body: Some(body),
+            };
+            Ok(parser.make_expr(ExprKind::Closure(Box::new(function)), span))
+        })
+    }
+
+    fn parse_if(&mut self) -> ParseResult<Expr> {
+        self.node(NodeKind::IfExpr, |parser| {
+            let start = parser.expect(TokenKind::Keyword(KeywordKind::If))?;
+            let condition = parser.with_struct_literal(false, Self::parse_expression)?;
+            let then_branch = parser.parse_block()?;
+
+            let else_branch = if parser.eat(TokenKind::Keyword(KeywordKind::Else)) {
+                if parser.at_keyword(KeywordKind::If) {
+                    Some(Box::new(parser.parse_if()?))
+                } else {
+                    let block = parser.parse_block()?;
+                    let span = block.span;
+                    Some(Box::new(parser.make_expr(ExprKind::Block(block), span)))
+                }
+            } else {
+                None
+            };
+
+            let span = start.to(parser.previous_span());
+            Ok(parser.make_expr(
+                ExprKind::If {
+                    condition: Box::new(condition),
+                    then_branch,
+                    else_branch,
+                },
+                span,
+            ))
+        })
+    }
+
+    fn parse_when(&mut self) -> ParseResult<Expr> {
+        self.node(NodeKind::WhenExpr, |parser| {
+            let start = parser.expect(TokenKind::Keyword(KeywordKind::When))?;
+            let scrutinee = parser.with_struct_literal(false, Self::parse_expression)?;
+            let arms = parser.node(NodeKind::WhenArmList, |parser| {
+                parser.expect(TokenKind::CurlyBrace { opened: true })?;
+                parser.with_struct_literal(true, Self::parse_when_arms)
+            })?;
+
+            let span = start.to(parser.previous_span());
+            Ok(parser.make_expr(
+                ExprKind::When {
+                    scrutinee: Box::new(scrutinee),
+                    arms,
+                },
+                span,
+            ))
+        })
+    }
+
+    /// Parses the arms of a `when` up to and including the closing `}`. Arms are separated by
+    /// commas, which can be left out after an arm whose body is a block
+    fn parse_when_arms(&mut self) -> ParseResult<Vec<WhenArm>> {
+        let closing = TokenKind::CurlyBrace { opened: false };
+        let mut arms = Vec::new();
+        while !self.eat(closing) {
+            match self.parse_when_arm() {
+                Ok(arm) => {
+                    let block_like = arm.body.kind.is_block_like();
+                    arms.push(arm);
+                    if self.eat(TokenKind::Comma) || block_like {
+                        continue;
+                    }
+                    self.expect(closing)?;
+                    break;
+                }
+                Err(error) => {
+                    self.report(error.clone());
+                    if !self.skip_list_element(closing) {
+                        return Err(error);
+                    }
+                    self.eat(TokenKind::Comma);
+                }
+            }
+        }
+        Ok(arms)
+    }
+
+    fn parse_when_arm(&mut self) -> ParseResult<WhenArm> {
+        self.node(NodeKind::WhenArm, |parser| {
+            let pattern = parser.parse_pattern()?;
+            let guard = if parser.at_keyword(KeywordKind::If) {
+                Some(parser.node(NodeKind::WhenGuard, |parser| {
+                    parser.bump();
+                    parser.parse_expression()
+                })?)
+            } else {
+                None
+            };
+            parser.expect(TokenKind::FatArrow)?;
+            let body = parser.parse_expression()?;
+            Ok(WhenArm {
+                id: parser.next_id(),
+                span: pattern.span.to(body.span),
+                pattern,
+                guard,
+                body,
+            })
+        })
+    }
+
+    pub fn parse_pattern(&mut self) -> ParseResult<Pattern> {
+        let checkpoint = self.checkpoint();
+        let start = self.current_span();
+        let next = self.tokens.get(self.cursor + 1).map(|x| x.kind);
+        let kind = match self.peek_kind() {
+            Some(TokenKind::Identifier(symbol)) if symbol.as_str() == "_" => {
+                self.node(NodeKind::WildcardPattern, |parser| {
+                    parser.bump();
+                    Ok(PatternKind::Wildcard)
+                })?
+            }
+            Some(TokenKind::Identifier(_)) if next == Some(TokenKind::TypeAssign) => {
+                self.node(NodeKind::VariantPattern, Self::parse_variant_pattern)?
+            }
+            Some(TokenKind::Identifier(_) | TokenKind::Keyword(KeywordKind::Mut)) => {
+                self.node(NodeKind::BindingPattern, |parser| {
+                    let mutable = parser.eat(TokenKind::Keyword(KeywordKind::Mut));
+                    let name = parser.expect_identifier()?;
+                    Ok(PatternKind::Binding { mutable, name })
+                })?
+            }
+            Some(TokenKind::Literal(_)) => self.parse_literal_pattern(checkpoint)?,
+            Some(TokenKind::Parenthesis { opened: true }) => {
+                self.node(NodeKind::TuplePattern, Self::parse_tuple_pattern)?
+            }
+            _ => return Err(self.error("a pattern")),
+        };
+        Ok(Pattern {
+            id: self.next_id(),
+            kind,
+            span: start.to(self.previous_span()),
+        })
+    }
+
+    fn parse_variant_pattern(&mut self) -> ParseResult<PatternKind> {
+        let first = self.expect_identifier()?;
+        let mut segments = vec![first];
+        while self.eat(TokenKind::TypeAssign) {
+            segments.push(self.expect_identifier()?);
+        }
+        let path = Path {
+            segments,
+            span: first.span.to(self.previous_span()),
+        };
+
+        let payload = if self.at(TokenKind::Parenthesis { opened: true }) {
+            self.node(NodeKind::PatternList, |parser| {
+                parser.bump();
+                parser.parse_comma_separated(
+                    TokenKind::Parenthesis { opened: false },
+                    Self::parse_pattern,
+                )
+            })?
+        } else {
+            Vec::new()
+        };
+        Ok(PatternKind::Variant { path, payload })
+    }
+
+    /// Parses `(a, b)`. A single pattern without a trailing comma, such as `(a)`, is only grouped
+    /// by the parentheses rather than being a tuple
+    fn parse_tuple_pattern(&mut self) -> ParseResult<PatternKind> {
+        self.expect(TokenKind::Parenthesis { opened: true })?;
+        if self.eat(TokenKind::Parenthesis { opened: false }) {
+            return Ok(PatternKind::Tuple(Vec::new()));
+        }
+        let first = self.parse_pattern()?;
+        if self.eat(TokenKind::Parenthesis { opened: false }) {
+            return Ok(first.kind);
+        }
+        self.expect(TokenKind::Comma)?;
+
+        let mut elements = vec![first];
+        elements.extend(self.parse_comma_separated(
+            TokenKind::Parenthesis { opened: false },
+            Self::parse_pattern,
+        )?);
+        Ok(PatternKind::Tuple(elements))
+    }
+
+    /// Parses a literal, or a range if the literal is followed by `..` or `..=`
+    fn parse_literal_pattern(&mut self, checkpoint: Checkpoint) -> ParseResult<PatternKind> {
+        let start = self.node(NodeKind::LiteralPattern, Self::expect_literal)?;
+        let inclusive = match self.peek_kind() {
+            Some(TokenKind::DotDot) => false,
+            Some(TokenKind::DotDotEqual) => true,
+            _ => return Ok(PatternKind::Literal(start)),
+        };
+        self.node_at(checkpoint, NodeKind::RangePattern, |parser| {
+            parser.bump();
+            let end = parser.node(NodeKind::LiteralPattern, Self::expect_literal)?;
+            Ok(PatternKind::Range {
+                start,
+                end,
+                inclusive,
+            })
+        })
+    }
+
+    fn expect_literal(&mut self) -> ParseResult<LiteralKind> {
+        match self.peek_kind() {
+            Some(TokenKind::Literal(literal)) => {
+                self.bump();
+                Ok(literal)
+            }
+            _ => Err(self.error("a literal")),
+        }
+    }
+
+    fn parse_for(&mut self) -> ParseResult<Expr> {
+        self.node(NodeKind::ForExpr, |parser| {
+            let start = parser.expect(TokenKind::Keyword(KeywordKind::For))?;
+            let pattern = parser.parse_pattern()?;
+            let kind = match parser.peek_kind() {
+                Some(TokenKind::Keyword(KeywordKind::In)) => ForKind::In,
+                Some(TokenKind::Keyword(KeywordKind::Of)) => ForKind::Of,
+                _ => return Err(parser.error("`in` or `of`")),
+            };
+            parser.bump();
+            let iterable = parser.with_struct_literal(false, Self::parse_expression)?;
+            let body = parser.parse_block()?;
+
+            let span = start.to(parser.previous_span());
+            Ok(parser.make_expr(
+                ExprKind::For {
+                    kind,
+                    pattern,
+                    iterable: Box::new(iterable),
+                    body,
+                },
+                span,
+            ))
+        })
+    }
+}
+
+/// Flips the sign of a numeric [LiteralKind], returning [None] if the result does not fit
+fn negate_literal(literal: LiteralKind) -> Option<LiteralKind> {
+    Some(match literal {
+        LiteralKind::Int8(x) => LiteralKind::Int8(x.checked_neg()?),
+        LiteralKind::Int32(x) => LiteralKind::Int32(x.checked_neg()?),
+        LiteralKind::Int64(x) => LiteralKind::Int64(x.checked_neg()?),
+        LiteralKind::Float32(x) => LiteralKind::Float32(-x),
+        LiteralKind::Float64(x) => LiteralKind::Float64(-x),
+        _ => return None,
+    })
+}
@@ -0,0 +1,669 @@
+use shark_core::{source::Span, symbol::Symbol};
+use shark_lex::{token::TokenKind, Lexer};
+
+use crate::{
+    ast::{Block, Function, Item, ItemKind, StatementKind, TypeExprKind, Visibility},
+    cst::NodeKind,
+    dump::{dump_block, dump_expr, dump_module},
+    parse, parse_recovering, parse_syntax, Parser,
+};
+
+fn expect_function(item: &Item) -> &Function {
+    match &item.kind {
+        ItemKind::Function(function) => function,
+        _ => panic!("expected a function"),
+    }
+}
+
+fn expect_body(function: &Function) -> &Block {
+    function.body.as_ref().expect("expected a body")
+}
+
+fn parse_expression(source: &str) -> String {
+    let mut lexer = Lexer::new(None, source);
+    lexer.lex();
+    let mut parser = Parser::new(&lexer.completed_tokens);
+    let expr = parser
+        .parse_expression()
+        .expect("failed to parse expression");
+    assert_eq!(
+        parser.cursor,
+        lexer.completed_tokens.len(),
+        "not every token was parsed"
+    );
+    dump_expr(&expr)
+}
+
+#[test]
+fn test_precedence() {
+    assert_eq!(parse_expression("1 + 2 * 3 - 4"), "(- (+ 1 (* 2 3)) 4)");
+    assert_eq!(parse_expression("(1 + 2) * 3"), "(* (+ 1 2) 3)");
+    assert_eq!(parse_expression("1 << 2 + 3 & 4"), "(& (<< 1 (+ 2 3)) 4)");
+    assert_eq!(
+        parse_expression("a < b && c == d | !e"),
+        "(| (&& (< a b) (== c d)) (! e))"
+    );
+}
+
+#[test]
+fn test_left_associativity() {
+    assert_eq!(parse_expression("8 / 4 / 2"), "(/ (/ 8 4) 2)");
+    assert_eq!(parse_expression("a - b - c"), "(- (- a b) c)");
+}
+
+#[test]
+fn test_unary() {
+    assert_eq!(parse_expression("-a * !b"), "(* (- a) (! b))");
+    assert_eq!(parse_expression("- -a"), "(- (- a))");
+}
+
+#[test]
+fn test_negative_literal_split() {
+    assert_eq!(parse_expression("a -1"), "(- a 1)");
+    assert_eq!(parse_expression("2 * 3 -1 * 4"), "(- (* 2 3) (* 1 4))");
+    assert_eq!(parse_expression("-1"), "-1");
+}
+
+#[test]
+fn test_assignment() {
+    assert_eq!(parse_expression("a = b = 1"), "(= a (= b 1))");
+    assert_eq!(parse_expression("a += 2 * 3"), "(+= a (* 2 3))");
+}
+
+#[test]
+fn test_calls_and_fields() {
+    assert_eq!(
+        parse_expression("foo.bar(1, x + 2,).baz"),
+        "(. (call (. foo bar) 1 (+ x 2)) baz)"
+    );
+    assert_eq!(parse_expression("f()()"), "(call (call f))");
+}
+
+#[test]
+fn test_if_else() {
+    assert_eq!(
+        parse_expression("if a { 1 } else if b { 2 } else { 3 }"),
+        "(if a { 1 } (if b { 2 } { 3 }))"
+    );
+}
+
+#[test]
+fn test_for() {
+    assert_eq!(
+        parse_expression("for x in items { total += x; }"),
+        "(for x in items { (+= total x); })"
+    );
+    assert_eq!(
+        parse_expression("for (i, (a, _)) of pairs { yield a + i; }"),
+        "(for (i, (a, _)) of pairs { (yield (+ a i)); })"
+    );
+}
+
+#[test]
+fn test_function() {
+    let module = parse(
+        None,
+        "pub fun add(a :: Int32, mut b :: Int32) :: Int32 {\n    ret a + b;\n}\nfun main() {}",
+    )
+    .expect("failed to parse module");
+    assert_eq!(module.items.len(), 2);
+
+    let function = expect_function(&module.items[0]);
+    assert_eq!(module.items[0].visibility, Visibility::Public);
+    assert_eq!(function.name.symbol, Symbol::intern("add"));
+    assert_eq!(function.parameters.len(), 2);
+    assert!(!function.parameters[0].mutable);
+    assert!(function.parameters[1].mutable);
+    assert!(matches!(
+        function.return_type.as_ref().map(|x| &x.kind),
+        Some(TypeExprKind::Named { name, .. }) if name.symbol == Symbol::intern("Int32")
+    ));
+    assert_eq!(dump_block(expect_body(function)), "{ (ret (+ a b)); }");
+
+    let main = expect_function(&module.items[1]);
+    assert_eq!(module.items[1].visibility, Visibility::Private);
+    assert!(main.parameters.is_empty());
+    assert!(main.return_type.is_none());
+}
+
+#[test]
+fn test_type_declarations() {
+    let module = parse(
+        None,
+        "type Point { x :: Float32, y :: Float32, }
+        pub enum Shape { Circle(Float32), Rect(Point, Point), Empty }",
+    )
+    .expect("failed to parse module");
+    assert_eq!(
+        dump_module(&module),
+        "(type Point (x Float32) (y Float32))\n(pub enum Shape (Circle Float32) (Rect Point Point) Empty)\n"
+    );
+    assert_eq!(
+        module.items[1].kind.name().map(|x| x.symbol),
+        Some(Symbol::intern("Shape"))
+    );
+}
+
+#[test]
+fn test_constants() {
+    let module = parse(
+        None,
+        "pub const LIMIT :: UInt8 = 200uint8 - 1;
+        enum Colour { Red = 1, Green, Blue = LIMIT * 2 }",
+    )
+    .expect("failed to parse module");
+    assert_eq!(
+        dump_module(&module),
+        "(pub const LIMIT UInt8 (- 200uint8 1))\n(enum Colour (= Red 1) Green (= Blue (* LIMIT 2)))\n"
+    );
+    assert!(parse(None, "const LIMIT = 1;").is_err());
+    assert!(parse(None, "const LIMIT :: Int32;").is_err());
+}
+
+#[test]
+fn test_arrays() {
+    assert_eq!(parse_expression("[1, 2 + 3, x]"), "(array 1 (+ 2 3) x)");
+    assert_eq!(parse_expression("[]"), "(array)");
+    assert_eq!(
+        parse_expression("[0; LENGTH * 2]"),
+        "(repeat 0 (* LENGTH 2))"
+    );
+    assert_eq!(
+        parse_expression("[[1, 2], [3,]]"),
+        "(array (array 1 2) (array 3))"
+    );
+    assert_eq!(
+        parse_expression("[Point { x = 1 }; 2].len()"),
+        "(call (. (repeat (struct Point (x 1)) 2) len))"
+    );
+
+    let module = parse(
+        None,
+        "const SIZE :: Int64 = 2;
+        fun grid(cells :: [[Bool; SIZE]; SIZE + 1]) :: [Int32; 3] { [0; 3] }",
+    )
+    .expect("failed to parse module");
+    assert_eq!(
+        dump_module(&module),
+        "(const SIZE Int64 2)\n(fun grid (cells [[Bool; SIZE]; (+ SIZE 1)]) :: [Int32; 3] { (repeat 0 3) })\n"
+    );
+    assert!(parse(None, "fun f(x :: [Int32]) {}").is_err());
+    assert!(parse(None, "fun f() { [1; 2, 3] }").is_err());
+
+    let (_, syntax, diagnostics) = parse_syntax(None, "fun f(x :: [Int32; 2]) { [x; 1]; [1, 2] }");
+    assert!(diagnostics.is_empty());
+    let kinds: Vec<NodeKind> = syntax
+        .descendants()
+        .iter()
+        .map(|x| x.kind())
+        .filter(|x| {
+            matches!(
+                x,
+                NodeKind::ArrayType | NodeKind::ArrayExpr | NodeKind::RepeatExpr
+            )
+        })
+        .collect();
+    assert_eq!(
+        kinds,
+        [
+            NodeKind::ArrayType,
+            NodeKind::RepeatExpr,
+            NodeKind::ArrayExpr
+        ]
+    );
+}
+
+#[test]
+fn test_generics() {
+    let module = parse(
+        None,
+        "fun largest<T :: Ord + Show, U>(values :: List<T>, extra :: Map<U, Pair<T, U>>) :: T
+            where U :: Show, List<T> :: Show, { ret first(values); }
+        type Pair<A, B> where A :: Eq { first :: A, second :: B }
+        enum Option<T> { Some(T), None }",
+    )
+    .expect("failed to parse module");
+    assert_eq!(
+        dump_module(&module),
+        "(fun largest<T :: Ord + Show, U> (values List<T>, extra Map<U, Pair<T, U>>) :: T where U :: Show, List<T> :: Show { (ret (call first values)); })
+(type Pair<A, B> where A :: Eq (first A) (second B))
+(enum Option<T> (Some T) None)
+"
+    );
+
+    // `>>` closes two lists of type arguments at once
+    let module =
+        parse(None, "fun f(a :: A<B<C>>, b :: D<E<F<G>>, H>) {}").expect("failed to parse module");
+    assert_eq!(
+        dump_module(&module),
+        "(fun f (a A<B<C>>, b D<E<F<G>>, H>) { })\n"
+    );
+    assert!(parse(None, "fun f(a :: A<B>>) {}").is_err());
+}
+
+#[test]
+fn test_traits_and_impls() {
+    let module = parse(
+        None,
+        "trait Show {
+            fun show(self) :: Str;
+            fun twice(self, mut other :: Self) :: Str { ret self.show(); }
+        }
+        impl<T :: Show> Show for Pair<T, T> where T :: Eq {
+            fun show(self) :: Str { ret \"pair\"; }
+        }
+        impl Show for Int32 {}",
+    )
+    .expect("failed to parse module");
+    assert_eq!(
+        dump_module(&module),
+        "(trait Show (fun show (self Self) :: Str) (fun twice (self Self, mut other Self) :: Str { (ret (call (. self show))); }))
+(impl<T :: Show> Show for Pair<T, T> where T :: Eq (fun show (self Self) :: Str { (ret \"pair\"); }))
+(impl Show for Int32)
+"
+    );
+    assert_eq!(
+        module.items[0].kind.name().map(|x| x.symbol),
+        Some(Symbol::intern("Show"))
+    );
+    assert!(module.items[1].kind.name().is_none());
+}
+
+#[test]
+fn test_extern_and_inherent_impls() {
+    let module = parse(
+        None,
+        "pub extern type Vec<T>;
+        extern fun println(template :: Str, ...);
+        impl<T> Vec<T> where T :: Show {
+            extern fun len(self :: ref Self) :: Int64;
+            fun is_empty(self :: ref Self) :: Bool { self.len() == 0 }
+        }",
+    )
+    .expect("failed to parse module");
+    assert_eq!(
+        dump_module(&module),
+        "(pub extern type Vec<T>)
+(extern fun println (template Str, ...))
+(impl<T> Vec<T> where T :: Show (extern fun len (self ref Self) :: Int64) (fun is_empty (self ref Self) :: Bool { (== (call (. self len)) 0) }))
+"
+    );
+
+    let error = parse(None, "extern fun f(..., x :: Int32);").expect_err("parsing should fail");
+    assert_eq!(
+        error.to_string(),
+        "expected `)` after `...`, found identifier `x`"
+    );
+}
+
+#[test]
+fn test_foreign_declarations() {
+    let module = parse(
+        None,
+        "extern \"C\" type Point { x :: Int32, y :: Int32 }
+        extern \"C\" type File;
+        extern \"C\" fun printf(format :: Str, ...) :: Int32;
+        extern \"C\" unsafe fun fill(point :: ptr mut Point);",
+    )
+    .expect("failed to parse module");
+    assert_eq!(
+        dump_module(&module),
+        "(extern \"C\" type Point (x Int32) (y Int32))
+(extern \"C\" type File)
+(extern \"C\" fun printf (format Str, ...) :: Int32)
+(extern \"C\" unsafe fun fill (point ptr mut Point))
+"
+    );
+
+    let error = parse(None, "extern \"Rust\" fun f();").expect_err("parsing should fail");
+    assert_eq!(
+        error.to_string(),
+        "expected the ABI `\"C\"`, found \"Rust\""
+    );
+}
+
+#[test]
+fn test_use_declarations() {
+    let module = parse(
+        None,
+        "use shapes;
+        pub use shapes::circle::{area, Circle,};
+        use math::PI;",
+    )
+    .expect("failed to parse module");
+    assert_eq!(
+        dump_module(&module),
+        "(use shapes)
+(pub use shapes::circle::{area, Circle})
+(use math::PI)
+"
+    );
+    let ItemKind::Use(use_decl) = &module.items[1].kind else {
+        panic!("expected a use declaration");
+    };
+    let imports: Vec<String> = use_decl.imports().iter().map(|x| x.to_string()).collect();
+    assert_eq!(imports, ["shapes::circle::area", "shapes::circle::Circle"]);
+    assert!(module.items[1].kind.name().is_none());
+
+    assert!(parse(None, "use shapes::{area}::more;").is_err());
+    assert!(parse(None, "use ::shapes;").is_err());
+    assert!(parse(None, "use shapes").is_err());
+}
+
+#[test]
+fn test_struct_literals_and_paths() {
+    assert_eq!(
+        parse_expression("Point { x = 1.5, y = a.b }.x"),
+        "(. (struct Point (x 1.5) (y (. a b))) x)"
+    );
+    assert_eq!(
+        parse_expression("Shape::Rect(Point { x = 1, y = 2 }, origin)"),
+        "(call Shape::Rect (struct Point (x 1) (y 2)) origin)"
+    );
+    assert_eq!(parse_expression("Shape::Empty"), "Shape::Empty");
+    // A struct literal can not be the condition of an `if` unless it is wrapped in parentheses
+    assert_eq!(parse_expression("if a { b }"), "(if a { b })");
+    assert_eq!(
+        parse_expression("if (Point { x = 1 }).x { b }"),
+        "(if (. (struct Point (x 1)) x) { b })"
+    );
+    assert_eq!(
+        parse_expression("for p in points { Point { x = p } }"),
+        "(for p in points { (struct Point (x p)) })"
+    );
+}
+
+#[test]
+fn test_when() {
+    assert_eq!(
+        parse_expression(
+            "when shape {
+                Shape::Circle(radius) if radius > 1.0 => radius,
+                Shape::Rect(_, Corner::Origin) => { 0 }
+                Shape::Empty => 1,
+            }"
+        ),
+        "(when shape (Shape::Circle(radius) if (> radius 1) => radius) (Shape::Rect(_, Corner::Origin) => { 0 }) (Shape::Empty => 1))"
+    );
+}
+
+#[test]
+fn test_patterns() {
+    assert_eq!(
+        parse_expression(
+            "when (a, b) { (0, mut x) => x, (1..=9, _) => 1, ('a'..'z',) => 2, ((y)) => y, () => 3 }"
+        ),
+        "(when (tuple a b) ((0, mut x) => x) ((1..=9, _) => 1) (('a'..'z',) => 2) (y => y) (() => 3))"
+    );
+    assert_eq!(
+        parse_expression("(1, (2,), ())"),
+        "(tuple 1 (tuple 2) (tuple))"
+    );
+}
+
+#[test]
+fn test_statements() {
+    let module = parse(
+        None,
+        "fun main() {
+    let a :: Float32 = 3.5;
+    let mut b = a * 2.0;
+    if b > a {
+        b = a;
+    }
+    for i in b { print(i); }
+    b
+}",
+    )
+    .expect("failed to parse module");
+
+    let main = expect_function(&module.items[0]);
+    assert_eq!(
+        dump_block(expect_body(main)),
+        "{ (let a :: Float32 3.5) (let mut b (* a 2)) (if (> b a) { (= b a); }); (for i in b { (call print i); }); b }"
+    );
+
+    let StatementKind::Let(let_statement) = &expect_body(main).statements[0].kind else {
+        panic!("expected a let statement");
+    };
+    assert!(!let_statement.mutable);
+    assert!(let_statement.ty.is_some());
+    let StatementKind::Let(let_statement) = &expect_body(main).statements[1].kind else {
+        panic!("expected a let statement");
+    };
+    assert!(let_statement.mutable);
+    assert!(let_statement.ty.is_none());
+}
+
+#[test]
+fn test_block_like_statements() {
+    let body = |source: &str| {
+        let module =
+            parse(None, &format!("fun main() {{ {} }}", source)).expect("failed to parse module");
+        dump_block(expect_body(expect_function(&module.items[0])))
+    };
+    assert_eq!(body("if c { a; }\n-1"), "{ (if c { a; }); -1 }");
+    assert_eq!(
+        body("{ r = 2; }\n*(ref r)"),
+        "{ { (= r 2); }; (* (ref r)) }"
+    );
+    assert_eq!(body("when x { _ => 1, }\n(x)"), "{ (when x (_ => 1)); x }");
+    assert_eq!(
+        body("unsafe { f(); }\n-x;"),
+        "{ (unsafe { (call f); }); (- x); }"
+    );
+    // A `.` still carries on the expression, as does anything after a block-like expression which
+    // does not start a statement
+    assert_eq!(
+        body("if c { a } else { b }.len() + 1"),
+        "{ (+ (call (. (if c { a } { b }) len)) 1) }"
+    );
+    assert_eq!(
+        body("let x = if c { 1 } else { 2 }\n-1;"),
+        "{ (let x (- (if c { 1 } { 2 }) 1)) }"
+    );
+}
+
+#[test]
+fn test_spans() {
+    let source = "fun main() { ret 1 + foo(2); }";
+    let module = parse(None, source).expect("failed to parse module");
+    let main = expect_function(&module.items[0]);
+
+    assert_eq!(
+        &source[module.items[0].span.start..module.items[0].span.end],
+        source
+    );
+    let StatementKind::Expr(expr) = &expect_body(main).statements[0].kind else {
+        panic!("expected an expression statement");
+    };
+    assert_eq!(&source[expr.span.start..expr.span.end], "ret 1 + foo(2)");
+}
+
+#[test]
+fn test_unexpected_token() {
+    let error = parse(None, "fun main() { let = 5; }").expect_err("parsing should fail");
+    assert_eq!(error.found, Some(TokenKind::Equal));
+    assert_eq!(error.to_string(), "expected an identifier, found `=`");
+
+    let error = parse(None, "fun main() { 1 + 2").expect_err("parsing should fail");
+    assert_eq!(error.found, None);
+    assert_eq!(
+        error.to_string(),
+        "expected `;` or `}`, found the end of the file"
+    );
+}
+
+#[test]
+fn test_recovery_keeps_going() {
+    let (module, errors) = parse_recovering(
+        None,
+        "fun broken( { let x = ; }\nfun main() { let a = ; ret a; }",
+    );
+    assert_eq!(errors.len(), 2);
+    assert_eq!(errors[1].found, Some(TokenKind::EOL));
+    assert_eq!(
+        errors[1].to_diagnostic().primary_span(),
+        Some(errors[1].span)
+    );
+
+    assert!(matches!(module.items[0].kind, ItemKind::Error));
+    let main = expect_function(&module.items[1]);
+    assert_eq!(
+        dump_block(expect_body(main)),
+        "{ (let a <error>) (ret a); }"
+    );
+}
+
+#[test]
+fn test_syntax_tree_shape() {
+    let source = "fun f(a :: Int32) {\n    ret a + 1; // done\n}\n";
+    let (_, syntax, diagnostics) = parse_syntax(None, source);
+    assert!(diagnostics.is_empty());
+    assert_eq!(syntax.kind(), NodeKind::SourceFile);
+
+    let function = syntax
+        .child_of_kind(NodeKind::Function)
+        .expect("missing function");
+    assert_eq!(function.span(), Span::new(0, 44));
+    let block = function
+        .child_of_kind(NodeKind::Block)
+        .expect("missing block");
+    let kinds: Vec<NodeKind> = block.descendants().iter().map(|x| x.kind()).collect();
+    assert_eq!(
+        kinds,
+        [
+            NodeKind::Block,
+            NodeKind::ExprStatement,
+            NodeKind::ReturnExpr,
+            NodeKind::BinaryExpr,
+            NodeKind::NameExpr,
+            NodeKind::LiteralExpr,
+        ]
+    );
+
+    // Trivia stays where it was written, the comment belongs to the block
+    let comment = block
+        .child_tokens()
+        .into_iter()
+        .find(|x| matches!(x.kind(), TokenKind::Comment(_)))
+        .expect("missing comment");
+    assert_eq!(comment.text(), "// done");
+    assert_eq!(
+        syntax.last_token().map(|x| x.kind()),
+        Some(TokenKind::Whitespace)
+    );
+}
+
+#[test]
+fn test_syntax_tree_navigation() {
+    let source = "fun main() { let total = first + second.value; }";
+    let (_, syntax, _) = parse_syntax(None, source);
+
+    let offset = source.find("second").expect("missing name");
+    let token = syntax.token_at_offset(offset).expect("missing token");
+    assert_eq!(token.text(), "second");
+    assert_eq!(token.parent().kind(), NodeKind::NameExpr);
+    assert_eq!(
+        token.previous_token().map(|x| x.kind()),
+        Some(TokenKind::Whitespace)
+    );
+    assert_eq!(token.next_token().map(|x| x.kind()), Some(TokenKind::Dot));
+
+    let ancestors: Vec<NodeKind> = token.parent().ancestors().map(|x| x.kind()).collect();
+    assert_eq!(
+        ancestors,
+        [
+            NodeKind::NameExpr,
+            NodeKind::FieldExpr,
+            NodeKind::BinaryExpr,
+            NodeKind::LetStatement,
+            NodeKind::Block,
+            NodeKind::Function,
+            NodeKind::SourceFile,
+        ]
+    );
+
+    let span = Span::new(offset, offset + "second.value".len());
+    let field = syntax.covering_node(span);
+    assert_eq!(field.kind(), NodeKind::FieldExpr);
+    assert_eq!(field.text(), "second.value");
+    assert!(syntax.token_at_offset(source.len()).is_none());
+}
+
+#[test]
+fn test_syntax_tree_errors() {
+    let source = "fun main() { let a = $; b c; }\n}";
+    let (module, syntax, diagnostics) = parse_syntax(None, source);
+    assert_eq!(syntax.text(), source);
+    assert_eq!(module.items.len(), 2);
+
+    // The unknown character is only reported once, by the lexer
+    let messages: Vec<&str> = diagnostics.iter().map(|x| x.message.as_str()).collect();
+    assert_eq!(
+        messages,
+        [
+            "unknown character `$`",
+            "expected `;` or `}`, found identifier `c`",
+            "expected an item, found `}`",
+        ]
+    );
+
+    // Skipped tokens end up in error nodes rather than being dropped
+    let errors: Vec<String> = syntax
+        .descendants()
+        .iter()
+        .filter(|x| x.kind() == NodeKind::Error)
+        .map(|x| x.text())
+        .collect();
+    assert_eq!(errors, ["$;", "c;", "}"]);
+}
+
+#[test]
+fn test_references_and_unsafe() {
+    assert_eq!(parse_expression("ref a.b"), "(ref (. a b))");
+    assert_eq!(parse_expression("*ref mut a = 1"), "(= (* (ref mut a)) 1)");
+    assert_eq!(parse_expression("**p + 1"), "(+ (* (* p)) 1)");
+    assert_eq!(
+        parse_expression("unsafe { *ptr mut x }"),
+        "(unsafe { (* (ptr mut x)) })"
+    );
+
+    let module = parse(
+        None,
+        "pub unsafe fun read(p :: ptr Int32, r :: ref mut ptr Int32) :: ref Int32 { ref *p }",
+    )
+    .expect("failed to parse module");
+    assert_eq!(
+        dump_module(&module),
+        "(pub unsafe fun read (p ptr Int32, r ref mut ptr Int32) :: ref Int32 { (ref (* p)) })\n"
+    );
+}
+
+#[test]
+fn test_generators() {
+    let module = parse(
+        None,
+        "fun count(n :: Int32) :: yield Int32 { for i in upto(n) { yield i * 2; } yield -1; }",
+    )
+    .expect("failed to parse module");
+    assert_eq!(
+        dump_module(&module),
+        "(fun count (n Int32) :: yield Int32 { (for i in (call upto n) { (yield (* i 2)); }); (yield -1); })\n"
+    );
+}
+
+#[test]
+fn test_closures() {
+    let module = parse(
+        None,
+        "fun adder(n :: Int32) :: fun(Int32) :: Int32 { fun(x :: Int32) :: Int32 { x + n } } \
+         fun run(f :: fun()) { f(); }",
+    )
+    .expect("failed to parse module");
+    assert_eq!(
+        dump_module(&module),
+        "(fun adder (n Int32) :: fun(Int32) :: Int32 { (fun <closure> (x Int32) :: Int32 { (+ x n) }) })\n\
+         (fun run (f fun()) { (call f); })\n"
+    );
+}
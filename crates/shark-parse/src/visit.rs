@@ -0,0 +1,214 @@
+//! Walks the abstract syntax tree without changing it. A [Visitor] is called for every expression
+//! and type within a [Module], and carries on into the nodes within them through the `walk_`
+//! functions unless it stops there
+
+use crate::ast::{
+    Block, Expr, ExprKind, Function, Generics, ItemKind, Module, StatementKind, TypeExpr,
+    TypeExprKind,
+};
+
+pub trait Visitor<'ast>: Sized {
+    fn visit_expr(&mut self, expr: &'ast Expr) {
+        walk_expr(self, expr);
+    }
+
+    fn visit_type(&mut self, ty: &'ast TypeExpr) {
+        walk_type(self, ty);
+    }
+}
+
+pub fn walk_module<'ast>(visitor: &mut impl Visitor<'ast>, module: &'ast Module) {
+    for item in &module.items {
+        match &item.kind {
+            ItemKind::Function(function) => walk_function(visitor, function),
+            ItemKind::Type(type_decl) => {
+                walk_generics(visitor, &type_decl.generics);
+                for field in &type_decl.fields {
+                    visitor.visit_type(&field.ty);
+                }
+            }
+            ItemKind::Enum(enum_decl) => {
+                walk_generics(visitor, &enum_decl.generics);
+                for variant in &enum_decl.variants {
+                    variant.payload.iter().for_each(|x| visitor.visit_type(x));
+                    if let Some(discriminant) = &variant.discriminant {
+                        visitor.visit_expr(discriminant);
+                    }
+                }
+            }
+            ItemKind::Trait(trait_decl) => {
+                trait_decl
+                    .methods
+                    .iter()
+                    .for_each(|x| walk_function(visitor, x));
+            }
+            ItemKind::Impl(impl_decl) => {
+                walk_generics(visitor, &impl_decl.generics);
+                visitor.visit_type(&impl_decl.self_type);
+                impl_decl
+                    .methods
+                    .iter()
+                    .for_each(|x| walk_function(visitor, x));
+            }
+            ItemKind::Const(const_decl) => {
+                visitor.visit_type(&const_decl.ty);
+                visitor.visit_expr(&const_decl.value);
+            }
+            ItemKind::Use(_) | ItemKind::Error => {}
+        }
+    }
+}
+
+pub fn walk_function<'ast>(visitor: &mut impl Visitor<'ast>, function: &'ast Function) {
+    walk_generics(visitor, &function.generics);
+    for parameter in &function.parameters {
+        visitor.visit_type(&parameter.ty);
+    }
+    if let Some(return_type) = &function.return_type {
+        visitor.visit_type(return_type);
+    }
+    if let Some(body) = &function.body {
+        walk_block(visitor, body);
+    }
+}
+
+fn walk_generics<'ast>(visitor: &mut impl Visitor<'ast>, generics: &'ast Generics) {
+    for predicate in &generics.where_clause {
+        visitor.visit_type(&predicate.ty);
+    }
+}
+
+pub fn walk_block<'ast>(visitor: &mut impl Visitor<'ast>, block: &'ast Block) {
+    for statement in &block.statements {
+        match &statement.kind {
+            StatementKind::Let(let_statement) => {
+                if let Some(ty) = &let_statement.ty {
+                    visitor.visit_type(ty);
+                }
+                if let Some(value) = &let_statement.value {
+                    visitor.visit_expr(value);
+                }
+            }
+            StatementKind::Expr(expr) => visitor.visit_expr(expr),
+            StatementKind::Error => {}
+        }
+    }
+    if let Some(tail) = &block.tail {
+        visitor.visit_expr(tail);
+    }
+}
+
+pub fn walk_expr<'ast>(visitor: &mut impl Visitor<'ast>, expr: &'ast Expr) {
+    match &expr.kind {
+        ExprKind::Literal(_) | ExprKind::Name(_) | ExprKind::Path(_) | ExprKind::Error => {}
+        ExprKind::StructLiteral { fields, .. } => {
+            fields.iter().for_each(|x| visitor.visit_expr(&x.value));
+        }
+        ExprKind::Unary { operand, .. } | ExprKind::Reference { operand, .. } => {
+            visitor.visit_expr(operand);
+        }
+        ExprKind::Binary { left, right, .. } => {
+            visitor.visit_expr(left);
+            visitor.visit_expr(right);
+        }
+        ExprKind::Assign { target, value, .. } => {
+            visitor.visit_expr(target);
+            visitor.visit_expr(value);
+        }
+        ExprKind::Tuple(elements) | ExprKind::Array(elements) => {
+            elements.iter().for_each(|x| visitor.visit_expr(x));
+        }
+        ExprKind::Repeat { value, length } => {
+            visitor.visit_expr(value);
+            visitor.visit_expr(length);
+        }
+        ExprKind::Call { callee, arguments } => {
+            visitor.visit_expr(callee);
+            arguments.iter().for_each(|x| visitor.visit_expr(x));
+        }
+        ExprKind::Field { object, .. } => visitor.visit_expr(object),
+        ExprKind::Block(block) | ExprKind::Unsafe(block) => walk_block(visitor, block),
+        ExprKind::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => {
+            visitor.visit_expr(condition);
+            walk_block(visitor, then_branch);
+            if let Some(else_branch) = else_branch {
+                visitor.visit_expr(else_branch);
+            }
+        }
+        ExprKind::For { iterable, body, .. } => {
+            visitor.visit_expr(iterable);
+            walk_block(visitor, body);
+        }
+        ExprKind::When { scrutinee, arms } => {
+            visitor.visit_expr(scrutinee);
+            for arm in arms {
+                if let Some(guard) = &arm.guard {
+                    visitor.visit_expr(guard);
+                }
+                visitor.visit_expr(&arm.body);
+            }
+        }
+        ExprKind::Return(value) => {
+            if let Some(value) = value {
+                visitor.visit_expr(value);
+            }
+        }
+        ExprKind::Yield(value) => visitor.visit_expr(value),
+        ExprKind::Closure(function) => walk_function(visitor, function),
+    }
+}
+
+pub fn walk_type<'ast>(visitor: &mut impl Visitor<'ast>, ty: &'ast TypeExpr) {
+    match &ty.kind {
+        TypeExprKind::Named { arguments, .. } => {
+            arguments.iter().for_each(|x| visitor.visit_type(x));
+        }
+        TypeExprKind::Reference { pointee, .. } | TypeExprKind::Generator(pointee) => {
+            visitor.visit_type(pointee);
+        }
+        TypeExprKind::Function {
+            parameters,
+            return_type,
+        } => {
+            parameters.iter().for_each(|x| visitor.visit_type(x));
+            if let Some(return_type) = return_type {
+                visitor.visit_type(return_type);
+            }
+        }
+        TypeExprKind::Array { element, length } => {
+            visitor.visit_type(element);
+            visitor.visit_expr(length);
+        }
+        TypeExprKind::Error => {}
+    }
+}
+
+/// Gets the length of every array type and every `[value; length]` within a module, in the order
+/// they were written. These are worked out while compiling, the same way as constants
+pub fn array_lengths(module: &Module) -> Vec<&Expr> {
+    struct Lengths<'ast>(Vec<&'ast Expr>);
+
+    impl<'ast> Visitor<'ast> for Lengths<'ast> {
+        fn visit_expr(&mut self, expr: &'ast Expr) {
+            walk_expr(self, expr);
+            if let ExprKind::Repeat { length, .. } = &expr.kind {
+                self.0.push(length);
+            }
+        }
+
+        fn visit_type(&mut self, ty: &'ast TypeExpr) {
+            walk_type(self, ty);
+            if let TypeExprKind::Array { length, .. } = &ty.kind {
+                self.0.push(length);
+            }
+        }
+    }
+
+    let mut lengths = Lengths(Vec::new());
+    walk_module(&mut lengths, module);
+    lengths.0
+}
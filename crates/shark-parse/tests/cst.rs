@@ -0,0 +1,102 @@
+//! Checks that the concrete syntax tree is lossless, meaning its text is always exactly the source
+//! it was parsed from, no matter how broken that source is
+
+use std::{fs, path::Path};
+
+use shark_parse::{dump::dump_module, parse_recovering, parse_syntax};
+
+const PROGRAM: &str = "// Adds things up
+use geometry::{distance, Line};
+
+type Point { x :: Float32, y :: Float32 }
+enum Shape { Circle(Float32), Rect(Point, Point), Empty }
+enum Option<T> { Some(T), None }
+
+trait Area {
+    fun area(self) :: Float32;
+    fun double(self) :: Float32 { self.area() * 2.0 }
+}
+
+impl<T :: Area> Area for Option<Option<T>> where T :: Area {
+    fun area(self) :: Float32 { 0.0 }
+}
+
+pub fun sum(mut values :: List, start :: Int32) :: Int32 {
+    let mut total = start; /* running total */
+    for value in values {
+        total += value * -1;
+    }
+    if total >= 10 && !done() { ret total.first; } else { total - 1 }
+}
+
+fun main() {
+    sum(list(1, 2.5, 'c', \"text\"), 0);
+    let shape = Shape::Rect(Point { x = 1.0, y = 2.0 }, origin);
+    let area = when (shape, 1) {
+        (Shape::Circle(r), _) if r > 0.0 => r * r,
+        (_, 0..=9) => { 1.0 }
+        _ => 0.0,
+    };
+}
+";
+
+fn assert_lossless(source: &str) {
+    let (_, syntax, _) = parse_syntax(None, source);
+    assert_eq!(
+        syntax.text(),
+        source,
+        "the syntax tree lost some of {:?}",
+        source
+    );
+    assert_eq!(syntax.span().end, source.len());
+}
+
+#[test]
+fn test_round_trip_program() {
+    assert_lossless(PROGRAM);
+    assert_lossless("");
+    assert_lossless("   \n\t// only trivia\n/* here */ ");
+}
+
+#[test]
+fn test_round_trip_every_prefix() {
+    for (end, _) in PROGRAM.char_indices() {
+        assert_lossless(&PROGRAM[..end]);
+    }
+}
+
+#[test]
+fn test_round_trip_junk() {
+    let junk = [
+        "fun $ main() { @@ }",
+        "}}}{{{ fun",
+        "pub pub pub",
+        "fun main() { let a = \"unterminated",
+        "fun main() { /* unterminated",
+        "fun main() { a = ((( b; ret ret; } )))",
+        "λ fun ünïcode() :: 😀 { 12xyz + '' }",
+        "fun f(a b c :: , ::) :: :: {",
+        ";;; let let let ;",
+    ];
+    for source in junk {
+        assert_lossless(source);
+    }
+}
+
+#[test]
+fn test_round_trip_corpus() {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/recovery");
+    for entry in fs::read_dir(corpus).expect("failed to read the recovery corpus") {
+        let path = entry.expect("failed to read a corpus entry").path();
+        if path.extension().is_none_or(|x| x != "shark") {
+            continue;
+        }
+        let source = fs::read_to_string(&path).expect("failed to read a corpus file");
+        assert_lossless(&source);
+
+        // Building the syntax tree must not change what the parser makes of the source
+        let (module, _, _) = parse_syntax(None, &source);
+        let (expected, _) = parse_recovering(None, &source);
+        assert_eq!(dump_module(&module), dump_module(&expected));
+    }
+}
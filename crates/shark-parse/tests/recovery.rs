@@ -0,0 +1,57 @@
+//! Runs the parser over every file in `tests/recovery` and compares the diagnostics and the
+//! recovered syntax tree against the `.snap` file next to it. Run with `SHARK_BLESS=1` to update
+//! the snapshots after an intentional change
+
+use std::{fs, path::Path};
+
+use shark_parse::{dump::dump_module, parse_recovering};
+
+fn render_snapshot(path: &Path, source: &str) -> String {
+    let file_name = Path::new(path.file_name().expect("corpus files have a name"));
+    let (module, errors) = parse_recovering(Some(file_name), source);
+
+    let mut snapshot = String::from("--- diagnostics ---\n");
+    for error in &errors {
+        snapshot.push_str(&error.to_diagnostic().render(Some(file_name), source));
+        snapshot.push('\n');
+    }
+    snapshot.push_str("--- syntax tree ---\n");
+    snapshot.push_str(&dump_module(&module));
+    snapshot
+}
+
+#[test]
+fn test_recovery_snapshots() {
+    let corpus = Path::new(env!("CARGO_MANIFEST_DIR")).join("tests/recovery");
+    let bless = std::env::var_os("SHARK_BLESS").is_some();
+
+    let mut paths: Vec<_> = fs::read_dir(&corpus)
+        .expect("failed to read the recovery corpus")
+        .map(|x| x.expect("failed to read a corpus entry").path())
+        .filter(|x| x.extension().is_some_and(|x| x == "shark"))
+        .collect();
+    paths.sort();
+    assert!(!paths.is_empty(), "the recovery corpus is empty");
+
+    let mut mismatches = Vec::new();
+    for path in paths {
+        let source = fs::read_to_string(&path).expect("failed to read a corpus file");
+        let actual = render_snapshot(&path, &source);
+        let snapshot_path = path.with_extension("snap");
+
+        if bless {
+            fs::write(&snapshot_path, &actual).expect("failed to write a snapshot");
+            continue;
+        }
+        let expected = fs::read_to_string(&snapshot_path).unwrap_or_default();
+        if expected != actual {
+            mismatches.push(format!(
+                "snapshot mismatch for {}\n--- expected ---\n{}\n--- actual ---\n{}",
+                path.display(),
+                expected,
+                actual
+            ));
+        }
+    }
+    assert!(mismatches.is_empty(), "{}", mismatches.join("\n"));
+}
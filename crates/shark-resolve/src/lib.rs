@@ -0,0 +1,9 @@
+pub use merge::merge;
+pub use resolve::resolve;
+
+pub mod merge;
+pub mod resolve;
+pub mod tree;
+
+#[cfg(test)]
+pub mod tests;
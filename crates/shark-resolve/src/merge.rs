@@ -0,0 +1,400 @@
+//! Merges the modules of a [ModuleTree] into the single [Module] semantic analysis and everything
+//! after it work on. The items of the root module keep their names, while those of other modules
+//! are named after their path, such as `geometry::area` or `std::Option` for the prelude, and every
+//! name which refers to an item is replaced with the name of that item. `use` declarations have nothing left to do and are dropped
+//!
+//! The spans of each module are moved to where its source is within [ModuleTree::text], and its
+//! node ids past those of the modules before it, so both stay unique within the merged module
+
+use std::collections::HashMap;
+
+use shark_core::{source::Span, symbol::Symbol};
+use shark_parse::ast::{
+    Block, Expr, ExprKind, Function, Generics, Ident, Item, ItemKind, Module, NodeId, Path,
+    Pattern, PatternKind, StatementKind, TypeExpr, TypeExprKind,
+};
+
+use crate::{
+    resolve::{Res, Resolutions},
+    tree::{ModuleId, ModuleTree},
+};
+
+struct Merger<'tree> {
+    resolutions: &'tree Resolutions,
+    /// The name every item has within the merged module, by the module declaring it and its node
+    names: HashMap<(ModuleId, NodeId), Symbol>,
+    /// The module being merged
+    module: ModuleId,
+    /// How far the spans of the module are moved
+    offset: usize,
+    /// How far the node ids of the module are moved
+    first_id: u32,
+    /// One past the last node id of the merged module so far
+    next_id: u32,
+    /// The generic parameters around what is being merged, which names of items do not refer to
+    generics: Vec<Symbol>,
+}
+
+/// Merges every module of a tree whose names have been resolved without errors
+pub fn merge(tree: &ModuleTree, resolutions: &Resolutions) -> Module {
+    let mut names = HashMap::new();
+    for (id, module) in tree.modules() {
+        for item in &module.ast.items {
+            if let Some(name) = item.kind.name() {
+                names.insert((id, item.id), qualified(&module.path, name.symbol));
+            }
+        }
+    }
+    let mut merger = Merger {
+        resolutions,
+        names,
+        module: ModuleTree::ROOT,
+        offset: 0,
+        first_id: 0,
+        next_id: 0,
+        generics: Vec::new(),
+    };
+    let mut items = Vec::new();
+    for (id, module) in tree.modules() {
+        merger.module = id;
+        merger.offset = module.offset;
+        merger.first_id = merger.next_id;
+        for item in &module.ast.items {
+            if !matches!(item.kind, ItemKind::Use(_)) {
+                items.push(merger.item(id, item.clone()));
+            }
+        }
+    }
+    Module {
+        items,
+        span: Span::new(0, tree.text().len()),
+    }
+}
+
+/// Names an item declared by the module at a path
+fn qualified(path: &[Symbol], name: Symbol) -> Symbol {
+    if path.is_empty() {
+        return name;
+    }
+    let mut segments: Vec<&str> = path.iter().map(|x| x.as_str()).collect();
+    segments.push(name.as_str());
+    Symbol::intern(&segments.join("::"))
+}
+
+impl Merger<'_> {
+    fn span(&self, span: &mut Span) {
+        *span = Span::new(span.start + self.offset, span.end + self.offset);
+    }
+
+    fn id(&mut self, id: &mut NodeId) {
+        *id = NodeId(id.0 + self.first_id);
+        self.next_id = self.next_id.max(id.0 + 1);
+    }
+
+    fn ident(&self, ident: &mut Ident) {
+        self.span(&mut ident.span);
+    }
+
+    /// Finds the item the first segments of a path name, going through modules as in
+    /// `shapes::Square::new`, returning how many segments name it along with its name
+    fn lookup(&self, segments: &[Ident]) -> Option<(usize, Symbol)> {
+        let first = segments.first()?;
+        if self.generics.contains(&first.symbol) {
+            return None;
+        }
+        let mut res = self.resolutions.lookup(self.module, first.symbol)?.res;
+        let mut length = 1;
+        loop {
+            match res {
+                Res::Module(module) => {
+                    let segment = segments.get(length)?;
+                    res = self.resolutions.scope(module).get(segment.symbol)?.res;
+                    length += 1;
+                }
+                Res::Def(_, module, node) => return Some((length, self.names[&(module, node)])),
+                Res::Local(_) => return None,
+            }
+        }
+    }
+
+    /// Replaces the segments of a path which name an item with the name of the item
+    fn path(&self, path: &mut Path) {
+        let found = self.lookup(&path.segments);
+        self.span(&mut path.span);
+        for segment in &mut path.segments {
+            self.ident(segment);
+        }
+        if let Some((length, symbol)) = found {
+            let span = path.segments[0].span.to(path.segments[length - 1].span);
+            path.segments
+                .splice(..length, std::iter::once(Ident { symbol, span }));
+        }
+    }
+
+    fn item(&mut self, module: ModuleId, mut item: Item) -> Item {
+        if let Some(name) = self.names.get(&(module, item.id)).copied() {
+            match &mut item.kind {
+                ItemKind::Function(function) => function.name.symbol = name,
+                ItemKind::Type(type_decl) => type_decl.name.symbol = name,
+                ItemKind::Enum(enum_decl) => enum_decl.name.symbol = name,
+                ItemKind::Trait(trait_decl) => trait_decl.name.symbol = name,
+                ItemKind::Const(const_decl) => const_decl.name.symbol = name,
+                ItemKind::Impl(_) | ItemKind::Use(_) | ItemKind::Error => {}
+            }
+        }
+        self.id(&mut item.id);
+        self.span(&mut item.span);
+        match &mut item.kind {
+            ItemKind::Function(function) => self.function(function),
+            ItemKind::Type(type_decl) => {
+                self.ident(&mut type_decl.name);
+                let count = self.generics(&mut type_decl.generics);
+                for field in &mut type_decl.fields {
+                    self.id(&mut field.id);
+                    self.span(&mut field.span);
+                    self.ident(&mut field.name);
+                    self.type_expr(&mut field.ty);
+                }
+                self.generics.truncate(self.generics.len() - count);
+            }
+            ItemKind::Enum(enum_decl) => {
+                self.ident(&mut enum_decl.name);
+                let count = self.generics(&mut enum_decl.generics);
+                for variant in &mut enum_decl.variants {
+                    self.id(&mut variant.id);
+                    self.span(&mut variant.span);
+                    self.ident(&mut variant.name);
+                    variant.payload.iter_mut().for_each(|x| self.type_expr(x));
+                    if let Some(discriminant) = &mut variant.discriminant {
+                        self.expr(discriminant);
+                    }
+                }
+                self.generics.truncate(self.generics.len() - count);
+            }
+            ItemKind::Trait(trait_decl) => {
+                self.ident(&mut trait_decl.name);
+                trait_decl.methods.iter_mut().for_each(|x| self.function(x));
+            }
+            ItemKind::Impl(impl_decl) => {
+                let count = self.generics(&mut impl_decl.generics);
+                if let Some(trait_path) = &mut impl_decl.trait_path {
+                    self.path(trait_path);
+                }
+                self.type_expr(&mut impl_decl.self_type);
+                impl_decl.methods.iter_mut().for_each(|x| self.function(x));
+                self.generics.truncate(self.generics.len() - count);
+            }
+            ItemKind::Const(const_decl) => {
+                self.ident(&mut const_decl.name);
+                self.type_expr(&mut const_decl.ty);
+                self.expr(&mut const_decl.value);
+            }
+            ItemKind::Use(_) | ItemKind::Error => {}
+        }
+        item
+    }
+
+    /// Brings generic parameters into scope, returning how many there are so that they can be
+    /// taken out of it again
+    fn generics(&mut self, generics: &mut Generics) -> usize {
+        self.generics
+            .extend(generics.params.iter().map(|x| x.name.symbol));
+        for param in &mut generics.params {
+            self.id(&mut param.id);
+            self.span(&mut param.span);
+            self.ident(&mut param.name);
+            param.bounds.iter_mut().for_each(|x| self.path(x));
+        }
+        for predicate in &mut generics.where_clause {
+            self.id(&mut predicate.id);
+            self.span(&mut predicate.span);
+            self.type_expr(&mut predicate.ty);
+            predicate.bounds.iter_mut().for_each(|x| self.path(x));
+        }
+        generics.params.len()
+    }
+
+    fn function(&mut self, function: &mut Function) {
+        self.ident(&mut function.name);
+        let count = self.generics(&mut function.generics);
+        for parameter in &mut function.parameters {
+            self.id(&mut parameter.id);
+            self.span(&mut parameter.span);
+            self.ident(&mut parameter.name);
+            self.type_expr(&mut parameter.ty);
+        }
+        if let Some(return_type) = &mut function.return_type {
+            self.type_expr(return_type);
+        }
+        if let Some(body) = &mut function.body {
+            self.block(body);
+        }
+        self.generics.truncate(self.generics.len() - count);
+    }
+
+    fn type_expr(&mut self, ty: &mut TypeExpr) {
+        self.id(&mut ty.id);
+        self.span(&mut ty.span);
+        match &mut ty.kind {
+            TypeExprKind::Named { name, arguments } => {
+                if let Some((_, symbol)) = self.lookup(std::slice::from_ref(name)) {
+                    name.symbol = symbol;
+                }
+                self.ident(name);
+                arguments.iter_mut().for_each(|x| self.type_expr(x));
+            }
+            TypeExprKind::Reference { pointee, .. } => self.type_expr(pointee),
+            TypeExprKind::Generator(item) => self.type_expr(item),
+            TypeExprKind::Function {
+                parameters,
+                return_type,
+            } => {
+                parameters.iter_mut().for_each(|x| self.type_expr(x));
+                if let Some(return_type) = return_type {
+                    self.type_expr(return_type);
+                }
+            }
+            TypeExprKind::Array { element, length } => {
+                self.type_expr(element);
+                self.expr(length);
+            }
+            TypeExprKind::Error => {}
+        }
+    }
+
+    fn block(&mut self, block: &mut Block) {
+        self.id(&mut block.id);
+        self.span(&mut block.span);
+        for statement in &mut block.statements {
+            self.id(&mut statement.id);
+            self.span(&mut statement.span);
+            match &mut statement.kind {
+                StatementKind::Let(let_statement) => {
+                    self.ident(&mut let_statement.name);
+                    if let Some(ty) = &mut let_statement.ty {
+                        self.type_expr(ty);
+                    }
+                    if let Some(value) = &mut let_statement.value {
+                        self.expr(value);
+                    }
+                }
+                StatementKind::Expr(expr) => self.expr(expr),
+                StatementKind::Error => {}
+            }
+        }
+        if let Some(tail) = &mut block.tail {
+            self.expr(tail);
+        }
+    }
+
+    fn expr(&mut self, expr: &mut Expr) {
+        let res = self.resolutions.res_of(self.module, expr.id);
+        self.id(&mut expr.id);
+        self.span(&mut expr.span);
+        match &mut expr.kind {
+            ExprKind::Literal(_) | ExprKind::Error => {}
+            ExprKind::Name(ident) => {
+                if let Some(Res::Def(_, module, node)) = res {
+                    ident.symbol = self.names[&(module, node)];
+                }
+                self.ident(ident);
+            }
+            ExprKind::Path(path) => self.path(path),
+            ExprKind::StructLiteral { path, fields } => {
+                self.path(path);
+                for field in fields {
+                    self.span(&mut field.span);
+                    self.ident(&mut field.name);
+                    self.expr(&mut field.value);
+                }
+            }
+            ExprKind::Unary { operand, .. } | ExprKind::Reference { operand, .. } => {
+                self.expr(operand)
+            }
+            ExprKind::Binary { left, right, .. } => {
+                self.expr(left);
+                self.expr(right);
+            }
+            ExprKind::Assign { target, value, .. } => {
+                self.expr(target);
+                self.expr(value);
+            }
+            ExprKind::Tuple(elements) | ExprKind::Array(elements) => {
+                elements.iter_mut().for_each(|x| self.expr(x))
+            }
+            ExprKind::Repeat { value, length } => {
+                self.expr(value);
+                self.expr(length);
+            }
+            ExprKind::Call { callee, arguments } => {
+                self.expr(callee);
+                arguments.iter_mut().for_each(|x| self.expr(x));
+            }
+            ExprKind::Field { object, field } => {
+                self.expr(object);
+                self.ident(field);
+            }
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => self.block(block),
+            ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.expr(condition);
+                self.block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.expr(else_branch);
+                }
+            }
+            ExprKind::For {
+                pattern,
+                iterable,
+                body,
+                ..
+            } => {
+                self.pattern(pattern);
+                self.expr(iterable);
+                self.block(body);
+            }
+            ExprKind::When { scrutinee, arms } => {
+                self.expr(scrutinee);
+                for arm in arms {
+                    self.id(&mut arm.id);
+                    self.span(&mut arm.span);
+                    self.pattern(&mut arm.pattern);
+                    if let Some(guard) = &mut arm.guard {
+                        self.expr(guard);
+                    }
+                    self.expr(&mut arm.body);
+                }
+            }
+            ExprKind::Return(value) => {
+                if let Some(value) = value {
+                    self.expr(value);
+                }
+            }
+            ExprKind::Yield(value) => self.expr(value),
+            ExprKind::Closure(function) => self.function(function),
+        }
+        // A path through modules to an item is only the name of the item now
+        if let ExprKind::Path(path) = &expr.kind {
+            if let [name] = path.segments.as_slice() {
+                expr.kind = ExprKind::Name(*name);
+            }
+        }
+    }
+
+    fn pattern(&mut self, pattern: &mut Pattern) {
+        self.id(&mut pattern.id);
+        self.span(&mut pattern.span);
+        match &mut pattern.kind {
+            PatternKind::Binding { name, .. } => self.ident(name),
+            PatternKind::Tuple(elements) => elements.iter_mut().for_each(|x| self.pattern(x)),
+            PatternKind::Variant { path, payload } => {
+                self.path(path);
+                payload.iter_mut().for_each(|x| self.pattern(x));
+            }
+            PatternKind::Wildcard | PatternKind::Literal(_) | PatternKind::Range { .. } => {}
+        }
+    }
+}
@@ -0,0 +1,625 @@
+//! Name resolution. Every module gets a scope holding the items it declares and the names its
+//! `use` declarations import, after which the names used within function bodies are resolved
+//! against the local bindings around them, then that scope and then the public names of the
+//! prelude of the tree, which the names of the module shadow
+
+use std::collections::{HashMap, HashSet};
+
+use shark_core::{diagnostic::Diagnostic, source::Span, suggest::closest_match, symbol::Symbol};
+use shark_parse::{
+    ast::{
+        Block, Expr, ExprKind, Function, Ident, ItemKind, NodeId, Path, Pattern, PatternKind,
+        StatementKind, Visibility,
+    },
+    visit,
+};
+
+use crate::tree::{describe_path, ModuleId, ModuleTree};
+
+/// What kind of item a name refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefKind {
+    Function,
+    Type,
+    Enum,
+    Trait,
+    Const,
+}
+
+impl DefKind {
+    pub fn describe(&self) -> &'static str {
+        match self {
+            Self::Function => "function",
+            Self::Type => "type",
+            Self::Enum => "enum",
+            Self::Trait => "trait",
+            Self::Const => "constant",
+        }
+    }
+}
+
+/// What a name refers to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Res {
+    /// A parameter or a binding introduced by `let`, `for` or a pattern, identified by the node
+    /// which declares it
+    Local(NodeId),
+    /// An item, identified by the module declaring it and its node within that module
+    Def(DefKind, ModuleId, NodeId),
+    Module(ModuleId),
+}
+
+impl Res {
+    /// Whether the name can be used as a value
+    fn is_value(&self) -> bool {
+        matches!(
+            self,
+            Self::Local(_) | Self::Def(DefKind::Function | DefKind::Const, _, _)
+        )
+    }
+
+    fn describe(&self) -> &'static str {
+        match self {
+            Self::Local(_) => "local binding",
+            Self::Def(kind, _, _) => kind.describe(),
+            Self::Module(_) => "module",
+        }
+    }
+}
+
+/// A name within the scope of a module
+#[derive(Debug, Clone, Copy)]
+pub struct Binding {
+    pub res: Res,
+    /// Where the name is declared or imported
+    pub span: Span,
+    /// Whether other modules can import the name
+    pub public: bool,
+}
+
+/// The names which can be used anywhere within a module
+#[derive(Debug, Clone, Default)]
+pub struct ModuleScope {
+    bindings: HashMap<Symbol, Binding>,
+    /// Every name in the order it was bound, which keeps suggestions deterministic
+    order: Vec<Symbol>,
+}
+
+impl ModuleScope {
+    pub fn get(&self, name: Symbol) -> Option<&Binding> {
+        self.bindings.get(&name)
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = Symbol> + '_ {
+        self.order.iter().copied()
+    }
+
+    fn insert(&mut self, name: Symbol, binding: Binding) {
+        self.order.push(name);
+        self.bindings.insert(name, binding);
+    }
+}
+
+/// The names resolved within a [ModuleTree]
+#[derive(Debug, Clone, Default)]
+pub struct Resolutions {
+    scopes: Vec<ModuleScope>,
+    /// What every name expression, path and pattern binding resolved to
+    names: HashMap<(ModuleId, NodeId), Res>,
+    prelude: Option<Prelude>,
+}
+
+/// The prelude of a tree, whose public names every module can use
+#[derive(Debug, Clone, Copy)]
+struct Prelude {
+    module: ModuleId,
+    /// The name the prelude itself goes by within every module, which is the last segment of its
+    /// path
+    name: Symbol,
+    binding: Binding,
+}
+
+impl Resolutions {
+    pub fn scope(&self, module: ModuleId) -> &ModuleScope {
+        &self.scopes[module.0 as usize]
+    }
+
+    /// Looks up a name which can be used within a module, which is either in its scope, a public
+    /// name of the prelude or the prelude itself
+    pub fn lookup(&self, module: ModuleId, name: Symbol) -> Option<&Binding> {
+        self.scope(module).get(name).or_else(|| {
+            let prelude = self.prelude.as_ref()?;
+            if name == prelude.name {
+                return Some(&prelude.binding);
+            }
+            let binding = self.scope(prelude.module).get(name)?;
+            binding.public.then_some(binding)
+        })
+    }
+
+    /// Gets the public names of the prelude which a module does not shadow
+    fn prelude_names(&self, module: ModuleId) -> impl Iterator<Item = (Symbol, &Binding)> + '_ {
+        let scope = self.prelude.map(|x| self.scope(x.module));
+        scope
+            .into_iter()
+            .flat_map(|scope| scope.names().map(|x| (x, &scope.bindings[&x])))
+            .filter(move |(name, binding)| {
+                binding.public && self.scope(module).get(*name).is_none()
+            })
+    }
+
+    pub fn res_of(&self, module: ModuleId, id: NodeId) -> Option<Res> {
+        self.names.get(&(module, id)).copied()
+    }
+}
+
+/// One name imported by a `use` declaration
+#[derive(Debug, Clone)]
+struct Import {
+    module: ModuleId,
+    path: Path,
+    public: bool,
+}
+
+enum ImportOutcome {
+    Resolved(Res),
+    /// The name may still be imported by the module it comes from
+    Undetermined,
+    Failed(Diagnostic),
+}
+
+struct Resolver<'tree> {
+    tree: &'tree ModuleTree,
+    resolutions: Resolutions,
+    diagnostics: Vec<(ModuleId, Diagnostic)>,
+    /// The module whose bodies are being resolved
+    module: ModuleId,
+    /// The local bindings around the expression being resolved, innermost last
+    locals: Vec<Vec<(Symbol, NodeId)>>,
+}
+
+/// Runs between parsing and semantic analysis. Builds the scope of every module in a [ModuleTree],
+/// resolves its imports and then the names used by its function bodies. Diagnostics are paired
+/// with the module they belong to
+pub fn resolve(tree: &ModuleTree) -> (Resolutions, Vec<(ModuleId, Diagnostic)>) {
+    let mut resolver = Resolver {
+        tree,
+        resolutions: Resolutions {
+            prelude: tree.prelude().map(|module| Prelude {
+                module,
+                name: *tree
+                    .module(module)
+                    .path
+                    .last()
+                    .expect("the prelude is not the root"),
+                binding: Binding {
+                    res: Res::Module(module),
+                    span: Span::new(0, 0),
+                    public: false,
+                },
+            }),
+            ..Resolutions::default()
+        },
+        diagnostics: Vec::new(),
+        module: ModuleTree::ROOT,
+        locals: Vec::new(),
+    };
+    resolver.collect_items();
+    resolver.resolve_imports();
+    for (id, _) in tree.modules() {
+        resolver.resolve_bodies(id);
+    }
+    (resolver.resolutions, resolver.diagnostics)
+}
+
+impl Resolver<'_> {
+    fn scope(&self, module: ModuleId) -> &ModuleScope {
+        self.resolutions.scope(module)
+    }
+
+    fn error(&mut self, module: ModuleId, diagnostic: Diagnostic) {
+        self.diagnostics.push((module, diagnostic));
+    }
+
+    /// Binds the name of every item. Items declared more than once are reported by semantic
+    /// analysis, so only the first is kept here
+    fn collect_items(&mut self) {
+        for (id, module) in self.tree.modules() {
+            let mut scope = ModuleScope::default();
+            for item in &module.ast.items {
+                let (kind, name) = match &item.kind {
+                    ItemKind::Function(function) => (DefKind::Function, function.name),
+                    ItemKind::Type(type_decl) => (DefKind::Type, type_decl.name),
+                    ItemKind::Enum(enum_decl) => (DefKind::Enum, enum_decl.name),
+                    ItemKind::Trait(trait_decl) => (DefKind::Trait, trait_decl.name),
+                    ItemKind::Const(const_decl) => (DefKind::Const, const_decl.name),
+                    ItemKind::Impl(_) | ItemKind::Use(_) | ItemKind::Error => continue,
+                };
+                if scope.get(name.symbol).is_none() {
+                    let binding = Binding {
+                        res: Res::Def(kind, id, item.id),
+                        span: name.span,
+                        public: item.visibility == Visibility::Public,
+                    };
+                    scope.insert(name.symbol, binding);
+                }
+            }
+            self.resolutions.scopes.push(scope);
+        }
+    }
+
+    /// Resolves imports until no more can be, since an import can name something which is itself
+    /// imported by another module. Whatever is left over cannot be resolved
+    fn resolve_imports(&mut self) {
+        let mut pending: Vec<Import> = Vec::new();
+        for (id, module) in self.tree.modules() {
+            for item in &module.ast.items {
+                if let ItemKind::Use(use_decl) = &item.kind {
+                    pending.extend(use_decl.imports().into_iter().map(|path| Import {
+                        module: id,
+                        path,
+                        public: item.visibility == Visibility::Public,
+                    }));
+                }
+            }
+        }
+
+        loop {
+            let unresolved: HashSet<(ModuleId, Symbol)> = pending
+                .iter()
+                .map(|x| (x.module, x.path.name().symbol))
+                .collect();
+            let count = pending.len();
+            let mut remaining = Vec::new();
+            for import in pending {
+                match self.resolve_import(&import, Some(&unresolved)) {
+                    ImportOutcome::Resolved(res) => self.bind_import(&import, res),
+                    ImportOutcome::Undetermined => remaining.push(import),
+                    ImportOutcome::Failed(diagnostic) => self.error(import.module, diagnostic),
+                }
+            }
+            let progress = remaining.len() < count;
+            pending = remaining;
+            if pending.is_empty() || !progress {
+                break;
+            }
+        }
+
+        for import in pending {
+            if let ImportOutcome::Failed(diagnostic) = self.resolve_import(&import, None) {
+                self.error(import.module, diagnostic);
+            }
+        }
+    }
+
+    /// Works out what an import refers to. Names which are still `unresolved` in the module they
+    /// come from are undetermined rather than missing
+    fn resolve_import(
+        &self,
+        import: &Import,
+        unresolved: Option<&HashSet<(ModuleId, Symbol)>>,
+    ) -> ImportOutcome {
+        let segments: Vec<Symbol> = import.path.segments.iter().map(|x| x.symbol).collect();
+        let Some((target, length)) = self.tree.longest_prefix(&segments) else {
+            let first = import.path.segments[0];
+            return ImportOutcome::Failed(
+                Diagnostic::error(format!("unresolved import `{}`", import.path)).with_primary(
+                    first.span,
+                    format!("could not find module `{}`", first.symbol),
+                ),
+            );
+        };
+        if length == segments.len() {
+            return ImportOutcome::Resolved(Res::Module(target));
+        }
+
+        let name = import.path.segments[length];
+        let path = &self.tree.module(target).path;
+        if length + 1 < segments.len() {
+            return ImportOutcome::Failed(
+                Diagnostic::error(format!("unresolved import `{}`", import.path)).with_primary(
+                    name.span,
+                    format!(
+                        "could not find module `{}` in {}",
+                        name.symbol,
+                        describe_path(path)
+                    ),
+                ),
+            );
+        }
+        if self.scope(target).get(name.symbol).is_none()
+            && unresolved.is_some_and(|x| x.contains(&(target, name.symbol)))
+        {
+            return ImportOutcome::Undetermined;
+        }
+        match self.lookup_in(target, name) {
+            Ok(res) => ImportOutcome::Resolved(res),
+            Err(diagnostic) => ImportOutcome::Failed(diagnostic),
+        }
+    }
+
+    /// Looks up a name within the scope of another module, which must be public
+    fn lookup_in(&self, target: ModuleId, name: Ident) -> Result<Res, Diagnostic> {
+        let scope = self.scope(target);
+        let path = describe_path(&self.tree.module(target).path);
+        let Some(binding) = scope.get(name.symbol) else {
+            let mut diagnostic = Diagnostic::error(format!("no `{}` in {path}", name.symbol))
+                .with_primary(name.span, "not found");
+            let candidates = scope
+                .names()
+                .filter(|x| scope.get(*x).is_some_and(|x| x.public));
+            if let Some(similar) = closest_match(name.symbol, candidates) {
+                diagnostic = diagnostic.with_note(format!("did you mean `{similar}`?"));
+            }
+            return Err(diagnostic);
+        };
+        if !binding.public {
+            return Err(Diagnostic::error(format!("`{}` is private", name.symbol))
+                .with_primary(name.span, format!("private {}", binding.res.describe()))
+                .with_note(format!(
+                    "`{}` is not marked `pub` within {path}",
+                    name.symbol
+                )));
+        }
+        Ok(binding.res)
+    }
+
+    fn bind_import(&mut self, import: &Import, res: Res) {
+        let name = import.path.name();
+        let scope = &mut self.resolutions.scopes[import.module.0 as usize];
+        if let Some(previous) = scope.get(name.symbol) {
+            let diagnostic = Diagnostic::error(format!(
+                "the name `{}` is defined multiple times",
+                name.symbol
+            ))
+            .with_primary(name.span, "imported here")
+            .with_secondary(
+                previous.span,
+                format!("previous definition of `{}` here", name.symbol),
+            );
+            self.error(import.module, diagnostic);
+            return;
+        }
+        let binding = Binding {
+            res,
+            span: name.span,
+            public: import.public,
+        };
+        scope.insert(name.symbol, binding);
+    }
+
+    fn resolve_bodies(&mut self, module: ModuleId) {
+        self.module = module;
+        for item in &self.tree.module(module).ast.items {
+            match &item.kind {
+                ItemKind::Function(function) => self.resolve_function(function),
+                ItemKind::Trait(trait_decl) => trait_decl
+                    .methods
+                    .iter()
+                    .for_each(|x| self.resolve_function(x)),
+                ItemKind::Impl(impl_decl) => impl_decl
+                    .methods
+                    .iter()
+                    .for_each(|x| self.resolve_function(x)),
+                ItemKind::Enum(enum_decl) => enum_decl
+                    .variants
+                    .iter()
+                    .filter_map(|x| x.discriminant.as_ref())
+                    .for_each(|x| self.resolve_expr(x)),
+                ItemKind::Const(const_decl) => self.resolve_expr(&const_decl.value),
+                _ => {}
+            }
+        }
+        // Array lengths are worked out while compiling, so they can not name locals
+        for length in visit::array_lengths(&self.tree.module(module).ast) {
+            self.resolve_expr(length);
+        }
+    }
+
+    fn resolve_function(&mut self, function: &Function) {
+        let Some(body) = &function.body else {
+            return;
+        };
+        self.locals.push(
+            function
+                .parameters
+                .iter()
+                .map(|x| (x.name.symbol, x.id))
+                .collect(),
+        );
+        self.resolve_block(body);
+        self.locals.pop();
+    }
+
+    fn resolve_block(&mut self, block: &Block) {
+        self.locals.push(Vec::new());
+        for statement in &block.statements {
+            match &statement.kind {
+                StatementKind::Let(let_statement) => {
+                    // The value is resolved first so that `let x = x + 1` refers to an earlier `x`
+                    if let Some(value) = &let_statement.value {
+                        self.resolve_expr(value);
+                    }
+                    self.declare(let_statement.name.symbol, statement.id);
+                }
+                StatementKind::Expr(expr) => self.resolve_expr(expr),
+                StatementKind::Error => {}
+            }
+        }
+        if let Some(tail) = &block.tail {
+            self.resolve_expr(tail);
+        }
+        self.locals.pop();
+    }
+
+    fn declare(&mut self, name: Symbol, id: NodeId) {
+        self.locals
+            .last_mut()
+            .expect("a binding is always declared within a scope")
+            .push((name, id));
+    }
+
+    fn resolve_expr(&mut self, expr: &Expr) {
+        match &expr.kind {
+            ExprKind::Literal(_) | ExprKind::Error => {}
+            ExprKind::Name(ident) => self.resolve_name(*ident, expr.id),
+            ExprKind::Path(path) => self.resolve_path(path, expr.id),
+            ExprKind::StructLiteral { path, fields } => {
+                self.resolve_path(path, expr.id);
+                fields.iter().for_each(|x| self.resolve_expr(&x.value));
+            }
+            ExprKind::Unary { operand, .. } | ExprKind::Reference { operand, .. } => {
+                self.resolve_expr(operand)
+            }
+            ExprKind::Binary { left, right, .. } => {
+                self.resolve_expr(left);
+                self.resolve_expr(right);
+            }
+            ExprKind::Assign { target, value, .. } => {
+                self.resolve_expr(target);
+                self.resolve_expr(value);
+            }
+            ExprKind::Tuple(elements) | ExprKind::Array(elements) => {
+                elements.iter().for_each(|x| self.resolve_expr(x))
+            }
+            // The length is resolved along with the other array lengths
+            ExprKind::Repeat { value, .. } => self.resolve_expr(value),
+            ExprKind::Call { callee, arguments } => {
+                self.resolve_expr(callee);
+                arguments.iter().for_each(|x| self.resolve_expr(x));
+            }
+            ExprKind::Field { object, .. } => self.resolve_expr(object),
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => self.resolve_block(block),
+            ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                self.resolve_expr(condition);
+                self.resolve_block(then_branch);
+                if let Some(else_branch) = else_branch {
+                    self.resolve_expr(else_branch);
+                }
+            }
+            ExprKind::For {
+                pattern,
+                iterable,
+                body,
+                ..
+            } => {
+                self.resolve_expr(iterable);
+                self.locals.push(Vec::new());
+                self.bind_pattern(pattern);
+                self.resolve_block(body);
+                self.locals.pop();
+            }
+            ExprKind::When { scrutinee, arms } => {
+                self.resolve_expr(scrutinee);
+                for arm in arms {
+                    self.locals.push(Vec::new());
+                    self.bind_pattern(&arm.pattern);
+                    if let Some(guard) = &arm.guard {
+                        self.resolve_expr(guard);
+                    }
+                    self.resolve_expr(&arm.body);
+                    self.locals.pop();
+                }
+            }
+            ExprKind::Return(value) => {
+                if let Some(value) = value {
+                    self.resolve_expr(value);
+                }
+            }
+            ExprKind::Yield(value) => self.resolve_expr(value),
+            // The body of a closure can use the locals around it
+            ExprKind::Closure(function) => self.resolve_function(function),
+        }
+    }
+
+    fn bind_pattern(&mut self, pattern: &Pattern) {
+        match &pattern.kind {
+            PatternKind::Binding { name, .. } => self.declare(name.symbol, pattern.id),
+            PatternKind::Tuple(elements) => elements.iter().for_each(|x| self.bind_pattern(x)),
+            PatternKind::Variant { path, payload } => {
+                self.resolve_path(path, pattern.id);
+                payload.iter().for_each(|x| self.bind_pattern(x));
+            }
+            PatternKind::Wildcard | PatternKind::Literal(_) | PatternKind::Range { .. } => {}
+        }
+    }
+
+    fn lookup_local(&self, name: Symbol) -> Option<NodeId> {
+        self.locals
+            .iter()
+            .rev()
+            .find_map(|x| x.iter().rev().find(|(local, _)| *local == name))
+            .map(|(_, id)| *id)
+    }
+
+    /// Resolves a name used as a value, which is either a local binding, a function or a constant
+    fn resolve_name(&mut self, ident: Ident, id: NodeId) {
+        if let Some(local) = self.lookup_local(ident.symbol) {
+            self.resolutions
+                .names
+                .insert((self.module, id), Res::Local(local));
+            return;
+        }
+
+        let diagnostic = match self.resolutions.lookup(self.module, ident.symbol) {
+            Some(binding) if binding.res.is_value() => {
+                self.resolutions
+                    .names
+                    .insert((self.module, id), binding.res);
+                return;
+            }
+            Some(binding) => Diagnostic::error(format!(
+                "expected a value, found {} `{}`",
+                binding.res.describe(),
+                ident.symbol
+            ))
+            .with_primary(ident.span, "not a value"),
+            None => {
+                let diagnostic = Diagnostic::error(format!("unresolved name `{}`", ident.symbol))
+                    .with_primary(ident.span, "not found in this scope");
+                let locals = self.locals.iter().rev().flat_map(|x| x.iter().rev());
+                let scope = self.scope(self.module);
+                let values = scope
+                    .names()
+                    .filter(|x| scope.get(*x).is_some_and(|x| x.res.is_value()));
+                let prelude = self
+                    .resolutions
+                    .prelude_names(self.module)
+                    .filter(|(_, x)| x.res.is_value())
+                    .map(|(x, _)| x);
+                let candidates = locals.map(|(x, _)| *x).chain(values).chain(prelude);
+                match closest_match(ident.symbol, candidates) {
+                    Some(similar) => diagnostic.with_note(format!("did you mean `{similar}`?")),
+                    None => diagnostic,
+                }
+            }
+        };
+        self.error(self.module, diagnostic);
+    }
+
+    /// Resolves a path through an imported module, such as `geometry::area`. Other paths, such
+    /// as enum variants, are left for type checking
+    fn resolve_path(&mut self, path: &Path, id: NodeId) {
+        let [first, name, rest @ ..] = path.segments.as_slice() else {
+            return;
+        };
+        let Some(Res::Module(target)) = self
+            .resolutions
+            .lookup(self.module, first.symbol)
+            .map(|x| x.res)
+        else {
+            return;
+        };
+        match self.lookup_in(target, *name) {
+            Ok(res) if rest.is_empty() => {
+                self.resolutions.names.insert((self.module, id), res);
+            }
+            Ok(_) => {}
+            Err(diagnostic) => self.error(self.module, diagnostic),
+        }
+    }
+}
@@ -0,0 +1,227 @@
+use std::collections::HashMap;
+
+use shark_core::symbol::Symbol;
+
+use shark_parse::dump::dump_module;
+
+use crate::{
+    merge, resolve,
+    resolve::{DefKind, Res},
+    tree::{ModuleId, ModuleTree},
+};
+
+fn load(root: &str, modules: &[(&str, &str)]) -> ModuleTree {
+    let mut loader: HashMap<String, String> = modules
+        .iter()
+        .map(|(path, source)| (path.to_string(), source.to_string()))
+        .collect();
+    let tree = ModuleTree::load(None, root, &mut loader);
+    for (_, module) in tree.modules() {
+        assert!(module.diagnostics.is_empty(), "{:?}", module.diagnostics);
+    }
+    tree
+}
+
+fn errors(tree: &ModuleTree) -> Vec<String> {
+    let (_, diagnostics) = resolve(tree);
+    diagnostics
+        .iter()
+        .map(|(_, x)| {
+            let notes: Vec<&str> = x.notes.iter().map(|x| x.as_str()).collect();
+            [x.message.as_str()]
+                .into_iter()
+                .chain(notes)
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .collect()
+}
+
+#[test]
+fn test_module_tree() {
+    let tree = load(
+        "use geometry::{distance, Line};
+        use util;",
+        &[
+            (
+                "geometry",
+                "use geometry::shapes::Circle; pub fun distance() {}",
+            ),
+            ("geometry::shapes", "pub type Circle { radius :: Float32 }"),
+            ("util", ""),
+            ("unused", ""),
+        ],
+    );
+    let paths: Vec<Vec<&str>> = tree
+        .modules()
+        .map(|(_, x)| x.path.iter().map(|x| x.as_str()).collect())
+        .collect();
+    assert_eq!(
+        paths,
+        [
+            vec![],
+            vec!["geometry"],
+            vec!["util"],
+            vec!["geometry", "shapes"]
+        ]
+    );
+    let segments = ["geometry", "shapes", "Circle"].map(Symbol::intern);
+    assert_eq!(tree.longest_prefix(&segments), Some((ModuleId(3), 2)));
+}
+
+#[test]
+fn test_imports() {
+    let tree = load(
+        "use a::{f, Point, hidden, missing, g};
+        use a::b::c;
+        use nowhere::x;
+        use c::{reexported};
+        fun f() {}",
+        &[
+            (
+                "a",
+                "pub fun f() {} pub type Point {} fun hidden() {} pub fun gg() {}",
+            ),
+            ("c", "pub use d::reexported;"),
+            ("d", "pub fun reexported() {}"),
+        ],
+    );
+    assert_eq!(
+        errors(&tree),
+        [
+            "the name `f` is defined multiple times",
+            "`hidden` is private, `hidden` is not marked `pub` within module `a`",
+            "no `missing` in module `a`",
+            "no `g` in module `a`, did you mean `f`?",
+            "unresolved import `a::b::c`",
+            "unresolved import `nowhere::x`",
+        ]
+    );
+}
+
+#[test]
+fn test_scopes() {
+    let tree = load(
+        "use geometry;
+        fun count(items :: Int32) :: Int32 {
+            let total = items;
+            let total = total + 1;
+            for item in total {
+                let inner = item;
+            }
+            when total {
+                n if n > 0 => n,
+                _ => geometry::area(),
+            }
+        }",
+        &[("geometry", "pub fun area() :: Int32 { 0 }")],
+    );
+    assert!(errors(&tree).is_empty());
+
+    let (resolutions, _) = resolve(&tree);
+    let scope = resolutions.scope(ModuleTree::ROOT);
+    let geometry = scope
+        .get(Symbol::intern("geometry"))
+        .expect("missing import");
+    assert_eq!(geometry.res, Res::Module(ModuleId(1)));
+    let count = scope
+        .get(Symbol::intern("count"))
+        .expect("missing function");
+    assert!(matches!(
+        count.res,
+        Res::Def(DefKind::Function, ModuleTree::ROOT, _)
+    ));
+}
+
+#[test]
+fn test_unresolved_names() {
+    let tree = load(
+        "use geometry;
+        type Point { x :: Int32 }
+        fun count(items :: Int32) :: Int32 {
+            let total = itemz;
+            for item in total {
+                let inner = item;
+            }
+            inner + Point + cout(total);
+            geometry::secret();
+            geometry::are()
+        }",
+        &[("geometry", "pub fun area() :: Int32 { 0 } fun secret() {}")],
+    );
+    assert_eq!(
+        errors(&tree),
+        [
+            "unresolved name `itemz`, did you mean `items`?",
+            "unresolved name `inner`",
+            "expected a value, found type `Point`",
+            "unresolved name `cout`, did you mean `count`?",
+            "`secret` is private, `secret` is not marked `pub` within module `geometry`",
+            "no `are` in module `geometry`, did you mean `area`?",
+        ]
+    );
+}
+
+#[test]
+fn test_constants() {
+    let tree = load(
+        "use geometry;
+        const DOUBLE :: Int32 = geometry::SIDES * 2;
+        enum Shape { Triangle = DOUBLE, Square = SQAURE }
+        fun count() :: Int32 { DOUBLE + geometry::SIDES }",
+        &[("geometry", "pub const SIDES :: Int32 = 3;")],
+    );
+    assert_eq!(errors(&tree), ["unresolved name `SQAURE`"]);
+
+    let (resolutions, _) = resolve(&tree);
+    let double = resolutions
+        .scope(ModuleTree::ROOT)
+        .get(Symbol::intern("DOUBLE"))
+        .expect("missing constant");
+    assert!(matches!(
+        double.res,
+        Res::Def(DefKind::Const, ModuleTree::ROOT, _)
+    ));
+}
+
+#[test]
+fn test_merge() {
+    let tree = load(
+        "use geometry::{area, Circle, shapes};
+        use geometry;
+        fun f<T>(shape :: Circle, t :: T) :: Int32 {
+            let area = area();
+            shapes::Kind::Round;
+            area + geometry::area()
+        }",
+        &[
+            (
+                "geometry",
+                "use geometry::shapes::Kind;
+                pub type Circle { kind :: Kind }
+                pub fun area() :: Int32 { 0 }",
+            ),
+            ("geometry::shapes", "pub enum Kind { Round }"),
+        ],
+    );
+    let (resolutions, diagnostics) = resolve(&tree);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let module = merge(&tree, &resolutions);
+    let dump = dump_module(&module);
+    assert!(dump.contains("(shape geometry::Circle, t T)"), "{}", dump);
+    assert!(dump.contains("(+ area (call geometry::area))"), "{}", dump);
+    assert!(dump.contains("geometry::shapes::Kind::Round;"), "{}", dump);
+    assert!(dump.contains("(kind geometry::shapes::Kind)"), "{}", dump);
+
+    // Spans point into the text of the tree, and node ids are unique
+    let text = tree.text();
+    let names: Vec<&str> = module
+        .items
+        .iter()
+        .filter_map(|x| x.kind.name())
+        .map(|x| &text[x.span.start..x.span.end])
+        .collect();
+    assert_eq!(names, ["f", "Circle", "area", "Kind"]);
+    let ids: Vec<u32> = module.items.iter().map(|x| x.id.0).collect();
+    assert!(ids.windows(2).all(|x| x[0] < x[1]), "{:?}", ids);
+}
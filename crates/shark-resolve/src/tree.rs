@@ -0,0 +1,190 @@
+//! The module tree. Every module is a file: the root module is the file being compiled and the
+//! module `a::b` is the file `a/b.shark` in the directory of the root module. Modules are loaded
+//! as the `use` declarations of loaded modules mention them, so files which are never used are
+//! never read
+//!
+//! A tree can also have a prelude, a module such as the standard library which is added once the
+//! others are loaded. Every module can use its public items without importing them, see
+//! [crate::resolve]
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use shark_core::{diagnostic::Diagnostic, symbol::Symbol};
+use shark_parse::{
+    ast::{ItemKind, Module},
+    parse_syntax,
+};
+
+/// The extension of every source file
+pub const EXTENSION: &str = "shark";
+
+/// Identifies a module within a [ModuleTree]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ModuleId(pub u32);
+
+/// Describes the path of a module for use in diagnostics
+pub fn describe_path(path: &[Symbol]) -> String {
+    if path.is_empty() {
+        return "the root module".to_string();
+    }
+    let segments: Vec<&str> = path.iter().map(|x| x.as_str()).collect();
+    format!("module `{}`", segments.join("::"))
+}
+
+#[derive(Debug, Clone)]
+pub struct ModuleData {
+    /// The path of the module from the root, which is empty for the root itself
+    pub path: Vec<Symbol>,
+    /// The file the module was read from, if it came from one
+    pub file: Option<PathBuf>,
+    pub source: String,
+    /// Where the source starts within the [ModuleTree::text] of the tree
+    pub offset: usize,
+    pub ast: Module,
+    /// Every syntax error within the module
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+/// Finds the source of the modules within a [ModuleTree]
+pub trait ModuleLoader {
+    /// Reads the module at a path from the root, returning the file it was read from if any, or
+    /// [None] if there is no such module
+    fn load(&mut self, path: &[Symbol]) -> Option<(Option<PathBuf>, String)>;
+}
+
+/// Loads modules from the files within the directory of the root module
+#[derive(Debug, Clone)]
+pub struct FileLoader {
+    pub directory: PathBuf,
+}
+
+impl ModuleLoader for FileLoader {
+    fn load(&mut self, path: &[Symbol]) -> Option<(Option<PathBuf>, String)> {
+        let mut file = self.directory.clone();
+        for segment in path {
+            file.push(segment.as_str());
+        }
+        file.set_extension(EXTENSION);
+        let source = fs::read_to_string(&file).ok()?;
+        Some((Some(file), source))
+    }
+}
+
+/// Loads modules from memory, where each source is keyed by the path of its module such as
+/// `a::b`
+impl ModuleLoader for HashMap<String, String> {
+    fn load(&mut self, path: &[Symbol]) -> Option<(Option<PathBuf>, String)> {
+        let key: Vec<&str> = path.iter().map(|x| x.as_str()).collect();
+        self.get(&key.join("::")).map(|x| (None, x.clone()))
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct ModuleTree {
+    modules: Vec<ModuleData>,
+    paths: HashMap<Vec<Symbol>, ModuleId>,
+    prelude: Option<ModuleId>,
+}
+
+impl ModuleTree {
+    pub const ROOT: ModuleId = ModuleId(0);
+
+    /// Parses the root module, then every module mentioned by its `use` declarations and theirs
+    /// in turn
+    pub fn load(root: Option<&Path>, source: &str, loader: &mut dyn ModuleLoader) -> Self {
+        let mut tree = Self::default();
+        tree.add(Vec::new(), root.map(Path::to_path_buf), source.to_string());
+
+        let mut index = 0;
+        while index < tree.modules.len() {
+            let imports: Vec<Vec<Symbol>> = tree.modules[index]
+                .ast
+                .items
+                .iter()
+                .filter_map(|x| match &x.kind {
+                    ItemKind::Use(use_decl) => Some(use_decl.imports()),
+                    _ => None,
+                })
+                .flatten()
+                .map(|x| x.segments.iter().map(|x| x.symbol).collect())
+                .collect();
+            for path in imports {
+                // The longest prefix which is a module wins, the rest of the path names an item
+                for length in (1..=path.len()).rev() {
+                    let prefix = &path[..length];
+                    if tree.paths.contains_key(prefix) {
+                        break;
+                    }
+                    if let Some((file, source)) = loader.load(prefix) {
+                        tree.add(prefix.to_vec(), file, source);
+                        break;
+                    }
+                }
+            }
+            index += 1;
+        }
+        tree
+    }
+
+    /// Adds the prelude of the tree. It takes the place of any module loaded under the same path
+    pub fn add_prelude(&mut self, path: Vec<Symbol>, file: Option<PathBuf>, source: String) {
+        self.prelude = Some(ModuleId(self.modules.len() as u32));
+        self.add(path, file, source);
+    }
+
+    fn add(&mut self, path: Vec<Symbol>, file: Option<PathBuf>, source: String) {
+        let (ast, _, diagnostics) = parse_syntax(file.as_deref(), &source);
+        let offset = match self.modules.last() {
+            Some(previous) => previous.offset + previous.source.len() + 1,
+            None => 0,
+        };
+        self.paths
+            .insert(path.clone(), ModuleId(self.modules.len() as u32));
+        self.modules.push(ModuleData {
+            path,
+            file,
+            source,
+            offset,
+            ast,
+            diagnostics,
+        });
+    }
+
+    /// Gets the sources of every module one after the other, each on a new line after the one
+    /// before it. The spans of a merged module point into this text, see [crate::merge]
+    pub fn text(&self) -> String {
+        let sources: Vec<&str> = self.modules.iter().map(|x| x.source.as_str()).collect();
+        sources.join("\n")
+    }
+
+    pub fn module(&self, id: ModuleId) -> &ModuleData {
+        &self.modules[id.0 as usize]
+    }
+
+    pub fn modules(&self) -> impl Iterator<Item = (ModuleId, &ModuleData)> {
+        self.modules
+            .iter()
+            .enumerate()
+            .map(|(index, module)| (ModuleId(index as u32), module))
+    }
+
+    pub fn prelude(&self) -> Option<ModuleId> {
+        self.prelude
+    }
+
+    pub fn lookup(&self, path: &[Symbol]) -> Option<ModuleId> {
+        self.paths.get(path).copied()
+    }
+
+    /// Finds the module named by the longest prefix of a path, along with the length of that
+    /// prefix. The root module is never a prefix since it has no name
+    pub fn longest_prefix(&self, path: &[Symbol]) -> Option<(ModuleId, usize)> {
+        (1..=path.len())
+            .rev()
+            .find_map(|length| self.lookup(&path[..length]).map(|x| (x, length)))
+    }
+}
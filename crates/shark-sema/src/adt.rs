@@ -0,0 +1,690 @@
+use std::collections::{HashMap, HashSet};
+
+use shark_core::{
+    diagnostic::Diagnostic,
+    suggest::closest_match,
+    symbol::{sym, Symbol},
+};
+use shark_parse::ast::{Abi, Ident, ItemKind, Module, Path, TypeExpr, TypeExprKind};
+
+use crate::{
+    foreign,
+    generics::TypeScope,
+    layout::Layout,
+    ty::{AdtId, Length, PrimitiveType, Type},
+};
+
+/// `name :: Type` within a `type`
+#[derive(Debug, Clone, PartialEq)]
+pub struct FieldDef {
+    pub name: Ident,
+    pub ty: Type,
+}
+
+/// A variant of an `enum` along with the types of the values it carries
+#[derive(Debug, Clone, PartialEq)]
+pub struct VariantDef {
+    pub name: Ident,
+    pub payload: Vec<Type>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AdtKind {
+    /// A product type declared with `type`
+    Struct { fields: Vec<FieldDef> },
+    /// A tagged union declared with `enum`
+    Enum { variants: Vec<VariantDef> },
+    /// An opaque type declared with `extern type`, whose values only the runtime can make or look
+    /// into, or with `extern "C" type`, which is only ever handled through a `ptr`
+    Extern,
+}
+
+/// The definition of an algebraic data type
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdtDef {
+    pub name: Ident,
+    /// The names of the generic parameters, which the types of the fields and variants can use.
+    /// Their bounds are kept by the [crate::traits::TraitTable]
+    pub generics: Vec<Ident>,
+    pub kind: AdtKind,
+    /// The ABI of a type declared `extern "C"`, whose fields are laid out the way C does
+    pub abi: Option<Abi>,
+}
+
+impl AdtDef {
+    /// Gets the fields of a `type`, or nothing for an `enum`
+    pub fn fields(&self) -> &[FieldDef] {
+        match &self.kind {
+            AdtKind::Struct { fields } => fields,
+            AdtKind::Enum { .. } | AdtKind::Extern => &[],
+        }
+    }
+
+    /// Gets the variants of an `enum`, or nothing for a `type`
+    pub fn variants(&self) -> &[VariantDef] {
+        match &self.kind {
+            AdtKind::Struct { .. } | AdtKind::Extern => &[],
+            AdtKind::Enum { variants } => variants,
+        }
+    }
+
+    /// Gets the index and definition of a field
+    pub fn field(&self, name: Symbol) -> Option<(usize, &FieldDef)> {
+        self.fields()
+            .iter()
+            .enumerate()
+            .find(|(_, x)| x.name.symbol == name)
+    }
+
+    /// Gets the index and definition of a variant
+    pub fn variant(&self, name: Symbol) -> Option<(usize, &VariantDef)> {
+        self.variants()
+            .iter()
+            .enumerate()
+            .find(|(_, x)| x.name.symbol == name)
+    }
+
+    pub fn is_enum(&self) -> bool {
+        matches!(self.kind, AdtKind::Enum { .. })
+    }
+
+    pub fn is_extern(&self) -> bool {
+        matches!(self.kind, AdtKind::Extern)
+    }
+
+    /// Maps each generic parameter to an argument, in order
+    pub fn substitution(&self, arguments: &[Type]) -> HashMap<Symbol, Type> {
+        self.generics
+            .iter()
+            .map(|x| x.symbol)
+            .zip(arguments.iter().cloned())
+            .collect()
+    }
+
+    /// Describes what kind of type this is, for use in diagnostics
+    pub fn describe(&self) -> String {
+        match self.kind {
+            AdtKind::Struct { .. } => format!("type `{}`", self.name.symbol),
+            AdtKind::Enum { .. } => format!("enum `{}`", self.name.symbol),
+            AdtKind::Extern => format!("extern type `{}`", self.name.symbol),
+        }
+    }
+}
+
+/// Every algebraic data type declared in a [Module], along with their layouts
+#[derive(Debug, Clone, Default)]
+pub struct TypeTable {
+    adts: Vec<AdtDef>,
+    names: HashMap<Symbol, AdtId>,
+    /// The layout of every [AdtDef], or [None] if the type has no layout because it contains
+    /// itself or an erroneous type. Generic types are laid out on demand instead, since their
+    /// layout depends on their arguments
+    layouts: Vec<Option<Layout>>,
+    /// Every type which contains itself
+    infinite: HashSet<AdtId>,
+}
+
+impl TypeTable {
+    /// Collects every `type` and `enum` of a [Module], resolving the types they mention and
+    /// computing their layouts
+    pub fn collect(module: &Module) -> (Self, Vec<Diagnostic>) {
+        let mut table = Self::default();
+        let mut diagnostics = Vec::new();
+
+        // Every name is declared first so that types can refer to types declared after them
+        let mut declarations = Vec::new();
+        for item in &module.items {
+            let (name, generics, abi) = match &item.kind {
+                ItemKind::Type(type_decl) => (type_decl.name, &type_decl.generics, type_decl.abi),
+                ItemKind::Enum(enum_decl) => (enum_decl.name, &enum_decl.generics, None),
+                _ => continue,
+            };
+            if let Some(previous) = table.names.get(&name.symbol) {
+                let previous = table.adts[previous.0 as usize].name;
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "the type `{}` is defined multiple times",
+                        name.symbol
+                    ))
+                    .with_primary(name.span, "redefined here")
+                    .with_secondary(previous.span, "previously defined here"),
+                );
+                continue;
+            }
+            if PrimitiveType::from_symbol(name.symbol).is_some() {
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "`{}` is a built in type and can not be redefined",
+                        name.symbol
+                    ))
+                    .with_primary(name.span, ""),
+                );
+                continue;
+            }
+
+            let mut params: Vec<Ident> = Vec::new();
+            for param in &generics.params {
+                match params.iter().find(|x| x.symbol == param.name.symbol) {
+                    Some(previous) => {
+                        diagnostics.push(duplicate("generic parameter", param.name, *previous))
+                    }
+                    None => params.push(param.name),
+                }
+            }
+
+            let id = AdtId(table.adts.len() as u32);
+            table.names.insert(name.symbol, id);
+            table.adts.push(AdtDef {
+                name,
+                generics: params,
+                kind: AdtKind::Struct { fields: Vec::new() },
+                abi,
+            });
+            declarations.push((id, &item.kind));
+        }
+
+        for (id, kind) in declarations {
+            let scope = TypeScope::default().with_params(&table.adt(id).generics);
+            let kind = match kind {
+                ItemKind::Type(type_decl) if type_decl.is_extern && type_decl.fields.is_empty() => {
+                    AdtKind::Extern
+                }
+                ItemKind::Type(type_decl) => {
+                    let mut fields: Vec<FieldDef> = Vec::new();
+                    for field in &type_decl.fields {
+                        let ty = table.resolve_type(&field.ty, &scope, &mut diagnostics);
+                        if let Some(previous) =
+                            fields.iter().find(|x| x.name.symbol == field.name.symbol)
+                        {
+                            diagnostics.push(duplicate("field", field.name, previous.name));
+                            continue;
+                        }
+                        fields.push(FieldDef {
+                            name: field.name,
+                            ty,
+                        });
+                    }
+                    AdtKind::Struct { fields }
+                }
+                ItemKind::Enum(enum_decl) => {
+                    let mut variants: Vec<VariantDef> = Vec::new();
+                    for variant in &enum_decl.variants {
+                        let payload = variant
+                            .payload
+                            .iter()
+                            .map(|x| table.resolve_type(x, &scope, &mut diagnostics))
+                            .collect();
+                        if let Some(previous) = variants
+                            .iter()
+                            .find(|x| x.name.symbol == variant.name.symbol)
+                        {
+                            diagnostics.push(duplicate("variant", variant.name, previous.name));
+                            continue;
+                        }
+                        if let (Some(value), false) =
+                            (&variant.discriminant, variant.payload.is_empty())
+                        {
+                            diagnostics.push(
+                                Diagnostic::error(format!(
+                                    "the variant `{}` carries a payload and can not be given a \
+                                     discriminant",
+                                    variant.name.symbol
+                                ))
+                                .with_primary(value.span, "")
+                                .with_note("only variants without a payload have discriminants"),
+                            );
+                        }
+                        variants.push(VariantDef {
+                            name: variant.name,
+                            payload,
+                        });
+                    }
+                    AdtKind::Enum { variants }
+                }
+                _ => unreachable!("only types and enums are declared"),
+            };
+            table.adts[id.0 as usize].kind = kind;
+        }
+
+        table.infinite = table.find_infinite_types(&mut diagnostics);
+        table.compute_layouts();
+        table.check_foreign_types(module, &mut diagnostics);
+        (table, diagnostics)
+    }
+
+    /// Checks that the types declared `extern "C"` are not generic, and that their fields are C
+    /// types
+    fn check_foreign_types(&self, module: &Module, diagnostics: &mut Vec<Diagnostic>) {
+        for item in &module.items {
+            let ItemKind::Type(type_decl) = &item.kind else {
+                continue;
+            };
+            let Some(id) = self.lookup(type_decl.name.symbol) else {
+                continue;
+            };
+            let adt = self.adt(id);
+            if type_decl.abi.is_none() || adt.name.span != type_decl.name.span {
+                continue;
+            }
+            if let Some(param) = type_decl.generics.params.first() {
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "the `extern \"C\"` type `{}` cannot be generic",
+                        adt.name.symbol
+                    ))
+                    .with_primary(param.span, ""),
+                );
+                continue;
+            }
+            for (field, decl) in adt.fields().iter().zip(&type_decl.fields) {
+                let position = foreign::Position::Field;
+                foreign::check_c_type(self, &field.ty, position, decl.ty.span, diagnostics);
+            }
+        }
+    }
+
+    pub fn adt(&self, id: AdtId) -> &AdtDef {
+        &self.adts[id.0 as usize]
+    }
+
+    /// Gets every [AdtDef] in the order they were declared
+    pub fn adts(&self) -> impl Iterator<Item = (AdtId, &AdtDef)> {
+        self.adts
+            .iter()
+            .enumerate()
+            .map(|(index, adt)| (AdtId(index as u32), adt))
+    }
+
+    pub fn lookup(&self, name: Symbol) -> Option<AdtId> {
+        self.names.get(&name).copied()
+    }
+
+    /// Resolves a [TypeExpr] into a [Type], reporting names which are not types. The [TypeScope]
+    /// says which generic parameters can be named and what `Self` means
+    pub fn resolve_type(
+        &self,
+        ty: &TypeExpr,
+        scope: &TypeScope,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Type {
+        let (name, arguments) = match &ty.kind {
+            TypeExprKind::Named { name, arguments } => (name, arguments),
+            TypeExprKind::Reference {
+                kind,
+                mutable,
+                pointee,
+            } => {
+                return Type::Reference {
+                    kind: *kind,
+                    mutable: *mutable,
+                    pointee: Box::new(self.resolve_type(pointee, scope, diagnostics)),
+                }
+            }
+            TypeExprKind::Generator(item) => {
+                return Type::Generator(Box::new(self.resolve_type(item, scope, diagnostics)))
+            }
+            TypeExprKind::Function {
+                parameters,
+                return_type,
+            } => {
+                return Type::Function {
+                    parameters: parameters
+                        .iter()
+                        .map(|x| self.resolve_type(x, scope, diagnostics))
+                        .collect(),
+                    return_type: Box::new(match return_type {
+                        Some(return_type) => self.resolve_type(return_type, scope, diagnostics),
+                        None => Type::Unit,
+                    }),
+                }
+            }
+            TypeExprKind::Array { element, length } => {
+                let element = self.resolve_type(element, scope, diagnostics);
+                return Type::Array(Box::new(element), Length::of(length));
+            }
+            TypeExprKind::Error => return Type::Error,
+        };
+        let resolved: Vec<Type> = arguments
+            .iter()
+            .map(|x| self.resolve_type(x, scope, diagnostics))
+            .collect();
+
+        let (resolved_type, expected) = if scope.params.contains(&name.symbol) {
+            (Type::Param(name.symbol), 0)
+        } else if name.symbol == sym::SELF_TYPE {
+            let Some(self_type) = &scope.self_type else {
+                diagnostics.push(
+                    Diagnostic::error(
+                        "`Self` can only be used within a trait or an implementation",
+                    )
+                    .with_primary(name.span, ""),
+                );
+                return Type::Error;
+            };
+            (self_type.clone(), 0)
+        } else if let Some(primitive) = PrimitiveType::from_symbol(name.symbol) {
+            (Type::Primitive(primitive), 0)
+        } else if let Some(id) = self.lookup(name.symbol) {
+            let expected = self.adt(id).generics.len();
+            let mut resolved = resolved.clone();
+            resolved.resize(expected, Type::Error);
+            (Type::Adt(id, resolved), expected)
+        } else {
+            let mut diagnostic = Diagnostic::error(format!("cannot find type `{}`", name.symbol))
+                .with_primary(name.span, "not found");
+            let candidates = scope
+                .params
+                .iter()
+                .copied()
+                .chain(PrimitiveType::ALL.map(|x| Symbol::intern(x.name())))
+                .chain(self.adts.iter().map(|x| x.name.symbol));
+            if let Some(similar) = closest_match(name.symbol, candidates) {
+                diagnostic = diagnostic.with_note(format!("did you mean `{similar}`?"));
+            }
+            diagnostics.push(diagnostic);
+            return Type::Error;
+        };
+
+        if arguments.len() != expected {
+            let takes = match expected {
+                0 => "no generic arguments".to_string(),
+                1 => "1 generic argument".to_string(),
+                count => format!("{} generic arguments", count),
+            };
+            let supplied = match arguments.len() {
+                0 => "none were".to_string(),
+                1 => "1 was".to_string(),
+                count => format!("{} were", count),
+            };
+            diagnostics.push(
+                Diagnostic::error(format!(
+                    "the type `{}` takes {} but {} supplied",
+                    name.symbol, takes, supplied
+                ))
+                .with_primary(ty.span, ""),
+            );
+            return Type::Error;
+        }
+        resolved_type
+    }
+
+    /// Resolves a path such as `Shape::Circle` to the enum it belongs to and the index of the
+    /// variant, reporting paths which do not name a variant
+    pub fn resolve_variant(
+        &self,
+        path: &Path,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<(AdtId, usize)> {
+        let [enum_name, variant_name] = path.segments.as_slice() else {
+            diagnostics.push(
+                Diagnostic::error(format!("cannot find `{}`", path))
+                    .with_primary(path.span, "not found"),
+            );
+            return None;
+        };
+        let Some(id) = self.lookup(enum_name.symbol) else {
+            let mut diagnostic =
+                Diagnostic::error(format!("cannot find type `{}`", enum_name.symbol))
+                    .with_primary(enum_name.span, "not found");
+            let candidates = self
+                .adts
+                .iter()
+                .filter(|x| x.is_enum())
+                .map(|x| x.name.symbol);
+            if let Some(similar) = closest_match(enum_name.symbol, candidates) {
+                diagnostic = diagnostic.with_note(format!("did you mean `{similar}`?"));
+            }
+            diagnostics.push(diagnostic);
+            return None;
+        };
+
+        let adt = self.adt(id);
+        if !adt.is_enum() {
+            diagnostics.push(
+                Diagnostic::error(format!("expected an enum, found {}", adt.describe()))
+                    .with_primary(enum_name.span, "")
+                    .with_secondary(adt.name.span, "declared here"),
+            );
+            return None;
+        }
+        match adt.variant(variant_name.symbol) {
+            Some((index, _)) => Some((id, index)),
+            None => {
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "no variant named `{}` in enum `{}`",
+                        variant_name.symbol, enum_name.symbol
+                    ))
+                    .with_primary(variant_name.span, "")
+                    .with_secondary(adt.name.span, "enum declared here"),
+                );
+                None
+            }
+        }
+    }
+
+    /// Gets the name of a [Type] as it would be written in the source
+    pub fn type_name(&self, ty: &Type) -> String {
+        match ty {
+            Type::Primitive(primitive) => primitive.to_string(),
+            Type::Unit => "()".to_string(),
+            Type::Adt(id, arguments) if arguments.is_empty() => {
+                self.adt(*id).name.symbol.to_string()
+            }
+            Type::Adt(id, arguments) => {
+                let arguments: Vec<String> = arguments.iter().map(|x| self.type_name(x)).collect();
+                format!("{}<{}>", self.adt(*id).name.symbol, arguments.join(", "))
+            }
+            Type::Reference {
+                kind,
+                mutable,
+                pointee,
+            } => {
+                let mutable = if *mutable { "mut " } else { "" };
+                format!("{} {}{}", kind, mutable, self.type_name(pointee))
+            }
+            Type::Generator(item) => format!("yield {}", self.type_name(item)),
+            Type::Array(element, length) => format!("[{}; {}]", self.type_name(element), length),
+            Type::Function {
+                parameters,
+                return_type,
+            } => {
+                let parameters: Vec<String> =
+                    parameters.iter().map(|x| self.type_name(x)).collect();
+                match **return_type {
+                    Type::Unit => format!("fun({})", parameters.join(", ")),
+                    _ => format!(
+                        "fun({}) :: {}",
+                        parameters.join(", "),
+                        self.type_name(return_type)
+                    ),
+                }
+            }
+            Type::Param(name) => name.to_string(),
+            Type::Error => "{unknown}".to_string(),
+        }
+    }
+
+    /// Gets the types of the fields of a `type`, or the payloads of the variants of an `enum`,
+    /// with the generic arguments of `ty` filled in
+    pub fn field_type(&self, ty: &Type, field: &Type) -> Type {
+        match ty {
+            Type::Adt(id, arguments) => field.substitute(&self.adt(*id).substitution(arguments)),
+            _ => field.clone(),
+        }
+    }
+
+    /// Gets the [Layout] of a [Type], or [None] if it has none
+    pub fn layout_of(&self, ty: &Type) -> Option<Layout> {
+        match ty {
+            Type::Primitive(primitive) => Some(Layout::primitive(*primitive)),
+            Type::Unit => Some(Layout::unit()),
+            // A generator only holds a pointer to the frame which keeps its state between values
+            Type::Reference { .. } | Type::Generator(_) => Some(Layout::pointer()),
+            // An array keeps its values behind a pointer, the same way as a `Vec`
+            Type::Array(..) => Some(Layout::pointer()),
+            Type::Adt(id, arguments) if arguments.is_empty() => self.layouts[id.0 as usize].clone(),
+            Type::Adt(id, _) if self.infinite.contains(id) => None,
+            Type::Adt(id, _) => self.compute_layout(*id, ty, &mut |table, ty| table.layout_of(ty)),
+            // What a closure captured is only known once it is made
+            Type::Function { .. } | Type::Param(_) | Type::Error => None,
+        }
+    }
+
+    /// Gets every [AdtId] mentioned by a [Type], including within its generic arguments
+    fn mentioned_adts(ty: &Type, adts: &mut Vec<AdtId>) {
+        if let Type::Adt(id, arguments) = ty {
+            adts.push(*id);
+            for argument in arguments {
+                Self::mentioned_adts(argument, adts);
+            }
+        }
+    }
+
+    /// Gets the types an [AdtDef] stores directly, rather than behind some indirection. A type
+    /// given as a generic argument might be stored directly so it counts as well
+    fn contained_adts(&self, id: AdtId) -> Vec<AdtId> {
+        let types: Vec<&Type> = match &self.adt(id).kind {
+            AdtKind::Struct { fields } => fields.iter().map(|x| &x.ty).collect(),
+            AdtKind::Enum { variants } => variants.iter().flat_map(|x| &x.payload).collect(),
+            AdtKind::Extern => Vec::new(),
+        };
+        let mut adts = Vec::new();
+        for ty in types {
+            Self::mentioned_adts(ty, &mut adts);
+        }
+        adts
+    }
+
+    /// Finds every type which contains itself, since those would need an infinite amount of
+    /// memory. One error is reported for each cycle
+    fn find_infinite_types(&self, diagnostics: &mut Vec<Diagnostic>) -> HashSet<AdtId> {
+        let mut infinite = HashSet::new();
+        for (id, adt) in self.adts() {
+            if infinite.contains(&id) {
+                continue;
+            }
+            let mut path = Vec::new();
+            if !self.reaches(id, id, &mut path, &mut HashSet::new()) {
+                continue;
+            }
+
+            let mut diagnostic = Diagnostic::error(format!(
+                "recursive type `{}` has infinite size",
+                adt.name.symbol
+            ))
+            .with_primary(adt.name.span, "");
+            for step in &path {
+                diagnostic = diagnostic.with_secondary(
+                    self.adt(*step).name.span,
+                    "contains the next type in the cycle by value",
+                );
+            }
+            diagnostics.push(diagnostic.with_note(
+                "a type can not contain itself, every value would need an infinite amount of memory",
+            ));
+            infinite.insert(id);
+            infinite.extend(path);
+        }
+        infinite
+    }
+
+    /// Checks if `target` can be reached from `from` by following contained types, recording the
+    /// types along the way in `path`
+    fn reaches(
+        &self,
+        from: AdtId,
+        target: AdtId,
+        path: &mut Vec<AdtId>,
+        visited: &mut HashSet<AdtId>,
+    ) -> bool {
+        for next in self.contained_adts(from) {
+            if next == target {
+                return true;
+            }
+            if !visited.insert(next) {
+                continue;
+            }
+            path.push(next);
+            if self.reaches(next, target, path, visited) {
+                return true;
+            }
+            path.pop();
+        }
+        false
+    }
+
+    fn compute_layouts(&mut self) {
+        let mut layouts: Vec<Option<Option<Layout>>> = vec![None; self.adts.len()];
+        for index in 0..self.adts.len() {
+            self.layout_with(&Type::Adt(AdtId(index as u32), Vec::new()), &mut layouts);
+        }
+        self.layouts = layouts.into_iter().map(Option::flatten).collect();
+    }
+
+    /// Computes the layout of a [Type] while the layouts of non-generic types are still being
+    /// cached, computing the layouts of the types it contains first
+    fn layout_with(&self, ty: &Type, layouts: &mut Vec<Option<Option<Layout>>>) -> Option<Layout> {
+        let Type::Adt(id, arguments) = ty else {
+            return self.layout_of(ty);
+        };
+        if !arguments.is_empty() {
+            if self.infinite.contains(id) {
+                return None;
+            }
+            return self.compute_layout(*id, ty, &mut |table, ty| table.layout_with(ty, layouts));
+        }
+        if let Some(layout) = &layouts[id.0 as usize] {
+            return layout.clone();
+        }
+        let layout = if self.infinite.contains(id) || !self.adt(*id).generics.is_empty() {
+            None
+        } else {
+            self.compute_layout(*id, ty, &mut |table, ty| table.layout_with(ty, layouts))
+        };
+        layouts[id.0 as usize] = Some(layout.clone());
+        layout
+    }
+
+    /// Computes the layout of `ty`, an instance of the [AdtDef], using `layout_of` for the types
+    /// it contains
+    fn compute_layout(
+        &self,
+        id: AdtId,
+        ty: &Type,
+        layout_of: &mut dyn FnMut(&Self, &Type) -> Option<Layout>,
+    ) -> Option<Layout> {
+        let mut field_layout = |field: &Type| layout_of(self, &self.field_type(ty, field));
+        match &self.adt(id).kind {
+            AdtKind::Struct { fields } => fields
+                .iter()
+                .map(|x| field_layout(&x.ty))
+                .collect::<Option<Vec<_>>>()
+                .map(|x| Layout::structure(&x)),
+            AdtKind::Enum { variants } => variants
+                .iter()
+                .map(|x| x.payload.iter().map(&mut field_layout).collect())
+                .collect::<Option<Vec<Vec<_>>>>()
+                .map(|x| Layout::enumeration(&x)),
+            // The runtime keeps the value itself, handing out a pointer to it
+            AdtKind::Extern => Some(Layout::pointer()),
+        }
+    }
+}
+
+pub(crate) fn duplicate(what: &str, name: Ident, previous: Ident) -> Diagnostic {
+    Diagnostic::error(format!(
+        "the {} `{}` is declared more than once",
+        what, name.symbol
+    ))
+    .with_primary(name.span, "declared again here")
+    .with_secondary(previous.span, "first declared here")
+}
+
+/// Describes a number of values, such as "1 value" or "2 values"
+pub fn values(count: usize) -> String {
+    match count {
+        1 => "1 value".to_string(),
+        count => format!("{} values", count),
+    }
+}
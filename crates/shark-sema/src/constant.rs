@@ -0,0 +1,103 @@
+//! The `const` items of a module, collected along with the functions so that bodies can name
+//! them wherever they are declared. Their values are worked out while compiling, after checking
+
+use std::collections::HashMap;
+
+use shark_core::{diagnostic::Diagnostic, symbol::Symbol};
+use shark_parse::ast::{Ident, ItemKind, Module, NodeId};
+
+use crate::{adt::TypeTable, function::FunctionTable, generics::TypeScope, ty::Type};
+
+/// A `const` item and the type it was declared with
+#[derive(Debug, Clone)]
+pub struct ConstDef {
+    pub name: Ident,
+    pub ty: Type,
+    /// The item which declares the constant
+    pub item: NodeId,
+}
+
+/// Every `const` declared at the top level of a [Module]
+#[derive(Debug, Clone, Default)]
+pub struct ConstTable {
+    constants: Vec<ConstDef>,
+    names: HashMap<Symbol, usize>,
+    items: HashMap<NodeId, usize>,
+}
+
+impl ConstTable {
+    /// Collects every `const` of a [Module]. Their types must be primitive, which are the only
+    /// values the evaluator knows about, and their names can not be those of functions
+    pub fn collect(
+        module: &Module,
+        types: &TypeTable,
+        functions: &FunctionTable,
+    ) -> (Self, Vec<Diagnostic>) {
+        let mut table = Self::default();
+        let mut diagnostics = Vec::new();
+        for item in &module.items {
+            let ItemKind::Const(const_decl) = &item.kind else {
+                continue;
+            };
+            let name = const_decl.name;
+            let ty = types.resolve_type(&const_decl.ty, &TypeScope::default(), &mut diagnostics);
+            if !matches!(ty, Type::Primitive(_) | Type::Error) {
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "the constant `{}` can not be of type `{}`",
+                        name.symbol,
+                        types.type_name(&ty)
+                    ))
+                    .with_primary(const_decl.ty.span, "")
+                    .with_note("constants are integers, floats, `Bool`, `Char` or `Str`"),
+                );
+            }
+
+            if let Some(previous) = table.names.get(&name.symbol) {
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "the constant `{}` is defined multiple times",
+                        name.symbol
+                    ))
+                    .with_primary(name.span, "redefined here")
+                    .with_secondary(
+                        table.constants[*previous].name.span,
+                        "previously defined here",
+                    ),
+                );
+            } else if let Some(function) = functions.lookup(name.symbol) {
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "the name `{}` is already used by a function",
+                        name.symbol
+                    ))
+                    .with_primary(name.span, "")
+                    .with_secondary(function.name.span, "the function is defined here"),
+                );
+            } else {
+                table.names.insert(name.symbol, table.constants.len());
+            }
+            table.items.insert(item.id, table.constants.len());
+            table.constants.push(ConstDef {
+                name,
+                ty,
+                item: item.id,
+            });
+        }
+        (table, diagnostics)
+    }
+
+    pub fn lookup(&self, name: Symbol) -> Option<&ConstDef> {
+        self.names.get(&name).map(|x| &self.constants[*x])
+    }
+
+    /// Gets the constant declared by an item
+    pub fn const_of_item(&self, item: NodeId) -> Option<&ConstDef> {
+        self.items.get(&item).map(|x| &self.constants[*x])
+    }
+
+    /// Gets every [ConstDef] in the order they were declared
+    pub fn constants(&self) -> &[ConstDef] {
+        &self.constants
+    }
+}
@@ -0,0 +1,127 @@
+//! Checks the declarations of `extern "C"` functions and types. Their values cross into C, so they
+//! must be of types whose layout C agrees on: numbers, `Bool`, `Char`, `ptr`s, and the types
+//! declared `extern "C"` themselves. A `Str` is given to C as a pointer to its bytes followed by a
+//! zero, and taken back the same way, so it can be passed to and returned by foreign functions but
+//! can not be the field of a C type
+
+use shark_core::diagnostic::Diagnostic;
+use shark_parse::ast::ReferenceKind;
+
+use crate::{
+    adt::TypeTable,
+    generics::FunctionSig,
+    ty::{PrimitiveType, Type},
+};
+
+/// Where a type is used within a foreign declaration, which decides what it can be
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Position {
+    Parameter,
+    Return,
+    Field,
+}
+
+impl Position {
+    fn note(self) -> &'static str {
+        match self {
+            Self::Parameter | Self::Return => {
+                "C functions take and return numbers, `Bool`, `Char`, `Str`, `ptr`s and the types \
+                 declared `extern \"C\"`"
+            }
+            Self::Field => {
+                "the fields of an `extern \"C\"` type can be numbers, `Bool`, `Char`, `ptr`s and \
+                 other types declared `extern \"C\"`"
+            }
+        }
+    }
+}
+
+/// Checks whether values of a type can cross into C where it is used
+pub fn is_c_type(types: &TypeTable, ty: &Type, position: Position) -> bool {
+    match ty {
+        Type::Primitive(PrimitiveType::Str) => position != Position::Field,
+        Type::Primitive(_) => true,
+        Type::Unit => position == Position::Return,
+        Type::Reference {
+            kind: ReferenceKind::Ptr,
+            pointee,
+            ..
+        } => is_c_pointee(types, pointee),
+        // Opaque C types have no size, so only a `ptr` to one can be passed around
+        Type::Adt(id, _) => {
+            let adt = types.adt(*id);
+            adt.abi.is_some() && !adt.is_extern()
+        }
+        _ => false,
+    }
+}
+
+/// Checks whether a `ptr` to values of a type can cross into C
+fn is_c_pointee(types: &TypeTable, ty: &Type) -> bool {
+    match ty {
+        Type::Adt(id, _) => types.adt(*id).abi.is_some(),
+        ty => is_c_type(types, ty, Position::Field),
+    }
+}
+
+/// Reports a type which can not cross into C where it is used
+pub fn check_c_type(
+    types: &TypeTable,
+    ty: &Type,
+    position: Position,
+    span: shark_core::source::Span,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    if ty.contains_error() || is_c_type(types, ty, position) {
+        return;
+    }
+    let mut diagnostic = Diagnostic::error(format!("`{}` is not a C type", types.type_name(ty)))
+        .with_primary(span, "")
+        .with_note(position.note());
+    if let Type::Reference {
+        kind: ReferenceKind::Ref,
+        ..
+    } = ty
+    {
+        diagnostic = diagnostic.with_note("consider using a `ptr` instead of a `ref`");
+    }
+    diagnostics.push(diagnostic);
+}
+
+/// Checks the signature of an `extern "C"` function, which can not be generic and whose
+/// parameters and return type must be C types
+pub(crate) fn check_function(
+    types: &TypeTable,
+    sig: &FunctionSig,
+    what: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let name = sig.name;
+    if what != "function" {
+        diagnostics.push(
+            Diagnostic::error(format!(
+                "the {} `{}` cannot be `extern \"C\"`",
+                what, name.symbol
+            ))
+            .with_primary(name.span, "")
+            .with_note("only free functions can be written in C"),
+        );
+        return;
+    }
+    if !sig.generics.params.is_empty() {
+        diagnostics.push(
+            Diagnostic::error(format!(
+                "the `extern \"C\"` function `{}` cannot be generic",
+                name.symbol
+            ))
+            .with_primary(name.span, ""),
+        );
+        return;
+    }
+    for (ty, span) in sig.parameters.iter().zip(&sig.parameter_spans) {
+        check_c_type(types, ty, Position::Parameter, *span, diagnostics);
+    }
+    if let Some(span) = sig.return_span {
+        check_c_type(types, &sig.return_type, Position::Return, span, diagnostics);
+    }
+}
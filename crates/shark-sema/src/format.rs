@@ -0,0 +1,59 @@
+//! Templates such as `"{} + {} = {}"`, which the variadic functions of the standard library fill
+//! in with the values they are given after the template. `{{` and `}}` stand for a brace itself
+
+use std::fmt::{self, Display, Formatter};
+
+/// Why a template could not be read
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TemplateError {
+    /// A `{` which is neither followed by `}` nor by another `{`
+    UnclosedPlaceholder,
+    /// A `}` which neither closes a placeholder nor is followed by another `}`
+    UnmatchedBrace,
+}
+
+impl Display for TemplateError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::UnclosedPlaceholder => write!(f, "`{{` must be followed by `}}` or `{{`"),
+            Self::UnmatchedBrace => write!(f, "`}}` must be preceded by `{{` or followed by `}}`"),
+        }
+    }
+}
+
+/// Splits a template into the text around its placeholders, so a template with `n` placeholders
+/// has `n + 1` pieces of text
+pub fn split_template(template: &str) -> Result<Vec<String>, TemplateError> {
+    let mut pieces = vec![String::new()];
+    let mut characters = template.chars().peekable();
+    while let Some(character) = characters.next() {
+        let current = pieces.last_mut().expect("there is always a piece");
+        match (character, characters.peek()) {
+            ('{', Some('{')) | ('}', Some('}')) => {
+                current.push(character);
+                characters.next();
+            }
+            ('{', Some('}')) => {
+                characters.next();
+                pieces.push(String::new());
+            }
+            ('{', _) => return Err(TemplateError::UnclosedPlaceholder),
+            ('}', _) => return Err(TemplateError::UnmatchedBrace),
+            _ => current.push(character),
+        }
+    }
+    Ok(pieces)
+}
+
+/// Fills in the placeholders of a template which was split by [split_template] with the given
+/// values in order. Placeholders without a value are left empty
+pub fn fill_template(pieces: &[String], values: &[String]) -> String {
+    let mut output = String::new();
+    for (index, piece) in pieces.iter().enumerate() {
+        if index > 0 {
+            output.push_str(values.get(index - 1).map_or("", |x| x.as_str()));
+        }
+        output.push_str(piece);
+    }
+    output
+}
@@ -0,0 +1,113 @@
+//! The signatures of the free functions of a module, collected before any body is checked so that
+//! functions can call each other regardless of the order they are declared in
+
+use std::collections::HashMap;
+
+use shark_core::{diagnostic::Diagnostic, symbol::Symbol};
+use shark_parse::ast::{ItemKind, Module, NodeId};
+
+use crate::{
+    adt::TypeTable,
+    foreign,
+    generics::{FunctionSig, TypeScope},
+    traits::TraitTable,
+};
+
+/// Every `fun` declared at the top level of a [Module]
+#[derive(Debug, Clone, Default)]
+pub struct FunctionTable {
+    functions: Vec<FunctionSig>,
+    names: HashMap<Symbol, usize>,
+    /// The signature of each item, including functions which were defined multiple times
+    items: HashMap<NodeId, usize>,
+}
+
+impl FunctionTable {
+    pub fn collect(
+        module: &Module,
+        types: &TypeTable,
+        traits: &TraitTable,
+    ) -> (Self, Vec<Diagnostic>) {
+        let mut table = Self::default();
+        let mut diagnostics = Vec::new();
+        for item in &module.items {
+            let ItemKind::Function(function) = &item.kind else {
+                continue;
+            };
+            let sig =
+                traits.lower_signature(types, function, &TypeScope::default(), &mut diagnostics);
+            let name = sig.name;
+            check_declaration(types, &sig, "function", &mut diagnostics);
+
+            let index = table.functions.len();
+            match table.names.get(&name.symbol) {
+                Some(previous) => diagnostics.push(
+                    Diagnostic::error(format!(
+                        "the function `{}` is defined multiple times",
+                        name.symbol
+                    ))
+                    .with_primary(name.span, "redefined here")
+                    .with_secondary(
+                        table.functions[*previous].name.span,
+                        "previously defined here",
+                    ),
+                ),
+                None => {
+                    table.names.insert(name.symbol, index);
+                }
+            }
+            table.items.insert(item.id, index);
+            table.functions.push(sig);
+        }
+        (table, diagnostics)
+    }
+
+    pub fn lookup(&self, name: Symbol) -> Option<&FunctionSig> {
+        self.names.get(&name).map(|x| &self.functions[*x])
+    }
+
+    /// Gets the signature of the function declared by an item
+    pub fn signature_of(&self, item: NodeId) -> Option<&FunctionSig> {
+        self.items.get(&item).map(|x| &self.functions[*x])
+    }
+}
+
+/// Checks that a function or a method has a body unless it is `extern`, that only `extern`
+/// functions are variadic, and that foreign functions only use C types. The methods of traits are
+/// not checked here since they can leave out their body but cannot be `extern`
+pub(crate) fn check_declaration(
+    types: &TypeTable,
+    sig: &FunctionSig,
+    what: &str,
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let name = sig.name;
+    if sig.abi.is_some() {
+        foreign::check_function(types, sig, what, diagnostics);
+    }
+    if sig.is_extern && sig.has_body {
+        diagnostics.push(
+            Diagnostic::error(format!(
+                "the `extern` {} `{}` cannot have a body",
+                what, name.symbol
+            ))
+            .with_primary(name.span, "")
+            .with_note("the body of an `extern` function is provided by the runtime"),
+        );
+    } else if !sig.is_extern && !sig.has_body {
+        diagnostics.push(
+            Diagnostic::error(format!("the {} `{}` has no body", what, name.symbol))
+                .with_primary(name.span, "")
+                .with_note(
+                    "only the methods of a trait and `extern` functions can leave out their body",
+                ),
+        );
+    }
+    if sig.is_variadic && !sig.is_extern {
+        diagnostics.push(
+            Diagnostic::error(format!("the {} `{}` cannot be variadic", what, name.symbol))
+                .with_primary(name.span, "")
+                .with_note("only `extern` functions can take any number of arguments"),
+        );
+    }
+}
@@ -0,0 +1,239 @@
+//! Generic parameters and the trait bounds placed on them. An item such as
+//! `fun largest<T :: Ord>(values :: List<T>) :: T` is checked once with `T` left as a
+//! [Type::Param], and each use of the item binds its parameters to the types it is used with by
+//! matching, see [match_type]
+
+use std::collections::HashMap;
+
+use shark_core::{
+    source::Span,
+    symbol::{sym, Symbol},
+};
+use shark_parse::ast::{Abi, Ident};
+
+use crate::{traits::TraitId, ty::Type};
+
+/// What the names of types mean at some point in the source, beyond the types declared by the
+/// module
+#[derive(Debug, Clone, Default)]
+pub struct TypeScope {
+    /// Every generic parameter which can be named
+    pub params: Vec<Symbol>,
+    /// What `Self` means, if it can be used at all. Within a trait it is [Type::Param] while
+    /// within an implementation it is the type being implemented for
+    pub self_type: Option<Type>,
+}
+
+impl TypeScope {
+    /// Creates a scope with more generic parameters in it
+    pub fn with_params(&self, params: &[Ident]) -> Self {
+        let mut scope = self.clone();
+        scope.params.extend(params.iter().map(|x| x.symbol));
+        scope
+    }
+
+    /// The scope within a trait, where `Self` is a generic parameter bound by the trait itself
+    pub fn within_trait() -> Self {
+        Self {
+            params: Vec::new(),
+            self_type: Some(Type::Param(sym::SELF_TYPE)),
+        }
+    }
+}
+
+/// A requirement that a type implements a trait, written either as a bound on a generic
+/// parameter or within a `where` clause
+#[derive(Debug, Clone, PartialEq)]
+pub struct Predicate {
+    pub ty: Type,
+    pub trait_id: TraitId,
+    /// Where the bound was written
+    pub span: Span,
+}
+
+/// The generic parameters of an item along with the predicates its uses must satisfy
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct GenericsDef {
+    pub params: Vec<Ident>,
+    pub predicates: Vec<Predicate>,
+}
+
+impl GenericsDef {
+    /// Maps each generic parameter to an argument, in order
+    pub fn substitution(&self, arguments: &[Type]) -> HashMap<Symbol, Type> {
+        self.params
+            .iter()
+            .map(|x| x.symbol)
+            .zip(arguments.iter().cloned())
+            .collect()
+    }
+}
+
+/// The signature of a function or a method with every type resolved
+#[derive(Debug, Clone, PartialEq)]
+pub struct FunctionSig {
+    pub name: Ident,
+    pub generics: GenericsDef,
+    /// The type of every parameter, including `self`
+    pub parameters: Vec<Type>,
+    /// Where the type of each parameter was written
+    pub parameter_spans: Vec<Span>,
+    pub return_type: Type,
+    /// Where the return type was written, if it was
+    pub return_span: Option<Span>,
+    /// Whether the first parameter is `self`, making the function a method which can be called
+    /// with `value.method()`
+    pub has_self: bool,
+    pub has_body: bool,
+    /// Whether the function is `unsafe`, so it can only be called from within `unsafe` code.
+    /// Foreign functions are always `unsafe`
+    pub is_unsafe: bool,
+    /// Whether the function is `extern`, so its body is provided by the runtime
+    pub is_extern: bool,
+    /// The ABI of a foreign function, whose body is linked in rather than provided by the runtime
+    pub abi: Option<Abi>,
+    /// Whether the function takes any number of arguments after its parameters, each of which must
+    /// implement `Show`
+    pub is_variadic: bool,
+}
+
+/// Matches `pattern` against `ty`, binding the generic parameters of `pattern` to the parts of
+/// `ty` they stand for. A parameter which is already bound must stand for the same type again.
+/// Generic parameters within `ty` are treated like any other type
+pub fn match_type(pattern: &Type, ty: &Type, bindings: &mut HashMap<Symbol, Type>) -> bool {
+    match (pattern, ty) {
+        (Type::Param(param), _) => match bindings.get(param) {
+            Some(bound) => bound == ty || ty.contains_error(),
+            None => {
+                bindings.insert(*param, ty.clone());
+                true
+            }
+        },
+        (Type::Adt(pattern_id, pattern_arguments), Type::Adt(id, arguments)) => {
+            pattern_id == id
+                && pattern_arguments
+                    .iter()
+                    .zip(arguments)
+                    .all(|(x, y)| match_type(x, y, bindings))
+        }
+        (
+            Type::Reference {
+                kind: pattern_kind,
+                mutable: pattern_mutable,
+                pointee: pattern_pointee,
+            },
+            Type::Reference {
+                kind,
+                mutable,
+                pointee,
+            },
+        ) => {
+            pattern_kind == kind
+                && pattern_mutable == mutable
+                && match_type(pattern_pointee, pointee, bindings)
+        }
+        (Type::Generator(pattern_item), Type::Generator(item)) => {
+            match_type(pattern_item, item, bindings)
+        }
+        // Lengths are only known once they have been evaluated, so they are not compared here
+        (Type::Array(pattern_element, _), Type::Array(element, _)) => {
+            match_type(pattern_element, element, bindings)
+        }
+        (
+            Type::Function {
+                parameters: pattern_parameters,
+                return_type: pattern_return,
+            },
+            Type::Function {
+                parameters,
+                return_type,
+            },
+        ) => {
+            pattern_parameters.len() == parameters.len()
+                && pattern_parameters
+                    .iter()
+                    .zip(parameters)
+                    .all(|(x, y)| match_type(x, y, bindings))
+                && match_type(pattern_return, return_type, bindings)
+        }
+        (_, Type::Error) => true,
+        _ => pattern == ty,
+    }
+}
+
+/// Checks if two types could ever be the same type, treating every generic parameter in either of
+/// them as a placeholder for any type
+pub fn unify(left: &Type, right: &Type, bindings: &mut HashMap<Symbol, Type>) -> bool {
+    let left = resolve(left, bindings);
+    let right = resolve(right, bindings);
+    match (&left, &right) {
+        (Type::Param(x), Type::Param(y)) if x == y => true,
+        (Type::Param(param), other) | (other, Type::Param(param)) => {
+            let other = other.substitute(bindings);
+            if other.mentions(*param) {
+                return false;
+            }
+            bindings.insert(*param, other);
+            true
+        }
+        (Type::Adt(left_id, left_arguments), Type::Adt(right_id, right_arguments)) => {
+            left_id == right_id
+                && left_arguments
+                    .iter()
+                    .zip(right_arguments)
+                    .all(|(x, y)| unify(x, y, bindings))
+        }
+        (
+            Type::Reference {
+                kind: left_kind,
+                mutable: left_mutable,
+                pointee: left_pointee,
+            },
+            Type::Reference {
+                kind: right_kind,
+                mutable: right_mutable,
+                pointee: right_pointee,
+            },
+        ) => {
+            left_kind == right_kind
+                && left_mutable == right_mutable
+                && unify(left_pointee, right_pointee, bindings)
+        }
+        (Type::Generator(left_item), Type::Generator(right_item)) => {
+            unify(left_item, right_item, bindings)
+        }
+        (Type::Array(left_element, _), Type::Array(right_element, _)) => {
+            unify(left_element, right_element, bindings)
+        }
+        (
+            Type::Function {
+                parameters: left_parameters,
+                return_type: left_return,
+            },
+            Type::Function {
+                parameters: right_parameters,
+                return_type: right_return,
+            },
+        ) => {
+            left_parameters.len() == right_parameters.len()
+                && left_parameters
+                    .iter()
+                    .zip(right_parameters)
+                    .all(|(x, y)| unify(x, y, bindings))
+                && unify(left_return, right_return, bindings)
+        }
+        _ => left == right,
+    }
+}
+
+/// Follows the bindings of a generic parameter until reaching something which is not bound
+fn resolve(ty: &Type, bindings: &HashMap<Symbol, Type>) -> Type {
+    let mut ty = ty.clone();
+    while let Type::Param(param) = ty {
+        match bindings.get(&param) {
+            Some(bound) => ty = bound.clone(),
+            None => break,
+        }
+    }
+    ty
+}
@@ -0,0 +1,133 @@
+//! Works out how values are stored in memory. Every value has a size and an alignment, and the
+//! values within a `type` or an `enum` variant are stored one after the other in the order they
+//! were declared, each padded to its alignment like a C struct
+//!
+//! An `enum` starts with a tag saying which variant it holds, followed by the values of that
+//! variant. Every variant shares the same memory so the `enum` is as big as its biggest variant
+
+use crate::ty::PrimitiveType;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Layout {
+    pub size: u64,
+    pub align: u64,
+    pub kind: LayoutKind,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LayoutKind {
+    /// A value with no parts, such as a number
+    Scalar,
+    /// The offset of every field, in declaration order
+    Struct { offsets: Vec<u64> },
+    /// The tag is stored at offset zero, followed by the payload of the variant it names. Each
+    /// variant lists the offsets of its payload values
+    Enum {
+        tag: PrimitiveType,
+        variants: Vec<Vec<u64>>,
+    },
+}
+
+impl Layout {
+    pub fn primitive(primitive: PrimitiveType) -> Self {
+        let size = match primitive {
+            PrimitiveType::Int8 | PrimitiveType::UInt8 | PrimitiveType::Bool => 1,
+            PrimitiveType::Int32
+            | PrimitiveType::UInt32
+            | PrimitiveType::Float32
+            | PrimitiveType::Char => 4,
+            PrimitiveType::Int64 | PrimitiveType::UInt64 | PrimitiveType::Float64 => 8,
+            // A pointer to the bytes of the string followed by its length
+            PrimitiveType::Str => {
+                return Self {
+                    size: 16,
+                    align: 8,
+                    kind: LayoutKind::Struct {
+                        offsets: vec![0, 8],
+                    },
+                }
+            }
+        };
+        Self {
+            size,
+            align: size,
+            kind: LayoutKind::Scalar,
+        }
+    }
+
+    /// The layout of a `ref` or a `ptr`, which is an address
+    pub fn pointer() -> Self {
+        Self {
+            size: 8,
+            align: 8,
+            kind: LayoutKind::Scalar,
+        }
+    }
+
+    pub fn unit() -> Self {
+        Self {
+            size: 0,
+            align: 1,
+            kind: LayoutKind::Scalar,
+        }
+    }
+
+    /// Lays out the fields of a `type` in declaration order
+    pub fn structure(fields: &[Layout]) -> Self {
+        let (offsets, size, align) = place_fields(0, fields);
+        Self {
+            size: align_to(size, align),
+            align,
+            kind: LayoutKind::Struct { offsets },
+        }
+    }
+
+    /// Lays out an `enum` from the payload of each of its variants
+    pub fn enumeration(variants: &[Vec<Layout>]) -> Self {
+        let tag = if variants.len() <= 1 << 8 {
+            PrimitiveType::UInt8
+        } else {
+            PrimitiveType::UInt32
+        };
+        let tag_layout = Self::primitive(tag);
+
+        let mut size = tag_layout.size;
+        let mut align = tag_layout.align;
+        let mut offsets = Vec::with_capacity(variants.len());
+        for payload in variants {
+            let (variant_offsets, variant_size, variant_align) =
+                place_fields(tag_layout.size, payload);
+            size = size.max(variant_size);
+            align = align.max(variant_align);
+            offsets.push(variant_offsets);
+        }
+        Self {
+            size: align_to(size, align),
+            align,
+            kind: LayoutKind::Enum {
+                tag,
+                variants: offsets,
+            },
+        }
+    }
+}
+
+/// Places each field after the previous one, starting at `start`. Returns the offset of every
+/// field along with the end of the last field and the largest alignment
+fn place_fields(start: u64, fields: &[Layout]) -> (Vec<u64>, u64, u64) {
+    let mut offset = start;
+    let mut align = 1;
+    let mut offsets = Vec::with_capacity(fields.len());
+    for field in fields {
+        offset = align_to(offset, field.align);
+        offsets.push(offset);
+        offset += field.size;
+        align = align.max(field.align);
+    }
+    (offsets, offset, align)
+}
+
+/// Rounds `offset` up to the next multiple of `align`
+pub fn align_to(offset: u64, align: u64) -> u64 {
+    offset.div_ceil(align) * align
+}
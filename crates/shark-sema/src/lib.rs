@@ -0,0 +1,71 @@
+use adt::TypeTable;
+use constant::ConstTable;
+use function::FunctionTable;
+use shark_core::{diagnostic::Diagnostic, symbol::Symbol};
+use shark_parse::ast::Module;
+use traits::TraitTable;
+use ty::Type;
+
+pub mod adt;
+pub mod constant;
+pub mod foreign;
+pub mod format;
+pub mod function;
+pub mod generics;
+pub mod layout;
+pub mod numeric;
+pub mod pattern;
+pub mod traits;
+pub mod ty;
+
+#[cfg(test)]
+pub mod tests;
+
+/// Everything a [Module] declares, collected by [check_module]
+#[derive(Debug, Clone, Default)]
+pub struct ModuleDefs {
+    pub types: TypeTable,
+    pub traits: TraitTable,
+    pub functions: FunctionTable,
+    pub constants: ConstTable,
+}
+
+impl ModuleDefs {
+    /// Says what a call is which a backend without access to the runtime of an engine found no
+    /// body for: a call to an `extern` function or method, whose body the runtime provides, a call
+    /// to an `extern "C"` function, or otherwise a call to a generic one. Methods are looked up on
+    /// the type `receiver`
+    pub fn unsupported_call(&self, receiver: Option<&Type>, name: Symbol) -> &'static str {
+        match receiver {
+            Some(ty) if self.traits.is_extern_method(ty, name) => "calls to `extern` methods",
+            Some(_) => "calls to generic methods",
+            None if self.functions.lookup(name).is_some_and(|x| x.abi.is_some()) => {
+                "calls to `extern \"C\"` functions"
+            }
+            None if self.functions.lookup(name).is_some_and(|x| x.is_extern) => {
+                "calls to `extern` functions"
+            }
+            None => "calls to generic functions",
+        }
+    }
+}
+
+/// Step three of compilation. Collects the types, traits, functions and constants declared by a
+/// [Module], checking the declarations themselves. How the bodies of functions and methods use
+/// them is checked along with their types, see `shark_typeck`
+pub fn check_module(module: &Module) -> (ModuleDefs, Vec<Diagnostic>) {
+    let (table, mut diagnostics) = TypeTable::collect(module);
+    let (traits, mut trait_diagnostics) = TraitTable::collect(module, &table);
+    diagnostics.append(&mut trait_diagnostics);
+    let (functions, mut function_diagnostics) = FunctionTable::collect(module, &table, &traits);
+    diagnostics.append(&mut function_diagnostics);
+    let (constants, mut const_diagnostics) = ConstTable::collect(module, &table, &functions);
+    diagnostics.append(&mut const_diagnostics);
+    let defs = ModuleDefs {
+        types: table,
+        traits,
+        functions,
+        constants,
+    };
+    (defs, diagnostics)
+}
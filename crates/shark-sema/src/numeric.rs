@@ -0,0 +1,252 @@
+//! The semantics of arithmetic on numbers, which every way of running a program follows
+//!
+//! `+`, `-`, `*`, `/` and a unary `-` on integers do what the program was built to do when the
+//! result does not fit in the type: trap in a debug build and wrap around in two's complement in a
+//! release build, see [Overflow]. Dividing by zero and shifting by at least the number of bits in
+//! the type trap whichever way the program was built. Operations on floats never trap
+//!
+//! Numbers are never converted implicitly, apart from literals without a suffix which take on the
+//! type their context expects. Other conversions are explicit, through [Intrinsic]s such as
+//! `x.to_int64()`. Those which can lose information follow the build as well: a value which does
+//! not fit in the integer type it is converted to traps in a debug build, while a release build
+//! wraps integers around and saturates floats, a `NaN` becoming zero
+//!
+//! The methods of integers which choose what happens on overflow whichever way the program was
+//! built are [Intrinsic]s too: `wrapping_add`, `trapping_add` and `saturating_add`, and likewise
+//! for `sub`, `mul` and `div`. An intrinsic is found before any trait method of the same name
+
+use shark_core::symbol::Symbol;
+use shark_parse::ast::BinaryOperator;
+
+use crate::ty::PrimitiveType;
+
+/// What integer arithmetic does when its result does not fit in its type. A program is built to
+/// either trap or wrap, while only the `saturating_` methods saturate
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum Overflow {
+    /// Stops the program, which is what a debug build does
+    #[default]
+    Trap,
+    /// Keeps the bits which fit, which is what a release build does
+    Wrap,
+    /// Gives the smallest or largest value of the type, whichever is nearest
+    Saturate,
+}
+
+impl Overflow {
+    /// Gets the prefix of the methods which overflow this way
+    pub fn prefix(&self) -> &'static str {
+        match self {
+            Self::Trap => "trapping",
+            Self::Wrap => "wrapping",
+            Self::Saturate => "saturating",
+        }
+    }
+}
+
+/// An operation on a number written as a call to one of its methods
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Intrinsic {
+    /// `x.wrapping_add(y)` and the like, one of `+`, `-`, `*` and `/` on integers which overflows
+    /// the same way however the program was built
+    Arith(BinaryOperator, Overflow),
+    /// `x.to_float64()` and the like, converting a number to another numeric type
+    Convert(PrimitiveType),
+}
+
+/// The operators which have a method for each way of overflowing
+const OPERATORS: [(BinaryOperator, &str); 4] = [
+    (BinaryOperator::Add, "add"),
+    (BinaryOperator::Subtract, "sub"),
+    (BinaryOperator::Multiply, "mul"),
+    (BinaryOperator::Divide, "div"),
+];
+
+/// The numeric types, which can be converted to one another
+pub const NUMERIC: [PrimitiveType; 8] = [
+    PrimitiveType::Int8,
+    PrimitiveType::UInt8,
+    PrimitiveType::Int32,
+    PrimitiveType::UInt32,
+    PrimitiveType::Int64,
+    PrimitiveType::UInt64,
+    PrimitiveType::Float32,
+    PrimitiveType::Float64,
+];
+
+impl Intrinsic {
+    /// Finds the intrinsic a method of a primitive type refers to
+    pub fn lookup(receiver: PrimitiveType, method: Symbol) -> Option<Self> {
+        let method = method.as_str();
+        if receiver.is_integer() {
+            for overflow in [Overflow::Trap, Overflow::Wrap, Overflow::Saturate] {
+                let Some(rest) = method.strip_prefix(overflow.prefix()) else {
+                    continue;
+                };
+                let operator = OPERATORS
+                    .iter()
+                    .find(|(_, name)| rest.strip_prefix('_') == Some(name))?;
+                return Some(Self::Arith(operator.0, overflow));
+            }
+        }
+        if receiver.is_integer() || receiver.is_float() {
+            let target = NUMERIC
+                .into_iter()
+                .find(|x| method.strip_prefix("to_") == Some(&x.name().to_lowercase()))?;
+            return Some(Self::Convert(target));
+        }
+        None
+    }
+
+    /// Gets the number of arguments the method takes besides the number it is called on
+    pub fn arity(&self) -> usize {
+        match self {
+            Self::Arith(..) => 1,
+            Self::Convert(_) => 0,
+        }
+    }
+
+    /// Gets the type the method returns when called on a number of type `receiver`
+    pub fn return_type(&self, receiver: PrimitiveType) -> PrimitiveType {
+        match self {
+            Self::Arith(..) => receiver,
+            Self::Convert(target) => *target,
+        }
+    }
+}
+
+/// Gets the method which converts a number to a numeric type
+pub fn conversion_method(target: PrimitiveType) -> String {
+    format!("to_{}", target.name().to_lowercase())
+}
+
+/// Checks if every value of a numeric type can be converted to another without losing information
+pub fn is_lossless(from: PrimitiveType, to: PrimitiveType) -> bool {
+    match (from.is_float(), to.is_float()) {
+        (true, true) => from == to || to == PrimitiveType::Float64,
+        (true, false) => false,
+        (false, true) => {
+            let (min, max) = from.bounds();
+            let mantissa = match to {
+                PrimitiveType::Float32 => 24,
+                _ => 53,
+            };
+            -(1 << mantissa) <= min && max <= 1 << mantissa
+        }
+        (false, false) => {
+            let ((min, max), (to_min, to_max)) = (from.bounds(), to.bounds());
+            to_min <= min && max <= to_max
+        }
+    }
+}
+
+/// Why an operation on numbers stops the program
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NumericError {
+    Overflow,
+    DivisionByZero,
+}
+
+/// Gets the number of bits of an integer type
+pub fn bits(ty: PrimitiveType) -> u32 {
+    match ty {
+        PrimitiveType::Int8 | PrimitiveType::UInt8 => 8,
+        PrimitiveType::Int32 | PrimitiveType::UInt32 | PrimitiveType::Char => 32,
+        _ => 64,
+    }
+}
+
+/// Keeps the bits of a value which fit in an integer type, as wrapping around does
+pub fn wrap(ty: PrimitiveType, value: i128) -> i128 {
+    let bits = bits(ty);
+    let value = value & ((1 << bits) - 1);
+    let (min, _) = ty.bounds();
+    match min < 0 && value >> (bits - 1) == 1 {
+        true => value - (1 << bits),
+        false => value,
+    }
+}
+
+/// Gives a value which does not fit in an integer type the nearest one which does
+pub fn saturate(ty: PrimitiveType, value: i128) -> i128 {
+    let (min, max) = ty.bounds();
+    value.clamp(min, max)
+}
+
+/// Makes a value fit in an integer type the way [Overflow] says to
+fn fit(ty: PrimitiveType, overflow: Overflow, value: i128) -> Result<i128, NumericError> {
+    let (min, max) = ty.bounds();
+    match overflow {
+        _ if (min..=max).contains(&value) => Ok(value),
+        Overflow::Trap => Err(NumericError::Overflow),
+        Overflow::Wrap => Ok(wrap(ty, value)),
+        Overflow::Saturate => Ok(saturate(ty, value)),
+    }
+}
+
+/// Applies `+`, `-`, `*` or `/` to two integers of a type, which is what every backend must agree
+/// with
+pub fn arith(
+    operator: BinaryOperator,
+    overflow: Overflow,
+    ty: PrimitiveType,
+    x: i128,
+    y: i128,
+) -> Result<i128, NumericError> {
+    let result = match operator {
+        BinaryOperator::Add => x + y,
+        BinaryOperator::Subtract => x - y,
+        // Only two `UInt64`s can overflow an `i128`, in which case the result is too large for
+        // any type but still wraps around to the right bits
+        BinaryOperator::Multiply => match x.checked_mul(y) {
+            Some(result) => result,
+            None if overflow == Overflow::Wrap => x.wrapping_mul(y),
+            None => i128::MAX,
+        },
+        BinaryOperator::Divide if y == 0 => return Err(NumericError::DivisionByZero),
+        BinaryOperator::Divide => x / y,
+        _ => panic!("`{}` is not an arithmetic operator", operator),
+    };
+    fit(ty, overflow, result)
+}
+
+/// Negates an integer of a type
+pub fn negate(overflow: Overflow, ty: PrimitiveType, x: i128) -> Result<i128, NumericError> {
+    fit(ty, overflow, -x)
+}
+
+/// A number as [convert] sees it, whatever its type
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Number {
+    Integer(i128),
+    Float(f64),
+}
+
+/// Converts a number to a numeric type. Floats are rounded to the nearest value of the type they
+/// are converted to, or truncated towards zero when converted to an integer. Floats which do not
+/// fit saturate rather than wrap around, which would make no sense for them
+pub fn convert(
+    number: Number,
+    target: PrimitiveType,
+    overflow: Overflow,
+) -> Result<Number, NumericError> {
+    match (number, target) {
+        (Number::Integer(x), PrimitiveType::Float32) => Ok(Number::Float((x as f32).into())),
+        (Number::Integer(x), PrimitiveType::Float64) => Ok(Number::Float(x as f64)),
+        (Number::Integer(x), _) => fit(target, overflow, x).map(Number::Integer),
+        (Number::Float(x), PrimitiveType::Float32) => Ok(Number::Float((x as f32).into())),
+        (Number::Float(x), PrimitiveType::Float64) => Ok(Number::Float(x)),
+        (Number::Float(x), _) if x.is_nan() => match overflow {
+            Overflow::Trap => Err(NumericError::Overflow),
+            _ => Ok(Number::Integer(0)),
+        },
+        // Converting to `i128` saturates beyond the range of every integer type
+        (Number::Float(x), _) => {
+            let overflow = match overflow {
+                Overflow::Trap => Overflow::Trap,
+                _ => Overflow::Saturate,
+            };
+            fit(target, overflow, x.trunc() as i128).map(Number::Integer)
+        }
+    }
+}
@@ -0,0 +1,646 @@
+//! Checks the arms of a `when`. Every arm must be reachable, meaning an earlier arm does not
+//! already match everything it matches, and together the arms must match every possible value
+//!
+//! Patterns are first lowered into a [Pat], which is either a wildcard or a [Constructor] applied
+//! to more patterns. Both checks are then answered by the usefulness algorithm described in
+//! "Warnings for pattern matching" by Luc Maranget: a pattern is useful if it matches a value
+//! which no earlier pattern matches. An arm is unreachable if its pattern is not useful, and the
+//! arms are exhaustive if a wildcard after them is not useful. The values which make a wildcard
+//! useful are the patterns which are not covered
+
+use shark_core::{diagnostic::Diagnostic, source::Span, symbol::Symbol};
+use shark_lex::token::LiteralKind;
+use shark_parse::ast::{Ident, Pattern, PatternKind};
+
+use crate::{
+    adt::{values, AdtKind, TypeTable},
+    ty::{AdtId, PrimitiveType, Type},
+};
+
+/// The most missing patterns worth working out, since only a few are shown to the user
+const WITNESS_LIMIT: usize = 4;
+
+/// Something a value can be built from, such as a variant of an `enum`
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constructor {
+    Tuple(usize),
+    Variant {
+        adt: AdtId,
+        index: usize,
+    },
+    Bool(bool),
+    /// An inclusive range of integers, or characters as their code points
+    Range {
+        start: i128,
+        end: i128,
+        ty: PrimitiveType,
+    },
+    Str(Symbol),
+    /// A float, compared by its bits
+    Float(u64),
+    /// A pattern which could not be lowered. It matches nothing, so it never makes another arm
+    /// unreachable
+    Opaque,
+}
+
+/// A lowered pattern
+#[derive(Debug, Clone, PartialEq)]
+pub enum Pat {
+    Wild,
+    Ctor(Constructor, Vec<Pat>),
+}
+
+impl Pat {
+    /// Checks if the pattern contains a part which could not be lowered
+    fn is_opaque(&self) -> bool {
+        match self {
+            Pat::Wild => false,
+            Pat::Ctor(Constructor::Opaque, _) => true,
+            Pat::Ctor(_, fields) => fields.iter().any(Pat::is_opaque),
+        }
+    }
+}
+
+/// What is known about the values in one column of the pattern matrix
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ColumnType {
+    Enum(AdtId),
+    Tuple(usize),
+    Bool,
+    Range(PrimitiveType),
+    /// The values can not be listed, either because there are infinitely many of them or because
+    /// their type is not known. Only a wildcard covers all of them
+    Unlisted,
+}
+
+/// An arm of a `when` after its pattern has been lowered
+#[derive(Debug, Clone)]
+pub struct Arm {
+    pub pattern: Pat,
+    /// Arms with a guard might not match even when their pattern does, so they never cover
+    /// anything
+    pub guarded: bool,
+    pub span: Span,
+}
+
+/// Finds a part of a pattern which does not match every value of its type, such as a literal.
+/// Variants are always counted as refutable, even those of an `enum` with a single variant
+pub fn find_refutable(pattern: &Pattern) -> Option<&Pattern> {
+    match &pattern.kind {
+        PatternKind::Wildcard | PatternKind::Binding { .. } => None,
+        PatternKind::Tuple(elements) => elements.iter().find_map(find_refutable),
+        PatternKind::Literal(_) | PatternKind::Range { .. } | PatternKind::Variant { .. } => {
+            Some(pattern)
+        }
+    }
+}
+
+/// Lowers a pattern into a [Pat]. `expected` is the type of the values being matched, and every
+/// name the pattern binds is added to `bindings` along with its type
+pub fn lower_pattern(
+    table: &TypeTable,
+    pattern: &Pattern,
+    expected: &Type,
+    bindings: &mut Vec<(Ident, Type)>,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Pat {
+    match &pattern.kind {
+        PatternKind::Wildcard => Pat::Wild,
+        PatternKind::Binding { name, .. } => {
+            if let Some((previous, _)) = bindings.iter().find(|(x, _)| x.symbol == name.symbol) {
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "`{}` is bound more than once in the same pattern",
+                        name.symbol
+                    ))
+                    .with_primary(name.span, "bound again here")
+                    .with_secondary(previous.span, "first bound here"),
+                );
+            }
+            bindings.push((*name, expected.clone()));
+            Pat::Wild
+        }
+        PatternKind::Literal(literal) => {
+            match lower_literal(table, literal, expected, pattern.span, diagnostics) {
+                Some(constructor) => Pat::Ctor(constructor, Vec::new()),
+                None => Pat::Ctor(Constructor::Opaque, Vec::new()),
+            }
+        }
+        PatternKind::Range {
+            start,
+            end,
+            inclusive,
+        } => lower_range(
+            table,
+            (start, end, *inclusive),
+            expected,
+            pattern.span,
+            diagnostics,
+        ),
+        PatternKind::Tuple(elements) => {
+            if *expected != Type::Error {
+                diagnostics.push(mismatch(table, expected, "a tuple", pattern.span));
+                return Pat::Ctor(Constructor::Opaque, Vec::new());
+            }
+            let elements = elements
+                .iter()
+                .map(|x| lower_pattern(table, x, &Type::Error, bindings, diagnostics))
+                .collect::<Vec<_>>();
+            Pat::Ctor(Constructor::Tuple(elements.len()), elements)
+        }
+        PatternKind::Variant { path, payload } => {
+            let Some((adt, index)) = table.resolve_variant(path, diagnostics) else {
+                return Pat::Ctor(Constructor::Opaque, Vec::new());
+            };
+            let definition = table.adt(adt);
+            let enum_type = match expected {
+                Type::Adt(id, _) if *id == adt => expected.clone(),
+                Type::Error => Type::Adt(adt, vec![Type::Error; definition.generics.len()]),
+                _ => {
+                    let found = format!("`{}`", definition.name.symbol);
+                    diagnostics.push(mismatch(table, expected, &found, pattern.span));
+                    return Pat::Ctor(Constructor::Opaque, Vec::new());
+                }
+            };
+
+            let types: Vec<Type> = definition.variants()[index]
+                .payload
+                .iter()
+                .map(|x| table.field_type(&enum_type, x))
+                .collect();
+            if payload.len() != types.len() {
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "the variant `{}` carries {} but the pattern has {}",
+                        path,
+                        values(types.len()),
+                        payload.len()
+                    ))
+                    .with_primary(pattern.span, ""),
+                );
+            }
+            let mut fields: Vec<Pat> = payload
+                .iter()
+                .zip(&types)
+                .map(|(x, ty)| lower_pattern(table, x, ty, bindings, diagnostics))
+                .collect();
+            fields.resize(types.len(), Pat::Wild);
+            Pat::Ctor(Constructor::Variant { adt, index }, fields)
+        }
+    }
+}
+
+/// Gets the type of a literal within a pattern. An unsuffixed integer or float takes on the
+/// integer or float type it is matched against
+fn literal_type(literal: &LiteralKind, expected: &Type) -> PrimitiveType {
+    let ty = PrimitiveType::of_literal(literal);
+    match (literal, expected) {
+        (LiteralKind::Int32(_), Type::Primitive(expected)) if expected.is_integer() => *expected,
+        (LiteralKind::Float32(_), Type::Primitive(expected)) if expected.is_float() => *expected,
+        _ => ty,
+    }
+}
+
+/// Gets the value of an integer or character literal
+fn literal_value(literal: &LiteralKind) -> Option<i128> {
+    Some(match *literal {
+        LiteralKind::UInt8(x) => x.into(),
+        LiteralKind::Int8(x) => x.into(),
+        LiteralKind::UInt32(x) => x.into(),
+        LiteralKind::Int32(x) => x.into(),
+        LiteralKind::UInt64(x) => x.into(),
+        LiteralKind::Int64(x) => x.into(),
+        LiteralKind::Char(x) => u32::from(x).into(),
+        _ => return None,
+    })
+}
+
+fn lower_literal(
+    table: &TypeTable,
+    literal: &LiteralKind,
+    expected: &Type,
+    span: Span,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Option<Constructor> {
+    let ty = literal_type(literal, expected);
+    if *expected != Type::Error && *expected != Type::Primitive(ty) {
+        diagnostics.push(mismatch(table, expected, &format!("`{}`", ty), span));
+        return None;
+    }
+
+    Some(match *literal {
+        LiteralKind::Boolean(x) => Constructor::Bool(x),
+        LiteralKind::Str(x) => Constructor::Str(x),
+        LiteralKind::Float32(x) => Constructor::Float(f64::from(x).to_bits()),
+        LiteralKind::Float64(x) => Constructor::Float(x.to_bits()),
+        _ => {
+            let value = literal_value(literal).expect("every other literal has a value");
+            let (min, max) = ty.bounds();
+            if value < min || value > max {
+                diagnostics.push(
+                    Diagnostic::error(format!("the literal `{}` does not fit in `{}`", value, ty))
+                        .with_primary(span, "")
+                        .with_note(format!("`{}` ranges from {} to {}", ty, min, max)),
+                );
+                return None;
+            }
+            Constructor::Range {
+                start: value,
+                end: value,
+                ty,
+            }
+        }
+    })
+}
+
+fn lower_range(
+    table: &TypeTable,
+    (start, end, inclusive): (&LiteralKind, &LiteralKind, bool),
+    expected: &Type,
+    span: Span,
+    diagnostics: &mut Vec<Diagnostic>,
+) -> Pat {
+    let opaque = Pat::Ctor(Constructor::Opaque, Vec::new());
+    let (Some(start_value), Some(end_value)) = (literal_value(start), literal_value(end)) else {
+        diagnostics.push(
+            Diagnostic::error("only integers and characters can be used in range patterns")
+                .with_primary(span, ""),
+        );
+        return opaque;
+    };
+
+    let ty = literal_type(start, expected);
+    let end_ty = literal_type(end, expected);
+    if ty != end_ty {
+        diagnostics.push(
+            Diagnostic::error(format!(
+                "mismatched types in range pattern: `{}` and `{}`",
+                ty, end_ty
+            ))
+            .with_primary(span, ""),
+        );
+        return opaque;
+    }
+    if *expected != Type::Error && *expected != Type::Primitive(ty) {
+        diagnostics.push(mismatch(table, expected, &format!("`{}`", ty), span));
+        return opaque;
+    }
+
+    let end_value = if inclusive { end_value } else { end_value - 1 };
+    if start_value > end_value {
+        let message = if inclusive {
+            "lower range bound must be less than or equal to upper"
+        } else {
+            "lower range bound must be less than upper"
+        };
+        diagnostics.push(Diagnostic::error(message).with_primary(span, "this range is empty"));
+        return opaque;
+    }
+    Pat::Ctor(
+        Constructor::Range {
+            start: start_value,
+            end: end_value,
+            ty,
+        },
+        Vec::new(),
+    )
+}
+
+fn mismatch(table: &TypeTable, expected: &Type, found: &str, span: Span) -> Diagnostic {
+    Diagnostic::error(format!(
+        "mismatched types: expected `{}`, found {}",
+        table.type_name(expected),
+        found
+    ))
+    .with_primary(span, "this pattern can never match")
+}
+
+/// Runs the usefulness algorithm over a pattern matrix
+struct Matcher<'matcher> {
+    table: &'matcher TypeTable,
+}
+
+impl Matcher<'_> {
+    fn column_type_of(&self, ty: &Type) -> Option<ColumnType> {
+        match *ty {
+            Type::Adt(adt, _) => match self.table.adt(adt).kind {
+                AdtKind::Enum { .. } => Some(ColumnType::Enum(adt)),
+                AdtKind::Struct { .. } | AdtKind::Extern => Some(ColumnType::Unlisted),
+            },
+            Type::Primitive(PrimitiveType::Bool) => Some(ColumnType::Bool),
+            Type::Primitive(primitive) if primitive.is_integer() => {
+                Some(ColumnType::Range(primitive))
+            }
+            Type::Primitive(PrimitiveType::Char) => Some(ColumnType::Range(PrimitiveType::Char)),
+            Type::Primitive(_)
+            | Type::Unit
+            | Type::Reference { .. }
+            | Type::Generator(_)
+            | Type::Function { .. }
+            | Type::Array(..) => Some(ColumnType::Unlisted),
+            // The type of a generic payload is left to the patterns in its column
+            Type::Param(_) | Type::Error => None,
+        }
+    }
+
+    /// Works out what is in a column from the constructors used in it
+    fn infer_column_type<'pat>(&self, heads: impl Iterator<Item = &'pat Pat>) -> ColumnType {
+        for head in heads {
+            let Pat::Ctor(constructor, _) = head else {
+                continue;
+            };
+            return match constructor {
+                Constructor::Tuple(arity) => ColumnType::Tuple(*arity),
+                Constructor::Variant { adt, .. } => ColumnType::Enum(*adt),
+                Constructor::Bool(_) => ColumnType::Bool,
+                Constructor::Range { ty, .. } => ColumnType::Range(*ty),
+                Constructor::Str(_) | Constructor::Float(_) => ColumnType::Unlisted,
+                Constructor::Opaque => continue,
+            };
+        }
+        ColumnType::Unlisted
+    }
+
+    /// Lists every constructor of a column, or [None] if they can not be listed
+    fn all_constructors(&self, ty: ColumnType) -> Option<Vec<Constructor>> {
+        Some(match ty {
+            ColumnType::Enum(adt) => (0..self.table.adt(adt).variants().len())
+                .map(|index| Constructor::Variant { adt, index })
+                .collect(),
+            ColumnType::Tuple(arity) => vec![Constructor::Tuple(arity)],
+            ColumnType::Bool => vec![Constructor::Bool(false), Constructor::Bool(true)],
+            ColumnType::Range(ty) => {
+                let (start, end) = ty.bounds();
+                vec![Constructor::Range { start, end, ty }]
+            }
+            ColumnType::Unlisted => return None,
+        })
+    }
+
+    /// Splits a range constructor into pieces which every range in the column either fully
+    /// covers or does not touch at all. Any other constructor is left as it is
+    fn split<'pat>(
+        &self,
+        constructor: Constructor,
+        heads: impl Iterator<Item = &'pat Pat>,
+    ) -> Vec<Constructor> {
+        let Constructor::Range { start, end, ty } = constructor else {
+            return vec![constructor];
+        };
+        let mut points = vec![start, end + 1];
+        for head in heads {
+            if let Pat::Ctor(
+                Constructor::Range {
+                    start: from,
+                    end: to,
+                    ..
+                },
+                _,
+            ) = head
+            {
+                points.extend(
+                    [*from, to + 1]
+                        .into_iter()
+                        .filter(|x| *x > start && *x <= end),
+                );
+            }
+        }
+        points.sort_unstable();
+        points.dedup();
+        points
+            .windows(2)
+            .map(|x| Constructor::Range {
+                start: x[0],
+                end: x[1] - 1,
+                ty,
+            })
+            .collect()
+    }
+
+    /// Gets how many patterns a constructor is applied to, along with their types
+    fn fields(&self, constructor: &Constructor) -> Vec<Option<ColumnType>> {
+        match constructor {
+            Constructor::Tuple(arity) => vec![None; *arity],
+            Constructor::Variant { adt, index } => self.table.adt(*adt).variants()[*index]
+                .payload
+                .iter()
+                .map(|x| self.column_type_of(x))
+                .collect(),
+            _ => Vec::new(),
+        }
+    }
+
+    /// Checks if a constructor written in a pattern matches everything the split constructor does
+    fn covers(written: &Constructor, split: &Constructor) -> bool {
+        match (written, split) {
+            (
+                Constructor::Range { start, end, .. },
+                Constructor::Range {
+                    start: split_start,
+                    end: split_end,
+                    ..
+                },
+            ) => start <= split_start && split_end <= end,
+            (Constructor::Opaque, _) => false,
+            _ => written == split,
+        }
+    }
+
+    /// Specializes a row for a constructor, see [Matcher::useful]. Returns [None] if the row can
+    /// not match values built from the constructor
+    fn specialize(&self, row: &[Pat], constructor: &Constructor, arity: usize) -> Option<Vec<Pat>> {
+        let (head, rest) = row.split_first().expect("rows are never empty here");
+        let mut result = match head {
+            Pat::Wild => vec![Pat::Wild; arity],
+            Pat::Ctor(written, fields) if Self::covers(written, constructor) => fields.clone(),
+            Pat::Ctor(..) => return None,
+        };
+        result.resize(arity, Pat::Wild);
+        result.extend_from_slice(rest);
+        Some(result)
+    }
+
+    /// Finds the values which `row` matches but no row of the `matrix` does, written as patterns.
+    /// An empty result means that `row` is not useful. `types` holds what is known about each
+    /// column. At most `limit` patterns are found
+    fn useful(
+        &self,
+        matrix: &[Vec<Pat>],
+        row: &[Pat],
+        types: &[Option<ColumnType>],
+        limit: usize,
+    ) -> Vec<Vec<Pat>> {
+        let Some((head, rest)) = row.split_first() else {
+            return if matrix.is_empty() {
+                vec![Vec::new()]
+            } else {
+                Vec::new()
+            };
+        };
+
+        let heads = || matrix.iter().map(|x| &x[0]).chain(std::iter::once(head));
+        let ty = types[0].unwrap_or_else(|| self.infer_column_type(heads()));
+
+        let constructors = match head {
+            Pat::Ctor(constructor, _) => self.split(constructor.clone(), heads()),
+            Pat::Wild => match self.all_constructors(ty) {
+                Some(constructors) => constructors
+                    .into_iter()
+                    .flat_map(|x| self.split(x, heads()))
+                    .collect(),
+                None => {
+                    // There are values no constructor in the column matches, and only the rows
+                    // starting with a wildcard match those
+                    let default: Vec<Vec<Pat>> = matrix
+                        .iter()
+                        .filter(|x| x[0] == Pat::Wild)
+                        .map(|x| x[1..].to_vec())
+                        .collect();
+                    return self
+                        .useful(&default, rest, &types[1..], limit)
+                        .into_iter()
+                        .map(|x| [vec![Pat::Wild], x].concat())
+                        .collect();
+                }
+            },
+        };
+
+        let mut witnesses = Vec::new();
+        for constructor in constructors {
+            let fields = self.fields(&constructor);
+            let arity = fields.len();
+            let specialized: Vec<Vec<Pat>> = matrix
+                .iter()
+                .filter_map(|x| self.specialize(x, &constructor, arity))
+                .collect();
+            let Some(specialized_row) = self.specialize(row, &constructor, arity) else {
+                continue;
+            };
+            let types = [fields, types[1..].to_vec()].concat();
+
+            for witness in self.useful(
+                &specialized,
+                &specialized_row,
+                &types,
+                limit - witnesses.len(),
+            ) {
+                let (fields, rest) = witness.split_at(arity);
+                let mut rebuilt = vec![Pat::Ctor(constructor.clone(), fields.to_vec())];
+                rebuilt.extend_from_slice(rest);
+                witnesses.push(rebuilt);
+            }
+            if witnesses.len() >= limit {
+                break;
+            }
+        }
+        witnesses
+    }
+
+    /// Writes a pattern out the way it would be written in the source
+    fn display(&self, pattern: &Pat) -> String {
+        let Pat::Ctor(constructor, fields) = pattern else {
+            return "_".to_string();
+        };
+        let fields: Vec<String> = fields.iter().map(|x| self.display(x)).collect();
+        match constructor {
+            Constructor::Tuple(1) => format!("({},)", fields[0]),
+            Constructor::Tuple(_) => format!("({})", fields.join(", ")),
+            Constructor::Variant { adt, index } => {
+                let adt = self.table.adt(*adt);
+                let name = format!(
+                    "{}::{}",
+                    adt.name.symbol,
+                    adt.variants()[*index].name.symbol
+                );
+                match fields.is_empty() {
+                    true => name,
+                    false => format!("{}({})", name, fields.join(", ")),
+                }
+            }
+            Constructor::Bool(x) => x.to_string(),
+            Constructor::Range { start, end, ty } => {
+                if (*start, *end) == ty.bounds() {
+                    return "_".to_string();
+                }
+                let start = display_value(*start, *ty);
+                match start == display_value(*end, *ty) {
+                    true => start,
+                    false => format!("{}..={}", start, display_value(*end, *ty)),
+                }
+            }
+            Constructor::Str(x) => format!("{:?}", x.as_str()),
+            Constructor::Float(x) => f64::from_bits(*x).to_string(),
+            Constructor::Opaque => "_".to_string(),
+        }
+    }
+}
+
+/// Writes an integer or character value the way it would be written as a literal
+fn display_value(value: i128, ty: PrimitiveType) -> String {
+    match ty {
+        PrimitiveType::Char => match u32::try_from(value).ok().and_then(char::from_u32) {
+            Some(character) => format!("{:?}", character),
+            None => format!("'\\u{{{:x}}}'", value),
+        },
+        PrimitiveType::Int32 => value.to_string(),
+        ty => format!("{}{}", value, ty.name().to_lowercase()),
+    }
+}
+
+/// Checks the lowered arms of a `when`, warning about arms which can never be reached and
+/// reporting values which no arm matches
+pub fn check_arms(
+    table: &TypeTable,
+    scrutinee: &Type,
+    scrutinee_span: Span,
+    arms: &[Arm],
+    diagnostics: &mut Vec<Diagnostic>,
+) {
+    let matcher = Matcher { table };
+    let types = [matcher.column_type_of(scrutinee).or_else(|| {
+        // Without the type of the scrutinee, guarded arms still tell what is being matched
+        Some(matcher.infer_column_type(arms.iter().map(|x| &x.pattern)))
+    })];
+
+    let mut matrix: Vec<Vec<Pat>> = Vec::new();
+    for arm in arms {
+        let row = vec![arm.pattern.clone()];
+        // A pattern which could not be lowered has already been reported
+        if !arm.pattern.is_opaque() && matcher.useful(&matrix, &row, &types, 1).is_empty() {
+            diagnostics.push(Diagnostic::warning("unreachable pattern").with_primary(
+                arm.span,
+                "the arms before this one already match everything it matches",
+            ));
+        }
+        if !arm.guarded {
+            matrix.push(row);
+        }
+    }
+
+    let missing = matcher.useful(&matrix, &[Pat::Wild], &types, WITNESS_LIMIT);
+    if missing.is_empty() {
+        return;
+    }
+    let mut patterns: Vec<String> = missing
+        .iter()
+        .map(|x| format!("`{}`", matcher.display(&x[0])))
+        .collect();
+    let list = match patterns.len() {
+        1 => patterns.remove(0),
+        count if count < WITNESS_LIMIT => {
+            let last = patterns.pop().expect("there are several patterns");
+            format!("{} and {}", patterns.join(", "), last)
+        }
+        _ => format!("{} and more", patterns[..WITNESS_LIMIT - 1].join(", ")),
+    };
+    let noun = if missing.len() == 1 {
+        "pattern"
+    } else {
+        "patterns"
+    };
+    diagnostics.push(
+        Diagnostic::error(format!("non-exhaustive patterns: {} not covered", list))
+            .with_primary(scrutinee_span, format!("{} {} not covered", noun, list))
+            .with_note("add arms for the missing patterns, or a `_` arm to match everything else"),
+    );
+}
@@ -0,0 +1,333 @@
+use shark_core::symbol::Symbol;
+use shark_parse::parse;
+
+use crate::{
+    adt::TypeTable,
+    check_module,
+    layout::LayoutKind,
+    numeric::{self, Number, NumericError, Overflow},
+    ty::{PrimitiveType, Type},
+};
+
+fn check(source: &str) -> (TypeTable, Vec<String>) {
+    let module = parse(None, source).expect("failed to parse module");
+    let (defs, diagnostics) = check_module(&module);
+    (
+        defs.types,
+        diagnostics.iter().map(|x| x.message.clone()).collect(),
+    )
+}
+
+fn adt_type(table: &TypeTable, name: &str) -> Type {
+    let id = table.lookup(Symbol::intern(name)).expect("missing type");
+    Type::Adt(id, Vec::new())
+}
+
+#[test]
+fn test_collect_types() {
+    let (table, errors) = check(
+        "enum Shape { Circle(Float32), Rect(Point, Point), Empty }
+        type Point { x :: Float32, y :: Float32 }",
+    );
+    assert!(errors.is_empty(), "{:?}", errors);
+
+    let Type::Adt(shape, _) = adt_type(&table, "Shape") else {
+        panic!("expected an adt");
+    };
+    let shape = table.adt(shape);
+    assert!(shape.is_enum());
+    let (index, rect) = shape
+        .variant(Symbol::intern("Rect"))
+        .expect("missing variant");
+    assert_eq!(index, 1);
+    assert_eq!(rect.payload, vec![adt_type(&table, "Point"); 2]);
+    assert_eq!(table.type_name(&adt_type(&table, "Point")), "Point");
+}
+
+#[test]
+fn test_declaration_errors() {
+    let (_, errors) = check(
+        "type Point { x :: Float32, x :: Int32, z :: Vector }
+        enum Point { A }
+        enum Shape { A, A }
+        type Int32 { value :: Int32 }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "the type `Point` is defined multiple times",
+            "`Int32` is a built in type and can not be redefined",
+            "the field `x` is declared more than once",
+            "cannot find type `Vector`",
+            "the variant `A` is declared more than once",
+        ]
+    );
+}
+
+#[test]
+fn test_infinite_types() {
+    let (table, errors) = check(
+        "type List { value :: Int32, next :: List }
+        type A { b :: B }
+        enum B { Empty, Full(A) }
+        type Fine { a :: Int32 }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "recursive type `List` has infinite size",
+            "recursive type `A` has infinite size",
+        ]
+    );
+    assert_eq!(table.layout_of(&adt_type(&table, "List")), None);
+    assert_eq!(table.layout_of(&adt_type(&table, "B")), None);
+    assert!(table.layout_of(&adt_type(&table, "Fine")).is_some());
+}
+
+#[test]
+fn test_struct_layout() {
+    let (table, _) = check(
+        "type Mixed { a :: UInt8, b :: Int64, c :: Bool, d :: Int32 }
+        type Point { x :: Float32, y :: Float32 }
+        type Line { start :: Point, end :: Point, name :: Str }
+        type Empty {}",
+    );
+
+    let mixed = table
+        .layout_of(&adt_type(&table, "Mixed"))
+        .expect("no layout");
+    assert_eq!((mixed.size, mixed.align), (24, 8));
+    assert_eq!(
+        mixed.kind,
+        LayoutKind::Struct {
+            offsets: vec![0, 8, 16, 20]
+        }
+    );
+
+    let line = table
+        .layout_of(&adt_type(&table, "Line"))
+        .expect("no layout");
+    assert_eq!((line.size, line.align), (32, 8));
+    assert_eq!(
+        line.kind,
+        LayoutKind::Struct {
+            offsets: vec![0, 8, 16]
+        }
+    );
+
+    let empty = table
+        .layout_of(&adt_type(&table, "Empty"))
+        .expect("no layout");
+    assert_eq!((empty.size, empty.align), (0, 1));
+}
+
+#[test]
+fn test_enum_layout() {
+    let (table, _) = check(
+        "type Point { x :: Float32, y :: Float32 }
+        enum Shape { Circle(Float32), Rect(Point, Point), Empty }
+        enum Direction { North, East, South, West }
+        enum Wide { Small(UInt8), Big(Int64, UInt8) }",
+    );
+
+    let shape = table
+        .layout_of(&adt_type(&table, "Shape"))
+        .expect("no layout");
+    assert_eq!((shape.size, shape.align), (20, 4));
+    assert_eq!(
+        shape.kind,
+        LayoutKind::Enum {
+            tag: PrimitiveType::UInt8,
+            variants: vec![vec![4], vec![4, 12], vec![]]
+        }
+    );
+
+    let direction = table
+        .layout_of(&adt_type(&table, "Direction"))
+        .expect("no layout");
+    assert_eq!((direction.size, direction.align), (1, 1));
+
+    let wide = table
+        .layout_of(&adt_type(&table, "Wide"))
+        .expect("no layout");
+    assert_eq!((wide.size, wide.align), (24, 8));
+    assert_eq!(
+        wide.kind,
+        LayoutKind::Enum {
+            tag: PrimitiveType::UInt8,
+            variants: vec![vec![1], vec![8, 16]]
+        }
+    );
+}
+
+#[test]
+fn test_trait_errors() {
+    let (_, errors) = check(
+        "trait Show { fun show(self) :: Str; fun name() :: Str; }
+        trait Show {}
+        trait Point {}
+        type Point { x :: Int32 }
+        impl Show for Point { fun show(self, extra :: Int32) :: Str { \"\" } fun other(self) {} }
+        impl Show for Bool { fun show(self) :: Int32 { 1 } fun name() :: Str { \"\" } }
+        impl Missing for Point {}
+        impl Point for Bool {}
+        impl<T> Show for Int32 { fun show(self) :: Str { \"\" } fun name() :: Str { \"\" } }
+        fun helper<T :: Eq>(value :: T) :: Self;
+        fun helper() {}",
+    );
+    assert_eq!(
+        errors,
+        [
+            "the trait `Show` is defined multiple times",
+            "the name `Point` is already used by a type",
+            "the method `show` does not match its declaration in the trait `Show`",
+            "`other` is not a method of the trait `Show`",
+            "missing method `name` in the implementation of `Show` for `Point`",
+            "the method `show` does not match its declaration in the trait `Show`",
+            "cannot find trait `Missing`",
+            "expected a trait, found type `Point`",
+            "the generic parameter `T` is not used by the implemented type `Int32`",
+            "cannot find trait `Eq`",
+            "`Self` can only be used within a trait or an implementation",
+            "the function `helper` has no body",
+            "the function `helper` is defined multiple times",
+        ]
+    );
+}
+
+#[test]
+fn test_coherence() {
+    let (_, errors) = check(
+        "trait Show { fun show(self) :: Str; }
+        enum Option<T> { Some(T), None }
+        impl<T> Show for Option<T> { fun show(self) :: Str { \"\" } }
+        impl Show for Option<Int32> { fun show(self) :: Str { \"\" } }
+        impl<T> Show for Option<Option<T>> { fun show(self) :: Str { \"\" } }
+        impl Show for Int32 { fun show(self) :: Str { \"\" } }
+        impl Show for Bool { fun show(self) :: Str { \"\" } }
+        impl<T> Show for T { fun show(self) :: Str { \"\" } }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "conflicting implementations of the trait `Show` for `Option<Int32>`",
+            "conflicting implementations of the trait `Show` for `Option<Option<T>>`",
+            "conflicting implementations of the trait `Show` for `T`",
+        ]
+    );
+}
+
+#[test]
+fn test_constants() {
+    let (_, errors) = check(
+        "type Point { x :: Int32 }
+        enum Shape { Circle(Float64) = 2, Empty = 3 }
+        const ORIGIN :: Point = Point { x = 0 };
+        const LIMIT :: Int32 = 10;
+        const LIMIT :: Int32 = 20;
+        const area :: Int32 = 1;
+        fun area() :: Int32 { LIMIT + 1 }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "the variant `Circle` carries a payload and can not be given a discriminant",
+            "the constant `ORIGIN` can not be of type `Point`",
+            "the constant `LIMIT` is defined multiple times",
+            "the name `area` is already used by a function",
+        ]
+    );
+}
+
+#[test]
+fn test_numeric_semantics() {
+    use shark_parse::ast::BinaryOperator::{Add, Divide, Multiply, Subtract};
+    use PrimitiveType::{Float32, Int64, Int8, UInt64, UInt8};
+
+    let arith = |operator, overflow, ty, x, y| numeric::arith(operator, overflow, ty, x, y);
+    assert_eq!(arith(Add, Overflow::Trap, Int8, 100, 27), Ok(127));
+    assert_eq!(
+        arith(Add, Overflow::Trap, Int8, 100, 28),
+        Err(NumericError::Overflow)
+    );
+    assert_eq!(arith(Add, Overflow::Wrap, Int8, 100, 28), Ok(-128));
+    assert_eq!(arith(Subtract, Overflow::Saturate, UInt8, 1, 2), Ok(0));
+    assert_eq!(arith(Divide, Overflow::Wrap, Int8, -128, -1), Ok(-128));
+    assert_eq!(
+        arith(Divide, Overflow::Saturate, Int8, 1, 0),
+        Err(NumericError::DivisionByZero)
+    );
+    let max = u64::MAX as i128;
+    assert_eq!(arith(Multiply, Overflow::Wrap, UInt64, max, max), Ok(1));
+    assert_eq!(
+        arith(Multiply, Overflow::Saturate, UInt64, max, max),
+        Ok(max)
+    );
+
+    let convert = |number, target, overflow| numeric::convert(number, target, overflow);
+    assert_eq!(
+        convert(Number::Integer(300), UInt8, Overflow::Wrap),
+        Ok(Number::Integer(44))
+    );
+    assert_eq!(
+        convert(Number::Float(-1e30), Int64, Overflow::Wrap),
+        Ok(Number::Integer(i64::MIN as i128))
+    );
+    assert_eq!(
+        convert(Number::Float(f64::NAN), Int8, Overflow::Wrap),
+        Ok(Number::Integer(0))
+    );
+    assert_eq!(
+        convert(Number::Float(f64::NAN), Int8, Overflow::Trap),
+        Err(NumericError::Overflow)
+    );
+    assert_eq!(
+        convert(Number::Integer(16777217), Float32, Overflow::Trap),
+        Ok(Number::Float(16777216.0))
+    );
+
+    assert!(numeric::is_lossless(UInt8, Int64));
+    assert!(!numeric::is_lossless(Int64, UInt64));
+    assert!(!numeric::is_lossless(Int64, PrimitiveType::Float64));
+    assert!(numeric::is_lossless(
+        PrimitiveType::Int32,
+        PrimitiveType::Float64
+    ));
+}
+
+#[test]
+fn test_foreign_declarations() {
+    let (table, errors) = check(
+        "extern \"C\" type Point { x :: Int32, y :: Float64 }
+        extern \"C\" type File;
+        extern \"C\" fun fopen(path :: Str, mode :: Str) :: ptr mut File;
+        extern \"C\" fun length(point :: ptr Point) :: Float64;
+        extern \"C\" fun printf(format :: Str, ...) :: Int32;",
+    );
+    assert!(errors.is_empty(), "{:?}", errors);
+    let layout = table.layout_of(&adt_type(&table, "Point"));
+    assert_eq!(layout.map(|x| (x.size, x.align)), Some((16, 8)));
+
+    let (_, errors) = check(
+        "extern \"C\" type Named { name :: Str }
+        extern \"C\" type Wrapper<T> { value :: T }
+        extern \"C\" type File;
+        type Point { x :: Int32 }
+        extern \"C\" fun by_ref(value :: ref Int32);
+        extern \"C\" fun by_value(file :: File) :: Point;
+        extern \"C\" fun generic<T>(value :: ptr T);
+        extern \"C\" fun body() {}",
+    );
+    assert_eq!(
+        errors,
+        [
+            "`Str` is not a C type",
+            "the `extern \"C\"` type `Wrapper` cannot be generic",
+            "`ref Int32` is not a C type",
+            "`File` is not a C type",
+            "`Point` is not a C type",
+            "the `extern \"C\"` function `generic` cannot be generic",
+            "the `extern` function `body` cannot have a body",
+        ]
+    );
+}
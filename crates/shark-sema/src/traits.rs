@@ -0,0 +1,766 @@
+//! Traits and their implementations. A trait lists the methods a type must provide, and an
+//! `impl` provides them for a type. Implementations can be generic, such as
+//! `impl<T :: Show> Show for List<T>`, so whether a type implements a trait is answered by
+//! matching it against the type of every implementation and then checking the bounds of the one
+//! which matched, see [TraitTable::implements]
+//!
+//! Implementations must be coherent, meaning no type is covered by two implementations of the
+//! same trait. Otherwise it would be ambiguous which methods are used
+
+use std::collections::HashMap;
+
+use shark_core::{
+    diagnostic::Diagnostic,
+    source::Span,
+    suggest::closest_match,
+    symbol::{sym, Symbol},
+};
+use shark_parse::ast::{
+    Function, Generics, Ident, ImplDecl, ItemKind, Module, NodeId, Path, TypeExprKind,
+};
+
+use crate::{
+    adt::{duplicate, TypeTable},
+    function::check_declaration,
+    generics::{match_type, unify, FunctionSig, GenericsDef, Predicate, TypeScope},
+    ty::{AdtId, PrimitiveType, Type},
+};
+
+/// How deeply the bounds of implementations are followed before giving up, which stops
+/// implementations whose bounds require themselves from looping forever
+const RECURSION_LIMIT: usize = 32;
+
+/// Identifies a trait within a [TraitTable]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TraitId(pub u32);
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraitDef {
+    pub name: Ident,
+    /// Every method in declaration order. Within their signatures `Self` is a [Type::Param]
+    pub methods: Vec<FunctionSig>,
+}
+
+impl TraitDef {
+    pub fn method(&self, name: Symbol) -> Option<&FunctionSig> {
+        self.methods.iter().find(|x| x.name.symbol == name)
+    }
+}
+
+/// `impl<Generics> Trait for Type where ...`, or `impl<Generics> Type where ...` for an inherent
+/// implementation whose methods belong to the type itself
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImplDef {
+    /// The implemented trait, or [None] if it could not be found or the implementation is inherent
+    pub trait_id: Option<TraitId>,
+    pub is_inherent: bool,
+    pub generics: GenericsDef,
+    pub self_type: Type,
+    /// Every method in declaration order. Within their signatures `Self` is the [ImplDef::self_type]
+    pub methods: Vec<FunctionSig>,
+    /// Where the trait and the type were written
+    pub span: Span,
+}
+
+impl ImplDef {
+    pub fn method(&self, name: Symbol) -> Option<&FunctionSig> {
+        self.methods.iter().find(|x| x.name.symbol == name)
+    }
+}
+
+/// Every trait and implementation declared in a [Module], along with the bounds placed on the
+/// generic parameters of types
+#[derive(Debug, Clone, Default)]
+pub struct TraitTable {
+    traits: Vec<TraitDef>,
+    names: HashMap<Symbol, TraitId>,
+    impls: Vec<ImplDef>,
+    /// The trait or implementation declared by each item
+    trait_items: HashMap<NodeId, TraitId>,
+    impl_items: HashMap<NodeId, usize>,
+    adt_predicates: HashMap<AdtId, Vec<Predicate>>,
+    /// `std::Vec`, whose implementations arrays share since they are kept the same way at runtime
+    vec: Option<AdtId>,
+}
+
+impl TraitTable {
+    /// Collects every trait and implementation of a [Module], checking that the implementations
+    /// provide the methods of their traits and do not overlap
+    pub fn collect(module: &Module, types: &TypeTable) -> (Self, Vec<Diagnostic>) {
+        let mut table = Self {
+            vec: types.lookup(sym::VEC),
+            ..Self::default()
+        };
+        let mut diagnostics = Vec::new();
+
+        // Every trait is declared first so that bounds can refer to traits declared after them
+        let mut declarations = Vec::new();
+        for item in &module.items {
+            let ItemKind::Trait(trait_decl) = &item.kind else {
+                continue;
+            };
+            let name = trait_decl.name;
+            if let Some(previous) = table.names.get(&name.symbol) {
+                let previous = table.traits[previous.0 as usize].name;
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "the trait `{}` is defined multiple times",
+                        name.symbol
+                    ))
+                    .with_primary(name.span, "redefined here")
+                    .with_secondary(previous.span, "previously defined here"),
+                );
+                continue;
+            }
+            if let Some(adt) = types.lookup(name.symbol) {
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "the name `{}` is already used by a type",
+                        name.symbol
+                    ))
+                    .with_primary(name.span, "")
+                    .with_secondary(types.adt(adt).name.span, "the type is defined here"),
+                );
+                continue;
+            }
+
+            let id = TraitId(table.traits.len() as u32);
+            table.names.insert(name.symbol, id);
+            table.trait_items.insert(item.id, id);
+            table.traits.push(TraitDef {
+                name,
+                methods: Vec::new(),
+            });
+            declarations.push((id, trait_decl));
+        }
+
+        for item in &module.items {
+            let (name, generics) = match &item.kind {
+                ItemKind::Type(type_decl) => (type_decl.name, &type_decl.generics),
+                ItemKind::Enum(enum_decl) => (enum_decl.name, &enum_decl.generics),
+                _ => continue,
+            };
+            // Types which were defined multiple times only keep their first definition
+            let Some(id) = types
+                .lookup(name.symbol)
+                .filter(|x| types.adt(*x).name.span == name.span)
+            else {
+                continue;
+            };
+            let scope = TypeScope::default().with_params(&types.adt(id).generics);
+            let predicates = table.lower_predicates(types, generics, &scope, &mut diagnostics);
+            table.adt_predicates.insert(id, predicates);
+        }
+
+        for (id, trait_decl) in declarations {
+            let methods = table.lower_methods(
+                types,
+                &trait_decl.methods,
+                &TypeScope::within_trait(),
+                &mut diagnostics,
+            );
+            for method in methods.iter().filter(|x| x.is_extern || x.is_variadic) {
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "the method `{}` of a trait cannot be `extern`",
+                        method.name.symbol
+                    ))
+                    .with_primary(method.name.span, "")
+                    .with_note("an `extern` method can be declared by an implementation instead"),
+                );
+            }
+            table.traits[id.0 as usize].methods = methods;
+        }
+
+        for item in &module.items {
+            if let ItemKind::Impl(impl_decl) = &item.kind {
+                let implementation = table.lower_impl(types, impl_decl, &mut diagnostics);
+                table.impl_items.insert(item.id, table.impls.len());
+                table.impls.push(implementation);
+            }
+        }
+
+        table.check_coherence(types, &mut diagnostics);
+        (table, diagnostics)
+    }
+
+    pub fn trait_def(&self, id: TraitId) -> &TraitDef {
+        &self.traits[id.0 as usize]
+    }
+
+    pub fn lookup(&self, name: Symbol) -> Option<TraitId> {
+        self.names.get(&name).copied()
+    }
+
+    /// Gets the trait declared by an item, unless it was a duplicate
+    pub fn trait_of_item(&self, item: NodeId) -> Option<TraitId> {
+        self.trait_items.get(&item).copied()
+    }
+
+    /// Gets the implementation declared by an item
+    pub fn impl_of_item(&self, item: NodeId) -> Option<&ImplDef> {
+        self.impl_items.get(&item).map(|x| &self.impls[*x])
+    }
+
+    /// Gets the bounds placed on the generic parameters of a `type` or an `enum`
+    pub fn adt_predicates(&self, id: AdtId) -> &[Predicate] {
+        self.adt_predicates.get(&id).map_or(&[], |x| x.as_slice())
+    }
+
+    /// Gets every trait with a method of the given name which takes `self`
+    pub fn traits_with_method(&self, name: Symbol) -> Vec<TraitId> {
+        (0..self.traits.len())
+            .map(|x| TraitId(x as u32))
+            .filter(|x| self.trait_def(*x).method(name).is_some_and(|x| x.has_self))
+            .collect()
+    }
+
+    /// Finds a method or an associated function declared by an inherent implementation for types
+    /// like `head`. Only the ADT or primitive of `head` is looked at, so its generic arguments
+    /// need not be known yet
+    pub fn inherent_function(&self, head: &Type, name: Symbol) -> Option<(&ImplDef, &FunctionSig)> {
+        self.impls
+            .iter()
+            .filter(|x| x.is_inherent && same_head(&x.self_type, head))
+            .find_map(|x| Some((x, x.method(name)?)))
+    }
+
+    /// Whether the method `name` of types like `head` is `extern`, in any implementation. Backends
+    /// which cannot call into the runtime of an engine use this to explain why they found no body
+    pub fn is_extern_method(&self, head: &Type, name: Symbol) -> bool {
+        self.impls
+            .iter()
+            .filter(|x| same_head(&x.self_type, head))
+            .any(|x| x.method(name).is_some_and(|x| x.is_extern))
+    }
+
+    /// Resolves a path such as `Vec::new` into the associated function of a type. Paths which name
+    /// a variant of an `enum` are left alone
+    pub fn resolve_associated(
+        &self,
+        types: &TypeTable,
+        path: &Path,
+    ) -> Option<(&ImplDef, &FunctionSig)> {
+        let [type_name, name] = path.segments.as_slice() else {
+            return None;
+        };
+        let head = match types.lookup(type_name.symbol) {
+            Some(id) if types.adt(id).variant(name.symbol).is_some() => return None,
+            Some(id) => Type::Adt(id, Vec::new()),
+            None => Type::Primitive(PrimitiveType::from_symbol(type_name.symbol)?),
+        };
+        self.inherent_function(&head, name.symbol)
+    }
+
+    /// Resolves the path of a bound or an implementation into a trait, reporting paths which do
+    /// not name one
+    pub fn resolve_trait(
+        &self,
+        path: &Path,
+        types: &TypeTable,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Option<TraitId> {
+        let name = path.name();
+        if let Some(id) = self
+            .lookup(name.symbol)
+            .filter(|_| path.segments.len() == 1)
+        {
+            return Some(id);
+        }
+        let diagnostic = match types.lookup(name.symbol) {
+            Some(adt) if path.segments.len() == 1 => Diagnostic::error(format!(
+                "expected a trait, found {}",
+                types.adt(adt).describe()
+            ))
+            .with_primary(path.span, "")
+            .with_secondary(types.adt(adt).name.span, "declared here"),
+            _ => {
+                let diagnostic = Diagnostic::error(format!("cannot find trait `{}`", path))
+                    .with_primary(path.span, "not found");
+                let candidates = self.traits.iter().map(|x| x.name.symbol);
+                match closest_match(name.symbol, candidates) {
+                    Some(similar) => diagnostic.with_note(format!("did you mean `{similar}`?")),
+                    None => diagnostic,
+                }
+            }
+        };
+        diagnostics.push(diagnostic);
+        None
+    }
+
+    /// Lowers the generic parameters of an item along with their bounds and its `where` clause.
+    /// The parameters can be named within the bounds on top of whatever the [TypeScope] allows
+    pub fn lower_generics(
+        &self,
+        types: &TypeTable,
+        generics: &Generics,
+        scope: &TypeScope,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> GenericsDef {
+        let mut params: Vec<Ident> = Vec::new();
+        for param in &generics.params {
+            match params.iter().find(|x| x.symbol == param.name.symbol) {
+                Some(previous) => {
+                    diagnostics.push(duplicate("generic parameter", param.name, *previous))
+                }
+                None => params.push(param.name),
+            }
+        }
+        let scope = scope.with_params(&params);
+        GenericsDef {
+            predicates: self.lower_predicates(types, generics, &scope, diagnostics),
+            params,
+        }
+    }
+
+    /// Lowers the bounds of the generic parameters and the `where` clause of an item
+    fn lower_predicates(
+        &self,
+        types: &TypeTable,
+        generics: &Generics,
+        scope: &TypeScope,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Vec<Predicate> {
+        let mut predicates = Vec::new();
+        let mut add = |ty: &Type, bounds: &[Path], diagnostics: &mut Vec<Diagnostic>| {
+            for bound in bounds {
+                if let Some(trait_id) = self.resolve_trait(bound, types, diagnostics) {
+                    predicates.push(Predicate {
+                        ty: ty.clone(),
+                        trait_id,
+                        span: bound.span,
+                    });
+                }
+            }
+        };
+        for param in &generics.params {
+            add(&Type::Param(param.name.symbol), &param.bounds, diagnostics);
+        }
+        for predicate in &generics.where_clause {
+            let ty = types.resolve_type(&predicate.ty, scope, diagnostics);
+            add(&ty, &predicate.bounds, diagnostics);
+        }
+        predicates
+    }
+
+    /// Lowers the signature of a function or a method. The [TypeScope] is that of the trait or
+    /// implementation the method belongs to, if any
+    pub fn lower_signature(
+        &self,
+        types: &TypeTable,
+        function: &Function,
+        scope: &TypeScope,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> FunctionSig {
+        let generics = self.lower_generics(types, &function.generics, scope, diagnostics);
+        let scope = scope.with_params(&generics.params);
+        let parameters = function
+            .parameters
+            .iter()
+            .map(|x| types.resolve_type(&x.ty, &scope, diagnostics))
+            .collect();
+        let return_type = match &function.return_type {
+            Some(ty) => types.resolve_type(ty, &scope, diagnostics),
+            None => Type::Unit,
+        };
+        FunctionSig {
+            name: function.name,
+            generics,
+            parameters,
+            parameter_spans: function.parameters.iter().map(|x| x.ty.span).collect(),
+            return_type,
+            return_span: function.return_type.as_ref().map(|x| x.span),
+            has_self: function
+                .parameters
+                .first()
+                .is_some_and(|x| x.name.symbol == sym::SELF),
+            has_body: function.body.is_some(),
+            is_unsafe: function.is_unsafe || function.abi.is_some(),
+            is_extern: function.is_extern,
+            abi: function.abi,
+            is_variadic: function.is_variadic,
+        }
+    }
+
+    /// Lowers the signatures of the methods of a trait or an implementation, reporting methods
+    /// which are declared more than once
+    fn lower_methods(
+        &self,
+        types: &TypeTable,
+        methods: &[Function],
+        scope: &TypeScope,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> Vec<FunctionSig> {
+        let mut lowered: Vec<FunctionSig> = Vec::new();
+        for method in methods {
+            if let Some(previous) = lowered.iter().find(|x| x.name.symbol == method.name.symbol) {
+                diagnostics.push(duplicate("method", method.name, previous.name));
+            }
+            lowered.push(self.lower_signature(types, method, scope, diagnostics));
+        }
+        lowered
+    }
+
+    fn lower_impl(
+        &self,
+        types: &TypeTable,
+        impl_decl: &ImplDecl,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) -> ImplDef {
+        let trait_id = impl_decl
+            .trait_path
+            .as_ref()
+            .and_then(|x| self.resolve_trait(x, types, diagnostics));
+        let generics = self.lower_generics(
+            types,
+            &impl_decl.generics,
+            &TypeScope::default(),
+            diagnostics,
+        );
+        let mut scope = TypeScope::default().with_params(&generics.params);
+        let self_type = types.resolve_type(&impl_decl.self_type, &scope, diagnostics);
+
+        // Every parameter must be decided by the type, otherwise nothing says what it stands for
+        for param in &generics.params {
+            if !self_type.contains_error() && !self_type.mentions(param.symbol) {
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "the generic parameter `{}` is not used by the implemented type `{}`",
+                        param.symbol,
+                        types.type_name(&self_type)
+                    ))
+                    .with_primary(param.span, "unused generic parameter")
+                    .with_secondary(impl_decl.self_type.span, ""),
+                );
+            }
+        }
+
+        scope.self_type = Some(self_type.clone());
+        let methods = self.lower_methods(types, &impl_decl.methods, &scope, diagnostics);
+        for method in &methods {
+            check_declaration(types, method, "method", diagnostics);
+        }
+        let is_inherent = impl_decl.trait_path.is_none();
+        if is_inherent && !matches!(self_type, Type::Adt(..) | Type::Primitive(_) | Type::Error) {
+            diagnostics.push(
+                Diagnostic::error(format!(
+                    "cannot define inherent methods for `{}`",
+                    types.type_name(&self_type)
+                ))
+                .with_primary(impl_decl.self_type.span, "")
+                .with_note("only a `type`, an `enum` or a primitive type can have its own methods"),
+            );
+        }
+        let implementation = ImplDef {
+            trait_id,
+            is_inherent,
+            generics,
+            self_type,
+            methods,
+            span: impl_decl
+                .trait_path
+                .as_ref()
+                .map_or(impl_decl.self_type.span, |x| x.span)
+                .to(impl_decl.self_type.span),
+        };
+        if let Some(trait_id) = trait_id {
+            self.check_impl_methods(types, trait_id, impl_decl, &implementation, diagnostics);
+        }
+        implementation
+    }
+
+    /// Checks that an implementation provides every method of its trait which has no default, and
+    /// that each method it provides matches the declaration in the trait
+    fn check_impl_methods(
+        &self,
+        types: &TypeTable,
+        trait_id: TraitId,
+        impl_decl: &ImplDecl,
+        implementation: &ImplDef,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let definition = self.trait_def(trait_id);
+        for (method, sig) in impl_decl.methods.iter().zip(&implementation.methods) {
+            let Some(expected) = definition.method(sig.name.symbol) else {
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "`{}` is not a method of the trait `{}`",
+                        sig.name.symbol, definition.name.symbol
+                    ))
+                    .with_primary(sig.name.span, "not a method of the trait")
+                    .with_secondary(definition.name.span, "trait declared here"),
+                );
+                continue;
+            };
+
+            let describe = |count: usize, what: &str| match count {
+                1 => format!("1 {}", what),
+                count => format!("{} {}s", count, what),
+            };
+            let mismatch = |message: String| {
+                Diagnostic::error(format!(
+                    "the method `{}` does not match its declaration in the trait `{}`",
+                    sig.name.symbol, definition.name.symbol
+                ))
+                .with_primary(sig.name.span, message)
+                .with_secondary(expected.name.span, "declared here")
+            };
+            if expected.generics.params.len() != sig.generics.params.len() {
+                diagnostics.push(mismatch(format!(
+                    "expected {}, found {}",
+                    describe(expected.generics.params.len(), "generic parameter"),
+                    sig.generics.params.len()
+                )));
+                continue;
+            }
+            if expected.parameters.len() != sig.parameters.len() {
+                diagnostics.push(mismatch(format!(
+                    "expected {}, found {}",
+                    describe(expected.parameters.len(), "parameter"),
+                    sig.parameters.len()
+                )));
+                continue;
+            }
+
+            // The generic parameters of the two methods are matched up by position
+            let mut mapping: HashMap<Symbol, Type> = expected
+                .generics
+                .params
+                .iter()
+                .zip(&sig.generics.params)
+                .map(|(x, y)| (x.symbol, Type::Param(y.symbol)))
+                .collect();
+            mapping.insert(sym::SELF_TYPE, implementation.self_type.clone());
+
+            let written = method.parameters.iter().map(|x| x.ty.span).chain([method
+                .return_type
+                .as_ref()
+                .map_or(sig.name.span, |x| x.span)]);
+            let pairs = expected
+                .parameters
+                .iter()
+                .zip(&sig.parameters)
+                .chain([(&expected.return_type, &sig.return_type)]);
+            for ((expected_type, found), span) in pairs.zip(written) {
+                let expected_type = expected_type.substitute(&mapping);
+                if expected_type != *found
+                    && !expected_type.contains_error()
+                    && !found.contains_error()
+                {
+                    diagnostics.push(mismatch(String::new()).with_primary(
+                        span,
+                        format!(
+                            "expected `{}`, found `{}`",
+                            types.type_name(&expected_type),
+                            types.type_name(found)
+                        ),
+                    ));
+                    break;
+                }
+            }
+        }
+
+        let missing: Vec<String> = definition
+            .methods
+            .iter()
+            .filter(|x| !x.has_body)
+            .filter(|x| {
+                !implementation
+                    .methods
+                    .iter()
+                    .any(|y| y.name.symbol == x.name.symbol)
+            })
+            .map(|x| format!("`{}`", x.name.symbol))
+            .collect();
+        if !missing.is_empty() {
+            let noun = if missing.len() == 1 {
+                "method"
+            } else {
+                "methods"
+            };
+            let self_type = match &impl_decl.self_type.kind {
+                TypeExprKind::Named { .. }
+                | TypeExprKind::Reference { .. }
+                | TypeExprKind::Generator(_)
+                | TypeExprKind::Function { .. }
+                | TypeExprKind::Array { .. } => types.type_name(&implementation.self_type),
+                TypeExprKind::Error => "{unknown}".to_string(),
+            };
+            diagnostics.push(
+                Diagnostic::error(format!(
+                    "missing {} {} in the implementation of `{}` for `{}`",
+                    noun,
+                    missing.join(", "),
+                    definition.name.symbol,
+                    self_type
+                ))
+                .with_primary(implementation.span, "")
+                .with_secondary(definition.name.span, "trait declared here"),
+            );
+        }
+    }
+
+    /// Reports implementations of the same trait which cover the same type. Only the types are
+    /// compared, two implementations overlap even if their bounds could never both hold
+    fn check_coherence(&self, types: &TypeTable, diagnostics: &mut Vec<Diagnostic>) {
+        for (index, implementation) in self.impls.iter().enumerate() {
+            if implementation.is_inherent {
+                self.check_inherent_coherence(types, index, diagnostics);
+                continue;
+            }
+            let Some(trait_id) = implementation.trait_id else {
+                continue;
+            };
+            let conflict = self.impls[..index].iter().find(|previous| {
+                previous.trait_id == Some(trait_id) && overlap(previous, implementation)
+            });
+            if let Some(previous) = conflict {
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "conflicting implementations of the trait `{}` for `{}`",
+                        self.trait_def(trait_id).name.symbol,
+                        types.type_name(&implementation.self_type)
+                    ))
+                    .with_primary(implementation.span, "conflicting implementation")
+                    .with_secondary(previous.span, "first implementation here"),
+                );
+            }
+        }
+    }
+
+    /// Reports the methods of an inherent implementation which an earlier inherent implementation
+    /// for an overlapping type already declares
+    fn check_inherent_coherence(
+        &self,
+        types: &TypeTable,
+        index: usize,
+        diagnostics: &mut Vec<Diagnostic>,
+    ) {
+        let implementation = &self.impls[index];
+        for method in &implementation.methods {
+            let previous = self.impls[..index]
+                .iter()
+                .filter(|x| x.is_inherent && overlap(x, implementation))
+                .find_map(|x| x.method(method.name.symbol));
+            if let Some(previous) = previous {
+                diagnostics.push(
+                    Diagnostic::error(format!(
+                        "duplicate definitions of the method `{}` for `{}`",
+                        method.name.symbol,
+                        types.type_name(&implementation.self_type)
+                    ))
+                    .with_primary(method.name.span, "duplicate definition")
+                    .with_secondary(previous.name.span, "first definition here"),
+                );
+            }
+        }
+    }
+
+    /// Checks if a type implements a trait. `environment` holds the predicates which are known to
+    /// hold, such as the bounds of the function being checked. Erroneous types implement every
+    /// trait so that they are not reported again
+    pub fn implements(&self, ty: &Type, trait_id: TraitId, environment: &[Predicate]) -> bool {
+        self.implements_at_depth(ty, trait_id, environment, 0)
+    }
+
+    fn implements_at_depth(
+        &self,
+        ty: &Type,
+        trait_id: TraitId,
+        environment: &[Predicate],
+        depth: usize,
+    ) -> bool {
+        if ty.contains_error() {
+            return true;
+        }
+        if depth > RECURSION_LIMIT {
+            return false;
+        }
+        if let (Type::Array(element, _), Some(vec)) = (ty, self.vec) {
+            let ty = Type::Adt(vec, vec![*element.clone()]);
+            return self.implements_at_depth(&ty, trait_id, environment, depth + 1);
+        }
+        if environment
+            .iter()
+            .any(|x| x.trait_id == trait_id && x.ty == *ty)
+        {
+            return true;
+        }
+        self.impls
+            .iter()
+            .filter(|x| x.trait_id == Some(trait_id))
+            .any(|implementation| {
+                let mut bindings = HashMap::new();
+                match_type(&implementation.self_type, ty, &mut bindings)
+                    && implementation.generics.predicates.iter().all(|x| {
+                        self.implements_at_depth(
+                            &x.ty.substitute(&bindings),
+                            x.trait_id,
+                            environment,
+                            depth + 1,
+                        )
+                    })
+            })
+    }
+
+    /// Requires a type to implement a trait, returning the error to report if it does not. `bound`
+    /// is where the requirement was written, if anywhere
+    pub fn require(
+        &self,
+        types: &TypeTable,
+        predicate: &Predicate,
+        environment: &[Predicate],
+        span: Span,
+    ) -> Option<Diagnostic> {
+        if self.implements(&predicate.ty, predicate.trait_id, environment) {
+            return None;
+        }
+        let name = self.trait_def(predicate.trait_id).name.symbol;
+        let mut diagnostic = Diagnostic::error(format!(
+            "the trait `{}` is not implemented for `{}`",
+            name,
+            types.type_name(&predicate.ty)
+        ))
+        .with_primary(span, "")
+        .with_secondary(predicate.span, "required by this bound");
+        if let Type::Param(param) = predicate.ty {
+            diagnostic =
+                diagnostic.with_note(format!("consider adding the bound `{} :: {}`", param, name));
+        }
+        Some(diagnostic)
+    }
+}
+
+/// Checks if two implementations cover a type in common. The parameters of the earlier
+/// implementation are renamed so that both can use the same names without being mixed up
+fn overlap(previous: &ImplDef, implementation: &ImplDef) -> bool {
+    if previous.self_type.contains_error() || implementation.self_type.contains_error() {
+        return false;
+    }
+    let renamed: HashMap<Symbol, Type> = previous
+        .generics
+        .params
+        .iter()
+        .map(|x| {
+            (
+                x.symbol,
+                Type::Param(Symbol::intern(&format!("{}'", x.symbol))),
+            )
+        })
+        .collect();
+    let previous_type = previous.self_type.substitute(&renamed);
+    unify(
+        &previous_type,
+        &implementation.self_type,
+        &mut HashMap::new(),
+    )
+}
+
+/// Checks if two types are the same ADT or primitive, regardless of their generic arguments
+fn same_head(a: &Type, b: &Type) -> bool {
+    match (a, b) {
+        (Type::Adt(a, _), Type::Adt(b, _)) => a == b,
+        (Type::Primitive(a), Type::Primitive(b)) => a == b,
+        _ => false,
+    }
+}
@@ -0,0 +1,237 @@
+use std::{collections::HashMap, fmt::Display};
+
+use shark_core::symbol::Symbol;
+use shark_lex::token::LiteralKind;
+use shark_parse::ast::{Expr, ExprKind, NodeId, ReferenceKind};
+
+/// Identifies an algebraic data type, a `type` or an `enum`, within a [crate::adt::TypeTable]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AdtId(pub u32);
+
+/// The types built into the language
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PrimitiveType {
+    Int8,
+    UInt8,
+    Int32,
+    UInt32,
+    Int64,
+    UInt64,
+    Float32,
+    Float64,
+    Bool,
+    Char,
+    Str,
+}
+
+impl PrimitiveType {
+    pub const ALL: [PrimitiveType; 11] = [
+        Self::Int8,
+        Self::UInt8,
+        Self::Int32,
+        Self::UInt32,
+        Self::Int64,
+        Self::UInt64,
+        Self::Float32,
+        Self::Float64,
+        Self::Bool,
+        Self::Char,
+        Self::Str,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Int8 => "Int8",
+            Self::UInt8 => "UInt8",
+            Self::Int32 => "Int32",
+            Self::UInt32 => "UInt32",
+            Self::Int64 => "Int64",
+            Self::UInt64 => "UInt64",
+            Self::Float32 => "Float32",
+            Self::Float64 => "Float64",
+            Self::Bool => "Bool",
+            Self::Char => "Char",
+            Self::Str => "Str",
+        }
+    }
+
+    /// Gets the [PrimitiveType] a type name refers to
+    pub fn from_symbol(symbol: Symbol) -> Option<Self> {
+        Self::ALL.into_iter().find(|x| x.name() == symbol.as_str())
+    }
+
+    /// Gets the type of a literal exactly as the lexer typed it
+    pub fn of_literal(literal: &LiteralKind) -> Self {
+        match literal {
+            LiteralKind::UInt8(_) => Self::UInt8,
+            LiteralKind::Int8(_) => Self::Int8,
+            LiteralKind::UInt32(_) => Self::UInt32,
+            LiteralKind::Int32(_) => Self::Int32,
+            LiteralKind::UInt64(_) => Self::UInt64,
+            LiteralKind::Int64(_) => Self::Int64,
+            LiteralKind::Float32(_) => Self::Float32,
+            LiteralKind::Float64(_) => Self::Float64,
+            LiteralKind::Str(_) => Self::Str,
+            LiteralKind::Char(_) => Self::Char,
+            LiteralKind::Boolean(_) => Self::Bool,
+        }
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(
+            self,
+            Self::Int8 | Self::UInt8 | Self::Int32 | Self::UInt32 | Self::Int64 | Self::UInt64
+        )
+    }
+
+    pub fn is_float(&self) -> bool {
+        matches!(self, Self::Float32 | Self::Float64)
+    }
+
+    /// Gets the smallest and largest value of an integer or character type
+    pub fn bounds(&self) -> (i128, i128) {
+        match self {
+            Self::Int8 => (i8::MIN.into(), i8::MAX.into()),
+            Self::UInt8 => (0, u8::MAX.into()),
+            Self::Int32 => (i32::MIN.into(), i32::MAX.into()),
+            Self::UInt32 => (0, u32::MAX.into()),
+            Self::Int64 => (i64::MIN.into(), i64::MAX.into()),
+            Self::UInt64 => (0, u64::MAX.into()),
+            Self::Char => (0, u32::from(char::MAX).into()),
+            _ => unreachable!("`{}` is not an integer type", self),
+        }
+    }
+}
+
+impl Display for PrimitiveType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// A type as understood by semantic analysis, with every name resolved
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+    Primitive(PrimitiveType),
+    /// The type of expressions which produce no value, such as a function without a return type
+    Unit,
+    /// A `type` or an `enum` along with its generic arguments, which are empty when it has no
+    /// generic parameters
+    Adt(AdtId, Vec<Type>),
+    /// `ref T`, `ref mut T`, `ptr T` or `ptr mut T`
+    Reference {
+        kind: ReferenceKind,
+        mutable: bool,
+        pointee: Box<Type>,
+    },
+    /// `yield T`, a suspended generator which produces values of type `T` as it is iterated
+    Generator(Box<Type>),
+    /// `fun(A, B) :: R`, a closure along with the values it captured
+    Function {
+        parameters: Vec<Type>,
+        return_type: Box<Type>,
+    },
+    /// `[T; length]`, a fixed number of values of type `T`
+    Array(Box<Type>, Length),
+    /// A generic parameter, or `Self` within a trait
+    Param(Symbol),
+    /// A type which could not be worked out. The error has already been reported so anything
+    /// involving this type is not checked any further
+    Error,
+}
+
+impl Type {
+    /// Replaces every generic parameter which has a mapping with the type it maps to
+    pub fn substitute(&self, mapping: &HashMap<Symbol, Type>) -> Type {
+        match self {
+            Self::Param(name) => mapping.get(name).cloned().unwrap_or(Self::Param(*name)),
+            Self::Adt(id, arguments) => Self::Adt(
+                *id,
+                arguments.iter().map(|x| x.substitute(mapping)).collect(),
+            ),
+            Self::Reference {
+                kind,
+                mutable,
+                pointee,
+            } => Self::Reference {
+                kind: *kind,
+                mutable: *mutable,
+                pointee: Box::new(pointee.substitute(mapping)),
+            },
+            Self::Generator(item) => Self::Generator(Box::new(item.substitute(mapping))),
+            Self::Array(element, length) => {
+                Self::Array(Box::new(element.substitute(mapping)), *length)
+            }
+            Self::Function {
+                parameters,
+                return_type,
+            } => Self::Function {
+                parameters: parameters.iter().map(|x| x.substitute(mapping)).collect(),
+                return_type: Box::new(return_type.substitute(mapping)),
+            },
+            _ => self.clone(),
+        }
+    }
+
+    /// Checks if the type mentions the generic parameter
+    pub fn mentions(&self, param: Symbol) -> bool {
+        match self {
+            Self::Param(name) => *name == param,
+            Self::Adt(_, arguments) => arguments.iter().any(|x| x.mentions(param)),
+            Self::Reference { pointee, .. }
+            | Self::Generator(pointee)
+            | Self::Array(pointee, _) => pointee.mentions(param),
+            Self::Function {
+                parameters,
+                return_type,
+            } => parameters.iter().any(|x| x.mentions(param)) || return_type.mentions(param),
+            _ => false,
+        }
+    }
+
+    /// Checks if any part of the type could not be worked out
+    pub fn contains_error(&self) -> bool {
+        match self {
+            Self::Error => true,
+            Self::Adt(_, arguments) => arguments.iter().any(Self::contains_error),
+            Self::Reference { pointee, .. }
+            | Self::Generator(pointee)
+            | Self::Array(pointee, _) => pointee.contains_error(),
+            Self::Function {
+                parameters,
+                return_type,
+            } => parameters.iter().any(Self::contains_error) || return_type.contains_error(),
+            _ => false,
+        }
+    }
+}
+
+/// How many values an array holds
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Length {
+    /// As many as an array such as `[a, b, c]` lists
+    Count(u64),
+    /// Whatever the expression with this id evaluates to. It is written where the array is, as in
+    /// `[T; length]`, and only evaluated once the module has been checked
+    Const(NodeId),
+}
+
+impl Length {
+    /// Gets the length written as an expression, which is known right away if it is a literal
+    /// such as `3`
+    pub fn of(expr: &Expr) -> Self {
+        match expr.kind {
+            ExprKind::Literal(LiteralKind::Int32(count)) if count >= 0 => Self::Count(count as u64),
+            _ => Self::Const(expr.id),
+        }
+    }
+}
+
+impl Display for Length {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Count(count) => write!(f, "{}", count),
+            Self::Const(_) => write!(f, "_"),
+        }
+    }
+}
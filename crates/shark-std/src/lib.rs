@@ -0,0 +1,192 @@
+//! The standard library of Shark, written in Shark itself in `std.shark`. It is the prelude of
+//! every program, the module `std` whose public items every module can use without importing them,
+//! see [load], and the functions it declares `extern` are provided by the [runtime] of whichever
+//! engine runs the program. Programs compiled to C or x86-64 are linked with [runtime::C_SOURCE],
+//! and WebAssembly modules import the runtime from their host
+//!
+//! Only the items of the standard library a program can reach are kept, the rest are blanked out
+//! so that spans within it still match `std.shark`. This keeps generated code small for programs
+//! which use little of it. Items a program declares under the same names as those of the
+//! standard library shadow them within the program, while the standard library keeps using its
+//! own
+
+use std::{
+    collections::HashSet,
+    path::{Path, PathBuf},
+};
+
+use shark_core::symbol::{sym, Symbol};
+use shark_lex::{token::TokenKind, Lexer};
+use shark_parse::ast::{ItemKind, Module, TypeExprKind};
+use shark_resolve::tree::{ModuleLoader, ModuleTree};
+use shark_sema::ty::PrimitiveType;
+
+pub mod runtime;
+
+#[cfg(test)]
+pub mod tests;
+
+/// The source of the standard library
+pub const SOURCE: &str = include_str!("std.shark");
+
+/// The path the standard library is reported under in diagnostics
+pub const PATH: &str = "<std>/std.shark";
+
+/// The items of the standard library which are always kept, since the runtime relies on them
+const ALWAYS_REACHABLE: [&str; 1] = ["Show"];
+
+/// Loads a program along with the modules it uses, see [ModuleTree::load], then adds the parts of
+/// the standard library they can reach as the prelude of the tree
+pub fn load(root: Option<&Path>, source: &str, loader: &mut dyn ModuleLoader) -> ModuleTree {
+    let mut tree = ModuleTree::load(root, source, loader);
+    let sources: Vec<&str> = tree.modules().map(|(_, x)| x.source.as_str()).collect();
+    let library = prelude(&sources);
+    tree.add_prelude(vec![sym::STD], Some(PathBuf::from(PATH)), library);
+    tree
+}
+
+/// Gets the text of the standard library with every item the programs can not reach blanked out,
+/// which is as long as [SOURCE]
+pub fn prelude(programs: &[&str]) -> String {
+    let library = Library::parse();
+    let mentioned = programs.iter().flat_map(|x| scan(x)).collect();
+    let kept = library.reachable(mentioned);
+
+    let mut source = String::with_capacity(SOURCE.len());
+    let mut cursor = 0;
+    for (index, item) in library.items.iter().enumerate() {
+        if kept.contains(&index) {
+            blank(&mut source, &SOURCE[cursor..item.start]);
+            source.push_str(&SOURCE[item.start..item.end]);
+            cursor = item.end;
+        }
+    }
+    blank(&mut source, &SOURCE[cursor..]);
+    source
+}
+
+/// Appends text with everything but its new lines replaced by spaces, keeping both its length and
+/// its lines
+fn blank(output: &mut String, text: &str) {
+    output.extend(text.chars().map(|x| match x {
+        '\n' | '\r' => x,
+        _ => ' ',
+    }));
+    // Characters longer than a byte become one space each, so they are made up for
+    let missing = text.len() - text.chars().count();
+    output.extend(std::iter::repeat_n(' ', missing));
+}
+
+/// Finds the names a program mentions. Only tokens are looked at, so this works for programs
+/// which do not parse. An array is a `Vec` at runtime, built through `Vec::new` and `push`, so a
+/// `[` mentions those
+fn scan(program: &str) -> HashSet<Symbol> {
+    let mut lexer = Lexer::new(None, program);
+    lexer.lex();
+    lexer
+        .completed_tokens
+        .iter()
+        .flat_map(|x| match x.kind {
+            TokenKind::Identifier(symbol) => vec![symbol],
+            TokenKind::SquareBracket { opened: true } => ["Vec", "new", "push"]
+                .into_iter()
+                .map(Symbol::intern)
+                .collect(),
+            _ => Vec::new(),
+        })
+        .collect()
+}
+
+/// An item of the standard library, along with what it needs
+struct LibraryItem {
+    start: usize,
+    end: usize,
+    /// The name the item declares, which is [None] for an implementation
+    name: Option<Symbol>,
+    /// For an implementation, the names of its type and of its trait if it has one, which must
+    /// both be kept for it to be kept
+    requires: Vec<Symbol>,
+    /// For an inherent implementation, the names of its methods, one of which must be mentioned
+    /// for it to be kept
+    methods: Vec<Symbol>,
+    /// Every name the item mentions
+    mentions: Vec<Symbol>,
+}
+
+struct Library {
+    items: Vec<LibraryItem>,
+}
+
+impl Library {
+    fn parse() -> Self {
+        let module: Module = shark_parse::parse(None, SOURCE).expect("the standard library parses");
+        let items = module
+            .items
+            .iter()
+            .map(|item| {
+                let requires = match &item.kind {
+                    ItemKind::Impl(impl_decl) => {
+                        let self_type = match &impl_decl.self_type.kind {
+                            TypeExprKind::Named { name, .. } => Some(name.symbol),
+                            _ => None,
+                        };
+                        let trait_name = impl_decl.trait_path.as_ref().map(|x| x.name().symbol);
+                        self_type.into_iter().chain(trait_name).collect()
+                    }
+                    _ => Vec::new(),
+                };
+                let methods = match &item.kind {
+                    ItemKind::Impl(impl_decl) if impl_decl.trait_path.is_none() => {
+                        impl_decl.methods.iter().map(|x| x.name.symbol).collect()
+                    }
+                    _ => Vec::new(),
+                };
+                let mentions = scan(&SOURCE[item.span.start..item.span.end]);
+                LibraryItem {
+                    start: item.span.start,
+                    end: item.span.end,
+                    name: item.kind.name().map(|x| x.symbol),
+                    requires,
+                    methods,
+                    mentions: mentions.into_iter().collect(),
+                }
+            })
+            .collect();
+        Self { items }
+    }
+
+    /// Gets the index of every item which the names can reach, directly or through other items
+    fn reachable(&self, mut names: HashSet<Symbol>) -> HashSet<usize> {
+        names.extend(ALWAYS_REACHABLE.map(Symbol::intern));
+        let mut kept: HashSet<usize> = HashSet::new();
+        // The names of the kept items, along with the primitive types which are always there
+        let mut available: HashSet<Symbol> = PrimitiveType::ALL
+            .iter()
+            .map(|x| Symbol::intern(x.name()))
+            .collect();
+        loop {
+            let before = kept.len();
+            for (index, item) in self.items.iter().enumerate() {
+                if kept.contains(&index) {
+                    continue;
+                }
+                let keep = match item.name {
+                    Some(name) => names.contains(&name),
+                    None => {
+                        item.requires.iter().all(|x| available.contains(x))
+                            && (item.methods.is_empty()
+                                || item.methods.iter().any(|x| names.contains(x)))
+                    }
+                };
+                if keep {
+                    kept.insert(index);
+                    available.extend(item.name);
+                    names.extend(item.mentions.iter().copied());
+                }
+            }
+            if kept.len() == before {
+                return kept;
+            }
+        }
+    }
+}
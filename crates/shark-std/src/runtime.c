@@ -0,0 +1,667 @@
+/* The runtime of programs compiled to C or to x86-64, providing the functions the IR calls under
+ * the names and with the signatures `shark_ir::runtime` gives them. They behave like the runtime
+ * of the interpreter, so a compiled program writes out the same text
+ *
+ * A `Str` is the address of its bytes followed by their number, and a `Vec` is the address of its
+ * items followed by how many there are and how many fit. Memory is never freed */
+
+#include <errno.h>
+#include <locale.h>
+#include <math.h>
+#include <stdbool.h>
+#include <stdint.h>
+#include <stdio.h>
+#include <stdlib.h>
+#include <string.h>
+#include <wctype.h>
+
+typedef struct ShStr {
+    uint8_t *bytes;
+    int64_t length;
+} ShStr;
+
+typedef struct ShVec {
+    uint8_t *items;
+    int64_t length;
+    int64_t capacity;
+} ShVec;
+
+static int shark_argc = 0;
+static char **shark_argv = NULL;
+
+/* Keeps the arguments of the program for `shark_args`, and lets changing the case of letters work
+ * beyond ASCII where the system knows UTF-8 */
+void shark_start(int argc, char **argv) {
+    shark_argc = argc;
+    shark_argv = argv;
+    if (setlocale(LC_CTYPE, "C.UTF-8") == NULL) setlocale(LC_CTYPE, "en_US.UTF-8");
+}
+
+/* Stops the program with a runtime error, whose message is the text followed by the `Str` at the
+ * address if there is one, reporting where it happened */
+void shark_panic(const char *message, const void *text, const char *at) {
+    fflush(stdout);
+    fprintf(stderr, "error: %s", message);
+    if (text != NULL) {
+        const ShStr *str = text;
+        fwrite(str->bytes, 1, (size_t)str->length, stderr);
+    }
+    fprintf(stderr, "\n --> %s\n", at);
+    exit(101);
+}
+
+void *shark_alloc(int64_t size) {
+    void *memory = calloc(1, size > 0 ? (size_t)size : 1);
+    if (memory == NULL) shark_panic("out of memory", NULL, "<runtime>");
+    return memory;
+}
+
+static ShStr shark_str_of(const uint8_t *bytes, int64_t length) {
+    uint8_t *copy = shark_alloc(length + 1);
+    if (length > 0) memcpy(copy, bytes, (size_t)length);
+    return (ShStr){copy, length};
+}
+
+static void shark_store_text(void *out, const char *text) {
+    *(ShStr *)out = shark_str_of((const uint8_t *)text, (int64_t)strlen(text));
+}
+
+/* Decodes the character starting at a byte of valid UTF-8, giving how many bytes it takes */
+static int shark_decode(const uint8_t *bytes, uint32_t *character) {
+    if (bytes[0] < 0x80) {
+        *character = bytes[0];
+        return 1;
+    }
+    if (bytes[0] < 0xE0) {
+        *character = ((uint32_t)(bytes[0] & 0x1F) << 6) | (bytes[1] & 0x3F);
+        return 2;
+    }
+    if (bytes[0] < 0xF0) {
+        *character = ((uint32_t)(bytes[0] & 0x0F) << 12) | ((uint32_t)(bytes[1] & 0x3F) << 6) |
+                     (bytes[2] & 0x3F);
+        return 3;
+    }
+    *character = ((uint32_t)(bytes[0] & 0x07) << 18) | ((uint32_t)(bytes[1] & 0x3F) << 12) |
+                 ((uint32_t)(bytes[2] & 0x3F) << 6) | (bytes[3] & 0x3F);
+    return 4;
+}
+
+static int shark_encode(uint32_t character, uint8_t *bytes) {
+    if (character < 0x80) {
+        bytes[0] = (uint8_t)character;
+        return 1;
+    }
+    if (character < 0x800) {
+        bytes[0] = (uint8_t)(0xC0 | (character >> 6));
+        bytes[1] = (uint8_t)(0x80 | (character & 0x3F));
+        return 2;
+    }
+    if (character < 0x10000) {
+        bytes[0] = (uint8_t)(0xE0 | (character >> 12));
+        bytes[1] = (uint8_t)(0x80 | ((character >> 6) & 0x3F));
+        bytes[2] = (uint8_t)(0x80 | (character & 0x3F));
+        return 3;
+    }
+    bytes[0] = (uint8_t)(0xF0 | (character >> 18));
+    bytes[1] = (uint8_t)(0x80 | ((character >> 12) & 0x3F));
+    bytes[2] = (uint8_t)(0x80 | ((character >> 6) & 0x3F));
+    bytes[3] = (uint8_t)(0x80 | (character & 0x3F));
+    return 4;
+}
+
+/* Whether a byte starts a character rather than continuing one */
+static bool shark_starts_character(uint8_t byte) {
+    return (byte & 0xC0) != 0x80;
+}
+
+static int64_t shark_count_characters(const uint8_t *bytes, int64_t length) {
+    int64_t count = 0;
+    for (int64_t index = 0; index < length; index++) count += shark_starts_character(bytes[index]);
+    return count;
+}
+
+/* Whether a character is white space the way Unicode defines it, which is what `trim` removes */
+static bool shark_is_white_space(uint32_t character) {
+    switch (character) {
+    case 0x09: case 0x0A: case 0x0B: case 0x0C: case 0x0D: case 0x20: case 0x85: case 0xA0:
+    case 0x1680: case 0x2028: case 0x2029: case 0x202F: case 0x205F: case 0x3000:
+        return true;
+    default:
+        return character >= 0x2000 && character <= 0x200A;
+    }
+}
+
+/* A growing buffer of bytes, which becomes a `Str` once it is done */
+typedef struct ShBuffer {
+    uint8_t *bytes;
+    int64_t length;
+    int64_t capacity;
+} ShBuffer;
+
+static void shark_buffer_push(ShBuffer *buffer, const uint8_t *bytes, int64_t length) {
+    if (buffer->length + length + 1 > buffer->capacity) {
+        int64_t capacity = buffer->capacity * 2 + length + 16;
+        uint8_t *grown = shark_alloc(capacity);
+        if (buffer->length > 0) memcpy(grown, buffer->bytes, (size_t)buffer->length);
+        buffer->bytes = grown;
+        buffer->capacity = capacity;
+    }
+    if (length > 0) memcpy(buffer->bytes + buffer->length, bytes, (size_t)length);
+    buffer->length += length;
+}
+
+static void shark_buffer_finish(ShBuffer *buffer, void *out) {
+    if (buffer->bytes == NULL) shark_buffer_push(buffer, NULL, 0);
+    *(ShStr *)out = (ShStr){buffer->bytes, buffer->length};
+}
+
+bool shark_write(int32_t stream, void *text) {
+    const ShStr *str = text;
+    FILE *file = stream == 1 ? stdout : stderr;
+    size_t written = fwrite(str->bytes, 1, (size_t)str->length, file);
+    return written == (size_t)str->length && fflush(file) == 0;
+}
+
+void shark_exit(int32_t code) {
+    fflush(stdout);
+    exit(code);
+}
+
+/* Makes room for one more item of a number of bytes at the end of a `Vec` */
+static void shark_vec_reserve(ShVec *vec, int64_t size) {
+    if (vec->length < vec->capacity) return;
+    int64_t capacity = vec->capacity == 0 ? 4 : vec->capacity * 2;
+    uint8_t *items = shark_alloc(capacity * size);
+    if (vec->length > 0 && size > 0) memcpy(items, vec->items, (size_t)(vec->length * size));
+    vec->items = items;
+    vec->capacity = capacity;
+}
+
+void shark_vec_push(void *vec, void *item, int64_t size) {
+    ShVec *items = vec;
+    shark_vec_reserve(items, size);
+    if (size > 0) memcpy(items->items + items->length * size, item, (size_t)size);
+    items->length++;
+}
+
+void shark_vec_insert(void *vec, int64_t index, void *item, int64_t size) {
+    ShVec *items = vec;
+    shark_vec_reserve(items, size);
+    if (size > 0) {
+        uint8_t *at = items->items + index * size;
+        memmove(at + size, at, (size_t)((items->length - index) * size));
+        memcpy(at, item, (size_t)size);
+    }
+    items->length++;
+}
+
+void shark_vec_remove(void *vec, int64_t index, void *out, int64_t size) {
+    ShVec *items = vec;
+    if (size > 0) {
+        uint8_t *at = items->items + index * size;
+        memcpy(out, at, (size_t)size);
+        memmove(at, at + size, (size_t)((items->length - index - 1) * size));
+    }
+    items->length--;
+}
+
+void shark_vec_copy(void *out, void *vec, int64_t size) {
+    const ShVec *items = vec;
+    ShVec copy = {NULL, items->length, items->length};
+    copy.items = shark_alloc(items->length * size);
+    if (items->length > 0 && size > 0) {
+        memcpy(copy.items, items->items, (size_t)(items->length * size));
+    }
+    *(ShVec *)out = copy;
+}
+
+void shark_args(void *out) {
+    ShVec args = {NULL, 0, 0};
+    for (int index = 1; index < shark_argc; index++) {
+        ShStr arg = shark_str_of((const uint8_t *)shark_argv[index], strlen(shark_argv[index]));
+        shark_vec_push(&args, &arg, sizeof(ShStr));
+    }
+    *(ShVec *)out = args;
+}
+
+/* Copies a `Str` for C, which expects its bytes to be followed by a zero */
+void *shark_cstr(void *text) {
+    const ShStr *str = text;
+    return shark_str_of(str->bytes, str->length).bytes;
+}
+
+/* Stores a copy of a string returned by C, where a null pointer is the empty string */
+void shark_str_from_cstr(void *out, void *text) {
+    shark_store_text(out, text == NULL ? "" : (const char *)text);
+}
+
+/* Stores why a file could not be used, the way the interpreter words it */
+static void shark_store_error(void *out, int error) {
+    char text[256];
+    snprintf(text, sizeof(text), "%s (os error %d)", strerror(error), error);
+    shark_store_text(out, text);
+}
+
+bool shark_read_file(void *out, void *path) {
+    FILE *file = fopen(shark_cstr(path), "rb");
+    if (file == NULL) {
+        shark_store_error(out, errno);
+        return false;
+    }
+    ShBuffer buffer = {NULL, 0, 0};
+    uint8_t chunk[4096];
+    size_t read;
+    while ((read = fread(chunk, 1, sizeof(chunk), file)) > 0) {
+        shark_buffer_push(&buffer, chunk, (int64_t)read);
+    }
+    int error = ferror(file) ? errno : 0;
+    fclose(file);
+    if (error != 0) {
+        shark_store_error(out, error);
+        return false;
+    }
+    shark_buffer_finish(&buffer, out);
+    return true;
+}
+
+bool shark_write_file(void *out, void *path, void *contents) {
+    const ShStr *str = contents;
+    FILE *file = fopen(shark_cstr(path), "wb");
+    if (file == NULL) {
+        shark_store_error(out, errno);
+        return false;
+    }
+    size_t written = fwrite(str->bytes, 1, (size_t)str->length, file);
+    int error = written != (size_t)str->length ? errno : 0;
+    if (fclose(file) != 0 && error == 0) error = errno;
+    if (error != 0) {
+        shark_store_error(out, error);
+        return false;
+    }
+    return true;
+}
+
+void shark_show_int(void *out, int64_t value) {
+    char text[32];
+    snprintf(text, sizeof(text), "%lld", (long long)value);
+    shark_store_text(out, text);
+}
+
+void shark_show_uint(void *out, uint64_t value) {
+    char text[32];
+    snprintf(text, sizeof(text), "%llu", (unsigned long long)value);
+    shark_store_text(out, text);
+}
+
+/* Writes out a float the way the interpreter does: with the fewest digits which read back as the
+ * same float, in decimal unless it is very large or very small */
+static void shark_show_float(void *out, double value, bool single) {
+    if (value != value || value == INFINITY || value == -INFINITY) {
+        shark_store_text(out, value != value ? "NaN" : value > 0 ? "inf" : "-inf");
+        return;
+    }
+    char scientific[64];
+    for (int precision = 1; precision <= 17; precision++) {
+        snprintf(scientific, sizeof(scientific), "%.*e", precision - 1, value);
+        bool same = single ? strtof(scientific, NULL) == (float)value
+                           : strtod(scientific, NULL) == value;
+        if (same) break;
+    }
+    char digits[32];
+    int count = 0;
+    char *cursor = scientific;
+    for (; *cursor != 'e'; cursor++) {
+        if (*cursor >= '0' && *cursor <= '9') digits[count++] = *cursor;
+    }
+    int exponent = atoi(cursor + 1);
+    while (count > 1 && digits[count - 1] == '0') count--;
+    char text[400];
+    int length = 0;
+    if (signbit(value)) text[length++] = '-';
+    double magnitude = fabs(value);
+    if (magnitude == 0 || (magnitude >= 1e-4 && magnitude < 1e16)) {
+        int point = exponent + 1;
+        if (point <= 0) {
+            text[length++] = '0';
+            text[length++] = '.';
+            for (int index = 0; index < -point; index++) text[length++] = '0';
+            for (int index = 0; index < count; index++) text[length++] = digits[index];
+        } else {
+            for (int index = 0; index < point; index++) {
+                text[length++] = index < count ? digits[index] : '0';
+            }
+            text[length++] = '.';
+            if (point >= count) text[length++] = '0';
+            for (int index = point; index < count; index++) text[length++] = digits[index];
+        }
+        text[length] = 0;
+    } else {
+        text[length++] = digits[0];
+        if (count > 1) {
+            text[length++] = '.';
+            for (int index = 1; index < count; index++) text[length++] = digits[index];
+        }
+        snprintf(text + length, sizeof(text) - length, "e%d", exponent);
+    }
+    shark_store_text(out, text);
+}
+
+void shark_show_float32(void *out, float value) {
+    shark_show_float(out, value, true);
+}
+
+void shark_show_float64(void *out, double value) {
+    shark_show_float(out, value, false);
+}
+
+void shark_show_char(void *out, uint32_t value) {
+    uint8_t bytes[4];
+    *(ShStr *)out = shark_str_of(bytes, shark_encode(value, bytes));
+}
+
+/* Goes through a template, filling it in with the parts into a buffer if there is one, and gives
+ * the number of its placeholders or -1 if it is invalid */
+static int64_t shark_template(const ShStr *template, ShBuffer *buffer, const ShStr *parts) {
+    int64_t placeholders = 0;
+    const uint8_t *bytes = template->bytes;
+    for (int64_t index = 0; index < template->length; index++) {
+        uint8_t byte = bytes[index];
+        bool last = index + 1 == template->length;
+        if ((byte == '{' || byte == '}') && !last && bytes[index + 1] == byte) {
+            if (buffer != NULL) shark_buffer_push(buffer, &byte, 1);
+            index++;
+        } else if (byte == '{' && !last && bytes[index + 1] == '}') {
+            if (buffer != NULL) {
+                shark_buffer_push(buffer, parts[placeholders].bytes, parts[placeholders].length);
+            }
+            placeholders++;
+            index++;
+        } else if (byte == '{' || byte == '}') {
+            return -1;
+        } else if (buffer != NULL) {
+            shark_buffer_push(buffer, &byte, 1);
+        }
+    }
+    return placeholders;
+}
+
+int64_t shark_placeholders(void *template) {
+    return shark_template(template, NULL, NULL);
+}
+
+/* Fills in a template, whose placeholders have been counted to be as many as the parts */
+void shark_format(void *out, void *template, void *parts, int64_t count) {
+    (void)count;
+    ShBuffer buffer = {NULL, 0, 0};
+    shark_template(template, &buffer, parts);
+    shark_buffer_finish(&buffer, out);
+}
+
+int32_t shark_str_compare(void *left, void *right) {
+    const ShStr *a = left, *b = right;
+    int64_t shortest = a->length < b->length ? a->length : b->length;
+    int result = shortest > 0 ? memcmp(a->bytes, b->bytes, (size_t)shortest) : 0;
+    if (result != 0) return result < 0 ? -1 : 1;
+    return a->length < b->length ? -1 : a->length > b->length;
+}
+
+int64_t shark_str_len(void *text) {
+    const ShStr *str = text;
+    return shark_count_characters(str->bytes, str->length);
+}
+
+void shark_str_concat(void *out, void *left, void *right) {
+    const ShStr a = *(ShStr *)left, b = *(ShStr *)right;
+    ShBuffer buffer = {NULL, 0, 0};
+    shark_buffer_push(&buffer, a.bytes, a.length);
+    shark_buffer_push(&buffer, b.bytes, b.length);
+    shark_buffer_finish(&buffer, out);
+}
+
+/* Finds the byte the first match of a pattern starts at from a byte on, or -1 */
+static int64_t shark_find_bytes(const ShStr *haystack, const ShStr *pattern, int64_t from) {
+    for (int64_t index = from; index + pattern->length <= haystack->length; index++) {
+        if (pattern->length == 0 ||
+            memcmp(haystack->bytes + index, pattern->bytes, (size_t)pattern->length) == 0) {
+            return index;
+        }
+    }
+    return -1;
+}
+
+int64_t shark_str_find(void *haystack, void *pattern) {
+    const ShStr *str = haystack;
+    int64_t found = shark_find_bytes(str, pattern, 0);
+    return found < 0 ? -1 : shark_count_characters(str->bytes, found);
+}
+
+bool shark_str_starts_with(void *text, void *prefix) {
+    const ShStr *a = text, *b = prefix;
+    return b->length <= a->length &&
+           (b->length == 0 || memcmp(a->bytes, b->bytes, (size_t)b->length) == 0);
+}
+
+bool shark_str_ends_with(void *text, void *suffix) {
+    const ShStr *a = text, *b = suffix;
+    return b->length <= a->length &&
+           (b->length == 0 ||
+            memcmp(a->bytes + a->length - b->length, b->bytes, (size_t)b->length) == 0);
+}
+
+/* Gets the byte a character starts at, which is the length of the string past its end */
+static int64_t shark_character_start(const ShStr *str, int64_t character) {
+    int64_t index = 0;
+    for (; index < str->length; index++) {
+        if (shark_starts_character(str->bytes[index]) && character-- == 0) return index;
+    }
+    return index;
+}
+
+bool shark_str_slice(void *out, void *text, int64_t start, int64_t end) {
+    const ShStr *str = text;
+    int64_t length = shark_count_characters(str->bytes, str->length);
+    if (start < 0 || end < start || end > length) return false;
+    int64_t first = shark_character_start(str, start);
+    int64_t last = shark_character_start(str, end);
+    *(ShStr *)out = shark_str_of(str->bytes + first, last - first);
+    return true;
+}
+
+bool shark_str_char_at(void *out, void *text, int64_t index) {
+    const ShStr *str = text;
+    if (index < 0) return false;
+    int64_t start = shark_character_start(str, index);
+    if (start >= str->length) return false;
+    shark_decode(str->bytes + start, out);
+    return true;
+}
+
+void shark_str_trim(void *out, void *text) {
+    const ShStr *str = text;
+    int64_t start = 0, end = str->length;
+    while (start < end) {
+        uint32_t character;
+        int size = shark_decode(str->bytes + start, &character);
+        if (!shark_is_white_space(character)) break;
+        start += size;
+    }
+    while (end > start) {
+        int64_t last = end - 1;
+        while (!shark_starts_character(str->bytes[last])) last--;
+        uint32_t character;
+        shark_decode(str->bytes + last, &character);
+        if (!shark_is_white_space(character)) break;
+        end = last;
+    }
+    *(ShStr *)out = shark_str_of(str->bytes + start, end - start);
+}
+
+/* Changes the case of every letter of a string */
+static void shark_change_case(void *out, const ShStr *str, bool upper) {
+    ShBuffer buffer = {NULL, 0, 0};
+    for (int64_t index = 0; index < str->length;) {
+        uint32_t character;
+        index += shark_decode(str->bytes + index, &character);
+        character = (uint32_t)(upper ? towupper((wint_t)character) : towlower((wint_t)character));
+        uint8_t bytes[4];
+        shark_buffer_push(&buffer, bytes, shark_encode(character, bytes));
+    }
+    shark_buffer_finish(&buffer, out);
+}
+
+void shark_str_to_upper(void *out, void *text) {
+    shark_change_case(out, text, true);
+}
+
+void shark_str_to_lower(void *out, void *text) {
+    shark_change_case(out, text, false);
+}
+
+bool shark_str_repeat(void *out, void *text, int64_t count) {
+    const ShStr str = *(ShStr *)text;
+    if (count < 0) return false;
+    ShBuffer buffer = {NULL, 0, 0};
+    for (int64_t index = 0; index < count; index++) {
+        shark_buffer_push(&buffer, str.bytes, str.length);
+    }
+    shark_buffer_finish(&buffer, out);
+    return true;
+}
+
+void shark_str_replace(void *out, void *text, void *from, void *to) {
+    const ShStr str = *(ShStr *)text, pattern = *(ShStr *)from, replacement = *(ShStr *)to;
+    ShBuffer buffer = {NULL, 0, 0};
+    if (pattern.length == 0) {
+        /* An empty pattern matches before every character and at the end */
+        shark_buffer_push(&buffer, replacement.bytes, replacement.length);
+        for (int64_t index = 0; index < str.length;) {
+            int64_t next = index + 1;
+            while (next < str.length && !shark_starts_character(str.bytes[next])) next++;
+            shark_buffer_push(&buffer, str.bytes + index, next - index);
+            shark_buffer_push(&buffer, replacement.bytes, replacement.length);
+            index = next;
+        }
+        shark_buffer_finish(&buffer, out);
+        return;
+    }
+    int64_t index = 0, found;
+    while ((found = shark_find_bytes(&str, &pattern, index)) >= 0) {
+        shark_buffer_push(&buffer, str.bytes + index, found - index);
+        shark_buffer_push(&buffer, replacement.bytes, replacement.length);
+        index = found + pattern.length;
+    }
+    shark_buffer_push(&buffer, str.bytes + index, str.length - index);
+    shark_buffer_finish(&buffer, out);
+}
+
+static void shark_push_part(ShVec *parts, const uint8_t *bytes, int64_t length) {
+    ShStr part = shark_str_of(bytes, length);
+    shark_vec_push(parts, &part, sizeof(ShStr));
+}
+
+void shark_str_split(void *out, void *text, void *separator) {
+    const ShStr str = *(ShStr *)text, pattern = *(ShStr *)separator;
+    ShVec parts = {NULL, 0, 0};
+    if (pattern.length == 0) {
+        for (int64_t index = 0; index < str.length;) {
+            int64_t next = index + 1;
+            while (next < str.length && !shark_starts_character(str.bytes[next])) next++;
+            shark_push_part(&parts, str.bytes + index, next - index);
+            index = next;
+        }
+    } else {
+        int64_t index = 0, found;
+        while ((found = shark_find_bytes(&str, &pattern, index)) >= 0) {
+            shark_push_part(&parts, str.bytes + index, found - index);
+            index = found + pattern.length;
+        }
+        shark_push_part(&parts, str.bytes + index, str.length - index);
+    }
+    *(ShVec *)out = parts;
+}
+
+void shark_str_lines(void *out, void *text) {
+    const ShStr str = *(ShStr *)text;
+    ShVec lines = {NULL, 0, 0};
+    int64_t start = 0;
+    while (start < str.length) {
+        int64_t end = start;
+        while (end < str.length && str.bytes[end] != '\n') end++;
+        int64_t length = end - start;
+        if (end < str.length && length > 0 && str.bytes[end - 1] == '\r') length--;
+        shark_push_part(&lines, str.bytes + start, length);
+        start = end + 1;
+    }
+    *(ShVec *)out = lines;
+}
+
+void shark_str_chars(void *out, void *text) {
+    const ShStr str = *(ShStr *)text;
+    ShVec characters = {NULL, 0, 0};
+    for (int64_t index = 0; index < str.length;) {
+        uint32_t character;
+        index += shark_decode(str.bytes + index, &character);
+        shark_vec_push(&characters, &character, sizeof(character));
+    }
+    *(ShVec *)out = characters;
+}
+
+/* Gets the text of a string once trimmed, followed by a zero */
+static char *shark_trimmed(void *text) {
+    ShStr trimmed;
+    shark_str_trim(&trimmed, text);
+    return (char *)trimmed.bytes;
+}
+
+bool shark_str_parse_int(void *out, void *text) {
+    const char *digits = shark_trimmed(text);
+    bool negative = *digits == '-';
+    if (*digits == '-' || *digits == '+') digits++;
+    if (*digits == 0) return false;
+    uint64_t limit = negative ? (uint64_t)INT64_MAX + 1 : (uint64_t)INT64_MAX;
+    uint64_t value = 0;
+    for (; *digits != 0; digits++) {
+        if (*digits < '0' || *digits > '9') return false;
+        uint64_t digit = (uint64_t)(*digits - '0');
+        if (value > (limit - digit) / 10) return false;
+        value = value * 10 + digit;
+    }
+    *(int64_t *)out = negative ? (int64_t)(0 - value) : (int64_t)value;
+    return true;
+}
+
+/* Whether a string starts with a word, ignoring case, giving what follows it */
+static const char *shark_skip_word(const char *text, const char *word) {
+    for (; *word != 0; text++, word++) {
+        if (*text == 0 || (*text | 0x20) != *word) return NULL;
+    }
+    return text;
+}
+
+bool shark_str_parse_float(void *out, void *text) {
+    const char *number = shark_trimmed(text);
+    const char *cursor = number;
+    if (*cursor == '-' || *cursor == '+') cursor++;
+    const char *rest = shark_skip_word(cursor, "infinity");
+    if (rest == NULL) rest = shark_skip_word(cursor, "inf");
+    if (rest == NULL) rest = shark_skip_word(cursor, "nan");
+    if (rest == NULL) {
+        /* Only decimal numbers are read, which `strtod` goes beyond */
+        int digits = 0;
+        for (; *cursor >= '0' && *cursor <= '9'; cursor++) digits++;
+        if (*cursor == '.') {
+            for (cursor++; *cursor >= '0' && *cursor <= '9'; cursor++) digits++;
+        }
+        if (digits == 0) return false;
+        if (*cursor == 'e' || *cursor == 'E') {
+            cursor++;
+            if (*cursor == '-' || *cursor == '+') cursor++;
+            if (*cursor < '0' || *cursor > '9') return false;
+            while (*cursor >= '0' && *cursor <= '9') cursor++;
+        }
+        rest = cursor;
+    }
+    if (*rest != 0) return false;
+    *(double *)out = strtod(number, NULL);
+    return true;
+}
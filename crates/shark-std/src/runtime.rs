@@ -0,0 +1,710 @@
+//! The runtime behind the `extern` functions of the standard library. Every engine which runs
+//! programs, the interpreter and the VM, provides the values it works with through a [Machine],
+//! and [call] carries out a [Builtin] on top of it, so the builtins behave the same everywhere
+//!
+//! A program talks to the outside world through its [Host], which holds the arguments it was run
+//! with and where its output goes
+
+use std::{
+    cmp::Ordering,
+    io::{self, Write},
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use shark_sema::{
+    format::{fill_template, split_template},
+    ty::PrimitiveType,
+};
+
+/// The runtime of the backends compiling programs to C and to x86-64, in C. It provides the
+/// functions of `shark_ir::runtime` along with `shark_start`, which `main` calls with its arguments
+/// first, and `shark_panic`, which stops the program with a runtime error
+pub const C_SOURCE: &str = include_str!("runtime.c");
+
+/// What a program was run with and where its output goes
+pub struct Host {
+    /// The arguments given to the program, not including the program itself
+    pub args: Vec<String>,
+    pub stdout: Box<dyn Write + Send>,
+    pub stderr: Box<dyn Write + Send>,
+}
+
+impl Host {
+    /// A host whose output goes to the standard output and error of the process
+    pub fn new(args: Vec<String>) -> Self {
+        Self {
+            args,
+            stdout: Box::new(io::stdout()),
+            stderr: Box::new(io::stderr()),
+        }
+    }
+
+    /// A host whose output is kept in memory, so it can be looked at once the program is done.
+    /// The standard output and error are kept together, in the order they were written
+    pub fn captured(args: Vec<String>) -> (Self, Captured) {
+        let captured = Captured::default();
+        let host = Self {
+            args,
+            stdout: Box::new(captured.clone()),
+            stderr: Box::new(captured.clone()),
+        };
+        (host, captured)
+    }
+}
+
+impl Default for Host {
+    fn default() -> Self {
+        Self::new(Vec::new())
+    }
+}
+
+/// The output of a program run with [Host::captured]
+#[derive(Debug, Clone, Default)]
+pub struct Captured(Arc<Mutex<Vec<u8>>>);
+
+impl Captured {
+    pub fn text(&self) -> String {
+        String::from_utf8_lossy(&self.0.lock().expect("the output is not poisoned")).into_owned()
+    }
+}
+
+impl Write for Captured {
+    fn write(&mut self, buffer: &[u8]) -> io::Result<usize> {
+        self.0
+            .lock()
+            .expect("the output is not poisoned")
+            .extend_from_slice(buffer);
+        Ok(buffer.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Why a builtin stopped instead of producing a value
+#[derive(Debug)]
+pub enum Stop<E> {
+    /// A runtime error, pointing at the call of the builtin
+    Error { message: String, label: String },
+    /// `exit`, which stops the program as if `main` returned the code
+    Exit(i32),
+    /// An error of the engine, such as one raised by a `show` method the builtin called
+    Engine(E),
+}
+
+fn error<T, E>(message: impl Into<String>, label: impl Into<String>) -> Result<T, Stop<E>> {
+    Err(Stop::Error {
+        message: message.into(),
+        label: label.into(),
+    })
+}
+
+/// The error of a builtin given a value it can not work on. Checking rules this out, so it only
+/// happens for engines running code which was not checked, such as a corrupted bytecode file
+fn invalid<T, E>(expected: &str) -> Result<T, Stop<E>> {
+    error("invalid argument", format!("expected {}", expected))
+}
+
+/// The entries of a `Map`, in the order of their keys
+pub type Entries<V> = Rc<Vec<(V, V)>>;
+
+/// What an engine provides to run builtins with its own values. References are followed by
+/// [Machine::read], and lists and maps are kept behind an [Rc] so they are only copied when a
+/// shared one changes
+pub trait Machine {
+    type Value: Clone;
+    type Error;
+
+    fn host(&mut self) -> &mut Host;
+
+    /// Writes out a value through its `Show` implementation
+    fn show(&mut self, value: &Self::Value) -> Result<String, Self::Error>;
+    /// Writes out a value of a primitive type
+    fn show_primitive(&self, value: &Self::Value) -> String;
+
+    fn unit(&self) -> Self::Value;
+    fn bool(&self, value: bool) -> Self::Value;
+    fn char(&self, value: char) -> Self::Value;
+    fn int64(&self, value: i64) -> Self::Value;
+    fn float64(&self, value: f64) -> Self::Value;
+    fn string(&self, value: &str) -> Self::Value;
+    fn list(&self, items: Rc<Vec<Self::Value>>) -> Self::Value;
+    fn map(&self, entries: Entries<Self::Value>) -> Self::Value;
+    /// Makes an `Option::Some` or an `Option::None`
+    fn option(&self, value: Option<Self::Value>) -> Self::Value;
+    /// Makes a `Result::Ok` or a `Result::Err`
+    fn result(&self, value: Result<Self::Value, Self::Value>) -> Self::Value;
+    /// Makes a generator which yields the values in order
+    fn generator(&mut self, items: Vec<Self::Value>) -> Self::Value;
+
+    fn to_integer(&self, value: &Self::Value) -> Option<i128>;
+    fn to_str(&self, value: &Self::Value) -> Option<Rc<str>>;
+    fn to_list(&self, value: &Self::Value) -> Option<Rc<Vec<Self::Value>>>;
+    fn to_map(&self, value: &Self::Value) -> Option<Entries<Self::Value>>;
+    /// Gets the payload of an `Option` or a `Result`, along with whether it is the first variant
+    fn to_variant(&self, value: &Self::Value) -> Option<(bool, Option<Self::Value>)>;
+    fn compare(&self, left: &Self::Value, right: &Self::Value) -> Option<Ordering>;
+
+    /// Follows references until reaching the value they point to
+    fn read(&self, value: &Self::Value) -> Self::Value;
+    /// Replaces the value a reference points to, giving false if the value is not a reference
+    fn write(&self, reference: &Self::Value, value: Self::Value) -> bool;
+}
+
+macro_rules! builtins {
+    ($($variant:ident => $name:literal,)*) => {
+        /// A function of the runtime, which an `extern` function of the standard library is
+        #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+        pub enum Builtin {
+            $($variant,)*
+            /// `show` of the primitive type
+            ShowPrimitive(PrimitiveType),
+        }
+
+        impl Builtin {
+            /// Finds the builtin an `extern` function is, given the name of the type whose
+            /// implementation declares it if it is a method. Items of the standard library are
+            /// named the way they are within a merged module, such as `std::Vec`
+            pub fn lookup(owner: Option<&str>, name: &str) -> Option<Self> {
+                let full = match owner {
+                    Some(owner) => format!("{}::{}", owner, name),
+                    None => name.to_string(),
+                };
+                match full.as_str() {
+                    $($name => Some(Self::$variant),)*
+                    _ if name == "show" => owner
+                        .and_then(|x| PrimitiveType::ALL.into_iter().find(|y| y.name() == x))
+                        .map(Self::ShowPrimitive),
+                    _ => None,
+                }
+            }
+
+            /// Gets the name of the builtin, which is how [Builtin::lookup] finds it again
+            pub fn name(&self) -> String {
+                match self {
+                    $(Self::$variant => $name.to_string(),)*
+                    Self::ShowPrimitive(ty) => format!("{}::show", ty),
+                }
+            }
+        }
+    };
+}
+
+builtins! {
+    Print => "std::print",
+    Println => "std::println",
+    Eprint => "std::eprint",
+    Eprintln => "std::eprintln",
+    Format => "std::format",
+    Panic => "std::panic",
+    Exit => "std::exit",
+    Args => "std::args",
+    ReadFile => "std::read_file",
+    WriteFile => "std::write_file",
+    ShowOption => "std::Option::show",
+    ShowResult => "std::Result::show",
+    StrLen => "Str::len",
+    StrIsEmpty => "Str::is_empty",
+    StrConcat => "Str::concat",
+    StrContains => "Str::contains",
+    StrStartsWith => "Str::starts_with",
+    StrEndsWith => "Str::ends_with",
+    StrFind => "Str::find",
+    StrSlice => "Str::slice",
+    StrCharAt => "Str::char_at",
+    StrTrim => "Str::trim",
+    StrToUpper => "Str::to_upper",
+    StrToLower => "Str::to_lower",
+    StrRepeat => "Str::repeat",
+    StrReplace => "Str::replace",
+    StrSplit => "Str::split",
+    StrLines => "Str::lines",
+    StrChars => "Str::chars",
+    StrParseInt => "Str::parse_int",
+    StrParseFloat => "Str::parse_float",
+    VecNew => "std::Vec::new",
+    VecLen => "std::Vec::len",
+    VecPush => "std::Vec::push",
+    VecPop => "std::Vec::pop",
+    VecGet => "std::Vec::get",
+    VecSet => "std::Vec::set",
+    VecInsert => "std::Vec::insert",
+    VecRemove => "std::Vec::remove",
+    VecClear => "std::Vec::clear",
+    VecIter => "std::Vec::iter",
+    ShowVec => "std::Vec::show",
+    MapNew => "std::Map::new",
+    MapLen => "std::Map::len",
+    MapInsert => "std::Map::insert",
+    MapGet => "std::Map::get",
+    MapContainsKey => "std::Map::contains_key",
+    MapRemove => "std::Map::remove",
+    MapClear => "std::Map::clear",
+    MapKeys => "std::Map::keys",
+    MapValues => "std::Map::values",
+    ShowMap => "std::Map::show",
+}
+
+impl Builtin {
+    /// The number of arguments the builtin takes. The ones which fill in a template take any
+    /// number of values after it
+    pub fn arity(&self) -> usize {
+        match self {
+            Self::Args | Self::VecNew | Self::MapNew => 0,
+            Self::StrConcat
+            | Self::StrContains
+            | Self::StrStartsWith
+            | Self::StrEndsWith
+            | Self::StrFind
+            | Self::StrCharAt
+            | Self::StrRepeat
+            | Self::StrSplit
+            | Self::WriteFile
+            | Self::VecPush
+            | Self::VecGet
+            | Self::VecRemove
+            | Self::MapGet
+            | Self::MapContainsKey
+            | Self::MapRemove => 2,
+            Self::StrSlice
+            | Self::StrReplace
+            | Self::VecSet
+            | Self::VecInsert
+            | Self::MapInsert => 3,
+            _ => 1,
+        }
+    }
+
+    /// The types of the standard library the builtin makes values of, which an engine must know
+    /// to make them
+    pub fn library_types(&self) -> &'static [&'static str] {
+        match self {
+            Self::Args | Self::StrSplit | Self::StrLines | Self::StrChars | Self::VecNew => {
+                &["std::Vec"]
+            }
+            Self::StrFind
+            | Self::StrCharAt
+            | Self::StrParseInt
+            | Self::StrParseFloat
+            | Self::VecGet
+            | Self::MapGet => &["std::Option"],
+            Self::VecPop => &["std::Vec", "std::Option"],
+            Self::MapInsert | Self::MapRemove => &["std::Map", "std::Option"],
+            Self::ReadFile | Self::WriteFile => &["std::Result"],
+            Self::VecPush | Self::VecSet | Self::VecInsert | Self::VecRemove | Self::VecClear => {
+                &["std::Vec"]
+            }
+            Self::MapNew | Self::MapClear => &["std::Map"],
+            _ => &[],
+        }
+    }
+}
+
+/// The error of calling an `extern "C"` function, which only programs compiled to C can call
+pub fn foreign<E>(name: &str) -> Stop<E> {
+    Stop::Error {
+        message: format!(
+            "the foreign function `{}` can only be called by a program built with `--target=c`",
+            name
+        ),
+        label: "called here".to_string(),
+    }
+}
+
+/// Carries out a builtin. The arguments have been checked to be of the types the `extern`
+/// function declares
+pub fn call<M: Machine>(
+    machine: &mut M,
+    builtin: Builtin,
+    arguments: Vec<M::Value>,
+) -> Result<M::Value, Stop<M::Error>> {
+    if arguments.len() < builtin.arity() {
+        return error(
+            "invalid call",
+            format!(
+                "`{}` takes {} arguments but {} were given",
+                builtin.name(),
+                builtin.arity(),
+                arguments.len()
+            ),
+        );
+    }
+    let text = |index: usize| -> Result<Rc<str>, Stop<M::Error>> {
+        match machine.to_str(&machine.read(&arguments[index])) {
+            Some(text) => Ok(text),
+            None => invalid("a `Str`"),
+        }
+    };
+    let integer = |index: usize| -> Result<i128, Stop<M::Error>> {
+        match machine.to_integer(&machine.read(&arguments[index])) {
+            Some(integer) => Ok(integer),
+            None => invalid("an integer"),
+        }
+    };
+    let list = |index: usize| -> Result<Rc<Vec<M::Value>>, Stop<M::Error>> {
+        match machine.to_list(&machine.read(&arguments[index])) {
+            Some(items) => Ok(items),
+            None => invalid("a `Vec`"),
+        }
+    };
+    let entries = |index: usize| -> Result<Entries<M::Value>, Stop<M::Error>> {
+        match machine.to_map(&machine.read(&arguments[index])) {
+            Some(entries) => Ok(entries),
+            None => invalid("a `Map`"),
+        }
+    };
+    let value = match builtin {
+        Builtin::Print | Builtin::Println | Builtin::Eprint | Builtin::Eprintln => {
+            let mut output = fill(machine, &arguments)?;
+            if matches!(builtin, Builtin::Println | Builtin::Eprintln) {
+                output.push('\n');
+            }
+            let host = machine.host();
+            let stream = match builtin {
+                Builtin::Print | Builtin::Println => &mut host.stdout,
+                _ => &mut host.stderr,
+            };
+            if let Err(reason) = stream
+                .write_all(output.as_bytes())
+                .and_then(|()| stream.flush())
+            {
+                return error("could not write the output", reason.to_string());
+            }
+            machine.unit()
+        }
+        Builtin::Format => {
+            let output = fill(machine, &arguments)?;
+            machine.string(&output)
+        }
+        Builtin::Panic => return error(format!("panicked: {}", text(0)?), "panicked here"),
+        Builtin::Exit => return Err(Stop::Exit(integer(0)? as i32)),
+        Builtin::Args => {
+            let args: Vec<String> = machine.host().args.clone();
+            let items = args.iter().map(|x| machine.string(x)).collect();
+            machine.list(Rc::new(items))
+        }
+        Builtin::ReadFile => match std::fs::read_to_string(&*text(0)?) {
+            Ok(contents) => machine.result(Ok(machine.string(&contents))),
+            Err(reason) => machine.result(Err(machine.string(&reason.to_string()))),
+        },
+        Builtin::WriteFile => {
+            let contents = text(1)?;
+            match std::fs::write(&*text(0)?, contents.as_bytes()) {
+                Ok(()) => machine.result(Ok(machine.int64(contents.len() as i64))),
+                Err(reason) => machine.result(Err(machine.string(&reason.to_string()))),
+            }
+        }
+        Builtin::ShowPrimitive(_) => {
+            let value = machine.read(&arguments[0]);
+            machine.string(&machine.show_primitive(&value))
+        }
+        Builtin::ShowOption | Builtin::ShowResult => {
+            let value = machine.read(&arguments[0]);
+            let Some((first, payload)) = machine.to_variant(&value) else {
+                return invalid("an `Option` or a `Result`");
+            };
+            let name = match (builtin, first) {
+                (Builtin::ShowOption, true) => "Some",
+                (Builtin::ShowOption, false) => "None",
+                (_, true) => "Ok",
+                (_, false) => "Err",
+            };
+            let text = match payload {
+                Some(payload) => {
+                    let shown = machine.show(&payload).map_err(Stop::Engine)?;
+                    format!("{}({})", name, shown)
+                }
+                None => name.to_string(),
+            };
+            machine.string(&text)
+        }
+        Builtin::StrLen => machine.int64(text(0)?.chars().count() as i64),
+        Builtin::StrIsEmpty => machine.bool(text(0)?.is_empty()),
+        Builtin::StrConcat => machine.string(&format!("{}{}", text(0)?, text(1)?)),
+        Builtin::StrContains => machine.bool(text(0)?.contains(&*text(1)?)),
+        Builtin::StrStartsWith => machine.bool(text(0)?.starts_with(&*text(1)?)),
+        Builtin::StrEndsWith => machine.bool(text(0)?.ends_with(&*text(1)?)),
+        Builtin::StrFind => {
+            let haystack = text(0)?;
+            let found = haystack
+                .find(&*text(1)?)
+                .map(|x| machine.int64(haystack[..x].chars().count() as i64));
+            machine.option(found)
+        }
+        Builtin::StrSlice => {
+            let haystack = text(0)?;
+            let length = haystack.chars().count() as i128;
+            let (start, end) = (integer(1)?, integer(2)?);
+            if start < 0 || end < start || end > length {
+                return error(
+                    "string index out of bounds",
+                    format!(
+                        "the range {}..{} does not fit in a string of {} characters",
+                        start, end, length
+                    ),
+                );
+            }
+            let slice: String = haystack
+                .chars()
+                .skip(start as usize)
+                .take((end - start) as usize)
+                .collect();
+            machine.string(&slice)
+        }
+        Builtin::StrCharAt => {
+            let (haystack, index) = (text(0)?, integer(1)?);
+            let character = usize::try_from(index)
+                .ok()
+                .and_then(|x| haystack.chars().nth(x))
+                .map(|x| machine.char(x));
+            machine.option(character)
+        }
+        Builtin::StrTrim => machine.string(text(0)?.trim()),
+        Builtin::StrToUpper => machine.string(&text(0)?.to_uppercase()),
+        Builtin::StrToLower => machine.string(&text(0)?.to_lowercase()),
+        Builtin::StrRepeat => {
+            let count = integer(1)?;
+            if count < 0 {
+                return error(
+                    "cannot repeat a string a negative number of times",
+                    format!("the count is {}", count),
+                );
+            }
+            machine.string(&text(0)?.repeat(count as usize))
+        }
+        Builtin::StrReplace => machine.string(&text(0)?.replace(&*text(1)?, &text(2)?)),
+        Builtin::StrSplit => {
+            let (haystack, separator) = (text(0)?, text(1)?);
+            let parts: Vec<M::Value> = match separator.is_empty() {
+                true => haystack
+                    .chars()
+                    .map(|x| machine.string(&x.to_string()))
+                    .collect(),
+                false => haystack
+                    .split(&*separator)
+                    .map(|x| machine.string(x))
+                    .collect(),
+            };
+            machine.list(Rc::new(parts))
+        }
+        Builtin::StrLines => {
+            let lines = text(0)?.lines().map(|x| machine.string(x)).collect();
+            machine.list(Rc::new(lines))
+        }
+        Builtin::StrChars => {
+            let characters = text(0)?.chars().map(|x| machine.char(x)).collect();
+            machine.list(Rc::new(characters))
+        }
+        Builtin::StrParseInt => {
+            let parsed = text(0)?
+                .trim()
+                .parse::<i64>()
+                .ok()
+                .map(|x| machine.int64(x));
+            machine.option(parsed)
+        }
+        Builtin::StrParseFloat => {
+            let parsed = text(0)?
+                .trim()
+                .parse::<f64>()
+                .ok()
+                .map(|x| machine.float64(x));
+            machine.option(parsed)
+        }
+        Builtin::VecNew => machine.list(Rc::new(Vec::new())),
+        Builtin::VecLen => machine.int64(list(0)?.len() as i64),
+        Builtin::VecPush => {
+            let mut items = take_list(machine, &arguments[0])?;
+            Rc::make_mut(&mut items).push(arguments[1].clone());
+            store(machine, &arguments[0], machine.list(items))?;
+            machine.unit()
+        }
+        Builtin::VecPop => {
+            let mut items = take_list(machine, &arguments[0])?;
+            let popped = Rc::make_mut(&mut items).pop();
+            store(machine, &arguments[0], machine.list(items))?;
+            machine.option(popped)
+        }
+        Builtin::VecGet => {
+            let (items, index) = (list(0)?, integer(1)?);
+            let item = usize::try_from(index)
+                .ok()
+                .and_then(|x| items.get(x).cloned());
+            machine.option(item)
+        }
+        Builtin::VecSet | Builtin::VecInsert | Builtin::VecRemove => {
+            let index = integer(1)?;
+            let mut items = take_list(machine, &arguments[0])?;
+            let length = items.len();
+            let limit = match builtin {
+                Builtin::VecInsert => length + 1,
+                _ => length,
+            };
+            let Some(index) = usize::try_from(index).ok().filter(|x| *x < limit) else {
+                store(machine, &arguments[0], machine.list(items))?;
+                return error(
+                    "index out of bounds",
+                    format!("the index is {} but the length is {}", index, length),
+                );
+            };
+            let result = match builtin {
+                Builtin::VecSet => {
+                    Rc::make_mut(&mut items)[index] = arguments[2].clone();
+                    machine.unit()
+                }
+                Builtin::VecInsert => {
+                    Rc::make_mut(&mut items).insert(index, arguments[2].clone());
+                    machine.unit()
+                }
+                _ => Rc::make_mut(&mut items).remove(index),
+            };
+            store(machine, &arguments[0], machine.list(items))?;
+            result
+        }
+        Builtin::VecClear => {
+            store(machine, &arguments[0], machine.list(Rc::new(Vec::new())))?;
+            machine.unit()
+        }
+        Builtin::VecIter => {
+            let items = list(0)?.as_ref().clone();
+            machine.generator(items)
+        }
+        Builtin::ShowVec => {
+            let items = list(0)?;
+            let shown = items
+                .iter()
+                .map(|x| machine.show(x))
+                .collect::<Result<Vec<_>, _>>()
+                .map_err(Stop::Engine)?;
+            machine.string(&format!("[{}]", shown.join(", ")))
+        }
+        Builtin::MapNew => machine.map(Rc::new(Vec::new())),
+        Builtin::MapLen => machine.int64(entries(0)?.len() as i64),
+        Builtin::MapInsert | Builtin::MapRemove => {
+            let mut map = take_map(machine, &arguments[0])?;
+            let key = &arguments[1];
+            let position =
+                map.binary_search_by(|(x, _)| machine.compare(x, key).unwrap_or(Ordering::Less));
+            let entries = Rc::make_mut(&mut map);
+            let previous = match (builtin, position) {
+                (Builtin::MapInsert, Ok(index)) => Some(std::mem::replace(
+                    &mut entries[index].1,
+                    arguments[2].clone(),
+                )),
+                (Builtin::MapInsert, Err(index)) => {
+                    entries.insert(index, (key.clone(), arguments[2].clone()));
+                    None
+                }
+                (_, Ok(index)) => Some(entries.remove(index).1),
+                (_, Err(_)) => None,
+            };
+            store(machine, &arguments[0], machine.map(map))?;
+            machine.option(previous)
+        }
+        Builtin::MapGet | Builtin::MapContainsKey => {
+            let map = entries(0)?;
+            let key = &arguments[1];
+            let found = map
+                .binary_search_by(|(x, _)| machine.compare(x, key).unwrap_or(Ordering::Less))
+                .ok()
+                .map(|x| map[x].1.clone());
+            match builtin {
+                Builtin::MapGet => machine.option(found),
+                _ => machine.bool(found.is_some()),
+            }
+        }
+        Builtin::MapClear => {
+            store(machine, &arguments[0], machine.map(Rc::new(Vec::new())))?;
+            machine.unit()
+        }
+        Builtin::MapKeys | Builtin::MapValues => {
+            let map = entries(0)?;
+            let items = map
+                .iter()
+                .map(|(key, value)| match builtin {
+                    Builtin::MapKeys => key.clone(),
+                    _ => value.clone(),
+                })
+                .collect();
+            machine.generator(items)
+        }
+        Builtin::ShowMap => {
+            let map = entries(0)?;
+            let mut shown = Vec::new();
+            for (key, value) in map.iter() {
+                let key = machine.show(key).map_err(Stop::Engine)?;
+                let value = machine.show(value).map_err(Stop::Engine)?;
+                shown.push(format!("{}: {}", key, value));
+            }
+            machine.string(&format!("{{{}}}", shown.join(", ")))
+        }
+    };
+    Ok(value)
+}
+
+/// Fills in the template which is the first argument with the values after it
+fn fill<M: Machine>(machine: &mut M, arguments: &[M::Value]) -> Result<String, Stop<M::Error>> {
+    let Some(template) = machine.to_str(&machine.read(&arguments[0])) else {
+        return invalid("a `Str`");
+    };
+    let pieces = match split_template(&template) {
+        Ok(pieces) => pieces,
+        Err(reason) => return error("invalid template", reason.to_string()),
+    };
+    let values = &arguments[1..];
+    if pieces.len() - 1 != values.len() {
+        return error(
+            "invalid template",
+            format!(
+                "the template has {} placeholders but {} values were given",
+                pieces.len() - 1,
+                values.len()
+            ),
+        );
+    }
+    let shown = values
+        .iter()
+        .map(|x| machine.show(x))
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(Stop::Engine)?;
+    Ok(fill_template(&pieces, &shown))
+}
+
+/// Takes the list a reference points to, leaving a unit behind so that the list is not shared and
+/// can be changed without copying it. The list must be written back
+fn take_list<M: Machine>(
+    machine: &M,
+    reference: &M::Value,
+) -> Result<Rc<Vec<M::Value>>, Stop<M::Error>> {
+    let Some(items) = machine.to_list(&machine.read(reference)) else {
+        return invalid("a `Vec`");
+    };
+    store(machine, reference, machine.unit())?;
+    Ok(items)
+}
+
+/// Like [take_list], for a map
+fn take_map<M: Machine>(
+    machine: &M,
+    reference: &M::Value,
+) -> Result<Entries<M::Value>, Stop<M::Error>> {
+    let Some(entries) = machine.to_map(&machine.read(reference)) else {
+        return invalid("a `Map`");
+    };
+    store(machine, reference, machine.unit())?;
+    Ok(entries)
+}
+
+/// Replaces the value a reference given to a builtin points to
+fn store<M: Machine>(
+    machine: &M,
+    reference: &M::Value,
+    value: M::Value,
+) -> Result<(), Stop<M::Error>> {
+    match machine.write(reference, value) {
+        true => Ok(()),
+        false => invalid("a reference"),
+    }
+}
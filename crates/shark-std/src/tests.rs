@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+
+use crate::{
+    load, prelude,
+    runtime::{Builtin, Host},
+    SOURCE,
+};
+
+/// Checks a program along with the standard library, getting the messages of every diagnostic
+fn check(program: &str) -> Vec<String> {
+    let tree = load(None, program, &mut HashMap::new());
+    let mut messages: Vec<String> = tree
+        .modules()
+        .flat_map(|(_, x)| &x.diagnostics)
+        .map(|x| x.message.clone())
+        .collect();
+    let (resolutions, diagnostics) = shark_resolve::resolve(&tree);
+    messages.extend(diagnostics.into_iter().map(|(_, x)| x.message));
+    if !messages.is_empty() {
+        return messages;
+    }
+    let module = shark_resolve::merge(&tree, &resolutions);
+    let (defs, diagnostics) = shark_sema::check_module(&module);
+    if !diagnostics.is_empty() {
+        return diagnostics.into_iter().map(|x| x.message).collect();
+    }
+    let (types, diagnostics) = shark_typeck::check_module(&module, &defs);
+    if !diagnostics.is_empty() {
+        return diagnostics.into_iter().map(|x| x.message).collect();
+    }
+    shark_borrowck::check_module(&module, &defs, &types)
+        .into_iter()
+        .map(|x| x.message)
+        .collect()
+}
+
+#[test]
+fn test_whole_library_checks() {
+    let program = "fun main() :: Int32 {
+        let mut items = Vec::new();
+        items.push(1);
+        let mut names = Map::new();
+        names.insert(1, \"a\".to_upper());
+        let first = Option::Some(1).unwrap_or(items.pop().unwrap());
+        let read :: Result<Str, Str> = Result::Ok(\"\");
+        println(\"{} {} {}\", first, read.is_ok(), \"a b\".split(\" \").join(\", \"));
+        eprint(format(\"{}\", names));
+        let written = write_file(\"\", \"\").unwrap_err();
+        let read = read_file(written).expect(\"unreadable\");
+        exit(read.len().to_int32());
+        args().len().to_int32()
+    }";
+    let library = prelude(&[program]);
+    for item in [
+        "trait Show",
+        "enum Option",
+        "enum Result",
+        "type Vec",
+        "type Map",
+    ] {
+        assert!(library.contains(item), "missing {}", item);
+    }
+    let errors = check(program);
+    assert!(errors.is_empty(), "{:?}", errors);
+}
+
+#[test]
+fn test_unused_library_is_blanked() {
+    let program = "fun main() :: Int32 { 0 }";
+    let library = prelude(&[program]);
+    assert_eq!(library.len(), SOURCE.len());
+    assert_eq!(library.lines().count(), SOURCE.lines().count());
+    assert!(!library.contains("Vec"));
+    assert!(!library.contains("Option"));
+    assert!(library.contains("trait Show"));
+    assert!(check(program).is_empty());
+}
+
+#[test]
+fn test_reachable_through_items() {
+    // `split` gives a `Vec`, whose `pop` gives an `Option`
+    let library = prelude(&["fun main() { let x = \"a b\".split(\" \"); }"]);
+    assert!(library.contains("enum Option"));
+    assert!(library.contains("type Vec"));
+    assert!(!library.contains("type Map"));
+    // What other modules mention is kept as well
+    let library = prelude(&["fun main() {}", "pub fun f() { Map::new(); }"]);
+    assert!(library.contains("type Map"));
+}
+
+#[test]
+fn test_program_items_shadow_library() {
+    let program = "enum Option { Some(Int32), None }
+        fun main() :: Int32 { when Option::Some(1) { Option::Some(x) => x, Option::None => 0 } }";
+    assert!(check(program).is_empty(), "{:?}", check(program));
+
+    // The standard library keeps using its own items, whatever the program names its own
+    let program = "trait Show { fun show(self) :: Int32; }
+        type Option { value :: Int32 }
+        type Point { x :: Int32 }
+        impl Show for Point { fun show(self) :: Int32 { self.x } }
+        fun print(option :: Option) :: Int32 { option.value }
+        fun main() :: Int32 {
+            let mut values = Vec::new();
+            values.push(1);
+            println(\"{} {}\", values.pop().unwrap(), \"a\".find(\"a\"));
+            print(Option { value = Point { x = 1 }.show() })
+        }";
+    assert!(check(program).is_empty(), "{:?}", check(program));
+    let program = "trait Show { fun show(self) :: Str; }
+        type Point { x :: Int32 }
+        impl Show for Point { fun show(self) :: Str { \"point\" } }
+        fun main() { println(\"{}\", Point { x = 1 }); }";
+    assert_eq!(
+        check(program),
+        ["the trait `std::Show` is not implemented for `Point`"]
+    );
+
+    // The standard library can still be named through `std`
+    let program = "type Vec { x :: Int32 }
+        fun main() {
+            let mut values = std::Vec::new();
+            values.push(Vec { x = 1 });
+            std::println(\"{}\", values.len());
+        }";
+    assert!(check(program).is_empty(), "{:?}", check(program));
+}
+
+#[test]
+fn test_library_programs() {
+    let errors = check(
+        "fun main() {
+            let mut items = Vec::new();
+            items.push(\"a\");
+            items.push(\"b\");
+            println(\"{} {}\", items.join(\", \"), items.len());
+            let mut counts = Map::new();
+            counts.insert(\"a\", 1);
+            when counts.get(\"a\") {
+                Option::Some(count) => println(\"{}\", count),
+                Option::None => {},
+            }
+        }",
+    );
+    assert!(errors.is_empty(), "{:?}", errors);
+}
+
+#[test]
+fn test_template_errors() {
+    let errors = check("fun main() { println(\"{} {}\", 1); }");
+    assert_eq!(
+        errors,
+        ["the template has 2 placeholders but 1 value was given"]
+    );
+    let errors = check("fun main() { println(\"{\"); }");
+    assert_eq!(errors, ["invalid template"]);
+    let errors =
+        check("type Point { x :: Int32 } fun main() { println(\"{}\", Point { x = 1 }); }");
+    assert_eq!(
+        errors,
+        ["the trait `std::Show` is not implemented for `Point`"]
+    );
+}
+
+#[test]
+fn test_builtin_names() {
+    for name in [
+        "std::print",
+        "std::Vec::push",
+        "std::Map::keys",
+        "Int32::show",
+        "Str::split",
+    ] {
+        let (owner, method) = match name.rsplit_once("::") {
+            Some((owner, method)) => (Some(owner), method),
+            None => (None, name),
+        };
+        let builtin = Builtin::lookup(owner, method).expect("missing builtin");
+        assert_eq!(builtin.name(), name);
+    }
+    assert_eq!(Builtin::lookup(None, "missing"), None);
+    // Only the items of the standard library are builtins
+    assert_eq!(Builtin::lookup(None, "print"), None);
+    assert_eq!(Builtin::lookup(Some("Vec"), "push"), None);
+    assert_eq!(Builtin::lookup(Some("Point"), "show"), None);
+}
+
+#[test]
+fn test_captured_host() {
+    use std::io::Write;
+
+    let (mut host, captured) = Host::captured(vec!["a".to_string()]);
+    write!(host.stdout, "out ").unwrap();
+    write!(host.stderr, "err").unwrap();
+    assert_eq!(captured.text(), "out err");
+    assert_eq!(host.args, ["a"]);
+}
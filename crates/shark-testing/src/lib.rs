@@ -0,0 +1,147 @@
+//! Helpers shared by the tests of everything which runs Shark programs. A program is checked the
+//! way `sharkc` checks it before it is handed to an engine, and is run by the interpreter for the
+//! result of the engine to be compared against
+
+use std::{
+    collections::HashMap,
+    path::PathBuf,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use shark_core::{diagnostic::Diagnostic, source::LineIndex};
+use shark_interp::value::Value;
+use shark_lower::Body;
+use shark_parse::ast::Module;
+use shark_sema::{numeric::Overflow, ModuleDefs};
+use shark_std::runtime::Host;
+use shark_typeck::TypeckResults;
+
+pub mod overflow;
+pub mod suite;
+
+/// A module which got through every check without errors
+pub struct Checked {
+    pub module: Module,
+    pub defs: ModuleDefs,
+    pub types: TypeckResults,
+}
+
+impl Checked {
+    /// Lowers every body of the module, which must not fail
+    pub fn lower(&self) -> Vec<Body<'_>> {
+        let (bodies, diagnostics) = shark_lower::lower_module(&self.module);
+        assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+        bodies
+    }
+}
+
+/// Parses, resolves and checks a module along with the standard library, which must be free of
+/// errors, then replaces its constants by their values
+pub fn check(source: &str) -> Checked {
+    let tree = shark_std::load(None, source, &mut HashMap::new());
+    for (_, module) in tree.modules() {
+        assert!(module.diagnostics.is_empty(), "{:?}", module.diagnostics);
+    }
+    let (resolutions, diagnostics) = shark_resolve::resolve(&tree);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let mut module = shark_resolve::merge(&tree, &resolutions);
+    let (defs, diagnostics) = shark_sema::check_module(&module);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let (types, diagnostics) = shark_typeck::check_module(&module, &defs);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let diagnostics = shark_borrowck::check_module(&module, &defs, &types);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let (constants, diagnostics) = shark_const::evaluate(&module, &types, source);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    shark_const::substitute(&mut module, &constants);
+    Checked {
+        module,
+        defs,
+        types,
+    }
+}
+
+/// Runs a closure on a thread with as much stack as the interpreter needs, since deep recursion
+/// needs more than test threads are given
+pub fn on_interpreter_stack<T: Send>(run: impl FnOnce() -> T + Send) -> T {
+    std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(shark_interp::STACK_SIZE)
+            .spawn_scoped(scope, run)
+            .expect("failed to spawn the interpreter thread")
+            .join()
+            .expect("the interpreter panicked")
+    })
+}
+
+/// Runs a module which must check without errors by the interpreter, handing how it went to
+/// `outcome` before leaving the thread it ran on
+pub fn interpret<T: Send>(
+    source: &str,
+    overflow: Overflow,
+    outcome: impl FnOnce(Result<Value, Diagnostic>) -> T + Send,
+) -> T {
+    let checked = check(source);
+    let bodies = checked.lower();
+    on_interpreter_stack(|| {
+        outcome(shark_interp::run(
+            &checked.module,
+            &checked.defs,
+            &checked.types,
+            &bodies,
+            overflow,
+            Host::default(),
+        ))
+    })
+}
+
+/// Gets the exit code of a program from what `main` returned, or 101 along with where the error
+/// which stopped it happened, the way a compiled program reports it
+pub fn exit_status(source: &str, result: Result<Value, Diagnostic>) -> (u8, String) {
+    match result {
+        Ok(Value::Int32(code)) => (code as u8, String::new()),
+        Ok(_) => (0, String::new()),
+        Err(diagnostic) => {
+            let span = diagnostic
+                .primary_span()
+                .expect("runtime errors have a span");
+            let position = LineIndex::new(source).position(None, span.start);
+            (
+                101,
+                format!("error: {}\n --> {}", diagnostic.message, position),
+            )
+        }
+    }
+}
+
+/// Gets an exit status from what `main` returned as text, or from the message of the error which
+/// stopped it, for engines which report the result of a program that way
+pub fn text_status(result: Result<String, (String, String)>) -> (u8, String) {
+    match result {
+        Ok(text) => (text.parse::<i32>().map_or(0, |x| x as u8), String::new()),
+        Err((message, _)) => (101, format!("error: {}", message)),
+    }
+}
+
+/// Gets the message of a runtime error along with the source its span covers
+pub fn error_source(source: &str, diagnostic: Diagnostic) -> (String, String) {
+    let span = diagnostic
+        .primary_span()
+        .expect("runtime errors have a span");
+    (diagnostic.message, source[span.start..span.end].to_string())
+}
+
+/// Gives every test program a directory of its own, as tests run in parallel
+static PROGRAMS: AtomicUsize = AtomicUsize::new(0);
+
+/// Creates an empty directory for the files of one test program, named after the crate testing it
+pub fn scratch_directory(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!(
+        "{}-{}-{}",
+        name,
+        std::process::id(),
+        PROGRAMS.fetch_add(1, Ordering::Relaxed)
+    ));
+    std::fs::create_dir_all(&directory).expect("failed to create a directory for the program");
+    directory
+}
@@ -0,0 +1,86 @@
+//! Programs whose result depends on how they were built to overflow, run the same way by every
+//! engine so that they all agree on what wrapping around, trapping and saturating give
+
+use shark_sema::numeric::Overflow;
+
+use crate::{exit_status, interpret};
+
+/// A program which exits with a code when built to wrap around, and stops with an error when
+/// built to trap
+pub struct OverflowProgram {
+    pub source: &'static str,
+    pub wrapped: u8,
+    pub trapped: &'static str,
+}
+
+pub const OVERFLOW_PROGRAMS: &[OverflowProgram] = &[
+    // A release build wraps around where a debug build traps, and converting a float saturates
+    OverflowProgram {
+        source: "fun bump(x :: Int8) :: Int8 {
+            x + 100
+        }
+
+        pub fun main() :: Int32 {
+            let a = bump(100);
+            let min :: Int8 = -128;
+            let b = -min;
+            let c = min.wrapping_sub(1);
+            let d = (2000000000).saturating_mul(3);
+            let e = (250uint8).saturating_add(10);
+            let f = (9223372036854775807int64).saturating_add(1);
+            let g = (5uint64).saturating_sub(6);
+            let h = (1.5e30float64).to_int64();
+            let i = (0 - 1).to_uint8();
+            let j = (0.0 / 0.0).to_int32();
+            let k = (16777217).to_float32();
+            let l = (18446744073709551615uint64).to_float64();
+            let m = (1.0e19float64).to_uint64();
+            if a == -56 && b == -128 && c == 127 && d == 2147483647 && e == 255
+                && f == 9223372036854775807int64 && g == 0uint64 && h == f && i == 255 && j == 0
+                && k == 16777216.0 && l == 18446744073709551616.0
+                && m == 10000000000000000000uint64 {
+                42
+            } else {
+                1
+            }
+        }",
+        wrapped: 42,
+        trapped: "attempt to add with overflow",
+    },
+    OverflowProgram {
+        source: "pub fun main() :: Int32 {
+            let small = (7.9).to_int32();
+            let wide = (200uint8).to_int32();
+            let half = (-7).trapping_div(2);
+            small + wide + half + (300).to_uint8().to_int32()
+        }",
+        wrapped: 248,
+        trapped: "attempt to convert with overflow",
+    },
+];
+
+/// Runs every one of [OVERFLOW_PROGRAMS] by an engine built both ways. The engine gives the exit
+/// status of a program the way [exit_status] does, and must agree with the interpreter on the exit
+/// code and on the first line of the error
+pub fn check_overflow_modes(engine: impl Fn(&str, Overflow) -> (u8, String)) {
+    for program in OVERFLOW_PROGRAMS {
+        let source = program.source;
+        assert_eq!(
+            engine(source, Overflow::Wrap),
+            (program.wrapped, String::new()),
+            "{}",
+            source
+        );
+        let (code, error) = engine(source, Overflow::Trap);
+        let (expected_code, expected) =
+            interpret(source, Overflow::Trap, |x| exit_status(source, x));
+        assert_eq!((code, expected_code), (101, 101), "{}", source);
+        assert_eq!(error.lines().next(), expected.lines().next(), "{}", source);
+        assert_eq!(
+            error.lines().next(),
+            Some(format!("error: {}", program.trapped).as_str()),
+            "{}",
+            source
+        );
+    }
+}
@@ -0,0 +1,90 @@
+//! The programs every engine running Shark is tested against, which are kept in `tests/programs`
+//! along with what they must write out. Each engine runs them with its own [TestProgram::args] and
+//! compares the [transcript] of the run to [TestProgram::expected]
+
+use std::path::PathBuf;
+
+/// A program of the suite, which removes the file it may write to once it is dropped
+#[derive(Debug)]
+pub struct TestProgram {
+    pub name: String,
+    pub source: String,
+    /// The arguments the program is run with. The first is the path of a file the program may
+    /// write to, which is different for every program and process
+    pub args: Vec<String>,
+    /// The [transcript] of running the program
+    pub expected: String,
+}
+
+impl Drop for TestProgram {
+    fn drop(&mut self) {
+        // The program may not have written the file, or may have been stopped before it did
+        let _ = std::fs::remove_file(&self.args[0]);
+    }
+}
+
+/// Gets every program of the suite, in the order of their names
+pub fn programs() -> Vec<TestProgram> {
+    let directory = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/programs");
+    let mut paths: Vec<PathBuf> = std::fs::read_dir(&directory)
+        .expect("the programs of the suite can be read")
+        .map(|x| x.expect("the programs of the suite can be read").path())
+        .filter(|x| x.extension().is_some_and(|x| x == "shark"))
+        .collect();
+    paths.sort();
+    paths
+        .into_iter()
+        .map(|path| {
+            let name = path
+                .file_stem()
+                .expect("programs have a name")
+                .to_string_lossy()
+                .into_owned();
+            let source = std::fs::read_to_string(&path).expect("the program can be read");
+            let expected = std::fs::read_to_string(path.with_extension("out"))
+                .unwrap_or_else(|_| panic!("`{}` has no expected output", name));
+            let scratch = std::env::temp_dir().join(format!(
+                "shark-suite-{}-{}.txt",
+                std::process::id(),
+                name
+            ));
+            TestProgram {
+                name,
+                source,
+                args: vec![scratch.to_string_lossy().into_owned(), "second".to_string()],
+                expected,
+            }
+        })
+        .collect()
+}
+
+/// Writes out how running a program went: everything it wrote, followed by the integer `main`
+/// returned or the message of the runtime error which stopped it
+pub fn transcript(output: &str, result: Result<i128, String>) -> String {
+    match result {
+        Ok(code) => format!("{}=> {}\n", output, code),
+        Err(message) => format!("{}error: {}\n", output, message),
+    }
+}
+
+/// Gets the [transcript] of running an executable which wrote stdout and stderr to the same
+/// `output`. A runtime error exits with code 101 after writing `error: ` and its message, followed
+/// by the line pointing to where it happened
+pub fn executable_transcript(output: &str, code: i32) -> String {
+    if code != 101 {
+        return transcript(output, Ok(code.into()));
+    }
+    let start = match output.rfind("\nerror: ") {
+        Some(index) => index + 1,
+        None if output.starts_with("error: ") => 0,
+        None => panic!(
+            "the program exited with code 101 without an error:\n{}",
+            output
+        ),
+    };
+    let message = output[start + "error: ".len()..]
+        .lines()
+        .next()
+        .unwrap_or_default();
+    transcript(&output[..start], Err(message.to_string()))
+}
@@ -0,0 +1,1723 @@
+//! Infers the type of every expression within a function body. Each expression whose type is not
+//! known up front gets a type variable, and the ways expressions are combined make their types
+//! equal, solving the variables through unification. Literals without a suffix lex as `Int32` or
+//! `Float32`, so those get variables which adapt to their context and fall back to that type
+//!
+//! How a body uses what the module declares is checked along the way: struct literals, enum
+//! variants, field accesses, and calls of generic functions and methods, whose trait bounds must
+//! hold. Checks which need the types to be known, such as those bounds or whether the arms of a
+//! `when` are exhaustive, wait until the function has been inferred. Names which do not resolve
+//! have already been reported by name resolution, so they are given [Ty::Error] silently
+
+use std::{collections::HashMap, fmt::Display};
+
+use shark_core::{
+    diagnostic::Diagnostic,
+    source::Span,
+    symbol::{sym, Symbol},
+};
+use shark_lex::token::LiteralKind;
+use shark_parse::ast::{
+    BinaryOperator, Block, Expr, ExprKind, FieldInit, ForKind, Function, Ident, NodeId, Path,
+    Pattern, PatternKind, StatementKind, UnaryOperator, WhenArm,
+};
+use shark_sema::{
+    adt::{values, AdtDef},
+    foreign::{check_c_type, Position},
+    format,
+    generics::{FunctionSig, Predicate, TypeScope},
+    numeric::{self, Intrinsic},
+    pattern::{self, Arm},
+    traits::TraitId,
+    ty::{AdtId, Length, PrimitiveType, Type},
+    ModuleDefs,
+};
+
+use crate::{
+    ty::{InferTable, Ty, VarKind},
+    TypeckResults,
+};
+
+pub struct TypeChecker<'check> {
+    defs: &'check ModuleDefs,
+    infer: InferTable,
+    scopes: Vec<HashMap<Symbol, Ty>>,
+    /// The generic parameters which can be named within the function being checked
+    type_scope: TypeScope,
+    /// The predicates known to hold within the function being checked
+    predicates: Vec<Predicate>,
+    /// The return type of the function being checked and where it was written. Generators return
+    /// `()`, their declared return type being what they yield
+    return_type: (Ty, Option<Span>),
+    /// The type the function being checked yields, if it is a generator
+    yield_type: Option<Ty>,
+    /// Every `let` without a type, which must have been inferred by the end of the function
+    lets: Vec<(Ident, Ty)>,
+    /// Every integer literal which adapts to its context, checked to fit once the function has
+    /// been inferred
+    literals: Vec<(i32, Ty, Span)>,
+    /// Every value given to a variadic function after its parameters, along with the name of the
+    /// function, which must implement `Show` once the function has been inferred
+    shown: Vec<(Ty, Span, Symbol)>,
+    /// Every value given to a variadic foreign function after its parameters, which must be of a
+    /// C type once the function has been inferred
+    foreign: Vec<(Ty, Span)>,
+    /// Every bound a call requires, along with the type it applies to and the span of the call,
+    /// which must hold once the function has been inferred
+    bounds: Vec<(Ty, Predicate, Span)>,
+    /// Every `when` and `for` loop, whose patterns are checked against the type they match once
+    /// the function has been inferred
+    matches: Vec<Match>,
+    /// The type of every expression and `let` within the function being checked
+    types: HashMap<NodeId, Ty>,
+    pub results: TypeckResults,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl<'check> TypeChecker<'check> {
+    pub fn new(defs: &'check ModuleDefs) -> Self {
+        Self {
+            defs,
+            infer: InferTable::default(),
+            scopes: Vec::new(),
+            type_scope: TypeScope::default(),
+            predicates: Vec::new(),
+            return_type: (Ty::Unit, None),
+            yield_type: None,
+            lets: Vec::new(),
+            literals: Vec::new(),
+            shown: Vec::new(),
+            foreign: Vec::new(),
+            bounds: Vec::new(),
+            matches: Vec::new(),
+            types: HashMap::new(),
+            results: TypeckResults::default(),
+            diagnostics: Vec::new(),
+        }
+    }
+
+    /// Infers the types within the body of a function or a method, if it has one. The
+    /// [TypeScope] and the predicates are those of the trait or implementation the method belongs
+    /// to, if any
+    pub fn check_function(
+        &mut self,
+        function: &Function,
+        sig: &FunctionSig,
+        scope: &TypeScope,
+        predicates: &[Predicate],
+    ) {
+        let Some(body) = &function.body else {
+            return;
+        };
+        self.infer = InferTable::default();
+        self.type_scope = scope.with_params(&sig.generics.params);
+        self.predicates = predicates
+            .iter()
+            .chain(&sig.generics.predicates)
+            .cloned()
+            .collect();
+        self.return_type = (
+            Ty::from_type(&sig.return_type, &HashMap::new()),
+            sig.return_span,
+        );
+        self.yield_type = None;
+        if let Ty::Generator(item) = &self.return_type.0 {
+            self.yield_type = Some(*item.clone());
+            self.return_type.0 = Ty::Unit;
+        }
+
+        let mut scope = HashMap::new();
+        for (parameter, ty) in function.parameters.iter().zip(&sig.parameters) {
+            self.check_well_formed(ty, parameter.ty.span);
+            scope.insert(parameter.name.symbol, Ty::from_type(ty, &HashMap::new()));
+        }
+        if let Some(return_type) = &function.return_type {
+            self.check_well_formed(&sig.return_type, return_type.span);
+        }
+        self.scopes.push(scope);
+        let ty = self.check_block(body);
+        self.scopes.pop();
+
+        let (return_type, return_span) = self.return_type.clone();
+        let span = body.tail.as_ref().map_or(body.span, |x| x.span);
+        self.coerce(&ty, span, &return_type, return_span);
+        self.finish();
+    }
+
+    /// Infers the types within the value of a constant or a discriminant, which must be of type
+    /// `expected`
+    pub fn check_const(&mut self, value: &Expr, expected: &Ty, expected_span: Option<Span>) {
+        self.infer = InferTable::default();
+        self.type_scope = TypeScope::default();
+        self.predicates = Vec::new();
+        self.return_type = (Ty::Unit, None);
+        self.yield_type = None;
+
+        self.scopes.push(HashMap::new());
+        let ty = self.check_expr(value);
+        self.scopes.pop();
+        self.coerce(&ty, value.span, expected, expected_span);
+        self.finish();
+    }
+
+    /// Falls back to the default types of literals, then reports anything which is still unknown
+    /// and records the types which were inferred
+    fn finish(&mut self) {
+        self.infer.apply_defaults();
+
+        for (name, ty) in std::mem::take(&mut self.lets) {
+            if self.contains_var(&self.infer.resolve_fully(&ty)) {
+                self.diagnostics.push(
+                    Diagnostic::error("type annotations needed")
+                        .with_primary(name.span, "the type of this binding cannot be inferred")
+                        .with_note(format!(
+                            "consider giving it a type, such as `let {} :: Int32`",
+                            name.symbol
+                        )),
+                );
+            }
+        }
+
+        for (value, ty, span) in std::mem::take(&mut self.literals) {
+            let Ty::Primitive(primitive) = self.infer.resolve(&ty) else {
+                continue;
+            };
+            let (min, max) = primitive.bounds();
+            if i128::from(value) < min || i128::from(value) > max {
+                self.diagnostics.push(
+                    Diagnostic::error(format!(
+                        "the literal `{}` does not fit in `{}`",
+                        value, primitive
+                    ))
+                    .with_primary(span, "")
+                    .with_note(format!("`{}` ranges from {} to {}", primitive, min, max)),
+                );
+            }
+        }
+
+        let show = self.defs.traits.lookup(sym::SHOW);
+        for (ty, span, function) in std::mem::take(&mut self.shown) {
+            let ty = self.infer.resolve_fully(&ty);
+            let (Some(show), Some(shown)) = (show, ty.to_type()) else {
+                continue;
+            };
+            if !self.defs.traits.implements(&shown, show, &self.predicates) {
+                let mut diagnostic = Diagnostic::error(format!(
+                    "the trait `{}` is not implemented for `{}`",
+                    sym::SHOW,
+                    self.type_name(&ty)
+                ))
+                .with_primary(span, "")
+                .with_note(format!(
+                    "the values given to `{}` are shown through the trait `{}`",
+                    function,
+                    sym::SHOW
+                ));
+                if let Ty::Param(param) = ty {
+                    diagnostic = diagnostic.with_note(format!(
+                        "consider adding the bound `{} :: {}`",
+                        param,
+                        sym::SHOW
+                    ));
+                }
+                self.diagnostics.push(diagnostic);
+            }
+        }
+
+        for (ty, span) in std::mem::take(&mut self.foreign) {
+            if let Some(ty) = self.infer.resolve_fully(&ty).to_type() {
+                let position = Position::Parameter;
+                check_c_type(&self.defs.types, &ty, position, span, &mut self.diagnostics);
+            }
+        }
+
+        // Bounds on types which are still not fully known can not be checked
+        for (ty, predicate, span) in std::mem::take(&mut self.bounds) {
+            let resolved = self.infer.resolve_fully(&ty).to_type();
+            if let Some(ty) = resolved.filter(|x| !x.contains_error()) {
+                self.require(&Predicate { ty, ..predicate }, span);
+            }
+        }
+
+        let types = &self.defs.types;
+        for pending in std::mem::take(&mut self.matches) {
+            // Tuples are matched against [Type::Error], which any tuple pattern fits
+            let ty = self
+                .infer
+                .resolve_fully(&pending.ty)
+                .to_type()
+                .unwrap_or(Type::Error);
+            let arms: Vec<Arm> = pending
+                .arms
+                .iter()
+                .map(|(pattern, guarded)| Arm {
+                    pattern: pattern::lower_pattern(
+                        types,
+                        pattern,
+                        &ty,
+                        &mut Vec::new(),
+                        &mut self.diagnostics,
+                    ),
+                    guarded: *guarded,
+                    span: pattern.span,
+                })
+                .collect();
+            if pending.is_when {
+                pattern::check_arms(types, &ty, pending.span, &arms, &mut self.diagnostics);
+            }
+        }
+
+        for (id, ty) in std::mem::take(&mut self.types) {
+            let ty = match self.infer.resolve_fully(&ty) {
+                ty if self.contains_var(&ty) => Ty::Error,
+                ty => ty,
+            };
+            self.results.types.insert(id, ty);
+        }
+    }
+
+    fn contains_var(&self, ty: &Ty) -> bool {
+        match ty {
+            Ty::Var(_) => true,
+            Ty::Adt(_, types) | Ty::Tuple(types) => types.iter().any(|x| self.contains_var(x)),
+            Ty::Reference { pointee, .. } | Ty::Generator(pointee) | Ty::Array(pointee, _) => {
+                self.contains_var(pointee)
+            }
+            Ty::Function {
+                parameters,
+                return_type,
+            } => parameters.iter().any(|x| self.contains_var(x)) || self.contains_var(return_type),
+            _ => false,
+        }
+    }
+
+    /// Gets the name of a type as it would be written in the source
+    fn type_name(&self, ty: &Ty) -> String {
+        let list = |types: &[Ty]| {
+            types
+                .iter()
+                .map(|x| self.type_name(x))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self.infer.resolve(ty) {
+            Ty::Primitive(primitive) => primitive.to_string(),
+            Ty::Unit => "()".to_string(),
+            Ty::Adt(id, arguments) if arguments.is_empty() => {
+                self.defs.types.adt(id).name.symbol.to_string()
+            }
+            Ty::Adt(id, arguments) => {
+                format!(
+                    "{}<{}>",
+                    self.defs.types.adt(id).name.symbol,
+                    list(&arguments)
+                )
+            }
+            Ty::Tuple(elements) if elements.len() == 1 => {
+                format!("({},)", self.type_name(&elements[0]))
+            }
+            Ty::Tuple(elements) => format!("({})", list(&elements)),
+            Ty::Reference {
+                kind,
+                mutable,
+                pointee,
+            } => {
+                let mutable = if mutable { "mut " } else { "" };
+                format!("{} {}{}", kind, mutable, self.type_name(&pointee))
+            }
+            Ty::Generator(item) => format!("yield {}", self.type_name(&item)),
+            Ty::Array(element, length) => format!("[{}; {}]", self.type_name(&element), length),
+            Ty::Function {
+                parameters,
+                return_type,
+            } => match self.infer.resolve(&return_type) {
+                Ty::Unit => format!("fun({})", list(&parameters)),
+                _ => format!(
+                    "fun({}) :: {}",
+                    list(&parameters),
+                    self.type_name(&return_type)
+                ),
+            },
+            Ty::Param(name) => name.to_string(),
+            Ty::Var(var) => match self.infer.kind(var) {
+                VarKind::General => "_".to_string(),
+                VarKind::Integer => "{integer}".to_string(),
+                VarKind::Float => "{float}".to_string(),
+            },
+            Ty::Never => "Never".to_string(),
+            Ty::Error => "{unknown}".to_string(),
+        }
+    }
+
+    /// Requires an expression of type `found` to be of type `expected`, reporting where both
+    /// types came from if they differ
+    fn coerce(&mut self, found: &Ty, found_span: Span, expected: &Ty, expected_span: Option<Span>) {
+        // A `mut` reference can be used where one without `mut` is expected
+        let found = match (self.infer.resolve(expected), self.infer.resolve(found)) {
+            (
+                Ty::Reference {
+                    kind,
+                    mutable: false,
+                    ..
+                },
+                Ty::Reference {
+                    kind: found_kind,
+                    mutable: true,
+                    pointee,
+                },
+            ) if kind == found_kind => &Ty::Reference {
+                kind,
+                mutable: false,
+                pointee,
+            },
+            _ => found,
+        };
+        if self.unify(expected, found, found_span) {
+            return;
+        }
+        let expected_name = self.type_name(expected);
+        let found_name = self.type_name(found);
+        let mut diagnostic = Diagnostic::error(format!(
+            "mismatched types: expected `{}`, found `{}`",
+            expected_name, found_name
+        ))
+        .with_primary(found_span, format!("this is `{}`", found_name));
+        if let Some(span) = expected_span {
+            diagnostic = diagnostic.with_secondary(
+                span,
+                format!("expected `{}` because of this", expected_name),
+            );
+        }
+        if let (Ty::Primitive(from), Ty::Primitive(to)) =
+            (self.infer.resolve(found), self.infer.resolve(expected))
+        {
+            if numeric::NUMERIC.contains(&from) && numeric::NUMERIC.contains(&to) {
+                let method = numeric::conversion_method(to);
+                let effect = match numeric::is_lossless(from, to) {
+                    true => String::new(),
+                    false if to.is_float() => format!(", which rounds it to the nearest `{}`", to),
+                    false => ", which traps in a debug build if it does not fit".to_string(),
+                };
+                diagnostic = diagnostic.with_note(format!(
+                    "numbers are never converted implicitly, use `.{}()` to convert it{}",
+                    method, effect
+                ));
+            }
+        }
+        self.diagnostics.push(diagnostic);
+    }
+
+    /// Makes two types the same, recording the array lengths this requires to be the same. Those
+    /// are compared once they have been evaluated, see [TypeckResults::length_checks]
+    fn unify(&mut self, expected: &Ty, found: &Ty, span: Span) -> bool {
+        let unified = self.infer.unify(expected, found);
+        for (expected, found) in self.infer.take_lengths() {
+            self.results.lengths.push((expected, found, span));
+        }
+        unified
+    }
+
+    /// Requires the generic arguments of a type to satisfy the bounds of its declaration
+    fn check_well_formed(&mut self, ty: &Type, span: Span) {
+        let Type::Adt(id, arguments) = ty else {
+            return;
+        };
+        let substitution = self.defs.types.adt(*id).substitution(arguments);
+        for predicate in self.defs.traits.adt_predicates(*id) {
+            let predicate = Predicate {
+                ty: predicate.ty.substitute(&substitution),
+                ..predicate.clone()
+            };
+            self.require(&predicate, span);
+        }
+        for argument in arguments {
+            self.check_well_formed(argument, span);
+        }
+    }
+
+    fn require(&mut self, predicate: &Predicate, span: Span) {
+        let defs = self.defs;
+        if let Some(diagnostic) =
+            defs.traits
+                .require(&defs.types, predicate, &self.predicates, span)
+        {
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    /// Requires the bounds of a callee to hold for the types its generic parameters map to, once
+    /// those are known
+    fn require_bounds(
+        &mut self,
+        predicates: &[Predicate],
+        mapping: &HashMap<Symbol, Ty>,
+        span: Span,
+    ) {
+        for predicate in predicates {
+            let ty = Ty::from_type(&predicate.ty, mapping);
+            self.bounds.push((ty, predicate.clone(), span));
+        }
+    }
+
+    fn declare(&mut self, name: Ident, ty: Ty) {
+        self.scopes
+            .last_mut()
+            .expect("there is always a scope within a function")
+            .insert(name.symbol, ty);
+    }
+
+    fn lookup(&self, name: Symbol) -> Option<Ty> {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|x| x.get(&name).cloned())
+            .or_else(|| {
+                let constant = self.defs.constants.lookup(name)?;
+                Some(Ty::from_type(&constant.ty, &HashMap::new()))
+            })
+    }
+
+    fn check_block(&mut self, block: &Block) -> Ty {
+        self.scopes.push(HashMap::new());
+        let mut diverges = false;
+        for statement in &block.statements {
+            match &statement.kind {
+                StatementKind::Let(let_statement) => {
+                    let annotation = let_statement.ty.as_ref().map(|x| {
+                        let defs = self.defs;
+                        let ty =
+                            defs.types
+                                .resolve_type(x, &self.type_scope, &mut self.diagnostics);
+                        self.check_well_formed(&ty, x.span);
+                        (Ty::from_type(&ty, &HashMap::new()), x.span)
+                    });
+                    let value = let_statement
+                        .value
+                        .as_ref()
+                        .map(|x| (self.check_expr(x), x.span));
+                    let ty = match (annotation, value) {
+                        (Some((annotation, annotation_span)), Some((value, value_span))) => {
+                            self.coerce(&value, value_span, &annotation, Some(annotation_span));
+                            annotation
+                        }
+                        (Some((annotation, _)), None) => annotation,
+                        (None, value) => {
+                            let ty = match value {
+                                Some((value, _)) => value,
+                                None => self.infer.new_var(VarKind::General),
+                            };
+                            self.lets.push((let_statement.name, ty.clone()));
+                            ty
+                        }
+                    };
+                    self.types.insert(statement.id, ty.clone());
+                    self.declare(let_statement.name, ty);
+                }
+                StatementKind::Expr(expr) => {
+                    let ty = self.check_expr(expr);
+                    diverges |= self.infer.resolve(&ty) == Ty::Never;
+                }
+                StatementKind::Error => {}
+            }
+        }
+        let ty = match &block.tail {
+            Some(tail) => self.check_expr(tail),
+            None if diverges => Ty::Never,
+            None => Ty::Unit,
+        };
+        self.scopes.pop();
+        ty
+    }
+
+    fn check_expr(&mut self, expr: &Expr) -> Ty {
+        let ty = self.infer_expr(expr);
+        self.types.insert(expr.id, ty.clone());
+        ty
+    }
+
+    fn infer_expr(&mut self, expr: &Expr) -> Ty {
+        match &expr.kind {
+            ExprKind::Literal(literal) => self.check_literal(literal, expr.span),
+            ExprKind::Name(ident) => self.lookup(ident.symbol).unwrap_or(Ty::Error),
+            // A method named through its trait, which only has a type once it is called
+            ExprKind::Path(path) if self.defs.traits.lookup(path.segments[0].symbol).is_some() => {
+                Ty::Error
+            }
+            ExprKind::Path(path) => match self.resolve_variant(path) {
+                Some((id, [])) => self.instantiate(id).0,
+                Some((_, payload)) => {
+                    self.diagnostics.push(
+                        Diagnostic::error(format!(
+                            "the variant `{}` carries {} and must be called",
+                            path,
+                            values(payload.len())
+                        ))
+                        .with_primary(path.span, "not called"),
+                    );
+                    Ty::Error
+                }
+                None => Ty::Error,
+            },
+            ExprKind::StructLiteral { path, fields } => self.check_struct_literal(path, fields),
+            ExprKind::Unary { operator, operand } => {
+                let ty = self.check_expr(operand);
+                match operator {
+                    UnaryOperator::Not => match self.infer.resolve(&ty) {
+                        Ty::Var(var) if self.infer.kind(var) == VarKind::Integer => ty,
+                        resolved if resolved.is_integer() => ty,
+                        _ => {
+                            let bool_type = Ty::Primitive(PrimitiveType::Bool);
+                            self.check_operand(operator, &ty, operand.span, &bool_type)
+                        }
+                    },
+                    UnaryOperator::Negate => {
+                        self.check_numeric(operator, &ty, operand.span);
+                        ty
+                    }
+                    UnaryOperator::Deref => self.check_deref(&ty, operand.span),
+                }
+            }
+            ExprKind::Reference {
+                kind,
+                mutable,
+                operand,
+            } => Ty::Reference {
+                kind: *kind,
+                mutable: *mutable,
+                pointee: Box::new(self.check_expr(operand)),
+            },
+            ExprKind::Binary {
+                operator,
+                left,
+                right,
+            } => self.check_binary(*operator, left, right),
+            ExprKind::Assign {
+                operator,
+                target,
+                value,
+            } => {
+                let target_type = self.check_expr(target);
+                let value_type = self.check_expr(value);
+                if let Some(operator) = operator {
+                    self.check_numeric(operator, &target_type, target.span);
+                }
+                self.coerce(&value_type, value.span, &target_type, Some(target.span));
+                Ty::Unit
+            }
+            ExprKind::Tuple(elements) if elements.is_empty() => Ty::Unit,
+            ExprKind::Tuple(elements) => {
+                Ty::Tuple(elements.iter().map(|x| self.check_expr(x)).collect())
+            }
+            ExprKind::Array(elements) => {
+                let element = self.infer.new_var(VarKind::General);
+                let mut expected_span = None;
+                for value in elements {
+                    let ty = self.check_expr(value);
+                    self.coerce(&ty, value.span, &element, expected_span);
+                    expected_span = Some(value.span);
+                }
+                Ty::Array(Box::new(element), Length::Count(elements.len() as u64))
+            }
+            // The length is checked on its own, like the value of a constant
+            ExprKind::Repeat { value, length } => {
+                let ty = self.check_expr(value);
+                Ty::Array(Box::new(ty), Length::of(length))
+            }
+            ExprKind::Call { callee, arguments } => self.check_call(expr, callee, arguments),
+            ExprKind::Field { object, field } => {
+                let object_type = self.check_expr(object);
+                self.check_field(&object_type, object.span, *field)
+            }
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => self.check_block(block),
+            ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let condition_type = self.check_expr(condition);
+                self.coerce(
+                    &condition_type,
+                    condition.span,
+                    &Ty::Primitive(PrimitiveType::Bool),
+                    None,
+                );
+                let then_type = self.check_block(then_branch);
+                let then_span = then_branch
+                    .tail
+                    .as_ref()
+                    .map_or(then_branch.span, |x| x.span);
+                let Some(else_branch) = else_branch else {
+                    self.coerce(&then_type, then_span, &Ty::Unit, None);
+                    return Ty::Unit;
+                };
+                let else_type = self.check_expr(else_branch);
+                let else_span = match &else_branch.kind {
+                    ExprKind::Block(block) => block.tail.as_ref().map_or(block.span, |x| x.span),
+                    _ => else_branch.span,
+                };
+                match self.infer.resolve(&then_type) {
+                    Ty::Never => else_type,
+                    _ => {
+                        self.coerce(&else_type, else_span, &then_type, Some(then_span));
+                        then_type
+                    }
+                }
+            }
+            ExprKind::For {
+                kind,
+                pattern,
+                iterable,
+                body,
+            } => {
+                let ty = self.check_expr(iterable);
+                let item = self.check_iterable(&ty, iterable.span);
+                if let Some(refutable) = pattern::find_refutable(pattern) {
+                    self.diagnostics.push(
+                        Diagnostic::error("refutable pattern in `for` loop")
+                            .with_primary(refutable.span, "this pattern does not match every value")
+                            .with_note("use a `when` within the loop to match only some values"),
+                    );
+                }
+                let expected = match kind {
+                    ForKind::In => item,
+                    ForKind::Of => {
+                        if matches!(&pattern.kind, PatternKind::Tuple(x) if x.len() != 2) {
+                            self.diagnostics.push(
+                                Diagnostic::error("mismatched types: expected a pair")
+                                    .with_primary(pattern.span, "expected `(index, value)`")
+                                    .with_note("`for ... of` pairs each value with its index"),
+                            );
+                        }
+                        Ty::Tuple(vec![Ty::Primitive(PrimitiveType::Int64), item])
+                    }
+                };
+                self.matches.push(Match {
+                    ty: expected.clone(),
+                    span: iterable.span,
+                    arms: vec![(pattern.clone(), false)],
+                    is_when: false,
+                });
+                self.scopes.push(HashMap::new());
+                self.check_pattern(pattern, &expected);
+                self.check_block(body);
+                self.scopes.pop();
+                Ty::Unit
+            }
+            ExprKind::When { scrutinee, arms } => self.check_when(scrutinee, arms),
+            ExprKind::Return(value) => {
+                let (return_type, return_span) = self.return_type.clone();
+                match value {
+                    Some(value) if self.yield_type.is_some() => {
+                        self.check_expr(value);
+                        self.diagnostics.push(
+                            Diagnostic::error("cannot return a value from a generator")
+                                .with_primary(value.span, "returned here")
+                                .with_note(
+                                    "use `yield` to produce a value, and `ret` alone to stop",
+                                ),
+                        );
+                    }
+                    Some(value) => {
+                        let ty = self.check_expr(value);
+                        self.coerce(&ty, value.span, &return_type, return_span);
+                    }
+                    None => self.coerce(&Ty::Unit, expr.span, &return_type, return_span),
+                }
+                Ty::Never
+            }
+            ExprKind::Yield(value) => {
+                let ty = self.check_expr(value);
+                match self.yield_type.clone() {
+                    Some(item) => {
+                        let span = self.return_type.1;
+                        self.coerce(&ty, value.span, &item, span);
+                    }
+                    None => self.diagnostics.push(
+                        Diagnostic::error("`yield` outside of a generator")
+                            .with_primary(expr.span, "cannot yield here")
+                            .with_note(format!(
+                                "declare the return type as `yield {}` to make the function a generator",
+                                self.type_name(&ty)
+                            )),
+                    ),
+                }
+                Ty::Unit
+            }
+            ExprKind::Closure(function) => self.check_closure(function),
+            ExprKind::Error => Ty::Error,
+        }
+    }
+
+    /// Infers the types within the body of a closure, which returns from itself rather than the
+    /// function around it and can use the locals of that function
+    fn check_closure(&mut self, function: &Function) -> Ty {
+        let defs = self.defs;
+        let mut scope = HashMap::new();
+        let mut parameters = Vec::new();
+        for parameter in &function.parameters {
+            let ty =
+                defs.types
+                    .resolve_type(&parameter.ty, &self.type_scope, &mut self.diagnostics);
+            self.check_well_formed(&ty, parameter.ty.span);
+            let ty = Ty::from_type(&ty, &HashMap::new());
+            scope.insert(parameter.name.symbol, ty.clone());
+            parameters.push(ty);
+        }
+        let return_type = match &function.return_type {
+            Some(return_type) => {
+                let ty =
+                    defs.types
+                        .resolve_type(return_type, &self.type_scope, &mut self.diagnostics);
+                self.check_well_formed(&ty, return_type.span);
+                Ty::from_type(&ty, &HashMap::new())
+            }
+            None => Ty::Unit,
+        };
+
+        let return_span = function.return_type.as_ref().map(|x| x.span);
+        let outer_return =
+            std::mem::replace(&mut self.return_type, (return_type.clone(), return_span));
+        let outer_yield = self.yield_type.take();
+        if let Ty::Generator(item) = &return_type {
+            self.yield_type = Some(*item.clone());
+            self.return_type.0 = Ty::Unit;
+        }
+        self.scopes.push(scope);
+        let body = function.body.as_ref().expect("a closure always has a body");
+        let ty = self.check_block(body);
+        self.scopes.pop();
+        let (expected, expected_span) = self.return_type.clone();
+        let span = body.tail.as_ref().map_or(body.span, |x| x.span);
+        self.coerce(&ty, span, &expected, expected_span);
+        self.return_type = outer_return;
+        self.yield_type = outer_yield;
+
+        Ty::Function {
+            parameters,
+            return_type: Box::new(return_type),
+        }
+    }
+
+    /// Gets the type of the values iterating something produces, which must be a generator
+    fn check_iterable(&mut self, ty: &Ty, span: Span) -> Ty {
+        match self.infer.resolve(ty) {
+            Ty::Generator(item) => *item,
+            Ty::Var(var) if self.infer.kind(var) == VarKind::General => {
+                self.annotations_needed(span, "a `for` loop".to_string());
+                Ty::Error
+            }
+            Ty::Error | Ty::Never => Ty::Error,
+            ty => {
+                self.diagnostics.push(
+                    Diagnostic::error(format!("`{}` is not an iterator", self.type_name(&ty)))
+                        .with_primary(span, "cannot be iterated")
+                        .with_note("only generators, whose type is `yield T`, can be iterated"),
+                );
+                Ty::Error
+            }
+        }
+    }
+
+    /// Gets the type a `*` produces from its operand, which must be a reference
+    fn check_deref(&mut self, ty: &Ty, span: Span) -> Ty {
+        match self.infer.resolve(ty) {
+            Ty::Reference { pointee, .. } => *pointee,
+            Ty::Var(var) if self.infer.kind(var) == VarKind::General => {
+                self.annotations_needed(span, "`*`".to_string());
+                Ty::Error
+            }
+            Ty::Never | Ty::Error => Ty::Error,
+            _ => {
+                let diagnostic = Diagnostic::error(format!(
+                    "the type `{}` cannot be dereferenced",
+                    self.type_name(ty)
+                ))
+                .with_primary(span, "expected a `ref` or a `ptr`");
+                self.diagnostics.push(diagnostic);
+                Ty::Error
+            }
+        }
+    }
+
+    /// Gets the type of a field of a value, which can be reached through references
+    fn check_field(&mut self, object: &Ty, object_span: Span, field: Ident) -> Ty {
+        match self.auto_deref(object) {
+            Ty::Adt(id, arguments) => {
+                let adt = self.defs.types.adt(id);
+                match adt.field(field.symbol) {
+                    Some((_, definition)) => {
+                        let mapping = substitution(&adt.generics, &arguments);
+                        Ty::from_type(&definition.ty, &mapping)
+                    }
+                    None => {
+                        self.diagnostics.push(unknown_field(adt, field));
+                        Ty::Error
+                    }
+                }
+            }
+            Ty::Var(var) if self.infer.kind(var) == VarKind::General => {
+                self.annotations_needed(object_span, format!("`.{}`", field.symbol));
+                Ty::Error
+            }
+            Ty::Never | Ty::Error => Ty::Error,
+            ty => {
+                self.diagnostics.push(
+                    Diagnostic::error(format!(
+                        "no field `{}` on type `{}`",
+                        field.symbol,
+                        self.type_name(&ty)
+                    ))
+                    .with_primary(field.span, "unknown field"),
+                );
+                Ty::Error
+            }
+        }
+    }
+
+    /// Follows references until reaching the value they point to, since fields can be read
+    /// through any number of them
+    fn auto_deref(&self, ty: &Ty) -> Ty {
+        match self.infer.resolve(ty) {
+            Ty::Reference { pointee, .. } => self.auto_deref(&pointee),
+            ty => ty,
+        }
+    }
+
+    fn check_literal(&mut self, literal: &LiteralKind, span: Span) -> Ty {
+        match *literal {
+            LiteralKind::Int32(value) => {
+                let ty = self.infer.new_var(VarKind::Integer);
+                self.literals.push((value, ty.clone(), span));
+                ty
+            }
+            LiteralKind::Float32(_) => self.infer.new_var(VarKind::Float),
+            _ => Ty::Primitive(PrimitiveType::of_literal(literal)),
+        }
+    }
+
+    /// Requires the operand of an operator to be of a certain type, returning that type
+    fn check_operand(&mut self, operator: impl Display, ty: &Ty, span: Span, expected: &Ty) -> Ty {
+        if !self.unify(expected, ty, span) {
+            let diagnostic = Diagnostic::error(format!(
+                "the operator `{}` cannot be applied to `{}`",
+                operator,
+                self.type_name(ty)
+            ))
+            .with_primary(span, format!("expected `{}`", self.type_name(expected)));
+            self.diagnostics.push(diagnostic);
+        }
+        expected.clone()
+    }
+
+    /// Requires the operand of an arithmetic operator to be a number. Operands whose type is not
+    /// known yet are let through
+    fn check_numeric(&mut self, operator: impl Display, ty: &Ty, span: Span) {
+        match self.infer.resolve(ty) {
+            Ty::Primitive(primitive) if primitive.is_integer() || primitive.is_float() => {}
+            Ty::Var(_) | Ty::Never | Ty::Error => {}
+            _ => {
+                let diagnostic = Diagnostic::error(format!(
+                    "the operator `{}` cannot be applied to `{}`",
+                    operator,
+                    self.type_name(ty)
+                ))
+                .with_primary(span, "expected a number");
+                self.diagnostics.push(diagnostic);
+            }
+        }
+    }
+
+    /// Like [Self::check_numeric] but only for integers
+    fn check_integer(&mut self, operator: impl Display, ty: &Ty, span: Span) {
+        let is_integer = match self.infer.resolve(ty) {
+            Ty::Primitive(primitive) => primitive.is_integer(),
+            Ty::Var(var) => self.infer.kind(var) != VarKind::Float,
+            Ty::Never | Ty::Error => true,
+            _ => false,
+        };
+        if !is_integer {
+            let diagnostic = Diagnostic::error(format!(
+                "the operator `{}` cannot be applied to `{}`",
+                operator,
+                self.type_name(ty)
+            ))
+            .with_primary(span, "expected an integer");
+            self.diagnostics.push(diagnostic);
+        }
+    }
+
+    fn check_binary(&mut self, operator: BinaryOperator, left: &Expr, right: &Expr) -> Ty {
+        let left_type = self.check_expr(left);
+        let right_type = self.check_expr(right);
+        let bool_type = Ty::Primitive(PrimitiveType::Bool);
+        match operator {
+            BinaryOperator::And | BinaryOperator::Or => {
+                self.check_operand(operator, &left_type, left.span, &bool_type);
+                self.check_operand(operator, &right_type, right.span, &bool_type)
+            }
+            _ if operator.is_comparison() => {
+                self.coerce(&right_type, right.span, &left_type, Some(left.span));
+                bool_type
+            }
+            // The amount to shift by can be any integer
+            BinaryOperator::ShiftLeft | BinaryOperator::ShiftRight => {
+                self.check_integer(operator, &left_type, left.span);
+                self.check_integer(operator, &right_type, right.span);
+                left_type
+            }
+            BinaryOperator::BitwiseAnd => {
+                self.coerce(&right_type, right.span, &left_type, Some(left.span));
+                if self.infer.resolve(&left_type) != bool_type {
+                    self.check_integer(operator, &left_type, left.span);
+                }
+                left_type
+            }
+            _ => {
+                self.coerce(&right_type, right.span, &left_type, Some(left.span));
+                self.check_numeric(operator, &left_type, left.span);
+                left_type
+            }
+        }
+    }
+
+    fn annotations_needed(&mut self, span: Span, usage: String) {
+        self.diagnostics
+            .push(Diagnostic::error("type annotations needed").with_primary(
+                span,
+                format!("the type of this must be known to use {}", usage),
+            ));
+    }
+
+    /// Resolves a path such as `Shape::Circle` to the enum it belongs to and the payload of the
+    /// variant, reporting paths which are not variants
+    fn resolve_variant(&mut self, path: &Path) -> Option<(AdtId, &'check [Type])> {
+        let types = &self.defs.types;
+        let (id, index) = types.resolve_variant(path, &mut self.diagnostics)?;
+        Some((id, &types.adt(id).variants()[index].payload))
+    }
+
+    /// Gets the type of a value of an ADT with a new variable for each generic argument, along
+    /// with what each generic parameter maps to
+    fn instantiate(&mut self, id: AdtId) -> (Ty, HashMap<Symbol, Ty>) {
+        let arguments: Vec<Ty> = self
+            .defs
+            .types
+            .adt(id)
+            .generics
+            .iter()
+            .map(|_| self.infer.new_var(VarKind::General))
+            .collect();
+        let mapping = substitution(&self.defs.types.adt(id).generics, &arguments);
+        (Ty::Adt(id, arguments), mapping)
+    }
+
+    fn check_struct_literal(&mut self, path: &Path, fields: &[FieldInit]) -> Ty {
+        let values: Vec<Ty> = fields.iter().map(|x| self.check_expr(&x.value)).collect();
+        let types = &self.defs.types;
+        let name = path.name();
+        let Some(id) = types
+            .lookup(name.symbol)
+            .filter(|_| path.segments.len() == 1)
+        else {
+            self.diagnostics.push(
+                Diagnostic::error(format!("cannot find type `{}`", path))
+                    .with_primary(path.span, "not found"),
+            );
+            return Ty::Error;
+        };
+
+        let adt = types.adt(id);
+        if adt.is_enum() {
+            self.diagnostics.push(
+                Diagnostic::error(format!("expected a type with fields, found {}", adt.describe()))
+                    .with_primary(path.span, "")
+                    .with_note("enum values are created through their variants, such as `Enum::Variant(value)`"),
+            );
+            return Ty::Error;
+        }
+        if adt.is_extern() {
+            self.diagnostics.push(
+                Diagnostic::error(format!(
+                    "expected a type with fields, found {}",
+                    adt.describe()
+                ))
+                .with_primary(path.span, "")
+                .with_note(
+                    "values of an `extern type` can only be made by the functions which provide it",
+                ),
+            );
+            return Ty::Error;
+        }
+
+        let (ty, mapping) = self.instantiate(id);
+        let mut initialised: Vec<&FieldInit> = Vec::new();
+        for (field, value) in fields.iter().zip(&values) {
+            if let Some(previous) = initialised
+                .iter()
+                .find(|x| x.name.symbol == field.name.symbol)
+            {
+                self.diagnostics.push(
+                    Diagnostic::error(format!(
+                        "the field `{}` is initialised more than once",
+                        field.name.symbol
+                    ))
+                    .with_primary(field.name.span, "initialised again here")
+                    .with_secondary(previous.name.span, "first initialised here"),
+                );
+                continue;
+            }
+            match adt.field(field.name.symbol) {
+                Some((_, definition)) => {
+                    let expected = Ty::from_type(&definition.ty, &mapping);
+                    self.coerce(
+                        value,
+                        field.value.span,
+                        &expected,
+                        Some(definition.name.span),
+                    );
+                }
+                None => self.diagnostics.push(unknown_field(adt, field.name)),
+            }
+            initialised.push(field);
+        }
+
+        let missing: Vec<String> = adt
+            .fields()
+            .iter()
+            .filter(|x| !initialised.iter().any(|y| y.name.symbol == x.name.symbol))
+            .map(|x| format!("`{}`", x.name.symbol))
+            .collect();
+        if !missing.is_empty() {
+            let noun = if missing.len() == 1 {
+                "field"
+            } else {
+                "fields"
+            };
+            self.diagnostics.push(
+                Diagnostic::error(format!(
+                    "missing {} {} in the literal of `{}`",
+                    noun,
+                    missing.join(", "),
+                    name.symbol
+                ))
+                .with_primary(path.span, ""),
+            );
+        }
+        ty
+    }
+
+    fn check_call(&mut self, expr: &Expr, callee: &Expr, arguments: &[Expr]) -> Ty {
+        let argument_types: Vec<(Ty, Span)> = arguments
+            .iter()
+            .map(|x| (self.check_expr(x), x.span))
+            .collect();
+        match &callee.kind {
+            ExprKind::Path(path) => match self.defs.traits.lookup(path.segments[0].symbol) {
+                Some(trait_id) => self.check_trait_call(trait_id, path, &argument_types, expr.span),
+                None if self
+                    .defs
+                    .traits
+                    .resolve_associated(&self.defs.types, path)
+                    .is_some() =>
+                {
+                    self.check_associated_call(path, &argument_types, expr.span)
+                }
+                None => {
+                    let Some((id, payload)) = self.resolve_variant(path) else {
+                        return Ty::Error;
+                    };
+                    if payload.len() != argument_types.len() {
+                        self.diagnostics.push(
+                            Diagnostic::error(format!(
+                                "the variant `{}` carries {} but {} supplied",
+                                path,
+                                values(payload.len()),
+                                match argument_types.len() {
+                                    1 => "1 was".to_string(),
+                                    count => format!("{} were", count),
+                                }
+                            ))
+                            .with_primary(expr.span, ""),
+                        );
+                    }
+                    let (ty, mapping) = self.instantiate(id);
+                    let span = path.name().span;
+                    for (expected, (argument, argument_span)) in payload.iter().zip(&argument_types)
+                    {
+                        let expected = Ty::from_type(expected, &mapping);
+                        self.coerce(argument, *argument_span, &expected, Some(span));
+                    }
+                    ty
+                }
+            },
+            ExprKind::Name(name) if self.lookup(name.symbol).is_none() => {
+                let functions = &self.defs.functions;
+                match functions.lookup(name.symbol) {
+                    Some(sig) => {
+                        // The template of a variadic C function is its own business
+                        if sig.is_variadic && sig.abi.is_none() {
+                            self.check_template(sig, arguments);
+                        }
+                        let mapping = self.fresh_generics(sig, HashMap::new());
+                        self.require_bounds(&sig.generics.predicates, &mapping, expr.span);
+                        self.check_arguments(sig, &mapping, &argument_types, 0, expr.span)
+                    }
+                    // Name resolution has reported it
+                    None => Ty::Error,
+                }
+            }
+            ExprKind::Field { object, field } => {
+                let object_type = self.check_expr(object);
+                self.check_method_call(object_type, object.span, *field, &argument_types, expr)
+            }
+            _ => {
+                let ty = self.check_expr(callee);
+                self.check_closure_call(&ty, callee.span, &argument_types, expr.span)
+            }
+        }
+    }
+
+    /// Checks a call of a closure, which is any callee that is not named by a path
+    fn check_closure_call(
+        &mut self,
+        ty: &Ty,
+        callee_span: Span,
+        arguments: &[(Ty, Span)],
+        span: Span,
+    ) -> Ty {
+        let (parameters, return_type) = match self.infer.resolve(ty) {
+            Ty::Function {
+                parameters,
+                return_type,
+            } => (parameters, return_type),
+            Ty::Var(var) if self.infer.kind(var) == VarKind::General => {
+                self.annotations_needed(callee_span, "a call".to_string());
+                return Ty::Error;
+            }
+            Ty::Error | Ty::Never => return Ty::Error,
+            ty => {
+                self.diagnostics.push(
+                    Diagnostic::error(format!("`{}` is not a function", self.type_name(&ty)))
+                        .with_primary(callee_span, "cannot be called")
+                        .with_note(
+                            "only functions and closures, whose type is `fun(...)`, can be called",
+                        ),
+                );
+                return Ty::Error;
+            }
+        };
+        if arguments.len() != parameters.len() {
+            self.diagnostics.push(
+                Diagnostic::error(format!(
+                    "the closure {}",
+                    takes(parameters.len(), arguments.len())
+                ))
+                .with_primary(span, "")
+                .with_secondary(callee_span, format!("this is `{}`", self.type_name(ty))),
+            );
+        }
+        for (expected, (argument, argument_span)) in parameters.iter().zip(arguments) {
+            self.coerce(argument, *argument_span, expected, Some(callee_span));
+        }
+        *return_type
+    }
+
+    /// Maps each generic parameter of a signature to a new variable, on top of an existing
+    /// mapping
+    fn fresh_generics(
+        &mut self,
+        sig: &FunctionSig,
+        mut mapping: HashMap<Symbol, Ty>,
+    ) -> HashMap<Symbol, Ty> {
+        for param in &sig.generics.params {
+            mapping.insert(param.symbol, self.infer.new_var(VarKind::General));
+        }
+        mapping
+    }
+
+    /// Checks the arguments of a call against the parameters of the callee, skipping the first
+    /// `skip` parameters which were supplied some other way, and gets the type the call returns
+    fn check_arguments(
+        &mut self,
+        sig: &FunctionSig,
+        mapping: &HashMap<Symbol, Ty>,
+        arguments: &[(Ty, Span)],
+        skip: usize,
+        span: Span,
+    ) -> Ty {
+        let parameters = &sig.parameters[skip.min(sig.parameters.len())..];
+        let too_many = arguments.len() > parameters.len() && !sig.is_variadic;
+        if arguments.len() < parameters.len() || too_many {
+            let what = if sig.has_self { "method" } else { "function" };
+            let mut message =
+                arity_message(what, sig.name.symbol, parameters.len(), arguments.len());
+            if sig.is_variadic {
+                message = message.replacen(" takes ", " takes at least ", 1);
+            }
+            self.diagnostics.push(
+                Diagnostic::error(message)
+                    .with_primary(span, "")
+                    .with_secondary(sig.name.span, format!("the {} is declared here", what)),
+            );
+        }
+        let parameter_spans = &sig.parameter_spans[skip.min(sig.parameter_spans.len())..];
+        for ((parameter, parameter_span), (argument, argument_span)) in
+            parameters.iter().zip(parameter_spans).zip(arguments)
+        {
+            let expected = Ty::from_type(parameter, mapping);
+            self.coerce(argument, *argument_span, &expected, Some(*parameter_span));
+        }
+        for (argument, argument_span) in arguments.iter().skip(parameters.len()) {
+            match (sig.is_variadic, sig.abi) {
+                (true, Some(_)) => self.foreign.push((argument.clone(), *argument_span)),
+                (true, None) => {
+                    self.shown
+                        .push((argument.clone(), *argument_span, sig.name.symbol))
+                }
+                (false, _) => {}
+            }
+        }
+        Ty::from_type(&sig.return_type, mapping)
+    }
+
+    /// Checks a call such as `Vec::new()` of a function declared by an inherent implementation,
+    /// whose generic parameters all get new variables
+    fn check_associated_call(&mut self, path: &Path, arguments: &[(Ty, Span)], span: Span) -> Ty {
+        let defs = self.defs;
+        let Some((implementation, sig)) = defs.traits.resolve_associated(&defs.types, path) else {
+            return Ty::Error;
+        };
+        let mapping = implementation
+            .generics
+            .params
+            .iter()
+            .map(|x| (x.symbol, self.infer.new_var(VarKind::General)))
+            .collect();
+        let mapping = self.fresh_generics(sig, mapping);
+        self.require_bounds(&implementation.generics.predicates, &mapping, span);
+        self.require_bounds(&sig.generics.predicates, &mapping, span);
+        self.check_arguments(sig, &mapping, arguments, 0, span)
+    }
+
+    /// Checks a call of a method declared by an inherent implementation for the type of the
+    /// receiver, or for the type it refers to. The generic arguments of the receiver need not be
+    /// known yet since the type of the implementation is unified with it
+    fn check_inherent_call(
+        &mut self,
+        receiver: &Ty,
+        method: Ident,
+        arguments: &[(Ty, Span)],
+        span: Span,
+    ) -> Option<Ty> {
+        let traits = &self.defs.traits;
+        let mut receiver = self.infer.resolve(receiver);
+        let (implementation, sig) = loop {
+            if let Ty::Array(element, _) = &receiver {
+                let vec = self.defs.types.lookup(sym::VEC)?;
+                if !ARRAY_METHODS.contains(&method.symbol.as_str()) {
+                    return None;
+                }
+                receiver = Ty::Adt(vec, vec![*element.clone()]);
+            }
+            let head = match &receiver {
+                Ty::Adt(id, _) => Some(Type::Adt(*id, Vec::new())),
+                Ty::Primitive(primitive) => Some(Type::Primitive(*primitive)),
+                _ => None,
+            };
+            let found = head
+                .and_then(|x| traits.inherent_function(&x, method.symbol))
+                .filter(|(_, sig)| sig.has_self);
+            if let Some(found) = found {
+                break found;
+            }
+            match receiver {
+                Ty::Reference { pointee, .. } => receiver = self.infer.resolve(&pointee),
+                _ => return None,
+            }
+        };
+        let mapping: HashMap<Symbol, Ty> = implementation
+            .generics
+            .params
+            .iter()
+            .map(|x| (x.symbol, self.infer.new_var(VarKind::General)))
+            .collect();
+        let self_type = Ty::from_type(&implementation.self_type, &mapping);
+        self.unify(&self_type, &receiver, span);
+        let mapping = self.fresh_generics(sig, mapping);
+        self.require_bounds(&implementation.generics.predicates, &mapping, span);
+        self.require_bounds(&sig.generics.predicates, &mapping, span);
+        Some(self.check_arguments(sig, &mapping, arguments, 1, span))
+    }
+
+    /// Checks a call such as `value.show()`. The type of `value` must be known at this point in
+    /// order to find the trait providing the method, or the [Intrinsic] if it is a number
+    fn check_method_call(
+        &mut self,
+        object: Ty,
+        object_span: Span,
+        method: Ident,
+        arguments: &[(Ty, Span)],
+        call: &Expr,
+    ) -> Ty {
+        let resolved = match self.infer.resolve(&object) {
+            Ty::Var(var) if self.infer.kind(var) == VarKind::General => {
+                self.annotations_needed(object_span, format!("`.{}()`", method.symbol));
+                return Ty::Error;
+            }
+            // Numbers whose type is still open take on their default type to find the method
+            Ty::Var(var) => {
+                let ty = match self.infer.kind(var) {
+                    VarKind::Float => Ty::Primitive(PrimitiveType::Float32),
+                    _ => Ty::Primitive(PrimitiveType::Int32),
+                };
+                self.unify(&object, &ty, object_span);
+                ty
+            }
+            ty => self.infer.resolve_fully(&ty),
+        };
+        if let Ty::Primitive(primitive) = resolved {
+            if let Some(intrinsic) = Intrinsic::lookup(primitive, method.symbol) {
+                let ty = self.check_intrinsic(intrinsic, primitive, object_span, method, arguments);
+                self.results.intrinsics.insert(call.id, intrinsic);
+                return ty;
+            }
+        }
+        if let Some(ty) = self.check_inherent_call(&resolved, method, arguments, call.span) {
+            return ty;
+        }
+        let Some(object_type) = resolved.to_type().filter(|x| !x.contains_error()) else {
+            return Ty::Error;
+        };
+
+        let traits = &self.defs.traits;
+        let candidates = traits.traits_with_method(method.symbol);
+        // Like inherent methods, trait methods are found for the type a reference points to too
+        let mut receiver = &object_type;
+        let trait_id = loop {
+            let found = candidates
+                .iter()
+                .copied()
+                .find(|x| traits.implements(receiver, *x, &self.predicates));
+            if let Some(trait_id) = found {
+                break trait_id;
+            }
+            match receiver {
+                Type::Reference { pointee, .. } => receiver = pointee,
+                _ => {
+                    let diagnostic =
+                        self.missing_method(&resolved, &object_type, method, &candidates);
+                    self.diagnostics.push(diagnostic);
+                    return Ty::Error;
+                }
+            }
+        };
+        let sig = traits
+            .trait_def(trait_id)
+            .method(method.symbol)
+            .expect("the trait was found through the method");
+        let self_type = Ty::from_type(receiver, &HashMap::new());
+        let mapping = self.fresh_generics(sig, HashMap::from([(sym::SELF_TYPE, self_type)]));
+        self.require_bounds(&sig.generics.predicates, &mapping, call.span);
+        self.check_arguments(sig, &mapping, arguments, 1, call.span)
+    }
+
+    /// Describes why no method was found for a call such as `value.show()`, given the traits
+    /// which provide a method of that name
+    fn missing_method(
+        &self,
+        object: &Ty,
+        object_type: &Type,
+        method: Ident,
+        candidates: &[TraitId],
+    ) -> Diagnostic {
+        let traits = &self.defs.traits;
+        let type_name = self.type_name(object);
+        match candidates {
+            [] => Diagnostic::error(format!(
+                "no method named `{}` found for `{}`",
+                method.symbol, type_name
+            ))
+            .with_primary(method.span, "method not found"),
+            [trait_id] => {
+                let name = traits.trait_def(*trait_id).name.symbol;
+                let diagnostic = Diagnostic::error(format!(
+                    "the trait `{}` is not implemented for `{}`",
+                    name, type_name
+                ))
+                .with_primary(
+                    method.span,
+                    format!("`{}` requires `{}`", method.symbol, name),
+                )
+                .with_note(format!(
+                    "the method `{}` is provided by the trait `{}`",
+                    method.symbol, name
+                ));
+                match object_type {
+                    Type::Param(param) => diagnostic
+                        .with_note(format!("consider adding the bound `{} :: {}`", param, name)),
+                    _ => diagnostic,
+                }
+            }
+            candidates => {
+                let names: Vec<String> = candidates
+                    .iter()
+                    .map(|x| format!("`{}`", traits.trait_def(*x).name.symbol))
+                    .collect();
+                Diagnostic::error(format!(
+                    "no method named `{}` found for `{}`",
+                    method.symbol, type_name
+                ))
+                .with_primary(method.span, "method not found")
+                .with_note(format!(
+                    "the method is provided by the traits {}, none of which are implemented for `{}`",
+                    names.join(", "),
+                    type_name
+                ))
+            }
+        }
+    }
+
+    /// Checks that the template given to a variadic function such as `println`, if it is written
+    /// out, has one placeholder for each value after it
+    fn check_template(&mut self, sig: &FunctionSig, arguments: &[Expr]) {
+        let Some(template) = arguments.first() else {
+            return;
+        };
+        let ExprKind::Literal(LiteralKind::Str(text)) = &template.kind else {
+            return;
+        };
+        let values = arguments.len().saturating_sub(sig.parameters.len());
+        match format::split_template(text.as_str()) {
+            Err(error) => self.diagnostics.push(
+                Diagnostic::error("invalid template")
+                    .with_primary(template.span, error.to_string())
+                    .with_note("a brace is written as `{{` or `}}` within a template"),
+            ),
+            Ok(pieces) if pieces.len() - 1 != values => self.diagnostics.push(
+                Diagnostic::error(format!(
+                    "the template has {} but {} given",
+                    match pieces.len() - 1 {
+                        1 => "1 placeholder".to_string(),
+                        count => format!("{} placeholders", count),
+                    },
+                    match values {
+                        1 => "1 value was".to_string(),
+                        count => format!("{} values were", count),
+                    }
+                ))
+                .with_primary(template.span, "")
+                .with_secondary(sig.name.span, "the function is declared here"),
+            ),
+            Ok(_) => {}
+        }
+    }
+
+    /// Checks a call to an [Intrinsic] of a number, whose arguments are numbers of the same type
+    fn check_intrinsic(
+        &mut self,
+        intrinsic: Intrinsic,
+        receiver: PrimitiveType,
+        receiver_span: Span,
+        method: Ident,
+        arguments: &[(Ty, Span)],
+    ) -> Ty {
+        if arguments.len() != intrinsic.arity() {
+            self.diagnostics.push(
+                Diagnostic::error(arity_message(
+                    "method",
+                    method.symbol,
+                    intrinsic.arity(),
+                    arguments.len(),
+                ))
+                .with_primary(method.span, ""),
+            );
+        }
+        let expected = Ty::Primitive(receiver);
+        for (argument, argument_span) in arguments.iter().take(intrinsic.arity()) {
+            self.coerce(argument, *argument_span, &expected, Some(receiver_span));
+        }
+        Ty::Primitive(intrinsic.return_type(receiver))
+    }
+
+    /// Checks a call such as `Show::show(value)`, where `Self` is inferred from the arguments
+    fn check_trait_call(
+        &mut self,
+        trait_id: TraitId,
+        path: &Path,
+        arguments: &[(Ty, Span)],
+        span: Span,
+    ) -> Ty {
+        let traits = &self.defs.traits;
+        let definition = traits.trait_def(trait_id);
+        let [trait_name, method] = path.segments.as_slice() else {
+            self.diagnostics.push(
+                Diagnostic::error(format!("cannot find `{}`", path))
+                    .with_primary(path.span, "not found"),
+            );
+            return Ty::Error;
+        };
+        let Some(sig) = definition.method(method.symbol) else {
+            self.diagnostics.push(
+                Diagnostic::error(format!(
+                    "no method named `{}` in the trait `{}`",
+                    method.symbol, trait_name.symbol
+                ))
+                .with_primary(method.span, "")
+                .with_secondary(definition.name.span, "trait declared here"),
+            );
+            return Ty::Error;
+        };
+        let self_type = self.infer.new_var(VarKind::General);
+        let mapping = self.fresh_generics(sig, HashMap::from([(sym::SELF_TYPE, self_type)]));
+        let required = Predicate {
+            ty: Type::Param(sym::SELF_TYPE),
+            trait_id,
+            span: trait_name.span,
+        };
+        self.require_bounds(&[required], &mapping, span);
+        self.require_bounds(&sig.generics.predicates, &mapping, span);
+        self.check_arguments(sig, &mapping, arguments, 0, span)
+    }
+
+    fn check_when(&mut self, scrutinee: &Expr, arms: &[WhenArm]) -> Ty {
+        let scrutinee_type = self.check_expr(scrutinee);
+        let mut result: Option<(Ty, Span)> = None;
+        for arm in arms {
+            self.scopes.push(HashMap::new());
+            self.check_pattern(&arm.pattern, &scrutinee_type);
+            if let Some(guard) = &arm.guard {
+                let ty = self.check_expr(guard);
+                self.coerce(&ty, guard.span, &Ty::Primitive(PrimitiveType::Bool), None);
+            }
+            let ty = self.check_expr(&arm.body);
+            self.scopes.pop();
+
+            match &result {
+                Some((expected, expected_span)) => {
+                    let (expected, expected_span) = (expected.clone(), *expected_span);
+                    self.coerce(&ty, arm.body.span, &expected, Some(expected_span));
+                }
+                None if self.infer.resolve(&ty) == Ty::Never => {}
+                None => result = Some((ty, arm.body.span)),
+            }
+        }
+        self.matches.push(Match {
+            ty: scrutinee_type,
+            span: scrutinee.span,
+            arms: arms
+                .iter()
+                .map(|x| (x.pattern.clone(), x.guard.is_some()))
+                .collect(),
+            is_when: true,
+        });
+        result.map_or(Ty::Never, |(ty, _)| ty)
+    }
+
+    /// Declares the bindings of a pattern. Patterns which do not fit the scrutinee are reported
+    /// once it has been inferred, so nothing is reported here
+    fn check_pattern(&mut self, pattern: &Pattern, expected: &Ty) {
+        match &pattern.kind {
+            PatternKind::Wildcard => {}
+            PatternKind::Binding { name, .. } => self.declare(*name, expected.clone()),
+            PatternKind::Literal(literal) | PatternKind::Range { start: literal, .. } => {
+                let ty = match literal {
+                    LiteralKind::Int32(_) => self.infer.new_var(VarKind::Integer),
+                    LiteralKind::Float32(_) => self.infer.new_var(VarKind::Float),
+                    _ => Ty::Primitive(PrimitiveType::of_literal(literal)),
+                };
+                self.unify(expected, &ty, pattern.span);
+            }
+            PatternKind::Tuple(elements) => {
+                let types: Vec<Ty> = elements
+                    .iter()
+                    .map(|_| self.infer.new_var(VarKind::General))
+                    .collect();
+                self.unify(expected, &Ty::Tuple(types.clone()), pattern.span);
+                for (element, ty) in elements.iter().zip(&types) {
+                    self.check_pattern(element, ty);
+                }
+            }
+            PatternKind::Variant { path, payload } => {
+                let types = &self.defs.types;
+                let found = types.resolve_variant(path, &mut Vec::new());
+                let Some((id, types)) =
+                    found.map(|(id, index)| (id, &types.adt(id).variants()[index].payload))
+                else {
+                    for element in payload {
+                        self.check_pattern(element, &Ty::Error);
+                    }
+                    return;
+                };
+                let (ty, mapping) = self.instantiate(id);
+                self.unify(expected, &ty, pattern.span);
+                for (index, element) in payload.iter().enumerate() {
+                    let ty = types
+                        .get(index)
+                        .map_or(Ty::Error, |x| Ty::from_type(x, &mapping));
+                    self.check_pattern(element, &ty);
+                }
+            }
+        }
+        self.types.insert(pattern.id, expected.clone());
+    }
+}
+
+/// The methods of `Vec` which arrays have too, since they are kept as one at runtime. Those which
+/// change the length are left out
+const ARRAY_METHODS: &[&str] = &["len", "is_empty", "get", "set", "iter", "join"];
+
+/// The patterns of a `when` or a `for` loop, and the type of the value they match
+struct Match {
+    ty: Ty,
+    span: Span,
+    /// Each pattern along with whether its arm has a guard
+    arms: Vec<(Pattern, bool)>,
+    /// Whether the arms must be exhaustive and reachable, which is only checked for a `when`
+    is_when: bool,
+}
+
+fn unknown_field(adt: &AdtDef, field: Ident) -> Diagnostic {
+    let diagnostic = Diagnostic::error(format!(
+        "no field `{}` on type `{}`",
+        field.symbol, adt.name.symbol
+    ))
+    .with_primary(field.span, "unknown field");
+    if adt.fields().is_empty() {
+        return diagnostic;
+    }
+    let fields: Vec<String> = adt
+        .fields()
+        .iter()
+        .map(|x| format!("`{}`", x.name.symbol))
+        .collect();
+    diagnostic.with_note(format!("the available fields are {}", fields.join(", ")))
+}
+
+/// Describes a call with the wrong number of arguments
+fn arity_message(what: &str, name: Symbol, expected: usize, found: usize) -> String {
+    format!("the {} `{}` {}", what, name, takes(expected, found))
+}
+
+/// Describes how many arguments a callee takes and how many it was given
+fn takes(expected: usize, found: usize) -> String {
+    format!(
+        "takes {} but {} supplied",
+        match expected {
+            1 => "1 argument".to_string(),
+            count => format!("{} arguments", count),
+        },
+        match found {
+            1 => "1 was".to_string(),
+            count => format!("{} were", count),
+        }
+    )
+}
+
+/// Maps each generic parameter to an argument, in order
+fn substitution(params: &[Ident], arguments: &[Ty]) -> HashMap<Symbol, Ty> {
+    params
+        .iter()
+        .map(|x| x.symbol)
+        .zip(arguments.iter().cloned())
+        .collect()
+}
@@ -0,0 +1,119 @@
+use std::collections::HashMap;
+
+use check::TypeChecker;
+use shark_core::{diagnostic::Diagnostic, source::Span, symbol::sym};
+use shark_parse::{
+    ast::{ItemKind, Module, NodeId},
+    visit,
+};
+use shark_sema::{
+    generics::{Predicate, TypeScope},
+    numeric::Intrinsic,
+    ty::{Length, PrimitiveType, Type},
+    ModuleDefs,
+};
+use ty::Ty;
+
+pub mod check;
+pub mod ty;
+
+#[cfg(test)]
+pub mod tests;
+
+/// The types inferred for a [Module]
+#[derive(Debug, Clone, Default)]
+pub struct TypeckResults {
+    /// The type of every expression, pattern and `let` statement within a function body or the
+    /// value of a constant. Types which could not be inferred are [Ty::Error]
+    types: HashMap<NodeId, Ty>,
+    /// The [Intrinsic] every call to a method of a number refers to
+    intrinsics: HashMap<NodeId, Intrinsic>,
+    /// Every pair of array lengths which must be the same, the expected one first, along with
+    /// where they were required to be
+    lengths: Vec<(Length, Length, Span)>,
+}
+
+impl TypeckResults {
+    pub fn type_of(&self, id: NodeId) -> Option<&Ty> {
+        self.types.get(&id)
+    }
+
+    /// Gets the [Intrinsic] a call such as `x.wrapping_add(y)` refers to, or [None] if it calls
+    /// a trait method
+    pub fn intrinsic_of(&self, call: NodeId) -> Option<Intrinsic> {
+        self.intrinsics.get(&call).copied()
+    }
+
+    /// Gets the pairs of array lengths which must be the same, which can only be compared once
+    /// the lengths written as expressions have been evaluated
+    pub fn length_checks(&self) -> &[(Length, Length, Span)] {
+        &self.lengths
+    }
+}
+
+/// Step four of compilation. Infers the types within every function and method body of a
+/// [Module], and within the values of its constants, discriminants and array lengths, and checks
+/// how they use what semantic analysis has collected from its declarations
+pub fn check_module(module: &Module, defs: &ModuleDefs) -> (TypeckResults, Vec<Diagnostic>) {
+    let mut checker = TypeChecker::new(defs);
+    for item in &module.items {
+        match &item.kind {
+            ItemKind::Function(function) => {
+                if let Some(sig) = defs.functions.signature_of(item.id) {
+                    checker.check_function(function, sig, &TypeScope::default(), &[]);
+                }
+            }
+            ItemKind::Trait(trait_decl) => {
+                let Some(trait_id) = defs.traits.trait_of_item(item.id) else {
+                    continue;
+                };
+                let predicates = [Predicate {
+                    ty: Type::Param(sym::SELF_TYPE),
+                    trait_id,
+                    span: trait_decl.name.span,
+                }];
+                let methods = &defs.traits.trait_def(trait_id).methods;
+                for (method, sig) in trait_decl.methods.iter().zip(methods) {
+                    checker.check_function(method, sig, &TypeScope::within_trait(), &predicates);
+                }
+            }
+            ItemKind::Impl(impl_decl) => {
+                let Some(implementation) = defs.traits.impl_of_item(item.id) else {
+                    continue;
+                };
+                let mut scope = TypeScope::default().with_params(&implementation.generics.params);
+                scope.self_type = Some(implementation.self_type.clone());
+                for (method, sig) in impl_decl.methods.iter().zip(&implementation.methods) {
+                    checker.check_function(
+                        method,
+                        sig,
+                        &scope,
+                        &implementation.generics.predicates,
+                    );
+                }
+            }
+            ItemKind::Enum(enum_decl) => {
+                let int64 = Ty::Primitive(PrimitiveType::Int64);
+                for value in enum_decl
+                    .variants
+                    .iter()
+                    .filter_map(|x| x.discriminant.as_ref())
+                {
+                    checker.check_const(value, &int64, None);
+                }
+            }
+            ItemKind::Const(const_decl) => {
+                if let Some(constant) = defs.constants.const_of_item(item.id) {
+                    let ty = Ty::from_type(&constant.ty, &HashMap::new());
+                    checker.check_const(&const_decl.value, &ty, Some(const_decl.ty.span));
+                }
+            }
+            _ => {}
+        }
+    }
+    let int64 = Ty::Primitive(PrimitiveType::Int64);
+    for length in visit::array_lengths(module) {
+        checker.check_const(length, &int64, None);
+    }
+    (checker.results, checker.diagnostics)
+}
@@ -0,0 +1,780 @@
+use shark_core::symbol::Symbol;
+use shark_parse::{
+    ast::{ExprKind, ItemKind, StatementKind},
+    parse,
+};
+use shark_sema::{
+    ty::{Length, PrimitiveType, Type},
+    ModuleDefs,
+};
+
+use crate::{check_module, ty::Ty};
+
+/// Checks a module, returning the messages of the diagnostics from type checking alone
+fn check(source: &str) -> Vec<String> {
+    let module = parse(None, source).expect("failed to parse module");
+    let (defs, _) = shark_sema::check_module(&module);
+    let (_, diagnostics) = check_module(&module, &defs);
+    diagnostics.iter().map(|x| x.message.clone()).collect()
+}
+
+/// Checks a module, returning the messages of the diagnostics from semantic analysis followed by
+/// those from type checking
+fn check_all(source: &str) -> (ModuleDefs, Vec<String>) {
+    let module = parse(None, source).expect("failed to parse module");
+    let (defs, mut diagnostics) = shark_sema::check_module(&module);
+    let (_, mut found) = check_module(&module, &defs);
+    diagnostics.append(&mut found);
+    let messages = diagnostics.iter().map(|x| x.message.clone()).collect();
+    (defs, messages)
+}
+
+/// Checks a module, returning the type inferred for each `let` in the first function
+fn let_types(source: &str) -> Vec<(Symbol, Ty)> {
+    let module = parse(None, source).expect("failed to parse module");
+    let (defs, diagnostics) = shark_sema::check_module(&module);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+    let (results, diagnostics) = check_module(&module, &defs);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+    let ItemKind::Function(function) = &module.items[0].kind else {
+        panic!("expected a function");
+    };
+    let body = function.body.as_ref().expect("missing body");
+    body.statements
+        .iter()
+        .filter_map(|x| match &x.kind {
+            StatementKind::Let(let_statement) => Some((
+                let_statement.name.symbol,
+                results.type_of(x.id).expect("missing type").clone(),
+            )),
+            _ => None,
+        })
+        .collect()
+}
+
+#[test]
+fn test_let_inference() {
+    let types = let_types(
+        "fun main(flag :: Bool) {
+            let a :: Float32 = 3.14;
+            let b = 1;
+            let c :: UInt8 = b;
+            let d = 2.5;
+            let e = (a, d, flag);
+            let f;
+            f = b + 2;
+            let g = 7;
+        }",
+    );
+    let primitive = Ty::Primitive;
+    assert_eq!(
+        types,
+        [
+            (Symbol::intern("a"), primitive(PrimitiveType::Float32)),
+            (Symbol::intern("b"), primitive(PrimitiveType::UInt8)),
+            (Symbol::intern("c"), primitive(PrimitiveType::UInt8)),
+            (Symbol::intern("d"), primitive(PrimitiveType::Float32)),
+            (
+                Symbol::intern("e"),
+                Ty::Tuple(vec![
+                    primitive(PrimitiveType::Float32),
+                    primitive(PrimitiveType::Float32),
+                    primitive(PrimitiveType::Bool),
+                ])
+            ),
+            (Symbol::intern("f"), primitive(PrimitiveType::UInt8)),
+            (Symbol::intern("g"), primitive(PrimitiveType::Int32)),
+        ]
+    );
+}
+
+#[test]
+fn test_generic_inference() {
+    let source = "enum Option<T> { Some(T), None }
+        type Pair<A, B> { first :: A, second :: B }
+        fun pick<T>(a :: T, b :: T) :: T { a }
+        fun main() {
+            let none = Option::None;
+            let some :: Option<Int64> = none;
+            let pair = Pair { first = 1uint8, second = Option::Some('c') };
+            let second = pair.second;
+            let picked = pick(2, 3int64);
+            let nested = when second { Option::Some(x) => x, Option::None => 'z' };
+        }";
+    let module = parse(None, source).expect("failed to parse module");
+    let (defs, _) = shark_sema::check_module(&module);
+    let (results, diagnostics) = check_module(&module, &defs);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+    let option = defs
+        .types
+        .lookup(Symbol::intern("Option"))
+        .expect("missing type");
+    let ItemKind::Function(function) = &module.items[3].kind else {
+        panic!("expected a function");
+    };
+    let body = function.body.as_ref().expect("missing body");
+    let types: Vec<&Ty> = body
+        .statements
+        .iter()
+        .map(|x| results.type_of(x.id).expect("missing type"))
+        .collect();
+    let char_type = Ty::Primitive(PrimitiveType::Char);
+    assert_eq!(
+        types[0],
+        &Ty::Adt(option, vec![Ty::Primitive(PrimitiveType::Int64)])
+    );
+    assert_eq!(types[3], &Ty::Adt(option, vec![char_type.clone()]));
+    assert_eq!(types[4], &Ty::Primitive(PrimitiveType::Int64));
+    assert_eq!(types[5], &char_type);
+
+    // Every expression has a type as well
+    let StatementKind::Let(let_statement) = &body.statements[2].kind else {
+        panic!("expected a let");
+    };
+    let value = let_statement.value.as_ref().expect("missing value");
+    assert!(matches!(value.kind, ExprKind::StructLiteral { .. }));
+    assert!(matches!(results.type_of(value.id), Some(Ty::Adt(..))));
+}
+
+#[test]
+fn test_mismatched_types() {
+    let errors = check(
+        "type Point { x :: Int32, y :: Int32 }
+        fun area(point :: Point) :: Int32 { point.x * point.y }
+        fun main(point :: Point) :: Bool {
+            let a :: Float32 = \"text\";
+            let b :: Point = Point { x = 1, y = true };
+            let c = area(1, 2);
+            let d :: Str = area(point);
+            if 1 { 2 } else { 'c' };
+            let e = !3.0 && true;
+            point.x = 2.5;
+            ret 1;
+            false
+        }
+        fun tail() :: Str { 5 }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "mismatched types: expected `Float32`, found `Str`",
+            "mismatched types: expected `Int32`, found `Bool`",
+            "the function `area` takes 1 argument but 2 were supplied",
+            "mismatched types: expected `Point`, found `{integer}`",
+            "mismatched types: expected `Str`, found `Int32`",
+            "mismatched types: expected `Bool`, found `{integer}`",
+            "mismatched types: expected `{integer}`, found `Char`",
+            "the operator `!` cannot be applied to `{float}`",
+            "mismatched types: expected `Int32`, found `{float}`",
+            "mismatched types: expected `Bool`, found `{integer}`",
+            "mismatched types: expected `Str`, found `{integer}`",
+        ]
+    );
+}
+
+#[test]
+fn test_literals_and_annotations() {
+    let errors = check(
+        "enum Option<T> { Some(T), None }
+        fun main() {
+            let small :: UInt8 = 300;
+            let negative :: UInt64 = 5;
+            let wide :: Float64 = 1.5;
+            let whole :: Float64 = 1;
+            let fixed :: UInt8 = 1int64;
+            let unknown = Option::None;
+            let shifted = 1 << 2.0;
+            let added = true + false;
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "mismatched types: expected `Float64`, found `{integer}`",
+            "mismatched types: expected `UInt8`, found `Int64`",
+            "the operator `<<` cannot be applied to `{float}`",
+            "the operator `+` cannot be applied to `Bool`",
+            "type annotations needed",
+            "the literal `300` does not fit in `UInt8`",
+        ]
+    );
+}
+
+#[test]
+fn test_constants() {
+    let types = let_types(
+        "fun main() {
+            let a = LIMIT;
+            let b = SMALL + 1;
+            let c = NAME;
+        }
+        const LIMIT :: Int64 = 1 << 40;
+        const SMALL :: UInt8 = 255;
+        const NAME :: Str = \"shark\";",
+    );
+    assert_eq!(
+        types,
+        [
+            (Symbol::intern("a"), Ty::Primitive(PrimitiveType::Int64)),
+            (Symbol::intern("b"), Ty::Primitive(PrimitiveType::UInt8)),
+            (Symbol::intern("c"), Ty::Primitive(PrimitiveType::Str)),
+        ]
+    );
+
+    let errors = check(
+        "const FLAG :: Bool = 1;
+        const SMALL :: UInt8 = 256;
+        enum Colour { Red = 1, Green = true }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "mismatched types: expected `Bool`, found `{integer}`",
+            "the literal `256` does not fit in `UInt8`",
+            "mismatched types: expected `Int64`, found `Bool`",
+        ]
+    );
+}
+
+#[test]
+fn test_mismatch_spans() {
+    let source = "fun main() {\n    let a :: Float32 = true;\n}";
+    let module = parse(None, source).expect("failed to parse module");
+    let (defs, _) = shark_sema::check_module(&module);
+    let (_, diagnostics) = check_module(&module, &defs);
+    assert_eq!(
+        diagnostics[0].render(None, source),
+        "error: mismatched types: expected `Float32`, found `Bool`
+ --> unknown:2:24
+  |
+2 |     let a :: Float32 = true;
+  |              -------   ^^^^ this is `Bool`
+  |              |
+  |              expected `Float32` because of this
+"
+    );
+}
+
+#[test]
+fn test_generators() {
+    let types = let_types(
+        "fun main(numbers :: yield Int64) {
+            let mut total = 0;
+            for n in numbers {
+                total += n;
+            }
+            let mut last = 0;
+            for (index, _) of numbers {
+                last = index;
+            }
+        }",
+    );
+    let primitive = Ty::Primitive;
+    assert_eq!(
+        types,
+        [
+            (Symbol::intern("total"), primitive(PrimitiveType::Int64)),
+            (Symbol::intern("last"), primitive(PrimitiveType::Int64)),
+        ]
+    );
+
+    let errors = check(
+        "fun count(limit :: Int32) :: yield Int32 {
+            yield limit;
+            yield true;
+            ret limit;
+        }
+        fun main() {
+            yield 1;
+            for x in 10 {}
+            for (a, b, c) of count(3) {}
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "mismatched types: expected `Int32`, found `Bool`",
+            "cannot return a value from a generator",
+            "`yield` outside of a generator",
+            "`{integer}` is not an iterator",
+            "mismatched types: expected a pair",
+        ]
+    );
+}
+
+#[test]
+fn test_numeric_methods() {
+    let types = let_types(
+        "fun main() {
+            let x :: Int32 = 5;
+            let a = x.to_int64();
+            let b = x.wrapping_add(1);
+            let c = (2.5).to_uint8();
+        }",
+    );
+    assert_eq!(
+        types[1..],
+        [
+            (Symbol::intern("a"), Ty::Primitive(PrimitiveType::Int64)),
+            (Symbol::intern("b"), Ty::Primitive(PrimitiveType::Int32)),
+            (Symbol::intern("c"), Ty::Primitive(PrimitiveType::UInt8)),
+        ]
+    );
+
+    let source = "fun main() {
+            let x :: Int32 = 5;
+            let a :: Int64 = x;
+            let b :: Int8 = x;
+            let c :: Float32 = x;
+            let d = x.trapping_sub(1, 2);
+            let e = x.saturating_mul(1int64);
+        }";
+    let module = parse(None, source).expect("failed to parse module");
+    let (defs, _) = shark_sema::check_module(&module);
+    let (_, diagnostics) = check_module(&module, &defs);
+    let errors: Vec<_> = diagnostics
+        .iter()
+        .map(|x| (x.message.as_str(), x.notes.first().map(|x| x.as_str())))
+        .collect();
+    assert_eq!(
+        errors,
+        [
+            (
+                "mismatched types: expected `Int64`, found `Int32`",
+                Some("numbers are never converted implicitly, use `.to_int64()` to convert it")
+            ),
+            (
+                "mismatched types: expected `Int8`, found `Int32`",
+                Some(
+                    "numbers are never converted implicitly, use `.to_int8()` to convert it, \
+                     which traps in a debug build if it does not fit"
+                )
+            ),
+            (
+                "mismatched types: expected `Float32`, found `Int32`",
+                Some(
+                    "numbers are never converted implicitly, use `.to_float32()` to convert it, \
+                     which rounds it to the nearest `Float32`"
+                )
+            ),
+            (
+                "the method `trapping_sub` takes 1 argument but 2 were supplied",
+                None
+            ),
+            (
+                "mismatched types: expected `Int32`, found `Int64`",
+                Some(
+                    "numbers are never converted implicitly, use `.to_int32()` to convert it, \
+                     which traps in a debug build if it does not fit"
+                )
+            ),
+        ]
+    );
+}
+
+#[test]
+fn test_struct_literals() {
+    let (_, errors) = check_all(
+        "type Point { x :: Float32, y :: Float32 }
+        enum Shape { Circle(Float32) }
+        fun main() {
+            let a = Point { x = 1.0, y = 2.0 };
+            let b = Point { x = 1.0, x = 2.0, z = 3.0 };
+            let c = Shape { x = 1.0 };
+            let d = Vector { x = 1.0 };
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "the field `x` is initialised more than once",
+            "no field `z` on type `Point`",
+            "missing field `y` in the literal of `Point`",
+            "expected a type with fields, found enum `Shape`",
+            "cannot find type `Vector`",
+        ]
+    );
+}
+
+#[test]
+fn test_field_access() {
+    let (_, errors) = check_all(
+        "type Point { x :: Float32, y :: Float32 }
+        type Line { start :: Point, end :: Point }
+        fun length(line :: Line) :: Float32 {
+            let dx = line.end.x - line.start.x;
+            let dz = line.end.z;
+            let value :: Int32 = 5;
+            ret dx + value.x + unknown.anything;
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "no field `z` on type `Point`",
+            "no field `x` on type `Int32`"
+        ]
+    );
+}
+
+#[test]
+fn test_variants() {
+    let (_, errors) = check_all(
+        "type Point { x :: Float32, y :: Float32 }
+        enum Shape { Circle(Float32), Rect(Point, Point), Empty }
+        fun main() {
+            let a = Shape::Circle(1.0);
+            let b = Shape::Empty;
+            let c = Shape::Rect(Point { x = 1.0, y = 1.0 });
+            let d = Shape::Circle;
+            let e = Shape::Square;
+            let f = Point::Origin;
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "the variant `Shape::Rect` carries 2 values but 1 was supplied",
+            "the variant `Shape::Circle` carries 1 value and must be called",
+            "no variant named `Square` in enum `Shape`",
+            "expected an enum, found type `Point`",
+        ]
+    );
+}
+
+#[test]
+fn test_when_exhaustiveness() {
+    let (_, errors) = check_all(
+        "type Point { x :: Float32, y :: Float32 }
+        enum Shape { Circle(Float32), Rect(Point, Point), Empty }
+        fun area(shape :: Shape) {
+            let a = when shape {
+                Shape::Circle(r) => r,
+                Shape::Empty => 0.0,
+            };
+            let b = when shape {
+                Shape::Circle(r) if r > 1.0 => r,
+                _ => 0.0,
+            };
+            let c = when shape {
+                Shape::Circle(r) if r > 1.0 => r,
+                Shape::Rect(a, b) => 1.0,
+                Shape::Empty => 0.0,
+            };
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "non-exhaustive patterns: `Shape::Rect(_, _)` not covered",
+            "non-exhaustive patterns: `Shape::Circle(_)` not covered",
+        ]
+    );
+}
+
+#[test]
+fn test_when_unreachable_arms() {
+    let (_, diagnostics) = check_all(
+        "enum Direction { North, East, South, West }
+        fun turn(direction :: Direction, flag :: Bool) {
+            when direction {
+                Direction::North => 1,
+                _ => 2,
+                Direction::South => 3,
+            };
+            when (flag, direction) {
+                (true, _) => 1,
+                (false, Direction::North) => 2,
+                (_, Direction::North) => 3,
+                (false, _) => 4,
+            };
+        }",
+    );
+    assert_eq!(diagnostics, ["unreachable pattern", "unreachable pattern"]);
+}
+
+#[test]
+fn test_when_tuples_and_ranges() {
+    let (_, errors) = check_all(
+        "fun classify(a :: Bool, b :: Bool, small :: Int8, letter :: Char, count :: UInt32) {
+            when (a, b) {
+                (true, true) => 1,
+                (false, _) => 2,
+            };
+            when small {
+                -128..0 => 1,
+                0 => 2,
+                1..=127 => 3,
+            };
+            when letter {
+                'a'..='z' => 1,
+                'A'..='Z' => 2,
+            };
+            when count {
+                0 => 1,
+                2..=10 => 2,
+            };
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "non-exhaustive patterns: `(true, false)` not covered",
+            "non-exhaustive patterns: `'\\0'..='@'`, `'['..='`'` and `'{'..='\\u{10ffff}'` not covered",
+            "non-exhaustive patterns: `1uint32` and `11uint32..=4294967295uint32` not covered",
+        ]
+    );
+}
+
+#[test]
+fn test_pattern_errors() {
+    let (_, errors) = check_all(
+        "enum Shape { Circle(Float32), Empty }
+        fun main(shape :: Shape, value :: UInt8, shapes :: yield Shape) {
+            when shape {
+                Shape::Circle(r, r) => 1,
+                Shape::Square => 2,
+                Point::Empty => 3,
+                5 => 4,
+                _ => 5,
+            };
+            when value {
+                300 => 1,
+                9..1 => 2,
+                1.0..2.0 => 3,
+                true => 4,
+                _ => 5,
+            };
+            for Shape::Empty in shapes {}
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "refutable pattern in `for` loop",
+            "the variant `Shape::Circle` carries 1 value but the pattern has 2",
+            "no variant named `Square` in enum `Shape`",
+            "cannot find type `Point`",
+            "mismatched types: expected `Shape`, found `Int32`",
+            "the literal `300` does not fit in `UInt8`",
+            "lower range bound must be less than upper",
+            "only integers and characters can be used in range patterns",
+            "mismatched types: expected `UInt8`, found `Bool`",
+        ]
+    );
+}
+
+#[test]
+fn test_generic_types() {
+    let (defs, errors) = check_all(
+        "enum Option<T> { Some(T), None }
+        type Pair<A, B> { first :: A, second :: B }
+        fun main(pair :: Pair<Int32, Option<Bool>>, bad :: Option, worse :: Int32<Bool>) {
+            let first = pair.second;
+            when first {
+                Option::Some(true) => 1,
+                Option::None => 2,
+            };
+            let made = Pair { first = 1, second = 'c' };
+            when made.second {
+                'a' => 1,
+            };
+        }
+        fun oops(value :: T) {}",
+    );
+    assert_eq!(
+        errors,
+        [
+            "the type `Option` takes 1 generic argument but none were supplied",
+            "the type `Int32` takes no generic arguments but 1 was supplied",
+            "cannot find type `T`",
+            "non-exhaustive patterns: `Option::Some(false)` not covered",
+            "non-exhaustive patterns: `'\\0'..='`'` and `'b'..='\\u{10ffff}'` not covered",
+        ]
+    );
+
+    let pair = defs
+        .types
+        .lookup(Symbol::intern("Pair"))
+        .expect("missing type");
+    let int32 = Type::Primitive(PrimitiveType::Int32);
+    let instance = Type::Adt(pair, vec![int32.clone(), int32]);
+    assert_eq!(defs.types.type_name(&instance), "Pair<Int32, Int32>");
+    assert_eq!(defs.types.layout_of(&instance).map(|x| x.size), Some(8));
+}
+
+#[test]
+fn test_trait_bounds() {
+    let (_, errors) = check_all(
+        "trait Show { fun show(self) :: Str; fun twice(self) :: Str { self.show() } }
+        type Point { x :: Int32 }
+        type Wrapper<T> where T :: Show { value :: T }
+        impl Show for Int32 { fun show(self) :: Str { \"int\" } }
+        impl<T :: Show> Show for Wrapper<T> { fun show(self) :: Str { self.value.show() } }
+        fun print<T :: Show>(value :: T) :: Str { value.twice() }
+        fun debug<T>(value :: T) { value.show(); }
+        fun main(point :: Point, wrapped :: Wrapper<Int32>) {
+            print(1);
+            print(wrapped);
+            print(point);
+            Show::show(point);
+            point.show();
+            point.missing();
+            let nested :: Wrapper<Wrapper<Point>> = wrapped;
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "the trait `Show` is not implemented for `T`",
+            "the trait `Show` is not implemented for `Point`",
+            "no method named `missing` found for `Point`",
+            "the trait `Show` is not implemented for `Wrapper<Point>`",
+            "the trait `Show` is not implemented for `Point`",
+            "mismatched types: expected `Wrapper<Wrapper<Point>>`, found `Wrapper<Int32>`",
+            "the trait `Show` is not implemented for `Point`",
+            "the trait `Show` is not implemented for `Point`",
+        ]
+    );
+}
+
+#[test]
+fn test_trait_method_receivers() {
+    let errors = check(
+        "trait Area {
+            fun area(self :: ref Self) :: Int32;
+            fun double(self :: ref Self) :: Int32 { self.area() * 2 }
+        }
+        fun twice<T>(x :: ref T) :: Int32 where T :: Area { x.double() }
+        fun nested<T>(x :: ref ref T) :: Int32 where T :: Area { x.area() }
+        fun unbounded<T>(x :: ref T) :: Int32 { x.area() }",
+    );
+    assert_eq!(errors, ["the trait `Area` is not implemented for `ref T`"]);
+}
+
+#[test]
+fn test_unknown_methods() {
+    let (_, errors) = check_all(
+        "fun main(x :: Float64, n :: Int8) :: Int8 {
+            let a = x.wrapping_add(1.0);
+            let b = x.to_uint8();
+            n.saturating_div(b).trapping_subtract(1)
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "no method named `wrapping_add` found for `Float64`",
+            "mismatched types: expected `Int8`, found `UInt8`",
+            "no method named `trapping_subtract` found for `Int8`",
+        ]
+    );
+}
+
+#[test]
+fn test_closures() {
+    let types = let_types(
+        "fun main(n :: Int64) {
+            let add = fun(x :: Int64) :: Int64 { x + n };
+            let sum = add(1);
+            let log = fun(message :: Str) {};
+            let count = fun() :: yield Int32 { yield 1; };
+        }",
+    );
+    let int64 = Ty::Primitive(PrimitiveType::Int64);
+    assert_eq!(
+        types,
+        [
+            (
+                Symbol::intern("add"),
+                Ty::Function {
+                    parameters: vec![int64.clone()],
+                    return_type: Box::new(int64.clone()),
+                }
+            ),
+            (Symbol::intern("sum"), int64),
+            (
+                Symbol::intern("log"),
+                Ty::Function {
+                    parameters: vec![Ty::Primitive(PrimitiveType::Str)],
+                    return_type: Box::new(Ty::Unit),
+                }
+            ),
+            (
+                Symbol::intern("count"),
+                Ty::Function {
+                    parameters: Vec::new(),
+                    return_type: Box::new(Ty::Generator(Box::new(Ty::Primitive(
+                        PrimitiveType::Int32
+                    )))),
+                }
+            ),
+        ]
+    );
+
+    let errors = check(
+        "fun main(n :: Int32) :: Int32 {
+            let add = fun(x :: Int32) :: Int32 { ret x + n; };
+            let wrong :: fun(Bool) = add;
+            n(1) + add(1, 2) + add(true)
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "mismatched types: expected `fun(Bool)`, found `fun(Int32) :: Int32`",
+            "`Int32` is not a function",
+            "the closure takes 1 argument but 2 were supplied",
+            "mismatched types: expected `Int32`, found `Bool`",
+        ]
+    );
+}
+
+#[test]
+fn test_arrays() {
+    let types = let_types(
+        "fun main(flag :: Bool) {
+            let a = [1int64, 2, 3];
+            let b :: [Bool; 2] = [flag; 2];
+            let c = [a, [4, 5, 6]];
+        }",
+    );
+    let int64 = Ty::Primitive(PrimitiveType::Int64);
+    let array = |element, count| Ty::Array(Box::new(element), Length::Count(count));
+    assert_eq!(
+        types,
+        [
+            (Symbol::intern("a"), array(int64.clone(), 3)),
+            (
+                Symbol::intern("b"),
+                array(Ty::Primitive(PrimitiveType::Bool), 2)
+            ),
+            (Symbol::intern("c"), array(array(int64, 3), 2)),
+        ]
+    );
+
+    // Lengths which are not literals are compared once they have been evaluated
+    let errors = check(
+        "const N :: Int64 = 2;
+        fun main(values :: [Int32; 2], other :: [Int32; N]) :: [Bool; 2] {
+            let a = [1, true];
+            let b = [values; 'c'];
+            let c :: [Int32; 3] = values;
+            let d :: [Int32; 3] = other;
+            let e = [values, [4, 5]].len();
+            values
+        }",
+    );
+    assert_eq!(
+        errors,
+        [
+            "mismatched types: expected `{integer}`, found `Bool`",
+            "mismatched types: expected `[Int32; 3]`, found `[Int32; 2]`",
+            "no method named `len` found for `[[Int32; 2]; 2]`",
+            "mismatched types: expected `[Bool; 2]`, found `[Int32; 2]`",
+            "mismatched types: expected `Int64`, found `Char`",
+        ]
+    );
+}
@@ -0,0 +1,388 @@
+//! The types used while inferring, which unlike [Type] can contain type variables standing for
+//! types which are not known yet. Variables are solved by unification, see [InferTable::unify]
+
+use std::collections::HashMap;
+
+use shark_core::symbol::{sym, Symbol};
+use shark_parse::ast::ReferenceKind;
+use shark_sema::ty::{AdtId, Length, PrimitiveType, Type};
+
+/// Identifies a type variable within an [InferTable]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TypeVar(pub u32);
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Ty {
+    Primitive(PrimitiveType),
+    Unit,
+    Adt(AdtId, Vec<Ty>),
+    /// `(a, b)`, which is only produced by tuple expressions
+    Tuple(Vec<Ty>),
+    /// `ref T`, `ref mut T`, `ptr T` or `ptr mut T`
+    Reference {
+        kind: ReferenceKind,
+        mutable: bool,
+        pointee: Box<Ty>,
+    },
+    /// `yield T`, a generator producing values of type `T`
+    Generator(Box<Ty>),
+    /// `[T; N]`, whose length is only compared once it has been evaluated
+    Array(Box<Ty>, Length),
+    /// `fun(A, B) :: R`, a closure
+    Function {
+        parameters: Vec<Ty>,
+        return_type: Box<Ty>,
+    },
+    /// A generic parameter of the function being checked, which stands for one particular type
+    Param(Symbol),
+    Var(TypeVar),
+    /// The type of expressions which never produce a value, such as `return`. It fits wherever any
+    /// other type is expected
+    Never,
+    /// A type which could not be worked out. The error has already been reported
+    Error,
+}
+
+impl Ty {
+    /// Converts a [Type] from a declaration, replacing the generic parameters which have a mapping
+    pub fn from_type(ty: &Type, mapping: &HashMap<Symbol, Ty>) -> Self {
+        match ty {
+            Type::Primitive(primitive) => Self::Primitive(*primitive),
+            Type::Unit => Self::Unit,
+            Type::Adt(id, arguments) => Self::Adt(
+                *id,
+                arguments
+                    .iter()
+                    .map(|x| Self::from_type(x, mapping))
+                    .collect(),
+            ),
+            Type::Reference {
+                kind,
+                mutable,
+                pointee,
+            } => Self::Reference {
+                kind: *kind,
+                mutable: *mutable,
+                pointee: Box::new(Self::from_type(pointee, mapping)),
+            },
+            Type::Generator(item) => Self::Generator(Box::new(Self::from_type(item, mapping))),
+            Type::Array(element, length) => {
+                Self::Array(Box::new(Self::from_type(element, mapping)), *length)
+            }
+            Type::Function {
+                parameters,
+                return_type,
+            } => Self::Function {
+                parameters: parameters
+                    .iter()
+                    .map(|x| Self::from_type(x, mapping))
+                    .collect(),
+                return_type: Box::new(Self::from_type(return_type, mapping)),
+            },
+            Type::Param(name) => mapping.get(name).cloned().unwrap_or(Self::Param(*name)),
+            Type::Error => Self::Error,
+        }
+    }
+
+    /// Converts back into a [Type], which is only possible once every variable has been solved
+    /// and for the types a [Type] can represent
+    pub fn to_type(&self) -> Option<Type> {
+        Some(match self {
+            Self::Primitive(primitive) => Type::Primitive(*primitive),
+            Self::Unit => Type::Unit,
+            Self::Adt(id, arguments) => Type::Adt(
+                *id,
+                arguments.iter().map(Self::to_type).collect::<Option<_>>()?,
+            ),
+            Self::Reference {
+                kind,
+                mutable,
+                pointee,
+            } => Type::Reference {
+                kind: *kind,
+                mutable: *mutable,
+                pointee: Box::new(pointee.to_type()?),
+            },
+            Self::Generator(item) => Type::Generator(Box::new(item.to_type()?)),
+            Self::Array(element, length) => Type::Array(Box::new(element.to_type()?), *length),
+            Self::Function {
+                parameters,
+                return_type,
+            } => Type::Function {
+                parameters: parameters
+                    .iter()
+                    .map(Self::to_type)
+                    .collect::<Option<_>>()?,
+                return_type: Box::new(return_type.to_type()?),
+            },
+            Self::Param(name) => Type::Param(*name),
+            Self::Error => Type::Error,
+            Self::Tuple(_) | Self::Var(_) | Self::Never => return None,
+        })
+    }
+
+    pub fn is_integer(&self) -> bool {
+        matches!(self, Self::Primitive(x) if x.is_integer())
+    }
+
+    /// Replaces `Self` within a type inferred for a trait method by the type the method is
+    /// generated for, leaving it as it is outside of methods
+    pub fn substitute_self(&self, self_type: Option<&Ty>) -> Ty {
+        let Some(self_type) = self_type else {
+            return self.clone();
+        };
+        match self {
+            Self::Param(name) if *name == sym::SELF_TYPE => self_type.clone(),
+            Self::Adt(id, arguments) => Self::Adt(
+                *id,
+                arguments
+                    .iter()
+                    .map(|x| x.substitute_self(Some(self_type)))
+                    .collect(),
+            ),
+            Self::Tuple(elements) => Self::Tuple(
+                elements
+                    .iter()
+                    .map(|x| x.substitute_self(Some(self_type)))
+                    .collect(),
+            ),
+            Self::Reference {
+                kind,
+                mutable,
+                pointee,
+            } => Self::Reference {
+                kind: *kind,
+                mutable: *mutable,
+                pointee: Box::new(pointee.substitute_self(Some(self_type))),
+            },
+            Self::Generator(item) => {
+                Self::Generator(Box::new(item.substitute_self(Some(self_type))))
+            }
+            Self::Array(element, length) => {
+                Self::Array(Box::new(element.substitute_self(Some(self_type))), *length)
+            }
+            Self::Function {
+                parameters,
+                return_type,
+            } => Self::Function {
+                parameters: parameters
+                    .iter()
+                    .map(|x| x.substitute_self(Some(self_type)))
+                    .collect(),
+                return_type: Box::new(return_type.substitute_self(Some(self_type))),
+            },
+            _ => self.clone(),
+        }
+    }
+}
+
+/// What a type variable can be solved to
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VarKind {
+    /// Any type
+    General,
+    /// Only integer types, created for integer literals which adapt to their context
+    Integer,
+    /// Only floating point types, created for float literals which adapt to their context
+    Float,
+}
+
+impl VarKind {
+    /// The type a variable of this kind becomes when nothing else decides it
+    fn default_type(&self) -> Option<Ty> {
+        match self {
+            Self::General => None,
+            Self::Integer => Some(Ty::Primitive(PrimitiveType::Int32)),
+            Self::Float => Some(Ty::Primitive(PrimitiveType::Float32)),
+        }
+    }
+
+    fn accepts(&self, ty: &Ty) -> bool {
+        match (self, ty) {
+            (Self::General, _) => true,
+            (Self::Integer, Ty::Primitive(primitive)) => primitive.is_integer(),
+            (Self::Float, Ty::Primitive(primitive)) => primitive.is_float(),
+            _ => false,
+        }
+    }
+}
+
+/// The type variables of a function along with what has been learned about them
+#[derive(Debug, Clone, Default)]
+pub struct InferTable {
+    vars: Vec<(VarKind, Option<Ty>)>,
+    /// The array lengths which unification required to be the same, see [InferTable::take_lengths]
+    lengths: Vec<(Length, Length)>,
+}
+
+impl InferTable {
+    pub fn new_var(&mut self, kind: VarKind) -> Ty {
+        self.vars.push((kind, None));
+        Ty::Var(TypeVar(self.vars.len() as u32 - 1))
+    }
+
+    pub fn kind(&self, var: TypeVar) -> VarKind {
+        self.vars[var.0 as usize].0
+    }
+
+    /// Follows solved variables until reaching a type which is not a solved variable
+    pub fn resolve(&self, ty: &Ty) -> Ty {
+        let mut ty = ty.clone();
+        while let Ty::Var(var) = ty {
+            match &self.vars[var.0 as usize].1 {
+                Some(solved) => ty = solved.clone(),
+                None => break,
+            }
+        }
+        ty
+    }
+
+    /// Replaces every solved variable anywhere within a type
+    pub fn resolve_fully(&self, ty: &Ty) -> Ty {
+        match self.resolve(ty) {
+            Ty::Adt(id, arguments) => Ty::Adt(
+                id,
+                arguments.iter().map(|x| self.resolve_fully(x)).collect(),
+            ),
+            Ty::Tuple(elements) => {
+                Ty::Tuple(elements.iter().map(|x| self.resolve_fully(x)).collect())
+            }
+            Ty::Reference {
+                kind,
+                mutable,
+                pointee,
+            } => Ty::Reference {
+                kind,
+                mutable,
+                pointee: Box::new(self.resolve_fully(&pointee)),
+            },
+            Ty::Generator(item) => Ty::Generator(Box::new(self.resolve_fully(&item))),
+            Ty::Array(element, length) => Ty::Array(Box::new(self.resolve_fully(&element)), length),
+            Ty::Function {
+                parameters,
+                return_type,
+            } => Ty::Function {
+                parameters: parameters.iter().map(|x| self.resolve_fully(x)).collect(),
+                return_type: Box::new(self.resolve_fully(&return_type)),
+            },
+            ty => ty,
+        }
+    }
+
+    /// Makes two types the same by solving the variables within them, returning false if they
+    /// can never be the same
+    pub fn unify(&mut self, left: &Ty, right: &Ty) -> bool {
+        let left = self.resolve(left);
+        let right = self.resolve(right);
+        match (&left, &right) {
+            (Ty::Error, _) | (_, Ty::Error) | (Ty::Never, _) | (_, Ty::Never) => true,
+            (Ty::Var(x), Ty::Var(y)) if x == y => true,
+            (Ty::Var(x), Ty::Var(y)) => {
+                let kind = match (self.kind(*x), self.kind(*y)) {
+                    (VarKind::General, kind) | (kind, VarKind::General) => kind,
+                    (x, y) if x == y => x,
+                    _ => return false,
+                };
+                self.vars[y.0 as usize].0 = kind;
+                self.vars[x.0 as usize].1 = Some(right.clone());
+                true
+            }
+            (Ty::Var(var), ty) | (ty, Ty::Var(var)) => {
+                if !self.kind(*var).accepts(ty) || self.occurs(*var, ty) {
+                    return false;
+                }
+                self.vars[var.0 as usize].1 = Some(ty.clone());
+                true
+            }
+            (Ty::Adt(left_id, left_arguments), Ty::Adt(right_id, right_arguments)) => {
+                left_id == right_id && self.unify_all(left_arguments, right_arguments)
+            }
+            (Ty::Tuple(left_elements), Ty::Tuple(right_elements)) => {
+                left_elements.len() == right_elements.len()
+                    && self.unify_all(left_elements, right_elements)
+            }
+            (
+                Ty::Reference {
+                    kind: left_kind,
+                    mutable: left_mutable,
+                    pointee: left_pointee,
+                },
+                Ty::Reference {
+                    kind: right_kind,
+                    mutable: right_mutable,
+                    pointee: right_pointee,
+                },
+            ) => {
+                left_kind == right_kind
+                    && left_mutable == right_mutable
+                    && self.unify(left_pointee, right_pointee)
+            }
+            (Ty::Generator(left_item), Ty::Generator(right_item)) => {
+                self.unify(left_item, right_item)
+            }
+            (Ty::Array(left_element, left_length), Ty::Array(right_element, right_length)) => {
+                match (left_length, right_length) {
+                    (Length::Count(x), Length::Count(y)) if x != y => return false,
+                    (x, y) if x != y => self.lengths.push((*x, *y)),
+                    _ => {}
+                }
+                self.unify(left_element, right_element)
+            }
+            (
+                Ty::Function {
+                    parameters: left_parameters,
+                    return_type: left_return,
+                },
+                Ty::Function {
+                    parameters: right_parameters,
+                    return_type: right_return,
+                },
+            ) => {
+                left_parameters.len() == right_parameters.len()
+                    && self.unify_all(left_parameters, right_parameters)
+                    && self.unify(left_return, right_return)
+            }
+            _ => left == right,
+        }
+    }
+
+    /// Takes the pairs of array lengths which have been required to be the same since this was
+    /// last called. Lengths written as expressions are only known once they have been evaluated,
+    /// so unifying them always succeeds and they are compared afterwards
+    pub fn take_lengths(&mut self) -> Vec<(Length, Length)> {
+        std::mem::take(&mut self.lengths)
+    }
+
+    fn unify_all(&mut self, left: &[Ty], right: &[Ty]) -> bool {
+        left.iter()
+            .zip(right)
+            .fold(true, |result, (x, y)| self.unify(x, y) && result)
+    }
+
+    /// Checks if a variable appears within a type, which would make solving it to that type
+    /// produce an infinite type
+    fn occurs(&self, var: TypeVar, ty: &Ty) -> bool {
+        match self.resolve(ty) {
+            Ty::Var(other) => other == var,
+            Ty::Adt(_, types) | Ty::Tuple(types) => types.iter().any(|x| self.occurs(var, x)),
+            Ty::Reference { pointee, .. } | Ty::Generator(pointee) | Ty::Array(pointee, _) => {
+                self.occurs(var, &pointee)
+            }
+            Ty::Function {
+                parameters,
+                return_type,
+            } => parameters.iter().any(|x| self.occurs(var, x)) || self.occurs(var, &return_type),
+            _ => false,
+        }
+    }
+
+    /// Solves every integer and float variable which is still unsolved to its default type
+    pub fn apply_defaults(&mut self) {
+        for index in 0..self.vars.len() {
+            let (kind, solved) = &self.vars[index];
+            if solved.is_none() {
+                self.vars[index].1 = kind.default_type();
+            }
+        }
+    }
+}
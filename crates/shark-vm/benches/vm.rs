@@ -0,0 +1,91 @@
+//! Compares running programs on the VM against running them on the interpreter: calls, loops
+//! over generators of `Int64`, and building strings with the standard library
+
+use criterion::{criterion_group, Criterion};
+use shark_sema::numeric::Overflow;
+use shark_std::runtime::Host;
+
+const FIB: &str = "fun fib(n :: Int64) :: Int64 {
+    if n < 2 {
+        ret n;
+    }
+    fib(n - 1) + fib(n - 2)
+}
+
+pub fun main() :: Int64 {
+    fib(25)
+}";
+
+const LOOP: &str = "fun count(n :: Int64) :: yield Int64 {
+    if n > 0 {
+        for x in count(n - 1) {
+            yield x;
+        }
+        yield n;
+    }
+}
+
+pub fun main() :: Int64 {
+    let mut total :: Int64 = 0;
+    for x in count(200) {
+        for y in count(200) {
+            total += x * y & 255;
+        }
+    }
+    total
+}";
+
+const STRINGS: &str = "pub fun main() :: Int64 {
+    let mut words = Vec::new();
+    for (index, _) of \"x\".repeat(2000).chars().iter() {
+        words.push(format(\"{}:{}\", index, index * index));
+    }
+    let text = words.join(\",\");
+    text.to_upper().replace(\":\", \"=\").len()
+}";
+
+fn run_benchmark(criterion: &mut Criterion) {
+    for (name, source) in [("fib", FIB), ("loop", LOOP), ("strings", STRINGS)] {
+        let checked = shark_testing::check(source);
+        let bodies = checked.lower();
+        let (module, defs, types) = (&checked.module, &checked.defs, &checked.types);
+        let program = shark_vm::compile_module(module, defs, types, &bodies, Overflow::Trap);
+
+        let mut group = criterion.benchmark_group(name);
+        group.sample_size(10);
+        group.bench_function("vm", |bencher| {
+            bencher.iter(|| {
+                shark_vm::run(&program, Host::default()).expect("the program failed on the VM")
+            })
+        });
+        group.bench_function("interpreter", |bencher| {
+            bencher.iter(|| {
+                shark_interp::run(
+                    module,
+                    defs,
+                    types,
+                    &bodies,
+                    Overflow::Trap,
+                    Host::default(),
+                )
+                .expect("the program failed on the interpreter")
+            })
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, run_benchmark);
+
+fn main() {
+    // The interpreter recurses deeper than the main thread has stack for
+    std::thread::Builder::new()
+        .stack_size(shark_interp::STACK_SIZE)
+        .spawn(|| {
+            benches();
+            Criterion::default().configure_from_args().final_summary();
+        })
+        .expect("failed to spawn the benchmark thread")
+        .join()
+        .expect("the benchmark panicked");
+}
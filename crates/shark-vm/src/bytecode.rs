@@ -0,0 +1,310 @@
+//! The instructions of the VM along with the [Program] they make up. Instructions work on the
+//! registers of the frame of the function they are within, and every register is a slot that can
+//! hold any [crate::value::Value]. The registers of a function start with its locals, parameters
+//! first, followed by the temporaries its expressions need
+
+use std::fmt::Display;
+
+use shark_core::source::Span;
+use shark_sema::{numeric::Overflow, ty::PrimitiveType};
+
+/// A register within the frame of a function
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Reg(pub u16);
+
+impl Display for Reg {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "r{}", self.0)
+    }
+}
+
+/// An operation on two numbers of the same type which can fail, as integer arithmetic can overflow
+/// and integers can be divided by zero
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ArithOp {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    BitwiseAnd,
+    ShiftLeft,
+    ShiftRight,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CompareOp {
+    Greater,
+    Lesser,
+    GreaterOrEqual,
+    LessOrEqual,
+    EqualTo,
+    NotEqual,
+}
+
+/// An index into the instructions of a function
+pub type Label = u32;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    /// Loads a value from the constant pool of the [Program]
+    Const {
+        dst: Reg,
+        constant: u32,
+    },
+    Move {
+        dst: Reg,
+        src: Reg,
+    },
+    /// Applies an arithmetic operation to two numbers of type `ty`, overflowing the way
+    /// `overflow` says to. Shifts take an amount of any integer type as `right`
+    Arith {
+        op: ArithOp,
+        ty: PrimitiveType,
+        overflow: Overflow,
+        dst: Reg,
+        left: Reg,
+        right: Reg,
+    },
+    Compare {
+        op: CompareOp,
+        dst: Reg,
+        left: Reg,
+        right: Reg,
+    },
+    Negate {
+        ty: PrimitiveType,
+        overflow: Overflow,
+        dst: Reg,
+        src: Reg,
+    },
+    /// Converts a number to the numeric type `ty`
+    Convert {
+        ty: PrimitiveType,
+        overflow: Overflow,
+        dst: Reg,
+        src: Reg,
+    },
+    /// Flips a `Bool`, or every bit of an integer
+    Not {
+        dst: Reg,
+        src: Reg,
+    },
+    Jump {
+        target: Label,
+    },
+    JumpIf {
+        condition: Reg,
+        target: Label,
+    },
+    JumpIfNot {
+        condition: Reg,
+        target: Label,
+    },
+    /// Calls a function with the arguments in `count` registers from `arguments`. Calling a
+    /// generator creates it without running any of its body
+    Call {
+        dst: Reg,
+        function: u32,
+        arguments: Reg,
+        count: u16,
+    },
+    /// Calls the closure within `callee` with the arguments in `count` registers from
+    /// `arguments`, followed by the values it captured
+    CallValue {
+        dst: Reg,
+        callee: Reg,
+        arguments: Reg,
+        count: u16,
+    },
+    /// Makes a closure of a function, capturing the values in `count` registers from `captures`
+    MakeClosure {
+        dst: Reg,
+        function: u32,
+        captures: Reg,
+        count: u16,
+    },
+    /// Calls the method named by a `Str` constant, found through the type of the first argument
+    CallMethod {
+        dst: Reg,
+        method: u32,
+        arguments: Reg,
+        count: u16,
+    },
+    Return {
+        src: Reg,
+    },
+    /// Suspends the generator running in this frame, handing the value to the [Instruction::Next]
+    /// which resumed it
+    Yield {
+        src: Reg,
+    },
+    /// Resumes a generator, storing what it yields. Once it is done, jumps to `exit` instead
+    Next {
+        dst: Reg,
+        generator: Reg,
+        exit: Label,
+    },
+    /// Builds a tuple from `count` registers
+    Tuple {
+        dst: Reg,
+        start: Reg,
+        count: u16,
+    },
+    /// Builds an instance of a `type`, with its fields in the order they are declared
+    Struct {
+        dst: Reg,
+        adt: u32,
+        start: Reg,
+        count: u16,
+    },
+    Variant {
+        dst: Reg,
+        adt: u32,
+        variant: u32,
+        start: Reg,
+        count: u16,
+    },
+    /// Gets a field, element or payload value of the value within `src`
+    Field {
+        dst: Reg,
+        src: Reg,
+        index: u16,
+    },
+    /// Replaces a field, element or payload value of the value within `target`
+    SetField {
+        target: Reg,
+        index: u16,
+        src: Reg,
+    },
+    /// Checks if the value within `src` is the variant with the index `variant`
+    IsVariant {
+        dst: Reg,
+        src: Reg,
+        variant: u32,
+    },
+    /// Moves a value into memory of its own, storing a reference to it. Locals which are
+    /// referenced live in memory like this, with their register holding the reference
+    Alloc {
+        dst: Reg,
+        src: Reg,
+    },
+    /// Gets a reference to a field, element or payload value of the value a reference points to
+    Project {
+        dst: Reg,
+        src: Reg,
+        index: u16,
+    },
+    Load {
+        dst: Reg,
+        src: Reg,
+    },
+    Store {
+        target: Reg,
+        src: Reg,
+    },
+    /// Stops the program because no arm of a `when` matched
+    NoMatch,
+}
+
+/// A value within the constant pool
+#[derive(Debug, Clone, PartialEq)]
+pub enum Constant {
+    Unit,
+    Bool(bool),
+    /// An integer or a `Char` of a primitive type, widened so that any of them fits
+    Integer(PrimitiveType, i128),
+    Float(PrimitiveType, f64),
+    Str(String),
+}
+
+impl Display for Constant {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unit => write!(f, "()"),
+            Self::Bool(x) => write!(f, "{}", x),
+            Self::Integer(PrimitiveType::Char, x) => {
+                let character = u32::try_from(*x).ok().and_then(char::from_u32);
+                write!(f, "{:?}", character.unwrap_or(char::REPLACEMENT_CHARACTER))
+            }
+            Self::Integer(ty, x) => write!(f, "{} :: {}", x, ty),
+            Self::Float(ty, x) => write!(f, "{:?} :: {}", x, ty),
+            Self::Str(x) => write!(f, "{:?}", x),
+        }
+    }
+}
+
+/// A compiled function or method
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    /// The number of parameters, which are passed in the first registers
+    pub arity: u16,
+    /// For a closure, the number of values it captures, which are passed in the registers after
+    /// the parameters
+    pub captures: u16,
+    /// The number of registers its frame needs
+    pub registers: u16,
+    /// Whether calling the function creates a generator rather than running it
+    pub is_generator: bool,
+    /// Whether a method takes `self` by reference, so that a value it is called on is borrowed
+    pub self_by_reference: bool,
+    /// For an `extern` function, the name of the builtin of the runtime it is, such as
+    /// `Vec::push`. Such a function has no code
+    pub builtin: Option<String>,
+    /// Whether it is an `extern "C"` function, which has no code and stops the program when it
+    /// is called, as the VM can not call into C
+    pub is_foreign: bool,
+    pub code: Vec<Instruction>,
+    /// The source each instruction was compiled from, which runtime errors point at
+    pub spans: Vec<Span>,
+}
+
+/// The type of a value as far as finding its methods is concerned
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TypeKey {
+    Primitive(PrimitiveType),
+    Unit,
+    Tuple,
+    Adt(u32),
+    Reference,
+    Generator,
+    Closure,
+}
+
+/// The methods of one `impl`, including the defaults of the trait it does not replace
+#[derive(Debug, Clone, PartialEq)]
+pub struct Impl {
+    /// The type the methods are for, or [None] if the `impl` is for every type
+    pub self_type: Option<TypeKey>,
+    /// The name of each method along with the function it is
+    pub methods: Vec<(String, u32)>,
+}
+
+/// The names of a `type` or an `enum`, used when disassembling and printing values
+#[derive(Debug, Clone, PartialEq)]
+pub struct AdtInfo {
+    pub name: String,
+    /// The name of every variant, which is empty for a `type`
+    pub variants: Vec<String>,
+}
+
+/// Everything a module compiles into
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Program {
+    pub constants: Vec<Constant>,
+    pub functions: Vec<Function>,
+    pub impls: Vec<Impl>,
+    pub adts: Vec<AdtInfo>,
+    /// The function the program starts from, if it has a `pub fun main()`
+    pub entry: Option<u32>,
+}
+
+impl Program {
+    /// Finds the function with a name, which is the first one declared if methods share it
+    pub fn function(&self, name: &str) -> Option<u32> {
+        self.functions
+            .iter()
+            .position(|x| x.name == name)
+            .map(|x| x as u32)
+    }
+}
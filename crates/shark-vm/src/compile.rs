@@ -0,0 +1,1640 @@
+//! Compiles lowered function bodies into bytecode. Every local of a [Body] gets the register with
+//! its index, and the blocks are laid out one after another with jumps between them. Expressions
+//! are compiled into the registers above the locals, which are freed again after each statement
+//!
+//! Locals which are ever referenced, by `ref`, `ptr` or by calling a method which takes `self` by
+//! reference, live in memory of their own. Their register holds a reference to that memory rather
+//! than the value itself, so that writes through the reference are seen by the local
+//!
+//! `extern` functions and methods compile into functions without code which name the builtin of
+//! the runtime they are, and `extern "C"` functions into ones without code which are foreign
+//!
+//! A closure compiles into a function of its own, which takes the values it captured after its
+//! parameters. Making the closure copies those values out of the locals around it
+
+use std::collections::{HashMap, HashSet};
+
+use shark_core::{
+    source::Span,
+    symbol::{sym, Symbol},
+};
+use shark_lex::token::LiteralKind;
+use shark_lower::{
+    body::{BlockId, LocalId, Statement, Terminator},
+    lower::visit_block,
+    Body,
+};
+use shark_parse::ast::{
+    BinaryOperator, Block, Expr, ExprKind, ForKind, Function, ItemKind, Module, NodeId, Path,
+    Pattern, PatternKind, StatementKind, TypeExprKind, UnaryOperator, Visibility,
+};
+use shark_sema::{
+    generics::FunctionSig,
+    numeric::{Intrinsic, Overflow},
+    traits::{ImplDef, TraitId},
+    ty::{AdtId, PrimitiveType, Type},
+    ModuleDefs,
+};
+use shark_typeck::{ty::Ty, TypeckResults};
+
+use crate::bytecode::{
+    AdtInfo, ArithOp, CompareOp, Constant, Function as CompiledFunction, Impl, Instruction, Label,
+    Program, Reg, TypeKey,
+};
+
+/// Compiles a [Module] which has been checked and lowered without errors into a [Program], whose
+/// integer arithmetic overflows the way the program is being built to
+pub fn compile_module<'ast>(
+    module: &'ast Module,
+    defs: &ModuleDefs,
+    types: &TypeckResults,
+    bodies: &[Body<'ast>],
+    overflow: Overflow,
+) -> Program {
+    let mut compiler = Compiler {
+        defs,
+        types,
+        overflow,
+        program: Program::default(),
+        functions: HashMap::new(),
+        associated: Vec::new(),
+        closures: bodies
+            .iter()
+            .enumerate()
+            .map(|(index, x)| {
+                let captures = x.captures.clone();
+                (x.function as *const Function, (index as u32, captures))
+            })
+            .collect(),
+    };
+    compiler.program.adts = defs
+        .types
+        .adts()
+        .map(|(_, x)| AdtInfo {
+            name: x.name.symbol.to_string(),
+            variants: x
+                .variants()
+                .iter()
+                .map(|x| x.name.symbol.to_string())
+                .collect(),
+        })
+        .collect();
+
+    let body_of = |function: &Function| {
+        bodies
+            .iter()
+            .position(|x| std::ptr::eq(x.function, function))
+            .map(|x| x as u32)
+    };
+    let mut names = vec![String::new(); bodies.len()];
+    // The functions without code for `extern` functions and methods, which come after the others
+    let mut builtins = Vec::new();
+    let mut builtin = |name: String, builtin: Option<String>, sig: Option<&FunctionSig>| {
+        let index = (bodies.len() + builtins.len()) as u32;
+        builtins.push(CompiledFunction {
+            name,
+            arity: sig.map_or(0, |x| x.parameters.len() as u16),
+            captures: 0,
+            registers: sig.map_or(0, |x| x.parameters.len() as u16),
+            is_generator: false,
+            self_by_reference: sig.is_some_and(|x| {
+                x.has_self && matches!(x.parameters.first(), Some(Type::Reference { .. }))
+            }),
+            is_foreign: builtin.is_none(),
+            builtin,
+            code: Vec::new(),
+            spans: Vec::new(),
+        });
+        index
+    };
+    let mut defaults: HashMap<(TraitId, Symbol), u32> = HashMap::new();
+    for item in &module.items {
+        match &item.kind {
+            ItemKind::Function(function) if function.is_extern => {
+                let sig = defs.functions.lookup(function.name.symbol);
+                let name = function.name.symbol.to_string();
+                let foreign = function.abi.is_some();
+                let index = builtin(name.clone(), (!foreign).then_some(name), sig);
+                compiler.functions.insert(function.name.symbol, index);
+            }
+            ItemKind::Function(function) => {
+                let Some(index) = body_of(function) else {
+                    continue;
+                };
+                names[index as usize] = function.name.symbol.to_string();
+                compiler.functions.insert(function.name.symbol, index);
+                let is_main = function.name.symbol.as_str() == "main"
+                    && item.visibility == Visibility::Public
+                    && function.parameters.is_empty();
+                if is_main {
+                    compiler.program.entry = Some(index);
+                }
+            }
+            ItemKind::Trait(trait_decl) => {
+                let Some(trait_id) = defs.traits.trait_of_item(item.id) else {
+                    continue;
+                };
+                for method in &trait_decl.methods {
+                    if let Some(index) = body_of(method) {
+                        names[index as usize] =
+                            format!("{}::{}", trait_decl.name.symbol, method.name.symbol);
+                        defaults.insert((trait_id, method.name.symbol), index);
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+    // Implementations come last, as they take the defaults of their trait
+    for item in &module.items {
+        let ItemKind::Impl(impl_decl) = &item.kind else {
+            continue;
+        };
+        let Some(implementation) = defs.traits.impl_of_item(item.id) else {
+            continue;
+        };
+        let type_name = defs.types.type_name(&implementation.self_type);
+        let mut methods = Vec::new();
+        for method in &impl_decl.methods {
+            let name = format!("{}::{}", type_name, method.name.symbol);
+            let index = match method.is_extern {
+                true => {
+                    let owner = match &implementation.self_type {
+                        Type::Adt(id, _) => defs.types.adt(*id).name.symbol.to_string(),
+                        ty => defs.types.type_name(ty),
+                    };
+                    let sig = implementation.method(method.name.symbol);
+                    builtin(
+                        name,
+                        Some(format!("{}::{}", owner, method.name.symbol)),
+                        sig,
+                    )
+                }
+                false => match body_of(method) {
+                    Some(index) => {
+                        names[index as usize] = name;
+                        index
+                    }
+                    None => continue,
+                },
+            };
+            methods.push((method.name.symbol.to_string(), index));
+            if implementation.is_inherent {
+                compiler
+                    .associated
+                    .push((implementation, method.name.symbol, index));
+            }
+        }
+        if let Some(trait_id) = implementation.trait_id {
+            for ((default_trait, name), index) in &defaults {
+                let replaced = methods.iter().any(|(x, _)| x == name.as_str());
+                if *default_trait == trait_id && !replaced {
+                    methods.push((name.to_string(), *index));
+                }
+            }
+        }
+        // Keep the order the same between compilations of the same module
+        methods.sort();
+        let self_type = match &implementation.self_type {
+            Type::Param(_) => None,
+            // An array is a `Vec` at runtime
+            Type::Array(..) => defs.types.lookup(sym::VEC).map(|x| TypeKey::Adt(x.0)),
+            ty => Some(type_key(ty)),
+        };
+        compiler.program.impls.push(Impl { self_type, methods });
+    }
+
+    // The closures within a function come right after its body, and are named after it
+    let mut outer = (String::new(), 0);
+    for (body, name) in bodies.iter().zip(&mut names) {
+        match body.function.name.symbol == sym::CLOSURE {
+            true => {
+                outer.1 += 1;
+                *name = format!("{}::<closure {}>", outer.0, outer.1);
+            }
+            false => outer = (name.clone(), 0),
+        }
+    }
+    for (body, name) in bodies.iter().zip(names) {
+        let function = FunctionCompiler::new(&mut compiler, body).compile(name);
+        compiler.program.functions.push(function);
+    }
+    compiler.program.functions.extend(builtins);
+    compiler.program
+}
+
+fn type_key(ty: &Type) -> TypeKey {
+    match ty {
+        Type::Primitive(primitive) => TypeKey::Primitive(*primitive),
+        Type::Unit => TypeKey::Unit,
+        Type::Adt(id, _) => TypeKey::Adt(id.0),
+        Type::Reference { .. } => TypeKey::Reference,
+        Type::Generator(_) => TypeKey::Generator,
+        Type::Function { .. } => TypeKey::Closure,
+        Type::Param(_) | Type::Array(..) | Type::Error => {
+            unreachable!("`{:?}` is not the type of a value", ty)
+        }
+    }
+}
+
+/// Whether a function is a method taking `self` by reference
+fn takes_self_by_reference(function: &Function) -> bool {
+    function.parameters.first().is_some_and(|x| {
+        x.name.symbol.as_str() == "self" && matches!(x.ty.kind, TypeExprKind::Reference { .. })
+    })
+}
+
+struct Compiler<'c> {
+    defs: &'c ModuleDefs,
+    types: &'c TypeckResults,
+    /// What the arithmetic of the program does when it overflows
+    overflow: Overflow,
+    program: Program,
+    /// The index of every function which is not a method
+    functions: HashMap<Symbol, u32>,
+    /// The index of every function and method of an inherent implementation, which can be called
+    /// through a path like `Vec::new`
+    associated: Vec<(&'c ImplDef, Symbol, u32)>,
+    /// The index of the body of every function along with the locals it captures, which is how
+    /// closures are found
+    closures: HashMap<*const Function, (u32, Vec<LocalId>)>,
+}
+
+impl Compiler<'_> {
+    fn constant(&mut self, constant: Constant) -> u32 {
+        let constants = &mut self.program.constants;
+        match constants.iter().position(|x| *x == constant) {
+            Some(index) => index as u32,
+            None => {
+                constants.push(constant);
+                constants.len() as u32 - 1
+            }
+        }
+    }
+
+    /// Whether the method called on a value of some type takes `self` by reference. Methods of an
+    /// inherent implementation are found through the type, and every implementation of a trait
+    /// method takes `self` the same way the trait declares it
+    fn receiver_by_reference(&self, receiver: Option<&Ty>, name: Symbol) -> bool {
+        let mut receiver = receiver;
+        while let Some(Ty::Reference { pointee, .. }) = receiver {
+            receiver = Some(pointee);
+        }
+        let head = match receiver {
+            Some(Ty::Adt(id, _)) => Some(Type::Adt(*id, Vec::new())),
+            Some(Ty::Primitive(primitive)) => Some(Type::Primitive(*primitive)),
+            Some(Ty::Array(..)) => self
+                .defs
+                .types
+                .lookup(sym::VEC)
+                .map(|x| Type::Adt(x, Vec::new())),
+            _ => None,
+        };
+        let inherent = head
+            .and_then(|x| self.defs.traits.inherent_function(&x, name))
+            .filter(|(_, sig)| sig.has_self);
+        match inherent {
+            Some((_, sig)) => matches!(sig.parameters.first(), Some(Type::Reference { .. })),
+            None => self.method_by_reference(name),
+        }
+    }
+
+    /// Whether the trait methods with a name take `self` by reference
+    fn method_by_reference(&self, name: Symbol) -> bool {
+        let traits = &self.defs.traits;
+        traits.traits_with_method(name).first().is_some_and(|x| {
+            let method = traits.trait_def(*x).method(name);
+            method.is_some_and(|x| matches!(x.parameters.first(), Some(Type::Reference { .. })))
+        })
+    }
+}
+
+/// Where an assignment stores its value
+enum Place {
+    /// A register, or a field, element or payload value within the value in it
+    Register(Reg, Vec<u16>),
+    /// The memory the reference within a register points to
+    Memory(Reg),
+}
+
+struct FunctionCompiler<'c, 'b, 'ast> {
+    compiler: &'c mut Compiler<'b>,
+    body: &'c Body<'ast>,
+    code: Vec<Instruction>,
+    spans: Vec<Span>,
+    /// The register the next temporary is stored in
+    next_register: u16,
+    registers: u16,
+    /// The locals which live in memory of their own
+    boxed: HashSet<LocalId>,
+    /// The label each block starts at
+    blocks: Vec<Label>,
+    /// The jumps to blocks, which are patched once every block has been laid out
+    block_jumps: Vec<(usize, BlockId)>,
+}
+
+impl<'c, 'b, 'ast> FunctionCompiler<'c, 'b, 'ast> {
+    fn new(compiler: &'c mut Compiler<'b>, body: &'c Body<'ast>) -> Self {
+        let locals = body.locals.len() as u16;
+        Self {
+            compiler,
+            body,
+            code: Vec::new(),
+            spans: Vec::new(),
+            next_register: locals,
+            registers: locals,
+            boxed: HashSet::new(),
+            blocks: Vec::new(),
+            block_jumps: Vec::new(),
+        }
+    }
+
+    fn compile(mut self, name: String) -> CompiledFunction {
+        let function = self.body.function;
+        self.find_boxed();
+        let spans = function.parameters.iter().map(|x| x.span);
+        let captures = self.body.captures.iter().map(|_| function.name.span);
+        for (index, span) in spans.chain(captures).enumerate() {
+            if self.boxed.contains(&LocalId(index as u32)) {
+                let register = Reg(index as u16);
+                self.emit(
+                    Instruction::Alloc {
+                        dst: register,
+                        src: register,
+                    },
+                    span,
+                );
+            }
+        }
+
+        for (index, block) in self.body.blocks.iter().enumerate() {
+            self.blocks.push(self.label());
+            for statement in &block.statements {
+                let mark = self.next_register;
+                self.statement(statement);
+                self.next_register = mark;
+            }
+            self.terminator(BlockId(index as u32), &block.terminator);
+            self.next_register = self.body.locals.len() as u16;
+        }
+        for (at, block) in std::mem::take(&mut self.block_jumps) {
+            let target = self.blocks[block.0 as usize];
+            self.patch(at, target);
+        }
+
+        CompiledFunction {
+            name,
+            arity: function.parameters.len() as u16,
+            captures: self.body.captures.len() as u16,
+            registers: self.registers,
+            is_generator: self.body.generator.is_some(),
+            self_by_reference: takes_self_by_reference(function),
+            builtin: None,
+            is_foreign: false,
+            code: self.code,
+            spans: self.spans,
+        }
+    }
+
+    /// Finds every local which is referenced, either directly or by a method call
+    fn find_boxed(&mut self) {
+        let Some(block) = &self.body.function.body else {
+            return;
+        };
+        let mut roots = Vec::new();
+        visit_block(block, &mut |expr| match &expr.kind {
+            ExprKind::Reference { operand, .. } => roots.push(self.root(operand)),
+            ExprKind::Call { callee, .. }
+                if self.compiler.types.intrinsic_of(expr.id).is_none() =>
+            {
+                if let ExprKind::Field { object, field } = &callee.kind {
+                    let receiver = self.ty(object.id);
+                    if self.compiler.receiver_by_reference(receiver, field.symbol)
+                        && !self.is_reference(object)
+                    {
+                        roots.push(self.root(object));
+                    }
+                }
+            }
+            _ => {}
+        });
+        self.boxed.extend(roots.into_iter().flatten());
+    }
+
+    /// Gets the local a place expression is within, unless it is reached through a reference
+    fn root(&self, expr: &Expr) -> Option<LocalId> {
+        match &expr.kind {
+            ExprKind::Name(_) => self.body.names.get(&expr.id).copied(),
+            ExprKind::Field { object, .. } if !self.is_reference(object) => self.root(object),
+            _ => None,
+        }
+    }
+
+    fn ty(&self, id: NodeId) -> Option<&'b Ty> {
+        self.compiler.types.type_of(id)
+    }
+
+    fn is_reference(&self, expr: &Expr) -> bool {
+        matches!(self.ty(expr.id), Some(Ty::Reference { .. }))
+    }
+
+    /// Gets the primitive type of an expression, which type checking has made sure it is of
+    fn primitive(&self, expr: &Expr) -> PrimitiveType {
+        match self.ty(expr.id) {
+            Some(Ty::Primitive(primitive)) => *primitive,
+            ty => panic!("expected a primitive type, found {:?}", ty),
+        }
+    }
+
+    fn label(&self) -> Label {
+        self.code.len() as Label
+    }
+
+    fn emit(&mut self, instruction: Instruction, span: Span) -> usize {
+        self.code.push(instruction);
+        self.spans.push(span);
+        self.code.len() - 1
+    }
+
+    /// Points the jump at an index to a label
+    fn patch(&mut self, at: usize, label: Label) {
+        match &mut self.code[at] {
+            Instruction::Jump { target }
+            | Instruction::JumpIf { target, .. }
+            | Instruction::JumpIfNot { target, .. }
+            | Instruction::Next { exit: target, .. } => *target = label,
+            instruction => panic!("{:?} is not a jump", instruction),
+        }
+    }
+
+    fn patch_here(&mut self, jumps: Vec<usize>) {
+        let label = self.label();
+        for at in jumps {
+            self.patch(at, label);
+        }
+    }
+
+    fn jump_to_block(&mut self, block: BlockId, span: Span) {
+        let at = self.emit(Instruction::Jump { target: 0 }, span);
+        self.block_jumps.push((at, block));
+    }
+
+    fn temp(&mut self) -> Reg {
+        self.temps(1)
+    }
+
+    /// Allocates registers next to each other, returning the first of them
+    fn temps(&mut self, count: u16) -> Reg {
+        let first = Reg(self.next_register);
+        self.next_register += count;
+        self.registers = self.registers.max(self.next_register);
+        first
+    }
+
+    fn local(&self, local: LocalId) -> Reg {
+        Reg(local.0 as u16)
+    }
+
+    fn constant(&mut self, dst: Reg, constant: Constant, span: Span) {
+        let constant = self.compiler.constant(constant);
+        self.emit(Instruction::Const { dst, constant }, span);
+    }
+
+    fn statement(&mut self, statement: &Statement<'ast>) {
+        match statement {
+            Statement::Let(local, value) => {
+                let span = self.body.local(*local).span;
+                self.declare(*local, *value, span);
+            }
+            Statement::Eval(expr) => {
+                self.expr(expr);
+            }
+            Statement::StartIteration {
+                iterator,
+                counter,
+                iterable,
+            } => {
+                self.expr_to(iterable, self.local(*iterator));
+                if let Some(counter) = counter {
+                    let constant = Constant::Integer(PrimitiveType::Int64, 0);
+                    self.constant(self.local(*counter), constant, iterable.span);
+                }
+            }
+        }
+    }
+
+    fn declare(&mut self, local: LocalId, value: Option<&Expr>, span: Span) {
+        let register = self.local(local);
+        let src = match self.boxed.contains(&local) {
+            true => self.temp(),
+            false => register,
+        };
+        match value {
+            Some(value) => self.expr_to(value, src),
+            None => self.constant(src, Constant::Unit, span),
+        }
+        if src != register {
+            self.emit(Instruction::Alloc { dst: register, src }, span);
+        }
+    }
+
+    fn terminator(&mut self, block: BlockId, terminator: &Terminator<'ast>) {
+        let span = self.body.function.name.span;
+        match terminator {
+            Terminator::Goto(target) if target.0 == block.0 + 1 => {}
+            Terminator::Goto(target) => self.jump_to_block(*target, span),
+            Terminator::Branch {
+                condition,
+                then_block,
+                else_block,
+            } => {
+                let condition_register = self.expr(condition);
+                let at = self.emit(
+                    Instruction::JumpIfNot {
+                        condition: condition_register,
+                        target: 0,
+                    },
+                    condition.span,
+                );
+                self.block_jumps.push((at, *else_block));
+                self.jump_to_block(*then_block, condition.span);
+            }
+            Terminator::When { scrutinee, arms } => {
+                let value = self.temp();
+                self.expr_to(scrutinee, value);
+                for (arm, target) in arms {
+                    let mark = self.next_register;
+                    let mut fails = Vec::new();
+                    self.test_pattern(&arm.pattern, value, &mut fails);
+                    self.bind(&arm.pattern, value);
+                    if let Some(guard) = &arm.guard {
+                        let condition = self.expr(guard);
+                        let at = self.emit(
+                            Instruction::JumpIfNot {
+                                condition,
+                                target: 0,
+                            },
+                            guard.span,
+                        );
+                        fails.push(at);
+                    }
+                    self.jump_to_block(*target, arm.span);
+                    self.patch_here(fails);
+                    self.next_register = mark;
+                }
+                self.emit(Instruction::NoMatch, scrutinee.span);
+            }
+            Terminator::Next {
+                iterator,
+                counter,
+                pattern,
+                body,
+                exit,
+            } => {
+                let value = self.temp();
+                let at = self.emit(
+                    Instruction::Next {
+                        dst: value,
+                        generator: self.local(*iterator),
+                        exit: 0,
+                    },
+                    pattern.span,
+                );
+                self.block_jumps.push((at, *exit));
+                let value = match counter {
+                    Some(counter) => self.count(self.local(*counter), value, pattern.span),
+                    None => value,
+                };
+                self.bind(pattern, value);
+                self.jump_to_block(*body, pattern.span);
+            }
+            Terminator::Yield { value, resume } => {
+                let src = self.expr(value);
+                self.emit(Instruction::Yield { src }, value.span);
+                if resume.0 != block.0 + 1 {
+                    self.jump_to_block(*resume, value.span);
+                }
+            }
+            Terminator::Return(value) => {
+                let src = match value {
+                    Some(value) => self.expr(value),
+                    None => {
+                        let src = self.temp();
+                        self.constant(src, Constant::Unit, span);
+                        src
+                    }
+                };
+                self.emit(Instruction::Return { src }, span);
+            }
+        }
+    }
+
+    /// Pairs the value a `for ... of` loop was given with its counter, then increments the
+    /// counter. Returns the register holding the pair
+    fn count(&mut self, counter: Reg, value: Reg, span: Span) -> Reg {
+        let pair = self.temps(2);
+        let second = Reg(pair.0 + 1);
+        self.emit(
+            Instruction::Move {
+                dst: pair,
+                src: counter,
+            },
+            span,
+        );
+        self.emit(
+            Instruction::Move {
+                dst: second,
+                src: value,
+            },
+            span,
+        );
+        self.emit(
+            Instruction::Tuple {
+                dst: pair,
+                start: pair,
+                count: 2,
+            },
+            span,
+        );
+        let one = self.temp();
+        self.constant(one, Constant::Integer(PrimitiveType::Int64, 1), span);
+        self.emit(
+            Instruction::Arith {
+                op: ArithOp::Add,
+                ty: PrimitiveType::Int64,
+                overflow: Overflow::Trap,
+                dst: counter,
+                left: counter,
+                right: one,
+            },
+            span,
+        );
+        pair
+    }
+
+    /// Creates an empty `Vec` through `Vec::new`, which is what an array is at runtime. Returns
+    /// the register holding a reference to it
+    fn new_vec(&mut self, span: Span) -> Reg {
+        let defs = self.compiler.defs;
+        let vec = defs.types.lookup(sym::VEC).expect("`Vec` is used");
+        let (implementation, _) = defs
+            .traits
+            .inherent_function(&Type::Adt(vec, Vec::new()), Symbol::intern("new"))
+            .expect("`Vec::new` is defined");
+        let function = self
+            .compiler
+            .associated
+            .iter()
+            .find(|(x, name, _)| std::ptr::eq(*x, implementation) && name.as_str() == "new")
+            .map(|(_, _, index)| *index)
+            .expect("associated functions are compiled");
+        let list = self.temp();
+        let instruction = Instruction::Call {
+            dst: list,
+            function,
+            arguments: list,
+            count: 0,
+        };
+        self.emit(instruction, span);
+        self.emit(
+            Instruction::Alloc {
+                dst: list,
+                src: list,
+            },
+            span,
+        );
+        list
+    }
+
+    /// Pushes a value onto the `Vec` a register refers to
+    fn push(&mut self, list: Reg, value: Reg, span: Span) {
+        let arguments = self.temps(2);
+        let second = Reg(arguments.0 + 1);
+        self.emit(
+            Instruction::Move {
+                dst: arguments,
+                src: list,
+            },
+            span,
+        );
+        self.emit(
+            Instruction::Move {
+                dst: second,
+                src: value,
+            },
+            span,
+        );
+        let method = self.compiler.constant(Constant::Str("push".to_string()));
+        let result = self.temp();
+        let instruction = Instruction::CallMethod {
+            dst: result,
+            method,
+            arguments,
+            count: 2,
+        };
+        self.emit(instruction, span);
+    }
+
+    /// Compiles an expression, returning the register its value ends up in. A local is used
+    /// directly rather than copied
+    fn expr(&mut self, expr: &Expr) -> Reg {
+        if let Some(local) = self.body.names.get(&expr.id) {
+            if !self.boxed.contains(local) {
+                return self.local(*local);
+            }
+        }
+        let dst = self.temp();
+        self.expr_to(expr, dst);
+        dst
+    }
+
+    /// Compiles expressions into registers next to each other, returning the first of them
+    fn exprs_to_temps<'e>(&mut self, exprs: impl ExactSizeIterator<Item = &'e Expr>) -> Reg {
+        let start = self.temps(exprs.len() as u16);
+        for (index, expr) in exprs.enumerate() {
+            self.expr_to(expr, Reg(start.0 + index as u16));
+        }
+        start
+    }
+
+    fn expr_to(&mut self, expr: &Expr, dst: Reg) {
+        let span = expr.span;
+        match &expr.kind {
+            ExprKind::Literal(literal) => {
+                let ty = match self.ty(expr.id) {
+                    Some(Ty::Primitive(primitive)) => *primitive,
+                    _ => PrimitiveType::of_literal(literal),
+                };
+                self.constant(dst, literal_constant(literal, ty), span);
+            }
+            ExprKind::Array(values) => {
+                let list = self.new_vec(span);
+                for value in values {
+                    let value = self.expr(value);
+                    self.push(list, value, span);
+                }
+                self.emit(Instruction::Load { dst, src: list }, span);
+            }
+            ExprKind::Repeat { value, length } => {
+                let ExprKind::Literal(LiteralKind::Int64(count)) = length.kind else {
+                    unreachable!("lengths are replaced by their value");
+                };
+                let list = self.new_vec(span);
+                let value = self.expr(value);
+                let counter = self.temp();
+                self.constant(counter, Constant::Integer(PrimitiveType::Int64, 0), span);
+                let end = self.temp();
+                let count = Constant::Integer(PrimitiveType::Int64, count as i128);
+                self.constant(end, count, span);
+                let one = self.temp();
+                self.constant(one, Constant::Integer(PrimitiveType::Int64, 1), span);
+                let condition = self.temp();
+                let start = self.label();
+                self.emit(
+                    Instruction::Compare {
+                        op: CompareOp::Lesser,
+                        dst: condition,
+                        left: counter,
+                        right: end,
+                    },
+                    span,
+                );
+                let exit = self.emit(
+                    Instruction::JumpIfNot {
+                        condition,
+                        target: 0,
+                    },
+                    span,
+                );
+                self.push(list, value, span);
+                self.emit(
+                    Instruction::Arith {
+                        op: ArithOp::Add,
+                        ty: PrimitiveType::Int64,
+                        overflow: Overflow::Trap,
+                        dst: counter,
+                        left: counter,
+                        right: one,
+                    },
+                    span,
+                );
+                self.emit(Instruction::Jump { target: start }, span);
+                self.patch_here(vec![exit]);
+                self.emit(Instruction::Load { dst, src: list }, span);
+            }
+            ExprKind::Name(name) => {
+                let Some(local) = self.body.names.get(&expr.id).copied() else {
+                    panic!("`{}` is not a value", name.symbol);
+                };
+                let src = self.local(local);
+                match self.boxed.contains(&local) {
+                    true => self.emit(Instruction::Load { dst, src }, span),
+                    false if src == dst => return,
+                    false => self.emit(Instruction::Move { dst, src }, span),
+                };
+            }
+            ExprKind::Path(path) => {
+                let (adt, variant) = self.variant(path);
+                let start = Reg(0);
+                let count = 0;
+                self.emit(
+                    Instruction::Variant {
+                        dst,
+                        adt,
+                        variant,
+                        start,
+                        count,
+                    },
+                    span,
+                );
+            }
+            ExprKind::StructLiteral { path, fields } => {
+                let types = &self.compiler.defs.types;
+                let id = types
+                    .lookup(path.name().symbol)
+                    .expect("struct literals are checked to name a type");
+                let adt = types.adt(id);
+                let count = adt.fields().len() as u16;
+                let indices: Vec<usize> = fields
+                    .iter()
+                    .map(|x| adt.field(x.name.symbol).map_or(0, |(index, _)| index))
+                    .collect();
+                let start = self.temps(count);
+                for (field, index) in fields.iter().zip(indices) {
+                    self.expr_to(&field.value, Reg(start.0 + index as u16));
+                }
+                self.emit(
+                    Instruction::Struct {
+                        dst,
+                        adt: id.0,
+                        start,
+                        count,
+                    },
+                    span,
+                );
+            }
+            ExprKind::Unary { operator, operand } => {
+                let src = self.expr(operand);
+                let instruction = match operator {
+                    UnaryOperator::Negate => Instruction::Negate {
+                        ty: self.primitive(expr),
+                        overflow: self.compiler.overflow,
+                        dst,
+                        src,
+                    },
+                    UnaryOperator::Not => Instruction::Not { dst, src },
+                    UnaryOperator::Deref => Instruction::Load { dst, src },
+                };
+                self.emit(instruction, span);
+            }
+            ExprKind::Reference { operand, .. } => self.reference_to(operand, dst),
+            ExprKind::Binary {
+                operator: operator @ (BinaryOperator::And | BinaryOperator::Or),
+                left,
+                right,
+            } => {
+                self.expr_to(left, dst);
+                let condition = dst;
+                let at = match operator {
+                    BinaryOperator::And => self.emit(
+                        Instruction::JumpIfNot {
+                            condition,
+                            target: 0,
+                        },
+                        span,
+                    ),
+                    _ => self.emit(
+                        Instruction::JumpIf {
+                            condition,
+                            target: 0,
+                        },
+                        span,
+                    ),
+                };
+                self.expr_to(right, dst);
+                self.patch_here(vec![at]);
+            }
+            ExprKind::Binary {
+                operator,
+                left,
+                right,
+            } => {
+                // The right operand could change a local used directly as the left one
+                let left_register = match is_simple(right) {
+                    true => self.expr(left),
+                    false => {
+                        let register = self.temp();
+                        self.expr_to(left, register);
+                        register
+                    }
+                };
+                let right = self.expr(right);
+                self.binary(*operator, left, dst, left_register, right, span);
+            }
+            ExprKind::Assign {
+                operator,
+                target,
+                value,
+            } => {
+                self.assign(*operator, target, value, span);
+                self.constant(dst, Constant::Unit, span);
+            }
+            ExprKind::Tuple(elements) if elements.is_empty() => {
+                self.constant(dst, Constant::Unit, span)
+            }
+            ExprKind::Tuple(elements) => {
+                let start = self.exprs_to_temps(elements.iter());
+                let count = elements.len() as u16;
+                self.emit(Instruction::Tuple { dst, start, count }, span);
+            }
+            ExprKind::Call { callee, arguments } => match self.compiler.types.intrinsic_of(expr.id)
+            {
+                Some(intrinsic) => self.intrinsic(dst, intrinsic, callee, arguments, span),
+                None => self.call(dst, callee, arguments, span),
+            },
+            ExprKind::Field { object, field } => {
+                let mut src = self.expr(object);
+                let mut ty = self.ty(object.id);
+                while let Some(Ty::Reference { pointee, .. }) = ty {
+                    let loaded = self.temp();
+                    self.emit(Instruction::Load { dst: loaded, src }, span);
+                    src = loaded;
+                    ty = Some(pointee);
+                }
+                let index = self.field_index(ty, field.symbol);
+                self.emit(Instruction::Field { dst, src, index }, span);
+            }
+            ExprKind::Block(block) | ExprKind::Unsafe(block) => self.block(block, dst),
+            ExprKind::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                let condition_register = self.expr(condition);
+                let to_else = self.emit(
+                    Instruction::JumpIfNot {
+                        condition: condition_register,
+                        target: 0,
+                    },
+                    condition.span,
+                );
+                self.block(then_branch, dst);
+                let to_end = self.emit(Instruction::Jump { target: 0 }, span);
+                self.patch_here(vec![to_else]);
+                match else_branch {
+                    Some(else_branch) => self.expr_to(else_branch, dst),
+                    None => self.constant(dst, Constant::Unit, span),
+                }
+                self.patch_here(vec![to_end]);
+            }
+            ExprKind::For {
+                kind,
+                pattern,
+                iterable,
+                body,
+            } => {
+                let generator = self.temp();
+                self.expr_to(iterable, generator);
+                let counter = match kind {
+                    ForKind::In => None,
+                    ForKind::Of => {
+                        let counter = self.temp();
+                        let zero = Constant::Integer(PrimitiveType::Int64, 0);
+                        self.constant(counter, zero, iterable.span);
+                        Some(counter)
+                    }
+                };
+                let value = self.temp();
+                let header = self.label();
+                let exit = self.emit(
+                    Instruction::Next {
+                        dst: value,
+                        generator,
+                        exit: 0,
+                    },
+                    pattern.span,
+                );
+                let mark = self.next_register;
+                let bound = match counter {
+                    Some(counter) => self.count(counter, value, pattern.span),
+                    None => value,
+                };
+                self.bind(pattern, bound);
+                let result = self.temp();
+                self.block(body, result);
+                self.next_register = mark;
+                self.emit(Instruction::Jump { target: header }, span);
+                self.patch_here(vec![exit]);
+                self.constant(dst, Constant::Unit, span);
+            }
+            ExprKind::When { scrutinee, arms } => {
+                let value = self.temp();
+                self.expr_to(scrutinee, value);
+                let mut ends = Vec::new();
+                for arm in arms {
+                    let mark = self.next_register;
+                    let mut fails = Vec::new();
+                    self.test_pattern(&arm.pattern, value, &mut fails);
+                    self.bind(&arm.pattern, value);
+                    if let Some(guard) = &arm.guard {
+                        let condition = self.expr(guard);
+                        let at = self.emit(
+                            Instruction::JumpIfNot {
+                                condition,
+                                target: 0,
+                            },
+                            guard.span,
+                        );
+                        fails.push(at);
+                    }
+                    self.expr_to(&arm.body, dst);
+                    ends.push(self.emit(Instruction::Jump { target: 0 }, arm.span));
+                    self.patch_here(fails);
+                    self.next_register = mark;
+                }
+                self.emit(Instruction::NoMatch, scrutinee.span);
+                self.patch_here(ends);
+            }
+            ExprKind::Return(value) => {
+                let src = match value {
+                    Some(value) => self.expr(value),
+                    None => {
+                        self.constant(dst, Constant::Unit, span);
+                        dst
+                    }
+                };
+                self.emit(Instruction::Return { src }, span);
+            }
+            ExprKind::Yield(_) => unreachable!("a `yield` is always lowered into a terminator"),
+            ExprKind::Closure(function) => {
+                let (function, captures) = self
+                    .compiler
+                    .closures
+                    .get(&(&**function as *const Function))
+                    .cloned()
+                    .expect("every closure is lowered");
+                let start = self.temps(captures.len() as u16);
+                for (index, local) in captures.iter().enumerate() {
+                    let dst = Reg(start.0 + index as u16);
+                    let src = self.local(*local);
+                    match self.boxed.contains(local) {
+                        true => self.emit(Instruction::Load { dst, src }, span),
+                        false => self.emit(Instruction::Move { dst, src }, span),
+                    };
+                }
+                let instruction = Instruction::MakeClosure {
+                    dst,
+                    function,
+                    captures: start,
+                    count: captures.len() as u16,
+                };
+                self.emit(instruction, span);
+            }
+            ExprKind::Error => unreachable!("programs with errors are never compiled"),
+        }
+    }
+
+    fn block(&mut self, block: &Block, dst: Reg) {
+        for statement in &block.statements {
+            let mark = self.next_register;
+            match &statement.kind {
+                StatementKind::Let(let_statement) => {
+                    let local = self.body.declarations[&statement.id];
+                    self.declare(local, let_statement.value.as_ref(), statement.span);
+                }
+                StatementKind::Expr(expr) => {
+                    self.expr(expr);
+                }
+                StatementKind::Error => {}
+            }
+            self.next_register = mark;
+        }
+        match &block.tail {
+            Some(tail) => self.expr_to(tail, dst),
+            None => self.constant(dst, Constant::Unit, block.span),
+        }
+    }
+
+    fn binary(
+        &mut self,
+        operator: BinaryOperator,
+        operand: &Expr,
+        dst: Reg,
+        left: Reg,
+        right: Reg,
+        span: Span,
+    ) {
+        let compare = |op| Instruction::Compare {
+            op,
+            dst,
+            left,
+            right,
+        };
+        let arith = |op| Instruction::Arith {
+            op,
+            ty: self.primitive(operand),
+            overflow: self.compiler.overflow,
+            dst,
+            left,
+            right,
+        };
+        let instruction = match operator {
+            BinaryOperator::Add => arith(ArithOp::Add),
+            BinaryOperator::Subtract => arith(ArithOp::Subtract),
+            BinaryOperator::Multiply => arith(ArithOp::Multiply),
+            BinaryOperator::Divide => arith(ArithOp::Divide),
+            BinaryOperator::BitwiseAnd => arith(ArithOp::BitwiseAnd),
+            BinaryOperator::ShiftLeft => arith(ArithOp::ShiftLeft),
+            BinaryOperator::ShiftRight => arith(ArithOp::ShiftRight),
+            BinaryOperator::Greater => compare(CompareOp::Greater),
+            BinaryOperator::Lesser => compare(CompareOp::Lesser),
+            BinaryOperator::GreaterOrEqual => compare(CompareOp::GreaterOrEqual),
+            BinaryOperator::LessOrEqual => compare(CompareOp::LessOrEqual),
+            BinaryOperator::EqualTo => compare(CompareOp::EqualTo),
+            BinaryOperator::NotEqual => compare(CompareOp::NotEqual),
+            BinaryOperator::And | BinaryOperator::Or => {
+                unreachable!("`&&` and `|` short circuit before reaching here")
+            }
+        };
+        self.emit(instruction, span);
+    }
+
+    fn assign(
+        &mut self,
+        operator: Option<BinaryOperator>,
+        target: &Expr,
+        value: &Expr,
+        span: Span,
+    ) {
+        let src = self.temp();
+        self.expr_to(value, src);
+        let place = self
+            .place(target)
+            .expect("assignments are checked to have a place as their target");
+        let src = match operator {
+            Some(operator) => {
+                let current = self.temp();
+                self.read(&place, current, span);
+                self.binary(operator, target, current, current, src, span);
+                current
+            }
+            None => src,
+        };
+        match place {
+            Place::Register(register, path) if path.is_empty() => {
+                self.emit(Instruction::Move { dst: register, src }, span);
+            }
+            Place::Register(register, path) => self.store_path(register, &path, src, span),
+            Place::Memory(target) => {
+                self.emit(Instruction::Store { target, src }, span);
+            }
+        }
+    }
+
+    /// Replaces the value at the end of a path of parts within the value in a register
+    fn store_path(&mut self, register: Reg, path: &[u16], src: Reg, span: Span) {
+        let [index, rest @ ..] = path else {
+            return;
+        };
+        let instruction = match rest.is_empty() {
+            true => Instruction::SetField {
+                target: register,
+                index: *index,
+                src,
+            },
+            false => {
+                let part = self.temp();
+                self.emit(
+                    Instruction::Field {
+                        dst: part,
+                        src: register,
+                        index: *index,
+                    },
+                    span,
+                );
+                self.store_path(part, rest, src, span);
+                Instruction::SetField {
+                    target: register,
+                    index: *index,
+                    src: part,
+                }
+            }
+        };
+        self.emit(instruction, span);
+    }
+
+    fn read(&mut self, place: &Place, dst: Reg, span: Span) {
+        match place {
+            Place::Register(register, path) => {
+                let mut src = *register;
+                for index in path {
+                    let index = *index;
+                    self.emit(Instruction::Field { dst, src, index }, span);
+                    src = dst;
+                }
+                if src != dst {
+                    self.emit(Instruction::Move { dst, src }, span);
+                }
+            }
+            Place::Memory(src) => {
+                self.emit(Instruction::Load { dst, src: *src }, span);
+            }
+        }
+    }
+
+    /// Works out where an expression naming a value in memory keeps it, or [None] if the
+    /// expression produces a temporary value instead
+    fn place(&mut self, expr: &Expr) -> Option<Place> {
+        match &expr.kind {
+            ExprKind::Name(_) => {
+                let local = *self.body.names.get(&expr.id)?;
+                let register = self.local(local);
+                match self.boxed.contains(&local) {
+                    true => Some(Place::Memory(register)),
+                    false => Some(Place::Register(register, Vec::new())),
+                }
+            }
+            ExprKind::Field { object, field } => {
+                let ty = self.ty(object.id);
+                let place = match ty {
+                    Some(Ty::Reference { .. }) => Place::Memory(self.expr(object)),
+                    _ => self.place(object)?,
+                };
+                let mut ty = ty;
+                let mut place = place;
+                // Fields are reached through references automatically
+                while let Some(Ty::Reference { pointee, .. }) = ty {
+                    if let Ty::Reference { .. } = &**pointee {
+                        let Place::Memory(src) = place else {
+                            unreachable!("references are always kept in memory places");
+                        };
+                        let loaded = self.temp();
+                        self.emit(Instruction::Load { dst: loaded, src }, expr.span);
+                        place = Place::Memory(loaded);
+                    }
+                    ty = Some(&**pointee);
+                }
+                let index = self.field_index(ty, field.symbol);
+                Some(match place {
+                    Place::Register(register, mut path) => {
+                        path.push(index);
+                        Place::Register(register, path)
+                    }
+                    Place::Memory(src) => {
+                        let dst = self.temp();
+                        self.emit(Instruction::Project { dst, src, index }, expr.span);
+                        Place::Memory(dst)
+                    }
+                })
+            }
+            ExprKind::Unary {
+                operator: UnaryOperator::Deref,
+                operand,
+            } => Some(Place::Memory(self.expr(operand))),
+            _ => None,
+        }
+    }
+
+    /// Stores a reference to the value an expression names, or to a copy of a temporary value
+    fn reference_to(&mut self, expr: &Expr, dst: Reg) {
+        match self.place(expr) {
+            Some(Place::Memory(src)) => {
+                self.emit(Instruction::Move { dst, src }, expr.span);
+            }
+            Some(place @ Place::Register(..)) => {
+                // Only happens for locals which are never referenced by name
+                self.read(&place, dst, expr.span);
+                self.emit(Instruction::Alloc { dst, src: dst }, expr.span);
+            }
+            None => {
+                self.expr_to(expr, dst);
+                self.emit(Instruction::Alloc { dst, src: dst }, expr.span);
+            }
+        }
+    }
+
+    fn field_index(&self, ty: Option<&Ty>, field: Symbol) -> u16 {
+        let Some(Ty::Adt(id, _)) = ty else {
+            panic!("expected a `type`, found {:?}", ty);
+        };
+        let (index, _) = self
+            .compiler
+            .defs
+            .types
+            .adt(*id)
+            .field(field)
+            .expect("fields are checked to exist");
+        index as u16
+    }
+
+    fn variant(&self, path: &Path) -> (u32, u32) {
+        let (id, index): (AdtId, usize) = self
+            .compiler
+            .defs
+            .types
+            .resolve_variant(path, &mut Vec::new())
+            .expect("variants are checked to exist");
+        (id.0, index as u32)
+    }
+
+    /// Compiles a call to an [Intrinsic] of a number
+    fn intrinsic(
+        &mut self,
+        dst: Reg,
+        intrinsic: Intrinsic,
+        callee: &Expr,
+        arguments: &[Expr],
+        span: Span,
+    ) {
+        let ExprKind::Field { object, .. } = &callee.kind else {
+            unreachable!("intrinsics are called as methods");
+        };
+        let src = self.expr(object);
+        let instruction = match intrinsic {
+            Intrinsic::Arith(operator, overflow) => Instruction::Arith {
+                op: match operator {
+                    BinaryOperator::Add => ArithOp::Add,
+                    BinaryOperator::Subtract => ArithOp::Subtract,
+                    BinaryOperator::Multiply => ArithOp::Multiply,
+                    _ => ArithOp::Divide,
+                },
+                ty: self.primitive(object),
+                overflow,
+                dst,
+                left: src,
+                right: self.expr(&arguments[0]),
+            },
+            Intrinsic::Convert(ty) => Instruction::Convert {
+                ty,
+                overflow: self.compiler.overflow,
+                dst,
+                src,
+            },
+        };
+        self.emit(instruction, span);
+    }
+
+    fn call(&mut self, dst: Reg, callee: &Expr, arguments: &[Expr], span: Span) {
+        let defs = self.compiler.defs;
+        match &callee.kind {
+            ExprKind::Field { object, field } => {
+                let start = self.temps(arguments.len() as u16 + 1);
+                let receiver = self.ty(object.id);
+                if self.compiler.receiver_by_reference(receiver, field.symbol)
+                    && !self.is_reference(object)
+                {
+                    self.reference_to(object, start);
+                } else {
+                    self.expr_to(object, start);
+                }
+                for (index, argument) in arguments.iter().enumerate() {
+                    self.expr_to(argument, Reg(start.0 + 1 + index as u16));
+                }
+                let method = Constant::Str(field.symbol.to_string());
+                let instruction = Instruction::CallMethod {
+                    dst,
+                    method: self.compiler.constant(method),
+                    arguments: start,
+                    count: arguments.len() as u16 + 1,
+                };
+                self.emit(instruction, span);
+            }
+            ExprKind::Path(path) if defs.traits.lookup(path.segments[0].symbol).is_some() => {
+                let start = self.exprs_to_temps(arguments.iter());
+                let method = Constant::Str(path.name().symbol.to_string());
+                let instruction = Instruction::CallMethod {
+                    dst,
+                    method: self.compiler.constant(method),
+                    arguments: start,
+                    count: arguments.len() as u16,
+                };
+                self.emit(instruction, span);
+            }
+            ExprKind::Path(path) if defs.traits.resolve_associated(&defs.types, path).is_some() => {
+                let (implementation, sig) = defs
+                    .traits
+                    .resolve_associated(&defs.types, path)
+                    .expect("the path was just resolved");
+                let function = self
+                    .compiler
+                    .associated
+                    .iter()
+                    .find(|(x, name, _)| {
+                        std::ptr::eq(*x, implementation) && *name == sig.name.symbol
+                    })
+                    .map(|(_, _, index)| *index)
+                    .expect("associated functions are compiled");
+                let start = self.exprs_to_temps(arguments.iter());
+                let instruction = Instruction::Call {
+                    dst,
+                    function,
+                    arguments: start,
+                    count: arguments.len() as u16,
+                };
+                self.emit(instruction, span);
+            }
+            ExprKind::Path(path) => {
+                let (adt, variant) = self.variant(path);
+                let start = self.exprs_to_temps(arguments.iter());
+                let count = arguments.len() as u16;
+                self.emit(
+                    Instruction::Variant {
+                        dst,
+                        adt,
+                        variant,
+                        start,
+                        count,
+                    },
+                    span,
+                );
+            }
+            ExprKind::Name(name) if !self.body.names.contains_key(&callee.id) => {
+                let function = *self
+                    .compiler
+                    .functions
+                    .get(&name.symbol)
+                    .expect("calls are checked to name a function");
+                let start = self.exprs_to_temps(arguments.iter());
+                let instruction = Instruction::Call {
+                    dst,
+                    function,
+                    arguments: start,
+                    count: arguments.len() as u16,
+                };
+                self.emit(instruction, span);
+            }
+            _ => {
+                let callee = self.expr(callee);
+                let start = self.exprs_to_temps(arguments.iter());
+                let instruction = Instruction::CallValue {
+                    dst,
+                    callee,
+                    arguments: start,
+                    count: arguments.len() as u16,
+                };
+                self.emit(instruction, span);
+            }
+        }
+    }
+
+    /// Emits the checks of a pattern against the value within a register, adding a jump to
+    /// `fails` for every check which fails
+    fn test_pattern(&mut self, pattern: &Pattern, value: Reg, fails: &mut Vec<usize>) {
+        let span = pattern.span;
+        let mut check = |this: &mut Self, op, literal: &LiteralKind| {
+            let ty = match this.ty(pattern.id) {
+                Some(Ty::Primitive(primitive)) => *primitive,
+                _ => PrimitiveType::of_literal(literal),
+            };
+            let constant = this.temp();
+            this.constant(constant, literal_constant(literal, ty), span);
+            let condition = this.temp();
+            this.emit(
+                Instruction::Compare {
+                    op,
+                    dst: condition,
+                    left: value,
+                    right: constant,
+                },
+                span,
+            );
+            fails.push(this.emit(
+                Instruction::JumpIfNot {
+                    condition,
+                    target: 0,
+                },
+                span,
+            ));
+        };
+        match &pattern.kind {
+            PatternKind::Wildcard | PatternKind::Binding { .. } => {}
+            PatternKind::Literal(literal) => check(self, CompareOp::EqualTo, literal),
+            PatternKind::Range {
+                start,
+                end,
+                inclusive,
+            } => {
+                check(self, CompareOp::GreaterOrEqual, start);
+                match inclusive {
+                    true => check(self, CompareOp::LessOrEqual, end),
+                    false => check(self, CompareOp::Lesser, end),
+                }
+            }
+            PatternKind::Tuple(elements) => self.test_parts(elements, value, fails),
+            PatternKind::Variant { path, payload } => {
+                let (_, variant) = self.variant(path);
+                let condition = self.temp();
+                self.emit(
+                    Instruction::IsVariant {
+                        dst: condition,
+                        src: value,
+                        variant,
+                    },
+                    span,
+                );
+                fails.push(self.emit(
+                    Instruction::JumpIfNot {
+                        condition,
+                        target: 0,
+                    },
+                    span,
+                ));
+                self.test_parts(payload, value, fails);
+            }
+        }
+    }
+
+    fn test_parts(&mut self, patterns: &[Pattern], value: Reg, fails: &mut Vec<usize>) {
+        for (index, pattern) in patterns.iter().enumerate() {
+            if matches!(
+                pattern.kind,
+                PatternKind::Wildcard | PatternKind::Binding { .. }
+            ) {
+                continue;
+            }
+            let part = self.temp();
+            self.emit(
+                Instruction::Field {
+                    dst: part,
+                    src: value,
+                    index: index as u16,
+                },
+                pattern.span,
+            );
+            self.test_pattern(pattern, part, fails);
+        }
+    }
+
+    /// Stores the parts of a value in the locals a pattern binds, once it is known to match
+    fn bind(&mut self, pattern: &Pattern, value: Reg) {
+        match &pattern.kind {
+            PatternKind::Binding { .. } => {
+                let local = self.body.declarations[&pattern.id];
+                let dst = self.local(local);
+                let instruction = match self.boxed.contains(&local) {
+                    true => Instruction::Alloc { dst, src: value },
+                    false => Instruction::Move { dst, src: value },
+                };
+                self.emit(instruction, pattern.span);
+            }
+            PatternKind::Tuple(elements)
+            | PatternKind::Variant {
+                payload: elements, ..
+            } => {
+                for (index, element) in elements.iter().enumerate() {
+                    if !binds(element) {
+                        continue;
+                    }
+                    let part = self.temp();
+                    self.emit(
+                        Instruction::Field {
+                            dst: part,
+                            src: value,
+                            index: index as u16,
+                        },
+                        element.span,
+                    );
+                    self.bind(element, part);
+                }
+            }
+            PatternKind::Wildcard | PatternKind::Literal(_) | PatternKind::Range { .. } => {}
+        }
+    }
+}
+
+/// Checks if a pattern binds any locals
+fn binds(pattern: &Pattern) -> bool {
+    match &pattern.kind {
+        PatternKind::Binding { .. } => true,
+        PatternKind::Tuple(elements)
+        | PatternKind::Variant {
+            payload: elements, ..
+        } => elements.iter().any(binds),
+        PatternKind::Wildcard | PatternKind::Literal(_) | PatternKind::Range { .. } => false,
+    }
+}
+
+/// Checks if evaluating an expression can not change any local
+fn is_simple(expr: &Expr) -> bool {
+    matches!(
+        expr.kind,
+        ExprKind::Literal(_) | ExprKind::Name(_) | ExprKind::Path(_)
+    )
+}
+
+fn literal_constant(literal: &LiteralKind, ty: PrimitiveType) -> Constant {
+    let integer = match *literal {
+        LiteralKind::UInt8(x) => x as i128,
+        LiteralKind::Int8(x) => x as i128,
+        LiteralKind::UInt32(x) => x as i128,
+        LiteralKind::Int32(x) => x as i128,
+        LiteralKind::UInt64(x) => x as i128,
+        LiteralKind::Int64(x) => x as i128,
+        LiteralKind::Float32(x) => return Constant::Float(ty, x as f64),
+        LiteralKind::Float64(x) => return Constant::Float(ty, x),
+        LiteralKind::Str(x) => return Constant::Str(x.to_string()),
+        LiteralKind::Char(x) => return Constant::Integer(PrimitiveType::Char, x as i128),
+        LiteralKind::Boolean(x) => return Constant::Bool(x),
+    };
+    Constant::Integer(ty, integer)
+}
@@ -0,0 +1,260 @@
+//! Writes a [Program] out as text, one instruction per line
+
+use std::fmt::Write;
+
+use shark_sema::numeric::Overflow;
+
+use crate::bytecode::{ArithOp, CompareOp, Instruction, Program, Reg, TypeKey};
+
+fn registers(start: Reg, count: u16) -> String {
+    let registers: Vec<String> = (0..count).map(|x| Reg(start.0 + x).to_string()).collect();
+    format!("({})", registers.join(", "))
+}
+
+fn arith_name(op: ArithOp) -> &'static str {
+    match op {
+        ArithOp::Add => "add",
+        ArithOp::Subtract => "sub",
+        ArithOp::Multiply => "mul",
+        ArithOp::Divide => "div",
+        ArithOp::BitwiseAnd => "and",
+        ArithOp::ShiftLeft => "shl",
+        ArithOp::ShiftRight => "shr",
+    }
+}
+
+/// Gets the suffix of an instruction which overflows some other way than trapping
+fn overflow_suffix(overflow: Overflow) -> &'static str {
+    match overflow {
+        Overflow::Trap => "",
+        Overflow::Wrap => ".wrap",
+        Overflow::Saturate => ".sat",
+    }
+}
+
+fn compare_name(op: CompareOp) -> &'static str {
+    match op {
+        CompareOp::Greater => "gt",
+        CompareOp::Lesser => "lt",
+        CompareOp::GreaterOrEqual => "ge",
+        CompareOp::LessOrEqual => "le",
+        CompareOp::EqualTo => "eq",
+        CompareOp::NotEqual => "ne",
+    }
+}
+
+/// Writes out a single instruction, naming the constants, functions and types it refers to
+pub fn disassemble_instruction(program: &Program, instruction: &Instruction) -> String {
+    let adt = |id: u32| program.adts[id as usize].name.clone();
+    match *instruction {
+        Instruction::Const { dst, constant } => {
+            format!("const {}, {}", dst, program.constants[constant as usize])
+        }
+        Instruction::Move { dst, src } => format!("move {}, {}", dst, src),
+        Instruction::Arith {
+            op,
+            ty,
+            overflow,
+            dst,
+            left,
+            right,
+        } => format!(
+            "{}{}.{} {}, {}, {}",
+            arith_name(op),
+            overflow_suffix(overflow),
+            ty,
+            dst,
+            left,
+            right
+        ),
+        Instruction::Compare {
+            op,
+            dst,
+            left,
+            right,
+        } => format!("{} {}, {}, {}", compare_name(op), dst, left, right),
+        Instruction::Negate {
+            ty,
+            overflow,
+            dst,
+            src,
+        } => format!("neg{}.{} {}, {}", overflow_suffix(overflow), ty, dst, src),
+        Instruction::Convert {
+            ty,
+            overflow,
+            dst,
+            src,
+        } => format!("conv{}.{} {}, {}", overflow_suffix(overflow), ty, dst, src),
+        Instruction::Not { dst, src } => format!("not {}, {}", dst, src),
+        Instruction::Jump { target } => format!("jump {}", target),
+        Instruction::JumpIf { condition, target } => format!("jump.if {}, {}", condition, target),
+        Instruction::JumpIfNot { condition, target } => {
+            format!("jump.ifnot {}, {}", condition, target)
+        }
+        Instruction::Call {
+            dst,
+            function,
+            arguments,
+            count,
+        } => format!(
+            "call {}, {}{}",
+            dst,
+            program.functions[function as usize].name,
+            registers(arguments, count)
+        ),
+        Instruction::CallValue {
+            dst,
+            callee,
+            arguments,
+            count,
+        } => format!(
+            "call.value {}, {}{}",
+            dst,
+            callee,
+            registers(arguments, count)
+        ),
+        Instruction::MakeClosure {
+            dst,
+            function,
+            captures,
+            count,
+        } => format!(
+            "closure {}, {}{}",
+            dst,
+            program.functions[function as usize].name,
+            registers(captures, count)
+        ),
+        Instruction::CallMethod {
+            dst,
+            method,
+            arguments,
+            count,
+        } => format!(
+            "call.method {}, {}{}",
+            dst,
+            program.constants[method as usize],
+            registers(arguments, count)
+        ),
+        Instruction::Return { src } => format!("ret {}", src),
+        Instruction::Yield { src } => format!("yield {}", src),
+        Instruction::Next {
+            dst,
+            generator,
+            exit,
+        } => format!("next {}, {}, {}", dst, generator, exit),
+        Instruction::Tuple { dst, start, count } => {
+            format!("tuple {}, {}", dst, registers(start, count))
+        }
+        Instruction::Struct {
+            dst,
+            adt: id,
+            start,
+            count,
+        } => format!("struct {}, {}{}", dst, adt(id), registers(start, count)),
+        Instruction::Variant {
+            dst,
+            adt: id,
+            variant,
+            start,
+            count,
+        } => {
+            let info = &program.adts[id as usize];
+            let name = format!("{}::{}", info.name, info.variants[variant as usize]);
+            match count {
+                0 => format!("variant {}, {}", dst, name),
+                _ => format!("variant {}, {}{}", dst, name, registers(start, count)),
+            }
+        }
+        Instruction::Field { dst, src, index } => format!("field {}, {}.{}", dst, src, index),
+        Instruction::SetField { target, index, src } => {
+            format!("setfield {}.{}, {}", target, index, src)
+        }
+        Instruction::IsVariant { dst, src, variant } => {
+            format!("isvariant {}, {}, {}", dst, src, variant)
+        }
+        Instruction::Alloc { dst, src } => format!("alloc {}, {}", dst, src),
+        Instruction::Project { dst, src, index } => {
+            format!("project {}, {}.{}", dst, src, index)
+        }
+        Instruction::Load { dst, src } => format!("load {}, {}", dst, src),
+        Instruction::Store { target, src } => format!("store {}, {}", target, src),
+        Instruction::NoMatch => "nomatch".to_string(),
+    }
+}
+
+fn type_name(program: &Program, key: Option<TypeKey>) -> String {
+    match key {
+        Some(TypeKey::Primitive(primitive)) => primitive.to_string(),
+        Some(TypeKey::Unit) => "()".to_string(),
+        Some(TypeKey::Tuple) => "(..)".to_string(),
+        Some(TypeKey::Adt(id)) => program.adts[id as usize].name.clone(),
+        Some(TypeKey::Reference) => "ref ..".to_string(),
+        Some(TypeKey::Generator) => "yield ..".to_string(),
+        Some(TypeKey::Closure) => "fun(..)".to_string(),
+        None => "..".to_string(),
+    }
+}
+
+/// Writes out every function of a program, followed by the methods of every `impl`
+///
+/// ```text
+/// fun double (parameters: 1, registers: 3)
+///     0  const r2, 2 :: Int32
+///     1  mul.Int32 r1, r0, r2
+///     2  ret r1
+/// ```
+pub fn disassemble(program: &Program) -> String {
+    let mut result = String::new();
+    for (index, function) in program.functions.iter().enumerate() {
+        if index > 0 {
+            result.push('\n');
+        }
+        let kind = if function.is_generator {
+            "generator"
+        } else {
+            "fun"
+        };
+        if let Some(builtin) = &function.builtin {
+            let _ = writeln!(
+                result,
+                "extern fun {} (parameters: {}, builtin: {})",
+                function.name, function.arity, builtin
+            );
+            continue;
+        }
+        if function.is_foreign {
+            let _ = writeln!(
+                result,
+                "extern \"C\" fun {} (parameters: {})",
+                function.name, function.arity
+            );
+            continue;
+        }
+        let captures = match function.captures {
+            0 => String::new(),
+            count => format!(", captures: {}", count),
+        };
+        let _ = writeln!(
+            result,
+            "{} {} (parameters: {}{}, registers: {})",
+            kind, function.name, function.arity, captures, function.registers
+        );
+        let width = function.code.len().saturating_sub(1).to_string().len();
+        for (label, instruction) in function.code.iter().enumerate() {
+            let text = disassemble_instruction(program, instruction);
+            let _ = writeln!(result, "    {:>width$}  {}", label, text);
+        }
+    }
+    for implementation in &program.impls {
+        let _ = writeln!(
+            result,
+            "\nimpl for {}",
+            type_name(program, implementation.self_type)
+        );
+        for (name, function) in &implementation.methods {
+            let target = &program.functions[*function as usize].name;
+            let _ = writeln!(result, "    {} => {}", name, target);
+        }
+    }
+    result
+}
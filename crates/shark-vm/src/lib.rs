@@ -0,0 +1,32 @@
+use bytecode::Program;
+use shark_core::diagnostic::Diagnostic;
+use shark_sema::ty::PrimitiveType;
+use shark_std::runtime::Host;
+use value::Value;
+use vm::{Halt, Vm};
+
+pub mod bytecode;
+pub mod compile;
+pub mod disasm;
+pub mod sbc;
+pub mod value;
+pub mod vm;
+
+#[cfg(test)]
+pub mod tests;
+
+pub use compile::compile_module;
+
+/// Runs a [Program] from its `pub fun main()` within a host. Returns the value `main` returns, the
+/// code given to `exit` as an `Int32`, or the runtime error which stopped the program
+pub fn run(program: &Program, host: Host) -> Result<Value, Diagnostic> {
+    let Some(entry) = program.entry else {
+        return Err(Diagnostic::error("`main` function not found")
+            .with_note("a program is run from a `pub fun main()` which takes no parameters"));
+    };
+    match Vm::new(program, host).call(entry, Vec::new()) {
+        Ok(value) => Ok(value),
+        Err(Halt::Exit(code)) => Ok(Value::Integer(PrimitiveType::Int32, code as i128)),
+        Err(Halt::Error(diagnostic)) => Err(diagnostic),
+    }
+}
@@ -0,0 +1,1035 @@
+//! The `.sbc` file format, a [Program] written out as bytes. Files start with the magic bytes
+//! `SBC\0` and a version, followed by the constants, types, functions and implementations of the
+//! program, in that order. Numbers are little endian, and strings and lists are prefixed with
+//! their length as a `u32`
+//!
+//! Reading a file checks that every index within it is in bounds, so that a file which was not
+//! written by [write] can not make the VM read past the end of anything
+
+use std::fmt::Display;
+
+use shark_core::source::Span;
+use shark_sema::{
+    numeric::{Overflow, NUMERIC},
+    ty::PrimitiveType,
+};
+use shark_std::runtime::Builtin;
+
+use crate::bytecode::{
+    AdtInfo, ArithOp, CompareOp, Constant, Function, Impl, Instruction, Label, Program, Reg,
+    TypeKey,
+};
+
+pub const MAGIC: &[u8; 4] = b"SBC\0";
+
+/// The version of the format written by [write]. Only files of this version can be read
+pub const VERSION: u16 = 5;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SbcError {
+    /// The file does not start with [MAGIC]
+    NotBytecode,
+    UnsupportedVersion(u16),
+    UnexpectedEnd,
+    /// The file has the right layout, but something within it is not valid
+    Invalid(&'static str),
+}
+
+impl Display for SbcError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NotBytecode => write!(f, "not a Shark bytecode file"),
+            Self::UnsupportedVersion(version) => write!(
+                f,
+                "unsupported bytecode version {}, expected version {}",
+                version, VERSION
+            ),
+            Self::UnexpectedEnd => write!(f, "unexpected end of bytecode file"),
+            Self::Invalid(what) => write!(f, "invalid bytecode file: {}", what),
+        }
+    }
+}
+
+impl std::error::Error for SbcError {}
+
+fn arith_ops() -> [ArithOp; 7] {
+    [
+        ArithOp::Add,
+        ArithOp::Subtract,
+        ArithOp::Multiply,
+        ArithOp::Divide,
+        ArithOp::BitwiseAnd,
+        ArithOp::ShiftLeft,
+        ArithOp::ShiftRight,
+    ]
+}
+
+fn overflows() -> [Overflow; 3] {
+    [Overflow::Trap, Overflow::Wrap, Overflow::Saturate]
+}
+
+fn compare_ops() -> [CompareOp; 6] {
+    [
+        CompareOp::Greater,
+        CompareOp::Lesser,
+        CompareOp::GreaterOrEqual,
+        CompareOp::LessOrEqual,
+        CompareOp::EqualTo,
+        CompareOp::NotEqual,
+    ]
+}
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u16(&mut self, value: u16) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn len(&mut self, value: usize) {
+        self.u32(value as u32);
+    }
+
+    fn reg(&mut self, value: Reg) {
+        self.u16(value.0);
+    }
+
+    fn str(&mut self, value: &str) {
+        self.len(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn primitive(&mut self, value: PrimitiveType) {
+        let index = PrimitiveType::ALL.iter().position(|x| *x == value);
+        self.u8(index.expect("every primitive type is listed") as u8);
+    }
+
+    fn overflow(&mut self, value: Overflow) {
+        let index = overflows().iter().position(|x| *x == value);
+        self.u8(index.expect("every way of overflowing is listed") as u8);
+    }
+
+    fn constant(&mut self, constant: &Constant) {
+        match constant {
+            Constant::Unit => self.u8(0),
+            Constant::Bool(x) => {
+                self.u8(1);
+                self.u8(*x as u8);
+            }
+            Constant::Integer(ty, x) => {
+                self.u8(2);
+                self.primitive(*ty);
+                self.bytes.extend_from_slice(&x.to_le_bytes());
+            }
+            Constant::Float(ty, x) => {
+                self.u8(3);
+                self.primitive(*ty);
+                self.bytes.extend_from_slice(&x.to_le_bytes());
+            }
+            Constant::Str(x) => {
+                self.u8(4);
+                self.str(x);
+            }
+        }
+    }
+
+    fn type_key(&mut self, key: Option<TypeKey>) {
+        match key {
+            None => self.u8(0),
+            Some(TypeKey::Primitive(primitive)) => {
+                self.u8(1);
+                self.primitive(primitive);
+            }
+            Some(TypeKey::Unit) => self.u8(2),
+            Some(TypeKey::Tuple) => self.u8(3),
+            Some(TypeKey::Adt(id)) => {
+                self.u8(4);
+                self.u32(id);
+            }
+            Some(TypeKey::Reference) => self.u8(5),
+            Some(TypeKey::Generator) => self.u8(6),
+            Some(TypeKey::Closure) => self.u8(7),
+        }
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        match *instruction {
+            Instruction::Const { dst, constant } => {
+                self.u8(0);
+                self.reg(dst);
+                self.u32(constant);
+            }
+            Instruction::Move { dst, src } => {
+                self.u8(1);
+                self.reg(dst);
+                self.reg(src);
+            }
+            Instruction::Arith {
+                op,
+                ty,
+                overflow,
+                dst,
+                left,
+                right,
+            } => {
+                self.u8(2);
+                let op = arith_ops().iter().position(|x| *x == op);
+                self.u8(op.expect("every operation is listed") as u8);
+                self.primitive(ty);
+                self.overflow(overflow);
+                self.reg(dst);
+                self.reg(left);
+                self.reg(right);
+            }
+            Instruction::Compare {
+                op,
+                dst,
+                left,
+                right,
+            } => {
+                self.u8(3);
+                let op = compare_ops().iter().position(|x| *x == op);
+                self.u8(op.expect("every comparison is listed") as u8);
+                self.reg(dst);
+                self.reg(left);
+                self.reg(right);
+            }
+            Instruction::Negate {
+                ty,
+                overflow,
+                dst,
+                src,
+            } => {
+                self.u8(4);
+                self.primitive(ty);
+                self.overflow(overflow);
+                self.reg(dst);
+                self.reg(src);
+            }
+            Instruction::Not { dst, src } => {
+                self.u8(5);
+                self.reg(dst);
+                self.reg(src);
+            }
+            Instruction::Jump { target } => {
+                self.u8(6);
+                self.u32(target);
+            }
+            Instruction::JumpIf { condition, target } => {
+                self.u8(7);
+                self.reg(condition);
+                self.u32(target);
+            }
+            Instruction::JumpIfNot { condition, target } => {
+                self.u8(8);
+                self.reg(condition);
+                self.u32(target);
+            }
+            Instruction::Call {
+                dst,
+                function,
+                arguments,
+                count,
+            } => {
+                self.u8(9);
+                self.reg(dst);
+                self.u32(function);
+                self.reg(arguments);
+                self.u16(count);
+            }
+            Instruction::CallMethod {
+                dst,
+                method,
+                arguments,
+                count,
+            } => {
+                self.u8(10);
+                self.reg(dst);
+                self.u32(method);
+                self.reg(arguments);
+                self.u16(count);
+            }
+            Instruction::Return { src } => {
+                self.u8(11);
+                self.reg(src);
+            }
+            Instruction::Yield { src } => {
+                self.u8(12);
+                self.reg(src);
+            }
+            Instruction::Next {
+                dst,
+                generator,
+                exit,
+            } => {
+                self.u8(13);
+                self.reg(dst);
+                self.reg(generator);
+                self.u32(exit);
+            }
+            Instruction::Tuple { dst, start, count } => {
+                self.u8(14);
+                self.reg(dst);
+                self.reg(start);
+                self.u16(count);
+            }
+            Instruction::Struct {
+                dst,
+                adt,
+                start,
+                count,
+            } => {
+                self.u8(15);
+                self.reg(dst);
+                self.u32(adt);
+                self.reg(start);
+                self.u16(count);
+            }
+            Instruction::Variant {
+                dst,
+                adt,
+                variant,
+                start,
+                count,
+            } => {
+                self.u8(16);
+                self.reg(dst);
+                self.u32(adt);
+                self.u32(variant);
+                self.reg(start);
+                self.u16(count);
+            }
+            Instruction::Field { dst, src, index } => {
+                self.u8(17);
+                self.reg(dst);
+                self.reg(src);
+                self.u16(index);
+            }
+            Instruction::SetField { target, index, src } => {
+                self.u8(18);
+                self.reg(target);
+                self.u16(index);
+                self.reg(src);
+            }
+            Instruction::IsVariant { dst, src, variant } => {
+                self.u8(19);
+                self.reg(dst);
+                self.reg(src);
+                self.u32(variant);
+            }
+            Instruction::Alloc { dst, src } => {
+                self.u8(20);
+                self.reg(dst);
+                self.reg(src);
+            }
+            Instruction::Project { dst, src, index } => {
+                self.u8(21);
+                self.reg(dst);
+                self.reg(src);
+                self.u16(index);
+            }
+            Instruction::Load { dst, src } => {
+                self.u8(22);
+                self.reg(dst);
+                self.reg(src);
+            }
+            Instruction::Store { target, src } => {
+                self.u8(23);
+                self.reg(target);
+                self.reg(src);
+            }
+            Instruction::NoMatch => self.u8(24),
+            Instruction::Convert {
+                ty,
+                overflow,
+                dst,
+                src,
+            } => {
+                self.u8(25);
+                self.primitive(ty);
+                self.overflow(overflow);
+                self.reg(dst);
+                self.reg(src);
+            }
+            Instruction::CallValue {
+                dst,
+                callee,
+                arguments,
+                count,
+            } => {
+                self.u8(26);
+                self.reg(dst);
+                self.reg(callee);
+                self.reg(arguments);
+                self.u16(count);
+            }
+            Instruction::MakeClosure {
+                dst,
+                function,
+                captures,
+                count,
+            } => {
+                self.u8(27);
+                self.reg(dst);
+                self.u32(function);
+                self.reg(captures);
+                self.u16(count);
+            }
+        }
+    }
+}
+
+/// Writes a program out as the bytes of a `.sbc` file
+pub fn write(program: &Program) -> Vec<u8> {
+    let mut writer = Writer::default();
+    writer.bytes.extend_from_slice(MAGIC);
+    writer.u16(VERSION);
+
+    writer.len(program.constants.len());
+    for constant in &program.constants {
+        writer.constant(constant);
+    }
+    writer.len(program.adts.len());
+    for adt in &program.adts {
+        writer.str(&adt.name);
+        writer.len(adt.variants.len());
+        for variant in &adt.variants {
+            writer.str(variant);
+        }
+    }
+    writer.len(program.functions.len());
+    for function in &program.functions {
+        writer.str(&function.name);
+        writer.u16(function.arity);
+        writer.u16(function.captures);
+        writer.u16(function.registers);
+        writer.u8(function.is_generator as u8
+            | (function.self_by_reference as u8) << 1
+            | (function.builtin.is_some() as u8) << 2
+            | (function.is_foreign as u8) << 3);
+        if let Some(builtin) = &function.builtin {
+            writer.str(builtin);
+        }
+        writer.len(function.code.len());
+        for (instruction, span) in function.code.iter().zip(&function.spans) {
+            writer.instruction(instruction);
+            writer.len(span.start);
+            writer.len(span.end);
+        }
+    }
+    writer.len(program.impls.len());
+    for implementation in &program.impls {
+        writer.type_key(implementation.self_type);
+        writer.len(implementation.methods.len());
+        for (name, function) in &implementation.methods {
+            writer.str(name);
+            writer.u32(*function);
+        }
+    }
+    match program.entry {
+        Some(entry) => {
+            writer.u8(1);
+            writer.u32(entry);
+        }
+        None => writer.u8(0),
+    }
+    writer.bytes
+}
+
+struct Reader<'bytes> {
+    bytes: &'bytes [u8],
+}
+
+impl Reader<'_> {
+    fn take<const N: usize>(&mut self) -> Result<[u8; N], SbcError> {
+        let (taken, rest) = self
+            .bytes
+            .split_first_chunk::<N>()
+            .ok_or(SbcError::UnexpectedEnd)?;
+        self.bytes = rest;
+        Ok(*taken)
+    }
+
+    fn u8(&mut self) -> Result<u8, SbcError> {
+        Ok(self.take::<1>()?[0])
+    }
+
+    fn u16(&mut self) -> Result<u16, SbcError> {
+        Ok(u16::from_le_bytes(self.take()?))
+    }
+
+    fn u32(&mut self) -> Result<u32, SbcError> {
+        Ok(u32::from_le_bytes(self.take()?))
+    }
+
+    /// Reads the length of a list, which can not be longer than the rest of the file as every
+    /// element takes at least a byte
+    fn len(&mut self) -> Result<usize, SbcError> {
+        let len = self.u32()? as usize;
+        match len <= self.bytes.len() {
+            true => Ok(len),
+            false => Err(SbcError::UnexpectedEnd),
+        }
+    }
+
+    fn reg(&mut self) -> Result<Reg, SbcError> {
+        Ok(Reg(self.u16()?))
+    }
+
+    fn str(&mut self) -> Result<String, SbcError> {
+        let len = self.len()?;
+        let (text, rest) = self.bytes.split_at(len);
+        self.bytes = rest;
+        String::from_utf8(text.to_vec()).map_err(|_| SbcError::Invalid("a string is not UTF-8"))
+    }
+
+    fn primitive(&mut self) -> Result<PrimitiveType, SbcError> {
+        let index = self.u8()? as usize;
+        PrimitiveType::ALL
+            .get(index)
+            .copied()
+            .ok_or(SbcError::Invalid("unknown primitive type"))
+    }
+
+    fn overflow(&mut self) -> Result<Overflow, SbcError> {
+        let index = self.u8()? as usize;
+        overflows()
+            .get(index)
+            .copied()
+            .ok_or(SbcError::Invalid("unknown way of overflowing"))
+    }
+
+    fn constant(&mut self) -> Result<Constant, SbcError> {
+        Ok(match self.u8()? {
+            0 => Constant::Unit,
+            1 => Constant::Bool(self.u8()? != 0),
+            2 => {
+                let ty = self.primitive()?;
+                let value = i128::from_le_bytes(self.take()?);
+                if !(ty.is_integer() || ty == PrimitiveType::Char) {
+                    return Err(SbcError::Invalid(
+                        "integer constant of a type which is not an integer",
+                    ));
+                }
+                let (min, max) = ty.bounds();
+                if !(min..=max).contains(&value) {
+                    return Err(SbcError::Invalid("integer constant out of range"));
+                }
+                Constant::Integer(ty, value)
+            }
+            3 => {
+                let ty = self.primitive()?;
+                if !ty.is_float() {
+                    return Err(SbcError::Invalid(
+                        "float constant of a type which is not a float",
+                    ));
+                }
+                Constant::Float(ty, f64::from_le_bytes(self.take()?))
+            }
+            4 => Constant::Str(self.str()?),
+            _ => return Err(SbcError::Invalid("unknown kind of constant")),
+        })
+    }
+
+    fn type_key(&mut self) -> Result<Option<TypeKey>, SbcError> {
+        Ok(Some(match self.u8()? {
+            0 => return Ok(None),
+            1 => TypeKey::Primitive(self.primitive()?),
+            2 => TypeKey::Unit,
+            3 => TypeKey::Tuple,
+            4 => TypeKey::Adt(self.u32()?),
+            5 => TypeKey::Reference,
+            6 => TypeKey::Generator,
+            7 => TypeKey::Closure,
+            _ => return Err(SbcError::Invalid("unknown kind of type")),
+        }))
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, SbcError> {
+        Ok(match self.u8()? {
+            0 => Instruction::Const {
+                dst: self.reg()?,
+                constant: self.u32()?,
+            },
+            1 => Instruction::Move {
+                dst: self.reg()?,
+                src: self.reg()?,
+            },
+            2 => Instruction::Arith {
+                op: *arith_ops()
+                    .get(self.u8()? as usize)
+                    .ok_or(SbcError::Invalid("unknown arithmetic operation"))?,
+                ty: self.primitive()?,
+                overflow: self.overflow()?,
+                dst: self.reg()?,
+                left: self.reg()?,
+                right: self.reg()?,
+            },
+            3 => Instruction::Compare {
+                op: *compare_ops()
+                    .get(self.u8()? as usize)
+                    .ok_or(SbcError::Invalid("unknown comparison"))?,
+                dst: self.reg()?,
+                left: self.reg()?,
+                right: self.reg()?,
+            },
+            4 => Instruction::Negate {
+                ty: self.primitive()?,
+                overflow: self.overflow()?,
+                dst: self.reg()?,
+                src: self.reg()?,
+            },
+            5 => Instruction::Not {
+                dst: self.reg()?,
+                src: self.reg()?,
+            },
+            6 => Instruction::Jump {
+                target: self.u32()?,
+            },
+            7 => Instruction::JumpIf {
+                condition: self.reg()?,
+                target: self.u32()?,
+            },
+            8 => Instruction::JumpIfNot {
+                condition: self.reg()?,
+                target: self.u32()?,
+            },
+            9 => Instruction::Call {
+                dst: self.reg()?,
+                function: self.u32()?,
+                arguments: self.reg()?,
+                count: self.u16()?,
+            },
+            10 => Instruction::CallMethod {
+                dst: self.reg()?,
+                method: self.u32()?,
+                arguments: self.reg()?,
+                count: self.u16()?,
+            },
+            11 => Instruction::Return { src: self.reg()? },
+            12 => Instruction::Yield { src: self.reg()? },
+            13 => Instruction::Next {
+                dst: self.reg()?,
+                generator: self.reg()?,
+                exit: self.u32()?,
+            },
+            14 => Instruction::Tuple {
+                dst: self.reg()?,
+                start: self.reg()?,
+                count: self.u16()?,
+            },
+            15 => Instruction::Struct {
+                dst: self.reg()?,
+                adt: self.u32()?,
+                start: self.reg()?,
+                count: self.u16()?,
+            },
+            16 => Instruction::Variant {
+                dst: self.reg()?,
+                adt: self.u32()?,
+                variant: self.u32()?,
+                start: self.reg()?,
+                count: self.u16()?,
+            },
+            17 => Instruction::Field {
+                dst: self.reg()?,
+                src: self.reg()?,
+                index: self.u16()?,
+            },
+            18 => Instruction::SetField {
+                target: self.reg()?,
+                index: self.u16()?,
+                src: self.reg()?,
+            },
+            19 => Instruction::IsVariant {
+                dst: self.reg()?,
+                src: self.reg()?,
+                variant: self.u32()?,
+            },
+            20 => Instruction::Alloc {
+                dst: self.reg()?,
+                src: self.reg()?,
+            },
+            21 => Instruction::Project {
+                dst: self.reg()?,
+                src: self.reg()?,
+                index: self.u16()?,
+            },
+            22 => Instruction::Load {
+                dst: self.reg()?,
+                src: self.reg()?,
+            },
+            23 => Instruction::Store {
+                target: self.reg()?,
+                src: self.reg()?,
+            },
+            24 => Instruction::NoMatch,
+            25 => Instruction::Convert {
+                ty: self.primitive()?,
+                overflow: self.overflow()?,
+                dst: self.reg()?,
+                src: self.reg()?,
+            },
+            26 => Instruction::CallValue {
+                dst: self.reg()?,
+                callee: self.reg()?,
+                arguments: self.reg()?,
+                count: self.u16()?,
+            },
+            27 => Instruction::MakeClosure {
+                dst: self.reg()?,
+                function: self.u32()?,
+                captures: self.reg()?,
+                count: self.u16()?,
+            },
+            _ => return Err(SbcError::Invalid("unknown instruction")),
+        })
+    }
+}
+
+/// Reads a program from the bytes of a `.sbc` file
+pub fn read(bytes: &[u8]) -> Result<Program, SbcError> {
+    let mut reader = Reader { bytes };
+    if reader.take::<4>().ok().as_ref() != Some(MAGIC) {
+        return Err(SbcError::NotBytecode);
+    }
+    let version = reader.u16()?;
+    if version != VERSION {
+        return Err(SbcError::UnsupportedVersion(version));
+    }
+
+    let mut program = Program::default();
+    for _ in 0..reader.len()? {
+        program.constants.push(reader.constant()?);
+    }
+    for _ in 0..reader.len()? {
+        let name = reader.str()?;
+        let variants = (0..reader.len()?)
+            .map(|_| reader.str())
+            .collect::<Result<_, _>>()?;
+        program.adts.push(AdtInfo { name, variants });
+    }
+    for _ in 0..reader.len()? {
+        let name = reader.str()?;
+        let arity = reader.u16()?;
+        let captures = reader.u16()?;
+        let registers = reader.u16()?;
+        let flags = reader.u8()?;
+        let builtin = match flags & 4 != 0 {
+            true => {
+                let builtin = reader.str()?;
+                let (owner, name) = match builtin.rsplit_once("::") {
+                    Some((owner, name)) => (Some(owner), name),
+                    None => (None, builtin.as_str()),
+                };
+                if Builtin::lookup(owner, name).is_none() {
+                    return Err(SbcError::Invalid("unknown builtin"));
+                }
+                Some(builtin)
+            }
+            false => None,
+        };
+        let length = reader.len()?;
+        let mut code = Vec::with_capacity(length);
+        let mut spans = Vec::with_capacity(length);
+        for _ in 0..length {
+            code.push(reader.instruction()?);
+            let (start, end) = (reader.u32()? as usize, reader.u32()? as usize);
+            if end < start {
+                return Err(SbcError::Invalid("span ends before it starts"));
+            }
+            spans.push(Span::new(start, end));
+        }
+        program.functions.push(Function {
+            name,
+            arity,
+            captures,
+            registers,
+            is_generator: flags & 1 != 0,
+            self_by_reference: flags & 2 != 0,
+            builtin,
+            is_foreign: flags & 8 != 0,
+            code,
+            spans,
+        });
+    }
+    for _ in 0..reader.len()? {
+        let self_type = reader.type_key()?;
+        let methods = (0..reader.len()?)
+            .map(|_| Ok((reader.str()?, reader.u32()?)))
+            .collect::<Result<_, _>>()?;
+        program.impls.push(Impl { self_type, methods });
+    }
+    program.entry = match reader.u8()? {
+        0 => None,
+        _ => Some(reader.u32()?),
+    };
+    if !reader.bytes.is_empty() {
+        return Err(SbcError::Invalid("trailing bytes after the program"));
+    }
+    validate(&program)?;
+    Ok(program)
+}
+
+/// Checks that every index within a program is in bounds, and that every instruction works on
+/// the kind of values it is meant for. What is in the registers is only known once the program
+/// runs, so the VM stops a program whose registers hold the wrong kind of value with an error
+fn validate(program: &Program) -> Result<(), SbcError> {
+    let functions = program.functions.len() as u32;
+    let adts = &program.adts;
+    let constant = |index: u32| match program.constants.get(index as usize) {
+        Some(_) => Ok(()),
+        None => Err(SbcError::Invalid("constant out of bounds")),
+    };
+    let adt = |index: u32| match adts.get(index as usize) {
+        Some(adt) => Ok(adt),
+        None => Err(SbcError::Invalid("type out of bounds")),
+    };
+    let function = |index: u32| match index < functions {
+        true => Ok(()),
+        false => Err(SbcError::Invalid("function out of bounds")),
+    };
+
+    for compiled in &program.functions {
+        if compiled.arity as u32 + compiled.captures as u32 > compiled.registers as u32 {
+            return Err(SbcError::Invalid("more parameters than registers"));
+        }
+        let native = compiled.builtin.is_some() || compiled.is_foreign;
+        if native && compiled.captures > 0 {
+            return Err(SbcError::Invalid("builtin which captures values"));
+        }
+        if let Some(builtin) = compiled.builtin.as_deref() {
+            // The builtin was found when it was read
+            let builtin = match builtin.rsplit_once("::") {
+                Some((owner, name)) => Builtin::lookup(Some(owner), name),
+                None => Builtin::lookup(None, builtin),
+            };
+            for name in builtin.map_or(&[][..], |x| x.library_types()) {
+                let variants = match *name {
+                    "std::Option" | "std::Result" => 2,
+                    _ => 0,
+                };
+                if !adts
+                    .iter()
+                    .any(|x| x.name == *name && x.variants.len() == variants)
+                {
+                    return Err(SbcError::Invalid("builtin without the types it makes"));
+                }
+            }
+        }
+        let length = compiled.code.len() as Label;
+        let registers = |start: Reg, count: u16| match start.0 as u32 + count as u32
+            <= compiled.registers as u32
+        {
+            true => Ok(()),
+            false => Err(SbcError::Invalid("register out of bounds")),
+        };
+        let register = |x: Reg| registers(x, 1);
+        let label = |x: Label| match x < length {
+            true => Ok(()),
+            false => Err(SbcError::Invalid("jump out of bounds")),
+        };
+        // Running off the end of a function is never possible for compiled code, and builtins
+        // and foreign functions have no code of their own
+        match compiled.code.last() {
+            Some(Instruction::Return { .. } | Instruction::Jump { .. } | Instruction::NoMatch) => {}
+            None if compiled.builtin.is_some() || compiled.is_foreign => {}
+            _ => return Err(SbcError::Invalid("function does not end with a return")),
+        }
+        for instruction in &compiled.code {
+            match *instruction {
+                Instruction::Const { dst, constant: x } => {
+                    register(dst)?;
+                    constant(x)?;
+                }
+                Instruction::Negate { ty, .. } if !(ty.is_integer() || ty.is_float()) => {
+                    return Err(SbcError::Invalid("negating a type which is not a number"));
+                }
+                Instruction::Convert { ty, .. } if !NUMERIC.contains(&ty) => {
+                    return Err(SbcError::Invalid(
+                        "converting to a type which is not a number",
+                    ));
+                }
+                Instruction::Arith { op, ty, .. } if !arith_applies(op, ty) => {
+                    return Err(SbcError::Invalid(
+                        "arithmetic on a type it does not apply to",
+                    ));
+                }
+                Instruction::Yield { .. } if !compiled.is_generator => {
+                    return Err(SbcError::Invalid("yield outside of a generator"));
+                }
+                Instruction::Move { dst, src }
+                | Instruction::Negate { dst, src, .. }
+                | Instruction::Convert { dst, src, .. }
+                | Instruction::Not { dst, src }
+                | Instruction::Field { dst, src, .. }
+                | Instruction::Alloc { dst, src }
+                | Instruction::Project { dst, src, .. }
+                | Instruction::Load { dst, src }
+                | Instruction::Store {
+                    target: dst, src, ..
+                }
+                | Instruction::SetField {
+                    target: dst, src, ..
+                }
+                | Instruction::IsVariant { dst, src, .. } => {
+                    register(dst)?;
+                    register(src)?;
+                }
+                Instruction::Arith {
+                    dst, left, right, ..
+                }
+                | Instruction::Compare {
+                    dst, left, right, ..
+                } => {
+                    register(dst)?;
+                    register(left)?;
+                    register(right)?;
+                }
+                Instruction::Jump { target } => label(target)?,
+                Instruction::JumpIf { condition, target }
+                | Instruction::JumpIfNot { condition, target } => {
+                    register(condition)?;
+                    label(target)?;
+                }
+                Instruction::Call {
+                    dst,
+                    function: x,
+                    arguments,
+                    count,
+                } => {
+                    register(dst)?;
+                    function(x)?;
+                    registers(arguments, count)?;
+                    // Builtins and foreign functions can be variadic, taking any number of
+                    // arguments past their arity
+                    let callee = &program.functions[x as usize];
+                    let variadic = callee.builtin.is_some() || callee.is_foreign;
+                    if count < callee.arity || (count != callee.arity && !variadic) {
+                        return Err(SbcError::Invalid("wrong number of arguments"));
+                    }
+                    if callee.captures > 0 {
+                        return Err(SbcError::Invalid("closure called without its captures"));
+                    }
+                }
+                // The number of arguments is checked when the closure is called, as which
+                // function it is is only known then
+                Instruction::CallValue {
+                    dst,
+                    callee,
+                    arguments,
+                    count,
+                } => {
+                    register(dst)?;
+                    register(callee)?;
+                    registers(arguments, count)?;
+                }
+                Instruction::MakeClosure {
+                    dst,
+                    function: x,
+                    captures,
+                    count,
+                } => {
+                    register(dst)?;
+                    function(x)?;
+                    registers(captures, count)?;
+                    let closure = &program.functions[x as usize];
+                    if closure.builtin.is_some() || closure.is_foreign {
+                        return Err(SbcError::Invalid("closure of a builtin"));
+                    }
+                    if count != closure.captures {
+                        return Err(SbcError::Invalid("wrong number of captures"));
+                    }
+                }
+                Instruction::CallMethod {
+                    dst,
+                    method,
+                    arguments,
+                    count,
+                } => {
+                    register(dst)?;
+                    registers(arguments, count)?;
+                    if count == 0 {
+                        return Err(SbcError::Invalid("method called without a receiver"));
+                    }
+                    match program.constants.get(method as usize) {
+                        Some(Constant::Str(_)) => {}
+                        _ => return Err(SbcError::Invalid("method is not named by a string")),
+                    }
+                }
+                Instruction::Return { src } | Instruction::Yield { src } => register(src)?,
+                Instruction::Next {
+                    dst,
+                    generator,
+                    exit,
+                } => {
+                    register(dst)?;
+                    register(generator)?;
+                    label(exit)?;
+                }
+                Instruction::Tuple { dst, start, count } => {
+                    register(dst)?;
+                    registers(start, count)?;
+                }
+                Instruction::Struct {
+                    dst,
+                    adt: x,
+                    start,
+                    count,
+                } => {
+                    register(dst)?;
+                    adt(x)?;
+                    registers(start, count)?;
+                }
+                Instruction::Variant {
+                    dst,
+                    adt: x,
+                    variant,
+                    start,
+                    count,
+                } => {
+                    register(dst)?;
+                    if variant as usize >= adt(x)?.variants.len() {
+                        return Err(SbcError::Invalid("variant out of bounds"));
+                    }
+                    registers(start, count)?;
+                }
+                Instruction::NoMatch => {}
+            }
+        }
+    }
+    for implementation in &program.impls {
+        if let Some(TypeKey::Adt(x)) = implementation.self_type {
+            adt(x)?;
+        }
+        for (_, x) in &implementation.methods {
+            function(*x)?;
+        }
+    }
+    if let Some(entry) = program.entry {
+        function(entry)?;
+    }
+    Ok(())
+}
+
+/// Checks if an arithmetic operation can be applied to values of a type
+fn arith_applies(op: ArithOp, ty: PrimitiveType) -> bool {
+    match op {
+        ArithOp::Add | ArithOp::Subtract | ArithOp::Multiply | ArithOp::Divide => {
+            ty.is_integer() || ty.is_float()
+        }
+        ArithOp::BitwiseAnd => ty.is_integer() || ty == PrimitiveType::Bool,
+        ArithOp::ShiftLeft | ArithOp::ShiftRight => ty.is_integer(),
+    }
+}
@@ -0,0 +1,556 @@
+use shark_core::source::Span;
+use shark_sema::{numeric::Overflow, ty::PrimitiveType};
+use shark_std::runtime::Host;
+use shark_testing::{error_source, interpret, overflow::check_overflow_modes, suite, text_status};
+
+use crate::{
+    bytecode::{ArithOp, Constant, Function, Instruction, Program, Reg},
+    compile_module,
+    disasm::disassemble,
+    run, sbc,
+    value::Value,
+};
+
+/// Compiles a module which must check without errors
+fn compile(source: &str) -> Program {
+    compile_as(source, Overflow::Trap)
+}
+
+/// Disassembles the functions a program declares, leaving out the builtins and impls of the
+/// standard library which follow them
+fn disassemble_own(program: &Program) -> String {
+    let mut own = program.clone();
+    own.functions.retain(|x| x.builtin.is_none());
+    own.impls.clear();
+    disassemble(&own)
+}
+
+/// Like [compile], for a program built to overflow some way
+fn compile_as(source: &str, overflow: Overflow) -> Program {
+    let checked = shark_testing::check(source);
+    let bodies = checked.lower();
+    let (module, defs, types) = (&checked.module, &checked.defs, &checked.types);
+    compile_module(module, defs, types, &bodies, overflow)
+}
+
+/// Runs a module on the VM, returning what `main` returned as text, or the message of the runtime
+/// error along with the source its span covers. The result must be the same as when the module is
+/// run by the interpreter, and when it is run after a round trip through the `.sbc` format
+fn execute(source: &str) -> Result<String, (String, String)> {
+    execute_as(source, Overflow::Trap)
+}
+
+/// Like [execute], for a program built to overflow some way
+fn execute_as(source: &str, overflow: Overflow) -> Result<String, (String, String)> {
+    let program = compile_as(source, overflow);
+    let read = sbc::read(&sbc::write(&program)).expect("failed to read the written program");
+    assert_eq!(read, program);
+
+    let result = run(&program, Host::default())
+        .map(|x| x.render(&program))
+        .map_err(|x| error_source(source, x));
+    let interpreted = interpret(source, overflow, |result| {
+        result
+            .map(|x| x.to_string())
+            .map_err(|x| error_source(source, x))
+    });
+    assert_eq!(result, interpreted);
+    result
+}
+
+#[test]
+fn test_arithmetic() {
+    let result = execute(
+        "pub fun main() :: Int32 {
+            let mut total = 2 + 3 * 4;
+            total -= 10 / 3;
+            total *= -2;
+            let shifted = (1 << 4) >> 1;
+            let masked = 13 & 7;
+            total + shifted + masked
+        }",
+    );
+    assert_eq!(result, Ok("-9".to_string()));
+
+    let result = execute(
+        "pub fun main() :: Bool {
+            let half :: Float64 = 1.0 / 4.0 * 2.0;
+            let max :: UInt8 = 200 + 55;
+            3 >= 3 && 1 != 2 && !(!true | 2 < 1) && half == 0.5 && max == 255
+        }",
+    );
+    assert_eq!(result, Ok("true".to_string()));
+}
+
+#[test]
+fn test_control_flow() {
+    let result = execute(
+        "fun fib(n :: Int64) :: Int64 {
+            if n < 2 {
+                ret n;
+            }
+            fib(n - 1) + fib(n - 2)
+        }
+
+        fun sign(n :: Int32) :: Int32 {
+            if n > 0 { 1 } else if n < 0 { -1 } else { 0 }
+        }
+
+        pub fun main() :: Int64 {
+            if sign(-5) == -1 && sign(0) == 0 && sign(7) == 1 {
+                fib(20)
+            } else {
+                0
+            }
+        }",
+    );
+    assert_eq!(result, Ok("6765".to_string()));
+}
+
+#[test]
+fn test_types_and_patterns() {
+    let result = execute(
+        "type Point { x :: Int32, y :: Int32 }
+
+        enum Shape {
+            Circle(Int32),
+            Rectangle(Point, Point),
+            Empty,
+        }
+
+        fun area(shape :: Shape) :: Int32 {
+            when shape {
+                Shape::Circle(radius) => 3 * radius * radius,
+                Shape::Rectangle(a, b) => (b.x - a.x) * (b.y - a.y),
+                Shape::Empty => 0,
+            }
+        }
+
+        fun size(n :: Int32) :: Int32 {
+            when n {
+                0 => 0,
+                1..10 => 1,
+                x if x > 100 => 3,
+                _ => 2,
+            }
+        }
+
+        pub fun main() :: Int32 {
+            let rectangle = Shape::Rectangle(Point { x = 1, y = 2 }, Point { y = 6, x = 4 });
+            let total = area(Shape::Circle(2)) + area(rectangle) + area(Shape::Empty);
+            total * 10000 + size(0) * 1000 + size(9) * 100 + size(10) * 10 + size(500)
+        }",
+    );
+    assert_eq!(result, Ok("240123".to_string()));
+}
+
+#[test]
+fn test_references() {
+    let result = execute(
+        "type Counter { count :: Int32 }
+
+        fun bump(counter :: ref mut Counter) {
+            counter.count += 1;
+        }
+
+        fun set(target :: ref mut Int32, value :: Int32) {
+            *target = value;
+        }
+
+        pub fun main() :: Int32 {
+            let mut counter = Counter { count = 0 };
+            bump(ref mut counter);
+            bump(ref mut counter);
+            let mut n = 1;
+            set(ref mut n, 40);
+            let pointer = ptr mut n;
+            unsafe {
+                *pointer += 2;
+            }
+            counter.count * 100 + n
+        }",
+    );
+    assert_eq!(result, Ok("242".to_string()));
+}
+
+#[test]
+fn test_methods() {
+    let result = execute(
+        "trait Shape {
+            fun area(self :: ref Self) :: Int32;
+            fun double(self :: ref Self) :: Int32 {
+                Shape::area(self) * 2
+            }
+        }
+
+        type Square { side :: Int32 }
+        type Circle { radius :: Int32 }
+
+        impl Shape for Square {
+            fun area(self :: ref Self) :: Int32 {
+                self.side * self.side
+            }
+        }
+
+        impl Shape for Circle {
+            fun area(self :: ref Self) :: Int32 {
+                3 * self.radius * self.radius
+            }
+
+            fun double(self :: ref Self) :: Int32 {
+                0
+            }
+        }
+
+        pub fun main() :: Int32 {
+            let square = Square { side = 3 };
+            let circle = Circle { radius = 1 };
+            square.double() * 100 + circle.double() * 10 + Shape::area(ref circle)
+        }",
+    );
+    assert_eq!(result, Ok("1803".to_string()));
+}
+
+#[test]
+fn test_generators() {
+    let result = execute(
+        "fun upto(n :: Int32) :: yield Int32 {
+            if n > 0 {
+                for x in upto(n - 1) {
+                    yield x;
+                }
+                yield n;
+            }
+        }
+
+        fun evens(numbers :: yield Int32) :: yield Int32 {
+            for x in numbers {
+                if x / 2 * 2 == x {
+                    yield x;
+                }
+            }
+        }
+
+        pub fun main() :: Int64 {
+            let mut sum = 0;
+            for x in evens(upto(10)) {
+                sum += x;
+            }
+            let mut last = 0;
+            for (i, x) of upto(5) {
+                last = i;
+            }
+            if sum == 30 { last } else { -1 }
+        }",
+    );
+    assert_eq!(result, Ok("4".to_string()));
+}
+
+#[test]
+fn test_runtime_errors() {
+    let result = execute(
+        "pub fun main() :: Int8 {
+            let x :: Int8 = 100;
+            x + x
+        }",
+    );
+    assert_eq!(
+        result,
+        Err((
+            "attempt to add with overflow".to_string(),
+            "x + x".to_string()
+        ))
+    );
+
+    let result = execute(
+        "fun divide(a :: Int32, b :: Int32) :: Int32 {
+            a / b
+        }
+
+        pub fun main() :: Int32 {
+            divide(1, 0)
+        }",
+    );
+    assert_eq!(
+        result,
+        Err(("attempt to divide by zero".to_string(), "a / b".to_string()))
+    );
+
+    let result =
+        execute("fun forever(n :: Int32) :: Int32 { forever(n) } pub fun main() { forever(1); }");
+    assert_eq!(
+        result,
+        Err(("stack overflow".to_string(), "forever(n)".to_string()))
+    );
+
+    let result = execute(
+        "extern \"C\" fun printf(format :: Str, ...) :: Int32;
+        pub fun main() { unsafe { printf(\"%d\\n\", 1); } }",
+    );
+    assert_eq!(
+        result,
+        Err((
+            "the foreign function `printf` can only be called by a program built with \
+             `--target=c`"
+                .to_string(),
+            "printf(\"%d\\n\", 1)".to_string()
+        ))
+    );
+}
+
+#[test]
+fn test_closures() {
+    let source =
+        "fun compose(f :: fun(Int32) :: Int32, g :: fun(Int32) :: Int32) :: fun(Int32) :: Int32 {
+            fun(x :: Int32) :: Int32 { g(f(x)) }
+        }
+
+        fun countdown(from :: Int32) :: fun() :: yield Int32 {
+            fun() :: yield Int32 {
+                let mut n = from;
+                while_positive(n);
+                yield n;
+                yield n - 1;
+            }
+        }
+
+        fun while_positive(n :: Int32) {}
+
+        pub fun main() :: Int32 {
+            let base = 10;
+            let adder = fun(a :: Int32) :: fun(Int32) :: Int32 {
+                fun(b :: Int32) :: Int32 { a + b + base }
+            };
+            let add = adder(1);
+            let twice = compose(add, add);
+            let mut total = twice(0);
+            for x in countdown(3)() {
+                total += x;
+            }
+            let mut value = 0;
+            let target = ref mut value;
+            let set = fun(x :: Int32) { *target = x; };
+            set(7);
+            total + value
+        }";
+    assert_eq!(execute(source), Ok("34".to_string()));
+
+    let program = compile(
+        "pub fun main() :: Int32 {
+            let n = 2;
+            let double = fun(x :: Int32) :: Int32 { x * n };
+            double(21)
+        }",
+    );
+    assert_eq!(
+        disassemble_own(&program),
+        "fun main (parameters: 0, registers: 4)
+    0  const r0, 2 :: Int32
+    1  move r2, r0
+    2  closure r1, main::<closure 1>(r2)
+    3  const r3, 21 :: Int32
+    4  call.value r2, r1(r3)
+    5  ret r2
+
+fun main::<closure 1> (parameters: 1, captures: 1, registers: 3)
+    0  mul.Int32 r2, r0, r1
+    1  ret r2
+"
+    );
+
+    // A closure made without the value it captures
+    let mut invalid = program.clone();
+    invalid.functions[0].code[2] = Instruction::MakeClosure {
+        dst: Reg(1),
+        function: 1,
+        captures: Reg(2),
+        count: 0,
+    };
+    assert_eq!(
+        sbc::read(&sbc::write(&invalid)),
+        Err(sbc::SbcError::Invalid("wrong number of captures"))
+    );
+
+    // Calling something which is not a closure
+    let mut invalid = program.clone();
+    invalid.functions[0].code[4] = Instruction::CallValue {
+        dst: Reg(2),
+        callee: Reg(0),
+        arguments: Reg(3),
+        count: 1,
+    };
+    let read = sbc::read(&sbc::write(&invalid)).expect("failed to read the written program");
+    let error = run(&read, Host::default()).expect_err("the program must stop with an error");
+    assert_eq!(error.message, "invalid bytecode");
+}
+
+#[test]
+fn test_disassemble() {
+    let program = compile(
+        "fun double(n :: Int32) :: Int32 {
+            n * 2
+        }
+
+        pub fun main() :: Int32 {
+            double(21)
+        }",
+    );
+    assert_eq!(
+        disassemble_own(&program),
+        "fun double (parameters: 1, registers: 3)
+    0  const r2, 2 :: Int32
+    1  mul.Int32 r1, r0, r2
+    2  ret r1
+
+fun main (parameters: 0, registers: 2)
+    0  const r1, 21 :: Int32
+    1  call r0, double(r1)
+    2  ret r0
+"
+    );
+
+    // A release build wraps around, as do methods asking to
+    let program = compile_as(
+        "pub fun main() :: Int64 {
+            let x :: Int8 = 100;
+            (-x).saturating_mul(x).to_int64()
+        }",
+        Overflow::Wrap,
+    );
+    assert_eq!(
+        disassemble_own(&program),
+        "fun main (parameters: 0, registers: 4)
+    0  const r0, 100 :: Int8
+    1  neg.wrap.Int8 r3, r0
+    2  mul.sat.Int8 r2, r3, r0
+    3  conv.wrap.Int64 r1, r2
+    4  ret r1
+"
+    );
+}
+
+#[test]
+fn test_sbc_errors() {
+    let bytes = sbc::write(&compile("pub fun main() {}"));
+    assert_eq!(sbc::read(b"\x7fELF"), Err(sbc::SbcError::NotBytecode));
+
+    let mut newer = bytes.clone();
+    newer[4] = 6;
+    assert_eq!(sbc::read(&newer), Err(sbc::SbcError::UnsupportedVersion(6)));
+
+    assert_eq!(
+        sbc::read(&bytes[..bytes.len() - 1]),
+        Err(sbc::SbcError::UnexpectedEnd)
+    );
+
+    // Point the entry at a function which does not exist
+    let mut invalid = bytes.clone();
+    let last = invalid.len() - 4;
+    let count = compile("pub fun main() {}").functions.len() as u32;
+    invalid[last..].copy_from_slice(&count.to_le_bytes());
+    assert_eq!(
+        sbc::read(&invalid),
+        Err(sbc::SbcError::Invalid("function out of bounds"))
+    );
+}
+
+#[test]
+fn test_invalid_bytecode() {
+    // Adds a `Float32` to an `Int32` as if both were `Int32`
+    let main = Function {
+        name: "main".to_string(),
+        arity: 0,
+        captures: 0,
+        registers: 3,
+        is_generator: false,
+        self_by_reference: false,
+        builtin: None,
+        is_foreign: false,
+        code: vec![
+            Instruction::Const {
+                dst: Reg(0),
+                constant: 0,
+            },
+            Instruction::Const {
+                dst: Reg(1),
+                constant: 1,
+            },
+            Instruction::Arith {
+                op: ArithOp::Add,
+                ty: PrimitiveType::Int32,
+                overflow: Overflow::Trap,
+                dst: Reg(2),
+                left: Reg(0),
+                right: Reg(1),
+            },
+            Instruction::Return { src: Reg(2) },
+        ],
+        spans: vec![Span::new(0, 0); 4],
+    };
+    let program = Program {
+        constants: vec![
+            Constant::Integer(PrimitiveType::Int32, 9),
+            Constant::Float(PrimitiveType::Float32, 2.25),
+        ],
+        functions: vec![main],
+        impls: Vec::new(),
+        adts: Vec::new(),
+        entry: Some(0),
+    };
+    let read = sbc::read(&sbc::write(&program)).expect("failed to read the written program");
+    let error = run(&read, Host::default()).expect_err("the program must stop with an error");
+    assert_eq!(error.message, "invalid bytecode");
+
+    // The same program, where the constant and the operation disagree on the type
+    let mut mistyped = program.clone();
+    mistyped.constants[1] = Constant::Float(PrimitiveType::Int32, 2.25);
+    assert_eq!(
+        sbc::read(&sbc::write(&mistyped)),
+        Err(sbc::SbcError::Invalid(
+            "float constant of a type which is not a float"
+        ))
+    );
+
+    let mut mistyped = program.clone();
+    mistyped.functions[0].code[2] = Instruction::Arith {
+        op: ArithOp::ShiftLeft,
+        ty: PrimitiveType::Float64,
+        overflow: Overflow::Trap,
+        dst: Reg(2),
+        left: Reg(0),
+        right: Reg(1),
+    };
+    assert_eq!(
+        sbc::read(&sbc::write(&mistyped)),
+        Err(sbc::SbcError::Invalid(
+            "arithmetic on a type it does not apply to"
+        ))
+    );
+}
+
+#[test]
+fn test_overflow_modes() {
+    check_overflow_modes(|source, overflow| text_status(execute_as(source, overflow)));
+}
+
+#[test]
+fn test_library_programs() {
+    for test in suite::programs() {
+        let program = compile(&test.source);
+        let read = sbc::read(&sbc::write(&program)).expect("failed to read the written program");
+        assert_eq!(read, program);
+        let (host, captured) = Host::captured(test.args.clone());
+        let result = run(&program, host)
+            .map(|x| match x {
+                Value::Integer(_, code) => code,
+                x => panic!("`main` returns an integer, found {:?}", x),
+            })
+            .map_err(|x| x.message);
+        assert_eq!(
+            suite::transcript(&captured.text(), result),
+            test.expected,
+            "{}",
+            test.name
+        );
+    }
+}
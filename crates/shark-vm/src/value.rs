@@ -0,0 +1,262 @@
+//! The values held by the registers of the VM. Values are copied whenever they are moved between
+//! registers, so aggregates share their parts until one of the copies is changed
+
+use std::{cell::RefCell, cmp::Ordering, rc::Rc};
+
+use shark_sema::ty::PrimitiveType;
+
+use crate::{
+    bytecode::{Constant, Program, TypeKey},
+    vm::GeneratorState,
+};
+
+#[derive(Debug, Clone)]
+pub enum Value {
+    Unit,
+    Bool(bool),
+    /// An integer or a `Char`, widened so that any of them fits
+    Integer(PrimitiveType, i128),
+    Float(PrimitiveType, f64),
+    Str(Rc<str>),
+    Tuple(Rc<Vec<Value>>),
+    /// An instance of a `type`, with its fields in the order they are declared
+    Struct(u32, Rc<Vec<Value>>),
+    /// A variant of an `enum`, identified by its index, along with its payload
+    Variant(u32, u32, Rc<Vec<Value>>),
+    /// A `ref` or a `ptr`
+    Reference(Place),
+    Generator(Rc<RefCell<GeneratorState>>),
+    /// A closure of the function with an index, along with the values it captured
+    Closure(u32, Rc<Vec<Value>>),
+    /// A `Vec` of the standard library, whose `type` has the index given
+    List(u32, Rc<Vec<Value>>),
+    /// A `Map` of the standard library, with its entries in the order of their keys
+    Map(u32, Rc<Vec<(Value, Value)>>),
+}
+
+/// A value in memory of its own, or a part of it reached by following field, element and payload
+/// indices
+#[derive(Debug, Clone)]
+pub struct Place {
+    pub memory: Rc<RefCell<Value>>,
+    pub path: Vec<u16>,
+}
+
+impl Place {
+    pub fn new(value: Value) -> Self {
+        Self {
+            memory: Rc::new(RefCell::new(value)),
+            path: Vec::new(),
+        }
+    }
+
+    pub fn project(&self, index: u16) -> Self {
+        let mut path = self.path.clone();
+        path.push(index);
+        Self {
+            memory: self.memory.clone(),
+            path,
+        }
+    }
+
+    /// Reads the value at the place, which is [None] if the value in memory has no part at its
+    /// path. That is only ever the case for a corrupted program
+    pub fn read(&self) -> Option<Value> {
+        let memory = self.memory.borrow();
+        let mut current = &*memory;
+        for index in &self.path {
+            current = current.parts().get(*index as usize)?;
+        }
+        Some(current.clone())
+    }
+
+    /// Replaces the value at the place, giving false if it has no part at its path like
+    /// [Place::read]
+    pub fn write(&self, value: Value) -> bool {
+        let mut memory = self.memory.borrow_mut();
+        let mut current = &mut *memory;
+        for index in &self.path {
+            match current.part_mut(*index) {
+                Some(part) => current = part,
+                None => return false,
+            }
+        }
+        *current = value;
+        true
+    }
+}
+
+impl Value {
+    pub fn from_constant(constant: &Constant) -> Self {
+        match constant {
+            Constant::Unit => Self::Unit,
+            Constant::Bool(x) => Self::Bool(*x),
+            Constant::Integer(ty, x) => Self::Integer(*ty, *x),
+            Constant::Float(ty, x) => Self::Float(*ty, *x),
+            Constant::Str(x) => Self::Str(x.as_str().into()),
+        }
+    }
+
+    /// Describes what kind of value this is, for errors about values of the wrong kind
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Unit => "`()`",
+            Self::Bool(_) => "a `Bool`",
+            Self::Integer(_, _) => "an integer",
+            Self::Float(_, _) => "a float",
+            Self::Str(_) => "a `Str`",
+            Self::Tuple(_) => "a tuple",
+            Self::Struct(_, _) => "a `type`",
+            Self::Variant(_, _, _) => "an `enum`",
+            Self::Reference(_) => "a reference",
+            Self::Generator(_) => "a generator",
+            Self::Closure(_, _) => "a closure",
+            Self::List(_, _) => "a `Vec`",
+            Self::Map(_, _) => "a `Map`",
+        }
+    }
+
+    /// Checks if the value is a reference leading to the memory, directly or through the
+    /// references it points to
+    pub fn leads_to(&self, memory: &Rc<RefCell<Value>>) -> bool {
+        let mut current = self.clone();
+        while let Self::Reference(place) = current {
+            if Rc::ptr_eq(&place.memory, memory) {
+                return true;
+            }
+            match place.read() {
+                Some(value) => current = value,
+                None => return false,
+            }
+        }
+        false
+    }
+
+    /// Gets the fields, elements or payload within the value
+    pub fn parts(&self) -> &[Value] {
+        match self {
+            Self::Tuple(parts) | Self::Struct(_, parts) | Self::Variant(_, _, parts) => parts,
+            _ => &[],
+        }
+    }
+
+    /// Gets a part of the value to change, first copying the parts if another value shares them
+    pub fn part_mut(&mut self, index: u16) -> Option<&mut Value> {
+        match self {
+            Self::Tuple(parts) | Self::Struct(_, parts) | Self::Variant(_, _, parts) => {
+                Rc::make_mut(parts).get_mut(index as usize)
+            }
+            _ => None,
+        }
+    }
+
+    pub fn type_key(&self) -> TypeKey {
+        match self {
+            Self::Unit => TypeKey::Unit,
+            Self::Bool(_) => TypeKey::Primitive(PrimitiveType::Bool),
+            Self::Integer(ty, _) | Self::Float(ty, _) => TypeKey::Primitive(*ty),
+            Self::Str(_) => TypeKey::Primitive(PrimitiveType::Str),
+            Self::Tuple(_) => TypeKey::Tuple,
+            Self::Struct(id, _)
+            | Self::Variant(id, _, _)
+            | Self::List(id, _)
+            | Self::Map(id, _) => TypeKey::Adt(*id),
+            Self::Reference(_) => TypeKey::Reference,
+            Self::Generator(_) => TypeKey::Generator,
+            Self::Closure(_, _) => TypeKey::Closure,
+        }
+    }
+
+    /// Compares two values of the same type. Values of types which have no order, such as a
+    /// `type`, are only ever equal or not
+    pub fn compare(&self, other: &Value) -> Option<Ordering> {
+        match (self, other) {
+            (Self::Unit, Self::Unit) => Some(Ordering::Equal),
+            (Self::Bool(x), Self::Bool(y)) => x.partial_cmp(y),
+            (Self::Integer(_, x), Self::Integer(_, y)) => x.partial_cmp(y),
+            (Self::Float(_, x), Self::Float(_, y)) => x.partial_cmp(y),
+            (Self::Str(x), Self::Str(y)) => x.partial_cmp(y),
+            (Self::Tuple(x), Self::Tuple(y)) | (Self::Struct(_, x), Self::Struct(_, y)) => {
+                equal_parts(x, y)
+            }
+            (Self::Variant(_, x, x_payload), Self::Variant(_, y, y_payload)) => match x == y {
+                true => equal_parts(x_payload, y_payload),
+                false => None,
+            },
+            (Self::Reference(x), Self::Reference(y)) => {
+                match Rc::ptr_eq(&x.memory, &y.memory) && x.path == y.path {
+                    true => Some(Ordering::Equal),
+                    false => None,
+                }
+            }
+            (Self::Generator(x), Self::Generator(y)) if Rc::ptr_eq(x, y) => Some(Ordering::Equal),
+            (Self::List(_, x), Self::List(_, y)) if x.len() == y.len() => equal_parts(x, y),
+            (Self::Map(_, x), Self::Map(_, y)) if x.len() == y.len() => {
+                let equal = x.iter().zip(y.iter()).all(|((a, b), (c, d))| {
+                    a.compare(c) == Some(Ordering::Equal) && b.compare(d) == Some(Ordering::Equal)
+                });
+                equal.then_some(Ordering::Equal)
+            }
+            _ => None,
+        }
+    }
+
+    /// Writes the value out the way it would be written in the source
+    pub fn render(&self, program: &Program) -> String {
+        let list = |values: &[Value]| {
+            values
+                .iter()
+                .map(|x| x.render(program))
+                .collect::<Vec<_>>()
+                .join(", ")
+        };
+        match self {
+            Self::Unit => "()".to_string(),
+            Self::Bool(x) => x.to_string(),
+            Self::Integer(PrimitiveType::Char, x) => u32::try_from(*x)
+                .ok()
+                .and_then(char::from_u32)
+                .unwrap_or(char::REPLACEMENT_CHARACTER)
+                .to_string(),
+            Self::Integer(_, x) => x.to_string(),
+            Self::Float(PrimitiveType::Float32, x) => format!("{:?}", *x as f32),
+            Self::Float(_, x) => format!("{:?}", x),
+            Self::Str(x) => x.to_string(),
+            Self::Tuple(values) if values.len() == 1 => format!("({},)", list(values)),
+            Self::Tuple(values) => format!("({})", list(values)),
+            Self::Struct(id, values) => {
+                format!("{} {{ {} }}", program.adts[*id as usize].name, list(values))
+            }
+            Self::Variant(id, index, values) => {
+                let adt = &program.adts[*id as usize];
+                let name = format!("{}::{}", adt.name, adt.variants[*index as usize]);
+                match values.is_empty() {
+                    true => name,
+                    false => format!("{}({})", name, list(values)),
+                }
+            }
+            Self::Reference(place) => match place.read() {
+                Some(value) => format!("ref {}", value.render(program)),
+                None => "ref <invalid>".to_string(),
+            },
+            Self::Generator(_) => "<generator>".to_string(),
+            Self::Closure(_, _) => "<closure>".to_string(),
+            Self::List(_, values) => format!("[{}]", list(values)),
+            Self::Map(_, entries) => {
+                let entries: Vec<String> = entries
+                    .iter()
+                    .map(|(x, y)| format!("{}: {}", x.render(program), y.render(program)))
+                    .collect();
+                format!("{{{}}}", entries.join(", "))
+            }
+        }
+    }
+}
+
+fn equal_parts(left: &[Value], right: &[Value]) -> Option<Ordering> {
+    let equal = left
+        .iter()
+        .zip(right)
+        .all(|(x, y)| x.compare(y) == Some(Ordering::Equal));
+    equal.then_some(Ordering::Equal)
+}
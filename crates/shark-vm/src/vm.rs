@@ -0,0 +1,914 @@
+//! Runs a [Program]. Every call pushes a [Frame] owning the registers of the function, so that a
+//! generator can be suspended by keeping the registers of its frame in a [GeneratorState] until
+//! it is resumed again
+//!
+//! Functions which are builtins of the runtime run straight away without a frame of their own,
+//! with the VM providing its values to the runtime as a [Machine]
+
+use std::{cell::RefCell, cmp::Ordering, collections::HashMap, rc::Rc};
+
+use shark_core::{diagnostic::Diagnostic, source::Span, symbol::sym};
+use shark_parse::ast::BinaryOperator;
+use shark_sema::{
+    numeric::{self, Number, NumericError, Overflow},
+    ty::PrimitiveType,
+};
+use shark_std::runtime::{self, Builtin, Entries, Host, Machine, Stop};
+
+use crate::{
+    bytecode::{ArithOp, CompareOp, Instruction, Label, Program, Reg, TypeKey},
+    value::{Place, Value},
+};
+
+/// How deep calls can nest before the program is stopped
+pub const MAX_FRAMES: usize = 1 << 16;
+
+/// A generator between being resumed
+#[derive(Debug)]
+pub struct GeneratorState {
+    function: u32,
+    registers: Vec<Value>,
+    pc: usize,
+    done: bool,
+    /// Whether the generator is currently running, in which case it can not be resumed again
+    running: bool,
+    /// For a generator made by the runtime, the values it has left to yield
+    items: Option<std::vec::IntoIter<Value>>,
+}
+
+/// Why a program stopped before its function returned
+#[derive(Debug)]
+pub enum Halt {
+    Error(Diagnostic),
+    /// The program called `exit` with a code
+    Exit(i32),
+}
+
+struct Frame {
+    function: u32,
+    pc: usize,
+    registers: Vec<Value>,
+    /// The register of the frame below which receives the value this frame returns or yields
+    dst: Reg,
+    /// For a generator which was resumed, where the frame below continues once it is done
+    exit: Option<Label>,
+    generator: Option<Rc<RefCell<GeneratorState>>>,
+}
+
+/// Why an instruction failed, which becomes a [Diagnostic] pointing at the instruction
+struct Trap {
+    message: &'static str,
+    label: String,
+}
+
+impl Trap {
+    fn overflow(message: &'static str, ty: PrimitiveType) -> Self {
+        Self {
+            message,
+            label: format!("the result does not fit in `{}`", ty),
+        }
+    }
+
+    /// An instruction given a value it can not work on. Compiled programs never do this, but a
+    /// corrupted `.sbc` file can
+    fn invalid(label: String) -> Self {
+        Self {
+            message: "invalid bytecode",
+            label,
+        }
+    }
+
+    fn expected(expected: &str, found: &Value) -> Self {
+        Self::invalid(format!("expected {}, found {}", expected, found.kind()))
+    }
+}
+
+pub struct Vm<'program> {
+    program: &'program Program,
+    constants: Vec<Value>,
+    /// The builtin each function is, if it is one
+    builtins: Vec<Option<Builtin>>,
+    /// The `Vec`, `Map`, `Option` and `Result` of the standard library, when the program uses them
+    library: Library,
+    host: Host,
+    /// The methods of the `impl`s for one particular type
+    methods: HashMap<(TypeKey, &'program str), u32>,
+    /// The methods of the `impl`s for every type, which are only used when there is no `impl` for
+    /// the particular type
+    generic_methods: HashMap<&'program str, u32>,
+    frames: Vec<Frame>,
+}
+
+/// The types of the standard library the runtime makes values of, by their index
+#[derive(Default)]
+struct Library {
+    vec: Option<u32>,
+    map: Option<u32>,
+    option: Option<u32>,
+    result: Option<u32>,
+}
+
+impl<'program> Vm<'program> {
+    /// Prepares to run a program within a host
+    pub fn new(program: &'program Program, host: Host) -> Self {
+        let mut methods = HashMap::new();
+        let mut generic_methods = HashMap::new();
+        for implementation in &program.impls {
+            for (name, function) in &implementation.methods {
+                match implementation.self_type {
+                    Some(key) => methods.insert((key, name.as_str()), *function),
+                    None => generic_methods.insert(name.as_str(), *function),
+                };
+            }
+        }
+        let builtins = program
+            .functions
+            .iter()
+            .map(|x| {
+                let builtin = x.builtin.as_deref()?;
+                match builtin.rsplit_once("::") {
+                    Some((owner, name)) => Builtin::lookup(Some(owner), name),
+                    None => Builtin::lookup(None, builtin),
+                }
+            })
+            .collect();
+        let lookup = |name: &str| {
+            let index = program.adts.iter().position(|x| x.name == name)?;
+            Some(index as u32)
+        };
+        Self {
+            program,
+            constants: program.constants.iter().map(Value::from_constant).collect(),
+            builtins,
+            library: Library {
+                vec: lookup(sym::VEC.as_str()),
+                map: lookup(sym::MAP.as_str()),
+                option: lookup(sym::OPTION.as_str()),
+                result: lookup(sym::RESULT.as_str()),
+            },
+            host,
+            methods,
+            generic_methods,
+            frames: Vec::new(),
+        }
+    }
+
+    /// Calls a function, running it to completion
+    pub fn call(&mut self, function: u32, arguments: Vec<Value>) -> Result<Value, Halt> {
+        let base = self.frames.len();
+        match self.start(function, arguments, Reg(0))? {
+            Some(value) => Ok(value),
+            None => self.execute(base),
+        }
+    }
+
+    /// Pushes a frame for a function, or returns the generator it creates
+    fn start(
+        &mut self,
+        function: u32,
+        mut arguments: Vec<Value>,
+        dst: Reg,
+    ) -> Result<Option<Value>, Halt> {
+        if let Some(builtin) = self.builtins[function as usize] {
+            return match runtime::call(self, builtin, arguments) {
+                Ok(value) => Ok(Some(value)),
+                Err(stop) => Err(self.stopped(stop)),
+            };
+        }
+        let compiled = &self.program.functions[function as usize];
+        if compiled.is_foreign {
+            return Err(self.stopped(runtime::foreign(&compiled.name)));
+        }
+        arguments.resize(compiled.registers as usize, Value::Unit);
+        if compiled.is_generator {
+            return Ok(Some(Value::Generator(Rc::new(RefCell::new(
+                GeneratorState {
+                    function,
+                    registers: arguments,
+                    pc: 0,
+                    done: false,
+                    running: false,
+                    items: None,
+                },
+            )))));
+        }
+        self.push(Frame {
+            function,
+            pc: 0,
+            registers: arguments,
+            dst,
+            exit: None,
+            generator: None,
+        })?;
+        Ok(None)
+    }
+
+    fn frame(&mut self) -> &mut Frame {
+        self.frames.last_mut().expect("a function is running")
+    }
+
+    /// Gets the span of the instruction being run
+    fn span(&self) -> Span {
+        let frame = self.frames.last().expect("a function is running");
+        // The instruction has already been stepped past
+        self.program.functions[frame.function as usize].spans[frame.pc - 1]
+    }
+
+    fn error(&self, trap: Trap) -> Halt {
+        Halt::Error(Diagnostic::error(trap.message).with_primary(self.span(), trap.label))
+    }
+
+    /// Turns a builtin stopping into halting at the call being run
+    fn stopped(&self, stop: Stop<Halt>) -> Halt {
+        match stop {
+            Stop::Error { message, label } => {
+                Halt::Error(Diagnostic::error(message).with_primary(self.span(), label))
+            }
+            Stop::Exit(code) => Halt::Exit(code),
+            Stop::Engine(halt) => halt,
+        }
+    }
+
+    /// Runs instructions until the frame at `base` returns
+    fn execute(&mut self, base: usize) -> Result<Value, Halt> {
+        loop {
+            let frame = self.frames.last_mut().expect("a function is running");
+            let function = frame.function as usize;
+            let instruction = self.program.functions[function].code[frame.pc];
+            frame.pc += 1;
+            let registers = &mut frame.registers;
+            macro_rules! get {
+                ($register:expr) => {
+                    &registers[$register.0 as usize]
+                };
+            }
+            macro_rules! set {
+                ($register:expr, $value:expr) => {{
+                    let value = $value;
+                    registers[$register.0 as usize] = value;
+                }};
+            }
+            match instruction {
+                Instruction::Const { dst, constant } => {
+                    set!(dst, self.constants[constant as usize].clone())
+                }
+                Instruction::Move { dst, src } => set!(dst, get!(src).clone()),
+                Instruction::Arith {
+                    op,
+                    ty,
+                    overflow,
+                    dst,
+                    left,
+                    right,
+                } => match arith(op, ty, overflow, get!(left), get!(right)) {
+                    Ok(value) => set!(dst, value),
+                    Err(trap) => return Err(self.error(trap)),
+                },
+                Instruction::Compare {
+                    op,
+                    dst,
+                    left,
+                    right,
+                } => {
+                    let ordering = get!(left).compare(get!(right));
+                    set!(dst, Value::Bool(compare(op, ordering)))
+                }
+                Instruction::Negate {
+                    ty,
+                    overflow,
+                    dst,
+                    src,
+                } => match negate(ty, overflow, get!(src)) {
+                    Ok(value) => set!(dst, value),
+                    Err(trap) => return Err(self.error(trap)),
+                },
+                Instruction::Convert {
+                    ty,
+                    overflow,
+                    dst,
+                    src,
+                } => match convert(ty, overflow, get!(src)) {
+                    Ok(value) => set!(dst, value),
+                    Err(trap) => return Err(self.error(trap)),
+                },
+                Instruction::Not { dst, src } => match not(get!(src)) {
+                    Ok(value) => set!(dst, value),
+                    Err(trap) => return Err(self.error(trap)),
+                },
+                Instruction::Jump { target } => frame.pc = target as usize,
+                Instruction::JumpIf { condition, target } => match boolean(get!(condition)) {
+                    Ok(true) => frame.pc = target as usize,
+                    Ok(false) => {}
+                    Err(trap) => return Err(self.error(trap)),
+                },
+                Instruction::JumpIfNot { condition, target } => match boolean(get!(condition)) {
+                    Ok(false) => frame.pc = target as usize,
+                    Ok(true) => {}
+                    Err(trap) => return Err(self.error(trap)),
+                },
+                Instruction::Call {
+                    dst,
+                    function,
+                    arguments,
+                    count,
+                } => {
+                    let start = arguments.0 as usize;
+                    let arguments = registers[start..start + count as usize].to_vec();
+                    self.call_function(function, arguments, dst)?;
+                }
+                Instruction::CallValue {
+                    dst,
+                    callee,
+                    arguments,
+                    count,
+                } => {
+                    let (function, captures) = match get!(callee) {
+                        Value::Closure(function, captures) => (*function, captures.clone()),
+                        value => {
+                            let trap = Trap::expected("a closure", value);
+                            return Err(self.error(trap));
+                        }
+                    };
+                    let start = arguments.0 as usize;
+                    let mut arguments = registers[start..start + count as usize].to_vec();
+                    let arity = self.program.functions[function as usize].arity;
+                    if count != arity {
+                        let label = format!("the closure takes {} arguments, not {}", arity, count);
+                        return Err(self.error(Trap::invalid(label)));
+                    }
+                    arguments.extend(captures.iter().cloned());
+                    self.call_function(function, arguments, dst)?;
+                }
+                Instruction::MakeClosure {
+                    dst,
+                    function,
+                    captures,
+                    count,
+                } => {
+                    let start = captures.0 as usize;
+                    let captures = registers[start..start + count as usize].to_vec();
+                    set!(dst, Value::Closure(function, Rc::new(captures)))
+                }
+                Instruction::CallMethod {
+                    dst,
+                    method,
+                    arguments,
+                    count,
+                } => {
+                    let start = arguments.0 as usize;
+                    let arguments = registers[start..start + count as usize].to_vec();
+                    let Value::Str(method) = &self.constants[method as usize] else {
+                        panic!("methods are named by `Str` constants");
+                    };
+                    let method = method.clone();
+                    self.call_method(&method, arguments, dst)?;
+                }
+                Instruction::Return { src } => {
+                    let value = get!(src).clone();
+                    let frame = self.frames.pop().expect("a function is running");
+                    if let Some(generator) = frame.generator {
+                        let mut generator = generator.borrow_mut();
+                        generator.done = true;
+                        generator.running = false;
+                        let exit = frame.exit.expect("a resumed generator has an exit");
+                        self.frame().pc = exit as usize;
+                    } else if self.frames.len() == base {
+                        return Ok(value);
+                    } else {
+                        self.frame().registers[frame.dst.0 as usize] = value;
+                    }
+                }
+                Instruction::Yield { src } => {
+                    let value = get!(src).clone();
+                    let frame = self.frames.pop().expect("a function is running");
+                    let generator = frame.generator.expect("only generators yield");
+                    let mut generator = generator.borrow_mut();
+                    generator.registers = frame.registers;
+                    generator.pc = frame.pc;
+                    generator.running = false;
+                    self.frame().registers[frame.dst.0 as usize] = value;
+                }
+                Instruction::Next {
+                    dst,
+                    generator,
+                    exit,
+                } => {
+                    let generator = match get!(generator) {
+                        Value::Generator(generator) => generator.clone(),
+                        value => {
+                            let trap = Trap::expected("a generator", value);
+                            return Err(self.error(trap));
+                        }
+                    };
+                    let mut state = generator.borrow_mut();
+                    if let Some(items) = &mut state.items {
+                        match items.next() {
+                            Some(value) => set!(dst, value),
+                            None => frame.pc = exit as usize,
+                        }
+                        continue;
+                    }
+                    if state.done {
+                        frame.pc = exit as usize;
+                        continue;
+                    }
+                    if state.running {
+                        drop(state);
+                        return Err(self.error(Trap {
+                            message: "generator resumed while it is already running",
+                            label: "the generator is resumed from within itself".to_string(),
+                        }));
+                    }
+                    state.running = true;
+                    let resumed = Frame {
+                        function: state.function,
+                        pc: state.pc,
+                        registers: std::mem::take(&mut state.registers),
+                        dst,
+                        exit: Some(exit),
+                        generator: Some(generator.clone()),
+                    };
+                    drop(state);
+                    self.push(resumed)?;
+                }
+                Instruction::Tuple { dst, start, count } => {
+                    let start = start.0 as usize;
+                    let parts = registers[start..start + count as usize].to_vec();
+                    set!(dst, Value::Tuple(Rc::new(parts)))
+                }
+                Instruction::Struct {
+                    dst,
+                    adt,
+                    start,
+                    count,
+                } => {
+                    let start = start.0 as usize;
+                    let parts = registers[start..start + count as usize].to_vec();
+                    set!(dst, Value::Struct(adt, Rc::new(parts)))
+                }
+                Instruction::Variant {
+                    dst,
+                    adt,
+                    variant,
+                    start,
+                    count,
+                } => {
+                    let start = start.0 as usize;
+                    let parts = registers[start..start + count as usize].to_vec();
+                    set!(dst, Value::Variant(adt, variant, Rc::new(parts)))
+                }
+                Instruction::Field { dst, src, index } => match part(get!(src), index) {
+                    Ok(value) => set!(dst, value),
+                    Err(trap) => return Err(self.error(trap)),
+                },
+                Instruction::SetField { target, index, src } => {
+                    let value = get!(src).clone();
+                    if let Err(trap) = set_part(&mut registers[target.0 as usize], index, value) {
+                        return Err(self.error(trap));
+                    }
+                }
+                Instruction::IsVariant { dst, src, variant } => {
+                    let matches = matches!(get!(src), Value::Variant(_, x, _) if *x == variant);
+                    set!(dst, Value::Bool(matches))
+                }
+                Instruction::Alloc { dst, src } => {
+                    set!(dst, Value::Reference(Place::new(get!(src).clone())))
+                }
+                Instruction::Project { dst, src, index } => match reference(get!(src)) {
+                    Ok(place) => set!(dst, Value::Reference(place.project(index))),
+                    Err(trap) => return Err(self.error(trap)),
+                },
+                Instruction::Load { dst, src } => match reference(get!(src)).and_then(read) {
+                    Ok(value) => set!(dst, value),
+                    Err(trap) => return Err(self.error(trap)),
+                },
+                Instruction::Store { target, src } => {
+                    if let Err(trap) = reference(get!(target)).and_then(|x| write(x, get!(src))) {
+                        return Err(self.error(trap));
+                    }
+                }
+                Instruction::NoMatch => {
+                    return Err(self.error(Trap {
+                        message: "no arm of the `when` matched",
+                        label: "matched here".to_string(),
+                    }));
+                }
+            }
+        }
+    }
+
+    fn push(&mut self, frame: Frame) -> Result<(), Halt> {
+        if self.frames.len() >= MAX_FRAMES {
+            return Err(self.error(Trap {
+                message: "stack overflow",
+                label: format!("calls are nested more than {} deep", MAX_FRAMES),
+            }));
+        }
+        self.frames.push(frame);
+        Ok(())
+    }
+
+    fn call_function(
+        &mut self,
+        function: u32,
+        arguments: Vec<Value>,
+        dst: Reg,
+    ) -> Result<(), Halt> {
+        if let Some(generator) = self.start(function, arguments, dst)? {
+            self.frame().registers[dst.0 as usize] = generator;
+        }
+        Ok(())
+    }
+
+    /// Calls a method through the type of its first argument, reaching through references when
+    /// the type they point to has the method instead
+    fn call_method(
+        &mut self,
+        method: &str,
+        mut arguments: Vec<Value>,
+        dst: Reg,
+    ) -> Result<(), Halt> {
+        let mut receiver = arguments[0].clone();
+        let mut place = None;
+        let function = loop {
+            if let Some(function) = self.find_method(&receiver, method) {
+                break function;
+            }
+            match receiver {
+                Value::Reference(reference) => {
+                    receiver = read(&reference).map_err(|x| self.error(x))?;
+                    place = Some(reference);
+                }
+                _ => {
+                    let label = format!("{} has no method `{}`", receiver.kind(), method);
+                    return Err(self.error(Trap::invalid(label)));
+                }
+            }
+        };
+        let by_reference = self.program.functions[function as usize].self_by_reference;
+        arguments[0] = match (by_reference, receiver) {
+            (true, receiver @ Value::Reference(_)) => receiver,
+            (true, receiver) => Value::Reference(place.unwrap_or_else(|| Place::new(receiver))),
+            (false, receiver) => receiver,
+        };
+        self.call_function(function, arguments, dst)
+    }
+
+    fn find_method(&self, receiver: &Value, method: &str) -> Option<u32> {
+        let key = receiver.type_key();
+        self.methods
+            .get(&(key, method))
+            .or_else(|| self.generic_methods.get(method))
+            .copied()
+    }
+}
+
+fn reference(value: &Value) -> Result<&Place, Trap> {
+    match value {
+        Value::Reference(place) => Ok(place),
+        _ => Err(Trap::expected("a reference", value)),
+    }
+}
+
+fn read(place: &Place) -> Result<Value, Trap> {
+    place
+        .read()
+        .ok_or_else(|| Trap::invalid("the reference points to a part which does not exist".into()))
+}
+
+/// Writes a value to a place, which must not lead back to the place as following the references
+/// would then never end
+fn write(place: &Place, value: &Value) -> Result<(), Trap> {
+    if value.leads_to(&place.memory) {
+        return Err(Trap::invalid("a reference can not point to itself".into()));
+    }
+    match place.write(value.clone()) {
+        true => Ok(()),
+        false => Err(Trap::invalid(
+            "the reference points to a part which does not exist".into(),
+        )),
+    }
+}
+
+fn boolean(value: &Value) -> Result<bool, Trap> {
+    match value {
+        Value::Bool(x) => Ok(*x),
+        _ => Err(Trap::expected("a `Bool`", value)),
+    }
+}
+
+/// Gets a field, element or payload value of a value
+fn part(value: &Value, index: u16) -> Result<Value, Trap> {
+    match value.parts().get(index as usize) {
+        Some(part) => Ok(part.clone()),
+        None => Err(missing_part(value, index)),
+    }
+}
+
+/// Replaces a field, element or payload value of a value
+fn set_part(target: &mut Value, index: u16, value: Value) -> Result<(), Trap> {
+    if let Some(part) = target.part_mut(index) {
+        *part = value;
+        return Ok(());
+    }
+    Err(missing_part(target, index))
+}
+
+fn missing_part(value: &Value, index: u16) -> Trap {
+    Trap::invalid(format!("{} has no part {}", value.kind(), index))
+}
+
+fn compare(op: CompareOp, ordering: Option<Ordering>) -> bool {
+    match op {
+        CompareOp::Greater => ordering == Some(Ordering::Greater),
+        CompareOp::Lesser => ordering == Some(Ordering::Less),
+        CompareOp::GreaterOrEqual => matches!(ordering, Some(Ordering::Greater | Ordering::Equal)),
+        CompareOp::LessOrEqual => matches!(ordering, Some(Ordering::Less | Ordering::Equal)),
+        CompareOp::EqualTo => ordering == Some(Ordering::Equal),
+        CompareOp::NotEqual => ordering != Some(Ordering::Equal),
+    }
+}
+
+fn round(ty: PrimitiveType, value: f64) -> Value {
+    match ty {
+        PrimitiveType::Float32 => Value::Float(ty, value as f32 as f64),
+        _ => Value::Float(ty, value),
+    }
+}
+
+fn arith(
+    op: ArithOp,
+    ty: PrimitiveType,
+    overflow: Overflow,
+    left: &Value,
+    right: &Value,
+) -> Result<Value, Trap> {
+    match (left, right) {
+        (Value::Float(_, x), Value::Float(_, y)) if ty.is_float() => Ok(round(
+            ty,
+            match op {
+                ArithOp::Add => x + y,
+                ArithOp::Subtract => x - y,
+                ArithOp::Multiply => x * y,
+                ArithOp::Divide => x / y,
+                _ => {
+                    let label = format!("`{:?}` is not an operation on floats", op);
+                    return Err(Trap::invalid(label));
+                }
+            },
+        )),
+        (Value::Bool(x), Value::Bool(y)) if ty == PrimitiveType::Bool => Ok(Value::Bool(x & y)),
+        (Value::Integer(_, x), Value::Integer(_, y)) if ty.is_integer() => {
+            integer_arith(op, ty, overflow, *x, *y)
+        }
+        _ => Err(Trap::invalid(format!(
+            "cannot apply `{:?}` to {} and {} as `{}`",
+            op,
+            left.kind(),
+            right.kind(),
+            ty
+        ))),
+    }
+}
+
+fn integer_arith(
+    op: ArithOp,
+    ty: PrimitiveType,
+    overflow: Overflow,
+    x: i128,
+    y: i128,
+) -> Result<Value, Trap> {
+    let (operator, message) = match op {
+        ArithOp::Add => (BinaryOperator::Add, "attempt to add with overflow"),
+        ArithOp::Subtract => (
+            BinaryOperator::Subtract,
+            "attempt to subtract with overflow",
+        ),
+        ArithOp::Multiply => (
+            BinaryOperator::Multiply,
+            "attempt to multiply with overflow",
+        ),
+        ArithOp::Divide => (BinaryOperator::Divide, "attempt to divide with overflow"),
+        ArithOp::BitwiseAnd => return Ok(Value::Integer(ty, x & y)),
+        ArithOp::ShiftLeft | ArithOp::ShiftRight => {
+            let (message, name) = match op {
+                ArithOp::ShiftLeft => ("attempt to shift left with overflow", "left"),
+                _ => ("attempt to shift right with overflow", "right"),
+            };
+            if y < 0 || y >= numeric::bits(ty) as i128 {
+                return Err(Trap {
+                    message,
+                    label: format!("cannot shift {} by {}", name, y),
+                });
+            }
+            let result = match op {
+                ArithOp::ShiftLeft => numeric::wrap(ty, x.wrapping_shl(y as u32)),
+                _ => x >> y,
+            };
+            return Ok(Value::Integer(ty, result));
+        }
+    };
+    match numeric::arith(operator, overflow, ty, x, y) {
+        Ok(result) => Ok(Value::Integer(ty, result)),
+        Err(NumericError::DivisionByZero) => Err(Trap {
+            message: "attempt to divide by zero",
+            label: "the divisor is zero".to_string(),
+        }),
+        Err(NumericError::Overflow) => Err(Trap::overflow(message, ty)),
+    }
+}
+
+fn negate(ty: PrimitiveType, overflow: Overflow, value: &Value) -> Result<Value, Trap> {
+    match value {
+        Value::Float(_, x) if ty.is_float() => Ok(Value::Float(ty, -x)),
+        Value::Integer(_, x) if ty.is_integer() => match numeric::negate(overflow, ty, *x) {
+            Ok(result) => Ok(Value::Integer(ty, result)),
+            Err(_) => Err(Trap::overflow("attempt to negate with overflow", ty)),
+        },
+        _ => Err(Trap::expected(&format!("a `{}`", ty), value)),
+    }
+}
+
+/// Converts a number to the numeric type `ty`
+fn convert(ty: PrimitiveType, overflow: Overflow, value: &Value) -> Result<Value, Trap> {
+    let number = match value {
+        Value::Integer(_, x) => Number::Integer(*x),
+        Value::Float(_, x) => Number::Float(*x),
+        _ => return Err(Trap::expected("a number", value)),
+    };
+    match numeric::convert(number, ty, overflow) {
+        Ok(Number::Integer(x)) => Ok(Value::Integer(ty, x)),
+        Ok(Number::Float(x)) => Ok(Value::Float(ty, x)),
+        Err(_) => Err(Trap {
+            message: "attempt to convert with overflow",
+            label: format!("the value does not fit in `{}`", ty),
+        }),
+    }
+}
+
+/// Flips a `Bool`, or every bit of an integer
+fn not(value: &Value) -> Result<Value, Trap> {
+    match value {
+        Value::Bool(x) => Ok(Value::Bool(!x)),
+        Value::Integer(ty, x) => match ty.bounds() {
+            (0, max) => Ok(Value::Integer(*ty, max - x)),
+            _ => Ok(Value::Integer(*ty, !x)),
+        },
+        _ => Err(Trap::expected("a `Bool` or an integer", value)),
+    }
+}
+
+impl Machine for Vm<'_> {
+    type Value = Value;
+    type Error = Halt;
+
+    fn host(&mut self) -> &mut Host {
+        &mut self.host
+    }
+
+    fn show(&mut self, value: &Value) -> Result<String, Halt> {
+        let mut value = value.clone();
+        let function = loop {
+            if let Some(function) = self.find_method(&value, "show") {
+                break function;
+            }
+            match value {
+                Value::Reference(place) => value = read(&place).map_err(|x| self.error(x))?,
+                _ => {
+                    let label = format!("{} does not implement `Show`", value.kind());
+                    return Err(self.error(Trap::invalid(label)));
+                }
+            }
+        };
+        let by_reference = self.program.functions[function as usize].self_by_reference;
+        if by_reference && !matches!(value, Value::Reference(_)) {
+            value = Value::Reference(Place::new(value));
+        }
+        match self.call(function, vec![value])? {
+            Value::Str(text) => Ok(text.to_string()),
+            value => Err(self.error(Trap::expected("`show` to give a `Str`", &value))),
+        }
+    }
+
+    fn show_primitive(&self, value: &Value) -> String {
+        value.render(self.program)
+    }
+
+    fn unit(&self) -> Value {
+        Value::Unit
+    }
+
+    fn bool(&self, value: bool) -> Value {
+        Value::Bool(value)
+    }
+
+    fn char(&self, value: char) -> Value {
+        Value::Integer(PrimitiveType::Char, value as i128)
+    }
+
+    fn int64(&self, value: i64) -> Value {
+        Value::Integer(PrimitiveType::Int64, value as i128)
+    }
+
+    fn float64(&self, value: f64) -> Value {
+        Value::Float(PrimitiveType::Float64, value)
+    }
+
+    fn string(&self, value: &str) -> Value {
+        Value::Str(value.into())
+    }
+
+    fn list(&self, items: Rc<Vec<Value>>) -> Value {
+        Value::List(self.library.vec.expect("`Vec` is used"), items)
+    }
+
+    fn map(&self, entries: Entries<Value>) -> Value {
+        Value::Map(self.library.map.expect("`Map` is used"), entries)
+    }
+
+    fn option(&self, value: Option<Value>) -> Value {
+        let id = self.library.option.expect("`Option` is used");
+        match value {
+            Some(value) => Value::Variant(id, 0, Rc::new(vec![value])),
+            None => Value::Variant(id, 1, Rc::new(Vec::new())),
+        }
+    }
+
+    fn result(&self, value: Result<Value, Value>) -> Value {
+        let id = self.library.result.expect("`Result` is used");
+        match value {
+            Ok(value) => Value::Variant(id, 0, Rc::new(vec![value])),
+            Err(error) => Value::Variant(id, 1, Rc::new(vec![error])),
+        }
+    }
+
+    fn generator(&mut self, items: Vec<Value>) -> Value {
+        Value::Generator(Rc::new(RefCell::new(GeneratorState {
+            function: 0,
+            registers: Vec::new(),
+            pc: 0,
+            done: false,
+            running: false,
+            items: Some(items.into_iter()),
+        })))
+    }
+
+    fn to_integer(&self, value: &Value) -> Option<i128> {
+        match value {
+            Value::Integer(_, x) => Some(*x),
+            _ => None,
+        }
+    }
+
+    fn to_str(&self, value: &Value) -> Option<Rc<str>> {
+        match value {
+            Value::Str(text) => Some(text.clone()),
+            _ => None,
+        }
+    }
+
+    fn to_list(&self, value: &Value) -> Option<Rc<Vec<Value>>> {
+        match value {
+            Value::List(_, items) => Some(items.clone()),
+            _ => None,
+        }
+    }
+
+    fn to_map(&self, value: &Value) -> Option<Entries<Value>> {
+        match value {
+            Value::Map(_, entries) => Some(entries.clone()),
+            _ => None,
+        }
+    }
+
+    fn to_variant(&self, value: &Value) -> Option<(bool, Option<Value>)> {
+        match value {
+            Value::Variant(_, index, payload) => Some((*index == 0, payload.first().cloned())),
+            _ => None,
+        }
+    }
+
+    fn compare(&self, left: &Value, right: &Value) -> Option<Ordering> {
+        left.compare(right)
+    }
+
+    fn read(&self, value: &Value) -> Value {
+        match value {
+            // A reference to a part which does not exist leaves nothing for the builtin to use
+            Value::Reference(place) => self.read(&place.read().unwrap_or(Value::Unit)),
+            value => value.clone(),
+        }
+    }
+
+    fn write(&self, reference: &Value, value: Value) -> bool {
+        let mut place = reference.clone();
+        while let Value::Reference(inner) = place {
+            match inner.read() {
+                Some(Value::Reference(next)) => place = Value::Reference(next),
+                Some(_) => return inner.write(value),
+                None => return false,
+            }
+        }
+        false
+    }
+}
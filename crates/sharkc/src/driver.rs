@@ -0,0 +1,157 @@
+//! Runs the steps of compilation over a source file, reporting every diagnostic along the way
+//!
+//! The source file is checked along with the modules it uses and the parts of the standard library
+//! they use, see [shark_std::load]. The modules are merged into one before semantic analysis, see
+//! [shark_resolve::merge], and diagnostics are reported against whichever file they point into
+
+use std::{
+    cell::RefCell,
+    path::{Path, PathBuf},
+};
+
+use shark_const::ConstResults;
+use shark_core::{diagnostic::Diagnostic, source::Span};
+use shark_parse::ast::Module;
+use shark_resolve::tree::{FileLoader, ModuleData, ModuleTree};
+use shark_sema::ModuleDefs;
+use shark_typeck::TypeckResults;
+
+/// A source file being compiled
+pub struct Session {
+    pub path: PathBuf,
+    /// The source file followed by the source of every module it uses and then the standard
+    /// library, see [ModuleTree::text]. Every span points into it
+    pub source: String,
+    tree: ModuleTree,
+    /// Every diagnostic reported so far, in order
+    reported: RefCell<Vec<Diagnostic>>,
+}
+
+/// A [Module] which has been checked without errors, and whose constants have been replaced by
+/// their values
+pub struct Checked {
+    pub module: Module,
+    pub defs: ModuleDefs,
+    pub types: TypeckResults,
+    pub constants: ConstResults,
+}
+
+impl Session {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|x| format!("could not read `{}`: {}", path.display(), x))?;
+        Ok(Self::new(path, &source))
+    }
+
+    /// Makes a session for a source file which has already been read. The modules it uses are
+    /// read from the directory it is in
+    pub fn new(path: &Path, source: &str) -> Self {
+        let mut loader = FileLoader {
+            directory: path.parent().unwrap_or(Path::new("")).to_path_buf(),
+        };
+        let tree = shark_std::load(Some(path), source, &mut loader);
+        Self {
+            path: path.to_path_buf(),
+            source: tree.text(),
+            tree,
+            reported: RefCell::default(),
+        }
+    }
+
+    /// Prints diagnostics to stderr, returning whether any of them were errors
+    pub fn report(&self, diagnostics: &[Diagnostic]) -> bool {
+        for diagnostic in diagnostics {
+            eprintln!("{}", self.render(diagnostic));
+        }
+        self.reported.borrow_mut().extend_from_slice(diagnostics);
+        diagnostics.iter().any(Diagnostic::is_error)
+    }
+
+    /// Gets every diagnostic reported so far
+    pub fn reported(&self) -> Vec<Diagnostic> {
+        self.reported.borrow().clone()
+    }
+
+    /// Renders a diagnostic against the file of the module it points into. Labels in other
+    /// modules are left out
+    pub(crate) fn render(&self, diagnostic: &Diagnostic) -> String {
+        let start = diagnostic.primary_span().map_or(0, |x| x.start);
+        let (_, module) = self
+            .tree
+            .modules()
+            .filter(|(_, x)| x.offset <= start)
+            .last()
+            .expect("the source file starts the source");
+        let (start, end) = (module.offset, module.offset + module.source.len());
+        let mut diagnostic = diagnostic.clone();
+        diagnostic
+            .labels
+            .retain(|x| start <= x.span.start && x.span.end <= end);
+        for label in &mut diagnostic.labels {
+            label.span = Span::new(label.span.start - start, label.span.end - start);
+        }
+        diagnostic.render(module.file.as_deref(), &module.source)
+    }
+
+    /// Resolves the names of the source file and the modules it uses, returning them merged into
+    /// one module unless there was an error
+    fn resolve(&self) -> Option<Module> {
+        let mut failed = false;
+        for (_, module) in self.tree.modules() {
+            failed |= self.report_within(module, &module.diagnostics);
+        }
+        if failed {
+            return None;
+        }
+        let (resolutions, diagnostics) = shark_resolve::resolve(&self.tree);
+        for (id, diagnostic) in diagnostics {
+            failed |= self.report_within(self.tree.module(id), &[diagnostic]);
+        }
+        match failed {
+            true => None,
+            false => Some(shark_resolve::merge(&self.tree, &resolutions)),
+        }
+    }
+
+    /// Reports diagnostics found within a module before it was merged, whose spans point into its
+    /// own source
+    fn report_within(&self, module: &ModuleData, diagnostics: &[Diagnostic]) -> bool {
+        let mut diagnostics = diagnostics.to_vec();
+        for label in diagnostics.iter_mut().flat_map(|x| &mut x.labels) {
+            label.span = Span::new(
+                label.span.start + module.offset,
+                label.span.end + module.offset,
+            );
+        }
+        self.report(&diagnostics)
+    }
+
+    /// Parses and checks the source file and evaluates its constants, stopping after the first
+    /// step which finds an error
+    pub fn check(&self) -> Option<Checked> {
+        let mut module = self.resolve()?;
+        let (defs, diagnostics) = shark_sema::check_module(&module);
+        if self.report(&diagnostics) {
+            return None;
+        }
+        let (types, diagnostics) = shark_typeck::check_module(&module, &defs);
+        if self.report(&diagnostics) {
+            return None;
+        }
+        let diagnostics = shark_borrowck::check_module(&module, &defs, &types);
+        if self.report(&diagnostics) {
+            return None;
+        }
+        let (constants, diagnostics) = shark_const::evaluate(&module, &types, &self.source);
+        if self.report(&diagnostics) {
+            return None;
+        }
+        shark_const::substitute(&mut module, &constants);
+        Some(Checked {
+            module,
+            defs,
+            types,
+            constants,
+        })
+    }
+}
@@ -1,3 +1,698 @@
-pub fn main() {
-    println!("Hello, World!");
+use std::{
+    path::{Path, PathBuf},
+    process::ExitCode,
+    time::Duration,
+};
+
+use driver::{Checked, Session};
+use shark_core::diagnostic::Diagnostic;
+use shark_interp::value::Value;
+use shark_ir::OptLevel;
+use shark_lower::Body;
+use shark_sema::numeric::Overflow;
+use shark_std::runtime::Host;
+use shark_vm::bytecode::Program;
+
+pub mod driver;
+#[cfg(test)]
+pub mod tests;
+
+const USAGE: &str =
+    "usage: sharkc run [--vm|--jit] [--release] [--time-passes] [--emit=bytecode|sbc|ir] [-O0|-O1|-O2] <file> [-- <args>...]
+       sharkc build --target=c|wasm32|x86_64 [--release] [-O0|-O1|-O2] [--link=<path>...] [-o <output>] <file>
+       sharkc bindgen [-o <output>] <header>
+       sharkc fmt [--check] <file>...";
+
+/// The exit code of a program stopped by a runtime error
+const RUNTIME_ERROR: u8 = 101;
+
+/// What to write out instead of running a program
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Emit {
+    /// The disassembled bytecode, written to stdout
+    Bytecode,
+    /// A `.sbc` file next to the source file
+    Sbc,
+    /// The IR, optimised at the level given, written to stdout
+    Ir,
+}
+
+enum Command {
+    /// Checks a file then runs it, starting from its `pub fun main()`. Files ending in `.sbc` are
+    /// always run on the VM
+    Run {
+        path: PathBuf,
+        engine: Engine,
+        emit: Option<Emit>,
+        /// Whether to report how long compiling each function took, which only the JIT does
+        time_passes: bool,
+        /// How hard to optimise the IR, which the engines running programs do not use, so it is
+        /// only built to be emitted
+        opt_level: Option<OptLevel>,
+        /// What arithmetic does when it overflows, which `--release` makes wrap around
+        overflow: Overflow,
+        /// The arguments after `--`, which the program gets from `args()`
+        args: Vec<String>,
+    },
+    /// Compiles a file into an executable, next to it unless an output is given
+    Build {
+        path: PathBuf,
+        target: Target,
+        output: Option<PathBuf>,
+        /// How hard to optimise the IR the backends lower from
+        opt_level: OptLevel,
+        overflow: Overflow,
+        /// The C files, objects and libraries to link with, for the `extern "C"` functions
+        link: Vec<PathBuf>,
+    },
+    /// Generates the `extern "C"` declarations for a C header, written to stdout unless an output
+    /// is given
+    Bindgen {
+        path: PathBuf,
+        output: Option<PathBuf>,
+    },
+    /// Formats files in place, using the settings of the `sharkfmt.toml` found for each
+    Fmt {
+        paths: Vec<PathBuf>,
+        /// Whether to only list the files which are not formatted, failing if there are any
+        check: bool,
+    },
+}
+
+/// What runs a program for `sharkc run`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Engine {
+    Interpreter,
+    Vm,
+    /// The JIT, which falls back to the interpreter for functions it can not compile
+    Jit,
+}
+
+/// What a program is compiled into by `sharkc build`
+#[derive(Clone, Copy, PartialEq, Eq)]
+enum Target {
+    /// C, which the system C compiler turns into an executable
+    C,
+    /// A WebAssembly module, written both in the binary and in the text format, which imports
+    /// `shark.panic` to report runtime errors
+    Wasm32,
+    /// x86-64 assembly for Linux, which the system toolchain assembles and links
+    X86_64,
+}
+
+impl Command {
+    fn parse(mut arguments: impl Iterator<Item = String>) -> Result<Self, String> {
+        let command = arguments.next().ok_or(USAGE)?;
+        match command.as_str() {
+            "run" => {}
+            "build" => return Self::parse_build(arguments),
+            "bindgen" => return Self::parse_bindgen(arguments),
+            "fmt" => return Self::parse_fmt(arguments),
+            _ => return Err(format!("unknown command `{}`\n{}", command, USAGE)),
+        }
+        let mut path = None;
+        let mut engine = Engine::Interpreter;
+        let mut emit = None;
+        let mut time_passes = false;
+        let mut opt_level = None;
+        let mut overflow = Overflow::Trap;
+        let mut args = Vec::new();
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "--" => args.extend(arguments.by_ref()),
+                "--vm" if engine != Engine::Jit => engine = Engine::Vm,
+                "--release" => overflow = Overflow::Wrap,
+                "--jit" if engine != Engine::Vm => engine = Engine::Jit,
+                "--time-passes" => time_passes = true,
+                "--emit=bytecode" => emit = Some(Emit::Bytecode),
+                "--emit=sbc" => emit = Some(Emit::Sbc),
+                "--emit=ir" => emit = Some(Emit::Ir),
+                _ if argument.starts_with("-O") => match OptLevel::from_flag(&argument[2..]) {
+                    Some(level) => opt_level = Some(level),
+                    None => return Err(format!("unknown level `{}`\n{}", &argument[2..], USAGE)),
+                },
+                _ if !argument.starts_with('-') && path.is_none() => path = Some(argument.into()),
+                _ => return Err(format!("unexpected argument `{}`\n{}", argument, USAGE)),
+            }
+        }
+        if time_passes && (engine != Engine::Jit || emit.is_some()) {
+            return Err(format!(
+                "`--time-passes` can only be used with `--jit`\n{}",
+                USAGE
+            ));
+        }
+        if engine == Engine::Jit && emit.is_some() {
+            return Err(format!("`--emit` can not be used with `--jit`\n{}", USAGE));
+        }
+        if opt_level.is_some() && emit != Some(Emit::Ir) {
+            return Err(format!("`-O` can only be used with `--emit=ir`\n{}", USAGE));
+        }
+        Ok(Self::Run {
+            path: path.ok_or(USAGE)?,
+            engine,
+            emit,
+            time_passes,
+            opt_level,
+            overflow,
+            args,
+        })
+    }
+
+    fn parse_build(mut arguments: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut path = None;
+        let mut target = None;
+        let mut output = None;
+        let mut opt_level = OptLevel::default();
+        let mut overflow = Overflow::Trap;
+        let mut link = Vec::new();
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "--release" => overflow = Overflow::Wrap,
+                "--target=c" => target = Some(Target::C),
+                _ if argument.starts_with("--link=") => link.push(argument[7..].into()),
+                "--target=wasm32" => target = Some(Target::Wasm32),
+                "--target=x86_64" => target = Some(Target::X86_64),
+                "-o" => output = Some(arguments.next().ok_or(USAGE)?.into()),
+                _ if argument.starts_with("-O") => match OptLevel::from_flag(&argument[2..]) {
+                    Some(level) => opt_level = level,
+                    None => return Err(format!("unknown level `{}`\n{}", &argument[2..], USAGE)),
+                },
+                _ if argument.starts_with("--target=") => {
+                    return Err(format!("unknown target `{}`\n{}", &argument[9..], USAGE))
+                }
+                _ if !argument.starts_with('-') && path.is_none() => path = Some(argument.into()),
+                _ => return Err(format!("unexpected argument `{}`\n{}", argument, USAGE)),
+            }
+        }
+        if !link.is_empty() && target != Some(Target::C) {
+            return Err(format!(
+                "`--link` can only be used with `--target=c`\n{}",
+                USAGE
+            ));
+        }
+        Ok(Self::Build {
+            path: path.ok_or(USAGE)?,
+            target: target.ok_or(USAGE)?,
+            output,
+            opt_level,
+            overflow,
+            link,
+        })
+    }
+
+    fn parse_bindgen(mut arguments: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut path = None;
+        let mut output = None;
+        while let Some(argument) = arguments.next() {
+            match argument.as_str() {
+                "-o" => output = Some(arguments.next().ok_or(USAGE)?.into()),
+                _ if !argument.starts_with('-') && path.is_none() => path = Some(argument.into()),
+                _ => return Err(format!("unexpected argument `{}`\n{}", argument, USAGE)),
+            }
+        }
+        Ok(Self::Bindgen {
+            path: path.ok_or(USAGE)?,
+            output,
+        })
+    }
+
+    fn parse_fmt(arguments: impl Iterator<Item = String>) -> Result<Self, String> {
+        let mut paths = Vec::new();
+        let mut check = false;
+        for argument in arguments {
+            match argument.as_str() {
+                "--check" => check = true,
+                _ if !argument.starts_with('-') => paths.push(argument.into()),
+                _ => return Err(format!("unexpected argument `{}`\n{}", argument, USAGE)),
+            }
+        }
+        if paths.is_empty() {
+            return Err(USAGE.to_string());
+        }
+        Ok(Self::Fmt { paths, check })
+    }
+}
+
+fn run(
+    session: &Session,
+    engine: Engine,
+    emit: Option<Emit>,
+    time_passes: bool,
+    opt_level: Option<OptLevel>,
+    overflow: Overflow,
+    args: Vec<String>,
+) -> ExitCode {
+    let Some(checked) = session.check() else {
+        return ExitCode::FAILURE;
+    };
+    let (bodies, diagnostics) = shark_lower::lower_module(&checked.module);
+    if session.report(&diagnostics) {
+        return ExitCode::FAILURE;
+    }
+    if emit == Some(Emit::Ir) {
+        return emit_ir(&checked, &bodies, opt_level.unwrap_or_default(), overflow);
+    }
+    if engine == Engine::Vm || emit.is_some() {
+        if let (None, Err(diagnostic)) = (emit, shark_interp::find_main(&checked.module)) {
+            session.report(&[diagnostic]);
+            return ExitCode::FAILURE;
+        }
+        let program = shark_vm::compile_module(
+            &checked.module,
+            &checked.defs,
+            &checked.types,
+            &bodies,
+            overflow,
+        );
+        return match emit {
+            Some(Emit::Bytecode) => {
+                print!("{}", shark_vm::disasm::disassemble(&program));
+                ExitCode::SUCCESS
+            }
+            Some(Emit::Sbc) => {
+                let path = session.path.with_extension("sbc");
+                match std::fs::write(&path, shark_vm::sbc::write(&program)) {
+                    Ok(()) => ExitCode::SUCCESS,
+                    Err(x) => {
+                        eprintln!("error: could not write `{}`: {}", path.display(), x);
+                        ExitCode::FAILURE
+                    }
+                }
+            }
+            Some(Emit::Ir) => unreachable!("the IR is emitted before compiling to bytecode"),
+            None => match shark_vm::run(&program, Host::new(args)) {
+                Ok(value) => ExitCode::from(exit_code(&value)),
+                Err(diagnostic) => {
+                    session.report(&[diagnostic]);
+                    ExitCode::from(RUNTIME_ERROR)
+                }
+            },
+        };
+    }
+
+    // Only a plain exit code leaves the thread, as values can not be sent between threads
+    let result = std::thread::scope(|scope| {
+        std::thread::Builder::new()
+            .stack_size(shark_interp::STACK_SIZE)
+            .spawn_scoped(scope, || {
+                let host = Host::new(args);
+                let result = match engine {
+                    Engine::Jit => run_jit(&checked, &bodies, time_passes, overflow, host),
+                    _ => shark_interp::run(
+                        &checked.module,
+                        &checked.defs,
+                        &checked.types,
+                        &bodies,
+                        overflow,
+                        host,
+                    ),
+                };
+                result.map(|x| match x {
+                    Value::Int32(code) => code as u8,
+                    _ => 0,
+                })
+            })
+            .expect("failed to spawn the interpreter thread")
+            .join()
+    });
+    match result {
+        Ok(Ok(code)) => ExitCode::from(code),
+        Ok(Err(diagnostic)) => {
+            session.report(&[diagnostic]);
+            ExitCode::from(RUNTIME_ERROR)
+        }
+        // The panic has already been printed by the thread
+        Err(_) => {
+            eprintln!("error: the interpreter stopped unexpectedly");
+            ExitCode::from(RUNTIME_ERROR)
+        }
+    }
+}
+
+/// Builds the IR of a program and optimises it, writing it to stdout
+fn emit_ir(checked: &Checked, bodies: &[Body], level: OptLevel, overflow: Overflow) -> ExitCode {
+    match optimized_ir(checked, bodies, level, overflow) {
+        Some(module) => {
+            print!("{}", module);
+            ExitCode::SUCCESS
+        }
+        None => ExitCode::FAILURE,
+    }
+}
+
+/// Builds the IR of a program and optimises it. IR which does not verify is reported, as it is a
+/// bug in the builder or in a pass
+fn optimized_ir(
+    checked: &Checked,
+    bodies: &[Body],
+    level: OptLevel,
+    overflow: Overflow,
+) -> Option<shark_ir::ir::Module> {
+    let mut module = shark_ir::build(
+        &checked.module,
+        &checked.defs,
+        &checked.types,
+        bodies,
+        overflow,
+    );
+    shark_ir::optimize(&mut module, level);
+    match shark_ir::verify::verify(&module) {
+        Ok(()) => Some(module),
+        Err(error) => {
+            eprintln!("error: the IR is malformed {}", error);
+            None
+        }
+    }
+}
+
+/// Compiles a program with the JIT and runs it, first reporting how long compiling each function
+/// took if asked to
+fn run_jit(
+    checked: &Checked,
+    bodies: &[Body],
+    time_passes: bool,
+    overflow: Overflow,
+    host: Host,
+) -> Result<Value, Diagnostic> {
+    let mut program = shark_jit::compile(
+        &checked.module,
+        &checked.defs,
+        &checked.types,
+        bodies,
+        overflow,
+        host,
+        &[],
+    )?;
+    if time_passes {
+        let total: Duration = program.timings.iter().map(|x| x.time).sum();
+        for timing in &program.timings {
+            eprintln!("{}", timing);
+        }
+        eprintln!("{:>9.3}ms  total", total.as_secs_f64() * 1000.0);
+    }
+    program.run()
+}
+
+/// Compiles a file into an executable from its optimised IR. The generated source is kept next to
+/// the executable
+fn build(
+    session: &Session,
+    target: Target,
+    output: Option<PathBuf>,
+    opt_level: OptLevel,
+    overflow: Overflow,
+    link: &[PathBuf],
+) -> ExitCode {
+    let Some(checked) = session.check() else {
+        return ExitCode::FAILURE;
+    };
+    let (bodies, diagnostics) = shark_lower::lower_module(&checked.module);
+    if session.report(&diagnostics) {
+        return ExitCode::FAILURE;
+    }
+    if let Err(diagnostic) = shark_interp::find_main(&checked.module) {
+        session.report(&[diagnostic]);
+        return ExitCode::FAILURE;
+    }
+    let Some(module) = optimized_ir(&checked, &bodies, opt_level, overflow) else {
+        return ExitCode::FAILURE;
+    };
+    let output = output.unwrap_or_else(|| session.path.with_extension(""));
+    let (code, extension) = match target {
+        Target::C => (
+            Ok(shark_codegen_c::generate(
+                &module,
+                Some(&session.path),
+                &session.source,
+            )),
+            "c",
+        ),
+        Target::Wasm32 => {
+            return build_wasm(session, &module, &output);
+        }
+        Target::X86_64 => (
+            shark_codegen_x86::generate(&module, Some(&session.path), &session.source),
+            "s",
+        ),
+    };
+    let code = match code {
+        Ok(code) => code,
+        Err(diagnostics) => {
+            session.report(&diagnostics);
+            return ExitCode::FAILURE;
+        }
+    };
+    let result = source_path(&output, extension)
+        .and_then(|source_path| {
+            std::fs::write(&source_path, code)
+                .map_err(|x| format!("could not write `{}`: {}", source_path.display(), x))
+                .map(|()| source_path)
+        })
+        .and_then(|source_path| match target {
+            Target::C => shark_codegen_c::build_executable(&source_path, &output, link),
+            Target::Wasm32 => unreachable!("WebAssembly modules are not linked"),
+            Target::X86_64 => shark_codegen_x86::build_executable(&source_path, &output),
+        });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The path of the generated source kept next to `output`. An output which already has the
+/// `extension` of the source is rejected, as the executable would overwrite the source it is built
+/// from
+fn source_path(output: &Path, extension: &str) -> Result<PathBuf, String> {
+    let source_path = output.with_extension(extension);
+    if source_path == output {
+        return Err(format!(
+            "the output `{}` would overwrite the generated source, use a path without the extension `.{}`",
+            output.display(),
+            extension
+        ));
+    }
+    Ok(source_path)
+}
+
+/// Writes the WebAssembly module of a program next to `output`, as a `.wasm` binary and as `.wat`
+/// text, generated from its optimised IR. The module is validated first, as a module which fails is a bug in the backend
+fn build_wasm(session: &Session, module: &shark_ir::ir::Module, output: &Path) -> ExitCode {
+    let module = match shark_codegen_wasm::generate(module, Some(&session.path), &session.source) {
+        Ok(module) => module,
+        Err(diagnostics) => {
+            session.report(&diagnostics);
+            return ExitCode::FAILURE;
+        }
+    };
+    let files = [
+        ("wasm", shark_codegen_wasm::encode::encode(&module)),
+        ("wat", shark_codegen_wasm::wat::print(&module).into_bytes()),
+    ];
+    let result = shark_codegen_wasm::validate::validate(&module)
+        .map_err(|x| format!("the generated module is invalid: {}", x))
+        .and_then(|()| {
+            files.iter().try_for_each(|(extension, contents)| {
+                let path = output.with_extension(extension);
+                std::fs::write(&path, contents)
+                    .map_err(|x| format!("could not write `{}`: {}", path.display(), x))
+            })
+        });
+    match result {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// The exit code for the value returned by `main`, which is its `Int32` cut down to a byte
+fn exit_code(value: &shark_vm::value::Value) -> u8 {
+    match value {
+        shark_vm::value::Value::Integer(_, code) => *code as u8,
+        _ => 0,
+    }
+}
+
+/// Generates the declarations for a C header. Declarations which can not be written in Shark are
+/// skipped with a warning, which does not stop the others from being written
+fn bindgen(path: &Path, output: Option<&Path>) -> ExitCode {
+    let header = match std::fs::read_to_string(path) {
+        Ok(header) => header,
+        Err(error) => {
+            eprintln!("error: could not read `{}`: {}", path.display(), error);
+            return ExitCode::FAILURE;
+        }
+    };
+    let bindings = match shark_bindgen::generate(&header) {
+        Ok(bindings) => bindings,
+        Err(diagnostic) => {
+            eprintln!("{}", diagnostic.render(Some(path), &header));
+            return ExitCode::FAILURE;
+        }
+    };
+    for warning in &bindings.warnings {
+        eprintln!("{}", warning.render(Some(path), &header));
+    }
+    let code = format!(
+        "// Generated by `sharkc bindgen` from `{}`\n\n{}",
+        path.display(),
+        bindings.code
+    );
+    let Some(output) = output else {
+        print!("{}", code);
+        return ExitCode::SUCCESS;
+    };
+    match std::fs::write(output, code) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(error) => {
+            eprintln!("error: could not write `{}`: {}", output.display(), error);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+/// Formats each file, or with `check` lists the files which formatting would change. Every file is
+/// looked at even if an earlier one fails
+fn fmt(paths: &[PathBuf], check: bool) -> ExitCode {
+    let mut result = ExitCode::SUCCESS;
+    for path in paths {
+        match fmt_file(path, check) {
+            Ok(false) => {}
+            Ok(true) => {
+                println!("{}", path.display());
+                result = ExitCode::FAILURE;
+            }
+            Err(message) => {
+                eprintln!("{}", message);
+                result = ExitCode::FAILURE;
+            }
+        }
+    }
+    result
+}
+
+/// Formats a file, or with `check` returns whether formatting would change it
+fn fmt_file(path: &Path, check: bool) -> Result<bool, String> {
+    let read = |path: &Path| {
+        std::fs::read_to_string(path)
+            .map_err(|x| format!("error: could not read `{}`: {}", path.display(), x))
+    };
+    let config = match shark_fmt::Config::find(path) {
+        Some(config_path) => {
+            let text = read(&config_path)?;
+            shark_fmt::Config::parse(&text).map_err(|x| x.render(Some(&config_path), &text))?
+        }
+        None => shark_fmt::Config::default(),
+    };
+    let source = read(path)?;
+    let formatted = shark_fmt::format(Some(path), &source, &config).map_err(|diagnostics| {
+        let rendered: Vec<String> = diagnostics
+            .iter()
+            .map(|x| x.render(Some(path), &source))
+            .collect();
+        rendered.join("\n")
+    })?;
+    if check || formatted == source {
+        return Ok(formatted != source);
+    }
+    std::fs::write(path, formatted)
+        .map_err(|x| format!("error: could not write `{}`: {}", path.display(), x))?;
+    Ok(false)
+}
+
+/// Runs a `.sbc` file on the VM. Its source is not at hand, so runtime errors are reported without
+/// pointing at the code which caused them
+fn run_bytecode(path: &Path, emit: Option<Emit>, args: Vec<String>) -> ExitCode {
+    let program = std::fs::read(path)
+        .map_err(|x| format!("could not read `{}`: {}", path.display(), x))
+        .and_then(|bytes| {
+            shark_vm::sbc::read(&bytes).map_err(|x| format!("`{}`: {}", path.display(), x))
+        });
+    let program: Program = match program {
+        Ok(program) => program,
+        Err(message) => {
+            eprintln!("error: {}", message);
+            return ExitCode::FAILURE;
+        }
+    };
+    match emit {
+        Some(Emit::Bytecode) => {
+            print!("{}", shark_vm::disasm::disassemble(&program));
+            ExitCode::SUCCESS
+        }
+        Some(Emit::Sbc) => {
+            eprintln!("error: `{}` is already bytecode", path.display());
+            ExitCode::FAILURE
+        }
+        Some(Emit::Ir) => {
+            eprintln!("error: `{}` is bytecode, which has no IR", path.display());
+            ExitCode::FAILURE
+        }
+        None => match shark_vm::run(&program, Host::new(args)) {
+            Ok(value) => ExitCode::from(exit_code(&value)),
+            Err(mut diagnostic) => {
+                diagnostic.labels.clear();
+                eprintln!("{}", diagnostic.render(Some(path), ""));
+                ExitCode::from(RUNTIME_ERROR)
+            }
+        },
+    }
+}
+
+pub fn main() -> ExitCode {
+    let command = match Command::parse(std::env::args().skip(1)) {
+        Ok(command) => command,
+        Err(message) => {
+            eprintln!("{}", message);
+            return ExitCode::FAILURE;
+        }
+    };
+    match command {
+        Command::Run {
+            path, emit, args, ..
+        } if path.extension().is_some_and(|x| x == "sbc") => run_bytecode(&path, emit, args),
+        Command::Run {
+            path,
+            engine,
+            emit,
+            time_passes,
+            opt_level,
+            overflow,
+            args,
+        } => match Session::load(&path) {
+            Ok(session) => run(
+                &session,
+                engine,
+                emit,
+                time_passes,
+                opt_level,
+                overflow,
+                args,
+            ),
+            Err(message) => {
+                eprintln!("error: {}", message);
+                ExitCode::FAILURE
+            }
+        },
+        Command::Build {
+            path,
+            target,
+            output,
+            opt_level,
+            overflow,
+            link,
+        } => match Session::load(&path) {
+            Ok(session) => build(&session, target, output, opt_level, overflow, &link),
+            Err(message) => {
+                eprintln!("error: {}", message);
+                ExitCode::FAILURE
+            }
+        },
+        Command::Bindgen { path, output } => bindgen(&path, output.as_deref()),
+        Command::Fmt { paths, check } => fmt(&paths, check),
+    }
 }
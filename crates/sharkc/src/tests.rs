@@ -0,0 +1,258 @@
+use std::path::{Path, PathBuf};
+
+use shark_interp::value::Value;
+use shark_ir::OptLevel;
+use shark_sema::numeric::Overflow;
+use shark_std::runtime::Host;
+use shark_testing::scratch_directory;
+
+use crate::driver::{Checked, Session};
+
+/// Checks a program, returning the message of every diagnostic reported along with its notes
+fn check(source: &str) -> (bool, Vec<String>) {
+    let session = Session::new(Path::new("main.shark"), source);
+    let checked = session.check().is_some();
+    let messages = session
+        .reported()
+        .iter()
+        .map(|x| {
+            let notes: Vec<&str> = x.notes.iter().map(|x| x.as_str()).collect();
+            [x.message.as_str()]
+                .into_iter()
+                .chain(notes)
+                .collect::<Vec<_>>()
+                .join(", ")
+        })
+        .collect();
+    (checked, messages)
+}
+
+#[test]
+fn test_check_program() {
+    let (checked, messages) = check(
+        "fun double(x :: Int32) :: Int32 { x * 2 }
+pub fun main() :: Int32 { double(1) }",
+    );
+    assert!(checked);
+    assert_eq!(messages, Vec::<String>::new());
+}
+
+#[test]
+fn test_unresolved_function() {
+    let (checked, messages) = check(
+        "fun double(x :: Int32) :: Int32 { x * 2 }
+pub fun main() :: Int32 { dobule(1) }",
+    );
+    assert!(!checked);
+    assert_eq!(
+        messages,
+        ["unresolved name `dobule`, did you mean `double`?"]
+    );
+}
+
+#[test]
+fn test_unresolved_local() {
+    let (checked, messages) = check("pub fun main() { x }");
+    assert!(!checked);
+    assert_eq!(messages, ["unresolved name `x`"]);
+}
+
+#[test]
+fn test_unresolved_import() {
+    let (checked, messages) = check("use missing::thing;\npub fun main() {}");
+    assert!(!checked);
+    assert_eq!(messages.len(), 1);
+    assert!(
+        messages[0].starts_with("unresolved import"),
+        "{:?}",
+        messages
+    );
+}
+
+#[test]
+fn test_program_items_shadow_library() {
+    let (checked, messages) = check(
+        "trait Show { fun show(self) :: Str; }
+type Option { value :: Int64 }
+pub fun main() {
+    let option = Option { value = 1 };
+    println(\"{}\", option.value);
+}",
+    );
+    assert!(checked, "{:?}", messages);
+
+    // Errors in the program are reported against its own file, not the standard library
+    let session = Session::new(Path::new("main.shark"), "pub fun main() {\n    \"open\n}");
+    assert!(session.check().is_none());
+    for diagnostic in session.reported() {
+        let rendered = session.render(&diagnostic);
+        assert!(rendered.contains("--> main.shark:"), "{}", rendered);
+    }
+    let rendered = session.render(&session.reported()[0]);
+    assert!(rendered.contains("--> main.shark:2:5"), "{}", rendered);
+}
+
+#[test]
+fn test_output_overwrites_source() {
+    assert_eq!(
+        crate::source_path(Path::new("prog"), "c"),
+        Ok(PathBuf::from("prog.c"))
+    );
+    assert_eq!(
+        crate::source_path(Path::new("out/prog.exe"), "s"),
+        Ok(PathBuf::from("out/prog.s"))
+    );
+    for (output, extension) in [("prog.c", "c"), ("out/foo.s", "s")] {
+        let error = crate::source_path(Path::new(output), extension).unwrap_err();
+        assert!(
+            error.starts_with(&format!("the output `{}`", output)),
+            "{}",
+            error
+        );
+    }
+}
+
+/// A module used by the programs of [test_modules]. Only it mentions `Vec`, which the standard
+/// library must keep for it
+const SHAPES: &str = "pub type Square {
+    side :: Int64,
+}
+
+impl Square {
+    fun new(side :: Int64) :: Square {
+        Square { side = side }
+    }
+
+    fun area(self :: ref Self) :: Int64 {
+        area2(self.side)
+    }
+}
+
+pub enum Shape {
+    Dot,
+    Box(Square),
+}
+
+pub fun area2(side :: Int64) :: Int64 {
+    side * side
+}
+
+pub fun only(value :: Int64) :: Option<Int64> {
+    let mut values = Vec::new();
+    values.push(value);
+    values.get(0)
+}
+";
+
+/// Writes the files of a program into a directory of its own, checking `main.shark`. The
+/// directory is removed again once the files have been read
+fn check_files(files: &[(&str, &str)]) -> (Option<Checked>, Vec<String>) {
+    let directory = scratch_directory("sharkc");
+    for (name, source) in files {
+        std::fs::write(directory.join(name), source).expect("failed to write the program");
+    }
+    let session = Session::load(&directory.join("main.shark")).expect("failed to read the program");
+    let _ = std::fs::remove_dir_all(&directory);
+    let checked = session.check();
+    let messages = session
+        .reported()
+        .iter()
+        .map(|x| x.message.clone())
+        .collect();
+    (checked, messages)
+}
+
+#[test]
+fn test_modules() {
+    let (checked, messages) = check_files(&[
+        (
+            "main.shark",
+            "use shapes;
+use shapes::{area2, Shape, Square};
+
+fun describe(shape :: Shape) :: Int64 {
+    when shape {
+        Shape::Dot => 0,
+        shapes::Shape::Box(square) => square.area(),
+    }
+}
+
+pub fun main() :: Int32 {
+    let square = Square::new(3);
+    let other = shapes::Square { side = 4 };
+    let first = shapes::only(7).unwrap();
+    println(\"{} {} {} {} {}\", area2(2), shapes::area2(5), square.area(), other.area(), first);
+    println(\"{}\", describe(Shape::Box(Square::new(6))));
+    0
+}
+",
+        ),
+        ("shapes.shark", SHAPES),
+    ]);
+    let checked = checked.unwrap_or_else(|| panic!("{:?}", messages));
+    let (bodies, diagnostics) = shark_lower::lower_module(&checked.module);
+    assert!(diagnostics.is_empty(), "{:?}", diagnostics);
+
+    let (host, captured) = Host::captured(Vec::new());
+    let result = shark_interp::run(
+        &checked.module,
+        &checked.defs,
+        &checked.types,
+        &bodies,
+        Overflow::Trap,
+        host,
+    );
+    assert!(matches!(result, Ok(Value::Int32(0))), "{:?}", result);
+    assert_eq!(captured.text(), "4 25 9 16 7\n36\n");
+
+    let program = shark_vm::compile_module(
+        &checked.module,
+        &checked.defs,
+        &checked.types,
+        &bodies,
+        Overflow::Trap,
+    );
+    let (host, captured) = Host::captured(Vec::new());
+    assert!(shark_vm::run(&program, host).is_ok());
+    assert_eq!(captured.text(), "4 25 9 16 7\n36\n");
+
+    for level in [OptLevel::O0, OptLevel::O2] {
+        assert!(crate::optimized_ir(&checked, &bodies, level, Overflow::Trap).is_some());
+    }
+}
+
+#[test]
+fn test_module_errors() {
+    let (_, messages) = check_files(&[
+        (
+            "main.shark",
+            "use shapes::Square;\npub fun main() { Square { side = true }; }",
+        ),
+        ("shapes.shark", SHAPES),
+    ]);
+    assert_eq!(
+        messages,
+        ["mismatched types: expected `Int64`, found `Bool`"]
+    );
+
+    let (_, messages) = check_files(&[
+        ("main.shark", "use shapes;\npub fun main() {}"),
+        ("shapes.shark", "pub fun f() :: Int64 { true }"),
+    ]);
+    assert_eq!(
+        messages,
+        ["mismatched types: expected `Int64`, found `Bool`"]
+    );
+
+    // Items which are not imported are not in scope
+    let (_, messages) = check_files(&[
+        ("main.shark", "use shapes;\npub fun main() { area2(1); }"),
+        ("shapes.shark", SHAPES),
+    ]);
+    assert_eq!(messages.len(), 1);
+    assert!(
+        messages[0].starts_with("unresolved name `area2`"),
+        "{:?}",
+        messages
+    );
+}